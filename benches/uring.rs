@@ -0,0 +1,134 @@
+// Copyright 2020-2021, The Tremor Team
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+//! Compares the io_uring backed files and tcp streams with async-std
+
+#[cfg(target_os = "linux")]
+mod uring {
+    use async_std::fs::File;
+    use async_std::net::{TcpListener, TcpStream};
+    use async_std::prelude::*;
+    use async_std::task;
+    use criterion::{black_box, Criterion, Throughput};
+    use futures::io::{AsyncRead, AsyncWrite};
+    use std::path::Path;
+    use tremor_runtime::uring::{self, Ring};
+
+    /// Bytes written and read per iteration
+    const TOTAL: usize = 16 * 1024 * 1024;
+    /// Bytes per call, like the tcp and file ramps use
+    const CHUNK: usize = 8 * 1024;
+
+    async fn write(mut w: impl AsyncWrite + Unpin) -> std::io::Result<()> {
+        let chunk = [b'x'; CHUNK];
+        for _ in 0..TOTAL / CHUNK {
+            w.write_all(&chunk).await?;
+        }
+        w.flush().await?;
+        w.close().await
+    }
+
+    async fn read(mut r: impl AsyncRead + Unpin) -> std::io::Result<usize> {
+        let mut buf = [0; CHUNK];
+        let mut total = 0;
+        loop {
+            match r.read(&mut buf).await? {
+                0 => return Ok(total),
+                n => total += n,
+            }
+        }
+    }
+
+    async fn file_write(ring: Option<&'static Ring>, path: &Path) -> std::io::Result<()> {
+        let file = File::create(path).await?;
+        match ring {
+            Some(ring) => write(uring::File::new(ring, file)?).await,
+            None => write(file).await,
+        }
+    }
+
+    async fn file_read(ring: Option<&'static Ring>, path: &Path) -> std::io::Result<usize> {
+        let file = File::open(path).await?;
+        match ring {
+            Some(ring) => read(uring::File::new(ring, file)?).await,
+            None => read(file).await,
+        }
+    }
+
+    async fn tcp(ring: Option<&'static Ring>) -> std::io::Result<usize> {
+        let listener = TcpListener::bind("127.0.0.1:0").await?;
+        let addr = listener.local_addr()?;
+        let writer = task::spawn(async move {
+            let stream = TcpStream::connect(addr).await?;
+            match ring {
+                Some(ring) => write(uring::TcpStream::new(ring, stream)).await,
+                None => write(stream).await,
+            }
+        });
+        let (stream, _) = listener.accept().await?;
+        let n = match ring {
+            Some(ring) => read(uring::TcpStream::new(ring, stream)).await?,
+            None => read(stream).await?,
+        };
+        writer.await?;
+        Ok(n)
+    }
+
+    pub fn uring_benchmark(c: &mut Criterion) {
+        let ring = if let Some(ring) = uring::ring() {
+            ring
+        } else {
+            eprintln!("io_uring is not available, skipping");
+            return;
+        };
+        let dir = tempfile::tempdir().expect("temporary directory");
+        let path = dir.path().join("bench");
+        let backends = [("async-std", None), ("io-uring", Some(ring))];
+
+        let mut group = c.benchmark_group("file-write");
+        group.throughput(Throughput::Bytes(TOTAL as u64));
+        for (name, ring) in backends {
+            group.bench_function(name, |b| {
+                b.iter(|| task::block_on(file_write(ring, &path)).expect("written"))
+            });
+        }
+        group.finish();
+
+        let mut group = c.benchmark_group("file-read");
+        group.throughput(Throughput::Bytes(TOTAL as u64));
+        for (name, ring) in backends {
+            group.bench_function(name, |b| {
+                b.iter(|| black_box(task::block_on(file_read(ring, &path)).expect("read")))
+            });
+        }
+        group.finish();
+
+        let mut group = c.benchmark_group("tcp");
+        group.throughput(Throughput::Bytes(TOTAL as u64));
+        for (name, ring) in backends {
+            group.bench_function(name, |b| {
+                b.iter(|| black_box(task::block_on(tcp(ring)).expect("sent")))
+            });
+        }
+        group.finish();
+    }
+}
+
+#[cfg(target_os = "linux")]
+criterion::criterion_group!(benches, uring::uring_benchmark);
+#[cfg(target_os = "linux")]
+criterion::criterion_main!(benches);
+
+#[cfg(not(target_os = "linux"))]
+fn main() {}
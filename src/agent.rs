@@ -0,0 +1,570 @@
+// Copyright 2020-2021, The Tremor Team
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+//! Agent mode, the node periodically pulls the artefacts it should run from
+//! a controller and applies the differences to the artefacts it runs.
+//!
+//! The controller serves the desired artefacts as a YAML or JSON document
+//! with the onramps, offramps, bindings and mappings of a tremor config and
+//! trickle queries by pipeline id under `pipeline`. Changed queries are
+//! published as a new version of the pipeline that its running instances are
+//! reloaded with, instances of bindings that link a changed onramp or
+//! offramp are unlinked before it is replaced and linked again afterwards.
+//!
+//! After every attempt to apply a new document the node `POST`s its status
+//! to the controller. Only artefacts the agent published are managed by it.
+
+use crate::config::{Binding, MappingMap, OffRampVec, OnRampVec};
+use crate::errors::Result;
+use crate::repository::BindingArtefact;
+use crate::system::World;
+use crate::url::{ResourceType, TremorUrl};
+use crate::{interpolate, utils};
+use async_std::sync::RwLock;
+use async_std::task;
+use hashbrown::{HashMap, HashSet};
+use sha2::{Digest, Sha256};
+use std::cmp::Reverse;
+use std::collections::BTreeMap;
+use std::sync::Arc;
+use std::time::Duration;
+use tremor_common::time::nanotime;
+use tremor_pipeline::{query::Query, FN_REGISTRY};
+use tremor_value::Value;
+
+/// Configuration of the agent mode
+#[derive(Clone, Debug)]
+pub struct Config {
+    /// URL the desired artefacts are pulled from
+    pub url: String,
+    /// time between two pulls
+    pub interval: Duration,
+    /// bearer token to authenticate with at the controller
+    pub token: Option<String>,
+}
+
+/// The artefacts a controller wants a node to run
+#[derive(Clone, Debug, Default, Deserialize, Serialize)]
+#[serde(deny_unknown_fields)]
+pub struct Desired {
+    /// trickle queries by pipeline id
+    #[serde(default)]
+    pub(crate) pipeline: BTreeMap<String, String>,
+    #[serde(default)]
+    pub(crate) onramp: OnRampVec,
+    #[serde(default)]
+    pub(crate) offramp: OffRampVec,
+    #[serde(default)]
+    pub(crate) binding: Vec<Binding>,
+    #[serde(default)]
+    pub(crate) mapping: MappingMap,
+}
+
+/// State of the agent, reported to the controller
+#[derive(Clone, Debug, Default, PartialEq, Serialize, Deserialize)]
+pub struct Status {
+    /// the node, its hostname
+    pub node: String,
+    /// sha256 of the applied document
+    pub applied: Option<String>,
+    /// number of artefacts and instances the agent manages
+    pub artefacts: usize,
+    /// time of the last successful pull in nanoseconds
+    pub last_pull: Option<u64>,
+    /// error of the last pull or of applying the last document
+    pub error: Option<String>,
+}
+
+fn artefact_url(resource_type: ResourceType, id: &str) -> Result<TremorUrl> {
+    TremorUrl::parse(&format!("/{}/{}", resource_type, id))
+}
+
+impl Desired {
+    /// The artefacts and their content
+    fn artefacts(&self) -> Result<HashMap<TremorUrl, Value<'static>>> {
+        let mut artefacts = HashMap::new();
+        for (id, query) in &self.pipeline {
+            let url = artefact_url(ResourceType::Pipeline, id)?;
+            artefacts.insert(url, Value::from(query.clone()));
+        }
+        for onramp in &self.onramp {
+            let url = artefact_url(ResourceType::Onramp, &onramp.id)?;
+            artefacts.insert(url, tremor_value::to_value(onramp)?);
+        }
+        for offramp in &self.offramp {
+            let url = artefact_url(ResourceType::Offramp, &offramp.id)?;
+            artefacts.insert(url, tremor_value::to_value(offramp)?);
+        }
+        for binding in &self.binding {
+            let url = artefact_url(ResourceType::Binding, &binding.id)?;
+            artefacts.insert(url, tremor_value::to_value(binding)?);
+        }
+        Ok(artefacts)
+    }
+
+    fn len(&self) -> usize {
+        self.pipeline.len()
+            + self.onramp.len()
+            + self.offramp.len()
+            + self.binding.len()
+            + self.mapping.len()
+    }
+
+    /// Whether the binding `id` links one of the artefacts
+    fn links_any(&self, id: &str, artefacts: &HashSet<TremorUrl>) -> bool {
+        self.binding
+            .iter()
+            .filter(|binding| binding.id == id)
+            .flat_map(|binding| binding.links.iter())
+            .flat_map(|(from, to)| std::iter::once(from).chain(to.iter()))
+            .any(|url| {
+                let mut url = url.clone();
+                url.trim_to_artefact();
+                artefacts.contains(&url)
+            })
+    }
+
+    /// Removes an artefact or mapping
+    fn remove(&mut self, url: &TremorUrl) {
+        let id = url.artefact().unwrap_or_default();
+        match url.resource_type() {
+            Some(ResourceType::Binding) if url.instance().is_some() => {
+                self.mapping.remove(url);
+            }
+            Some(ResourceType::Pipeline) => {
+                self.pipeline.remove(id);
+            }
+            Some(ResourceType::Onramp) => self.onramp.retain(|o| o.id != id),
+            Some(ResourceType::Offramp) => self.offramp.retain(|o| o.id != id),
+            Some(ResourceType::Binding) => self.binding.retain(|b| b.id != id),
+            None => (),
+        }
+    }
+
+    /// Replaces an artefact or mapping with the one of `other`
+    fn copy(&mut self, other: &Self, url: &TremorUrl) {
+        self.remove(url);
+        let id = url.artefact().unwrap_or_default();
+        match url.resource_type() {
+            Some(ResourceType::Binding) if url.instance().is_some() => {
+                if let Some(mapping) = other.mapping.get(url) {
+                    self.mapping.insert(url.clone(), mapping.clone());
+                }
+            }
+            Some(ResourceType::Pipeline) => {
+                if let Some(query) = other.pipeline.get(id) {
+                    self.pipeline.insert(id.to_string(), query.clone());
+                }
+            }
+            Some(ResourceType::Onramp) => self
+                .onramp
+                .extend(other.onramp.iter().filter(|o| o.id == id).cloned()),
+            Some(ResourceType::Offramp) => self
+                .offramp
+                .extend(other.offramp.iter().filter(|o| o.id == id).cloned()),
+            Some(ResourceType::Binding) => self
+                .binding
+                .extend(other.binding.iter().filter(|b| b.id == id).cloned()),
+            None => (),
+        }
+    }
+}
+
+/// The steps to get from one set of artefacts to another, in order
+#[derive(Debug, Default, PartialEq)]
+struct Plan {
+    /// instances to unlink
+    unlink: Vec<TremorUrl>,
+    /// artefacts to unpublish, bindings first
+    unpublish: Vec<TremorUrl>,
+    /// pipelines with a changed query
+    reload: Vec<TremorUrl>,
+    /// artefacts to publish, bindings last
+    publish: Vec<TremorUrl>,
+    /// instances to link
+    link: Vec<TremorUrl>,
+}
+
+/// Bindings link onramps, offramps and pipelines, so they're handled last
+fn rank(url: &TremorUrl) -> u8 {
+    match url.resource_type() {
+        Some(ResourceType::Offramp) => 0,
+        Some(ResourceType::Onramp) => 1,
+        Some(ResourceType::Pipeline) => 2,
+        Some(ResourceType::Binding) | None => 3,
+    }
+}
+
+fn plan(current: &Desired, next: &Desired) -> Result<Plan> {
+    let before = current.artefacts()?;
+    let after = next.artefacts()?;
+    let mut plan = Plan::default();
+    // artefacts that are replaced, pipelines are reloaded in place instead
+    let mut replaced = HashSet::new();
+    for (url, content) in &before {
+        match after.get(url) {
+            Some(c) if c == content => (),
+            Some(_) if url.resource_type() == Some(ResourceType::Pipeline) => {
+                plan.reload.push(url.clone());
+            }
+            _ => {
+                plan.unpublish.push(url.clone());
+                replaced.insert(url.clone());
+            }
+        }
+    }
+    for (url, content) in &after {
+        match before.get(url) {
+            Some(c) if c == content => (),
+            Some(_) if url.resource_type() == Some(ResourceType::Pipeline) => (),
+            _ => {
+                plan.publish.push(url.clone());
+                replaced.insert(url.clone());
+            }
+        }
+    }
+    let relink = |instance: &TremorUrl| {
+        let mut binding = instance.clone();
+        binding.trim_to_artefact();
+        let id = binding.artefact().unwrap_or_default();
+        replaced.contains(&binding)
+            || current.links_any(id, &replaced)
+            || next.links_any(id, &replaced)
+    };
+    plan.unlink = current
+        .mapping
+        .iter()
+        .filter(|(url, mapping)| relink(*url) || next.mapping.get(*url) != Some(*mapping))
+        .map(|(url, _)| url.clone())
+        .collect();
+    plan.link = next
+        .mapping
+        .iter()
+        .filter(|(url, mapping)| relink(*url) || current.mapping.get(*url) != Some(*mapping))
+        .map(|(url, _)| url.clone())
+        .collect();
+    plan.unlink.sort_by_key(ToString::to_string);
+    plan.unpublish
+        .sort_by_key(|url| (Reverse(rank(url)), url.to_string()));
+    plan.reload.sort_by_key(ToString::to_string);
+    plan.publish.sort_by_key(|url| (rank(url), url.to_string()));
+    plan.link.sort_by_key(ToString::to_string);
+    Ok(plan)
+}
+
+fn parse_query(url: &TremorUrl, query: &str) -> Result<Query> {
+    let aggr_reg = tremor_script::registry::aggr();
+    let module_path = tremor_script::path::load();
+    Ok(Query::parse(
+        &module_path,
+        query,
+        &url.to_string(),
+        vec![],
+        &*FN_REGISTRY.lock()?,
+        &aggr_reg,
+    )?)
+}
+
+async fn publish(world: &World, next: &Desired, url: &TremorUrl) -> Result<()> {
+    let id = url.artefact().unwrap_or_default();
+    match url.resource_type() {
+        Some(ResourceType::Pipeline) => {
+            if let Some(query) = next.pipeline.get(id) {
+                let query = parse_query(url, query)?;
+                world.repo.publish_pipeline(url, false, query).await?;
+            }
+        }
+        Some(ResourceType::Onramp) => {
+            if let Some(onramp) = next.onramp.iter().find(|o| o.id == id) {
+                world
+                    .repo
+                    .publish_onramp(url, false, onramp.clone())
+                    .await?;
+            }
+        }
+        Some(ResourceType::Offramp) => {
+            if let Some(offramp) = next.offramp.iter().find(|o| o.id == id) {
+                world
+                    .repo
+                    .publish_offramp(url, false, offramp.clone())
+                    .await?;
+            }
+        }
+        Some(ResourceType::Binding) => {
+            if let Some(binding) = next.binding.iter().find(|b| b.id == id) {
+                let artefact = BindingArtefact {
+                    binding: binding.clone(),
+                    mapping: None,
+                };
+                world.repo.publish_binding(url, false, artefact).await?;
+            }
+        }
+        None => (),
+    }
+    Ok(())
+}
+
+async fn unpublish(world: &World, url: &TremorUrl) -> Result<()> {
+    match url.resource_type() {
+        Some(ResourceType::Pipeline) => world.repo.unpublish_pipeline(url).await.map(|_| ()),
+        Some(ResourceType::Onramp) => world.repo.unpublish_onramp(url).await.map(|_| ()),
+        Some(ResourceType::Offramp) => world.repo.unpublish_offramp(url).await.map(|_| ()),
+        Some(ResourceType::Binding) => world.repo.unpublish_binding(url).await.map(|_| ()),
+        None => Ok(()),
+    }
+}
+
+/// Applies the differences between `current` and `next`, `current` is
+/// updated with every step so it stays accurate if a step fails
+async fn apply(world: &World, current: &mut Desired, next: &Desired) -> Result<()> {
+    let plan = plan(current, next)?;
+    for url in &plan.unlink {
+        if let Some(mapping) = current.mapping.get(url) {
+            info!("[Agent] Unlinking {}", url);
+            world.unlink_binding(url, mapping.clone()).await?;
+        }
+        current.remove(url);
+    }
+    for url in &plan.unpublish {
+        info!("[Agent] Unpublishing {}", url);
+        unpublish(world, url).await?;
+        current.remove(url);
+    }
+    for url in &plan.reload {
+        info!("[Agent] Reloading {}", url);
+        publish(world, next, url).await?;
+        if let Some(wrapper) = world.repo.find_pipeline(url).await? {
+            world.activate_pipeline(url, wrapper.version).await?;
+        }
+        current.copy(next, url);
+    }
+    for url in &plan.publish {
+        info!("[Agent] Publishing {}", url);
+        publish(world, next, url).await?;
+        current.copy(next, url);
+    }
+    for url in &plan.link {
+        if let Some(mapping) = next.mapping.get(url) {
+            info!("[Agent] Linking {}", url);
+            world.link_binding(url, mapping.clone()).await?;
+        }
+        current.copy(next, url);
+    }
+    Ok(())
+}
+
+async fn pull(config: &Config, node: &str) -> Result<Vec<u8>> {
+    let mut request = surf::get(&config.url).header("X-Tremor-Node", node);
+    if let Some(token) = &config.token {
+        request = request.header("Authorization", format!("Bearer {}", token));
+    }
+    let mut response = request.await?;
+    if !response.status().is_success() {
+        return Err(format!("Failed to pull {}: {}", config.url, response.status()).into());
+    }
+    Ok(response.body_bytes().await?)
+}
+
+async fn report(config: &Config, status: &Status) -> Result<()> {
+    let mut request = surf::post(&config.url)
+        .content_type(surf::http::mime::JSON)
+        .body(simd_json::to_vec(status)?);
+    if let Some(token) = &config.token {
+        request = request.header("Authorization", format!("Bearer {}", token));
+    }
+    let response = request.await?;
+    if !response.status().is_success() {
+        return Err(format!("Controller rejected the status: {}", response.status()).into());
+    }
+    Ok(())
+}
+
+/// Parses a pulled document, resolving secrets and variables like config files
+async fn parse(body: Vec<u8>) -> Result<Desired> {
+    let raw = String::from_utf8(body)?;
+    interpolate::from_yaml(&raw).await
+}
+
+/// A node controlled by a controller
+#[derive(Clone, Debug)]
+pub struct Agent {
+    status: Arc<RwLock<Status>>,
+}
+
+impl Agent {
+    /// Starts pulling artefacts from the controller
+    #[must_use]
+    pub fn start(world: World, config: Config) -> Self {
+        let status = Arc::new(RwLock::new(Status {
+            node: utils::hostname(),
+            ..Status::default()
+        }));
+        task::spawn(run(world, config, status.clone()));
+        Self { status }
+    }
+
+    /// State of the agent
+    pub async fn status(&self) -> Status {
+        self.status.read().await.clone()
+    }
+}
+
+async fn run(world: World, config: Config, status: Arc<RwLock<Status>>) {
+    info!("[Agent] Controlled by {}", config.url);
+    let node = status.read().await.node.clone();
+    let mut current = Desired::default();
+    let mut applied: Option<String> = None;
+    loop {
+        match pull(&config, &node).await {
+            Ok(body) => {
+                let sha256 = hex::encode(Sha256::digest(&body));
+                status.write().await.last_pull = Some(nanotime());
+                if applied.as_ref() != Some(&sha256) {
+                    let result = match parse(body).await {
+                        Ok(next) => apply(&world, &mut current, &next).await,
+                        Err(e) => Err(e),
+                    };
+                    let report_status = {
+                        let mut status = status.write().await;
+                        status.artefacts = current.len();
+                        match result {
+                            Ok(()) => {
+                                info!("[Agent] Applied {}", sha256);
+                                status.applied = Some(sha256.clone());
+                                status.error = None;
+                                applied = Some(sha256);
+                            }
+                            Err(e) => {
+                                error!("[Agent] Failed to apply {}: {}", sha256, e);
+                                status.error = Some(e.to_string());
+                            }
+                        }
+                        status.clone()
+                    };
+                    if let Err(e) = report(&config, &report_status).await {
+                        debug!("[Agent] Failed to report the status: {}", e);
+                    }
+                }
+            }
+            Err(e) => {
+                warn!("[Agent] {}", e);
+                status.write().await.error = Some(e.to_string());
+            }
+        }
+        task::sleep(config.interval).await;
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    const DESIRED: &str = r#"
+pipeline:
+  main: select event from in into out;
+onramp:
+  - id: in
+    type: metronome
+    config:
+      interval: 1000
+offramp:
+  - id: out
+    type: stdout
+binding:
+  - id: default
+    links:
+      '/onramp/in/{instance}/out': ['/pipeline/main/{instance}/in']
+      '/pipeline/main/{instance}/out': ['/offramp/out/{instance}/in']
+mapping:
+  /binding/default/01:
+    instance: "01"
+"#;
+
+    fn urls(urls: &[&str]) -> Result<Vec<TremorUrl>> {
+        urls.iter().map(|url| TremorUrl::parse(url)).collect()
+    }
+
+    #[test]
+    fn plans() -> Result<()> {
+        let empty = Desired::default();
+        let desired: Desired = serde_yaml::from_str(DESIRED)?;
+        assert_eq!(plan(&desired, &desired)?, Plan::default());
+
+        let initial = plan(&empty, &desired)?;
+        assert_eq!(
+            initial.publish,
+            urls(&[
+                "/offramp/out",
+                "/onramp/in",
+                "/pipeline/main",
+                "/binding/default"
+            ])?
+        );
+        assert_eq!(initial.link, urls(&["/binding/default/01"])?);
+
+        let mut query = desired.clone();
+        query.pipeline.insert(
+            "main".to_string(),
+            "select 42 from in into out;".to_string(),
+        );
+        let reload = plan(&desired, &query)?;
+        assert_eq!(
+            reload,
+            Plan {
+                reload: urls(&["/pipeline/main"])?,
+                ..Plan::default()
+            }
+        );
+
+        let changed: Desired =
+            serde_yaml::from_str(&DESIRED.replace("interval: 1000", "interval: 500"))?;
+        let relink = plan(&desired, &changed)?;
+        assert_eq!(relink.unlink, urls(&["/binding/default/01"])?);
+        assert_eq!(relink.unpublish, urls(&["/onramp/in"])?);
+        assert_eq!(relink.publish, urls(&["/onramp/in"])?);
+        assert_eq!(relink.link, urls(&["/binding/default/01"])?);
+
+        let removal = plan(&desired, &empty)?;
+        assert_eq!(removal.unlink, urls(&["/binding/default/01"])?);
+        assert_eq!(
+            removal.unpublish,
+            urls(&[
+                "/binding/default",
+                "/pipeline/main",
+                "/onramp/in",
+                "/offramp/out"
+            ])?
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn tracks_applied_steps() -> Result<()> {
+        let desired: Desired = serde_yaml::from_str(DESIRED)?;
+        let mut current = Desired::default();
+        for url in plan(&current, &desired)?.publish {
+            current.copy(&desired, &url);
+        }
+        let remaining = plan(&current, &desired)?;
+        assert!(remaining.publish.is_empty());
+        assert_eq!(remaining.link, urls(&["/binding/default/01"])?);
+
+        current.copy(&desired, &TremorUrl::parse("/binding/default/01")?);
+        assert_eq!(current.len(), 5);
+        assert_eq!(plan(&current, &desired)?, Plan::default());
+        current.remove(&TremorUrl::parse("/onramp/in")?);
+        assert_eq!(current.len(), 4);
+        Ok(())
+    }
+}
@@ -0,0 +1,260 @@
+// Copyright 2020-2021, The Tremor Team
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+//! Backfills, replaying historical data before switching to the live data
+//!
+//! The `backfill` onramps of a binding are linked first, its other onramps
+//! only once all of them replayed their data, so the pipelines catch up in
+//! order after an outage:
+//!
+//! ```yaml
+//! binding:
+//!   - id: catch-up
+//!     backfill: [ /onramp/archive/{instance}/out ]
+//!     links:
+//!       /onramp/archive/{instance}/out: [ /pipeline/main/{instance}/in ]
+//!       /onramp/live/{instance}/out: [ /pipeline/main/{instance}/in ]
+//! ```
+//!
+//! A backfill onramp is done once its source disconnects, e.g. a `file` or
+//! `replay` onramp at the end of its data.
+
+use crate::errors::Result;
+use crate::url::TremorUrl;
+use async_channel::{unbounded, Receiver, Sender};
+use std::collections::BTreeMap;
+use std::sync::atomic::{AtomicBool, AtomicU64, Ordering};
+use std::sync::{Arc, Mutex};
+use tremor_common::time::nanotime;
+
+lazy_static! {
+    /// backfills by binding instance
+    static ref BACKFILLS: Mutex<BTreeMap<String, Arc<Backfill>>> = Mutex::new(BTreeMap::new());
+}
+
+/// State of a backfill
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum State {
+    /// the backfill onramps replay their data, the live onramps are paused
+    Replaying,
+    /// the backfill is done and the live onramps are linked
+    Live,
+}
+
+/// Progress of a backfill onramp
+#[derive(Clone, Debug, PartialEq, Serialize)]
+pub struct ReplayStatus {
+    /// the onramp instance
+    pub onramp: String,
+    /// number of events replayed
+    pub events: u64,
+    /// if the onramp replayed all its data
+    pub done: bool,
+}
+
+/// Progress of the backfill of a binding
+#[derive(Clone, Debug, PartialEq, Serialize)]
+pub struct Status {
+    /// the binding instance
+    pub binding: String,
+    /// state of the backfill
+    pub state: State,
+    /// progress of the backfill onramps
+    pub onramps: Vec<ReplayStatus>,
+    /// start of the backfill in nanoseconds
+    pub started: u64,
+    /// switch to the live onramps in nanoseconds
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub completed: Option<u64>,
+}
+
+/// Tracks an onramp replaying historical data, its source records the
+/// events it sends and completes the replay when it disconnects
+#[derive(Debug)]
+pub struct Replay {
+    onramp: TremorUrl,
+    events: AtomicU64,
+    done: AtomicBool,
+    tx: Sender<()>,
+}
+
+impl Replay {
+    /// Records an event sent by the onramp
+    pub(crate) fn record(&self) {
+        self.events.fetch_add(1, Ordering::Relaxed);
+    }
+
+    /// Marks the onramp as done
+    pub(crate) fn complete(&self) {
+        if !self.done.swap(true, Ordering::AcqRel) {
+            info!(
+                "[Backfill] {} replayed {} events",
+                self.onramp,
+                self.events.load(Ordering::Relaxed)
+            );
+            // the receiver is gone if the backfill was cancelled
+            if self.tx.try_send(()).is_err() {
+                debug!("[Backfill] {} completed after cancellation", self.onramp);
+            }
+        }
+    }
+
+    fn status(&self) -> ReplayStatus {
+        ReplayStatus {
+            onramp: self.onramp.to_string(),
+            events: self.events.load(Ordering::Relaxed),
+            done: self.done.load(Ordering::Acquire),
+        }
+    }
+}
+
+/// The backfill of a binding instance
+#[derive(Debug)]
+pub(crate) struct Backfill {
+    binding: TremorUrl,
+    replays: Vec<Arc<Replay>>,
+    started: u64,
+    completed: AtomicU64,
+    rx: Receiver<()>,
+}
+
+impl Backfill {
+    /// Registers the backfill of a binding instance, replacing a previous one
+    pub(crate) fn start(binding: &TremorUrl, onramps: &[TremorUrl]) -> Result<Arc<Self>> {
+        let (tx, rx) = unbounded();
+        let replays = onramps
+            .iter()
+            .map(|onramp| {
+                Arc::new(Replay {
+                    onramp: onramp.clone(),
+                    events: AtomicU64::new(0),
+                    done: AtomicBool::new(false),
+                    tx: tx.clone(),
+                })
+            })
+            .collect();
+        let backfill = Arc::new(Self {
+            binding: binding.clone(),
+            replays,
+            started: nanotime(),
+            completed: AtomicU64::new(0),
+            rx,
+        });
+        if let Some(previous) = BACKFILLS
+            .lock()?
+            .insert(binding.to_string(), backfill.clone())
+        {
+            previous.rx.close();
+        }
+        info!("[Backfill] {} replaying {} onramps", binding, onramps.len());
+        Ok(backfill)
+    }
+
+    /// The replay of a backfill onramp
+    pub(crate) fn replay(&self, onramp: &TremorUrl) -> Option<Arc<Replay>> {
+        self.replays.iter().find(|r| &r.onramp == onramp).cloned()
+    }
+
+    /// Waits for all onramps to replay their data, returns `false` if the
+    /// backfill was cancelled
+    pub(crate) async fn replayed(&self) -> bool {
+        for _ in 0..self.replays.len() {
+            if self.rx.recv().await.is_err() {
+                return false;
+            }
+        }
+        self.completed.store(nanotime(), Ordering::Release);
+        info!("[Backfill] {} switching to live", self.binding);
+        true
+    }
+
+    fn status(&self) -> Status {
+        let completed = self.completed.load(Ordering::Acquire);
+        Status {
+            binding: self.binding.to_string(),
+            state: if completed == 0 {
+                State::Replaying
+            } else {
+                State::Live
+            },
+            onramps: self.replays.iter().map(|r| r.status()).collect(),
+            started: self.started,
+            completed: if completed == 0 {
+                None
+            } else {
+                Some(completed)
+            },
+        }
+    }
+}
+
+/// Cancels the backfill of an unlinked binding instance, its live onramps
+/// are no longer linked
+pub(crate) fn cancel(binding: &TremorUrl) -> Result<()> {
+    if let Some(backfill) = BACKFILLS.lock()?.remove(&binding.to_string()) {
+        backfill.rx.close();
+    }
+    Ok(())
+}
+
+/// Progress of the backfills of the linked bindings
+///
+/// # Errors
+///  * if the lock of the backfills is poisoned
+pub fn status() -> Result<Vec<Status>> {
+    Ok(BACKFILLS.lock()?.values().map(|b| b.status()).collect())
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[async_std::test]
+    async fn replays() -> Result<()> {
+        let binding = TremorUrl::parse("/binding/catch-up/01")?;
+        let archive = TremorUrl::parse("/onramp/archive/01")?;
+        let other = TremorUrl::parse("/onramp/other/01")?;
+        let backfill = Backfill::start(&binding, &[archive.clone(), other.clone()])?;
+        let replay = backfill.replay(&archive).ok_or("no replay")?;
+        replay.record();
+        replay.record();
+        replay.complete();
+        replay.complete();
+
+        let status = status()?
+            .into_iter()
+            .find(|s| s.binding == binding.to_string())
+            .ok_or("no status")?;
+        assert_eq!(State::Replaying, status.state);
+        assert_eq!(
+            Some(&ReplayStatus {
+                onramp: archive.to_string(),
+                events: 2,
+                done: true
+            }),
+            status.onramps.first()
+        );
+
+        backfill.replay(&other).ok_or("no replay")?.complete();
+        assert!(backfill.replayed().await);
+        assert_eq!(State::Live, backfill.status().state);
+
+        // cancelling stops waiting for the replays
+        let cancelled = Backfill::start(&binding, &[archive])?;
+        cancel(&binding)?;
+        assert!(!cancelled.replayed().await);
+        Ok(())
+    }
+}
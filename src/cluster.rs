@@ -0,0 +1,496 @@
+// Copyright 2020-2021, The Tremor Team
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+//! Clustering, the nodes of a cluster form a raft group replicating the
+//! modifying API requests. Every node applies the committed requests in
+//! the same order, so publishing, linking and namespaces are consistent
+//! across the cluster and survive the failure of a minority of nodes.
+//!
+//! Onramps configured as `singleton` only run on the node holding the lease
+//! of the leader, on failure of that node another one takes over once it
+//! is elected.
+//!
+//! After applying a command a node snapshots its state, on restart it
+//! restores the snapshot and only applies the commands after it.
+
+mod raft;
+mod state;
+mod storage;
+
+pub use raft::{
+    AppendRequest, AppendResponse, Entry, RoleKind, Snapshot, SnapshotRequest, VoteRequest,
+    VoteResponse,
+};
+pub use state::{capture, restore};
+
+use crate::errors::{ErrorKind, Result};
+use async_channel::{bounded, unbounded, Receiver, Sender};
+use async_std::future::timeout;
+use async_std::task;
+use raft::{Raft, Rpc};
+use serde::{de::DeserializeOwned, Serialize};
+use std::collections::HashMap;
+use std::path::PathBuf;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::Arc;
+use std::time::Duration;
+use storage::Storage;
+
+/// Id of a node
+pub type NodeId = u64;
+
+const TICK: Duration = Duration::from_millis(50);
+const RPC_TIMEOUT: Duration = Duration::from_millis(500);
+/// How long submitting a request waits for it to be applied
+const SUBMIT_TIMEOUT: Duration = Duration::from_secs(10);
+
+/// If the node is part of a cluster
+static CLUSTERED: AtomicBool = AtomicBool::new(false);
+/// If the node holds the lease of the leader
+static LEASE: AtomicBool = AtomicBool::new(false);
+
+/// If singleton onramps run on this node, nodes that aren't part of a
+/// cluster always run them
+#[must_use]
+pub fn holds_lease() -> bool {
+    !CLUSTERED.load(Ordering::Acquire) || LEASE.load(Ordering::Acquire)
+}
+
+/// A modifying API request
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub struct Command {
+    /// HTTP method
+    pub method: String,
+    /// path of the request
+    pub path: String,
+    /// content type of the body
+    pub content_type: Option<String>,
+    /// content type the response is requested in
+    pub accept: Option<String>,
+    /// the body
+    pub body: Vec<u8>,
+}
+
+/// The response of applying a command on the node it was submitted to
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub struct Applied {
+    /// HTTP status
+    pub status: u16,
+    /// content type of the body
+    pub content_type: Option<String>,
+    /// the body
+    pub body: Vec<u8>,
+}
+
+/// What the cluster committed, to be applied in order
+#[derive(Debug, PartialEq)]
+pub enum Committed {
+    /// a snapshot taken by `capture` that replaces the state, as the node
+    /// restarted or fell behind the log of the leader
+    Snapshot(Vec<u8>),
+    /// a command to apply
+    Command {
+        /// index of the command in the log
+        index: u64,
+        /// term of the command
+        term: u64,
+        /// the command
+        command: Command,
+    },
+}
+
+/// Configuration of a cluster node
+#[derive(Clone, Debug)]
+pub struct Config {
+    /// id of the node
+    pub id: NodeId,
+    /// API endpoints of all nodes of the cluster by id, including this one,
+    /// e.g. `http://10.0.0.1:9898`
+    pub nodes: HashMap<NodeId, String>,
+    /// directory the node persists its state and log in
+    pub dir: PathBuf,
+    /// API token for requests to other nodes
+    pub token: Option<String>,
+}
+
+/// State of a node
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct Status {
+    /// id of the node
+    pub id: NodeId,
+    /// role of the node
+    pub role: RoleKind,
+    /// current term
+    pub term: u64,
+    /// the leader, if known
+    pub leader: Option<NodeId>,
+    /// index of the last committed entry
+    pub commit: u64,
+    /// index of the last entry
+    pub last_index: u64,
+    /// index of the last entry the snapshot covers
+    pub snapshot: u64,
+    /// if the node holds the lease and runs the singleton onramps
+    pub lease: bool,
+    /// API endpoints of all nodes by id
+    pub nodes: HashMap<NodeId, String>,
+}
+
+/// Outcome of submitting a command to the local node
+enum Submitted {
+    Applied(Applied),
+    /// the command has to be submitted to the leader
+    Forward(String),
+    /// the command was replaced by another leader before it was committed
+    Lost,
+}
+
+enum Msg {
+    Tick,
+    Vote(VoteRequest, Sender<VoteResponse>),
+    Append(AppendRequest, Sender<AppendResponse>),
+    Snapshot(SnapshotRequest, Sender<AppendResponse>),
+    VoteResponse(NodeId, VoteResponse),
+    AppendResponse(NodeId, AppendResponse),
+    Submit(Command, Sender<Result<Submitted>>),
+    Applied(u64, u64, Applied, Option<Vec<u8>>),
+    Status(Sender<Status>),
+}
+
+/// A node of a cluster
+#[derive(Clone, Debug)]
+pub struct Cluster {
+    tx: Sender<Msg>,
+    config: Arc<Config>,
+}
+
+impl std::fmt::Debug for Msg {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Self::Tick => write!(f, "Tick"),
+            Self::Vote(r, _) => write!(f, "Vote({:?})", r),
+            Self::Append(r, _) => write!(f, "Append({} entries)", r.entries.len()),
+            Self::Snapshot(r, _) => write!(f, "Snapshot({})", r.snapshot.index),
+            Self::VoteResponse(n, r) => write!(f, "VoteResponse({}, {:?})", n, r),
+            Self::AppendResponse(n, r) => write!(f, "AppendResponse({}, {:?})", n, r),
+            Self::Submit(c, _) => write!(f, "Submit({} {})", c.method, c.path),
+            Self::Applied(i, t, _, _) => write!(f, "Applied({}, {})", i, t),
+            Self::Status(_) => write!(f, "Status"),
+        }
+    }
+}
+
+async fn post<Req, Res>(config: &Config, url: &str, rpc: &str, request: &Req) -> Result<Res>
+where
+    Req: Serialize,
+    Res: DeserializeOwned,
+{
+    let mut req = surf::post(format!("{}/cluster/{}", url.trim_end_matches('/'), rpc))
+        .content_type(surf::http::mime::JSON)
+        .body(simd_json::to_vec(request)?);
+    if let Some(token) = &config.token {
+        req = req.header("Authorization", format!("Bearer {}", token));
+    }
+    let mut response = req.await?;
+    if !response.status().is_success() {
+        return Err(format!(
+            "Cluster {} request to {} failed: {}",
+            rpc,
+            url,
+            response.status()
+        )
+        .into());
+    }
+    let mut body = response.body_bytes().await?;
+    Ok(simd_json::from_slice(&mut body)?)
+}
+
+/// Sends an RPC to a peer and its response back to the node
+fn send(config: Arc<Config>, tx: Sender<Msg>, peer: NodeId, rpc: Rpc) {
+    task::spawn(async move {
+        let url = if let Some(url) = config.nodes.get(&peer) {
+            url
+        } else {
+            return;
+        };
+        let response = match rpc {
+            Rpc::Vote(request) => timeout(RPC_TIMEOUT, post(&config, url, "vote", &request))
+                .await
+                .map(|r| r.map(|r| Msg::VoteResponse(peer, r))),
+            Rpc::Append(request) => timeout(RPC_TIMEOUT, post(&config, url, "append", &request))
+                .await
+                .map(|r| r.map(|r| Msg::AppendResponse(peer, r))),
+            Rpc::Snapshot(request) => {
+                timeout(RPC_TIMEOUT, post(&config, url, "snapshot", &request))
+                    .await
+                    .map(|r| r.map(|r| Msg::AppendResponse(peer, r)))
+            }
+        };
+        match response {
+            Ok(Ok(msg)) => {
+                if tx.send(msg).await.is_err() {
+                    debug!("[Cluster::{}] Node stopped", config.id);
+                }
+            }
+            // unreachable peers are common while nodes restart
+            Ok(Err(e)) => debug!("[Cluster::{}] RPC to {} failed: {}", config.id, peer, e),
+            Err(_) => debug!("[Cluster::{}] RPC to {} timed out", config.id, peer),
+        }
+    });
+}
+
+async fn tick(tx: Sender<Msg>) {
+    while tx.send(Msg::Tick).await.is_ok() {
+        task::sleep(TICK).await;
+    }
+}
+
+impl Cluster {
+    /// Starts the node, committed commands and snapshots are sent to the
+    /// returned receiver in order and commands have to be reported as applied
+    ///
+    /// # Errors
+    ///  * if the node isn't part of the cluster or its storage can't be read
+    pub fn start(config: Config) -> Result<(Self, Receiver<Committed>)> {
+        if !config.nodes.contains_key(&config.id) {
+            return Err(format!("Node {} is not one of the cluster nodes", config.id).into());
+        }
+        let (mut storage, persisted) = Storage::open(&config.dir)?;
+        let peers = config
+            .nodes
+            .keys()
+            .copied()
+            .filter(|n| *n != config.id)
+            .collect();
+        let mut raft = Raft::new(config.id, peers, persisted);
+        let config = Arc::new(config);
+        let (tx, rx) = bounded(crate::QSIZE);
+        // unbounded so the node never waits for commands to be applied
+        let (committed_tx, committed_rx) = unbounded();
+        CLUSTERED.store(true, Ordering::Release);
+
+        let node_tx = tx.clone();
+        let node_config = config.clone();
+        task::spawn(async move {
+            let config = node_config;
+            info!("[Cluster::{}] Node started", config.id);
+            let mut waiters: HashMap<u64, (u64, Sender<Result<Submitted>>)> = HashMap::new();
+            while let Ok(msg) = rx.recv().await {
+                match msg {
+                    Msg::Tick => raft.tick(),
+                    Msg::Vote(request, reply) => {
+                        let response = raft.handle_vote(&request);
+                        // the vote has to be persisted before it is cast
+                        if let Err(e) = storage.persist(&mut raft).await {
+                            error!("[Cluster::{}] Failed to persist: {}", config.id, e);
+                            continue;
+                        }
+                        if reply.send(response).await.is_err() {
+                            debug!("[Cluster::{}] Vote requester is gone", config.id);
+                        }
+                    }
+                    Msg::Append(request, reply) => {
+                        let response = raft.handle_append(request);
+                        if let Err(e) = storage.persist(&mut raft).await {
+                            error!("[Cluster::{}] Failed to persist: {}", config.id, e);
+                            continue;
+                        }
+                        if reply.send(response).await.is_err() {
+                            debug!("[Cluster::{}] Append requester is gone", config.id);
+                        }
+                    }
+                    Msg::Snapshot(request, reply) => {
+                        let response = raft.handle_snapshot(request);
+                        if let Err(e) = storage.persist(&mut raft).await {
+                            error!("[Cluster::{}] Failed to persist: {}", config.id, e);
+                            continue;
+                        }
+                        if reply.send(response).await.is_err() {
+                            debug!("[Cluster::{}] Snapshot requester is gone", config.id);
+                        }
+                    }
+                    Msg::VoteResponse(peer, response) => raft.handle_vote_response(peer, &response),
+                    Msg::AppendResponse(peer, response) => {
+                        raft.handle_append_response(peer, &response);
+                    }
+                    Msg::Submit(command, reply) => {
+                        let submitted = match raft.propose(command) {
+                            Some((index, term)) => {
+                                waiters.insert(index, (term, reply));
+                                None
+                            }
+                            None => Some(match raft.leader.and_then(|l| config.nodes.get(&l)) {
+                                Some(url) => Ok(Submitted::Forward(url.clone())),
+                                None => {
+                                    Err(ErrorKind::ClusterUnavailable("no leader".into()).into())
+                                }
+                            }),
+                        };
+                        if let Some(submitted) = submitted {
+                            if reply.send(submitted).await.is_err() {
+                                debug!("[Cluster::{}] Submitter is gone", config.id);
+                            }
+                        }
+                    }
+                    Msg::Applied(index, term, applied, snapshot) => {
+                        if let Some(snapshot) = snapshot {
+                            raft.snapshot_taken(index, term, snapshot);
+                        }
+                        if let Some((waiting_term, reply)) = waiters.remove(&index) {
+                            let submitted = if waiting_term == term {
+                                Submitted::Applied(applied)
+                            } else {
+                                Submitted::Lost
+                            };
+                            if reply.send(Ok(submitted)).await.is_err() {
+                                debug!("[Cluster::{}] Submitter is gone", config.id);
+                            }
+                        }
+                    }
+                    Msg::Status(reply) => {
+                        let status = Status {
+                            id: config.id,
+                            role: raft.role(),
+                            term: raft.state.term,
+                            leader: raft.leader,
+                            commit: raft.commit,
+                            last_index: raft.last_index(),
+                            snapshot: raft.snapshot.index,
+                            lease: raft.has_lease(),
+                            nodes: config.nodes.clone(),
+                        };
+                        if reply.send(status).await.is_err() {
+                            debug!("[Cluster::{}] Status requester is gone", config.id);
+                        }
+                    }
+                }
+                if let Err(e) = storage.persist(&mut raft).await {
+                    error!("[Cluster::{}] Failed to persist: {}", config.id, e);
+                }
+                let lease = raft.has_lease();
+                if LEASE.swap(lease, Ordering::AcqRel) != lease {
+                    if lease {
+                        info!("[Cluster::{}] Acquired the lease", config.id);
+                    } else {
+                        info!("[Cluster::{}] Lost the lease", config.id);
+                    }
+                }
+                for (peer, rpc) in raft.outbox.drain(..) {
+                    send(config.clone(), node_tx.clone(), peer, rpc);
+                }
+                for committed in raft.take_committed() {
+                    if committed_tx.send(committed).await.is_err() {
+                        error!("[Cluster::{}] Nothing applies the commands", config.id);
+                    }
+                }
+            }
+            LEASE.store(false, Ordering::Release);
+            info!("[Cluster::{}] Node stopped", config.id);
+        });
+        task::spawn(tick(tx.clone()));
+        Ok((Self { tx, config }, committed_rx))
+    }
+
+    /// Id of the node
+    #[must_use]
+    pub fn id(&self) -> NodeId {
+        self.config.id
+    }
+
+    /// Handles a vote request of a candidate
+    ///
+    /// # Errors
+    ///  * if the node is stopped
+    pub async fn vote(&self, request: VoteRequest) -> Result<VoteResponse> {
+        let (tx, rx) = bounded(1);
+        self.tx.send(Msg::Vote(request, tx)).await?;
+        Ok(rx.recv().await?)
+    }
+
+    /// Handles an append request of the leader
+    ///
+    /// # Errors
+    ///  * if the node is stopped
+    pub async fn append(&self, request: AppendRequest) -> Result<AppendResponse> {
+        let (tx, rx) = bounded(1);
+        self.tx.send(Msg::Append(request, tx)).await?;
+        Ok(rx.recv().await?)
+    }
+
+    /// Handles a snapshot request of the leader
+    ///
+    /// # Errors
+    ///  * if the node is stopped
+    pub async fn snapshot(&self, request: SnapshotRequest) -> Result<AppendResponse> {
+        let (tx, rx) = bounded(1);
+        self.tx.send(Msg::Snapshot(request, tx)).await?;
+        Ok(rx.recv().await?)
+    }
+
+    /// Submits a command to the cluster and waits until it is applied,
+    /// returning the response of the node that applied it
+    ///
+    /// # Errors
+    ///  * if the cluster has no leader or the command isn't applied in time
+    pub async fn submit(&self, command: Command) -> Result<Applied> {
+        let (tx, rx) = bounded(1);
+        self.tx.send(Msg::Submit(command.clone(), tx)).await?;
+        let submitted = timeout(SUBMIT_TIMEOUT, rx.recv()).await.map_err(|_| {
+            ErrorKind::ClusterUnavailable("timed out applying the change".into())
+        })???;
+        match submitted {
+            Submitted::Applied(applied) => Ok(applied),
+            Submitted::Forward(leader) => timeout(
+                SUBMIT_TIMEOUT,
+                post(&self.config, &leader, "submit", &command),
+            )
+            .await
+            .map_err(|_| {
+                ErrorKind::ClusterUnavailable("timed out forwarding to the leader".into())
+            })?,
+            Submitted::Lost => Err(ErrorKind::ClusterUnavailable(
+                "the leader changed, retry the change".into(),
+            )
+            .into()),
+        }
+    }
+
+    /// Reports a committed command as applied, with the snapshot of the
+    /// state after it if one was taken
+    ///
+    /// # Errors
+    ///  * if the node is stopped
+    pub async fn applied(
+        &self,
+        index: u64,
+        term: u64,
+        applied: Applied,
+        snapshot: Option<Vec<u8>>,
+    ) -> Result<()> {
+        Ok(self
+            .tx
+            .send(Msg::Applied(index, term, applied, snapshot))
+            .await?)
+    }
+
+    /// State of the node
+    ///
+    /// # Errors
+    ///  * if the node is stopped
+    pub async fn status(&self) -> Result<Status> {
+        let (tx, rx) = bounded(1);
+        self.tx.send(Msg::Status(tx)).await?;
+        Ok(rx.recv().await?)
+    }
+}
@@ -0,0 +1,1000 @@
+// Copyright 2020-2021, The Tremor Team
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+//! The raft consensus protocol. It is driven by ticks and messages and
+//! collects the RPCs to send in an outbox, so it doesn't do any IO itself.
+//!
+//! The applied commands are snapshotted, once a snapshot covers enough
+//! entries the log is compacted up to it. Nodes that fall behind the
+//! compacted log of the leader are sent its snapshot instead of entries.
+
+use super::{Command, Committed, NodeId};
+use hashbrown::{HashMap, HashSet};
+use rand::Rng;
+
+/// Ticks between heartbeats of the leader
+pub(crate) const HEARTBEAT_TICKS: u32 = 2;
+/// Minimum ticks without a leader before a follower starts an election,
+/// the timeout is randomized between this and twice as many ticks
+pub(crate) const ELECTION_TICKS: u32 = 6;
+/// Ticks a leader holds its lease after it last heard from a majority, half
+/// the election timeout to leave room for the latency of the responses. A
+/// new leader waits as long before it takes the lease, so the lease of its
+/// predecessor has expired by then.
+const LEASE_TICKS: u32 = ELECTION_TICKS / 2;
+/// Maximum number of entries in a single append request
+const MAX_ENTRIES: usize = 64;
+/// Entries covered by the snapshot before the log is compacted, nodes that
+/// fall behind by fewer entries catch up from the log
+const COMPACT_ENTRIES: u64 = 1024;
+
+/// An entry of the replicated log, leaders start their term with an entry
+/// without a command
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub struct Entry {
+    /// term the entry was created in
+    pub term: u64,
+    /// the command, `None` for the entry starting a term
+    pub command: Option<Command>,
+}
+
+/// State that has to survive restarts
+#[derive(Clone, Debug, Default, PartialEq, Serialize, Deserialize)]
+pub(crate) struct HardState {
+    pub(crate) term: u64,
+    pub(crate) voted_for: Option<NodeId>,
+}
+
+/// The last entry removed from the log by compacting it
+#[derive(Clone, Copy, Debug, Default, PartialEq, Serialize, Deserialize)]
+pub(crate) struct Compacted {
+    pub(crate) index: u64,
+    pub(crate) term: u64,
+}
+
+/// The state after applying the commands up to an index
+#[derive(Clone, Debug, Default, PartialEq, Serialize, Deserialize)]
+pub struct Snapshot {
+    /// index of the last entry it covers
+    pub index: u64,
+    /// term of the last entry it covers
+    pub term: u64,
+    /// the state, as produced by whatever applies the commands
+    pub data: Vec<u8>,
+}
+
+/// What a node persisted before it (re)started
+#[derive(Debug, Default)]
+pub(crate) struct Persisted {
+    pub(crate) state: HardState,
+    pub(crate) compacted: Compacted,
+    pub(crate) snapshot: Snapshot,
+    /// the entries after the compacted ones
+    pub(crate) log: Vec<Entry>,
+}
+
+/// Request of a candidate for the vote of a node
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub struct VoteRequest {
+    /// term of the candidate
+    pub term: u64,
+    /// the candidate
+    pub candidate: NodeId,
+    /// index of the last entry in the log of the candidate
+    pub last_log_index: u64,
+    /// term of the last entry in the log of the candidate
+    pub last_log_term: u64,
+}
+
+/// Answer to a vote request
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub struct VoteResponse {
+    /// term of the node, for the candidate to update itself
+    pub term: u64,
+    /// if the node voted for the candidate
+    pub granted: bool,
+}
+
+/// Request of a leader to append entries to the log of a node, doubles as heartbeat
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub struct AppendRequest {
+    /// term of the leader
+    pub term: u64,
+    /// the leader
+    pub leader: NodeId,
+    /// index of the entry preceding the new ones
+    pub prev_log_index: u64,
+    /// term of the entry preceding the new ones
+    pub prev_log_term: u64,
+    /// the new entries
+    pub entries: Vec<Entry>,
+    /// commit index of the leader
+    pub leader_commit: u64,
+}
+
+/// Answer to an append or snapshot request
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub struct AppendResponse {
+    /// term of the node, for the leader to update itself
+    pub term: u64,
+    /// if the log of the node matched the one of the leader
+    pub success: bool,
+    /// on success the index up to which the logs match, otherwise the index
+    /// the leader should retry after
+    pub match_index: u64,
+}
+
+/// Request of a leader to install its snapshot on a node that is behind
+/// its compacted log
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub struct SnapshotRequest {
+    /// term of the leader
+    pub term: u64,
+    /// the leader
+    pub leader: NodeId,
+    /// the snapshot of the leader
+    pub snapshot: Snapshot,
+}
+
+/// An RPC to send to another node
+#[derive(Clone, Debug, PartialEq)]
+pub(crate) enum Rpc {
+    Vote(VoteRequest),
+    Append(AppendRequest),
+    Snapshot(SnapshotRequest),
+}
+
+#[derive(Debug)]
+enum Role {
+    Follower,
+    Candidate {
+        votes: HashSet<NodeId>,
+    },
+    Leader {
+        /// index of the next entry to send to each peer
+        next: HashMap<NodeId, u64>,
+        /// index up to which the log of each peer is known to match
+        matched: HashMap<NodeId, u64>,
+        /// tick the node became leader at
+        elected: u64,
+        /// tick of the last response of each peer in the current term
+        contacted: HashMap<NodeId, u64>,
+    },
+}
+
+/// Role of a node
+#[derive(Clone, Copy, Debug, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum RoleKind {
+    /// follows a leader
+    Follower,
+    /// runs for leader
+    Candidate,
+    /// replicates its log to the other nodes
+    Leader,
+}
+
+#[derive(Debug)]
+pub(crate) struct Raft {
+    id: NodeId,
+    peers: Vec<NodeId>,
+    pub(crate) state: HardState,
+    pub(crate) compacted: Compacted,
+    /// the entries after the compacted ones
+    pub(crate) log: Vec<Entry>,
+    pub(crate) snapshot: Snapshot,
+    pub(crate) commit: u64,
+    applied: u64,
+    /// if the snapshot has to be restored before applying further entries
+    restore: bool,
+    role: Role,
+    pub(crate) leader: Option<NodeId>,
+    /// ticks since the last message of the leader, the start of an
+    /// election or the last heartbeat
+    elapsed: u32,
+    timeout: u32,
+    /// ticks since the node started
+    clock: u64,
+    pub(crate) outbox: Vec<(NodeId, Rpc)>,
+    /// if the hard state changed since it was persisted
+    pub(crate) state_changed: bool,
+    /// if the snapshot changed since it was persisted
+    pub(crate) snapshot_changed: bool,
+    /// lowest index of the log changed since it was persisted
+    pub(crate) log_changed_from: Option<u64>,
+}
+
+fn election_timeout() -> u32 {
+    rand::thread_rng().gen_range(ELECTION_TICKS..ELECTION_TICKS * 2)
+}
+
+impl Raft {
+    /// A node continuing from what it persisted, the commands its snapshot
+    /// covers are already applied once the snapshot is restored
+    pub(crate) fn new(id: NodeId, peers: Vec<NodeId>, persisted: Persisted) -> Self {
+        let Persisted {
+            state,
+            compacted,
+            snapshot,
+            log,
+        } = persisted;
+        let mut raft = Self {
+            id,
+            peers,
+            state,
+            compacted,
+            log,
+            commit: snapshot.index,
+            applied: snapshot.index,
+            restore: snapshot.index > 0,
+            snapshot,
+            role: Role::Follower,
+            leader: None,
+            elapsed: 0,
+            timeout: election_timeout(),
+            clock: 0,
+            outbox: Vec::new(),
+            state_changed: false,
+            snapshot_changed: false,
+            log_changed_from: None,
+        };
+        let index = raft.snapshot.index;
+        if index > raft.last_index() || raft.term_at(index) != raft.snapshot.term {
+            // a snapshot of the leader replaced the log, but the node
+            // stopped before the log was persisted
+            raft.log.clear();
+            raft.compacted = Compacted {
+                index,
+                term: raft.snapshot.term,
+            };
+        }
+        raft
+    }
+
+    pub(crate) fn role(&self) -> RoleKind {
+        match self.role {
+            Role::Follower => RoleKind::Follower,
+            Role::Candidate { .. } => RoleKind::Candidate,
+            Role::Leader { .. } => RoleKind::Leader,
+        }
+    }
+
+    /// If the node is the leader and a majority of the nodes recently
+    /// confirmed it, only one node of the cluster holds the lease at a time
+    pub(crate) fn has_lease(&self) -> bool {
+        if let Role::Leader {
+            elected, contacted, ..
+        } = &self.role
+        {
+            let lease = u64::from(LEASE_TICKS);
+            let recent = self.clock.saturating_sub(lease);
+            let confirmed = 1 + contacted.values().filter(|t| **t >= recent).count();
+            self.clock >= elected + lease && confirmed >= self.quorum()
+        } else {
+            false
+        }
+    }
+
+    pub(crate) fn last_index(&self) -> u64 {
+        self.compacted.index + self.log.len() as u64
+    }
+
+    fn entry(&self, index: u64) -> Option<&Entry> {
+        index
+            .checked_sub(self.compacted.index + 1)
+            .and_then(|i| self.log.get(usize::try_from(i).ok()?))
+    }
+
+    /// Term of the entry at `index`, 0 if the entry isn't known
+    fn term_at(&self, index: u64) -> u64 {
+        if index == self.compacted.index {
+            self.compacted.term
+        } else {
+            self.entry(index).map_or(0, |e| e.term)
+        }
+    }
+
+    fn quorum(&self) -> usize {
+        (self.peers.len() + 1) / 2 + 1
+    }
+
+    fn push(&mut self, entry: Entry) {
+        self.log.push(entry);
+        let index = self.last_index();
+        self.log_changed_from = Some(self.log_changed_from.map_or(index, |i| i.min(index)));
+    }
+
+    fn truncate(&mut self, index: u64) {
+        // entries from `index` on are removed
+        let len = index.saturating_sub(self.compacted.index + 1);
+        self.log
+            .truncate(usize::try_from(len).unwrap_or(usize::MAX));
+        self.log_changed_from = Some(self.log_changed_from.map_or(index, |i| i.min(index)));
+    }
+
+    /// Removes the entries up to `index` from the log, they have to be
+    /// covered by the snapshot
+    fn compact(&mut self, index: u64, term: u64) {
+        let removed = index.saturating_sub(self.compacted.index);
+        let removed = usize::try_from(removed).unwrap_or(usize::MAX);
+        self.log.drain(..removed.min(self.log.len()));
+        self.compacted = Compacted { index, term };
+    }
+
+    fn step_down(&mut self, term: u64) {
+        if term > self.state.term {
+            self.state.term = term;
+            self.state.voted_for = None;
+            self.state_changed = true;
+            self.leader = None;
+        }
+        if !matches!(self.role, Role::Follower) {
+            info!(
+                "[Cluster::{}] Following in term {}",
+                self.id, self.state.term
+            );
+        }
+        self.role = Role::Follower;
+        self.elapsed = 0;
+    }
+
+    /// Advances the clock by one tick
+    pub(crate) fn tick(&mut self) {
+        self.elapsed += 1;
+        self.clock += 1;
+        if matches!(self.role, Role::Leader { .. }) {
+            if self.elapsed >= HEARTBEAT_TICKS {
+                self.elapsed = 0;
+                self.broadcast_append();
+            }
+        } else if self.elapsed >= self.timeout {
+            self.start_election();
+        }
+    }
+
+    fn start_election(&mut self) {
+        self.state.term += 1;
+        self.state.voted_for = Some(self.id);
+        self.state_changed = true;
+        self.leader = None;
+        self.elapsed = 0;
+        self.timeout = election_timeout();
+        info!(
+            "[Cluster::{}] Starting election for term {}",
+            self.id, self.state.term
+        );
+        let mut votes = HashSet::new();
+        votes.insert(self.id);
+        self.role = Role::Candidate { votes };
+        if self.quorum() <= 1 {
+            self.become_leader();
+            return;
+        }
+        let request = VoteRequest {
+            term: self.state.term,
+            candidate: self.id,
+            last_log_index: self.last_index(),
+            last_log_term: self.term_at(self.last_index()),
+        };
+        for peer in &self.peers {
+            self.outbox.push((*peer, Rpc::Vote(request.clone())));
+        }
+    }
+
+    fn become_leader(&mut self) {
+        info!("[Cluster::{}] Leading in term {}", self.id, self.state.term);
+        let next_index = self.last_index() + 1;
+        self.role = Role::Leader {
+            next: self.peers.iter().map(|p| (*p, next_index)).collect(),
+            matched: self.peers.iter().map(|p| (*p, 0)).collect(),
+            elected: self.clock,
+            contacted: HashMap::new(),
+        };
+        self.leader = Some(self.id);
+        // entries of earlier terms only commit along with one of the current term
+        self.push(Entry {
+            term: self.state.term,
+            command: None,
+        });
+        self.advance_commit();
+        self.elapsed = 0;
+        self.broadcast_append();
+    }
+
+    /// The request to send the entries from `next_index` on, which has to
+    /// be after the compacted ones
+    fn append_request(&self, next_index: u64) -> AppendRequest {
+        let prev_log_index = next_index.saturating_sub(1);
+        let entries = usize::try_from(prev_log_index.saturating_sub(self.compacted.index))
+            .ok()
+            .and_then(|start| self.log.get(start..))
+            .unwrap_or_default();
+        AppendRequest {
+            term: self.state.term,
+            leader: self.id,
+            prev_log_index,
+            prev_log_term: self.term_at(prev_log_index),
+            entries: entries.iter().take(MAX_ENTRIES).cloned().collect(),
+            leader_commit: self.commit,
+        }
+    }
+
+    fn send_append(&mut self, peer: NodeId) {
+        if let Role::Leader { next, .. } = &self.role {
+            let next_index = next.get(&peer).copied().unwrap_or(1);
+            let rpc = if next_index <= self.compacted.index {
+                // the peer is behind the compacted log
+                Rpc::Snapshot(SnapshotRequest {
+                    term: self.state.term,
+                    leader: self.id,
+                    snapshot: self.snapshot.clone(),
+                })
+            } else {
+                Rpc::Append(self.append_request(next_index))
+            };
+            self.outbox.push((peer, rpc));
+        }
+    }
+
+    fn broadcast_append(&mut self) {
+        for peer in self.peers.clone() {
+            self.send_append(peer);
+        }
+    }
+
+    fn advance_commit(&mut self) {
+        if let Role::Leader { matched, .. } = &self.role {
+            let mut index = self.last_index();
+            while index > self.commit && self.term_at(index) == self.state.term {
+                let replicas = 1 + matched.values().filter(|m| **m >= index).count();
+                if replicas >= self.quorum() {
+                    self.commit = index;
+                    break;
+                }
+                index -= 1;
+            }
+        }
+    }
+
+    pub(crate) fn handle_vote(&mut self, request: &VoteRequest) -> VoteResponse {
+        if request.term > self.state.term {
+            self.step_down(request.term);
+        }
+        let up_to_date = (request.last_log_term, request.last_log_index)
+            >= (self.term_at(self.last_index()), self.last_index());
+        let granted = request.term == self.state.term
+            && self
+                .state
+                .voted_for
+                .map_or(true, |v| v == request.candidate)
+            && up_to_date;
+        if granted {
+            self.state.voted_for = Some(request.candidate);
+            self.state_changed = true;
+            self.elapsed = 0;
+        }
+        VoteResponse {
+            term: self.state.term,
+            granted,
+        }
+    }
+
+    pub(crate) fn handle_vote_response(&mut self, peer: NodeId, response: &VoteResponse) {
+        if response.term > self.state.term {
+            self.step_down(response.term);
+            return;
+        }
+        let quorum = self.quorum();
+        if let Role::Candidate { votes } = &mut self.role {
+            if response.term == self.state.term && response.granted {
+                votes.insert(peer);
+                if votes.len() >= quorum {
+                    self.become_leader();
+                }
+            }
+        }
+    }
+
+    /// Follows the leader of a request, `None` if the request is of an
+    /// earlier term and rejected
+    fn follow(&mut self, term: u64, leader: NodeId) -> Option<()> {
+        if term < self.state.term {
+            return None;
+        }
+        if term > self.state.term || !matches!(self.role, Role::Follower) {
+            self.step_down(term);
+        }
+        self.leader = Some(leader);
+        self.elapsed = 0;
+        Some(())
+    }
+
+    pub(crate) fn handle_append(&mut self, request: AppendRequest) -> AppendResponse {
+        if self.follow(request.term, request.leader).is_none() {
+            return AppendResponse {
+                term: self.state.term,
+                success: false,
+                match_index: 0,
+            };
+        }
+
+        let prev = request.prev_log_index;
+        // compacted entries are committed, so they match the ones of the leader
+        let matches = prev < self.compacted.index
+            || (prev <= self.last_index() && self.term_at(prev) == request.prev_log_term);
+        if !matches {
+            return AppendResponse {
+                term: self.state.term,
+                success: false,
+                match_index: prev.saturating_sub(1).min(self.last_index()),
+            };
+        }
+        let mut index = prev;
+        for entry in request.entries {
+            index += 1;
+            if index <= self.compacted.index {
+                continue;
+            }
+            if index <= self.last_index() {
+                if self.term_at(index) == entry.term {
+                    continue;
+                }
+                self.truncate(index);
+            }
+            self.push(entry);
+        }
+        if request.leader_commit > self.commit {
+            self.commit = request.leader_commit.min(index).max(self.commit);
+        }
+        AppendResponse {
+            term: self.state.term,
+            success: true,
+            match_index: index,
+        }
+    }
+
+    /// Installs the snapshot of the leader, unless the node already
+    /// committed the entries it covers
+    pub(crate) fn handle_snapshot(&mut self, request: SnapshotRequest) -> AppendResponse {
+        if self.follow(request.term, request.leader).is_none() {
+            return AppendResponse {
+                term: self.state.term,
+                success: false,
+                match_index: 0,
+            };
+        }
+        let snapshot = request.snapshot;
+        let index = snapshot.index;
+        if index > self.commit {
+            info!(
+                "[Cluster::{}] Installing the snapshot up to {}",
+                self.id, index
+            );
+            if index <= self.last_index() && self.term_at(index) == snapshot.term {
+                // the entries following the snapshot are kept
+                self.compact(index, snapshot.term);
+            } else {
+                self.log.clear();
+                self.compacted = Compacted {
+                    index,
+                    term: snapshot.term,
+                };
+            }
+            self.commit = index;
+            self.applied = index;
+            self.restore = true;
+            self.snapshot = snapshot;
+            self.snapshot_changed = true;
+        }
+        AppendResponse {
+            term: self.state.term,
+            success: true,
+            match_index: index,
+        }
+    }
+
+    pub(crate) fn handle_append_response(&mut self, peer: NodeId, response: &AppendResponse) {
+        if response.term > self.state.term {
+            self.step_down(response.term);
+            return;
+        }
+        if response.term < self.state.term {
+            return;
+        }
+        let clock = self.clock;
+        let retry = if let Role::Leader {
+            next,
+            matched,
+            contacted,
+            ..
+        } = &mut self.role
+        {
+            // any response of the current term confirms the leadership
+            contacted.insert(peer, clock);
+            if response.success {
+                let peer_match = matched.entry(peer).or_insert(0);
+                *peer_match = (*peer_match).max(response.match_index);
+                next.insert(peer, *peer_match + 1);
+                false
+            } else {
+                let peer_next = next.entry(peer).or_insert(1);
+                *peer_next = peer_next
+                    .saturating_sub(1)
+                    .min(response.match_index + 1)
+                    .max(1);
+                true
+            }
+        } else {
+            false
+        };
+        if retry {
+            self.send_append(peer);
+        } else {
+            self.advance_commit();
+        }
+    }
+
+    /// Appends a command to the log if this node is the leader, returning
+    /// its index and term
+    pub(crate) fn propose(&mut self, command: Command) -> Option<(u64, u64)> {
+        if !matches!(self.role, Role::Leader { .. }) {
+            return None;
+        }
+        self.push(Entry {
+            term: self.state.term,
+            command: Some(command),
+        });
+        self.advance_commit();
+        self.broadcast_append();
+        Some((self.last_index(), self.state.term))
+    }
+
+    /// What has to be applied since it was last taken, the snapshot to
+    /// restore if it replaced the state and the committed commands
+    pub(crate) fn take_committed(&mut self) -> Vec<Committed> {
+        let mut committed = Vec::new();
+        if self.restore {
+            self.restore = false;
+            committed.push(Committed::Snapshot(self.snapshot.data.clone()));
+        }
+        while self.applied < self.commit {
+            self.applied += 1;
+            if let Some(Entry {
+                term,
+                command: Some(command),
+            }) = self.entry(self.applied)
+            {
+                committed.push(Committed::Command {
+                    index: self.applied,
+                    term: *term,
+                    command: command.clone(),
+                });
+            }
+        }
+        committed
+    }
+
+    /// Replaces the snapshot with the state after applying the entry at
+    /// `index`, compacting the log once the snapshot covers enough entries
+    pub(crate) fn snapshot_taken(&mut self, index: u64, term: u64, data: Vec<u8>) {
+        // a snapshot of the leader may have replaced the applied state since
+        if index <= self.snapshot.index || index > self.applied {
+            return;
+        }
+        self.snapshot = Snapshot { index, term, data };
+        self.snapshot_changed = true;
+        if index - self.compacted.index > COMPACT_ENTRIES {
+            self.compact(index, term);
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::errors::Result;
+
+    fn command(path: &str) -> Command {
+        Command {
+            method: "POST".to_string(),
+            path: path.to_string(),
+            content_type: None,
+            accept: None,
+            body: Vec::new(),
+        }
+    }
+
+    fn node(nodes: &mut [Raft], id: NodeId) -> Option<&mut Raft> {
+        nodes.iter_mut().find(|n| n.id == id)
+    }
+
+    /// Delivers all RPCs until the outboxes are empty
+    fn deliver(nodes: &mut [Raft], down: &[NodeId]) {
+        loop {
+            let mut rpcs = Vec::new();
+            for node in nodes.iter_mut() {
+                let from = node.id;
+                rpcs.extend(node.outbox.drain(..).map(|(to, rpc)| (from, to, rpc)));
+            }
+            if rpcs.is_empty() {
+                return;
+            }
+            for (from, to, rpc) in rpcs {
+                if down.contains(&from) || down.contains(&to) {
+                    continue;
+                }
+                match rpc {
+                    Rpc::Vote(request) => {
+                        if let Some(response) = node(nodes, to).map(|n| n.handle_vote(&request)) {
+                            if let Some(n) = node(nodes, from) {
+                                n.handle_vote_response(to, &response);
+                            }
+                        }
+                    }
+                    Rpc::Append(request) => {
+                        if let Some(response) = node(nodes, to).map(|n| n.handle_append(request)) {
+                            if let Some(n) = node(nodes, from) {
+                                n.handle_append_response(to, &response);
+                            }
+                        }
+                    }
+                    Rpc::Snapshot(request) => {
+                        if let Some(response) = node(nodes, to).map(|n| n.handle_snapshot(request))
+                        {
+                            if let Some(n) = node(nodes, from) {
+                                n.handle_append_response(to, &response);
+                            }
+                        }
+                    }
+                }
+            }
+        }
+    }
+
+    fn cluster() -> Vec<Raft> {
+        (1..=3)
+            .map(|id| {
+                let peers = (1..=3).filter(|p| *p != id).collect();
+                Raft::new(id, peers, Persisted::default())
+            })
+            .collect()
+    }
+
+    fn run(nodes: &mut [Raft], ticks: u32, down: &[NodeId]) {
+        for _ in 0..ticks {
+            for node in nodes.iter_mut() {
+                if !down.contains(&node.id) {
+                    node.tick();
+                }
+            }
+            deliver(nodes, down);
+        }
+    }
+
+    fn leader(nodes: &[Raft], down: &[NodeId]) -> Option<NodeId> {
+        let leaders: Vec<_> = nodes
+            .iter()
+            .filter(|n| !down.contains(&n.id) && n.role() == RoleKind::Leader)
+            .map(|n| n.id)
+            .collect();
+        match leaders.as_slice() {
+            [leader] => Some(*leader),
+            _ => None,
+        }
+    }
+
+    /// Takes the committed commands and snapshots the state after them
+    fn apply(node: &mut Raft) -> Vec<Committed> {
+        let committed = node.take_committed();
+        if let Some(Committed::Command { index, term, .. }) = committed.last() {
+            node.snapshot_taken(*index, *term, index.to_string().into_bytes());
+        }
+        committed
+    }
+
+    #[test]
+    fn elects_and_replicates() -> Result<()> {
+        let mut nodes = cluster();
+        run(&mut nodes, ELECTION_TICKS * 10, &[]);
+        let first = leader(&nodes, &[]).ok_or("no leader")?;
+        let leading = node(&mut nodes, first).ok_or("no leader")?;
+        let (index, _) = leading.propose(command("/pipeline")).ok_or("not leading")?;
+        run(&mut nodes, HEARTBEAT_TICKS * 2, &[]);
+        for n in &mut nodes {
+            assert!(n.commit >= index);
+            let committed = n.take_committed();
+            assert!(committed.iter().any(
+                |c| matches!(c, Committed::Command { command: c, .. } if *c == command("/pipeline"))
+            ));
+        }
+
+        // the remaining nodes elect a new leader with the full log
+        run(&mut nodes, ELECTION_TICKS * 10, &[first]);
+        let second = leader(&nodes, &[first]).ok_or("no new leader")?;
+        assert_ne!(first, second);
+        let leading = node(&mut nodes, second).ok_or("no leader")?;
+        assert!(leading.propose(command("/binding")).is_some());
+        run(&mut nodes, HEARTBEAT_TICKS * 2, &[first]);
+
+        // the old leader catches up once it is back
+        run(&mut nodes, ELECTION_TICKS * 10, &[]);
+        for n in &nodes {
+            assert_eq!(nodes.first().map(|first| &first.log), Some(&n.log));
+        }
+        Ok(())
+    }
+
+    #[test]
+    fn single_lease_holder() -> Result<()> {
+        let mut nodes = cluster();
+        run(&mut nodes, ELECTION_TICKS * 10, &[]);
+        let first = leader(&nodes, &[]).ok_or("no leader")?;
+        let holders = |nodes: &[Raft]| -> Vec<NodeId> {
+            nodes
+                .iter()
+                .filter(|n| n.has_lease())
+                .map(|n| n.id)
+                .collect()
+        };
+        assert_eq!(vec![first], holders(&nodes));
+
+        // the leader is cut off, it still leads but loses the lease before
+        // the others elect a new leader that takes it
+        for _ in 0..ELECTION_TICKS * 10 {
+            for node in nodes.iter_mut() {
+                node.tick();
+            }
+            deliver(&mut nodes, &[first]);
+            assert!(holders(&nodes).len() <= 1);
+        }
+        let second = leader(&nodes, &[first]).ok_or("no new leader")?;
+        assert_ne!(first, second);
+        assert_eq!(vec![second], holders(&nodes));
+        Ok(())
+    }
+
+    #[test]
+    fn conflicting_entries_are_replaced() {
+        let mut node = Raft::new(2, vec![1, 3], Persisted::default());
+        let entry = |term, path: &str| Entry {
+            term,
+            command: Some(command(path)),
+        };
+        let response = node.handle_append(AppendRequest {
+            term: 1,
+            leader: 1,
+            prev_log_index: 0,
+            prev_log_term: 0,
+            entries: vec![entry(1, "/a"), entry(1, "/b")],
+            leader_commit: 1,
+        });
+        assert!(response.success);
+        assert_eq!(1, node.commit);
+
+        // a gap is rejected
+        let response = node.handle_append(AppendRequest {
+            term: 2,
+            leader: 3,
+            prev_log_index: 5,
+            prev_log_term: 2,
+            entries: vec![],
+            leader_commit: 1,
+        });
+        assert!(!response.success);
+        assert_eq!(2, response.match_index);
+
+        // as if the log was persisted
+        node.log_changed_from = None;
+        let response = node.handle_append(AppendRequest {
+            term: 2,
+            leader: 3,
+            prev_log_index: 1,
+            prev_log_term: 1,
+            entries: vec![entry(2, "/c")],
+            leader_commit: 2,
+        });
+        assert!(response.success);
+        assert_eq!(vec![entry(1, "/a"), entry(2, "/c")], node.log);
+        assert_eq!(Some(2), node.log_changed_from);
+        assert_eq!(2, node.commit);
+    }
+
+    #[test]
+    fn single_node() {
+        let mut node = Raft::new(1, vec![], Persisted::default());
+        for _ in 0..ELECTION_TICKS * 2 {
+            node.tick();
+        }
+        assert_eq!(RoleKind::Leader, node.role());
+        assert_eq!(Some((2, 1)), node.propose(command("/onramp")));
+        assert_eq!(2, node.commit);
+    }
+
+    #[test]
+    fn compacts_and_installs_snapshots() -> Result<()> {
+        let mut nodes = cluster();
+        run(&mut nodes, ELECTION_TICKS * 10, &[]);
+        let first = leader(&nodes, &[]).ok_or("no leader")?;
+        let behind = nodes
+            .iter()
+            .map(|n| n.id)
+            .find(|id| *id != first)
+            .ok_or("no follower")?;
+        for i in 0..=COMPACT_ENTRIES {
+            let leading = node(&mut nodes, first).ok_or("no leader")?;
+            let path = format!("/pipeline/{}", i);
+            leading.propose(command(&path)).ok_or("not leading")?;
+            deliver(&mut nodes, &[behind]);
+            for n in nodes.iter_mut().filter(|n| n.id != behind) {
+                apply(n);
+            }
+        }
+        let leading = node(&mut nodes, first).ok_or("no leader")?;
+        assert!(leading.compacted.index > COMPACT_ENTRIES);
+        assert!(leading.log.len() < 2);
+        let snapshot = leading.snapshot.clone();
+        assert_eq!(leading.last_index(), snapshot.index);
+
+        // the node that fell behind the compacted log gets the snapshot
+        run(&mut nodes, HEARTBEAT_TICKS * 2, &[]);
+        let behind = node(&mut nodes, behind).ok_or("no follower")?;
+        assert_eq!(snapshot.index, behind.last_index());
+        assert_eq!(snapshot.index, behind.commit);
+        assert_eq!(
+            vec![Committed::Snapshot(snapshot.data)],
+            behind.take_committed()
+        );
+        assert!(behind.snapshot_changed);
+        Ok(())
+    }
+
+    #[test]
+    fn restarts_from_snapshot() {
+        let persisted = || Persisted {
+            state: HardState {
+                term: 3,
+                voted_for: None,
+            },
+            compacted: Compacted::default(),
+            snapshot: Snapshot {
+                index: 2,
+                term: 2,
+                data: b"2".to_vec(),
+            },
+            log: (1..=3)
+                .map(|term| Entry {
+                    term,
+                    command: Some(command("/a")),
+                })
+                .collect(),
+        };
+        // the entries the snapshot covers aren't applied again
+        let mut node = Raft::new(1, vec![2, 3], persisted());
+        assert_eq!(2, node.commit);
+        assert_eq!(3, node.last_index());
+        assert_eq!(
+            vec![Committed::Snapshot(b"2".to_vec())],
+            node.take_committed()
+        );
+
+        // the snapshot of a leader replaced the log, which wasn't persisted
+        let mut stale = persisted();
+        stale.snapshot.index = 5;
+        stale.snapshot.term = 4;
+        let node = Raft::new(1, vec![2, 3], stale);
+        assert!(node.log.is_empty());
+        assert_eq!(Compacted { index: 5, term: 4 }, node.compacted);
+        assert_eq!(5, node.last_index());
+    }
+}
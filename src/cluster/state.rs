@@ -0,0 +1,178 @@
+// Copyright 2020-2021, The Tremor Team
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+//! The state the applied commands built up, as snapshotted by the cluster:
+//! the namespaces, every version of the pipelines and the configuration of
+//! the onramps, offramps, bindings and their mappings, flags and layouts.
+//!
+//! Runtime overrides like metrics overrides, canaries or offramp states
+//! aren't part of it. Restoring a snapshot only adds what the node is
+//! missing, artefacts it already has, e.g. from its configuration files,
+//! are kept as they are.
+
+use crate::config::Config;
+use crate::errors::Result;
+use crate::namespace::{Namespace, Quota};
+use crate::system::World;
+use crate::url::TremorUrl;
+
+/// A namespace with its tokens, which a `Namespace` never serializes
+#[derive(Debug, Serialize, Deserialize)]
+struct NamespaceState {
+    name: String,
+    quota: Quota,
+    tokens: Vec<String>,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+struct PipelineState {
+    id: String,
+    /// sources of all versions, oldest first
+    versions: Vec<String>,
+    /// number of the active version
+    version: usize,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+struct State {
+    namespaces: Vec<NamespaceState>,
+    pipelines: Vec<PipelineState>,
+    config: Config,
+}
+
+/// Captures the state of `world`
+///
+/// # Errors
+///  * if the state can't be read or serialized
+pub async fn capture(world: &World) -> Result<Vec<u8>> {
+    let mut namespaces = Vec::new();
+    for name in world.ns.list().await {
+        if let Some(Namespace {
+            name,
+            quota,
+            tokens,
+            ..
+        }) = world.ns.find(&name).await
+        {
+            namespaces.push(NamespaceState {
+                name,
+                quota,
+                tokens,
+            });
+        }
+    }
+    let mut pipelines = Vec::new();
+    for id in world.repo.list_pipelines().await? {
+        if let Some(wrapper) = world.repo.find_pipeline(&id).await? {
+            if !wrapper.system {
+                pipelines.push(PipelineState {
+                    id: id.to_string(),
+                    versions: wrapper
+                        .versions
+                        .iter()
+                        .map(|v| v.source().to_string())
+                        .collect(),
+                    version: wrapper.version,
+                });
+            }
+        }
+    }
+    let state = State {
+        namespaces,
+        pipelines,
+        config: world.to_config().await?,
+    };
+    Ok(simd_json::to_vec(&state)?)
+}
+
+/// Restores the state captured by `capture` on `world`
+///
+/// # Errors
+///  * if the state is invalid or can't be deployed
+pub async fn restore(world: &World, data: &[u8]) -> Result<()> {
+    let mut data = data.to_vec();
+    let state: State = simd_json::from_slice(&mut data)?;
+    for NamespaceState {
+        name,
+        quota,
+        tokens,
+    } in state.namespaces
+    {
+        if world.ns.find(&name).await.is_none() {
+            world
+                .ns
+                .create(Namespace {
+                    name,
+                    quota,
+                    tokens,
+                })
+                .await?;
+        }
+    }
+    for PipelineState {
+        id,
+        versions,
+        version,
+    } in state.pipelines
+    {
+        let url = TremorUrl::parse(&id)?;
+        if world.repo.find_pipeline(&url).await?.is_some() {
+            continue;
+        }
+        for source in &versions {
+            // the id of the url may be qualified with a namespace, unlike
+            // the one of the query
+            let (_, query) = crate::parse_query(source, &id, &id)?;
+            world.repo.publish_pipeline(&url, false, query).await?;
+        }
+        if version != versions.len() {
+            world.activate_pipeline(&url, version).await?;
+        }
+    }
+
+    let mut config = state.config;
+    let mut onramps = Vec::new();
+    for onramp in config.onramp {
+        let url = TremorUrl::parse(&format!("/onramp/{}", onramp.id))?;
+        if world.repo.find_onramp(&url).await?.is_none() {
+            onramps.push(onramp);
+        }
+    }
+    config.onramp = onramps;
+    let mut offramps = Vec::new();
+    for offramp in config.offramp {
+        let url = TremorUrl::parse(&format!("/offramp/{}", offramp.id))?;
+        if world.repo.find_offramp(&url).await?.is_none() {
+            offramps.push(offramp);
+        }
+    }
+    config.offramp = offramps;
+    let mut bindings = Vec::new();
+    for binding in config.binding {
+        let url = TremorUrl::parse(&format!("/binding/{}", binding.id))?;
+        if world.repo.find_binding(&url).await?.is_none() {
+            bindings.push(binding);
+        }
+    }
+    config.binding = bindings;
+    let mut mapping = crate::MappingMap::new();
+    for (instance, links) in config.mapping {
+        if world.reg.find_binding(&instance).await?.is_none() {
+            mapping.insert(instance, links);
+        }
+    }
+    config.mapping = mapping;
+    crate::publish_config(world, crate::incarnate(config)?).await?;
+    Ok(())
+}
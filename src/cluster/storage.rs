@@ -0,0 +1,206 @@
+// Copyright 2020-2021, The Tremor Team
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+//! Persists the hard state, the snapshot and the log of a node in a
+//! directory, as `state.json`, `snapshot` with the index and term of the
+//! snapshot on its first line followed by its data, and `log.jsonl` with
+//! the last compacted index and term on its first line followed by one
+//! entry per line.
+
+use super::raft::{Compacted, Persisted, Raft, Snapshot};
+use crate::errors::Result;
+use async_std::task;
+use std::fs::{self, File, OpenOptions};
+use std::io::{BufRead, BufReader, Write};
+use std::path::{Path, PathBuf};
+
+const STATE: &str = "state.json";
+const SNAPSHOT: &str = "snapshot";
+const LOG: &str = "log.jsonl";
+
+#[derive(Debug)]
+pub(crate) struct Storage {
+    dir: PathBuf,
+    /// index of the last entry in the log file
+    persisted: u64,
+    /// index of the last compacted entry in the log file
+    compacted: u64,
+}
+
+/// The encoded changes to write
+#[derive(Default)]
+struct Changes {
+    state: Option<Vec<u8>>,
+    snapshot: Option<Vec<u8>>,
+    /// the log, replacing the file or appended to it
+    log: Option<(bool, Vec<u8>)>,
+}
+
+/// Writes a file at once by renaming a temporary one
+fn replace(path: &Path, data: &[u8]) -> Result<()> {
+    let tmp = path.with_extension("tmp");
+    let mut file = File::create(&tmp)?;
+    file.write_all(data)?;
+    file.sync_all()?;
+    fs::rename(tmp, path)?;
+    Ok(())
+}
+
+/// Reads a file, `None` if it doesn't exist
+fn read(path: &Path) -> Result<Option<Vec<u8>>> {
+    match fs::read(path) {
+        Ok(data) => Ok(Some(data)),
+        Err(e) if e.kind() == std::io::ErrorKind::NotFound => Ok(None),
+        Err(e) => Err(e.into()),
+    }
+}
+
+fn line<T: serde::Serialize>(data: &mut Vec<u8>, value: &T) -> Result<()> {
+    data.extend(simd_json::to_vec(value)?);
+    data.push(b'\n');
+    Ok(())
+}
+
+impl Changes {
+    fn is_empty(&self) -> bool {
+        self.state.is_none() && self.snapshot.is_none() && self.log.is_none()
+    }
+
+    /// Writes the changes, the snapshot before the log as the log may have
+    /// been compacted up to it
+    fn write(self, dir: &Path) -> Result<()> {
+        if let Some(state) = self.state {
+            replace(&dir.join(STATE), &state)?;
+        }
+        if let Some(snapshot) = self.snapshot {
+            replace(&dir.join(SNAPSHOT), &snapshot)?;
+        }
+        match self.log {
+            Some((true, log)) => replace(&dir.join(LOG), &log)?,
+            Some((false, entries)) => {
+                let mut file = OpenOptions::new().append(true).open(dir.join(LOG))?;
+                file.write_all(&entries)?;
+                file.sync_all()?;
+            }
+            None => (),
+        }
+        Ok(())
+    }
+}
+
+impl Storage {
+    /// Opens the storage in `dir`, creating it if needed, and reads what
+    /// was persisted
+    pub(crate) fn open(dir: &Path) -> Result<(Self, Persisted)> {
+        fs::create_dir_all(dir)?;
+        let mut persisted = Persisted::default();
+        if let Some(mut data) = read(&dir.join(STATE))? {
+            persisted.state = simd_json::from_slice(&mut data)?;
+        }
+        if let Some(mut data) = read(&dir.join(SNAPSHOT))? {
+            let split = data.iter().position(|b| *b == b'\n').unwrap_or(data.len());
+            let snapshot_data = data.get(split + 1..).unwrap_or_default().to_vec();
+            data.truncate(split);
+            let Compacted { index, term } = simd_json::from_slice(&mut data)?;
+            persisted.snapshot = Snapshot {
+                index,
+                term,
+                data: snapshot_data,
+            };
+        }
+        match File::open(dir.join(LOG)) {
+            Ok(file) => {
+                let mut lines = BufReader::new(file).lines();
+                if let Some(header) = lines.next() {
+                    persisted.compacted = simd_json::from_slice(&mut header?.into_bytes())?;
+                }
+                for entry in lines {
+                    let mut entry = entry?.into_bytes();
+                    if !entry.is_empty() {
+                        persisted.log.push(simd_json::from_slice(&mut entry)?);
+                    }
+                }
+            }
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => {
+                let mut header = Vec::new();
+                line(&mut header, &persisted.compacted)?;
+                replace(&dir.join(LOG), &header)?;
+            }
+            Err(e) => return Err(e.into()),
+        }
+        let storage = Self {
+            dir: dir.to_path_buf(),
+            persisted: persisted.compacted.index + persisted.log.len() as u64,
+            compacted: persisted.compacted.index,
+        };
+        Ok((storage, persisted))
+    }
+
+    /// Persists the changes of the hard state, the snapshot and the log of
+    /// a node, they are only marked as persisted once they are written
+    pub(crate) async fn persist(&mut self, raft: &mut Raft) -> Result<()> {
+        let mut changes = Changes::default();
+        if raft.state_changed {
+            changes.state = Some(simd_json::to_vec(&raft.state)?);
+        }
+        if raft.snapshot_changed {
+            let snapshot = &raft.snapshot;
+            let mut data = Vec::with_capacity(snapshot.data.len() + 32);
+            line(
+                &mut data,
+                &Compacted {
+                    index: snapshot.index,
+                    term: snapshot.term,
+                },
+            )?;
+            data.extend_from_slice(&snapshot.data);
+            changes.snapshot = Some(data);
+        }
+        let replaced = raft
+            .log_changed_from
+            .map_or(false, |from| from <= self.persisted);
+        if replaced || raft.compacted.index != self.compacted {
+            // entries were replaced or compacted, the whole log is rewritten
+            let mut data = Vec::new();
+            line(&mut data, &raft.compacted)?;
+            for entry in &raft.log {
+                line(&mut data, entry)?;
+            }
+            changes.log = Some((true, data));
+        } else if raft.log_changed_from.is_some() {
+            let start = self.persisted.saturating_sub(raft.compacted.index);
+            let start = usize::try_from(start).unwrap_or(usize::MAX);
+            let mut data = Vec::new();
+            for entry in raft.log.get(start..).unwrap_or_default() {
+                line(&mut data, entry)?;
+            }
+            changes.log = Some((false, data));
+        }
+        if changes.is_empty() {
+            return Ok(());
+        }
+
+        let log_written = changes.log.is_some();
+        let dir = self.dir.clone();
+        task::spawn_blocking(move || changes.write(&dir)).await?;
+        raft.state_changed = false;
+        raft.snapshot_changed = false;
+        if log_written {
+            raft.log_changed_from = None;
+            self.persisted = raft.last_index();
+            self.compacted = raft.compacted.index;
+        }
+        Ok(())
+    }
+}
@@ -19,6 +19,7 @@ pub(crate) mod binflux;
 pub(crate) mod csv;
 pub(crate) mod influx;
 pub(crate) mod json;
+pub(crate) mod layout;
 pub(crate) mod msgpack;
 pub(crate) mod null;
 pub(crate) mod statsd;
@@ -112,7 +113,10 @@ pub fn lookup(name: &str) -> Result<Box<dyn Codec>> {
         "binary" => Ok(Box::new(binary::Binary {})),
         "syslog" => Ok(Box::new(syslog::Syslog::utcnow())),
         "csv" => Ok(Box::new(csv::Csv {})),
-        _ => Err(format!("Codec '{}' not found.", name).into()),
+        _ => match name.strip_prefix("layout:") {
+            Some(layout) => Ok(Box::new(layout::Layout::lookup(layout)?)),
+            None => Err(format!("Codec '{}' not found.", name).into()),
+        },
     }
 }
 
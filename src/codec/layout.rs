@@ -0,0 +1,446 @@
+// Copyright 2020-2021, The Tremor Team
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+//! Codec for fixed binary layouts declared in the `layout` section of the
+//! config, used as `layout:<name>`.
+//!
+//! Every field is read from its offset in the frame into a record, frames
+//! shorter than the layout are errors and bytes not covered by a field are
+//! ignored. Encoding writes the fields of a record into a zeroed frame.
+
+use super::prelude::*;
+use crate::config::{Bits, Endian, Field, FieldType, Layout as Config};
+use byteorder::{BigEndian, ByteOrder, LittleEndian};
+use std::collections::{BTreeMap, HashSet};
+use std::convert::TryFrom;
+use std::sync::RwLock;
+
+lazy_static! {
+    static ref LAYOUTS: RwLock<BTreeMap<String, Config>> = RwLock::new(BTreeMap::new());
+}
+
+/// Size in bytes of fixed size fields
+fn width(kind: FieldType) -> Option<usize> {
+    match kind {
+        FieldType::U8 | FieldType::I8 | FieldType::Bool => Some(1),
+        FieldType::U16 | FieldType::I16 => Some(2),
+        FieldType::U32 | FieldType::I32 | FieldType::F32 => Some(4),
+        FieldType::U64 | FieldType::I64 | FieldType::F64 => Some(8),
+        FieldType::String | FieldType::Bytes => None,
+    }
+}
+
+fn is_unsigned(kind: FieldType) -> bool {
+    matches!(
+        kind,
+        FieldType::U8 | FieldType::U16 | FieldType::U32 | FieldType::U64
+    )
+}
+
+/// Bytes a frame needs at least to hold all fields
+fn min_len(layout: &Config) -> usize {
+    layout
+        .fields
+        .iter()
+        .map(|f| f.offset + width(f.kind).or(f.size).unwrap_or_default())
+        .max()
+        .unwrap_or_default()
+}
+
+fn mask(size: u32) -> u64 {
+    if size >= 64 {
+        u64::MAX
+    } else {
+        (1 << size) - 1
+    }
+}
+
+fn validate(name: &str, layout: &Config) -> Result<()> {
+    let invalid = |reason: String| Error::from(ErrorKind::InvalidLayout(name.to_string(), reason));
+    let mut names = HashSet::new();
+    for field in &layout.fields {
+        if !names.insert(field.name.as_str()) {
+            return Err(invalid(format!("duplicate field `{}`", field.name)));
+        }
+        let width = width(field.kind);
+        if width.is_some() && field.size.is_some() {
+            return Err(invalid(format!(
+                "field `{}` sets a size, only string and bytes fields have one",
+                field.name
+            )));
+        }
+        if field.bits.is_empty() {
+            continue;
+        }
+        if !is_unsigned(field.kind) {
+            return Err(invalid(format!(
+                "field `{}` has bits, only unsigned integer fields have them",
+                field.name
+            )));
+        }
+        let bits = width.unwrap_or_default() * 8;
+        let mut bit_names = HashSet::new();
+        for b in &field.bits {
+            if !bit_names.insert(b.name.as_str()) {
+                return Err(invalid(format!(
+                    "duplicate bits `{}` in field `{}`",
+                    b.name, field.name
+                )));
+            }
+            if b.size == 0 || b.offset.saturating_add(b.size) as usize > bits {
+                return Err(invalid(format!(
+                    "bits `{}` of field `{}` are outside of its {} bits",
+                    b.name, field.name, bits
+                )));
+            }
+        }
+    }
+    Ok(())
+}
+
+/// Defines or replaces the layout `name`
+///
+/// # Errors
+///  * if the layout is invalid or the layouts lock is poisoned
+pub(crate) fn set(name: &str, layout: Config) -> Result<()> {
+    validate(name, &layout)?;
+    LAYOUTS.write()?.insert(name.to_string(), layout);
+    Ok(())
+}
+
+/// All layouts by name
+///
+/// # Errors
+///  * if the layouts lock is poisoned
+pub(crate) fn list() -> Result<BTreeMap<String, Config>> {
+    Ok(LAYOUTS.read()?.clone())
+}
+
+#[derive(Clone)]
+pub struct Layout {
+    layout: Config,
+    len: usize,
+}
+
+impl Layout {
+    /// The codec for the layout `name`
+    ///
+    /// # Errors
+    ///  * if there is no such layout or the layouts lock is poisoned
+    pub(crate) fn lookup(name: &str) -> Result<Self> {
+        let layout = LAYOUTS
+            .read()?
+            .get(name)
+            .cloned()
+            .ok_or_else(|| Error::from(format!("Layout '{}' not found.", name)))?;
+        let len = min_len(&layout);
+        Ok(Self { layout, len })
+    }
+}
+
+fn uint<'value>(u: u64) -> Value<'value> {
+    i64::try_from(u).map_or_else(|_| Value::from(u), Value::from)
+}
+
+fn bitfields<'value>(bits: &[Bits], u: u64) -> Value<'value> {
+    let mut record = Object::with_capacity(bits.len());
+    for b in bits {
+        record.insert(b.name.clone().into(), uint((u >> b.offset) & mask(b.size)));
+    }
+    Value::from(record)
+}
+
+fn decode_field<'input>(
+    field: &Field,
+    endian: Endian,
+    data: &'input [u8],
+) -> Result<Value<'input>> {
+    let end = width(field.kind)
+        .or(field.size)
+        .map_or(data.len(), |w| field.offset + w);
+    let raw = data.get(field.offset..end).ok_or_else(|| {
+        ErrorKind::InvalidLayoutData(format!(
+            "field `{}` needs bytes {} to {} but the frame has {}",
+            field.name,
+            field.offset,
+            end,
+            data.len()
+        ))
+    })?;
+    let big = field.endian.unwrap_or(endian) == Endian::Big;
+    let read_uint = || {
+        if big {
+            BigEndian::read_uint(raw, raw.len())
+        } else {
+            LittleEndian::read_uint(raw, raw.len())
+        }
+    };
+    Ok(match field.kind {
+        FieldType::U8 | FieldType::U16 | FieldType::U32 | FieldType::U64 => {
+            if field.bits.is_empty() {
+                uint(read_uint())
+            } else {
+                bitfields(&field.bits, read_uint())
+            }
+        }
+        FieldType::I8 | FieldType::I16 | FieldType::I32 | FieldType::I64 => Value::from(if big {
+            BigEndian::read_int(raw, raw.len())
+        } else {
+            LittleEndian::read_int(raw, raw.len())
+        }),
+        FieldType::F32 => Value::from(f64::from(if big {
+            BigEndian::read_f32(raw)
+        } else {
+            LittleEndian::read_f32(raw)
+        })),
+        FieldType::F64 => Value::from(if big {
+            BigEndian::read_f64(raw)
+        } else {
+            LittleEndian::read_f64(raw)
+        }),
+        FieldType::Bool => Value::from(read_uint() != 0),
+        FieldType::String => {
+            let len = raw.iter().rposition(|b| *b != 0).map_or(0, |p| p + 1);
+            Value::from(std::str::from_utf8(&raw[..len])?)
+        }
+        FieldType::Bytes => Value::Bytes(raw.into()),
+    })
+}
+
+fn encode_field(field: &Field, endian: Endian, value: &Value, frame: &mut Vec<u8>) -> Result<()> {
+    let invalid = || {
+        Error::from(ErrorKind::InvalidLayoutData(format!(
+            "field `{}` can't be encoded from {}",
+            field.name,
+            value.encode()
+        )))
+    };
+    let big = field.endian.unwrap_or(endian) == Endian::Big;
+    let width = if let Some(width) = width(field.kind) {
+        width
+    } else {
+        let data: &[u8] = match value {
+            Value::Bytes(b) => b,
+            _ => value.as_str().ok_or_else(invalid)?.as_bytes(),
+        };
+        let end = field.offset + field.size.unwrap_or_else(|| data.len());
+        if field.offset + data.len() > end {
+            return Err(invalid());
+        }
+        if frame.len() < end {
+            frame.resize(end, 0);
+        }
+        frame[field.offset..field.offset + data.len()].copy_from_slice(data);
+        return Ok(());
+    };
+    let raw = &mut frame[field.offset..field.offset + width];
+    match field.kind {
+        FieldType::U8 | FieldType::U16 | FieldType::U32 | FieldType::U64 => {
+            let u = if field.bits.is_empty() {
+                value.as_u64().ok_or_else(invalid)?
+            } else {
+                let mut u = 0;
+                for b in &field.bits {
+                    let v = value.get_u64(b.name.as_str()).unwrap_or_default();
+                    if v > mask(b.size) {
+                        return Err(invalid());
+                    }
+                    u |= v << b.offset;
+                }
+                u
+            };
+            if width < 8 && u >> (width * 8) != 0 {
+                return Err(invalid());
+            }
+            if big {
+                BigEndian::write_uint(raw, u, width);
+            } else {
+                LittleEndian::write_uint(raw, u, width);
+            }
+        }
+        FieldType::I8 | FieldType::I16 | FieldType::I32 | FieldType::I64 => {
+            let i = value.as_i64().ok_or_else(invalid)?;
+            let shift = 64 - width * 8;
+            if (i << shift) >> shift != i {
+                return Err(invalid());
+            }
+            if big {
+                BigEndian::write_int(raw, i, width);
+            } else {
+                LittleEndian::write_int(raw, i, width);
+            }
+        }
+        FieldType::F32 => {
+            // ALLOW: f32 fields lose the precision they don't have
+            #[allow(clippy::cast_possible_truncation)]
+            let f = value.cast_f64().ok_or_else(invalid)? as f32;
+            if big {
+                BigEndian::write_f32(raw, f);
+            } else {
+                LittleEndian::write_f32(raw, f);
+            }
+        }
+        FieldType::F64 => {
+            let f = value.cast_f64().ok_or_else(invalid)?;
+            if big {
+                BigEndian::write_f64(raw, f);
+            } else {
+                LittleEndian::write_f64(raw, f);
+            }
+        }
+        FieldType::Bool => raw[0] = u8::from(value.as_bool().ok_or_else(invalid)?),
+        FieldType::String | FieldType::Bytes => (),
+    }
+    Ok(())
+}
+
+impl Codec for Layout {
+    #[cfg(not(tarpaulin_include))]
+    fn name(&self) -> &str {
+        "layout"
+    }
+
+    fn decode<'input>(
+        &mut self,
+        data: &'input mut [u8],
+        _ingest_ns: u64,
+    ) -> Result<Option<Value<'input>>> {
+        let data: &'input [u8] = data;
+        let mut record = Object::with_capacity(self.layout.fields.len());
+        for field in &self.layout.fields {
+            let value = decode_field(field, self.layout.endian, data)?;
+            record.insert(field.name.clone().into(), value);
+        }
+        Ok(Some(Value::from(record)))
+    }
+
+    fn encode(&self, data: &Value) -> Result<Vec<u8>> {
+        let mut frame = vec![0; self.len];
+        for field in &self.layout.fields {
+            let value = data.get(field.name.as_str()).ok_or_else(|| {
+                ErrorKind::InvalidLayoutData(format!("missing field `{}`", field.name))
+            })?;
+            encode_field(field, self.layout.endian, value, &mut frame)?;
+        }
+        Ok(frame)
+    }
+
+    #[cfg(not(tarpaulin_include))]
+    fn boxed_clone(&self) -> Box<dyn Codec> {
+        Box::new(self.clone())
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use tremor_value::literal;
+
+    const SENSOR: &str = r#"
+endian: big
+fields:
+  - { name: id, offset: 0, type: u16 }
+  - { name: temperature, offset: 2, type: f32, endian: little }
+  - name: status
+    offset: 6
+    type: u8
+    bits:
+      - { name: alarm, offset: 0, size: 1 }
+      - { name: mode, offset: 1, size: 3 }
+  - { name: serial, offset: 7, type: string, size: 8 }
+  - { name: delta, offset: 15, type: i16 }
+  - { name: payload, offset: 17, type: bytes }
+"#;
+
+    #[test]
+    fn decode_and_encode() -> Result<()> {
+        set("test-sensor", serde_yaml::from_str(SENSOR)?)?;
+        let mut codec = crate::codec::lookup("layout:test-sensor")?;
+        let frame = vec![
+            0x01,
+            0x02, // id
+            0x00,
+            0x00,
+            0xac,
+            0x41,        // temperature
+            0b0000_1011, // status
+            b'A',
+            b'B',
+            b'1',
+            b'2',
+            0,
+            0,
+            0,
+            0, // serial
+            0xff,
+            0xfe, // delta
+            0xde,
+            0xad, // payload
+        ];
+        let mut data = frame.clone();
+        let value = codec.decode(data.as_mut_slice(), 0)?.unwrap_or_default();
+        let payload = Value::Bytes(vec![0xde_u8, 0xad].into());
+        let expected = literal!({
+            "id": 258,
+            "temperature": 21.5,
+            "status": {"alarm": 1, "mode": 5},
+            "serial": "AB12",
+            "delta": -2,
+            "payload": payload
+        });
+        assert_eq!(expected, value);
+        assert_eq!(frame, codec.encode(&value)?);
+
+        let mut short = vec![0x01, 0x02, 0x00];
+        assert!(codec.decode(short.as_mut_slice(), 0).is_err());
+        let overflow = literal!({
+            "id": 70000,
+            "temperature": 21.5,
+            "status": {"alarm": 1, "mode": 5},
+            "serial": "AB12",
+            "delta": -2,
+            "payload": "snot"
+        });
+        assert!(codec.encode(&overflow).is_err());
+        Ok(())
+    }
+
+    #[test]
+    fn invalid_layouts() -> Result<()> {
+        let bits_on_float = r#"
+fields:
+  - name: f
+    offset: 0
+    type: f32
+    bits: [{ name: b, offset: 0, size: 1 }]
+"#;
+        let bits_outside = r#"
+fields:
+  - name: u
+    offset: 0
+    type: u8
+    bits: [{ name: b, offset: 6, size: 3 }]
+"#;
+        let duplicate = r#"
+fields:
+  - { name: a, offset: 0, type: u8 }
+  - { name: a, offset: 1, type: u8 }
+"#;
+        for layout in &[bits_on_float, bits_outside, duplicate] {
+            assert!(set("test-invalid", serde_yaml::from_str(layout)?).is_err());
+        }
+        assert!(crate::codec::lookup("layout:test-invalid").is_err());
+        Ok(())
+    }
+}
@@ -13,7 +13,9 @@
 // limitations under the License.
 
 use crate::url::TremorUrl;
+use crate::Event;
 use hashbrown::HashMap;
+use tremor_script::prelude::*;
 
 pub(crate) type Id = String;
 pub(crate) type OnRampVec = Vec<OnRamp>;
@@ -21,6 +23,9 @@ pub(crate) type OffRampVec = Vec<OffRamp>;
 pub(crate) type BindingVec = Vec<Binding>;
 pub(crate) type BindingMap = HashMap<TremorUrl, Vec<TremorUrl>>;
 pub(crate) type MappingMap = HashMap<TremorUrl, HashMap<String, String>>;
+pub(crate) type MetricsMap = HashMap<TremorUrl, Metrics>;
+pub(crate) type FlagMap = HashMap<String, Flag>;
+pub(crate) type LayoutMap = HashMap<String, Layout>;
 
 /// A full tremor config
 #[derive(Clone, Debug, Serialize, Deserialize)]
@@ -34,6 +39,15 @@ pub struct Config {
     pub(crate) binding: Vec<Binding>,
     #[serde(default = "Default::default")]
     pub(crate) mapping: MappingMap,
+    /// metrics overrides by artefact
+    #[serde(default = "Default::default")]
+    pub(crate) metrics: MetricsMap,
+    /// feature flags by name
+    #[serde(default = "Default::default")]
+    pub(crate) flags: FlagMap,
+    /// binary layouts decoded by the `layout:<name>` codecs
+    #[serde(default = "Default::default")]
+    pub(crate) layout: LayoutMap,
 }
 
 /// Configuration for an onramp
@@ -72,10 +86,145 @@ pub struct OnRamp {
     pub(crate) postprocessors: Option<Vec<String>>,
     #[serde(skip_serializing_if = "Option::is_none")]
     pub(crate) metrics_interval_s: Option<u64>,
+    /// resource limits enforced on events entering the system via this onramp
+    #[serde(default = "Default::default")]
+    pub(crate) limits: OnRampLimits,
+    /// priority of the events of this onramp, unless set per event in `$priority`
+    #[serde(
+        default = "Default::default",
+        skip_serializing_if = "Priority::is_normal"
+    )]
+    pub(crate) priority: Priority,
+    /// in a cluster run only on the node holding the lease of the leader, for
+    /// sources that must not be consumed by more than one node
+    #[serde(default = "Default::default")]
+    pub(crate) singleton: bool,
+    /// number of reader instances, for onramps that support it (`kafka`,
+    /// `tcp` and `rest`). With more than one, events carry the index of the
+    /// instance that read them in `$instance`
+    #[serde(default = "d_parallelism")]
+    pub(crate) parallelism: usize,
     #[serde(skip_serializing_if = "Option::is_none")]
     pub(crate) config: tremor_pipeline::ConfigMap,
 }
 
+fn d_parallelism() -> usize {
+    1
+}
+
+/// Resource limits of an onramp
+///
+/// e.g.:
+///       limits:
+///         max_event_size: 1048576
+///         max_in_flight: 1000
+///         max_age: 30000
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct OnRampLimits {
+    /// maximum size in bytes of the raw data of a single event,
+    /// larger events are routed to the `err` port instead of being decoded
+    #[serde(default = "Default::default", skip_serializing_if = "Option::is_none")]
+    pub(crate) max_event_size: Option<usize>,
+    /// maximum number of events sent but not yet acknowledged or failed,
+    /// only enforced for transactional onramps
+    #[serde(default = "Default::default", skip_serializing_if = "Option::is_none")]
+    pub(crate) max_in_flight: Option<u64>,
+    /// maximum age in milliseconds of an event, it sets the `$deadline` of
+    /// events unless the source set one. Expired events are routed to the
+    /// `expired` port of the pipeline node they reach, and not sent by offramps
+    #[serde(default = "Default::default", skip_serializing_if = "Option::is_none")]
+    pub(crate) max_age: Option<u64>,
+}
+
+/// Priority of an event on the pipeline and offramp queues, `high` priority
+/// events overtake queued `normal` priority events, so control plane or
+/// heartbeat events keep flowing when bulk traffic saturates the queues
+///
+/// e.g.:
+///       priority: high
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Priority {
+    /// events are queued in order
+    Normal,
+    /// events overtake queued `normal` priority events
+    High,
+}
+
+impl Default for Priority {
+    fn default() -> Self {
+        Self::Normal
+    }
+}
+
+impl Priority {
+    /// metadata key of the priority of an event
+    pub(crate) const META: &'static str = "priority";
+
+    #[allow(clippy::trivially_copy_pass_by_ref)] // required by serde
+    fn is_normal(&self) -> bool {
+        *self == Self::Normal
+    }
+
+    /// The priority of an event, set in `$priority` and `normal` if absent
+    pub(crate) fn of(event: &Event) -> Self {
+        if event.data.suffix().meta().get_str(Self::META) == Some("high") {
+            Self::High
+        } else {
+            Self::Normal
+        }
+    }
+}
+
+/// How a canary version of a pipeline receives events
+#[derive(Clone, Copy, Debug, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum CanaryMode {
+    /// the canary processes a percentage of the events instead of the active version
+    Split,
+    /// the canary processes a copy of every event and its output is discarded
+    Mirror,
+}
+
+impl Default for CanaryMode {
+    fn default() -> Self {
+        Self::Split
+    }
+}
+
+/// Configuration of a canary deployment of a pipeline version
+#[derive(Clone, Debug, Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct Canary {
+    /// version of the pipeline to deploy
+    pub version: usize,
+    #[serde(default = "Default::default")]
+    pub(crate) mode: CanaryMode,
+    /// percentage of the events the canary processes in `split` mode
+    #[serde(default = "d_canary_percentage")]
+    pub(crate) percentage: u8,
+    /// number of events the canary processes before it is promoted or rolled back
+    #[serde(default = "d_canary_min_events")]
+    pub(crate) min_events: u64,
+    /// percentage points the error rate of the canary may exceed the one of
+    /// the active version by before it is rolled back
+    #[serde(default = "d_canary_tolerance")]
+    pub(crate) tolerance: f64,
+}
+
+fn d_canary_percentage() -> u8 {
+    10
+}
+
+fn d_canary_min_events() -> u64 {
+    1000
+}
+
+fn d_canary_tolerance() -> f64 {
+    1.0
+}
+
 /// Configuration of an offramp
 #[derive(Clone, Debug, Serialize, Deserialize)]
 #[serde(deny_unknown_fields)]
@@ -110,10 +259,49 @@ pub struct OffRamp {
     pub(crate) postprocessors: Option<Vec<String>>,
     #[serde(skip_serializing_if = "Option::is_none")]
     pub(crate) metrics_interval_s: Option<u64>,
+    /// initialize the sink on its first event instead of on startup, until
+    /// then the offramp reports itself as ready
+    #[serde(default = "Default::default")]
+    pub(crate) lazy: bool,
+    /// milliseconds the first event of a lazy offramp retries initializing
+    /// the sink for before it is failed
+    #[serde(default = "d_ready_grace_ms")]
+    pub(crate) ready_grace_ms: u64,
+    /// keeps the events the offramp failed to deliver that nothing retries
+    /// for inspection and replay
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub(crate) journal: Option<Journal>,
     #[serde(skip_serializing_if = "Option::is_none")]
     pub(crate) config: tremor_pipeline::ConfigMap,
 }
 
+fn d_ready_grace_ms() -> u64 {
+    5000
+}
+
+/// Journal of the failed deliveries of an offramp
+///
+/// e.g.:
+///       offramp:
+///         - id: out
+///           type: rest
+///           journal:
+///             dir: /var/lib/tremor/journal
+///             max_entries: 1000
+#[derive(Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct Journal {
+    /// directory of the journal database, shared by the offramps using it
+    pub(crate) dir: String,
+    /// number of entries kept, the oldest are dropped
+    #[serde(default = "d_journal_max_entries")]
+    pub(crate) max_entries: usize,
+}
+
+fn d_journal_max_entries() -> usize {
+    10_000
+}
+
 /// Configuration for a Binding
 #[derive(Clone, Debug, Serialize, Deserialize)]
 #[serde(deny_unknown_fields)]
@@ -123,4 +311,221 @@ pub struct Binding {
     #[serde(default = "Default::default")]
     pub(crate) description: String,
     pub(crate) links: BindingMap, // is this right? this should be url to url?
+    /// Onramps replaying historical data, the other onramps of the binding
+    /// are only linked once all of them are done
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub(crate) backfill: Vec<TremorUrl>,
+    /// What happens to events sent to a linked pipeline input whose queue
+    /// is full, by link target
+    #[serde(default, skip_serializing_if = "HashMap::is_empty")]
+    pub(crate) overflow: HashMap<TremorUrl, Overflow>,
+    /// Pipeline instances running a warm standby that takes over when the
+    /// active graph fails to process an event, by pipeline instance
+    #[serde(default, skip_serializing_if = "HashMap::is_empty")]
+    pub(crate) standby: HashMap<TremorUrl, Standby>,
+}
+
+/// Warm standby of a pipeline instance, its state is synced from the active
+/// graph periodically
+///
+/// e.g.:
+///       binding:
+///         - id: main
+///           links:
+///             '/onramp/in/{instance}/out': [ '/pipeline/main/{instance}/in' ]
+///           standby:
+///             '/pipeline/main/{instance}':
+///               sync_interval_ms: 500
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct Standby {
+    /// milliseconds between syncs of the state of the active graph to the
+    /// standby
+    #[serde(default = "d_sync_interval_ms")]
+    pub(crate) sync_interval_ms: u64,
+}
+
+fn d_sync_interval_ms() -> u64 {
+    1000
+}
+
+/// Behaviour of a link into a pipeline when the queue of the pipeline is full
+///
+/// e.g.:
+///       binding:
+///         - id: main
+///           links:
+///             '/onramp/in/{instance}/out': [ '/pipeline/main/{instance}/in' ]
+///           overflow:
+///             '/pipeline/main/{instance}/in': drop-oldest
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum Overflow {
+    /// wait until the pipeline made room, this is the default
+    Block,
+    /// drop the oldest queued event to make room
+    DropOldest,
+    /// drop the event that doesn't fit
+    DropNewest,
+    /// route the event that doesn't fit to the `overflow` output of the
+    /// sending pipeline, only links from pipelines have one
+    RouteToOverflowPort,
+}
+
+impl Default for Overflow {
+    fn default() -> Self {
+        Self::Block
+    }
+}
+
+/// Overrides of the metrics of an artefact, they apply to instances created
+/// after they are loaded
+///
+/// e.g.:
+///       metrics:
+///         /pipeline/main:
+///           interval_s: 5
+///         /onramp/debug:
+///           enabled: false
+/// A feature flag, queried in scripts with `flags::enabled(name, key)`
+///
+/// e.g.:
+///       flags:
+///         new-routing:
+///           rollout: 10
+///           allow: [ "customer-1" ]
+#[derive(Clone, Debug, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct Flag {
+    /// percentage of the keys the flag is enabled for
+    #[serde(default)]
+    pub rollout: u8,
+    /// keys the flag is enabled for regardless of the rollout
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub allow: Vec<String>,
+    /// keys the flag is disabled for regardless of the rollout
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub deny: Vec<String>,
+}
+
+/// A fixed binary layout, decoded and encoded by the codec `layout:<name>`
+///
+/// e.g.:
+///       layout:
+///         sensor:
+///           endian: big
+///           fields:
+///             - { name: id, offset: 0, type: u16 }
+///             - { name: temperature, offset: 2, type: f32, endian: little }
+///             - name: status
+///               offset: 6
+///               type: u8
+///               bits:
+///                 - { name: alarm, offset: 0, size: 1 }
+///                 - { name: mode, offset: 1, size: 3 }
+///             - { name: serial, offset: 7, type: string, size: 8 }
+#[derive(Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct Layout {
+    /// byte order of the fields that don't set their own
+    #[serde(default)]
+    pub endian: Endian,
+    /// the fields of the layout
+    pub fields: Vec<Field>,
+}
+
+/// A field of a binary layout
+#[derive(Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct Field {
+    /// name of the field in the decoded record
+    pub name: String,
+    /// offset of the field in bytes from the start of the frame
+    pub offset: usize,
+    /// type of the field
+    #[serde(rename = "type")]
+    pub kind: FieldType,
+    /// size in bytes of `string` and `bytes` fields, to the end of the frame
+    /// if unset
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub size: Option<usize>,
+    /// byte order, overriding the one of the layout
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub endian: Option<Endian>,
+    /// bitfields of unsigned integer fields, decoded into a record instead
+    /// of the integer
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub bits: Vec<Bits>,
+}
+
+/// Type of a field of a binary layout
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum FieldType {
+    /// unsigned 8 bit integer
+    U8,
+    /// unsigned 16 bit integer
+    U16,
+    /// unsigned 32 bit integer
+    U32,
+    /// unsigned 64 bit integer
+    U64,
+    /// signed 8 bit integer
+    I8,
+    /// signed 16 bit integer
+    I16,
+    /// signed 32 bit integer
+    I32,
+    /// signed 64 bit integer
+    I64,
+    /// 32 bit float
+    F32,
+    /// 64 bit float
+    F64,
+    /// a byte, `0` is false
+    Bool,
+    /// UTF-8 text, padded with trailing zero bytes
+    String,
+    /// raw bytes
+    Bytes,
+}
+
+/// Byte order of a field of a binary layout
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Endian {
+    /// most significant byte first, the network byte order
+    Big,
+    /// least significant byte first
+    Little,
+}
+
+impl Default for Endian {
+    fn default() -> Self {
+        Self::Big
+    }
+}
+
+/// A bitfield of an unsigned integer field
+#[derive(Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct Bits {
+    /// name of the bitfield in the record of the field
+    pub name: String,
+    /// offset in bits from the least significant bit
+    pub offset: u32,
+    /// size in bits
+    pub size: u32,
+}
+
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct Metrics {
+    /// disables the metrics with `false`, with `true` metrics are reported
+    /// even if the artefact doesn't configure an interval
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub enabled: Option<bool>,
+    /// interval in seconds, replacing the one configured in the artefact
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub interval_s: Option<u64>,
 }
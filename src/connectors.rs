@@ -21,3 +21,24 @@ pub mod otel;
 pub mod gcp;
 
 pub(crate) mod pb;
+
+/// SASL authentication for kafka
+pub(crate) mod kafka;
+
+/// DNS based service discovery for outbound connections
+pub(crate) mod discovery;
+
+/// Framing of the transport between tremor nodes
+pub(crate) mod interconnect;
+
+/// Onramps and offramps provided by sidecar plugins
+pub mod plugin;
+
+/// Proxies for outbound connections
+pub mod proxy;
+
+/// QUIC endpoints (experimental)
+pub(crate) mod quic;
+
+/// TLS configuration shared by connectors
+pub(crate) mod tls;
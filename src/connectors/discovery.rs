@@ -0,0 +1,337 @@
+// Copyright 2020-2021, The Tremor Team
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+//! Service discovery via DNS, e.g. for endpoints behind headless kubernetes
+//! services.
+//!
+//! The addresses of a host are re-resolved periodically and requests are
+//! balanced across them round robin. Connections to addresses that are no
+//! longer returned are drained by the offramps.
+
+use crate::connectors::proxy;
+use crate::errors::Result;
+use async_std::net::TcpStream;
+use async_std::task;
+use async_std_resolver::{resolver_from_system_conf, AsyncStdResolver};
+use std::net::SocketAddr;
+use std::sync::{Arc, RwLock, Weak};
+use std::time::Duration;
+
+/// Records endpoints are discovered from
+#[derive(Debug, Clone, Copy, Deserialize, PartialEq)]
+#[serde(rename_all = "lowercase")]
+pub enum Records {
+    /// `A` and `AAAA` records of the host, using the configured port
+    A,
+    /// `SRV` records of the host, using their targets and ports, only the
+    /// records with the highest priority are used
+    Srv,
+}
+
+fn default_records() -> Records {
+    Records::A
+}
+
+fn default_interval() -> u64 {
+    30_000
+}
+
+/// How the host of an offramp is resolved, without it the host is resolved
+/// once on each connect
+#[derive(Debug, Clone, Deserialize, PartialEq)]
+pub struct Resolution {
+    /// Records to resolve (default: `a`)
+    #[serde(default = "default_records")]
+    pub records: Records,
+    /// Interval to re-resolve the host in in milliseconds (default: 30000)
+    #[serde(default = "default_interval")]
+    pub interval: u64,
+}
+
+#[derive(Debug, Default)]
+struct State {
+    addrs: Vec<SocketAddr>,
+    /// incremented whenever `addrs` changes
+    generation: u64,
+    next: usize,
+}
+
+impl State {
+    fn update(&mut self, mut addrs: Vec<SocketAddr>) {
+        addrs.sort_unstable();
+        addrs.dedup();
+        if addrs != self.addrs {
+            info!("Discovered endpoints changed to {:?}", addrs);
+            self.addrs = addrs;
+            self.generation += 1;
+        }
+    }
+}
+
+/// The discovered addresses of a host, re-resolved in the background as long
+/// as this is alive
+#[derive(Debug, Clone)]
+pub(crate) struct Discovery {
+    host: String,
+    state: Arc<RwLock<State>>,
+}
+
+impl Discovery {
+    /// Starts discovering the addresses of `host`, `port` is used for `A`
+    /// and `AAAA` records
+    pub(crate) fn start(host: &str, port: u16, resolution: &Resolution) -> Self {
+        let state = Arc::new(RwLock::new(State::default()));
+        task::spawn(discover(
+            host.to_string(),
+            port,
+            resolution.clone(),
+            Arc::downgrade(&state),
+        ));
+        Self {
+            host: host.to_string(),
+            state,
+        }
+    }
+
+    /// The discovered addresses
+    pub(crate) fn addrs(&self) -> Vec<SocketAddr> {
+        self.state
+            .read()
+            .map(|state| state.addrs.clone())
+            .unwrap_or_default()
+    }
+
+    /// The next address to use, `None` if nothing was discovered yet
+    pub(crate) fn next(&self) -> Option<SocketAddr> {
+        let mut state = self.state.write().ok()?;
+        if state.addrs.is_empty() {
+            return None;
+        }
+        let idx = state.next % state.addrs.len();
+        state.next = idx + 1;
+        Some(state.addrs[idx])
+    }
+
+    /// The addresses in `connected` that were removed since `generation`,
+    /// which is updated to the current generation
+    pub(crate) fn removed(
+        &self,
+        generation: &mut u64,
+        connected: &[SocketAddr],
+    ) -> Vec<SocketAddr> {
+        let state = match self.state.read() {
+            Ok(state) => state,
+            Err(_) => return vec![],
+        };
+        if state.generation == *generation {
+            return vec![];
+        }
+        *generation = state.generation;
+        connected
+            .iter()
+            .filter(|addr| !state.addrs.contains(addr))
+            .copied()
+            .collect()
+    }
+
+    /// Connects to the next address or to the host itself if nothing was
+    /// discovered yet
+    pub(crate) async fn connect(&self, port: u16) -> Result<(Option<SocketAddr>, TcpStream)> {
+        if let Some(addr) = self.next() {
+            Ok((Some(addr), TcpStream::connect(addr).await?))
+        } else {
+            Ok((None, TcpStream::connect((self.host.as_str(), port)).await?))
+        }
+    }
+}
+
+async fn lookup(
+    resolver: &AsyncStdResolver,
+    host: &str,
+    port: u16,
+    records: Records,
+) -> Result<Vec<SocketAddr>> {
+    match records {
+        Records::A => Ok(resolver
+            .lookup_ip(host)
+            .await?
+            .iter()
+            .map(|ip| SocketAddr::new(ip, port))
+            .collect()),
+        Records::Srv => {
+            let srvs = resolver.srv_lookup(host).await?;
+            let priority = srvs.iter().map(|srv| srv.priority()).min();
+            let mut addrs = Vec::new();
+            for srv in srvs.iter().filter(|srv| Some(srv.priority()) == priority) {
+                let ips = resolver.lookup_ip(srv.target().to_utf8()).await?;
+                addrs.extend(ips.iter().map(|ip| SocketAddr::new(ip, srv.port())));
+            }
+            Ok(addrs)
+        }
+    }
+}
+
+/// Re-resolves `host` until the `Discovery` is dropped
+async fn discover(host: String, port: u16, resolution: Resolution, state: Weak<RwLock<State>>) {
+    let resolver = match resolver_from_system_conf().await {
+        Ok(resolver) => resolver,
+        Err(e) => {
+            error!("Failed to create a DNS resolver for {}: {}", host, e);
+            return;
+        }
+    };
+    let interval = Duration::from_millis(resolution.interval);
+    loop {
+        let addrs = lookup(&resolver, &host, port, resolution.records).await;
+        let state = match state.upgrade() {
+            Some(state) => state,
+            None => return,
+        };
+        match addrs {
+            // keep the last known addresses on errors
+            Ok(addrs) if addrs.is_empty() => warn!("No addresses discovered for {}", host),
+            Ok(addrs) => {
+                if let Ok(mut state) = state.write() {
+                    state.update(addrs);
+                }
+            }
+            Err(e) => warn!("Failed to resolve {}: {}", host, e),
+        }
+        drop(state);
+        task::sleep(interval).await;
+    }
+}
+
+/// HTTP client sending each request over a new connection to the next
+/// discovered address of the host, so no connections to removed addresses
+/// are kept
+pub(crate) struct DiscoveryClient {
+    discovery: Discovery,
+    tls: Option<Arc<rustls::ClientConfig>>,
+    config: http_client::Config,
+}
+
+impl std::fmt::Debug for DiscoveryClient {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "DiscoveryClient({})", self.discovery.host)
+    }
+}
+
+impl DiscoveryClient {
+    pub(crate) fn new(discovery: Discovery, tls: Option<Arc<rustls::ClientConfig>>) -> Self {
+        Self {
+            discovery,
+            tls,
+            config: http_client::Config::default(),
+        }
+    }
+}
+
+#[async_trait::async_trait]
+impl http_client::HttpClient for DiscoveryClient {
+    async fn send(&self, req: http_types::Request) -> http_types::Result<http_types::Response> {
+        let url = req.url().clone();
+        let host = url
+            .host_str()
+            .ok_or_else(|| http_types::format_err!("No host in {}", url))?
+            .to_string();
+        let port = url
+            .port_or_known_default()
+            .ok_or_else(|| http_types::format_err!("No port in {}", url))?;
+        // requests to other hosts, e.g. set via `$endpoint`, are sent directly
+        let stream = if host == self.discovery.host {
+            self.discovery
+                .connect(port)
+                .await
+                .map_err(|e| http_types::format_err!("{}", e))?
+                .1
+        } else {
+            TcpStream::connect((host.as_str(), port)).await?
+        };
+        proxy::send_request(stream, &host, self.tls.clone(), req).await
+    }
+
+    fn set_config(&mut self, config: http_client::Config) -> http_types::Result<()> {
+        self.config = config;
+        Ok(())
+    }
+
+    fn config(&self) -> &http_client::Config {
+        &self.config
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    fn discovery(addrs: &[&str]) -> Discovery {
+        let mut state = State::default();
+        state.update(addrs.iter().filter_map(|a| a.parse().ok()).collect());
+        Discovery {
+            host: "tremor".to_string(),
+            state: Arc::new(RwLock::new(state)),
+        }
+    }
+
+    #[test]
+    fn round_robin() -> Result<()> {
+        let d = discovery(&[]);
+        assert_eq!(d.next(), None);
+
+        let d = discovery(&["10.0.0.2:80", "10.0.0.1:80", "10.0.0.2:80"]);
+        let a1: SocketAddr = "10.0.0.1:80".parse()?;
+        let a2: SocketAddr = "10.0.0.2:80".parse()?;
+        assert_eq!(d.addrs(), vec![a1, a2]);
+        assert_eq!(d.next(), Some(a1));
+        assert_eq!(d.next(), Some(a2));
+        assert_eq!(d.next(), Some(a1));
+        Ok(())
+    }
+
+    #[test]
+    fn removed() -> Result<()> {
+        let d = discovery(&["10.0.0.1:80", "10.0.0.2:80"]);
+        let a1: SocketAddr = "10.0.0.1:80".parse()?;
+        let a2: SocketAddr = "10.0.0.2:80".parse()?;
+        let mut generation = 0;
+        assert!(d.removed(&mut generation, &[a1, a2]).is_empty());
+        assert_eq!(generation, 1);
+
+        // unchanged addresses don't bump the generation
+        d.state.write()?.update(vec![a2, a1]);
+        assert!(d.removed(&mut generation, &[a1, a2]).is_empty());
+        assert_eq!(generation, 1);
+
+        d.state.write()?.update(vec![a2]);
+        assert_eq!(d.removed(&mut generation, &[a1, a2]), vec![a1]);
+        assert_eq!(generation, 2);
+        assert!(d.removed(&mut generation, &[a1, a2]).is_empty());
+        assert_eq!(d.next(), Some(a2));
+        Ok(())
+    }
+
+    #[test]
+    fn config() -> Result<()> {
+        let r: Resolution = serde_yaml::from_str("records: srv")?;
+        assert_eq!(
+            r,
+            Resolution {
+                records: Records::Srv,
+                interval: 30_000
+            }
+        );
+        Ok(())
+    }
+}
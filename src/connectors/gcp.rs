@@ -13,6 +13,7 @@
 // limitations under the License.
 
 pub(crate) mod auth;
+pub(crate) mod bigquery;
 pub(crate) mod pubsub;
 pub(crate) mod pubsub_auth;
 pub(crate) mod storage;
@@ -0,0 +1,244 @@
+// Copyright 2020-2021, The Tremor Team
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+use crate::connectors::gcp::pubsub_auth::{AuthInterceptor, AuthedService};
+use crate::errors::{Error, Result};
+use googapis::google::cloud::bigquery::storage::v1::big_query_write_client::BigQueryWriteClient;
+use googapis::CERTIFICATES;
+use gouth::Token;
+use prost_types::field_descriptor_proto::{Label, Type};
+use prost_types::{DescriptorProto, FieldDescriptorProto};
+use tonic::service::interceptor::InterceptedService;
+use tonic::transport::{Certificate, Channel, ClientTlsConfig};
+use tremor_value::prelude::*;
+
+/// The type of a column
+#[derive(Deserialize, Debug, Clone, Copy, PartialEq)]
+#[serde(rename_all = "UPPERCASE")]
+pub(crate) enum ColumnType {
+    String,
+    Int64,
+    Float64,
+    Bool,
+    Bytes,
+    /// nanoseconds since the epoch, written as microseconds
+    Timestamp,
+    /// any value, written as JSON string
+    Json,
+}
+
+#[derive(Deserialize, Debug, Clone, Copy, PartialEq)]
+#[serde(rename_all = "UPPERCASE")]
+pub(crate) enum Mode {
+    Nullable,
+    Required,
+    Repeated,
+}
+
+impl Default for Mode {
+    fn default() -> Self {
+        Self::Nullable
+    }
+}
+
+/// A column of the table, the value is taken from the event field of the
+/// same name
+#[derive(Deserialize, Debug, Clone)]
+pub(crate) struct Column {
+    pub name: String,
+    #[serde(rename = "type")]
+    pub column_type: ColumnType,
+    #[serde(default)]
+    pub mode: Mode,
+}
+
+#[cfg(not(tarpaulin_include))]
+pub(crate) async fn setup_write_client() -> Result<BigQueryWriteClient<AuthedService>> {
+    let token = Token::new()?;
+    let tls_config = ClientTlsConfig::new()
+        .ca_certificate(Certificate::from_pem(CERTIFICATES))
+        .domain_name("bigquerystorage.googleapis.com");
+
+    let channel = Channel::from_static("https://bigquerystorage.googleapis.com")
+        .tls_config(tls_config)?
+        .connect()
+        .await?;
+    let interceptor = InterceptedService::new(channel, AuthInterceptor::new(token));
+    Ok(BigQueryWriteClient::new(interceptor))
+}
+
+/// The protobuf descriptor of the rows, columns are numbered in order
+pub(crate) fn descriptor(columns: &[Column]) -> Result<DescriptorProto> {
+    let field = columns
+        .iter()
+        .enumerate()
+        .map(|(i, column)| {
+            let field_type = match column.column_type {
+                ColumnType::String | ColumnType::Json => Type::String,
+                ColumnType::Int64 | ColumnType::Timestamp => Type::Int64,
+                ColumnType::Float64 => Type::Double,
+                ColumnType::Bool => Type::Bool,
+                ColumnType::Bytes => Type::Bytes,
+            };
+            let label = match column.mode {
+                Mode::Nullable => Label::Optional,
+                Mode::Required => Label::Required,
+                Mode::Repeated => Label::Repeated,
+            };
+            Ok(FieldDescriptorProto {
+                name: Some(column.name.clone()),
+                number: Some(i32::try_from(i + 1)?),
+                label: Some(label as i32),
+                r#type: Some(field_type as i32),
+                ..FieldDescriptorProto::default()
+            })
+        })
+        .collect::<Result<_>>()?;
+    Ok(DescriptorProto {
+        name: Some("tremor_row".to_string()),
+        field,
+        ..DescriptorProto::default()
+    })
+}
+
+fn encode_value(tag: u32, column: &Column, value: &Value, buf: &mut Vec<u8>) -> Result<()> {
+    use prost::encoding;
+    let invalid = || -> Error {
+        format!(
+            "Invalid value for {:?} column `{}`: {}",
+            column.column_type, column.name, value
+        )
+        .into()
+    };
+    match column.column_type {
+        ColumnType::String => match value.as_str() {
+            Some(s) => encoding::string::encode(tag, &s.to_string(), buf),
+            None => encoding::string::encode(tag, &value.encode(), buf),
+        },
+        ColumnType::Json => encoding::string::encode(tag, &value.encode(), buf),
+        ColumnType::Int64 => {
+            encoding::int64::encode(tag, &value.as_i64().ok_or_else(invalid)?, buf)
+        }
+        ColumnType::Timestamp => {
+            let micros = value.as_i64().ok_or_else(invalid)? / 1000;
+            encoding::int64::encode(tag, &micros, buf);
+        }
+        ColumnType::Float64 => {
+            encoding::double::encode(tag, &value.cast_f64().ok_or_else(invalid)?, buf);
+        }
+        ColumnType::Bool => encoding::bool::encode(tag, &value.as_bool().ok_or_else(invalid)?, buf),
+        ColumnType::Bytes => {
+            let bytes = if let Some(bytes) = value.as_bytes() {
+                bytes.to_vec()
+            } else {
+                value.as_str().ok_or_else(invalid)?.as_bytes().to_vec()
+            };
+            encoding::bytes::encode(tag, &bytes, buf);
+        }
+    }
+    Ok(())
+}
+
+/// Encodes a record as a row of the `descriptor`
+pub(crate) fn encode_row(columns: &[Column], value: &Value) -> Result<Vec<u8>> {
+    let mut buf = Vec::new();
+    for (i, column) in columns.iter().enumerate() {
+        let tag = u32::try_from(i + 1)?;
+        let value = value.get(column.name.as_str()).filter(|v| !v.is_null());
+        match (value, column.mode) {
+            (None, Mode::Required) => {
+                return Err(format!("Missing required column `{}`", column.name).into());
+            }
+            (None, _) => (),
+            (Some(values), Mode::Repeated) => {
+                for value in values.as_array().ok_or_else(|| {
+                    Error::from(format!("Repeated column `{}` needs an array", column.name))
+                })? {
+                    encode_value(tag, column, value, &mut buf)?;
+                }
+            }
+            (Some(value), _) => encode_value(tag, column, value, &mut buf)?,
+        }
+    }
+    Ok(buf)
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use prost::Message;
+
+    /// A message to decode the rows of the test table
+    #[derive(Clone, PartialEq, prost::Message)]
+    struct Row {
+        #[prost(string, required, tag = "1")]
+        name: String,
+        #[prost(int64, optional, tag = "2")]
+        count: Option<i64>,
+        #[prost(int64, optional, tag = "3")]
+        time: Option<i64>,
+        #[prost(string, repeated, tag = "4")]
+        tags: Vec<String>,
+        #[prost(string, optional, tag = "5")]
+        extra: Option<String>,
+    }
+
+    fn columns() -> Vec<Column> {
+        let column = |name: &str, column_type, mode| Column {
+            name: name.to_string(),
+            column_type,
+            mode,
+        };
+        vec![
+            column("name", ColumnType::String, Mode::Required),
+            column("count", ColumnType::Int64, Mode::Nullable),
+            column("time", ColumnType::Timestamp, Mode::Nullable),
+            column("tags", ColumnType::String, Mode::Repeated),
+            column("extra", ColumnType::Json, Mode::Nullable),
+        ]
+    }
+
+    #[test]
+    fn rows() -> Result<()> {
+        let columns = columns();
+        let descriptor = descriptor(&columns)?;
+        assert_eq!(descriptor.field.len(), 5);
+        assert_eq!(descriptor.field[3].label, Some(Label::Repeated as i32));
+
+        let row = encode_row(
+            &columns,
+            &literal!({
+                "name": "snot",
+                "time": 1_633_000_000_123_456_789_u64,
+                "tags": ["a", "b"],
+                "extra": {"badger": true},
+                "ignored": 1
+            }),
+        )?;
+        let row = Row::decode(row.as_slice()).map_err(|e| Error::from(e.to_string()))?;
+        assert_eq!(
+            row,
+            Row {
+                name: "snot".to_string(),
+                count: None,
+                time: Some(1_633_000_000_123_456),
+                tags: vec!["a".to_string(), "b".to_string()],
+                extra: Some(r#"{"badger":true}"#.to_string()),
+            }
+        );
+        assert!(encode_row(&columns, &literal!({"count": 1})).is_err());
+        assert!(encode_row(&columns, &literal!({"name": "snot", "count": "1"})).is_err());
+        Ok(())
+    }
+}
@@ -37,6 +37,12 @@ pub(crate) struct AuthInterceptor {
     token: Token,
 }
 
+impl AuthInterceptor {
+    pub(crate) fn new(token: Token) -> Self {
+        Self { token }
+    }
+}
+
 pub(crate) type AuthedService = InterceptedService<Channel, AuthInterceptor>;
 
 impl Interceptor for AuthInterceptor {
@@ -74,6 +74,30 @@ pub(crate) async fn add_object_with_slice(
     Ok(body)
 }
 
+/// Uploads an object with a content type, the name may contain any character
+pub(crate) async fn put_object(
+    client: &GcsClient,
+    bucket_name: &str,
+    object_name: &str,
+    content: Vec<u8>,
+    content_type: &str,
+) -> Result<()> {
+    let object_name: String =
+        url::form_urlencoded::byte_serialize(object_name.as_bytes()).collect();
+    let url = format!(
+        "https://storage.googleapis.com/upload/storage/v1/b/{}/o?uploadType=media&name={}",
+        bucket_name, object_name
+    );
+    client
+        .post(url)?
+        .header("content-type", content_type)
+        .body(content)
+        .send()
+        .await?
+        .error_for_status()?;
+    Ok(())
+}
+
 pub(crate) async fn delete_object(
     client: &GcsClient,
     bucket_name: &str,
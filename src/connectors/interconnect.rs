@@ -0,0 +1,169 @@
+// Copyright 2020-2021, The Tremor Team
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+//! Framing of the interconnect onramp and offramp, which connect pipelines
+//! on different tremor nodes.
+//!
+//! A frame is the 4 byte big endian length of the rest of the frame, a flags
+//! byte and a JSON object with the `pipeline` the event is for, its `data`
+//! and its `meta`. Large payloads are compressed with snappy. The receiver
+//! acknowledges frames with the 8 byte big endian number of frames it
+//! accepted on the connection so far, the sender stops sending while too
+//! many frames are unacknowledged.
+
+use crate::errors::{Error, Result};
+use futures::io::{AsyncRead, AsyncReadExt, AsyncWrite, AsyncWriteExt};
+use tremor_value::prelude::*;
+use tremor_value::Value;
+
+/// Upper bound for the length of a frame
+pub(crate) const MAX_FRAME: usize = 64 * 1024 * 1024;
+
+/// The payload is compressed with snappy
+const COMPRESSED: u8 = 0b1;
+
+/// A received event
+#[derive(Debug, PartialEq)]
+pub(crate) struct Frame {
+    /// artefact id of the pipeline the event is for
+    pub(crate) pipeline: String,
+    pub(crate) data: Value<'static>,
+    pub(crate) meta: Value<'static>,
+}
+
+/// Encodes an event for `pipeline`, compressing payloads of more than
+/// `compress_above` bytes
+pub(crate) fn encode(
+    pipeline: &str,
+    data: &Value,
+    meta: &Value,
+    compress_above: Option<usize>,
+) -> Result<Vec<u8>> {
+    let mut payload = Value::object_with_capacity(3);
+    payload.try_insert("pipeline", pipeline);
+    payload.try_insert("data", data.clone());
+    payload.try_insert("meta", meta.clone());
+    let mut payload = payload.encode().into_bytes();
+    let mut flags = 0;
+    if compress_above.map_or(false, |above| payload.len() > above) {
+        payload = snap::raw::Encoder::new().compress_vec(&payload)?;
+        flags |= COMPRESSED;
+    }
+    let len = u32::try_from(payload.len() + 1)?;
+    let mut frame = Vec::with_capacity(payload.len() + 5);
+    frame.extend_from_slice(&len.to_be_bytes());
+    frame.push(flags);
+    frame.append(&mut payload);
+    Ok(frame)
+}
+
+/// Reads the next frame, `None` if the connection was closed
+pub(crate) async fn read<R: AsyncRead + Unpin>(reader: &mut R) -> Result<Option<Frame>> {
+    let mut len = [0_u8; 4];
+    match reader.read_exact(&mut len).await {
+        Ok(()) => (),
+        Err(e) if e.kind() == std::io::ErrorKind::UnexpectedEof => return Ok(None),
+        Err(e) => return Err(e.into()),
+    }
+    let len = u32::from_be_bytes(len) as usize;
+    if len == 0 || len > MAX_FRAME {
+        return Err(format!("Invalid interconnect frame length {}", len).into());
+    }
+    let mut flags = [0_u8; 1];
+    reader.read_exact(&mut flags).await?;
+    let [flags] = flags;
+    let mut payload = vec![0_u8; len - 1];
+    reader.read_exact(&mut payload).await?;
+    if flags & COMPRESSED != 0 {
+        payload = snap::raw::Decoder::new().decompress_vec(&payload)?;
+    }
+    let value = tremor_value::parse_to_value(&mut payload)?;
+    let pipeline = value
+        .get_str("pipeline")
+        .ok_or_else(|| Error::from("Interconnect frame without pipeline"))?
+        .to_string();
+    Ok(Some(Frame {
+        pipeline,
+        data: value
+            .get("data")
+            .map_or_else(Value::null, Value::clone_static),
+        meta: value
+            .get("meta")
+            .map_or_else(Value::object, Value::clone_static),
+    }))
+}
+
+/// Acknowledges all frames up to the `accepted`th
+pub(crate) async fn ack<W: AsyncWrite + Unpin>(writer: &mut W, accepted: u64) -> Result<()> {
+    Ok(writer.write_all(&accepted.to_be_bytes()).await?)
+}
+
+/// Reads the next acknowledgement, the number of frames accepted so far
+pub(crate) async fn read_ack<R: AsyncRead + Unpin>(reader: &mut R) -> Result<u64> {
+    let mut accepted = [0_u8; 8];
+    reader.read_exact(&mut accepted).await?;
+    Ok(u64::from_be_bytes(accepted))
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use futures::io::Cursor;
+    use tremor_value::literal;
+
+    #[async_std::test]
+    async fn frames() -> Result<()> {
+        let data = literal!({"snot": "badger", "values": [1, 2, 3]});
+        let meta = literal!({"kafka": {"topic": "edge"}});
+        let large = Value::from("x".repeat(4096));
+
+        let mut wire = encode("aggregate", &data, &meta, Some(1024))?;
+        let plain = wire.len();
+        let compressed = encode("aggregate", &large, &Value::object(), Some(1024))?;
+        assert!(compressed.len() < 4096);
+        assert_eq!(compressed.get(4), Some(&COMPRESSED));
+        wire.extend(compressed);
+
+        let mut reader = Cursor::new(wire);
+        let frame = read(&mut reader).await?.ok_or("no frame")?;
+        assert_eq!(frame.pipeline, "aggregate");
+        assert_eq!(frame.data, data);
+        assert_eq!(frame.meta, meta);
+        assert_eq!(reader.position(), plain as u64);
+        let frame = read(&mut reader).await?.ok_or("no frame")?;
+        assert_eq!(frame.data, large);
+        assert_eq!(read(&mut reader).await?, None);
+        Ok(())
+    }
+
+    #[async_std::test]
+    async fn invalid_frames() -> Result<()> {
+        let mut oversized = Cursor::new(u32::MAX.to_be_bytes().to_vec());
+        assert!(read(&mut oversized).await.is_err());
+        let mut truncated = Cursor::new(vec![0, 0, 0, 8, 0]);
+        assert!(read(&mut truncated).await.is_err());
+        Ok(())
+    }
+
+    #[async_std::test]
+    async fn acks() -> Result<()> {
+        let mut wire = Cursor::new(Vec::new());
+        ack(&mut wire, 1).await?;
+        ack(&mut wire, 42).await?;
+        wire.set_position(0);
+        assert_eq!(read_ack(&mut wire).await?, 1);
+        assert_eq!(read_ack(&mut wire).await?, 42);
+        Ok(())
+    }
+}
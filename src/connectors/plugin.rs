@@ -0,0 +1,330 @@
+// Copyright 2020-2021, The Tremor Team
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+//! Onramps and offramps shipped as separately compiled sidecar executables,
+//! so connectors can be released independently of tremor.
+//!
+//! Plugins are discovered in the subdirectories of a plugin directory with a
+//! `plugin.yaml` manifest:
+//!
+//! ```yaml
+//! name: sqs              # the onramp and offramp `type` the plugin provides
+//! command: ./tremor-sqs  # relative to the manifest
+//! args: []
+//! onramp: true
+//! offramp: true
+//! transactional: false   # the onramp acks and fails its events
+//! codec: json            # the default codec
+//! ```
+//!
+//! Every onramp or offramp starts its own sidecar process and talks to it in
+//! line delimited JSON over its stdin and stdout, its stderr is inherited.
+//! Payloads are base64 encoded.
+//!
+//! * The first line is `{"init": {"kind": "onramp" | "offramp", "id": <url>,
+//!   "config": <config>}}`, answered with `{"ok": true}`.
+//! * Onramps ask for data with `{"pull": <event id>}`, answered with
+//!   `{"data": <payload>, "meta": <metadata>}`, or `{"empty": <ms>}` to be
+//!   asked again after that many milliseconds. With `transactional` acks and
+//!   fails of events are sent before the next pull as `{"ack": <event id>}` and
+//!   `{"fail": <event id>}`, without an answer.
+//! * Offramps send every encoded event as `{"data": <payload>, "meta":
+//!   <metadata>}`, answered with `{"ok": true}` once it is delivered.
+//!
+//! Any request can be answered with `{"error": <message>}` instead.
+
+use crate::errors::{Error, Result};
+use async_std::io::BufReader;
+use async_std::prelude::*;
+use async_std::process::{Child, ChildStdin, ChildStdout, Command, Stdio};
+use serde_yaml::Value as YamlValue;
+use std::collections::HashMap;
+use std::path::{Path, PathBuf};
+use std::sync::RwLock;
+use tremor_script::prelude::*;
+use tremor_value::literal;
+
+const MANIFEST: &str = "plugin.yaml";
+
+lazy_static! {
+    static ref PLUGINS: RwLock<HashMap<String, Manifest>> = RwLock::new(HashMap::new());
+}
+
+/// Which side of a plugin to start
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub(crate) enum Kind {
+    Onramp,
+    Offramp,
+}
+
+impl Kind {
+    fn as_str(self) -> &'static str {
+        match self {
+            Self::Onramp => "onramp",
+            Self::Offramp => "offramp",
+        }
+    }
+}
+
+/// The manifest of a plugin
+#[derive(Debug, Clone, Deserialize, PartialEq)]
+#[serde(deny_unknown_fields)]
+pub struct Manifest {
+    /// The onramp and offramp type the plugin provides
+    pub name: String,
+    /// The executable of the sidecar, relative to the manifest
+    pub command: String,
+    /// Arguments of the sidecar
+    #[serde(default)]
+    pub args: Vec<String>,
+    /// The plugin provides an onramp
+    #[serde(default)]
+    pub onramp: bool,
+    /// The plugin provides an offramp
+    #[serde(default)]
+    pub offramp: bool,
+    /// The onramp acks and fails its events
+    #[serde(default)]
+    pub transactional: bool,
+    /// The default codec (default: `json`)
+    #[serde(default = "d_codec")]
+    pub codec: String,
+    /// The directory of the manifest
+    #[serde(skip)]
+    pub dir: PathBuf,
+}
+
+fn d_codec() -> String {
+    "json".to_string()
+}
+
+impl Manifest {
+    fn provides(&self, kind: Kind) -> bool {
+        match kind {
+            Kind::Onramp => self.onramp,
+            Kind::Offramp => self.offramp,
+        }
+    }
+}
+
+/// Registers the plugins in the subdirectories of `dir`, returning their number
+///
+/// # Errors
+///  * if a manifest is invalid or provides an already registered type
+pub fn discover(dir: &Path) -> Result<usize> {
+    let mut found = Vec::new();
+    for entry in std::fs::read_dir(dir)? {
+        let path = entry?.path().join(MANIFEST);
+        if path.is_file() {
+            let raw = std::fs::read_to_string(&path)?;
+            let mut manifest: Manifest = serde_yaml::from_str(&raw).map_err(|e| {
+                Error::from(format!(
+                    "Invalid plugin manifest `{}`: {}",
+                    path.display(),
+                    e
+                ))
+            })?;
+            manifest.dir = path.parent().map(Path::to_path_buf).unwrap_or_default();
+            found.push(manifest);
+        }
+    }
+    let mut plugins = PLUGINS.write()?;
+    let n = found.len();
+    for manifest in found {
+        if let Some(other) = plugins.get(&manifest.name) {
+            return Err(format!(
+                "Plugin {} in `{}` is already provided by `{}`",
+                manifest.name,
+                manifest.dir.display(),
+                other.dir.display()
+            )
+            .into());
+        }
+        info!(
+            "Discovered connector plugin {} in `{}`",
+            manifest.name,
+            manifest.dir.display()
+        );
+        plugins.insert(manifest.name.clone(), manifest);
+    }
+    Ok(n)
+}
+
+/// The plugin providing an onramp or offramp type
+pub(crate) fn find(name: &str, kind: Kind) -> Option<Manifest> {
+    PLUGINS
+        .read()
+        .ok()?
+        .get(name)
+        .filter(|m| m.provides(kind))
+        .cloned()
+}
+
+/// A running sidecar
+pub(crate) struct Sidecar {
+    name: String,
+    // killed once the sidecar is dropped
+    child: Child,
+    stdin: ChildStdin,
+    stdout: BufReader<ChildStdout>,
+}
+
+impl Sidecar {
+    /// Starts the sidecar of an onramp or offramp and initializes it
+    pub(crate) async fn start(
+        manifest: &Manifest,
+        kind: Kind,
+        id: &str,
+        config: &Option<YamlValue>,
+    ) -> Result<Self> {
+        let mut child = Command::new(manifest.dir.join(&manifest.command))
+            .args(&manifest.args)
+            .current_dir(&manifest.dir)
+            .stdin(Stdio::piped())
+            .stdout(Stdio::piped())
+            .stderr(Stdio::inherit())
+            .kill_on_drop(true)
+            .spawn()
+            .map_err(|e| Error::from(format!("Failed to start plugin {}: {}", manifest.name, e)))?;
+        let (stdin, stdout) = match (child.stdin.take(), child.stdout.take()) {
+            (Some(stdin), Some(stdout)) => (stdin, stdout),
+            _ => return Err(format!("Plugin {} has no stdio", manifest.name).into()),
+        };
+        let mut sidecar = Self {
+            name: manifest.name.clone(),
+            child,
+            stdin,
+            stdout: BufReader::new(stdout),
+        };
+        let config = config
+            .as_ref()
+            .map(simd_json::serde::to_owned_value)
+            .transpose()?
+            .map_or_else(Value::null, Value::from);
+        sidecar
+            .request(&literal!({
+                "init": {
+                    "kind": kind.as_str(),
+                    "id": id.to_string(),
+                    "config": config,
+                }
+            }))
+            .await?;
+        Ok(sidecar)
+    }
+
+    /// If the sidecar process exited
+    pub(crate) fn exited(&mut self) -> bool {
+        !matches!(self.child.try_status(), Ok(None))
+    }
+
+    /// Sends a message without waiting for an answer
+    pub(crate) async fn send(&mut self, msg: &Value<'_>) -> Result<()> {
+        let mut line = msg.encode();
+        line.push('\n');
+        self.stdin.write_all(line.as_bytes()).await?;
+        self.stdin.flush().await?;
+        Ok(())
+    }
+
+    /// Sends a request and returns the answer
+    pub(crate) async fn request(&mut self, msg: &Value<'_>) -> Result<Value<'static>> {
+        self.send(msg).await?;
+        let mut line = String::new();
+        if self.stdout.read_line(&mut line).await? == 0 {
+            return Err(format!("Plugin {} exited", self.name).into());
+        }
+        let mut line = line.into_bytes();
+        let answer = Value::from(simd_json::to_owned_value(&mut line)?);
+        if let Some(error) = answer.get_str("error") {
+            Err(format!("Plugin {}: {}", self.name, error).into())
+        } else {
+            Ok(answer)
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    fn plugin(dir: &Path, name: &str, manifest: &str) -> Result<()> {
+        let dir = dir.join(name);
+        std::fs::create_dir(&dir)?;
+        std::fs::write(dir.join(MANIFEST), manifest)?;
+        Ok(())
+    }
+
+    #[test]
+    fn discovery() -> Result<()> {
+        let dir = tempfile::tempdir()?;
+        plugin(
+            dir.path(),
+            "a",
+            "name: test-a\ncommand: ./a\nonramp: true\n",
+        )?;
+        plugin(
+            dir.path(),
+            "b",
+            "name: test-b\ncommand: ./b\nofframp: true\ncodec: string\n",
+        )?;
+        std::fs::create_dir(dir.path().join("c"))?;
+        assert_eq!(2, discover(dir.path())?);
+
+        let a = find("test-a", Kind::Onramp).ok_or("test-a not found")?;
+        assert_eq!("json", a.codec);
+        assert_eq!(dir.path().join("a"), a.dir);
+        assert!(find("test-a", Kind::Offramp).is_none());
+        assert!(find("test-b", Kind::Offramp).is_some());
+        // types can only be provided once
+        assert!(discover(dir.path()).is_err());
+        Ok(())
+    }
+
+    #[cfg(unix)]
+    #[async_std::test]
+    async fn sidecar() -> Result<()> {
+        use std::os::unix::fs::PermissionsExt;
+        let dir = tempfile::tempdir()?;
+        let script = dir.path().join("echo.sh");
+        std::fs::write(
+            &script,
+            "#!/bin/sh\nwhile read -r line; do\n  case \"$line\" in\n    *fail*) echo '{\"error\": \"snot\"}';;\n    *) echo '{\"ok\": true}';;\n  esac\ndone\n",
+        )?;
+        std::fs::set_permissions(&script, std::fs::Permissions::from_mode(0o755))?;
+        let manifest = Manifest {
+            name: "echo".to_string(),
+            command: "./echo.sh".to_string(),
+            args: vec![],
+            onramp: true,
+            offramp: false,
+            transactional: false,
+            codec: d_codec(),
+            dir: dir.path().to_path_buf(),
+        };
+        let mut sidecar = Sidecar::start(
+            &manifest,
+            Kind::Onramp,
+            "tremor://localhost/onramp/echo/01",
+            &None,
+        )
+        .await?;
+        assert_eq!(
+            literal!({"ok": true}),
+            sidecar.request(&literal!({"pull": 1})).await?
+        );
+        assert!(sidecar.request(&literal!({"fail": 1})).await.is_err());
+        Ok(())
+    }
+}
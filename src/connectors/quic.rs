@@ -0,0 +1,128 @@
+// Copyright 2020-2021, The Tremor Team
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+//! QUIC endpoints of the quic onramp and offramp (experimental).
+//!
+//! In `raw` mode each message is sent on its own unidirectional stream, so a
+//! lost packet only delays the message it belongs to. In `http3` mode each
+//! message is the body of an HTTP/3 `POST` request instead. Clients keep the
+//! session tickets of the servers they connected to and, if enabled on both
+//! ends, send 0-RTT data when reconnecting. 0-RTT data can be replayed by an
+//! attacker, so it is off by default.
+
+use crate::connectors::tls::TlsConfig;
+use crate::errors::Result;
+use rustls::ProtocolVersion;
+use serde::Deserialize;
+use std::sync::Arc;
+
+/// How messages are carried over a QUIC connection
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub(crate) enum Mode {
+    /// one unidirectional stream per message
+    Raw,
+    /// one HTTP/3 `POST` request per message
+    Http3,
+}
+
+impl Default for Mode {
+    fn default() -> Self {
+        Self::Raw
+    }
+}
+
+impl Mode {
+    /// ALPN protocol used if none is configured, QUIC requires one
+    fn default_alpn(self) -> &'static str {
+        match self {
+            Self::Raw => "tremor",
+            Self::Http3 => "h3",
+        }
+    }
+}
+
+/// Falls back to the default ALPN protocol of the mode
+fn alpn(alpn_protocols: &mut Vec<Vec<u8>>, mode: Mode) {
+    if alpn_protocols.is_empty() {
+        alpn_protocols.push(mode.default_alpn().as_bytes().to_vec());
+    }
+}
+
+/// Endpoint configuration for servers, accepting 0-RTT data of resumed
+/// sessions if `zero_rtt` is set
+pub(crate) fn server_config(
+    tls: &TlsConfig,
+    mode: Mode,
+    zero_rtt: bool,
+) -> Result<quinn::ServerConfig> {
+    let mut crypto = tls.server_config()?;
+    crypto.versions = vec![ProtocolVersion::TLSv1_3];
+    alpn(&mut crypto.alpn_protocols, mode);
+    if zero_rtt {
+        // QUIC requires servers that accept 0-RTT to allow 0xffffffff bytes
+        crypto.max_early_data_size = u32::MAX;
+    }
+    let mut config = quinn::ServerConfig::default();
+    config.crypto = Arc::new(crypto);
+    Ok(config)
+}
+
+/// Endpoint configuration for clients, sessions are resumed with 0-RTT if
+/// `zero_rtt` is set. The session tickets are stored in the configuration,
+/// so it needs to be reused for reconnects.
+pub(crate) fn client_config(
+    tls: &TlsConfig,
+    mode: Mode,
+    zero_rtt: bool,
+) -> Result<quinn::ClientConfig> {
+    let mut crypto = tls.client_config()?;
+    crypto.versions = vec![ProtocolVersion::TLSv1_3];
+    alpn(&mut crypto.alpn_protocols, mode);
+    crypto.enable_early_data = zero_rtt;
+    let mut config = quinn::ClientConfig::default();
+    config.crypto = Arc::new(crypto);
+    Ok(config)
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn client() -> Result<()> {
+        let tls: TlsConfig = serde_yaml::from_str("min_version: 1.2")?;
+        let config = client_config(&tls, Mode::Raw, true)?;
+        assert_eq!(config.crypto.versions, vec![ProtocolVersion::TLSv1_3]);
+        assert_eq!(config.crypto.alpn_protocols, vec![b"tremor".to_vec()]);
+        assert!(config.crypto.enable_early_data);
+
+        let config = client_config(&tls, Mode::Http3, false)?;
+        assert_eq!(config.crypto.alpn_protocols, vec![b"h3".to_vec()]);
+
+        let tls: TlsConfig = serde_yaml::from_str("alpn: [snot]")?;
+        let config = client_config(&tls, Mode::Http3, false)?;
+        assert_eq!(config.crypto.alpn_protocols, vec![b"snot".to_vec()]);
+        assert!(!config.crypto.enable_early_data);
+        Ok(())
+    }
+
+    #[test]
+    fn mode() -> Result<()> {
+        assert_eq!(Mode::default(), Mode::Raw);
+        assert_eq!(serde_yaml::from_str::<Mode>("http3")?, Mode::Http3);
+        assert!(serde_yaml::from_str::<Mode>("h2").is_err());
+        Ok(())
+    }
+}
@@ -0,0 +1,433 @@
+// Copyright 2020-2021, The Tremor Team
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+//! TLS configuration shared by the tcp, ws, rest, kafka, elastic and quic connectors.
+
+use crate::errors::{Error, ErrorKind, Result};
+use async_tls::{TlsAcceptor, TlsConnector};
+use rustls::internal::pemfile::{certs, pkcs8_private_keys, rsa_private_keys};
+use rustls::{
+    AllowAnyAuthenticatedClient, Certificate, ClientCertVerified, ClientCertVerifier, ClientConfig,
+    DistinguishedNames, NoClientAuth, PrivateKey, ProtocolVersion, RootCertStore,
+    ServerCertVerified, ServerCertVerifier, ServerConfig, TLSError,
+};
+use serde::{Deserialize, Deserializer};
+use std::cell::RefCell;
+use std::io::BufReader;
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+
+/// Minimum TLS version
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum TlsVersion {
+    /// TLS 1.2
+    V1_2,
+    /// TLS 1.3
+    V1_3,
+}
+
+impl Default for TlsVersion {
+    fn default() -> Self {
+        Self::V1_2
+    }
+}
+
+impl<'de> Deserialize<'de> for TlsVersion {
+    fn deserialize<D>(deserializer: D) -> std::result::Result<Self, D::Error>
+    where
+        D: Deserializer<'de>,
+    {
+        use serde::de::Error;
+        // `1.2` in yaml is a number, `"1.2"` a string
+        #[derive(Deserialize)]
+        #[serde(untagged)]
+        enum Raw {
+            Str(String),
+            Num(f64),
+        }
+        let version = match Raw::deserialize(deserializer)? {
+            Raw::Str(s) => s,
+            Raw::Num(n) => n.to_string(),
+        };
+        match version.as_str() {
+            "1.2" => Ok(Self::V1_2),
+            "1.3" => Ok(Self::V1_3),
+            other => Err(D::Error::custom(format!(
+                "Unsupported TLS version `{}`, use `1.2` or `1.3`",
+                other
+            ))),
+        }
+    }
+}
+
+/// TLS configuration of a connector
+#[derive(Debug, Clone, Default, Deserialize)]
+pub struct TlsConfig {
+    /// CA bundle (PEM) to verify the peer with, clients use the webpki roots
+    /// if not set. For servers this enables client certificate verification.
+    #[serde(default)]
+    pub cafile: Option<PathBuf>,
+    /// Certificate chain (PEM) presented to the peer, required for servers
+    #[serde(default)]
+    pub cert: Option<PathBuf>,
+    /// Private key (PEM, PKCS8 or RSA) of `cert`
+    #[serde(default)]
+    pub key: Option<PathBuf>,
+    /// Server name used for SNI and certificate verification, defaults to the host
+    #[serde(default)]
+    pub domain: Option<String>,
+    /// ALPN protocols to negotiate, e.g. `h2` or `http/1.1`
+    #[serde(default)]
+    pub alpn: Vec<String>,
+    /// Minimum TLS version, `1.2` (default) or `1.3`
+    #[serde(default)]
+    pub min_version: TlsVersion,
+    /// Skip verification of the server certificate, for clients only.
+    /// Never use this in production.
+    #[serde(default)]
+    pub insecure_skip_verify: bool,
+}
+
+/// Accepts any server certificate
+struct NoVerification;
+
+impl ServerCertVerifier for NoVerification {
+    fn verify_server_cert(
+        &self,
+        _roots: &RootCertStore,
+        _presented_certs: &[Certificate],
+        _dns_name: webpki::DNSNameRef,
+        _ocsp_response: &[u8],
+    ) -> std::result::Result<ServerCertVerified, TLSError> {
+        Ok(ServerCertVerified::assertion())
+    }
+}
+
+impl TlsConfig {
+    /// The server name to verify, `domain` if set, `host` otherwise
+    pub(crate) fn domain<'a>(&'a self, host: &'a str) -> &'a str {
+        self.domain.as_deref().unwrap_or(host)
+    }
+
+    /// Fails if any of `options` is set, for connectors that can't honour them
+    pub(crate) fn unsupported(&self, connector: &str, options: &[&str]) -> Result<()> {
+        for option in options {
+            let set = match *option {
+                "domain" => self.domain.is_some(),
+                "alpn" => !self.alpn.is_empty(),
+                "min_version" => self.min_version != TlsVersion::V1_2,
+                "insecure_skip_verify" => self.insecure_skip_verify,
+                _ => false,
+            };
+            if set {
+                return Err(Error::from(ErrorKind::TLSError(format!(
+                    "`{}` is not supported by the {} connector",
+                    option, connector
+                ))));
+            }
+        }
+        Ok(())
+    }
+
+    /// The client certificate and key, if configured
+    fn identity(&self) -> Result<Option<(Vec<Certificate>, PrivateKey)>> {
+        match (&self.cert, &self.key) {
+            (Some(cert), Some(key)) => Ok(Some((load_certs(cert)?, load_keys(key)?))),
+            (None, None) => Ok(None),
+            _ => Err(Error::from(ErrorKind::TLSError(
+                "`cert` and `key` need to be configured together".to_string(),
+            ))),
+        }
+    }
+
+    fn alpn_protocols(&self) -> Vec<Vec<u8>> {
+        self.alpn.iter().map(|p| p.as_bytes().to_vec()).collect()
+    }
+
+    fn versions(&self) -> Vec<ProtocolVersion> {
+        match self.min_version {
+            TlsVersion::V1_2 => vec![ProtocolVersion::TLSv1_3, ProtocolVersion::TLSv1_2],
+            TlsVersion::V1_3 => vec![ProtocolVersion::TLSv1_3],
+        }
+    }
+
+    /// rustls configuration for clients
+    pub(crate) fn client_config(&self) -> Result<ClientConfig> {
+        let mut config = ClientConfig::new();
+        if let Some(cafile) = &self.cafile {
+            config.root_store = load_roots(cafile)?;
+        } else {
+            config
+                .root_store
+                .add_server_trust_anchors(&webpki_roots::TLS_SERVER_ROOTS);
+        }
+        if let Some((certs, key)) = self.identity()? {
+            config.set_single_client_cert(certs, key)?;
+        }
+        config.alpn_protocols = self.alpn_protocols();
+        config.versions = self.versions();
+        if self.insecure_skip_verify {
+            config
+                .dangerous()
+                .set_certificate_verifier(Arc::new(NoVerification));
+        }
+        Ok(config)
+    }
+
+    /// Connector for clients
+    pub(crate) fn connector(&self) -> Result<TlsConnector> {
+        Ok(TlsConnector::from(Arc::new(self.client_config()?)))
+    }
+
+    /// rustls configuration for servers, requiring client certificates
+    /// signed by `cafile` if it is set
+    pub(crate) fn server_config(&self) -> Result<ServerConfig> {
+        let client_auth: Arc<dyn ClientCertVerifier> = if let Some(cafile) = &self.cafile {
+            Arc::new(RecordingVerifier(AllowAnyAuthenticatedClient::new(
+                load_roots(cafile)?,
+            )))
+        } else {
+            NoClientAuth::new()
+        };
+        let (certs, key) = self.identity()?.ok_or_else(|| {
+            Error::from(ErrorKind::TLSError(
+                "`cert` and `key` are required for servers".to_string(),
+            ))
+        })?;
+        let mut config = ServerConfig::new(client_auth);
+        config.set_single_cert(certs, key)?;
+        config.alpn_protocols = self.alpn_protocols();
+        config.versions = self.versions();
+        Ok(config)
+    }
+
+    /// Acceptor for servers
+    pub(crate) fn acceptor(&self) -> Result<TlsAcceptor> {
+        Ok(TlsAcceptor::from(Arc::new(self.server_config()?)))
+    }
+
+    /// Configures librdkafka, which uses OpenSSL, for SSL
+    pub(crate) fn configure_rdkafka(&self, config: &mut rdkafka::ClientConfig) -> Result<()> {
+        self.unsupported("kafka", &["domain", "alpn", "min_version"])?;
+        config.set("security.protocol", "SSL");
+        if let Some(cafile) = &self.cafile {
+            config.set("ssl.ca.location", &*cafile.to_string_lossy());
+        }
+        if let Some(cert) = &self.cert {
+            config.set("ssl.certificate.location", &*cert.to_string_lossy());
+        }
+        if let Some(key) = &self.key {
+            config.set("ssl.key.location", &*key.to_string_lossy());
+        }
+        if self.insecure_skip_verify {
+            config
+                .set("enable.ssl.certificate.verification", "false")
+                .set("ssl.endpoint.identification.algorithm", "none");
+        }
+        Ok(())
+    }
+}
+
+fn load_roots(path: &Path) -> Result<RootCertStore> {
+    let mut roots = RootCertStore::empty();
+    let mut reader = BufReader::new(tremor_common::file::open(path)?);
+    roots.add_pem_file(&mut reader).map_err(|_e| {
+        Error::from(ErrorKind::TLSError(format!(
+            "Invalid certificate in {}",
+            path.display()
+        )))
+    })?;
+    Ok(roots)
+}
+
+// Load the passed certificates file
+fn load_certs(path: &Path) -> Result<Vec<Certificate>> {
+    let certfile = tremor_common::file::open(path)?;
+    let mut reader = BufReader::new(certfile);
+    certs(&mut reader).map_err(|_| {
+        Error::from(ErrorKind::TLSError(format!(
+            "Invalid certificate in {}",
+            path.display()
+        )))
+    })
+}
+
+// Load the passed keys file
+fn load_keys(path: &Path) -> Result<PrivateKey> {
+    // prefer to load pkcs8 keys
+    // this will only error if we have invalid pkcs8 key base64 or we couldnt read the file.
+    let mut keys: Vec<PrivateKey> = {
+        let keyfile = tremor_common::file::open(path)?;
+        let mut reader = BufReader::new(keyfile);
+        pkcs8_private_keys(&mut reader).map_err(|_e| {
+            Error::from(ErrorKind::TLSError(format!(
+                "Invalid PKCS8 Private key in {}",
+                path.display()
+            )))
+        })
+    }?;
+
+    // only attempt to load as RSA keys if file has no pkcs8 keys
+    if keys.is_empty() {
+        let keyfile = tremor_common::file::open(path)?;
+        let mut reader = BufReader::new(keyfile);
+        keys = rsa_private_keys(&mut reader).map_err(|_e| {
+            Error::from(ErrorKind::TLSError(format!(
+                "Invalid RSA Private key in {}",
+                path.display()
+            )))
+        })?;
+    }
+
+    if keys.is_empty() {
+        Err(Error::from(ErrorKind::TLSError(format!(
+            "No valid private keys (RSA or PKCS8) found in {}",
+            path.display()
+        ))))
+    } else {
+        // ALLOW: we know keys is not empty
+        Ok(keys.remove(0))
+    }
+}
+
+/// rustls configuration for clients from PEM data instead of files, e.g. of
+/// a kubeconfig
+pub(crate) fn client_config_from_pem(
+    ca: Option<&[u8]>,
+    identity: Option<(&[u8], &[u8])>,
+    insecure_skip_verify: bool,
+) -> Result<ClientConfig> {
+    let tls_error = |what: &str| Error::from(ErrorKind::TLSError(format!("Invalid {}", what)));
+    let mut config = ClientConfig::new();
+    if let Some(mut ca) = ca {
+        config
+            .root_store
+            .add_pem_file(&mut ca)
+            .map_err(|_e| tls_error("CA certificate"))?;
+    } else {
+        config
+            .root_store
+            .add_server_trust_anchors(&webpki_roots::TLS_SERVER_ROOTS);
+    }
+    if let Some((mut cert, key)) = identity {
+        let certs = certs(&mut cert).map_err(|_e| tls_error("certificate"))?;
+        let mut keys = pkcs8_private_keys(&mut &*key).map_err(|_e| tls_error("PKCS8 key"))?;
+        if keys.is_empty() {
+            keys = rsa_private_keys(&mut &*key).map_err(|_e| tls_error("RSA key"))?;
+        }
+        if keys.is_empty() {
+            return Err(tls_error("private key, no RSA or PKCS8 key found"));
+        }
+        config.set_single_client_cert(certs, keys.remove(0))?;
+    }
+    if insecure_skip_verify {
+        config
+            .dangerous()
+            .set_certificate_verifier(Arc::new(NoVerification));
+    }
+    Ok(config)
+}
+
+async_std::task_local! {
+    /// Common name of the client certificate verified in the current task
+    static PEER_CN: RefCell<Option<String>> = RefCell::new(None);
+}
+
+/// The common name of the client certificate verified by an acceptor in the
+/// current task, handshakes run in the task awaiting the accept
+pub(crate) fn peer_cn() -> Option<String> {
+    PEER_CN.try_with(|cn| cn.borrow_mut().take()).ok().flatten()
+}
+
+fn common_name(der: &[u8]) -> Option<String> {
+    let (_, cert) = x509_parser::parse_x509_certificate(der).ok()?;
+    let cn = cert.subject().iter_common_name().next()?;
+    cn.as_str().ok().map(ToString::to_string)
+}
+
+/// Verifies client certificates with the wrapped verifier and records the
+/// common name of verified ones for [`peer_cn`]
+struct RecordingVerifier(Arc<dyn ClientCertVerifier>);
+
+impl ClientCertVerifier for RecordingVerifier {
+    fn offer_client_auth(&self) -> bool {
+        self.0.offer_client_auth()
+    }
+
+    fn client_auth_mandatory(&self, sni: Option<&webpki::DNSName>) -> Option<bool> {
+        self.0.client_auth_mandatory(sni)
+    }
+
+    fn client_auth_root_subjects(
+        &self,
+        sni: Option<&webpki::DNSName>,
+    ) -> Option<DistinguishedNames> {
+        self.0.client_auth_root_subjects(sni)
+    }
+
+    fn verify_client_cert(
+        &self,
+        presented_certs: &[Certificate],
+        sni: Option<&webpki::DNSName>,
+    ) -> std::result::Result<ClientCertVerified, TLSError> {
+        let verified = self.0.verify_client_cert(presented_certs, sni)?;
+        let cn = presented_certs
+            .first()
+            .and_then(|cert| common_name(&cert.0));
+        // outside of a task there is no one to pick it up
+        let _ = PEER_CN.try_with(|peer_cn| *peer_cn.borrow_mut() = cn);
+        Ok(verified)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn config() -> Result<()> {
+        let tls: TlsConfig = serde_yaml::from_str("min_version: 1.3\nalpn: [h2]\n")?;
+        assert_eq!(tls.min_version, TlsVersion::V1_3);
+        assert_eq!(tls.domain("localhost"), "localhost");
+        assert_eq!(tls.versions(), vec![ProtocolVersion::TLSv1_3]);
+        assert!(tls.unsupported("kafka", &["domain"]).is_ok());
+        assert!(tls.unsupported("kafka", &["alpn"]).is_err());
+
+        let tls: TlsConfig = serde_yaml::from_str("min_version: \"1.2\"\ndomain: tremor\n")?;
+        assert_eq!(tls.min_version, TlsVersion::V1_2);
+        assert_eq!(tls.domain("localhost"), "tremor");
+        assert!(serde_yaml::from_str::<TlsConfig>("min_version: 1.1\n").is_err());
+
+        let tls: TlsConfig = serde_yaml::from_str("cert: ./cert.pem\n")?;
+        assert!(tls.client_config().is_err());
+        Ok(())
+    }
+
+    #[test]
+    fn rdkafka() -> Result<()> {
+        let tls: TlsConfig = serde_yaml::from_str(
+            "cafile: ca.pem\ncert: cert.pem\nkey: key.pem\ninsecure_skip_verify: true\n",
+        )?;
+        let mut config = rdkafka::ClientConfig::new();
+        tls.configure_rdkafka(&mut config)?;
+        assert_eq!(config.get("security.protocol"), Some("SSL"));
+        assert_eq!(config.get("ssl.ca.location"), Some("ca.pem"));
+        assert_eq!(config.get("ssl.key.location"), Some("key.pem"));
+        assert_eq!(
+            config.get("ssl.endpoint.identification.algorithm"),
+            Some("none")
+        );
+        Ok(())
+    }
+}
@@ -0,0 +1,331 @@
+// Copyright 2020-2021, The Tremor Team
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+//! Step debugging of pipeline instances over a websocket
+//!
+//! With `tremor server run --debug-endpoint <host:port>` clients connect to
+//! `ws://<host:port>/pipeline/<artefact>/<instance>` to attach to a running
+//! pipeline instance, one client at a time. Clients send JSON commands:
+//!
+//! * `{"break": {"node": "<id>"}}` / `{"break": {"line": <line>}}` - pause
+//!   before an event is handed to the node, or to the node created from the
+//!   statement spanning the line of the query
+//! * `{"clear": {"node": "<id>"}}` / `{"clear": {"line": <line>}}` - remove a
+//!   breakpoint
+//! * `"pause"` - pause before the next node an event is handed to
+//! * `"step"` - hand the event to the node and pause before the next one
+//! * `"continue"` - run until the next breakpoint
+//! * `"inspect"` - report the status
+//! * `"detach"` - resume the pipeline and end the session
+//!
+//! and receive `{"status": {...}}` with the breakpoints and, while paused,
+//! the node, event, metadata and state at hand after every command and
+//! whenever the pipeline pauses, or `{"error": "..."}`.
+//!
+//! A paused pipeline processes no other events, signals or management
+//! messages until it is stepped through or continued.
+
+use crate::errors::{Error, Result};
+use crate::pipeline::{Eventset, MgmtMsg};
+use crate::system::World;
+use crate::url::TremorUrl;
+use async_channel::{bounded, Receiver, Sender, TryRecvError};
+use async_std::net::{TcpListener, TcpStream};
+use async_std::task;
+use async_tungstenite::tungstenite::handshake::server::{ErrorResponse, Request, Response};
+use async_tungstenite::tungstenite::Message;
+use futures::{SinkExt, StreamExt};
+use std::collections::BTreeMap;
+use std::sync::Mutex;
+use tremor_pipeline::debug::{Breakpoint, Debugger, Paused};
+use tremor_pipeline::errors::Result as PipelineResult;
+use tremor_pipeline::ExecutableGraph;
+
+lazy_static! {
+    /// status of the debugged pipelines by instance
+    static ref SESSIONS: Mutex<BTreeMap<String, Status>> = Mutex::new(BTreeMap::new());
+}
+
+/// A command of a debugging client
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Command {
+    /// adds a breakpoint
+    Break(Breakpoint),
+    /// removes a breakpoint
+    Clear(Breakpoint),
+    /// pauses before the next node
+    Pause,
+    /// steps to the next node
+    Step,
+    /// runs until the next breakpoint
+    Continue,
+    /// reports the status
+    Inspect,
+    /// ends the session
+    Detach,
+}
+
+/// Debugging status of a pipeline instance
+#[derive(Clone, Debug, PartialEq, Serialize)]
+pub struct Status {
+    /// the pipeline instance
+    pub pipeline: String,
+    /// active breakpoints
+    pub breakpoints: Vec<Breakpoint>,
+    /// if the pipeline pauses before every node
+    pub stepping: bool,
+    /// where the pipeline is paused
+    pub paused: Option<Paused>,
+}
+
+/// An update sent to a debugging client
+#[derive(Clone, Debug, PartialEq, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Update {
+    /// the status after a command or pause
+    Status(Status),
+    /// a command was rejected
+    Error(String),
+}
+
+/// A debugging session attached to a pipeline instance
+#[derive(Debug)]
+pub(crate) struct Session {
+    pub(crate) commands: Receiver<Command>,
+    pub(crate) updates: Sender<Update>,
+}
+
+impl Session {
+    /// Reports the status of the graph to the client
+    async fn report(&self, pid: &TremorUrl, graph: &mut ExecutableGraph) {
+        let paused = graph.paused();
+        let status = graph.debugger_mut().map(|d| Status {
+            pipeline: pid.to_string(),
+            breakpoints: d.breakpoints().to_vec(),
+            stepping: d.is_stepping(),
+            paused,
+        });
+        if let Some(status) = status {
+            match SESSIONS.lock() {
+                Ok(mut sessions) => {
+                    sessions.insert(status.pipeline.clone(), status.clone());
+                }
+                Err(e) => error!("[Debugger::{}] Error recording status: {}", pid, e),
+            }
+            // a gone client is noticed by its commands channel closing
+            if self.updates.send(Update::Status(status)).await.is_err() {
+                debug!("[Debugger::{}] Client is gone", pid);
+            }
+        }
+    }
+}
+
+/// Applies a command that doesn't move the graph on
+fn apply(graph: &mut ExecutableGraph, command: Command) {
+    if let Some(debugger) = graph.debugger_mut() {
+        match command {
+            Command::Break(b) => debugger.set(b),
+            Command::Clear(b) => debugger.clear(&b),
+            Command::Pause => debugger.pause(),
+            Command::Step | Command::Continue | Command::Inspect | Command::Detach => (),
+        }
+    }
+}
+
+/// Attaches a session to a graph
+pub(crate) async fn attach(
+    pid: &TremorUrl,
+    graph: &mut ExecutableGraph,
+    debug: &mut Option<Session>,
+    session: Session,
+) {
+    if debug.is_some() {
+        let e = format!("Pipeline {} is already being debugged", pid);
+        if let Err(e) = session.updates.send(Update::Error(e)).await {
+            error!("[Debugger::{}] Error rejecting session: {}", pid, e);
+        }
+    } else {
+        info!("[Debugger::{}] Attaching", pid);
+        graph.attach_debugger(Debugger::default());
+        session.report(pid, graph).await;
+        *debug = Some(session);
+    }
+}
+
+/// Detaches the session from a graph, running it to completion if paused
+fn detach(
+    pid: &TremorUrl,
+    graph: &mut ExecutableGraph,
+    debug: &mut Option<Session>,
+    returns: &mut Eventset,
+) -> PipelineResult<()> {
+    info!("[Debugger::{}] Detaching", pid);
+    *debug = None;
+    match SESSIONS.lock() {
+        Ok(mut sessions) => {
+            sessions.remove(&pid.to_string());
+        }
+        Err(e) => error!("[Debugger::{}] Error removing status: {}", pid, e),
+    }
+    graph.detach_debugger(returns).map(|_| ())
+}
+
+/// Applies the commands received while the graph is running
+pub(crate) async fn poll(
+    pid: &TremorUrl,
+    graph: &mut ExecutableGraph,
+    debug: &mut Option<Session>,
+) {
+    let mut changed = false;
+    while let Some(session) = debug.as_ref() {
+        match session.commands.try_recv() {
+            Ok(Command::Detach) | Err(TryRecvError::Closed) => {
+                // a graph that isn't paused produces no events on detaching
+                if let Err(e) = detach(pid, graph, debug, &mut Eventset::new()) {
+                    error!("[Debugger::{}] Error detaching: {}", pid, e);
+                }
+                return;
+            }
+            Ok(command) => {
+                apply(graph, command);
+                changed = true;
+            }
+            Err(TryRecvError::Empty) => break,
+        }
+    }
+    if let Some(session) = debug.as_ref() {
+        if graph.debugger_mut().is_none() {
+            // the graph was replaced by a reload or canary
+            graph.attach_debugger(Debugger::default());
+            changed = true;
+        }
+        if changed {
+            session.report(pid, graph).await;
+        }
+    }
+}
+
+/// Lets the client step through a paused graph until it completes
+///
+/// # Errors
+///  * if an event fails to be processed
+pub(crate) async fn step_through(
+    pid: &TremorUrl,
+    graph: &mut ExecutableGraph,
+    debug: &mut Option<Session>,
+    returns: &mut Eventset,
+) -> PipelineResult<()> {
+    let mut paused = false;
+    while graph.is_paused() {
+        paused = true;
+        let command = if let Some(session) = debug.as_ref() {
+            session.report(pid, graph).await;
+            session.commands.recv().await.unwrap_or(Command::Detach)
+        } else {
+            Command::Detach
+        };
+        match command {
+            Command::Step => graph.step(returns)?,
+            Command::Continue => graph.resume(returns)?,
+            Command::Detach => return detach(pid, graph, debug, returns),
+            command => apply(graph, command),
+        }
+    }
+    if let (true, Some(session)) = (paused, debug.as_ref()) {
+        session.report(pid, graph).await;
+    }
+    Ok(())
+}
+
+/// Debugging status of the debugged pipeline instances
+///
+/// # Errors
+///  * if the lock of the sessions is poisoned
+pub fn status() -> Result<Vec<Status>> {
+    Ok(SESSIONS.lock()?.values().cloned().collect())
+}
+
+/// Accepts debugging clients on the endpoint
+///
+/// # Errors
+///  * if the endpoint can't be bound
+pub async fn listen(world: World, endpoint: String) -> Result<()> {
+    let listener = TcpListener::bind(&endpoint).await?;
+    info!("Debugger listening on ws://{}", endpoint);
+    while let Ok((stream, peer)) = listener.accept().await {
+        let world = world.clone();
+        task::spawn(async move {
+            if let Err(e) = serve(&world, stream).await {
+                warn!("[Debugger] Session of {} failed: {}", peer, e);
+            }
+        });
+    }
+    Ok(())
+}
+
+/// Relays the commands and updates of one client
+async fn serve(world: &World, stream: TcpStream) -> Result<()> {
+    let mut path = String::new();
+    let callback = |req: &Request, res: Response| -> std::result::Result<Response, ErrorResponse> {
+        path = req.uri().path().to_string();
+        Ok(res)
+    };
+    let ws = async_tungstenite::accept_hdr_async(stream, callback).await?;
+    let (mut ws_tx, mut ws_rx) = ws.split();
+
+    let mut id = TremorUrl::parse(&path)?;
+    id.trim_to_instance();
+    let addr = match world.reg.find_pipeline(&id).await? {
+        Some(addr) => addr,
+        None => {
+            let e = Update::Error(format!("Pipeline {} not found", id));
+            ws_tx.send(Message::Text(simd_json::to_string(&e)?)).await?;
+            return Err(Error::from(format!("Pipeline {} not found", id)));
+        }
+    };
+    let (commands_tx, commands) = bounded(64);
+    let (updates, updates_rx) = bounded(64);
+    addr.send_mgmt(MgmtMsg::Debug(Session {
+        commands,
+        updates: updates.clone(),
+    }))
+    .await?;
+
+    // the updates end once the pipeline drops the session
+    let writer = task::spawn(async move {
+        while let Ok(update) = updates_rx.recv().await {
+            let text = simd_json::to_string(&update)?;
+            ws_tx.send(Message::Text(text)).await?;
+        }
+        Ok::<(), Error>(())
+    });
+    while let Some(msg) = ws_rx.next().await {
+        match msg? {
+            Message::Text(mut text) => match simd_json::serde::from_str::<Command>(&mut text) {
+                Ok(command) => commands_tx.send(command).await?,
+                Err(e) => {
+                    let e = Update::Error(format!("Invalid command: {}", e));
+                    updates.send(e).await?;
+                }
+            },
+            Message::Close(_) => break,
+            _ => (),
+        }
+    }
+    // the pipeline detaches once the commands end
+    drop(commands_tx);
+    drop(updates);
+    writer.await
+}
@@ -0,0 +1,207 @@
+// Copyright 2020-2021, The Tremor Team
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+//! Embedding tremor into applications
+//!
+//! Applications exchange events with their deployments through `embedded`
+//! onramps and offramps, which are connected to in-process channels instead
+//! of the outside world:
+//!
+//! ```yaml
+//! onramp:
+//!   - id: app-in
+//!     type: embedded
+//!     config:
+//!       channel: in
+//! offramp:
+//!   - id: app-out
+//!     type: embedded
+//!     config:
+//!       channel: out
+//! ```
+//!
+//! The channels are opened with [`World::embedded_onramp`] and
+//! [`World::embedded_offramp`] before the onramps and offramps are bound,
+//! pipelines and configurations are loaded from strings with
+//! [`World::load_query`] and [`World::load_config`].
+//!
+//! [`World::embedded_onramp`]: crate::system::World::embedded_onramp
+//! [`World::embedded_offramp`]: crate::system::World::embedded_offramp
+//! [`World::load_query`]: crate::system::World::load_query
+//! [`World::load_config`]: crate::system::World::load_config
+
+use crate::errors::Result;
+use crate::QSIZE;
+use async_channel::{bounded, Receiver, Sender};
+use async_std::task::{self, JoinHandle};
+use hashbrown::HashMap;
+use std::sync::Mutex;
+use tremor_script::prelude::*;
+
+lazy_static! {
+    /// receiving ends of the channels of embedded onramps by name
+    static ref INPUTS: Mutex<HashMap<String, Receiver<Embedded>>> = Mutex::new(HashMap::new());
+    /// sending ends of the channels of embedded offramps by name
+    static ref OUTPUTS: Mutex<HashMap<String, Sender<Embedded>>> = Mutex::new(HashMap::new());
+}
+
+/// An event exchanged with an embedding application
+#[derive(Debug, Clone, PartialEq)]
+pub struct Embedded {
+    /// The event
+    pub data: Value<'static>,
+    /// Its metadata
+    pub meta: Value<'static>,
+}
+
+impl From<Value<'static>> for Embedded {
+    fn from(data: Value<'static>) -> Self {
+        Self {
+            data,
+            meta: Value::object(),
+        }
+    }
+}
+
+/// Sends events to the `embedded` onramps of a channel
+#[derive(Debug, Clone)]
+pub struct Injector {
+    channel: String,
+    tx: Sender<Embedded>,
+}
+
+impl Injector {
+    /// Sends an event, waiting while the onramps are busy
+    ///
+    /// # Errors
+    ///  * if the channel was closed
+    pub async fn send<E: Into<Embedded>>(&self, event: E) -> Result<()> {
+        self.tx
+            .send(event.into())
+            .await
+            .map_err(|_| format!("Embedded channel {} is closed", self.channel).into())
+    }
+
+    /// Closes the channel, disconnecting its onramps once they received the
+    /// events sent so far
+    pub fn close(&self) {
+        self.tx.close();
+        if let Ok(mut inputs) = INPUTS.lock() {
+            inputs.remove(&self.channel);
+        }
+    }
+}
+
+/// Opens the channel of `embedded` onramps
+pub(crate) fn open_input(channel: &str) -> Result<Injector> {
+    let (tx, rx) = bounded(QSIZE);
+    INPUTS.lock()?.insert(channel.to_string(), rx);
+    Ok(Injector {
+        channel: channel.to_string(),
+        tx,
+    })
+}
+
+/// Opens the channel of `embedded` offramps
+pub(crate) fn open_output(channel: &str) -> Result<Receiver<Embedded>> {
+    let (tx, rx) = bounded(QSIZE);
+    OUTPUTS.lock()?.insert(channel.to_string(), tx);
+    Ok(rx)
+}
+
+/// Calls a callback with the events of `embedded` offramps
+pub(crate) fn on_output<F>(channel: &str, mut callback: F) -> Result<JoinHandle<()>>
+where
+    F: FnMut(Embedded) + Send + 'static,
+{
+    let rx = open_output(channel)?;
+    Ok(task::spawn(async move {
+        while let Ok(event) = rx.recv().await {
+            callback(event);
+        }
+    }))
+}
+
+/// The receiving end of the channel of an `embedded` onramp
+pub(crate) fn input(channel: &str) -> Result<Receiver<Embedded>> {
+    INPUTS
+        .lock()?
+        .get(channel)
+        .cloned()
+        .ok_or_else(|| format!("Embedded channel {} isn't open", channel).into())
+}
+
+/// The sending end of the channel of an `embedded` offramp
+pub(crate) fn output(channel: &str) -> Result<Sender<Embedded>> {
+    OUTPUTS
+        .lock()?
+        .get(channel)
+        .cloned()
+        .ok_or_else(|| format!("Embedded channel {} isn't open", channel).into())
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::errors::Error;
+    use crate::system::World;
+    use async_std::future::timeout;
+    use std::time::Duration;
+    use tremor_value::literal;
+
+    #[async_std::test]
+    async fn embedded() -> Result<()> {
+        let (world, _handle) = World::start(10).await?;
+        let injector = world.embedded_onramp("embed-test-in")?;
+        let events = world.embedded_offramp("embed-test-out")?;
+        let id = world
+            .load_query("embed-test", "select event.snot from in into out;")
+            .await?;
+        assert_eq!(Some("embed-test"), id.artefact());
+        world
+            .load_config(
+                r#"
+onramp:
+  - id: embed-test-in
+    type: embedded
+    config:
+      channel: embed-test-in
+offramp:
+  - id: embed-test-out
+    type: embedded
+    config:
+      channel: embed-test-out
+binding:
+  - id: embed-test
+    links:
+      '/onramp/embed-test-in/{instance}/out': ['/pipeline/embed-test/{instance}/in']
+      '/pipeline/embed-test/{instance}/out': ['/offramp/embed-test-out/{instance}/in']
+mapping:
+  /binding/embed-test/01:
+    instance: "01"
+"#,
+            )
+            .await?;
+
+        injector.send(literal!({"snot": "badger"})).await?;
+        let event = timeout(Duration::from_secs(10), events.recv())
+            .await
+            .map_err(|_| Error::from("Timeout waiting for the embedded event"))??;
+        assert_eq!(Value::from("badger"), event.data);
+
+        assert!(output("embed-test-missing").is_err());
+        world.stop().await?;
+        Ok(())
+    }
+}
@@ -169,7 +169,15 @@ error_chain! {
         TonicStatusError(tonic::Status);
         RustlsError(rustls::TLSError);
         Hex(hex::FromHexError);
+        QuicConnectError(quinn::ConnectError);
+        QuicConnectionError(quinn::ConnectionError);
+        QuicEndpointError(quinn::EndpointError);
+        QuicWriteError(quinn::WriteError);
+        H3Error(h3::Error);
+        HttpError(http::Error);
         CsvError(csv::Error);
+        MongoError(mongodb::error::Error);
+        BsonSerError(mongodb::bson::ser::Error);
         ModeParseError(file_mode::ModeParseError);
     }
 
@@ -200,6 +208,63 @@ error_chain! {
                 display("Cannot unpublish system artefact {}.", key)
         }
 
+        ActivateFailedSystemArtefact(key: String) {
+            description("The artefact is a system artefact and its version cannot be changed")
+                display("Cannot change the version of system artefact {}.", key)
+        }
+        ArtefactVersionNotFound(key: String, version: usize) {
+            description("The artefact version was not found")
+                display("The version {} of {} was not found.", version, key)
+        }
+        RollbackFailedNoPreviousVersion(key: String) {
+            description("The artefact has no previous version to roll back to")
+                display("Cannot roll back {} which has no previous version.", key)
+        }
+        CanaryFailedNoInstances(key: String) {
+            description("The pipeline has no running instances to deploy a canary to")
+                display("Cannot deploy a canary of {} which has no running instances.", key)
+        }
+        InvalidNamespace(name: String) {
+            description("The namespace name is invalid")
+                display("Invalid namespace name {:?}, it must not be empty, `system` or contain `::`, `/` or whitespace.", name)
+        }
+        NamespaceExists(name: String) {
+            description("The namespace already exists")
+                display("The namespace {} already exists.", name)
+        }
+        NamespaceNotFound(name: String) {
+            description("The namespace was not found")
+                display("The namespace {} was not found.", name)
+        }
+        NamespaceNotEmpty(name: String) {
+            description("The namespace still has artefacts")
+                display("Cannot remove the namespace {} which still has artefacts.", name)
+        }
+        QuotaExceeded(name: String, quota: String) {
+            description("The quota of the namespace is exceeded")
+                display("The namespace {} exceeded its quota: {}.", name, quota)
+        }
+        InvalidCanaryPercentage(percentage: u8) {
+            description("The canary percentage is above 100")
+                display("Invalid canary percentage {}, it must be at most 100.", percentage)
+        }
+        ClusterUnavailable(reason: String) {
+            description("The cluster can't accept changes")
+                display("The cluster can't accept changes: {}.", reason)
+        }
+        InvalidFlagRollout(name: String, rollout: u8) {
+            description("The rollout percentage of the flag is above 100")
+                display("Invalid rollout {} of flag {}, it must be at most 100.", rollout, name)
+        }
+        InstanceNotFound(key: String) {
+            description("The instance is not running")
+                display("The instance {} is not running.", key)
+        }
+        RestoreFailed(key: String, reason: String) {
+            description("The snapshot can't be restored in the pipeline instance")
+                display("Cannot restore the snapshot in {}: {}.", key, reason)
+        }
+
         BindFailedAlreadyExists(key: String) {
             description("The binding already exists")
                 display("The binding with the id {} already exists.", key)
@@ -248,6 +313,22 @@ error_chain! {
             description("Invalid BInflux Line Protocol data")
                 display("Invalid BInflux Line Protocol data: {}", s)
         }
+        InvalidLayout(name: String, reason: String) {
+            description("Invalid binary layout")
+                display("Invalid binary layout {}: {}", name, reason)
+        }
+        InvalidLayoutData(s: String) {
+            description("Invalid binary layout data")
+                display("Invalid binary layout data: {}", s)
+        }
+        InvalidFlowData(s: String) {
+            description("Invalid NetFlow, IPFIX or sFlow data")
+                display("Invalid NetFlow, IPFIX or sFlow data: {}", s)
+        }
+        InvalidCaptureFile(s: String) {
+            description("Invalid pcap or pcapng file")
+                display("Invalid pcap or pcapng file: {}", s)
+        }
         InvalidSyslogData(s: &'static str) {
             description("Invalid Syslog Protocol data")
                 display("Invalid Syslog Protocol data: {}", s)
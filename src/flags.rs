@@ -0,0 +1,171 @@
+// Copyright 2020-2021, The Tremor Team
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+//! Feature flags, evaluated in scripts with `flags::enabled(name, key)` to
+//! roll out behavioural changes of pipelines gradually.
+//!
+//! A flag is enabled for the keys it allows, disabled for the ones it denies
+//! and enabled for `rollout` percent of all other keys. Keys are bucketed by
+//! a stable hash of the flag name and the key, so a key that is enabled
+//! stays enabled while the rollout grows, on every node. Unknown flags are
+//! disabled.
+
+use crate::config::Flag;
+use crate::errors::{ErrorKind, Result};
+use std::collections::BTreeMap;
+use std::sync::RwLock;
+
+lazy_static! {
+    static ref FLAGS: RwLock<BTreeMap<String, Flag>> = RwLock::new(BTreeMap::new());
+}
+
+/// FNV-1a, stable across versions and nodes unlike the std hashers
+fn bucket(name: &str, key: &str) -> u64 {
+    let mut hash: u64 = 0xcbf2_9ce4_8422_2325;
+    for b in name.bytes().chain(Some(0)).chain(key.bytes()) {
+        hash ^= u64::from(b);
+        hash = hash.wrapping_mul(0x0100_0000_01b3);
+    }
+    hash % 100
+}
+
+impl Flag {
+    /// If the flag `name` is enabled for `key`
+    #[must_use]
+    pub fn enabled(&self, name: &str, key: &str) -> bool {
+        if self.deny.iter().any(|k| k == key) {
+            false
+        } else if self.allow.iter().any(|k| k == key) {
+            true
+        } else {
+            bucket(name, key) < u64::from(self.rollout)
+        }
+    }
+}
+
+/// Defines or replaces the flag `name`
+///
+/// # Errors
+///  * if the rollout is above 100 percent or the flags lock is poisoned
+pub fn set(name: &str, flag: Flag) -> Result<()> {
+    if flag.rollout > 100 {
+        return Err(ErrorKind::InvalidFlagRollout(name.to_string(), flag.rollout).into());
+    }
+    FLAGS.write()?.insert(name.to_string(), flag);
+    Ok(())
+}
+
+/// The flag `name`
+///
+/// # Errors
+///  * if the flags lock is poisoned
+pub fn get(name: &str) -> Result<Option<Flag>> {
+    Ok(FLAGS.read()?.get(name).cloned())
+}
+
+/// All flags by name
+///
+/// # Errors
+///  * if the flags lock is poisoned
+pub fn list() -> Result<BTreeMap<String, Flag>> {
+    Ok(FLAGS.read()?.clone())
+}
+
+/// Removes the flag `name`, returning it
+///
+/// # Errors
+///  * if the flags lock is poisoned
+pub fn remove(name: &str) -> Result<Option<Flag>> {
+    Ok(FLAGS.write()?.remove(name))
+}
+
+/// If the flag `name` is enabled for `key`, unknown flags are disabled
+pub(crate) fn enabled(name: &str, key: &str) -> bool {
+    FLAGS
+        .read()
+        .map(|flags| {
+            flags
+                .get(name)
+                .map_or(false, |flag| flag.enabled(name, key))
+        })
+        .unwrap_or_default()
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    fn keys(flag: &Flag) -> usize {
+        (0..1000)
+            .filter(|i| flag.enabled("rollout", &i.to_string()))
+            .count()
+    }
+
+    #[test]
+    fn rollout() {
+        let mut flag = Flag::default();
+        assert_eq!(0, keys(&flag));
+        flag.rollout = 30;
+        let at_30: Vec<_> = (0..1000)
+            .filter(|i| flag.enabled("rollout", &i.to_string()))
+            .collect();
+        // roughly the rollout percentage of the keys
+        assert!((200..400).contains(&at_30.len()));
+        flag.rollout = 60;
+        // keys stay enabled as the rollout grows
+        assert!(at_30
+            .iter()
+            .all(|i| flag.enabled("rollout", &i.to_string())));
+        flag.rollout = 100;
+        assert_eq!(1000, keys(&flag));
+    }
+
+    #[test]
+    fn targeting() {
+        let flag = Flag {
+            rollout: 100,
+            allow: vec!["badger".to_string()],
+            deny: vec!["snot".to_string()],
+        };
+        assert!(!flag.enabled("targeting", "snot"));
+        assert!(flag.enabled("targeting", "badger"));
+        let flag = Flag { rollout: 0, ..flag };
+        assert!(flag.enabled("targeting", "badger"));
+        assert!(!flag.enabled("targeting", "other"));
+    }
+
+    #[test]
+    fn registry() -> Result<()> {
+        assert!(!enabled("registry", "snot"));
+        let flag = Flag {
+            rollout: 100,
+            ..Flag::default()
+        };
+        set("registry", flag.clone())?;
+        assert!(enabled("registry", "snot"));
+        assert_eq!(Some(flag.clone()), get("registry")?);
+        assert_eq!(Some(&flag), list()?.get("registry"));
+        assert!(set(
+            "registry",
+            Flag {
+                rollout: 101,
+                ..Flag::default()
+            }
+        )
+        .is_err());
+        assert_eq!(Some(flag), remove("registry")?);
+        assert!(!enabled("registry", "snot"));
+        Ok(())
+    }
+}
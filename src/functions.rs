@@ -15,6 +15,7 @@
 use crate::errors::Result;
 use crate::version::VERSION;
 use tremor_pipeline::FN_REGISTRY;
+use tremor_script::prelude::*;
 use tremor_script::registry::Registry;
 use tremor_script::tremor_fn;
 
@@ -33,12 +34,22 @@ pub fn load() -> Result<()> {
 ///  * if we can't install extensions
 pub fn install(reg: &mut Registry) -> Result<()> {
     crate::connectors::otel::load(reg);
+    #[cfg(feature = "wasm")]
+    tremor_pipeline::wasm::install(reg)?;
 
     reg.insert(tremor_fn!(system|instance(_context) {
         Ok(Value::from(instance!()))
     }))
     .insert(tremor_fn!(system|version(_context) {
         Ok(Value::from(VERSION).into_static())
+    }))
+    .insert(tremor_fn!(flags|enabled(_context, _name, _key) {
+        let key = _key.as_str().map(String::from).or_else(|| _key.as_i64().map(|k| k.to_string()));
+        if let (Some(name), Some(key)) = (_name.as_str(), key) {
+            Ok(Value::from(crate::flags::enabled(name, &key)))
+        } else {
+            Err(FunctionError::BadType{mfa: this_mfa()})
+        }
     }));
 
     Ok(())
@@ -0,0 +1,376 @@
+// Copyright 2020-2021, The Tremor Team
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+//! Interpolation of references in artefact files, so secrets don't need to
+//! be part of them:
+//!
+//! * `${NAME}` is replaced with the environment variable `NAME`
+//! * `${file:/path}` is replaced with the content of the file at `/path`
+//! * `${<scheme>:<reference>}` is resolved by the secret store registered for `scheme`
+//! * `$${` is replaced with a literal `${`
+//!
+//! References are only replaced once enabled with
+//! [`Interpolator::enable_references`], so artefacts containing a literal `${`
+//! keep loading unchanged by default. Only allowlisted environment variables
+//! and files can be referenced.
+//!
+//! In yaml files scalars tagged with `!secret <name>` are replaced with the
+//! secret `name` of the default secret store, e.g. [`vault::Vault`] or [`kubernetes::Kubernetes`].
+//! Secrets are resolved each time an artefact is loaded, so rotated secrets
+//! are picked up when artefacts are published again.
+
+use crate::errors::{Error, Result};
+use hashbrown::HashMap;
+use serde::de::DeserializeOwned;
+use std::path::{Path, PathBuf};
+use std::sync::{Arc, RwLock};
+
+/// Secrets mounted by kubernetes
+pub mod kubernetes;
+/// Secrets stored in `HashiCorp` Vault
+pub mod vault;
+mod yaml;
+
+lazy_static! {
+    static ref INTERPOLATOR: RwLock<Arc<Interpolator>> =
+        RwLock::new(Arc::new(Interpolator::default()));
+}
+
+/// A store secrets can be resolved from
+#[async_trait::async_trait]
+pub trait SecretStore: Send + Sync {
+    /// Resolves the secret for `reference`, `None` if there is no such secret
+    ///
+    /// # Errors
+    ///  * if the store can't be queried
+    async fn resolve(&self, reference: &str) -> Result<Option<String>>;
+}
+
+/// Resolves the references in artefact files
+#[derive(Default)]
+pub struct Interpolator {
+    allowed_env: Vec<String>,
+    allowed_files: Vec<PathBuf>,
+    stores: HashMap<String, Box<dyn SecretStore>>,
+    default_store: Option<String>,
+    references: bool,
+}
+
+impl Interpolator {
+    /// Creates an interpolator allowing references to the environment variables
+    /// matching `allowed_env`, where a trailing `*` matches any suffix, and
+    /// to files in the directories `allowed_files`
+    #[must_use]
+    pub fn new(allowed_env: Vec<String>, allowed_files: Vec<PathBuf>) -> Self {
+        Self {
+            allowed_env,
+            allowed_files,
+            stores: HashMap::new(),
+            default_store: None,
+            references: false,
+        }
+    }
+
+    /// Enables replacing `${...}` references, artefacts are loaded as is otherwise
+    pub fn enable_references(&mut self) {
+        self.references = true;
+    }
+
+    /// Registers a secret store for references of the form `${<scheme>:<reference>}`
+    pub fn register_store(&mut self, scheme: &str, store: Box<dyn SecretStore>) {
+        self.stores.insert(scheme.to_string(), store);
+    }
+
+    /// Sets the store `!secret <name>` tags are resolved with
+    pub fn set_default_store(&mut self, scheme: &str) {
+        self.default_store = Some(scheme.to_string());
+    }
+
+    async fn secret(&self, scheme: &str, reference: &str) -> Result<String> {
+        self.stores
+            .get(scheme)
+            .ok_or_else(|| Error::from(format!("No secret store for `{}`", scheme)))?
+            .resolve(reference)
+            .await?
+            .ok_or_else(|| format!("Unknown secret `{}:{}`", scheme, reference).into())
+    }
+
+    fn env_allowed(&self, name: &str) -> bool {
+        self.allowed_env.iter().any(|pattern| {
+            pattern
+                .strip_suffix('*')
+                .map_or(pattern == name, |prefix| name.starts_with(prefix))
+        })
+    }
+
+    fn file_allowed(&self, path: &Path) -> bool {
+        path.canonicalize().map_or(false, |path| {
+            self.allowed_files
+                .iter()
+                .filter_map(|dir| dir.canonicalize().ok())
+                .any(|dir| path.starts_with(dir))
+        })
+    }
+
+    async fn resolve(&self, reference: &str) -> Result<String> {
+        match reference.split_once(':') {
+            Some(("file", path)) => {
+                let path = Path::new(path);
+                if !self.file_allowed(path) {
+                    return Err(format!(
+                        "File `{}` is not allowed to be referenced",
+                        path.display()
+                    )
+                    .into());
+                }
+                let content = std::fs::read_to_string(path)?;
+                Ok(content.trim_end_matches(&['\r', '\n'][..]).to_string())
+            }
+            Some((scheme, secret)) => self.secret(scheme, secret).await,
+            None => {
+                if !self.env_allowed(reference) {
+                    return Err(format!(
+                        "Environment variable `{}` is not allowed to be referenced",
+                        reference
+                    )
+                    .into());
+                }
+                std::env::var(reference)
+                    .map_err(|_e| format!("Environment variable `{}` is not set", reference).into())
+            }
+        }
+    }
+
+    /// Replaces all references in `raw`, if references are enabled
+    ///
+    /// # Errors
+    ///  * if a reference is not allowed or can't be resolved
+    pub async fn interpolate(&self, raw: &str) -> Result<String> {
+        if !self.references {
+            return Ok(raw.to_string());
+        }
+        let mut out = String::with_capacity(raw.len());
+        for segment in segments(raw)? {
+            match segment {
+                Segment::Text(text) => out.push_str(text),
+                Segment::Placeholder(reference) => out.push_str(&self.resolve(reference).await?),
+            }
+        }
+        Ok(out)
+    }
+
+    /// Deserializes yaml after replacing references, scalars tagged with
+    /// `!secret <name>` are resolved with the default store
+    ///
+    /// # Errors
+    ///  * if the yaml is invalid or a secret or reference can't be resolved
+    pub async fn from_yaml<T: DeserializeOwned>(&self, raw: &str) -> Result<T> {
+        let raw = self.interpolate(raw).await?;
+        let node = yaml::load(&raw)?;
+        let mut names = Vec::new();
+        node.secrets(&mut names);
+        let mut secrets = HashMap::new();
+        for name in names {
+            if !secrets.contains_key(name) {
+                let scheme = self
+                    .default_store
+                    .as_deref()
+                    .ok_or_else(|| Error::from("No secret store configured for `!secret`"))?;
+                secrets.insert(name.to_string(), self.secret(scheme, name).await?);
+            }
+        }
+        Ok(serde_yaml::from_value(node.resolve(&secrets)?)?)
+    }
+}
+
+/// A part of a string with `${...}` placeholders
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub(crate) enum Segment<'raw> {
+    Text(&'raw str),
+    /// the content of a `${...}` placeholder
+    Placeholder(&'raw str),
+}
+
+/// Splits `raw` into text and `${...}` placeholders, `$${` is a literal `${`.
+/// This is shared by everything that renders `${...}` templates.
+///
+/// # Errors
+///  * if a placeholder isn't terminated
+pub(crate) fn segments(raw: &str) -> Result<Vec<Segment>> {
+    let mut segments = Vec::new();
+    let mut rest = raw;
+    while let Some(start) = rest.find('$') {
+        let (text, tail) = rest.split_at(start);
+        if !text.is_empty() {
+            segments.push(Segment::Text(text));
+        }
+        if tail.starts_with("$${") {
+            segments.push(Segment::Text(&tail[1..3]));
+            rest = &tail[3..];
+        } else if let Some(tail) = tail.strip_prefix("${") {
+            let end = tail
+                .find('}')
+                .ok_or_else(|| Error::from("Unterminated `${` reference"))?;
+            segments.push(Segment::Placeholder(&tail[..end]));
+            rest = &tail[end + 1..];
+        } else {
+            segments.push(Segment::Text(&tail[..1]));
+            rest = &tail[1..];
+        }
+    }
+    if !rest.is_empty() {
+        segments.push(Segment::Text(rest));
+    }
+    Ok(segments)
+}
+
+/// The content of the placeholder if `raw` is nothing but one placeholder
+pub(crate) fn placeholder(raw: &str) -> Option<&str> {
+    match segments(raw).ok()?.as_slice() {
+        [Segment::Placeholder(content)] => Some(content),
+        _ => None,
+    }
+}
+
+/// Sets the interpolator used when loading artefact files
+///
+/// # Errors
+///  * if the interpolator lock is poisoned
+pub fn set(interpolator: Interpolator) -> Result<()> {
+    *INTERPOLATOR.write()? = Arc::new(interpolator);
+    Ok(())
+}
+
+/// Replaces all references in `raw` using the interpolator set with [`set`]
+///
+/// # Errors
+///  * if a reference is not allowed or can't be resolved
+pub async fn interpolate(raw: &str) -> Result<String> {
+    let interpolator = INTERPOLATOR.read()?.clone();
+    interpolator.interpolate(raw).await
+}
+
+/// Deserializes yaml, resolving `!secret` tags and all references using the
+/// interpolator set with [`set`]
+///
+/// # Errors
+///  * if the yaml is invalid or a secret or reference can't be resolved
+pub async fn from_yaml<T: DeserializeOwned>(raw: &str) -> Result<T> {
+    let interpolator = INTERPOLATOR.read()?.clone();
+    interpolator.from_yaml(raw).await
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    struct Static;
+    #[async_trait::async_trait]
+    impl SecretStore for Static {
+        async fn resolve(&self, reference: &str) -> Result<Option<String>> {
+            Ok((reference == "db/password").then(|| "badger".to_string()))
+        }
+    }
+
+    #[test]
+    fn template_segments() -> Result<()> {
+        assert_eq!(
+            segments("a ${b} $${c} $d")?,
+            vec![
+                Segment::Text("a "),
+                Segment::Placeholder("b"),
+                Segment::Text(" "),
+                Segment::Text("${"),
+                Segment::Text("c} "),
+                Segment::Text("$"),
+                Segment::Text("d"),
+            ]
+        );
+        assert!(segments("${a").is_err());
+        assert_eq!(placeholder("${seq}"), Some("seq"));
+        assert_eq!(placeholder("${a}${b}"), None);
+        assert_eq!(placeholder("x${a}"), None);
+        Ok(())
+    }
+
+    #[async_std::test]
+    async fn interpolate() -> Result<()> {
+        std::env::set_var("TREMOR_TEST_INTERPOLATE_USER", "snot");
+        let dir = tempfile::tempdir()?;
+        let secret = dir.path().join("secret");
+        std::fs::write(&secret, "s3cr3t\n")?;
+
+        let mut i = Interpolator::new(
+            vec!["TREMOR_TEST_INTERPOLATE_*".to_string()],
+            vec![dir.path().to_path_buf()],
+        );
+        i.register_store("vault", Box::new(Static));
+        assert_eq!(
+            i.interpolate("pw: ${vault:db/password}").await?,
+            "pw: ${vault:db/password}"
+        );
+        i.enable_references();
+
+        assert_eq!(
+            i.interpolate("user: ${TREMOR_TEST_INTERPOLATE_USER}")
+                .await?,
+            "user: snot"
+        );
+        assert_eq!(
+            i.interpolate(&format!("key: ${{file:{}}}", secret.display()))
+                .await?,
+            "key: s3cr3t"
+        );
+        assert_eq!(
+            i.interpolate("pw: ${vault:db/password}").await?,
+            "pw: badger"
+        );
+        assert_eq!(i.interpolate("$meta $${HOME} $").await?, "$meta ${HOME} $");
+
+        assert!(i.interpolate("${HOME}").await.is_err());
+        assert!(i
+            .interpolate("${TREMOR_TEST_INTERPOLATE_UNSET}")
+            .await
+            .is_err());
+        assert!(i.interpolate("${file:/etc/passwd}").await.is_err());
+        assert!(i.interpolate("${vault:db/user}").await.is_err());
+        assert!(i.interpolate("${consul:db/user}").await.is_err());
+        assert!(i.interpolate("${HOME").await.is_err());
+        Ok(())
+    }
+
+    #[async_std::test]
+    async fn secret_tags() -> Result<()> {
+        let mut i = Interpolator::default();
+        i.register_store("vault", Box::new(Static));
+        let raw = "password: !secret db/password\nuser: snot\nnote: \"!secret db/user\"\n";
+        assert!(i.from_yaml::<serde_yaml::Value>(raw).await.is_err());
+        i.set_default_store("vault");
+        assert_eq!(
+            i.from_yaml::<serde_yaml::Value>(raw).await?,
+            serde_yaml::from_str::<serde_yaml::Value>(
+                "password: badger\nuser: snot\nnote: \"!secret db/user\"\n"
+            )?
+        );
+        assert!(i
+            .from_yaml::<serde_yaml::Value>("password: !secret db/user")
+            .await
+            .is_err());
+        assert!(i
+            .from_yaml::<serde_yaml::Value>("password: !secret \n")
+            .await
+            .is_err());
+        Ok(())
+    }
+}
@@ -0,0 +1,86 @@
+// Copyright 2020-2021, The Tremor Team
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+use super::SecretStore;
+use crate::errors::Result;
+use std::path::{Component, Path, PathBuf};
+
+/// Default directory secrets are mounted at
+pub const DEFAULT_DIR: &str = "/var/run/secrets/tremor";
+
+/// Secrets mounted as files by kubernetes, a reference is the path of the
+/// file relative to the mount directory, e.g. `kafka/password`.
+///
+/// The file is read on every lookup, so secrets rotated by kubernetes are
+/// picked up without a restart.
+pub struct Kubernetes {
+    dir: PathBuf,
+}
+
+impl Kubernetes {
+    /// Secrets mounted at `dir`
+    #[must_use]
+    pub fn new<P: Into<PathBuf>>(dir: P) -> Self {
+        Self { dir: dir.into() }
+    }
+}
+
+#[async_trait::async_trait]
+impl SecretStore for Kubernetes {
+    async fn resolve(&self, reference: &str) -> Result<Option<String>> {
+        let relative = Path::new(reference);
+        if !relative
+            .components()
+            .all(|c| matches!(c, Component::Normal(_)))
+        {
+            return Err(format!("Invalid kubernetes secret `{}`", reference).into());
+        }
+        let path = async_std::path::PathBuf::from(self.dir.join(relative));
+        if !path.is_file().await {
+            return Ok(None);
+        }
+        let secret = async_std::fs::read_to_string(path).await?;
+        Ok(Some(secret.trim_end_matches(&['\r', '\n'][..]).to_string()))
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[async_std::test]
+    async fn mounted_secrets() -> Result<()> {
+        let tmp = tempfile::tempdir()?;
+        let dir = tmp.path();
+        std::fs::create_dir_all(dir.join("kafka"))?;
+        std::fs::write(dir.join("kafka").join("password"), "badger\n")?;
+
+        let k8s = Kubernetes::new(dir);
+        assert_eq!(
+            k8s.resolve("kafka/password").await?,
+            Some("badger".to_string())
+        );
+        assert_eq!(k8s.resolve("kafka/user").await?, None);
+        assert!(k8s.resolve("../etc/passwd").await.is_err());
+        assert!(k8s.resolve("/etc/passwd").await.is_err());
+
+        // rotated secrets are read again
+        std::fs::write(dir.join("kafka").join("password"), "snot")?;
+        assert_eq!(
+            k8s.resolve("kafka/password").await?,
+            Some("snot".to_string())
+        );
+        Ok(())
+    }
+}
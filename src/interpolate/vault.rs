@@ -0,0 +1,244 @@
+// Copyright 2020-2021, The Tremor Team
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+use super::SecretStore;
+use crate::errors::{Error, Result};
+use async_std::sync::Mutex;
+use http_types::StatusCode;
+use std::path::PathBuf;
+use tremor_common::time::nanotime;
+use tremor_script::prelude::*;
+use tremor_value::literal;
+
+/// Default location of the kubernetes service account token
+const K8S_JWT: &str = "/var/run/secrets/kubernetes.io/serviceaccount/token";
+
+/// How to authenticate against vault
+pub enum Auth {
+    /// A static token
+    Token(String),
+    /// Kubernetes auth using the service account token of the pod
+    Kubernetes {
+        /// Vault role to log in as
+        role: String,
+        /// Mount of the kubernetes auth method
+        mount: String,
+        /// Service account token
+        jwt_path: PathBuf,
+    },
+}
+
+struct Token {
+    token: String,
+    renewable: bool,
+    /// when the token should be renewed, `None` if it doesn't expire
+    renew_at: Option<u64>,
+}
+
+impl Token {
+    /// Reads the `auth` section of a login or renewal response
+    fn from_auth(mut body: Vec<u8>) -> Result<Self> {
+        let body = tremor_value::parse_to_value(&mut body)?;
+        let auth = body
+            .get("auth")
+            .ok_or_else(|| Error::from("Vault response without `auth`"))?;
+        let token = auth
+            .get_str("client_token")
+            .ok_or_else(|| Error::from("Vault response without a client token"))?
+            .to_string();
+        let lease = auth.get_u64("lease_duration").unwrap_or_default();
+        Ok(Self {
+            token,
+            renewable: auth.get_bool("renewable").unwrap_or_default(),
+            // renew after two thirds of the lease
+            renew_at: (lease > 0).then(|| nanotime() + lease * 2 / 3 * 1_000_000_000),
+        })
+    }
+}
+
+/// Reads `field` of a KV version 2 secret
+fn secret_field(mut body: Vec<u8>, field: &str) -> Result<Option<String>> {
+    let body = tremor_value::parse_to_value(&mut body)?;
+    Ok(body.get("data").and_then(|d| d.get("data")).and_then(|d| {
+        d.get(field)
+            .map(|v| v.as_str().map_or_else(|| v.encode(), ToString::to_string))
+    }))
+}
+
+/// Secrets in the KV version 2 secrets engine of `HashiCorp` Vault, a
+/// reference is the path of the secret and the field to read, e.g.
+/// `kafka#password`. Without a field, `value` is read.
+///
+/// Secrets are fetched on every lookup, tokens are renewed before their lease
+/// ends and fetched again with a new login if the renewal fails.
+pub struct Vault {
+    addr: String,
+    kv_mount: String,
+    auth: Auth,
+    token: Mutex<Option<Token>>,
+}
+
+impl Vault {
+    /// Vault at `addr`, e.g. `https://vault:8200`, reading secrets from the
+    /// KV engine mounted at `kv_mount`
+    #[must_use]
+    pub fn new(addr: &str, kv_mount: &str, auth: Auth) -> Self {
+        Self {
+            addr: addr.trim_end_matches('/').to_string(),
+            kv_mount: kv_mount.trim_matches('/').to_string(),
+            auth,
+            token: Mutex::new(None),
+        }
+    }
+
+    /// Configures vault from `VAULT_ADDR`, `VAULT_KV_MOUNT` (default `secret`)
+    /// and either `VAULT_TOKEN` or `VAULT_K8S_ROLE`, `VAULT_K8S_MOUNT` (default
+    /// `kubernetes`) and `VAULT_K8S_JWT` for kubernetes auth
+    ///
+    /// # Errors
+    ///  * if `VAULT_ADDR` or both `VAULT_TOKEN` and `VAULT_K8S_ROLE` are not set
+    pub fn from_env() -> Result<Self> {
+        let var = |name: &str| std::env::var(name).ok();
+        let addr = var("VAULT_ADDR").ok_or_else(|| Error::from("VAULT_ADDR is not set"))?;
+        let auth = match (var("VAULT_TOKEN"), var("VAULT_K8S_ROLE")) {
+            (Some(token), _) => Auth::Token(token),
+            (None, Some(role)) => Auth::Kubernetes {
+                role,
+                mount: var("VAULT_K8S_MOUNT").unwrap_or_else(|| "kubernetes".to_string()),
+                jwt_path: PathBuf::from(
+                    var("VAULT_K8S_JWT").unwrap_or_else(|| K8S_JWT.to_string()),
+                ),
+            },
+            (None, None) => {
+                return Err("Either VAULT_TOKEN or VAULT_K8S_ROLE needs to be set".into())
+            }
+        };
+        let kv_mount = var("VAULT_KV_MOUNT").unwrap_or_else(|| "secret".to_string());
+        Ok(Self::new(&addr, &kv_mount, auth))
+    }
+
+    async fn login(&self) -> Result<Token> {
+        match &self.auth {
+            Auth::Token(token) => Ok(Token {
+                token: token.clone(),
+                renewable: false,
+                renew_at: None,
+            }),
+            Auth::Kubernetes {
+                role,
+                mount,
+                jwt_path,
+            } => {
+                let jwt = async_std::fs::read_to_string(jwt_path).await?;
+                let body = literal!({"role": role.clone(), "jwt": jwt.trim().to_string()});
+                let mut response = surf::post(format!("{}/v1/auth/{}/login", self.addr, mount))
+                    .body(body.encode())
+                    .await?;
+                if !response.status().is_success() {
+                    return Err(format!("Vault login failed: {}", response.status()).into());
+                }
+                Token::from_auth(response.body_bytes().await?)
+            }
+        }
+    }
+
+    async fn renew(&self, token: &str) -> Result<Token> {
+        let mut response = surf::post(format!("{}/v1/auth/token/renew-self", self.addr))
+            .header("X-Vault-Token", token)
+            .await?;
+        if !response.status().is_success() {
+            return Err(format!("Vault token renewal failed: {}", response.status()).into());
+        }
+        Token::from_auth(response.body_bytes().await?)
+    }
+
+    /// A valid token, renewing or fetching a new one if needed
+    async fn token(&self) -> Result<String> {
+        let mut cached = self.token.lock().await;
+        let now = nanotime();
+        let token = match cached.take() {
+            Some(token) if token.renew_at.map_or(true, |at| now < at) => token,
+            Some(token) if token.renewable => match self.renew(&token.token).await {
+                Ok(token) => token,
+                Err(e) => {
+                    warn!("Failed to renew vault token, logging in again: {}", e);
+                    self.login().await?
+                }
+            },
+            Some(_) | None => self.login().await?,
+        };
+        let value = token.token.clone();
+        *cached = Some(token);
+        Ok(value)
+    }
+}
+
+#[async_trait::async_trait]
+impl SecretStore for Vault {
+    async fn resolve(&self, reference: &str) -> Result<Option<String>> {
+        let (path, field) = reference.split_once('#').unwrap_or((reference, "value"));
+        let token = self.token().await?;
+        let mut response = surf::get(format!(
+            "{}/v1/{}/data/{}",
+            self.addr,
+            self.kv_mount,
+            path.trim_start_matches('/')
+        ))
+        .header("X-Vault-Token", token)
+        .await?;
+        match response.status() {
+            StatusCode::NotFound => Ok(None),
+            status if status.is_success() => secret_field(response.body_bytes().await?, field),
+            status => Err(format!("Failed to read vault secret `{}`: {}", path, status).into()),
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn kv2_secret() -> Result<()> {
+        let body = br#"{"data": {"data": {"password": "badger", "port": 9092}, "metadata": {}}}"#;
+        assert_eq!(
+            secret_field(body.to_vec(), "password")?,
+            Some("badger".to_string())
+        );
+        assert_eq!(
+            secret_field(body.to_vec(), "port")?,
+            Some("9092".to_string())
+        );
+        assert_eq!(secret_field(body.to_vec(), "user")?, None);
+        Ok(())
+    }
+
+    #[test]
+    fn auth_token() -> Result<()> {
+        let body =
+            br#"{"auth": {"client_token": "s.snot", "lease_duration": 3600, "renewable": true}}"#;
+        let token = Token::from_auth(body.to_vec())?;
+        assert_eq!(token.token, "s.snot");
+        assert!(token.renewable);
+        assert!(token.renew_at.is_some());
+
+        let body = br#"{"auth": {"client_token": "s.snot", "lease_duration": 0}}"#;
+        let token = Token::from_auth(body.to_vec())?;
+        assert!(!token.renewable);
+        assert_eq!(token.renew_at, None);
+
+        assert!(Token::from_auth(br#"{"errors": []}"#.to_vec()).is_err());
+        Ok(())
+    }
+}
@@ -0,0 +1,208 @@
+// Copyright 2020-2021, The Tremor Team
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+//! Loads yaml keeping `!secret <name>` tags, which `serde_yaml` drops, so they
+//! can be resolved before the document is deserialized.
+
+use crate::errors::{Error, Result};
+use hashbrown::HashMap;
+use serde_yaml::{Mapping, Value};
+use yaml_rust::parser::{Event, EventReceiver, Parser};
+use yaml_rust::scanner::{TScalarStyle, TokenType};
+use yaml_rust::Yaml;
+
+/// A yaml node whose `!secret` tags are not resolved yet
+#[derive(Clone, Debug, PartialEq)]
+pub(crate) enum Node {
+    Value(Value),
+    Secret(String),
+    Sequence(Vec<Node>),
+    Mapping(Vec<(Node, Node)>),
+}
+
+impl Node {
+    /// Names of all secrets referenced in the node
+    pub(crate) fn secrets<'node>(&'node self, names: &mut Vec<&'node str>) {
+        match self {
+            Node::Value(_) => (),
+            Node::Secret(name) => names.push(name),
+            Node::Sequence(items) => items.iter().for_each(|item| item.secrets(names)),
+            Node::Mapping(entries) => entries.iter().for_each(|(key, value)| {
+                key.secrets(names);
+                value.secrets(names);
+            }),
+        }
+    }
+
+    /// The node with secrets replaced by their value in `secrets`
+    pub(crate) fn resolve(self, secrets: &HashMap<String, String>) -> Result<Value> {
+        Ok(match self {
+            Node::Value(value) => value,
+            Node::Secret(name) => Value::String(
+                secrets
+                    .get(&name)
+                    .cloned()
+                    .ok_or_else(|| Error::from(format!("Unknown secret `{}`", name)))?,
+            ),
+            Node::Sequence(items) => Value::Sequence(
+                items
+                    .into_iter()
+                    .map(|item| item.resolve(secrets))
+                    .collect::<Result<_>>()?,
+            ),
+            Node::Mapping(entries) => {
+                let mut mapping = Mapping::new();
+                for (key, value) in entries {
+                    mapping.insert(key.resolve(secrets)?, value.resolve(secrets)?);
+                }
+                Value::Mapping(mapping)
+            }
+        })
+    }
+}
+
+/// A collection that is still being parsed, with its anchor
+enum Open {
+    Sequence(Vec<Node>, usize),
+    Mapping(Vec<(Node, Node)>, Option<Node>, usize),
+}
+
+#[derive(Default)]
+struct Loader {
+    open: Vec<Open>,
+    anchors: HashMap<usize, Node>,
+    root: Option<Node>,
+    error: Option<Error>,
+}
+
+impl Loader {
+    fn insert(&mut self, node: Node, anchor: usize) {
+        if anchor > 0 {
+            self.anchors.insert(anchor, node.clone());
+        }
+        match self.open.last_mut() {
+            Some(Open::Sequence(items, _)) => items.push(node),
+            Some(Open::Mapping(entries, key, _)) => match key.take() {
+                Some(key) => entries.push((key, node)),
+                None => *key = Some(node),
+            },
+            None => self.root = Some(node),
+        }
+    }
+}
+
+impl EventReceiver for Loader {
+    fn on_event(&mut self, event: Event) {
+        match event {
+            Event::Scalar(value, style, anchor, tag) => match scalar(value, style, tag.as_ref()) {
+                Ok(node) => self.insert(node, anchor),
+                Err(e) => self.error = self.error.take().or(Some(e)),
+            },
+            Event::Alias(anchor) => {
+                let node = self
+                    .anchors
+                    .get(&anchor)
+                    .cloned()
+                    .unwrap_or(Node::Value(Value::Null));
+                self.insert(node, 0);
+            }
+            Event::SequenceStart(anchor) => self.open.push(Open::Sequence(Vec::new(), anchor)),
+            Event::MappingStart(anchor) => {
+                self.open.push(Open::Mapping(Vec::new(), None, anchor));
+            }
+            Event::SequenceEnd | Event::MappingEnd => match self.open.pop() {
+                Some(Open::Sequence(items, anchor)) => self.insert(Node::Sequence(items), anchor),
+                Some(Open::Mapping(entries, _, anchor)) => {
+                    self.insert(Node::Mapping(entries), anchor);
+                }
+                None => (),
+            },
+            Event::Nothing
+            | Event::StreamStart
+            | Event::StreamEnd
+            | Event::DocumentStart
+            | Event::DocumentEnd => (),
+        }
+    }
+}
+
+fn scalar(value: String, style: TScalarStyle, tag: Option<&TokenType>) -> Result<Node> {
+    match tag {
+        Some(TokenType::Tag(handle, suffix)) if handle == "!" && suffix == "secret" => {
+            let name = value.trim();
+            if name.is_empty() {
+                Err("`!secret` without a name".into())
+            } else {
+                Ok(Node::Secret(name.to_string()))
+            }
+        }
+        Some(TokenType::Tag(handle, suffix)) if handle == "!!" && suffix == "str" => {
+            Ok(Node::Value(Value::String(value)))
+        }
+        _ if style != TScalarStyle::Plain => Ok(Node::Value(Value::String(value))),
+        _ => Ok(Node::Value(match Yaml::from_str(&value) {
+            Yaml::Null => Value::Null,
+            Yaml::Boolean(b) => Value::Bool(b),
+            Yaml::Integer(i) => Value::Number(i.into()),
+            real @ Yaml::Real(_) => real
+                .as_f64()
+                .map_or(Value::String(value), |f| Value::Number(f.into())),
+            _ => Value::String(value),
+        })),
+    }
+}
+
+/// Parses the first document in `raw`
+pub(crate) fn load(raw: &str) -> Result<Node> {
+    let mut loader = Loader::default();
+    Parser::new(raw.chars())
+        .load(&mut loader, false)
+        .map_err(|e| Error::from(format!("Invalid yaml: {}", e)))?;
+    if let Some(e) = loader.error {
+        return Err(e);
+    }
+    Ok(loader.root.unwrap_or(Node::Value(Value::Null)))
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn tags() -> Result<()> {
+        let node = load(
+            "a: !secret db/password\nb: &x [1, 2.5, true, ~, '3', snot]\nc: *x\nd: !!str 4\n",
+        )?;
+        let mut names = Vec::new();
+        node.secrets(&mut names);
+        assert_eq!(names, vec!["db/password"]);
+
+        let mut secrets = HashMap::new();
+        secrets.insert("db/password".to_string(), "badger".to_string());
+        let expected: Value = serde_yaml::from_str(
+            "a: badger\nb: [1, 2.5, true, ~, '3', snot]\nc: [1, 2.5, true, ~, '3', snot]\nd: '4'\n",
+        )?;
+        assert_eq!(node.resolve(&secrets)?, expected);
+
+        // a `!secret` in a string is just text
+        let node = load("a: 'x !secret y'")?;
+        assert_eq!(
+            node.resolve(&HashMap::new())?,
+            serde_yaml::from_str::<Value>("a: x !secret y")?
+        );
+        assert!(load("a: !secret ''").is_err());
+        assert!(load("a: [").is_err());
+        Ok(())
+    }
+}
@@ -46,20 +46,37 @@ extern crate test_case;
 
 #[macro_use]
 pub(crate) mod macros;
+/// Agent mode, pulling the artefacts to run from a controller
+pub mod agent;
 pub(crate) mod async_sink;
+/// Backfills replaying historical data before the live data
+pub mod backfill;
+/// Replication of API changes across a cluster of nodes
+pub mod cluster;
 /// Tremor codecs
 pub mod codec;
 /// Tremor runtime configuration
 pub mod config;
+/// Step debugging of pipeline instances
+pub mod debugger;
+/// Embedding tremor into applications
+pub mod embed;
 /// Tremor runtime errors
 pub mod errors;
+/// Feature flags evaluated in scripts
+pub mod flags;
 /// Tremor function library
 pub mod functions;
+/// Interpolation of environment variables and secrets in artefact files
+pub mod interpolate;
 pub(crate) mod lifecycle;
 /// Runtime metrics helper
 pub mod metrics;
+/// Namespaces for sharing a tremor node
+pub mod namespace;
 pub(crate) mod offramp;
 pub(crate) mod onramp;
+pub(crate) mod overflow;
 pub(crate) mod permge;
 pub(crate) mod pipeline;
 /// Onramp Preprocessors
@@ -71,10 +88,15 @@ pub(crate) mod ramp;
 pub mod registry;
 /// The tremor repository
 pub mod repository;
+/// Deterministic simulation of deployments for integration tests
+pub mod simulation;
 pub(crate) mod sink;
 pub(crate) mod source;
 /// Tremor runtime system
 pub mod system;
+/// io_uring backed file and tcp I/O
+#[cfg(all(feature = "io-uring", target_os = "linux"))]
+pub mod uring;
 /// Tremor URI
 pub mod url;
 /// Utility functions
@@ -85,7 +107,7 @@ pub mod version;
 /// Tremor connector extensions
 pub mod connectors;
 
-use std::{io::BufReader, path::Path};
+use std::{io::Read, path::Path};
 
 use crate::errors::{Error, Result};
 
@@ -93,10 +115,14 @@ pub(crate) type OnRampVec = Vec<OnRamp>;
 pub(crate) type OffRampVec = Vec<OffRamp>;
 pub(crate) type BindingVec = config::BindingVec;
 pub(crate) type MappingMap = config::MappingMap;
+pub(crate) type MetricsMap = config::MetricsMap;
+pub(crate) type FlagMap = config::FlagMap;
+pub(crate) type LayoutMap = config::LayoutMap;
 
 pub(crate) use crate::config::{Binding, OffRamp, OnRamp};
 use crate::repository::BindingArtefact;
 use crate::url::TremorUrl;
+use futures::future::{try_join, try_join_all};
 pub(crate) use serde_yaml::Value as OpConfig;
 use system::World;
 pub(crate) use tremor_pipeline::Event;
@@ -118,6 +144,12 @@ pub struct IncarnatedConfig {
     pub bindings: BindingVec,
     /// Mappings
     pub mappings: MappingMap,
+    /// Metrics overrides
+    pub metrics: MetricsMap,
+    /// Feature flags
+    pub flags: FlagMap,
+    /// Binary layouts
+    pub layouts: LayoutMap,
 }
 
 /// Incarnates a configuration into it's runnable state
@@ -133,6 +165,9 @@ pub fn incarnate(config: config::Config) -> Result<IncarnatedConfig> {
         offramps,
         bindings,
         mappings: config.mapping,
+        metrics: config.metrics,
+        flags: config.flags,
+        layouts: config.layout,
     })
 }
 
@@ -153,8 +188,17 @@ fn incarnate_links(config: &[Binding]) -> BindingVec {
 /// # Errors
 /// Fails if the file can not be loaded
 pub async fn load_query_file(world: &World, file_name: &str) -> Result<usize> {
+    let (id, query) = read_query_file(file_name).await?;
+    info!("Loading {} from file {}.", id, file_name);
+    world.repo.publish_pipeline(&id, false, query).await?;
+
+    Ok(1)
+}
+
+/// Reads a tremor query file, or a SQL one, returning the pipeline id and the
+/// query
+pub(crate) async fn read_query_file(file_name: &str) -> Result<(TremorUrl, Query)> {
     use std::ffi::OsStr;
-    use std::io::Read;
     info!("Loading configuration from {}", file_name);
     let file_id = Path::new(file_name)
         .file_stem()
@@ -165,13 +209,31 @@ pub async fn load_query_file(world: &World, file_name: &str) -> Result<usize> {
 
     file.read_to_string(&mut raw)
         .map_err(|e| Error::from(format!("Could not open file {} => {}", file_name, e)))?;
+    let raw = interpolate::interpolate(&raw)
+        .await
+        .map_err(|e| Error::from(format!("Could not interpolate {} => {}", file_name, e)))?;
+    // SQL queries are translated into trickle and loaded as such
+    let raw = if Path::new(file_name).extension() == Some(OsStr::new("sql")) {
+        tremor_script::sql::to_trickle(&raw)?
+    } else {
+        raw
+    };
+    parse_query(&raw, file_name, &file_id)
+}
 
+/// Parses a tremor query, returning the pipeline id, its `#!config id` or the
+/// given default, and the query
+pub(crate) fn parse_query(
+    raw: &str,
+    file_name: &str,
+    default_id: &str,
+) -> Result<(TremorUrl, Query)> {
     // TODO: Should ideally be const
     let aggr_reg = tremor_script::registry::aggr();
     let module_path = tremor_script::path::load();
     let query = Query::parse(
         &module_path,
-        &raw,
+        raw,
         file_name,
         vec![],
         &*FN_REGISTRY.lock()?,
@@ -181,49 +243,66 @@ pub async fn load_query_file(world: &World, file_name: &str) -> Result<usize> {
         Ok(query) => query,
         Err(e) => {
             let mut h = TermHighlighter::stderr();
-            if let Err(e) = Script::format_error_from_script(&raw, &mut h, &e) {
+            if let Err(e) = Script::format_error_from_script(raw, &mut h, &e) {
                 eprintln!("Error: {}", e);
             };
 
             return Err(format!("failed to load trickle script: {}", file_name).into());
         }
     };
-    let id = query.id().unwrap_or(&file_id);
+    let id = query.id().unwrap_or(default_id);
 
     let id = TremorUrl::parse(&format!("/pipeline/{}", id))?;
-    info!("Loading {} from file {}.", id, file_name);
-    world.repo.publish_pipeline(&id, false, query).await?;
-
-    Ok(1)
+    Ok((id, query))
 }
 
 /// Loads a config yaml file
 /// # Errors
 /// Fails if the file can not be loaded
 pub async fn load_cfg_file(world: &World, file_name: &str) -> Result<usize> {
-    info!("Loading configuration from {}", file_name);
-    let mut count = 0;
-    let file = tremor_common::file::open(file_name)?;
-    let buffered_reader = BufReader::new(file);
-    let config: config::Config = serde_yaml::from_reader(buffered_reader)?;
-    let config = crate::incarnate(config)?;
+    let config = read_cfg_file(file_name).await?;
+    publish_config(world, config).await
+}
 
-    for o in config.offramps {
-        let id = TremorUrl::parse(&format!("/offramp/{}", o.id))?;
-        info!("Loading {} from file.", id);
-        world.repo.publish_offramp(&id, false, o).await?;
-        count += 1;
+/// Publishes the artefacts of a configuration and links its mappings
+pub(crate) async fn publish_config(world: &World, config: IncarnatedConfig) -> Result<usize> {
+    let (count, mappings) = publish_artefacts(world, config).await?;
+    Ok(count + link_mappings(world, mappings).await?)
+}
+
+/// Publishes the artefacts of a configuration concurrently, returning its
+/// mappings to be linked once the artefacts of all configurations are published
+/// # Errors
+/// Fails if an artefact can not be published
+pub async fn publish_artefacts(
+    world: &World,
+    config: IncarnatedConfig,
+) -> Result<(usize, MappingMap)> {
+    for (artefact, overrides) in config.metrics {
+        metrics::set_overrides(&artefact, overrides)?;
+    }
+    for (name, flag) in config.flags {
+        flags::set(&name, flag)?;
+    }
+    for (name, layout) in config.layouts {
+        codec::layout::set(&name, layout)?;
     }
 
-    for o in config.onramps {
+    let offramps = config.offramps.into_iter().map(|o| async move {
+        let id = TremorUrl::parse(&format!("/offramp/{}", o.id))?;
+        info!("Loading {}.", id);
+        world.repo.publish_offramp(&id, false, o).await
+    });
+    let onramps = config.onramps.into_iter().map(|o| async move {
         let id = TremorUrl::parse(&format!("/onramp/{}", o.id))?;
-        info!("Loading {} from file.", id);
-        world.repo.publish_onramp(&id, false, o).await?;
-        count += 1;
-    }
-    for binding in config.bindings {
+        info!("Loading {}.", id);
+        world.repo.publish_onramp(&id, false, o).await
+    });
+    let (offramps, onramps) = try_join(try_join_all(offramps), try_join_all(onramps)).await?;
+
+    let bindings = try_join_all(config.bindings.into_iter().map(|binding| async move {
         let id = TremorUrl::parse(&format!("/binding/{}", binding.id))?;
-        info!("Loading {} from file.", id);
+        info!("Loading {}.", id);
         world
             .repo
             .publish_binding(
@@ -234,16 +313,44 @@ pub async fn load_cfg_file(world: &World, file_name: &str) -> Result<usize> {
                     mapping: None,
                 },
             )
-            .await?;
-        count += 1;
-    }
-    for (binding, mapping) in config.mappings {
-        world.link_binding(&binding, mapping).await?;
-        count += 1;
-    }
+            .await
+    }))
+    .await?;
+    Ok((
+        offramps.len() + onramps.len() + bindings.len(),
+        config.mappings,
+    ))
+}
+
+/// Links the mappings of a configuration concurrently
+/// # Errors
+/// Fails if a mapping can not be linked
+pub async fn link_mappings(world: &World, mappings: MappingMap) -> Result<usize> {
+    let count = mappings.len();
+    try_join_all(
+        mappings
+            .into_iter()
+            .map(|(binding, mapping)| async move { world.link_binding(&binding, mapping).await }),
+    )
+    .await?;
     Ok(count)
 }
 
+/// Reads and incarnates a config yaml file
+/// # Errors
+/// Fails if the file can not be read or is not a valid configuration
+pub async fn read_cfg_file(file_name: &str) -> Result<IncarnatedConfig> {
+    info!("Loading configuration from {}", file_name);
+    let mut file = tremor_common::file::open(file_name)?;
+    let mut raw = String::new();
+    file.read_to_string(&mut raw)
+        .map_err(|e| Error::from(format!("Could not open file {} => {}", file_name, e)))?;
+    let config: config::Config = interpolate::from_yaml(&raw)
+        .await
+        .map_err(|e| Error::from(format!("Could not load {} => {}", file_name, e)))?;
+    crate::incarnate(config)
+}
+
 #[cfg(test)]
 mod test {
     use super::*;
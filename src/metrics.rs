@@ -12,16 +12,300 @@
 // See the License for the specific language governing permissions and
 // limitations under the License.
 
+//! Internal metrics of onramps, offramps and pipelines. Every metric is
+//! tagged with the same flattened identifiers of the instance reporting it:
+//! `host`, `kind`, `artefact`, `instance` (the binding instance) and
+//! `version` of the artefact.
+//!
+//! Events dropped anywhere in the runtime are counted by location, port and
+//! reason. The counts are available through [`dropped`] and reported to the
+//! metrics pipeline as `dropped_events` when they change.
+
+use crate::config::Metrics;
+use crate::errors::Result;
+use crate::namespace;
 use crate::pipeline;
+use crate::url::ports::IN;
 use crate::url::TremorUrl;
+use crate::utils;
+use async_std::task;
 use beef::Cow;
 use halfbrown::HashMap;
+use std::collections::BTreeMap;
+use std::fmt;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::{Arc, Mutex, RwLock};
+use std::time::Duration;
+use tremor_common::time::nanotime;
 use tremor_pipeline::Event;
 use tremor_script::prelude::*;
 
 /// Metrics instance name
 pub static mut INSTANCE: &str = "tremor";
 
+/// Interval in seconds of artefacts with metrics enabled by an override
+/// that configure no interval themselves
+const DEFAULT_INTERVAL_S: u64 = 10;
+
+/// How often changed counts of dropped events are reported
+const DROPS_INTERVAL: Duration = Duration::from_secs(10);
+
+/// location, port and reason of dropped events
+type DropKey = (String, String, DropReason);
+
+lazy_static! {
+    static ref OVERRIDES: RwLock<HashMap<TremorUrl, Metrics>> = RwLock::new(HashMap::new());
+    static ref HOST: String = utils::hostname();
+    static ref DROPS: Mutex<BTreeMap<DropKey, (TremorUrl, Arc<AtomicU64>)>> =
+        Mutex::new(BTreeMap::new());
+}
+
+/// Overrides the metrics of an artefact for the instances created afterwards
+///
+/// # Errors
+///  * if the overrides lock is poisoned
+pub(crate) fn set_overrides(artefact: &TremorUrl, metrics: Metrics) -> Result<()> {
+    let mut artefact = artefact.clone();
+    artefact.trim_to_artefact();
+    OVERRIDES.write()?.insert(artefact, metrics);
+    Ok(())
+}
+
+/// The metrics interval of an instance in seconds, the one configured in its
+/// artefact unless overridden, `None` if its metrics are disabled
+///
+/// # Errors
+///  * if the overrides lock is poisoned
+pub(crate) fn interval_s(instance: &TremorUrl, configured: Option<u64>) -> Result<Option<u64>> {
+    let mut artefact = instance.clone();
+    artefact.trim_to_artefact();
+    let overrides = OVERRIDES
+        .read()?
+        .get(&artefact)
+        .copied()
+        .unwrap_or_default();
+    Ok(match overrides {
+        Metrics {
+            enabled: Some(false),
+            ..
+        } => None,
+        Metrics {
+            interval_s: Some(interval),
+            ..
+        } => Some(interval),
+        Metrics {
+            enabled: Some(true),
+            ..
+        } => configured.or(Some(DEFAULT_INTERVAL_S)),
+        Metrics { enabled: None, .. } => configured,
+    })
+}
+
+/// The tags identifying an instance in its metrics
+pub(crate) fn instance_tags(
+    instance: &TremorUrl,
+    version: usize,
+) -> HashMap<Cow<'static, str>, Value<'static>> {
+    let mut tags = location_tags(instance);
+    tags.insert(Cow::from("version"), Value::from(version));
+    tags
+}
+
+/// The tags identifying an instance without its version
+fn location_tags(instance: &TremorUrl) -> HashMap<Cow<'static, str>, Value<'static>> {
+    let mut tags = HashMap::with_capacity(8);
+    tags.insert(Cow::from("host"), Value::from(HOST.clone()));
+    if let Some(kind) = instance.resource_type() {
+        tags.insert(Cow::from("kind"), Value::from(kind.to_string()));
+    }
+    if let Some(artefact) = instance.artefact() {
+        tags.insert(Cow::from("artefact"), Value::from(artefact.to_string()));
+    }
+    if let Some(id) = instance.instance() {
+        tags.insert(Cow::from("instance"), Value::from(id.to_string()));
+    }
+    tags
+}
+
+/// Why events were dropped
+#[derive(Clone, Copy, Debug, PartialEq, Eq, PartialOrd, Ord, Hash, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum DropReason {
+    /// shed by a qos operator to the unlinked `overflow` output of a pipeline
+    Overflow,
+    /// an onramp couldn't decode or preprocess the data, or it exceeded the
+    /// limits, and the `err` output of the onramp isn't linked
+    Decode,
+    /// processing failed and the `err` output of the pipeline isn't linked
+    Error,
+    /// the event rate quota of the namespace was exceeded
+    RateLimit,
+    /// the event expired in an offramp or in a window of a pipeline with an
+    /// unlinked `expired` output
+    Expired,
+    /// the offramp failed or was out of rotation, and the event isn't
+    /// transactional so it isn't retried
+    SinkFailure,
+    /// the event was sent to an output that isn't linked
+    Unlinked,
+    /// the queue of a linked pipeline was full and the overflow policy of
+    /// the link drops events
+    QueueFull,
+}
+
+impl DropReason {
+    /// Reason of events dropped on an unlinked output of a pipeline
+    pub(crate) fn of_output(port: &str) -> Self {
+        match port {
+            "overflow" => Self::Overflow,
+            "expired" => Self::Expired,
+            "err" => Self::Error,
+            _ => Self::Unlinked,
+        }
+    }
+}
+
+impl fmt::Display for DropReason {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.write_str(match self {
+            Self::Overflow => "overflow",
+            Self::Decode => "decode",
+            Self::Error => "error",
+            Self::RateLimit => "rate_limit",
+            Self::Expired => "expired",
+            Self::SinkFailure => "sink_failure",
+            Self::Unlinked => "unlinked",
+            Self::QueueFull => "queue_full",
+        })
+    }
+}
+
+/// Events dropped at a location
+#[derive(Clone, Debug, PartialEq, Serialize)]
+pub struct Dropped {
+    /// the instance that dropped the events
+    pub location: String,
+    /// port the events were dropped on
+    pub port: String,
+    /// why the events were dropped
+    pub reason: DropReason,
+    /// number of dropped events since the start
+    pub count: u64,
+}
+
+/// The events dropped since the start by location, port and reason
+///
+/// # Errors
+///  * if the lock of the counts is poisoned
+pub fn dropped() -> Result<Vec<Dropped>> {
+    Ok(DROPS
+        .lock()?
+        .iter()
+        .map(|((location, port, reason), (_, count))| Dropped {
+            location: location.clone(),
+            port: port.clone(),
+            reason: *reason,
+            count: count.load(Ordering::Relaxed),
+        })
+        .collect())
+}
+
+/// Counts the events an instance drops, instances of `system` artefacts
+/// like the metrics pipeline don't carry user data and aren't counted
+#[derive(Debug)]
+pub(crate) struct Drops {
+    location: TremorUrl,
+    counted: bool,
+    counters: Vec<(String, DropReason, Arc<AtomicU64>)>,
+}
+
+impl Drops {
+    pub(crate) fn new(location: TremorUrl) -> Self {
+        let counted = location
+            .artefact()
+            .and_then(namespace::split)
+            .map_or(true, |(ns, _)| ns != namespace::SYSTEM);
+        Self {
+            location,
+            counted,
+            counters: Vec::new(),
+        }
+    }
+
+    /// Records events dropped on a port
+    pub(crate) fn record(&mut self, port: &str, reason: DropReason, count: u64) {
+        if !self.counted {
+            return;
+        }
+        if let Some((_, _, counter)) = self
+            .counters
+            .iter()
+            .find(|(p, r, _)| p == port && *r == reason)
+        {
+            counter.fetch_add(count, Ordering::Relaxed);
+            return;
+        }
+        let key = (self.location.to_string(), port.to_string(), reason);
+        let counter = match DROPS.lock() {
+            Ok(mut drops) => drops
+                .entry(key)
+                .or_insert_with(|| (self.location.clone(), Arc::new(AtomicU64::new(0))))
+                .1
+                .clone(),
+            // counted without being exposed rather than not at all
+            Err(_) => Arc::new(AtomicU64::new(0)),
+        };
+        counter.fetch_add(count, Ordering::Relaxed);
+        self.counters.push((port.to_string(), reason, counter));
+    }
+}
+
+/// Reports changed counts of dropped events to the metrics pipeline
+pub(crate) async fn report_drops(metrics: pipeline::Addr) {
+    let mut reported: BTreeMap<DropKey, u64> = BTreeMap::new();
+    loop {
+        task::sleep(DROPS_INTERVAL).await;
+        let drops: Vec<_> = match DROPS.lock() {
+            Ok(drops) => drops
+                .iter()
+                .map(|(key, (location, count))| {
+                    (key.clone(), location.clone(), count.load(Ordering::Relaxed))
+                })
+                .collect(),
+            Err(e) => {
+                error!("Failed to report dropped events: {}", e);
+                continue;
+            }
+        };
+        let timestamp = nanotime();
+        for ((location_id, port, reason), location, count) in drops {
+            let key = (location_id, port, reason);
+            if reported.get(&key) == Some(&count) {
+                continue;
+            }
+            let mut tags = location_tags(&location);
+            tags.insert(Cow::from("port"), Value::from(key.1.clone()));
+            tags.insert(Cow::from("reason"), Value::from(reason.to_string()));
+            let value =
+                tremor_pipeline::influx_value(Cow::from("dropped_events"), tags, count, timestamp);
+            let event = Event {
+                data: value.into(),
+                ingest_ns: timestamp,
+                ..Event::default()
+            };
+            if metrics
+                .send(pipeline::Msg::Event { input: IN, event })
+                .await
+                .is_err()
+            {
+                info!("Metrics pipeline stopped, no longer reporting dropped events");
+                return;
+            }
+            reported.insert(key, count);
+        }
+    }
+}
+
 #[derive(Debug)]
 pub(crate) struct Ramp {
     r#in: u64,
@@ -32,6 +316,8 @@ pub(crate) struct Ramp {
 #[derive(Debug)]
 pub(crate) struct RampReporter {
     artefact_url: TremorUrl,
+    /// tags identifying the instance
+    tags: HashMap<Cow<'static, str>, Value<'static>>,
     metrics: Ramp,
     metrics_pipeline: Option<(TremorUrl, pipeline::Addr)>,
     flush_interval: Option<u64>, // as nano-seconds
@@ -40,8 +326,11 @@ pub(crate) struct RampReporter {
 
 impl RampReporter {
     pub(crate) fn new(artefact_url: TremorUrl, flush_interval_s: Option<u64>) -> Self {
+        // onramps and offramps aren't versioned
+        let tags = instance_tags(&artefact_url, 1);
         Self {
             artefact_url,
+            tags,
             metrics: Ramp {
                 r#in: 0,
                 out: 0,
@@ -53,6 +342,24 @@ impl RampReporter {
         }
     }
 
+    /// A reporter for one of the reader instances of an onramp
+    pub(crate) fn for_instance(&self, instance: usize) -> Self {
+        let mut tags = self.tags.clone();
+        tags.insert(Cow::from("instance"), (instance as u64).into());
+        Self {
+            artefact_url: self.artefact_url.clone(),
+            tags,
+            metrics: Ramp {
+                r#in: 0,
+                out: 0,
+                err: 0,
+            },
+            metrics_pipeline: None,
+            flush_interval: self.flush_interval,
+            last_flush_ns: 0,
+        }
+    }
+
     pub(crate) fn set_metrics_pipeline(&mut self, pipeline_tuple: (TremorUrl, pipeline::Addr)) {
         self.metrics_pipeline = Some(pipeline_tuple);
     }
@@ -86,9 +393,9 @@ impl RampReporter {
 
     #[must_use]
     fn make_event(&self, timestamp: u64, port: &'static str, count: u64) -> Event {
-        let mut tags: HashMap<Cow<'static, str>, Value<'static>> = HashMap::with_capacity(2);
-        tags.insert_nocheck(Cow::from("ramp"), self.artefact_url.to_string().into());
-        tags.insert_nocheck(Cow::from("port"), port.into());
+        let mut tags = self.tags.clone();
+        tags.insert(Cow::from("ramp"), self.artefact_url.to_string().into());
+        tags.insert(Cow::from("port"), port.into());
 
         let value = tremor_pipeline::influx_value(Cow::from("ramp_events"), tags, count, timestamp);
         // full metrics payload
@@ -139,6 +446,10 @@ mod test {
         assert_eq!(v["measurement"], "ramp_events");
         assert_eq!(v["tags"]["ramp"], "tremor://localhost/onramp/example/00");
         assert_eq!(v["tags"]["port"], "test");
+        assert_eq!(v["tags"]["kind"], "onramp");
+        assert_eq!(v["tags"]["artefact"], "example");
+        assert_eq!(v["tags"]["instance"], "00");
+        assert_eq!(v["tags"]["version"], 1);
         assert_eq!(v["fields"]["count"], 42);
         assert_eq!(v["timestamp"], 123);
         assert_eq!(r.periodic_flush(1), None);
@@ -146,4 +457,63 @@ mod test {
         assert_eq!(r.periodic_flush(1_000_000_001), None);
         assert_eq!(r.periodic_flush(2_000_000_000), Some(2_000_000_000));
     }
+
+    #[test]
+    fn overrides() -> Result<()> {
+        let artefact = TremorUrl::parse("/pipeline/overridden")?;
+        let instance = TremorUrl::parse("/pipeline/overridden/01")?;
+        assert_eq!(Some(5), interval_s(&instance, Some(5))?);
+
+        let disabled = Metrics {
+            enabled: Some(false),
+            interval_s: Some(1),
+        };
+        set_overrides(&artefact, disabled)?;
+        assert_eq!(None, interval_s(&instance, Some(5))?);
+
+        let enabled = Metrics {
+            enabled: Some(true),
+            interval_s: None,
+        };
+        set_overrides(&artefact, enabled)?;
+        assert_eq!(Some(DEFAULT_INTERVAL_S), interval_s(&instance, None)?);
+        assert_eq!(Some(5), interval_s(&instance, Some(5))?);
+
+        let interval = Metrics {
+            enabled: None,
+            interval_s: Some(1),
+        };
+        set_overrides(&artefact, interval)?;
+        assert_eq!(Some(1), interval_s(&instance, Some(5))?);
+        Ok(())
+    }
+
+    #[test]
+    fn drops() -> Result<()> {
+        let location = TremorUrl::parse("/pipeline/dropping/01")?;
+        let mut drops = Drops::new(location.clone());
+        drops.record("overflow", DropReason::of_output("overflow"), 1);
+        drops.record("overflow", DropReason::Overflow, 2);
+        drops.record("in", DropReason::RateLimit, 1);
+        // a second instance at the same location shares the counts
+        Drops::new(location).record("in", DropReason::RateLimit, 1);
+
+        let mut system = Drops::new(TremorUrl::parse("/pipeline/system::metrics/system")?);
+        system.record("out", DropReason::Unlinked, 1);
+
+        let dropped: Vec<_> = dropped()?
+            .into_iter()
+            .filter(|d| d.location.contains("dropping") || d.location.contains("system::"))
+            .map(|d| (d.port, d.reason, d.count))
+            .collect();
+        assert_eq!(
+            vec![
+                ("in".to_string(), DropReason::RateLimit, 2),
+                ("overflow".to_string(), DropReason::Overflow, 3),
+            ],
+            dropped
+        );
+        assert_eq!("rate_limit", DropReason::RateLimit.to_string());
+        Ok(())
+    }
 }
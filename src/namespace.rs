@@ -0,0 +1,340 @@
+// Copyright 2020-2021, The Tremor Team
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+//! Namespaces let teams share a tremor node. The ids of the artefacts of a
+//! namespace are prefixed with its name and `::`, like the ids of the
+//! `system::` artefacts.
+
+use crate::config::Binding;
+use crate::errors::{ErrorKind, Result};
+use crate::repository::Repositories;
+use crate::url::TremorUrl;
+use async_std::sync::RwLock;
+use hashbrown::HashMap;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::Arc;
+
+/// Separates the namespace from the id of an artefact
+pub const SEPARATOR: &str = "::";
+/// Namespace of the artefacts tremor deploys itself
+pub const SYSTEM: &str = "system";
+
+/// Limits of a namespace
+#[derive(Clone, Debug, Default, PartialEq, Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct Quota {
+    /// maximum number of pipelines published in the namespace
+    #[serde(default)]
+    pub max_pipelines: Option<usize>,
+    /// maximum number of events per second processed by all pipelines of the namespace
+    #[serde(default)]
+    pub max_event_rate: Option<u64>,
+}
+
+/// A namespace
+#[derive(Clone, Debug, Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct Namespace {
+    /// name of the namespace
+    pub name: String,
+    #[serde(default)]
+    /// limits of the namespace
+    pub quota: Quota,
+    /// API tokens that grant access to the namespace, they are never serialized
+    #[serde(default, skip_serializing)]
+    pub tokens: Vec<String>,
+}
+
+/// Qualifies the id of an artefact with a namespace
+#[must_use]
+pub fn qualify(namespace: &str, id: &str) -> String {
+    format!("{}{}{}", namespace, SEPARATOR, id)
+}
+
+/// Splits a qualified artefact id into namespace and id
+#[must_use]
+pub fn split(id: &str) -> Option<(&str, &str)> {
+    id.split_once(SEPARATOR)
+}
+
+/// Qualifies the artefact of a URL with a namespace, `system` artefacts are
+/// shared by all namespaces and kept as they are
+fn qualify_url(namespace: &str, url: &TremorUrl) -> TremorUrl {
+    let mut qualified = url.clone();
+    if let Some(artefact) = url.artefact() {
+        if split(artefact).map_or(true, |(ns, _)| ns != SYSTEM) {
+            qualified.set_artefact(&qualify(namespace, artefact));
+        }
+    }
+    qualified
+}
+
+/// Qualifies the id of a binding and the artefacts it links with a namespace
+pub fn qualify_binding(namespace: &str, binding: &mut Binding) {
+    binding.id = qualify(namespace, &binding.id);
+    binding.links = binding
+        .links
+        .iter()
+        .map(|(from, tos)| {
+            let tos = tos.iter().map(|to| qualify_url(namespace, to)).collect();
+            (qualify_url(namespace, from), tos)
+        })
+        .collect();
+}
+
+/// Event rate limit shared by the pipelines of a namespace
+#[derive(Debug, Default)]
+pub(crate) struct RateLimit {
+    /// events per second, `0` for no limit
+    max: AtomicU64,
+    /// the current second
+    window: AtomicU64,
+    /// events admitted in the current second
+    count: AtomicU64,
+}
+
+impl RateLimit {
+    /// If an event arriving at `now` (in nanoseconds) is within the limit
+    pub(crate) fn admit(&self, now: u64) -> bool {
+        let max = self.max.load(Ordering::Relaxed);
+        if max == 0 {
+            return true;
+        }
+        let window = now / 1_000_000_000;
+        if self.window.swap(window, Ordering::Relaxed) != window {
+            self.count.store(0, Ordering::Relaxed);
+        }
+        self.count.fetch_add(1, Ordering::Relaxed) < max
+    }
+}
+
+lazy_static! {
+    static ref RATE_LIMITS: std::sync::RwLock<HashMap<String, Arc<RateLimit>>> =
+        std::sync::RwLock::new(HashMap::new());
+}
+
+/// The rate limit of a namespace, unlimited until the namespace sets one
+pub(crate) fn rate_limit(namespace: &str) -> Arc<RateLimit> {
+    if let Some(limit) = RATE_LIMITS
+        .read()
+        .ok()
+        .and_then(|limits| limits.get(namespace).cloned())
+    {
+        return limit;
+    }
+    match RATE_LIMITS.write() {
+        Ok(mut limits) => limits.entry(namespace.to_string()).or_default().clone(),
+        // a poisoned lock only costs the namespace its limit
+        Err(_) => Arc::new(RateLimit::default()),
+    }
+}
+
+/// The namespaces of a tremor node
+#[derive(Clone, Debug, Default)]
+pub struct Namespaces {
+    namespaces: Arc<RwLock<HashMap<String, Namespace>>>,
+}
+
+impl Namespaces {
+    /// Creates a namespace
+    ///
+    /// # Errors
+    ///  * if the name is invalid or the namespace exists
+    pub async fn create(&self, namespace: Namespace) -> Result<Namespace> {
+        let name = &namespace.name;
+        if name.is_empty()
+            || name == SYSTEM
+            || name.contains(SEPARATOR)
+            || name.contains(|c: char| c == '/' || c.is_whitespace())
+        {
+            return Err(ErrorKind::InvalidNamespace(name.clone()).into());
+        }
+        let mut namespaces = self.namespaces.write().await;
+        if namespaces.contains_key(name) {
+            return Err(ErrorKind::NamespaceExists(name.clone()).into());
+        }
+        Self::apply(&namespace);
+        namespaces.insert(name.clone(), namespace.clone());
+        Ok(namespace)
+    }
+
+    /// Replaces the quota of a namespace
+    ///
+    /// # Errors
+    ///  * if the namespace doesn't exist
+    pub async fn set_quota(&self, name: &str, quota: Quota) -> Result<Namespace> {
+        let mut namespaces = self.namespaces.write().await;
+        let namespace = namespaces
+            .get_mut(name)
+            .ok_or_else(|| ErrorKind::NamespaceNotFound(name.to_string()))?;
+        namespace.quota = quota;
+        Self::apply(namespace);
+        Ok(namespace.clone())
+    }
+
+    /// Removes a namespace that has no artefacts
+    ///
+    /// # Errors
+    ///  * if the namespace doesn't exist or still has artefacts
+    pub async fn remove(&self, name: &str, repo: &Repositories) -> Result<Namespace> {
+        let mut namespaces = self.namespaces.write().await;
+        if !namespaces.contains_key(name) {
+            return Err(ErrorKind::NamespaceNotFound(name.to_string()).into());
+        }
+        let artefacts = repo
+            .list_pipelines()
+            .await?
+            .into_iter()
+            .chain(repo.list_onramps().await?)
+            .chain(repo.list_offramps().await?)
+            .chain(repo.list_bindings().await?);
+        if Self::count_in(name, artefacts) > 0 {
+            return Err(ErrorKind::NamespaceNotEmpty(name.to_string()).into());
+        }
+        let namespace = namespaces
+            .remove(name)
+            .ok_or_else(|| ErrorKind::NamespaceNotFound(name.to_string()))?;
+        rate_limit(name).max.store(0, Ordering::Relaxed);
+        Ok(namespace)
+    }
+
+    /// Finds a namespace
+    pub async fn find(&self, name: &str) -> Option<Namespace> {
+        self.namespaces.read().await.get(name).cloned()
+    }
+
+    /// The names of all namespaces
+    pub async fn list(&self) -> Vec<String> {
+        self.namespaces.read().await.keys().cloned().collect()
+    }
+
+    /// Ensures publishing the pipeline `id` stays within the quota of its
+    /// namespace, publishing a new version of a pipeline always does
+    ///
+    /// # Errors
+    ///  * if the namespace doesn't exist or is at its maximum of pipelines
+    pub async fn check_pipeline_quota(
+        &self,
+        name: &str,
+        id: &TremorUrl,
+        repo: &Repositories,
+    ) -> Result<()> {
+        let max = self
+            .find(name)
+            .await
+            .ok_or_else(|| ErrorKind::NamespaceNotFound(name.to_string()))?
+            .quota
+            .max_pipelines;
+        if let Some(max) = max {
+            let pipelines = repo.list_pipelines().await?;
+            if !pipelines.iter().any(|p| p.artefact() == id.artefact())
+                && Self::count_in(name, pipelines.iter().cloned()) >= max
+            {
+                return Err(ErrorKind::QuotaExceeded(
+                    name.to_string(),
+                    format!("max_pipelines of {}", max),
+                )
+                .into());
+            }
+        }
+        Ok(())
+    }
+
+    fn count_in<I: Iterator<Item = TremorUrl>>(name: &str, artefacts: I) -> usize {
+        artefacts
+            .filter(|url| {
+                url.artefact()
+                    .and_then(split)
+                    .map_or(false, |(ns, _)| ns == name)
+            })
+            .count()
+    }
+
+    fn apply(namespace: &Namespace) {
+        rate_limit(&namespace.name).max.store(
+            namespace.quota.max_event_rate.unwrap_or_default(),
+            Ordering::Relaxed,
+        );
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn qualify_binding_links() -> Result<()> {
+        let mut binding: Binding = serde_yaml::from_str(
+            r#"
+id: default
+links:
+  /onramp/in/01/out: [/pipeline/main/01/in]
+  /pipeline/main/01/out: [/offramp/system::stdout/system/in]
+"#,
+        )?;
+        qualify_binding("team", &mut binding);
+        assert_eq!("team::default", binding.id);
+        let from = TremorUrl::parse("/pipeline/team::main/01/out")?;
+        let tos = binding.links.get(&from).ok_or("no qualified link")?;
+        assert_eq!(
+            vec![TremorUrl::parse("/offramp/system::stdout/system/in")?],
+            *tos
+        );
+        let from = TremorUrl::parse("/onramp/team::in/01/out")?;
+        let tos = binding.links.get(&from).ok_or("no qualified link")?;
+        assert_eq!(vec![TremorUrl::parse("/pipeline/team::main/01/in")?], *tos);
+        Ok(())
+    }
+
+    #[test]
+    fn rate_limit_window() {
+        let limit = RateLimit::default();
+        assert!(limit.admit(0));
+        limit.max.store(2, Ordering::Relaxed);
+        assert!(limit.admit(1_000_000_000));
+        assert!(limit.admit(1_000_000_001));
+        assert!(!limit.admit(1_000_000_002));
+        assert!(limit.admit(2_000_000_000));
+    }
+
+    #[async_std::test]
+    async fn create_and_quota() -> Result<()> {
+        let namespaces = Namespaces::default();
+        let namespace = |name: &str| Namespace {
+            name: name.to_string(),
+            quota: Quota::default(),
+            tokens: vec![],
+        };
+        assert!(namespaces.create(namespace("system")).await.is_err());
+        assert!(namespaces.create(namespace("a::b")).await.is_err());
+        namespaces.create(namespace("team")).await?;
+        assert!(namespaces.create(namespace("team")).await.is_err());
+
+        let quota = Quota {
+            max_pipelines: None,
+            max_event_rate: Some(1),
+        };
+        namespaces.set_quota("team", quota.clone()).await?;
+        assert_eq!(
+            Some(quota),
+            namespaces.find("team").await.map(|ns| ns.quota)
+        );
+        assert_eq!(1, rate_limit("team").max.load(Ordering::Relaxed));
+        assert!(namespaces
+            .set_quota("other", Quota::default())
+            .await
+            .is_err());
+        Ok(())
+    }
+}
@@ -13,14 +13,17 @@
 // limitations under the License.
 
 use crate::codec::Codec;
+use crate::config::Priority;
+use crate::connectors::plugin;
 use crate::errors::Result;
-use crate::metrics::RampReporter;
+use crate::metrics::{DropReason, Drops, RampReporter};
 use crate::permge::PriorityMerge;
 use crate::pipeline;
 use crate::registry::ServantId;
 use crate::sink::{
-    self, amqp, blackhole, cb, debug, dns, elastic, exit, file, gcs, gpub, handle_response, kafka,
-    kv, nats, newrelic, otel, postgres, rest, stderr, stdout, tcp, udp, ws,
+    self, amqp, azure_blob, bigquery, blackhole, capture, cb, cql, debug, dns, elastic, embedded,
+    exit, file, gcs, gcs_objects, gpub, handle_response, interconnect, kafka, kv, loki, mongodb,
+    nats, newrelic, otel, postgres, quic, rest, snowflake, splunk, stderr, stdout, tcp, udp, ws,
 };
 use crate::source::Processors;
 use crate::url::ports::{IN, METRICS};
@@ -34,9 +37,14 @@ use halfbrown::HashMap;
 use pipeline::ConnectTarget;
 use std::borrow::{Borrow, BorrowMut};
 use std::fmt;
+use std::time::{Duration, Instant};
 use tremor_common::ids::OfframpIdGen;
 use tremor_common::time::nanotime;
 
+pub(crate) mod journal;
+
+use journal::Journal;
+
 #[derive(Debug)]
 pub enum Msg {
     Event {
@@ -55,10 +63,110 @@ pub enum Msg {
         tx: async_channel::Sender<bool>,
     },
     Terminate,
+    /// Queries the circuit breaker state
+    GetState(async_channel::Sender<OfframpState>),
+    /// Opens or closes the circuit breaker manually
+    SetState {
+        state: CbState,
+        tx: async_channel::Sender<OfframpState>,
+    },
+    /// Delivers entries of the journal to the sink again
+    Replay {
+        entries: Vec<journal::Entry>,
+        tx: async_channel::Sender<journal::Replayed>,
+    },
+}
+
+/// State of the circuit breaker of an offramp
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum CbState {
+    /// events flow to the offramp
+    Closed,
+    /// the offramp is taken out of rotation
+    Open,
+}
+
+/// The circuit breaker state of an offramp instance
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct OfframpState {
+    /// the effective state
+    pub circuit_breaker: CbState,
+    /// if the circuit breaker was opened manually
+    pub manual: bool,
+    /// if the sink reports itself as active
+    pub active: bool,
+}
+
+impl OfframpState {
+    fn new(manual_open: bool, active: bool) -> Self {
+        Self {
+            circuit_breaker: if manual_open || !active {
+                CbState::Open
+            } else {
+                CbState::Closed
+            },
+            manual: manual_open,
+            active,
+        }
+    }
 }
 
 pub(crate) type Sender = async_channel::Sender<ManagerMsg>;
-pub type Addr = async_channel::Sender<Msg>;
+
+/// Address for an offramp
+#[derive(Clone)]
+pub struct Addr {
+    addr: async_channel::Sender<Msg>,
+    /// queue of high priority events
+    prio_addr: async_channel::Sender<Msg>,
+}
+
+impl Addr {
+    /// creates a new address
+    pub(crate) fn new(
+        addr: async_channel::Sender<Msg>,
+        prio_addr: async_channel::Sender<Msg>,
+    ) -> Self {
+        Self { addr, prio_addr }
+    }
+
+    /// high priority events are queued separately and overtake the others
+    fn queue(&self, priority: Priority) -> &async_channel::Sender<Msg> {
+        match priority {
+            Priority::High => &self.prio_addr,
+            Priority::Normal => &self.addr,
+        }
+    }
+
+    pub(crate) async fn send(&self, msg: Msg) -> Result<()> {
+        let priority = match &msg {
+            Msg::Event { event, .. } => Priority::of(event),
+            _ => Priority::Normal,
+        };
+        Ok(self.queue(priority).send(msg).await?)
+    }
+
+    /// Sends an event with the priority the caller looked up
+    pub(crate) async fn send_event(
+        &self,
+        input: Cow<'static, str>,
+        event: Event,
+        priority: Priority,
+    ) -> Result<()> {
+        Ok(self
+            .queue(priority)
+            .send(Msg::Event { event, input })
+            .await?)
+    }
+}
+
+#[cfg(not(tarpaulin_include))]
+impl fmt::Debug for Addr {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "Offramp")
+    }
+}
 
 #[async_trait::async_trait]
 pub trait Offramp: Send {
@@ -109,28 +217,43 @@ pub trait Impl {
 pub fn lookup(name: &str, config: &Option<OpConfig>) -> Result<Box<dyn Offramp>> {
     match name {
         "amqp" => amqp::Amqp::from_config(config),
+        "azure-blob" => azure_blob::AzureBlob::from_config(config),
+        "bigquery" => bigquery::BigQuery::from_config(config),
         "blackhole" => blackhole::Blackhole::from_config(config),
+        "capture" => capture::Capture::from_config(config),
         "cb" => cb::Cb::from_config(config),
+        "cql" => cql::Cql::from_config(config),
         "debug" => debug::Debug::from_config(config),
         "dns" => dns::Dns::from_config(config),
         "elastic" => elastic::Elastic::from_config(config),
+        "embedded" => embedded::Embedded::from_config(config),
         "exit" => exit::Exit::from_config(config),
         "file" => file::File::from_config(config),
+        "interconnect" => interconnect::Interconnect::from_config(config),
         "kafka" => kafka::Kafka::from_config(config),
         "kv" => kv::Kv::from_config(config),
+        "loki" => loki::Loki::from_config(config),
+        "mongodb" => mongodb::MongoDb::from_config(config),
         "nats" => nats::Nats::from_config(config),
         "newrelic" => newrelic::NewRelic::from_config(config),
         "otel" => otel::OpenTelemetry::from_config(config),
         "postgres" => postgres::Postgres::from_config(config),
+        "quic" => quic::Quic::from_config(config),
         "rest" => rest::Rest::from_config(config),
+        "snowflake" => snowflake::Snowflake::from_config(config),
+        "splunk" => splunk::Splunk::from_config(config),
         "stderr" => stderr::StdErr::from_config(config),
         "stdout" => stdout::StdOut::from_config(config),
         "tcp" => tcp::Tcp::from_config(config),
         "udp" => udp::Udp::from_config(config),
         "ws" => ws::Ws::from_config(config),
         "gcs" => gcs::GoogleCloudStorage::from_config(config),
+        "gcs-objects" => gcs_objects::GcsObjects::from_config(config),
         "gpub" => gpub::GoogleCloudPubSub::from_config(config),
-        _ => Err(format!("Offramp {} not known", name).into()),
+        _ => match plugin::find(name, plugin::Kind::Offramp) {
+            Some(manifest) => sink::plugin::Plugin::from_manifest(manifest, config),
+            None => Err(format!("Offramp {} not known", name).into()),
+        },
     }
 }
 
@@ -143,6 +266,11 @@ pub(crate) struct Create {
     pub postprocessors: Vec<String>,
     pub metrics_reporter: RampReporter,
     pub is_linked: bool,
+    /// grace period for starting the offramp on its first event, `None`
+    /// starts it on creation
+    pub lazy: Option<Duration>,
+    /// journal of the failed deliveries
+    pub journal: Option<Journal>,
 }
 
 #[cfg(not(tarpaulin_include))]
@@ -163,6 +291,37 @@ pub(crate) struct Manager {
     qsize: usize,
 }
 
+/// Interval a lazy offramp retries starting in during its grace period
+const LAZY_START_RETRY: Duration = Duration::from_millis(100);
+
+#[allow(clippy::too_many_arguments)]
+async fn start_offramp(
+    offramp: &mut dyn Offramp,
+    offramp_uid: u64,
+    id: &TremorUrl,
+    codec: &dyn Codec,
+    codec_map: &HashMap<String, Box<dyn Codec>>,
+    preprocessors: &[String],
+    postprocessors: &[String],
+    is_linked: bool,
+    reply_channel: async_channel::Sender<sink::Reply>,
+) -> Result<()> {
+    offramp
+        .start(
+            offramp_uid,
+            id,
+            codec,
+            codec_map,
+            Processors {
+                pre: preprocessors,
+                post: postprocessors,
+            },
+            is_linked,
+            reply_channel,
+        )
+        .await
+}
+
 async fn send_to_pipelines(
     offramp_id: &TremorUrl,
     pipelines: &mut HashMap<TremorUrl, pipeline::Addr>,
@@ -181,6 +340,24 @@ async fn send_to_pipelines(
     }
 }
 
+/// Records an event that failed and that nothing retries in the journal
+fn journal_failure(
+    journal: Option<&Journal>,
+    offramp_url: &TremorUrl,
+    input: &str,
+    reason: &str,
+    event: &Event,
+) {
+    if let Some(journal) = journal {
+        if let Err(e) = journal.record(input, reason, event) {
+            error!(
+                "[Offramp::{}] Failed to journal the event: {}",
+                offramp_url, e
+            );
+        }
+    }
+}
+
 pub(crate) enum OfframpMsg {
     Msg(Msg),
     Reply(sink::Reply),
@@ -204,37 +381,45 @@ impl Manager {
             mut metrics_reporter,
             is_linked,
             id,
+            lazy,
+            journal,
         }: Create,
         offramp_uid: u64,
     ) -> Result<()> {
         let (msg_tx, msg_rx) = bounded::<Msg>(self.qsize);
+        let (prio_tx, prio_rx) = bounded::<Msg>(self.qsize);
         let (cf_tx, cf_rx) = unbounded::<sink::Reply>(); // we might need to wrap that somehow, but *shrug*
 
-        if let Err(e) = offramp
-            .start(
+        // lazy offramps are started by their first event and report
+        // themselves as ready until then
+        let mut started = lazy.is_none();
+        if started {
+            if let Err(e) = start_offramp(
+                &mut *offramp,
                 offramp_uid,
                 &id,
                 codec.borrow(),
                 &codec_map,
-                Processors {
-                    pre: &preprocessors,
-                    post: &postprocessors,
-                },
+                &preprocessors,
+                &postprocessors,
                 is_linked,
                 cf_tx.clone(),
             )
             .await
-        {
-            error!("Failed to create offramp {}: {}", id, e);
-            return Err(e);
+            {
+                error!("Failed to create offramp {}: {}", id, e);
+                return Err(e);
+            }
         }
         // merge channels and prioritize contraflow/insight events
-        let m_rx = msg_rx.map(OfframpMsg::Msg);
+        // high priority events overtake the queued messages
+        let m_rx = PriorityMerge::new(prio_rx, msg_rx).map(OfframpMsg::Msg);
         let c_rx = cf_rx.map(OfframpMsg::Reply);
         let mut to_and_from_offramp_rx = PriorityMerge::new(c_rx, m_rx);
 
         let offramp_url = id.clone();
-        let offramp_addr = msg_tx.clone();
+        let addr = Addr::new(msg_tx, prio_tx);
+        let offramp_addr = addr.clone();
 
         task::spawn::<_, Result<()>>(async move {
             let mut pipelines: HashMap<TremorUrl, pipeline::Addr> = HashMap::new();
@@ -242,6 +427,9 @@ impl Manager {
             // for linked offramp output (port to pipeline(s) mapping)
             let mut dest_pipelines: HashMap<Cow<'static, str>, Vec<(TremorUrl, pipeline::Addr)>> =
                 HashMap::new();
+            // circuit breaker opened via the API
+            let mut manual_open = false;
+            let mut drops = Drops::new(offramp_url.clone());
 
             info!("[Offramp::{}] started", offramp_url);
 
@@ -250,6 +438,9 @@ impl Manager {
                     OfframpMsg::Msg(m) => {
                         match m {
                             Msg::Signal(signal) => {
+                                if !started {
+                                    continue;
+                                }
                                 if let Some(insight) = offramp.on_signal(signal).await {
                                     send_to_pipelines(&offramp_url, &mut pipelines, insight).await;
                                 }
@@ -262,12 +453,120 @@ impl Manager {
                                 metrics_reporter.periodic_flush(ingest_ns);
                                 metrics_reporter.increment_in();
 
+                                if manual_open {
+                                    // taken out of rotation, events still in flight are failed
+                                    metrics_reporter.increment_err();
+                                    if transactional {
+                                        let e = Event::cb_fail(ingest_ns, ids);
+                                        send_to_pipelines(&offramp_url, &mut pipelines, e).await;
+                                    } else {
+                                        drops.record(&input, DropReason::SinkFailure, 1);
+                                        journal_failure(
+                                            journal.as_ref(),
+                                            &offramp_url,
+                                            &input,
+                                            "circuit breaker open",
+                                            &event,
+                                        );
+                                    }
+                                    continue;
+                                }
+                                if event.is_expired() {
+                                    // stale events, e.g. from recovering a backlog, are not sent
+                                    debug!(
+                                        "[Offramp::{}] Dropping expired event {}",
+                                        offramp_url, ids
+                                    );
+                                    metrics_reporter.increment_err();
+                                    if transactional {
+                                        let e = Event::cb_fail(ingest_ns, ids);
+                                        send_to_pipelines(&offramp_url, &mut pipelines, e).await;
+                                    } else {
+                                        drops.record(&input, DropReason::Expired, 1);
+                                    }
+                                    continue;
+                                }
+                                if let Some(grace) = lazy.filter(|_| !started) {
+                                    let deadline = Instant::now() + grace;
+                                    loop {
+                                        match start_offramp(
+                                            &mut *offramp,
+                                            offramp_uid,
+                                            &offramp_url,
+                                            codec.borrow(),
+                                            &codec_map,
+                                            &preprocessors,
+                                            &postprocessors,
+                                            is_linked,
+                                            cf_tx.clone(),
+                                        )
+                                        .await
+                                        {
+                                            Ok(()) => {
+                                                started = true;
+                                                break;
+                                            }
+                                            Err(e) if Instant::now() < deadline => {
+                                                debug!(
+                                                    "[Offramp::{}] Not ready yet: {}",
+                                                    offramp_url, e
+                                                );
+                                                task::sleep(LAZY_START_RETRY).await;
+                                            }
+                                            Err(e) => {
+                                                error!(
+                                                    "[Offramp::{}] Failed to start: {}",
+                                                    offramp_url, e
+                                                );
+                                                break;
+                                            }
+                                        }
+                                    }
+                                    if !started {
+                                        metrics_reporter.increment_err();
+                                        if transactional {
+                                            let e = Event::cb_fail(ingest_ns, ids);
+                                            send_to_pipelines(&offramp_url, &mut pipelines, e)
+                                                .await;
+                                        } else {
+                                            drops.record(&input, DropReason::SinkFailure, 1);
+                                            journal_failure(
+                                                journal.as_ref(),
+                                                &offramp_url,
+                                                &input,
+                                                "sink not started",
+                                                &event,
+                                            );
+                                        }
+                                        continue;
+                                    }
+                                    info!("[Offramp::{}] started lazily", offramp_url);
+                                }
+
+                                // kept for the journal, the sink consumes the event
+                                let journaled = journal
+                                    .as_ref()
+                                    .filter(|_| !transactional)
+                                    .map(|_| event.clone());
                                 let c: &mut dyn Codec = codec.borrow_mut();
                                 let fail = if let Err(err) =
                                     offramp.on_event(c, &codec_map, input.borrow(), event).await
                                 {
                                     error!("[Offramp::{}] On Event error: {}", offramp_url, err);
                                     metrics_reporter.increment_err();
+                                    if !transactional {
+                                        // nothing retries it, so the event is lost
+                                        drops.record(&input, DropReason::SinkFailure, 1);
+                                        if let Some(event) = &journaled {
+                                            journal_failure(
+                                                journal.as_ref(),
+                                                &offramp_url,
+                                                &input,
+                                                &err.to_string(),
+                                                event,
+                                            );
+                                        }
+                                    }
                                     true
                                 } else {
                                     metrics_reporter.increment_out();
@@ -294,7 +593,7 @@ impl Manager {
                                     // TODO: if we are linked we should only send CB restore/break events if we receive one from the dest_pipelines
                                     if !is_linked || !dest_pipelines.is_empty() {
                                         let insight = Event::restore_or_break(
-                                            offramp.is_active(),
+                                            !started || offramp.is_active(),
                                             nanotime(),
                                         );
                                         if let Err(e) = addr.send_insight(insight).await {
@@ -343,7 +642,7 @@ impl Manager {
                                     // to all the connected inputs
                                     if is_linked && !pipelines.is_empty() {
                                         let insight = Event::restore_or_break(
-                                            offramp.is_active(),
+                                            !started || offramp.is_active(),
                                             nanotime(),
                                         );
                                         let mut iter = pipelines.iter();
@@ -396,15 +695,80 @@ impl Manager {
                                 );
                                 if marked_done {
                                     info!("[Offramp::{}] Marked as done ", offramp_url);
-                                    offramp.terminate().await;
+                                    if started {
+                                        offramp.terminate().await;
+                                    }
                                     break;
                                 }
                             }
                             Msg::Terminate => {
                                 info!("[Offramp::{}] Terminating...", offramp_url);
-                                offramp.terminate().await;
+                                if started {
+                                    offramp.terminate().await;
+                                }
                                 break;
                             }
+                            Msg::GetState(tx) => {
+                                let state =
+                                    OfframpState::new(manual_open, !started || offramp.is_active());
+                                if let Err(e) = tx.send(state).await {
+                                    error!("[Offramp::{}] State error: {}", offramp_url, e);
+                                }
+                            }
+                            Msg::SetState { state, tx } => {
+                                manual_open = state == CbState::Open;
+                                info!(
+                                    "[Offramp::{}] Circuit breaker manually {}",
+                                    offramp_url,
+                                    if manual_open { "opened" } else { "closed" }
+                                );
+                                // closing forces the pipelines to retry
+                                let cb = if manual_open {
+                                    Event::cb_trigger(nanotime())
+                                } else {
+                                    Event::cb_restore(nanotime())
+                                };
+                                send_to_pipelines(&offramp_url, &mut pipelines, cb).await;
+                                let state =
+                                    OfframpState::new(manual_open, !started || offramp.is_active());
+                                if let Err(e) = tx.send(state).await {
+                                    error!("[Offramp::{}] State error: {}", offramp_url, e);
+                                }
+                            }
+                            Msg::Replay { entries, tx } => {
+                                let mut replayed = journal::Replayed::default();
+                                for entry in entries {
+                                    let delivered = started && !manual_open && {
+                                        let c: &mut dyn Codec = codec.borrow_mut();
+                                        let r = offramp
+                                            .on_event(c, &codec_map, &entry.input, entry.event())
+                                            .await;
+                                        if let Err(e) = &r {
+                                            warn!(
+                                                "[Offramp::{}] Replaying entry {} failed: {}",
+                                                offramp_url, entry.id, e
+                                            );
+                                        }
+                                        r.is_ok()
+                                    };
+                                    if delivered {
+                                        replayed.replayed += 1;
+                                        if let Some(Err(e)) =
+                                            journal.as_ref().map(|j| j.remove(entry.id))
+                                        {
+                                            error!(
+                                                "[Offramp::{}] Failed to remove entry {}: {}",
+                                                offramp_url, entry.id, e
+                                            );
+                                        }
+                                    } else {
+                                        replayed.failed += 1;
+                                    }
+                                }
+                                if let Err(e) = tx.send(replayed).await {
+                                    error!("[Offramp::{}] Replay error: {}", offramp_url, e);
+                                }
+                            }
                         }
                     }
                     OfframpMsg::Reply(sink::Reply::Insight(event)) => {
@@ -422,7 +786,7 @@ impl Manager {
             info!("[Offramp::{}] stopped", offramp_url);
             Ok(())
         });
-        r.send(Ok(msg_tx)).await?;
+        r.send(Ok(addr)).await?;
         Ok(())
     }
 
@@ -438,7 +802,16 @@ impl Manager {
                         break;
                     }
                     ManagerMsg::Create(r, c) => {
-                        self.offramp_task(r, *c, offramp_id_gen.next_id()).await?;
+                        // offramps start concurrently, a slow sink does not hold up the others
+                        let mngr = Self::new(self.qsize);
+                        let offramp_uid = offramp_id_gen.next_id();
+                        task::spawn(async move {
+                            if let Err(e) = mngr.offramp_task(r.clone(), *c, offramp_uid).await {
+                                if let Err(e) = r.send(Err(e)).await {
+                                    error!("Offramp manager error: {}", e);
+                                }
+                            }
+                        });
                     }
                 };
                 info!("Stopping offramps...");
@@ -597,6 +970,8 @@ mod tests {
                 metrics_reporter: ramp_reporter,
                 offramp: Box::new(offramp),
                 is_linked: true,
+                lazy: None,
+                journal: None,
             }),
         );
         sender.send(create).await?;
@@ -609,12 +984,15 @@ mod tests {
         }
 
         let fake_pipeline_id = TremorUrl::parse("/pipeline/fake/instance/out")?;
-        let (tx, _rx) = async_channel::unbounded();
+        let (tx, rx) = async_channel::unbounded();
+        let (prio_tx, _prio_rx) = async_channel::unbounded();
         let (cf_tx, _cf_rx) = async_channel::unbounded();
         let (mgmt_tx, _mgmt_rx) = async_channel::unbounded();
 
         let fake_pipeline = Box::new(pipeline::Addr::new(
             tx,
+            rx,
+            prio_tx,
             cf_tx,
             mgmt_tx,
             fake_pipeline_id.clone(),
@@ -648,6 +1026,42 @@ mod tests {
             e => assert!(false, "Expected event msg, got {:?}", e),
         }
 
+        // open the circuit breaker manually, events no longer reach the offramp
+        let (state_tx, state_rx) = async_channel::bounded(1);
+        offramp_sender
+            .send(Msg::SetState {
+                state: CbState::Open,
+                tx: state_tx,
+            })
+            .await?;
+        let state = state_rx.recv().await?;
+        assert_eq!(CbState::Open, state.circuit_breaker);
+        assert!(state.manual);
+        offramp_sender
+            .send(Msg::Event {
+                input: IN,
+                event: Event::default(),
+            })
+            .await?;
+        let (state_tx, state_rx) = async_channel::bounded(1);
+        offramp_sender
+            .send(Msg::SetState {
+                state: CbState::Closed,
+                tx: state_tx,
+            })
+            .await?;
+        assert_eq!(CbState::Closed, state_rx.recv().await?.circuit_breaker);
+        let (state_tx, state_rx) = async_channel::bounded(1);
+        offramp_sender.send(Msg::GetState(state_tx)).await?;
+        assert_eq!(
+            OfframpState {
+                circuit_breaker: CbState::Closed,
+                manual: false,
+                active: true
+            },
+            state_rx.recv().await?
+        );
+
         let (disc_tx, disc_rx) = async_channel::bounded(1);
         offramp_sender
             .send(Msg::Disconnect {
@@ -673,4 +1087,55 @@ mod tests {
         handle.cancel().await;
         Ok(())
     }
+
+    #[async_std::test]
+    async fn offramp_lazy_start_test() -> Result<()> {
+        let mngr = Manager::new(QSIZE);
+        let (handle, sender) = mngr.start();
+        let (tx, rx) = async_channel::bounded(1);
+        let id = TremorUrl::parse("/offramp/fake/lazy")?;
+        let (offramp_tx, offramp_rx) = async_channel::unbounded();
+        let create = ManagerMsg::Create(
+            tx,
+            Box::new(Create {
+                id: id.clone(),
+                codec: crate::codec::lookup("json")?,
+                codec_map: HashMap::new(),
+                preprocessors: vec![],
+                postprocessors: vec![],
+                metrics_reporter: RampReporter::new(id, None),
+                offramp: Box::new(FakeOfframp::new(offramp_tx)),
+                is_linked: false,
+                lazy: Some(Duration::from_millis(100)),
+                journal: None,
+            }),
+        );
+        sender.send(create).await?;
+        let offramp_sender = rx.recv().await??;
+
+        // not started before the first event, but reported as ready
+        let (state_tx, state_rx) = async_channel::bounded(1);
+        offramp_sender.send(Msg::GetState(state_tx)).await?;
+        assert!(state_rx.recv().await?.active);
+        assert!(offramp_rx.is_empty());
+
+        offramp_sender
+            .send(Msg::Event {
+                input: IN,
+                event: Event::default(),
+            })
+            .await?;
+        match offramp_rx.recv().await? {
+            FakeOfframpMsg::Start(_) => {}
+            e => assert!(false, "Expected start msg, got {:?}", e),
+        }
+        match offramp_rx.recv().await? {
+            FakeOfframpMsg::Event(_) => {}
+            e => assert!(false, "Expected event msg, got {:?}", e),
+        }
+
+        sender.send(ManagerMsg::Stop).await?;
+        handle.cancel().await;
+        Ok(())
+    }
 }
@@ -0,0 +1,310 @@
+// Copyright 2020-2021, The Tremor Team
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+//! Journal of the events an offramp failed to deliver that nothing else
+//! retries, i.e. the non-transactional ones. Transactional events are failed
+//! upstream instead.
+//!
+//! Entries are kept in a sled database per directory, with a tree per
+//! offramp instance, and can be listed, inspected and replayed through the
+//! API. Replayed entries are removed from the journal.
+
+use crate::config::Journal as Config;
+use crate::errors::Result;
+use crate::url::TremorUrl;
+use crate::Event;
+use halfbrown::HashMap;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::{Arc, Mutex, RwLock};
+use tremor_common::time::nanotime;
+use tremor_value::Value;
+
+lazy_static! {
+    static ref DBS: Mutex<HashMap<String, sled::Db>> = Mutex::new(HashMap::new());
+    static ref JOURNALS: RwLock<HashMap<TremorUrl, Journal>> = RwLock::new(HashMap::new());
+}
+
+/// A failed delivery
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub struct Entry {
+    /// id of the entry in the journal
+    pub id: u64,
+    /// when the delivery failed
+    pub failed_ns: u64,
+    /// ingest time of the event
+    pub ingest_ns: u64,
+    /// the port of the offramp the event was sent to
+    pub input: String,
+    /// why the delivery failed
+    pub reason: String,
+    /// the event value
+    #[serde(deserialize_with = "static_value")]
+    pub value: Value<'static>,
+    /// the event metadata
+    #[serde(deserialize_with = "static_value")]
+    pub meta: Value<'static>,
+}
+
+fn static_value<'de, D>(deserializer: D) -> std::result::Result<Value<'static>, D::Error>
+where
+    D: serde::Deserializer<'de>,
+{
+    let value: Value<'de> = serde::Deserialize::deserialize(deserializer)?;
+    Ok(value.into_static())
+}
+
+impl Entry {
+    /// The event to deliver again
+    pub(crate) fn event(&self) -> Event {
+        Event {
+            ingest_ns: self.ingest_ns,
+            data: (self.value.clone(), self.meta.clone()).into(),
+            ..Event::default()
+        }
+    }
+}
+
+/// An entry without its payload
+#[derive(Clone, Debug, PartialEq, Serialize)]
+pub struct Summary {
+    /// id of the entry in the journal
+    pub id: u64,
+    /// when the delivery failed
+    pub failed_ns: u64,
+    /// ingest time of the event
+    pub ingest_ns: u64,
+    /// the port of the offramp the event was sent to
+    pub input: String,
+    /// why the delivery failed
+    pub reason: String,
+}
+
+impl From<Entry> for Summary {
+    fn from(entry: Entry) -> Self {
+        Self {
+            id: entry.id,
+            failed_ns: entry.failed_ns,
+            ingest_ns: entry.ingest_ns,
+            input: entry.input,
+            reason: entry.reason,
+        }
+    }
+}
+
+/// Selects the entries to replay, by id and by the time their delivery
+/// failed. Everything is selected without any criteria.
+#[derive(Clone, Debug, Default, PartialEq, Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct Replay {
+    /// ids of the entries, any entry if empty
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub ids: Vec<u64>,
+    /// entries that failed at or after this time
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub from_ns: Option<u64>,
+    /// entries that failed before this time
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub to_ns: Option<u64>,
+    /// pipeline instance the events are sent to, they are sent to the sink
+    /// of the offramp directly without one
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub pipeline: Option<TremorUrl>,
+}
+
+impl Replay {
+    fn selects(&self, entry: &Entry) -> bool {
+        (self.ids.is_empty() || self.ids.contains(&entry.id))
+            && self.from_ns.map_or(true, |from| entry.failed_ns >= from)
+            && self.to_ns.map_or(true, |to| entry.failed_ns < to)
+    }
+}
+
+/// Outcome of a replay
+#[derive(Clone, Copy, Debug, Default, PartialEq, Serialize)]
+pub struct Replayed {
+    /// entries delivered again and removed from the journal
+    pub replayed: usize,
+    /// entries that failed again and are kept
+    pub failed: usize,
+}
+
+/// The journal of an offramp instance
+#[derive(Clone, Debug)]
+pub(crate) struct Journal {
+    db: sled::Db,
+    tree: sled::Tree,
+    /// number of entries, `len` of sled trees has to scan them
+    len: Arc<AtomicUsize>,
+    max_entries: usize,
+}
+
+impl Journal {
+    /// Opens the journal of the offramp instance `id` and registers it
+    ///
+    /// # Errors
+    ///  * if the database can't be opened
+    pub(crate) fn open(id: &TremorUrl, config: &Config) -> Result<Self> {
+        let db = {
+            let mut dbs = DBS.lock()?;
+            if let Some(db) = dbs.get(&config.dir) {
+                db.clone()
+            } else {
+                let db = sled::open(&config.dir)?;
+                dbs.insert(config.dir.clone(), db.clone());
+                db
+            }
+        };
+        let tree = db.open_tree(id.to_string())?;
+        let journal = Self {
+            len: Arc::new(AtomicUsize::new(tree.len())),
+            tree,
+            db,
+            max_entries: config.max_entries,
+        };
+        JOURNALS.write()?.insert(id.clone(), journal.clone());
+        Ok(journal)
+    }
+
+    /// Records an event that failed, the oldest entries are dropped once the
+    /// journal is full
+    ///
+    /// # Errors
+    ///  * if the entry can't be stored
+    pub(crate) fn record(&self, input: &str, reason: &str, event: &Event) -> Result<()> {
+        let id = self.db.generate_id()?;
+        let (value, meta) = event.data.parts();
+        let entry = Entry {
+            id,
+            failed_ns: nanotime(),
+            ingest_ns: event.ingest_ns,
+            input: input.to_string(),
+            reason: reason.to_string(),
+            value: value.clone_static(),
+            meta: meta.clone_static(),
+        };
+        self.tree
+            .insert(id.to_be_bytes(), simd_json::to_vec(&entry)?)?;
+        let mut len = self.len.fetch_add(1, Ordering::AcqRel) + 1;
+        while len > self.max_entries {
+            if self.tree.pop_min()?.is_none() {
+                break;
+            }
+            len = self.len.fetch_sub(1, Ordering::AcqRel) - 1;
+        }
+        Ok(())
+    }
+
+    /// The entries, oldest first
+    ///
+    /// # Errors
+    ///  * if the entries can't be read
+    pub(crate) fn entries(&self) -> Result<Vec<Entry>> {
+        self.tree
+            .iter()
+            .values()
+            .map(|v| Ok(simd_json::from_slice(&mut v?.to_vec())?))
+            .collect()
+    }
+
+    /// The entry `id`
+    ///
+    /// # Errors
+    ///  * if the entry can't be read
+    pub(crate) fn get(&self, id: u64) -> Result<Option<Entry>> {
+        self.tree
+            .get(id.to_be_bytes())?
+            .map(|v| Ok(simd_json::from_slice(&mut v.to_vec())?))
+            .transpose()
+    }
+
+    /// The entries selected by `replay`
+    ///
+    /// # Errors
+    ///  * if the entries can't be read
+    pub(crate) fn select(&self, replay: &Replay) -> Result<Vec<Entry>> {
+        Ok(self
+            .entries()?
+            .into_iter()
+            .filter(|entry| replay.selects(entry))
+            .collect())
+    }
+
+    /// Removes the entry `id`, once it was replayed
+    ///
+    /// # Errors
+    ///  * if the entry can't be removed
+    pub(crate) fn remove(&self, id: u64) -> Result<()> {
+        if self.tree.remove(id.to_be_bytes())?.is_some() {
+            self.len.fetch_sub(1, Ordering::AcqRel);
+        }
+        Ok(())
+    }
+}
+
+/// The journal of the offramp instance `id`, if it has one
+///
+/// # Errors
+///  * if the journals lock is poisoned
+pub(crate) fn get(id: &TremorUrl) -> Result<Option<Journal>> {
+    Ok(JOURNALS.read()?.get(id).cloned())
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use tremor_value::literal;
+
+    #[test]
+    fn record_and_select() -> Result<()> {
+        let dir = tempfile::tempdir()?;
+        let config = Config {
+            dir: dir.path().to_string_lossy().to_string(),
+            max_entries: 2,
+        };
+        let id = TremorUrl::parse("/offramp/journal/01")?;
+        let journal = Journal::open(&id, &config)?;
+        for i in 1..=3_u64 {
+            let event = Event {
+                ingest_ns: i,
+                data: (literal!({ "i": i }), literal!({})).into(),
+                ..Event::default()
+            };
+            journal.record("in", "sink error", &event)?;
+        }
+        // the oldest entry was dropped
+        let entries = get(&id)?.ok_or("journal not registered")?.entries()?;
+        assert_eq!(
+            vec![2, 3],
+            entries.iter().map(|e| e.ingest_ns).collect::<Vec<_>>()
+        );
+        let last = &entries[1];
+        assert_eq!(Some(last), journal.get(last.id)?.as_ref());
+        assert_eq!(literal!({ "i": 3 }), last.value);
+
+        let by_id = Replay {
+            ids: vec![last.id],
+            ..Replay::default()
+        };
+        assert_eq!(vec![last.clone()], journal.select(&by_id)?);
+        let by_time = Replay {
+            to_ns: Some(last.failed_ns),
+            ..Replay::default()
+        };
+        assert_eq!(1, journal.select(&by_time)?.len());
+
+        journal.remove(last.id)?;
+        assert_eq!(None, journal.get(last.id)?);
+        Ok(())
+    }
+}
@@ -11,21 +11,30 @@
 // WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
 // See the License for the specific language governing permissions and
 // limitations under the License.
+use crate::backfill::Replay;
+use crate::config::{OnRampLimits, Priority};
+use crate::connectors::plugin;
 use crate::errors::Result;
 use crate::metrics::RampReporter;
 use crate::pipeline;
 use crate::repository::ServantId;
+#[cfg(target_os = "linux")]
+use crate::source::host_metrics;
 use crate::source::prelude::*;
-#[cfg(unix)]
-use crate::source::unix_socket;
+#[cfg(windows)]
+use crate::source::wineventlog;
 use crate::source::{
-    amqp, blaster, cb, crononome, discord, env, file, gsub, kafka, metronome, nats, otel, postgres,
-    rest, sse, stdin, tcp, udp, ws,
+    amqp, blaster, cb, crononome, discord, embedded, env, file, generator, gsub, influx,
+    interconnect, journald, kafka, kubernetes, metronome, nats, netflow, otel, pcap, postgres,
+    quic, replay, rest, sse, stdin, tcp, telegram, udp, webhook, ws,
 };
+#[cfg(unix)]
+use crate::source::{docker, unix_socket};
 use crate::url::TremorUrl;
 use async_std::task::{self, JoinHandle};
 use serde_yaml::Value;
 use std::fmt;
+use std::sync::Arc;
 use tremor_common::ids::OnrampIdGen;
 use tremor_pipeline::EventId;
 
@@ -45,6 +54,8 @@ pub enum Msg {
     Cb(CbAction, EventId),
     // TODO pick good naming here: LinkedEvent / Response / Result?
     Response(tremor_pipeline::Event),
+    /// The onramp replays historical data for a backfill
+    Backfill(Arc<Replay>),
 }
 
 pub type Addr = async_channel::Sender<Msg>;
@@ -57,11 +68,70 @@ pub(crate) struct OnrampConfig<'cfg> {
     pub metrics_reporter: RampReporter,
     pub is_linked: bool,
     pub err_required: bool,
+    pub limits: OnRampLimits,
+    pub priority: Priority,
+    pub singleton: bool,
+    /// index of the reader instance, if the onramp runs more than one
+    pub instance: Option<usize>,
 }
 #[async_trait::async_trait]
 pub(crate) trait Onramp: Send {
     async fn start(&mut self, config: OnrampConfig<'_>) -> Result<Addr>;
     fn default_codec(&self) -> &str;
+    /// If `start` may be called more than once, to run several reader
+    /// instances sharing the work
+    fn parallel(&self) -> bool {
+        false
+    }
+}
+
+/// Combines the addresses of the reader instances of an onramp, messages
+/// are sent to all of them, except responses that go to the instance that
+/// read the request
+fn fan_out(mut instances: Vec<(u64, Addr)>) -> Addr {
+    if instances.len() == 1 {
+        if let Some((_, addr)) = instances.pop() {
+            return addr;
+        }
+    }
+    let (tx, rx) = bounded(crate::QSIZE);
+    task::spawn::<_, Result<()>>(async move {
+        while let Ok(msg) = rx.recv().await {
+            match msg {
+                Msg::Disconnect { id, tx } => {
+                    let mut empty = true;
+                    for (_, addr) in &instances {
+                        let (instance_tx, instance_rx) = bounded(1);
+                        addr.send(Msg::Disconnect {
+                            id: id.clone(),
+                            tx: instance_tx,
+                        })
+                        .await?;
+                        empty &= instance_rx.recv().await?;
+                    }
+                    tx.send(empty).await?;
+                    if empty {
+                        break;
+                    }
+                }
+                Msg::Response(event) => {
+                    let instance = instances
+                        .iter()
+                        .find(|(uid, _)| event.id.get_max_by_source(*uid).is_some());
+                    if let Some((_, addr)) = instance {
+                        addr.send(Msg::Response(event)).await?;
+                    }
+                }
+                msg => {
+                    for (_, addr) in &instances {
+                        addr.send(msg.clone()).await?;
+                    }
+                }
+            }
+        }
+        Ok(())
+    });
+    tx
 }
 
 // just a lookup
@@ -75,25 +145,46 @@ pub(crate) fn lookup(
         "amqp" => amqp::Amqp::from_config(id, config),
         "blaster" => blaster::Blaster::from_config(id, config),
         "cb" => cb::Cb::from_config(id, config),
+        "embedded" => embedded::Embedded::from_config(id, config),
         "env" => env::Env::from_config(id, config),
         "file" => file::File::from_config(id, config),
+        "generator" => generator::Generate::from_config(id, config),
+        "influx" => influx::Influx::from_config(id, config),
+        "interconnect" => interconnect::Interconnect::from_config(id, config),
+        "journald" => journald::Journald::from_config(id, config),
         "kafka" => kafka::Kafka::from_config(id, config),
+        "kubernetes" => kubernetes::Kubernetes::from_config(id, config),
         "postgres" => postgres::Postgres::from_config(id, config),
         "metronome" => metronome::Metronome::from_config(id, config),
         "crononome" => crononome::Crononome::from_config(id, config),
         "stdin" => stdin::Stdin::from_config(id, config),
         "udp" => udp::Udp::from_config(id, config),
         "tcp" => tcp::Tcp::from_config(id, config),
+        "quic" => quic::Quic::from_config(id, config),
+        "replay" => replay::Replay::from_config(id, config),
         "rest" => rest::Rest::from_config(id, config),
         "sse" => sse::Sse::from_config(id, config),
         "ws" => ws::Ws::from_config(id, config),
+        "webhook" => webhook::Webhook::from_config(id, config),
         "discord" => discord::Discord::from_config(id, config),
+        "telegram" => telegram::Telegram::from_config(id, config),
         "otel" => otel::OpenTelemetry::from_config(id, config),
+        "pcap" => pcap::Pcap::from_config(id, config),
         "nats" => nats::Nats::from_config(id, config),
+        "netflow" => netflow::NetFlow::from_config(id, config),
         "gsub" => gsub::GoogleCloudPubSub::from_config(id, config),
+        #[cfg(target_os = "linux")]
+        "host-metrics" => host_metrics::HostMetrics::from_config(id, config),
+        #[cfg(unix)]
+        "docker" => docker::DockerOnramp::from_config(id, config),
         #[cfg(unix)]
         "unix-socket" => unix_socket::UnixSocket::from_config(id, config),
-        _ => Err(format!("[onramp:{}] Onramp type {} not known", id, name).into()),
+        #[cfg(windows)]
+        "wineventlog" => wineventlog::WinEventLog::from_config(id, config),
+        _ => match plugin::find(name, plugin::Kind::Onramp) {
+            Some(manifest) => crate::source::plugin::Plugin::from_manifest(id, manifest, config),
+            None => Err(format!("[onramp:{}] Onramp type {} not known", id, name).into()),
+        },
     }
 }
 
@@ -107,6 +198,10 @@ pub(crate) struct Create {
     pub metrics_reporter: RampReporter,
     pub is_linked: bool,
     pub err_required: bool,
+    pub limits: OnRampLimits,
+    pub priority: Priority,
+    pub singleton: bool,
+    pub parallelism: usize,
 }
 
 impl fmt::Debug for Create {
@@ -153,28 +248,53 @@ impl Manager {
                             is_linked,
                             id,
                             err_required,
+                            limits,
+                            priority,
+                            singleton,
+                            parallelism,
                         } = *c;
 
-                        match stream
-                            .start(OnrampConfig {
-                                onramp_uid: onramp_id_gen.next_id(),
-                                codec: &codec,
-                                codec_map,
-                                processors: Processors {
-                                    pre: &preprocessors,
-                                    post: &postprocessors,
-                                },
-                                metrics_reporter,
-                                is_linked,
-                                err_required,
-                            })
-                            .await
-                        {
-                            Ok(addr) => {
-                                info!("Onramp {} started.", id);
-                                r.send(Ok(addr)).await?;
+                        let instances: Vec<_> = if parallelism > 1 {
+                            (0..parallelism)
+                                .map(|i| (Some(i), metrics_reporter.for_instance(i)))
+                                .collect()
+                        } else {
+                            vec![(None, metrics_reporter)]
+                        };
+                        let mut addrs = Vec::with_capacity(instances.len());
+                        for (instance, metrics_reporter) in instances {
+                            let onramp_uid = onramp_id_gen.next_id();
+                            match stream
+                                .start(OnrampConfig {
+                                    onramp_uid,
+                                    codec: &codec,
+                                    codec_map: codec_map.clone(),
+                                    processors: Processors {
+                                        pre: &preprocessors,
+                                        post: &postprocessors,
+                                    },
+                                    metrics_reporter,
+                                    is_linked,
+                                    err_required,
+                                    limits,
+                                    priority,
+                                    singleton,
+                                    instance,
+                                })
+                                .await
+                            {
+                                Ok(addr) => addrs.push((onramp_uid, addr)),
+                                Err(e) => {
+                                    // dropping the started instances stops them
+                                    error!("Creating an onramp failed: {}", e);
+                                    addrs.clear();
+                                    break;
+                                }
                             }
-                            Err(e) => error!("Creating an onramp failed: {}", e),
+                        }
+                        if !addrs.is_empty() {
+                            info!("Onramp {} started.", id);
+                            r.send(Ok(fan_out(addrs))).await?;
                         }
                     }
                     Err(e) => {
@@ -455,4 +575,41 @@ links:
         );
         Ok(())
     }
+
+    #[async_std::test]
+    async fn fan_out_instances() -> Result<()> {
+        let (tx1, rx1) = async_channel::unbounded();
+        let (tx2, rx2) = async_channel::unbounded();
+        let addr = fan_out(vec![(1, tx1), (2, tx2)]);
+
+        addr.send(Msg::Cb(CbAction::Close, EventId::default()))
+            .await?;
+        assert!(matches!(rx1.recv().await?, Msg::Cb(CbAction::Close, _)));
+        assert!(matches!(rx2.recv().await?, Msg::Cb(CbAction::Close, _)));
+
+        // responses go to the instance that read the request
+        let event = Event {
+            id: EventId::new(2, 0, 42),
+            ..Event::default()
+        };
+        addr.send(Msg::Response(event)).await?;
+        assert!(matches!(rx2.recv().await?, Msg::Response(_)));
+        assert!(rx1.is_empty());
+
+        // disconnected once all instances are
+        let (tx, rx) = async_channel::bounded(1);
+        addr.send(Msg::Disconnect {
+            id: TremorUrl::parse("/pipeline/p/01")?,
+            tx,
+        })
+        .await?;
+        for instance in &[rx1, rx2] {
+            match instance.recv().await? {
+                Msg::Disconnect { tx, .. } => tx.send(true).await?,
+                _ => assert!(false, "Expected disconnect msg"),
+            }
+        }
+        assert!(rx.recv().await?);
+        Ok(())
+    }
 }
@@ -0,0 +1,150 @@
+// Copyright 2020-2021, The Tremor Team
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+//! Overflow policies of the links into pipelines. The queues of pipelines
+//! are bounded, the policy of a link decides what happens to events sent
+//! over it while the queue is full, see [`Overflow`].
+//!
+//! The policies are configured per link target in the bindings and apply to
+//! the links created afterwards, they are removed when the binding is
+//! unlinked.
+
+use crate::config::Overflow;
+use crate::errors::Result;
+use crate::url::TremorUrl;
+use async_channel::{Receiver, Sender, TrySendError};
+use halfbrown::HashMap;
+use std::sync::RwLock;
+
+lazy_static! {
+    static ref POLICIES: RwLock<HashMap<TremorUrl, Overflow>> = RwLock::new(HashMap::new());
+}
+
+/// Sets the overflow policy of the links to `target`
+///
+/// # Errors
+///  * if the policies lock is poisoned
+pub(crate) fn set(target: &TremorUrl, overflow: Overflow) -> Result<()> {
+    POLICIES.write()?.insert(target.clone(), overflow);
+    Ok(())
+}
+
+/// Removes the overflow policy of the links to `target`
+///
+/// # Errors
+///  * if the policies lock is poisoned
+pub(crate) fn remove(target: &TremorUrl) -> Result<()> {
+    POLICIES.write()?.remove(target);
+    Ok(())
+}
+
+/// The overflow policy of the links to `target`
+///
+/// # Errors
+///  * if the policies lock is poisoned
+pub(crate) fn get(target: &TremorUrl) -> Result<Overflow> {
+    Ok(POLICIES.read()?.get(target).copied().unwrap_or_default())
+}
+
+/// Outcome of offering a message to a bounded queue
+#[derive(Debug, PartialEq)]
+pub(crate) enum Offer<T> {
+    /// the message was queued
+    Queued,
+    /// the message, or the oldest queued one that made room for it, was
+    /// dropped
+    Dropped(T),
+    /// the queue is full, the message has to go to the `overflow` output
+    Overflow(T),
+}
+
+/// Queues `msg` in `tx` according to the `overflow` policy, `rx` is used to
+/// drop the oldest message.
+///
+/// # Errors
+///  * if the queue is closed
+pub(crate) async fn offer<T>(
+    tx: &Sender<T>,
+    rx: &Receiver<T>,
+    overflow: Overflow,
+    msg: T,
+) -> Result<Offer<T>> {
+    if overflow == Overflow::Block {
+        tx.send(msg).await?;
+        return Ok(Offer::Queued);
+    }
+    match tx.try_send(msg) {
+        Ok(()) => Ok(Offer::Queued),
+        Err(TrySendError::Full(msg)) => match overflow {
+            Overflow::DropNewest => Ok(Offer::Dropped(msg)),
+            Overflow::RouteToOverflowPort => Ok(Offer::Overflow(msg)),
+            Overflow::DropOldest | Overflow::Block => {
+                let oldest = rx.try_recv().ok();
+                // another sender can take the room we made, we only wait
+                // for the pipeline in that case
+                tx.send(msg).await?;
+                Ok(oldest.map_or(Offer::Queued, Offer::Dropped))
+            }
+        },
+        Err(e) => Err(e.into()),
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use async_channel::bounded;
+
+    #[async_std::test]
+    async fn policies() -> Result<()> {
+        let (tx, rx) = bounded(1);
+        assert_eq!(Offer::Queued, offer(&tx, &rx, Overflow::Block, 1).await?);
+
+        assert_eq!(
+            Offer::Dropped(2),
+            offer(&tx, &rx, Overflow::DropNewest, 2).await?
+        );
+        assert_eq!(
+            Offer::Overflow(3),
+            offer(&tx, &rx, Overflow::RouteToOverflowPort, 3).await?
+        );
+        assert_eq!(1, rx.recv().await?);
+
+        assert_eq!(
+            Offer::Queued,
+            offer(&tx, &rx, Overflow::DropOldest, 4).await?
+        );
+        assert_eq!(
+            Offer::Dropped(4),
+            offer(&tx, &rx, Overflow::DropOldest, 5).await?
+        );
+        assert_eq!(5, rx.recv().await?);
+
+        drop(rx);
+        let (_, rx) = bounded(1);
+        assert!(offer(&tx, &rx, Overflow::DropNewest, 6).await.is_err());
+        Ok(())
+    }
+
+    #[test]
+    fn lookup() -> Result<()> {
+        let target = TremorUrl::parse("/pipeline/overflow/01/in")?;
+        assert_eq!(Overflow::Block, get(&target)?);
+        set(&target, Overflow::DropNewest)?;
+        assert_eq!(Overflow::DropNewest, get(&target)?);
+        remove(&target)?;
+        assert_eq!(Overflow::Block, get(&target)?);
+        Ok(())
+    }
+}
@@ -11,11 +11,16 @@
 // WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
 // See the License for the specific language governing permissions and
 // limitations under the License.
-use crate::errors::{Error, Result};
+use crate::config::{self, Overflow, Priority};
+use crate::debugger;
+use crate::errors::{Error, Kind as ErrorKind, Result};
+use crate::metrics::{self, DropReason, Drops};
+use crate::namespace;
+use crate::overflow::{self, Offer};
 use crate::permge::{PriorityMerge, M};
 use crate::registry::ServantId;
 use crate::repository::PipelineArtefact;
-use crate::url::TremorUrl;
+use crate::url::{ports::OVERFLOW, TremorUrl};
 use crate::{offramp, onramp};
 use async_channel::{bounded, unbounded};
 use async_std::stream::StreamExt;
@@ -23,43 +28,69 @@ use async_std::task::{self, JoinHandle};
 use beef::Cow;
 use std::fmt;
 use std::time::Duration;
+use tremor_common::arena;
 use tremor_common::ids::OperatorIdGen;
 use tremor_common::time::nanotime;
 use tremor_pipeline::errors::ErrorKind as PipelineErrorKind;
-use tremor_pipeline::{CbAction, Event, ExecutableGraph, SignalKind};
+use tremor_pipeline::{CbAction, Event, ExecutableGraph, SignalKind, Snapshot};
+
+mod canary;
+pub(crate) mod standby;
+
+use canary::Canary;
+pub(crate) use canary::Outcome as CanaryOutcome;
+use standby::Standby;
 
 const TICK_MS: u64 = 100;
 pub(crate) type Sender = async_channel::Sender<ManagerMsg>;
 type Inputs = halfbrown::HashMap<TremorUrl, (bool, Input)>;
 type Dests = halfbrown::HashMap<Cow<'static, str>, Vec<(TremorUrl, Dest)>>;
-type Eventset = Vec<(Cow<'static, str>, Event)>;
+pub(crate) type Eventset = Vec<(Cow<'static, str>, Event)>;
 /// Address for a pipeline
 #[derive(Clone)]
 pub struct Addr {
     addr: async_channel::Sender<Msg>,
+    /// receiving end of `addr`, to drop the oldest events on overflow
+    drain: async_channel::Receiver<Msg>,
+    /// queue of high priority events
+    prio_addr: async_channel::Sender<Msg>,
     cf_addr: async_channel::Sender<CfMsg>,
     mgmt_addr: async_channel::Sender<MgmtMsg>,
     id: ServantId,
+    /// overflow policy of the link this address is used for
+    overflow: Overflow,
 }
 
 impl Addr {
     /// creates a new address
     pub(crate) fn new(
         addr: async_channel::Sender<Msg>,
+        drain: async_channel::Receiver<Msg>,
+        prio_addr: async_channel::Sender<Msg>,
         cf_addr: async_channel::Sender<CfMsg>,
         mgmt_addr: async_channel::Sender<MgmtMsg>,
         id: ServantId,
     ) -> Self {
         Self {
             addr,
+            drain,
+            prio_addr,
             cf_addr,
             mgmt_addr,
             id,
+            overflow: Overflow::default(),
         }
     }
+
+    /// the address for a link with the given overflow policy
+    #[must_use]
+    pub(crate) fn with_overflow(mut self, overflow: Overflow) -> Self {
+        self.overflow = overflow;
+        self
+    }
     #[cfg(not(tarpaulin_include))]
     pub fn len(&self) -> usize {
-        self.addr.len()
+        self.addr.len() + self.prio_addr.len()
     }
     #[cfg(not(tarpaulin_include))]
     pub fn id(&self) -> &ServantId {
@@ -70,13 +101,58 @@ impl Addr {
         Ok(self.cf_addr.send(CfMsg::Insight(event)).await?)
     }
 
+    /// high priority events are queued separately and overtake the others
+    fn queue(&self, priority: Priority) -> &async_channel::Sender<Msg> {
+        match priority {
+            Priority::High => &self.prio_addr,
+            Priority::Normal => &self.addr,
+        }
+    }
+
     pub(crate) async fn send(&self, msg: Msg) -> Result<()> {
-        Ok(self.addr.send(msg).await?)
+        Ok(self.queue(msg.priority()).send(msg).await?)
+    }
+
+    /// Sends an event according to the overflow policy of the link, high
+    /// priority events are never dropped. Signals are queued with them, so
+    /// dropping the oldest message never evicts a signal. Dropped transactional
+    /// events are failed upstream through the contraflow of the pipeline.
+    ///
+    /// The priority is looked up once by the caller, so events sent to
+    /// several destinations don't look it up for each of them.
+    pub(crate) async fn send_event(
+        &self,
+        input: Cow<'static, str>,
+        event: Event,
+        priority: Priority,
+    ) -> Result<Offer<Event>> {
+        if priority == Priority::High {
+            self.prio_addr.send(Msg::Event { event, input }).await?;
+            return Ok(Offer::Queued);
+        }
+        if self.overflow == Overflow::Block {
+            self.addr.send(Msg::Event { event, input }).await?;
+            return Ok(Offer::Queued);
+        }
+        let msg = Msg::Event { event, input };
+        match overflow::offer(&self.addr, &self.drain, self.overflow, msg).await? {
+            Offer::Queued => Ok(Offer::Queued),
+            Offer::Dropped(Msg::Event { event, .. }) => {
+                if event.transactional {
+                    self.send_insight(event.insight_fail()).await?;
+                }
+                Ok(Offer::Dropped(event))
+            }
+            Offer::Overflow(Msg::Event { event, .. }) => Ok(Offer::Overflow(event)),
+            Offer::Dropped(Msg::Signal(_)) | Offer::Overflow(Msg::Signal(_)) => {
+                Err("Signals are queued with high priority, they can't be dropped".into())
+            }
+        }
     }
 
     #[cfg(not(tarpaulin_include))]
     pub(crate) fn try_send(&self, msg: Msg) -> Result<()> {
-        Ok(self.addr.try_send(msg)?)
+        Ok(self.queue(msg.priority()).try_send(msg)?)
     }
 
     pub(crate) async fn send_mgmt(&self, msg: MgmtMsg) -> Result<()> {
@@ -118,6 +194,19 @@ pub(crate) enum MgmtMsg {
     },
     DisconnectOutput(Cow<'static, str>, TremorUrl),
     DisconnectInput(TremorUrl),
+    /// replaces the graph, keeping the inputs and outputs connected
+    Reload(Box<ExecutableGraph>),
+    /// runs a canary alongside the graph, replacing any running canary
+    /// without a verdict
+    Canary(Box<Canary>),
+    /// takes a snapshot of the state of the graph
+    Snapshot(async_channel::Sender<Snapshot>),
+    /// restores the state of the graph, events held by it are dropped
+    Restore(Box<Snapshot>, async_channel::Sender<Result<()>>),
+    /// keeps a warm standby of the graph, replacing any previous one
+    Standby(Box<Standby>),
+    /// attaches a debugging session, rejected if one is attached already
+    Debug(debugger::Session),
     #[cfg(test)]
     Echo(async_channel::Sender<()>),
 }
@@ -131,6 +220,17 @@ pub(crate) enum Msg {
     Signal(Event),
 }
 
+impl Msg {
+    /// signals always have the high priority, the queue of normal priority
+    /// events is drained by overflow policies and signals must not be lost
+    fn priority(&self) -> Priority {
+        match self {
+            Self::Event { event, .. } => Priority::of(event),
+            Self::Signal(_) => Priority::High,
+        }
+    }
+}
+
 #[derive(Debug)]
 pub enum Dest {
     Offramp(offramp::Addr),
@@ -139,13 +239,18 @@ pub enum Dest {
 }
 
 impl Dest {
-    pub async fn send_event(&mut self, input: Cow<'static, str>, event: Event) -> Result<()> {
+    pub(crate) async fn send_event(
+        &mut self,
+        input: Cow<'static, str>,
+        event: Event,
+        priority: Priority,
+    ) -> Result<Offer<Event>> {
         match self {
-            Self::Offramp(addr) => addr.send(offramp::Msg::Event { input, event }).await?,
-            Self::Pipeline(addr) => addr.send(Msg::Event { input, event }).await?,
+            Self::Offramp(addr) => addr.send_event(input, event, priority).await?,
+            Self::Pipeline(addr) => return addr.send_event(input, event, priority).await,
             Self::LinkedOnramp(addr) => addr.send(onramp::Msg::Response(event)).await?,
         }
-        Ok(())
+        Ok(Offer::Queued)
     }
     pub async fn send_signal(&mut self, signal: Event) -> Result<()> {
         match self {
@@ -200,11 +305,21 @@ impl From<ConnectTarget> for Input {
 pub struct Create {
     pub config: PipelineArtefact,
     pub id: ServantId,
+    /// version of the pipeline the config is
+    pub version: usize,
 }
 
 pub(crate) enum ManagerMsg {
     Stop,
     Create(async_channel::Sender<Result<Addr>>, Box<Create>),
+    Reload(async_channel::Sender<Result<()>>, Addr, Box<Create>),
+    Canary(
+        async_channel::Sender<Result<()>>,
+        Addr,
+        Box<Create>,
+        config::Canary,
+        async_channel::Sender<CanaryOutcome>,
+    ),
 }
 
 #[derive(Default, Debug)]
@@ -214,22 +329,61 @@ pub(crate) struct Manager {
 }
 
 #[inline]
-async fn send_events(eventset: &mut Eventset, dests: &mut Dests) -> Result<()> {
+async fn send_events(eventset: &mut Eventset, dests: &mut Dests, drops: &mut Drops) -> Result<()> {
+    let mut overflow = Vec::new();
     for (output, event) in eventset.drain(..) {
-        if let Some(dest) = dests.get_mut(&output) {
-            if let Some((last, rest)) = dest.split_last_mut() {
+        match dests
+            .get_mut(&output)
+            .and_then(|dest| dest.split_last_mut())
+        {
+            Some((last, rest)) => {
+                let priority = Priority::of(&event);
                 for (id, offramp) in rest {
                     let port = id.instance_port_required()?.to_string().into();
-                    offramp.send_event(port, event.clone()).await?;
+                    let offer = offramp.send_event(port, event.clone(), priority).await?;
+                    handle_offer(offer, &output, &mut overflow, drops);
                 }
                 let last_port = last.0.instance_port_required()?.to_string().into();
-                last.1.send_event(last_port, event).await?;
+                let offer = last.1.send_event(last_port, event, priority).await?;
+                handle_offer(offer, &output, &mut overflow, drops);
             }
-        };
+            None => drops.record(&output, DropReason::of_output(&output), 1),
+        }
+    }
+    // events that didn't fit into the queue of a linked pipeline go to the
+    // `overflow` output, they are dropped if they don't fit there either
+    for event in overflow {
+        match dests.get_mut(&OVERFLOW) {
+            Some(dests) => {
+                let priority = Priority::of(&event);
+                for (id, dest) in dests.iter_mut() {
+                    let port = id.instance_port_required()?.to_string().into();
+                    let offer = dest.send_event(port, event.clone(), priority).await?;
+                    if !matches!(offer, Offer::Queued) {
+                        drops.record(&OVERFLOW, DropReason::QueueFull, 1);
+                    }
+                }
+            }
+            None => drops.record(&OVERFLOW, DropReason::Overflow, 1),
+        }
     }
     Ok(())
 }
 
+#[inline]
+fn handle_offer(
+    offer: Offer<Event>,
+    output: &Cow<'static, str>,
+    overflow: &mut Vec<Event>,
+    drops: &mut Drops,
+) {
+    match offer {
+        Offer::Queued => (),
+        Offer::Dropped(_) => drops.record(output, DropReason::QueueFull, 1),
+        Offer::Overflow(event) => overflow.push(event),
+    }
+}
+
 #[inline]
 async fn send_signal(own_id: &TremorUrl, signal: Event, dests: &mut Dests) -> Result<()> {
     let mut offramps = dests.values_mut().flatten();
@@ -354,12 +508,72 @@ fn maybe_send(r: Result<()>) {
     }
 }
 
+fn format_event_error(pipeline: &ExecutableGraph, e: tremor_pipeline::errors::Error) -> String {
+    if let PipelineErrorKind::Script(script_kind) = e.0 {
+        let script_error = tremor_script::errors::Error(script_kind, e.1);
+        // possibly a hygienic error
+        pipeline
+            .source
+            .as_ref()
+            .and_then(|s| script_error.locate_in_source(s))
+            .map_or_else(
+                || format!(" {:?}", script_error),
+                |located| format!("\n{}", located),
+            ) // add a newline to have the error nicely formatted in the log
+    } else {
+        format!(" {}", e)
+    }
+}
+
+/// Promotes or rolls back the canary once it has processed enough events
+fn resolve_canary(pid: &TremorUrl, pipeline: &mut ExecutableGraph, canary: &mut Option<Canary>) {
+    if let Some(outcome) = canary.as_ref().and_then(Canary::verdict) {
+        if let Some(c) = canary.take() {
+            let (stats, baseline) = (c.stats, c.baseline);
+            let graph = c.resolve(outcome);
+            if outcome == CanaryOutcome::Promoted {
+                info!(
+                    "[Pipeline::{}] Promoting canary, {:?} against {:?}",
+                    pid, stats, baseline
+                );
+                *pipeline = graph;
+                pipeline.id = pid.to_string();
+            } else {
+                warn!(
+                    "[Pipeline::{}] Rolling back canary, {:?} against {:?}",
+                    pid, stats, baseline
+                );
+            }
+        }
+    }
+}
+
+/// Syncs the state of the active graph to the standby, a standby that can't
+/// be synced is dropped
+fn sync_standby(pid: &TremorUrl, pipeline: &ExecutableGraph, standby: &mut Option<Standby>) {
+    if let Some(Err(e)) = standby.as_mut().map(|s| s.sync(pipeline, nanotime())) {
+        warn!("[Pipeline::{}] Dropping the standby: {}", pid, e);
+        *standby = None;
+    }
+}
+
+/// Closes the event queue once the pipeline task ends, the addresses of the
+/// pipeline hold receivers of it to drop the oldest events on overflow
+struct CloseOnDrop(async_channel::Receiver<Msg>);
+
+impl Drop for CloseOnDrop {
+    fn drop(&mut self) {
+        self.0.close();
+    }
+}
+
 #[allow(clippy::too_many_lines)]
 async fn pipeline_task(
     id: TremorUrl,
     mut pipeline: ExecutableGraph,
     addr: Addr,
     rx: async_channel::Receiver<Msg>,
+    prio_rx: async_channel::Receiver<Msg>,
     cf_rx: async_channel::Receiver<CfMsg>,
     mgmt_rx: async_channel::Receiver<MgmtMsg>,
 ) -> Result<()> {
@@ -369,48 +583,156 @@ async fn pipeline_task(
 
     let mut dests: Dests = halfbrown::HashMap::new();
     let mut inputs: Inputs = halfbrown::HashMap::new();
-    let mut eventset: Eventset = Vec::new();
+    // allocated up front so the first event doesn't allocate it from the arena,
+    // it lives as long as the pipeline
+    let mut eventset: Eventset = Vec::with_capacity(64);
+    let mut canary: Option<Canary> = None;
+    let mut standby: Option<Standby> = None;
+    let mut debug: Option<debugger::Session> = None;
+    // events of namespaced pipelines count against the event rate of their namespace
+    let rate_limit = pid
+        .artefact()
+        .and_then(namespace::split)
+        .filter(|(ns, _)| *ns != namespace::SYSTEM)
+        .map(|(ns, _)| namespace::rate_limit(ns));
+    let mut throttled = false;
+    let mut drops = Drops::new(pid.clone());
+    let _queue = CloseOnDrop(rx.clone());
 
     info!("[Pipeline:{}] starting task.", id);
 
-    let ff = rx.map(M::F);
+    // high priority events overtake the queued forward event flow
+    let ff = PriorityMerge::new(prio_rx.map(M::F), rx.map(M::F));
     let cf = cf_rx.map(M::C);
     let mf = mgmt_rx.map(M::M);
 
     // prioritize management flow over contra flow over forward event flow
     let mut s = PriorityMerge::new(mf, PriorityMerge::new(cf, ff));
     while let Some(msg) = s.next().await {
+        if debug.is_some() {
+            debugger::poll(&pid, &mut pipeline, &mut debug).await;
+        }
         match msg {
             M::C(msg) => {
+                if let (Some(c), CfMsg::Insight(insight)) = (canary.as_mut(), &msg) {
+                    // insights can't be attributed to either graph, so both get them
+                    // but only the ones of the active graph are forwarded to the inputs
+                    c.graph.contraflow(None, insight.clone());
+                }
                 handle_cf_msg(msg, &mut pipeline, &inputs).await?;
             }
-            M::F(Msg::Event { input, event }) => {
-                match pipeline.enqueue(&input, event, &mut eventset) {
-                    Ok(()) => {
-                        handle_insights(&mut pipeline, &inputs).await;
-                        maybe_send(send_events(&mut eventset, &mut dests).await);
+            M::F(Msg::Event { input, mut event }) => {
+                if let Some(limit) = &rate_limit {
+                    if limit.admit(nanotime()) {
+                        throttled = false;
+                    } else {
+                        if !throttled {
+                            warn!(
+                                "[Pipeline::{}] Event rate quota of the namespace exceeded, dropping events",
+                                pid
+                            );
+                        }
+                        throttled = true;
+                        drops.record(&input, DropReason::RateLimit, 1);
+                        // tell transactional inputs the event was not processed
+                        handle_insight(None, event.insight_fail(), &mut pipeline, &inputs).await;
+                        continue;
                     }
-                    Err(e) => {
-                        let err_str = if let PipelineErrorKind::Script(script_kind) = e.0 {
-                            let script_error = tremor_script::errors::Error(script_kind, e.1);
-                            // possibly a hygienic error
-                            pipeline
-                                .source
-                                .as_ref()
-                                .and_then(|s| script_error.locate_in_source(s))
-                                .map_or_else(
-                                    || format!(" {:?}", script_error),
-                                    |located| format!("\n{}", located),
-                                ) // add a newline to have the error nicely formatted in the log
-                        } else {
-                            format!(" {}", e)
-                        };
-                        error!("Error handling event:{}", err_str);
+                }
+                let event = if let Some(c) = canary.as_mut() {
+                    if c.takes_next() {
+                        let r = arena::scope(|| c.graph.enqueue(&input, event, &mut eventset));
+                        c.stats.record(r.is_err(), &eventset);
+                        match r {
+                            Ok(()) => {
+                                handle_insights(&mut c.graph, &inputs).await;
+                                maybe_send(
+                                    send_events(&mut eventset, &mut dests, &mut drops).await,
+                                );
+                            }
+                            Err(e) => {
+                                let err_str = format_event_error(&c.graph, e);
+                                error!("Error handling event in canary:{}", err_str);
+                            }
+                        }
+                        None
+                    } else if c.is_mirror() {
+                        // the output of the mirrored events is discarded
+                        let mut mirrored = Eventset::new();
+                        let r =
+                            arena::scope(|| c.graph.enqueue(&input, event.clone(), &mut mirrored));
+                        c.stats.record(r.is_err(), &mirrored);
+                        c.graph.insights.clear();
+                        Some(event)
+                    } else {
+                        Some(event)
+                    }
+                } else {
+                    Some(event)
+                };
+                if let Some(event) = event {
+                    // the standby retries the event if the active graph fails on it
+                    let retry = standby.as_ref().map(|_| event.clone());
+                    let r = match (
+                        arena::scope(|| pipeline.enqueue(&input, event, &mut eventset)),
+                        standby.as_mut(),
+                        retry,
+                    ) {
+                        (Err(e), Some(s), Some(event)) => {
+                            warn!(
+                                "[Pipeline::{}] Standby taking over after error:{}",
+                                pid,
+                                format_event_error(&pipeline, e)
+                            );
+                            s.take_over(&mut pipeline);
+                            eventset.clear();
+                            arena::scope(|| pipeline.enqueue(&input, event, &mut eventset))
+                        }
+                        (r, _, _) => r,
+                    };
+                    let r = match r {
+                        Ok(()) => {
+                            debugger::step_through(&pid, &mut pipeline, &mut debug, &mut eventset)
+                                .await
+                        }
+                        e => e,
+                    };
+                    if let Some(c) = canary.as_mut() {
+                        c.baseline.record(r.is_err(), &eventset);
+                    }
+                    match r {
+                        Ok(()) => {
+                            handle_insights(&mut pipeline, &inputs).await;
+                            maybe_send(send_events(&mut eventset, &mut dests, &mut drops).await);
+                        }
+                        Err(e) => {
+                            drops.record(&input, DropReason::Error, 1);
+                            error!("Error handling event:{}", format_event_error(&pipeline, e));
+                        }
                     }
                 }
+                resolve_canary(&pid, &mut pipeline, &mut canary);
+                sync_standby(&pid, &pipeline, &mut standby);
             }
             M::F(Msg::Signal(signal)) => {
-                if let Err(e) = pipeline.enqueue_signal(signal.clone(), &mut eventset) {
+                if let Some(c) = canary.as_mut() {
+                    let mut canary_events = Eventset::new();
+                    if let Err(e) = c.graph.enqueue_signal(signal.clone(), &mut canary_events) {
+                        error!("[Pipeline::{}] Error handling signal in canary: {}", pid, e);
+                    } else if c.is_mirror() {
+                        c.graph.insights.clear();
+                    } else {
+                        handle_insights(&mut c.graph, &inputs).await;
+                        maybe_send(send_events(&mut canary_events, &mut dests, &mut drops).await);
+                    }
+                }
+                let r = match pipeline.enqueue_signal(signal.clone(), &mut eventset) {
+                    Ok(()) => {
+                        debugger::step_through(&pid, &mut pipeline, &mut debug, &mut eventset).await
+                    }
+                    e => e,
+                };
+                if let Err(e) = r {
                     let err_str = if let PipelineErrorKind::Script(script_kind) = e.0 {
                         let script_error = tremor_script::errors::Error(script_kind, e.1);
                         // possibly a hygienic error
@@ -429,8 +751,9 @@ async fn pipeline_task(
                 } else {
                     maybe_send(send_signal(&id, signal, &mut dests).await);
                     handle_insights(&mut pipeline, &inputs).await;
-                    maybe_send(send_events(&mut eventset, &mut dests).await);
+                    maybe_send(send_events(&mut eventset, &mut dests, &mut drops).await);
                 }
+                sync_standby(&pid, &pipeline, &mut standby);
             }
             M::M(MgmtMsg::ConnectInput {
                 input_url,
@@ -511,6 +834,46 @@ async fn pipeline_task(
                 info!("[Pipeline::{}] Disconnecting {} from 'in'", pid, &input_url);
                 inputs.remove(&input_url);
             }
+            M::M(MgmtMsg::Reload(graph)) => {
+                // events held by the old graph, e.g. in windows, are dropped
+                info!("[Pipeline::{}] Reloading", pid);
+                pipeline = *graph;
+                pipeline.id = pid.to_string();
+                // a running canary is dropped without a verdict
+                canary = None;
+                // the standby of the old graph is replaced by the manager
+                standby = None;
+            }
+            M::M(MgmtMsg::Snapshot(tx)) => {
+                if let Err(e) = tx.send(pipeline.snapshot()).await {
+                    error!("[Pipeline::{}] Error sending the snapshot: {}", pid, e);
+                }
+            }
+            M::M(MgmtMsg::Restore(snapshot, tx)) => {
+                info!("[Pipeline::{}] Restoring state from a snapshot", pid);
+                let r = pipeline.restore(*snapshot).map_err(|e| {
+                    Error::from(ErrorKind::RestoreFailed(pid.to_string(), e.to_string()))
+                });
+                if let Err(e) = tx.send(r).await {
+                    error!("[Pipeline::{}] Error responding to restore: {}", pid, e);
+                }
+            }
+            M::M(MgmtMsg::Standby(new)) => {
+                info!("[Pipeline::{}] Keeping a warm standby", pid);
+                standby = Some(*new);
+            }
+            M::M(MgmtMsg::Canary(new)) => {
+                if canary.is_some() {
+                    warn!("[Pipeline::{}] Replacing the running canary", pid);
+                }
+                info!("[Pipeline::{}] Deploying canary", pid);
+                let mut new = *new;
+                new.graph.id = pid.to_string();
+                canary = Some(new);
+            }
+            M::M(MgmtMsg::Debug(session)) => {
+                debugger::attach(&pid, &mut pipeline, &mut debug, session).await;
+            }
             #[cfg(test)]
             M::M(MgmtMsg::Echo(sender)) => {
                 if let Err(e) = sender.send(()).await {
@@ -547,6 +910,13 @@ impl Manager {
                     Ok(ManagerMsg::Create(r, create)) => {
                         r.send(self.start_pipeline(*create)).await?;
                     }
+                    Ok(ManagerMsg::Reload(r, addr, create)) => {
+                        r.send(self.reload_pipeline(&addr, *create).await).await?;
+                    }
+                    Ok(ManagerMsg::Canary(r, addr, create, config, outcome)) => {
+                        let res = self.deploy_canary(&addr, *create, config, outcome).await;
+                        r.send(res).await?;
+                    }
                     Err(e) => {
                         info!("Stopping Pipeline manager... {}", e);
                         break;
@@ -559,13 +929,54 @@ impl Manager {
         (h, tx)
     }
 
+    /// Creates the graph of a pipeline instance, with the metrics of the instance
+    fn graph(&mut self, req: &Create) -> Result<ExecutableGraph> {
+        let mut graph = req.config.to_pipe(&mut self.operator_id_gen)?;
+        let configured = graph.metric_interval().map(|ns| ns / 1_000_000_000);
+        let interval = metrics::interval_s(&req.id, configured)?.map(|s| s * 1_000_000_000);
+        graph.configure_metrics(interval, metrics::instance_tags(&req.id, req.version));
+        Ok(graph)
+    }
+
+    /// Creates the warm standby of a pipeline instance, if one is configured
+    fn standby(&mut self, req: &Create) -> Result<Option<Standby>> {
+        if let Some(config) = standby::get(&req.id)? {
+            Ok(Some(Standby::new(self.graph(req)?, config)))
+        } else {
+            Ok(None)
+        }
+    }
+
+    async fn reload_pipeline(&mut self, addr: &Addr, req: Create) -> Result<()> {
+        let pipeline = self.graph(&req)?;
+        let standby = self.standby(&req)?;
+        addr.send_mgmt(MgmtMsg::Reload(Box::new(pipeline))).await?;
+        if let Some(standby) = standby {
+            addr.send_mgmt(MgmtMsg::Standby(Box::new(standby))).await?;
+        }
+        Ok(())
+    }
+
+    async fn deploy_canary(
+        &mut self,
+        addr: &Addr,
+        req: Create,
+        config: config::Canary,
+        outcome: async_channel::Sender<CanaryOutcome>,
+    ) -> Result<()> {
+        let graph = self.graph(&req)?;
+        let canary = Canary::new(graph, config, outcome);
+        addr.send_mgmt(MgmtMsg::Canary(Box::new(canary))).await
+    }
+
     fn start_pipeline(&mut self, req: Create) -> Result<Addr> {
-        let config = req.config;
-        let pipeline = config.to_pipe(&mut self.operator_id_gen)?;
+        let pipeline = self.graph(&req)?;
+        let standby = self.standby(&req)?;
 
         let id = req.id.clone();
 
         let (tx, rx) = bounded::<Msg>(self.qsize);
+        let (prio_tx, prio_rx) = bounded::<Msg>(self.qsize);
         // We use a unbounded channel for counterflow, while an unbounded channel seems dangerous
         // there is soundness to this.
         // The unbounded channel ensures that on counterflow we never have to block, or in other
@@ -584,10 +995,16 @@ impl Manager {
         // N is normally < 1.
         let (cf_tx, cf_rx) = unbounded::<CfMsg>();
         let (mgmt_tx, mgmt_rx) = bounded::<MgmtMsg>(self.qsize);
+        if let Some(standby) = standby {
+            // the queue is empty, the task picks the standby up first
+            mgmt_tx
+                .try_send(MgmtMsg::Standby(Box::new(standby)))
+                .map_err(|e| format!("Failed to set up the standby of {}: {}", id, e))?;
+        }
 
-        task::spawn(tick(tx.clone()));
+        task::spawn(tick(prio_tx.clone()));
 
-        let addr = Addr::new(tx, cf_tx, mgmt_tx, req.id);
+        let addr = Addr::new(tx, rx.clone(), prio_tx, cf_tx, mgmt_tx, req.id);
         task::Builder::new()
             .name(format!("pipeline-{}", id))
             .spawn(pipeline_task(
@@ -595,6 +1012,7 @@ impl Manager {
                 pipeline,
                 addr.clone(),
                 rx,
+                prio_rx,
                 cf_rx,
                 mgmt_rx,
             ))?;
@@ -667,7 +1085,11 @@ mod tests {
         let (handle, sender) = manager.start();
 
         let (tx, rx) = async_channel::bounded(1);
-        let create = Create { config, id };
+        let create = Create {
+            config,
+            id,
+            version: 1,
+        };
         let create_msg = ManagerMsg::Create(tx, Box::new(create));
         sender.send(create_msg).await?;
         let addr = rx.recv().await??;
@@ -809,17 +1231,22 @@ mod tests {
         let manager = Manager::new(12);
         let (handle, sender) = manager.start();
         let (tx, rx) = async_channel::bounded(1);
-        let create = Create { config, id };
+        let create = Create {
+            config,
+            id,
+            version: 1,
+        };
         let create_msg = ManagerMsg::Create(tx, Box::new(create));
         sender.send(create_msg).await?;
         let addr = rx.recv().await??;
         let (offramp_tx, offramp_rx) = async_channel::unbounded();
+        let (offramp_prio_tx, _offramp_prio_rx) = async_channel::unbounded();
         let offramp_url = TremorUrl::parse("/offramp/fake_offramp/instance/in")?;
         // connect a channel so we can receive events from the back of the pipeline :)
         addr.send_mgmt(MgmtMsg::ConnectOutput {
             port: OUT,
             output_url: offramp_url.clone(),
-            target: ConnectTarget::Offramp(offramp_tx.clone()),
+            target: ConnectTarget::Offramp(offramp::Addr::new(offramp_tx, offramp_prio_tx)),
         })
         .await?;
         manager_fence(&addr).await?;
@@ -882,4 +1309,306 @@ mod tests {
         handle.cancel().await;
         Ok(())
     }
+
+    #[async_std::test]
+    async fn test_pipeline_reload() -> Result<()> {
+        let module_path = ModulePath { mounts: vec![] };
+        let aggr_reg: tremor_script::registry::Aggr = tremor_script::aggr_registry();
+        let parse = |query: &str| -> Result<PipelineArtefact> {
+            Ok(tremor_pipeline::query::Query(Query::parse(
+                &module_path,
+                "reload_test.trickle",
+                query,
+                vec![],
+                &*FN_REGISTRY.lock()?,
+                &aggr_reg,
+            )?))
+        };
+        let config = parse("select event from in into out;")?;
+        let id = TremorUrl::parse("/pipeline/reload_test/instance")?;
+        let manager = Manager::new(12);
+        let (handle, sender) = manager.start();
+        let (tx, rx) = async_channel::bounded(1);
+        let create = Create {
+            config,
+            id: id.clone(),
+            version: 1,
+        };
+        sender
+            .send(ManagerMsg::Create(tx, Box::new(create)))
+            .await?;
+        let addr = rx.recv().await??;
+        let (offramp_tx, offramp_rx) = async_channel::unbounded();
+        let (offramp_prio_tx, _offramp_prio_rx) = async_channel::unbounded();
+        addr.send_mgmt(MgmtMsg::ConnectOutput {
+            port: OUT,
+            output_url: TremorUrl::parse("/offramp/fake_offramp/instance/in")?,
+            target: ConnectTarget::Offramp(offramp::Addr::new(offramp_tx, offramp_prio_tx)),
+        })
+        .await?;
+        manager_fence(&addr).await?;
+
+        let send = |value: Value<'static>| {
+            addr.send(Msg::Event {
+                event: Event {
+                    data: value.into(),
+                    ..Event::default()
+                },
+                input: "in".into(),
+            })
+        };
+        send(literal!({"v": 1})).await?;
+        let event = wait_for_event(&offramp_rx, None).await?;
+        assert_eq!(&literal!({"v": 1}), event.data.suffix().value());
+
+        // the new graph stays connected to the offramp
+        let config = parse("select {\"v\": 2, \"in\": event.v} from in into out;")?;
+        let (tx, rx) = async_channel::bounded(1);
+        let create = Create {
+            config,
+            id,
+            version: 1,
+        };
+        sender
+            .send(ManagerMsg::Reload(tx, addr.clone(), Box::new(create)))
+            .await?;
+        rx.recv().await??;
+        manager_fence(&addr).await?;
+
+        send(literal!({"v": 1})).await?;
+        let event = wait_for_event(&offramp_rx, None).await?;
+        assert_eq!(&literal!({"v": 2, "in": 1}), event.data.suffix().value());
+
+        sender.send(ManagerMsg::Stop).await?;
+        handle.cancel().await;
+        Ok(())
+    }
+
+    #[async_std::test]
+    async fn test_pipeline_debug() -> Result<()> {
+        use debugger::{Command, Session, Update};
+        use tremor_pipeline::debug::Breakpoint;
+
+        let module_path = ModulePath { mounts: vec![] };
+        let aggr_reg: tremor_script::registry::Aggr = tremor_script::aggr_registry();
+        let config = tremor_pipeline::query::Query(Query::parse(
+            &module_path,
+            "debug_test.trickle",
+            "select event from in into out;",
+            vec![],
+            &*FN_REGISTRY.lock()?,
+            &aggr_reg,
+        )?);
+        let id = TremorUrl::parse("/pipeline/debug_test/instance")?;
+        let manager = Manager::new(12);
+        let (handle, sender) = manager.start();
+        let (tx, rx) = async_channel::bounded(1);
+        let create = Create {
+            config,
+            id: id.clone(),
+            version: 1,
+        };
+        sender
+            .send(ManagerMsg::Create(tx, Box::new(create)))
+            .await?;
+        let addr = rx.recv().await??;
+        let (offramp_tx, offramp_rx) = async_channel::unbounded();
+        let (offramp_prio_tx, _offramp_prio_rx) = async_channel::unbounded();
+        addr.send_mgmt(MgmtMsg::ConnectOutput {
+            port: OUT,
+            output_url: TremorUrl::parse("/offramp/fake_offramp/instance/in")?,
+            target: ConnectTarget::Offramp(offramp::Addr::new(offramp_tx, offramp_prio_tx)),
+        })
+        .await?;
+
+        let (commands, commands_rx) = async_channel::bounded(8);
+        let (updates_tx, updates) = async_channel::bounded(8);
+        addr.send_mgmt(MgmtMsg::Debug(Session {
+            commands: commands_rx,
+            updates: updates_tx,
+        }))
+        .await?;
+        let updates = &updates;
+        let status = move || async move {
+            match timeout(POSITIVE_RECV_TIMEOUT, updates.recv()).await {
+                Ok(Ok(Update::Status(status))) => Ok(status),
+                other => Err(Error::from(format!("unexpected update: {:?}", other))),
+            }
+        };
+        assert!(status().await?.paused.is_none());
+
+        commands
+            .send(Command::Break(Breakpoint::Node("select_0".into())))
+            .await?;
+        // commands are applied once the pipeline handles the next message
+        manager_fence(&addr).await?;
+        let breakpoints = status().await?.breakpoints;
+        assert_eq!(vec![Breakpoint::Node("select_0".into())], breakpoints);
+
+        addr.send(Msg::Event {
+            event: Event {
+                data: literal!({"v": 1}).into(),
+                ..Event::default()
+            },
+            input: "in".into(),
+        })
+        .await?;
+        let paused = status().await?.paused.ok_or("not paused")?;
+        assert_eq!("select_0", paused.node);
+        assert_eq!(literal!({"v": 1}), paused.event);
+        assert!(wait_for_event(&offramp_rx, Some(NEGATIVE_RECV_TIMEOUT))
+            .await
+            .is_err());
+
+        commands.send(Command::Step).await?;
+        let paused = status().await?.paused.ok_or("not paused")?;
+        assert_eq!("out", paused.node);
+
+        commands.send(Command::Continue).await?;
+        assert!(status().await?.paused.is_none());
+        let event = wait_for_event(&offramp_rx, None).await?;
+        assert_eq!(&literal!({"v": 1}), event.data.suffix().value());
+
+        // the pipeline drops the session once detached
+        commands.send(Command::Detach).await?;
+        manager_fence(&addr).await?;
+        assert!(updates.recv().await.is_err());
+
+        sender.send(ManagerMsg::Stop).await?;
+        handle.cancel().await;
+        Ok(())
+    }
+
+    #[async_std::test]
+    async fn test_pipeline_canary() -> Result<()> {
+        let module_path = ModulePath { mounts: vec![] };
+        let aggr_reg: tremor_script::registry::Aggr = tremor_script::aggr_registry();
+        let parse = |query: &str| -> Result<PipelineArtefact> {
+            Ok(tremor_pipeline::query::Query(Query::parse(
+                &module_path,
+                "canary_test.trickle",
+                query,
+                vec![],
+                &*FN_REGISTRY.lock()?,
+                &aggr_reg,
+            )?))
+        };
+        let id = TremorUrl::parse("/pipeline/canary_test/instance")?;
+        let manager = Manager::new(12);
+        let (handle, sender) = manager.start();
+        let (tx, rx) = async_channel::bounded(1);
+        let create = Create {
+            config: parse("select \"stable\" from in into out;")?,
+            id: id.clone(),
+            version: 1,
+        };
+        sender
+            .send(ManagerMsg::Create(tx, Box::new(create)))
+            .await?;
+        let addr = rx.recv().await??;
+        let (offramp_tx, offramp_rx) = async_channel::unbounded();
+        let (offramp_prio_tx, _offramp_prio_rx) = async_channel::unbounded();
+        addr.send_mgmt(MgmtMsg::ConnectOutput {
+            port: OUT,
+            output_url: TremorUrl::parse("/offramp/fake_offramp/instance/in")?,
+            target: ConnectTarget::Offramp(offramp::Addr::new(offramp_tx, offramp_prio_tx)),
+        })
+        .await?;
+
+        // every second event goes to the canary, which is promoted after two
+        let (tx, rx) = async_channel::bounded(1);
+        let (outcome_tx, outcome_rx) = async_channel::bounded(1);
+        let create = Create {
+            config: parse("select \"canary\" from in into out;")?,
+            id,
+            version: 2,
+        };
+        let config = config::Canary {
+            version: 2,
+            mode: config::CanaryMode::Split,
+            percentage: 50,
+            min_events: 2,
+            tolerance: 1.0,
+        };
+        sender
+            .send(ManagerMsg::Canary(
+                tx,
+                addr.clone(),
+                Box::new(create),
+                config,
+                outcome_tx,
+            ))
+            .await?;
+        rx.recv().await??;
+        manager_fence(&addr).await?;
+
+        let mut received = Vec::new();
+        for _ in 0..5 {
+            addr.send(Msg::Event {
+                event: Event::default(),
+                input: "in".into(),
+            })
+            .await?;
+            let event = wait_for_event(&offramp_rx, None).await?;
+            received.push(
+                event
+                    .data
+                    .suffix()
+                    .value()
+                    .as_str()
+                    .map(ToString::to_string),
+            );
+        }
+        let expected: Vec<_> = ["stable", "canary", "stable", "canary", "canary"]
+            .iter()
+            .map(|s| Some((*s).to_string()))
+            .collect();
+        assert_eq!(expected, received);
+        assert_eq!(CanaryOutcome::Promoted, outcome_rx.recv().await?);
+
+        sender.send(ManagerMsg::Stop).await?;
+        handle.cancel().await;
+        Ok(())
+    }
+
+    #[async_std::test]
+    async fn high_priority_events_overtake() -> Result<()> {
+        let (tx, rx) = async_channel::unbounded();
+        let (prio_tx, prio_rx) = async_channel::unbounded();
+        let (cf_tx, _cf_rx) = async_channel::unbounded();
+        let (mgmt_tx, _mgmt_rx) = async_channel::unbounded();
+        let addr = Addr::new(
+            tx,
+            rx.clone(),
+            prio_tx,
+            cf_tx,
+            mgmt_tx,
+            TremorUrl::parse("/pipeline/priority/instance")?,
+        );
+        let mut high = Event::default();
+        high.data = (Value::from("heartbeat"), literal!({"priority": "high"})).into();
+
+        addr.send(Msg::Event {
+            event: Event::default(),
+            input: "in".into(),
+        })
+        .await?;
+        addr.send(Msg::Event {
+            event: high,
+            input: "in".into(),
+        })
+        .await?;
+        assert_eq!(1, rx.len());
+        assert_eq!(1, prio_rx.len());
+
+        // the pipeline task takes the high priority event first
+        let mut queue = PriorityMerge::new(prio_rx, rx);
+        for expected in [Priority::High, Priority::Normal] {
+            match queue.next().await {
+                Some(Msg::Event { event, .. }) => assert_eq!(expected, Priority::of(&event)),
+                other => assert!(false, "Expected an event, got {:?}", other),
+            }
+        }
+        Ok(())
+    }
 }
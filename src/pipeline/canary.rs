@@ -0,0 +1,128 @@
+// Copyright 2020-2021, The Tremor Team
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+//! Canary deployments, a version of a pipeline runs alongside the active
+//! one in the same instance and replaces it unless it fails more often.
+
+use crate::config::{Canary as Config, CanaryMode};
+use crate::url::ports::ERR;
+use beef::Cow;
+use tremor_pipeline::{Event, ExecutableGraph};
+
+/// How a canary deployment ended
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub(crate) enum Outcome {
+    /// the canary replaced the active version
+    Promoted,
+    /// the canary was dropped
+    RolledBack,
+}
+
+/// Processed events and how many of them failed
+#[derive(Debug, Default, Clone, Copy)]
+pub(crate) struct Stats {
+    events: u64,
+    errors: u64,
+}
+
+impl Stats {
+    /// Records an event, it failed if processing it failed or sent an event
+    /// to the `err` port
+    pub(crate) fn record(&mut self, failed: bool, eventset: &[(Cow<'static, str>, Event)]) {
+        self.events += 1;
+        if failed || eventset.iter().any(|(port, _)| *port == ERR) {
+            self.errors += 1;
+        }
+    }
+
+    #[allow(clippy::cast_precision_loss)]
+    fn error_rate(self) -> f64 {
+        if self.events == 0 {
+            0.0
+        } else {
+            self.errors as f64 / self.events as f64
+        }
+    }
+}
+
+/// A version of a pipeline running alongside the active one
+#[derive(Debug)]
+pub(crate) struct Canary {
+    pub(crate) graph: ExecutableGraph,
+    config: Config,
+    /// stats of the canary
+    pub(crate) stats: Stats,
+    /// stats of the active version while the canary runs
+    pub(crate) baseline: Stats,
+    /// accumulated percentage, spreads the events sent to the canary evenly
+    credit: u16,
+    outcome: async_channel::Sender<Outcome>,
+}
+
+impl Canary {
+    pub(crate) fn new(
+        graph: ExecutableGraph,
+        config: Config,
+        outcome: async_channel::Sender<Outcome>,
+    ) -> Self {
+        Self {
+            graph,
+            config,
+            stats: Stats::default(),
+            baseline: Stats::default(),
+            credit: 0,
+            outcome,
+        }
+    }
+
+    /// If the canary gets a copy of every event
+    pub(crate) fn is_mirror(&self) -> bool {
+        self.config.mode == CanaryMode::Mirror
+    }
+
+    /// If the next event goes to the canary instead of the active version
+    pub(crate) fn takes_next(&mut self) -> bool {
+        if self.is_mirror() {
+            return false;
+        }
+        self.credit += u16::from(self.config.percentage);
+        if self.credit >= 100 {
+            self.credit -= 100;
+            true
+        } else {
+            false
+        }
+    }
+
+    /// The outcome, once the canary processed enough events
+    pub(crate) fn verdict(&self) -> Option<Outcome> {
+        if self.stats.events < self.config.min_events {
+            None
+        } else if self.stats.error_rate()
+            > self.baseline.error_rate() + self.config.tolerance / 100.0
+        {
+            Some(Outcome::RolledBack)
+        } else {
+            Some(Outcome::Promoted)
+        }
+    }
+
+    /// Ends the deployment, reporting the outcome
+    pub(crate) fn resolve(self, outcome: Outcome) -> ExecutableGraph {
+        if let Err(e) = self.outcome.try_send(outcome) {
+            warn!("Failed to report the canary outcome: {}", e);
+        }
+        self.graph
+    }
+}
@@ -0,0 +1,105 @@
+// Copyright 2020-2021, The Tremor Team
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+//! Warm standby of a pipeline instance, a second graph of the pipeline whose
+//! state is synced from the active one and that takes over when the active
+//! graph fails to process an event.
+//!
+//! Standbys are configured per pipeline instance in the bindings and apply
+//! to the instances started afterwards.
+
+use crate::config::Standby as Config;
+use crate::errors::Result;
+use crate::url::TremorUrl;
+use halfbrown::HashMap;
+use std::mem;
+use std::sync::RwLock;
+use tremor_pipeline::ExecutableGraph;
+
+lazy_static! {
+    static ref STANDBYS: RwLock<HashMap<TremorUrl, Config>> = RwLock::new(HashMap::new());
+}
+
+/// Configures a warm standby for the pipeline instance `id`
+///
+/// # Errors
+///  * if the standbys lock is poisoned
+pub(crate) fn set(id: &TremorUrl, config: Config) -> Result<()> {
+    STANDBYS.write()?.insert(id.clone(), config);
+    Ok(())
+}
+
+/// The warm standby configured for the pipeline instance `id`
+///
+/// # Errors
+///  * if the standbys lock is poisoned
+pub(crate) fn get(id: &TremorUrl) -> Result<Option<Config>> {
+    Ok(STANDBYS.read()?.get(id).copied())
+}
+
+/// A graph of the pipeline kept in sync with the active one
+#[derive(Debug)]
+pub(crate) struct Standby {
+    graph: ExecutableGraph,
+    interval_ns: u64,
+    next_sync: u64,
+}
+
+impl Standby {
+    pub(crate) fn new(graph: ExecutableGraph, config: Config) -> Self {
+        Self {
+            graph,
+            interval_ns: config.sync_interval_ms * 1_000_000,
+            next_sync: 0,
+        }
+    }
+
+    /// Syncs the state of `active` to the standby once the sync interval
+    /// passed
+    ///
+    /// # Errors
+    ///  * if the state of `active` can't be restored in the standby
+    pub(crate) fn sync(&mut self, active: &ExecutableGraph, now: u64) -> Result<()> {
+        if now < self.next_sync {
+            return Ok(());
+        }
+        self.next_sync = now + self.interval_ns;
+        Ok(self.graph.restore(active.snapshot())?)
+    }
+
+    /// Swaps the standby in for the failed `active` graph, the failed graph
+    /// becomes the standby and is synced on the next call to `sync`
+    pub(crate) fn take_over(&mut self, active: &mut ExecutableGraph) {
+        self.graph.id = active.id.clone();
+        mem::swap(&mut self.graph, active);
+        self.next_sync = 0;
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn lookup() -> Result<()> {
+        let id = TremorUrl::parse("/pipeline/standby/01")?;
+        assert_eq!(None, get(&id)?);
+        let config = Config {
+            sync_interval_ms: 10,
+        };
+        set(&id, config)?;
+        assert_eq!(Some(config), get(&id)?);
+        Ok(())
+    }
+}
@@ -45,12 +45,18 @@ pub struct RepoWrapper<A: Artefact> {
     pub instances: Vec<ServantId>,
     /// If this is a protected system artefact
     pub system: bool,
+    /// All published versions of the artefact, oldest first
+    pub versions: Vec<A>,
+    /// Number of the active version, versions are numbered from 1
+    pub version: usize,
 }
 
 /// Repository for artefacts
 #[derive(Default, Debug)]
 pub(crate) struct Repository<A: Artefact> {
     map: HashMap<ArtefactId, RepoWrapper<A>>,
+    /// if publishing an existing id creates a new version
+    versioned: bool,
 }
 
 impl<A: Artefact> Repository<A> {
@@ -71,6 +77,14 @@ impl<A: Artefact> Repository<A> {
     pub fn new() -> Self {
         Self {
             map: HashMap::new(),
+            versioned: false,
+        }
+    }
+    /// New repository keeping versions of its artefacts
+    pub fn versioned() -> Self {
+        Self {
+            map: HashMap::new(),
+            versioned: true,
         }
     }
     /// Retreives the artifact Id's
@@ -83,20 +97,62 @@ impl<A: Artefact> Repository<A> {
         self.map.get(&id)
     }
 
-    /// Publishes an artefact
+    /// Publishes an artefact, in versioned repositories publishing an
+    /// existing artefact adds and activates a new version of it
     pub fn publish(&mut self, mut id: ArtefactId, system: bool, artefact: A) -> Result<&A> {
         id.trim_to_artefact();
         match self.map.entry(id.clone()) {
+            Entry::Occupied(e) if self.versioned && !system && !e.get().system => {
+                let w = e.into_mut();
+                w.versions.push(artefact.clone());
+                w.version = w.versions.len();
+                w.artefact = artefact;
+                Ok(&w.artefact)
+            }
             Entry::Occupied(_) => Err(ErrorKind::PublishFailedAlreadyExists(id.to_string()).into()),
             Entry::Vacant(e) => Ok(&e
                 .insert(RepoWrapper {
                     instances: Vec::new(),
+                    versions: vec![artefact.clone()],
+                    version: 1,
                     artefact,
                     system,
                 })
                 .artefact),
         }
     }
+    /// Activates a version of an artefact, new instances are created from it
+    pub fn activate(&mut self, mut id: ArtefactId, version: usize) -> Result<&RepoWrapper<A>> {
+        id.trim_to_artefact();
+        let w = self
+            .map
+            .get_mut(&id)
+            .ok_or_else(|| ErrorKind::ArtefactNotFound(id.to_string()))?;
+        if w.system {
+            return Err(ErrorKind::ActivateFailedSystemArtefact(id.to_string()).into());
+        }
+        let artefact = version
+            .checked_sub(1)
+            .and_then(|i| w.versions.get(i))
+            .ok_or_else(|| ErrorKind::ArtefactVersionNotFound(id.to_string(), version))?;
+        w.artefact = artefact.clone();
+        w.version = version;
+        Ok(w)
+    }
+    /// Activates the version before the active one
+    pub fn rollback(&mut self, mut id: ArtefactId) -> Result<&RepoWrapper<A>> {
+        id.trim_to_artefact();
+        let version = self
+            .map
+            .get(&id)
+            .ok_or_else(|| ErrorKind::ArtefactNotFound(id.to_string()))?
+            .version;
+        if version > 1 {
+            self.activate(id, version - 1)
+        } else {
+            Err(ErrorKind::RollbackFailedNoPreviousVersion(id.to_string()).into())
+        }
+    }
     /// Unpublishes an artefact
     pub fn unpublish(&mut self, mut id: ArtefactId) -> Result<A> {
         id.trim_to_artefact();
@@ -151,6 +207,12 @@ pub(crate) enum Msg<A: Artefact> {
     ),
     PublishArtefact(async_channel::Sender<Result<A>>, ArtefactId, bool, A),
     UnpublishArtefact(async_channel::Sender<Result<A>>, ArtefactId),
+    ActivateVersion(
+        async_channel::Sender<Result<RepoWrapper<A>>>,
+        ArtefactId,
+        usize,
+    ),
+    Rollback(async_channel::Sender<Result<RepoWrapper<A>>>, ArtefactId),
     RegisterInstance(async_channel::Sender<Result<A>>, ArtefactId, ServantId),
     UnregisterInstance(async_channel::Sender<Result<A>>, ArtefactId, ServantId),
 }
@@ -179,6 +241,19 @@ impl<A: Artefact + Send + Sync + 'static> Repository<A> {
                         r.send(A::artefact_id(&id).and_then(|id| self.unpublish(id)))
                             .await?;
                     }
+                    Msg::ActivateVersion(r, id, version) => {
+                        r.send(A::artefact_id(&id).and_then(|id| {
+                            self.activate(id, version).map(std::clone::Clone::clone)
+                        }))
+                        .await?;
+                    }
+                    Msg::Rollback(r, id) => {
+                        r.send(
+                            A::artefact_id(&id)
+                                .and_then(|id| self.rollback(id).map(std::clone::Clone::clone)),
+                        )
+                        .await?;
+                    }
                     Msg::RegisterInstance(r, a_id, s_id) => {
                         r.send(
                             A::artefact_id(&a_id)
@@ -235,7 +310,7 @@ impl Repositories {
     #[must_use]
     pub fn new() -> Self {
         Self {
-            pipeline: Repository::new().start(),
+            pipeline: Repository::versioned().start(),
             onramp: Repository::new().start(),
             offramp: Repository::new().start(),
             binding: Repository::new().start(),
@@ -291,7 +366,9 @@ impl Repositories {
         self.pipeline
             .send(Msg::PublishArtefact(tx, id.clone(), system, artefact))
             .await?;
-        rx.recv().await?
+        let published = rx.recv().await??;
+        Self::publish_subflow(id, &published)?;
+        Ok(published)
     }
 
     /// Unpublish a pipeline
@@ -303,7 +380,53 @@ impl Repositories {
         self.pipeline
             .send(Msg::UnpublishArtefact(tx, id.clone()))
             .await?;
-        rx.recv().await?
+        let unpublished = rx.recv().await??;
+        if let Some(artefact_id) = id.artefact() {
+            tremor_pipeline::SUBFLOW_REGISTRY
+                .lock()?
+                .remove(artefact_id);
+        }
+        Ok(unpublished)
+    }
+
+    /// Activates a version of a pipeline
+    ///
+    /// # Errors
+    ///  * if the pipeline or the version doesn't exist
+    pub async fn activate_pipeline(
+        &self,
+        id: &TremorUrl,
+        version: usize,
+    ) -> Result<RepoWrapper<PipelineArtefact>> {
+        let (tx, rx) = bounded(1);
+        self.pipeline
+            .send(Msg::ActivateVersion(tx, id.clone(), version))
+            .await?;
+        let activated = rx.recv().await??;
+        Self::publish_subflow(id, &activated.artefact)?;
+        Ok(activated)
+    }
+
+    /// Activates the version of a pipeline before the active one
+    ///
+    /// # Errors
+    ///  * if the pipeline doesn't exist or has no previous version
+    pub async fn rollback_pipeline(&self, id: &TremorUrl) -> Result<RepoWrapper<PipelineArtefact>> {
+        let (tx, rx) = bounded(1);
+        self.pipeline.send(Msg::Rollback(tx, id.clone())).await?;
+        let activated = rx.recv().await??;
+        Self::publish_subflow(id, &activated.artefact)?;
+        Ok(activated)
+    }
+
+    // published pipelines can be embedded into others as subflows
+    fn publish_subflow(id: &TremorUrl, artefact: &PipelineArtefact) -> Result<()> {
+        if let Some(artefact_id) = id.artefact() {
+            tremor_pipeline::SUBFLOW_REGISTRY
+                .lock()?
+                .insert(artefact_id.to_string(), artefact.clone());
+        }
+        Ok(())
     }
 
     /// Bind a pipeline
@@ -12,11 +12,14 @@
 // See the License for the specific language governing permissions and
 // limitations under the License.
 
+use crate::backfill::{self, Backfill};
+use crate::config::Overflow;
 use crate::errors::{Error, Result};
-use crate::metrics::RampReporter;
+use crate::metrics::{self, RampReporter};
 use crate::offramp;
 use crate::onramp;
-use crate::pipeline;
+use crate::overflow;
+use crate::pipeline::{self, standby};
 use crate::registry::ServantId;
 use crate::system::{self, World};
 use crate::url::{ResourceType, TremorUrl};
@@ -24,11 +27,13 @@ use crate::{codec, pipeline::ConnectTarget};
 use beef::Cow;
 use hashbrown::HashMap;
 use std::collections::HashSet;
+use std::time::Duration;
 use tremor_pipeline::query;
 pub(crate) type Id = TremorUrl;
 pub(crate) use crate::OffRamp as OfframpArtefact;
 pub(crate) use crate::OnRamp as OnrampArtefact;
 use async_channel::bounded;
+use async_std::task;
 use async_trait::async_trait;
 
 /// A Binding
@@ -104,7 +109,7 @@ impl Artefact for Pipeline {
                     Some(ResourceType::Pipeline) => {
                         // TODO: connect both ways?
                         if let Some(p) = system.reg.find_pipeline(&to).await? {
-                            ConnectTarget::Pipeline(Box::new(p))
+                            ConnectTarget::Pipeline(Box::new(p.with_overflow(overflow::get(&to)?)))
                         } else {
                             return Err(format!("Pipeline {:?} not found", to).into());
                         }
@@ -219,8 +224,14 @@ impl Artefact for OfframpArtefact {
         } else {
             vec![]
         };
-        let metrics_reporter = RampReporter::new(servant_id.clone(), self.metrics_interval_s);
-
+        let interval = metrics::interval_s(&servant_id, self.metrics_interval_s)?;
+        let metrics_reporter = RampReporter::new(servant_id.clone(), interval);
+
+        let journal = self
+            .journal
+            .as_ref()
+            .map(|config| offramp::journal::Journal::open(&servant_id, config))
+            .transpose()?;
         let (tx, rx) = bounded(1);
 
         world
@@ -236,6 +247,10 @@ impl Artefact for OfframpArtefact {
                     postprocessors,
                     metrics_reporter,
                     is_linked: self.is_linked,
+                    lazy: self
+                        .lazy
+                        .then(|| Duration::from_millis(self.ready_grace_ms)),
+                    journal,
                 }),
             ))
             .await?;
@@ -325,6 +340,15 @@ impl Artefact for OnrampArtefact {
     type LinkRHS = TremorUrl;
     async fn spawn(&self, world: &World, servant_id: ServantId) -> Result<Self::SpawnResult> {
         let stream = onramp::lookup(&self.binding_type, &servant_id, &self.config)?;
+        if self.parallelism == 0 {
+            return Err(format!("Onramp {} needs a parallelism of at least 1", servant_id).into());
+        } else if self.parallelism > 1 && !stream.parallel() {
+            return Err(format!(
+                "Onramp {} of type {} does not support a parallelism above 1",
+                servant_id, self.binding_type
+            )
+            .into());
+        }
         let codec = self.codec.as_ref().map_or_else(
             || stream.default_codec().to_string(),
             std::clone::Clone::clone,
@@ -344,7 +368,8 @@ impl Artefact for OnrampArtefact {
             vec![]
         };
 
-        let metrics_reporter = RampReporter::new(servant_id.clone(), self.metrics_interval_s);
+        let interval = metrics::interval_s(&servant_id, self.metrics_interval_s)?;
+        let metrics_reporter = RampReporter::new(servant_id.clone(), interval);
         let (tx, rx) = bounded(1);
 
         world
@@ -361,6 +386,10 @@ impl Artefact for OnrampArtefact {
                     metrics_reporter,
                     is_linked: self.is_linked,
                     err_required: self.err_required,
+                    limits: self.limits,
+                    priority: self.priority,
+                    singleton: self.singleton,
+                    parallelism: self.parallelism,
                 }),
             ))
             .await?;
@@ -389,9 +418,17 @@ impl Artefact for OnrampArtefact {
                 // TODO: validate that `from` - the port name - is valid (OUT, ERR, METRICS)
                 if let Some(ResourceType::Pipeline) = to.resource_type() {
                     if let Some(pipeline) = system.reg.find_pipeline(&to).await? {
+                        let overflow = overflow::get(&to)?;
+                        if overflow == Overflow::RouteToOverflowPort {
+                            return Err(format!(
+                                "Onramp {} has no overflow output for its link to {}",
+                                id, to
+                            )
+                            .into());
+                        }
                         msgs.push(onramp::Msg::Connect(
                             from.into(),
-                            vec![(to.clone(), pipeline)],
+                            vec![(to.clone(), pipeline.with_overflow(overflow))],
                         ));
                     } else {
                         return Err(format!("Pipeline {:?} not found", to).into());
@@ -465,6 +502,90 @@ impl Artefact for OnrampArtefact {
 
 impl Binding {
     const LINKING_ERROR: &'static str = "links require the form of onramp -> pipeline or pipeline -> offramp or pipeline -> pipeline or pipeline -> onramp or offramp -> pipeline";
+
+    async fn link_onramp(system: &World, from: &TremorUrl, to: TremorUrl) -> Result<()> {
+        system.ensure_pipeline(&to).await?;
+        system.ensure_onramp(from).await?;
+        system
+            .link_onramp(
+                from,
+                vec![(from.instance_port_required()?.to_string(), to)]
+                    .into_iter()
+                    .collect(),
+            )
+            .await?;
+        Ok(())
+    }
+
+    /// Configures the standby of the pipeline instance `url`, instantiated
+    /// from `templ`, if the binding declares one
+    fn set_standby(&self, templ: &TremorUrl, url: &TremorUrl) -> Result<()> {
+        if let Some(config) = self
+            .binding
+            .standby
+            .iter()
+            .find_map(|(pipeline, config)| pipeline.same_instance_as(templ).then(|| config))
+        {
+            let mut id = url.clone();
+            id.trim_to_instance();
+            standby::set(&id, *config)?;
+        }
+        Ok(())
+    }
+
+    /// Links the backfill onramps, and the live ones once they replayed
+    /// their data
+    async fn link_backfill(
+        &self,
+        system: &World,
+        id: &TremorUrl,
+        onramps: Vec<(TremorUrl, TremorUrl)>,
+    ) -> Result<()> {
+        let (replays, live): (Vec<_>, Vec<_>) = onramps.into_iter().partition(|(from, _)| {
+            self.binding.backfill.iter().any(|b| {
+                b.resource_type() == Some(ResourceType::Onramp) && b.artefact() == from.artefact()
+            })
+        });
+        let mut instances: Vec<TremorUrl> = Vec::new();
+        for (from, _) in &replays {
+            let mut instance = from.clone();
+            instance.trim_to_instance();
+            if !instances.contains(&instance) {
+                instances.push(instance);
+            }
+        }
+        let backfill = Backfill::start(id, &instances)?;
+        // the replays are tracked from the first event, the onramps only
+        // pull events once they are linked
+        for instance in &instances {
+            system.ensure_onramp(instance).await?;
+            if let (Some(addr), Some(replay)) = (
+                system.reg.find_onramp(instance).await?,
+                backfill.replay(instance),
+            ) {
+                addr.send(onramp::Msg::Backfill(replay)).await?;
+            }
+        }
+        for (from, to) in replays {
+            Self::link_onramp(system, &from, to).await?;
+        }
+        // the live onramps are created now but paused until they are linked
+        for (from, to) in &live {
+            system.ensure_pipeline(to).await?;
+            system.ensure_onramp(from).await?;
+        }
+        let system = system.clone();
+        task::spawn(async move {
+            if backfill.replayed().await {
+                for (from, to) in live {
+                    if let Err(e) = Self::link_onramp(&system, &from, to).await {
+                        error!("[Backfill] Failed to link live onramp {}: {}", from, e);
+                    }
+                }
+            }
+        });
+        Ok(())
+    }
 }
 
 #[async_trait]
@@ -488,6 +609,27 @@ impl Artefact for Binding {
         let mut pipelines: Vec<(TremorUrl, TremorUrl)> = Vec::new(); // pipeline -> {onramp, offramp, pipeline}
         let mut onramps: Vec<(TremorUrl, TremorUrl)> = Vec::new(); // onramp -> pipeline
         let mut offramps: Vec<(TremorUrl, TremorUrl)> = Vec::new(); // linked offramps -> pipeline
+        if let Some(target) = self.binding.overflow.keys().find(|target| {
+            !self
+                .binding
+                .links
+                .values()
+                .flatten()
+                .any(|dst| dst == *target)
+        }) {
+            return Err(format!("Overflow policy for {}, which isn't linked to", target).into());
+        }
+        if let Some(pipeline) = self.binding.standby.keys().find(|pipeline| {
+            pipeline.resource_type() != Some(Pipeline)
+                || !self
+                    .binding
+                    .links
+                    .iter()
+                    .flat_map(|(src, dsts)| std::iter::once(src).chain(dsts))
+                    .any(|url| url.same_instance_as(pipeline))
+        }) {
+            return Err(format!("Standby for {}, which isn't a linked pipeline", pipeline).into());
+        }
         let mut res = self.clone();
         res.binding.links.clear();
         for (src, dsts) in self.binding.links.clone() {
@@ -501,6 +643,7 @@ impl Artefact for Binding {
                 }
                 let mut from = src.clone();
                 from.set_instance(&instance);
+                self.set_standby(&src, &from)?;
                 let mut tos: Vec<TremorUrl> = Vec::new();
                 for dst in dsts {
                     // TODO: we should be able to replace any part of the tremor url with mapping values, not just the instance
@@ -514,6 +657,10 @@ impl Artefact for Binding {
                         }
                         let mut to = dst.clone();
                         to.set_instance(&instance);
+                        if let Some(overflow) = self.binding.overflow.get(&dst) {
+                            overflow::set(&to, *overflow)?;
+                        }
+                        self.set_standby(&dst, &to)?;
                         tos.push(to.clone());
                         match (from.resource_type(), to.resource_type()) {
                             (Some(Onramp), Some(Pipeline)) => {
@@ -576,17 +723,12 @@ impl Artefact for Binding {
             }
         }
 
-        for (from, to) in onramps {
-            system.ensure_pipeline(&to).await?;
-            system.ensure_onramp(&from).await?;
-            system
-                .link_onramp(
-                    &from,
-                    vec![(from.instance_port_required()?.to_string(), to)]
-                        .into_iter()
-                        .collect(),
-                )
-                .await?;
+        if self.binding.backfill.is_empty() {
+            for (from, to) in onramps {
+                Self::link_onramp(system, &from, to).await?;
+            }
+        } else {
+            self.link_backfill(system, id, onramps).await?;
         }
 
         res.mapping = Some(vec![(id.clone(), mappings)].into_iter().collect());
@@ -596,7 +738,7 @@ impl Artefact for Binding {
     async fn unlink(
         &self,
         system: &World,
-        _: &TremorUrl,
+        id: &TremorUrl,
         _: HashMap<Self::LinkLHS, Self::LinkRHS>,
     ) -> Result<bool> {
         // TODO Quiescence Protocol ( termination correctness checks )
@@ -615,6 +757,10 @@ impl Artefact for Binding {
         // post-quiescence cleanup logic can hook off / block on etc...
         //
         info!("Unlinking Binding {}", self.binding.id);
+        backfill::cancel(id)?;
+        for to in self.binding.links.values().flatten() {
+            overflow::remove(to)?;
+        }
 
         for (from, tos) in &self.binding.links {
             if let Some(ResourceType::Onramp) = from.resource_type() {
@@ -0,0 +1,413 @@
+// Copyright 2020-2021, The Tremor Team
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+//! Deterministic simulation of a deployment, for integration tests
+//!
+//! Runs the pipelines of a deployment in a single task under virtual time:
+//! onramps are replaced by fixtures, offramps by recorders, and events are
+//! handed from one pipeline to the next in a fixed order. Timing dependent
+//! tests, e.g. of windows or batching, produce the same output on every run.
+//!
+//! ```yaml
+//! # capture files of the events of onramps, one
+//! # `{"ingest_ns": <ns>, "data": <event>}` per line
+//! fixtures:
+//!   blaster: in.json
+//! # virtual nanoseconds between two ticks of the pipelines (default: 100ms)
+//! tick: 100000000
+//! # virtual nanoseconds to go on after the last fixture event, e.g. to let
+//! # windows expire (default: 0)
+//! drain: 1000000000
+//! ```
+//!
+//! The virtual clock moves to the `ingest_ns` of every fixture event, the
+//! pipelines are ticked at every multiple of `tick` on the way. An event
+//! reaching an `exit` offramp ends the simulation.
+
+use crate::config::Binding;
+use crate::errors::{Error, Result};
+use crate::url::{ports::IN, ResourceType, TremorUrl};
+use crate::{read_cfg_file, read_query_file, MappingMap};
+use beef::Cow;
+use simd_json::OwnedValue;
+use std::collections::{BTreeMap, HashMap, HashSet, VecDeque};
+use std::io::{BufRead, BufReader};
+use tremor_common::ids::OperatorIdGen;
+use tremor_common::time;
+use tremor_pipeline::query::Query;
+use tremor_pipeline::{Event, EventIdGenerator, ExecutableGraph, SignalKind};
+use tremor_script::prelude::*;
+
+const EXIT: &str = "exit";
+
+/// Configuration of a simulation
+#[derive(Clone, Debug, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct Config {
+    /// capture files by onramp id
+    #[serde(default)]
+    pub fixtures: BTreeMap<String, String>,
+    /// virtual nanoseconds between two ticks
+    #[serde(default = "d_tick")]
+    pub tick: u64,
+    /// virtual nanoseconds to go on after the last fixture event
+    #[serde(default)]
+    pub drain: u64,
+}
+
+fn d_tick() -> u64 {
+    100_000_000
+}
+
+/// A line of a capture file
+#[derive(Deserialize)]
+struct Captured {
+    ingest_ns: u64,
+    data: OwnedValue,
+}
+
+struct Fixture {
+    ingest_ns: u64,
+    onramp: String,
+    data: OwnedValue,
+}
+
+/// An event received by a recorder
+#[derive(Clone, Debug, PartialEq, Serialize)]
+pub struct Recorded {
+    /// ingest time of the event
+    pub ingest_ns: u64,
+    /// the event value
+    pub data: Value<'static>,
+    /// the event metadata
+    pub meta: Value<'static>,
+}
+
+/// Outcome of a simulation
+#[derive(Clone, Debug, Default, PartialEq, Serialize)]
+pub struct Report {
+    /// number of fixture events emitted
+    pub events: u64,
+    /// number of ticks
+    pub ticks: u64,
+    /// events received by the offramp instances, by offramp id and instance
+    pub recorded: BTreeMap<String, BTreeMap<String, Vec<Recorded>>>,
+    /// errors of the pipelines handling events or signals
+    pub errors: Vec<String>,
+    /// if an event reached an `exit` offramp
+    pub exited: bool,
+}
+
+/// A loaded deployment
+pub struct Simulation {
+    config: Config,
+    /// pipeline instances in a fixed order
+    pipelines: BTreeMap<String, (TremorUrl, ExecutableGraph)>,
+    /// links from instance ports to instance ports
+    links: HashMap<TremorUrl, Vec<TremorUrl>>,
+    /// linked onramp instances by onramp id
+    onramps: BTreeMap<String, Vec<TremorUrl>>,
+    /// ids of the `exit` offramps
+    exits: HashSet<String>,
+}
+
+/// Replaces the mapped placeholders in the instance of a url
+fn instantiate(url: &TremorUrl, mapping: &HashMap<String, String>) -> TremorUrl {
+    let mut url = url.clone();
+    if let Some(instance) = url.instance() {
+        let instance = mapping
+            .iter()
+            .fold(instance.to_string(), |i, (name, value)| {
+                i.replace(&format!("%7B{}%7D", name), value)
+            });
+        url.set_instance(&instance);
+    }
+    url
+}
+
+fn instance_key(url: &TremorUrl) -> String {
+    let mut url = url.clone();
+    url.trim_to_instance();
+    url.to_string()
+}
+
+impl Simulation {
+    /// Loads a deployment from config yaml and trickle files
+    ///
+    /// # Errors
+    ///  * if a file can't be loaded or a mapping refers to a missing
+    ///    binding or pipeline
+    pub async fn load(config: Config, files: &[String]) -> Result<Self> {
+        let mut queries: HashMap<String, Query> = HashMap::new();
+        let mut bindings: HashMap<String, Binding> = HashMap::new();
+        let mut mappings = MappingMap::new();
+        let mut exits = HashSet::new();
+        for file in files {
+            if file.ends_with(".trickle") {
+                let (id, query) = read_query_file(file).await?;
+                queries.insert(id.artefact().unwrap_or_default().to_string(), query);
+            } else {
+                let cfg = read_cfg_file(file).await?;
+                exits.extend(
+                    cfg.offramps
+                        .iter()
+                        .filter(|o| o.binding_type == EXIT)
+                        .map(|o| o.id.clone()),
+                );
+                bindings.extend(cfg.bindings.into_iter().map(|b| (b.id.clone(), b)));
+                mappings.extend(cfg.mappings);
+            }
+        }
+
+        let mut simulation = Self {
+            config,
+            pipelines: BTreeMap::new(),
+            links: HashMap::new(),
+            onramps: BTreeMap::new(),
+            exits,
+        };
+        let mut idgen = OperatorIdGen::new();
+        // mappings are applied in a fixed order so operator ids are stable
+        let mut mappings: Vec<_> = mappings.into_iter().collect();
+        mappings.sort_by_key(|(binding, _)| binding.to_string());
+        for (binding, mapping) in mappings {
+            let id = binding.artefact().unwrap_or_default();
+            let binding = bindings
+                .get(id)
+                .ok_or_else(|| Error::from(format!("Binding {} not found", id)))?;
+            for (from, tos) in &binding.links {
+                let from = instantiate(from, &mapping);
+                let tos: Vec<_> = tos.iter().map(|to| instantiate(to, &mapping)).collect();
+                for url in tos.iter().chain(std::iter::once(&from)) {
+                    simulation.register(url, &queries, &mut idgen)?;
+                }
+                simulation.links.entry(from).or_default().extend(tos);
+            }
+        }
+        Ok(simulation)
+    }
+
+    /// Creates the pipeline instance or registers the onramp instance of a url
+    fn register(
+        &mut self,
+        url: &TremorUrl,
+        queries: &HashMap<String, Query>,
+        idgen: &mut OperatorIdGen,
+    ) -> Result<()> {
+        let key = instance_key(url);
+        match url.resource_type() {
+            Some(ResourceType::Pipeline) if !self.pipelines.contains_key(&key) => {
+                let id = url.artefact().unwrap_or_default();
+                let query = queries
+                    .get(id)
+                    .ok_or_else(|| Error::from(format!("Pipeline {} not found", id)))?;
+                let mut graph = query.to_pipe(idgen)?;
+                graph.id = key.clone();
+                let mut instance = url.clone();
+                instance.trim_to_instance();
+                self.pipelines.insert(key, (instance, graph));
+            }
+            Some(ResourceType::Onramp) => {
+                let id = url.artefact().unwrap_or_default().to_string();
+                let instances = self.onramps.entry(id).or_default();
+                if !instances.contains(url) {
+                    instances.push(url.clone());
+                }
+            }
+            _ => (),
+        }
+        Ok(())
+    }
+
+    /// Runs the fixtures through the deployment under virtual time
+    ///
+    /// # Errors
+    ///  * if a fixture can't be read
+    pub fn run(&mut self) -> Result<Report> {
+        let fixtures = self.fixtures()?;
+        let mut report = Report::default();
+        let mut now = fixtures.first().map_or(0, |f| f.ingest_ns);
+        let mut next_tick = now.saturating_add(self.config.tick);
+        let mut ids: BTreeMap<String, EventIdGenerator> = BTreeMap::new();
+        for (source_id, fixture) in (0_u64..).zip(fixtures) {
+            self.tick_until(fixture.ingest_ns, &mut next_tick, &mut report);
+            if report.exited {
+                break;
+            }
+            now = now.max(fixture.ingest_ns);
+            time::set_virtual(Some(now));
+            let ids = ids
+                .entry(fixture.onramp.clone())
+                .or_insert_with(|| EventIdGenerator::new(source_id));
+            let data = Value::from(fixture.data);
+            for from in self
+                .onramps
+                .get(&fixture.onramp)
+                .cloned()
+                .unwrap_or_default()
+            {
+                let event = Event {
+                    id: ids.next_id(),
+                    ingest_ns: now,
+                    data: (data.clone(), Value::object()).into(),
+                    ..Event::default()
+                };
+                self.route(&from, event, &mut report);
+            }
+            report.events += 1;
+        }
+        let until = now.saturating_add(self.config.drain);
+        self.tick_until(until, &mut next_tick, &mut report);
+        time::set_virtual(None);
+        Ok(report)
+    }
+
+    /// Reads the fixture events, ordered by time, onramp and line
+    fn fixtures(&self) -> Result<Vec<Fixture>> {
+        let mut events = Vec::new();
+        for (onramp, file) in &self.config.fixtures {
+            let file = tremor_common::file::open(file)?;
+            for line in BufReader::new(file).lines() {
+                let mut line = line?.into_bytes();
+                if line.iter().all(u8::is_ascii_whitespace) {
+                    continue;
+                }
+                let captured: Captured = simd_json::from_slice(&mut line)?;
+                events.push(Fixture {
+                    ingest_ns: captured.ingest_ns,
+                    onramp: onramp.clone(),
+                    data: captured.data,
+                });
+            }
+        }
+        // stable, so events of the same time keep the order of the fixtures
+        events.sort_by_key(|f| f.ingest_ns);
+        Ok(events)
+    }
+
+    /// Ticks the pipelines at every tick up to `until`
+    fn tick_until(&mut self, until: u64, next_tick: &mut u64, report: &mut Report) {
+        while *next_tick <= until && !report.exited && self.config.tick > 0 {
+            time::set_virtual(Some(*next_tick));
+            let tick = Event {
+                ingest_ns: *next_tick,
+                kind: Some(SignalKind::Tick),
+                ..Event::default()
+            };
+            let mut outputs = Vec::new();
+            for (instance, graph) in self.pipelines.values_mut() {
+                let mut returns = Vec::new();
+                if let Err(e) = graph.enqueue_signal(tick.clone(), &mut returns) {
+                    report.errors.push(format!("{}: {}", instance, e));
+                }
+                graph.insights.clear();
+                outputs.push((instance.clone(), returns));
+            }
+            for (instance, returns) in outputs {
+                self.route_returns(&instance, returns, report);
+            }
+            report.ticks += 1;
+            *next_tick = next_tick.saturating_add(self.config.tick);
+        }
+    }
+
+    /// Hands an event emitted on a port to the linked pipelines and offramps,
+    /// breadth first
+    fn route(&mut self, from: &TremorUrl, event: Event, report: &mut Report) {
+        let mut queue: VecDeque<(TremorUrl, Event)> = self
+            .links
+            .get(from)
+            .map(|tos| tos.iter().map(|to| (to.clone(), event.clone())).collect())
+            .unwrap_or_default();
+        while let Some((to, event)) = queue.pop_front() {
+            match to.resource_type() {
+                Some(ResourceType::Pipeline) => {
+                    let port = to.instance_port().unwrap_or(IN.as_ref()).to_string();
+                    let key = instance_key(&to);
+                    if let Some((instance, graph)) = self.pipelines.get_mut(&key) {
+                        let mut returns = Vec::new();
+                        if let Err(e) = graph.enqueue(&port, event, &mut returns) {
+                            report.errors.push(format!("{}: {}", instance, e));
+                        }
+                        graph.insights.clear();
+                        for (port, event) in returns {
+                            let mut from = instance.clone();
+                            from.set_port(port.as_ref());
+                            if let Some(tos) = self.links.get(&from) {
+                                queue.extend(tos.iter().map(|to| (to.clone(), event.clone())));
+                            }
+                        }
+                    }
+                }
+                Some(ResourceType::Offramp) => {
+                    let id = to.artefact().unwrap_or_default().to_string();
+                    report.exited |= self.exits.contains(&id);
+                    let instance = to.instance().unwrap_or_default().to_string();
+                    let (data, meta) = event.data.parts();
+                    report
+                        .recorded
+                        .entry(id)
+                        .or_default()
+                        .entry(instance)
+                        .or_default()
+                        .push(Recorded {
+                            ingest_ns: event.ingest_ns,
+                            data: data.clone_static(),
+                            meta: meta.clone_static(),
+                        });
+                }
+                _ => (),
+            }
+        }
+    }
+
+    fn route_returns(
+        &mut self,
+        instance: &TremorUrl,
+        returns: Vec<(Cow<'static, str>, Event)>,
+        report: &mut Report,
+    ) {
+        for (port, event) in returns {
+            let mut from = instance.clone();
+            from.set_port(port.as_ref());
+            self.route(&from, event, report);
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn instantiates_placeholders() -> Result<()> {
+        let mut mapping = HashMap::new();
+        mapping.insert("instance".to_string(), "01".to_string());
+        let url = TremorUrl::parse("/pipeline/main/{instance}/out")?;
+        let url = instantiate(&url, &mapping);
+        assert_eq!(Some("01"), url.instance());
+        assert_eq!(Some("out"), url.instance_port());
+        assert_eq!("tremor://localhost/pipeline/main/01", instance_key(&url));
+        Ok(())
+    }
+
+    #[test]
+    fn default_config() -> Result<()> {
+        let config: Config = serde_yaml::from_str("fixtures: {in: in.json}")?;
+        assert_eq!(100_000_000, config.tick);
+        assert_eq!(0, config.drain);
+        assert_eq!(Some(&"in.json".to_string()), config.fixtures.get("in"));
+        Ok(())
+    }
+}
@@ -20,23 +20,38 @@ use async_channel::Sender;
 use halfbrown::HashMap;
 
 pub(crate) mod amqp;
+pub(crate) mod azure_blob;
+pub(crate) mod bigquery;
 pub(crate) mod blackhole;
+pub(crate) mod capture;
 pub(crate) mod cb;
+pub(crate) mod console;
+pub(crate) mod cql;
 pub(crate) mod debug;
 pub(crate) mod dns;
 pub(crate) mod elastic;
+pub(crate) mod embedded;
 pub(crate) mod exit;
 pub(crate) mod file;
 pub(crate) mod gcs;
+pub(crate) mod gcs_objects;
 pub(crate) mod gpub;
+pub(crate) mod interconnect;
 pub(crate) mod kafka;
 pub(crate) mod kv;
+pub(crate) mod loki;
+pub(crate) mod mongodb;
 pub(crate) mod nats;
 pub(crate) mod newrelic;
+pub(crate) mod object_store;
 pub(crate) mod otel;
+pub(crate) mod plugin;
 pub(crate) mod postgres;
 pub(crate) mod prelude;
+pub(crate) mod quic;
 pub(crate) mod rest;
+pub(crate) mod snowflake;
+pub(crate) mod splunk;
 pub(crate) mod stderr;
 pub(crate) mod stdout;
 pub(crate) mod tcp;
@@ -61,6 +76,39 @@ pub enum Reply {
 /// A response is an event generated from the sink delivery.
 pub(crate) type ResultVec = Result<Option<Vec<Reply>>>;
 
+/// Ack insights of the transactional events in a batch, they are acked or
+/// failed together once the batch is written
+#[derive(Debug, Default)]
+pub(crate) struct BatchInsights(Vec<Event>);
+
+impl BatchInsights {
+    /// Adds the ack insight of `event` if it is transactional
+    pub(crate) fn push(&mut self, event: &Event) {
+        if event.transactional {
+            self.0.push(event.insight_ack());
+        }
+    }
+
+    /// Moves the insights of `other` into this batch
+    pub(crate) fn append(&mut self, other: Self) {
+        self.0.extend(other.0);
+    }
+
+    /// Replies acking the events of the batch, or failing them if the batch
+    /// wasn't written
+    pub(crate) fn replies(self, written: bool) -> Vec<Reply> {
+        self.0
+            .into_iter()
+            .map(|mut insight| {
+                if !written {
+                    insight.cb = CbAction::Fail;
+                }
+                Reply::Insight(insight)
+            })
+            .collect()
+    }
+}
+
 #[async_trait::async_trait]
 pub(crate) trait Sink {
     /// Handles an incoming event.
@@ -347,20 +395,26 @@ mod test {
         let e = Event::default();
 
         let (t11, r11) = async_channel::unbounded();
+        let (t1p, _r1p) = async_channel::unbounded();
         let (t12, r12) = async_channel::unbounded();
         let (t13, r13) = async_channel::unbounded();
         let p1 = pipeline::Addr::new(
             t11,
+            r11.clone(),
+            t1p,
             t12,
             t13,
             TremorUrl::parse("tremor://host/pipeline/name1/instance1/port1")?,
         );
 
         let (t21, r21) = async_channel::unbounded();
+        let (t2p, _r2p) = async_channel::unbounded();
         let (t22, r22) = async_channel::unbounded();
         let (t23, r23) = async_channel::unbounded();
         let p2 = pipeline::Addr::new(
             t21,
+            r21.clone(),
+            t2p,
             t22,
             t23,
             TremorUrl::parse("tremor://host/pipeline/name2/instance2/port2")?,
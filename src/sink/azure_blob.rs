@@ -0,0 +1,236 @@
+// Copyright 2020-2021, The Tremor Team
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+//! # Azure Blob Offramp
+//!
+//! Writes events into rolling block blobs of an Azure Storage container, see
+//! the [object store sinks](../object_store/index.html) for the rolling and
+//! the key templates. Requests are authorized with the account `key` or a
+//! `sas_token`.
+//!
+//! ## Configuration
+//!
+//! See [Config](struct.Config.html) for details.
+
+#![cfg(not(tarpaulin_include))]
+
+use crate::sink::object_store::{ObjectStore, ObjectStoreSink, RollConfig};
+use crate::sink::prelude::*;
+use chrono::Utc;
+use hmac::{Hmac, Mac};
+use http_types::headers::{AUTHORIZATION, CONTENT_TYPE};
+use sha2::Sha256;
+
+/// The storage service version the requests are signed for
+const VERSION: &str = "2020-10-02";
+
+#[derive(Deserialize, Debug)]
+pub struct Config {
+    pub account: String,
+    pub container: String,
+    /// Shared key of the account
+    pub key: Option<String>,
+    /// Shared access signature, used if there is no `key`
+    pub sas_token: Option<String>,
+    /// Blob service endpoint, e.g. for the storage emulator
+    /// (default: `https://<account>.blob.core.windows.net`)
+    pub endpoint: Option<String>,
+    #[serde(flatten)]
+    pub roll: RollConfig,
+}
+
+impl ConfigImpl for Config {}
+
+enum Credentials {
+    /// the decoded account key
+    SharedKey(Vec<u8>),
+    SasToken(String),
+}
+
+pub struct AzureBlob {
+    account: String,
+    /// url of the container
+    base: url::Url,
+    credentials: Credentials,
+}
+
+impl offramp::Impl for AzureBlob {
+    fn from_config(config: &Option<OpConfig>) -> Result<Box<dyn Offramp>> {
+        if let Some(config) = config {
+            let config: Config = Config::new(config)?;
+            let credentials = match (&config.key, &config.sas_token) {
+                (Some(key), _) => Credentials::SharedKey(base64::decode(key)?),
+                (None, Some(sas)) => Credentials::SasToken(sas.trim_start_matches('?').to_string()),
+                (None, None) => {
+                    return Err("The azure-blob offramp requires a `key` or `sas_token`".into())
+                }
+            };
+            let endpoint = config
+                .endpoint
+                .clone()
+                .unwrap_or_else(|| format!("https://{}.blob.core.windows.net", config.account));
+            let mut base = url::Url::parse(&endpoint)?;
+            base.path_segments_mut()
+                .map_err(|_e| Error::from("Invalid azure blob endpoint"))?
+                .pop_if_empty()
+                .push(&config.container);
+            let store = Self {
+                account: config.account,
+                base,
+                credentials,
+            };
+            ObjectStoreSink::new_box(store, config.roll)
+        } else {
+            Err("Missing config for azure-blob offramp".into())
+        }
+    }
+}
+
+impl AzureBlob {
+    fn blob_url(&self, key: &str) -> Result<url::Url> {
+        let mut url = self.base.clone();
+        url.path_segments_mut()
+            .map_err(|_e| Error::from("Invalid azure blob endpoint"))?
+            .extend(key.split('/'));
+        Ok(url)
+    }
+}
+
+/// The Shared Key signature of a `Put Blob` request
+fn sign(
+    key: &[u8],
+    account: &str,
+    path: &str,
+    headers: &[(&str, &str)],
+    content_length: usize,
+    content_type: &str,
+) -> Result<String> {
+    let mut ms_headers: Vec<_> = headers
+        .iter()
+        .filter(|(name, _)| name.starts_with("x-ms-"))
+        .collect();
+    ms_headers.sort_unstable();
+    let canonicalized_headers: String = ms_headers
+        .iter()
+        .map(|(name, value)| format!("{}:{}\n", name, value))
+        .collect();
+    let string_to_sign = format!(
+        "PUT\n\n\n{}\n\n{}\n\n\n\n\n\n\n{}/{}{}",
+        content_length, content_type, canonicalized_headers, account, path
+    );
+    let mut mac = Hmac::<Sha256>::new_from_slice(key).map_err(|e| Error::from(e.to_string()))?;
+    mac.update(string_to_sign.as_bytes());
+    Ok(base64::encode(mac.finalize().into_bytes()))
+}
+
+#[async_trait::async_trait]
+impl ObjectStore for AzureBlob {
+    fn name(&self) -> &str {
+        "AzureBlob"
+    }
+
+    async fn put(&self, key: &str, data: Vec<u8>, content_type: &str) -> Result<()> {
+        let mut url = self.blob_url(key)?;
+        let date = Utc::now().format("%a, %d %b %Y %H:%M:%S GMT").to_string();
+        let headers = [
+            ("x-ms-blob-type", "BlockBlob"),
+            ("x-ms-date", date.as_str()),
+            ("x-ms-version", VERSION),
+        ];
+        let authorization = match &self.credentials {
+            Credentials::SharedKey(secret) => {
+                let signature = sign(
+                    secret,
+                    &self.account,
+                    url.path(),
+                    &headers,
+                    data.len(),
+                    content_type,
+                )?;
+                Some(format!("SharedKey {}:{}", self.account, signature))
+            }
+            Credentials::SasToken(sas) => {
+                url.set_query(Some(sas));
+                None
+            }
+        };
+        let mut request = surf::put(url.as_str())
+            .header(CONTENT_TYPE, content_type)
+            .body(data);
+        for (name, value) in headers {
+            request = request.header(name, value);
+        }
+        if let Some(authorization) = authorization {
+            request = request.header(AUTHORIZATION, authorization);
+        }
+        let mut response = request.await?;
+        if response.status().is_success() {
+            Ok(())
+        } else {
+            let body = response.body_string().await.unwrap_or_default();
+            Err(format!("Azure responded with {}: {}", response.status(), body).into())
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn signature() -> Result<()> {
+        let headers = [
+            ("x-ms-version", VERSION),
+            ("x-ms-date", "Fri, 01 Oct 2021 12:34:56 GMT"),
+            ("x-ms-blob-type", "BlockBlob"),
+        ];
+        let expected = {
+            let string_to_sign = "PUT\n\n\n5\n\ntext/plain\n\n\n\n\n\n\n\
+                                  x-ms-blob-type:BlockBlob\n\
+                                  x-ms-date:Fri, 01 Oct 2021 12:34:56 GMT\n\
+                                  x-ms-version:2020-10-02\n\
+                                  /snot/logs/a/b.json";
+            let mut mac = Hmac::<Sha256>::new_from_slice(b"badger")
+                .map_err(|e| Error::from(e.to_string()))?;
+            mac.update(string_to_sign.as_bytes());
+            base64::encode(mac.finalize().into_bytes())
+        };
+        assert_eq!(
+            sign(
+                b"badger",
+                "snot",
+                "/logs/a/b.json",
+                &headers,
+                5,
+                "text/plain"
+            )?,
+            expected
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn urls() -> Result<()> {
+        let store = AzureBlob {
+            account: "snot".to_string(),
+            base: url::Url::parse("https://snot.blob.core.windows.net/logs")?,
+            credentials: Credentials::SasToken(String::new()),
+        };
+        assert_eq!(
+            store.blob_url("2021/10/01/a b.json")?.as_str(),
+            "https://snot.blob.core.windows.net/logs/2021/10/01/a%20b.json"
+        );
+        Ok(())
+    }
+}
@@ -0,0 +1,269 @@
+// Copyright 2020-2021, The Tremor Team
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+//! # BigQuery Offramp
+//!
+//! Streams events as rows into a BigQuery table via the default stream of the
+//! Storage Write API. The fields of an event are mapped to the columns of
+//! `schema` by name. Rows are written in batches of `batch_size` rows, or on
+//! the next signal, appends that fail on quota errors are retried.
+//!
+//! Authentication uses `GOOGLE_APPLICATION_CREDENTIALS`.
+//!
+//! ## Configuration
+//!
+//! See [Config](struct.Config.html) for details.
+
+#![cfg(not(tarpaulin_include))]
+
+use crate::connectors::gcp::bigquery::{self, Column};
+use crate::connectors::gcp::pubsub_auth::AuthedService;
+use crate::sink::prelude::*;
+use googapis::google::cloud::bigquery::storage::v1::{
+    append_rows_request, append_rows_response, big_query_write_client::BigQueryWriteClient,
+    AppendRowsRequest, ProtoRows, ProtoSchema,
+};
+use halfbrown::HashMap;
+use prost_types::DescriptorProto;
+use std::time::Duration;
+use tonic::Code;
+
+#[derive(Deserialize, Debug)]
+pub struct Config {
+    pub project_id: String,
+    pub dataset: String,
+    pub table: String,
+    /// Columns of the table to write
+    pub schema: Vec<Column>,
+    /// Rows per append (default: 500)
+    #[serde(default = "dflt_batch_size")]
+    pub batch_size: usize,
+    /// Retries of an append that failed on a quota error (default: 5)
+    #[serde(default = "dflt_max_retries")]
+    pub max_retries: u32,
+    /// Backoff before the first retry in milliseconds, it doubles with every
+    /// retry (default: 1000)
+    #[serde(default = "dflt_backoff")]
+    pub backoff: u64,
+}
+
+fn dflt_batch_size() -> usize {
+    500
+}
+
+fn dflt_max_retries() -> u32 {
+    5
+}
+
+fn dflt_backoff() -> u64 {
+    1000
+}
+
+impl ConfigImpl for Config {}
+
+impl Config {
+    /// The default stream of the table
+    fn write_stream(&self) -> String {
+        format!(
+            "projects/{}/datasets/{}/tables/{}/_default",
+            self.project_id, self.dataset, self.table
+        )
+    }
+}
+
+pub struct BigQuery {
+    config: Config,
+    descriptor: DescriptorProto,
+    client: Option<BigQueryWriteClient<AuthedService>>,
+    rows: Vec<Vec<u8>>,
+    insights: BatchInsights,
+}
+
+impl offramp::Impl for BigQuery {
+    fn from_config(config: &Option<OpConfig>) -> Result<Box<dyn Offramp>> {
+        if let Some(config) = config {
+            let config: Config = Config::new(config)?;
+            if config.schema.is_empty() {
+                return Err("The bigquery offramp requires a schema".into());
+            }
+            let descriptor = bigquery::descriptor(&config.schema)?;
+            Ok(SinkManager::new_box(Self {
+                config,
+                descriptor,
+                client: None,
+                rows: Vec::new(),
+                insights: BatchInsights::default(),
+            }))
+        } else {
+            Err("Missing config for bigquery offramp".into())
+        }
+    }
+}
+
+/// Errors after which an append is retried
+fn is_retryable(code: Code) -> bool {
+    matches!(
+        code,
+        Code::ResourceExhausted | Code::Unavailable | Code::Aborted
+    )
+}
+
+impl BigQuery {
+    async fn append(&mut self, rows: Vec<Vec<u8>>) -> Result<()> {
+        let client = if let Some(client) = &mut self.client {
+            client
+        } else {
+            self.client
+                .get_or_insert(bigquery::setup_write_client().await?)
+        };
+        let request = AppendRowsRequest {
+            write_stream: self.config.write_stream(),
+            rows: Some(append_rows_request::Rows::ProtoRows(
+                append_rows_request::ProtoData {
+                    writer_schema: Some(ProtoSchema {
+                        proto_descriptor: Some(self.descriptor.clone()),
+                    }),
+                    rows: Some(ProtoRows {
+                        serialized_rows: rows,
+                    }),
+                },
+            )),
+            ..AppendRowsRequest::default()
+        };
+        let mut responses = client
+            .append_rows(futures::stream::iter(vec![request]))
+            .await?
+            .into_inner();
+        match responses.message().await? {
+            Some(response) => match response.response {
+                Some(append_rows_response::Response::Error(status)) => {
+                    Err(tonic::Status::new(Code::from(status.code), status.message).into())
+                }
+                _ => Ok(()),
+            },
+            None => Err("BigQuery closed the append stream without a response".into()),
+        }
+    }
+
+    /// Writes the buffered rows, retrying quota errors
+    async fn flush(&mut self) -> Vec<Reply> {
+        let rows = std::mem::take(&mut self.rows);
+        let insights = std::mem::take(&mut self.insights);
+        let mut backoff = Duration::from_millis(self.config.backoff);
+        let mut retries = 0;
+        let res = loop {
+            match self.append(rows.clone()).await {
+                Err(Error(ErrorKind::TonicStatusError(status), _))
+                    if is_retryable(status.code()) && retries < self.config.max_retries =>
+                {
+                    warn!(
+                        "[Sink::BigQuery] Append failed, retrying in {:?}: {}",
+                        backoff, status
+                    );
+                    task::sleep(backoff).await;
+                    backoff *= 2;
+                    retries += 1;
+                }
+                res => break res,
+            }
+        };
+        if let Err(e) = &res {
+            error!(
+                "[Sink::BigQuery] Failed to append {} rows: {}",
+                rows.len(),
+                e
+            );
+            if matches!(e.0, ErrorKind::TonicTransportError(_)) {
+                self.client = None;
+            }
+        }
+        insights.replies(res.is_ok())
+    }
+}
+
+#[async_trait::async_trait]
+impl Sink for BigQuery {
+    async fn on_event(
+        &mut self,
+        _input: &str,
+        _codec: &mut dyn Codec,
+        _codec_map: &HashMap<String, Box<dyn Codec>>,
+        mut event: Event,
+    ) -> ResultVec {
+        let rows: Result<Vec<Vec<u8>>> = event
+            .value_iter()
+            .map(|value| bigquery::encode_row(&self.config.schema, value))
+            .collect();
+        match rows {
+            Ok(rows) => {
+                self.rows.extend(rows);
+                self.insights.push(&event);
+            }
+            Err(e) => {
+                error!("[Sink::BigQuery] Invalid row: {}", e);
+                return Ok(if event.transactional {
+                    Some(vec![sink::Reply::Insight(event.to_fail())])
+                } else {
+                    None
+                });
+            }
+        }
+        if self.rows.len() >= self.config.batch_size {
+            Ok(Some(self.flush().await))
+        } else {
+            Ok(None)
+        }
+    }
+
+    async fn on_signal(&mut self, _signal: Event) -> ResultVec {
+        if self.rows.is_empty() {
+            Ok(None)
+        } else {
+            Ok(Some(self.flush().await))
+        }
+    }
+
+    #[allow(clippy::too_many_arguments)]
+    async fn init(
+        &mut self,
+        _sink_uid: u64,
+        _sink_url: &TremorUrl,
+        _codec: &dyn Codec,
+        _codec_map: &HashMap<String, Box<dyn Codec>>,
+        _processors: Processors<'_>,
+        _is_linked: bool,
+        _reply_channel: Sender<sink::Reply>,
+    ) -> Result<()> {
+        self.client = Some(bigquery::setup_write_client().await?);
+        Ok(())
+    }
+
+    async fn terminate(&mut self) {
+        if !self.rows.is_empty() {
+            self.flush().await;
+        }
+    }
+
+    fn is_active(&self) -> bool {
+        true
+    }
+
+    fn auto_ack(&self) -> bool {
+        false
+    }
+
+    fn default_codec(&self) -> &str {
+        "json"
+    }
+}
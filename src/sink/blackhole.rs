@@ -16,6 +16,12 @@
 //!
 //! Offramp used for benchmarking to generate latency histograms
 //!
+//! It can also simulate a slow or unreliable destination for chaos testing of
+//! backpressure and contraflow: every event is delayed by a `latency` drawn
+//! from a distribution and fails with the probability `failure_rate`. With
+//! `report_interval_secs` the absorbed events, bytes, failures and delays are
+//! reported on the `out` port of linked blackholes.
+//!
 //! ## Configuration
 //!
 //! See [Config](struct.Config.html) for details.
@@ -28,25 +34,86 @@ use crate::sink::prelude::*;
 use halfbrown::HashMap;
 use hdrhistogram::serialization::{Deserializer, Serializer, V2Serializer};
 use hdrhistogram::Histogram;
+use rand::rngs::SmallRng;
+use rand::Rng;
 use std::fmt::Display;
 use std::io::{self, stdout, Read, Write};
 use std::process;
 use std::result;
 use std::str;
+use std::time::Duration;
+use tremor_common::rand::make_prng;
 
 #[derive(Deserialize, Debug, Clone)]
 pub struct Config {
     /// Number of seconds to collect data before the system is stopped.
+    #[serde(default)]
     pub stop_after_secs: u64,
     /// Significant figures for the histogram
+    #[serde(default = "dflt_significant_figures")]
     pub significant_figures: u64,
     /// Number of seconds to warmup, events during this time are not
     /// accounted for in the latency measurements
+    #[serde(default)]
     pub warmup_secs: u64,
+    /// Simulated latency of every event
+    pub latency: Option<Latency>,
+    /// Probability of an event to fail, between 0 and 1
+    #[serde(default)]
+    pub failure_rate: f64,
+    /// Seed of the simulation (default: the start time)
+    pub seed: Option<u64>,
+    /// Seconds between reports of the absorbed events, no reports if not set
+    pub report_interval_secs: Option<u64>,
+}
+
+fn dflt_significant_figures() -> u64 {
+    2
 }
 
 impl ConfigImpl for Config {}
 
+/// Distribution of the simulated latency, in milliseconds
+#[derive(Deserialize, Debug, Clone, Copy)]
+#[serde(tag = "distribution", rename_all = "lowercase")]
+pub enum Latency {
+    Fixed { ms: f64 },
+    Uniform { min_ms: f64, max_ms: f64 },
+    Normal { mean_ms: f64, stddev_ms: f64 },
+    Exponential { mean_ms: f64 },
+}
+
+impl Latency {
+    /// Draws a latency, negative draws of the normal distribution are 0
+    fn sample(self, rng: &mut SmallRng) -> Duration {
+        let ms = match self {
+            Self::Fixed { ms } => ms,
+            Self::Uniform { min_ms, max_ms } if max_ms > min_ms => rng.gen_range(min_ms..max_ms),
+            Self::Uniform { min_ms, .. } => min_ms,
+            Self::Normal { mean_ms, stddev_ms } => {
+                // Box-Muller transform
+                let u1: f64 = 1.0 - rng.gen::<f64>();
+                let u2: f64 = rng.gen();
+                let z = (-2.0 * u1.ln()).sqrt() * (2.0 * std::f64::consts::PI * u2).cos();
+                mean_ms + stddev_ms * z
+            }
+            Self::Exponential { mean_ms } => -mean_ms * (1.0 - rng.gen::<f64>()).ln(),
+        };
+        Duration::from_secs_f64(ms.max(0.0) / 1000.0)
+    }
+}
+
+/// What the blackhole absorbed since the last report
+#[derive(Debug, Default)]
+struct Absorbed {
+    events: u64,
+    bytes: usize,
+    failed: u64,
+    delay: Duration,
+    max_delay: Duration,
+    reported: u64,
+}
+
 /// A null offramp that records histograms
 pub struct Blackhole {
     // config: Config,
@@ -58,12 +125,20 @@ pub struct Blackhole {
     bytes: usize,
     count: u64,
     buf: Vec<u8>,
+    latency: Option<Latency>,
+    failure_rate: f64,
+    rng: SmallRng,
+    report_interval: Option<u64>,
+    absorbed: Absorbed,
 }
 
 impl offramp::Impl for Blackhole {
     fn from_config(config: &Option<OpConfig>) -> Result<Box<dyn Offramp>> {
         if let Some(config) = config {
             let config: Config = Config::new(config)?;
+            if !(0.0..=1.0).contains(&config.failure_rate) {
+                return Err("The blackhole failure_rate has to be between 0 and 1".into());
+            }
             let now_ns = nanotime();
             Ok(SinkManager::new_box(Self {
                 // config: config.clone(),
@@ -79,6 +154,14 @@ impl offramp::Impl for Blackhole {
                 bytes: 0,
                 count: 0,
                 buf: Vec::with_capacity(1024),
+                latency: config.latency,
+                failure_rate: config.failure_rate,
+                rng: make_prng(config.seed.unwrap_or(now_ns)),
+                report_interval: config.report_interval_secs.map(|s| s * 1_000_000_000),
+                absorbed: Absorbed {
+                    reported: now_ns,
+                    ..Absorbed::default()
+                },
             }))
         } else {
             Err("Blackhole offramp requires a config".into())
@@ -109,6 +192,9 @@ impl Sink for Blackhole {
         _codec_map: &HashMap<String, Box<dyn Codec>>,
         event: Event,
     ) -> ResultVec {
+        if self.latency.is_some() || self.failure_rate > 0.0 {
+            return self.simulate(codec, event).await;
+        }
         let now_ns = nanotime();
         if self.has_stop_limit && now_ns > self.stop_after {
             let mut buf = Vec::new();
@@ -146,8 +232,18 @@ impl Sink for Blackhole {
         "null"
     }
 
-    async fn on_signal(&mut self, _signal: Event) -> ResultVec {
-        Ok(None)
+    async fn on_signal(&mut self, signal: Event) -> ResultVec {
+        match self.report_interval {
+            Some(interval)
+                if signal.ingest_ns.saturating_sub(self.absorbed.reported) >= interval =>
+            {
+                Ok(Some(vec![sink::Reply::Response(
+                    OUT,
+                    self.report(signal.ingest_ns),
+                )]))
+            }
+            _ => Ok(None),
+        }
     }
 
     fn is_active(&self) -> bool {
@@ -155,7 +251,69 @@ impl Sink for Blackhole {
     }
 
     fn auto_ack(&self) -> bool {
-        true
+        self.failure_rate <= 0.0
+    }
+}
+
+impl Blackhole {
+    /// Absorbs an event after the simulated latency, failing it at the
+    /// failure rate
+    async fn simulate(&mut self, codec: &mut dyn Codec, mut event: Event) -> ResultVec {
+        if let Some(latency) = self.latency {
+            let delay = latency.sample(&mut self.rng);
+            task::sleep(delay).await;
+            self.absorbed.delay += delay;
+            self.absorbed.max_delay = self.absorbed.max_delay.max(delay);
+        }
+        for value in event.value_iter() {
+            if codec.encode_into(value, &mut self.buf).is_ok() {
+                self.absorbed.bytes += self.buf.len();
+            }
+            self.buf.clear();
+            self.absorbed.events += 1;
+        }
+        if self.failure_rate <= 0.0 {
+            return Ok(None);
+        }
+        let fail = self.rng.gen_bool(self.failure_rate);
+        if fail {
+            self.absorbed.failed += 1;
+        }
+        Ok(if event.transactional {
+            Some(vec![sink::Reply::Insight(if fail {
+                event.insight_fail()
+            } else {
+                event.insight_ack()
+            })])
+        } else {
+            None
+        })
+    }
+
+    /// Reports what was absorbed since the last report
+    fn report(&mut self, ingest_ns: u64) -> Event {
+        let absorbed = std::mem::take(&mut self.absorbed);
+        self.absorbed.reported = ingest_ns;
+        let mean_delay_ms = if absorbed.events == 0 {
+            0.0
+        } else {
+            absorbed.delay.as_secs_f64() * 1000.0 / absorbed.events as f64
+        };
+        let data = literal!({
+            "events": absorbed.events,
+            "bytes": absorbed.bytes,
+            "failed": absorbed.failed,
+            "delay_ms": {
+                "total": absorbed.delay.as_secs_f64() * 1000.0,
+                "mean": mean_delay_ms,
+                "max": absorbed.max_delay.as_secs_f64() * 1000.0
+            }
+        });
+        Event {
+            ingest_ns,
+            data: (data, Value::object()).into(),
+            ..Event::default()
+        }
     }
 }
 
@@ -245,3 +403,31 @@ fn quantiles<R: Read, W: Write>(
 
     Ok(())
 }
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn latencies() {
+        let mut rng = make_prng(42);
+        let fixed = Latency::Fixed { ms: 1.5 };
+        assert_eq!(fixed.sample(&mut rng), Duration::from_micros(1500));
+        let uniform = Latency::Uniform {
+            min_ms: 10.0,
+            max_ms: 20.0,
+        };
+        let normal = Latency::Normal {
+            mean_ms: 0.0,
+            stddev_ms: 10.0,
+        };
+        let exponential = Latency::Exponential { mean_ms: 5.0 };
+        for _ in 0..1000 {
+            let d = uniform.sample(&mut rng);
+            assert!(d >= Duration::from_millis(10) && d < Duration::from_millis(20));
+            // negative draws are cut off
+            assert!(normal.sample(&mut rng) >= Duration::from_millis(0));
+            assert!(exponential.sample(&mut rng) >= Duration::from_millis(0));
+        }
+    }
+}
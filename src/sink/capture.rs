@@ -0,0 +1,188 @@
+// Copyright 2020-2021, The Tremor Team
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+//! # Capture Offramp
+//!
+//! Records events with their metadata and ingest time to a file, one JSON
+//! record per line:
+//!
+//! ```json
+//! {"ingest_ns": 1234, "data": {"snot": "badger"}, "meta": {}}
+//! ```
+//!
+//! The file is compressed as a single stream, with `zstd` by default, and
+//! only complete once the offramp terminates. Captures can be replayed with
+//! the `replay` onramp, or uncompressed with `tremor run --replay`.
+//! The codec is not used, records are always JSON.
+//!
+//! ## Configuration
+//!
+//! See [Config](struct.Config.html) for details.
+
+#![cfg(not(tarpaulin_include))]
+
+use crate::sink::prelude::*;
+use async_compression::futures::write::{XzEncoder, ZstdEncoder};
+use async_std::io::prelude::*;
+use futures::io::AsyncWrite;
+use halfbrown::HashMap;
+use tremor_common::asy::file as cfile;
+use tremor_value::literal;
+
+/// An offramp that records events to a capture file
+pub struct Capture {
+    file: Option<Box<dyn AsyncWrite + Send + Unpin>>,
+    postprocessors: Postprocessors,
+    config: Config,
+}
+
+#[derive(Deserialize)]
+pub struct Config {
+    /// Filename to write the capture to
+    pub file: String,
+    /// Compression of the capture, `zstd`, `xz` or `none` (default: `zstd`)
+    #[serde(default)]
+    pub compression: Compression,
+}
+
+#[derive(Deserialize, Debug, Clone, Copy, PartialEq)]
+#[serde(rename_all = "lowercase")]
+pub enum Compression {
+    None,
+    Xz,
+    Zstd,
+}
+
+impl Default for Compression {
+    fn default() -> Self {
+        Self::Zstd
+    }
+}
+
+impl ConfigImpl for Config {}
+
+impl offramp::Impl for Capture {
+    fn from_config(config: &Option<OpConfig>) -> Result<Box<dyn Offramp>> {
+        if let Some(config) = config {
+            let config: Config = Config::new(config)?;
+
+            Ok(SinkManager::new_box(Self {
+                file: None,
+                config,
+                postprocessors: vec![],
+            }))
+        } else {
+            Err("Capture offramp requires a config".into())
+        }
+    }
+}
+
+/// A single line of a capture
+fn record(ingest_ns: u64, data: &Value, meta: &Value) -> Vec<u8> {
+    literal!({
+        "ingest_ns": ingest_ns,
+        "data": data.clone_static(),
+        "meta": meta.clone_static()
+    })
+    .encode()
+    .into_bytes()
+}
+
+#[async_trait::async_trait]
+impl Sink for Capture {
+    async fn terminate(&mut self) {
+        if let Some(file) = &mut self.file {
+            // finishes the compressed stream
+            if let Err(e) = file.close().await {
+                error!("Failed to close capture file: {}", e);
+            }
+        }
+    }
+
+    async fn on_event(
+        &mut self,
+        _input: &str,
+        _codec: &mut dyn Codec,
+        _codec_map: &HashMap<String, Box<dyn Codec>>,
+        mut event: Event,
+    ) -> ResultVec {
+        if let Some(file) = &mut self.file {
+            for (data, meta) in event.value_meta_iter() {
+                let raw = record(event.ingest_ns, data, meta);
+                let packets = postprocess(&mut self.postprocessors, event.ingest_ns, raw)?;
+                for packet in packets {
+                    file.write_all(&packet).await?;
+                    file.write_all(b"\n").await?;
+                }
+            }
+            // flushing a compressed stream would cost compression ratio
+            if self.config.compression == Compression::None {
+                file.flush().await?;
+            }
+        }
+        Ok(Some(vec![sink::Reply::Insight(event.insight_ack())]))
+    }
+    fn default_codec(&self) -> &str {
+        "json"
+    }
+    #[allow(clippy::too_many_arguments)]
+    async fn init(
+        &mut self,
+        _sink_uid: u64,
+        _sink_url: &TremorUrl,
+        _codec: &dyn Codec,
+        _codec_map: &HashMap<String, Box<dyn Codec>>,
+        processors: Processors<'_>,
+        _is_linked: bool,
+        _reply_channel: Sender<sink::Reply>,
+    ) -> Result<()> {
+        self.postprocessors = make_postprocessors(processors.post)?;
+        let file = cfile::create(&self.config.file).await?;
+        self.file = Some(match self.config.compression {
+            Compression::None => Box::new(file),
+            Compression::Xz => Box::new(XzEncoder::new(file)),
+            Compression::Zstd => Box::new(ZstdEncoder::new(file)),
+        });
+        Ok(())
+    }
+    async fn on_signal(&mut self, _signal: Event) -> ResultVec {
+        Ok(None)
+    }
+    fn is_active(&self) -> bool {
+        true
+    }
+    fn auto_ack(&self) -> bool {
+        true
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn capture_record() -> Result<()> {
+        let mut raw = record(42, &literal!({"snot": "badger"}), &literal!({"kafka": 1}));
+        let record = tremor_value::parse_to_value(&mut raw)?;
+        assert_eq!(
+            record,
+            literal!({
+                "ingest_ns": 42,
+                "data": {"snot": "badger"},
+                "meta": {"kafka": 1}
+            })
+        );
+        Ok(())
+    }
+}
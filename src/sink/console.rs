@@ -0,0 +1,178 @@
+// Copyright 2020-2021, The Tremor Team
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+//! Output formatting shared by the stdout and stderr offramps
+
+use crate::sink::prelude::*;
+use simd_json::StaticNode;
+use std::fmt::Write;
+
+/// How events are printed
+#[derive(Clone, Copy, Debug, Deserialize, PartialEq)]
+#[serde(rename_all = "lowercase")]
+pub(crate) enum Mode {
+    /// encoded with the codec and postprocessors
+    Codec,
+    /// as indented JSON
+    Pretty,
+    /// as indented JSON with terminal colors
+    Colorized,
+}
+
+impl Default for Mode {
+    fn default() -> Self {
+        Self::Codec
+    }
+}
+
+const RESET: &str = "\x1b[0m";
+const KEY: &str = "\x1b[1;34m";
+const STRING: &str = "\x1b[32m";
+const NUMBER: &str = "\x1b[36m";
+const LITERAL: &str = "\x1b[35m";
+
+fn indent(out: &mut String, depth: usize) {
+    for _ in 0..depth {
+        out.push_str("  ");
+    }
+}
+
+fn colorize_into(value: &Value, depth: usize, out: &mut String) -> std::fmt::Result {
+    match value {
+        Value::Static(StaticNode::Null | StaticNode::Bool(_)) => {
+            write!(out, "{}{}{}", LITERAL, value.encode(), RESET)
+        }
+        Value::Static(_) => write!(out, "{}{}{}", NUMBER, value.encode(), RESET),
+        Value::String(_) | Value::Bytes(_) => write!(out, "{}{}{}", STRING, value.encode(), RESET),
+        Value::Array(a) if a.is_empty() => write!(out, "[]"),
+        Value::Array(a) => {
+            out.push_str("[\n");
+            for (i, v) in a.iter().enumerate() {
+                if i > 0 {
+                    out.push_str(",\n");
+                }
+                indent(out, depth + 1);
+                colorize_into(v, depth + 1, out)?;
+            }
+            out.push('\n');
+            indent(out, depth);
+            write!(out, "]")
+        }
+        Value::Object(o) if o.is_empty() => write!(out, "{{}}"),
+        Value::Object(o) => {
+            out.push_str("{\n");
+            for (i, (k, v)) in o.iter().enumerate() {
+                if i > 0 {
+                    out.push_str(",\n");
+                }
+                indent(out, depth + 1);
+                let key = Value::from(k.to_string()).encode();
+                write!(out, "{}{}{}: ", KEY, key, RESET)?;
+                colorize_into(v, depth + 1, out)?;
+            }
+            out.push('\n');
+            indent(out, depth);
+            write!(out, "}}")
+        }
+    }
+}
+
+/// Indented JSON with ANSI colors
+pub(crate) fn colorize(value: &Value) -> String {
+    let mut out = String::new();
+    // writing to a string can't fail
+    let _ = colorize_into(value, 0, &mut out);
+    out
+}
+
+/// Renders a prefix template, `{ingest_ns}` and `{input}` are replaced by the
+/// ingest time and the input port of the event, `{$path}` by the metadata at
+/// the dotted path. Other braces are kept as they are.
+pub(crate) fn prefix(template: &str, ingest_ns: u64, input: &str, meta: &Value) -> String {
+    let mut out = String::with_capacity(template.len());
+    let mut rest = template;
+    while let Some(start) = rest.find('{') {
+        out.push_str(rest.get(..start).unwrap_or_default());
+        let after = rest.get(start + 1..).unwrap_or_default();
+        let placeholder = after.find('}').and_then(|end| after.get(..end));
+        let value = match placeholder {
+            Some("ingest_ns") => Some(ingest_ns.to_string()),
+            Some("input") => Some(input.to_string()),
+            Some(path) if path.starts_with('$') => {
+                let field = path
+                    .get(1..)
+                    .unwrap_or_default()
+                    .split('.')
+                    .filter(|k| !k.is_empty())
+                    .try_fold(meta, |v, k| v.get(k));
+                Some(field.map_or_else(String::new, |v| {
+                    v.as_str().map_or_else(|| v.encode(), ToString::to_string)
+                }))
+            }
+            _ => None,
+        };
+        match (value, placeholder) {
+            (Some(value), Some(placeholder)) => {
+                out.push_str(&value);
+                rest = after.get(placeholder.len() + 1..).unwrap_or_default();
+            }
+            _ => {
+                out.push('{');
+                rest = after;
+            }
+        }
+    }
+    out.push_str(rest);
+    out
+}
+
+/// Wraps the data of an event with its metadata
+pub(crate) fn with_meta<'value>(value: &Value<'value>, meta: &Value<'value>) -> Value<'value> {
+    literal!({
+        "data": value.clone(),
+        "meta": meta.clone()
+    })
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn prefixes() {
+        let meta = literal!({"kafka": {"topic": "snot", "partition": 3}});
+        assert_eq!(
+            prefix(
+                "[{input}@{ingest_ns}] {$kafka.topic}/{$kafka.partition} ",
+                42,
+                "in",
+                &meta
+            ),
+            "[in@42] snot/3 "
+        );
+        assert_eq!(prefix("{$missing}{x} {", 0, "in", &meta), "{x} {");
+        assert_eq!(prefix("plain", 0, "in", &meta), "plain");
+    }
+
+    #[test]
+    fn colors() {
+        let value = literal!({"snot": [1, "badger", null], "empty": {}});
+        let colorized = colorize(&value);
+        let stripped = [RESET, KEY, STRING, NUMBER, LITERAL]
+            .iter()
+            .fold(colorized.clone(), |s, c| s.replace(c, ""));
+        assert_eq!(stripped, value.encode_pp());
+        assert!(colorized.contains("\x1b[32m\"badger\"\x1b[0m"));
+    }
+}
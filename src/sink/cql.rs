@@ -0,0 +1,431 @@
+// Copyright 2020-2021, The Tremor Team
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+//! # CQL Offramp
+//!
+//! Writes events to Cassandra or ScyllaDB with prepared statements. The bind
+//! markers of a statement are bound to the event fields of the same name,
+//! e.g. `INSERT INTO metrics (host, ts, value) VALUES (?, ?, ?)` binds the
+//! fields `host`, `ts` and `value`. Values are converted to the column types,
+//! integers are nanoseconds for `timestamp` columns.
+//!
+//! The statement is chosen by name with `$cql.statement`, the first
+//! statement is the default. The values of a batched event are written in
+//! unlogged batches per partition, requests are routed to the replicas of the
+//! partition.
+//!
+//! ## Configuration
+//!
+//! See [Config](struct.Config.html) for details.
+
+#![cfg(not(tarpaulin_include))]
+
+use crate::sink::prelude::*;
+use halfbrown::HashMap;
+use scylla::batch::{Batch, BatchType};
+use scylla::frame::response::result::ColumnType;
+use scylla::frame::value::{SerializedValues, Value as CqlValue, ValueTooBig};
+use scylla::prepared_statement::PreparedStatement;
+use scylla::statement::Consistency as CqlConsistency;
+use scylla::transport::load_balancing::{
+    DcAwareRoundRobinPolicy, RoundRobinPolicy, TokenAwarePolicy,
+};
+use scylla::{Session, SessionBuilder};
+use std::sync::Arc;
+
+#[derive(Deserialize, Debug, Clone, Copy)]
+#[serde(rename_all = "snake_case")]
+pub enum Consistency {
+    Any,
+    One,
+    Two,
+    Three,
+    Quorum,
+    All,
+    LocalQuorum,
+    EachQuorum,
+    LocalOne,
+}
+
+impl From<Consistency> for CqlConsistency {
+    fn from(c: Consistency) -> Self {
+        match c {
+            Consistency::Any => Self::Any,
+            Consistency::One => Self::One,
+            Consistency::Two => Self::Two,
+            Consistency::Three => Self::Three,
+            Consistency::Quorum => Self::Quorum,
+            Consistency::All => Self::All,
+            Consistency::LocalQuorum => Self::LocalQuorum,
+            Consistency::EachQuorum => Self::EachQuorum,
+            Consistency::LocalOne => Self::LocalOne,
+        }
+    }
+}
+
+fn dflt_consistency() -> Consistency {
+    Consistency::LocalQuorum
+}
+
+#[derive(Deserialize, Debug, Clone)]
+pub struct Statement {
+    pub name: String,
+    pub query: String,
+    /// (default: `local_quorum`)
+    #[serde(default = "dflt_consistency")]
+    pub consistency: Consistency,
+}
+
+#[derive(Deserialize, Debug)]
+pub struct Config {
+    /// Contact points, `host:port`
+    pub nodes: Vec<String>,
+    pub keyspace: Option<String>,
+    pub username: Option<String>,
+    pub password: Option<String>,
+    /// Prefer the nodes of this datacenter
+    pub local_dc: Option<String>,
+    pub statements: Vec<Statement>,
+}
+
+impl ConfigImpl for Config {}
+
+/// A value serialized for a column
+#[derive(Debug, Clone, PartialEq)]
+struct Serialized(Vec<u8>);
+
+impl CqlValue for Serialized {
+    fn serialize(&self, buf: &mut Vec<u8>) -> std::result::Result<(), ValueTooBig> {
+        buf.extend_from_slice(&self.0);
+        Ok(())
+    }
+}
+
+fn put_bytes(bytes: &[u8], buf: &mut Vec<u8>) -> Result<()> {
+    buf.extend_from_slice(&i32::try_from(bytes.len())?.to_be_bytes());
+    buf.extend_from_slice(bytes);
+    Ok(())
+}
+
+/// The bytes of a value of a column type, without the length
+fn cql_bytes(typ: &ColumnType, value: &Value) -> Result<Vec<u8>> {
+    let invalid = || Error::from(format!("Invalid value for a {:?} column: {}", typ, value));
+    let int = || value.as_i64().ok_or_else(invalid);
+    Ok(match typ {
+        ColumnType::Ascii | ColumnType::Text => {
+            value.as_str().ok_or_else(invalid)?.as_bytes().to_vec()
+        }
+        ColumnType::Blob => match value.as_bytes() {
+            Some(bytes) => bytes.to_vec(),
+            None => value.as_str().ok_or_else(invalid)?.as_bytes().to_vec(),
+        },
+        ColumnType::Boolean => vec![u8::from(value.as_bool().ok_or_else(invalid)?)],
+        ColumnType::TinyInt => i8::try_from(int()?)?.to_be_bytes().to_vec(),
+        ColumnType::SmallInt => i16::try_from(int()?)?.to_be_bytes().to_vec(),
+        ColumnType::Int => i32::try_from(int()?)?.to_be_bytes().to_vec(),
+        ColumnType::BigInt | ColumnType::Counter => int()?.to_be_bytes().to_vec(),
+        ColumnType::Timestamp => (int()? / 1_000_000).to_be_bytes().to_vec(),
+        #[allow(clippy::cast_possible_truncation)]
+        ColumnType::Float => (value.cast_f64().ok_or_else(invalid)? as f32)
+            .to_be_bytes()
+            .to_vec(),
+        ColumnType::Double => value.cast_f64().ok_or_else(invalid)?.to_be_bytes().to_vec(),
+        ColumnType::Uuid | ColumnType::Timeuuid => {
+            let uuid = hex::decode(value.as_str().ok_or_else(invalid)?.replace('-', ""))?;
+            if uuid.len() != 16 {
+                return Err(invalid());
+            }
+            uuid
+        }
+        ColumnType::List(element) | ColumnType::Set(element) => {
+            let elements = value.as_array().ok_or_else(invalid)?;
+            let mut buf = i32::try_from(elements.len())?.to_be_bytes().to_vec();
+            for element_value in elements {
+                put_bytes(&cql_bytes(element, element_value)?, &mut buf)?;
+            }
+            buf
+        }
+        ColumnType::Map(key, element) => {
+            let entries = value.as_object().ok_or_else(invalid)?;
+            let mut buf = i32::try_from(entries.len())?.to_be_bytes().to_vec();
+            for (k, v) in entries.iter() {
+                put_bytes(&cql_bytes(key, &Value::from(k.to_string()))?, &mut buf)?;
+                put_bytes(&cql_bytes(element, v)?, &mut buf)?;
+            }
+            buf
+        }
+        other => return Err(format!("Unsupported column type {:?}", other).into()),
+    })
+}
+
+/// Serializes a value for a column, missing values and `null` are `NULL`
+fn serialize(typ: &ColumnType, value: Option<&Value>) -> Result<Serialized> {
+    let mut buf = Vec::new();
+    match value.filter(|v| !v.is_null()) {
+        Some(value) => put_bytes(&cql_bytes(typ, value)?, &mut buf)?,
+        None => buf.extend_from_slice(&(-1_i32).to_be_bytes()),
+    }
+    Ok(Serialized(buf))
+}
+
+/// Binds the event fields to the bind markers of a statement
+fn bind(prepared: &PreparedStatement, value: &Value) -> Result<SerializedValues> {
+    let mut values = SerializedValues::new();
+    for column in &prepared.get_prepared_metadata().col_specs {
+        let serialized = serialize(&column.typ, value.get(column.name.as_str()))
+            .map_err(|e| Error::from(format!("Column `{}`: {}", column.name, e)))?;
+        values
+            .add_value(&serialized)
+            .map_err(|e| Error::from(e.to_string()))?;
+    }
+    Ok(values)
+}
+
+pub struct Cql {
+    config: Config,
+    session: Option<Session>,
+    prepared: Vec<PreparedStatement>,
+}
+
+impl offramp::Impl for Cql {
+    fn from_config(config: &Option<OpConfig>) -> Result<Box<dyn Offramp>> {
+        if let Some(config) = config {
+            let config: Config = Config::new(config)?;
+            if config.statements.is_empty() {
+                return Err("The cql offramp requires a statement".into());
+            }
+            Ok(SinkManager::new_box(Self {
+                config,
+                session: None,
+                prepared: Vec::new(),
+            }))
+        } else {
+            Err("Missing config for cql offramp".into())
+        }
+    }
+}
+
+impl Cql {
+    async fn connect(&mut self) -> Result<()> {
+        let policy = if let Some(dc) = &self.config.local_dc {
+            TokenAwarePolicy::new(Box::new(DcAwareRoundRobinPolicy::new(dc.clone())))
+        } else {
+            TokenAwarePolicy::new(Box::new(RoundRobinPolicy::new()))
+        };
+        let mut builder = SessionBuilder::new()
+            .known_nodes(&self.config.nodes)
+            .load_balancing(Arc::new(policy));
+        if let (Some(username), Some(password)) = (&self.config.username, &self.config.password) {
+            builder = builder.user(username, password);
+        }
+        let session = builder
+            .build()
+            .await
+            .map_err(|e| Error::from(e.to_string()))?;
+        if let Some(keyspace) = &self.config.keyspace {
+            session
+                .use_keyspace(keyspace, false)
+                .await
+                .map_err(|e| Error::from(e.to_string()))?;
+        }
+        let mut prepared = Vec::with_capacity(self.config.statements.len());
+        for statement in &self.config.statements {
+            let mut p = session
+                .prepare(statement.query.as_str())
+                .await
+                .map_err(|e| Error::from(format!("Statement `{}`: {}", statement.name, e)))?;
+            p.set_consistency(statement.consistency.into());
+            prepared.push(p);
+        }
+        info!("[Sink::CQL] Connected to {}", self.config.nodes.join(", "));
+        self.prepared = prepared;
+        self.session = Some(session);
+        Ok(())
+    }
+
+    /// Writes the values of an event, in batches per statement and partition
+    async fn write(&self, session: &Session, event: &Event) -> Result<()> {
+        // (statement, partition key) -> values
+        let mut partitions: Vec<(usize, Vec<u8>, Vec<SerializedValues>)> = Vec::new();
+        for (value, meta) in event.value_meta_iter() {
+            let statement = match meta.get("cql").get_str("statement") {
+                Some(name) => self
+                    .config
+                    .statements
+                    .iter()
+                    .position(|s| s.name == name)
+                    .ok_or_else(|| Error::from(format!("Unknown statement `{}`", name)))?,
+                None => 0,
+            };
+            let prepared = self
+                .prepared
+                .get(statement)
+                .ok_or_else(|| Error::from("Statements are not prepared"))?;
+            let values = bind(prepared, value)?;
+            let key = prepared
+                .compute_partition_key(&values)
+                .map_err(|e| Error::from(e.to_string()))?
+                .to_vec();
+            if let Some((_, _, batch)) = partitions
+                .iter_mut()
+                .find(|(s, k, _)| *s == statement && *k == key)
+            {
+                batch.push(values);
+            } else {
+                partitions.push((statement, key, vec![values]));
+            }
+        }
+        for (statement, _, mut values) in partitions {
+            let prepared = self
+                .prepared
+                .get(statement)
+                .ok_or_else(|| Error::from("Statements are not prepared"))?;
+            if values.len() == 1 {
+                let values = values.pop().ok_or_else(|| Error::from("No values"))?;
+                session
+                    .execute(prepared, &values)
+                    .await
+                    .map_err(|e| Error::from(e.to_string()))?;
+            } else {
+                let mut batch = Batch::new(BatchType::Unlogged);
+                for _ in &values {
+                    batch.append_statement(prepared.clone());
+                }
+                batch.set_consistency(prepared.get_consistency());
+                session
+                    .batch(&batch, values)
+                    .await
+                    .map_err(|e| Error::from(e.to_string()))?;
+            }
+        }
+        Ok(())
+    }
+}
+
+#[async_trait::async_trait]
+impl Sink for Cql {
+    async fn on_event(
+        &mut self,
+        _input: &str,
+        _codec: &mut dyn Codec,
+        _codec_map: &HashMap<String, Box<dyn Codec>>,
+        mut event: Event,
+    ) -> ResultVec {
+        let session = if let Some(session) = &self.session {
+            session
+        } else {
+            return Ok(Some(vec![
+                sink::Reply::Insight(event.to_fail()),
+                sink::Reply::Insight(event.insight_trigger()),
+            ]));
+        };
+        let res = self.write(session, &event).await;
+        if let Err(e) = &res {
+            error!("[Sink::CQL] Failed to write event: {}", e);
+        }
+        Ok(if event.transactional {
+            Some(vec![sink::Reply::Insight(if res.is_ok() {
+                event.insight_ack()
+            } else {
+                event.insight_fail()
+            })])
+        } else {
+            None
+        })
+    }
+
+    async fn on_signal(&mut self, signal: Event) -> ResultVec {
+        if self.session.is_none() {
+            self.connect().await?;
+            Ok(Some(vec![sink::Reply::Insight(Event::cb_restore(
+                signal.ingest_ns,
+            ))]))
+        } else {
+            Ok(None)
+        }
+    }
+
+    #[allow(clippy::too_many_arguments)]
+    async fn init(
+        &mut self,
+        _sink_uid: u64,
+        _sink_url: &TremorUrl,
+        _codec: &dyn Codec,
+        _codec_map: &HashMap<String, Box<dyn Codec>>,
+        _processors: Processors<'_>,
+        _is_linked: bool,
+        _reply_channel: Sender<sink::Reply>,
+    ) -> Result<()> {
+        if let Err(e) = self.connect().await {
+            warn!("[Sink::CQL] Failed to connect: {}", e);
+        }
+        Ok(())
+    }
+
+    fn is_active(&self) -> bool {
+        self.session.is_some()
+    }
+
+    fn auto_ack(&self) -> bool {
+        false
+    }
+
+    fn default_codec(&self) -> &str {
+        "json"
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn values() -> Result<()> {
+        assert_eq!(
+            serialize(&ColumnType::Int, None)?.0,
+            vec![255, 255, 255, 255]
+        );
+        assert_eq!(
+            serialize(&ColumnType::Int, Some(&Value::from(258)))?.0,
+            vec![0, 0, 0, 4, 0, 0, 1, 2]
+        );
+        assert!(serialize(&ColumnType::TinyInt, Some(&Value::from(258))).is_err());
+        assert_eq!(
+            serialize(&ColumnType::Timestamp, Some(&Value::from(1_000_000_000)))?.0,
+            vec![0, 0, 0, 8, 0, 0, 0, 0, 0, 0, 3, 232]
+        );
+        assert_eq!(
+            serialize(&ColumnType::Text, Some(&Value::from("snot")))?.0,
+            b"\0\0\0\x04snot".to_vec()
+        );
+        assert_eq!(
+            serialize(
+                &ColumnType::List(Box::new(ColumnType::SmallInt)),
+                Some(&literal!([1, 2]))
+            )?
+            .0,
+            vec![0, 0, 0, 16, 0, 0, 0, 2, 0, 0, 0, 2, 0, 1, 0, 0, 0, 2, 0, 2]
+        );
+        assert_eq!(
+            serialize(
+                &ColumnType::Uuid,
+                Some(&Value::from("00112233-4455-6677-8899-aabbccddeeff"))
+            )?
+            .0[4..],
+            [0, 17, 34, 51, 68, 85, 102, 119, 136, 153, 170, 187, 204, 221, 238, 255]
+        );
+        assert!(serialize(&ColumnType::Inet, Some(&Value::from("127.0.0.1"))).is_err());
+        Ok(())
+    }
+}
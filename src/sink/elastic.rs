@@ -32,6 +32,8 @@
 
 #![cfg(not(tarpaulin_include))]
 
+use crate::connectors::proxy::{self, Kind, Proxy};
+use crate::connectors::tls::TlsConfig;
 use crate::sink::prelude::*;
 use async_channel::{bounded, Receiver, Sender};
 use async_std::task::JoinHandle;
@@ -55,12 +57,74 @@ pub struct Config {
     /// maximum number of paralel in flight batches (default: 4)
     #[serde(default = "concurrency")]
     pub concurrency: usize,
+    /// TLS for `https` nodes, `domain`, `alpn` and `min_version` are not supported
+    #[serde(default)]
+    pub tls: Option<TlsConfig>,
+    /// HTTP proxy for requests, overrides the default proxy
+    #[serde(default)]
+    pub proxy: Option<Proxy>,
 }
 fn concurrency() -> usize {
     4
 }
 impl ConfigImpl for Config {}
 
+/// The http client of elastic configured for TLS and proxies, `None` if
+/// neither is configured
+fn http_client(config: &Config) -> Result<Option<elastic_reqwest::Client>> {
+    let proxy = proxy::resolve(config.proxy.as_ref())?;
+    if config.tls.is_none() && proxy.is_none() {
+        return Ok(None);
+    }
+    let tls_err = |e: elastic_reqwest::Error| Error::from(ErrorKind::TLSError(e.to_string()));
+    let mut builder = elastic_reqwest::Client::builder();
+    if let Some(tls) = &config.tls {
+        tls.unsupported("elastic", &["domain", "alpn", "min_version"])?;
+        builder = builder.danger_accept_invalid_certs(tls.insecure_skip_verify);
+        if let Some(cafile) = &tls.cafile {
+            let pem = std::fs::read(cafile)?;
+            builder = builder.add_root_certificate(
+                elastic_reqwest::Certificate::from_pem(&pem).map_err(tls_err)?,
+            );
+        }
+        match (&tls.cert, &tls.key) {
+            (Some(cert), Some(key)) => {
+                // reqwest expects the key and the certificate chain in one PEM
+                let mut pem = std::fs::read(key)?;
+                pem.push(b'\n');
+                pem.extend(std::fs::read(cert)?);
+                builder =
+                    builder.identity(elastic_reqwest::Identity::from_pem(&pem).map_err(tls_err)?);
+            }
+            (None, None) => (),
+            _ => {
+                return Err(Error::from(ErrorKind::TLSError(
+                    "`cert` and `key` need to be configured together".to_string(),
+                )))
+            }
+        }
+    }
+    if let Some(proxy) = proxy {
+        let target = proxy.target()?;
+        if target.kind != Kind::Http {
+            return Err("The elastic offramp only supports HTTP proxies".into());
+        }
+        let url = target.url();
+        let mut reqwest_proxy = elastic_reqwest::Proxy::custom(move |req_url| {
+            if req_url.host_str().map_or(false, |host| proxy.bypass(host)) {
+                None
+            } else {
+                Some(url.clone())
+            }
+        });
+        if let Some((user, password)) = &target.credentials {
+            reqwest_proxy = reqwest_proxy.basic_auth(user, password);
+        }
+        builder = builder.proxy(reqwest_proxy);
+    }
+    Ok(Some(builder.build().map_err(tls_err)?))
+}
+
 pub struct Elastic {
     sink_url: TremorUrl,
     client: SyncClient,
@@ -76,9 +140,12 @@ impl offramp::Impl for Elastic {
     fn from_config(config: &Option<OpConfig>) -> Result<Box<dyn Offramp>> {
         if let Some(config) = config {
             let config: Config = Config::new(config)?;
-            let client = SyncClientBuilder::new()
-                .static_nodes(config.nodes.into_iter())
-                .build()?;
+            let http_client = http_client(&config)?;
+            let mut builder = SyncClientBuilder::new().static_nodes(config.nodes.into_iter());
+            if let Some(http_client) = http_client {
+                builder = builder.http_client(http_client);
+            }
+            let client = builder.build()?;
 
             let queue = AsyncSink::new(config.concurrency);
             let (tx, _rx) = bounded(1); // dummy value
@@ -0,0 +1,111 @@
+// Copyright 2020-2021, The Tremor Team
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+//! # Embedded Offramp
+//!
+//! Hands events to an application embedding tremor, through the channel it
+//! opened with `World::embedded_offramp` or `World::embedded_callback`.
+//! Events keep their metadata and are not encoded with a codec. Sending
+//! waits while the application is busy, so it slows down the pipelines.
+//!
+//! ## Configuration
+//!
+//! See [Config](struct.Config.html) for details.
+
+use crate::embed::{self, Embedded as Output};
+use crate::sink::prelude::*;
+use halfbrown::HashMap;
+
+#[derive(Deserialize, Debug)]
+pub struct Config {
+    /// Name of the channel the application opened
+    pub channel: String,
+}
+
+impl ConfigImpl for Config {}
+
+/// An offramp handing events to the embedding application
+pub struct Embedded {
+    config: Config,
+    tx: Option<Sender<Output>>,
+}
+
+impl offramp::Impl for Embedded {
+    fn from_config(config: &Option<OpConfig>) -> Result<Box<dyn Offramp>> {
+        if let Some(config) = config {
+            let config: Config = Config::new(config)?;
+            Ok(SinkManager::new_box(Self { config, tx: None }))
+        } else {
+            Err("Embedded offramp requires a config".into())
+        }
+    }
+}
+
+#[async_trait::async_trait]
+impl Sink for Embedded {
+    async fn on_event(
+        &mut self,
+        _input: &str,
+        _codec: &mut dyn Codec,
+        _codec_map: &HashMap<String, Box<dyn Codec>>,
+        event: Event,
+    ) -> ResultVec {
+        let tx = self
+            .tx
+            .as_ref()
+            .ok_or_else(|| Error::from("Embedded offramp isn't initialized"))?;
+        for (data, meta) in event.value_meta_iter() {
+            let output = Output {
+                data: data.clone_static(),
+                meta: meta.clone_static(),
+            };
+            if tx.send(output).await.is_err() {
+                error!("[Sink::Embedded] Channel {} is closed", self.config.channel);
+                self.tx = None;
+                return Ok(if event.transactional {
+                    Some(vec![sink::Reply::Insight(event.to_fail())])
+                } else {
+                    None
+                });
+            }
+        }
+        Ok(None)
+    }
+    fn default_codec(&self) -> &str {
+        "json"
+    }
+    #[allow(clippy::too_many_arguments)]
+    async fn init(
+        &mut self,
+        _sink_uid: u64,
+        _sink_url: &TremorUrl,
+        _codec: &dyn Codec,
+        _codec_map: &HashMap<String, Box<dyn Codec>>,
+        _processors: Processors<'_>,
+        _is_linked: bool,
+        _reply_channel: Sender<sink::Reply>,
+    ) -> Result<()> {
+        self.tx = Some(embed::output(&self.config.channel)?);
+        Ok(())
+    }
+    async fn on_signal(&mut self, _signal: Event) -> ResultVec {
+        Ok(None)
+    }
+    fn is_active(&self) -> bool {
+        self.tx.is_some()
+    }
+    fn auto_ack(&self) -> bool {
+        true
+    }
+}
@@ -25,12 +25,15 @@
 use crate::sink::prelude::*;
 use async_std::fs::File as FSFile;
 use async_std::io::prelude::*;
+use async_std::io::Write;
 use halfbrown::HashMap;
 use tremor_common::asy::file as cfile;
 
+type Writer = Box<dyn Write + std::marker::Unpin + Send>;
+
 /// An offramp that write a given file
 pub struct File {
-    file: Option<FSFile>,
+    file: Option<Writer>,
     postprocessors: Postprocessors,
     config: Config,
 }
@@ -59,6 +62,17 @@ impl offramp::Impl for File {
     }
 }
 
+impl File {
+    /// Writes through io_uring if it is available
+    fn writer(file: FSFile) -> Result<Writer> {
+        #[cfg(all(feature = "io-uring", target_os = "linux"))]
+        if let Some(ring) = crate::uring::ring() {
+            return Ok(Box::new(crate::uring::File::new(ring, file)?));
+        }
+        Ok(Box::new(file))
+    }
+}
+
 #[async_trait::async_trait]
 impl Sink for File {
     async fn terminate(&mut self) {
@@ -105,7 +119,7 @@ impl Sink for File {
     ) -> Result<()> {
         self.postprocessors = make_postprocessors(processors.post)?;
         let file = cfile::create(&self.config.file).await?;
-        self.file = Some(file);
+        self.file = Some(Self::writer(file)?);
         Ok(())
     }
     async fn on_signal(&mut self, _signal: Event) -> ResultVec {
@@ -0,0 +1,76 @@
+// Copyright 2020-2021, The Tremor Team
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+//! # GCS Objects Offramp
+//!
+//! Writes events into rolling objects of a Google Cloud Storage bucket, see
+//! the [object store sinks](../object_store/index.html) for the rolling and
+//! the key templates. Unlike the `gcs` offramp, events are data, not
+//! commands.
+//!
+//! Authentication uses `GOOGLE_APPLICATION_CREDENTIALS`.
+//!
+//! ## Configuration
+//!
+//! See [Config](struct.Config.html) for details.
+
+#![cfg(not(tarpaulin_include))]
+
+use crate::connectors::gcp::{
+    auth::{self, GcsClient},
+    storage,
+};
+use crate::sink::object_store::{ObjectStore, ObjectStoreSink, RollConfig};
+use crate::sink::prelude::*;
+use http::HeaderMap;
+
+#[derive(Deserialize, Debug)]
+pub struct Config {
+    pub bucket: String,
+    #[serde(flatten)]
+    pub roll: RollConfig,
+}
+
+impl ConfigImpl for Config {}
+
+pub struct GcsObjects {
+    bucket: String,
+    client: GcsClient,
+}
+
+impl offramp::Impl for GcsObjects {
+    fn from_config(config: &Option<OpConfig>) -> Result<Box<dyn Offramp>> {
+        if let Some(config) = config {
+            let config: Config = Config::new(config)?;
+            let store = Self {
+                bucket: config.bucket,
+                client: auth::json_api_client(&HeaderMap::new())?,
+            };
+            ObjectStoreSink::new_box(store, config.roll)
+        } else {
+            Err("Missing config for gcs-objects offramp".into())
+        }
+    }
+}
+
+#[async_trait::async_trait]
+impl ObjectStore for GcsObjects {
+    fn name(&self) -> &str {
+        "GcsObjects"
+    }
+
+    async fn put(&self, key: &str, data: Vec<u8>, content_type: &str) -> Result<()> {
+        storage::put_object(&self.client, &self.bucket, key, data, content_type).await
+    }
+}
@@ -0,0 +1,208 @@
+// Copyright 2020-2021, The Tremor Team
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+#![cfg(not(tarpaulin_include))]
+
+//! # Interconnect Offramp
+//!
+//! Sends events to a pipeline on another tremor node by name, through the
+//! interconnect onramp of that node. Events keep their metadata and are
+//! not encoded with a codec. Sending pauses while too many events are
+//! unacknowledged by the other node, so its pipelines slow down this one.
+//!
+//! ## Configuration
+//!
+//! See [Config](struct.Config.html) for details.
+
+use crate::connectors::interconnect;
+use crate::sink::prelude::*;
+use async_std::net::TcpStream;
+use halfbrown::HashMap;
+use std::time::Instant;
+
+/// Payloads above this size are compressed
+const COMPRESS_ABOVE: usize = 1024;
+
+#[derive(Deserialize, Debug)]
+pub struct Config {
+    /// Host of the interconnect onramp
+    pub host: String,
+    pub port: u16,
+    /// Id of the pipeline on the other node, it has to be linked to the
+    /// interconnect onramp there
+    pub pipeline: String,
+    /// Maximum number of unacknowledged events (default: 64)
+    #[serde(default = "default_window")]
+    pub window: u64,
+    /// Compress events larger than 1KiB with snappy (default: true)
+    #[serde(default = "default_compression")]
+    pub compression: bool,
+}
+
+fn default_window() -> u64 {
+    64
+}
+
+fn default_compression() -> bool {
+    true
+}
+
+impl ConfigImpl for Config {}
+
+/// An offramp sending events to a pipeline on another node
+pub struct Interconnect {
+    config: Config,
+    stream: Option<TcpStream>,
+    /// events sent on the connection
+    sent: u64,
+    /// events the other node acknowledged
+    acked: u64,
+}
+
+impl offramp::Impl for Interconnect {
+    fn from_config(config: &Option<OpConfig>) -> Result<Box<dyn Offramp>> {
+        if let Some(config) = config {
+            let config: Config = Config::new(config)?;
+            if config.window == 0 {
+                return Err("Interconnect offramp requires a window of at least 1".into());
+            }
+            Ok(SinkManager::new_box(Self {
+                config,
+                stream: None,
+                sent: 0,
+                acked: 0,
+            }))
+        } else {
+            Err("Interconnect offramp requires a config".into())
+        }
+    }
+}
+
+impl Interconnect {
+    async fn connect(&mut self) -> Result<()> {
+        let stream = TcpStream::connect((self.config.host.as_str(), self.config.port)).await?;
+        stream.set_nodelay(true)?;
+        info!(
+            "[Sink::Interconnect] Connected to {}:{}",
+            self.config.host, self.config.port
+        );
+        self.stream = Some(stream);
+        self.sent = 0;
+        self.acked = 0;
+        Ok(())
+    }
+
+    async fn send_event(&mut self, event: &Event) -> Result<()> {
+        let stream = self
+            .stream
+            .as_mut()
+            .ok_or_else(|| Error::from(ErrorKind::NoSocket))?;
+        let compress_above = if self.config.compression {
+            Some(COMPRESS_ABOVE)
+        } else {
+            None
+        };
+        for (data, meta) in event.value_meta_iter() {
+            while self.sent - self.acked >= self.config.window {
+                self.acked = interconnect::read_ack(stream).await?;
+            }
+            let frame = interconnect::encode(&self.config.pipeline, data, meta, compress_above)?;
+            stream.write_all(&frame).await?;
+            self.sent += 1;
+        }
+        Ok(())
+    }
+}
+
+#[async_trait::async_trait]
+impl Sink for Interconnect {
+    #[allow(clippy::cast_possible_truncation)]
+    async fn on_event(
+        &mut self,
+        _input: &str,
+        _codec: &mut dyn Codec,
+        _codec_map: &HashMap<String, Box<dyn Codec>>,
+        event: Event,
+    ) -> ResultVec {
+        let processing_start = Instant::now();
+        let replies = match self.send_event(&event).await {
+            Ok(()) => {
+                if event.transactional {
+                    Some(vec![sink::Reply::Insight(event.insight_ack_with_timing(
+                        processing_start.elapsed().as_millis() as u64,
+                    ))])
+                } else {
+                    None
+                }
+            }
+            // the connection is lost, trigger the CB until we reconnected
+            Err(e @ Error(ErrorKind::Io(_) | ErrorKind::NoSocket, _)) => {
+                error!("[Sink::Interconnect] Error sending event: {}.", e);
+                self.stream = None;
+                if event.transactional {
+                    Some(vec![
+                        sink::Reply::Insight(event.to_fail()),
+                        sink::Reply::Insight(event.insight_trigger()),
+                    ])
+                } else {
+                    Some(vec![sink::Reply::Insight(event.insight_trigger())])
+                }
+            }
+            Err(e) => {
+                error!("[Sink::Interconnect] Error sending event: {}", e);
+                if event.transactional {
+                    Some(vec![sink::Reply::Insight(event.to_fail())])
+                } else {
+                    None
+                }
+            }
+        };
+        Ok(replies)
+    }
+    fn default_codec(&self) -> &str {
+        "json"
+    }
+    #[allow(clippy::too_many_arguments)]
+    async fn init(
+        &mut self,
+        _sink_uid: u64,
+        _sink_url: &TremorUrl,
+        _codec: &dyn Codec,
+        _codec_map: &HashMap<String, Box<dyn Codec>>,
+        _processors: Processors<'_>,
+        _is_linked: bool,
+        _reply_channel: Sender<sink::Reply>,
+    ) -> Result<()> {
+        if let Err(e) = self.connect().await {
+            warn!("[Sink::Interconnect] Failed to connect: {}", e);
+        }
+        Ok(())
+    }
+    async fn on_signal(&mut self, signal: Event) -> ResultVec {
+        if self.stream.is_none() {
+            self.connect().await?;
+            Ok(Some(vec![sink::Reply::Insight(Event::cb_restore(
+                signal.ingest_ns,
+            ))]))
+        } else {
+            Ok(None)
+        }
+    }
+    fn is_active(&self) -> bool {
+        self.stream.is_some()
+    }
+    fn auto_ack(&self) -> bool {
+        false
+    }
+}
@@ -22,6 +22,8 @@
 //!
 //! See [Config](struct.Config.html) for details.
 
+use crate::connectors::kafka::{Sasl, TokenRefresh};
+use crate::connectors::tls::TlsConfig;
 use crate::sink::prelude::*;
 use async_channel::{bounded, Receiver, Sender};
 use halfbrown::HashMap;
@@ -60,6 +62,12 @@ pub struct Config {
     /// key to use for messages, defaults to none
     #[serde(default = "Default::default")]
     pub key: Option<String>,
+    /// SASL OAUTHBEARER authentication, tokens are refreshed before they expire
+    #[serde(default = "Default::default")]
+    pub sasl: Option<Sasl>,
+    /// TLS, e.g. to present a client certificate
+    #[serde(default = "Default::default")]
+    pub tls: Option<TlsConfig>,
 }
 
 impl Config {
@@ -74,6 +82,12 @@ impl Config {
             .set("bootstrap.servers", &self.brokers.join(","))
             .set("message.timeout.ms", "5000")
             .set("queue.buffering.max.ms", "0"); // set to 0 for sending each message out immediately without kafka client internal batching --> low latency, busy network
+        if let Some(tls) = &self.tls {
+            tls.configure_rdkafka(producer_config)?;
+        }
+        if let Some(sasl) = &self.sasl {
+            sasl.configure(producer_config);
+        }
 
         Ok(self
             .rdkafka_options
@@ -81,6 +95,16 @@ impl Config {
             .fold(producer_config, |c: &mut ClientConfig, (k, v)| c.set(k, v))
             .create()?)
     }
+
+    fn token_refresh(&self, producer: &FutureProducer) -> Option<TokenRefresh> {
+        self.sasl.clone().map(|sasl| {
+            TokenRefresh::start(
+                sasl,
+                producer.client().native_ptr(),
+                format!("Sink::kafka::{}", self.topic),
+            )
+        })
+    }
 }
 
 impl ConfigImpl for Config {}
@@ -93,6 +117,8 @@ fn d_host() -> String {
 pub struct Kafka {
     sink_url: TremorUrl,
     config: Config,
+    // declared before the producer so it is dropped first
+    _token_refresh: Option<TokenRefresh>,
     producer: FutureProducer,
     postprocessors: Postprocessors,
     reply_tx: Sender<sink::Reply>,
@@ -111,6 +137,7 @@ impl offramp::Impl for Kafka {
         if let Some(config) = config {
             let config: Config = Config::new(config)?;
             let producer = config.producer()?;
+            let token_refresh = config.token_refresh(&producer);
             // Create the thread pool where the expensive computation will be performed.
             let (dummy_tx, _) = bounded(1);
 
@@ -119,6 +146,7 @@ impl offramp::Impl for Kafka {
             Ok(SinkManager::new_box(Self {
                 sink_url: TremorUrl::from_offramp_id("kafka")?, // dummy
                 config,
+                _token_refresh: token_refresh,
                 producer,
                 postprocessors: vec![],
                 reply_tx: dummy_tx,
@@ -216,7 +244,10 @@ impl Kafka {
         );
 
         error!("[Sink::{}] Reinitiating client...", &self.sink_url);
+        // stop refreshing the token of the old client before it is dropped
+        self._token_refresh = None;
         self.producer = self.config.producer()?;
+        self._token_refresh = self.config.token_refresh(&self.producer);
         error!("[Sink::{}] Client reinitiated.", &self.sink_url);
 
         Ok(())
@@ -0,0 +1,471 @@
+// Copyright 2020-2021, The Tremor Team
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+//! # Loki Offramp
+//!
+//! Pushes events as log lines to Grafana Loki. Every event is encoded with the
+//! codec into a line and added to the stream of its labels, labels are taken
+//! from event fields by `labels`, from `static_labels` and from
+//! `$loki.labels`. Entries are pushed as snappy compressed protobuf in
+//! batches of `batch_size` entries, or on the next signal once
+//! `flush_interval` elapsed.
+//!
+//! Pushes are retried on `429` and server errors, honouring `Retry-After`.
+//! Multi tenant setups get `tenant`, or `$loki.tenant`, as `X-Scope-OrgID`.
+//!
+//! ## Configuration
+//!
+//! See [Config](struct.Config.html) for details.
+
+#![cfg(not(tarpaulin_include))]
+
+use crate::sink::prelude::*;
+use halfbrown::HashMap;
+use http_types::auth::BasicAuth;
+use http_types::headers::CONTENT_TYPE;
+use prost::Message;
+use std::collections::BTreeMap;
+use std::time::Duration;
+
+#[derive(Deserialize, Debug)]
+pub struct Config {
+    /// Base url of Loki, e.g. `http://localhost:3100`
+    pub endpoint: String,
+    /// Labels taken from event fields, label name to a dotted path of the
+    /// field, e.g. `kubernetes.namespace`
+    #[serde(default)]
+    pub labels: HashMap<String, String>,
+    /// Labels of all streams
+    #[serde(default)]
+    pub static_labels: HashMap<String, String>,
+    /// Tenant to push to, sent as `X-Scope-OrgID`
+    pub tenant: Option<String>,
+    pub username: Option<String>,
+    pub password: Option<String>,
+    /// Entries per push (default: 1000)
+    #[serde(default = "dflt_batch_size")]
+    pub batch_size: usize,
+    /// Milliseconds after which a partial batch is pushed (default: 1000)
+    #[serde(default = "dflt_flush_interval")]
+    pub flush_interval: u64,
+    /// Retries of a push that was rate limited or failed on the server
+    /// (default: 5)
+    #[serde(default = "dflt_max_retries")]
+    pub max_retries: u32,
+    /// Backoff before the first retry in milliseconds, it doubles with every
+    /// retry (default: 1000)
+    #[serde(default = "dflt_backoff")]
+    pub backoff: u64,
+}
+
+fn dflt_batch_size() -> usize {
+    1000
+}
+
+fn dflt_flush_interval() -> u64 {
+    1000
+}
+
+fn dflt_max_retries() -> u32 {
+    5
+}
+
+fn dflt_backoff() -> u64 {
+    1000
+}
+
+impl ConfigImpl for Config {}
+
+/// `logproto.PushRequest`
+#[derive(Clone, PartialEq, Message)]
+struct PushRequest {
+    #[prost(message, repeated, tag = "1")]
+    streams: Vec<Stream>,
+}
+
+/// `logproto.StreamAdapter`
+#[derive(Clone, PartialEq, Message)]
+struct Stream {
+    #[prost(string, tag = "1")]
+    labels: String,
+    #[prost(message, repeated, tag = "2")]
+    entries: Vec<Entry>,
+}
+
+/// `logproto.EntryAdapter`
+#[derive(Clone, PartialEq, Message)]
+struct Entry {
+    #[prost(message, optional, tag = "1")]
+    timestamp: Option<prost_types::Timestamp>,
+    #[prost(string, tag = "2")]
+    line: String,
+}
+
+fn label_value(value: &Value) -> String {
+    value
+        .as_str()
+        .map_or_else(|| value.encode(), ToString::to_string)
+}
+
+/// The labels of an event in the selector format of Loki,
+/// e.g. `{app="snot", level="info"}`
+fn labels(config: &Config, value: &Value, meta: &Value) -> Result<String> {
+    let mut labels: BTreeMap<String, String> = config
+        .static_labels
+        .iter()
+        .map(|(k, v)| (k.clone(), v.clone()))
+        .collect();
+    for (name, path) in &config.labels {
+        let field = path.split('.').try_fold(value, |value, key| value.get(key));
+        if let Some(field) = field.filter(|f| !f.is_null()) {
+            labels.insert(name.clone(), label_value(field));
+        }
+    }
+    if let Some(meta_labels) = meta.get_object("loki").and_then(|l| l.get("labels")) {
+        for (name, value) in meta_labels.as_object().into_iter().flatten() {
+            labels.insert(name.to_string(), label_value(value));
+        }
+    }
+    if labels.is_empty() {
+        return Err("Loki requires at least one label per stream".into());
+    }
+    let labels: Vec<String> = labels
+        .iter()
+        .map(|(name, value)| {
+            let value = value.replace('\\', "\\\\").replace('"', "\\\"");
+            format!("{}=\"{}\"", name, value.replace('\n', "\\n"))
+        })
+        .collect();
+    Ok(format!("{{{}}}", labels.join(", ")))
+}
+
+fn timestamp(ns: u64) -> Result<prost_types::Timestamp> {
+    Ok(prost_types::Timestamp {
+        seconds: i64::try_from(ns / 1_000_000_000)?,
+        nanos: i32::try_from(ns % 1_000_000_000)?,
+    })
+}
+
+/// The pending entries by tenant and stream labels
+#[derive(Default)]
+struct Batch {
+    streams: BTreeMap<Option<String>, BTreeMap<String, Vec<Entry>>>,
+    entries: usize,
+    insights: BatchInsights,
+}
+
+impl Batch {
+    fn push(&mut self, tenant: Option<String>, labels: String, entry: Entry) {
+        self.streams
+            .entry(tenant)
+            .or_default()
+            .entry(labels)
+            .or_default()
+            .push(entry);
+        self.entries += 1;
+    }
+}
+
+/// Encodes a push request, snappy compressed as Loki expects it
+fn push_body(streams: BTreeMap<String, Vec<Entry>>) -> Result<Vec<u8>> {
+    let request = PushRequest {
+        streams: streams
+            .into_iter()
+            .map(|(labels, entries)| Stream { labels, entries })
+            .collect(),
+    };
+    let mut buf = Vec::with_capacity(request.encoded_len());
+    request
+        .encode(&mut buf)
+        .map_err(|e| Error::from(e.to_string()))?;
+    Ok(snap::raw::Encoder::new().compress_vec(&buf)?)
+}
+
+pub struct Loki {
+    config: Config,
+    url: String,
+    batch: Batch,
+    last_flush: u64,
+}
+
+impl offramp::Impl for Loki {
+    fn from_config(config: &Option<OpConfig>) -> Result<Box<dyn Offramp>> {
+        if let Some(config) = config {
+            let config: Config = Config::new(config)?;
+            let url = format!("{}/loki/api/v1/push", config.endpoint.trim_end_matches('/'));
+            Ok(SinkManager::new_box(Self {
+                config,
+                url,
+                batch: Batch::default(),
+                last_flush: nanotime(),
+            }))
+        } else {
+            Err("Missing config for loki offramp".into())
+        }
+    }
+}
+
+/// The outcome of a single push
+enum Push {
+    Done,
+    /// rate limited or failed on the server, with the `Retry-After` delay
+    Retry(String, Option<Duration>),
+}
+
+impl Loki {
+    async fn push(&self, tenant: &Option<String>, body: Vec<u8>) -> Result<Push> {
+        let mut request = surf::post(&self.url)
+            .header(CONTENT_TYPE, "application/x-protobuf")
+            .body(body);
+        if let Some(tenant) = tenant {
+            request = request.header("X-Scope-OrgID", tenant.as_str());
+        }
+        if let (Some(username), Some(password)) = (&self.config.username, &self.config.password) {
+            let auth = BasicAuth::new(username, password);
+            request = request.header(auth.name(), auth.value());
+        }
+        let mut response = request.await?;
+        let status = response.status();
+        if status.is_success() {
+            return Ok(Push::Done);
+        }
+        let retry_after = response
+            .header("Retry-After")
+            .and_then(|v| v.last().as_str().parse::<u64>().ok())
+            .map(Duration::from_secs);
+        let body = response
+            .body_string()
+            .await
+            .unwrap_or_else(|e| format!("failed to load body {}", e));
+        let msg = format!("{}: {}", status, body.trim());
+        if status == 429 || status.is_server_error() {
+            Ok(Push::Retry(msg, retry_after))
+        } else {
+            Err(msg.into())
+        }
+    }
+
+    /// Pushes the streams of a tenant, retrying rate limits and server errors
+    async fn push_with_retries(
+        &self,
+        tenant: &Option<String>,
+        streams: BTreeMap<String, Vec<Entry>>,
+    ) -> Result<()> {
+        let body = push_body(streams)?;
+        let mut backoff = Duration::from_millis(self.config.backoff);
+        let mut retries = 0;
+        loop {
+            match self.push(tenant, body.clone()).await? {
+                Push::Done => return Ok(()),
+                Push::Retry(msg, _) if retries >= self.config.max_retries => {
+                    return Err(msg.into());
+                }
+                Push::Retry(msg, retry_after) => {
+                    let delay = retry_after.unwrap_or(backoff);
+                    warn!("[Sink::Loki] Push failed, retrying in {:?}: {}", delay, msg);
+                    task::sleep(delay).await;
+                    backoff *= 2;
+                    retries += 1;
+                }
+            }
+        }
+    }
+
+    async fn flush(&mut self) -> Vec<Reply> {
+        let batch = std::mem::take(&mut self.batch);
+        self.last_flush = nanotime();
+        let mut failed = false;
+        for (tenant, streams) in batch.streams {
+            if let Err(e) = self.push_with_retries(&tenant, streams).await {
+                error!("[Sink::Loki] Failed to push entries: {}", e);
+                failed = true;
+            }
+        }
+        batch.insights.replies(!failed)
+    }
+
+    fn add(&mut self, codec: &mut dyn Codec, event: &Event) -> Result<()> {
+        let mut entries = Vec::new();
+        for (value, meta) in event.value_meta_iter() {
+            let labels = labels(&self.config, value, meta)?;
+            let tenant = meta
+                .get_object("loki")
+                .and_then(|l| l.get("tenant"))
+                .and_then(ValueAccess::as_str)
+                .map(ToString::to_string)
+                .or_else(|| self.config.tenant.clone());
+            let ns = meta
+                .get_object("loki")
+                .and_then(|l| l.get("timestamp"))
+                .and_then(ValueAccess::as_u64)
+                .unwrap_or(event.ingest_ns);
+            let line = String::from_utf8(codec.encode(value)?)?;
+            entries.push((
+                tenant,
+                labels,
+                Entry {
+                    timestamp: Some(timestamp(ns)?),
+                    line,
+                },
+            ));
+        }
+        // only add complete events so a failed one isn't partially pushed
+        for (tenant, labels, entry) in entries {
+            self.batch.push(tenant, labels, entry);
+        }
+        Ok(())
+    }
+}
+
+#[async_trait::async_trait]
+impl Sink for Loki {
+    async fn on_event(
+        &mut self,
+        _input: &str,
+        codec: &mut dyn Codec,
+        _codec_map: &HashMap<String, Box<dyn Codec>>,
+        mut event: Event,
+    ) -> ResultVec {
+        if let Err(e) = self.add(codec, &event) {
+            error!("[Sink::Loki] Invalid event: {}", e);
+            return Ok(if event.transactional {
+                Some(vec![sink::Reply::Insight(event.to_fail())])
+            } else {
+                None
+            });
+        }
+        self.batch.insights.push(&event);
+        if self.batch.entries >= self.config.batch_size {
+            Ok(Some(self.flush().await))
+        } else {
+            Ok(None)
+        }
+    }
+
+    async fn on_signal(&mut self, signal: Event) -> ResultVec {
+        let interval = self.config.flush_interval * 1_000_000;
+        if self.batch.entries > 0 && signal.ingest_ns.saturating_sub(self.last_flush) >= interval {
+            Ok(Some(self.flush().await))
+        } else {
+            Ok(None)
+        }
+    }
+
+    #[allow(clippy::too_many_arguments)]
+    async fn init(
+        &mut self,
+        _sink_uid: u64,
+        _sink_url: &TremorUrl,
+        _codec: &dyn Codec,
+        _codec_map: &HashMap<String, Box<dyn Codec>>,
+        _processors: Processors<'_>,
+        _is_linked: bool,
+        _reply_channel: Sender<sink::Reply>,
+    ) -> Result<()> {
+        Ok(())
+    }
+
+    async fn terminate(&mut self) {
+        if self.batch.entries > 0 {
+            self.flush().await;
+        }
+    }
+
+    fn is_active(&self) -> bool {
+        true
+    }
+
+    fn auto_ack(&self) -> bool {
+        false
+    }
+
+    fn default_codec(&self) -> &str {
+        "json"
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    fn config() -> Result<Config> {
+        let config: OpConfig = serde_yaml::from_str(
+            r#"
+endpoint: "http://localhost:3100/"
+labels:
+  app: "app"
+  namespace: "kubernetes.namespace"
+static_labels:
+  env: "prod"
+"#,
+        )?;
+        Config::new(&config)
+    }
+
+    #[test]
+    fn stream_labels() -> Result<()> {
+        let config = config()?;
+        let value = literal!({
+            "app": "snot\"badger",
+            "kubernetes": {"namespace": "default"},
+            "message": "hello"
+        });
+        assert_eq!(
+            labels(&config, &value, &Value::object())?,
+            r#"{app="snot\"badger", env="prod", namespace="default"}"#
+        );
+        let meta = literal!({"loki": {"labels": {"env": "dev", "level": 3}}});
+        assert_eq!(
+            labels(&config, &literal!({}), &meta)?,
+            r#"{env="dev", level="3"}"#
+        );
+        let config = Config {
+            static_labels: HashMap::new(),
+            ..config
+        };
+        assert!(labels(&config, &literal!({}), &Value::object()).is_err());
+        Ok(())
+    }
+
+    #[test]
+    fn push_request() -> Result<()> {
+        let mut batch = Batch::default();
+        let entry = |line: &str| -> Result<Entry> {
+            Ok(Entry {
+                timestamp: Some(timestamp(1_633_000_000_123_456_789)?),
+                line: line.to_string(),
+            })
+        };
+        batch.push(None, r#"{app="snot"}"#.to_string(), entry("a")?);
+        batch.push(None, r#"{app="badger"}"#.to_string(), entry("b")?);
+        batch.push(None, r#"{app="snot"}"#.to_string(), entry("c")?);
+        assert_eq!(batch.entries, 3);
+
+        let streams = batch.streams.remove(&None).unwrap_or_default();
+        let body = snap::raw::Decoder::new().decompress_vec(&push_body(streams)?)?;
+        let request =
+            PushRequest::decode(body.as_slice()).map_err(|e| Error::from(e.to_string()))?;
+        assert_eq!(request.streams.len(), 2);
+        assert_eq!(request.streams[1].labels, r#"{app="snot"}"#);
+        assert_eq!(request.streams[1].entries, vec![entry("a")?, entry("c")?]);
+        assert_eq!(
+            request.streams[1].entries[0].timestamp,
+            Some(prost_types::Timestamp {
+                seconds: 1_633_000_000,
+                nanos: 123_456_789
+            })
+        );
+        Ok(())
+    }
+}
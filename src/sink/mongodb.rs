@@ -0,0 +1,497 @@
+// Copyright 2020-2021, The Tremor Team
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+//! # MongoDB Offramp
+//!
+//! Writes events as documents to MongoDB. The values of an event, e.g. of a
+//! batch, are written with one bulk write per collection and operation.
+//!
+//! The operation is `insert`, `upsert` (`$set` of the document on the
+//! matching document) or `replace`, set by `operation` or per event in
+//! `$mongodb.operation`. Upserts and replaces match the document with the
+//! filter in `$mongodb.filter`, or the fields of the document named in
+//! `filter_fields`. `$mongodb.collection` overrides the collection.
+//!
+//! Documents that fail to be written are sent to the `err` port with the
+//! error.
+//!
+//! ## Configuration
+//!
+//! See [Config](struct.Config.html) for details.
+
+#![cfg(not(tarpaulin_include))]
+
+use crate::sink::prelude::*;
+use halfbrown::HashMap;
+use mongodb::bson::{self, doc, Bson, Document};
+use mongodb::{Client, Database};
+use tremor_pipeline::{EventIdGenerator, EventOriginUri};
+
+#[derive(Deserialize, Serialize, Debug, Clone)]
+#[serde(untagged)]
+pub enum W {
+    Nodes(u32),
+    Tag(String),
+}
+
+/// Acknowledgement requested from the server, see
+/// <https://docs.mongodb.com/manual/reference/write-concern/>
+#[derive(Deserialize, Serialize, Debug, Clone)]
+pub struct WriteConcern {
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub w: Option<W>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub j: Option<bool>,
+    /// Timeout in milliseconds
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub wtimeout: Option<u64>,
+}
+
+#[derive(Deserialize, Debug, Clone, Copy, PartialEq)]
+#[serde(rename_all = "lowercase")]
+pub enum Operation {
+    Insert,
+    Upsert,
+    Replace,
+}
+
+impl Operation {
+    fn name(self) -> &'static str {
+        match self {
+            Self::Insert => "insert",
+            Self::Upsert => "upsert",
+            Self::Replace => "replace",
+        }
+    }
+
+    fn from_name(name: &str) -> Option<Self> {
+        match name {
+            "insert" => Some(Self::Insert),
+            "upsert" => Some(Self::Upsert),
+            "replace" => Some(Self::Replace),
+            _ => None,
+        }
+    }
+}
+
+#[derive(Deserialize, Debug)]
+pub struct Config {
+    /// Connection string, e.g. `mongodb://localhost:27017`
+    pub url: String,
+    pub database: String,
+    pub collection: String,
+    /// (default: `insert`)
+    #[serde(default = "dflt_operation")]
+    pub operation: Operation,
+    /// Fields of the document to match upserts and replaces on
+    /// (default: `["_id"]`)
+    #[serde(default = "dflt_filter_fields")]
+    pub filter_fields: Vec<String>,
+    pub write_concern: Option<WriteConcern>,
+    /// Stop a bulk write at the first failed document (default: false)
+    #[serde(default)]
+    pub ordered: bool,
+}
+
+fn dflt_operation() -> Operation {
+    Operation::Insert
+}
+
+fn dflt_filter_fields() -> Vec<String> {
+    vec!["_id".to_string()]
+}
+
+impl ConfigImpl for Config {}
+
+pub struct MongoDb {
+    config: Config,
+    database: Option<Database>,
+    origin_uri: EventOriginUri,
+    idgen: EventIdGenerator,
+    is_linked: bool,
+}
+
+impl offramp::Impl for MongoDb {
+    fn from_config(config: &Option<OpConfig>) -> Result<Box<dyn Offramp>> {
+        if let Some(config) = config {
+            let config: Config = Config::new(config)?;
+            let origin_uri = EventOriginUri {
+                uid: 0,
+                scheme: "tremor-mongodb".to_string(),
+                host: hostname(),
+                port: None,
+                path: vec![config.database.clone()],
+            };
+            Ok(SinkManager::new_box(Self {
+                config,
+                database: None,
+                origin_uri,
+                idgen: EventIdGenerator::new(0),
+                is_linked: false,
+            }))
+        } else {
+            Err("Missing config for mongodb offramp".into())
+        }
+    }
+}
+
+/// The statement of a document in an `insert` or `update` command
+fn statement(
+    operation: Operation,
+    value: &Value,
+    meta: &Value,
+    filter_fields: &[String],
+) -> Result<Document> {
+    let document = bson::to_document(value)?;
+    if operation == Operation::Insert {
+        return Ok(document);
+    }
+    let filter = if let Some(filter) = meta.get("mongodb").get("filter") {
+        bson::to_document(filter)?
+    } else {
+        let mut filter = Document::new();
+        for field in filter_fields {
+            let value = document
+                .get(field)
+                .ok_or_else(|| Error::from(format!("Missing filter field `{}`", field)))?;
+            filter.insert(field.clone(), value.clone());
+        }
+        filter
+    };
+    Ok(if operation == Operation::Upsert {
+        doc! { "q": filter, "u": { "$set": document }, "upsert": true }
+    } else {
+        doc! { "q": filter, "u": document, "upsert": false }
+    })
+}
+
+/// The documents of an event for one collection and operation
+struct BulkWrite<'event> {
+    collection: String,
+    operation: Operation,
+    statements: Vec<Document>,
+    /// the values and metadata of the statements
+    values: Vec<(&'event Value<'event>, &'event Value<'event>)>,
+}
+
+impl<'event> BulkWrite<'event> {
+    fn command(&self, config: &Config) -> Result<Document> {
+        let statements: Vec<Bson> = self
+            .statements
+            .iter()
+            .cloned()
+            .map(Bson::Document)
+            .collect();
+        let mut command = if self.operation == Operation::Insert {
+            doc! { "insert": self.collection.as_str(), "documents": statements }
+        } else {
+            doc! { "update": self.collection.as_str(), "updates": statements }
+        };
+        command.insert("ordered", config.ordered);
+        if let Some(write_concern) = &config.write_concern {
+            command.insert("writeConcern", bson::to_document(write_concern)?);
+        }
+        Ok(command)
+    }
+}
+
+/// The index and message of the failed statements of a command response
+fn write_errors(response: &Document) -> Vec<(usize, String)> {
+    let mut errors: Vec<(usize, String)> = response
+        .get_array("writeErrors")
+        .map(|errors| {
+            errors
+                .iter()
+                .filter_map(Bson::as_document)
+                .filter_map(|e| {
+                    let index = usize::try_from(e.get_i32("index").ok()?).ok()?;
+                    let message = e.get_str("errmsg").unwrap_or("write error").to_string();
+                    Some((index, message))
+                })
+                .collect()
+        })
+        .unwrap_or_default();
+    if let Ok(e) = response.get_document("writeConcernError") {
+        errors.push((
+            usize::MAX,
+            e.get_str("errmsg")
+                .unwrap_or("write concern error")
+                .to_string(),
+        ));
+    }
+    errors
+}
+
+impl MongoDb {
+    fn error_event(
+        &mut self,
+        event: &Event,
+        (collection, operation): (&str, Operation),
+        (value, meta): (&Value, &Value),
+        error: &str,
+    ) -> Event {
+        let mut id = self.idgen.next_id();
+        id.track(&event.id);
+        let data = literal!({
+            "error": error.to_string(),
+            "document": value.clone_static()
+        });
+        let mut err_meta = literal!({
+            "mongodb": {
+                "collection": collection.to_string(),
+                "operation": operation.name()
+            },
+            "error": error.to_string()
+        });
+        if let Some(correlation) = meta.get("correlation") {
+            err_meta.try_insert("correlation", correlation.clone_static());
+        }
+        Event {
+            id,
+            ingest_ns: event.ingest_ns,
+            data: (data, err_meta).into(),
+            origin_uri: Some(self.origin_uri.clone()),
+            ..Event::default()
+        }
+    }
+
+    fn response_event(&mut self, event: &Event, bulk: &BulkWrite, response: &Document) -> Event {
+        let mut id = self.idgen.next_id();
+        id.track(&event.id);
+        let data = literal!({
+            "collection": bulk.collection.clone(),
+            "operation": bulk.operation.name(),
+            "n": response.get_i32("n").unwrap_or_default(),
+            "modified": response.get_i32("nModified").unwrap_or_default()
+        });
+        let mut meta = Value::object();
+        if let Some(correlation) = event.correlation_meta() {
+            meta.try_insert("correlation", correlation);
+        }
+        Event {
+            id,
+            ingest_ns: event.ingest_ns,
+            data: (data, meta).into(),
+            origin_uri: Some(self.origin_uri.clone()),
+            ..Event::default()
+        }
+    }
+}
+
+#[async_trait::async_trait]
+impl Sink for MongoDb {
+    async fn on_event(
+        &mut self,
+        _input: &str,
+        _codec: &mut dyn Codec,
+        _codec_map: &HashMap<String, Box<dyn Codec>>,
+        mut event: Event,
+    ) -> ResultVec {
+        let database = if let Some(database) = &self.database {
+            database.clone()
+        } else {
+            return Ok(Some(vec![
+                sink::Reply::Insight(event.to_fail()),
+                sink::Reply::Insight(event.insight_trigger()),
+            ]));
+        };
+        let mut replies = Vec::new();
+        let mut failed = false;
+        let mut bulks: Vec<BulkWrite> = Vec::new();
+        for (value, meta) in event.value_meta_iter() {
+            let mongodb = meta.get("mongodb");
+            let collection = mongodb
+                .get_str("collection")
+                .unwrap_or(&self.config.collection)
+                .to_string();
+            let operation = mongodb
+                .get_str("operation")
+                .and_then(Operation::from_name)
+                .unwrap_or(self.config.operation);
+            let statement = match statement(operation, value, meta, &self.config.filter_fields) {
+                Ok(statement) => statement,
+                Err(e) => {
+                    let target = (collection.as_str(), operation);
+                    let e = self.error_event(&event, target, (value, meta), &e.to_string());
+                    replies.push(sink::Reply::Response(ERR, e));
+                    failed = true;
+                    continue;
+                }
+            };
+            let idx = bulks
+                .iter()
+                .position(|b| b.collection == collection && b.operation == operation)
+                .unwrap_or_else(|| {
+                    bulks.push(BulkWrite {
+                        collection,
+                        operation,
+                        statements: Vec::new(),
+                        values: Vec::new(),
+                    });
+                    bulks.len() - 1
+                });
+            // ALLOW: idx is an index of bulks
+            let bulk = &mut bulks[idx];
+            bulk.statements.push(statement);
+            bulk.values.push((value, meta));
+        }
+        for bulk in &bulks {
+            let target = (bulk.collection.as_str(), bulk.operation);
+            let response = database
+                .run_command(bulk.command(&self.config)?, None)
+                .await;
+            match response {
+                Ok(response) => {
+                    for (index, error) in write_errors(&response) {
+                        failed = true;
+                        let values = if index == usize::MAX {
+                            // a write concern error concerns all documents
+                            bulk.values.clone()
+                        } else {
+                            bulk.values.get(index).copied().into_iter().collect()
+                        };
+                        for value in values {
+                            let e = self.error_event(&event, target, value, &error);
+                            replies.push(sink::Reply::Response(ERR, e));
+                        }
+                    }
+                    if self.is_linked {
+                        let e = self.response_event(&event, bulk, &response);
+                        replies.push(sink::Reply::Response(OUT, e));
+                    }
+                }
+                Err(e) => {
+                    error!("[Sink::MongoDB] Bulk write failed: {}", e);
+                    failed = true;
+                    let error = e.to_string();
+                    for value in &bulk.values {
+                        let e = self.error_event(&event, target, *value, &error);
+                        replies.push(sink::Reply::Response(ERR, e));
+                    }
+                }
+            }
+        }
+        drop(bulks);
+        if event.transactional {
+            let insight = if failed {
+                event.insight_fail()
+            } else {
+                event.insight_ack()
+            };
+            replies.push(sink::Reply::Insight(insight));
+        }
+        Ok(Some(replies))
+    }
+
+    async fn on_signal(&mut self, signal: Event) -> ResultVec {
+        if self.database.is_none() {
+            let client = Client::with_uri_str(&self.config.url).await?;
+            let database = client.database(&self.config.database);
+            database.run_command(doc! { "ping": 1 }, None).await?;
+            self.database = Some(database);
+            Ok(Some(vec![sink::Reply::Insight(Event::cb_restore(
+                signal.ingest_ns,
+            ))]))
+        } else {
+            Ok(None)
+        }
+    }
+
+    #[allow(clippy::too_many_arguments)]
+    async fn init(
+        &mut self,
+        sink_uid: u64,
+        _sink_url: &TremorUrl,
+        _codec: &dyn Codec,
+        _codec_map: &HashMap<String, Box<dyn Codec>>,
+        _processors: Processors<'_>,
+        is_linked: bool,
+        _reply_channel: Sender<sink::Reply>,
+    ) -> Result<()> {
+        self.idgen = EventIdGenerator::new(sink_uid);
+        self.is_linked = is_linked;
+        let client = Client::with_uri_str(&self.config.url).await?;
+        self.database = Some(client.database(&self.config.database));
+        Ok(())
+    }
+
+    fn is_active(&self) -> bool {
+        self.database.is_some()
+    }
+
+    fn auto_ack(&self) -> bool {
+        false
+    }
+
+    fn default_codec(&self) -> &str {
+        "json"
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn statements() -> Result<()> {
+        let value = literal!({"_id": "snot", "count": 1});
+        let fields = vec!["_id".to_string()];
+        assert_eq!(
+            statement(Operation::Insert, &value, &Value::object(), &fields)?,
+            doc! { "_id": "snot", "count": 1_i64 }
+        );
+        assert_eq!(
+            statement(Operation::Upsert, &value, &Value::object(), &fields)?,
+            doc! {
+                "q": { "_id": "snot" },
+                "u": { "$set": { "_id": "snot", "count": 1_i64 } },
+                "upsert": true
+            }
+        );
+        let meta = literal!({"mongodb": {"filter": {"count": {"$lt": 2}}}});
+        assert_eq!(
+            statement(Operation::Replace, &value, &meta, &fields)?,
+            doc! {
+                "q": { "count": { "$lt": 2_i64 } },
+                "u": { "_id": "snot", "count": 1_i64 },
+                "upsert": false
+            }
+        );
+        assert!(statement(
+            Operation::Upsert,
+            &value,
+            &Value::object(),
+            &["id".to_string()]
+        )
+        .is_err());
+        Ok(())
+    }
+
+    #[test]
+    fn errors() {
+        let response = doc! {
+            "n": 1,
+            "writeErrors": [{ "index": 1, "code": 11000, "errmsg": "duplicate key" }],
+            "writeConcernError": { "code": 64, "errmsg": "waiting for replication timed out" }
+        };
+        assert_eq!(
+            write_errors(&response),
+            vec![
+                (1, "duplicate key".to_string()),
+                (usize::MAX, "waiting for replication timed out".to_string())
+            ]
+        );
+        assert!(write_errors(&doc! { "n": 2 }).is_empty());
+    }
+}
@@ -0,0 +1,331 @@
+// Copyright 2020-2021, The Tremor Team
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+//! # Object Store Sinks
+//!
+//! Shared implementation of the sinks writing events into rolling objects of
+//! an object store. Encoded events are written one per line into the current
+//! object, which is uploaded and replaced by a new one once it reaches
+//! `max_bytes` or `max_events`, or on the next signal once it is older than
+//! `max_age`. Events are acknowledged once their object was uploaded.
+//!
+//! Object keys are rendered from `key`, a template with the placeholders
+//! `{year}`, `{month}`, `{day}`, `{hour}`, `{minute}`, `{second}` of the time
+//! the object was opened in UTC, `{timestamp}` in nanoseconds, `{seq}` the
+//! number of the object since the start and `{hostname}`.
+//!
+//! A store only has to implement [`ObjectStore`].
+
+#![cfg(not(tarpaulin_include))]
+
+use crate::sink::prelude::*;
+use chrono::{Datelike, TimeZone, Timelike, Utc};
+use halfbrown::HashMap;
+use libflate::gzip;
+use std::io::Write;
+
+/// An object store the rolling objects are uploaded to
+#[async_trait::async_trait]
+pub(crate) trait ObjectStore: Send + Sync {
+    /// Name of the store in logs
+    fn name(&self) -> &str;
+
+    /// Uploads an object, replacing an existing object of the same key
+    async fn put(&self, key: &str, data: Vec<u8>, content_type: &str) -> Result<()>;
+}
+
+#[derive(Deserialize, Debug, Clone, Copy, PartialEq)]
+#[serde(rename_all = "lowercase")]
+pub enum Compression {
+    None,
+    Gzip,
+}
+
+impl Default for Compression {
+    fn default() -> Self {
+        Self::None
+    }
+}
+
+/// Configuration of the rolling objects, shared by all object store sinks
+#[derive(Deserialize, Debug, Clone)]
+pub struct RollConfig {
+    /// Template of the object keys, e.g. `logs/{year}/{month}/{day}/{timestamp}.json`
+    pub key: String,
+    /// Roll objects at this size in bytes before compression (default: 64MB)
+    #[serde(default = "dflt_max_bytes")]
+    pub max_bytes: usize,
+    /// Roll objects after this many events
+    pub max_events: Option<usize>,
+    /// Roll objects after this many milliseconds (default: 300000)
+    #[serde(default = "dflt_max_age")]
+    pub max_age: u64,
+    /// (default: `none`)
+    #[serde(default)]
+    pub compression: Compression,
+    /// Content type of the objects, `application/gzip` for compressed objects
+    /// (default: `application/octet-stream`)
+    #[serde(default = "dflt_content_type")]
+    pub content_type: String,
+}
+
+fn dflt_max_bytes() -> usize {
+    64 * 1024 * 1024
+}
+
+fn dflt_max_age() -> u64 {
+    300_000
+}
+
+fn dflt_content_type() -> String {
+    String::from("application/octet-stream")
+}
+
+impl RollConfig {
+    /// Checks the key template by rendering it once
+    pub(crate) fn validate(&self) -> Result<()> {
+        render_key(&self.key, nanotime(), 0, "")?;
+        Ok(())
+    }
+}
+
+/// Renders an object key from the template
+fn render_key(template: &str, ns: u64, seq: u64, host: &str) -> Result<String> {
+    let time = Utc.timestamp_nanos(i64::try_from(ns)?);
+    let mut key = String::with_capacity(template.len());
+    let mut rest = template;
+    while let Some(start) = rest.find('{') {
+        key.push_str(rest.get(..start).unwrap_or_default());
+        let after = rest.get(start + 1..).unwrap_or_default();
+        let end = after
+            .find('}')
+            .ok_or_else(|| Error::from(format!("Unclosed placeholder in key `{}`", template)))?;
+        let placeholder = after.get(..end).unwrap_or_default();
+        let value = match placeholder {
+            "year" => format!("{:04}", time.year()),
+            "month" => format!("{:02}", time.month()),
+            "day" => format!("{:02}", time.day()),
+            "hour" => format!("{:02}", time.hour()),
+            "minute" => format!("{:02}", time.minute()),
+            "second" => format!("{:02}", time.second()),
+            "timestamp" => ns.to_string(),
+            "seq" => seq.to_string(),
+            "hostname" => host.to_string(),
+            other => return Err(format!("Unknown placeholder `{{{}}}` in key", other).into()),
+        };
+        key.push_str(&value);
+        rest = after.get(end + 1..).unwrap_or_default();
+    }
+    key.push_str(rest);
+    Ok(key)
+}
+
+/// The object currently written to
+#[derive(Default)]
+struct Object {
+    data: Vec<u8>,
+    events: usize,
+    opened: u64,
+    insights: BatchInsights,
+}
+
+/// A sink writing rolling objects to a store
+pub(crate) struct ObjectStoreSink<S: ObjectStore> {
+    store: S,
+    config: RollConfig,
+    host: String,
+    seq: u64,
+    object: Object,
+}
+
+impl<S: ObjectStore + 'static> ObjectStoreSink<S> {
+    pub(crate) fn new_box(store: S, config: RollConfig) -> Result<Box<dyn Offramp>> {
+        config.validate()?;
+        Ok(SinkManager::new_box(Self {
+            store,
+            config,
+            host: hostname(),
+            seq: 0,
+            object: Object::default(),
+        }))
+    }
+
+    fn is_full(&self) -> bool {
+        self.object.data.len() >= self.config.max_bytes
+            || self
+                .config
+                .max_events
+                .map_or(false, |max| self.object.events >= max)
+    }
+
+    /// Uploads the current object and starts a new one
+    async fn roll(&mut self) -> Vec<Reply> {
+        let object = std::mem::take(&mut self.object);
+        let seq = self.seq;
+        self.seq += 1;
+        let res = match self.encode(object.data, object.opened, seq) {
+            Ok((key, data, content_type)) => {
+                let res = self.store.put(&key, data, content_type).await;
+                if res.is_ok() {
+                    debug!(
+                        "[Sink::{}] Wrote {} events to {}",
+                        self.store.name(),
+                        object.events,
+                        key
+                    );
+                }
+                res
+            }
+            Err(e) => Err(e),
+        };
+        if let Err(e) = &res {
+            error!(
+                "[Sink::{}] Failed to write {} events: {}",
+                self.store.name(),
+                object.events,
+                e
+            );
+        }
+        object.insights.replies(res.is_ok())
+    }
+
+    /// The key, data and content type of an object
+    fn encode(&self, data: Vec<u8>, opened: u64, seq: u64) -> Result<(String, Vec<u8>, &str)> {
+        let key = render_key(&self.config.key, opened, seq, &self.host)?;
+        match self.config.compression {
+            Compression::None => Ok((key, data, self.config.content_type.as_str())),
+            Compression::Gzip => {
+                let mut encoder = gzip::Encoder::new(Vec::with_capacity(data.len() / 4))?;
+                encoder.write_all(&data)?;
+                Ok((key, encoder.finish().into_result()?, "application/gzip"))
+            }
+        }
+    }
+}
+
+#[async_trait::async_trait]
+impl<S: ObjectStore + 'static> Sink for ObjectStoreSink<S> {
+    async fn on_event(
+        &mut self,
+        _input: &str,
+        codec: &mut dyn Codec,
+        _codec_map: &HashMap<String, Box<dyn Codec>>,
+        mut event: Event,
+    ) -> ResultVec {
+        let mut lines = Vec::new();
+        for value in event.value_iter() {
+            match codec.encode(value) {
+                Ok(mut line) => {
+                    line.push(b'\n');
+                    lines.push(line);
+                }
+                Err(e) => {
+                    error!(
+                        "[Sink::{}] Failed to encode event: {}",
+                        self.store.name(),
+                        e
+                    );
+                    return Ok(if event.transactional {
+                        Some(vec![sink::Reply::Insight(event.to_fail())])
+                    } else {
+                        None
+                    });
+                }
+            }
+        }
+        if self.object.events == 0 {
+            self.object.opened = event.ingest_ns;
+        }
+        for line in lines {
+            self.object.data.extend_from_slice(&line);
+            self.object.events += 1;
+        }
+        self.object.insights.push(&event);
+        if self.is_full() {
+            Ok(Some(self.roll().await))
+        } else {
+            Ok(None)
+        }
+    }
+
+    async fn on_signal(&mut self, signal: Event) -> ResultVec {
+        let max_age = self.config.max_age * 1_000_000;
+        if self.object.events > 0 && signal.ingest_ns.saturating_sub(self.object.opened) >= max_age
+        {
+            Ok(Some(self.roll().await))
+        } else {
+            Ok(None)
+        }
+    }
+
+    #[allow(clippy::too_many_arguments)]
+    async fn init(
+        &mut self,
+        _sink_uid: u64,
+        _sink_url: &TremorUrl,
+        _codec: &dyn Codec,
+        _codec_map: &HashMap<String, Box<dyn Codec>>,
+        _processors: Processors<'_>,
+        _is_linked: bool,
+        _reply_channel: Sender<sink::Reply>,
+    ) -> Result<()> {
+        Ok(())
+    }
+
+    async fn terminate(&mut self) {
+        if self.object.events > 0 {
+            self.roll().await;
+        }
+    }
+
+    fn is_active(&self) -> bool {
+        true
+    }
+
+    fn auto_ack(&self) -> bool {
+        false
+    }
+
+    fn default_codec(&self) -> &str {
+        "json"
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn keys() -> Result<()> {
+        // 2021-10-01T12:34:56.789Z
+        let ns = 1_633_091_696_789_000_000;
+        assert_eq!(
+            render_key(
+                "logs/{year}/{month}/{day}/{hour}{minute}{second}-{seq}.json",
+                ns,
+                7,
+                ""
+            )?,
+            "logs/2021/10/01/123456-7.json"
+        );
+        assert_eq!(
+            render_key("{hostname}/{timestamp}", ns, 0, "snot")?,
+            "snot/1633091696789000000"
+        );
+        assert_eq!(render_key("static", ns, 0, "")?, "static");
+        assert!(render_key("{badger}", ns, 0, "").is_err());
+        assert!(render_key("{year", ns, 0, "").is_err());
+        Ok(())
+    }
+}
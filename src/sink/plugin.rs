@@ -0,0 +1,128 @@
+// Copyright 2020-2021, The Tremor Team
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+#![cfg(not(tarpaulin_include))]
+
+//! Offramps provided by sidecar plugins, see `connectors::plugin` for the protocol.
+
+use crate::connectors::plugin::{Kind, Manifest, Sidecar};
+use crate::sink::prelude::*;
+use halfbrown::HashMap;
+
+pub struct Plugin {
+    manifest: Manifest,
+    config: Option<OpConfig>,
+    sidecar: Option<Sidecar>,
+    postprocessors: Postprocessors,
+}
+
+impl Plugin {
+    pub(crate) fn from_manifest(
+        manifest: Manifest,
+        config: &Option<OpConfig>,
+    ) -> Result<Box<dyn Offramp>> {
+        Ok(SinkManager::new_box(Self {
+            manifest,
+            config: config.clone(),
+            sidecar: None,
+            postprocessors: vec![],
+        }))
+    }
+
+    async fn deliver(&mut self, codec: &mut dyn Codec, event: &Event) -> Result<()> {
+        let sidecar = self
+            .sidecar
+            .as_mut()
+            .ok_or_else(|| Error::from(format!("Plugin {} isn't started", self.manifest.name)))?;
+        for (value, meta) in event.value_meta_iter() {
+            let raw = codec.encode(value)?;
+            for processed in postprocess(&mut self.postprocessors, event.ingest_ns, raw)? {
+                let msg = literal!({
+                    "data": base64::encode(&processed),
+                    "meta": meta.clone_static(),
+                });
+                sidecar.request(&msg).await?;
+            }
+        }
+        Ok(())
+    }
+}
+
+#[async_trait::async_trait]
+impl Sink for Plugin {
+    async fn on_event(
+        &mut self,
+        _input: &str,
+        codec: &mut dyn Codec,
+        _codec_map: &HashMap<String, Box<dyn Codec>>,
+        mut event: Event,
+    ) -> ResultVec {
+        let delivered = self.deliver(codec, &event).await;
+        if let Err(e) = &delivered {
+            error!(
+                "[Sink::{}] Failed to deliver event: {}",
+                self.manifest.name, e
+            );
+        }
+        Ok(if event.transactional {
+            let insight = if delivered.is_ok() {
+                event.insight_ack()
+            } else {
+                event.insight_fail()
+            };
+            Some(vec![sink::Reply::Insight(insight)])
+        } else {
+            None
+        })
+    }
+
+    #[allow(clippy::too_many_arguments)]
+    async fn init(
+        &mut self,
+        _sink_uid: u64,
+        sink_url: &TremorUrl,
+        _codec: &dyn Codec,
+        _codec_map: &HashMap<String, Box<dyn Codec>>,
+        processors: Processors<'_>,
+        _is_linked: bool,
+        _reply_channel: Sender<sink::Reply>,
+    ) -> Result<()> {
+        self.postprocessors = make_postprocessors(processors.post)?;
+        let sidecar = Sidecar::start(
+            &self.manifest,
+            Kind::Offramp,
+            &sink_url.to_string(),
+            &self.config,
+        )
+        .await?;
+        self.sidecar = Some(sidecar);
+        Ok(())
+    }
+
+    fn default_codec(&self) -> &str {
+        &self.manifest.codec
+    }
+
+    async fn on_signal(&mut self, _signal: Event) -> ResultVec {
+        Ok(None)
+    }
+
+    fn is_active(&self) -> bool {
+        self.sidecar.is_some()
+    }
+
+    fn auto_ack(&self) -> bool {
+        false
+    }
+}
@@ -0,0 +1,296 @@
+// Copyright 2020-2021, The Tremor Team
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+#![cfg(not(tarpaulin_include))]
+
+//! # QUIC Offramp (experimental)
+//!
+//! Sends each event on its own unidirectional stream of a QUIC connection,
+//! or in `http3` mode as the body of an HTTP/3 `POST` request to `path`.
+//! Reconnects resume the previous session and, if `zero_rtt` is set, send
+//! 0-RTT data.
+//!
+//! ## Configuration
+//!
+//! See [Config](struct.Config.html) for details.
+
+use crate::connectors::quic;
+use crate::connectors::tls::TlsConfig;
+use crate::sink::prelude::*;
+use async_std::net::ToSocketAddrs;
+use bytes::Bytes;
+use halfbrown::HashMap;
+use std::net::SocketAddr;
+use std::time::Instant;
+
+#[derive(Deserialize, Debug)]
+pub struct Config {
+    /// Host to connect to
+    host: String,
+    port: u16,
+    /// TLS, the webpki roots are used to verify the server if not set
+    #[serde(default)]
+    tls: TlsConfig,
+    /// `raw` streams or `http3` requests (default: raw)
+    #[serde(default)]
+    mode: quic::Mode,
+    /// Path of the HTTP/3 requests (default: /)
+    #[serde(default = "default_path")]
+    path: String,
+    /// Send data before the handshake completes when resuming a session, it
+    /// can be replayed by an attacker (default: false)
+    #[serde(default)]
+    zero_rtt: bool,
+}
+
+fn default_path() -> String {
+    "/".to_string()
+}
+
+impl ConfigImpl for Config {}
+
+/// An offramp that writes to a QUIC server
+pub struct Quic {
+    config: Config,
+    /// kept across reconnects, it stores the session tickets
+    client_config: quinn::ClientConfig,
+    endpoint: Option<quinn::Endpoint>,
+    connection: Option<quinn::Connection>,
+    /// sends the HTTP/3 requests of the connection in `http3` mode
+    requests: Option<h3::client::SendRequest<h3_quinn::OpenStreams, Bytes>>,
+    postprocessors: Postprocessors,
+}
+
+impl offramp::Impl for Quic {
+    fn from_config(config: &Option<OpConfig>) -> Result<Box<dyn Offramp>> {
+        if let Some(config) = config {
+            let config: Config = Config::new(config)?;
+            let client_config = quic::client_config(&config.tls, config.mode, config.zero_rtt)?;
+            Ok(SinkManager::new_box(Self {
+                config,
+                client_config,
+                endpoint: None,
+                connection: None,
+                requests: None,
+                postprocessors: vec![],
+            }))
+        } else {
+            Err("QUIC offramp requires a config".into())
+        }
+    }
+}
+
+impl Quic {
+    async fn connect(&mut self) -> Result<()> {
+        let addr = (self.config.host.as_str(), self.config.port)
+            .to_socket_addrs()
+            .await?
+            .next()
+            .ok_or_else(|| Error::from(format!("Can't resolve {}", self.config.host)))?;
+        let endpoint = if let Some(endpoint) = &self.endpoint {
+            endpoint.clone()
+        } else {
+            let local: SocketAddr = if addr.is_ipv4() {
+                ([0, 0, 0, 0], 0).into()
+            } else {
+                ([0; 16], 0).into()
+            };
+            let (endpoint, _incoming) = quinn::Endpoint::builder().bind(&local)?;
+            self.endpoint = Some(endpoint.clone());
+            endpoint
+        };
+        let connecting = endpoint.connect_with(
+            self.client_config.clone(),
+            &addr,
+            self.config.tls.domain(&self.config.host),
+        )?;
+        let connection = if self.config.zero_rtt {
+            match connecting.into_0rtt() {
+                Ok((connection, _accepted)) => {
+                    debug!("[Sink::QUIC] Resumed the session with 0-RTT");
+                    connection
+                }
+                Err(connecting) => connecting.await?,
+            }
+        } else {
+            connecting.await?
+        };
+        info!("[Sink::QUIC] Connected to {}", addr);
+        let handle = connection.connection.clone();
+        if self.config.mode == quic::Mode::Http3 {
+            let (mut driver, requests) =
+                h3::client::new(h3_quinn::Connection::new(connection)).await?;
+            // the driver handles the control streams of the connection
+            task::spawn(async move {
+                if let Err(e) = futures::future::poll_fn(|cx| driver.poll_close(cx)).await {
+                    debug!("[Sink::QUIC] HTTP/3 connection closed: {}", e);
+                }
+            });
+            self.requests = Some(requests);
+        }
+        self.connection = Some(handle);
+        Ok(())
+    }
+
+    /// serialize event and send it on a new stream
+    async fn send_event(&mut self, codec: &mut dyn Codec, event: &Event) -> Result<()> {
+        let connection = self
+            .connection
+            .as_ref()
+            .ok_or_else(|| Error::from(ErrorKind::NoSocket))?;
+        let mut data = Vec::new();
+        for value in event.value_iter() {
+            let raw = codec.encode(value)?;
+            data.extend(postprocess(&mut self.postprocessors, event.ingest_ns, raw)?);
+        }
+        if let Some(requests) = &mut self.requests {
+            let uri = format!(
+                "https://{}:{}{}",
+                self.config.host, self.config.port, self.config.path
+            );
+            return post(requests, &uri, data).await;
+        }
+        match send(connection, &data).await {
+            // the server rejected the 0-RTT data, the handshake is complete now
+            Err(Error(ErrorKind::QuicWriteError(quinn::WriteError::ZeroRttRejected), _)) => {
+                send(connection, &data).await
+            }
+            res => res,
+        }
+    }
+}
+
+async fn send(connection: &quinn::Connection, data: &[Vec<u8>]) -> Result<()> {
+    let mut stream = connection.open_uni().await?;
+    for chunk in data {
+        stream.write_all(chunk).await?;
+    }
+    stream.finish().await?;
+    Ok(())
+}
+
+/// Sends the data as the body of a HTTP/3 `POST` request
+async fn post(
+    requests: &mut h3::client::SendRequest<h3_quinn::OpenStreams, Bytes>,
+    uri: &str,
+    data: Vec<Vec<u8>>,
+) -> Result<()> {
+    let request = http::Request::post(uri).body(())?;
+    let mut stream = requests.send_request(request).await?;
+    for chunk in data {
+        stream.send_data(Bytes::from(chunk)).await?;
+    }
+    stream.finish().await?;
+    let response = stream.recv_response().await?;
+    if response.status().is_success() {
+        Ok(())
+    } else {
+        Err(format!("HTTP/3 request failed with status {}", response.status()).into())
+    }
+}
+
+/// Errors after which the connection is re-established
+fn lost_connection(e: &Error) -> bool {
+    matches!(
+        e.0,
+        ErrorKind::NoSocket
+            | ErrorKind::QuicConnectionError(_)
+            | ErrorKind::QuicWriteError(_)
+            | ErrorKind::H3Error(_)
+    )
+}
+
+#[async_trait::async_trait]
+impl Sink for Quic {
+    #[allow(clippy::cast_possible_truncation)]
+    async fn on_event(
+        &mut self,
+        _input: &str,
+        codec: &mut dyn Codec,
+        _codec_map: &HashMap<String, Box<dyn Codec>>,
+        event: Event,
+    ) -> ResultVec {
+        let processing_start = Instant::now();
+        let replies = match self.send_event(codec, &event).await {
+            Ok(()) => {
+                if event.transactional {
+                    Some(vec![sink::Reply::Insight(event.insight_ack_with_timing(
+                        processing_start.elapsed().as_millis() as u64,
+                    ))])
+                } else {
+                    None
+                }
+            }
+            // the connection is lost, trigger the CB until we reconnected
+            Err(e) if lost_connection(&e) => {
+                error!("[Sink::QUIC] Error sending event: {}.", e);
+                self.connection = None;
+                self.requests = None;
+                if event.transactional {
+                    Some(vec![
+                        sink::Reply::Insight(event.to_fail()),
+                        sink::Reply::Insight(event.insight_trigger()),
+                    ])
+                } else {
+                    Some(vec![sink::Reply::Insight(event.insight_trigger())])
+                }
+            }
+            Err(e) => {
+                error!("[Sink::QUIC] Error sending event: {}", e);
+                if event.transactional {
+                    Some(vec![sink::Reply::Insight(event.to_fail())])
+                } else {
+                    None
+                }
+            }
+        };
+        Ok(replies)
+    }
+    fn default_codec(&self) -> &str {
+        "json"
+    }
+    #[allow(clippy::too_many_arguments)]
+    async fn init(
+        &mut self,
+        _sink_uid: u64,
+        _sink_url: &TremorUrl,
+        _codec: &dyn Codec,
+        _codec_map: &HashMap<String, Box<dyn Codec>>,
+        processors: Processors<'_>,
+        _is_linked: bool,
+        _reply_channel: Sender<sink::Reply>,
+    ) -> Result<()> {
+        self.postprocessors = make_postprocessors(processors.post)?;
+        if let Err(e) = self.connect().await {
+            warn!("[Sink::QUIC] Failed to connect: {}", e);
+        }
+        Ok(())
+    }
+    async fn on_signal(&mut self, signal: Event) -> ResultVec {
+        if self.connection.is_none() {
+            self.connect().await?;
+            Ok(Some(vec![sink::Reply::Insight(Event::cb_restore(
+                signal.ingest_ns,
+            ))]))
+        } else {
+            Ok(None)
+        }
+    }
+    fn is_active(&self) -> bool {
+        self.connection.is_some()
+    }
+    fn auto_ack(&self) -> bool {
+        false
+    }
+}
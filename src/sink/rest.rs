@@ -15,6 +15,9 @@
 #![cfg(not(tarpaulin_include))]
 
 use crate::codec::Codec;
+use crate::connectors::discovery::{Discovery, DiscoveryClient, Resolution};
+use crate::connectors::proxy::{self, Proxy};
+use crate::connectors::tls::TlsConfig;
 use crate::errors::ErrorKind;
 use crate::sink::prelude::*;
 use async_channel::{bounded, Receiver, Sender};
@@ -25,6 +28,7 @@ use http_types::{headers::HeaderValue, Method};
 use serde::de::{self, MapAccess, Visitor};
 use serde::{Deserialize, Deserializer};
 use std::borrow::Borrow;
+use std::convert::TryFrom;
 use std::fmt;
 use std::marker::PhantomData;
 use std::str::FromStr;
@@ -253,6 +257,18 @@ pub struct Config {
 
     #[serde(default)]
     pub headers: HashMap<String, String>,
+
+    /// TLS for `https` endpoints, e.g. to present a client certificate
+    #[serde(default)]
+    pub tls: Option<TlsConfig>,
+
+    /// proxy for requests, overrides the default proxy
+    #[serde(default)]
+    pub proxy: Option<Proxy>,
+
+    /// re-resolve the endpoint host periodically and balance requests across its addresses
+    #[serde(default)]
+    pub resolution: Option<Resolution>,
 }
 
 fn dflt_concurrency() -> usize {
@@ -305,7 +321,33 @@ impl offramp::Impl for Rest {
         if let Some(config) = config {
             let config: Config = Config::new(config)?;
             let num_inflight_requests = Arc::new(AtomicMaxCounter::new(config.concurrency));
-            let client = surf::client();
+            let tls_config = if let Some(tls) = &config.tls {
+                tls.unsupported("rest", &["domain"])?;
+                Some(Arc::new(tls.client_config()?))
+            } else {
+                None
+            };
+            let proxy = proxy::resolve(config.proxy.as_ref())?;
+            let client = if let Some(resolution) = &config.resolution {
+                if proxy.is_some() {
+                    return Err("Rest offramp does not support a proxy with a resolution".into());
+                }
+                let url = config.endpoint.as_url()?;
+                let host = url.host_str().ok_or_else(|| {
+                    Error::from("Rest offramp resolution requires an endpoint host")
+                })?;
+                let port = url.port_or_known_default().unwrap_or(80);
+                let discovery = Discovery::start(host, port, resolution);
+                Client::with_http_client(DiscoveryClient::new(discovery, tls_config))
+            } else if let Some(proxy) = proxy {
+                Client::with_http_client(proxy::TunnelClient::new(proxy, tls_config))
+            } else if let Some(tls_config) = tls_config {
+                let http_config = surf::Config::new().set_tls_config(Some(tls_config));
+                Client::try_from(http_config)
+                    .map_err(|e| Error::from(format!("Failed to create http client: {}", e)))?
+            } else {
+                surf::client()
+            };
             Ok(SinkManager::new_box(Self {
                 uid: 0,
                 sink_url: TremorUrl::from_offramp_id("rest")?, // dummy
@@ -0,0 +1,389 @@
+// Copyright 2020-2021, The Tremor Team
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+//! # Splunk Offramp
+//!
+//! Sends events to the Splunk HTTP Event Collector. Events are sent in
+//! batches of `batch_size`, or on the next signal once `flush_interval`
+//! elapsed. `index`, `sourcetype`, `source`, `host`, `time` in seconds and
+//! indexed `fields` can be set per event in `$splunk` and default to the
+//! config.
+//!
+//! With `ack` the indexer acknowledgement of the channel is polled on signals
+//! and events are only acknowledged once Splunk indexed them, batches that
+//! aren't indexed within `ack_timeout` are failed.
+//!
+//! ## Configuration
+//!
+//! See [Config](struct.Config.html) for details.
+
+#![cfg(not(tarpaulin_include))]
+
+use crate::sink::prelude::*;
+use halfbrown::HashMap;
+use http_types::headers::{AUTHORIZATION, CONTENT_TYPE};
+
+#[derive(Deserialize, Debug)]
+pub struct Config {
+    /// Base url of the event collector, e.g. `https://splunk:8088`
+    pub endpoint: String,
+    /// HEC token
+    pub token: String,
+    pub index: Option<String>,
+    pub sourcetype: Option<String>,
+    pub source: Option<String>,
+    /// (default: the hostname)
+    pub host: Option<String>,
+    /// Events per request (default: 100)
+    #[serde(default = "dflt_batch_size")]
+    pub batch_size: usize,
+    /// Milliseconds after which a partial batch is sent (default: 1000)
+    #[serde(default = "dflt_flush_interval")]
+    pub flush_interval: u64,
+    /// Wait for indexer acknowledgement (default: false)
+    #[serde(default)]
+    pub ack: bool,
+    /// Channel of the requests, a random one is used if not set
+    pub channel: Option<String>,
+    /// Milliseconds to wait for the acknowledgement of a batch (default: 60000)
+    #[serde(default = "dflt_ack_timeout")]
+    pub ack_timeout: u64,
+}
+
+fn dflt_batch_size() -> usize {
+    100
+}
+
+fn dflt_flush_interval() -> u64 {
+    1000
+}
+
+fn dflt_ack_timeout() -> u64 {
+    60_000
+}
+
+impl ConfigImpl for Config {}
+
+/// A random channel id in GUID format
+fn channel() -> String {
+    let id = format!("{:032x}", rand::random::<u128>());
+    // ALLOW: the id has 32 characters
+    let (a, b, c, d, e) = (&id[..8], &id[8..12], &id[12..16], &id[16..20], &id[20..]);
+    format!("{}-{}-{}-{}-{}", a, b, c, d, e)
+}
+
+fn meta_str<'a>(meta: &'a Value, key: &str) -> Option<&'a str> {
+    meta.get_object("splunk")
+        .and_then(|s| s.get(key))
+        .and_then(ValueAccess::as_str)
+}
+
+/// An event in the HEC format
+fn hec_event(config: &Config, host: &str, ingest_ns: u64, value: &Value, meta: &Value) -> String {
+    let splunk = meta.get_object("splunk");
+    #[allow(clippy::cast_precision_loss)]
+    let time = splunk
+        .and_then(|s| s.get("time"))
+        .and_then(ValueAccess::cast_f64)
+        .unwrap_or(ingest_ns as f64 / 1_000_000_000.0);
+    let mut event = Value::object_with_capacity(7);
+    let mut set = |key: &'static str, v: Value<'static>| {
+        if let Some(event) = event.as_object_mut() {
+            event.insert(key.into(), v);
+        }
+    };
+    set("time", Value::from(time));
+    set(
+        "host",
+        Value::from(meta_str(meta, "host").unwrap_or(host).to_string()),
+    );
+    for (key, dflt) in [
+        ("index", &config.index),
+        ("sourcetype", &config.sourcetype),
+        ("source", &config.source),
+    ] {
+        if let Some(v) = meta_str(meta, key)
+            .map(ToString::to_string)
+            .or_else(|| dflt.clone())
+        {
+            set(key, Value::from(v));
+        }
+    }
+    if let Some(fields) = splunk.and_then(|s| s.get("fields")) {
+        set("fields", fields.clone_static());
+    }
+    set("event", value.clone_static());
+    event.encode()
+}
+
+/// A batch waiting for its indexer acknowledgement
+struct Pending {
+    ack_id: u64,
+    sent: u64,
+    insights: BatchInsights,
+}
+
+pub struct Splunk {
+    config: Config,
+    host: String,
+    channel: String,
+    /// newline separated events of the batch
+    body: String,
+    events: usize,
+    insights: BatchInsights,
+    pending: Vec<Pending>,
+    last_flush: u64,
+}
+
+impl offramp::Impl for Splunk {
+    fn from_config(config: &Option<OpConfig>) -> Result<Box<dyn Offramp>> {
+        if let Some(config) = config {
+            let config: Config = Config::new(config)?;
+            let host = config.host.clone().unwrap_or_else(hostname);
+            let channel = config.channel.clone().unwrap_or_else(channel);
+            Ok(SinkManager::new_box(Self {
+                config,
+                host,
+                channel,
+                body: String::new(),
+                events: 0,
+                insights: BatchInsights::default(),
+                pending: Vec::new(),
+                last_flush: nanotime(),
+            }))
+        } else {
+            Err("Missing config for splunk offramp".into())
+        }
+    }
+}
+
+/// The acknowledged ids of an ack response, e.g. `{"acks": {"1": true}}`
+fn acked(response: &Value) -> Vec<u64> {
+    let mut ids: Vec<u64> = response
+        .get_object("acks")
+        .into_iter()
+        .flatten()
+        .filter(|(_, acked)| acked.as_bool() == Some(true))
+        .filter_map(|(id, _)| id.parse().ok())
+        .collect();
+    ids.sort_unstable();
+    ids
+}
+
+impl Splunk {
+    fn url(&self, path: &str) -> String {
+        format!(
+            "{}/services/collector/{}",
+            self.config.endpoint.trim_end_matches('/'),
+            path
+        )
+    }
+
+    async fn post(&self, path: &str, body: String) -> Result<Value<'static>> {
+        let mut response = surf::post(self.url(path))
+            .header(AUTHORIZATION, format!("Splunk {}", self.config.token))
+            .header("X-Splunk-Request-Channel", self.channel.as_str())
+            .header(CONTENT_TYPE, "application/json")
+            .body(body)
+            .await?;
+        let mut body = response.body_bytes().await?;
+        if !response.status().is_success() {
+            return Err(format!(
+                "Splunk responded with {}: {}",
+                response.status(),
+                String::from_utf8_lossy(&body)
+            )
+            .into());
+        }
+        Ok(tremor_value::parse_to_value(&mut body)
+            .map_err(|e| Error::from(e.to_string()))?
+            .into_static())
+    }
+
+    async fn flush(&mut self) -> Vec<Reply> {
+        let body = std::mem::take(&mut self.body);
+        let insights = std::mem::take(&mut self.insights);
+        self.events = 0;
+        self.last_flush = nanotime();
+        match self.post("event", body).await {
+            Ok(response) if self.config.ack => {
+                if let Some(ack_id) = response.get_u64("ackId") {
+                    self.pending.push(Pending {
+                        ack_id,
+                        sent: nanotime(),
+                        insights,
+                    });
+                    return vec![];
+                }
+                error!("[Sink::Splunk] Indexer acknowledgement isn't enabled for the token");
+                insights.replies(false)
+            }
+            Ok(_) => insights.replies(true),
+            Err(e) => {
+                error!("[Sink::Splunk] Failed to send events: {}", e);
+                insights.replies(false)
+            }
+        }
+    }
+
+    /// Polls the acknowledgements of the pending batches
+    async fn poll_acks(&mut self, now: u64) -> Vec<Reply> {
+        let ids: Vec<Value> = self.pending.iter().map(|p| Value::from(p.ack_id)).collect();
+        let acked = match self.post("ack", literal!({ "acks": ids }).encode()).await {
+            Ok(response) => acked(&response),
+            Err(e) => {
+                warn!("[Sink::Splunk] Failed to poll acknowledgements: {}", e);
+                vec![]
+            }
+        };
+        let timeout = self.config.ack_timeout * 1_000_000;
+        let mut replies = Vec::new();
+        let mut pending = Vec::with_capacity(self.pending.len());
+        for p in self.pending.drain(..) {
+            if acked.contains(&p.ack_id) {
+                replies.extend(p.insights.replies(true));
+            } else if now.saturating_sub(p.sent) >= timeout {
+                warn!("[Sink::Splunk] Batch {} wasn't indexed in time", p.ack_id);
+                replies.extend(p.insights.replies(false));
+            } else {
+                pending.push(p);
+            }
+        }
+        self.pending = pending;
+        replies
+    }
+}
+
+#[async_trait::async_trait]
+impl Sink for Splunk {
+    async fn on_event(
+        &mut self,
+        _input: &str,
+        _codec: &mut dyn Codec,
+        _codec_map: &HashMap<String, Box<dyn Codec>>,
+        mut event: Event,
+    ) -> ResultVec {
+        for (value, meta) in event.value_meta_iter() {
+            let hec = hec_event(&self.config, &self.host, event.ingest_ns, value, meta);
+            self.body.push_str(&hec);
+            self.body.push('\n');
+            self.events += 1;
+        }
+        self.insights.push(&event);
+        if self.events >= self.config.batch_size {
+            Ok(Some(self.flush().await))
+        } else {
+            Ok(None)
+        }
+    }
+
+    async fn on_signal(&mut self, signal: Event) -> ResultVec {
+        let mut replies = Vec::new();
+        let interval = self.config.flush_interval * 1_000_000;
+        if self.events > 0 && signal.ingest_ns.saturating_sub(self.last_flush) >= interval {
+            replies.extend(self.flush().await);
+        }
+        if !self.pending.is_empty() {
+            replies.extend(self.poll_acks(signal.ingest_ns).await);
+        }
+        Ok(if replies.is_empty() {
+            None
+        } else {
+            Some(replies)
+        })
+    }
+
+    #[allow(clippy::too_many_arguments)]
+    async fn init(
+        &mut self,
+        _sink_uid: u64,
+        _sink_url: &TremorUrl,
+        _codec: &dyn Codec,
+        _codec_map: &HashMap<String, Box<dyn Codec>>,
+        _processors: Processors<'_>,
+        _is_linked: bool,
+        _reply_channel: Sender<sink::Reply>,
+    ) -> Result<()> {
+        Ok(())
+    }
+
+    async fn terminate(&mut self) {
+        if self.events > 0 {
+            self.flush().await;
+        }
+    }
+
+    fn is_active(&self) -> bool {
+        true
+    }
+
+    fn auto_ack(&self) -> bool {
+        false
+    }
+
+    fn default_codec(&self) -> &str {
+        "json"
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn events() -> Result<()> {
+        let config: OpConfig = serde_yaml::from_str(
+            r#"
+endpoint: "https://localhost:8088"
+token: "snot"
+index: "main"
+sourcetype: "_json"
+"#,
+        )?;
+        let config = Config::new(&config)?;
+        let mut hec = hec_event(
+            &config,
+            "badger",
+            1_500_000_000,
+            &literal!({"msg": "hello"}),
+            &literal!({"splunk": {"index": "security", "fields": {"env": "prod"}}}),
+        )
+        .into_bytes();
+        let hec = tremor_value::parse_to_value(&mut hec).map_err(|e| Error::from(e.to_string()))?;
+        assert_eq!(
+            hec,
+            literal!({
+                "time": 1.5,
+                "host": "badger",
+                "index": "security",
+                "sourcetype": "_json",
+                "fields": {"env": "prod"},
+                "event": {"msg": "hello"}
+            })
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn acks() {
+        assert_eq!(
+            acked(&literal!({"acks": {"1": true, "2": false, "3": true}})),
+            vec![1, 3]
+        );
+        assert!(acked(&literal!({"text": "Invalid"})).is_empty());
+        let channel = channel();
+        assert_eq!(channel.len(), 36);
+        assert_eq!(channel.matches('-').count(), 4);
+    }
+}
@@ -23,6 +23,7 @@
 //!
 //! This operator takes no configuration
 
+use crate::sink::console::{self, Mode};
 use crate::sink::prelude::*;
 use async_std::io;
 use halfbrown::HashMap;
@@ -34,9 +35,19 @@ pub struct StdErr {
 }
 #[derive(Clone, Debug, Deserialize, Default)]
 struct Config {
+    /// prefix of every event, `{ingest_ns}`, `{input}` and `{$meta.path}`
+    /// are replaced
     #[serde(default = "Default::default")]
     prefix: String,
 
+    /// `codec`, `pretty` or `colorized` JSON (default: `codec`)
+    #[serde(default = "Default::default")]
+    mode: Mode,
+
+    /// print events as `{"data": ..., "meta": ...}`
+    #[serde(default = "Default::default")]
+    include_meta: bool,
+
     /// write data to stderr as raw bytes, not in debug formatting
     #[serde(default = "Default::default")]
     raw: bool,
@@ -65,16 +76,35 @@ impl offramp::Impl for StdErr {
 impl Sink for StdErr {
     async fn on_event(
         &mut self,
-        _input: &str,
+        input: &str,
         codec: &mut dyn Codec,
         _codec_map: &HashMap<String, Box<dyn Codec>>,
         event: Event,
     ) -> ResultVec {
         let ingest_ns = event.ingest_ns;
-        for value in event.value_iter() {
+        for (value, meta) in event.value_meta_iter() {
+            let prefix = console::prefix(&self.config.prefix, ingest_ns, input, meta);
+            let wrapped;
+            let value = if self.config.include_meta {
+                wrapped = console::with_meta(value, meta);
+                &wrapped
+            } else {
+                value
+            };
+            let pretty = match self.config.mode {
+                Mode::Codec => None,
+                Mode::Pretty => Some(value.encode_pp()),
+                Mode::Colorized => Some(console::colorize(value)),
+            };
+            if let Some(pretty) = pretty {
+                self.stderr.write_all(prefix.as_bytes()).await?;
+                self.stderr.write_all(pretty.as_bytes()).await?;
+                self.stderr.write_all(b"\n").await?;
+                continue;
+            }
             let raw = codec.encode(value)?;
             for processed in postprocess(&mut self.postprocessors, ingest_ns, raw)? {
-                self.stderr.write_all(self.config.prefix.as_bytes()).await?;
+                self.stderr.write_all(prefix.as_bytes()).await?;
                 if self.config.raw {
                     self.stderr.write_all(&processed).await?;
                 } else if let Ok(s) = std::str::from_utf8(&processed) {
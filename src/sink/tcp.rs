@@ -22,20 +22,17 @@
 //!
 //! See [Config](struct.Config.html) for details.
 
+use std::net::SocketAddr;
 use std::time::Instant;
 
+use crate::connectors::discovery::{Discovery, Resolution};
+use crate::connectors::tls::TlsConfig;
 use crate::sink::prelude::*;
 use async_std::net::TcpStream;
 use halfbrown::HashMap;
 
-use async_tls::TlsConnector;
-use rustls::ClientConfig;
-
 use async_std::io::Write;
 use either::Either;
-use std::io::Cursor;
-use std::path::PathBuf;
-use std::sync::Arc;
 
 type Stream = Box<dyn Write + std::marker::Unpin + Send>;
 
@@ -43,6 +40,14 @@ type Stream = Box<dyn Write + std::marker::Unpin + Send>;
 pub struct Tcp {
     // TODO: check if we can/should use an enum here where we implement async_std::io::Write manually?
     stream: Option<Stream>,
+    /// discovered addresses of the host, if a resolution is configured
+    discovery: Option<Discovery>,
+    /// connections to the discovered addresses, events are sent round robin
+    streams: HashMap<SocketAddr, Stream>,
+    /// generation of the discovered addresses `streams` were drained for
+    generation: u64,
+    /// set if a connection to a discovered address failed
+    failed: bool,
     postprocessors: Postprocessors,
     config: Config,
 }
@@ -55,14 +60,12 @@ pub struct Config {
     pub ttl: u32,
     #[serde(default = "default_no_delay")]
     pub is_no_delay: bool,
+    /// `true` for TLS with the default configuration or the TLS configuration
     #[serde(with = "either::serde_untagged_optional", default = "Default::default")]
-    pub tls: Option<Either<TLSConfig, bool>>,
-}
-
-#[derive(Deserialize, Debug, Default)]
-pub struct TLSConfig {
-    cafile: Option<PathBuf>,
-    domain: Option<String>,
+    pub tls: Option<Either<TlsConfig, bool>>,
+    /// Re-resolve the host periodically and balance across its addresses
+    #[serde(default)]
+    pub resolution: Option<Resolution>,
 }
 
 fn default_no_delay() -> bool {
@@ -79,9 +82,17 @@ impl offramp::Impl for Tcp {
     fn from_config(config: &Option<OpConfig>) -> Result<Box<dyn Offramp>> {
         if let Some(config) = config {
             let config: Config = Config::new(config)?;
+            let discovery = config
+                .resolution
+                .as_ref()
+                .map(|resolution| Discovery::start(&config.host, config.port, resolution));
             Ok(SinkManager::new_box(Self {
                 config,
                 stream: None,
+                discovery,
+                streams: HashMap::new(),
+                generation: 0,
+                failed: false,
                 postprocessors: vec![],
             }))
         } else {
@@ -90,53 +101,107 @@ impl offramp::Impl for Tcp {
     }
 }
 
+async fn write_event(
+    stream: &mut Stream,
+    postprocessors: &mut Postprocessors,
+    codec: &mut dyn Codec,
+    event: &Event,
+) -> Result<()> {
+    for value in event.value_iter() {
+        let raw = codec.encode(value)?;
+        let packets = postprocess(postprocessors, event.ingest_ns, raw)?;
+        for packet in packets {
+            stream.write_all(&packet).await?;
+        }
+    }
+    Ok(())
+}
+
 impl Tcp {
     async fn send_event(&mut self, codec: &mut dyn Codec, event: &Event) -> Result<()> {
-        let stream = self
-            .stream
-            .as_mut()
-            .ok_or_else(|| Error::from(ErrorKind::NoSocket))?;
-        for value in event.value_iter() {
-            let raw = codec.encode(value)?;
-            let packets = postprocess(&mut self.postprocessors, event.ingest_ns, raw)?;
-            for packet in packets {
-                stream.write_all(&packet).await?;
+        self.drain().await;
+        let addr = self.discovery.as_ref().and_then(Discovery::next);
+        let stream = if let Some(addr) = addr {
+            // the connection to the host itself is only used until addresses are discovered
+            self.stream = None;
+            if !self.streams.contains_key(&addr) {
+                match Self::connect(&self.config, Some(addr)).await {
+                    Ok(stream) => {
+                        self.streams.insert(addr, stream);
+                    }
+                    Err(e) => {
+                        self.failed = true;
+                        return Err(e);
+                    }
+                }
+            }
+            self.streams.get_mut(&addr)
+        } else {
+            self.stream.as_mut()
+        }
+        .ok_or_else(|| Error::from(ErrorKind::NoSocket))?;
+        let res = write_event(stream, &mut self.postprocessors, codec, event).await;
+        if let (Some(addr), Err(Error(ErrorKind::Io(_), _))) = (addr, &res) {
+            self.streams.remove(&addr);
+            self.failed = true;
+        }
+        res
+    }
+
+    /// Closes the connections to addresses that are no longer discovered
+    /// after flushing them
+    async fn drain(&mut self) {
+        if let Some(discovery) = &self.discovery {
+            let connected: Vec<SocketAddr> = self.streams.keys().copied().collect();
+            for addr in discovery.removed(&mut self.generation, &connected) {
+                if let Some(mut stream) = self.streams.remove(&addr) {
+                    info!(
+                        "[Sink::TCP] Draining connection to removed address {}",
+                        addr
+                    );
+                    if let Err(e) = stream.flush().await {
+                        warn!("[Sink::TCP] Failed to drain connection to {}: {}", addr, e);
+                    }
+                }
             }
         }
-        Ok(())
     }
 
-    async fn connect(config: &Config) -> Result<Stream> {
-        let stream = TcpStream::connect((config.host.as_str(), config.port)).await?;
+    /// Connects to `addr` or the configured host
+    async fn connect(config: &Config, addr: Option<SocketAddr>) -> Result<Stream> {
+        let stream = if let Some(addr) = addr {
+            TcpStream::connect(addr).await?
+        } else {
+            TcpStream::connect((config.host.as_str(), config.port)).await?
+        };
         stream.set_ttl(config.ttl)?;
         stream.set_nodelay(config.is_no_delay)?;
         let s: Stream = match config.tls.as_ref() {
             Some(Either::Right(true)) => {
                 debug!("Returns a TLS stream via default TLS connector");
-                let tls_config = TLSConfig::default();
-                let c = connector(&tls_config).await?;
+                let c = TlsConfig::default().connector()?;
                 Box::new(c.connect(config.host.as_str(), stream).await?)
             }
             Some(Either::Left(tls)) => {
-                debug!("Returns a TLS stream by a TLS connector started wiht cafile provided in the config");
-                let c = connector(tls).await?;
-                Box::new(
-                    c.connect(
-                        tls.domain
-                            .as_ref()
-                            .map_or_else(|| config.host.as_str(), String::as_str),
-                        stream,
-                    )
-                    .await?,
-                )
-            }
-            Some(Either::Right(false)) | None => {
-                debug!("Returns the usual TCP stream");
-                Box::new(stream)
+                debug!("Returns a TLS stream by a TLS connector created from the config");
+                let c = tls.connector()?;
+                Box::new(c.connect(tls.domain(&config.host), stream).await?)
             }
+            Some(Either::Right(false)) | None => Self::plain(stream)?,
         };
         Ok(s)
     }
+
+    /// A stream without TLS, written through io_uring if it is available
+    fn plain(stream: TcpStream) -> Result<Stream> {
+        #[cfg(all(feature = "io-uring", target_os = "linux"))]
+        if let Some(ring) = crate::uring::ring() {
+            debug!("Returns a TCP stream written through io_uring");
+            return Ok(Box::new(crate::uring::TcpStream::new(ring, stream)));
+        }
+        debug!("Returns the usual TCP stream");
+        Ok(Box::new(stream))
+    }
 }
 
 #[async_trait::async_trait]
@@ -206,7 +271,7 @@ impl Sink for Tcp {
         _reply_channel: Sender<sink::Reply>,
     ) -> Result<()> {
         self.postprocessors = make_postprocessors(processors.post)?;
-        let stream = Self::connect(&self.config).await?;
+        let stream = Self::connect(&self.config, None).await?;
         //let stream = TcpStream::connect((self.config.host.as_str(), self.config.port)).await?;
         //stream.set_ttl(self.config.ttl)?;
         //stream.set_nodelay(self.config.is_no_delay)?;
@@ -214,8 +279,26 @@ impl Sink for Tcp {
         Ok(())
     }
     async fn on_signal(&mut self, signal: Event) -> ResultVec {
-        if self.stream.is_none() {
-            let stream = if let Ok(stream) = Self::connect(&self.config).await {
+        if self.failed {
+            let addr = self.discovery.as_ref().and_then(Discovery::next);
+            return if let Ok(stream) = Self::connect(&self.config, addr).await {
+                if let Some(addr) = addr {
+                    self.streams.insert(addr, stream);
+                } else {
+                    self.stream = Some(stream);
+                }
+                self.failed = false;
+                Ok(Some(vec![sink::Reply::Insight(Event::cb_restore(
+                    signal.ingest_ns,
+                ))]))
+            } else {
+                Ok(Some(vec![sink::Reply::Insight(Event::cb_trigger(
+                    signal.ingest_ns,
+                ))]))
+            };
+        }
+        if self.stream.is_none() && self.streams.is_empty() {
+            let stream = if let Ok(stream) = Self::connect(&self.config, None).await {
                 stream
             } else {
                 return Ok(Some(vec![sink::Reply::Insight(Event::cb_trigger(
@@ -231,29 +314,6 @@ impl Sink for Tcp {
         }
     }
     fn is_active(&self) -> bool {
-        self.stream.is_some()
+        self.stream.is_some() || !self.streams.is_empty()
     }
 }
-
-/// if we have a cafile configured, we only load it, and no other ca certificates
-/// if there is no cafile configured, we load the default webpki-roots from Mozilla
-async fn connector(config: &TLSConfig) -> Result<TlsConnector> {
-    Ok(match config {
-        TLSConfig {
-            cafile: Some(cafile),
-            ..
-        } => {
-            let mut config = ClientConfig::new();
-            let file = async_std::fs::read(cafile).await?;
-            let mut pem = Cursor::new(file);
-            config.root_store.add_pem_file(&mut pem).map_err(|_e| {
-                Error::from(ErrorKind::TLSError(format!(
-                    "Invalid certificate in {}",
-                    cafile.display()
-                )))
-            })?;
-            TlsConnector::from(Arc::new(config))
-        }
-        TLSConfig { cafile: None, .. } => TlsConnector::default(),
-    })
-}
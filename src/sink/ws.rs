@@ -14,14 +14,19 @@
 
 #![cfg(not(tarpaulin_include))]
 
+use crate::connectors::proxy::{self, Proxy};
+use crate::connectors::tls::TlsConfig;
 use crate::sink::prelude::*;
 use crate::source::prelude::*;
 use async_channel::{bounded, unbounded, Receiver, Sender};
-use async_std::net::TcpStream;
+use async_tls::TlsConnector;
+use async_tungstenite::async_std::{
+    client_async_tls_with_connector, connect_async_with_tls_connector, ConnectStream,
+};
 use async_tungstenite::tungstenite::error::Error as WsError;
 use async_tungstenite::tungstenite::error::ProtocolError as WsProtocolError;
 use async_tungstenite::tungstenite::Message;
-use async_tungstenite::{async_std::connect_async, WebSocketStream};
+use async_tungstenite::{stream::Stream, WebSocketStream};
 use futures::SinkExt;
 use halfbrown::HashMap;
 use std::boxed::Box;
@@ -49,6 +54,12 @@ pub struct Config {
     pub url: String,
     #[serde(default)]
     pub binary: bool,
+    /// TLS for `wss://` urls, the webpki roots are used if not set
+    #[serde(default)]
+    pub tls: Option<TlsConfig>,
+    /// proxy for connections, overrides the default proxy
+    #[serde(default)]
+    pub proxy: Option<Proxy>,
 }
 
 enum WsConnectionMsg {
@@ -76,6 +87,8 @@ pub struct Ws {
     connection_lifecycle_tx: Sender<WsConnectionMsg>,
     connection_lifecycle_rx: Receiver<WsConnectionMsg>,
     connections: HashMap<WsUrl, WsConnectionHandle>,
+    tls_connector: Option<TlsConnector>,
+    proxy: Option<Proxy>,
     is_linked: bool,
     /// We need to merge all op_metas we receive as we can receive
     /// disconnect/connect events independent of event handling
@@ -119,7 +132,7 @@ async fn handle_error(
 /// close the given stream if it is not already closed.
 async fn close_stream_on_error(
     e: WsError,
-    stream: &mut WebSocketStream<TcpStream>,
+    stream: &mut WebSocketStream<ConnectStream>,
     sink_url: &TremorUrl,
     url: &str,
 ) {
@@ -139,6 +152,32 @@ async fn close_stream_on_error(
     }
 }
 
+/// Connects to `url`, through `proxy` if set
+async fn connect(
+    url: &str,
+    tls_connector: Option<TlsConnector>,
+    proxy: Option<&Proxy>,
+) -> Result<WebSocketStream<ConnectStream>> {
+    let ws_stream = if let Some(proxy) = proxy {
+        let parsed = Url::parse(url)?;
+        let host = parsed
+            .host_str()
+            .ok_or_else(|| Error::from(format!("No host in url {}", url)))?;
+        let port = parsed
+            .port_or_known_default()
+            .ok_or_else(|| Error::from(format!("No port in url {}", url)))?;
+        let stream = proxy.connect(host, port).await?;
+        client_async_tls_with_connector(url, stream, tls_connector)
+            .await?
+            .0
+    } else {
+        connect_async_with_tls_connector(url, tls_connector)
+            .await?
+            .0
+    };
+    Ok(ws_stream)
+}
+
 #[allow(clippy::too_many_arguments, clippy::too_many_lines)]
 async fn ws_connection_loop(
     sink_url: TremorUrl,
@@ -149,6 +188,8 @@ async fn ws_connection_loop(
     tx: Sender<SendEventConnectionMsg>,
     rx: Receiver<SendEventConnectionMsg>,
     has_link: bool,
+    tls_connector: Option<TlsConnector>,
+    proxy: Option<Proxy>,
     mut preprocessors: Preprocessors,
     mut postprocessors: Postprocessors,
     mut codec: Box<dyn Codec>,
@@ -156,13 +197,17 @@ async fn ws_connection_loop(
     loop {
         let codec: &mut dyn Codec = codec.as_mut();
         info!("[Sink::{}] Connecting to {} ...", &sink_url, url);
-        let mut ws_stream = if let Ok((ws_stream, _)) = connect_async(&url).await {
-            if let Ok(peer) = ws_stream.get_ref().peer_addr() {
-                event_origin_url.port = Some(peer.port());
-                event_origin_url.host = peer.ip().to_string();
-            }
-            if let Ok(local) = ws_stream.get_ref().local_addr() {
-                event_origin_url.path = vec![local.port().to_string()];
+        let connected = connect(&url, tls_connector.clone(), proxy.as_ref()).await;
+        let mut ws_stream = if let Ok(ws_stream) = connected {
+            // the addresses of tls streams are taken from the url
+            if let Stream::Plain(tcp_stream) = ws_stream.get_ref() {
+                if let Ok(peer) = tcp_stream.peer_addr() {
+                    event_origin_url.port = Some(peer.port());
+                    event_origin_url.host = peer.ip().to_string();
+                }
+                if let Ok(local) = tcp_stream.local_addr() {
+                    event_origin_url.path = vec![local.port().to_string()];
+                }
             }
             ws_stream
         } else {
@@ -424,6 +469,8 @@ impl offramp::Impl for Ws {
             let config: Config = serde_yaml::from_value(config.clone())?;
             // ensure we have valid url
             Url::parse(&config.url)?;
+            let tls_connector = config.tls.as_ref().map(TlsConfig::connector).transpose()?;
+            let proxy = proxy::resolve(config.proxy.as_ref())?;
 
             let (tx, rx) = unbounded();
 
@@ -437,6 +484,8 @@ impl offramp::Impl for Ws {
                 connection_lifecycle_tx: tx,
                 connection_lifecycle_rx: rx,
                 connections: HashMap::new(),
+                tls_connector,
+                proxy,
                 is_linked: false,
                 merged_meta: OpMeta::default(),
                 reply_tx,
@@ -591,6 +640,8 @@ impl Sink for Ws {
                 conn_tx.clone(),
                 conn_rx,
                 self.is_linked,
+                self.tls_connector.clone(),
+                self.proxy.clone(),
                 make_preprocessors(self.preprocessors.as_slice())?,
                 make_postprocessors(self.postprocessors.as_slice())?,
                 self.shared_codec.boxed_clone(),
@@ -687,6 +738,8 @@ impl Sink for Ws {
                 conn_tx,
                 conn_rx,
                 is_linked,
+                self.tls_connector.clone(),
+                self.proxy.clone(),
                 make_preprocessors(self.preprocessors.as_slice())?,
                 make_postprocessors(self.postprocessors.as_slice())?,
                 self.shared_codec.boxed_clone(),
@@ -775,6 +828,8 @@ mod test {
         let config = Config {
             url: "http://idonotexist:65535/path".to_string(),
             binary: true,
+            tls: None,
+            proxy: None,
         };
         let mut sink = Ws {
             sink_url: url.clone(),
@@ -786,6 +841,8 @@ mod test {
             connection_lifecycle_rx: conn_rx,
             connection_lifecycle_tx: conn_tx,
             connections: HashMap::new(),
+            tls_connector: None,
+            proxy: None,
             is_linked: true,
             merged_meta: OpMeta::default(),
             reply_tx: reply_tx.clone(),
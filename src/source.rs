@@ -12,12 +12,16 @@
 // See the License for the specific language governing permissions and
 // limitations under the License.
 
+use crate::backfill::Replay;
+use crate::cluster;
+use crate::config::{OnRampLimits, Priority};
 use crate::errors::Error;
-use crate::metrics::RampReporter;
+use crate::metrics::{DropReason, Drops, RampReporter};
 use crate::onramp;
+use crate::overflow::Offer;
 use crate::pipeline;
 use crate::preprocessor::{make_preprocessors, preprocess, Preprocessors};
-use crate::url::ports::{ERR, METRICS, OUT};
+use crate::url::ports::{CONNECTIONS, ERR, METRICS, OUT};
 use crate::url::TremorUrl;
 use crate::{
     codec::{self, Codec},
@@ -30,34 +34,62 @@ use async_std::task;
 use beef::Cow;
 use halfbrown::HashMap;
 use std::collections::BTreeMap;
+use std::sync::Arc;
 use std::time::Duration;
 use tremor_common::time::nanotime;
+use tremor_pipeline::errors::{Error as PipelineError, ErrorKind as PipelineErrorKind};
 use tremor_pipeline::{CbAction, Event, EventId, EventOriginUri, DEFAULT_STREAM_ID};
 use tremor_script::prelude::*;
 
 use self::prelude::OnrampConfig;
 
+/// How often singleton sources on standby check for the cluster lease
+const STANDBY_INTERVAL: Duration = Duration::from_millis(100);
+
+/// Metadata key of the index of the reader instance of an event, for onramps
+/// running more than one
+const INSTANCE_META: &str = "instance";
+
 pub(crate) mod amqp;
 pub(crate) mod blaster;
 pub(crate) mod cb;
 pub(crate) mod crononome;
 pub(crate) mod discord;
+#[cfg(unix)]
+pub(crate) mod docker;
+pub(crate) mod embedded;
 pub(crate) mod env;
 pub(crate) mod file;
+pub(crate) mod generator;
 pub(crate) mod gsub;
+#[cfg(target_os = "linux")]
+pub(crate) mod host_metrics;
+pub(crate) mod influx;
+pub(crate) mod interconnect;
+pub(crate) mod journald;
 pub(crate) mod kafka;
+pub(crate) mod kubernetes;
 pub(crate) mod metronome;
 pub(crate) mod nats;
+pub(crate) mod netflow;
 pub(crate) mod otel;
+pub(crate) mod pcap;
+pub(crate) mod plugin;
 pub(crate) mod postgres;
 pub(crate) mod prelude;
+pub(crate) mod quic;
+pub(crate) mod replay;
 pub(crate) mod rest;
 pub(crate) mod sse;
 pub(crate) mod stdin;
 pub(crate) mod tcp;
+pub(crate) mod telegram;
 pub(crate) mod udp;
 #[cfg(unix)]
 pub mod unix_socket;
+pub(crate) mod webhook;
+#[cfg(windows)]
+pub(crate) mod wineventlog;
 pub(crate) mod ws;
 
 struct StaticValue(Value<'static>);
@@ -103,6 +135,19 @@ pub(crate) enum SourceReply {
         origin_uri: EventOriginUri,
         data: EventPayload,
     },
+    /// An already structured event for the linked instances of a pipeline
+    Routed {
+        origin_uri: EventOriginUri,
+        data: EventPayload,
+        /// artefact id of the pipeline
+        pipeline: String,
+    },
+    /// A connection lifecycle event for the `connections` port, e.g. when a
+    /// connection is opened or closed
+    Connection {
+        origin_uri: EventOriginUri,
+        data: EventPayload,
+    },
     /// A stream is opened
     StartStream(usize),
     /// A stream is closed
@@ -193,11 +238,25 @@ where
     triggered: bool,
     pipelines_out: Vec<(TremorUrl, pipeline::Addr)>,
     pipelines_err: Vec<(TremorUrl, pipeline::Addr)>,
+    pipelines_connections: Vec<(TremorUrl, pipeline::Addr)>,
     err_required: bool,
+    limits: OnRampLimits,
+    priority: Priority,
+    /// id of the first event not yet acknowledged or failed
+    settled_id: u64,
     id: u64,
     is_transactional: bool,
     /// Unique Id for the source
     uid: u64,
+    /// only runs on the node holding the cluster lease
+    singleton: bool,
+    /// if the singleton waits for the lease
+    standby: bool,
+    drops: Drops,
+    /// progress of replaying historical data for a backfill
+    replay: Option<Arc<Replay>>,
+    /// index of the reader instance, if the onramp runs more than one
+    instance: Option<usize>,
 }
 
 impl<T> SourceManager<T>
@@ -235,6 +294,22 @@ where
         meta: Option<StaticValue>, // See: https://github.com/rust-lang/rust/issues/63033
     ) -> Vec<Result<EventPayload>> {
         let mut results = vec![];
+        if let Some(max) = self.limits.max_event_size {
+            if data.len() > max {
+                let kind =
+                    PipelineErrorKind::LimitExceeded("max_event_size".to_string(), max, data.len());
+                results.push(Err(PipelineError::from(kind).into()));
+                return results;
+            }
+        }
+        // sources may override the codec by name instead of by a mime type of the codec map
+        if let Some(name) = &codec_override {
+            if !self.codec_map.contains_key(name) {
+                if let Ok(codec) = codec::lookup(name) {
+                    self.codec_map.insert(name.clone(), codec);
+                }
+            }
+        }
         match self.handle_pp(stream, ingest_ns, data) {
             Ok(data) => {
                 let meta_value = meta.map_or_else(Value::object, |m| m.0);
@@ -273,11 +348,46 @@ where
         results
     }
 
+    /// Returns true if the number of unsettled events reached `max_in_flight`
+    fn in_flight_exceeded(&self) -> bool {
+        self.is_transactional
+            && self
+                .limits
+                .max_in_flight
+                .map_or(false, |max| self.id.saturating_sub(self.settled_id) >= max)
+    }
+
+    fn settle(&mut self, id: u64) {
+        self.settled_id = self.settled_id.max(id + 1);
+    }
+
+    /// Singletons only run on the node holding the cluster lease, another
+    /// node takes over once it acquires the lease
+    fn stands_by(&mut self) -> bool {
+        let standby = self.singleton && !cluster::holds_lease();
+        if standby != self.standby {
+            if standby {
+                info!(
+                    "[Source::{}] Standing by for the cluster lease",
+                    self.source_id
+                );
+            } else {
+                info!(
+                    "[Source::{}] Running with the cluster lease",
+                    self.source_id
+                );
+            }
+            self.standby = standby;
+        }
+        standby
+    }
+
     fn needs_pipeline_msg(&self) -> bool {
         self.pipelines_out.is_empty()
             || self.triggered
             || !self.rx.is_empty()
             || (self.err_required && self.pipelines_err.is_empty())
+            || self.in_flight_exceeded()
     }
     async fn handle_pipelines(&mut self) -> Result<bool> {
         loop {
@@ -306,6 +416,8 @@ where
                                 &mut self.pipelines_out
                             } else if port == ERR {
                                 &mut self.pipelines_err
+                            } else if port == CONNECTIONS {
+                                &mut self.pipelines_connections
                             } else {
                                 return Err(format!(
                                     "Invalid Onramp Port: {}. Cannot connect.",
@@ -328,6 +440,7 @@ where
                         .pipelines_out
                         .iter()
                         .chain(self.pipelines_err.iter())
+                        .chain(self.pipelines_connections.iter())
                         .filter(|(pid, _)| pid == &id)
                     {
                         p.send_mgmt(pipeline::MgmtMsg::DisconnectInput(id.clone()))
@@ -339,6 +452,9 @@ where
                     empty_pipelines &= self.pipelines_out.is_empty();
                     self.pipelines_err.retain(|(pipeline, _)| pipeline != &id);
                     empty_pipelines &= self.pipelines_err.is_empty();
+                    self.pipelines_connections
+                        .retain(|(pipeline, _)| pipeline != &id);
+                    empty_pipelines &= self.pipelines_connections.is_empty();
 
                     tx.send(empty_pipelines).await?;
                     if empty_pipelines {
@@ -350,6 +466,7 @@ where
                     // TODO: stream handling
                     // when failing, we use the earliest/min event within the tracked set
                     if let Some((_stream_id, id)) = ids.get_min_by_source(self.uid) {
+                        self.settle(id);
                         self.source.fail(id);
                     }
                 }
@@ -358,6 +475,7 @@ where
                     // TODO: stream handling
                     // when acknowledging, we use the latest/max event within the tracked set
                     if let Some((_stream_id, id)) = ids.get_max_by_source(self.uid) {
+                        self.settle(id);
                         self.source.ack(id);
                     }
                 }
@@ -373,6 +491,10 @@ where
                 }
                 onramp::Msg::Cb(CbAction::None, _ids) => {}
 
+                onramp::Msg::Backfill(replay) => {
+                    info!("[Source::{}] Replaying for a backfill", self.source_id);
+                    self.replay = Some(replay);
+                }
                 onramp::Msg::Response(event) => {
                     if let Err(e) = self
                         .source
@@ -391,11 +513,37 @@ where
 
     pub(crate) async fn transmit_event(
         &mut self,
-        data: EventPayload,
+        mut data: EventPayload,
         ingest_ns: u64,
         origin_uri: EventOriginUri,
         port: Cow<'static, str>,
+        target: Option<&str>,
     ) -> bool {
+        if self.priority == Priority::High {
+            // the onramp priority applies unless the source set one
+            data.rent_mut(|data| {
+                let (_, meta) = data.parts_mut();
+                if meta.get(Priority::META).is_none() {
+                    meta.try_insert(Priority::META, "high");
+                }
+            });
+        }
+        if let Some(instance) = self.instance {
+            data.rent_mut(|data| {
+                let (_, meta) = data.parts_mut();
+                meta.try_insert(INSTANCE_META, instance as u64);
+            });
+        }
+        if let Some(max_age) = self.limits.max_age {
+            // the onramp deadline applies unless the source set one
+            let deadline = ingest_ns.saturating_add(max_age.saturating_mul(1_000_000));
+            data.rent_mut(|data| {
+                let (_, meta) = data.parts_mut();
+                if meta.get(Event::DEADLINE).is_none() {
+                    meta.try_insert(Event::DEADLINE, deadline);
+                }
+            });
+        }
         let event = Event {
             // TODO: use EventIdGen and stream handling
             id: EventId::new(self.uid, DEFAULT_STREAM_ID, self.id),
@@ -408,14 +556,44 @@ where
         };
         let mut error = false;
         self.id += 1;
+        if OUT == port {
+            if let Some(replay) = &self.replay {
+                replay.record();
+            }
+        }
         let pipelines = if OUT == port {
-            &mut self.pipelines_out
+            &self.pipelines_out
         } else if ERR == port {
-            &mut self.pipelines_err
+            &self.pipelines_err
+        } else if CONNECTIONS == port {
+            &self.pipelines_connections
         } else {
             return false;
         };
-        if let Some((last, pipelines)) = pipelines.split_last_mut() {
+        // events routed to a pipeline only go to the linked instances of it
+        let targeted =
+            |url: &TremorUrl| target.map_or(true, |target| url.artefact() == Some(target));
+        let last = match pipelines.iter().rposition(|(url, _)| targeted(url)) {
+            Some(last) => last,
+            None => {
+                if let Some(target) = target {
+                    warn!(
+                        "[Source::{}] [Onramp] pipeline {} is not linked, dropping event",
+                        self.source_id, target
+                    );
+                }
+                // errors are only emitted on `err` for decoding and preprocessing failures
+                let reason = if ERR == port && target.is_none() {
+                    DropReason::Decode
+                } else {
+                    DropReason::Unlinked
+                };
+                self.drops.record(&port, reason, 1);
+                return target.is_some();
+            }
+        };
+        let (pipelines, last) = pipelines.split_at(last);
+        if let Some(last) = last.first() {
             if let Some(t) = self.metrics_reporter.periodic_flush(ingest_ns) {
                 self.metrics_reporter.send(self.source.metrics(t));
             }
@@ -427,39 +605,46 @@ where
                 self.metrics_reporter.increment_out();
             }
 
-            for (input, addr) in pipelines {
+            let mut full = 0;
+            let priority = Priority::of(&event);
+            for (input, addr) in pipelines.iter().filter(|(url, _)| targeted(url)) {
                 if let Some(input) = input.instance_port() {
-                    if let Err(e) = addr
-                        .send(pipeline::Msg::Event {
-                            input: input.to_string().into(),
-                            event: event.clone(),
-                        })
+                    match addr
+                        .send_event(input.to_string().into(), event.clone(), priority)
                         .await
                     {
-                        error!(
-                            "[Source::{}] [Onramp] failed to send to pipeline: {}",
-                            self.source_id, e
-                        );
-                        error = true;
+                        Ok(Offer::Queued) => (),
+                        Ok(Offer::Dropped(_) | Offer::Overflow(_)) => full += 1,
+                        Err(e) => {
+                            error!(
+                                "[Source::{}] [Onramp] failed to send to pipeline: {}",
+                                self.source_id, e
+                            );
+                            error = true;
+                        }
                     }
                 }
             }
             if let Some(input) = last.0.instance_port() {
-                if let Err(e) = last
+                match last
                     .1
-                    .send(pipeline::Msg::Event {
-                        input: input.to_string().into(),
-                        event,
-                    })
+                    .send_event(input.to_string().into(), event, priority)
                     .await
                 {
-                    error!(
-                        "[Source::{}] [Onramp] failed to send to pipeline: {}",
-                        self.source_id, e
-                    );
-                    error = true;
+                    Ok(Offer::Queued) => (),
+                    Ok(Offer::Dropped(_) | Offer::Overflow(_)) => full += 1,
+                    Err(e) => {
+                        error!(
+                            "[Source::{}] [Onramp] failed to send to pipeline: {}",
+                            self.source_id, e
+                        );
+                        error = true;
+                    }
                 }
             }
+            if full > 0 {
+                self.drops.record(&port, DropReason::QueueFull, full);
+            }
         }
         error
     }
@@ -497,6 +682,7 @@ where
         Ok((
             Self {
                 source_id: source.id().clone(),
+                drops: Drops::new(source.id().clone()),
                 pp_template,
                 source,
                 rx,
@@ -510,9 +696,17 @@ where
                 id: 0,
                 pipelines_out: Vec::new(),
                 pipelines_err: Vec::new(),
+                pipelines_connections: Vec::new(),
                 uid: config.onramp_uid,
                 is_transactional,
                 err_required: config.err_required,
+                limits: config.limits,
+                priority: config.priority,
+                settled_id: 0,
+                singleton: config.singleton,
+                standby: false,
+                replay: None,
+                instance: config.instance,
             },
             tx,
         ))
@@ -539,7 +733,7 @@ where
                 |data| (OUT, data),
             );
             error |= self
-                .transmit_event(data, ingest_ns, origin_uri.clone(), port)
+                .transmit_event(data, ingest_ns, origin_uri.clone(), port, None)
                 .await;
         }
         error
@@ -551,6 +745,11 @@ where
                 return Ok(());
             }
 
+            if self.stands_by() {
+                task::sleep(STANDBY_INTERVAL).await;
+                continue;
+            }
+
             let pipelines_out_empty = self.pipelines_out.is_empty();
 
             if !self.triggered && !pipelines_out_empty {
@@ -562,10 +761,26 @@ where
                     Ok(SourceReply::EndStream(id)) => {
                         self.preprocessors.remove(&id);
                     }
+                    Ok(SourceReply::Connection { origin_uri, data }) => {
+                        if !self.pipelines_connections.is_empty() {
+                            self.transmit_event(data, nanotime(), origin_uri, CONNECTIONS, None)
+                                .await;
+                        }
+                    }
                     Ok(SourceReply::Structured { origin_uri, data }) => {
                         let ingest_ns = nanotime();
 
-                        self.transmit_event(data, ingest_ns, origin_uri, OUT).await;
+                        self.transmit_event(data, ingest_ns, origin_uri, OUT, None)
+                            .await;
+                    }
+                    Ok(SourceReply::Routed {
+                        origin_uri,
+                        data,
+                        pipeline,
+                    }) => {
+                        let ingest_ns = nanotime();
+                        self.transmit_event(data, ingest_ns, origin_uri, OUT, Some(&pipeline))
+                            .await;
                     }
                     Ok(SourceReply::BatchData {
                         mut origin_uri,
@@ -637,6 +852,9 @@ where
                     }
                     Ok(SourceReply::StateChange(SourceState::Disconnected)) => {
                         warn!("[Source::{}] Disconnected.", self.source_id);
+                        if let Some(replay) = &self.replay {
+                            replay.complete();
+                        }
                         return Ok(());
                     }
                     Ok(SourceReply::StateChange(SourceState::Connected)) => (),
@@ -698,15 +916,20 @@ mod tests {
             metrics_reporter: RampReporter::new(onramp_url.clone(), None),
             is_linked: false,
             err_required: false,
+            limits: OnRampLimits::default(),
+            priority: Priority::default(),
+            singleton: false,
+            instance: None,
         };
         let (sm, sender) = SourceManager::new(s, o_config).await?;
         let handle = task::spawn(sm.run());
 
         let pipeline_url = TremorUrl::parse("/pipeline/bla/01/in")?;
         let (tx1, rx1) = async_channel::unbounded();
+        let (tx_prio, _rx_prio) = async_channel::unbounded();
         let (tx2, _rx2) = async_channel::unbounded();
         let (tx3, rx3) = async_channel::unbounded();
-        let addr = pipeline::Addr::new(tx1, tx2, tx3, pipeline_url.clone());
+        let addr = pipeline::Addr::new(tx1, rx1.clone(), tx_prio, tx2, tx3, pipeline_url.clone());
 
         // trigger the source to ensure it is not being pulled from
         sender
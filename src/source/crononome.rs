@@ -13,8 +13,10 @@
 // limitations under the License.
 #![cfg(not(tarpaulin_include))]
 
+use crate::interpolate::{placeholder, segments, Segment};
 use crate::source::prelude::*;
 use chrono::{DateTime, Utc};
+use chrono_tz::Tz;
 use cron::Schedule;
 use std::clone::Clone;
 use std::cmp::Reverse;
@@ -30,6 +32,12 @@ use tremor_script::prelude::*;
 pub struct CronEntry {
     pub name: String,
     pub expr: String,
+    /// IANA timezone the expression is evaluated in, e.g. `Europe/Berlin`,
+    /// defaults to the onramp `timezone`
+    pub timezone: Option<String>,
+    /// payload template, strings can refer to `${name}`, `${expr}`,
+    /// `${timezone}`, `${scheduled}` (RFC 3339) and `${scheduled_ns}`,
+    /// `$${` is a literal `${`
     pub payload: Option<YamlValue>,
 }
 
@@ -38,6 +46,7 @@ pub struct CronEntryInt {
     pub name: String,
     pub expr: String,
     pub sched: Schedule,
+    pub tz: Tz,
     pub payload: Option<Value<'static>>,
 }
 
@@ -47,19 +56,87 @@ impl TryFrom<CronEntry> for CronEntryInt {
         let payload = if let Some(yaml_payload) = entry.payload {
             // We use this to translate a yaml value (payload in) to tremor value (payload out)
             let mut payload = simd_json::to_vec(&yaml_payload)?;
-            let tremor_payload = tremor_value::parse_to_value(&mut payload)?;
-            Some(tremor_payload.into_static())
+            let tremor_payload = tremor_value::parse_to_value(&mut payload)?.into_static();
+            // reject malformed placeholders up front
+            render(&tremor_payload, &[])?;
+            Some(tremor_payload)
         } else {
             None
         };
+        let tz = if let Some(tz) = &entry.timezone {
+            Tz::from_str(tz)?
+        } else {
+            Tz::UTC
+        };
         Ok(Self {
             sched: Schedule::from_str(entry.expr.as_str())?,
             name: entry.name,
             expr: entry.expr,
+            tz,
             payload,
         })
     }
 }
+
+impl CronEntryInt {
+    /// The name and rendered payload of a trigger scheduled at `at`
+    fn trigger(&self, at: DateTime<Utc>) -> (String, Option<Value<'static>>) {
+        let payload = self.payload.as_ref().map(|payload| {
+            let local = at.with_timezone(&self.tz);
+            let vars = [
+                ("name", Value::from(self.name.clone())),
+                ("expr", Value::from(self.expr.clone())),
+                ("timezone", Value::from(self.tz.name())),
+                ("scheduled", Value::from(local.to_rfc3339())),
+                ("scheduled_ns", Value::from(at.timestamp_nanos())),
+            ];
+            // placeholders were validated when the entry was created
+            render(payload, &vars).unwrap_or_else(|_| payload.clone())
+        });
+        (self.name.clone(), payload)
+    }
+}
+
+/// Replaces `${var}` placeholders in the strings of a payload template. A
+/// string that is a single placeholder is replaced by the value itself,
+/// unknown placeholders are left untouched.
+fn render(template: &Value<'static>, vars: &[(&str, Value<'static>)]) -> Result<Value<'static>> {
+    let lookup = |name: &str| vars.iter().find(|(n, _)| *n == name).map(|(_, v)| v);
+    Ok(match template {
+        Value::String(s) => {
+            if let Some(v) = placeholder(s).and_then(lookup) {
+                return Ok(v.clone());
+            }
+            let mut out = String::with_capacity(s.len());
+            for segment in segments(s)? {
+                match segment {
+                    Segment::Text(text) => out.push_str(text),
+                    Segment::Placeholder(name) => match lookup(name) {
+                        Some(Value::String(v)) => out.push_str(v),
+                        Some(v) => out.push_str(&v.encode()),
+                        None => {
+                            out.push_str("${");
+                            out.push_str(name);
+                            out.push('}');
+                        }
+                    },
+                }
+            }
+            Value::from(out)
+        }
+        Value::Array(a) => Value::from(
+            a.iter()
+                .map(|v| render(v, vars))
+                .collect::<Result<Vec<_>>>()?,
+        ),
+        Value::Object(o) => Value::from(
+            o.iter()
+                .map(|(k, v)| Ok((k.clone(), render(v, vars)?)))
+                .collect::<Result<tremor_value::Object>>()?,
+        ),
+        Value::Static(_) | Value::Bytes(_) => template.clone(),
+    })
+}
 impl std::fmt::Debug for CronEntryInt {
     fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
         write!(f, "{} - {}", self.name, self.expr)
@@ -81,6 +158,8 @@ impl PartialEq for CronEntry {
 #[derive(Deserialize, Clone)]
 pub struct Config {
     pub entries: Vec<CronEntry>,
+    /// IANA timezone for entries that don't set their own, defaults to UTC
+    pub timezone: Option<String>,
 }
 
 impl ConfigImpl for Config {}
@@ -244,9 +323,9 @@ impl ChronomicQueue {
     }
 
     pub fn enqueue(&mut self, entry: &CronEntryInt) {
-        if let Some(at) = entry.sched.upcoming(chrono::Utc).next() {
+        if let Some(at) = entry.sched.upcoming(entry.tz).next() {
             self.tpq.enqueue(TemporalItem {
-                at,
+                at: at.with_timezone(&Utc),
                 what: entry.clone(),
             });
         }
@@ -258,14 +337,14 @@ impl ChronomicQueue {
         for ti in &due {
             // Enqueue next scheduled event if any
             self.enqueue(&ti.what);
-            trigger.push((ti.what.name.clone(), ti.what.payload.clone()));
+            trigger.push(ti.what.trigger(ti.at));
         }
         trigger
     }
     pub fn next(&mut self) -> Option<(String, Option<Value<'static>>)> {
         self.tpq.pop().map(|ti| {
             self.enqueue(&ti.what);
-            (ti.what.name, ti.what.payload)
+            ti.what.trigger(ti.at)
         })
     }
 }
@@ -302,6 +381,10 @@ impl Source for Crononome {
 
     async fn init(&mut self) -> Result<SourceState> {
         for entry in &self.config.entries {
+            let mut entry = entry.clone();
+            if entry.timezone.is_none() {
+                entry.timezone = self.config.timezone.clone();
+            }
             match CronEntryInt::try_from(entry.clone()) {
                 Ok(entry) => self.cq.enqueue(&entry),
                 Err(e) => {
@@ -353,6 +436,65 @@ mod tests {
         Ok(())
     }
 
+    #[test]
+    pub fn test_timezone_and_payload_template() -> Result<()> {
+        use tremor_value::literal;
+        let entry = CronEntryInt::try_from(CronEntry {
+            name: "rollup".to_string(),
+            expr: "0 0 9 * * * *".to_string(),
+            timezone: Some("Europe/Berlin".to_string()),
+            payload: Some(serde_yaml::from_str(
+                r#"{"job": "${name}", "at": "${scheduled_ns}", "msg": "${name} in ${timezone} (${snot})"}"#,
+            )?),
+        })?;
+        assert_eq!(Tz::Europe__Berlin, entry.tz);
+        let at = DateTime::parse_from_rfc3339("2021-06-01T07:00:00Z")?.with_timezone(&Utc);
+        let (name, payload) = entry.trigger(at);
+        assert_eq!("rollup", name);
+        assert_eq!(
+            Some(literal!({
+                "job": "rollup",
+                "at": 1_622_530_800_000_000_000_i64,
+                "msg": "rollup in Europe/Berlin (${snot})"
+            })),
+            payload
+        );
+        // 09:00 in Berlin is 07:00 UTC during summer time and 08:00 in winter
+        let next = |at: &str| -> Result<Option<String>> {
+            let at = DateTime::parse_from_rfc3339(at)?.with_timezone(&entry.tz);
+            Ok(entry
+                .sched
+                .after(&at)
+                .next()
+                .map(|at| at.with_timezone(&Utc).to_rfc3339()))
+        };
+        assert_eq!(
+            Some("2021-06-01T07:00:00+00:00".to_string()),
+            next("2021-06-01T00:00:00Z")?
+        );
+        assert_eq!(
+            Some("2021-12-01T08:00:00+00:00".to_string()),
+            next("2021-12-01T00:00:00Z")?
+        );
+
+        assert!(CronEntryInt::try_from(CronEntry {
+            name: "unterminated".to_string(),
+            expr: "* * * * * * *".to_string(),
+            timezone: None,
+            payload: Some(serde_yaml::from_str(r#"{"msg": "${name"}"#)?),
+        })
+        .is_err());
+
+        assert!(CronEntryInt::try_from(CronEntry {
+            name: "bad".to_string(),
+            expr: "* * * * * * *".to_string(),
+            timezone: Some("Snot/Badger".to_string()),
+            payload: None,
+        })
+        .is_err());
+        Ok(())
+    }
+
     #[test]
     pub fn test_tpq_fill_drain() -> Result<()> {
         use chrono::prelude::Utc;
@@ -362,6 +504,7 @@ mod tests {
             what: CronEntryInt::try_from(CronEntry {
                 name: "a".to_string(),
                 expr: "* * * * * * 1970".to_string(),
+                timezone: None,
                 payload: None,
             })?,
         };
@@ -370,6 +513,7 @@ mod tests {
             what: CronEntryInt::try_from(CronEntry {
                 name: "b".to_string(),
                 expr: "* * * * * * *".to_string(),
+                timezone: None,
                 payload: None,
             })?,
         };
@@ -396,6 +540,7 @@ mod tests {
             what: CronEntryInt::try_from(CronEntry {
                 name: "a".to_string(),
                 expr: "* * * * * * 1970".to_string(),
+                timezone: None,
                 payload: None,
             })?,
         };
@@ -404,6 +549,7 @@ mod tests {
             what: CronEntryInt::try_from(CronEntry {
                 name: "b".to_string(),
                 expr: "* * * * * * *".to_string(),
+                timezone: None,
                 payload: None,
             })?,
         };
@@ -429,16 +575,19 @@ mod tests {
         let n1 = CronEntryInt::try_from(CronEntry {
             name: "a".to_string(),
             expr: "* * * * * * 1970".to_string(), // Dates before UNIX epoch start invalid
+            timezone: None,
             payload: None,
         })?;
         let n2 = CronEntryInt::try_from(CronEntry {
             name: "b".to_string(),
             expr: "* * * * * * *".to_string(),
+            timezone: None,
             payload: None,
         })?;
         let n3 = CronEntryInt::try_from(CronEntry {
             name: "c".to_string(),
             expr: "* * * * * * 2038".to_string(), // limit is 2038 due to the 2038 problem ( we'll be retired so letting this hang wait! )
+            timezone: None,
             payload: None,
         })?;
 
@@ -464,16 +613,19 @@ mod tests {
         let n1 = CronEntryInt::try_from(CronEntry {
             name: "a".to_string(),
             expr: "* * * * * * 1970".to_string(), // Dates before UNIX epoch start invalid
+            timezone: None,
             payload: None,
         })?;
         let n2 = CronEntryInt::try_from(CronEntry {
             name: "b".to_string(),
             expr: "* * * * * * *".to_string(),
+            timezone: None,
             payload: None,
         })?;
         let n3 = CronEntryInt::try_from(CronEntry {
             name: "c".to_string(),
             expr: "* * * * * * 2038".to_string(), // limit is 2038 due to the 2038 problem ( we'll be retired so letting this hang wait! )
+            timezone: None,
             payload: None,
         })?;
 
@@ -0,0 +1,508 @@
+// Copyright 2020-2021, The Tremor Team
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+#![cfg(not(tarpaulin_include))]
+
+//! # Docker Onramp
+//!
+//! Streams the stdout and stderr of containers from the Docker Engine API.
+//! Containers are followed when they start, the output of each container and
+//! stream is its own tremor stream, so preprocessors like `lines` work per
+//! container. The container is described in `$docker`.
+//!
+//! containerd has no API to stream container logs, its log files can be read
+//! with the file onramp.
+
+use crate::source::prelude::*;
+use async_channel::{Sender, TryRecvError};
+use async_std::net::TcpStream;
+use async_std::os::unix::net::UnixStream;
+use http_types::{Method, Request, Response};
+use std::collections::HashSet;
+use std::convert::TryFrom;
+use std::path::PathBuf;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+use tremor_value::literal;
+use url::Url;
+
+const BUFFER_SIZE_BYTES: usize = 8192;
+
+#[derive(Debug, Clone, Deserialize)]
+pub struct Config {
+    /// Docker API endpoint, `unix:///path` or `tcp://host:port`
+    /// (default: `unix:///var/run/docker.sock`)
+    #[serde(default = "dflt_host")]
+    pub host: String,
+    /// Names or id prefixes of the containers to follow, all if empty
+    #[serde(default)]
+    pub containers: Vec<String>,
+    /// Label filters of the containers to follow, `key` or `key=value`
+    #[serde(default)]
+    pub labels: Vec<String>,
+    #[serde(default = "t")]
+    pub stdout: bool,
+    #[serde(default = "t")]
+    pub stderr: bool,
+    /// Number of lines of history to read of containers that are already
+    /// running when the onramp starts, `all` or a number (default: `0`)
+    #[serde(default = "dflt_tail")]
+    pub tail: String,
+}
+
+fn dflt_host() -> String {
+    "unix:///var/run/docker.sock".to_string()
+}
+
+fn t() -> bool {
+    true
+}
+
+fn dflt_tail() -> String {
+    "0".to_string()
+}
+
+impl ConfigImpl for Config {}
+
+#[derive(Debug, Clone)]
+enum Endpoint {
+    Unix(PathBuf),
+    Tcp(String),
+}
+
+/// A client of the Docker Engine API
+#[derive(Debug, Clone)]
+struct Docker {
+    endpoint: Endpoint,
+}
+
+impl Docker {
+    fn new(host: &str) -> Result<Self> {
+        let endpoint = if let Some(path) = host.strip_prefix("unix://") {
+            Endpoint::Unix(PathBuf::from(path))
+        } else if let Some(addr) = host.strip_prefix("tcp://") {
+            Endpoint::Tcp(addr.to_string())
+        } else {
+            return Err(format!("Unsupported docker host {}", host).into());
+        };
+        Ok(Self { endpoint })
+    }
+
+    async fn get(&self, path: &str, query: &[(&str, &str)]) -> Result<Response> {
+        let mut url = Url::parse("http://docker")?;
+        url.set_path(path);
+        if !query.is_empty() {
+            url.query_pairs_mut().extend_pairs(query);
+        }
+        let req = Request::new(Method::Get, url);
+        let response = match &self.endpoint {
+            Endpoint::Unix(path) => async_h1::connect(UnixStream::connect(path).await?, req).await,
+            Endpoint::Tcp(addr) => {
+                async_h1::connect(TcpStream::connect(addr.as_str()).await?, req).await
+            }
+        }?;
+        if response.status().is_success() {
+            Ok(response)
+        } else {
+            Err(format!("Docker request {} failed: {}", path, response.status()).into())
+        }
+    }
+
+    async fn get_json(&self, path: &str, query: &[(&str, &str)]) -> Result<Value<'static>> {
+        let mut body = self.get(path, query).await?.body_bytes().await?;
+        Ok(tremor_value::parse_to_value(&mut body)?.into_static())
+    }
+}
+
+/// The details of a container we follow
+#[derive(Debug, Clone, PartialEq)]
+struct Container {
+    id: String,
+    name: String,
+    tty: bool,
+    meta: Value<'static>,
+}
+
+impl Container {
+    /// From the response of an inspect request
+    fn from_inspect(inspect: &Value) -> Option<Self> {
+        let id = inspect.get_str("Id")?.to_string();
+        let name = inspect
+            .get_str("Name")
+            .unwrap_or_default()
+            .trim_start_matches('/')
+            .to_string();
+        let config = inspect.get("Config");
+        let labels = config
+            .get("Labels")
+            .filter(|labels| labels.is_object())
+            .map_or_else(Value::object, Value::clone_static);
+        let meta = literal!({
+            "container_id": id.clone(),
+            "container_name": name.clone(),
+            "image": config.get_str("Image").unwrap_or_default().to_string(),
+            "labels": labels
+        });
+        Some(Self {
+            id,
+            name,
+            tty: config.get_bool("Tty").unwrap_or_default(),
+            meta,
+        })
+    }
+
+    fn matches(&self, containers: &[String]) -> bool {
+        containers.is_empty()
+            || containers
+                .iter()
+                .any(|c| *c == self.name || self.id.starts_with(c.as_str()))
+    }
+
+    fn stream_meta(&self, stream: &'static str) -> Value<'static> {
+        let mut docker = self.meta.clone();
+        docker.try_insert("stream", stream);
+        literal!({ "docker": docker })
+    }
+}
+
+/// Splits the next complete frame of a multiplexed log stream off `buf`,
+/// returning the stream (1 for stdout, 2 for stderr) and the payload
+fn next_frame(buf: &mut Vec<u8>) -> Option<(u8, Vec<u8>)> {
+    if buf.len() < 8 {
+        return None;
+    }
+    // ALLOW: we checked the length of the header above
+    let len = usize::try_from(u32::from_be_bytes([buf[4], buf[5], buf[6], buf[7]])).ok()?;
+    if buf.len() < 8 + len {
+        return None;
+    }
+    // ALLOW: we checked the length of the frame above
+    let stream = buf[0];
+    let payload = buf[8..8 + len].to_vec();
+    buf.drain(..8 + len);
+    Some((stream, payload))
+}
+
+/// Everything the tasks following containers share
+#[derive(Clone)]
+struct Follower {
+    docker: Docker,
+    config: Arc<Config>,
+    tx: Sender<SourceReply>,
+    origin_uri: EventOriginUri,
+    next_stream: Arc<AtomicUsize>,
+    /// ids of the followed containers
+    followed: Arc<Mutex<HashSet<String>>>,
+}
+
+impl Follower {
+    fn label_filters(&self) -> String {
+        literal!({ "label": self.config.labels.clone() }).encode()
+    }
+
+    /// Follows a container unless it is followed already
+    async fn follow(self, id: String, tail: String) {
+        let is_new = self
+            .followed
+            .lock()
+            .map_or(false, |mut followed| followed.insert(id.clone()));
+        if !is_new {
+            return;
+        }
+        if let Err(e) = self.stream_logs(&id, &tail).await {
+            warn!("Failed to follow container {}: {}", id, e);
+        }
+        if let Ok(mut followed) = self.followed.lock() {
+            followed.remove(&id);
+        }
+    }
+
+    async fn stream_logs(&self, id: &str, tail: &str) -> Result<()> {
+        let inspect = self
+            .docker
+            .get_json(&format!("/containers/{}/json", id), &[])
+            .await?;
+        let container = Container::from_inspect(&inspect)
+            .ok_or_else(|| Error::from(format!("Invalid details of container {}", id)))?;
+        if !container.matches(&self.config.containers) {
+            return Ok(());
+        }
+        let stdout = if self.config.stdout { "1" } else { "0" };
+        let stderr = if self.config.stderr { "1" } else { "0" };
+        let mut body = self
+            .docker
+            .get(
+                &format!("/containers/{}/logs", id),
+                &[
+                    ("follow", "1"),
+                    ("stdout", stdout),
+                    ("stderr", stderr),
+                    ("tail", tail),
+                ],
+            )
+            .await?
+            .take_body();
+        info!("Following container {}", container.name);
+
+        let stdout_stream = self.next_stream.fetch_add(2, Ordering::Relaxed);
+        let stderr_stream = stdout_stream + 1;
+        let stdout_meta = container.stream_meta("stdout");
+        let stderr_meta = container.stream_meta("stderr");
+        self.tx
+            .send(SourceReply::StartStream(stdout_stream))
+            .await?;
+        self.tx
+            .send(SourceReply::StartStream(stderr_stream))
+            .await?;
+        let mut buf = Vec::new();
+        let mut chunk = vec![0; BUFFER_SIZE_BYTES];
+        loop {
+            let n = body.read(&mut chunk).await?;
+            if n == 0 {
+                break;
+            }
+            // ALLOW: we define n as part of the read
+            buf.extend_from_slice(&chunk[..n]);
+            // containers with a TTY have a single raw stream
+            let frames: Vec<(u8, Vec<u8>)> = if container.tty {
+                vec![(1, std::mem::take(&mut buf))]
+            } else {
+                std::iter::from_fn(|| next_frame(&mut buf)).collect()
+            };
+            for (stream, data) in frames {
+                let (stream, meta) = if stream == 2 {
+                    (stderr_stream, &stderr_meta)
+                } else {
+                    (stdout_stream, &stdout_meta)
+                };
+                self.tx
+                    .send(SourceReply::Data {
+                        origin_uri: self.origin_uri.clone(),
+                        data,
+                        meta: Some(meta.clone()),
+                        codec_override: None,
+                        stream,
+                    })
+                    .await?;
+            }
+        }
+        info!("Container {} stopped", container.name);
+        self.tx.send(SourceReply::EndStream(stdout_stream)).await?;
+        self.tx.send(SourceReply::EndStream(stderr_stream)).await?;
+        Ok(())
+    }
+
+    /// Follows the running containers and the ones started later
+    async fn run(self) {
+        while !self.tx.is_closed() {
+            if let Err(e) = self.watch().await {
+                warn!("Failed to watch docker containers: {}", e);
+            }
+            task::sleep(Duration::from_secs(5)).await;
+        }
+    }
+
+    async fn watch(&self) -> Result<()> {
+        let labels = self.label_filters();
+        // subscribe before listing to not miss containers started in between
+        let events_filters = literal!({
+            "type": ["container"],
+            "event": ["start"],
+            "label": self.config.labels.clone()
+        })
+        .encode();
+        let mut events = self
+            .docker
+            .get("/events", &[("filters", events_filters.as_str())])
+            .await?
+            .take_body()
+            .lines();
+        let running = self
+            .docker
+            .get_json("/containers/json", &[("filters", labels.as_str())])
+            .await?;
+        for container in running.as_array().into_iter().flatten() {
+            if let Some(id) = container.get_str("Id") {
+                task::spawn(
+                    self.clone()
+                        .follow(id.to_string(), self.config.tail.clone()),
+                );
+            }
+        }
+        while let Some(line) = events.next().await {
+            let mut line = line?.into_bytes();
+            if line.is_empty() {
+                continue;
+            }
+            let event = tremor_value::parse_to_value(&mut line)?;
+            if let Some(id) = event
+                .get("Actor")
+                .get_str("ID")
+                .or_else(|| event.get_str("id"))
+            {
+                // started containers are followed from their first line
+                task::spawn(self.clone().follow(id.to_string(), "all".to_string()));
+            }
+        }
+        Ok(())
+    }
+}
+
+pub struct DockerOnramp {
+    pub config: Config,
+    onramp_id: TremorUrl,
+}
+
+impl onramp::Impl for DockerOnramp {
+    fn from_config(id: &TremorUrl, config: &Option<YamlValue>) -> Result<Box<dyn Onramp>> {
+        if let Some(config) = config {
+            let config: Config = Config::new(config)?;
+            Docker::new(&config.host)?;
+            Ok(Box::new(Self {
+                config,
+                onramp_id: id.clone(),
+            }))
+        } else {
+            Err("Missing config for docker onramp".into())
+        }
+    }
+}
+
+pub struct Int {
+    uid: u64,
+    config: Config,
+    onramp_id: TremorUrl,
+    listener: Option<Receiver<SourceReply>>,
+}
+
+impl std::fmt::Debug for Int {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "Docker")
+    }
+}
+
+impl Int {
+    fn from_config(uid: u64, onramp_id: TremorUrl, config: &Config) -> Self {
+        Self {
+            uid,
+            config: config.clone(),
+            onramp_id,
+            listener: None,
+        }
+    }
+}
+
+#[async_trait::async_trait()]
+impl Source for Int {
+    fn id(&self) -> &TremorUrl {
+        &self.onramp_id
+    }
+
+    async fn pull_event(&mut self, _id: u64) -> Result<SourceReply> {
+        self.listener.as_ref().map_or_else(
+            || Ok(SourceReply::StateChange(SourceState::Disconnected)),
+            |listener| match listener.try_recv() {
+                Ok(r) => Ok(r),
+                Err(TryRecvError::Empty) => Ok(SourceReply::Empty(10)),
+                Err(TryRecvError::Closed) => {
+                    Ok(SourceReply::StateChange(SourceState::Disconnected))
+                }
+            },
+        )
+    }
+
+    async fn init(&mut self) -> Result<SourceState> {
+        let docker = Docker::new(&self.config.host)?;
+        let (tx, rx) = bounded(crate::QSIZE);
+        let follower = Follower {
+            docker,
+            config: Arc::new(self.config.clone()),
+            tx,
+            origin_uri: EventOriginUri {
+                uid: self.uid,
+                scheme: "tremor-docker".to_string(),
+                host: hostname(),
+                port: None,
+                path: vec![self.config.host.clone()],
+            },
+            next_stream: Arc::new(AtomicUsize::new(1)),
+            followed: Arc::new(Mutex::new(HashSet::new())),
+        };
+        task::spawn(follower.run());
+        self.listener = Some(rx);
+        Ok(SourceState::Connected)
+    }
+
+    async fn terminate(&mut self) {
+        // stops following the containers
+        self.listener = None;
+    }
+}
+
+#[async_trait::async_trait]
+impl Onramp for DockerOnramp {
+    async fn start(&mut self, config: OnrampConfig<'_>) -> Result<onramp::Addr> {
+        let source = Int::from_config(config.onramp_uid, self.onramp_id.clone(), &self.config);
+        SourceManager::start(source, config).await
+    }
+
+    fn default_codec(&self) -> &str {
+        "string"
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn frames() {
+        let mut buf = vec![1, 0, 0, 0, 0, 0, 0, 5];
+        buf.extend_from_slice(b"snot\n");
+        buf.extend_from_slice(&[2, 0, 0, 0, 0, 0, 0, 7]);
+        buf.extend_from_slice(b"bad");
+        assert_eq!(next_frame(&mut buf), Some((1, b"snot\n".to_vec())));
+        assert_eq!(next_frame(&mut buf), None);
+        buf.extend_from_slice(b"ger\n");
+        assert_eq!(next_frame(&mut buf), Some((2, b"badger\n".to_vec())));
+        assert!(buf.is_empty());
+    }
+
+    #[test]
+    fn container() -> Result<()> {
+        let inspect = literal!({
+            "Id": "4b8a7e3c",
+            "Name": "/tremor",
+            "Config": {"Image": "tremorproject/tremor", "Labels": {"app": "tremor"}, "Tty": false}
+        });
+        let container =
+            Container::from_inspect(&inspect).ok_or_else(|| Error::from("no container"))?;
+        assert_eq!(container.name, "tremor");
+        assert!(container.matches(&[]));
+        assert!(container.matches(&["4b8a".to_string()]));
+        assert!(container.matches(&["tremor".to_string()]));
+        assert!(!container.matches(&["other".to_string()]));
+        assert_eq!(
+            container.stream_meta("stderr"),
+            literal!({"docker": {
+                "container_id": "4b8a7e3c",
+                "container_name": "tremor",
+                "image": "tremorproject/tremor",
+                "labels": {"app": "tremor"},
+                "stream": "stderr"
+            }})
+        );
+        Ok(())
+    }
+}
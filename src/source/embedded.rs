@@ -0,0 +1,119 @@
+// Copyright 2020-2021, The Tremor Team
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+//! # Embedded Onramp
+//!
+//! Receives the events an application embedding tremor sends through the
+//! channel it opened with `World::embedded_onramp`. Events are already
+//! structured and keep their metadata, so they are not decoded with a codec.
+//!
+//! ## Configuration
+//!
+//! See [Config](struct.Config.html) for details.
+
+use crate::embed::{self, Embedded as Input};
+use crate::source::prelude::*;
+use async_channel::TryRecvError;
+
+#[derive(Debug, Clone, Deserialize)]
+pub struct Config {
+    /// Name of the channel the application opened
+    pub channel: String,
+}
+
+impl ConfigImpl for Config {}
+
+pub struct Embedded {
+    pub config: Config,
+    onramp_id: TremorUrl,
+}
+
+pub struct Int {
+    config: Config,
+    origin_uri: EventOriginUri,
+    rx: Option<Receiver<Input>>,
+    onramp_id: TremorUrl,
+}
+
+impl std::fmt::Debug for Int {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "Embedded")
+    }
+}
+
+impl onramp::Impl for Embedded {
+    fn from_config(id: &TremorUrl, config: &Option<YamlValue>) -> Result<Box<dyn Onramp>> {
+        if let Some(config) = config {
+            let config: Config = Config::new(config)?;
+            Ok(Box::new(Self {
+                config,
+                onramp_id: id.clone(),
+            }))
+        } else {
+            Err("Missing config for embedded onramp".into())
+        }
+    }
+}
+
+#[async_trait::async_trait()]
+impl Source for Int {
+    fn id(&self) -> &TremorUrl {
+        &self.onramp_id
+    }
+
+    async fn pull_event(&mut self, _id: u64) -> Result<SourceReply> {
+        self.rx.as_ref().map_or_else(
+            || Ok(SourceReply::StateChange(SourceState::Disconnected)),
+            |rx| match rx.try_recv() {
+                Ok(event) => Ok(SourceReply::Structured {
+                    origin_uri: self.origin_uri.clone(),
+                    data: (event.data, event.meta).into(),
+                }),
+                Err(TryRecvError::Empty) => Ok(SourceReply::Empty(10)),
+                Err(TryRecvError::Closed) => {
+                    Ok(SourceReply::StateChange(SourceState::Disconnected))
+                }
+            },
+        )
+    }
+
+    async fn init(&mut self) -> Result<SourceState> {
+        self.rx = Some(embed::input(&self.config.channel)?);
+        Ok(SourceState::Connected)
+    }
+}
+
+#[async_trait::async_trait]
+impl Onramp for Embedded {
+    async fn start(&mut self, config: OnrampConfig<'_>) -> Result<onramp::Addr> {
+        let origin_uri = EventOriginUri {
+            uid: config.onramp_uid,
+            scheme: "tremor-embedded".to_string(),
+            host: hostname(),
+            port: None,
+            path: vec![self.config.channel.clone()],
+        };
+        let source = Int {
+            config: self.config.clone(),
+            origin_uri,
+            rx: None,
+            onramp_id: self.onramp_id.clone(),
+        };
+        SourceManager::start(source, config).await
+    }
+
+    fn default_codec(&self) -> &str {
+        "json"
+    }
+}
@@ -43,6 +43,8 @@ pub struct File {
 enum ArghDyn {
     Xz(Lines<BufReader<XzDecoder<BufReader<FSFile>>>>),
     File(Lines<BufReader<FSFile>>),
+    #[cfg(all(feature = "io-uring", target_os = "linux"))]
+    Uring(Lines<crate::uring::File>),
 }
 
 impl ArghDyn {
@@ -50,6 +52,8 @@ impl ArghDyn {
         match self {
             ArghDyn::Xz(l) => l.next().await,
             ArghDyn::File(l) => l.next().await,
+            #[cfg(all(feature = "io-uring", target_os = "linux"))]
+            ArghDyn::Uring(l) => l.next().await,
         }
     }
 }
@@ -69,14 +73,13 @@ impl Int {
     const SLEEP_ON_DONE_MS: u64 = 10;
 
     async fn from_config(uid: u64, onramp_id: TremorUrl, config: Config) -> Result<Self> {
-        let source_data_file = BufReader::new(file::open(&config.source).await?);
+        let source_data_file = file::open(&config.source).await?;
         let ext = file::extension(&config.source);
         let lines = if ext == Some("xz") {
-            let r = BufReader::new(XzDecoder::new(source_data_file));
+            let r = BufReader::new(XzDecoder::new(BufReader::new(source_data_file)));
             ArghDyn::Xz(r.lines())
         } else {
-            let r = source_data_file;
-            ArghDyn::File(r.lines())
+            Self::lines(source_data_file)?
         };
 
         let origin_uri = EventOriginUri {
@@ -93,6 +96,16 @@ impl Int {
             onramp_id,
         })
     }
+
+    /// Reads through io_uring if it is available
+    fn lines(source_data_file: FSFile) -> Result<ArghDyn> {
+        #[cfg(all(feature = "io-uring", target_os = "linux"))]
+        if let Some(ring) = crate::uring::ring() {
+            let r = crate::uring::File::new(ring, source_data_file)?;
+            return Ok(ArghDyn::Uring(r.lines()));
+        }
+        Ok(ArghDyn::File(BufReader::new(source_data_file).lines()))
+    }
 }
 
 impl onramp::Impl for File {
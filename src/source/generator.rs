@@ -0,0 +1,411 @@
+// Copyright 2020-2021, The Tremor Team
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+//! # Generator Onramp
+//!
+//! Emits synthetic events built from a template. Strings in the template of
+//! the form `${generator}` or `${generator:args}` are replaced with a freshly
+//! generated value for every event, everything else is emitted as is:
+//!
+//! ```yaml
+//! template:
+//!   id: "${seq}"
+//!   user: "${name}"
+//!   email: "${email}"
+//!   level: "${choice:info|warn|error}"
+//!   latency: "${float:0.5..250}"
+//!   tags: ["${word}", "${word}"]
+//! rate: 1000
+//! ramp_up: 10000000000
+//! seed: 42
+//! ```
+//!
+//! Supported generators are `seq`, `now`, `bool`, `int:min..max`,
+//! `float:min..max`, `choice:a|b|c`, `uuid`, `word`, `name`, `email` and
+//! `ipv4`.
+
+#![cfg(not(tarpaulin_include))]
+
+use crate::interpolate::placeholder;
+use crate::source::prelude::*;
+use rand::rngs::SmallRng;
+use rand::Rng;
+use std::time::Duration;
+use tremor_common::time::nanotime;
+
+const WORDS: [&str; 16] = [
+    "snot", "badger", "tremor", "event", "stream", "query", "window", "pipeline", "onramp",
+    "offramp", "codec", "select", "script", "metric", "signal", "insight",
+];
+const FIRST_NAMES: [&str; 8] = [
+    "Ada", "Alan", "Barbara", "Dennis", "Grace", "Ken", "Linus", "Margaret",
+];
+const LAST_NAMES: [&str; 8] = [
+    "Hopper",
+    "Kernighan",
+    "Liskov",
+    "Lovelace",
+    "Ritchie",
+    "Thompson",
+    "Torvalds",
+    "Turing",
+];
+const DOMAINS: [&str; 4] = ["example.com", "example.net", "example.org", "tremor.rs"];
+
+#[derive(Deserialize, Debug, Clone)]
+pub struct Config {
+    /// template of the events to generate
+    pub template: YamlValue,
+    /// events per second, unlimited if not set
+    pub rate: Option<u64>,
+    /// nanoseconds over which the rate increases linearly from zero to `rate`
+    #[serde(default = "Default::default")]
+    pub ramp_up: u64,
+    /// seed for the random generators, random if not set
+    pub seed: Option<u64>,
+    /// Number of events to stop after
+    pub iters: Option<u64>,
+}
+
+impl ConfigImpl for Config {}
+
+/// A value generator
+#[derive(Debug, Clone, PartialEq)]
+enum Generator {
+    Seq,
+    Now,
+    Bool,
+    Int(i64, i64),
+    Float(f64, f64),
+    Choice(Vec<String>),
+    Uuid,
+    Word,
+    Name,
+    Email,
+    Ipv4,
+}
+
+fn parse_range<T>(name: &str, args: Option<&str>) -> Result<(T, T)>
+where
+    T: std::str::FromStr + PartialOrd,
+{
+    let range = args.ok_or_else(|| Error::from(format!("`{}` requires a range", name)))?;
+    let (min, max) = range
+        .split_once("..")
+        .ok_or_else(|| Error::from(format!("Invalid range `{}` for `{}`", range, name)))?;
+    match (min.trim().parse::<T>(), max.trim().parse::<T>()) {
+        (Ok(min), Ok(max)) if min <= max => Ok((min, max)),
+        _ => Err(format!("Invalid range `{}` for `{}`", range, name).into()),
+    }
+}
+
+impl Generator {
+    /// Parses the inner part of a `${...}` placeholder
+    fn parse(spec: &str) -> Result<Self> {
+        let (name, args) = match spec.split_once(':') {
+            Some((name, args)) => (name, Some(args)),
+            None => (spec, None),
+        };
+        Ok(match name {
+            "seq" => Self::Seq,
+            "now" => Self::Now,
+            "bool" => Self::Bool,
+            "int" => {
+                let (min, max) = parse_range(name, args)?;
+                Self::Int(min, max)
+            }
+            "float" => {
+                let (min, max) = parse_range(name, args)?;
+                Self::Float(min, max)
+            }
+            "choice" => Self::Choice(
+                args.filter(|a| !a.is_empty())
+                    .ok_or_else(|| Error::from("`choice` requires options"))?
+                    .split('|')
+                    .map(ToString::to_string)
+                    .collect(),
+            ),
+            "uuid" => Self::Uuid,
+            "word" => Self::Word,
+            "name" => Self::Name,
+            "email" => Self::Email,
+            "ipv4" => Self::Ipv4,
+            other => return Err(format!("Unknown generator `{}`", other).into()),
+        })
+    }
+
+    fn generate(&self, rng: &mut SmallRng, seq: u64) -> Value<'static> {
+        fn pick<'a>(rng: &mut SmallRng, from: &[&'a str]) -> &'a str {
+            from[rng.gen_range(0..from.len())]
+        }
+        match self {
+            Self::Seq => Value::from(seq),
+            Self::Now => Value::from(nanotime()),
+            Self::Bool => Value::from(rng.gen::<bool>()),
+            Self::Int(min, max) => Value::from(rng.gen_range(*min..=*max)),
+            Self::Float(min, max) => Value::from(rng.gen_range(*min..=*max)),
+            Self::Choice(options) => Value::from(options[rng.gen_range(0..options.len())].clone()),
+            Self::Uuid => {
+                let (hi, lo) = (rng.gen::<u64>(), rng.gen::<u64>());
+                // version 4, variant 1
+                let hi = (hi & 0xffff_ffff_ffff_0fff) | 0x4000;
+                let lo = (lo & 0x3fff_ffff_ffff_ffff) | 0x8000_0000_0000_0000;
+                Value::from(format!(
+                    "{:08x}-{:04x}-{:04x}-{:04x}-{:012x}",
+                    hi >> 32,
+                    (hi >> 16) & 0xffff,
+                    hi & 0xffff,
+                    lo >> 48,
+                    lo & 0xffff_ffff_ffff
+                ))
+            }
+            Self::Word => Value::from(pick(rng, &WORDS)),
+            Self::Name => {
+                let first = pick(rng, &FIRST_NAMES);
+                Value::from(format!("{} {}", first, pick(rng, &LAST_NAMES)))
+            }
+            Self::Email => {
+                let first = pick(rng, &FIRST_NAMES).to_lowercase();
+                let last = pick(rng, &LAST_NAMES).to_lowercase();
+                Value::from(format!("{}.{}@{}", first, last, pick(rng, &DOMAINS)))
+            }
+            Self::Ipv4 => {
+                let ip: [u8; 4] = rng.gen();
+                Value::from(format!("{}.{}.{}.{}", ip[0], ip[1], ip[2], ip[3]))
+            }
+        }
+    }
+}
+
+/// A parsed event template
+#[derive(Debug, Clone, PartialEq)]
+enum Template {
+    Literal(Value<'static>),
+    Generator(Generator),
+    Array(Vec<Template>),
+    Object(Vec<(String, Template)>),
+}
+
+impl Template {
+    fn parse(value: &Value) -> Result<Self> {
+        Ok(match value {
+            Value::String(s) => {
+                if let Some(spec) = placeholder(s) {
+                    Self::Generator(Generator::parse(spec)?)
+                } else {
+                    Self::Literal(value.clone_static())
+                }
+            }
+            Value::Array(a) => Self::Array(a.iter().map(Self::parse).collect::<Result<_>>()?),
+            Value::Object(o) => Self::Object(
+                o.iter()
+                    .map(|(k, v)| Ok((k.to_string(), Self::parse(v)?)))
+                    .collect::<Result<_>>()?,
+            ),
+            Value::Static(_) | Value::Bytes(_) => Self::Literal(value.clone_static()),
+        })
+    }
+
+    fn generate(&self, rng: &mut SmallRng, seq: u64) -> Result<Value<'static>> {
+        Ok(match self {
+            Self::Literal(v) => v.clone(),
+            Self::Generator(g) => g.generate(rng, seq),
+            Self::Array(a) => Value::from(
+                a.iter()
+                    .map(|t| t.generate(rng, seq))
+                    .collect::<Result<Vec<_>>>()?,
+            ),
+            Self::Object(o) => {
+                let mut obj = Value::object_with_capacity(o.len());
+                for (k, t) in o {
+                    obj.insert(k.clone(), t.generate(rng, seq)?)?;
+                }
+                obj
+            }
+        })
+    }
+}
+
+/// Nanoseconds after the start at which the `n`th event is due when emitting
+/// `rate` events per second after a linear ramp up over `ramp_up` nanoseconds
+#[allow(clippy::cast_precision_loss, clippy::cast_sign_loss)]
+#[allow(clippy::cast_possible_truncation)]
+fn due_ns(n: u64, rate: u64, ramp_up: u64) -> u64 {
+    let (n, rate, ramp_up) = (n as f64, rate as f64, ramp_up as f64 / 1_000_000_000.0);
+    // events emitted during the ramp up is the area of the triangle
+    let ramp_events = rate * ramp_up / 2.0;
+    let secs = if n < ramp_events {
+        (2.0 * ramp_up * n / rate).sqrt()
+    } else {
+        n / rate + ramp_up / 2.0
+    };
+    (secs * 1_000_000_000.0) as u64
+}
+
+#[derive(Clone)]
+pub struct Generate {
+    pub config: Config,
+    onramp_id: TremorUrl,
+    template: Template,
+    rng: SmallRng,
+    count: u64,
+    start: u64,
+    origin_uri: EventOriginUri,
+}
+impl std::fmt::Debug for Generate {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "Generator")
+    }
+}
+
+impl onramp::Impl for Generate {
+    fn from_config(id: &TremorUrl, config: &Option<YamlValue>) -> Result<Box<dyn Onramp>> {
+        if let Some(config) = config {
+            let config: Config = Config::new(config)?;
+            // We use this to translate a yaml value to a tremor value
+            let mut template = simd_json::to_vec(&config.template)?;
+            let template = tremor_value::parse_to_value(&mut template)?;
+            let template = Template::parse(&template)
+                .map_err(|e| format!("[onramp:{}] Invalid template: {}", id, e))?;
+            let rng = tremor_common::rand::make_prng(config.seed.unwrap_or_else(nanotime));
+            let origin_uri = EventOriginUri {
+                uid: 0,
+                scheme: "tremor-generator".to_string(),
+                host: hostname(),
+                port: None,
+                path: vec![],
+            };
+
+            Ok(Box::new(Self {
+                config,
+                template,
+                rng,
+                count: 0,
+                start: 0,
+                origin_uri,
+                onramp_id: id.clone(),
+            }))
+        } else {
+            Err("Missing config for generator onramp".into())
+        }
+    }
+}
+
+#[async_trait::async_trait()]
+impl Source for Generate {
+    fn id(&self) -> &TremorUrl {
+        &self.onramp_id
+    }
+
+    async fn pull_event(&mut self, _id: u64) -> Result<SourceReply> {
+        if Some(self.count) == self.config.iters {
+            return Ok(SourceReply::StateChange(SourceState::Disconnected));
+        };
+        if let Some(rate) = self.config.rate.filter(|r| *r > 0) {
+            let due = self.start + due_ns(self.count, rate, self.config.ramp_up);
+            let now = nanotime();
+            if due > now {
+                task::sleep(Duration::from_nanos(due - now)).await;
+            }
+        }
+        let data = self.template.generate(&mut self.rng, self.count)?;
+        self.count += 1;
+
+        Ok(SourceReply::Structured {
+            origin_uri: self.origin_uri.clone(),
+            data: data.into(),
+        })
+    }
+    async fn init(&mut self) -> Result<SourceState> {
+        self.count = 0;
+        self.start = nanotime();
+        Ok(SourceState::Connected)
+    }
+}
+
+#[async_trait::async_trait]
+impl Onramp for Generate {
+    async fn start(&mut self, config: OnrampConfig<'_>) -> Result<onramp::Addr> {
+        self.origin_uri.uid = config.onramp_uid;
+        SourceManager::start(self.clone(), config).await
+    }
+    fn default_codec(&self) -> &str {
+        "json"
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use tremor_common::rand::make_prng;
+    use tremor_value::literal;
+
+    #[test]
+    fn parse_generators() {
+        assert_eq!(Generator::parse("seq").ok(), Some(Generator::Seq));
+        assert_eq!(
+            Generator::parse("int:1..10").ok(),
+            Some(Generator::Int(1, 10))
+        );
+        assert_eq!(
+            Generator::parse("choice:a|b").ok(),
+            Some(Generator::Choice(vec!["a".to_string(), "b".to_string()]))
+        );
+        assert!(Generator::parse("int:10..1").is_err());
+        assert!(Generator::parse("float").is_err());
+        assert!(Generator::parse("choice:").is_err());
+        assert!(Generator::parse("snot").is_err());
+    }
+
+    #[test]
+    fn seeded_template() -> Result<()> {
+        let template = Template::parse(&literal!({
+            "seq": "${seq}",
+            "n": "${int:1..3}",
+            "tags": ["${word}", "fixed"],
+            "id": "${uuid}",
+            "kind": "static",
+            "count": 7
+        }))?;
+        let a = template.generate(&mut make_prng(42), 1)?;
+        let b = template.generate(&mut make_prng(42), 1)?;
+        assert_eq!(a, b);
+        assert_eq!(a.get_u64("seq"), Some(1));
+        assert!(matches!(a.get_i64("n"), Some(1..=3)));
+        assert_eq!(a.get_str("kind"), Some("static"));
+        assert_eq!(a.get_u64("count"), Some(7));
+        assert_eq!(
+            a.get("tags").and_then(|t| t.as_array()).map(Vec::len),
+            Some(2)
+        );
+        let id = a.get_str("id").unwrap_or_default();
+        assert_eq!(id.len(), 36);
+        assert_eq!(id.chars().nth(14), Some('4'));
+        Ok(())
+    }
+
+    #[test]
+    fn ramp_up() {
+        // without ramp up events are evenly spaced
+        assert_eq!(due_ns(0, 10, 0), 0);
+        assert_eq!(due_ns(5, 10, 0), 500_000_000);
+        // 10 events/s reached after 2s, having emitted 10 events during the ramp
+        assert_eq!(due_ns(10, 10, 2_000_000_000), 2_000_000_000);
+        assert!(
+            due_ns(1, 10, 2_000_000_000)
+                > due_ns(11, 10, 2_000_000_000) - due_ns(10, 10, 2_000_000_000)
+        );
+        assert_eq!(due_ns(20, 10, 2_000_000_000), 3_000_000_000);
+    }
+}
@@ -0,0 +1,557 @@
+// Copyright 2020-2021, The Tremor Team
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+#![cfg(not(tarpaulin_include))]
+
+//! # Host Metrics Onramp
+//!
+//! Samples the CPU, memory, disk and network statistics of the host from
+//! procfs every `interval` and emits them as one event:
+//!
+//! ```json
+//! {
+//!   "host": "tremor-1",
+//!   "interval": 10000,
+//!   "cpu": {"cores": 8, "usage": 12.5, "user": 10.0, "system": 2.0, "iowait": 0.5, "steal": 0.0},
+//!   "load": {"1m": 0.5, "5m": 0.4, "15m": 0.3},
+//!   "memory": {"total": 16777216, "available": 8388608, "used": 8388608, "swap_total": 0, "swap_used": 0},
+//!   "disk": {"sda": {"reads": 10, "writes": 20, "read_bytes": 40960, "written_bytes": 81920}},
+//!   "network": {"eth0": {"rx_bytes": 1024, "rx_packets": 8, "rx_errors": 0, "tx_bytes": 512, "tx_packets": 4, "tx_errors": 0}}
+//! }
+//! ```
+//!
+//! CPU usage is in percent of the interval, disk and network counters are
+//! the deltas over the interval, memory is in bytes.
+
+use crate::source::prelude::*;
+use async_std::fs;
+use std::collections::BTreeMap;
+use std::time::Duration;
+use tremor_value::literal;
+
+const SECTOR_SIZE: u64 = 512;
+
+#[derive(Deserialize, Debug, Clone, Copy, PartialEq)]
+#[serde(rename_all = "lowercase")]
+pub enum Collector {
+    Cpu,
+    Memory,
+    Disk,
+    Network,
+}
+
+#[derive(Deserialize, Debug, Clone)]
+pub struct Config {
+    /// Interval in milliseconds (default: 10000)
+    #[serde(default = "dflt_interval")]
+    pub interval: u64,
+    /// Statistics to collect (default: all)
+    #[serde(default = "dflt_collectors")]
+    pub collectors: Vec<Collector>,
+    /// Mount point of procfs, e.g. the host's procfs mounted into a
+    /// container (default: `/proc`)
+    #[serde(default = "dflt_proc_path")]
+    pub proc_path: String,
+    /// Network interfaces to skip (default: `["lo"]`)
+    #[serde(default = "dflt_exclude_interfaces")]
+    pub exclude_interfaces: Vec<String>,
+}
+
+fn dflt_interval() -> u64 {
+    10_000
+}
+
+fn dflt_collectors() -> Vec<Collector> {
+    vec![
+        Collector::Cpu,
+        Collector::Memory,
+        Collector::Disk,
+        Collector::Network,
+    ]
+}
+
+fn dflt_proc_path() -> String {
+    "/proc".to_string()
+}
+
+fn dflt_exclude_interfaces() -> Vec<String> {
+    vec!["lo".to_string()]
+}
+
+impl ConfigImpl for Config {}
+
+/// CPU times in ticks
+#[derive(Debug, Clone, Copy, Default, PartialEq)]
+struct CpuTimes {
+    user: u64,
+    nice: u64,
+    system: u64,
+    idle: u64,
+    iowait: u64,
+    irq: u64,
+    softirq: u64,
+    steal: u64,
+}
+
+impl CpuTimes {
+    fn total(&self) -> u64 {
+        self.user
+            + self.nice
+            + self.system
+            + self.idle
+            + self.iowait
+            + self.irq
+            + self.softirq
+            + self.steal
+    }
+}
+
+/// Parses the aggregated CPU times and the number of cores of `/proc/stat`
+fn parse_stat(stat: &str) -> Option<(CpuTimes, usize)> {
+    let mut times = None;
+    let mut cores = 0;
+    for line in stat.lines() {
+        let mut fields = line.split_whitespace();
+        match fields.next() {
+            Some("cpu") => {
+                let mut next = || fields.next().and_then(|f| f.parse::<u64>().ok());
+                times = Some(CpuTimes {
+                    user: next()?,
+                    nice: next()?,
+                    system: next()?,
+                    idle: next()?,
+                    iowait: next().unwrap_or_default(),
+                    irq: next().unwrap_or_default(),
+                    softirq: next().unwrap_or_default(),
+                    steal: next().unwrap_or_default(),
+                });
+            }
+            Some(cpu) if cpu.starts_with("cpu") => cores += 1,
+            _ => (),
+        }
+    }
+    Some((times?, cores))
+}
+
+/// Parses `/proc/meminfo` into bytes by field
+fn parse_meminfo(meminfo: &str) -> BTreeMap<&str, u64> {
+    meminfo
+        .lines()
+        .filter_map(|line| {
+            let (name, value) = line.split_once(':')?;
+            let mut value = value.split_whitespace();
+            let n = value.next()?.parse::<u64>().ok()?;
+            let n = if value.next() == Some("kB") {
+                n.saturating_mul(1024)
+            } else {
+                n
+            };
+            Some((name, n))
+        })
+        .collect()
+}
+
+/// Disk counters
+#[derive(Debug, Clone, Copy, Default, PartialEq)]
+struct DiskStats {
+    reads: u64,
+    sectors_read: u64,
+    writes: u64,
+    sectors_written: u64,
+}
+
+/// Parses `/proc/diskstats`, skipping loop and ram devices
+fn parse_diskstats(diskstats: &str) -> BTreeMap<String, DiskStats> {
+    diskstats
+        .lines()
+        .filter_map(|line| {
+            let fields: Vec<&str> = line.split_whitespace().collect();
+            let name = *fields.get(2)?;
+            if name.starts_with("loop") || name.starts_with("ram") {
+                return None;
+            }
+            let field = |i: usize| fields.get(i).and_then(|f| f.parse::<u64>().ok());
+            Some((
+                name.to_string(),
+                DiskStats {
+                    reads: field(3)?,
+                    sectors_read: field(5)?,
+                    writes: field(7)?,
+                    sectors_written: field(9)?,
+                },
+            ))
+        })
+        .collect()
+}
+
+/// Network interface counters
+#[derive(Debug, Clone, Copy, Default, PartialEq)]
+struct NetStats {
+    rx_bytes: u64,
+    rx_packets: u64,
+    rx_errors: u64,
+    tx_bytes: u64,
+    tx_packets: u64,
+    tx_errors: u64,
+}
+
+/// Parses `/proc/net/dev`
+fn parse_net_dev(net_dev: &str) -> BTreeMap<String, NetStats> {
+    net_dev
+        .lines()
+        .filter_map(|line| {
+            let (name, counters) = line.split_once(':')?;
+            let counters: Vec<u64> = counters
+                .split_whitespace()
+                .map(str::parse)
+                .collect::<std::result::Result<_, _>>()
+                .ok()?;
+            let counter = |i: usize| counters.get(i).copied();
+            Some((
+                name.trim().to_string(),
+                NetStats {
+                    rx_bytes: counter(0)?,
+                    rx_packets: counter(1)?,
+                    rx_errors: counter(2)?,
+                    tx_bytes: counter(8)?,
+                    tx_packets: counter(9)?,
+                    tx_errors: counter(10)?,
+                },
+            ))
+        })
+        .collect()
+}
+
+/// Parses the 1, 5 and 15 minute load averages of `/proc/loadavg`
+fn parse_loadavg(loadavg: &str) -> Option<Value<'static>> {
+    let mut fields = loadavg.split_whitespace();
+    let mut next = || fields.next().and_then(|f| f.parse::<f64>().ok());
+    Some(literal!({ "1m": next()?, "5m": next()?, "15m": next()? }))
+}
+
+/// A sample of the counters, rates are computed from two samples
+#[derive(Debug, Clone, Default)]
+struct Sample {
+    cpu: Option<(CpuTimes, usize)>,
+    disks: BTreeMap<String, DiskStats>,
+    interfaces: BTreeMap<String, NetStats>,
+}
+
+#[allow(clippy::cast_precision_loss)]
+fn percent(ticks: u64, total: u64) -> f64 {
+    if total == 0 {
+        0.0
+    } else {
+        (ticks as f64 * 10_000.0 / total as f64).round() / 100.0
+    }
+}
+
+fn cpu_value(prev: &CpuTimes, cur: &CpuTimes, cores: usize) -> Value<'static> {
+    let total = cur.total().saturating_sub(prev.total());
+    let idle = (cur.idle + cur.iowait).saturating_sub(prev.idle + prev.iowait);
+    let user = (cur.user + cur.nice).saturating_sub(prev.user + prev.nice);
+    let system =
+        (cur.system + cur.irq + cur.softirq).saturating_sub(prev.system + prev.irq + prev.softirq);
+    literal!({
+        "cores": cores,
+        "usage": percent(total.saturating_sub(idle), total),
+        "user": percent(user, total),
+        "system": percent(system, total),
+        "iowait": percent(cur.iowait.saturating_sub(prev.iowait), total),
+        "steal": percent(cur.steal.saturating_sub(prev.steal), total)
+    })
+}
+
+fn memory_value(meminfo: &BTreeMap<&str, u64>) -> Value<'static> {
+    let get = |name: &str| meminfo.get(name).copied().unwrap_or_default();
+    let total = get("MemTotal");
+    let available = get("MemAvailable");
+    let swap_total = get("SwapTotal");
+    literal!({
+        "total": total,
+        "available": available,
+        "used": total.saturating_sub(available),
+        "swap_total": swap_total,
+        "swap_used": swap_total.saturating_sub(get("SwapFree"))
+    })
+}
+
+fn disk_value(
+    prev: &BTreeMap<String, DiskStats>,
+    cur: &BTreeMap<String, DiskStats>,
+) -> Value<'static> {
+    let mut disks = Value::object();
+    for (name, cur) in cur {
+        let prev = prev.get(name).copied().unwrap_or(*cur);
+        let sectors_read = cur.sectors_read.saturating_sub(prev.sectors_read);
+        let sectors_written = cur.sectors_written.saturating_sub(prev.sectors_written);
+        disks.try_insert(
+            name.clone(),
+            literal!({
+                "reads": cur.reads.saturating_sub(prev.reads),
+                "writes": cur.writes.saturating_sub(prev.writes),
+                "read_bytes": sectors_read * SECTOR_SIZE,
+                "written_bytes": sectors_written * SECTOR_SIZE
+            }),
+        );
+    }
+    disks
+}
+
+fn network_value(
+    prev: &BTreeMap<String, NetStats>,
+    cur: &BTreeMap<String, NetStats>,
+) -> Value<'static> {
+    let mut interfaces = Value::object();
+    for (name, cur) in cur {
+        let prev = prev.get(name).copied().unwrap_or(*cur);
+        interfaces.try_insert(
+            name.clone(),
+            literal!({
+                "rx_bytes": cur.rx_bytes.saturating_sub(prev.rx_bytes),
+                "rx_packets": cur.rx_packets.saturating_sub(prev.rx_packets),
+                "rx_errors": cur.rx_errors.saturating_sub(prev.rx_errors),
+                "tx_bytes": cur.tx_bytes.saturating_sub(prev.tx_bytes),
+                "tx_packets": cur.tx_packets.saturating_sub(prev.tx_packets),
+                "tx_errors": cur.tx_errors.saturating_sub(prev.tx_errors)
+            }),
+        );
+    }
+    interfaces
+}
+
+#[derive(Clone, Debug)]
+pub struct HostMetrics {
+    pub config: Config,
+    onramp_id: TremorUrl,
+}
+
+impl onramp::Impl for HostMetrics {
+    fn from_config(id: &TremorUrl, config: &Option<YamlValue>) -> Result<Box<dyn Onramp>> {
+        if let Some(config) = config {
+            let config: Config = Config::new(config)?;
+            if config.interval == 0 {
+                return Err("The host metrics interval must be positive".into());
+            }
+            Ok(Box::new(Self {
+                config,
+                onramp_id: id.clone(),
+            }))
+        } else {
+            Err("Missing config for host-metrics onramp".into())
+        }
+    }
+}
+
+struct Int {
+    config: Config,
+    onramp_id: TremorUrl,
+    origin_uri: EventOriginUri,
+    host: String,
+    previous: Sample,
+}
+
+impl std::fmt::Debug for Int {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "HostMetrics")
+    }
+}
+
+impl Int {
+    fn from_config(uid: u64, onramp_id: TremorUrl, config: &Config) -> Self {
+        let host = hostname();
+        let origin_uri = EventOriginUri {
+            uid,
+            scheme: "tremor-host-metrics".to_string(),
+            host: host.clone(),
+            port: None,
+            path: vec![config.proc_path.clone()],
+        };
+        Self {
+            config: config.clone(),
+            onramp_id,
+            origin_uri,
+            host,
+            previous: Sample::default(),
+        }
+    }
+
+    fn collects(&self, collector: Collector) -> bool {
+        self.config.collectors.contains(&collector)
+    }
+
+    async fn read(&self, file: &str) -> Result<String> {
+        Ok(fs::read_to_string(format!("{}/{}", self.config.proc_path, file)).await?)
+    }
+
+    /// Reads the counters, rates are computed against the previous sample
+    async fn sample(&self) -> Result<Sample> {
+        let mut sample = Sample::default();
+        if self.collects(Collector::Cpu) {
+            sample.cpu = parse_stat(&self.read("stat").await?);
+        }
+        if self.collects(Collector::Disk) {
+            sample.disks = parse_diskstats(&self.read("diskstats").await?);
+        }
+        if self.collects(Collector::Network) {
+            sample.interfaces = parse_net_dev(&self.read("net/dev").await?);
+            sample
+                .interfaces
+                .retain(|name, _| !self.config.exclude_interfaces.contains(name));
+        }
+        Ok(sample)
+    }
+}
+
+#[async_trait::async_trait()]
+impl Source for Int {
+    fn id(&self) -> &TremorUrl {
+        &self.onramp_id
+    }
+
+    async fn pull_event(&mut self, _id: u64) -> Result<SourceReply> {
+        task::sleep(Duration::from_millis(self.config.interval)).await;
+        let sample = self.sample().await?;
+        let mut data = literal!({
+            "host": self.host.clone(),
+            "interval": self.config.interval
+        });
+        if let (Some((prev, _)), Some((cur, cores))) = (&self.previous.cpu, &sample.cpu) {
+            data.try_insert("cpu", cpu_value(prev, cur, *cores));
+            if let Some(load) = parse_loadavg(&self.read("loadavg").await?) {
+                data.try_insert("load", load);
+            }
+        }
+        if self.collects(Collector::Memory) {
+            data.try_insert(
+                "memory",
+                memory_value(&parse_meminfo(&self.read("meminfo").await?)),
+            );
+        }
+        if self.collects(Collector::Disk) {
+            data.try_insert("disk", disk_value(&self.previous.disks, &sample.disks));
+        }
+        if self.collects(Collector::Network) {
+            data.try_insert(
+                "network",
+                network_value(&self.previous.interfaces, &sample.interfaces),
+            );
+        }
+        self.previous = sample;
+        Ok(SourceReply::Structured {
+            origin_uri: self.origin_uri.clone(),
+            data: data.into(),
+        })
+    }
+
+    async fn init(&mut self) -> Result<SourceState> {
+        self.previous = self.sample().await?;
+        Ok(SourceState::Connected)
+    }
+}
+
+#[async_trait::async_trait]
+impl Onramp for HostMetrics {
+    async fn start(&mut self, config: OnrampConfig<'_>) -> Result<onramp::Addr> {
+        let source = Int::from_config(config.onramp_uid, self.onramp_id.clone(), &self.config);
+        SourceManager::start(source, config).await
+    }
+
+    fn default_codec(&self) -> &str {
+        "json"
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn cpu() -> Result<()> {
+        let stat = "cpu  100 0 50 800 50 0 0 0 0 0\ncpu0 50 0 25 400 25 0 0 0 0 0\ncpu1 50 0 25 400 25 0 0 0 0 0\nintr 1234\n";
+        let (prev, cores) = parse_stat(stat).ok_or_else(|| Error::from("no cpu"))?;
+        assert_eq!(cores, 2);
+        let cur = CpuTimes {
+            user: 150,
+            system: 75,
+            idle: 850,
+            iowait: 75,
+            ..prev
+        };
+        assert_eq!(
+            cpu_value(&prev, &cur, cores),
+            literal!({
+                "cores": 2,
+                "usage": 50.0,
+                "user": 33.33,
+                "system": 16.67,
+                "iowait": 16.67,
+                "steal": 0.0
+            })
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn memory() {
+        let meminfo = "MemTotal:       16384 kB\nMemFree:         1024 kB\nMemAvailable:    4096 kB\nSwapTotal:       2048 kB\nSwapFree:        2048 kB\nHugePages_Total:       0\n";
+        let meminfo = parse_meminfo(meminfo);
+        assert_eq!(meminfo.get("HugePages_Total"), Some(&0));
+        assert_eq!(
+            memory_value(&meminfo),
+            literal!({
+                "total": 16_777_216,
+                "available": 4_194_304,
+                "used": 12_582_912,
+                "swap_total": 2_097_152,
+                "swap_used": 0
+            })
+        );
+    }
+
+    #[test]
+    fn disks() {
+        let prev = parse_diskstats(
+            "   7       0 loop0 10 0 20 0 0 0 0 0 0 0 0\n   8       0 sda 100 0 200 0 50 0 400 0 0 0 0\n",
+        );
+        assert_eq!(prev.len(), 1);
+        let cur = parse_diskstats("   8       0 sda 110 0 208 0 60 0 420 0 0 0 0\n");
+        assert_eq!(
+            disk_value(&prev, &cur),
+            literal!({"sda": {
+                "reads": 10, "writes": 10, "read_bytes": 4096, "written_bytes": 10240
+            }})
+        );
+    }
+
+    #[test]
+    fn network() {
+        let net_dev = "Inter-|   Receive                                                |  Transmit
+ face |bytes    packets errs drop fifo frame compressed multicast|bytes    packets errs drop fifo colls carrier compressed
+  eth0: 1000 10 0 0 0 0 0 0 2000 20 1 0 0 0 0 0
+";
+        let prev = parse_net_dev(net_dev);
+        let cur = parse_net_dev(&net_dev.replace("1000 10", "1500 15"));
+        assert_eq!(
+            network_value(&prev, &cur),
+            literal!({"eth0": {
+                "rx_bytes": 500, "rx_packets": 5, "rx_errors": 0,
+                "tx_bytes": 0, "tx_packets": 0, "tx_errors": 0
+            }})
+        );
+        assert_eq!(
+            parse_loadavg("0.50 0.40 0.30 1/123 4567"),
+            Some(literal!({"1m": 0.5, "5m": 0.4, "15m": 0.3}))
+        );
+    }
+}
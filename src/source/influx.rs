@@ -0,0 +1,380 @@
+// Copyright 2020-2021, The Tremor Team
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+#![cfg(not(tarpaulin_include))]
+
+//! # Influx Onramp
+//!
+//! Emulates the write endpoints of InfluxDB, `/write` of the 1.x API and
+//! `/api/v2/write`, so Telegraf and other agents can write to tremor. Every
+//! line becomes an event of the same shape as the `influx` codec decodes,
+//! with timestamps in nanoseconds regardless of the `precision` of the
+//! request. The database or bucket is put into `$influx.db`.
+//!
+//! `/ping` is answered for health checks, gzip encoded bodies are supported.
+
+use crate::source::prelude::*;
+use async_channel::{Sender, TryRecvError};
+use http_types::StatusCode;
+use std::io::Read;
+use std::sync::Arc;
+use tide::{Request, Response};
+use tremor_common::time::nanotime;
+use tremor_influx as influx;
+use tremor_value::literal;
+
+#[derive(Debug, Clone, Deserialize)]
+pub struct Config {
+    /// host to listen to, defaults to "0.0.0.0"
+    #[serde(default = "dflt_host")]
+    pub host: String,
+    /// port to listen to, defaults to 8086 like InfluxDB
+    #[serde(default = "dflt_port")]
+    pub port: u16,
+    /// token writers have to present, as `Authorization: Token <token>`
+    /// or as password, writes aren't authenticated if not set
+    pub token: Option<String>,
+    /// maximum size of a request body in bytes, defaults to 25MB like InfluxDB
+    #[serde(default = "dflt_max_body_size")]
+    pub max_body_size: usize,
+}
+
+fn dflt_host() -> String {
+    String::from("0.0.0.0")
+}
+
+fn dflt_port() -> u16 {
+    8086
+}
+
+fn dflt_max_body_size() -> usize {
+    25 * 1024 * 1024
+}
+
+impl ConfigImpl for Config {}
+
+/// Nanoseconds per unit of the `precision` of a write
+fn precision(precision: Option<&str>) -> Option<u64> {
+    match precision.unwrap_or("ns") {
+        "ns" | "n" => Some(1),
+        "us" | "u" => Some(1_000),
+        "ms" => Some(1_000_000),
+        "s" => Some(1_000_000_000),
+        "m" => Some(60_000_000_000),
+        "h" => Some(3_600_000_000_000),
+        _ => None,
+    }
+}
+
+/// Parses the lines of a write, timestamps are converted to nanoseconds and
+/// points without a timestamp get `now`
+fn parse(body: &str, factor: u64, now: u64) -> std::result::Result<Vec<Value<'static>>, String> {
+    let mut points = Vec::new();
+    for (i, line) in body.lines().enumerate() {
+        // points without a timestamp get the time of the write in the
+        // precision of the write
+        let point: Option<Value> = influx::decode(line, now / factor)
+            .map_err(|e| format!("unable to parse line {}: {}", i + 1, e))?;
+        if let Some(mut point) = point {
+            if factor != 1 {
+                let timestamp = point
+                    .get_u64("timestamp")
+                    .and_then(|ts| ts.checked_mul(factor))
+                    .ok_or_else(|| format!("timestamp out of range on line {}", i + 1))?;
+                if let Some(point) = point.as_object_mut() {
+                    point.insert("timestamp".into(), Value::from(timestamp));
+                }
+            }
+            points.push(point.into_static());
+        }
+    }
+    Ok(points)
+}
+
+pub struct Influx {
+    pub config: Config,
+    onramp_id: TremorUrl,
+}
+
+impl onramp::Impl for Influx {
+    fn from_config(id: &TremorUrl, config: &Option<YamlValue>) -> Result<Box<dyn Onramp>> {
+        if let Some(config) = config {
+            let config: Config = Config::new(config)?;
+            Ok(Box::new(Self {
+                config,
+                onramp_id: id.clone(),
+            }))
+        } else {
+            Err("Missing config for influx onramp".into())
+        }
+    }
+}
+
+#[derive(Clone)]
+struct ServerState {
+    tx: Sender<(Value<'static>, Value<'static>)>,
+    config: Arc<Config>,
+}
+
+/// An error in the format of InfluxDB
+fn error(status: StatusCode, msg: &str) -> Response {
+    Response::builder(status)
+        .header("X-Influxdb-Error", msg)
+        .body(literal!({ "error": msg.to_string() }).encode())
+        .content_type(http_types::mime::JSON)
+        .build()
+}
+
+fn authorized(req: &Request<ServerState>, token: &str) -> bool {
+    let header = req
+        .header("Authorization")
+        .map(|v| v.last().as_str().to_string());
+    let presented = header
+        .as_deref()
+        .and_then(|h| h.strip_prefix("Token "))
+        .map(ToString::to_string)
+        .or_else(|| {
+            req.url()
+                .query_pairs()
+                .find(|(k, _)| k == "p")
+                .map(|(_, v)| v.to_string())
+        });
+    presented.map_or(false, |p| p == token)
+}
+
+async fn ping(_req: Request<ServerState>) -> tide::Result<Response> {
+    Ok(Response::builder(StatusCode::NoContent)
+        .header("X-Influxdb-Version", "tremor")
+        .build())
+}
+
+async fn write(mut req: Request<ServerState>) -> tide::Result<Response> {
+    let state = req.state().clone();
+    if let Some(token) = &state.config.token {
+        if !authorized(&req, token) {
+            return Ok(error(StatusCode::Unauthorized, "authorization failed"));
+        }
+    }
+    let param = |name: &str| {
+        req.url()
+            .query_pairs()
+            .find(|(k, _)| k == name)
+            .map(|(_, v)| v.to_string())
+    };
+    let factor = match precision(param("precision").as_deref()) {
+        Some(factor) => factor,
+        None => return Ok(error(StatusCode::BadRequest, "invalid precision")),
+    };
+    let db = param("db").or_else(|| param("bucket"));
+    let gzip = req
+        .header("Content-Encoding")
+        .map_or(false, |v| v.last().as_str().eq_ignore_ascii_case("gzip"));
+
+    let max_body_size = state.config.max_body_size;
+    if req.len().map_or(false, |len| len > max_body_size) {
+        return Ok(error(StatusCode::PayloadTooLarge, "request too large"));
+    }
+    let mut body = req.body_bytes().await?;
+    if gzip {
+        let mut decoded = Vec::new();
+        let read = libflate::gzip::Decoder::new(body.as_slice()).and_then(|decoder| {
+            decoder
+                .take(
+                    u64::try_from(max_body_size)
+                        .unwrap_or(u64::MAX)
+                        .saturating_add(1),
+                )
+                .read_to_end(&mut decoded)
+        });
+        if let Err(e) = read {
+            return Ok(error(StatusCode::BadRequest, &e.to_string()));
+        }
+        body = decoded;
+    }
+    if body.len() > max_body_size {
+        return Ok(error(StatusCode::PayloadTooLarge, "request too large"));
+    }
+    let body = match std::str::from_utf8(&body) {
+        Ok(body) => body,
+        Err(e) => return Ok(error(StatusCode::BadRequest, &e.to_string())),
+    };
+    let points = match parse(body, factor, nanotime()) {
+        Ok(points) => points,
+        Err(e) => return Ok(error(StatusCode::BadRequest, &e)),
+    };
+    let meta = literal!({ "influx": { "db": db } });
+    for point in points {
+        if state.tx.send((point, meta.clone())).await.is_err() {
+            return Ok(error(StatusCode::ServiceUnavailable, "onramp stopped"));
+        }
+    }
+    Ok(Response::new(StatusCode::NoContent))
+}
+
+pub struct Int {
+    config: Config,
+    onramp_id: TremorUrl,
+    origin_uri: EventOriginUri,
+    listener: Option<Receiver<(Value<'static>, Value<'static>)>>,
+}
+
+impl std::fmt::Debug for Int {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "Influx")
+    }
+}
+
+impl Int {
+    fn from_config(uid: u64, onramp_id: TremorUrl, config: &Config) -> Self {
+        let origin_uri = EventOriginUri {
+            uid,
+            scheme: "tremor-influx".to_string(),
+            host: hostname(),
+            port: Some(config.port),
+            path: vec![],
+        };
+        Self {
+            config: config.clone(),
+            onramp_id,
+            origin_uri,
+            listener: None,
+        }
+    }
+}
+
+#[async_trait::async_trait()]
+impl Source for Int {
+    fn id(&self) -> &TremorUrl {
+        &self.onramp_id
+    }
+
+    async fn pull_event(&mut self, _id: u64) -> Result<SourceReply> {
+        if let Some(listener) = &self.listener {
+            match listener.try_recv() {
+                Ok(data) => Ok(SourceReply::Structured {
+                    origin_uri: self.origin_uri.clone(),
+                    data: data.into(),
+                }),
+                Err(TryRecvError::Empty) => Ok(SourceReply::Empty(10)),
+                Err(TryRecvError::Closed) => {
+                    Ok(SourceReply::StateChange(SourceState::Disconnected))
+                }
+            }
+        } else {
+            Ok(SourceReply::StateChange(SourceState::Disconnected))
+        }
+    }
+
+    async fn init(&mut self) -> Result<SourceState> {
+        let (tx, rx) = bounded(crate::QSIZE);
+        let mut server = tide::Server::with_state(ServerState {
+            tx,
+            config: Arc::new(self.config.clone()),
+        });
+        server.at("/write").post(write);
+        server.at("/api/v2/write").post(write);
+        server.at("/ping").get(ping).head(ping);
+
+        let addr = format!("{}:{}", self.config.host, self.config.port);
+        let source_id = self.onramp_id.to_string();
+        task::spawn(async move {
+            info!("[Source::{}] Listening at {}", source_id, addr);
+            if let Err(e) = server.listen(addr).await {
+                error!(
+                    "[Source::{}] Error while listening for influx writes: {}",
+                    source_id, e
+                );
+            }
+            warn!("[Source::{}] Server stopped", source_id);
+        });
+        self.listener = Some(rx);
+
+        Ok(SourceState::Connected)
+    }
+}
+
+#[async_trait::async_trait]
+impl Onramp for Influx {
+    async fn start(&mut self, config: OnrampConfig<'_>) -> Result<onramp::Addr> {
+        let source = Int::from_config(config.onramp_uid, self.onramp_id.clone(), &self.config);
+        SourceManager::start(source, config).await
+    }
+
+    fn default_codec(&self) -> &str {
+        "json"
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn lines() -> Result<()> {
+        let body = "# a comment\n\
+                    weather,location=us-midwest temperature=82 1465839830100400200\n\
+                    \n\
+                    cpu,host=snot usage_idle=99.5,cores=4i\n";
+        let points = parse(body, 1, 1_000)?;
+        assert_eq!(
+            points,
+            vec![
+                literal!({
+                    "measurement": "weather",
+                    "tags": {"location": "us-midwest"},
+                    "fields": {"temperature": 82.0},
+                    "timestamp": 1_465_839_830_100_400_200_u64
+                }),
+                literal!({
+                    "measurement": "cpu",
+                    "tags": {"host": "snot"},
+                    "fields": {"usage_idle": 99.5, "cores": 4},
+                    "timestamp": 1_000
+                }),
+            ]
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn precisions() -> Result<()> {
+        let factor = precision(Some("s")).ok_or_else(|| Error::from("no precision"))?;
+        let points = parse(
+            "m v=1i 1465839830\nm v=2i",
+            factor,
+            1_465_839_831_500_000_000,
+        )?;
+        assert_eq!(
+            points
+                .iter()
+                .map(|p| p.get_u64("timestamp"))
+                .collect::<Vec<_>>(),
+            vec![
+                Some(1_465_839_830_000_000_000),
+                Some(1_465_839_831_000_000_000)
+            ]
+        );
+        assert_eq!(precision(None), Some(1));
+        assert_eq!(precision(Some("ms")), Some(1_000_000));
+        assert_eq!(precision(Some("d")), None);
+        assert!(parse("m v=1i 18446744073709551615", factor, 0).is_err());
+        Ok(())
+    }
+
+    #[test]
+    fn invalid_line() {
+        assert!(
+            matches!(parse("m v=1i\nm,t= v=", 1, 0), Err(e) if e.starts_with("unable to parse line 2"))
+        );
+    }
+}
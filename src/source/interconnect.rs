@@ -0,0 +1,178 @@
+// Copyright 2020-2021, The Tremor Team
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+#![cfg(not(tarpaulin_include))]
+#![cfg(not(tarpaulin_include))]
+
+//! # Interconnect Onramp
+//!
+//! Receives the events interconnect offramps on other tremor nodes send to
+//! the pipelines of this node. Each event goes to the linked instances of
+//! the pipeline it is addressed to by name, so one onramp serves all
+//! pipelines of the node, e.g. the aggregation tier of edge nodes.
+//!
+//! Frames are acknowledged once the onramp took the event, which it only
+//! does while the pipelines keep up, so slow pipelines slow down the
+//! senders.
+//!
+//! ## Configuration
+//!
+//! See [Config](struct.Config.html) for details.
+
+use crate::connectors::interconnect;
+use crate::errors::Result;
+use crate::source::prelude::*;
+use async_channel::{Sender, TryRecvError};
+use async_std::net::{TcpListener, TcpStream};
+
+#[derive(Debug, Clone, Deserialize)]
+pub struct Config {
+    /// Host to listen on
+    pub host: String,
+    pub port: u16,
+}
+
+impl ConfigImpl for Config {}
+
+pub struct Interconnect {
+    pub config: Config,
+    onramp_id: TremorUrl,
+}
+
+pub struct Int {
+    uid: u64,
+    config: Config,
+    listener: Option<Receiver<SourceReply>>,
+    onramp_id: TremorUrl,
+}
+
+impl std::fmt::Debug for Int {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "Interconnect")
+    }
+}
+
+impl onramp::Impl for Interconnect {
+    fn from_config(id: &TremorUrl, config: &Option<YamlValue>) -> Result<Box<dyn Onramp>> {
+        if let Some(config) = config {
+            let config: Config = Config::new(config)?;
+            Ok(Box::new(Self {
+                config,
+                onramp_id: id.clone(),
+            }))
+        } else {
+            Err("Missing config for interconnect onramp".into())
+        }
+    }
+}
+
+/// Hands the events of a connection to the onramp and acknowledges them
+async fn read_loop(stream: TcpStream, tx: Sender<SourceReply>, origin_uri: EventOriginUri) {
+    let mut reader = stream.clone();
+    let mut writer = stream;
+    let mut accepted = 0_u64;
+    loop {
+        let frame = match interconnect::read(&mut reader).await {
+            Ok(Some(frame)) => frame,
+            Ok(None) => break,
+            Err(e) => {
+                warn!(
+                    "[Source::Interconnect] Connection from {} failed: {}",
+                    origin_uri.host, e
+                );
+                break;
+            }
+        };
+        let reply = SourceReply::Routed {
+            origin_uri: origin_uri.clone(),
+            data: (frame.data, frame.meta).into(),
+            pipeline: frame.pipeline,
+        };
+        if tx.send(reply).await.is_err() {
+            return;
+        }
+        accepted += 1;
+        if let Err(e) = interconnect::ack(&mut writer, accepted).await {
+            warn!(
+                "[Source::Interconnect] Connection from {} failed: {}",
+                origin_uri.host, e
+            );
+            break;
+        }
+    }
+    debug!(
+        "[Source::Interconnect] Connection from {} closed",
+        origin_uri.host
+    );
+}
+
+#[async_trait::async_trait()]
+impl Source for Int {
+    fn id(&self) -> &TremorUrl {
+        &self.onramp_id
+    }
+
+    async fn pull_event(&mut self, _id: u64) -> Result<SourceReply> {
+        self.listener.as_ref().map_or_else(
+            || Ok(SourceReply::StateChange(SourceState::Disconnected)),
+            |listener| match listener.try_recv() {
+                Ok(r) => Ok(r),
+                Err(TryRecvError::Empty) => Ok(SourceReply::Empty(10)),
+                Err(TryRecvError::Closed) => {
+                    Ok(SourceReply::StateChange(SourceState::Disconnected))
+                }
+            },
+        )
+    }
+
+    async fn init(&mut self) -> Result<SourceState> {
+        let listener = TcpListener::bind((self.config.host.as_str(), self.config.port)).await?;
+        let (tx, rx) = bounded(crate::QSIZE);
+        let uid = self.uid;
+        let path = vec![self.config.port.to_string()];
+        task::spawn(async move {
+            while let Ok((stream, peer)) = listener.accept().await {
+                if let Err(e) = stream.set_nodelay(true) {
+                    warn!("[Source::Interconnect] Failed to set nodelay: {}", e);
+                }
+                let origin_uri = EventOriginUri {
+                    uid,
+                    scheme: "tremor-interconnect".to_string(),
+                    host: peer.ip().to_string(),
+                    port: Some(peer.port()),
+                    path: path.clone(),
+                };
+                task::spawn(read_loop(stream, tx.clone(), origin_uri));
+            }
+        });
+        self.listener = Some(rx);
+        Ok(SourceState::Connected)
+    }
+}
+
+#[async_trait::async_trait]
+impl Onramp for Interconnect {
+    async fn start(&mut self, config: OnrampConfig<'_>) -> Result<onramp::Addr> {
+        let source = Int {
+            uid: config.onramp_uid,
+            config: self.config.clone(),
+            listener: None,
+            onramp_id: self.onramp_id.clone(),
+        };
+        SourceManager::start(source, config).await
+    }
+
+    fn default_codec(&self) -> &str {
+        "json"
+    }
+}
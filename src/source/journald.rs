@@ -0,0 +1,410 @@
+// Copyright 2020-2021, The Tremor Team
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+#![cfg(not(tarpaulin_include))]
+
+//! # Systemd Journal Onramp
+//!
+//! Follows the systemd journal via `journalctl`. Entries are mapped to
+//! events with lower case field names:
+//!
+//! | journal field          | event field  |
+//! |------------------------|--------------|
+//! | `MESSAGE`              | `message`    |
+//! | `PRIORITY`             | `priority`   |
+//! | `_HOSTNAME`            | `host`       |
+//! | `_SYSTEMD_UNIT`        | `unit`       |
+//! | `SYSLOG_IDENTIFIER`    | `identifier` |
+//! | `_PID`                 | `pid`        |
+//! | `__REALTIME_TIMESTAMP` | `timestamp`  |
+//!
+//! Further fields can be mapped with `fields`. With a `cursor_file` the
+//! cursor of the last acknowledged entry is persisted and the journal is
+//! resumed after it.
+
+use crate::source::prelude::*;
+use async_channel::{Sender, TryRecvError};
+use async_std::io::BufReader;
+use async_std::process::{Child, Command, Stdio};
+use halfbrown::HashMap;
+use std::collections::BTreeMap;
+use std::fs;
+use std::path::Path;
+
+/// Journal fields with their event field, numbers are converted
+const FIELDS: [(&str, &str, bool); 6] = [
+    ("MESSAGE", "message", false),
+    ("PRIORITY", "priority", true),
+    ("_HOSTNAME", "host", false),
+    ("_SYSTEMD_UNIT", "unit", false),
+    ("SYSLOG_IDENTIFIER", "identifier", false),
+    ("_PID", "pid", true),
+];
+
+#[derive(Debug, Clone, Deserialize)]
+pub struct Config {
+    /// Units to read entries of, all units if empty
+    #[serde(default)]
+    pub units: Vec<String>,
+    /// Maximum priority, a name like `warning` or a range like `0..4`
+    pub priority: Option<String>,
+    /// Further journal fields mapped to event fields
+    #[serde(default)]
+    pub fields: HashMap<String, String>,
+    /// Adds all fields of the entry as `journal`
+    #[serde(default)]
+    pub raw: bool,
+    /// File to persist the cursor of the last acknowledged entry in
+    pub cursor_file: Option<String>,
+    /// Reads the whole journal when there is no cursor, otherwise only new
+    /// entries are read
+    #[serde(default)]
+    pub from_start: bool,
+}
+
+impl ConfigImpl for Config {}
+
+pub struct Journald {
+    pub config: Config,
+    onramp_id: TremorUrl,
+}
+
+impl onramp::Impl for Journald {
+    fn from_config(id: &TremorUrl, config: &Option<YamlValue>) -> Result<Box<dyn Onramp>> {
+        if let Some(config) = config {
+            let config: Config = Config::new(config)?;
+            Ok(Box::new(Self {
+                config,
+                onramp_id: id.clone(),
+            }))
+        } else {
+            Err("Missing config for journald onramp".into())
+        }
+    }
+}
+
+/// A field value, binary values are arrays of bytes
+fn field_str(value: &Value) -> Option<String> {
+    if let Some(s) = value.as_str() {
+        Some(s.to_string())
+    } else {
+        let bytes: Option<Vec<u8>> = value.as_array()?.iter().map(Value::as_u8).collect();
+        Some(String::from_utf8_lossy(&bytes?).to_string())
+    }
+}
+
+/// Maps a journal entry to an event, returning it with its cursor
+fn map_entry(
+    entry: &Value,
+    fields: &HashMap<String, String>,
+    raw: bool,
+) -> (Value<'static>, Option<String>) {
+    let mut event = Value::object();
+    for (field, name, numeric) in FIELDS {
+        if let Some(value) = entry.get(field).and_then(field_str) {
+            if numeric {
+                if let Ok(n) = value.parse::<u64>() {
+                    event.try_insert(name, n);
+                    continue;
+                }
+            }
+            event.try_insert(name, value);
+        }
+    }
+    if let Some(timestamp) = entry
+        .get_str("__REALTIME_TIMESTAMP")
+        .and_then(|us| us.parse::<u64>().ok())
+    {
+        event.try_insert("timestamp", timestamp.saturating_mul(1000));
+    }
+    for (field, name) in fields {
+        if let Some(value) = entry.get(field.as_str()).and_then(field_str) {
+            event.try_insert(name.clone(), value);
+        }
+    }
+    if raw {
+        event.try_insert("journal", entry.clone_static());
+    }
+    let cursor = entry.get_str("__CURSOR").map(ToString::to_string);
+    (event, cursor)
+}
+
+fn read_cursor(path: &str) -> Result<Option<String>> {
+    match fs::read_to_string(path) {
+        Ok(cursor) if cursor.trim().is_empty() => Ok(None),
+        Ok(cursor) => Ok(Some(cursor.trim().to_string())),
+        Err(e) if e.kind() == std::io::ErrorKind::NotFound => Ok(None),
+        Err(e) => Err(e.into()),
+    }
+}
+
+fn write_cursor(path: &str, cursor: &str) -> Result<()> {
+    let tmp = Path::new(path).with_extension("tmp");
+    fs::write(&tmp, cursor)?;
+    fs::rename(&tmp, path)?;
+    Ok(())
+}
+
+/// The `journalctl` arguments to follow the journal
+fn journalctl_args(config: &Config, cursor: Option<&str>) -> Vec<String> {
+    let mut args = vec!["--follow".to_string(), "--output=json".to_string()];
+    if let Some(cursor) = cursor {
+        args.push("--lines=all".to_string());
+        args.push(format!("--after-cursor={}", cursor));
+    } else if config.from_start {
+        args.push("--lines=all".to_string());
+    } else {
+        args.push("--lines=0".to_string());
+    }
+    for unit in &config.units {
+        args.push(format!("--unit={}", unit));
+    }
+    if let Some(priority) = &config.priority {
+        args.push(format!("--priority={}", priority));
+    }
+    args
+}
+
+/// Reads the entries journalctl writes to stdout
+fn spawn_reader(
+    child: &mut Child,
+    config: Config,
+    tx: Sender<(Value<'static>, Option<String>)>,
+) -> Result<()> {
+    let stdout = child
+        .stdout
+        .take()
+        .ok_or_else(|| Error::from("journalctl has no stdout"))?;
+    let mut lines = BufReader::new(stdout).lines();
+    task::spawn(async move {
+        while let Some(line) = lines.next().await {
+            let mut line = match line {
+                Ok(line) => line.into_bytes(),
+                Err(e) => {
+                    error!("Failed to read the journal: {}", e);
+                    break;
+                }
+            };
+            match tremor_value::parse_to_value(&mut line) {
+                Ok(entry) => {
+                    if tx
+                        .send(map_entry(&entry, &config.fields, config.raw))
+                        .await
+                        .is_err()
+                    {
+                        break;
+                    }
+                }
+                Err(e) => warn!("Invalid journal entry: {}", e),
+            }
+        }
+    });
+    Ok(())
+}
+
+pub struct Int {
+    config: Config,
+    onramp_id: TremorUrl,
+    origin_uri: EventOriginUri,
+    child: Option<Child>,
+    listener: Option<Receiver<(Value<'static>, Option<String>)>>,
+    /// cursors by event id until they are acknowledged
+    in_flight: BTreeMap<u64, String>,
+}
+
+impl std::fmt::Debug for Int {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "Journald")
+    }
+}
+
+impl Int {
+    fn from_config(uid: u64, onramp_id: TremorUrl, config: &Config) -> Self {
+        let origin_uri = EventOriginUri {
+            uid,
+            scheme: "tremor-journald".to_string(),
+            host: hostname(),
+            port: None,
+            path: config.units.clone(),
+        };
+        Self {
+            config: config.clone(),
+            onramp_id,
+            origin_uri,
+            child: None,
+            listener: None,
+            in_flight: BTreeMap::new(),
+        }
+    }
+}
+
+#[async_trait::async_trait()]
+impl Source for Int {
+    fn id(&self) -> &TremorUrl {
+        &self.onramp_id
+    }
+
+    async fn pull_event(&mut self, id: u64) -> Result<SourceReply> {
+        if let Some(listener) = &self.listener {
+            match listener.try_recv() {
+                Ok((event, cursor)) => {
+                    if let (Some(cursor), true) = (cursor, self.is_transactional()) {
+                        self.in_flight.insert(id, cursor);
+                    }
+                    Ok(SourceReply::Structured {
+                        origin_uri: self.origin_uri.clone(),
+                        data: (event, Value::object()).into(),
+                    })
+                }
+                Err(TryRecvError::Empty) => Ok(SourceReply::Empty(10)),
+                Err(TryRecvError::Closed) => {
+                    Ok(SourceReply::StateChange(SourceState::Disconnected))
+                }
+            }
+        } else {
+            Ok(SourceReply::StateChange(SourceState::Disconnected))
+        }
+    }
+
+    fn ack(&mut self, id: u64) {
+        // acks are cumulative, the cursor of the latest entry covers the others
+        let pending = self.in_flight.split_off(&(id + 1));
+        let acked = std::mem::replace(&mut self.in_flight, pending);
+        if let (Some(path), Some(cursor)) = (&self.config.cursor_file, acked.values().last()) {
+            if let Err(e) = write_cursor(path, cursor) {
+                error!(
+                    "[Source::{}] Failed to persist the journal cursor: {}",
+                    self.onramp_id, e
+                );
+            }
+        }
+    }
+
+    fn is_transactional(&self) -> bool {
+        self.config.cursor_file.is_some()
+    }
+
+    async fn init(&mut self) -> Result<SourceState> {
+        let cursor = if let Some(path) = &self.config.cursor_file {
+            read_cursor(path)?
+        } else {
+            None
+        };
+        let mut child = Command::new("journalctl")
+            .args(journalctl_args(&self.config, cursor.as_deref()))
+            .stdout(Stdio::piped())
+            .stdin(Stdio::null())
+            .kill_on_drop(true)
+            .spawn()?;
+        let (tx, rx) = bounded(crate::QSIZE);
+        spawn_reader(&mut child, self.config.clone(), tx)?;
+        self.child = Some(child);
+        self.listener = Some(rx);
+        Ok(SourceState::Connected)
+    }
+
+    async fn terminate(&mut self) {
+        if let Some(mut child) = self.child.take() {
+            if let Err(e) = child.kill() {
+                warn!(
+                    "[Source::{}] Failed to stop journalctl: {}",
+                    self.onramp_id, e
+                );
+            }
+        }
+        self.listener = None;
+    }
+}
+
+#[async_trait::async_trait]
+impl Onramp for Journald {
+    async fn start(&mut self, config: OnrampConfig<'_>) -> Result<onramp::Addr> {
+        let source = Int::from_config(config.onramp_uid, self.onramp_id.clone(), &self.config);
+        SourceManager::start(source, config).await
+    }
+
+    fn default_codec(&self) -> &str {
+        "json"
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use tremor_value::literal;
+
+    #[test]
+    fn entries() {
+        let entry = literal!({
+            "__CURSOR": "s=1;i=2",
+            "__REALTIME_TIMESTAMP": "1633000000000001",
+            "MESSAGE": [115, 110, 111, 116],
+            "PRIORITY": "3",
+            "_HOSTNAME": "tremor",
+            "_SYSTEMD_UNIT": "tremor.service",
+            "_PID": "42",
+            "_TRANSPORT": "stdout"
+        });
+        let mut fields = HashMap::new();
+        fields.insert("_TRANSPORT".to_string(), "transport".to_string());
+        let (event, cursor) = map_entry(&entry, &fields, false);
+        assert_eq!(cursor.as_deref(), Some("s=1;i=2"));
+        assert_eq!(
+            event,
+            literal!({
+                "message": "snot",
+                "priority": 3,
+                "host": "tremor",
+                "unit": "tremor.service",
+                "pid": 42,
+                "timestamp": 1_633_000_000_000_001_000_u64,
+                "transport": "stdout"
+            })
+        );
+    }
+
+    #[test]
+    fn cursor() -> Result<()> {
+        let dir = tempfile::tempdir()?;
+        let path = dir.path().join("cursor");
+        let path = path.to_string_lossy();
+        assert_eq!(read_cursor(&path)?, None);
+        write_cursor(&path, "s=1;i=2")?;
+        assert_eq!(read_cursor(&path)?.as_deref(), Some("s=1;i=2"));
+        Ok(())
+    }
+
+    #[test]
+    fn args() {
+        let config = Config {
+            units: vec!["tremor.service".to_string()],
+            priority: Some("warning".to_string()),
+            fields: HashMap::new(),
+            raw: false,
+            cursor_file: None,
+            from_start: false,
+        };
+        assert_eq!(
+            journalctl_args(&config, Some("s=1")),
+            vec![
+                "--follow",
+                "--output=json",
+                "--lines=all",
+                "--after-cursor=s=1",
+                "--unit=tremor.service",
+                "--priority=warning"
+            ]
+        );
+        assert_eq!(journalctl_args(&config, None)[2], "--lines=0");
+    }
+}
@@ -13,6 +13,8 @@
 // limitations under the License.
 #![cfg(not(tarpaulin_include))]
 
+use crate::connectors::kafka::{Sasl, TokenRefresh};
+use crate::connectors::tls::TlsConfig;
 use crate::errors::Result;
 use crate::source::prelude::*;
 
@@ -84,6 +86,13 @@ pub struct Config {
     #[serde(default = "default_check_topic_metadata")]
     pub check_topic_metadata: bool,
 
+    /// SASL OAUTHBEARER authentication, tokens are refreshed before they expire
+    #[serde(default)]
+    pub sasl: Option<Sasl>,
+    /// TLS, e.g. to present a client certificate
+    #[serde(default)]
+    pub tls: Option<TlsConfig>,
+
     /// Optional rdkafka configuration
     ///
     /// Default settings:
@@ -133,13 +142,19 @@ impl<'consumer> From<BorrowedMessage<'consumer>> for MsgOffset {
 
 pub struct StreamAndMsgs<'consumer> {
     pub stream: stream_consumer::MessageStream<'consumer, LoggingConsumerContext, SmolRuntime>,
+    // part of the suffix so it is dropped before the consumer
+    _token_refresh: Option<TokenRefresh>,
 }
 
 impl<'consumer> StreamAndMsgs<'consumer> {
     fn new(
         stream: stream_consumer::MessageStream<'consumer, LoggingConsumerContext, SmolRuntime>,
+        token_refresh: Option<TokenRefresh>,
     ) -> Self {
-        Self { stream }
+        Self {
+            stream,
+            _token_refresh: token_refresh,
+        }
     }
 }
 
@@ -585,6 +600,13 @@ impl Source for Int {
             // but only commit the offsets explicitly stored via `consumer.store_offset`.
             .set("enable.auto.offset.store", "true");
 
+        if let Some(tls) = &self.config.tls {
+            tls.configure_rdkafka(&mut client_config)?;
+        }
+        if let Some(sasl) = &self.config.sasl {
+            sasl.configure(&mut client_config);
+        }
+
         self.config
             .rdkafka_options
             .iter()
@@ -617,8 +639,13 @@ impl Source for Int {
                 return Err(e.into());
             }
         };
-        let stream =
-            rentals::MessageStream::new(Box::new(consumer), |c| StreamAndMsgs::new(c.stream()));
+        let sasl = self.config.sasl.clone();
+        let id = format!("Source::{}", self.onramp_id);
+        let stream = rentals::MessageStream::new(Box::new(consumer), |c| {
+            let token_refresh =
+                sasl.map(|sasl| TokenRefresh::start(sasl, c.client().native_ptr(), id));
+            StreamAndMsgs::new(c.stream(), token_refresh)
+        });
         self.stream = Some(stream);
 
         Ok(SourceState::Connected)
@@ -672,4 +699,8 @@ impl Onramp for Kafka {
     fn default_codec(&self) -> &str {
         "json"
     }
+    fn parallel(&self) -> bool {
+        // the instances share the consumer group and split its partitions
+        true
+    }
 }
@@ -0,0 +1,647 @@
+// Copyright 2020-2021, The Tremor Team
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+#![cfg(not(tarpaulin_include))]
+
+//! # Kubernetes Onramp
+//!
+//! Watches a resource of the Kubernetes API, e.g. `pods` or `events`, and
+//! emits the full objects. The kind of change, `add`, `update` or `delete`,
+//! and the name of the object are in `$kubernetes`.
+//!
+//! The objects are listed first and emitted as `add` events, this is
+//! repeated when a watch expired.
+//!
+//! Authenticates in-cluster with the service account of the pod or with a
+//! kubeconfig using tokens, client certificates or basic auth.
+
+use crate::connectors::tls;
+use crate::source::prelude::*;
+use async_channel::{Sender, TryRecvError};
+use http_types::StatusCode;
+use std::convert::TryFrom;
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+use std::time::Duration;
+use surf::Client;
+use tremor_value::literal;
+use url::Url;
+
+const SERVICE_ACCOUNT: &str = "/var/run/secrets/kubernetes.io/serviceaccount";
+
+#[derive(Debug, Clone, Deserialize)]
+pub struct Config {
+    /// API group of the resource, the core group if empty
+    #[serde(default)]
+    pub group: String,
+    /// API version of the resource (default: `v1`)
+    #[serde(default = "dflt_version")]
+    pub version: String,
+    /// Resource to watch, e.g. `pods`, `events` or `deployments`
+    pub resource: String,
+    /// Namespace to watch, all namespaces if not set
+    pub namespace: Option<String>,
+    pub label_selector: Option<String>,
+    pub field_selector: Option<String>,
+    /// kubeconfig to authenticate with, the service account of the pod is
+    /// used in a cluster and `$KUBECONFIG` or `~/.kube/config` otherwise
+    pub kubeconfig: Option<PathBuf>,
+    /// context of the kubeconfig, the current context if not set
+    pub context: Option<String>,
+}
+
+fn dflt_version() -> String {
+    "v1".to_string()
+}
+
+impl ConfigImpl for Config {}
+
+impl Config {
+    /// Path of the resource in the API
+    fn resource_path(&self) -> String {
+        let base = if self.group.is_empty() {
+            format!("/api/{}", self.version)
+        } else {
+            format!("/apis/{}/{}", self.group, self.version)
+        };
+        match &self.namespace {
+            Some(namespace) => format!("{}/namespaces/{}/{}", base, namespace, self.resource),
+            None => format!("{}/{}", base, self.resource),
+        }
+    }
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+struct Kubeconfig {
+    current_context: Option<String>,
+    #[serde(default)]
+    contexts: Vec<NamedContext>,
+    #[serde(default)]
+    clusters: Vec<NamedCluster>,
+    #[serde(default)]
+    users: Vec<NamedUser>,
+}
+
+#[derive(Debug, Deserialize)]
+struct NamedContext {
+    name: String,
+    context: Context,
+}
+
+#[derive(Debug, Deserialize)]
+struct Context {
+    cluster: String,
+    user: String,
+}
+
+#[derive(Debug, Deserialize)]
+struct NamedCluster {
+    name: String,
+    cluster: Cluster,
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+struct Cluster {
+    server: String,
+    certificate_authority: Option<PathBuf>,
+    certificate_authority_data: Option<String>,
+    #[serde(default)]
+    insecure_skip_tls_verify: bool,
+}
+
+#[derive(Debug, Deserialize)]
+struct NamedUser {
+    name: String,
+    user: User,
+}
+
+#[derive(Debug, Default, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+struct User {
+    token: Option<String>,
+    #[serde(rename = "tokenFile")]
+    token_file: Option<PathBuf>,
+    client_certificate: Option<PathBuf>,
+    client_certificate_data: Option<String>,
+    client_key: Option<PathBuf>,
+    client_key_data: Option<String>,
+    username: Option<String>,
+    password: Option<String>,
+    exec: Option<YamlValue>,
+    auth_provider: Option<YamlValue>,
+}
+
+/// Reads base64 encoded `data` or the file at `path`, relative to `dir`
+fn pem(data: Option<&str>, path: Option<&Path>, dir: &Path) -> Result<Option<Vec<u8>>> {
+    if let Some(data) = data {
+        Ok(Some(base64::decode(data)?))
+    } else if let Some(path) = path {
+        Ok(Some(std::fs::read(dir.join(path))?))
+    } else {
+        Ok(None)
+    }
+}
+
+/// Credentials sent with each request
+#[derive(Debug, Clone)]
+enum Auth {
+    None,
+    Token(String),
+    /// re-read for each request, service account tokens are rotated
+    TokenFile(PathBuf),
+    Basic(String),
+}
+
+impl Auth {
+    fn header(&self) -> Result<Option<String>> {
+        Ok(match self {
+            Self::None => None,
+            Self::Token(token) => Some(format!("Bearer {}", token)),
+            Self::TokenFile(path) => {
+                Some(format!("Bearer {}", std::fs::read_to_string(path)?.trim()))
+            }
+            Self::Basic(credentials) => Some(format!("Basic {}", credentials)),
+        })
+    }
+}
+
+/// A connection to the API server
+#[derive(Clone)]
+struct Api {
+    server: Url,
+    auth: Auth,
+    client: Client,
+}
+
+impl Api {
+    fn new(server: &str, tls: rustls::ClientConfig, auth: Auth) -> Result<Self> {
+        // watches are long running requests
+        let config = surf::Config::new()
+            .set_timeout(None)
+            .set_tls_config(Some(Arc::new(tls)));
+        let client = Client::try_from(config)
+            .map_err(|e| Error::from(format!("Failed to create http client: {}", e)))?;
+        Ok(Self {
+            server: Url::parse(server)?,
+            auth,
+            client,
+        })
+    }
+
+    /// Connects with the service account of the pod
+    fn in_cluster(host: &str, port: &str) -> Result<Self> {
+        let dir = Path::new(SERVICE_ACCOUNT);
+        let ca = std::fs::read(dir.join("ca.crt"))?;
+        let tls = tls::client_config_from_pem(Some(&ca), None, false)?;
+        // IPv6 addresses need brackets in urls
+        let host = if host.contains(':') {
+            format!("[{}]", host)
+        } else {
+            host.to_string()
+        };
+        Self::new(
+            &format!("https://{}:{}", host, port),
+            tls,
+            Auth::TokenFile(dir.join("token")),
+        )
+    }
+
+    fn from_kubeconfig(path: &Path, context: Option<&str>) -> Result<Self> {
+        let kubeconfig: Kubeconfig = serde_yaml::from_reader(tremor_common::file::open(path)?)?;
+        let dir = path.parent().unwrap_or_else(|| Path::new("."));
+        Self::from_parsed(&kubeconfig, context, dir)
+    }
+
+    fn from_parsed(kubeconfig: &Kubeconfig, context: Option<&str>, dir: &Path) -> Result<Self> {
+        let name = context
+            .or_else(|| kubeconfig.current_context.as_deref())
+            .ok_or_else(|| Error::from("No context set in the kubeconfig"))?;
+        let context = kubeconfig
+            .contexts
+            .iter()
+            .find(|c| c.name == name)
+            .ok_or_else(|| Error::from(format!("Context {} not found in the kubeconfig", name)))?;
+        let cluster = kubeconfig
+            .clusters
+            .iter()
+            .find(|c| c.name == context.context.cluster)
+            .map(|c| &c.cluster)
+            .ok_or_else(|| {
+                Error::from(format!(
+                    "Cluster {} not found in the kubeconfig",
+                    context.context.cluster
+                ))
+            })?;
+        let default_user = User::default();
+        let user = kubeconfig
+            .users
+            .iter()
+            .find(|u| u.name == context.context.user)
+            .map_or(&default_user, |u| &u.user);
+        if user.exec.is_some() || user.auth_provider.is_some() {
+            return Err("`exec` and `auth-provider` kubeconfig users are not supported".into());
+        }
+
+        let ca = pem(
+            cluster.certificate_authority_data.as_deref(),
+            cluster.certificate_authority.as_deref(),
+            dir,
+        )?;
+        let cert = pem(
+            user.client_certificate_data.as_deref(),
+            user.client_certificate.as_deref(),
+            dir,
+        )?;
+        let key = pem(
+            user.client_key_data.as_deref(),
+            user.client_key.as_deref(),
+            dir,
+        )?;
+        let tls = tls::client_config_from_pem(
+            ca.as_deref(),
+            cert.as_deref().zip(key.as_deref()),
+            cluster.insecure_skip_tls_verify,
+        )?;
+        let auth = if let Some(token) = &user.token {
+            Auth::Token(token.clone())
+        } else if let Some(token_file) = &user.token_file {
+            Auth::TokenFile(dir.join(token_file))
+        } else if let Some((username, password)) =
+            user.username.as_ref().zip(user.password.as_ref())
+        {
+            Auth::Basic(base64::encode(format!("{}:{}", username, password)))
+        } else {
+            Auth::None
+        };
+        Self::new(&cluster.server, tls, auth)
+    }
+
+    /// Connects as configured, in-cluster or with a kubeconfig
+    fn connect(config: &Config) -> Result<Self> {
+        if let Some(path) = &config.kubeconfig {
+            return Self::from_kubeconfig(path, config.context.as_deref());
+        }
+        if let (Ok(host), Ok(port)) = (
+            std::env::var("KUBERNETES_SERVICE_HOST"),
+            std::env::var("KUBERNETES_SERVICE_PORT"),
+        ) {
+            return Self::in_cluster(&host, &port);
+        }
+        let path = std::env::var_os("KUBECONFIG")
+            .and_then(|paths| std::env::split_paths(&paths).next())
+            .or_else(|| std::env::var_os("HOME").map(|home| Path::new(&home).join(".kube/config")))
+            .ok_or_else(|| Error::from("No kubeconfig found"))?;
+        Self::from_kubeconfig(&path, config.context.as_deref())
+    }
+
+    async fn get(&self, path: &str, query: &[(&str, &str)]) -> Result<surf::Response> {
+        // keeps the path of servers behind proxies, e.g. `/k8s/clusters/c-1`
+        let mut url = self.server.clone();
+        url.set_path(&format!(
+            "{}{}",
+            self.server.path().trim_end_matches('/'),
+            path
+        ));
+        url.query_pairs_mut().extend_pairs(query);
+        let mut request = self.client.get(url);
+        if let Some(auth) = self.auth.header()? {
+            request = request.header("Authorization", auth);
+        }
+        Ok(request.await?)
+    }
+}
+
+/// The event and its metadata for an object, `kind` is used for objects of
+/// lists which don't have one
+fn object_event(
+    event: &'static str,
+    object: Value<'static>,
+    kind: &str,
+) -> (Value<'static>, Value<'static>) {
+    let metadata = object.get("metadata");
+    let mut meta = literal!({
+        "event": event,
+        "kind": object.get_str("kind").unwrap_or(kind).to_string(),
+        "name": metadata.get_str("name").unwrap_or_default().to_string(),
+        "resource_version": metadata.get_str("resourceVersion").unwrap_or_default().to_string()
+    });
+    if let Some(namespace) = metadata.get_str("namespace") {
+        meta.try_insert("namespace", namespace.to_string());
+    }
+    (object, literal!({ "kubernetes": meta }))
+}
+
+fn selectors(config: &Config) -> Vec<(&str, &str)> {
+    let mut query = Vec::new();
+    if let Some(selector) = &config.label_selector {
+        query.push(("labelSelector", selector.as_str()));
+    }
+    if let Some(selector) = &config.field_selector {
+        query.push(("fieldSelector", selector.as_str()));
+    }
+    query
+}
+
+/// Lists the objects as `add` events, returning the resource version to
+/// watch from
+async fn list(
+    api: &Api,
+    config: &Config,
+    tx: &Sender<(Value<'static>, Value<'static>)>,
+) -> Result<String> {
+    let path = config.resource_path();
+    let mut continue_token: Option<String> = None;
+    loop {
+        let mut query = selectors(config);
+        query.push(("limit", "500"));
+        if let Some(token) = &continue_token {
+            query.push(("continue", token.as_str()));
+        }
+        let mut response = api.get(&path, &query).await?;
+        if !response.status().is_success() {
+            return Err(format!("Listing {} failed: {}", path, response.status()).into());
+        }
+        let mut body = response.body_bytes().await?;
+        let list = tremor_value::parse_to_value(&mut body)?;
+        let kind = list
+            .get_str("kind")
+            .unwrap_or_default()
+            .trim_end_matches("List");
+        for item in list.get_array("items").into_iter().flatten() {
+            tx.send(object_event("add", item.clone_static(), kind))
+                .await?;
+        }
+        let metadata = list.get("metadata");
+        continue_token = metadata
+            .get_str("continue")
+            .filter(|token| !token.is_empty())
+            .map(ToString::to_string);
+        if continue_token.is_none() {
+            return Ok(metadata
+                .get_str("resourceVersion")
+                .unwrap_or_default()
+                .to_string());
+        }
+    }
+}
+
+/// Watches the objects from `resource_version` until the server ends the
+/// watch, returning the resource version to continue from, `None` if it
+/// expired
+async fn watch(
+    api: &Api,
+    config: &Config,
+    resource_version: &str,
+    tx: &Sender<(Value<'static>, Value<'static>)>,
+) -> Result<Option<String>> {
+    let path = config.resource_path();
+    let mut query = selectors(config);
+    query.push(("watch", "true"));
+    query.push(("allowWatchBookmarks", "true"));
+    query.push(("resourceVersion", resource_version));
+    let mut response = api.get(&path, &query).await?;
+    match response.status() {
+        StatusCode::Gone => return Ok(None),
+        status if !status.is_success() => {
+            return Err(format!("Watching {} failed: {}", path, status).into())
+        }
+        _ => (),
+    }
+    let mut resource_version = resource_version.to_string();
+    let mut lines = response.take_body().lines();
+    while let Some(line) = lines.next().await {
+        let mut line = line?.into_bytes();
+        if line.is_empty() {
+            continue;
+        }
+        let event = tremor_value::parse_to_value(&mut line)?;
+        let object = event.get("object");
+        let change = match event.get_str("type") {
+            Some("ADDED") => "add",
+            Some("MODIFIED") => "update",
+            Some("DELETED") => "delete",
+            Some("BOOKMARK") => {
+                if let Some(version) = object.get("metadata").get_str("resourceVersion") {
+                    resource_version = version.to_string();
+                }
+                continue;
+            }
+            Some("ERROR") if object.get_u64("code") == Some(410) => return Ok(None),
+            _ => {
+                return Err(format!(
+                    "Watching {} failed: {}",
+                    path,
+                    object.get_str("message").unwrap_or("unexpected event")
+                )
+                .into())
+            }
+        };
+        let object = object.map_or_else(Value::object, Value::clone_static);
+        if let Some(version) = object.get("metadata").get_str("resourceVersion") {
+            resource_version = version.to_string();
+        }
+        tx.send(object_event(change, object, "")).await?;
+    }
+    Ok(Some(resource_version))
+}
+
+async fn watch_loop(api: Api, config: Config, tx: Sender<(Value<'static>, Value<'static>)>) {
+    let mut resource_version: Option<String> = None;
+    while !tx.is_closed() {
+        let version = if let Some(version) = resource_version.take() {
+            version
+        } else {
+            match list(&api, &config, &tx).await {
+                Ok(version) => version,
+                Err(e) => {
+                    warn!("Failed to list {}: {}", config.resource, e);
+                    task::sleep(Duration::from_secs(5)).await;
+                    continue;
+                }
+            }
+        };
+        match watch(&api, &config, &version, &tx).await {
+            Ok(Some(version)) => resource_version = Some(version),
+            Ok(None) => info!("The watch of {} expired, listing again", config.resource),
+            Err(e) => {
+                warn!("Failed to watch {}: {}", config.resource, e);
+                resource_version = Some(version);
+                task::sleep(Duration::from_secs(5)).await;
+            }
+        }
+    }
+}
+
+pub struct Kubernetes {
+    pub config: Config,
+    onramp_id: TremorUrl,
+}
+
+impl onramp::Impl for Kubernetes {
+    fn from_config(id: &TremorUrl, config: &Option<YamlValue>) -> Result<Box<dyn Onramp>> {
+        if let Some(config) = config {
+            let config: Config = Config::new(config)?;
+            Ok(Box::new(Self {
+                config,
+                onramp_id: id.clone(),
+            }))
+        } else {
+            Err("Missing config for kubernetes onramp".into())
+        }
+    }
+}
+
+pub struct Int {
+    config: Config,
+    onramp_id: TremorUrl,
+    origin_uri: EventOriginUri,
+    rx: Option<Receiver<(Value<'static>, Value<'static>)>>,
+}
+
+impl std::fmt::Debug for Int {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "Kubernetes")
+    }
+}
+
+impl Int {
+    fn from_config(uid: u64, onramp_id: TremorUrl, config: &Config) -> Self {
+        let origin_uri = EventOriginUri {
+            uid,
+            scheme: "tremor-kubernetes".to_string(),
+            host: hostname(),
+            port: None,
+            path: vec![config.resource_path()],
+        };
+        Self {
+            config: config.clone(),
+            onramp_id,
+            origin_uri,
+            rx: None,
+        }
+    }
+}
+
+#[async_trait::async_trait()]
+impl Source for Int {
+    fn id(&self) -> &TremorUrl {
+        &self.onramp_id
+    }
+
+    async fn pull_event(&mut self, _id: u64) -> Result<SourceReply> {
+        self.rx.as_ref().map_or_else(
+            || Ok(SourceReply::StateChange(SourceState::Disconnected)),
+            |rx| match rx.try_recv() {
+                Ok(data) => Ok(SourceReply::Structured {
+                    origin_uri: self.origin_uri.clone(),
+                    data: data.into(),
+                }),
+                Err(TryRecvError::Empty) => Ok(SourceReply::Empty(10)),
+                Err(TryRecvError::Closed) => {
+                    Ok(SourceReply::StateChange(SourceState::Disconnected))
+                }
+            },
+        )
+    }
+
+    async fn init(&mut self) -> Result<SourceState> {
+        let api = Api::connect(&self.config)?;
+        info!(
+            "[Source::{}] Watching {} on {}",
+            self.onramp_id,
+            self.config.resource_path(),
+            api.server
+        );
+        let (tx, rx) = bounded(crate::QSIZE);
+        task::spawn(watch_loop(api, self.config.clone(), tx));
+        self.rx = Some(rx);
+        Ok(SourceState::Connected)
+    }
+
+    async fn terminate(&mut self) {
+        // stops the watch loop
+        self.rx = None;
+    }
+}
+
+#[async_trait::async_trait]
+impl Onramp for Kubernetes {
+    async fn start(&mut self, config: OnrampConfig<'_>) -> Result<onramp::Addr> {
+        let source = Int::from_config(config.onramp_uid, self.onramp_id.clone(), &self.config);
+        SourceManager::start(source, config).await
+    }
+
+    fn default_codec(&self) -> &str {
+        "json"
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn resource_path() -> Result<()> {
+        let config: Config = serde_yaml::from_str("resource: pods")?;
+        assert_eq!(config.resource_path(), "/api/v1/pods");
+        let config: Config =
+            serde_yaml::from_str("group: apps\nresource: deployments\nnamespace: tremor")?;
+        assert_eq!(
+            config.resource_path(),
+            "/apis/apps/v1/namespaces/tremor/deployments"
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn kubeconfig() -> Result<()> {
+        let kubeconfig: Kubeconfig = serde_yaml::from_str(
+            r#"
+current-context: kind
+contexts:
+  - name: kind
+    context: {cluster: kind, user: admin}
+clusters:
+  - name: kind
+    cluster: {server: "https://127.0.0.1:6443", insecure-skip-tls-verify: true}
+users:
+  - name: admin
+    user: {token: snot}
+"#,
+        )?;
+        let api = Api::from_parsed(&kubeconfig, None, Path::new("."))?;
+        assert_eq!(api.server.as_str(), "https://127.0.0.1:6443/");
+        assert_eq!(api.auth.header()?, Some("Bearer snot".to_string()));
+        assert!(Api::from_parsed(&kubeconfig, Some("prod"), Path::new(".")).is_err());
+        Ok(())
+    }
+
+    #[test]
+    fn events() {
+        let pod = literal!({"metadata": {"name": "tremor-0", "namespace": "default", "resourceVersion": "42"}});
+        let (object, meta) = object_event("add", pod.clone(), "Pod");
+        assert_eq!(object, pod);
+        assert_eq!(
+            meta,
+            literal!({"kubernetes": {
+                "event": "add",
+                "kind": "Pod",
+                "name": "tremor-0",
+                "resource_version": "42",
+                "namespace": "default"
+            }})
+        );
+    }
+}
@@ -0,0 +1,1141 @@
+// Copyright 2020-2021, The Tremor Team
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+#![cfg(not(tarpaulin_include))]
+
+//! # NetFlow Onramp
+//!
+//! Receives NetFlow v5, NetFlow v9, IPFIX and sFlow v5 datagrams over UDP
+//! and decodes them into an event per flow record, or per counter sample for
+//! sFlow. Every event carries the address of the `exporter` and the header
+//! of its datagram, the record itself is in `flow`, `options` or `counters`.
+//!
+//! Templates of NetFlow v9 and IPFIX are cached per exporter, observation
+//! domain and template id until they are not refreshed for
+//! `template_timeout_s`. Data records arriving before their template are
+//! dropped.
+
+use crate::source::prelude::*;
+use async_std::net::{ToSocketAddrs, UdpSocket};
+use byteorder::{BigEndian, ByteOrder};
+use socket2::{Domain, Protocol, Socket, Type};
+use std::collections::{HashMap, VecDeque};
+use std::convert::TryFrom;
+use std::net::{IpAddr, Ipv4Addr, Ipv6Addr};
+use tremor_common::time::nanotime;
+use tremor_value::literal;
+
+#[derive(Deserialize, Debug, Clone)]
+pub struct Config {
+    /// host to listen on, defaults to "0.0.0.0"
+    #[serde(default = "dflt_host")]
+    pub host: String,
+    /// port to listen on, defaults to 2055
+    #[serde(default = "dflt_port")]
+    pub port: u16,
+    /// Size of the receive buffer of the socket in bytes (`SO_RCVBUF`)
+    pub recv_buffer_size: Option<usize>,
+    /// seconds templates are kept without being refreshed by their exporter,
+    /// defaults to 1800
+    #[serde(default = "dflt_template_timeout_s")]
+    pub template_timeout_s: u64,
+}
+
+fn dflt_host() -> String {
+    String::from("0.0.0.0")
+}
+
+fn dflt_port() -> u16 {
+    2055
+}
+
+fn dflt_template_timeout_s() -> u64 {
+    1800
+}
+
+impl ConfigImpl for Config {}
+
+/// Length of variable length IPFIX fields in templates
+const VARIABLE: u16 = 65535;
+
+fn invalid(reason: &str) -> Error {
+    ErrorKind::InvalidFlowData(reason.to_string()).into()
+}
+
+/// Length fields of sFlow are 32 bit
+fn len(n: u32) -> usize {
+    usize::try_from(n).unwrap_or(usize::MAX)
+}
+
+/// Reads big endian values, failing on truncated data
+#[derive(Clone, Copy)]
+pub(crate) struct Reader<'data> {
+    data: &'data [u8],
+    pos: usize,
+}
+
+impl<'data> Reader<'data> {
+    pub(crate) fn new(data: &'data [u8]) -> Self {
+        Self { data, pos: 0 }
+    }
+    pub(crate) fn remaining(&self) -> usize {
+        self.data.len().saturating_sub(self.pos)
+    }
+    pub(crate) fn bytes(&mut self, n: usize) -> Result<&'data [u8]> {
+        let end = self
+            .pos
+            .checked_add(n)
+            .filter(|end| *end <= self.data.len())
+            .ok_or_else(|| invalid("truncated datagram"))?;
+        let bytes = &self.data[self.pos..end];
+        self.pos = end;
+        Ok(bytes)
+    }
+    /// A reader for the next `n` bytes
+    pub(crate) fn sub(&mut self, n: usize) -> Result<Reader<'data>> {
+        Ok(Reader::new(self.bytes(n)?))
+    }
+    pub(crate) fn u8(&mut self) -> Result<u8> {
+        Ok(self.bytes(1)?[0])
+    }
+    pub(crate) fn u16(&mut self) -> Result<u16> {
+        Ok(BigEndian::read_u16(self.bytes(2)?))
+    }
+    pub(crate) fn u32(&mut self) -> Result<u32> {
+        Ok(BigEndian::read_u32(self.bytes(4)?))
+    }
+    pub(crate) fn u64(&mut self) -> Result<u64> {
+        Ok(BigEndian::read_u64(self.bytes(8)?))
+    }
+    pub(crate) fn ipv4(&mut self) -> Result<String> {
+        Ok(Ipv4Addr::from(self.u32()?).to_string())
+    }
+    pub(crate) fn ipv6(&mut self) -> Result<String> {
+        let mut addr = [0; 16];
+        addr.copy_from_slice(self.bytes(16)?);
+        Ok(Ipv6Addr::from(addr).to_string())
+    }
+    pub(crate) fn mac(&mut self) -> Result<String> {
+        Ok(mac(self.bytes(6)?))
+    }
+}
+
+pub(crate) fn mac(bytes: &[u8]) -> String {
+    bytes
+        .iter()
+        .map(|b| format!("{:02x}", b))
+        .collect::<Vec<_>>()
+        .join(":")
+}
+
+pub(crate) fn set(
+    record: &mut Object<'static>,
+    key: &'static str,
+    value: impl Into<Value<'static>>,
+) {
+    record.insert(key.into(), value.into());
+}
+
+/// The header of a datagram with `record` added as `key`
+fn event(header: &Value<'static>, key: &'static str, record: Object<'static>) -> Value<'static> {
+    let mut event = header.clone();
+    if let Some(event) = event.as_object_mut() {
+        event.insert(key.into(), Value::from(record));
+    }
+    event
+}
+
+#[derive(Clone, Copy, Debug)]
+struct TemplateField {
+    id: u16,
+    len: u16,
+    enterprise: Option<u32>,
+    /// scope fields of NetFlow v9 options templates have their own types
+    scope: bool,
+}
+
+#[derive(Clone, Debug)]
+struct Template {
+    fields: Vec<TemplateField>,
+    options: bool,
+}
+
+/// exporter, observation domain (source id in NetFlow v9) and template id
+type TemplateKey = (IpAddr, u32, u16);
+
+struct Templates {
+    templates: HashMap<TemplateKey, (u64, Template)>,
+    timeout_ns: u64,
+}
+
+impl Templates {
+    fn new(timeout_s: u64) -> Self {
+        Self {
+            templates: HashMap::new(),
+            timeout_ns: timeout_s.saturating_mul(1_000_000_000),
+        }
+    }
+    fn insert(&mut self, key: TemplateKey, template: Template, now: u64) {
+        self.templates.insert(key, (now, template));
+    }
+    fn remove(&mut self, key: &TemplateKey) {
+        self.templates.remove(key);
+    }
+    fn get(&mut self, key: &TemplateKey, now: u64) -> Option<&Template> {
+        let timeout_ns = self.timeout_ns;
+        if self.templates.get(key).map_or(false, |(updated, _)| {
+            now.saturating_sub(*updated) > timeout_ns
+        }) {
+            self.templates.remove(key);
+        }
+        self.templates.get(key).map(|(_, template)| template)
+    }
+}
+
+/// Names of the NetFlow v9 and IPFIX information elements
+fn field_name(id: u16) -> Cow<'static, str> {
+    let name = match id {
+        1 => "bytes",
+        2 => "packets",
+        3 => "flows",
+        4 => "protocol",
+        5 => "tos",
+        6 => "tcp_flags",
+        7 => "src_port",
+        8 | 27 => "src_addr",
+        9 | 29 => "src_mask",
+        10 => "input",
+        11 => "dst_port",
+        12 | 28 => "dst_addr",
+        13 | 30 => "dst_mask",
+        14 => "output",
+        15 | 62 => "next_hop",
+        16 => "src_as",
+        17 => "dst_as",
+        21 => "last_switched",
+        22 => "first_switched",
+        32 => "icmp_type",
+        56 => "src_mac",
+        57 => "out_dst_mac",
+        58 => "vlan",
+        61 => "direction",
+        80 => "dst_mac",
+        81 => "out_src_mac",
+        85 => "total_bytes",
+        86 => "total_packets",
+        89 => "forwarding_status",
+        136 => "flow_end_reason",
+        148 => "flow_id",
+        150 => "start_s",
+        151 => "end_s",
+        152 => "start_ms",
+        153 => "end_ms",
+        154 => "start_us",
+        155 => "end_us",
+        156 => "start_ns",
+        157 => "end_ns",
+        225 => "post_nat_src_addr",
+        226 => "post_nat_dst_addr",
+        227 => "post_nat_src_port",
+        228 => "post_nat_dst_port",
+        _ => return format!("field_{}", id).into(),
+    };
+    name.into()
+}
+
+/// Names of the scope fields of NetFlow v9 options templates
+fn scope_name(id: u16) -> Cow<'static, str> {
+    let name = match id {
+        1 => "scope_system",
+        2 => "scope_interface",
+        3 => "scope_line_card",
+        4 => "scope_cache",
+        5 => "scope_template",
+        _ => return format!("scope_{}", id).into(),
+    };
+    name.into()
+}
+
+/// Unsigned integers up to 8 bytes, longer fields as bytes
+fn number(raw: &[u8]) -> Value<'static> {
+    if (1..=8).contains(&raw.len()) {
+        Value::from(BigEndian::read_uint(raw, raw.len()))
+    } else {
+        Value::Bytes(raw.to_vec().into())
+    }
+}
+
+/// Name and value of a field, addresses are formatted and variable length
+/// fields are text if they are valid UTF-8
+fn field_value(field: &TemplateField, raw: &[u8]) -> (Cow<'static, str>, Value<'static>) {
+    if let Some(enterprise) = field.enterprise {
+        return (format!("{}.{}", enterprise, field.id).into(), number(raw));
+    }
+    if field.scope {
+        return (scope_name(field.id), number(raw));
+    }
+    if field.len == VARIABLE {
+        let value = std::str::from_utf8(raw).map_or_else(
+            |_| Value::Bytes(raw.to_vec().into()),
+            |s| Value::from(s.to_string()),
+        );
+        return (field_name(field.id), value);
+    }
+    let mut r = Reader::new(raw);
+    let value = match (field.id, raw.len()) {
+        (8 | 12 | 15 | 225 | 226, 4) => r.ipv4().map(Value::from),
+        (27 | 28 | 62, 16) => r.ipv6().map(Value::from),
+        (56 | 57 | 80 | 81, 6) => r.mac().map(Value::from),
+        _ => Ok(number(raw)),
+    };
+    (field_name(field.id), value.unwrap_or_else(|_| number(raw)))
+}
+
+fn template_fields(set: &mut Reader, count: u16, ipfix: bool) -> Result<Vec<TemplateField>> {
+    let mut fields = Vec::with_capacity(usize::from(count));
+    for _ in 0..count {
+        let id = set.u16()?;
+        let len = set.u16()?;
+        let enterprise = if ipfix && id & 0x8000 != 0 {
+            Some(set.u32()?)
+        } else {
+            None
+        };
+        fields.push(TemplateField {
+            id: id & 0x7fff,
+            len,
+            enterprise,
+            scope: false,
+        });
+    }
+    Ok(fields)
+}
+
+/// Decodes the records of a data set, padding at its end is skipped
+fn data_set(
+    set: &mut Reader,
+    template: &Template,
+    header: &Value<'static>,
+    events: &mut Vec<Value<'static>>,
+) -> Result<()> {
+    let min_len = template
+        .fields
+        .iter()
+        .map(|f| {
+            if f.len == VARIABLE {
+                1
+            } else {
+                usize::from(f.len)
+            }
+        })
+        .sum::<usize>()
+        .max(1);
+    while set.remaining() >= min_len {
+        let mut record = Object::with_capacity(template.fields.len());
+        for field in &template.fields {
+            let len = if field.len == VARIABLE {
+                match set.u8()? {
+                    255 => usize::from(set.u16()?),
+                    len => usize::from(len),
+                }
+            } else {
+                usize::from(field.len)
+            };
+            let (name, value) = field_value(field, set.bytes(len)?);
+            record.insert(name, value);
+        }
+        let key = if template.options { "options" } else { "flow" };
+        events.push(event(header, key, record));
+    }
+    Ok(())
+}
+
+fn netflow_v5(mut r: Reader, exporter: IpAddr) -> Result<Vec<Value<'static>>> {
+    let count = r.u16()?;
+    let uptime_ms = r.u32()?;
+    let secs = r.u32()?;
+    let nsecs = r.u32()?;
+    let sequence = r.u32()?;
+    let engine_type = r.u8()?;
+    let engine_id = r.u8()?;
+    let sampling_interval = r.u16()? & 0x3fff;
+    let export_time = u64::from(secs) * 1_000_000_000 + u64::from(nsecs);
+    let header = literal!({
+        "exporter": exporter.to_string(),
+        "protocol": "netflow",
+        "version": 5,
+        "sequence": sequence,
+        "uptime_ms": uptime_ms,
+        "export_time": export_time,
+        "engine_type": engine_type,
+        "engine_id": engine_id,
+        "sampling_interval": sampling_interval
+    });
+    let mut events = Vec::with_capacity(usize::from(count));
+    for _ in 0..count {
+        let mut flow = Object::with_capacity(18);
+        set(&mut flow, "src_addr", r.ipv4()?);
+        set(&mut flow, "dst_addr", r.ipv4()?);
+        set(&mut flow, "next_hop", r.ipv4()?);
+        set(&mut flow, "input", r.u16()?);
+        set(&mut flow, "output", r.u16()?);
+        set(&mut flow, "packets", r.u32()?);
+        set(&mut flow, "bytes", r.u32()?);
+        set(&mut flow, "first_switched", r.u32()?);
+        set(&mut flow, "last_switched", r.u32()?);
+        set(&mut flow, "src_port", r.u16()?);
+        set(&mut flow, "dst_port", r.u16()?);
+        r.u8()?;
+        set(&mut flow, "tcp_flags", r.u8()?);
+        set(&mut flow, "protocol", r.u8()?);
+        set(&mut flow, "tos", r.u8()?);
+        set(&mut flow, "src_as", r.u16()?);
+        set(&mut flow, "dst_as", r.u16()?);
+        set(&mut flow, "src_mask", r.u8()?);
+        set(&mut flow, "dst_mask", r.u8()?);
+        r.u16()?;
+        events.push(event(&header, "flow", flow));
+    }
+    Ok(events)
+}
+
+fn netflow_v9(
+    mut r: Reader,
+    exporter: IpAddr,
+    templates: &mut Templates,
+    now: u64,
+) -> Result<Vec<Value<'static>>> {
+    r.u16()?; // count
+    let uptime_ms = r.u32()?;
+    let export_time = u64::from(r.u32()?) * 1_000_000_000;
+    let sequence = r.u32()?;
+    let source_id = r.u32()?;
+    let header = literal!({
+        "exporter": exporter.to_string(),
+        "protocol": "netflow",
+        "version": 9,
+        "sequence": sequence,
+        "uptime_ms": uptime_ms,
+        "export_time": export_time,
+        "source_id": source_id
+    });
+    let mut events = Vec::new();
+    while r.remaining() >= 4 {
+        let id = r.u16()?;
+        let set_len = usize::from(r.u16()?)
+            .checked_sub(4)
+            .ok_or_else(|| invalid("flowset shorter than its header"))?;
+        let mut set = r.sub(set_len)?;
+        match id {
+            0 => {
+                while set.remaining() >= 4 {
+                    let template_id = set.u16()?;
+                    let count = set.u16()?;
+                    let fields = template_fields(&mut set, count, false)?;
+                    let template = Template {
+                        fields,
+                        options: false,
+                    };
+                    templates.insert((exporter, source_id, template_id), template, now);
+                }
+            }
+            1 => {
+                while set.remaining() >= 6 {
+                    let template_id = set.u16()?;
+                    if template_id < 256 {
+                        // padding
+                        break;
+                    }
+                    let scope_len = set.u16()?;
+                    let option_len = set.u16()?;
+                    let mut fields = template_fields(&mut set, scope_len / 4, false)?;
+                    for field in &mut fields {
+                        field.scope = true;
+                    }
+                    fields.append(&mut template_fields(&mut set, option_len / 4, false)?);
+                    let template = Template {
+                        fields,
+                        options: true,
+                    };
+                    templates.insert((exporter, source_id, template_id), template, now);
+                }
+            }
+            2..=255 => (),
+            _ => match templates.get(&(exporter, source_id, id), now) {
+                Some(template) => data_set(&mut set, template, &header, &mut events)?,
+                None => debug!("NetFlow v9 data of {} without template {}", exporter, id),
+            },
+        }
+    }
+    Ok(events)
+}
+
+fn ipfix(
+    mut r: Reader,
+    exporter: IpAddr,
+    templates: &mut Templates,
+    now: u64,
+) -> Result<Vec<Value<'static>>> {
+    r.u16()?; // length
+    let export_time = u64::from(r.u32()?) * 1_000_000_000;
+    let sequence = r.u32()?;
+    let domain = r.u32()?;
+    let header = literal!({
+        "exporter": exporter.to_string(),
+        "protocol": "ipfix",
+        "version": 10,
+        "sequence": sequence,
+        "export_time": export_time,
+        "observation_domain": domain
+    });
+    let mut events = Vec::new();
+    while r.remaining() >= 4 {
+        let id = r.u16()?;
+        let set_len = usize::from(r.u16()?)
+            .checked_sub(4)
+            .ok_or_else(|| invalid("set shorter than its header"))?;
+        let mut set = r.sub(set_len)?;
+        match id {
+            2 | 3 => {
+                while set.remaining() >= 4 {
+                    let template_id = set.u16()?;
+                    if template_id < 256 {
+                        // padding
+                        break;
+                    }
+                    let count = set.u16()?;
+                    let key = (exporter, domain, template_id);
+                    if count == 0 {
+                        // withdrawn
+                        templates.remove(&key);
+                        continue;
+                    }
+                    if id == 3 {
+                        set.u16()?; // scope field count
+                    }
+                    let fields = template_fields(&mut set, count, true)?;
+                    let template = Template {
+                        fields,
+                        options: id == 3,
+                    };
+                    templates.insert(key, template, now);
+                }
+            }
+            4..=255 => (),
+            _ => match templates.get(&(exporter, domain, id), now) {
+                Some(template) => data_set(&mut set, template, &header, &mut events)?,
+                None => debug!("IPFIX data of {} without template {}", exporter, id),
+            },
+        }
+    }
+    Ok(events)
+}
+
+/// Addresses, ports and flags of a sampled ethernet frame, as far as its
+/// header was captured
+fn ethernet(data: &[u8], flow: &mut Object<'static>) -> Result<()> {
+    let mut r = Reader::new(data);
+    set(flow, "dst_mac", r.mac()?);
+    set(flow, "src_mac", r.mac()?);
+    let mut ethertype = r.u16()?;
+    if ethertype == 0x8100 {
+        set(flow, "vlan", r.u16()? & 0x0fff);
+        ethertype = r.u16()?;
+    }
+    set(flow, "ethertype", ethertype);
+    let protocol = match ethertype {
+        0x0800 => {
+            let ihl = usize::from(r.u8()? & 0x0f) * 4;
+            set(flow, "tos", r.u8()?);
+            r.bytes(6)?; // length, identification and fragment offset
+            r.u8()?; // ttl
+            let protocol = r.u8()?;
+            r.u16()?; // checksum
+            set(flow, "src_addr", r.ipv4()?);
+            set(flow, "dst_addr", r.ipv4()?);
+            r.bytes(ihl.saturating_sub(20))?;
+            protocol
+        }
+        0x86dd => {
+            set(flow, "tos", (r.u32()? >> 20) & 0xff);
+            r.u16()?; // payload length
+            let protocol = r.u8()?;
+            r.u8()?; // hop limit
+            set(flow, "src_addr", r.ipv6()?);
+            set(flow, "dst_addr", r.ipv6()?);
+            protocol
+        }
+        _ => return Ok(()),
+    };
+    set(flow, "protocol", protocol);
+    if protocol == 6 || protocol == 17 {
+        set(flow, "src_port", r.u16()?);
+        set(flow, "dst_port", r.u16()?);
+    }
+    if protocol == 6 {
+        r.bytes(9)?; // sequence, acknowledgement and data offset
+        set(flow, "tcp_flags", r.u8()?);
+    }
+    Ok(())
+}
+
+/// The sequence number and source of a sample, in expanded samples the type
+/// and index of the source are separate
+fn sample_header(s: &mut Reader, expanded: bool, kind: &str) -> Result<Object<'static>> {
+    let mut sample = Object::with_capacity(9);
+    set(&mut sample, "type", kind.to_string());
+    set(&mut sample, "sequence", s.u32()?);
+    let (source_type, source_index) = if expanded {
+        (s.u32()?, s.u32()?)
+    } else {
+        let source = s.u32()?;
+        (source >> 24, source & 0x00ff_ffff)
+    };
+    set(&mut sample, "source_id_type", source_type);
+    set(&mut sample, "source_id_index", source_index);
+    Ok(sample)
+}
+
+fn flow_sample(s: &mut Reader, expanded: bool, header: &Value<'static>) -> Result<Value<'static>> {
+    let mut sample = sample_header(s, expanded, "flow")?;
+    set(&mut sample, "sampling_rate", s.u32()?);
+    set(&mut sample, "sample_pool", s.u32()?);
+    set(&mut sample, "drops", s.u32()?);
+    let (input, output) = if expanded {
+        s.u32()?; // input format
+        let input = s.u32()?;
+        s.u32()?; // output format
+        (input, s.u32()?)
+    } else {
+        (s.u32()? & 0x3fff_ffff, s.u32()? & 0x3fff_ffff)
+    };
+    set(&mut sample, "input", input);
+    set(&mut sample, "output", output);
+    let mut flow = Object::with_capacity(16);
+    for _ in 0..s.u32()? {
+        let format = s.u32()?;
+        let record_len = len(s.u32()?);
+        let mut record = s.sub(record_len)?;
+        match format {
+            1 => {
+                let protocol = record.u32()?;
+                set(&mut flow, "frame_length", record.u32()?);
+                record.u32()?; // stripped
+                let header_len = len(record.u32()?);
+                let data = record.bytes(header_len.min(record.remaining()))?;
+                if protocol == 1 {
+                    // truncated headers keep what could be read
+                    ethernet(data, &mut flow).ok();
+                }
+            }
+            2 => {
+                set(&mut flow, "frame_length", record.u32()?);
+                set(&mut flow, "src_mac", mac(&record.bytes(8)?[..6]));
+                set(&mut flow, "dst_mac", mac(&record.bytes(8)?[..6]));
+                set(&mut flow, "ethertype", record.u32()?);
+            }
+            3 | 4 => {
+                set(&mut flow, "length", record.u32()?);
+                set(&mut flow, "protocol", record.u32()?);
+                let (src, dst) = if format == 3 {
+                    (record.ipv4()?, record.ipv4()?)
+                } else {
+                    (record.ipv6()?, record.ipv6()?)
+                };
+                set(&mut flow, "src_addr", src);
+                set(&mut flow, "dst_addr", dst);
+                set(&mut flow, "src_port", record.u32()?);
+                set(&mut flow, "dst_port", record.u32()?);
+                set(&mut flow, "tcp_flags", record.u32()?);
+                set(&mut flow, "tos", record.u32()?);
+            }
+            1001 => {
+                set(&mut flow, "src_vlan", record.u32()?);
+                set(&mut flow, "src_priority", record.u32()?);
+                set(&mut flow, "dst_vlan", record.u32()?);
+                set(&mut flow, "dst_priority", record.u32()?);
+            }
+            _ => (),
+        }
+    }
+    let mut value = event(header, "sample", sample);
+    if let Some(event) = value.as_object_mut() {
+        event.insert("flow".into(), Value::from(flow));
+    }
+    Ok(value)
+}
+
+fn counter_sample(
+    s: &mut Reader,
+    expanded: bool,
+    header: &Value<'static>,
+) -> Result<Value<'static>> {
+    let sample = sample_header(s, expanded, "counters")?;
+    let mut counters = Object::with_capacity(19);
+    for _ in 0..s.u32()? {
+        let format = s.u32()?;
+        let record_len = len(s.u32()?);
+        let mut record = s.sub(record_len)?;
+        if format != 1 {
+            continue;
+        }
+        // generic interface counters
+        set(&mut counters, "if_index", record.u32()?);
+        set(&mut counters, "if_type", record.u32()?);
+        set(&mut counters, "if_speed", record.u64()?);
+        set(&mut counters, "if_direction", record.u32()?);
+        set(&mut counters, "if_status", record.u32()?);
+        set(&mut counters, "in_octets", record.u64()?);
+        set(&mut counters, "in_ucast_pkts", record.u32()?);
+        set(&mut counters, "in_multicast_pkts", record.u32()?);
+        set(&mut counters, "in_broadcast_pkts", record.u32()?);
+        set(&mut counters, "in_discards", record.u32()?);
+        set(&mut counters, "in_errors", record.u32()?);
+        set(&mut counters, "in_unknown_protos", record.u32()?);
+        set(&mut counters, "out_octets", record.u64()?);
+        set(&mut counters, "out_ucast_pkts", record.u32()?);
+        set(&mut counters, "out_multicast_pkts", record.u32()?);
+        set(&mut counters, "out_broadcast_pkts", record.u32()?);
+        set(&mut counters, "out_discards", record.u32()?);
+        set(&mut counters, "out_errors", record.u32()?);
+        set(&mut counters, "promiscuous_mode", record.u32()?);
+    }
+    let mut value = event(header, "sample", sample);
+    if let Some(event) = value.as_object_mut() {
+        event.insert("counters".into(), Value::from(counters));
+    }
+    Ok(value)
+}
+
+/// Decodes an sFlow v5 datagram, after its version
+fn sflow(mut r: Reader, exporter: IpAddr) -> Result<Vec<Value<'static>>> {
+    let agent = match r.u32()? {
+        1 => r.ipv4()?,
+        2 => r.ipv6()?,
+        _ => return Err(invalid("unknown sFlow agent address type")),
+    };
+    let sub_agent_id = r.u32()?;
+    let sequence = r.u32()?;
+    let uptime_ms = r.u32()?;
+    let header = literal!({
+        "exporter": exporter.to_string(),
+        "protocol": "sflow",
+        "version": 5,
+        "agent": agent,
+        "sub_agent_id": sub_agent_id,
+        "sequence": sequence,
+        "uptime_ms": uptime_ms
+    });
+    let count = r.u32()?;
+    let mut events = Vec::with_capacity(len(count).min(64));
+    for _ in 0..count {
+        let format = r.u32()?;
+        let sample_len = len(r.u32()?);
+        let mut sample = r.sub(sample_len)?;
+        // formats of other enterprises than sFlow itself are skipped
+        match format {
+            1 | 3 => events.push(flow_sample(&mut sample, format == 3, &header)?),
+            2 | 4 => events.push(counter_sample(&mut sample, format == 4, &header)?),
+            _ => (),
+        }
+    }
+    Ok(events)
+}
+
+/// Decodes a NetFlow v5, NetFlow v9, IPFIX or sFlow v5 datagram into events
+fn decode(
+    data: &[u8],
+    exporter: IpAddr,
+    templates: &mut Templates,
+    now: u64,
+) -> Result<Vec<Value<'static>>> {
+    let mut r = Reader::new(data);
+    let version = r.u16()?;
+    match version {
+        5 => netflow_v5(r, exporter),
+        9 => netflow_v9(r, exporter, templates, now),
+        10 => ipfix(r, exporter, templates, now),
+        // the version of sFlow is 32 bit
+        0 if r.u16()? == 5 => sflow(r, exporter),
+        _ => Err(invalid("unknown version")),
+    }
+}
+
+pub struct NetFlow {
+    pub config: Config,
+    onramp_id: TremorUrl,
+}
+
+struct Int {
+    config: Config,
+    socket: Option<UdpSocket>,
+    buf: Vec<u8>,
+    templates: Templates,
+    /// decoded events of the last datagram not yet pulled
+    pending: VecDeque<(EventOriginUri, Value<'static>)>,
+    onramp_id: TremorUrl,
+    origin_uri: EventOriginUri,
+}
+
+impl std::fmt::Debug for Int {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "NetFlow")
+    }
+}
+
+impl Int {
+    fn from_config(uid: u64, onramp_id: TremorUrl, config: &Config) -> Self {
+        let origin_uri = EventOriginUri {
+            uid,
+            scheme: "tremor-netflow".to_string(),
+            host: String::default(),
+            port: None,
+            path: vec![config.port.to_string()], // captures receive port
+        };
+        Self {
+            config: config.clone(),
+            socket: None,
+            buf: vec![0; 65535],
+            templates: Templates::new(config.template_timeout_s),
+            pending: VecDeque::new(),
+            onramp_id,
+            origin_uri,
+        }
+    }
+
+    async fn bind(&mut self) -> Result<()> {
+        let config = &self.config;
+        let addr = (config.host.as_str(), config.port)
+            .to_socket_addrs()
+            .await?
+            .next()
+            .ok_or_else(|| Error::from(format!("Can't resolve {}", config.host)))?;
+        let socket = Socket::new(Domain::for_address(addr), Type::DGRAM, Some(Protocol::UDP))?;
+        if let Some(size) = config.recv_buffer_size {
+            socket.set_recv_buffer_size(size)?;
+        }
+        socket.bind(&addr.into())?;
+        info!(
+            "[NetFlow Onramp] listening on {}:{}",
+            config.host, config.port
+        );
+        self.socket = Some(UdpSocket::from(std::net::UdpSocket::from(socket)));
+        Ok(())
+    }
+}
+
+impl onramp::Impl for NetFlow {
+    fn from_config(onramp_id: &TremorUrl, config: &Option<YamlValue>) -> Result<Box<dyn Onramp>> {
+        if let Some(config) = config {
+            Ok(Box::new(Self {
+                config: Config::new(config)?,
+                onramp_id: onramp_id.clone(),
+            }))
+        } else {
+            Err("Missing config for netflow onramp".into())
+        }
+    }
+}
+
+#[async_trait::async_trait]
+impl Source for Int {
+    async fn pull_event(&mut self, _id: u64) -> Result<SourceReply> {
+        if let Some((origin_uri, data)) = self.pending.pop_front() {
+            return Ok(SourceReply::Structured {
+                origin_uri,
+                data: data.into(),
+            });
+        }
+        if let Some(socket) = self.socket.as_mut() {
+            match socket.recv_from(&mut self.buf).await {
+                Ok((n, peer)) => {
+                    let mut origin_uri = self.origin_uri.clone();
+                    origin_uri.host = peer.ip().to_string();
+                    origin_uri.port = Some(peer.port());
+                    // ALLOW: we get n from recv
+                    let events =
+                        decode(&self.buf[0..n], peer.ip(), &mut self.templates, nanotime())
+                            .map_err(|e| format!("Dropped a datagram from {}: {}", peer, e))?;
+                    self.pending
+                        .extend(events.into_iter().map(|e| (origin_uri.clone(), e)));
+                    Ok(SourceReply::Empty(0))
+                }
+                Err(e) => {
+                    if e.kind() == std::io::ErrorKind::WouldBlock {
+                        Ok(SourceReply::Empty(1))
+                    } else {
+                        Err(e.into())
+                    }
+                }
+            }
+        } else {
+            self.bind().await?;
+            Ok(SourceReply::StateChange(SourceState::Connected))
+        }
+    }
+    async fn init(&mut self) -> Result<SourceState> {
+        self.bind().await?;
+        Ok(SourceState::Connected)
+    }
+    fn id(&self) -> &TremorUrl {
+        &self.onramp_id
+    }
+}
+
+#[async_trait::async_trait]
+impl Onramp for NetFlow {
+    async fn start(&mut self, config: OnrampConfig<'_>) -> Result<onramp::Addr> {
+        let source = Int::from_config(config.onramp_uid, self.onramp_id.clone(), &self.config);
+        SourceManager::start(source, config).await
+    }
+    fn default_codec(&self) -> &str {
+        "null"
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    fn u8(b: &mut Vec<u8>, v: u8) {
+        b.push(v);
+    }
+    fn u16(b: &mut Vec<u8>, v: u16) {
+        b.extend_from_slice(&v.to_be_bytes());
+    }
+    fn u32(b: &mut Vec<u8>, v: u32) {
+        b.extend_from_slice(&v.to_be_bytes());
+    }
+
+    fn exporter() -> IpAddr {
+        IpAddr::from([192, 168, 0, 1])
+    }
+
+    #[test]
+    fn netflow_v5() -> Result<()> {
+        let mut d = Vec::new();
+        for v in [5, 1] {
+            u16(&mut d, v);
+        }
+        for v in [1000, 1_600_000_000, 500, 42] {
+            u32(&mut d, v);
+        }
+        for v in [0, 1] {
+            u8(&mut d, v);
+        }
+        u16(&mut d, 0x4064); // mode 1, interval 100
+        for v in [0x0a00_0001, 0x0a00_0002, 0] {
+            u32(&mut d, v);
+        }
+        for v in [3, 4] {
+            u16(&mut d, v);
+        }
+        for v in [10, 1500, 100, 900] {
+            u32(&mut d, v);
+        }
+        for v in [51234, 443] {
+            u16(&mut d, v);
+        }
+        for v in [0, 0x12, 6, 0] {
+            u8(&mut d, v);
+        }
+        for v in [64512, 15169] {
+            u16(&mut d, v);
+        }
+        for v in [24, 16] {
+            u8(&mut d, v);
+        }
+        u16(&mut d, 0);
+
+        let events = decode(&d, exporter(), &mut Templates::new(60), 0)?;
+        assert_eq!(1, events.len());
+        let e = &events[0];
+        assert_eq!(Some("192.168.0.1"), e.get_str("exporter"));
+        assert_eq!(Some(5), e.get_u64("version"));
+        assert_eq!(Some(42), e.get_u64("sequence"));
+        assert_eq!(Some(1_600_000_000_000_000_500), e.get_u64("export_time"));
+        assert_eq!(Some(100), e.get_u64("sampling_interval"));
+        let flow = e.get("flow").ok_or_else(|| Error::from("no flow"))?;
+        assert_eq!(Some("10.0.0.1"), flow.get_str("src_addr"));
+        assert_eq!(Some("10.0.0.2"), flow.get_str("dst_addr"));
+        assert_eq!(Some(443), flow.get_u64("dst_port"));
+        assert_eq!(Some(1500), flow.get_u64("bytes"));
+        assert_eq!(Some(6), flow.get_u64("protocol"));
+        assert_eq!(Some(15169), flow.get_u64("dst_as"));
+        assert_eq!(Some(16), flow.get_u64("dst_mask"));
+
+        // truncated records are errors
+        d.truncate(40);
+        assert!(decode(&d, exporter(), &mut Templates::new(60), 0).is_err());
+        Ok(())
+    }
+
+    fn v9_header(d: &mut Vec<u8>) {
+        for v in [9, 2] {
+            u16(d, v);
+        }
+        for v in [1000, 1_600_000_000, 7, 99] {
+            u32(d, v);
+        }
+    }
+
+    #[test]
+    fn netflow_v9() -> Result<()> {
+        let mut templates = Templates::new(60);
+        let mut data = Vec::new();
+        for v in [256, 4 + 12] {
+            u16(&mut data, v);
+        }
+        for v in [0x0a00_0001, 0x0a00_0002] {
+            u32(&mut data, v);
+        }
+        u8(&mut data, 17);
+        data.extend_from_slice(&[0, 0, 0]); // padding
+
+        // data before its template is dropped
+        let mut d = Vec::new();
+        v9_header(&mut d);
+        d.extend_from_slice(&data);
+        assert!(decode(&d, exporter(), &mut templates, 0)?.is_empty());
+
+        let mut d = Vec::new();
+        v9_header(&mut d);
+        for v in [0, 4 + 4 + 12, 256, 3, 8, 4, 12, 4, 4, 1] {
+            u16(&mut d, v);
+        }
+        d.extend_from_slice(&data);
+        let events = decode(&d, exporter(), &mut templates, 0)?;
+        assert_eq!(1, events.len());
+        let e = &events[0];
+        assert_eq!(Some(99), e.get_u64("source_id"));
+        let flow = e.get("flow").ok_or_else(|| Error::from("no flow"))?;
+        assert_eq!(Some("10.0.0.1"), flow.get_str("src_addr"));
+        assert_eq!(Some("10.0.0.2"), flow.get_str("dst_addr"));
+        assert_eq!(Some(17), flow.get_u64("protocol"));
+
+        // the template is cached for later datagrams of the same source
+        let mut d = Vec::new();
+        v9_header(&mut d);
+        d.extend_from_slice(&data);
+        assert_eq!(1, decode(&d, exporter(), &mut templates, 0)?.len());
+        let other = IpAddr::from([192, 168, 0, 2]);
+        assert!(decode(&d, other, &mut templates, 0)?.is_empty());
+        Ok(())
+    }
+
+    #[test]
+    fn ipfix() -> Result<()> {
+        let mut templates = Templates::new(60);
+        let mut d = Vec::new();
+        for v in [10, 0] {
+            u16(&mut d, v);
+        }
+        for v in [1_600_000_000, 1, 7] {
+            u32(&mut d, v);
+        }
+        // bytes, an enterprise field and a variable length field
+        for v in [2, 4 + 4 + 12 + 4, 300, 3, 1, 8, 0x8001, 2] {
+            u16(&mut d, v);
+        }
+        u32(&mut d, 9);
+        for v in [82, VARIABLE] {
+            u16(&mut d, v);
+        }
+        let header_len = 16;
+        let template = d.split_off(header_len);
+        let header = d;
+
+        let mut d = header.clone();
+        d.extend_from_slice(&template);
+        assert!(decode(&d, exporter(), &mut templates, 0)?.is_empty());
+
+        let mut d = header.clone();
+        for v in [300, 4 + 8 + 2 + 1 + 4] {
+            u16(&mut d, v);
+        }
+        d.extend_from_slice(&1_234_567_u64.to_be_bytes());
+        u16(&mut d, 5);
+        u8(&mut d, 4);
+        d.extend_from_slice(b"eth0");
+        let events = decode(&d, exporter(), &mut templates, 0)?;
+        assert_eq!(1, events.len());
+        let e = &events[0];
+        assert_eq!(Some("ipfix"), e.get_str("protocol"));
+        assert_eq!(Some(7), e.get_u64("observation_domain"));
+        let flow = e.get("flow").ok_or_else(|| Error::from("no flow"))?;
+        assert_eq!(Some(1_234_567), flow.get_u64("bytes"));
+        assert_eq!(Some(5), flow.get_u64("9.1"));
+        assert_eq!(Some("eth0"), flow.get_str("field_82"));
+
+        // templates time out unless refreshed
+        assert!(decode(&d, exporter(), &mut templates, 61_000_000_000)?.is_empty());
+        Ok(())
+    }
+
+    #[test]
+    fn sflow() -> Result<()> {
+        let mut frame = Vec::new();
+        frame.extend_from_slice(&[0, 1, 2, 3, 4, 5, 6, 7, 8, 9, 10, 11]);
+        u16(&mut frame, 0x0800);
+        frame.extend_from_slice(&[0x45, 0, 0, 40, 0, 0, 0, 0, 64, 6, 0, 0]);
+        frame.extend_from_slice(&[10, 0, 0, 1, 10, 0, 0, 2]);
+        for v in [51234, 443] {
+            u16(&mut frame, v);
+        }
+        frame.extend_from_slice(&[0, 0, 0, 0, 0, 0, 0, 0, 0x50, 0x02]);
+
+        let mut record = Vec::new();
+        for v in [1, 64, 4, 48] {
+            u32(&mut record, v);
+        }
+        record.extend_from_slice(&frame);
+
+        let mut sample = Vec::new();
+        for v in [3, 0x0300_0005, 512, 1024, 0, 5, 6, 1] {
+            u32(&mut sample, v);
+        }
+        for v in [1, 16 + 48] {
+            u32(&mut sample, v);
+        }
+        sample.extend_from_slice(&record);
+
+        let mut d = Vec::new();
+        for v in [5, 1, 0x0a00_00fe, 0, 11, 5000, 2, 1] {
+            u32(&mut d, v);
+        }
+        u32(&mut d, u32::try_from(sample.len()).unwrap_or_default());
+        d.extend_from_slice(&sample);
+        // a sample of another enterprise
+        for v in [(4300 << 12) | 1, 4, 0] {
+            u32(&mut d, v);
+        }
+
+        let events = decode(&d, exporter(), &mut Templates::new(60), 0)?;
+        assert_eq!(1, events.len());
+        let e = &events[0];
+        assert_eq!(Some("sflow"), e.get_str("protocol"));
+        assert_eq!(Some("10.0.0.254"), e.get_str("agent"));
+        let sample = e.get("sample").ok_or_else(|| Error::from("no sample"))?;
+        assert_eq!(Some("flow"), sample.get_str("type"));
+        assert_eq!(Some(512), sample.get_u64("sampling_rate"));
+        assert_eq!(Some(5), sample.get_u64("source_id_index"));
+        let flow = e.get("flow").ok_or_else(|| Error::from("no flow"))?;
+        assert_eq!(Some("06:07:08:09:0a:0b"), flow.get_str("src_mac"));
+        assert_eq!(Some("10.0.0.1"), flow.get_str("src_addr"));
+        assert_eq!(Some(443), flow.get_u64("dst_port"));
+        assert_eq!(Some(2), flow.get_u64("tcp_flags"));
+        assert_eq!(Some(64), flow.get_u64("frame_length"));
+        Ok(())
+    }
+}
@@ -0,0 +1,1025 @@
+// Copyright 2020-2021, The Tremor Team
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+#![cfg(not(tarpaulin_include))]
+
+//! # PCAP Onramp
+//!
+//! Reads packets from a pcap or pcapng `file`, or captures them live from an
+//! `interface` through libpcap with the `pcap` feature. Every packet becomes
+//! an event with its capture timestamp, the decoded ethernet, ARP, IPv4,
+//! IPv6, TCP, UDP and ICMP headers as far as they were captured, and the
+//! remaining bytes as `payload`.
+//!
+//! Live captures can be narrowed with a `filter` expression in the syntax of
+//! `tcpdump`, see `pcap-filter(7)`.
+
+use crate::source::netflow::{mac, set, Reader};
+use crate::source::prelude::*;
+use async_channel::TryRecvError;
+use std::convert::TryFrom;
+use std::fs;
+use std::io::{self, BufReader, Read};
+use std::process;
+
+#[derive(Deserialize, Debug, Clone)]
+pub struct Config {
+    /// pcap or pcapng file to read packets from
+    pub file: Option<String>,
+    /// interface to capture packets on, requires the `pcap` feature
+    pub interface: Option<String>,
+    /// filter expression for captured packets, like `tcp port 80`
+    pub filter: Option<String>,
+    /// capture all packets on the interface, not only those addressed to it
+    #[serde(default = "Default::default")]
+    pub promiscuous: bool,
+    /// bytes captured of live packets, defaults to 65535
+    #[serde(default = "dflt_snaplen")]
+    pub snaplen: usize,
+    /// terminate tremor once the file is read
+    #[serde(default = "Default::default")]
+    pub close_on_done: bool,
+}
+
+fn dflt_snaplen() -> usize {
+    65535
+}
+
+impl ConfigImpl for Config {}
+
+/// A captured packet
+#[derive(Debug)]
+struct Packet {
+    /// nanoseconds since the epoch
+    timestamp: u64,
+    /// length of the packet on the wire
+    length: u32,
+    link_type: u32,
+    interface: Option<String>,
+    data: Vec<u8>,
+}
+
+const PCAP: u32 = 0xa1b2_c3d4;
+const PCAP_NS: u32 = 0xa1b2_3c4d;
+const SECTION_HEADER: u32 = 0x0a0d_0d0a;
+const BYTE_ORDER: u32 = 0x1a2b_3c4d;
+const INTERFACE_DESCRIPTION: u32 = 1;
+const SIMPLE_PACKET: u32 = 3;
+const ENHANCED_PACKET: u32 = 6;
+/// larger blocks or packets are taken as a corrupt file
+const MAX_BLOCK: usize = 64 * 1024 * 1024;
+
+fn invalid(reason: &str) -> Error {
+    ErrorKind::InvalidCaptureFile(reason.to_string()).into()
+}
+
+fn array<const N: usize>(b: &[u8], at: usize) -> Result<[u8; N]> {
+    let mut array = [0; N];
+    array.copy_from_slice(
+        b.get(at..at + N)
+            .ok_or_else(|| invalid("truncated block"))?,
+    );
+    Ok(array)
+}
+
+struct Interface {
+    link_type: u32,
+    name: Option<String>,
+    /// timestamp units per second
+    units: u64,
+}
+
+enum Format {
+    Pcap { nanos: bool, link_type: u32 },
+    PcapNg { interfaces: Vec<Interface> },
+}
+
+/// Reads the packets of pcap and pcapng files
+struct CaptureFile<R> {
+    r: R,
+    big: bool,
+    format: Format,
+}
+
+impl<R: Read> CaptureFile<R> {
+    fn new(r: R) -> Result<Self> {
+        let mut file = Self {
+            r,
+            big: true,
+            format: Format::PcapNg { interfaces: vec![] },
+        };
+        let magic = file.read(4)?;
+        let (big, nanos) = match (
+            file.u32_at(&magic, 0),
+            u32::from_le_bytes(array(&magic, 0)?),
+        ) {
+            (Some(SECTION_HEADER), _) => {
+                let len = file.read(4)?;
+                file.section(&len)?;
+                return Ok(file);
+            }
+            (Some(PCAP), _) => (true, false),
+            (Some(PCAP_NS), _) => (true, true),
+            (_, PCAP) => (false, false),
+            (_, PCAP_NS) => (false, true),
+            _ => return Err(invalid("not a pcap or pcapng file")),
+        };
+        file.big = big;
+        // version, time zone, accuracy and snaplen before the link type
+        let header = file.read(20)?;
+        let link_type = file
+            .u32_at(&header, 16)
+            .ok_or_else(|| invalid("truncated header"))?;
+        file.format = Format::Pcap { nanos, link_type };
+        Ok(file)
+    }
+
+    fn u16_at(&self, b: &[u8], at: usize) -> Option<u16> {
+        let b = array(b, at).ok()?;
+        Some(if self.big {
+            u16::from_be_bytes(b)
+        } else {
+            u16::from_le_bytes(b)
+        })
+    }
+    fn u32_at(&self, b: &[u8], at: usize) -> Option<u32> {
+        let b = array(b, at).ok()?;
+        Some(if self.big {
+            u32::from_be_bytes(b)
+        } else {
+            u32::from_le_bytes(b)
+        })
+    }
+
+    /// `n` bytes, `None` at the end of the file
+    fn try_read(&mut self, n: usize) -> Result<Option<Vec<u8>>> {
+        if n > MAX_BLOCK {
+            return Err(invalid("block too large"));
+        }
+        let mut buf = vec![0; n];
+        let mut read = 0;
+        while read < n {
+            match self.r.read(&mut buf[read..]) {
+                Ok(0) => break,
+                Ok(m) => read += m,
+                Err(e) if e.kind() == io::ErrorKind::Interrupted => (),
+                Err(e) => return Err(e.into()),
+            }
+        }
+        if read == 0 && n > 0 {
+            Ok(None)
+        } else if read < n {
+            Err(invalid("truncated file"))
+        } else {
+            Ok(Some(buf))
+        }
+    }
+
+    fn read(&mut self, n: usize) -> Result<Vec<u8>> {
+        self.try_read(n)?.ok_or_else(|| invalid("truncated file"))
+    }
+
+    /// Reads the rest of a section header block after its length, a new
+    /// section can change the byte order and has its own interfaces
+    fn section(&mut self, len: &[u8]) -> Result<()> {
+        let order = self.read(4)?;
+        self.big = match array::<4>(&order, 0)? {
+            b if u32::from_be_bytes(b) == BYTE_ORDER => true,
+            b if u32::from_le_bytes(b) == BYTE_ORDER => false,
+            _ => return Err(invalid("unknown byte order")),
+        };
+        let len = self
+            .u32_at(len, 0)
+            .map_or(0, |l| usize::try_from(l).unwrap_or(usize::MAX));
+        self.read(
+            len.checked_sub(12)
+                .ok_or_else(|| invalid("short section header"))?,
+        )?;
+        self.format = Format::PcapNg { interfaces: vec![] };
+        Ok(())
+    }
+
+    fn interface(&self, body: &[u8]) -> Interface {
+        let mut interface = Interface {
+            link_type: self.u16_at(body, 0).map_or(0, u32::from),
+            name: None,
+            units: 1_000_000,
+        };
+        // options after the link type, reserved bytes and snaplen
+        let mut at = 8;
+        while let (Some(code), Some(len)) = (self.u16_at(body, at), self.u16_at(body, at + 2)) {
+            let len = usize::from(len);
+            let value = body.get(at + 4..at + 4 + len).unwrap_or_default();
+            match (code, value) {
+                (0, _) => break,
+                (2, name) => {
+                    interface.name = Some(
+                        String::from_utf8_lossy(name)
+                            .trim_end_matches('\0')
+                            .to_string(),
+                    );
+                }
+                (9, [resolution]) => {
+                    let exp = u32::from(resolution & 0x7f);
+                    let units = if resolution & 0x80 == 0 {
+                        10_u64.checked_pow(exp)
+                    } else {
+                        1_u64.checked_shl(exp)
+                    };
+                    interface.units = units.unwrap_or(interface.units);
+                }
+                _ => (),
+            }
+            // values are padded to 32 bits
+            at += 4 + (len + 3) / 4 * 4;
+        }
+        interface
+    }
+
+    /// The next packet, `None` at the end of the file
+    fn next_packet(&mut self) -> Result<Option<Packet>> {
+        match self.format {
+            Format::Pcap { nanos, link_type } => self.next_record(nanos, link_type),
+            Format::PcapNg { .. } => self.next_block(),
+        }
+    }
+
+    fn next_record(&mut self, nanos: bool, link_type: u32) -> Result<Option<Packet>> {
+        let header = match self.try_read(16)? {
+            Some(header) => header,
+            None => return Ok(None),
+        };
+        let field = |at| self.u32_at(&header, at).unwrap_or_default();
+        let (secs, fraction, captured, length) = (field(0), field(4), field(8), field(12));
+        let data = self.read(usize::try_from(captured).unwrap_or(usize::MAX))?;
+        let fraction = if nanos {
+            u64::from(fraction)
+        } else {
+            u64::from(fraction) * 1_000
+        };
+        Ok(Some(Packet {
+            timestamp: u64::from(secs) * 1_000_000_000 + fraction,
+            length,
+            link_type,
+            interface: None,
+            data,
+        }))
+    }
+
+    /// The packet of the next packet block, other blocks are skipped
+    fn next_block(&mut self) -> Result<Option<Packet>> {
+        loop {
+            let header = match self.try_read(8)? {
+                Some(header) => header,
+                None => return Ok(None),
+            };
+            let kind = self.u32_at(&header, 0).unwrap_or_default();
+            if kind == SECTION_HEADER {
+                self.section(&header[4..])?;
+                continue;
+            }
+            let len = self
+                .u32_at(&header, 4)
+                .map_or(0, |l| usize::try_from(l).unwrap_or(usize::MAX));
+            // the body and the trailing length
+            let body = self.read(len.checked_sub(8).ok_or_else(|| invalid("short block"))?)?;
+            let field = |at| self.u32_at(&body, at).unwrap_or_default();
+            match kind {
+                INTERFACE_DESCRIPTION => {
+                    let interface = self.interface(&body);
+                    if let Format::PcapNg { interfaces } = &mut self.format {
+                        interfaces.push(interface);
+                    }
+                }
+                ENHANCED_PACKET => {
+                    let ticks = (u64::from(field(4)) << 32) | u64::from(field(8));
+                    let captured = usize::try_from(field(12)).unwrap_or(usize::MAX);
+                    let data = body
+                        .get(20..20_usize.saturating_add(captured))
+                        .ok_or_else(|| invalid("truncated packet"))?
+                        .to_vec();
+                    let interface = match &self.format {
+                        Format::PcapNg { interfaces } => usize::try_from(field(0))
+                            .ok()
+                            .and_then(|i| interfaces.get(i)),
+                        Format::Pcap { .. } => None,
+                    }
+                    .ok_or_else(|| invalid("packet of an undescribed interface"))?;
+                    let timestamp =
+                        u128::from(ticks) * 1_000_000_000 / u128::from(interface.units.max(1));
+                    return Ok(Some(Packet {
+                        timestamp: u64::try_from(timestamp).unwrap_or(u64::MAX),
+                        length: field(16),
+                        link_type: interface.link_type,
+                        interface: interface.name.clone(),
+                        data,
+                    }));
+                }
+                SIMPLE_PACKET => {
+                    // simple packets have no timestamp and belong to the first interface
+                    let length = field(0);
+                    let end = usize::try_from(length)
+                        .unwrap_or(usize::MAX)
+                        .saturating_add(4)
+                        .min(body.len().saturating_sub(4));
+                    let (link_type, interface) = match &self.format {
+                        Format::PcapNg { interfaces } => interfaces
+                            .first()
+                            .map(|i| (i.link_type, i.name.clone()))
+                            .ok_or_else(|| invalid("packet of an undescribed interface"))?,
+                        Format::Pcap { link_type, .. } => (*link_type, None),
+                    };
+                    return Ok(Some(Packet {
+                        timestamp: 0,
+                        length,
+                        link_type,
+                        interface,
+                        data: body.get(4..end).unwrap_or_default().to_vec(),
+                    }));
+                }
+                _ => (),
+            }
+        }
+    }
+}
+
+/// Runs `f` on a copy of `r`, advancing `r` only if the header could be
+/// decoded
+fn layer<'data, T>(
+    r: &mut Reader<'data>,
+    f: impl FnOnce(&mut Reader<'data>) -> Result<T>,
+) -> Option<T> {
+    let mut copy = *r;
+    let res = f(&mut copy).ok()?;
+    *r = copy;
+    Some(res)
+}
+
+/// Ethernet header, returns the ethertype after VLAN tags
+fn ethernet(r: &mut Reader) -> Result<(Object<'static>, u16)> {
+    let mut header = Object::with_capacity(4);
+    set(&mut header, "dst", r.mac()?);
+    set(&mut header, "src", r.mac()?);
+    let mut ethertype = r.u16()?;
+    while ethertype == 0x8100 || ethertype == 0x88a8 {
+        let tci = r.u16()?;
+        if !header.contains_key("vlan") {
+            set(&mut header, "vlan", tci & 0x0fff);
+        }
+        ethertype = r.u16()?;
+    }
+    set(&mut header, "ethertype", ethertype);
+    Ok((header, ethertype))
+}
+
+/// Linux cooked capture header, returns the ethertype
+fn sll(r: &mut Reader) -> Result<(Object<'static>, u16)> {
+    let mut header = Object::with_capacity(4);
+    set(&mut header, "packet_type", r.u16()?);
+    set(&mut header, "hardware_type", r.u16()?);
+    let len = usize::from(r.u16()?);
+    let addr = r.bytes(8)?;
+    set(
+        &mut header,
+        "src",
+        mac(addr.get(..len.min(8)).unwrap_or_default()),
+    );
+    let ethertype = r.u16()?;
+    set(&mut header, "ethertype", ethertype);
+    Ok((header, ethertype))
+}
+
+fn arp(r: &mut Reader) -> Result<Object<'static>> {
+    let mut header = Object::with_capacity(7);
+    set(&mut header, "hardware_type", r.u16()?);
+    let protocol = r.u16()?;
+    set(&mut header, "protocol_type", protocol);
+    let hardware_len = r.u8()?;
+    let protocol_len = r.u8()?;
+    set(&mut header, "operation", r.u16()?);
+    if hardware_len == 6 && protocol_len == 4 {
+        set(&mut header, "sender_mac", r.mac()?);
+        set(&mut header, "sender_ip", r.ipv4()?);
+        set(&mut header, "target_mac", r.mac()?);
+        set(&mut header, "target_ip", r.ipv4()?);
+    }
+    Ok(header)
+}
+
+/// IPv4 header, returns the protocol unless the packet is a later fragment
+/// and a reader of the payload without the padding of the frame
+fn ipv4<'data>(r: &mut Reader<'data>) -> Result<(Object<'static>, Option<u8>, Reader<'data>)> {
+    let mut header = Object::with_capacity(10);
+    let version_ihl = r.u8()?;
+    let ihl = usize::from(version_ihl & 0x0f) * 4;
+    set(&mut header, "tos", r.u8()?);
+    let total = usize::from(r.u16()?);
+    set(&mut header, "length", total);
+    set(&mut header, "id", r.u16()?);
+    let flags_offset = r.u16()?;
+    let offset = flags_offset & 0x1fff;
+    set(&mut header, "flags", flags_offset >> 13);
+    set(&mut header, "fragment_offset", offset);
+    set(&mut header, "ttl", r.u8()?);
+    let protocol = r.u8()?;
+    set(&mut header, "protocol", protocol);
+    r.u16()?; // checksum
+    set(&mut header, "src", r.ipv4()?);
+    set(&mut header, "dst", r.ipv4()?);
+    r.bytes(
+        ihl.checked_sub(20)
+            .ok_or_else(|| Error::from("IPv4 header too short"))?,
+    )?;
+    let payload = total.saturating_sub(ihl).min(r.remaining());
+    let protocol = if offset == 0 { Some(protocol) } else { None };
+    Ok((header, protocol, r.sub(payload)?))
+}
+
+/// IPv6 header and its extension headers, returns the protocol unless the
+/// packet is a later fragment and a reader of the payload
+fn ipv6<'data>(r: &mut Reader<'data>) -> Result<(Object<'static>, Option<u8>, Reader<'data>)> {
+    let mut header = Object::with_capacity(7);
+    let first = r.u32()?;
+    set(&mut header, "traffic_class", (first >> 20) & 0xff);
+    set(&mut header, "flow_label", first & 0x000f_ffff);
+    let len = usize::from(r.u16()?);
+    set(&mut header, "length", len);
+    let mut next = r.u8()?;
+    set(&mut header, "hop_limit", r.u8()?);
+    set(&mut header, "src", r.ipv6()?);
+    set(&mut header, "dst", r.ipv6()?);
+    let mut payload = r.sub(len.min(r.remaining()))?;
+    let mut first_fragment = true;
+    loop {
+        match next {
+            // hop by hop, routing and destination options
+            0 | 43 | 60 => {
+                next = payload.u8()?;
+                let len = usize::from(payload.u8()?);
+                payload.bytes((len + 1) * 8 - 2)?;
+            }
+            44 => {
+                next = payload.u8()?;
+                payload.u8()?;
+                first_fragment = payload.u16()? >> 3 == 0;
+                payload.u32()?; // identification
+            }
+            _ => break,
+        }
+    }
+    set(&mut header, "next_header", next);
+    let protocol = if first_fragment { Some(next) } else { None };
+    Ok((header, protocol, payload))
+}
+
+fn tcp(r: &mut Reader) -> Result<Object<'static>> {
+    let mut header = Object::with_capacity(6);
+    set(&mut header, "src_port", r.u16()?);
+    set(&mut header, "dst_port", r.u16()?);
+    set(&mut header, "seq", r.u32()?);
+    set(&mut header, "ack", r.u32()?);
+    let offset_flags = r.u16()?;
+    set(&mut header, "flags", offset_flags & 0x01ff);
+    set(&mut header, "window", r.u16()?);
+    r.u16()?; // checksum
+    r.u16()?; // urgent pointer
+    let len = usize::from(offset_flags >> 12) * 4;
+    r.bytes(
+        len.checked_sub(20)
+            .ok_or_else(|| Error::from("TCP header too short"))?,
+    )?;
+    Ok(header)
+}
+
+fn udp(r: &mut Reader) -> Result<Object<'static>> {
+    let mut header = Object::with_capacity(3);
+    set(&mut header, "src_port", r.u16()?);
+    set(&mut header, "dst_port", r.u16()?);
+    set(&mut header, "length", r.u16()?);
+    r.u16()?; // checksum
+    Ok(header)
+}
+
+fn icmp(r: &mut Reader) -> Result<Object<'static>> {
+    let mut header = Object::with_capacity(2);
+    set(&mut header, "type", r.u8()?);
+    set(&mut header, "code", r.u8()?);
+    r.u16()?; // checksum
+    Ok(header)
+}
+
+/// Decodes the headers of a packet as far as they are captured and known,
+/// the rest is the payload
+fn decode(packet: Packet) -> Value<'static> {
+    let mut event = Object::with_capacity(10);
+    set(&mut event, "timestamp", packet.timestamp);
+    set(&mut event, "length", packet.length);
+    set(&mut event, "captured_length", packet.data.len());
+    set(&mut event, "link_type", packet.link_type);
+    if let Some(interface) = packet.interface {
+        set(&mut event, "interface", interface);
+    }
+    let mut r = Reader::new(&packet.data);
+    let ethertype = match packet.link_type {
+        1 => layer(&mut r, ethernet).map(|(header, ethertype)| {
+            set(&mut event, "ethernet", header);
+            ethertype
+        }),
+        113 => layer(&mut r, sll).map(|(header, ethertype)| {
+            set(&mut event, "sll", header);
+            ethertype
+        }),
+        // raw IP
+        12 | 14 | 101 => match packet.data.first().map(|b| b >> 4) {
+            Some(4) => Some(0x0800),
+            Some(6) => Some(0x86dd),
+            _ => None,
+        },
+        _ => None,
+    };
+    let mut protocol = None;
+    match ethertype {
+        Some(0x0800) => {
+            if let Some((header, p, payload)) = layer(&mut r, ipv4) {
+                set(&mut event, "ipv4", header);
+                protocol = p;
+                r = payload;
+            }
+        }
+        Some(0x86dd) => {
+            if let Some((header, p, payload)) = layer(&mut r, ipv6) {
+                set(&mut event, "ipv6", header);
+                protocol = p;
+                r = payload;
+            }
+        }
+        Some(0x0806) => {
+            if let Some(header) = layer(&mut r, arp) {
+                set(&mut event, "arp", header);
+            }
+        }
+        _ => (),
+    }
+    let transport = match protocol {
+        Some(6) => layer(&mut r, tcp).map(|h| ("tcp", h)),
+        Some(17) => layer(&mut r, udp).map(|h| ("udp", h)),
+        Some(1) => layer(&mut r, icmp).map(|h| ("icmp", h)),
+        Some(58) => layer(&mut r, icmp).map(|h| ("icmpv6", h)),
+        _ => None,
+    };
+    if let Some((key, header)) = transport {
+        set(&mut event, key, header);
+    }
+    let rest = r.remaining();
+    let payload = r.bytes(rest).unwrap_or_default();
+    set(&mut event, "payload", Value::Bytes(payload.to_vec().into()));
+    Value::from(event)
+}
+
+/// Live capture through libpcap
+#[cfg(feature = "pcap")]
+mod live {
+    use super::Packet;
+    use crate::errors::{Error, Result};
+    use std::convert::TryFrom;
+
+    fn error(e: pcap_rs::Error) -> Error {
+        Error::from(format!("Packet capture failed: {}", e))
+    }
+
+    pub(super) struct Capture {
+        capture: pcap_rs::Capture<pcap_rs::Active>,
+        interface: String,
+        link_type: u32,
+    }
+
+    impl Capture {
+        pub(super) fn open(
+            interface: &str,
+            filter: Option<&str>,
+            promiscuous: bool,
+            snaplen: usize,
+        ) -> Result<Self> {
+            let snaplen = i32::try_from(snaplen)
+                .map_err(|_| Error::from(format!("Invalid snaplen {}", snaplen)))?;
+            let mut capture = pcap_rs::Capture::from_device(interface)
+                .map_err(error)?
+                .promisc(promiscuous)
+                .snaplen(snaplen)
+                // wake up regularly to notice the onramp stopping
+                .timeout(100)
+                .open()
+                .map_err(error)?;
+            // compiled by libpcap and run in the kernel where supported
+            if let Some(filter) = filter {
+                capture
+                    .filter(filter, true)
+                    .map_err(|e| Error::from(format!("Invalid pcap filter `{}`: {}", filter, e)))?;
+            }
+            let link_type = u32::try_from(capture.get_datalink().0).unwrap_or_default();
+            Ok(Self {
+                capture,
+                interface: interface.to_string(),
+                link_type,
+            })
+        }
+
+        /// The next packet, `None` if there was none before the read timeout
+        pub(super) fn next_packet(&mut self) -> Result<Option<Packet>> {
+            match self.capture.next() {
+                Ok(packet) => {
+                    let ts = packet.header.ts;
+                    let secs = u64::try_from(ts.tv_sec).unwrap_or_default();
+                    let micros = u64::try_from(ts.tv_usec).unwrap_or_default();
+                    Ok(Some(Packet {
+                        timestamp: secs * 1_000_000_000 + micros * 1000,
+                        // `caplen` is the length of `data`
+                        length: packet.header.len,
+                        link_type: self.link_type,
+                        interface: Some(self.interface.clone()),
+                        data: packet.data.to_vec(),
+                    }))
+                }
+                Err(pcap_rs::Error::TimeoutExpired) => Ok(None),
+                Err(e) => Err(error(e)),
+            }
+        }
+    }
+}
+
+pub struct Pcap {
+    pub config: Config,
+    onramp_id: TremorUrl,
+}
+
+struct Int {
+    config: Config,
+    packets: Option<Receiver<Packet>>,
+    onramp_id: TremorUrl,
+    origin_uri: EventOriginUri,
+}
+
+impl std::fmt::Debug for Int {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "Pcap")
+    }
+}
+
+impl Int {
+    fn from_config(uid: u64, onramp_id: TremorUrl, config: &Config) -> Self {
+        let origin_uri = EventOriginUri {
+            uid,
+            scheme: "tremor-pcap".to_string(),
+            host: hostname(),
+            port: None,
+            path: vec![config
+                .file
+                .clone()
+                .or_else(|| config.interface.clone())
+                .unwrap_or_default()],
+        };
+        Self {
+            config: config.clone(),
+            packets: None,
+            onramp_id,
+            origin_uri,
+        }
+    }
+}
+
+impl onramp::Impl for Pcap {
+    fn from_config(onramp_id: &TremorUrl, config: &Option<YamlValue>) -> Result<Box<dyn Onramp>> {
+        if let Some(config) = config {
+            let config: Config = Config::new(config)?;
+            match (&config.file, &config.interface) {
+                (Some(_), None) if config.filter.is_some() => {
+                    return Err("The filter of the pcap onramp only applies to interfaces".into())
+                }
+                (Some(_), None) | (None, Some(_)) => (),
+                _ => {
+                    return Err(
+                        "The pcap onramp needs either a file or an interface to read from".into(),
+                    )
+                }
+            }
+            if cfg!(not(feature = "pcap")) && config.interface.is_some() {
+                return Err(
+                    "Live capture of the pcap onramp requires tremor to be built with the `pcap` feature"
+                        .into(),
+                );
+            }
+            Ok(Box::new(Self {
+                config,
+                onramp_id: onramp_id.clone(),
+            }))
+        } else {
+            Err("Missing config for pcap onramp".into())
+        }
+    }
+}
+
+/// Reads packets on a thread of its own, as files and live captures block
+fn spawn<F>(id: String, mut next: F) -> Receiver<Packet>
+where
+    F: FnMut() -> Result<Option<Packet>> + Send + 'static,
+{
+    let (tx, rx) = bounded(crate::QSIZE);
+    std::thread::spawn(move || loop {
+        match next() {
+            Ok(Some(packet)) => {
+                if task::block_on(tx.send(packet)).is_err() {
+                    break;
+                }
+            }
+            // live captures time out, files end
+            Ok(None) => {
+                if tx.is_closed() {
+                    break;
+                }
+            }
+            Err(e) => {
+                error!("[Source::{}] Error reading packets: {}", id, e);
+                break;
+            }
+        }
+    });
+    rx
+}
+
+#[async_trait::async_trait()]
+impl Source for Int {
+    fn id(&self) -> &TremorUrl {
+        &self.onramp_id
+    }
+
+    async fn pull_event(&mut self, _id: u64) -> Result<SourceReply> {
+        let packets = match &self.packets {
+            Some(packets) => packets,
+            None => return Ok(SourceReply::StateChange(SourceState::Disconnected)),
+        };
+        match packets.try_recv() {
+            Ok(packet) => Ok(SourceReply::Structured {
+                origin_uri: self.origin_uri.clone(),
+                data: decode(packet).into(),
+            }),
+            Err(TryRecvError::Empty) => Ok(SourceReply::Empty(10)),
+            Err(TryRecvError::Closed) => {
+                if self.config.close_on_done {
+                    // ALLOW: This is on purpose, close when done tells the onramp to terminate when it's done with sending it's data - this is for one-offs
+                    process::exit(0);
+                }
+                Ok(SourceReply::StateChange(SourceState::Disconnected))
+            }
+        }
+    }
+
+    async fn init(&mut self) -> Result<SourceState> {
+        let id = self.onramp_id.to_string();
+        let packets = if let Some(path) = &self.config.file {
+            let mut file = CaptureFile::new(BufReader::new(fs::File::open(path)?))?;
+            spawn(id, move || file.next_packet())
+        } else {
+            self.live(id)?
+        };
+        self.packets = Some(packets);
+        Ok(SourceState::Connected)
+    }
+}
+
+impl Int {
+    #[cfg(feature = "pcap")]
+    fn live(&self, id: String) -> Result<Receiver<Packet>> {
+        let interface = self.config.interface.clone().unwrap_or_default();
+        let mut capture = live::Capture::open(
+            &interface,
+            self.config.filter.as_deref(),
+            self.config.promiscuous,
+            self.config.snaplen,
+        )?;
+        info!("[Source::{}] Capturing packets on {}", id, interface);
+        Ok(spawn(id, move || capture.next_packet()))
+    }
+
+    #[cfg(not(feature = "pcap"))]
+    fn live(&self, _id: String) -> Result<Receiver<Packet>> {
+        Err(
+            "Live capture of the pcap onramp requires tremor to be built with the `pcap` feature"
+                .into(),
+        )
+    }
+}
+
+#[async_trait::async_trait]
+impl Onramp for Pcap {
+    async fn start(&mut self, config: OnrampConfig<'_>) -> Result<onramp::Addr> {
+        let source = Int::from_config(config.onramp_uid, self.onramp_id.clone(), &self.config);
+        SourceManager::start(source, config).await
+    }
+
+    fn default_codec(&self) -> &str {
+        "null"
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use std::io::Cursor;
+    use std::net::Ipv6Addr;
+
+    fn udp_frame() -> Vec<u8> {
+        let mut f = vec![0, 1, 2, 3, 4, 5, 6, 7, 8, 9, 10, 11, 0x08, 0x00];
+        f.extend_from_slice(&[0x45, 0, 0, 32, 0, 1, 0x40, 0, 64, 17, 0, 0]);
+        f.extend_from_slice(&[10, 0, 0, 1, 10, 0, 0, 2]);
+        f.extend_from_slice(&[0x14, 0xe9, 0, 53, 0, 12, 0, 0]);
+        f.extend_from_slice(b"ping");
+        // ethernet padding to the minimum frame size
+        f.resize(60, 0);
+        f
+    }
+
+    fn tcp_frame() -> Vec<u8> {
+        let mut f = vec![0, 1, 2, 3, 4, 5, 6, 7, 8, 9, 10, 11, 0x86, 0xdd];
+        f.extend_from_slice(&[0x60, 0, 0, 0, 0, 20, 6, 64]);
+        f.extend_from_slice(&Ipv6Addr::LOCALHOST.octets());
+        f.extend_from_slice(&Ipv6Addr::from(2).octets());
+        f.extend_from_slice(&[0x01, 0xbb, 0xc8, 0x22, 0, 0, 0, 1, 0, 0, 0, 2]);
+        f.extend_from_slice(&[0x50, 0x12, 0x04, 0x00, 0, 0, 0, 0]);
+        f
+    }
+
+    fn put_u16(b: &mut Vec<u8>, v: u16) {
+        b.extend_from_slice(&v.to_le_bytes());
+    }
+    fn put_u32(b: &mut Vec<u8>, v: u32) {
+        b.extend_from_slice(&v.to_le_bytes());
+    }
+    fn len(b: &[u8]) -> u32 {
+        u32::try_from(b.len()).unwrap_or_default()
+    }
+
+    #[test]
+    fn pcap() -> Result<()> {
+        let frame = udp_frame();
+        let mut f = Vec::new();
+        put_u32(&mut f, PCAP);
+        for v in [2, 4] {
+            put_u16(&mut f, v);
+        }
+        for v in [0, 0, 65535, 1] {
+            put_u32(&mut f, v);
+        }
+        for v in [1_600_000_000, 500, len(&frame), len(&frame)] {
+            put_u32(&mut f, v);
+        }
+        f.extend_from_slice(&frame);
+
+        let mut file = CaptureFile::new(Cursor::new(f))?;
+        let packet = file
+            .next_packet()?
+            .ok_or_else(|| Error::from("no packet"))?;
+        assert!(file.next_packet()?.is_none());
+        assert_eq!(1_600_000_000_000_500_000, packet.timestamp);
+        let e = decode(packet);
+        assert_eq!(Some(60), e.get_u64("captured_length"));
+        let ethernet = e
+            .get("ethernet")
+            .ok_or_else(|| Error::from("no ethernet"))?;
+        assert_eq!(Some("06:07:08:09:0a:0b"), ethernet.get_str("src"));
+        let ip = e.get("ipv4").ok_or_else(|| Error::from("no ipv4"))?;
+        assert_eq!(Some("10.0.0.1"), ip.get_str("src"));
+        assert_eq!(Some("10.0.0.2"), ip.get_str("dst"));
+        assert_eq!(Some(2), ip.get_u64("flags"));
+        let udp = e.get("udp").ok_or_else(|| Error::from("no udp"))?;
+        assert_eq!(Some(5353), udp.get_u64("src_port"));
+        assert_eq!(Some(53), udp.get_u64("dst_port"));
+        // without the padding of the frame
+        assert_eq!(
+            Some(&Value::Bytes(b"ping".to_vec().into())),
+            e.get("payload")
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn pcapng() -> Result<()> {
+        let mut f = Vec::new();
+        for v in [SECTION_HEADER, 28, BYTE_ORDER] {
+            put_u32(&mut f, v);
+        }
+        for v in [1, 0] {
+            put_u16(&mut f, v);
+        }
+        f.extend_from_slice(&[0xff; 8]);
+        put_u32(&mut f, 28);
+
+        for v in [INTERFACE_DESCRIPTION, 40] {
+            put_u32(&mut f, v);
+        }
+        for v in [1, 0] {
+            put_u16(&mut f, v);
+        }
+        put_u32(&mut f, 65535);
+        for v in [2, 4] {
+            put_u16(&mut f, v);
+        }
+        f.extend_from_slice(b"eth0");
+        // nanosecond timestamps
+        for v in [9, 1] {
+            put_u16(&mut f, v);
+        }
+        f.extend_from_slice(&[9, 0, 0, 0, 0, 0, 0, 0]);
+        put_u32(&mut f, 40);
+
+        // a block of an unknown type is skipped
+        for v in [0x0bad, 12, 12] {
+            put_u32(&mut f, v);
+        }
+
+        let frame = tcp_frame();
+        let ticks: u64 = 1_600_000_000_123_456_789;
+        for v in [ENHANCED_PACKET, 8 + 20 + 76 + 4, 0] {
+            put_u32(&mut f, v);
+        }
+        put_u32(&mut f, u32::try_from(ticks >> 32).unwrap_or_default());
+        u32(
+            &mut f,
+            u32::try_from(ticks & 0xffff_ffff).unwrap_or_default(),
+        );
+        for v in [len(&frame), len(&frame)] {
+            put_u32(&mut f, v);
+        }
+        f.extend_from_slice(&frame);
+        f.extend_from_slice(&[0, 0]);
+        put_u32(&mut f, 8 + 20 + 76 + 4);
+
+        let mut file = CaptureFile::new(Cursor::new(f))?;
+        let packet = file
+            .next_packet()?
+            .ok_or_else(|| Error::from("no packet"))?;
+        assert!(file.next_packet()?.is_none());
+        assert_eq!(ticks, packet.timestamp);
+        let e = decode(packet);
+        assert_eq!(Some("eth0"), e.get_str("interface"));
+        let ip = e.get("ipv6").ok_or_else(|| Error::from("no ipv6"))?;
+        assert_eq!(Some("::1"), ip.get_str("src"));
+        assert_eq!(Some("::2"), ip.get_str("dst"));
+        let tcp = e.get("tcp").ok_or_else(|| Error::from("no tcp"))?;
+        assert_eq!(Some(443), tcp.get_u64("src_port"));
+        assert_eq!(Some(51234), tcp.get_u64("dst_port"));
+        assert_eq!(Some(0x12), tcp.get_u64("flags"));
+        assert_eq!(Some(&Value::Bytes(vec![].into())), e.get("payload"));
+        Ok(())
+    }
+
+    #[test]
+    fn truncated() -> Result<()> {
+        // headers are decoded as far as they were captured
+        let mut data = udp_frame();
+        data.truncate(20);
+        let e = decode(Packet {
+            timestamp: 0,
+            length: 60,
+            link_type: 1,
+            interface: None,
+            data,
+        });
+        assert!(e.get("ethernet").is_some());
+        assert!(e.get("ipv4").is_none());
+        assert_eq!(
+            Some(&Value::Bytes(vec![0x45, 0, 0, 32, 0, 1].into())),
+            e.get("payload")
+        );
+
+        assert!(CaptureFile::new(Cursor::new(b"snot badger".to_vec())).is_err());
+        Ok(())
+    }
+
+    #[test]
+    fn config() -> Result<()> {
+        let id = TremorUrl::parse("/onramp/pcap/01/out")?;
+        let live: YamlValue = serde_yaml::from_str("{interface: eth0, filter: tcp port 80}")?;
+        let config = Config::new(&live)?;
+        assert_eq!(Some("tcp port 80"), config.filter.as_deref());
+        assert_eq!(
+            cfg!(feature = "pcap"),
+            Pcap::from_config(&id, &Some(live)).is_ok()
+        );
+
+        // filters only apply to live captures
+        let file: YamlValue = serde_yaml::from_str("{file: in.pcap, filter: udp}")?;
+        assert!(Pcap::from_config(&id, &Some(file)).is_err());
+        Ok(())
+    }
+}
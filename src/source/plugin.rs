@@ -0,0 +1,141 @@
+// Copyright 2020-2021, The Tremor Team
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+#![cfg(not(tarpaulin_include))]
+
+//! Onramps provided by sidecar plugins, see `connectors::plugin` for the protocol.
+
+use crate::connectors::plugin::{Kind, Manifest, Sidecar};
+use crate::source::prelude::*;
+
+pub struct Plugin {
+    onramp_id: TremorUrl,
+    manifest: Manifest,
+    config: Option<YamlValue>,
+}
+
+impl Plugin {
+    pub(crate) fn from_manifest(
+        id: &TremorUrl,
+        manifest: Manifest,
+        config: &Option<YamlValue>,
+    ) -> Result<Box<dyn Onramp>> {
+        Ok(Box::new(Self {
+            onramp_id: id.clone(),
+            manifest,
+            config: config.clone(),
+        }))
+    }
+}
+
+pub struct Int {
+    onramp_id: TremorUrl,
+    origin_uri: EventOriginUri,
+    manifest: Manifest,
+    config: Option<YamlValue>,
+    sidecar: Option<Sidecar>,
+    /// acks and fails sent with the next pull
+    pending: Vec<Value<'static>>,
+}
+
+impl std::fmt::Debug for Int {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "plugin {}", self.manifest.name)
+    }
+}
+
+#[async_trait::async_trait]
+impl Onramp for Plugin {
+    async fn start(&mut self, config: OnrampConfig<'_>) -> Result<onramp::Addr> {
+        let origin_uri = EventOriginUri {
+            uid: config.onramp_uid,
+            scheme: format!("tremor-{}", self.manifest.name),
+            host: hostname(),
+            port: None,
+            path: vec![],
+        };
+        let source = Int {
+            onramp_id: self.onramp_id.clone(),
+            origin_uri,
+            manifest: self.manifest.clone(),
+            config: self.config.clone(),
+            sidecar: None,
+            pending: Vec::new(),
+        };
+        SourceManager::start(source, config).await
+    }
+
+    fn default_codec(&self) -> &str {
+        &self.manifest.codec
+    }
+}
+
+#[async_trait::async_trait()]
+impl Source for Int {
+    fn id(&self) -> &TremorUrl {
+        &self.onramp_id
+    }
+
+    async fn pull_event(&mut self, id: u64) -> Result<SourceReply> {
+        let sidecar = match self.sidecar.as_mut() {
+            Some(sidecar) if !sidecar.exited() => sidecar,
+            _ => return Ok(SourceReply::StateChange(SourceState::Disconnected)),
+        };
+        for msg in self.pending.drain(..) {
+            sidecar.send(&msg).await?;
+        }
+        let answer = sidecar.request(&literal!({ "pull": id })).await?;
+        if let Some(data) = answer.get_str("data") {
+            Ok(SourceReply::Data {
+                origin_uri: self.origin_uri.clone(),
+                data: base64::decode(data)?,
+                meta: answer.get("meta").map(Value::clone_static),
+                codec_override: None,
+                stream: 0,
+            })
+        } else if let Some(ms) = answer.get_u64("empty") {
+            Ok(SourceReply::Empty(ms))
+        } else {
+            Err(format!(
+                "Plugin {} answered a pull with neither data nor empty",
+                self.manifest.name
+            )
+            .into())
+        }
+    }
+
+    async fn init(&mut self) -> Result<SourceState> {
+        let sidecar = Sidecar::start(
+            &self.manifest,
+            Kind::Onramp,
+            &self.onramp_id.to_string(),
+            &self.config,
+        )
+        .await?;
+        self.sidecar = Some(sidecar);
+        Ok(SourceState::Connected)
+    }
+
+    fn ack(&mut self, id: u64) {
+        self.pending.push(literal!({ "ack": id }));
+    }
+
+    fn fail(&mut self, id: u64) {
+        self.pending.push(literal!({ "fail": id }));
+    }
+
+    fn is_transactional(&self) -> bool {
+        self.manifest.transactional
+    }
+}
@@ -0,0 +1,376 @@
+// Copyright 2020-2021, The Tremor Team
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+#![cfg(not(tarpaulin_include))]
+
+//! # QUIC Onramp (experimental)
+//!
+//! Accepts QUIC connections and reads each unidirectional stream as its own
+//! tremor stream. In `http3` mode each HTTP/3 request is a tremor stream
+//! instead, its method and path are added to the metadata and it is answered
+//! with `202 Accepted` once the body is read. 0-RTT data of resumed sessions
+//! is only accepted if `zero_rtt` is set, since it can be replayed.
+
+use crate::connectors::quic;
+use crate::connectors::tls::TlsConfig;
+use crate::errors::Result;
+use crate::source::prelude::*;
+use async_channel::Sender;
+use async_channel::TryRecvError;
+use async_std::net::ToSocketAddrs;
+use bytes::{Buf, Bytes};
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::Arc;
+use tremor_value::literal;
+
+const BUFFER_SIZE_BYTES: usize = 8192;
+
+#[derive(Debug, Clone, Deserialize)]
+pub struct Config {
+    pub port: u16,
+    pub host: String,
+    /// TLS, `cert` and `key` are required, client certificates are required
+    /// if `cafile` is set
+    pub tls: TlsConfig,
+    /// `raw` streams or `http3` requests (default: raw)
+    #[serde(default)]
+    pub mode: quic::Mode,
+    /// Accept 0-RTT data of resumed sessions before the handshake completes,
+    /// only enable this if replayed events are harmless (default: false)
+    #[serde(default)]
+    pub zero_rtt: bool,
+}
+
+impl ConfigImpl for Config {}
+
+pub struct Quic {
+    pub config: Config,
+    onramp_id: TremorUrl,
+}
+
+pub struct Int {
+    uid: u64,
+    config: Config,
+    listener: Option<Receiver<SourceReply>>,
+    onramp_id: TremorUrl,
+}
+impl std::fmt::Debug for Int {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "QUIC")
+    }
+}
+impl Int {
+    fn from_config(uid: u64, onramp_id: TremorUrl, config: &Config) -> Self {
+        Self {
+            uid,
+            config: config.clone(),
+            listener: None,
+            onramp_id,
+        }
+    }
+}
+
+impl onramp::Impl for Quic {
+    fn from_config(id: &TremorUrl, config: &Option<YamlValue>) -> Result<Box<dyn Onramp>> {
+        if let Some(config) = config {
+            let config: Config = Config::new(config)?;
+            Ok(Box::new(Self {
+                config,
+                onramp_id: id.clone(),
+            }))
+        } else {
+            Err("Missing config for quic onramp".into())
+        }
+    }
+}
+
+#[async_trait::async_trait()]
+impl Source for Int {
+    fn id(&self) -> &TremorUrl {
+        &self.onramp_id
+    }
+
+    async fn pull_event(&mut self, _id: u64) -> Result<SourceReply> {
+        self.listener.as_ref().map_or_else(
+            || Ok(SourceReply::StateChange(SourceState::Disconnected)),
+            |listener| match listener.try_recv() {
+                Ok(r) => Ok(r),
+                Err(TryRecvError::Empty) => Ok(SourceReply::Empty(10)),
+                Err(TryRecvError::Closed) => {
+                    Ok(SourceReply::StateChange(SourceState::Disconnected))
+                }
+            },
+        )
+    }
+
+    async fn init(&mut self) -> Result<SourceState> {
+        let addr = (self.config.host.as_str(), self.config.port)
+            .to_socket_addrs()
+            .await?
+            .next()
+            .ok_or_else(|| Error::from(format!("Can't resolve {}", self.config.host)))?;
+        let mut builder = quinn::Endpoint::builder();
+        builder.listen(quic::server_config(
+            &self.config.tls,
+            self.config.mode,
+            self.config.zero_rtt,
+        )?);
+        let (endpoint, mut incoming) = builder.bind(&addr)?;
+        info!("[QUIC Onramp] listening on {}", endpoint.local_addr()?);
+
+        let (tx, rx) = bounded(crate::QSIZE);
+        let uid = self.uid;
+        let path = vec![self.config.port.to_string()];
+        let mode = self.config.mode;
+        let zero_rtt = self.config.zero_rtt;
+        task::spawn(async move {
+            // the endpoint is closed once it is dropped
+            let _endpoint = endpoint;
+            let next_stream = Arc::new(AtomicUsize::new(1));
+            let mut connection = 0;
+            while let Some(connecting) = incoming.next().await {
+                connection += 1;
+                let peer = connecting.remote_address();
+                let origin_uri = EventOriginUri {
+                    uid,
+                    scheme: "tremor-quic".to_string(),
+                    host: peer.ip().to_string(),
+                    port: Some(peer.port()),
+                    path: path.clone(), // captures server port
+                };
+                let meta = literal!({
+                    "connection": connection,
+                    "peer": {
+                        "host": peer.ip().to_string(),
+                        "port": peer.port()
+                    }
+                });
+                task::spawn(serve(
+                    connecting,
+                    mode,
+                    zero_rtt,
+                    tx.clone(),
+                    next_stream.clone(),
+                    origin_uri,
+                    meta,
+                ));
+            }
+        });
+        self.listener = Some(rx);
+
+        Ok(SourceState::Connected)
+    }
+}
+
+/// Reads the streams or requests of a connection until it is closed
+async fn serve(
+    connecting: quinn::Connecting,
+    mode: quic::Mode,
+    zero_rtt: bool,
+    tx: Sender<SourceReply>,
+    next_stream: Arc<AtomicUsize>,
+    origin_uri: EventOriginUri,
+    meta: Value<'static>,
+) {
+    // with 0-RTT the streams of resumed sessions are readable before the
+    // handshake completes
+    let connecting = if zero_rtt {
+        connecting
+            .into_0rtt()
+            .map(|(connection, _accepted)| connection)
+    } else {
+        Err(connecting)
+    };
+    let connection = match connecting {
+        Ok(connection) => connection,
+        Err(connecting) => match connecting.await {
+            Ok(connection) => connection,
+            Err(e) => {
+                warn!("[QUIC Onramp] Handshake failed: {}", e);
+                return;
+            }
+        },
+    };
+    if mode == quic::Mode::Http3 {
+        return serve_h3(connection, tx, next_stream, origin_uri, meta).await;
+    }
+    let quinn::NewConnection {
+        mut uni_streams, ..
+    } = connection;
+    while let Some(stream) = uni_streams.next().await {
+        match stream {
+            Ok(stream) => {
+                let stream_id = next_stream.fetch_add(1, Ordering::Relaxed);
+                task::spawn(read_stream(
+                    stream,
+                    tx.clone(),
+                    stream_id,
+                    origin_uri.clone(),
+                    meta.clone(),
+                ));
+            }
+            Err(e) => {
+                debug!("[QUIC Onramp] Connection closed: {}", e);
+                break;
+            }
+        }
+    }
+}
+
+/// Accepts the HTTP/3 requests of a connection until it is closed
+async fn serve_h3(
+    connection: quinn::NewConnection,
+    tx: Sender<SourceReply>,
+    next_stream: Arc<AtomicUsize>,
+    origin_uri: EventOriginUri,
+    meta: Value<'static>,
+) {
+    let mut connection =
+        match h3::server::Connection::new(h3_quinn::Connection::new(connection)).await {
+            Ok(connection) => connection,
+            Err(e) => {
+                warn!("[QUIC Onramp] HTTP/3 handshake failed: {}", e);
+                return;
+            }
+        };
+    loop {
+        match connection.accept().await {
+            Ok(Some((request, stream))) => {
+                let stream_id = next_stream.fetch_add(1, Ordering::Relaxed);
+                let mut meta = meta.clone();
+                meta.try_insert(
+                    "request",
+                    literal!({
+                        "method": request.method().to_string(),
+                        "path": request.uri().path().to_string()
+                    }),
+                );
+                task::spawn(read_request(
+                    stream,
+                    tx.clone(),
+                    stream_id,
+                    origin_uri.clone(),
+                    meta,
+                ));
+            }
+            Ok(None) => break,
+            Err(e) => {
+                debug!("[QUIC Onramp] Connection closed: {}", e);
+                break;
+            }
+        }
+    }
+}
+
+/// Reads the body of a HTTP/3 request and answers it once it is read
+async fn read_request(
+    mut stream: h3::server::RequestStream<h3_quinn::BidiStream<Bytes>, Bytes>,
+    tx: Sender<SourceReply>,
+    stream_id: usize,
+    origin_uri: EventOriginUri,
+    meta: Value<'static>,
+) {
+    if let Err(e) = tx.send(SourceReply::StartStream(stream_id)).await {
+        error!("QUIC Error: {}", e);
+        return;
+    }
+    let mut status = http::StatusCode::ACCEPTED;
+    loop {
+        let mut chunk = match stream.recv_data().await {
+            Ok(Some(chunk)) => chunk,
+            Ok(None) => break,
+            Err(e) => {
+                debug!("HTTP/3 request {} failed: {}", stream_id, e);
+                status = http::StatusCode::BAD_REQUEST;
+                break;
+            }
+        };
+        let data = chunk.copy_to_bytes(chunk.remaining()).to_vec();
+        if let Err(e) = tx
+            .send(SourceReply::Data {
+                origin_uri: origin_uri.clone(),
+                data,
+                meta: Some(meta.clone()),
+                codec_override: None,
+                stream: stream_id,
+            })
+            .await
+        {
+            error!("QUIC Error: {}", e);
+            return;
+        };
+    }
+    if let Err(e) = tx.send(SourceReply::EndStream(stream_id)).await {
+        error!("QUIC Error: {}", e);
+    };
+    let mut response = http::Response::new(());
+    *response.status_mut() = status;
+    if let Err(e) = stream.send_response(response).await {
+        debug!("HTTP/3 response {} failed: {}", stream_id, e);
+    } else if let Err(e) = stream.finish().await {
+        debug!("HTTP/3 response {} failed: {}", stream_id, e);
+    }
+}
+
+async fn read_stream(
+    mut stream: quinn::RecvStream,
+    tx: Sender<SourceReply>,
+    stream_id: usize,
+    origin_uri: EventOriginUri,
+    meta: Value<'static>,
+) {
+    if let Err(e) = tx.send(SourceReply::StartStream(stream_id)).await {
+        error!("QUIC Error: {}", e);
+        return;
+    }
+    let mut buffer = [0; BUFFER_SIZE_BYTES];
+    loop {
+        let n = match stream.read(&mut buffer).await {
+            Ok(Some(n)) => n,
+            Ok(None) => break,
+            Err(e) => {
+                debug!("QUIC stream {} failed: {}", stream_id, e);
+                break;
+            }
+        };
+        if let Err(e) = tx
+            .send(SourceReply::Data {
+                origin_uri: origin_uri.clone(),
+                // ALLOW: we define n as part of the read
+                data: buffer[0..n].to_vec(),
+                meta: Some(meta.clone()),
+                codec_override: None,
+                stream: stream_id,
+            })
+            .await
+        {
+            error!("QUIC Error: {}", e);
+            return;
+        };
+    }
+    if let Err(e) = tx.send(SourceReply::EndStream(stream_id)).await {
+        error!("QUIC Error: {}", e);
+    };
+}
+
+#[async_trait::async_trait]
+impl Onramp for Quic {
+    async fn start(&mut self, config: OnrampConfig<'_>) -> Result<onramp::Addr> {
+        let source = Int::from_config(config.onramp_uid, self.onramp_id.clone(), &self.config);
+        SourceManager::start(source, config).await
+    }
+
+    fn default_codec(&self) -> &str {
+        "json"
+    }
+}
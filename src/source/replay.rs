@@ -0,0 +1,226 @@
+// Copyright 2020-2021, The Tremor Team
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+//! # Replay Onramp
+//!
+//! Replays captures written by the `capture` offramp, keeping the time
+//! between events, optionally sped up or slowed down. Records are read from
+//! the capture as they are replayed.
+
+#![cfg(not(tarpaulin_include))]
+
+use crate::source::prelude::*;
+use async_compression::futures::bufread::{XzDecoder, ZstdDecoder};
+use async_std::io::{BufReader, Lines};
+use futures::io::AsyncRead;
+use std::time::Duration;
+use tremor_common::asy::file;
+
+#[derive(Deserialize, Debug, Clone)]
+pub struct Config {
+    /// capture file to replay, can be xz or zstd compressed
+    pub source: String,
+    /// factor to speed up the replay by, `2.0` replays twice as fast,
+    /// `0.5` at half the speed
+    #[serde(default = "default_speed")]
+    pub speed: f64,
+}
+
+fn default_speed() -> f64 {
+    1.0
+}
+
+impl ConfigImpl for Config {}
+
+pub struct Replay {
+    pub config: Config,
+    onramp_id: TremorUrl,
+}
+
+type Records = Lines<BufReader<Box<dyn AsyncRead + Send + Unpin>>>;
+
+struct Int {
+    config: Config,
+    onramp_id: TremorUrl,
+    records: Option<Records>,
+    /// captured ingest time of the previous record
+    last: Option<u64>,
+    origin_uri: EventOriginUri,
+}
+impl std::fmt::Debug for Int {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "Replay")
+    }
+}
+
+impl onramp::Impl for Replay {
+    fn from_config(id: &TremorUrl, config: &Option<YamlValue>) -> Result<Box<dyn Onramp>> {
+        if let Some(config) = config {
+            let config: Config = Config::new(config)?;
+            if config.speed <= 0.0 {
+                return Err(format!(
+                    "[onramp:{}] Invalid replay speed {}, it needs to be positive",
+                    id, config.speed
+                )
+                .into());
+            }
+            Ok(Box::new(Self {
+                config,
+                onramp_id: id.clone(),
+            }))
+        } else {
+            Err("Missing config for replay onramp".into())
+        }
+    }
+}
+
+/// Splits a capture record into its ingest time, data and metadata
+fn parse_record(mut raw: Vec<u8>) -> Result<(Option<u64>, Value<'static>, Value<'static>)> {
+    let record: Value<'static> = Value::from(simd_json::to_owned_value(&mut raw)?);
+    let ingest_ns = record.get_u64("ingest_ns");
+    let data = record
+        .get("data")
+        .cloned()
+        .ok_or_else(|| Error::from("Capture record without `data`"))?;
+    let meta = record.get("meta").cloned().unwrap_or_else(Value::object);
+    Ok((ingest_ns, data, meta))
+}
+
+/// Compression of a capture by its magic bytes
+#[derive(Debug, PartialEq)]
+enum Compression {
+    None,
+    Xz,
+    Zstd,
+}
+
+fn compression(head: &[u8]) -> Compression {
+    match head.get(0..4) {
+        Some(&[0xfd, b'7', b'z', b'X']) => Compression::Xz,
+        Some(&[0x28, 0xb5, 0x2f, 0xfd]) => Compression::Zstd,
+        _ => Compression::None,
+    }
+}
+
+/// Opens the records of a capture
+async fn open(path: &str) -> Result<Records> {
+    let mut reader = BufReader::new(file::open(path).await?);
+    // not imported, its `lines` would be ambiguous with the one of async-std
+    let kind = compression(futures::AsyncBufReadExt::fill_buf(&mut reader).await?);
+    let reader: Box<dyn AsyncRead + Send + Unpin> = match kind {
+        Compression::None => Box::new(reader),
+        Compression::Xz => Box::new(XzDecoder::new(reader)),
+        Compression::Zstd => Box::new(ZstdDecoder::new(reader)),
+    };
+    Ok(BufReader::new(reader).lines())
+}
+
+#[async_trait::async_trait()]
+impl Source for Int {
+    fn id(&self) -> &TremorUrl {
+        &self.onramp_id
+    }
+
+    async fn pull_event(&mut self, _id: u64) -> Result<SourceReply> {
+        let raw = loop {
+            let line = match self.records.as_mut() {
+                Some(records) => records.next().await.transpose()?,
+                None => None,
+            };
+            match line {
+                Some(line) if line.trim().is_empty() => continue,
+                Some(line) => break line.into_bytes(),
+                None => {
+                    self.records = None;
+                    return Ok(SourceReply::StateChange(SourceState::Disconnected));
+                }
+            }
+        };
+        let (ingest_ns, data, meta) = parse_record(raw)?;
+        if let (Some(last), Some(ingest_ns)) = (self.last, ingest_ns) {
+            let gap = Duration::from_nanos(ingest_ns.saturating_sub(last));
+            task::sleep(gap.div_f64(self.config.speed)).await;
+        }
+        if ingest_ns.is_some() {
+            self.last = ingest_ns;
+        }
+
+        Ok(SourceReply::Structured {
+            origin_uri: self.origin_uri.clone(),
+            data: (data, meta).into(),
+        })
+    }
+    async fn init(&mut self) -> Result<SourceState> {
+        self.records = Some(open(&self.config.source).await?);
+        self.last = None;
+        Ok(SourceState::Connected)
+    }
+}
+
+#[async_trait::async_trait]
+impl Onramp for Replay {
+    async fn start(&mut self, config: OnrampConfig<'_>) -> Result<onramp::Addr> {
+        let origin_uri = EventOriginUri {
+            uid: config.onramp_uid,
+            scheme: "tremor-replay".to_string(),
+            host: hostname(),
+            port: None,
+            path: vec![self.config.source.clone()],
+        };
+        let source = Int {
+            config: self.config.clone(),
+            onramp_id: self.onramp_id.clone(),
+            records: None,
+            last: None,
+            origin_uri,
+        };
+        SourceManager::start(source, config).await
+    }
+    fn default_codec(&self) -> &str {
+        "json"
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use tremor_value::literal;
+
+    #[test]
+    fn record() -> Result<()> {
+        let raw = br#"{"ingest_ns":42,"data":{"snot":"badger"},"meta":{"kafka":1}}"#.to_vec();
+        let (ingest_ns, data, meta) = parse_record(raw)?;
+        assert_eq!(ingest_ns, Some(42));
+        assert_eq!(data, literal!({"snot": "badger"}));
+        assert_eq!(meta, literal!({"kafka": 1}));
+
+        let (ingest_ns, _, meta) = parse_record(br#"{"data":1}"#.to_vec())?;
+        assert_eq!(ingest_ns, None);
+        assert_eq!(meta, Value::object());
+
+        assert!(parse_record(br#"{"snot":1}"#.to_vec()).is_err());
+        Ok(())
+    }
+
+    #[test]
+    fn magic() {
+        assert_eq!(
+            compression(&[0xfd, b'7', b'z', b'X', b'Z', 0]),
+            Compression::Xz
+        );
+        assert_eq!(compression(&[0x28, 0xb5, 0x2f, 0xfd, 0]), Compression::Zstd);
+        assert_eq!(compression(br#"{"data":1}"#), Compression::None);
+        assert_eq!(compression(b""), Compression::None);
+    }
+}
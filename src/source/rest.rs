@@ -18,15 +18,20 @@
 use crate::codec::Codec;
 use crate::postprocessor::{make_postprocessors, postprocess, Postprocessors};
 use crate::source::prelude::*;
+use crate::source::tcp;
 use async_channel::{unbounded, Sender, TryRecvError};
 use halfbrown::HashMap;
-use http_types::Mime;
+use http_types::{Method, Mime, StatusCode};
+use std::convert::TryFrom;
 use std::str::FromStr;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::Arc;
+use std::time::Duration;
 use tide::http::headers::HeaderValue;
 use tide::{Body, Request, Response};
 use tremor_script::Value;
 
-#[derive(Debug, Clone, Deserialize, Default)]
+#[derive(Debug, Clone, Deserialize)]
 pub struct Config {
     /// host to listen to, defaults to "0.0.0.0"
     #[serde(default = "dflt_host")]
@@ -34,6 +39,103 @@ pub struct Config {
     /// port to listen to, defaults to 8000
     #[serde(default = "dflt_port")]
     pub port: u16,
+    /// milliseconds to wait for the response of a linked pipeline, defaults to 10000
+    #[serde(default = "dflt_timeout")]
+    pub timeout: u64,
+    /// response if a linked pipeline doesn't respond in time, defaults to status 504
+    #[serde(default = "dflt_timeout_response")]
+    pub timeout_response: StaticResponse,
+    /// response if a linked pipeline drops the request, defaults to status 400
+    #[serde(default = "dflt_empty_response")]
+    pub empty_response: StaticResponse,
+    /// headers of responses of a linked pipeline, unless set in `$response.headers`
+    #[serde(default)]
+    pub response_headers: HashMap<String, String>,
+    /// routes to serve, all paths and methods are served if empty
+    #[serde(default)]
+    pub routes: Vec<Route>,
+}
+
+/// A route of the server
+#[derive(Debug, Clone, Deserialize)]
+pub struct Route {
+    /// path of the route, `:name` segments are put into `$request.params.name`
+    /// and a trailing `*` into `$request.params["*"]`
+    pub path: String,
+    /// methods of the route, all methods if empty
+    #[serde(default)]
+    pub methods: Vec<String>,
+    /// codec for request bodies, a codec name or a mime type of the codec map,
+    /// takes precedence over the `Content-Type` of requests
+    pub codec: Option<String>,
+    /// maximum size of request bodies in bytes, larger ones are rejected with 413
+    pub max_body_size: Option<usize>,
+    /// read bodies in chunks of this many bytes, sent as one stream per request
+    /// so preprocessors can split records across chunks, not for linked onramps
+    pub chunk_size: Option<usize>,
+    /// decodes `multipart/form-data` bodies
+    pub multipart: Option<Multipart>,
+}
+
+/// How parts of `multipart/form-data` bodies become events
+#[derive(Debug, Clone, Copy, Deserialize, PartialEq)]
+#[serde(rename_all = "lowercase")]
+pub enum MultipartMode {
+    /// an event per part with the part in `$request.part`, not for linked onramps
+    Parts,
+    /// one event with a record of the parts by name, their details are in
+    /// `$request.parts`
+    Record,
+}
+
+fn dflt_multipart_mode() -> MultipartMode {
+    MultipartMode::Parts
+}
+
+/// Decoding of `multipart/form-data` bodies
+#[derive(Debug, Clone, Copy, Deserialize)]
+pub struct Multipart {
+    #[serde(default = "dflt_multipart_mode")]
+    pub mode: MultipartMode,
+    /// maximum size of a part in bytes, larger ones are rejected with 413
+    pub max_part_size: Option<u64>,
+}
+
+impl Route {
+    /// names of the `:name` segments of the path
+    fn params(&self) -> impl Iterator<Item = &str> {
+        self.path
+            .split('/')
+            .filter_map(|segment| segment.strip_prefix(':'))
+    }
+}
+
+/// A response that isn't created from an event
+#[derive(Debug, Clone, Deserialize)]
+pub struct StaticResponse {
+    pub status: u16,
+    #[serde(default)]
+    pub headers: HashMap<String, String>,
+    #[serde(default)]
+    pub body: String,
+}
+
+impl StaticResponse {
+    fn with_status(status: u16) -> Self {
+        Self {
+            status,
+            headers: HashMap::new(),
+            body: String::new(),
+        }
+    }
+
+    fn build(&self) -> Response {
+        let mut builder = Response::builder(self.status).header("Server", "Tremor");
+        for (name, value) in &self.headers {
+            builder = builder.header(name.as_str(), value.as_str());
+        }
+        builder.body(Body::from_string(self.body.clone())).build()
+    }
 }
 
 // TODO possible to do this in source trait?
@@ -47,6 +149,18 @@ fn dflt_port() -> u16 {
     8000
 }
 
+fn dflt_timeout() -> u64 {
+    10_000
+}
+
+fn dflt_timeout_response() -> StaticResponse {
+    StaticResponse::with_status(504)
+}
+
+fn dflt_empty_response() -> StaticResponse {
+    StaticResponse::with_status(400)
+}
+
 pub struct Rest {
     pub config: Config,
     onramp_id: TremorUrl,
@@ -56,6 +170,13 @@ impl onramp::Impl for Rest {
     fn from_config(id: &TremorUrl, config: &Option<YamlValue>) -> Result<Box<dyn Onramp>> {
         if let Some(config) = config {
             let config: Config = Config::new(config)?;
+            StatusCode::try_from(config.timeout_response.status)?;
+            StatusCode::try_from(config.empty_response.status)?;
+            for route in &config.routes {
+                for method in &route.methods {
+                    Method::from_str(method)?;
+                }
+            }
             Ok(Box::new(Self {
                 config,
                 onramp_id: id.clone(),
@@ -82,6 +203,8 @@ pub struct Int {
     post_processors: Postprocessors,
     onramp_id: TremorUrl,
     is_linked: bool,
+    /// shares the port with the other instances of the onramp
+    reuse_port: bool,
     // TODO better way to manage this?
     response_txes: HashMap<u64, Sender<Response>>,
 }
@@ -99,6 +222,7 @@ impl Int {
         config: &Config,
         post_processors: &[String],
         is_linked: bool,
+        reuse_port: bool,
     ) -> Result<Self> {
         let config = config.clone();
         let post_processors = make_postprocessors(post_processors)?;
@@ -109,6 +233,7 @@ impl Int {
             post_processors,
             onramp_id,
             is_linked,
+            reuse_port,
             response_txes: HashMap::new(),
         })
     }
@@ -139,9 +264,29 @@ struct ServerState {
     tx: Sender<RestSourceReply>,
     uid: u64,
     link: bool,
+    timeout: Duration,
+    timeout_response: Arc<StaticResponse>,
+    /// the stream ids for chunked requests, 0 is used for all others
+    next_stream: Arc<AtomicUsize>,
 }
 
-async fn handle_request(mut req: Request<ServerState>) -> tide::Result<Response> {
+fn too_large() -> Response {
+    Response::builder(StatusCode::PayloadTooLarge)
+        .header("Server", "Tremor")
+        .build()
+}
+
+async fn handle_request(
+    mut req: Request<ServerState>,
+    route: Option<Arc<Route>>,
+) -> tide::Result<Response> {
+    let max_body_size = route.as_ref().and_then(|route| route.max_body_size);
+    if let (Some(max), Some(len)) = (max_body_size, req.len()) {
+        if len > max {
+            return Ok(too_large());
+        }
+    }
+
     // TODO cache parts of this and update host only on new request
     let origin_uri = EventOriginUri {
         uid: req.state().uid,
@@ -172,8 +317,10 @@ async fn handle_request(mut req: Request<ServerState>) -> tide::Result<Response>
         })
         .collect::<Value>();
 
-    let ct: Option<Mime> = req.content_type();
-    let codec_override = ct.map(|ct| ct.essence().to_string());
+    let codec_override = route
+        .as_ref()
+        .and_then(|route| route.codec.clone())
+        .or_else(|| req.content_type().map(|ct| ct.essence().to_string()));
 
     // request metadata
     let mut meta = Value::object_with_capacity(1);
@@ -198,53 +345,263 @@ async fn handle_request(mut req: Request<ServerState>) -> tide::Result<Response>
     request_meta.insert("method", req.method().to_string())?;
     request_meta.insert("headers", headers)?;
     request_meta.insert("url", url_meta)?;
+    if let Some(route) = &route {
+        let mut params = Value::object();
+        for name in route.params() {
+            params.insert(name.to_string(), req.param(name)?.to_string())?;
+        }
+        if let Some(wildcard) = req.wildcard() {
+            params.insert("*", wildcard.to_string())?;
+        }
+        request_meta.insert("route", route.path.clone())?;
+        request_meta.insert("params", params)?;
+    }
     meta.insert("request", request_meta)?;
 
-    let data = req.body_bytes().await?;
-    if req.state().link {
+    let multipart = route.as_ref().and_then(|route| route.multipart);
+    let boundary = req
+        .header("Content-Type")
+        .and_then(|ct| multer::parse_boundary(ct.as_str()).ok());
+    if let (Some(multipart), Some(boundary)) = (multipart, boundary) {
+        let body = req.take_body();
+        let parts = Parts {
+            origin_uri,
+            meta,
+            codec_override: route.as_ref().and_then(|route| route.codec.clone()),
+        };
+        return handle_multipart(req.state(), body, boundary, multipart, max_body_size, parts)
+            .await;
+    }
+    if let Some(chunk_size) = route.as_ref().and_then(|route| route.chunk_size) {
+        let body = req.take_body();
+        let chunks = Parts {
+            origin_uri,
+            meta,
+            codec_override,
+        };
+        return handle_chunked(req.state(), body, chunk_size, max_body_size, &chunks).await;
+    }
+
+    let data = if let Some(max) = max_body_size {
+        // read at most one byte more than allowed to detect too large bodies
+        // without buffering them
+        let limit = u64::try_from(max).unwrap_or(u64::MAX).saturating_add(1);
+        let mut data = Vec::new();
+        req.take_body().take(limit).read_to_end(&mut data).await?;
+        if data.len() > max {
+            return Ok(too_large());
+        }
+        data
+    } else {
+        req.body_bytes().await?
+    };
+    submit(
+        req.state(),
+        SourceReply::Data {
+            origin_uri,
+            data,
+            meta: Some(meta),
+            codec_override,
+            stream: 0,
+        },
+    )
+    .await
+}
+
+/// Sends the event of a request to the source, waiting for the response of
+/// the pipeline if linked
+async fn submit(state: &ServerState, reply: SourceReply) -> tide::Result<Response> {
+    if state.link {
         let (response_tx, response_rx) = unbounded();
 
-        // TODO check how tide handles request timeouts here
-        //
-        // also figure out best way to bubble up errors (to the end user)
+        // TODO figure out best way to bubble up errors (to the end user)
         // during processing of this data, that may occur before a valid
         // event is sent back from the pipeline.
         // eg: in case of invalid json input with json content-type/codec,
         // event processing fails at the codec decoding stage, even before
-        // this event reaches pipeline, but the rest source request just
-        // hangs (until it times out).
-        req.state()
+        // this event reaches pipeline, so the request is answered with the
+        // timeout response.
+        state
             .tx
-            .send(RestSourceReply(
-                Some(response_tx),
-                SourceReply::Data {
-                    origin_uri,
-                    data,
-                    meta: Some(meta),
-                    codec_override,
-                    stream: 0,
-                },
-            ))
+            .send(RestSourceReply(Some(response_tx), reply))
             .await?;
         // TODO honor accept header
-        Ok(response_rx.recv().await?)
+        match async_std::future::timeout(state.timeout, response_rx.recv()).await {
+            Ok(response) => Ok(response?),
+            Err(_) => Ok(state.timeout_response.build()),
+        }
     } else {
-        req.state()
-            .tx
-            .send(
-                SourceReply::Data {
-                    origin_uri,
+        state.tx.send(reply.into()).await?;
+
+        // TODO set proper content-type
+        Ok(accepted())
+    }
+}
+
+fn accepted() -> Response {
+    Response::builder(202).body(Body::empty()).build()
+}
+
+/// What the events of the chunks or parts of a request share
+struct Parts {
+    origin_uri: EventOriginUri,
+    meta: Value<'static>,
+    codec_override: Option<String>,
+}
+
+/// Sends the body in chunks as a stream, so preprocessors can split records
+/// across chunks
+async fn handle_chunked(
+    state: &ServerState,
+    mut body: Body,
+    chunk_size: usize,
+    max_body_size: Option<usize>,
+    chunks: &Parts,
+) -> tide::Result<Response> {
+    let stream = state.next_stream.fetch_add(1, Ordering::AcqRel);
+    state
+        .tx
+        .send(SourceReply::StartStream(stream).into())
+        .await?;
+    let mut total = 0;
+    let mut chunk = vec![0_u8; chunk_size];
+    let res = loop {
+        let n = match body.read(&mut chunk).await {
+            Ok(0) => break Ok(accepted()),
+            Ok(n) => n,
+            Err(e) => break Err(e.into()),
+        };
+        total += n;
+        // chunks that were already sent are processed
+        if max_body_size.map_or(false, |max| total > max) {
+            break Ok(too_large());
+        }
+        let reply = SourceReply::Data {
+            origin_uri: chunks.origin_uri.clone(),
+            data: chunk[..n].to_vec(),
+            meta: Some(chunks.meta.clone()),
+            codec_override: chunks.codec_override.clone(),
+            stream,
+        };
+        if let Err(e) = state.tx.send(reply.into()).await {
+            break Err(e.into());
+        }
+    };
+    state.tx.send(SourceReply::EndStream(stream).into()).await?;
+    res
+}
+
+/// The body as a stream of chunks for `multer`
+fn body_stream(body: Body) -> impl futures::Stream<Item = std::io::Result<Vec<u8>>> {
+    futures::stream::unfold(Some(body), |body| async move {
+        let mut body = body?;
+        let mut chunk = vec![0_u8; 8192];
+        match body.read(&mut chunk).await {
+            Ok(0) => None,
+            Ok(n) => {
+                chunk.truncate(n);
+                Some((Ok(chunk), Some(body)))
+            }
+            Err(e) => Some((Err(e), None)),
+        }
+    })
+}
+
+fn multipart_error(e: &multer::Error) -> Response {
+    let status = match e {
+        multer::Error::FieldSizeExceeded { .. } | multer::Error::StreamSizeExceeded { .. } => {
+            StatusCode::PayloadTooLarge
+        }
+        _ => StatusCode::BadRequest,
+    };
+    Response::builder(status)
+        .header("Server", "Tremor")
+        .body(Body::from_string(e.to_string()))
+        .build()
+}
+
+/// Decodes a `multipart/form-data` body, only one part is buffered at a time
+async fn handle_multipart(
+    state: &ServerState,
+    body: Body,
+    boundary: String,
+    multipart: Multipart,
+    max_body_size: Option<usize>,
+    parts: Parts,
+) -> tide::Result<Response> {
+    let mut limit = multer::SizeLimit::new();
+    if let Some(max) = max_body_size {
+        limit = limit.whole_stream(u64::try_from(max).unwrap_or(u64::MAX));
+    }
+    if let Some(max) = multipart.max_part_size {
+        limit = limit.per_field(max);
+    }
+    let mut decoder = multer::Multipart::with_constraints(
+        body_stream(body),
+        boundary,
+        multer::Constraints::new().size_limit(limit),
+    );
+    let mut record = Value::object();
+    let mut parts_meta = Value::object();
+    loop {
+        let mut field = match decoder.next_field().await {
+            Ok(Some(field)) => field,
+            Ok(None) => break,
+            Err(e) => return Ok(multipart_error(&e)),
+        };
+        let name = field.name().unwrap_or_default().to_string();
+        let content_type = field.content_type().map(|ct| ct.essence_str().to_string());
+        let mut part_meta = Value::object_with_capacity(3);
+        part_meta.insert("name", name.clone())?;
+        if let Some(file_name) = field.file_name() {
+            part_meta.insert("filename", file_name.to_string())?;
+        }
+        if let Some(content_type) = &content_type {
+            part_meta.insert("content_type", content_type.clone())?;
+        }
+        let mut data = Vec::new();
+        loop {
+            match field.chunk().await {
+                Ok(Some(chunk)) => data.extend_from_slice(&chunk),
+                Ok(None) => break,
+                Err(e) => return Ok(multipart_error(&e)),
+            }
+        }
+        match multipart.mode {
+            MultipartMode::Parts => {
+                let mut meta = parts.meta.clone();
+                meta.insert("part", part_meta)?;
+                let reply = SourceReply::Data {
+                    origin_uri: parts.origin_uri.clone(),
                     data,
                     meta: Some(meta),
-                    codec_override,
+                    codec_override: parts.codec_override.clone().or(content_type),
                     stream: 0,
-                }
-                .into(),
-            )
-            .await?;
-
-        // TODO set proper content-type
-        Ok(Response::builder(202).body(Body::empty()).build())
+                };
+                state.tx.send(reply.into()).await?;
+            }
+            MultipartMode::Record => {
+                let value = String::from_utf8(data)
+                    .map_or_else(|e| Value::Bytes(e.into_bytes().into()), Value::from);
+                record.insert(name.clone(), value)?;
+                parts_meta.insert(name, part_meta)?;
+            }
+        }
+    }
+    match multipart.mode {
+        MultipartMode::Parts => Ok(accepted()),
+        MultipartMode::Record => {
+            let Parts {
+                origin_uri,
+                mut meta,
+                ..
+            } = parts;
+            if let Some(request) = meta.get_mut("request") {
+                request.insert("parts", parts_meta)?;
+            }
+            let data = (record, meta).into();
+            submit(state, SourceReply::Structured { origin_uri, data }).await
+        }
     }
 }
 
@@ -252,88 +609,93 @@ fn make_response(
     default_codec: &dyn Codec,
     codec_map: &HashMap<String, Box<dyn Codec>>,
     post_processors: &mut Postprocessors,
+    response_headers: &HashMap<String, String>,
     event: &tremor_pipeline::Event,
 ) -> Result<Response> {
     let err: Error = "Empty event.".into();
     let ingest_ns = event.ingest_ns;
     let (response_data, meta) = event.value_meta_iter().next().ok_or(err)?;
+    let response_meta = meta.get("response");
 
-    if let Some(response_meta) = meta.get("response") {
-        let status = response_meta.get_u16("status").unwrap_or(200);
-
-        // hallo heinz! :)
-        let mut builder = Response::builder(status);
-        // extract headers
-        let mut header_content_type: Option<&str> = None;
-        if let Some(headers) = response_meta.get_object("headers") {
-            for (name, values) in headers {
-                if let Some(header_values) = values.as_array() {
-                    if name.eq_ignore_ascii_case("content-type") {
-                        // pick first value in case of multiple content-type headers
-                        header_content_type = header_values.first().and_then(Value::as_str);
-                    }
-                    let mut v = Vec::with_capacity(header_values.len());
-                    for value in header_values {
-                        if let Some(header_value) = value.as_str() {
-                            v.push(HeaderValue::from_str(header_value)?);
-                        }
-                    }
-                    builder = builder.header(name.as_ref(), v.as_slice());
-                } else if let Some(header_value) = values.as_str() {
-                    if name.eq_ignore_ascii_case("content-type") {
-                        header_content_type = Some(header_value);
+    let status = response_meta
+        .and_then(|m| m.get_u16("status"))
+        .unwrap_or(200);
+    // hallo heinz! :)
+    let mut builder = Response::builder(StatusCode::try_from(status)?);
+    // extract headers, the ones from `$response` take precedence
+    let mut header_content_type: Option<&str> = None;
+    for (name, value) in response_headers {
+        if name.eq_ignore_ascii_case("content-type") {
+            header_content_type = Some(value.as_str());
+        }
+        builder = builder.header(name.as_str(), value.as_str());
+    }
+    if let Some(headers) = response_meta.and_then(|m| m.get_object("headers")) {
+        for (name, values) in headers {
+            if let Some(header_values) = values.as_array() {
+                if name.eq_ignore_ascii_case("content-type") {
+                    // pick first value in case of multiple content-type headers
+                    header_content_type = header_values.first().and_then(Value::as_str);
+                }
+                let mut v = Vec::with_capacity(header_values.len());
+                for value in header_values {
+                    if let Some(header_value) = value.as_str() {
+                        v.push(HeaderValue::from_str(header_value)?);
                     }
-                    builder = builder.header(name.as_ref(), header_value);
                 }
+                builder = builder.header(name.as_ref(), v.as_slice());
+            } else if let Some(header_value) = values.as_str() {
+                if name.eq_ignore_ascii_case("content-type") {
+                    header_content_type = Some(header_value);
+                }
+                builder = builder.header(name.as_ref(), header_value);
             }
         }
+    }
 
-        let maybe_content_type = match header_content_type {
-            None => None,
-            Some(ct) => Some(Mime::from_str(ct)?),
-        };
+    let maybe_content_type = match header_content_type {
+        None => None,
+        Some(ct) => Some(Mime::from_str(ct)?),
+    };
 
-        if let Some((mime, dynamic_codec)) = maybe_content_type
-            .and_then(|mime| codec_map.get(mime.essence()).map(|c| (mime, c.as_ref())))
-        {
-            let processed = postprocess(
-                post_processors.as_mut_slice(),
-                ingest_ns,
-                dynamic_codec.encode(response_data)?,
-            )?;
+    if let Some((mime, dynamic_codec)) = maybe_content_type
+        .and_then(|mime| codec_map.get(mime.essence()).map(|c| (mime, c.as_ref())))
+    {
+        let processed = postprocess(
+            post_processors.as_mut_slice(),
+            ingest_ns,
+            dynamic_codec.encode(response_data)?,
+        )?;
 
-            // TODO: see if we can use a reader instead of creating a new vector
-            let v: Vec<u8> = processed.into_iter().flatten().collect();
-            let mut body = Body::from_bytes(v);
+        // TODO: see if we can use a reader instead of creating a new vector
+        let v: Vec<u8> = processed.into_iter().flatten().collect();
+        let mut body = Body::from_bytes(v);
 
+        body.set_mime(mime);
+        builder = builder.body(body);
+    } else {
+        // fallback to default codec
+        let processed = postprocess(
+            post_processors.as_mut_slice(),
+            ingest_ns,
+            default_codec.encode(response_data)?,
+        )?;
+        // TODO: see if we can use a reader instead of creating a new vector
+        let v: Vec<u8> = processed.into_iter().flatten().collect();
+        let mut body = Body::from_bytes(v);
+
+        // set mime type for default codec
+        // TODO: cache mime type for default codec
+        if let Some(mime) = default_codec
+            .mime_types()
+            .drain(..)
+            .find_map(|mstr| Mime::from_str(mstr).ok())
+        {
             body.set_mime(mime);
-            builder = builder.body(body);
-        } else {
-            // fallback to default codec
-            let processed = postprocess(
-                post_processors.as_mut_slice(),
-                ingest_ns,
-                default_codec.encode(response_data)?,
-            )?;
-            // TODO: see if we can use a reader instead of creating a new vector
-            let v: Vec<u8> = processed.into_iter().flatten().collect();
-            let mut body = Body::from_bytes(v);
-
-            // set mime type for default codec
-            // TODO: cache mime type for default codec
-            if let Some(mime) = default_codec
-                .mime_types()
-                .drain(..)
-                .find_map(|mstr| Mime::from_str(mstr).ok())
-            {
-                body.set_mime(mime);
-            }
-            builder = builder.body(body);
         }
-        Ok(builder.build())
-    } else {
-        Err("No response metadata available.".into())
+        builder = builder.body(body);
     }
+    Ok(builder.build())
 }
 
 #[async_trait::async_trait()]
@@ -348,6 +710,15 @@ impl Source for Int {
             |listener| {
                 match listener.try_recv() {
                     Ok(RestSourceReply(Some(response_tx), source_reply)) => {
+                        // forget requests that were answered with the timeout response
+                        let closed: Vec<u64> = response_txes
+                            .iter()
+                            .filter(|(_, tx)| tx.is_closed())
+                            .map(|(closed_id, _)| *closed_id)
+                            .collect();
+                        for closed_id in closed {
+                            response_txes.remove(&closed_id);
+                        }
                         // store a sender here to be able to send the response later
                         response_txes.insert(id, response_tx);
                         Ok(source_reply)
@@ -373,7 +744,13 @@ impl Source for Int {
                 if event.is_batch && self.is_linked {
                     return Err("Batched events not supported in linked REST source.".into());
                 }
-                let res = match make_response(codec, codec_map, &mut self.post_processors, &event) {
+                let res = match make_response(
+                    codec,
+                    codec_map,
+                    &mut self.post_processors,
+                    &self.config.response_headers,
+                    &event,
+                ) {
                     Ok(response) => response,
                     Err(e) => {
                         error!(
@@ -399,7 +776,9 @@ impl Source for Int {
                         builder.build()
                     }
                 };
-                response_tx.send(res).await?;
+                if response_tx.send(res).await.is_err() {
+                    debug!("HTTP session for event-id {} already timed out", event_id);
+                }
             } else {
                 debug!("No outstanding HTTP session for event-id {}", event_id);
             }
@@ -413,12 +792,13 @@ impl Source for Int {
             self.onramp_id, id, stream
         );
         if let Some(response_tx) = self.response_txes.remove(&id) {
-            // send no-content HTTP response
-            let res = Response::builder(400)
-                .header("Content-Length", "0")
-                .header("Server", "Tremor")
-                .build();
-            response_tx.send(res).await?;
+            if response_tx
+                .send(self.config.empty_response.build())
+                .await
+                .is_err()
+            {
+                debug!("HTTP session for event-id {} already timed out", id);
+            }
         }
         Ok(())
     }
@@ -431,21 +811,64 @@ impl Source for Int {
             tx: tx.clone(),
             uid: self.uid,
             link: self.is_linked,
+            timeout: Duration::from_millis(self.config.timeout),
+            timeout_response: Arc::new(self.config.timeout_response.clone()),
+            next_stream: Arc::new(AtomicUsize::new(1)),
         });
 
-        // TODO add override for path and method from config (defaulting to
-        // all paths and methods like below if not provided)
-        server.at("/").all(handle_request);
-        server.at("/*").all(handle_request);
-        // alt method without relying on server state
-        //server.at("/*").all(|r| handle_request(r, self.uid, self.is_linked));
+        for route in &self.config.routes {
+            let multipart_parts = route
+                .multipart
+                .map_or(false, |multipart| multipart.mode == MultipartMode::Parts);
+            if self.is_linked && (route.chunk_size.is_some() || multipart_parts) {
+                return Err(format!(
+                    "Route {}: `chunk_size` and multipart `parts` are not supported by linked rest onramps",
+                    route.path
+                )
+                .into());
+            }
+        }
+        if self.config.routes.is_empty() {
+            server
+                .at("/")
+                .all(|r: Request<ServerState>| handle_request(r, None));
+            server
+                .at("/*")
+                .all(|r: Request<ServerState>| handle_request(r, None));
+        }
+        for route in &self.config.routes {
+            let route = Arc::new(route.clone());
+            let mut methods = Vec::with_capacity(route.methods.len());
+            for method in &route.methods {
+                methods.push(Method::from_str(method)?);
+            }
+            let mut at = server.at(&route.path);
+            let endpoint = move |r: Request<ServerState>| handle_request(r, Some(route.clone()));
+            if methods.is_empty() {
+                at.all(endpoint);
+            } else {
+                for method in methods {
+                    at.method(method, endpoint.clone());
+                }
+            }
+        }
 
         let addr = format!("{}:{}", self.config.host, self.config.port);
         let source_id = self.onramp_id.to_string();
+        let shared = if self.reuse_port {
+            Some(tcp::bind_reuse_port(&self.config.host, self.config.port).await?)
+        } else {
+            None
+        };
 
         task::spawn::<_, Result<()>>(async move {
             info!("[Source::{}] Listening at {}", source_id, addr);
-            if let Err(e) = server.listen(addr).await {
+            let res = if let Some(listener) = shared {
+                server.listen(listener).await
+            } else {
+                server.listen(addr).await
+            };
+            if let Err(e) = res {
                 error!(
                     "[Source::{}] Error while listening from the rest server: {}",
                     e, source_id
@@ -479,6 +902,7 @@ impl Onramp for Rest {
             &self.config,
             config.processors.post,
             config.is_linked,
+            config.instance.is_some(),
         )?;
         SourceManager::start(source, config).await
     }
@@ -486,4 +910,161 @@ impl Onramp for Rest {
     fn default_codec(&self) -> &str {
         "json"
     }
+
+    fn parallel(&self) -> bool {
+        // the instances share the port
+        cfg!(unix)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::codec::json::{Json, Sorted};
+    use tremor_value::literal;
+
+    #[test]
+    fn route_params() -> Result<()> {
+        let route: Route = serde_yaml::from_str(
+            "path: /users/:id/posts/:post/*\nmethods: [GET]\nmax_body_size: 1024",
+        )?;
+        assert_eq!(route.params().collect::<Vec<_>>(), vec!["id", "post"]);
+        assert_eq!(route.max_body_size, Some(1024));
+        assert_eq!(route.codec, None);
+        Ok(())
+    }
+
+    #[async_std::test]
+    async fn multipart() -> Result<()> {
+        let (tx, rx) = bounded(8);
+        let state = ServerState {
+            tx,
+            uid: 0,
+            link: false,
+            timeout: Duration::from_secs(1),
+            timeout_response: Arc::new(StaticResponse::with_status(504)),
+            next_stream: Arc::new(AtomicUsize::new(1)),
+        };
+        let body = "--X\r\nContent-Disposition: form-data; name=\"snot\"\r\n\r\nbadger\r\n\
+                    --X\r\nContent-Disposition: form-data; name=\"file\"; filename=\"a.json\"\r\n\
+                    Content-Type: application/json\r\n\r\n{\"a\":1}\r\n--X--\r\n";
+        let parts = || Parts {
+            origin_uri: EventOriginUri::default(),
+            meta: literal!({"request": {}}),
+            codec_override: None,
+        };
+        let record = Multipart {
+            mode: MultipartMode::Record,
+            max_part_size: None,
+        };
+        let res = handle_multipart(
+            &state,
+            Body::from_string(body.to_string()),
+            "X".to_string(),
+            record,
+            None,
+            parts(),
+        )
+        .await?;
+        assert_eq!(res.status(), StatusCode::Accepted);
+        match rx.recv().await? {
+            RestSourceReply(None, SourceReply::Structured { data, .. }) => {
+                let (value, meta) = data.parts();
+                assert_eq!(value, &literal!({"snot": "badger", "file": "{\"a\":1}"}));
+                assert_eq!(
+                    meta.get("request").and_then(|r| r.get("parts")),
+                    Some(&literal!({
+                        "snot": {"name": "snot"},
+                        "file": {"name": "file", "filename": "a.json", "content_type": "application/json"}
+                    }))
+                );
+            }
+            _ => return Err("Expected a structured event".into()),
+        }
+
+        let parts_mode = Multipart {
+            mode: MultipartMode::Parts,
+            max_part_size: None,
+        };
+        handle_multipart(
+            &state,
+            Body::from_string(body.to_string()),
+            "X".to_string(),
+            parts_mode,
+            None,
+            parts(),
+        )
+        .await?;
+        for expected in &[None, Some("application/json".to_string())] {
+            match rx.recv().await? {
+                RestSourceReply(None, SourceReply::Data { codec_override, .. }) => {
+                    assert_eq!(&codec_override, expected);
+                }
+                _ => return Err("Expected a data event".into()),
+            }
+        }
+
+        let limited = Multipart {
+            mode: MultipartMode::Parts,
+            max_part_size: Some(4),
+        };
+        let res = handle_multipart(
+            &state,
+            Body::from_string(body.to_string()),
+            "X".to_string(),
+            limited,
+            None,
+            parts(),
+        )
+        .await?;
+        assert_eq!(res.status(), StatusCode::PayloadTooLarge);
+        Ok(())
+    }
+
+    #[async_std::test]
+    async fn responses() -> Result<()> {
+        let codec = Json::<Sorted>::default();
+        let mut headers = HashMap::new();
+        headers.insert("x-tremor".to_string(), "snot".to_string());
+        headers.insert("x-snot".to_string(), "badger".to_string());
+
+        // without `$response` the configured headers and status 200 are used
+        let event = Event {
+            data: (literal!({"snot": "badger"}), literal!({})).into(),
+            ..Event::default()
+        };
+        let mut res = make_response(&codec, &HashMap::new(), &mut vec![], &headers, &event)?;
+        assert_eq!(res.status(), StatusCode::Ok);
+        assert_eq!(
+            res.header("x-tremor").map(|h| h.as_str().to_string()),
+            Some("snot".to_string())
+        );
+        assert_eq!(res.body_string().await?, r#"{"snot":"badger"}"#);
+
+        let event = Event {
+            data: (
+                literal!("created"),
+                literal!({"response": {"status": 201, "headers": {"x-snot": "moo"}}}),
+            )
+                .into(),
+            ..Event::default()
+        };
+        let res = make_response(&codec, &HashMap::new(), &mut vec![], &headers, &event)?;
+        assert_eq!(res.status(), StatusCode::Created);
+        assert_eq!(
+            res.header("x-snot").map(|h| h.as_str().to_string()),
+            Some("moo".to_string())
+        );
+
+        let event = Event {
+            data: (literal!(null), literal!({"response": {"status": 1000}})).into(),
+            ..Event::default()
+        };
+        assert!(make_response(&codec, &HashMap::new(), &mut vec![], &headers, &event).is_err());
+
+        let mut res = StaticResponse::with_status(504).build();
+        assert_eq!(res.status(), StatusCode::GatewayTimeout);
+        assert_eq!(res.body_string().await?, "");
+        Ok(())
+    }
 }
@@ -13,17 +13,16 @@
 // limitations under the License.
 #![cfg(not(tarpaulin_include))]
 
-use crate::errors::{Error, ErrorKind, Result};
+use crate::connectors::tls::{self, TlsConfig};
+use crate::errors::Result;
 use crate::source::prelude::*;
 use async_channel::Sender;
 use async_channel::TryRecvError;
-use async_std::net::TcpListener;
+use async_std::net::{TcpListener, TcpStream, ToSocketAddrs};
 use async_tls::TlsAcceptor;
-use rustls::internal::pemfile::{certs, pkcs8_private_keys, rsa_private_keys};
-use rustls::{Certificate, NoClientAuth, PrivateKey, ServerConfig};
-use std::io::BufReader;
-use std::path::{Path, PathBuf};
-use std::sync::Arc;
+use socket2::{Domain, Protocol, Socket, Type};
+use std::net::SocketAddr;
+use tremor_value::literal;
 
 // TODO expose this as config (would have to change buffer to be vector?)
 const BUFFER_SIZE_BYTES: usize = 8192;
@@ -32,13 +31,8 @@ const BUFFER_SIZE_BYTES: usize = 8192;
 pub struct Config {
     pub port: u16,
     pub host: String,
-    pub tls: Option<TLSConfig>,
-}
-
-#[derive(Debug, Clone, Deserialize)]
-pub struct TLSConfig {
-    cert: PathBuf,
-    key: PathBuf,
+    /// TLS, requiring client certificates if `cafile` is set
+    pub tls: Option<TlsConfig>,
 }
 
 impl ConfigImpl for Config {}
@@ -53,6 +47,8 @@ pub struct Int {
     config: Config,
     listener: Option<Receiver<SourceReply>>,
     onramp_id: TremorUrl,
+    /// shares the port with the other instances of the onramp
+    reuse_port: bool,
 }
 impl std::fmt::Debug for Int {
     fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
@@ -60,7 +56,7 @@ impl std::fmt::Debug for Int {
     }
 }
 impl Int {
-    fn from_config(uid: u64, onramp_id: TremorUrl, config: &Config) -> Self {
+    fn from_config(uid: u64, onramp_id: TremorUrl, config: &Config, reuse_port: bool) -> Self {
         let config = config.clone();
 
         Self {
@@ -68,10 +64,27 @@ impl Int {
             config,
             listener: None,
             onramp_id,
+            reuse_port,
         }
     }
 }
 
+/// Binds a listener that shares its port with other listeners, the kernel
+/// distributes the connections between them
+pub(crate) async fn bind_reuse_port(host: &str, port: u16) -> Result<std::net::TcpListener> {
+    let addr = (host, port)
+        .to_socket_addrs()
+        .await?
+        .next()
+        .ok_or_else(|| Error::from(format!("Can't resolve {}", host)))?;
+    let socket = Socket::new(Domain::for_address(addr), Type::STREAM, Some(Protocol::TCP))?;
+    #[cfg(unix)]
+    socket.set_reuse_port(true)?;
+    socket.bind(&addr.into())?;
+    socket.listen(1024)?;
+    Ok(socket.into())
+}
+
 impl onramp::Impl for Tcp {
     fn from_config(id: &TremorUrl, config: &Option<YamlValue>) -> Result<Box<dyn Onramp>> {
         if let Some(config) = config {
@@ -106,17 +119,22 @@ impl Source for Int {
     }
 
     async fn init(&mut self) -> Result<SourceState> {
-        let listener = TcpListener::bind((self.config.host.as_str(), self.config.port)).await?;
+        let listener = if self.reuse_port {
+            TcpListener::from(bind_reuse_port(&self.config.host, self.config.port).await?)
+        } else {
+            TcpListener::bind((self.config.host.as_str(), self.config.port)).await?
+        };
         let (tx, rx) = bounded(crate::QSIZE);
         let uid = self.uid;
+        let local_port = listener.local_addr()?.port();
         let path = vec![self.config.port.to_string()];
 
-        let server_config: Option<ServerConfig> = if let Some(tls_config) = self.config.tls.as_ref()
-        {
-            Some(load_server_config(tls_config)?)
-        } else {
-            None
-        };
+        let tls_acceptor: Option<TlsAcceptor> = self
+            .config
+            .tls
+            .as_ref()
+            .map(TlsConfig::acceptor)
+            .transpose()?;
         task::spawn(async move {
             let mut stream_id = 0;
             while let Ok((stream, peer)) = listener.accept().await {
@@ -130,9 +148,7 @@ impl Source for Int {
                     // TODO also add token_num here?
                     path: path.clone(), // captures server port
                 };
-                let tls_acceptor: Option<TlsAcceptor> = server_config
-                    .clone()
-                    .map(|s| TlsAcceptor::from(Arc::new(s)));
+                let tls_acceptor = tls_acceptor.clone();
                 task::spawn(async move {
                     //let (reader, writer) = &mut (&stream, &stream);
                     if let Err(e) = tx.send(SourceReply::StartStream(stream_id)).await {
@@ -143,7 +159,9 @@ impl Source for Int {
                     if let Some(acceptor) = tls_acceptor {
                         match acceptor.accept(stream).await {
                             Ok(tls_stream) => {
-                                read_loop(tls_stream, tx, stream_id, origin_uri).await;
+                                let meta =
+                                    connection_meta(stream_id, peer, local_port, tls::peer_cn());
+                                read_loop(tls_stream, tx, stream_id, origin_uri, meta).await;
                             }
                             Err(_e) => {
                                 if let Err(e) = tx.send(SourceReply::EndStream(stream_id)).await {
@@ -152,7 +170,8 @@ impl Source for Int {
                             }
                         }
                     } else {
-                        read_loop(stream, tx, stream_id, origin_uri).await;
+                        let meta = connection_meta(stream_id, peer, local_port, None);
+                        plain_read_loop(stream, tx, stream_id, origin_uri, meta).await;
                     };
                 });
             }
@@ -166,13 +185,79 @@ impl Source for Int {
 #[async_trait::async_trait]
 impl Onramp for Tcp {
     async fn start(&mut self, config: OnrampConfig<'_>) -> Result<onramp::Addr> {
-        let source = Int::from_config(config.onramp_uid, self.onramp_id.clone(), &self.config);
+        let source = Int::from_config(
+            config.onramp_uid,
+            self.onramp_id.clone(),
+            &self.config,
+            config.instance.is_some(),
+        );
         SourceManager::start(source, config).await
     }
 
     fn default_codec(&self) -> &str {
         "json"
     }
+
+    fn parallel(&self) -> bool {
+        // the instances share the port
+        cfg!(unix)
+    }
+}
+
+/// Metadata of the events of a connection
+fn connection_meta(
+    stream_id: usize,
+    peer: SocketAddr,
+    local_port: u16,
+    tls_peer_cn: Option<String>,
+) -> Value<'static> {
+    let mut meta = literal!({
+        "connection": stream_id,
+        "peer": {
+            "host": peer.ip().to_string(),
+            "port": peer.port()
+        },
+        "local_port": local_port
+    });
+    if let Some(cn) = tls_peer_cn {
+        meta.try_insert("tls", literal!({ "peer_cn": cn }));
+    }
+    meta
+}
+
+/// Sends an `open` or `close` event for the connection to the `connections` port
+async fn send_connection_event(
+    tx: &Sender<SourceReply>,
+    origin_uri: &EventOriginUri,
+    meta: &Value<'static>,
+    event: &'static str,
+) {
+    let mut data = meta.clone();
+    data.try_insert("event", event);
+    let reply = SourceReply::Connection {
+        origin_uri: origin_uri.clone(),
+        data: (data, Value::object()).into(),
+    };
+    if let Err(e) = tx.send(reply).await {
+        error!("TCP Error: {}", e);
+    }
+}
+
+/// Reads a connection without TLS, through io_uring if it is available
+async fn plain_read_loop(
+    stream: TcpStream,
+    tx: Sender<SourceReply>,
+    stream_id: usize,
+    origin_uri: EventOriginUri,
+    meta: Value<'static>,
+) {
+    #[cfg(all(feature = "io-uring", target_os = "linux"))]
+    if let Some(ring) = crate::uring::ring() {
+        let stream = crate::uring::TcpStream::new(ring, stream);
+        read_loop(stream, tx, stream_id, origin_uri, meta).await;
+        return;
+    }
+    read_loop(stream, tx, stream_id, origin_uri, meta).await;
 }
 
 async fn read_loop(
@@ -180,90 +265,36 @@ async fn read_loop(
     tx: Sender<SourceReply>,
     stream_id: usize,
     origin_uri: EventOriginUri,
+    meta: Value<'static>,
 ) {
+    send_connection_event(&tx, &origin_uri, &meta, "open").await;
     let mut buffer = [0; BUFFER_SIZE_BYTES];
-    while let Ok(n) = stream.read(&mut buffer).await {
-        if n == 0 {
-            if let Err(e) = tx.send(SourceReply::EndStream(stream_id)).await {
-                error!("TCP Error: {}", e);
-            };
-            break;
+    loop {
+        let n = match stream.read(&mut buffer).await {
+            Ok(0) => break,
+            Ok(n) => n,
+            Err(e) => {
+                debug!("TCP connection {} failed: {}", stream_id, e);
+                break;
+            }
         };
         if let Err(e) = tx
             .send(SourceReply::Data {
                 origin_uri: origin_uri.clone(),
                 // ALLOW: we define n as part of the read
                 data: buffer[0..n].to_vec(),
-                meta: None, // TODO: add peer address etc. to meta
+                meta: Some(meta.clone()),
                 codec_override: None,
                 stream: stream_id,
             })
             .await
         {
             error!("TCP Error: {}", e);
-            break;
+            return;
         };
     }
-}
-
-// Load the passed certificates file
-fn load_certs(path: &Path) -> Result<Vec<Certificate>> {
-    let certfile = tremor_common::file::open(path)?;
-    let mut reader = BufReader::new(certfile);
-    certs(&mut reader).map_err(|_| {
-        Error::from(ErrorKind::TLSError(format!(
-            "Invalid certificate in {}",
-            path.display()
-        )))
-    })
-}
-
-// Load the passed keys file
-fn load_keys(path: &Path) -> Result<PrivateKey> {
-    // prefer to load pkcs8 keys
-    // this will only error if we have invalid pkcs8 key base64 or we couldnt read the file.
-    let mut keys: Vec<PrivateKey> = {
-        let keyfile = tremor_common::file::open(path)?;
-        let mut reader = BufReader::new(keyfile);
-        pkcs8_private_keys(&mut reader).map_err(|_e| {
-            Error::from(ErrorKind::TLSError(format!(
-                "Invalid PKCS8 Private key in {}",
-                path.display()
-            )))
-        })
-    }?;
-
-    // only attempt to load as RSA keys if file has no pkcs8 keys
-    if keys.is_empty() {
-        let keyfile = tremor_common::file::open(path)?;
-        let mut reader = BufReader::new(keyfile);
-        keys = rsa_private_keys(&mut reader).map_err(|_e| {
-            Error::from(ErrorKind::TLSError(format!(
-                "Invalid RSA Private key in {}",
-                path.display()
-            )))
-        })?;
-    }
-
-    if keys.is_empty() {
-        Err(Error::from(ErrorKind::TLSError(format!(
-            "No valid private keys (RSA or PKCS8) found in {}",
-            path.display()
-        ))))
-    } else {
-        // ALLOW: we know keys is not empty
-        Ok(keys.remove(0))
-    }
-}
-
-fn load_server_config(config: &TLSConfig) -> Result<ServerConfig> {
-    let certs = load_certs(&config.cert)?;
-    let keys = load_keys(&config.key)?;
-
-    let mut server_config = ServerConfig::new(NoClientAuth::new());
-    server_config
-        // set this server to use one cert together with the loaded private key
-        .set_single_cert(certs, keys)?;
-
-    Ok(server_config)
+    if let Err(e) = tx.send(SourceReply::EndStream(stream_id)).await {
+        error!("TCP Error: {}", e);
+    };
+    send_connection_event(&tx, &origin_uri, &meta, "close").await;
 }
@@ -0,0 +1,356 @@
+// Copyright 2020-2021, The Tremor Team
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+#![cfg(not(tarpaulin_include))]
+
+//! # Telegram Onramp
+//!
+//! Receives the updates of a telegram bot via long polling. Messages
+//! starting with a `/` get their parsed command in `command`, e.g.
+//! `{"name": "ack", "args": ["1234"]}` for `/ack 1234`.
+//!
+//! Linked responses are sent as messages, a string is sent as text to the
+//! chat in `$telegram.chat_id`, a record with a `send` field is sent as the
+//! parameters of `sendMessage` with the same `chat_id` as default.
+
+use crate::{codec::Codec, source::prelude::*, QSIZE};
+use async_channel::{Receiver, Sender, TryRecvError};
+use halfbrown::HashMap;
+use std::time::Duration;
+use surf::http::mime;
+use tremor_value::literal;
+
+fn dflt_api_url() -> String {
+    "https://api.telegram.org".to_string()
+}
+
+fn dflt_poll_timeout() -> u64 {
+    30
+}
+
+fn dflt_allowed_updates() -> Vec<String> {
+    vec!["message".to_string()]
+}
+
+#[derive(Deserialize, Clone)]
+pub struct Config {
+    /// Token of the bot
+    pub token: String,
+    /// Url of the bot API (default: `https://api.telegram.org`)
+    #[serde(default = "dflt_api_url")]
+    pub api_url: String,
+    /// Seconds a poll waits for updates (default: 30)
+    #[serde(default = "dflt_poll_timeout")]
+    pub poll_timeout: u64,
+    /// Update types to receive (default: `["message"]`)
+    #[serde(default = "dflt_allowed_updates")]
+    pub allowed_updates: Vec<String>,
+    /// Chats to accept updates from, all chats if empty
+    #[serde(default)]
+    pub allowed_chats: Vec<i64>,
+}
+
+impl ConfigImpl for Config {}
+
+impl Config {
+    fn method_url(&self, method: &str) -> String {
+        format!(
+            "{}/bot{}/{}",
+            self.api_url.trim_end_matches('/'),
+            self.token,
+            method
+        )
+    }
+}
+
+#[derive(Clone)]
+pub struct Telegram {
+    pub config: Config,
+    origin_uri: EventOriginUri,
+    onramp_id: TremorUrl,
+    client: Option<(
+        Sender<Value<'static>>,
+        Receiver<(Value<'static>, Value<'static>)>,
+    )>,
+}
+impl std::fmt::Debug for Telegram {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "Telegram")
+    }
+}
+
+impl onramp::Impl for Telegram {
+    fn from_config(id: &TremorUrl, config: &Option<YamlValue>) -> Result<Box<dyn Onramp>> {
+        if let Some(config) = config {
+            let config: Config = Config::new(config)?;
+            let origin_uri = EventOriginUri {
+                uid: 0,
+                scheme: "tremor-telegram".to_string(),
+                host: hostname(),
+                port: None,
+                path: vec![],
+            };
+
+            Ok(Box::new(Self {
+                origin_uri,
+                config,
+                onramp_id: id.clone(),
+                client: None,
+            }))
+        } else {
+            Err("Missing config for telegram onramp".into())
+        }
+    }
+}
+
+/// Calls a method of the bot API, returning its `result`
+async fn call(config: &Config, method: &str, params: &Value<'_>) -> Result<Value<'static>> {
+    let mut response = surf::post(config.method_url(method))
+        .body(params.encode())
+        .content_type(mime::JSON)
+        .await?;
+    let mut body = response.body_bytes().await?;
+    let body = tremor_value::parse_to_value(&mut body)?;
+    if body.get_bool("ok") == Some(true) {
+        Ok(body
+            .get("result")
+            .map_or_else(Value::null, Value::clone_static))
+    } else {
+        Err(format!(
+            "Telegram `{}` failed: {}",
+            method,
+            body.get_str("description").unwrap_or("unknown error")
+        )
+        .into())
+    }
+}
+
+/// Parses a bot command like `/ack@tremor_bot 1234`
+fn parse_command(text: &str) -> Option<Value<'static>> {
+    let mut words = text.strip_prefix('/')?.split_whitespace();
+    let name = words.next()?;
+    // commands in groups are addressed to a bot
+    let name = name.split('@').next().unwrap_or(name);
+    let args: Vec<Value<'static>> = words.map(|w| Value::from(w.to_string())).collect();
+    Some(literal!({ "name": name.to_string(), "args": args }))
+}
+
+/// The event and its metadata for an update, `None` if it comes from a chat
+/// that isn't allowed
+fn update_event(
+    update: &Value<'static>,
+    allowed_chats: &[i64],
+) -> Option<(Value<'static>, Value<'static>)> {
+    let message = update
+        .get("message")
+        .or_else(|| update.get("edited_message"))
+        .or_else(|| update.get("channel_post"))
+        .or_else(|| update.get("callback_query").get("message"));
+    let chat_id = message.get("chat").get_i64("id");
+    if !allowed_chats.is_empty() && !chat_id.map_or(false, |id| allowed_chats.contains(&id)) {
+        debug!("Ignoring a telegram update from chat {:?}", chat_id);
+        return None;
+    }
+    let mut event = update.clone();
+    if let Some(command) = message.get_str("text").and_then(parse_command) {
+        event.try_insert("command", command);
+    }
+    let mut meta = Value::object();
+    if let Some(chat_id) = chat_id {
+        let mut telegram = literal!({ "chat_id": chat_id });
+        if let Some(message_id) = message.get_i64("message_id") {
+            telegram.try_insert("message_id", message_id);
+        }
+        meta.try_insert("telegram", telegram);
+    }
+    Some((event, meta))
+}
+
+/// The `sendMessage` parameters of a linked response
+fn reply(value: &Value, meta: &Value) -> Option<Value<'static>> {
+    let chat_id = meta.get("telegram").get_i64("chat_id");
+    let mut params = if let Some(text) = value.as_str() {
+        literal!({ "text": text.to_string() })
+    } else {
+        value.get("send")?.clone_static()
+    };
+    if !params.contains_key("chat_id") {
+        params.try_insert("chat_id", chat_id?);
+    }
+    Some(params)
+}
+
+async fn poll_loop(config: Config, tx: Sender<(Value<'static>, Value<'static>)>) {
+    let mut offset: Option<u64> = None;
+    loop {
+        let mut params = literal!({
+            "timeout": config.poll_timeout,
+            "allowed_updates": config.allowed_updates.clone()
+        });
+        if let Some(offset) = offset {
+            params.try_insert("offset", offset);
+        }
+        let updates = match call(&config, "getUpdates", &params).await {
+            Ok(updates) => updates,
+            Err(e) => {
+                warn!("Failed to poll telegram updates: {}", e);
+                task::sleep(Duration::from_secs(5)).await;
+                continue;
+            }
+        };
+        for update in updates.as_array().into_iter().flatten() {
+            // confirms the update with the next poll
+            if let Some(id) = update.get_u64("update_id") {
+                offset = Some(id + 1);
+            }
+            if let Some(event) = update_event(update, &config.allowed_chats) {
+                if let Err(e) = tx.send(event).await {
+                    error!("Failed to forward event: {}", e);
+                    return;
+                }
+            }
+        }
+    }
+}
+
+async fn reply_loop(config: Config, rx: Receiver<Value<'static>>) {
+    while let Ok(params) = rx.recv().await {
+        if let Err(e) = call(&config, "sendMessage", &params).await {
+            error!("Telegram send error: {}", e);
+        }
+    }
+}
+
+#[async_trait::async_trait]
+impl Source for Telegram {
+    fn id(&self) -> &TremorUrl {
+        &self.onramp_id
+    }
+
+    async fn reply_event(
+        &mut self,
+        event: Event,
+        _codec: &dyn Codec,
+        _codec_map: &HashMap<String, Box<dyn Codec>>,
+    ) -> Result<()> {
+        if let Some((tx, _)) = self.client.as_mut() {
+            for (value, meta) in event.value_meta_iter() {
+                if let Some(params) = reply(value, meta) {
+                    if let Err(e) = tx.send(params).await {
+                        error!("Send error: {}", e);
+                    }
+                } else {
+                    warn!("Dropped a telegram response without a chat");
+                }
+            }
+        }
+        Ok(())
+    }
+
+    async fn pull_event(&mut self, _id: u64) -> Result<SourceReply> {
+        if let Some((_, rx)) = self.client.as_mut() {
+            match rx.try_recv() {
+                Ok(data) => Ok(SourceReply::Structured {
+                    origin_uri: self.origin_uri.clone(),
+                    data: data.into(),
+                }),
+                Err(TryRecvError::Empty) => Ok(SourceReply::Empty(100)),
+                Err(TryRecvError::Closed) => Err("telegram poll loop stopped".into()),
+            }
+        } else {
+            Err("telegram onramp isn't initialized".into())
+        }
+    }
+
+    async fn init(&mut self) -> Result<SourceState> {
+        let me = call(&self.config, "getMe", &Value::object()).await?;
+        info!(
+            "Telegram bot {} is connected",
+            me.get_str("username").unwrap_or_default()
+        );
+        let (tx, rx) = async_channel::bounded(QSIZE);
+        let (reply_tx, reply_rx) = async_channel::bounded(QSIZE);
+        self.client = Some((reply_tx, rx));
+        task::spawn(poll_loop(self.config.clone(), tx));
+        task::spawn(reply_loop(self.config.clone(), reply_rx));
+
+        Ok(SourceState::Connected)
+    }
+}
+
+#[async_trait::async_trait]
+impl Onramp for Telegram {
+    async fn start(&mut self, config: OnrampConfig<'_>) -> Result<onramp::Addr> {
+        SourceManager::start(self.clone(), config).await
+    }
+
+    fn default_codec(&self) -> &str {
+        "string"
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn commands() {
+        assert_eq!(parse_command("hello"), None);
+        assert_eq!(parse_command("/"), None);
+        assert_eq!(
+            parse_command("/ack@tremor_bot 1234  now"),
+            Some(literal!({"name": "ack", "args": ["1234", "now"]}))
+        );
+        assert_eq!(
+            parse_command("/status"),
+            Some(literal!({"name": "status", "args": []}))
+        );
+    }
+
+    #[test]
+    fn updates() -> Result<()> {
+        let update = literal!({
+            "update_id": 1,
+            "message": {"message_id": 7, "chat": {"id": -42}, "text": "/ack 1234"}
+        });
+        assert_eq!(update_event(&update, &[1]), None);
+        let (event, meta) = update_event(&update, &[-42]).ok_or_else(|| Error::from("no event"))?;
+        assert_eq!(
+            event.get("command"),
+            Some(&literal!({"name": "ack", "args": ["1234"]}))
+        );
+        assert_eq!(
+            meta,
+            literal!({"telegram": {"chat_id": -42, "message_id": 7}})
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn replies() {
+        let meta = literal!({"telegram": {"chat_id": -42}});
+        assert_eq!(
+            reply(&Value::from("acked"), &meta),
+            Some(literal!({"text": "acked", "chat_id": -42}))
+        );
+        assert_eq!(
+            reply(
+                &literal!({"send": {"chat_id": 1, "text": "*acked*", "parse_mode": "Markdown"}}),
+                &meta
+            ),
+            Some(literal!({"chat_id": 1, "text": "*acked*", "parse_mode": "Markdown"}))
+        );
+        assert_eq!(reply(&Value::from("acked"), &Value::object()), None);
+        assert_eq!(reply(&literal!({"other": 1}), &meta), None);
+    }
+}
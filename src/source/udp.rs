@@ -14,13 +14,40 @@
 #![cfg(not(tarpaulin_include))]
 
 use crate::source::prelude::*;
-use async_std::net::UdpSocket;
+use async_std::net::{ToSocketAddrs, UdpSocket};
+use socket2::{Domain, Protocol, Socket, Type};
+use std::net::{IpAddr, Ipv4Addr};
+use tremor_value::literal;
 
 #[derive(Deserialize, Debug, Clone)]
 pub struct Config {
     /// The port to listen on.
     pub port: u16,
     pub host: String,
+    /// Multicast groups to join
+    #[serde(default)]
+    pub multicast: Vec<Multicast>,
+    /// Size of the receive buffer of the socket in bytes (`SO_RCVBUF`)
+    pub recv_buffer_size: Option<usize>,
+    /// Maximum size of datagrams in bytes, larger ones are dropped with an
+    /// error instead of being truncated (default: 65535)
+    #[serde(default = "dflt_max_datagram_size")]
+    pub max_datagram_size: usize,
+}
+
+fn dflt_max_datagram_size() -> usize {
+    65535
+}
+
+#[derive(Deserialize, Debug, Clone)]
+pub struct Multicast {
+    /// Address of the multicast group
+    pub group: IpAddr,
+    /// Address of the interface to join IPv4 groups on, any interface if not set
+    pub interface: Option<Ipv4Addr>,
+    /// Index of the interface to join IPv6 groups on, 0 for any interface
+    #[serde(default)]
+    pub interface_index: u32,
 }
 
 impl ConfigImpl for Config {}
@@ -33,6 +60,8 @@ pub struct Udp {
 struct Int {
     config: Config,
     socket: Option<UdpSocket>,
+    /// one byte larger than `max_datagram_size` to detect larger datagrams
+    buf: Vec<u8>,
     onramp_id: TremorUrl,
     origin_uri: EventOriginUri,
 }
@@ -54,6 +83,7 @@ impl Int {
         Self {
             config: config.clone(),
             socket: None,
+            buf: vec![0; config.max_datagram_size + 1],
             onramp_id,
             origin_uri,
         }
@@ -63,6 +93,9 @@ impl onramp::Impl for Udp {
     fn from_config(onramp_id: &TremorUrl, config: &Option<YamlValue>) -> Result<Box<dyn Onramp>> {
         if let Some(config) = config {
             let config: Config = Config::new(config)?;
+            if let Some(m) = config.multicast.iter().find(|m| !m.group.is_multicast()) {
+                return Err(format!("{} is not a multicast group", m.group).into());
+            }
             Ok(Box::new(Self {
                 config,
                 onramp_id: onramp_id.clone(),
@@ -73,24 +106,69 @@ impl onramp::Impl for Udp {
     }
 }
 
+impl Int {
+    async fn bind(&mut self) -> Result<()> {
+        let config = &self.config;
+        let addr = (config.host.as_str(), config.port)
+            .to_socket_addrs()
+            .await?
+            .next()
+            .ok_or_else(|| Error::from(format!("Can't resolve {}", config.host)))?;
+        let socket = Socket::new(Domain::for_address(addr), Type::DGRAM, Some(Protocol::UDP))?;
+        if !config.multicast.is_empty() {
+            // allow other receivers of the groups on this host
+            socket.set_reuse_address(true)?;
+        }
+        if let Some(size) = config.recv_buffer_size {
+            socket.set_recv_buffer_size(size)?;
+        }
+        socket.bind(&addr.into())?;
+        let socket = UdpSocket::from(std::net::UdpSocket::from(socket));
+        for m in &config.multicast {
+            match m.group {
+                IpAddr::V4(group) => {
+                    socket.join_multicast_v4(group, m.interface.unwrap_or(Ipv4Addr::UNSPECIFIED))?
+                }
+                IpAddr::V6(group) => socket.join_multicast_v6(&group, m.interface_index)?,
+            }
+            info!("[UDP Onramp] joined multicast group {}", m.group);
+        }
+        info!(
+            "[UDP Onramp] listening on {}:{}",
+            self.config.host, self.config.port
+        );
+        self.socket = Some(socket);
+        Ok(())
+    }
+}
+
 #[async_trait::async_trait]
 impl Source for Int {
     async fn pull_event(&mut self, _id: u64) -> Result<SourceReply> {
-        let mut buf = [0; 65535];
-
         if let Some(socket) = self.socket.as_mut() {
-            match socket.recv_from(&mut buf).await {
+            match socket.recv_from(&mut self.buf).await {
+                Ok((n, peer)) if n > self.config.max_datagram_size => Err(format!(
+                    "Dropped a datagram from {} larger than the max_datagram_size of {} bytes",
+                    peer, self.config.max_datagram_size
+                )
+                .into()),
                 Ok((n, peer)) => {
                     let mut origin_uri = self.origin_uri.clone();
 
                     // TODO add a method in origin_uri for changes like this?
                     origin_uri.host = peer.ip().to_string();
                     origin_uri.port = Some(peer.port());
+                    let meta = literal!({
+                        "peer": {
+                            "host": peer.ip().to_string(),
+                            "port": peer.port()
+                        }
+                    });
                     Ok(SourceReply::Data {
                         origin_uri,
                         // ALLOW: we get n from recv
-                        data: buf[0..n].to_vec(),
-                        meta: None,
+                        data: self.buf[0..n].to_vec(),
+                        meta: Some(meta),
                         codec_override: None,
                         stream: 0,
                     })
@@ -104,22 +182,12 @@ impl Source for Int {
                 }
             }
         } else {
-            let socket = UdpSocket::bind((self.config.host.as_str(), self.config.port)).await?;
-            info!(
-                "[UDP Onramp] listening on {}:{}",
-                self.config.host, self.config.port
-            );
-            self.socket = Some(socket);
+            self.bind().await?;
             Ok(SourceReply::StateChange(SourceState::Connected))
         }
     }
     async fn init(&mut self) -> Result<SourceState> {
-        let socket = UdpSocket::bind((self.config.host.as_str(), self.config.port)).await?;
-        info!(
-            "[UDP Onramp] listening on {}:{}",
-            self.config.host, self.config.port
-        );
-        self.socket = Some(socket);
+        self.bind().await?;
         Ok(SourceState::Connected)
     }
     fn id(&self) -> &TremorUrl {
@@ -137,3 +205,45 @@ impl Onramp for Udp {
         "string"
     }
 }
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[async_std::test]
+    async fn datagrams() -> Result<()> {
+        let config: Config =
+            serde_yaml::from_str("host: 127.0.0.1\nport: 0\nmax_datagram_size: 8")?;
+        let mut source = Int::from_config(0, TremorUrl::parse("/onramp/udp/test")?, &config);
+        source.init().await?;
+        let addr = source
+            .socket
+            .as_ref()
+            .ok_or_else(|| Error::from("not bound"))?
+            .local_addr()?;
+
+        let client = UdpSocket::bind("127.0.0.1:0").await?;
+        client.send_to(b"snot", addr).await?;
+        match source.pull_event(0).await? {
+            SourceReply::Data { data, meta, .. } => {
+                assert_eq!(data, b"snot");
+                let port = client.local_addr()?.port();
+                assert_eq!(
+                    meta,
+                    Some(literal!({"peer": {"host": "127.0.0.1", "port": port}}))
+                );
+            }
+            other => return Err(format!("unexpected reply {:?}", other).into()),
+        }
+
+        // larger datagrams are not truncated
+        client.send_to(b"snot badger", addr).await?;
+        assert!(source.pull_event(0).await.is_err());
+        client.send_to(b"badger", addr).await?;
+        assert!(matches!(
+            source.pull_event(0).await?,
+            SourceReply::Data { .. }
+        ));
+        Ok(())
+    }
+}
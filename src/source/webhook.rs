@@ -0,0 +1,678 @@
+// Copyright 2020-2021, The Tremor Team
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+#![cfg(not(tarpaulin_include))]
+
+//! # Webhook Onramp
+//!
+//! Receives GitHub and GitLab webhooks. Deliveries are verified against the
+//! configured secret, their provider, event type and delivery id are put into
+//! `$webhook` and GitHub `ping` events are answered without an event.
+//!
+//! With a `journal` deliveries are persisted before they are accepted and
+//! replayed after a restart until the offramps acknowledged them.
+
+use crate::source::prelude::*;
+use crate::utils::constant_time_eq;
+use async_channel::{Sender, TryRecvError};
+use hmac::{Hmac, Mac};
+use http_types::StatusCode;
+use indexmap::IndexMap;
+use sha2::Sha256;
+use std::collections::{BTreeMap, VecDeque};
+use std::fs::{File, OpenOptions};
+use std::io::{BufRead, BufReader, Write};
+use std::path::{Path, PathBuf};
+use std::sync::{Arc, Mutex};
+use tide::{Request, Response};
+use tremor_common::time::nanotime;
+use tremor_value::literal;
+
+#[derive(Debug, Clone, Deserialize)]
+pub struct Config {
+    /// host to listen to, defaults to "0.0.0.0"
+    #[serde(default = "dflt_host")]
+    pub host: String,
+    /// port to listen to, defaults to 8000
+    #[serde(default = "dflt_port")]
+    pub port: u16,
+    /// path to receive webhooks on, defaults to "/"
+    #[serde(default = "dflt_path")]
+    pub path: String,
+    /// only accept webhooks of this provider, both are accepted if not set
+    pub provider: Option<Provider>,
+    /// secret of the webhooks, deliveries aren't verified if not set
+    pub secret: Option<String>,
+    /// file to journal deliveries in until they are acknowledged
+    pub journal: Option<PathBuf>,
+    /// maximum size of deliveries in bytes, defaults to 25MB like GitHub
+    #[serde(default = "dflt_max_body_size")]
+    pub max_body_size: usize,
+}
+
+fn dflt_host() -> String {
+    String::from("0.0.0.0")
+}
+
+fn dflt_port() -> u16 {
+    8000
+}
+
+fn dflt_path() -> String {
+    String::from("/")
+}
+
+fn dflt_max_body_size() -> usize {
+    25 * 1024 * 1024
+}
+
+impl ConfigImpl for Config {}
+
+/// The source control system sending webhooks
+#[derive(Debug, Clone, Copy, Deserialize, PartialEq)]
+#[serde(rename_all = "lowercase")]
+pub enum Provider {
+    Github,
+    Gitlab,
+}
+
+impl Provider {
+    fn name(self) -> &'static str {
+        match self {
+            Self::Github => "github",
+            Self::Gitlab => "gitlab",
+        }
+    }
+}
+
+/// The webhook headers of a request
+#[derive(Debug, PartialEq)]
+struct Hook {
+    provider: Provider,
+    /// normalized event type, e.g. `push` or `merge_request`
+    event: String,
+    delivery: Option<String>,
+    /// the signature for GitHub, the token for GitLab
+    signature: Option<String>,
+}
+
+impl Hook {
+    fn from_headers(header: impl Fn(&str) -> Option<String>) -> Option<Self> {
+        if let Some(event) = header("X-GitHub-Event") {
+            Some(Self {
+                provider: Provider::Github,
+                event,
+                delivery: header("X-GitHub-Delivery"),
+                signature: header("X-Hub-Signature-256"),
+            })
+        } else {
+            header("X-Gitlab-Event").map(|event| Self {
+                provider: Provider::Gitlab,
+                // `Merge Request Hook` becomes `merge_request`
+                event: event
+                    .trim_end_matches(" Hook")
+                    .to_lowercase()
+                    .replace(' ', "_"),
+                delivery: header("X-Gitlab-Event-UUID"),
+                signature: header("X-Gitlab-Token"),
+            })
+        }
+    }
+
+    /// Verifies the HMAC signature of GitHub or the token of GitLab
+    fn verify(&self, secret: &str, body: &[u8]) -> bool {
+        let signature = match &self.signature {
+            Some(signature) => signature,
+            None => return false,
+        };
+        match self.provider {
+            Provider::Github => signature
+                .strip_prefix("sha256=")
+                .and_then(|signature| hex::decode(signature).ok())
+                .map_or(false, |signature| {
+                    Hmac::<Sha256>::new_from_slice(secret.as_bytes()).map_or(false, |mut mac| {
+                        mac.update(body);
+                        mac.verify_slice(&signature).is_ok()
+                    })
+                }),
+            Provider::Gitlab => constant_time_eq(signature.as_bytes(), secret.as_bytes()),
+        }
+    }
+
+    fn meta(&self, delivery: &str) -> Value<'static> {
+        literal!({
+            "webhook": {
+                "provider": self.provider.name(),
+                "event": self.event.clone(),
+                "delivery": delivery.to_string()
+            }
+        })
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+struct Delivery {
+    id: String,
+    meta: Value<'static>,
+    body: Vec<u8>,
+}
+
+/// The journal is compacted once it grew this much since the last compaction
+const JOURNAL_COMPACTION_BYTES: u64 = 64 * 1024 * 1024;
+
+/// Deliveries that weren't acknowledged yet, as json lines of deliveries and
+/// `{"done": id}` markers.
+///
+/// Writes block, so the journal is only used from blocking tasks.
+struct Journal {
+    path: PathBuf,
+    file: File,
+    /// deliveries recorded but not done yet
+    pending: usize,
+    /// size of the journal file in bytes
+    size: u64,
+    /// size of the journal file after the last compaction
+    compacted_size: u64,
+}
+
+impl Journal {
+    /// Opens the journal, returning the deliveries to replay
+    fn open(path: &Path) -> Result<(Self, Vec<Delivery>)> {
+        let pending = Self::load(path)?;
+        let mut journal = Self {
+            path: path.to_path_buf(),
+            file: Self::rewrite(path, &pending)?,
+            pending: pending.len(),
+            size: 0,
+            compacted_size: 0,
+        };
+        journal.size = journal.file.metadata()?.len();
+        journal.compacted_size = journal.size;
+        Ok((journal, pending.into_iter().map(|(_, d)| d).collect()))
+    }
+
+    /// Reads the deliveries of the journal that aren't done yet
+    fn load(path: &Path) -> Result<IndexMap<String, Delivery>> {
+        let mut pending: IndexMap<String, Delivery> = IndexMap::new();
+        if !path.exists() {
+            return Ok(pending);
+        }
+        for line in BufReader::new(File::open(path)?).lines() {
+            let mut line = line?.into_bytes();
+            if line.is_empty() {
+                continue;
+            }
+            let entry = match tremor_value::parse_to_value(&mut line) {
+                Ok(entry) => entry,
+                Err(e) => {
+                    // e.g. a partially written line when we crashed
+                    warn!("Skipping a corrupt webhook journal entry: {}", e);
+                    continue;
+                }
+            };
+            if let Some(id) = entry.get_str("done") {
+                pending.shift_remove(id);
+            } else if let Some(id) = entry.get_str("delivery") {
+                let delivery = Delivery {
+                    id: id.to_string(),
+                    meta: entry
+                        .get("meta")
+                        .map_or_else(Value::object, Value::clone_static),
+                    body: base64::decode(entry.get_str("body").unwrap_or_default())?,
+                };
+                pending.insert(delivery.id.clone(), delivery);
+            }
+        }
+        Ok(pending)
+    }
+
+    /// Replaces the journal with one of the pending deliveries, returning
+    /// the new journal file opened for appending
+    fn rewrite(path: &Path, pending: &IndexMap<String, Delivery>) -> Result<File> {
+        let tmp = path.with_extension("tmp");
+        let mut file = File::create(&tmp)?;
+        for delivery in pending.values() {
+            file.write_all(&Self::entry(delivery))?;
+        }
+        file.sync_data()?;
+        drop(file);
+        std::fs::rename(&tmp, path)?;
+        Ok(OpenOptions::new().append(true).open(path)?)
+    }
+
+    /// The journal line of a delivery
+    fn entry(delivery: &Delivery) -> Vec<u8> {
+        let mut line = literal!({
+            "delivery": delivery.id.clone(),
+            "meta": delivery.meta.clone(),
+            "body": base64::encode(&delivery.body)
+        })
+        .encode()
+        .into_bytes();
+        line.push(b'\n');
+        line
+    }
+
+    fn append(&mut self, lines: &[u8]) -> Result<()> {
+        self.file.write_all(lines)?;
+        self.file.sync_data()?;
+        self.size += lines.len() as u64;
+        Ok(())
+    }
+
+    /// Records a delivery from its journal line, see [`Journal::entry`]
+    fn record(&mut self, entry: &[u8]) -> Result<()> {
+        self.append(entry)?;
+        self.pending += 1;
+        Ok(())
+    }
+
+    /// Marks deliveries as done and compacts the journal once nothing is
+    /// pending or it grew too much
+    fn done(&mut self, ids: &[String]) -> Result<()> {
+        let mut lines = Vec::new();
+        for id in ids {
+            lines.extend(literal!({ "done": id.clone() }).encode().into_bytes());
+            lines.push(b'\n');
+        }
+        self.append(&lines)?;
+        self.pending = self.pending.saturating_sub(ids.len());
+        if self.pending == 0 {
+            self.file.set_len(0)?;
+            self.file.sync_data()?;
+            self.size = 0;
+            self.compacted_size = 0;
+        } else if self.size > self.compacted_size + JOURNAL_COMPACTION_BYTES {
+            let pending = Self::load(&self.path)?;
+            self.file = Self::rewrite(&self.path, &pending)?;
+            self.pending = pending.len();
+            self.size = self.file.metadata()?.len();
+            self.compacted_size = self.size;
+        }
+        Ok(())
+    }
+}
+
+pub struct Webhook {
+    pub config: Config,
+    onramp_id: TremorUrl,
+}
+
+impl onramp::Impl for Webhook {
+    fn from_config(id: &TremorUrl, config: &Option<YamlValue>) -> Result<Box<dyn Onramp>> {
+        if let Some(config) = config {
+            let config: Config = Config::new(config)?;
+            if config.secret.is_none() {
+                warn!("[Onramp::{}] Webhooks aren't verified without a secret", id);
+            }
+            Ok(Box::new(Self {
+                config,
+                onramp_id: id.clone(),
+            }))
+        } else {
+            Err("Missing config for webhook onramp".into())
+        }
+    }
+}
+
+#[derive(Clone)]
+struct ServerState {
+    tx: Sender<Delivery>,
+    config: Arc<Config>,
+    journal: Option<Arc<Mutex<Journal>>>,
+}
+
+fn response(status: StatusCode, body: &str) -> Response {
+    Response::builder(status)
+        .header("Server", "Tremor")
+        .body(body)
+        .build()
+}
+
+async fn handle_request(mut req: Request<ServerState>) -> tide::Result<Response> {
+    let state = req.state().clone();
+    let hook = Hook::from_headers(|name| req.header(name).map(|v| v.last().as_str().to_string()));
+    let hook = match hook {
+        Some(hook) if state.config.provider.map_or(true, |p| p == hook.provider) => hook,
+        _ => {
+            return Ok(response(
+                StatusCode::BadRequest,
+                "Not a webhook of an accepted provider",
+            ))
+        }
+    };
+    if req
+        .len()
+        .map_or(false, |len| len > state.config.max_body_size)
+    {
+        return Ok(response(StatusCode::PayloadTooLarge, ""));
+    }
+    // chunked bodies have no length, so never read more than the limit
+    let mut body = Vec::new();
+    req.take_body()
+        .take(state.config.max_body_size as u64 + 1)
+        .read_to_end(&mut body)
+        .await?;
+    if body.len() > state.config.max_body_size {
+        return Ok(response(StatusCode::PayloadTooLarge, ""));
+    }
+    if let Some(secret) = &state.config.secret {
+        if !hook.verify(secret, &body) {
+            warn!(
+                "Rejected a {} webhook with an invalid signature",
+                hook.event
+            );
+            return Ok(response(StatusCode::Unauthorized, "Invalid signature"));
+        }
+    }
+    if hook.provider == Provider::Github && hook.event == "ping" {
+        return Ok(response(StatusCode::Ok, "pong"));
+    }
+    let id = hook
+        .delivery
+        .clone()
+        .unwrap_or_else(|| nanotime().to_string());
+    let delivery = Delivery {
+        meta: hook.meta(&id),
+        id,
+        body,
+    };
+    if let Some(journal) = state.journal.clone() {
+        let entry = Journal::entry(&delivery);
+        let recorded = task::spawn_blocking(move || {
+            journal
+                .lock()
+                .map_err(Error::from)
+                .and_then(|mut journal| journal.record(&entry))
+        })
+        .await;
+        if let Err(e) = recorded {
+            error!("Failed to journal webhook delivery {}: {}", delivery.id, e);
+            return Ok(response(StatusCode::InternalServerError, ""));
+        }
+    }
+    if state.tx.send(delivery).await.is_err() {
+        return Ok(response(StatusCode::ServiceUnavailable, ""));
+    }
+    Ok(response(StatusCode::Accepted, ""))
+}
+
+pub struct Int {
+    config: Config,
+    onramp_id: TremorUrl,
+    origin_uri: EventOriginUri,
+    listener: Option<Receiver<Delivery>>,
+    journal: Option<Arc<Mutex<Journal>>>,
+    /// journaled or failed deliveries to send again
+    replay: VecDeque<Delivery>,
+    /// deliveries by event id until they are acknowledged
+    in_flight: BTreeMap<u64, Delivery>,
+    /// ids of deliveries that are done but not yet marked in the journal
+    done: Vec<String>,
+}
+
+impl std::fmt::Debug for Int {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "Webhook")
+    }
+}
+
+impl Int {
+    fn from_config(uid: u64, onramp_id: TremorUrl, config: &Config) -> Self {
+        let origin_uri = EventOriginUri {
+            uid,
+            scheme: "tremor-webhook".to_string(),
+            host: hostname(),
+            port: Some(config.port),
+            path: vec![config.path.clone()],
+        };
+        Self {
+            config: config.clone(),
+            onramp_id,
+            origin_uri,
+            listener: None,
+            journal: None,
+            replay: VecDeque::new(),
+            in_flight: BTreeMap::new(),
+            done: Vec::new(),
+        }
+    }
+
+    fn done(&mut self, delivery: &Delivery) {
+        if self.journal.is_some() {
+            self.done.push(delivery.id.clone());
+        }
+    }
+
+    /// Marks the done deliveries in the journal
+    async fn flush_done(&mut self) {
+        if let Some(journal) = self.journal.clone() {
+            if self.done.is_empty() {
+                return;
+            }
+            let ids = std::mem::take(&mut self.done);
+            let count = ids.len();
+            let done = task::spawn_blocking(move || {
+                journal
+                    .lock()
+                    .map_err(Error::from)
+                    .and_then(|mut journal| journal.done(&ids))
+            })
+            .await;
+            if let Err(e) = done {
+                error!(
+                    "[Source::{}] Failed to journal {} deliveries as done: {}",
+                    self.onramp_id, count, e
+                );
+            }
+        }
+    }
+}
+
+#[async_trait::async_trait()]
+impl Source for Int {
+    fn id(&self) -> &TremorUrl {
+        &self.onramp_id
+    }
+
+    async fn pull_event(&mut self, id: u64) -> Result<SourceReply> {
+        self.flush_done().await;
+        let delivery = if let Some(delivery) = self.replay.pop_front() {
+            delivery
+        } else if let Some(listener) = &self.listener {
+            match listener.try_recv() {
+                Ok(delivery) => delivery,
+                Err(TryRecvError::Empty) => return Ok(SourceReply::Empty(10)),
+                Err(TryRecvError::Closed) => {
+                    return Ok(SourceReply::StateChange(SourceState::Disconnected))
+                }
+            }
+        } else {
+            return Ok(SourceReply::StateChange(SourceState::Disconnected));
+        };
+        let reply = SourceReply::Data {
+            origin_uri: self.origin_uri.clone(),
+            data: delivery.body.clone(),
+            meta: Some(delivery.meta.clone()),
+            codec_override: None,
+            stream: 0,
+        };
+        if self.journal.is_some() {
+            self.in_flight.insert(id, delivery);
+        }
+        Ok(reply)
+    }
+
+    async fn on_empty_event(&mut self, id: u64, _stream: usize) -> Result<()> {
+        if let Some(delivery) = self.in_flight.remove(&id) {
+            self.done(&delivery);
+        }
+        Ok(())
+    }
+
+    fn ack(&mut self, id: u64) {
+        // acks are cumulative
+        let pending = self.in_flight.split_off(&(id + 1));
+        let acked = std::mem::replace(&mut self.in_flight, pending);
+        for delivery in acked.values() {
+            self.done(delivery);
+        }
+    }
+
+    fn fail(&mut self, id: u64) {
+        if let Some(delivery) = self.in_flight.remove(&id) {
+            self.replay.push_back(delivery);
+        }
+    }
+
+    fn is_transactional(&self) -> bool {
+        self.config.journal.is_some()
+    }
+
+    async fn init(&mut self) -> Result<SourceState> {
+        let (tx, rx) = bounded(crate::QSIZE);
+        if let Some(path) = &self.config.journal {
+            let path = path.clone();
+            let (journal, pending) = task::spawn_blocking(move || Journal::open(&path)).await?;
+            if !pending.is_empty() {
+                info!(
+                    "[Source::{}] Replaying {} journaled deliveries",
+                    self.onramp_id,
+                    pending.len()
+                );
+            }
+            self.replay.extend(pending);
+            self.journal = Some(Arc::new(Mutex::new(journal)));
+        }
+        let mut server = tide::Server::with_state(ServerState {
+            tx,
+            config: Arc::new(self.config.clone()),
+            journal: self.journal.clone(),
+        });
+        server.at(&self.config.path).post(handle_request);
+
+        let addr = format!("{}:{}", self.config.host, self.config.port);
+        let source_id = self.onramp_id.to_string();
+        task::spawn(async move {
+            info!("[Source::{}] Listening at {}", source_id, addr);
+            if let Err(e) = server.listen(addr).await {
+                error!(
+                    "[Source::{}] Error while listening for webhooks: {}",
+                    source_id, e
+                );
+            }
+            warn!("[Source::{}] Server stopped", source_id);
+        });
+        self.listener = Some(rx);
+
+        Ok(SourceState::Connected)
+    }
+}
+
+#[async_trait::async_trait]
+impl Onramp for Webhook {
+    async fn start(&mut self, config: OnrampConfig<'_>) -> Result<onramp::Addr> {
+        let source = Int::from_config(config.onramp_uid, self.onramp_id.clone(), &self.config);
+        SourceManager::start(source, config).await
+    }
+
+    fn default_codec(&self) -> &str {
+        "json"
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    fn headers<'h>(headers: &'h [(&'h str, &'h str)]) -> impl Fn(&str) -> Option<String> + 'h {
+        move |name| {
+            headers
+                .iter()
+                .find(|(n, _)| n.eq_ignore_ascii_case(name))
+                .map(|(_, v)| (*v).to_string())
+        }
+    }
+
+    #[test]
+    fn github() -> Result<()> {
+        let body = br#"{"zen": "Keep it logically awesome."}"#;
+        let mut mac =
+            Hmac::<Sha256>::new_from_slice(b"snot").map_err(|e| Error::from(format!("{}", e)))?;
+        mac.update(body);
+        let signature = format!("sha256={}", hex::encode(mac.finalize().into_bytes()));
+        let hook = Hook::from_headers(headers(&[
+            ("X-GitHub-Event", "push"),
+            ("X-GitHub-Delivery", "72d3162e"),
+            ("X-Hub-Signature-256", signature.as_str()),
+        ]))
+        .ok_or_else(|| Error::from("no hook"))?;
+        assert_eq!(hook.provider, Provider::Github);
+        assert!(hook.verify("snot", body));
+        assert!(!hook.verify("badger", body));
+        assert!(!hook.verify("snot", b"{}"));
+        assert_eq!(
+            hook.meta("72d3162e"),
+            literal!({"webhook": {"provider": "github", "event": "push", "delivery": "72d3162e"}})
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn gitlab() -> Result<()> {
+        let hook = Hook::from_headers(headers(&[
+            ("X-Gitlab-Event", "Merge Request Hook"),
+            ("X-Gitlab-Token", "snot"),
+        ]))
+        .ok_or_else(|| Error::from("no hook"))?;
+        assert_eq!(hook.provider, Provider::Gitlab);
+        assert_eq!(hook.event, "merge_request");
+        assert!(hook.verify("snot", b"{}"));
+        assert!(!hook.verify("snot!", b"{}"));
+        assert_eq!(Hook::from_headers(headers(&[])), None);
+        Ok(())
+    }
+
+    #[test]
+    fn journal() -> Result<()> {
+        let dir = tempfile::tempdir()?;
+        let path = dir.path().join("webhooks.journal");
+        let delivery = |id: &str| Delivery {
+            id: id.to_string(),
+            meta: literal!({"webhook": {"delivery": id.to_string()}}),
+            body: id.as_bytes().to_vec(),
+        };
+        let (mut journal, pending) = Journal::open(&path)?;
+        assert!(pending.is_empty());
+        journal.record(&Journal::entry(&delivery("1")))?;
+        journal.record(&Journal::entry(&delivery("2")))?;
+        journal.record(&Journal::entry(&delivery("3")))?;
+        journal.done(&["2".to_string()])?;
+        drop(journal);
+
+        let (mut journal, pending) = Journal::open(&path)?;
+        assert_eq!(pending, vec![delivery("1"), delivery("3")]);
+        journal.done(&["1".to_string()])?;
+        assert!(std::fs::metadata(&path)?.len() > 0);
+
+        // the journal is truncated once nothing is pending
+        journal.done(&["3".to_string()])?;
+        assert_eq!(std::fs::metadata(&path)?.len(), 0);
+        journal.record(&Journal::entry(&delivery("4")))?;
+        drop(journal);
+
+        let (_, pending) = Journal::open(&path)?;
+        assert_eq!(pending, vec![delivery("4")]);
+        Ok(())
+    }
+}
@@ -0,0 +1,503 @@
+// Copyright 2020-2021, The Tremor Team
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+#![cfg(not(tarpaulin_include))]
+
+//! # Windows Event Log Onramp
+//!
+//! Subscribes to a channel of the Windows Event Log and emits the XML of
+//! each event. The `System` properties used for routing are in
+//! `$wineventlog`: `channel`, `provider`, `event_id`, `level` and
+//! `record_id`.
+//!
+//! `query` is an XPath filter of the channel, or a structured `<QueryList>`
+//! to read several channels, in which case `channel` is omitted. With a
+//! `bookmark_file` the bookmark of the last acknowledged event is persisted
+//! and the subscription resumes after it.
+
+use crate::source::prelude::*;
+use async_channel::{Sender, TryRecvError};
+use std::collections::BTreeMap;
+use std::convert::TryFrom;
+use std::ffi::OsStr;
+use std::fs;
+use std::os::windows::ffi::OsStrExt;
+use std::path::Path;
+use std::ptr::{null, null_mut};
+use tremor_value::literal;
+use winapi::shared::minwindef::DWORD;
+use winapi::shared::winerror::{ERROR_INSUFFICIENT_BUFFER, ERROR_NO_MORE_ITEMS, WAIT_TIMEOUT};
+use winapi::um::errhandlingapi::GetLastError;
+use winapi::um::handleapi::CloseHandle;
+use winapi::um::synchapi::{CreateEventW, ResetEvent, WaitForSingleObject};
+use winapi::um::winbase::WAIT_OBJECT_0;
+use winapi::um::winevt::{
+    EvtClose, EvtCreateBookmark, EvtNext, EvtRender, EvtRenderBookmark, EvtRenderEventXml,
+    EvtSubscribe, EvtSubscribeStartAfterBookmark, EvtSubscribeStartAtOldestRecord,
+    EvtSubscribeToFutureEvents, EvtUpdateBookmark, EVT_HANDLE,
+};
+use winapi::um::winnt::HANDLE;
+
+/// Number of events fetched at once
+const BATCH_SIZE: usize = 16;
+/// Milliseconds to wait for events before checking if we should stop
+const WAIT_MS: DWORD = 1000;
+
+#[derive(Debug, Clone, Deserialize)]
+pub struct Config {
+    /// Channel to subscribe to, e.g. `Security`
+    pub channel: Option<String>,
+    /// XPath filter of the channel or a structured `<QueryList>`
+    /// (default: `*`)
+    pub query: Option<String>,
+    /// File to persist the bookmark of the last acknowledged event in
+    pub bookmark_file: Option<String>,
+    /// Reads the existing events of the channel when there is no bookmark,
+    /// otherwise only new events are read
+    #[serde(default)]
+    pub from_start: bool,
+}
+
+impl ConfigImpl for Config {}
+
+pub struct WinEventLog {
+    pub config: Config,
+    onramp_id: TremorUrl,
+}
+
+impl onramp::Impl for WinEventLog {
+    fn from_config(id: &TremorUrl, config: &Option<YamlValue>) -> Result<Box<dyn Onramp>> {
+        if let Some(config) = config {
+            let config: Config = Config::new(config)?;
+            if config.channel.is_none() && config.query.is_none() {
+                return Err("The wineventlog onramp requires a channel or a query".into());
+            }
+            Ok(Box::new(Self {
+                config,
+                onramp_id: id.clone(),
+            }))
+        } else {
+            Err("Missing config for wineventlog onramp".into())
+        }
+    }
+}
+
+/// A null terminated UTF-16 string
+fn wide(s: &str) -> Vec<u16> {
+    OsStr::new(s).encode_wide().chain(Some(0)).collect()
+}
+
+fn win_error(function: &str) -> Error {
+    // SAFETY: reads the error of the calling thread
+    let code = unsafe { GetLastError() };
+    format!("{} failed with error {}", function, code).into()
+}
+
+/// An event log handle that is closed when dropped
+struct EvtHandle(EVT_HANDLE);
+
+impl EvtHandle {
+    fn new(handle: EVT_HANDLE, function: &str) -> Result<Self> {
+        if handle.is_null() {
+            Err(win_error(function))
+        } else {
+            Ok(Self(handle))
+        }
+    }
+}
+
+impl Drop for EvtHandle {
+    fn drop(&mut self) {
+        // SAFETY: the handle is valid and owned by us
+        unsafe { EvtClose(self.0) };
+    }
+}
+
+/// Renders an event or a bookmark as XML
+fn render(handle: &EvtHandle, flags: DWORD) -> Result<String> {
+    let mut used: DWORD = 0;
+    let mut count: DWORD = 0;
+    // SAFETY: the first call only asks for the size of the buffer
+    let ok = unsafe {
+        EvtRender(
+            null_mut(),
+            handle.0,
+            flags,
+            0,
+            null_mut(),
+            &mut used,
+            &mut count,
+        )
+    };
+    // SAFETY: reads the error of the calling thread
+    if ok == 0 && unsafe { GetLastError() } != ERROR_INSUFFICIENT_BUFFER {
+        return Err(win_error("EvtRender"));
+    }
+    let mut buffer: Vec<u16> = vec![0; usize::try_from(used)? / 2 + 1];
+    let size = DWORD::try_from(buffer.len() * 2)?;
+    // SAFETY: the buffer has the size EvtRender asked for
+    let ok = unsafe {
+        EvtRender(
+            null_mut(),
+            handle.0,
+            flags,
+            size,
+            buffer.as_mut_ptr().cast(),
+            &mut used,
+            &mut count,
+        )
+    };
+    if ok == 0 {
+        return Err(win_error("EvtRender"));
+    }
+    Ok(String::from_utf16_lossy(&buffer)
+        .trim_end_matches('\0')
+        .to_string())
+}
+
+/// The text of an element like `<EventID>4624</EventID>`, elements with
+/// attributes are ignored
+fn element<'x>(xml: &'x str, name: &str) -> Option<&'x str> {
+    let start = xml.find(&format!("<{}>", name))? + name.len() + 2;
+    // ALLOW: find returned an index of xml
+    let rest = &xml[start..];
+    // ALLOW: find returned an index of rest
+    Some(&rest[..rest.find('<')?])
+}
+
+/// The value of an attribute like `<Provider Name='...'/>`
+fn attribute<'x>(xml: &'x str, name: &str, attribute: &str) -> Option<&'x str> {
+    let start = xml.find(&format!("<{} ", name))?;
+    // ALLOW: find returned an index of xml
+    let tag = &xml[start..];
+    // ALLOW: find returned an index of tag
+    let tag = &tag[..tag.find('>')?];
+    let value = tag.find(&format!("{}=", attribute))? + attribute.len() + 1;
+    // ALLOW: find returned an index of tag
+    let value = &tag[value..];
+    let quote = value.chars().next()?;
+    // ALLOW: the quote is a single byte
+    let value = &value[1..];
+    // ALLOW: find returned an index of value
+    Some(&value[..value.find(quote)?])
+}
+
+/// The metadata of an event from its `System` properties
+fn event_meta(xml: &str) -> Value<'static> {
+    let mut meta = Value::object();
+    if let Some(channel) = element(xml, "Channel") {
+        meta.try_insert("channel", channel.to_string());
+    }
+    if let Some(provider) = attribute(xml, "Provider", "Name") {
+        meta.try_insert("provider", provider.to_string());
+    }
+    // event ids may have a Qualifiers attribute
+    let event_id = element(xml, "EventID").or_else(|| {
+        let start = xml.find("<EventID ")?;
+        // ALLOW: find returned an index of xml
+        let tag = &xml[start..];
+        // ALLOW: find returned indices of tag
+        tag.get(tag.find('>')? + 1..tag.find("</EventID>")?)
+    });
+    for (name, value) in [
+        ("event_id", event_id),
+        ("level", element(xml, "Level")),
+        ("record_id", element(xml, "EventRecordID")),
+    ] {
+        if let Some(n) = value.and_then(|v| v.parse::<u64>().ok()) {
+            meta.try_insert(name, n);
+        }
+    }
+    literal!({ "wineventlog": meta })
+}
+
+fn read_bookmark(path: &str) -> Result<Option<String>> {
+    match fs::read_to_string(path) {
+        Ok(bookmark) if bookmark.trim().is_empty() => Ok(None),
+        Ok(bookmark) => Ok(Some(bookmark)),
+        Err(e) if e.kind() == std::io::ErrorKind::NotFound => Ok(None),
+        Err(e) => Err(e.into()),
+    }
+}
+
+fn write_bookmark(path: &str, bookmark: &str) -> Result<()> {
+    let tmp = Path::new(path).with_extension("tmp");
+    fs::write(&tmp, bookmark)?;
+    fs::rename(&tmp, path)?;
+    Ok(())
+}
+
+/// An event with the bookmark after it
+type Record = (String, Option<String>);
+
+/// Reads the events of the subscription until the onramp is stopped, this
+/// blocks the calling thread
+fn subscribe(config: &Config, bookmark: Option<&str>, tx: &Sender<Record>) -> Result<()> {
+    // SAFETY: creates a manual reset event that is closed below
+    let signal: HANDLE = unsafe { CreateEventW(null_mut(), 1, 1, null()) };
+    if signal.is_null() {
+        return Err(win_error("CreateEventW"));
+    }
+    let res = read_events(config, bookmark, signal, tx);
+    // SAFETY: the event was created above
+    unsafe { CloseHandle(signal) };
+    res
+}
+
+fn read_events(
+    config: &Config,
+    bookmark: Option<&str>,
+    signal: HANDLE,
+    tx: &Sender<Record>,
+) -> Result<()> {
+    let channel = config.channel.as_deref().map(wide);
+    let query = wide(config.query.as_deref().unwrap_or("*"));
+    let (bookmark, flags) = if let Some(xml) = bookmark {
+        // SAFETY: the xml is null terminated
+        let handle = unsafe { EvtCreateBookmark(wide(xml).as_ptr()) };
+        (
+            EvtHandle::new(handle, "EvtCreateBookmark")?,
+            EvtSubscribeStartAfterBookmark,
+        )
+    } else {
+        // SAFETY: creates an empty bookmark
+        let handle = unsafe { EvtCreateBookmark(null()) };
+        let flags = if config.from_start {
+            EvtSubscribeStartAtOldestRecord
+        } else {
+            EvtSubscribeToFutureEvents
+        };
+        (EvtHandle::new(handle, "EvtCreateBookmark")?, flags)
+    };
+    let subscribe_bookmark = if flags == EvtSubscribeStartAfterBookmark {
+        bookmark.0
+    } else {
+        null_mut()
+    };
+    // SAFETY: the strings are null terminated and outlive the call
+    let subscription = unsafe {
+        EvtSubscribe(
+            null_mut(),
+            signal,
+            channel.as_ref().map_or(null(), |c| c.as_ptr()),
+            query.as_ptr(),
+            subscribe_bookmark,
+            null_mut(),
+            None,
+            flags,
+        )
+    };
+    let subscription = EvtHandle::new(subscription, "EvtSubscribe")?;
+    let mut events: [EVT_HANDLE; BATCH_SIZE] = [null_mut(); BATCH_SIZE];
+    while !tx.is_closed() {
+        // SAFETY: the signal is a valid event
+        match unsafe { WaitForSingleObject(signal, WAIT_MS) } {
+            WAIT_OBJECT_0 => (),
+            WAIT_TIMEOUT => continue,
+            _ => return Err(win_error("WaitForSingleObject")),
+        }
+        loop {
+            let mut returned: DWORD = 0;
+            // SAFETY: the array has room for BATCH_SIZE handles
+            let ok = unsafe {
+                EvtNext(
+                    subscription.0,
+                    DWORD::try_from(BATCH_SIZE)?,
+                    events.as_mut_ptr(),
+                    0,
+                    0,
+                    &mut returned,
+                )
+            };
+            if ok == 0 {
+                // SAFETY: reads the error of the calling thread
+                if unsafe { GetLastError() } == ERROR_NO_MORE_ITEMS {
+                    // SAFETY: the signal is a valid event
+                    unsafe { ResetEvent(signal) };
+                    break;
+                }
+                return Err(win_error("EvtNext"));
+            }
+            // ALLOW: EvtNext returns at most BATCH_SIZE events
+            let batch: Vec<EvtHandle> = events[..usize::try_from(returned)?]
+                .iter()
+                .map(|event| EvtHandle(*event))
+                .collect();
+            for event in batch {
+                let xml = render(&event, EvtRenderEventXml)?;
+                // SAFETY: both handles are valid
+                let bookmark_xml = if unsafe { EvtUpdateBookmark(bookmark.0, event.0) } == 0 {
+                    warn!("Failed to update the event log bookmark");
+                    None
+                } else {
+                    Some(render(&bookmark, EvtRenderBookmark)?)
+                };
+                if task::block_on(tx.send((xml, bookmark_xml))).is_err() {
+                    return Ok(());
+                }
+            }
+        }
+    }
+    Ok(())
+}
+
+pub struct Int {
+    config: Config,
+    onramp_id: TremorUrl,
+    origin_uri: EventOriginUri,
+    listener: Option<Receiver<Record>>,
+    /// bookmarks by event id until they are acknowledged
+    in_flight: BTreeMap<u64, String>,
+}
+
+impl std::fmt::Debug for Int {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "WinEventLog")
+    }
+}
+
+impl Int {
+    fn from_config(uid: u64, onramp_id: TremorUrl, config: &Config) -> Self {
+        let origin_uri = EventOriginUri {
+            uid,
+            scheme: "tremor-wineventlog".to_string(),
+            host: hostname(),
+            port: None,
+            path: config.channel.iter().cloned().collect(),
+        };
+        Self {
+            config: config.clone(),
+            onramp_id,
+            origin_uri,
+            listener: None,
+            in_flight: BTreeMap::new(),
+        }
+    }
+}
+
+#[async_trait::async_trait()]
+impl Source for Int {
+    fn id(&self) -> &TremorUrl {
+        &self.onramp_id
+    }
+
+    async fn pull_event(&mut self, id: u64) -> Result<SourceReply> {
+        if let Some(listener) = &self.listener {
+            match listener.try_recv() {
+                Ok((xml, bookmark)) => {
+                    if let (Some(bookmark), true) = (bookmark, self.is_transactional()) {
+                        self.in_flight.insert(id, bookmark);
+                    }
+                    Ok(SourceReply::Data {
+                        origin_uri: self.origin_uri.clone(),
+                        meta: Some(event_meta(&xml)),
+                        data: xml.into_bytes(),
+                        codec_override: None,
+                        stream: 0,
+                    })
+                }
+                Err(TryRecvError::Empty) => Ok(SourceReply::Empty(10)),
+                Err(TryRecvError::Closed) => {
+                    Ok(SourceReply::StateChange(SourceState::Disconnected))
+                }
+            }
+        } else {
+            Ok(SourceReply::StateChange(SourceState::Disconnected))
+        }
+    }
+
+    fn ack(&mut self, id: u64) {
+        // acks are cumulative, the latest bookmark covers the others
+        let pending = self.in_flight.split_off(&(id + 1));
+        let acked = std::mem::replace(&mut self.in_flight, pending);
+        if let (Some(path), Some(bookmark)) = (&self.config.bookmark_file, acked.values().last()) {
+            if let Err(e) = write_bookmark(path, bookmark) {
+                error!(
+                    "[Source::{}] Failed to persist the event log bookmark: {}",
+                    self.onramp_id, e
+                );
+            }
+        }
+    }
+
+    fn is_transactional(&self) -> bool {
+        self.config.bookmark_file.is_some()
+    }
+
+    async fn init(&mut self) -> Result<SourceState> {
+        let bookmark = if let Some(path) = &self.config.bookmark_file {
+            read_bookmark(path)?
+        } else {
+            None
+        };
+        let (tx, rx) = bounded(crate::QSIZE);
+        let config = self.config.clone();
+        let onramp_id = self.onramp_id.clone();
+        // the event log API is blocking
+        std::thread::Builder::new()
+            .name(format!("wineventlog-{}", self.onramp_id))
+            .spawn(move || {
+                if let Err(e) = subscribe(&config, bookmark.as_deref(), &tx) {
+                    error!(
+                        "[Source::{}] Event log subscription failed: {}",
+                        onramp_id, e
+                    );
+                }
+            })?;
+        self.listener = Some(rx);
+        Ok(SourceState::Connected)
+    }
+
+    async fn terminate(&mut self) {
+        // stops the subscription thread
+        self.listener = None;
+    }
+}
+
+#[async_trait::async_trait]
+impl Onramp for WinEventLog {
+    async fn start(&mut self, config: OnrampConfig<'_>) -> Result<onramp::Addr> {
+        let source = Int::from_config(config.onramp_uid, self.onramp_id.clone(), &self.config);
+        SourceManager::start(source, config).await
+    }
+
+    fn default_codec(&self) -> &str {
+        "string"
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn meta() {
+        let xml = "<Event xmlns='http://schemas.microsoft.com/win/2004/08/events/event'><System>\
+            <Provider Name='Microsoft-Windows-Security-Auditing' Guid='{54849625}'/>\
+            <EventID Qualifiers='0'>4624</EventID><Version>2</Version><Level>0</Level>\
+            <EventRecordID>1234</EventRecordID><Channel>Security</Channel>\
+            <Computer>tremor</Computer></System></Event>";
+        assert_eq!(
+            event_meta(xml),
+            literal!({"wineventlog": {
+                "channel": "Security",
+                "provider": "Microsoft-Windows-Security-Auditing",
+                "event_id": 4624,
+                "level": 0,
+                "record_id": 1234
+            }})
+        );
+        assert_eq!(element(xml, "Computer"), Some("tremor"));
+        assert_eq!(element(xml, "Task"), None);
+    }
+}
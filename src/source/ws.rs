@@ -13,10 +13,11 @@
 // limitations under the License.
 #![cfg(not(tarpaulin_include))]
 
+use crate::connectors::tls::TlsConfig;
 use crate::postprocessor::{make_postprocessors, postprocess, Postprocessors};
 use crate::{codec::Codec, source::prelude::*};
 use async_channel::{Sender, TryRecvError};
-use async_std::net::{TcpListener, TcpStream};
+use async_std::net::TcpListener;
 use async_std::task;
 use async_tungstenite::tungstenite::Message;
 use futures::{SinkExt, StreamExt};
@@ -31,6 +32,9 @@ pub struct Config {
     pub port: u16,
     /// Host to listen on
     pub host: String,
+    /// TLS, requiring client certificates if `cafile` is set
+    #[serde(default)]
+    pub tls: Option<TlsConfig>,
 }
 
 impl ConfigImpl for Config {}
@@ -123,15 +127,18 @@ impl Int {
     }
 }
 
-async fn handle_connection(
+async fn handle_connection<S>(
     source_url: TremorUrl,
     tx: Sender<WsSourceReply>,
-    raw_stream: TcpStream,
+    raw_stream: S,
     origin_uri: EventOriginUri,
     processors: Vec<String>,
     stream: usize,
     link: bool,
-) -> Result<()> {
+) -> Result<()>
+where
+    S: futures::io::AsyncRead + futures::io::AsyncWrite + Unpin,
+{
     let ws_stream = async_tungstenite::accept_async(raw_stream).await?;
 
     let (mut ws_write, mut ws_read) = ws_stream.split();
@@ -350,6 +357,12 @@ impl Source for Int {
 
         make_postprocessors(self.post_processors.as_slice())?; // just for verification before starting the onramp
         let processors = self.post_processors.clone();
+        let tls_acceptor = self
+            .config
+            .tls
+            .as_ref()
+            .map(TlsConfig::acceptor)
+            .transpose()?;
         task::spawn(async move {
             let mut stream_id = 0;
             while let Ok((stream, socket)) = listener.accept().await {
@@ -362,15 +375,35 @@ impl Source for Int {
                 };
 
                 stream_id += 1;
-                task::spawn(handle_connection(
-                    source_url.clone(),
-                    tx.clone(),
-                    stream,
-                    uri,
-                    processors.clone(),
-                    stream_id,
-                    link,
-                ));
+                if let Some(acceptor) = tls_acceptor.clone() {
+                    let source_url = source_url.clone();
+                    let tx = tx.clone();
+                    let processors = processors.clone();
+                    task::spawn(async move {
+                        match acceptor.accept(stream).await {
+                            Ok(stream) => {
+                                handle_connection(
+                                    source_url, tx, stream, uri, processors, stream_id, link,
+                                )
+                                .await
+                            }
+                            Err(e) => {
+                                error!("[Source::{}] TLS handshake failed: {}", source_url, e);
+                                Ok(())
+                            }
+                        }
+                    });
+                } else {
+                    task::spawn(handle_connection(
+                        source_url.clone(),
+                        tx.clone(),
+                        stream,
+                        uri,
+                        processors.clone(),
+                        stream_id,
+                        link,
+                    ));
+                }
             }
         });
 
@@ -12,20 +12,28 @@
 // See the License for the specific language governing permissions and
 // limitations under the License.
 
-use crate::config::{BindingVec, Config, MappingMap, OffRampVec, OnRampVec};
+use crate::config::{BindingVec, Config, MappingMap, MetricsMap, OffRampVec, OnRampVec, Priority};
+use crate::embed::{self, Embedded, Injector};
 use crate::errors::{Error, Kind as ErrorKind, Result};
 use crate::lifecycle::{ActivationState, ActivatorLifecycleFsm};
+use crate::metrics;
+use crate::namespace::Namespaces;
+use crate::overflow::Offer;
 use crate::registry::{Registries, ServantId};
 use crate::repository::{
-    Artefact, BindingArtefact, OfframpArtefact, OnrampArtefact, PipelineArtefact, Repositories,
+    Artefact, BindingArtefact, OfframpArtefact, OnrampArtefact, PipelineArtefact, RepoWrapper,
+    Repositories,
 };
-use crate::url::ports::METRICS;
+use crate::url::ports::{IN, METRICS};
 use crate::url::TremorUrl;
-use async_channel::bounded;
+use async_channel::{bounded, Receiver};
 use async_std::task::{self, JoinHandle};
 use hashbrown::HashMap;
+use tremor_pipeline::Snapshot;
 
 pub(crate) use crate::offramp;
+pub use crate::offramp::journal::{Entry as JournalEntry, Replay, Replayed, Summary};
+pub use crate::offramp::{CbState, OfframpState};
 pub(crate) use crate::onramp;
 pub(crate) use crate::pipeline;
 
@@ -58,6 +66,18 @@ pub(crate) enum ManagerMsg {
         async_channel::Sender<Result<pipeline::Addr>>,
         pipeline::Create,
     ),
+    ReloadPipeline(
+        async_channel::Sender<Result<()>>,
+        pipeline::Addr,
+        pipeline::Create,
+    ),
+    CanaryPipeline(
+        async_channel::Sender<Result<()>>,
+        pipeline::Addr,
+        pipeline::Create,
+        crate::config::Canary,
+        async_channel::Sender<pipeline::CanaryOutcome>,
+    ),
     CreateOnramp(
         async_channel::Sender<Result<onramp::Addr>>,
         Box<onramp::Create>,
@@ -93,6 +113,22 @@ impl Manager {
                             .send(pipeline::ManagerMsg::Create(r, Box::new(c)))
                             .await?;
                     }
+                    ManagerMsg::ReloadPipeline(r, addr, c) => {
+                        self.pipeline
+                            .send(pipeline::ManagerMsg::Reload(r, addr, Box::new(c)))
+                            .await?;
+                    }
+                    ManagerMsg::CanaryPipeline(r, addr, c, config, outcome) => {
+                        self.pipeline
+                            .send(pipeline::ManagerMsg::Canary(
+                                r,
+                                addr,
+                                Box::new(c),
+                                config,
+                                outcome,
+                            ))
+                            .await?;
+                    }
                     ManagerMsg::CreateOnramp(r, c) => {
                         self.onramp.send(onramp::ManagerMsg::Create(r, c)).await?;
                     }
@@ -125,6 +161,8 @@ pub struct World {
     pub repo: Repositories,
     /// Registry
     pub reg: Registries,
+    /// Namespaces
+    pub ns: Namespaces,
 }
 
 impl World {
@@ -222,6 +260,128 @@ impl World {
         }
     }
 
+    /// Activates a version of a pipeline and reloads its running instances
+    /// with it
+    ///
+    /// # Errors
+    ///  * if the pipeline or the version doesn't exist or an instance can't be reloaded
+    pub async fn activate_pipeline(
+        &self,
+        id: &TremorUrl,
+        version: usize,
+    ) -> Result<RepoWrapper<PipelineArtefact>> {
+        info!("Activating version {} of pipeline {}", version, id);
+        let wrapper = self.repo.activate_pipeline(id, version).await?;
+        self.reload_pipeline_instances(&wrapper).await?;
+        Ok(wrapper)
+    }
+
+    /// Rolls a pipeline back to the version before the active one and reloads
+    /// its running instances with it
+    ///
+    /// # Errors
+    ///  * if the pipeline has no previous version or an instance can't be reloaded
+    pub async fn rollback_pipeline(&self, id: &TremorUrl) -> Result<RepoWrapper<PipelineArtefact>> {
+        info!("Rolling back pipeline {}", id);
+        let wrapper = self.repo.rollback_pipeline(id).await?;
+        self.reload_pipeline_instances(&wrapper).await?;
+        Ok(wrapper)
+    }
+
+    /// Deploys a version of a pipeline as canary to its running instances.
+    /// Once every instance promoted it the version is activated, if any
+    /// instance rolls it back all instances are reloaded with the active version.
+    ///
+    /// Returns the number of instances the canary was deployed to.
+    ///
+    /// # Errors
+    ///  * if the version doesn't exist, the pipeline has no running instances
+    ///    or the canary can't be deployed
+    pub async fn canary_pipeline(
+        &self,
+        id: &TremorUrl,
+        config: crate::config::Canary,
+    ) -> Result<usize> {
+        if config.percentage > 100 {
+            return Err(ErrorKind::InvalidCanaryPercentage(config.percentage).into());
+        }
+        let wrapper = self
+            .repo
+            .find_pipeline(id)
+            .await?
+            .ok_or_else(|| Error::from(ErrorKind::ArtefactNotFound(id.to_string())))?;
+        let version = config.version;
+        let artefact = version
+            .checked_sub(1)
+            .and_then(|i| wrapper.versions.get(i))
+            .ok_or_else(|| ErrorKind::ArtefactVersionNotFound(id.to_string(), version))?;
+        let mut addrs = Vec::with_capacity(wrapper.instances.len());
+        for instance in &wrapper.instances {
+            if let Some(addr) = self.reg.find_pipeline(instance).await? {
+                addrs.push((instance.clone(), addr));
+            }
+        }
+        if addrs.is_empty() {
+            return Err(ErrorKind::CanaryFailedNoInstances(id.to_string()).into());
+        }
+        info!(
+            "Deploying version {} of pipeline {} as canary to {} instances",
+            version,
+            id,
+            addrs.len()
+        );
+        let instances = addrs.len();
+        let (outcome_tx, outcome_rx) = bounded(instances);
+        for (instance, addr) in addrs {
+            let (tx, rx) = bounded(1);
+            self.system
+                .send(ManagerMsg::CanaryPipeline(
+                    tx,
+                    addr,
+                    pipeline::Create {
+                        config: artefact.clone(),
+                        id: instance,
+                        version,
+                    },
+                    config.clone(),
+                    outcome_tx.clone(),
+                ))
+                .await?;
+            rx.recv().await??;
+        }
+        drop(outcome_tx);
+
+        let world = self.clone();
+        let id = id.clone();
+        task::spawn(async move {
+            let mut promoted = 0;
+            while let Ok(outcome) = outcome_rx.recv().await {
+                if outcome == pipeline::CanaryOutcome::Promoted {
+                    promoted += 1;
+                    continue;
+                }
+                warn!("Version {} of pipeline {} was rolled back", version, id);
+                // instances that already promoted the canary go back to the active version
+                let res = match world.repo.find_pipeline(&id).await {
+                    Ok(Some(wrapper)) => world.reload_pipeline_instances(&wrapper).await,
+                    Ok(None) => Ok(()),
+                    Err(e) => Err(e),
+                };
+                if let Err(e) = res {
+                    error!("Failed to roll back pipeline {}: {}", id, e);
+                }
+                return;
+            }
+            if promoted == instances {
+                info!("Version {} of pipeline {} was promoted", version, id);
+                if let Err(e) = world.repo.activate_pipeline(&id, version).await {
+                    error!("Failed to activate version {} of {}: {}", version, id, e);
+                }
+            }
+        });
+        Ok(instances)
+    }
+
     /// Stop the runtime
     ///
     /// # Errors
@@ -448,6 +608,143 @@ impl World {
         }
     }
 
+    /// Snapshot of the state of a pipeline instance, `None` if it isn't running
+    ///
+    /// # Errors
+    ///  * if the pipeline can't be reached
+    pub async fn snapshot_pipeline(&self, id: &TremorUrl) -> Result<Option<Snapshot>> {
+        if let Some(pipeline) = self.reg.find_pipeline(id).await? {
+            let (tx, rx) = bounded(1);
+            pipeline.send_mgmt(pipeline::MgmtMsg::Snapshot(tx)).await?;
+            Ok(Some(rx.recv().await?))
+        } else {
+            Ok(None)
+        }
+    }
+
+    /// Restores the state of a pipeline instance from a snapshot of the same
+    /// pipeline, events held by the instance are dropped. `false` if it isn't
+    /// running.
+    ///
+    /// # Errors
+    ///  * if the pipeline can't be reached or the snapshot can't be restored
+    pub async fn restore_pipeline(&self, id: &TremorUrl, snapshot: Snapshot) -> Result<bool> {
+        if let Some(pipeline) = self.reg.find_pipeline(id).await? {
+            let (tx, rx) = bounded(1);
+            pipeline
+                .send_mgmt(pipeline::MgmtMsg::Restore(Box::new(snapshot), tx))
+                .await?;
+            rx.recv().await??;
+            Ok(true)
+        } else {
+            Ok(false)
+        }
+    }
+
+    /// Circuit breaker state of an offramp instance, `None` if it isn't running
+    ///
+    /// # Errors
+    ///  * if the offramp can't be reached
+    pub async fn offramp_state(&self, id: &TremorUrl) -> Result<Option<OfframpState>> {
+        if let Some(offramp) = self.reg.find_offramp(id).await? {
+            let (tx, rx) = bounded(1);
+            offramp.send(offramp::Msg::GetState(tx)).await?;
+            Ok(Some(rx.recv().await?))
+        } else {
+            Ok(None)
+        }
+    }
+
+    /// Opens or closes the circuit breaker of an offramp instance manually,
+    /// `None` if it isn't running
+    ///
+    /// # Errors
+    ///  * if the offramp can't be reached
+    pub async fn set_offramp_state(
+        &self,
+        id: &TremorUrl,
+        state: CbState,
+    ) -> Result<Option<OfframpState>> {
+        if let Some(offramp) = self.reg.find_offramp(id).await? {
+            let (tx, rx) = bounded(1);
+            offramp.send(offramp::Msg::SetState { state, tx }).await?;
+            Ok(Some(rx.recv().await?))
+        } else {
+            Ok(None)
+        }
+    }
+
+    /// The failed deliveries of an offramp instance, `None` if it has no
+    /// journal
+    ///
+    /// # Errors
+    ///  * if the journal can't be read
+    pub fn offramp_journal(&self, id: &TremorUrl) -> Result<Option<Vec<Summary>>> {
+        offramp::journal::get(id)?
+            .map(|journal| Ok(journal.entries()?.into_iter().map(Summary::from).collect()))
+            .transpose()
+    }
+
+    /// A failed delivery of an offramp instance, `None` if there is no such
+    /// entry
+    ///
+    /// # Errors
+    ///  * if the journal can't be read
+    pub fn offramp_journal_entry(
+        &self,
+        id: &TremorUrl,
+        entry: u64,
+    ) -> Result<Option<JournalEntry>> {
+        Ok(offramp::journal::get(id)?
+            .map(|journal| journal.get(entry))
+            .transpose()?
+            .flatten())
+    }
+
+    /// Replays the failed deliveries of an offramp instance selected by
+    /// `replay`, into a pipeline instance or directly to the sink. `None` if
+    /// the offramp has no journal.
+    ///
+    /// # Errors
+    ///  * if the journal can't be read or the pipeline or offramp isn't
+    ///    running
+    pub async fn replay_offramp_journal(
+        &self,
+        id: &TremorUrl,
+        replay: Replay,
+    ) -> Result<Option<Replayed>> {
+        let journal = if let Some(journal) = offramp::journal::get(id)? {
+            journal
+        } else {
+            return Ok(None);
+        };
+        let entries = journal.select(&replay)?;
+        if let Some(pipeline_id) = &replay.pipeline {
+            let pipeline =
+                self.reg.find_pipeline(pipeline_id).await?.ok_or_else(|| {
+                    Error::from(ErrorKind::InstanceNotFound(pipeline_id.to_string()))
+                })?;
+            let mut replayed = Replayed::default();
+            for entry in entries {
+                let event = entry.event();
+                let priority = Priority::of(&event);
+                if let Offer::Queued = pipeline.send_event(IN, event, priority).await? {
+                    journal.remove(entry.id)?;
+                    replayed.replayed += 1;
+                } else {
+                    replayed.failed += 1;
+                }
+            }
+            Ok(Some(replayed))
+        } else if let Some(offramp) = self.reg.find_offramp(id).await? {
+            let (tx, rx) = bounded(1);
+            offramp.send(offramp::Msg::Replay { entries, tx }).await?;
+            Ok(Some(rx.recv().await?))
+        } else {
+            Err(ErrorKind::InstanceNotFound(id.to_string()).into())
+        }
+    }
+
     /// Link an offramp
     ///
     /// # Errors
@@ -584,6 +881,10 @@ impl World {
             offramp,
             binding,
             mapping,
+            // overrides aren't tracked once they are applied
+            metrics: MetricsMap::default(),
+            flags: crate::flags::list()?.into_iter().collect(),
+            layout: crate::codec::layout::list()?.into_iter().collect(),
         };
         Ok(config)
     }
@@ -610,6 +911,58 @@ impl World {
         Err(ErrorKind::ArtefactNotFound(id.to_string()).into())
     }
 
+    /// Loads a pipeline from a tremor query, using its `#!config id` or
+    /// the given name as id
+    ///
+    /// # Errors
+    ///  * if the query is invalid or the pipeline can't be published
+    pub async fn load_query(&self, name: &str, query: &str) -> Result<TremorUrl> {
+        let (id, query) = crate::parse_query(query, name, name)?;
+        info!("Loading {}.", id);
+        self.repo.publish_pipeline(&id, false, query).await?;
+        Ok(id)
+    }
+
+    /// Loads a YAML configuration of onramps, offramps, bindings and
+    /// mappings, returning the number of artefacts published
+    ///
+    /// # Errors
+    ///  * if the configuration is invalid or can't be deployed
+    pub async fn load_config(&self, config: &str) -> Result<usize> {
+        let config: Config = crate::interpolate::from_yaml(config).await?;
+        crate::publish_config(self, crate::incarnate(config)?).await
+    }
+
+    /// Opens the channel of `embedded` onramps, to be done before they are
+    /// bound
+    ///
+    /// # Errors
+    ///  * if the channel can't be registered
+    pub fn embedded_onramp(&self, channel: &str) -> Result<Injector> {
+        embed::open_input(channel)
+    }
+
+    /// Opens the channel of `embedded` offramps, to be done before they are
+    /// bound
+    ///
+    /// # Errors
+    ///  * if the channel can't be registered
+    pub fn embedded_offramp(&self, channel: &str) -> Result<Receiver<Embedded>> {
+        embed::open_output(channel)
+    }
+
+    /// Opens the channel of `embedded` offramps, calling `callback` with each
+    /// of their events until the channel is closed
+    ///
+    /// # Errors
+    ///  * if the channel can't be registered
+    pub fn embedded_callback<F>(&self, channel: &str, callback: F) -> Result<JoinHandle<()>>
+    where
+        F: FnMut(Embedded) + Send + 'static,
+    {
+        embed::on_output(channel, callback)
+    }
+
     /// Starts the runtime system
     ///
     /// # Errors
@@ -632,7 +985,12 @@ impl World {
 
         let repo = Repositories::new();
         let reg = Registries::new();
-        let mut world = Self { system, repo, reg };
+        let mut world = Self {
+            system,
+            repo,
+            reg,
+            ns: Namespaces::default(),
+        };
 
         world.register_system().await?;
         Ok((world, system_h))
@@ -656,10 +1014,12 @@ impl World {
             .await?;
         self.bind_pipeline(&METRICS_PIPELINE).await?;
 
-        self.reg
+        let metrics = self
+            .reg
             .find_pipeline(&METRICS_PIPELINE)
             .await?
             .ok_or_else(|| Error::from("Failed to initialize metrics pipeline."))?;
+        task::spawn(metrics::report_drops(metrics));
 
         let artefact_passthrough = tremor_pipeline::query::Query::parse(
             module_path,
@@ -712,13 +1072,49 @@ type: stderr
         config: PipelineArtefact,
         id: ServantId,
     ) -> Result<pipeline::Addr> {
+        let version = self
+            .repo
+            .find_pipeline(&id)
+            .await?
+            .map_or(1, |wrapper| wrapper.version);
         let (tx, rx) = bounded(1);
         self.system
             .send(ManagerMsg::CreatePipeline(
                 tx,
-                pipeline::Create { config, id },
+                pipeline::Create {
+                    config,
+                    id,
+                    version,
+                },
             ))
             .await?;
         rx.recv().await?
     }
+
+    /// Replaces the graphs of the running instances of a pipeline with its
+    /// active version
+    async fn reload_pipeline_instances(
+        &self,
+        wrapper: &RepoWrapper<PipelineArtefact>,
+    ) -> Result<()> {
+        for id in &wrapper.instances {
+            if let Some(addr) = self.reg.find_pipeline(id).await? {
+                info!("Reloading pipeline {} with version {}", id, wrapper.version);
+                let (tx, rx) = bounded(1);
+                self.system
+                    .send(ManagerMsg::ReloadPipeline(
+                        tx,
+                        addr,
+                        pipeline::Create {
+                            config: wrapper.artefact.clone(),
+                            id: id.clone(),
+                            version: wrapper.version,
+                        },
+                    ))
+                    .await?;
+                rx.recv().await??;
+            }
+        }
+        Ok(())
+    }
 }
@@ -0,0 +1,649 @@
+// Copyright 2020-2021, The Tremor Team
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+//! io_uring backed I/O for the file and tcp onramps and offramps on linux,
+//! enabled with the `io-uring` feature.
+//!
+//! All of them share one [`Ring`], a thread reaps its completions and wakes
+//! the tasks waiting for them. [`File`] and [`TcpStream`] own a read and a
+//! write buffer that are handed to the ring for every operation, so the
+//! kernel never sees memory of the caller and the buffers are reused instead
+//! of allocated per operation. If a file or stream is dropped while an
+//! operation is in flight, the ring keeps its buffer and descriptor until
+//! the kernel is done with them.
+//!
+//! Reads fill the read buffer, `poll_read` hands out what the caller has
+//! room for and keeps the rest for the next call. Writes copy the data into
+//! the write buffer and are accepted once the previous write completed,
+//! `poll_flush` waits for the last one. Sockets stay non-blocking, every
+//! `recv` and `send` is linked to a poll for readiness.
+//!
+//! If the kernel doesn't support io_uring [`ring`] returns `None` and the
+//! ramps fall back to async-std.
+
+use futures::io::{AsyncBufRead, AsyncRead, AsyncWrite};
+use futures::ready;
+use iouring::{opcode, squeue, types, IoUring};
+use std::collections::HashMap;
+use std::io::{self, Read};
+use std::marker::PhantomData;
+use std::mem;
+use std::net::Shutdown;
+use std::os::unix::io::{AsRawFd, FromRawFd, IntoRawFd};
+use std::pin::Pin;
+use std::sync::{Arc, Mutex, MutexGuard, PoisonError};
+use std::task::{Context, Poll, Waker};
+
+/// Size of the read and the write buffer of a file or stream
+const BUFFER: usize = 64 * 1024;
+/// Entries of the submission queue
+const ENTRIES: u32 = 256;
+/// `user_data` of polls and cancellations, their completions are ignored
+const IGNORED: u64 = u64::MAX;
+
+lazy_static! {
+    static ref RING: Option<&'static Ring> = match Ring::start() {
+        Ok(ring) => {
+            info!("Using io_uring for file and tcp I/O");
+            Some(ring)
+        }
+        Err(e) => {
+            warn!(
+                "io_uring is not available, falling back to async-std: {}",
+                e
+            );
+            None
+        }
+    };
+}
+
+/// The shared ring, `None` if io_uring is not available
+#[must_use]
+pub fn ring() -> Option<&'static Ring> {
+    *RING
+}
+
+/// Descriptor an operation uses
+type Fd = Arc<dyn AsRawFd + Send + Sync>;
+
+/// An operation submitted to the ring
+struct Op {
+    /// the memory the kernel reads from or writes to
+    buf: Vec<u8>,
+    /// keeps the descriptor open until the kernel is done with it
+    _fd: Fd,
+    /// result of the completion, a negative errno on errors
+    result: Option<i32>,
+    waker: Option<Waker>,
+    /// the owner was dropped, the operation is removed once it completes
+    orphaned: bool,
+}
+
+struct Inner {
+    ring: IoUring,
+    ops: HashMap<u64, Op>,
+    next: u64,
+}
+
+/// An io_uring instance shared by all files and streams
+pub struct Ring {
+    inner: Mutex<Inner>,
+}
+
+fn queue_full() -> io::Error {
+    io::Error::new(io::ErrorKind::Other, "io_uring submission queue is full")
+}
+
+impl Ring {
+    /// Sets up the ring and the thread reaping its completions
+    fn start() -> io::Result<&'static Self> {
+        let ring = IoUring::new(ENTRIES)?;
+        // ALLOW: eventfd has no preconditions, its result is checked below
+        let efd = unsafe { libc::eventfd(0, libc::EFD_CLOEXEC) };
+        if efd < 0 {
+            return Err(io::Error::last_os_error());
+        }
+        // ALLOW: the eventfd was just created and is owned by the reaper
+        let events = unsafe { std::fs::File::from_raw_fd(efd) };
+        ring.submitter().register_eventfd(efd)?;
+        let ring: &'static Self = Box::leak(Box::new(Self {
+            inner: Mutex::new(Inner {
+                ring,
+                ops: HashMap::new(),
+                next: 0,
+            }),
+        }));
+        std::thread::Builder::new()
+            .name("io-uring".to_string())
+            .spawn(move || ring.reap(events))?;
+        Ok(ring)
+    }
+
+    fn lock(&self) -> MutexGuard<'_, Inner> {
+        // the state stays consistent if a holder panics
+        self.inner.lock().unwrap_or_else(PoisonError::into_inner)
+    }
+
+    /// Waits for completions, the kernel signals `events` for every one
+    fn reap(&self, mut events: std::fs::File) {
+        let mut count = [0; 8];
+        let mut wakers = Vec::new();
+        loop {
+            if let Err(e) = events.read_exact(&mut count) {
+                if e.kind() == io::ErrorKind::Interrupted {
+                    continue;
+                }
+                error!("io_uring completions can't be reaped: {}", e);
+                return;
+            }
+            {
+                let mut inner = self.lock();
+                let Inner { ring, ops, .. } = &mut *inner;
+                for cqe in ring.completion() {
+                    let id = cqe.user_data();
+                    if id == IGNORED {
+                        continue;
+                    }
+                    let orphaned = ops.get_mut(&id).map_or(false, |op| {
+                        op.result = Some(cqe.result());
+                        wakers.extend(op.waker.take());
+                        op.orphaned
+                    });
+                    if orphaned {
+                        ops.remove(&id);
+                    }
+                }
+            }
+            for waker in wakers.drain(..) {
+                waker.wake();
+            }
+        }
+    }
+
+    /// Submits `op`, after `poll` if given, and keeps `buf` and `fd` until
+    /// it completes
+    ///
+    /// # Safety
+    /// the entries may only use the memory of `buf` and the descriptor `fd`
+    unsafe fn submit(
+        &self,
+        fd: &Fd,
+        buf: Vec<u8>,
+        poll: Option<squeue::Entry>,
+        op: squeue::Entry,
+    ) -> io::Result<u64> {
+        let mut inner = self.lock();
+        let needed = if poll.is_some() { 2 } else { 1 };
+        let free = |ring: &mut IoUring| {
+            let sq = ring.submission();
+            sq.capacity() - sq.len()
+        };
+        if free(&mut inner.ring) < needed {
+            inner.ring.submit()?;
+            // the poll is linked to the next entry, so both go in or none
+            if free(&mut inner.ring) < needed {
+                return Err(queue_full());
+            }
+        }
+        let id = inner.next;
+        inner.next += 1;
+        {
+            let mut sq = inner.ring.submission();
+            if let Some(poll) = poll {
+                sq.push(&poll.flags(squeue::Flags::IO_LINK).user_data(IGNORED))
+                    .map_err(|_| queue_full())?;
+            }
+            sq.push(&op.user_data(id)).map_err(|_| queue_full())?;
+        }
+        let submitted = inner.ring.submit();
+        inner.ops.insert(
+            id,
+            Op {
+                buf,
+                _fd: fd.clone(),
+                result: None,
+                waker: None,
+                // the entries are submitted with the next operation
+                orphaned: submitted.is_err(),
+            },
+        );
+        submitted.map(|_| id)
+    }
+
+    /// Polls the operation `id`, returns its buffer once it completed
+    fn poll(&self, id: u64, cx: &mut Context<'_>) -> Poll<(Vec<u8>, io::Result<usize>)> {
+        let mut inner = self.lock();
+        if let Some(op) = inner.ops.get_mut(&id) {
+            if op.result.is_none() {
+                op.waker = Some(cx.waker().clone());
+                return Poll::Pending;
+            }
+        }
+        Poll::Ready(match inner.ops.remove(&id) {
+            Some(Op {
+                buf,
+                result: Some(res),
+                ..
+            }) => {
+                let res = usize::try_from(res).map_err(|_| io::Error::from_raw_os_error(-res));
+                (buf, res)
+            }
+            _ => (
+                Vec::new(),
+                Err(io::Error::new(
+                    io::ErrorKind::Other,
+                    "io_uring operation is gone",
+                )),
+            ),
+        })
+    }
+
+    /// Gives up on the operation `id`, the kernel is asked to cancel it
+    fn cancel(&self, id: u64) {
+        let mut inner = self.lock();
+        let in_flight = inner.ops.get_mut(&id).map_or(false, |op| {
+            op.orphaned = op.result.is_none();
+            op.orphaned
+        });
+        if !in_flight {
+            inner.ops.remove(&id);
+            return;
+        }
+        let cancel = opcode::AsyncCancel::new(id).build().user_data(IGNORED);
+        // ALLOW: a cancellation uses no memory, it only names the operation
+        let pushed = unsafe { inner.ring.submission().push(&cancel).is_ok() };
+        if pushed {
+            if let Err(e) = inner.ring.submit() {
+                debug!("io_uring cancellation was not submitted: {}", e);
+            }
+        }
+    }
+}
+
+/// What a [`File`] or [`TcpStream`] reads and writes
+pub trait Kind {
+    /// The owned descriptor
+    type Target: AsRawFd + Send + Sync + 'static;
+    /// Sockets are non-blocking, their operations wait for readiness first
+    const SOCKET: bool;
+    /// Closes the writing half
+    ///
+    /// # Errors
+    ///  * if the descriptor can't be shut down
+    fn close(target: &Self::Target) -> io::Result<()>;
+}
+
+/// A regular file, read and written at offsets
+#[derive(Debug)]
+pub enum Regular {}
+
+impl Kind for Regular {
+    type Target = std::fs::File;
+    const SOCKET: bool = false;
+    fn close(_target: &Self::Target) -> io::Result<()> {
+        Ok(())
+    }
+}
+
+/// A tcp socket
+#[derive(Debug)]
+pub enum Socket {}
+
+impl Kind for Socket {
+    type Target = std::net::TcpStream;
+    const SOCKET: bool = true;
+    fn close(target: &Self::Target) -> io::Result<()> {
+        target.shutdown(Shutdown::Write)
+    }
+}
+
+/// A file or socket read and written through the ring
+pub struct Io<K: Kind> {
+    ring: &'static Ring,
+    target: Arc<K::Target>,
+    fd: Fd,
+    read_offset: u64,
+    write_offset: u64,
+    /// empty while a read is in flight
+    read_buf: Vec<u8>,
+    read: Option<u64>,
+    /// the data read and not consumed yet is `read_buf[pos..filled]`
+    pos: usize,
+    filled: usize,
+    /// empty while a write is in flight
+    write_buf: Vec<u8>,
+    write: Option<u64>,
+    /// the data written so far is `write_buf[..written]`
+    written: usize,
+    kind: PhantomData<fn() -> K>,
+}
+
+/// A file read and written through the ring
+pub type File = Io<Regular>;
+/// A tcp stream read and written through the ring
+pub type TcpStream = Io<Socket>;
+
+impl<K: Kind> Io<K> {
+    fn with(ring: &'static Ring, target: K::Target, write_offset: u64) -> Self {
+        let target = Arc::new(target);
+        Self {
+            ring,
+            fd: target.clone(),
+            target,
+            read_offset: 0,
+            write_offset,
+            read_buf: Vec::new(),
+            read: None,
+            pos: 0,
+            filled: 0,
+            write_buf: Vec::with_capacity(BUFFER),
+            write: None,
+            written: 0,
+            kind: PhantomData,
+        }
+    }
+
+    fn raw(&self) -> types::Fd {
+        types::Fd(self.target.as_raw_fd())
+    }
+
+    /// A poll for `events` if this is a socket
+    fn readiness(&self, events: libc::c_short) -> Option<squeue::Entry> {
+        // ALLOW: poll events are positive flags
+        #[allow(clippy::cast_sign_loss)]
+        let events = events as u16;
+        K::SOCKET.then(|| opcode::PollAdd::new(self.raw(), events.into()).build())
+    }
+
+    /// Starts reading into the read buffer
+    fn start_read(&mut self) -> io::Result<()> {
+        let mut buf = mem::take(&mut self.read_buf);
+        // only allocates for the first read, the buffer comes back filled
+        buf.resize(BUFFER, 0);
+        let (ptr, len) = (buf.as_mut_ptr(), len(&buf));
+        let op = if K::SOCKET {
+            opcode::Recv::new(self.raw(), ptr, len).build()
+        } else {
+            opcode::Read::new(self.raw(), ptr, len)
+                .offset(offset(self.read_offset))
+                .build()
+        };
+        let poll = self.readiness(libc::POLLIN);
+        // ALLOW: the entries use the memory of `buf` and our descriptor
+        self.read = Some(unsafe { self.ring.submit(&self.fd, buf, poll, op)? });
+        Ok(())
+    }
+
+    /// Starts writing what is left in the write buffer
+    fn start_write(&mut self) -> io::Result<()> {
+        let buf = mem::take(&mut self.write_buf);
+        let rest = &buf[self.written..];
+        let (ptr, len) = (rest.as_ptr(), len(rest));
+        let op = if K::SOCKET {
+            opcode::Send::new(self.raw(), ptr, len).build()
+        } else {
+            opcode::Write::new(self.raw(), ptr, len)
+                .offset(offset(self.write_offset))
+                .build()
+        };
+        let poll = self.readiness(libc::POLLOUT);
+        // ALLOW: the entries use the memory of `buf` and our descriptor
+        match unsafe { self.ring.submit(&self.fd, buf, poll, op) } {
+            Ok(id) => {
+                self.write = Some(id);
+                Ok(())
+            }
+            Err(e) => {
+                self.written = 0;
+                Err(e)
+            }
+        }
+    }
+
+    /// Waits until the write buffer is written, resubmitting short writes
+    fn poll_written(&mut self, cx: &mut Context<'_>) -> Poll<io::Result<()>> {
+        while let Some(id) = self.write {
+            let (buf, res) = ready!(self.ring.poll(id, cx));
+            self.write = None;
+            self.write_buf = buf;
+            match res {
+                Ok(0) => {
+                    self.write_buf.clear();
+                    self.written = 0;
+                    return Poll::Ready(Err(io::ErrorKind::WriteZero.into()));
+                }
+                Ok(n) => {
+                    self.written += n;
+                    self.write_offset += n as u64;
+                }
+                Err(e) if e.kind() == io::ErrorKind::WouldBlock => {}
+                Err(e) => {
+                    self.write_buf.clear();
+                    self.written = 0;
+                    return Poll::Ready(Err(e));
+                }
+            }
+            if self.written < self.write_buf.len() {
+                self.start_write()?;
+            }
+        }
+        self.write_buf.clear();
+        self.written = 0;
+        Poll::Ready(Ok(()))
+    }
+}
+
+/// Length of a buffer for an entry, buffers are at most `BUFFER` long
+fn len(buf: &[u8]) -> u32 {
+    u32::try_from(buf.len()).unwrap_or(u32::MAX)
+}
+
+/// Offset of a file for an entry
+// ALLOW: files larger than `i64::MAX` bytes don't exist
+#[allow(clippy::cast_possible_wrap)]
+fn offset(at: u64) -> libc::off_t {
+    at as libc::off_t
+}
+
+impl File {
+    /// Takes over a file opened with async-std, reads start at the
+    /// beginning and writes at the current end of the file
+    ///
+    /// # Errors
+    ///  * if the metadata of the file can't be read
+    pub fn new(ring: &'static Ring, file: async_std::fs::File) -> io::Result<Self> {
+        // ALLOW: the descriptor was released by async-std and is owned by us from here on
+        let file = unsafe { std::fs::File::from_raw_fd(file.into_raw_fd()) };
+        let write_offset = file.metadata()?.len();
+        Ok(Self::with(ring, file, write_offset))
+    }
+}
+
+impl TcpStream {
+    /// Takes over a connected stream of async-std, it stays non-blocking
+    #[must_use]
+    pub fn new(ring: &'static Ring, stream: async_std::net::TcpStream) -> Self {
+        // ALLOW: the descriptor was released by async-std and is owned by us from here on
+        let stream = unsafe { std::net::TcpStream::from_raw_fd(stream.into_raw_fd()) };
+        Self::with(ring, stream, 0)
+    }
+}
+
+impl<K: Kind> Drop for Io<K> {
+    fn drop(&mut self) {
+        for id in self.read.take().into_iter().chain(self.write.take()) {
+            self.ring.cancel(id);
+        }
+    }
+}
+
+impl<K: Kind> AsyncBufRead for Io<K> {
+    fn poll_fill_buf(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<io::Result<&[u8]>> {
+        let this = self.get_mut();
+        loop {
+            if let Some(id) = this.read {
+                let (buf, res) = ready!(this.ring.poll(id, cx));
+                this.read = None;
+                this.read_buf = buf;
+                this.pos = 0;
+                this.filled = 0;
+                match res {
+                    Ok(n) => {
+                        this.filled = n;
+                        this.read_offset += n as u64;
+                        // an empty read is the end of the file or stream
+                        break;
+                    }
+                    Err(e) if e.kind() == io::ErrorKind::WouldBlock => {}
+                    Err(e) => return Poll::Ready(Err(e)),
+                }
+            } else if this.pos < this.filled {
+                break;
+            }
+            this.start_read()?;
+        }
+        Poll::Ready(Ok(&this.read_buf[this.pos..this.filled]))
+    }
+
+    fn consume(self: Pin<&mut Self>, amt: usize) {
+        let this = self.get_mut();
+        this.pos = (this.pos + amt).min(this.filled);
+    }
+}
+
+impl<K: Kind> AsyncRead for Io<K> {
+    fn poll_read(
+        mut self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+        buf: &mut [u8],
+    ) -> Poll<io::Result<usize>> {
+        let available = ready!(self.as_mut().poll_fill_buf(cx))?;
+        let n = available.len().min(buf.len());
+        buf[..n].copy_from_slice(&available[..n]);
+        self.consume(n);
+        Poll::Ready(Ok(n))
+    }
+}
+
+impl<K: Kind> AsyncWrite for Io<K> {
+    fn poll_write(
+        self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+        buf: &[u8],
+    ) -> Poll<io::Result<usize>> {
+        let this = self.get_mut();
+        ready!(this.poll_written(cx))?;
+        if buf.is_empty() {
+            return Poll::Ready(Ok(0));
+        }
+        let n = buf.len().min(BUFFER);
+        this.write_buf.extend_from_slice(&buf[..n]);
+        this.start_write()?;
+        Poll::Ready(Ok(n))
+    }
+
+    fn poll_flush(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<io::Result<()>> {
+        self.get_mut().poll_written(cx)
+    }
+
+    fn poll_close(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<io::Result<()>> {
+        let this = self.get_mut();
+        ready!(this.poll_written(cx))?;
+        Poll::Ready(K::close(&this.target))
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use async_std::io::prelude::*;
+    use async_std::prelude::*;
+
+    #[async_std::test]
+    async fn file_roundtrip() -> io::Result<()> {
+        let ring = if let Some(ring) = ring() {
+            ring
+        } else {
+            // the kernel running the tests doesn't support io_uring
+            return Ok(());
+        };
+        let dir = tempfile::tempdir()?;
+        let path = dir.path().join("uring.txt");
+
+        let mut file = File::new(ring, async_std::fs::File::create(&path).await?)?;
+        file.write_all(b"snot\n").await?;
+        file.write_all(b"badger\n").await?;
+        file.flush().await?;
+
+        let file = File::new(ring, async_std::fs::File::open(&path).await?)?;
+        let mut lines = file.lines();
+        assert_eq!("snot", lines.next().await.transpose()?.unwrap_or_default());
+        assert_eq!(
+            "badger",
+            lines.next().await.transpose()?.unwrap_or_default()
+        );
+        assert!(lines.next().await.is_none());
+
+        // reads into small buffers keep the rest of what the ring read
+        let mut file = File::new(ring, async_std::fs::File::open(&path).await?)?;
+        let mut data = Vec::new();
+        let mut buf = [0; 3];
+        loop {
+            let n = file.read(&mut buf).await?;
+            if n == 0 {
+                break;
+            }
+            data.extend_from_slice(&buf[..n]);
+        }
+        assert_eq!(b"snot\nbadger\n", data.as_slice());
+        Ok(())
+    }
+
+    #[async_std::test]
+    async fn tcp_roundtrip() -> io::Result<()> {
+        let ring = if let Some(ring) = ring() {
+            ring
+        } else {
+            return Ok(());
+        };
+        let listener = async_std::net::TcpListener::bind("127.0.0.1:0").await?;
+        let addr = listener.local_addr()?;
+        let client = async_std::task::spawn(async move {
+            let stream = async_std::net::TcpStream::connect(addr).await?;
+            let mut stream = TcpStream::new(ring, stream);
+            let data: Vec<u8> = b"snot badger "
+                .iter()
+                .copied()
+                .cycle()
+                .take(200_000)
+                .collect();
+            stream.write_all(&data).await?;
+            stream.close().await?;
+            io::Result::Ok(data)
+        });
+        let (stream, _) = listener.accept().await?;
+        let mut stream = TcpStream::new(ring, stream);
+        let mut received = Vec::new();
+        let mut buf = [0; 1000];
+        loop {
+            let n = stream.read(&mut buf).await?;
+            if n == 0 {
+                break;
+            }
+            received.extend_from_slice(&buf[..n]);
+        }
+        assert_eq!(client.await?, received);
+        Ok(())
+    }
+}
@@ -57,6 +57,12 @@ pub mod ports {
 
     /// standard metrics port
     pub const METRICS: Cow<'static, str> = Cow::const_str("metrics");
+
+    /// port for connection lifecycle events of onramps
+    pub const CONNECTIONS: Cow<'static, str> = Cow::const_str("connections");
+
+    /// port for events that didn't fit into the queue of a linked pipeline
+    pub const OVERFLOW: Cow<'static, str> = Cow::const_str("overflow");
 }
 
 /// A tremor URL identifying an entity in tremor
@@ -261,6 +267,19 @@ impl TremorUrl {
         }
     }
 
+    /// Sets the artefact of the URL, will extend
+    /// the scope to `Scope::Artefact` if it was
+    /// `Type` before.
+    pub fn set_artefact<S>(&mut self, a: &S)
+    where
+        S: ToString + ?Sized,
+    {
+        self.artefact = Some(a.to_string());
+        if self.scope == Scope::Type {
+            self.scope = Scope::Artefact;
+        }
+    }
+
     /// Sets the port of the URL, will extend
     /// the scope to `Scope::Port` if it was
     /// `Servant` before.
@@ -25,3 +25,10 @@ pub fn hostname() -> String {
         })
         .unwrap_or_else(|_| "tremor_host.local".to_string())
 }
+
+/// Compares secrets like tokens or signatures in constant time, so the time
+/// it takes doesn't reveal how much of a guess was right
+#[must_use]
+pub fn constant_time_eq(a: &[u8], b: &[u8]) -> bool {
+    a.len() == b.len() && a.iter().zip(b).fold(0, |acc, (x, y)| acc | (x ^ y)) == 0
+}
@@ -15,15 +15,26 @@
 use crate::errors::Error;
 use http_types::{headers, StatusCode};
 use serde::{Deserialize, Serialize};
+use std::sync::Arc;
 use tide::Response;
 use tremor_runtime::system::World;
 use tremor_runtime::url::TremorUrl;
 
+pub mod agent;
+pub mod audit;
+pub mod backfill;
 pub mod binding;
+pub mod cluster;
+pub mod debugger;
+pub mod drops;
+pub mod flag;
+pub mod log_level;
+pub mod namespace;
 pub mod offramp;
 pub mod onramp;
 pub mod pipeline;
 pub mod prelude;
+pub mod stats;
 pub mod version;
 
 pub type Request = tide::Request<State>;
@@ -32,6 +43,19 @@ pub type Result<T> = std::result::Result<T, Error>;
 #[derive(Clone)]
 pub struct State {
     pub world: World,
+    /// log of all modifying operations
+    pub audit: Arc<audit::AuditLog>,
+    /// runtime adjustable log levels, if supported by the logger in use
+    pub log_levels: Option<Arc<dyn log_level::LogLevels>>,
+    /// statistics of the allocator, if available
+    pub allocator: Option<Arc<dyn stats::Allocator>>,
+    /// API tokens with access to everything, without any the API is open
+    /// except for namespaces with tokens
+    pub tokens: Vec<String>,
+    /// the cluster the node is part of, changes are replicated through it
+    pub cluster: Option<tremor_runtime::cluster::Cluster>,
+    /// the agent pulling the artefacts from a controller, if controlled
+    pub agent: Option<tremor_runtime::agent::Agent>,
 }
 
 #[derive(Clone, Copy, Debug)]
@@ -142,6 +166,42 @@ where
     }
 }
 
+/// Qualifies an artefact id with the namespace of the request, if it has one
+fn qualified_id(req: &Request, id: &str) -> String {
+    req.param("namespace").map_or_else(
+        |_| id.to_string(),
+        |ns| tremor_runtime::namespace::qualify(ns, id),
+    )
+}
+
+/// The URL of the artefact `aid` of the request
+fn artefact_url(req: &Request, kind: &str) -> Result<TremorUrl> {
+    let id = qualified_id(req, req.param("aid").unwrap_or_default());
+    build_url(&[kind, &id])
+}
+
+/// The URL of the instance `sid` of the artefact `aid` of the request
+fn instance_url(req: &Request, kind: &str) -> Result<TremorUrl> {
+    let id = qualified_id(req, req.param("aid").unwrap_or_default());
+    build_url(&[kind, &id, req.param("sid").unwrap_or_default()])
+}
+
+/// The ids of the artefacts, for namespaced requests only the ones of the
+/// namespace, without it
+fn artefact_ids(req: &Request, artefacts: &[TremorUrl]) -> Vec<String> {
+    let ns = req.param("namespace").ok();
+    artefacts
+        .iter()
+        .filter_map(TremorUrl::artefact)
+        .filter_map(|id| match ns {
+            Some(ns) => tremor_runtime::namespace::split(id)
+                .filter(|(id_ns, _)| *id_ns == ns)
+                .map(|(_, id)| id.to_string()),
+            None => Some(id.to_string()),
+        })
+        .collect()
+}
+
 fn build_url(path: &[&str]) -> Result<TremorUrl> {
     let url = format!("/{}", path.join("/"));
     TremorUrl::parse(&url).map_err(|_e| {
@@ -0,0 +1,26 @@
+// Copyright 2020-2021, The Tremor Team
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+use crate::api::prelude::*;
+
+pub async fn get(req: Request) -> Result<Response> {
+    let agent = req.state().agent.as_ref().ok_or_else(|| {
+        Error::new(
+            StatusCode::NotFound,
+            "The node is not controlled by a controller".into(),
+        )
+    })?;
+    let result = agent.status().await;
+    reply(&req, result, StatusCode::Ok)
+}
@@ -0,0 +1,221 @@
+// Copyright 2020-2021, The Tremor Team
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+use crate::api::prelude::*;
+use http_types::Method;
+use sha2::{Digest, Sha256};
+use std::collections::VecDeque;
+use std::fs::{File, OpenOptions};
+use std::io::Write;
+use std::path::Path;
+use std::sync::Mutex;
+use tide::{Middleware, Next};
+use tremor_common::time::nanotime;
+
+/// Number of entries kept in memory and served via `GET /audit`
+const MAX_ENTRIES: usize = 1000;
+
+/// A single management operation performed via the API
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Eq)]
+pub struct Entry {
+    /// time of the operation in nanoseconds since the epoch
+    pub timestamp: u64,
+    /// address of the client that performed the operation
+    pub remote: Option<String>,
+    /// the operation, e.g. `publish`, `unpublish`, `link` or `unlink`
+    pub action: String,
+    pub method: String,
+    pub path: String,
+    /// hex encoded sha256 hash of the request body, absent for empty bodies
+    pub body_sha256: Option<String>,
+    /// status code of the response
+    pub status: u16,
+}
+
+/// Append only log of all modifying API operations
+#[derive(Debug, Default)]
+pub struct AuditLog {
+    entries: Mutex<VecDeque<Entry>>,
+    file: Option<Mutex<File>>,
+}
+
+impl AuditLog {
+    /// Creates an audit log that additionally appends every entry,
+    /// one JSON document per line, to the file at `path`
+    pub fn with_file<P: AsRef<Path>>(path: P) -> std::io::Result<Self> {
+        let file = OpenOptions::new().create(true).append(true).open(path)?;
+        Ok(Self {
+            entries: Mutex::new(VecDeque::with_capacity(MAX_ENTRIES)),
+            file: Some(Mutex::new(file)),
+        })
+    }
+
+    fn record(&self, entry: Entry) {
+        if let Some(file) = &self.file {
+            let written = simd_json::to_string(&entry)
+                .map_err(|e| e.to_string())
+                .and_then(|line| {
+                    let mut file = file.lock().map_err(|e| e.to_string())?;
+                    writeln!(file, "{}", line).map_err(|e| e.to_string())
+                });
+            if let Err(e) = written {
+                error!("Failed to write audit log entry {:?}: {}", entry, e);
+            }
+        }
+        if let Ok(mut entries) = self.entries.lock() {
+            if entries.len() >= MAX_ENTRIES {
+                entries.pop_front();
+            }
+            entries.push_back(entry);
+        }
+    }
+
+    /// The most recent entries, oldest first
+    #[must_use]
+    pub fn entries(&self) -> Vec<Entry> {
+        self.entries
+            .lock()
+            .map(|e| e.iter().cloned().collect())
+            .unwrap_or_default()
+    }
+}
+
+/// Names the management operation of a request
+fn action(method: Method, path: &str) -> String {
+    let mut segments = path.split('/').filter(|s| !s.is_empty()).count();
+    // artefacts in namespaces are managed like the ones outside of them
+    if segments > 2 && path.starts_with("/ns/") {
+        segments -= 2;
+    }
+    match (method, segments) {
+        (Method::Post, 1) => "publish".to_string(),
+        (Method::Post, 3) if path.ends_with("/rollback") => "rollback".to_string(),
+        (Method::Post, 3) if path.ends_with("/canary") => "canary".to_string(),
+        (Method::Put, 4) if path.contains("/versions/") => "activate".to_string(),
+        (Method::Put, 2) if path.starts_with("/flag/") => "set-flag".to_string(),
+        (Method::Delete, 2) if path.starts_with("/flag/") => "remove-flag".to_string(),
+        (Method::Post, 4) if path.ends_with("/snapshot") => "snapshot".to_string(),
+        (Method::Post, 4) if path.ends_with("/restore") => "restore".to_string(),
+        (Method::Post, 5) if path.ends_with("/journal/replay") => "replay".to_string(),
+        (Method::Delete, 2) => "unpublish".to_string(),
+        (Method::Post, 3) => "link".to_string(),
+        (Method::Delete, 3) => "unlink".to_string(),
+        (method, _) => method.to_string().to_lowercase(),
+    }
+}
+
+/// Records every request that is not read-only in the audit log, except for
+/// the traffic between cluster nodes
+pub struct Audit;
+
+#[tide::utils::async_trait]
+impl Middleware<State> for Audit {
+    async fn handle(&self, mut req: Request, next: Next<'_, State>) -> tide::Result {
+        let method = req.method();
+        if matches!(method, Method::Get | Method::Head | Method::Options)
+            || req.url().path().starts_with("/cluster/")
+        {
+            return Ok(next.run(req).await);
+        }
+        let body = req.body_bytes().await?;
+        let body_sha256 = if body.is_empty() {
+            None
+        } else {
+            Some(format!("{:x}", Sha256::digest(&body)))
+        };
+        req.set_body(body);
+        let timestamp = nanotime();
+        let remote = req.remote().map(ToString::to_string);
+        let path = req.url().path().to_string();
+        let audit = req.state().audit.clone();
+
+        let res = next.run(req).await;
+        audit.record(Entry {
+            timestamp,
+            remote,
+            action: action(method, &path),
+            method: method.to_string(),
+            path,
+            body_sha256,
+            status: res.status().into(),
+        });
+        Ok(res)
+    }
+}
+
+// ALLOW: We allow this since it's required for generalizing accept functions
+#[allow(clippy::unused_async)]
+pub async fn get(req: Request) -> Result<Response> {
+    let entries = req.state().audit.entries();
+    reply(&req, entries, StatusCode::Ok)
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    fn entry(path: &str) -> Entry {
+        Entry {
+            timestamp: 0,
+            remote: None,
+            action: action(Method::Post, path),
+            method: "POST".to_string(),
+            path: path.to_string(),
+            body_sha256: None,
+            status: 201,
+        }
+    }
+
+    #[test]
+    fn actions() {
+        assert_eq!(action(Method::Post, "/onramp"), "publish");
+        assert_eq!(action(Method::Delete, "/onramp/snot"), "unpublish");
+        assert_eq!(action(Method::Post, "/binding/snot/badger"), "link");
+        assert_eq!(action(Method::Delete, "/binding/snot/badger"), "unlink");
+        assert_eq!(action(Method::Put, "/log-level"), "put");
+        assert_eq!(action(Method::Post, "/pipeline/snot/rollback"), "rollback");
+        assert_eq!(action(Method::Post, "/pipeline/snot/canary"), "canary");
+        assert_eq!(action(Method::Post, "/ns/team/pipeline"), "publish");
+        assert_eq!(
+            action(Method::Delete, "/ns/team/binding/snot/badger"),
+            "unlink"
+        );
+        assert_eq!(action(Method::Put, "/pipeline/snot/versions/2"), "activate");
+        assert_eq!(
+            action(Method::Post, "/pipeline/snot/01/snapshot"),
+            "snapshot"
+        );
+        assert_eq!(
+            action(Method::Post, "/ns/team/pipeline/snot/01/restore"),
+            "restore"
+        );
+        assert_eq!(
+            action(Method::Post, "/offramp/snot/01/journal/replay"),
+            "replay"
+        );
+        assert_eq!(action(Method::Put, "/flag/snot"), "set-flag");
+        assert_eq!(action(Method::Delete, "/flag/snot"), "remove-flag");
+    }
+
+    #[test]
+    fn bounded() {
+        let log = AuditLog::default();
+        for i in 0..=MAX_ENTRIES {
+            log.record(entry(&format!("/pipeline/{}", i)));
+        }
+        let entries = log.entries();
+        assert_eq!(entries.len(), MAX_ENTRIES);
+        assert_eq!(entries[0].path, "/pipeline/1");
+    }
+}
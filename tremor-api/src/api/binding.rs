@@ -25,17 +25,15 @@ struct BindingWrap {
 pub async fn list_artefact(req: Request) -> Result<Response> {
     let repo = &req.state().world.repo;
 
-    let result: Vec<_> = repo
-        .list_bindings()
-        .await?
-        .iter()
-        .filter_map(|v| v.artefact().map(String::from))
-        .collect();
+    let result = artefact_ids(&req, &repo.list_bindings().await?);
     reply(&req, result, StatusCode::Ok)
 }
 
 pub async fn publish_artefact(req: Request) -> Result<Response> {
-    let (req, binding): (_, tremor_runtime::config::Binding) = decode(req).await?;
+    let (req, mut binding): (_, tremor_runtime::config::Binding) = decode(req).await?;
+    if let Ok(ns) = req.param("namespace") {
+        tremor_runtime::namespace::qualify_binding(ns, &mut binding);
+    }
     let url = build_url(&["binding", &binding.id])?;
 
     let repo = &req.state().world.repo;
@@ -54,16 +52,14 @@ pub async fn publish_artefact(req: Request) -> Result<Response> {
 }
 
 pub async fn unpublish_artefact(req: Request) -> Result<Response> {
-    let id = req.param("aid").unwrap_or_default();
-    let url = build_url(&["binding", id])?;
+    let url = artefact_url(&req, "binding")?;
     let repo = &req.state().world.repo;
     let result = repo.unpublish_binding(&url).await?;
     reply(&req, result.binding, StatusCode::Ok)
 }
 
 pub async fn get_artefact(req: Request) -> Result<Response> {
-    let id = req.param("aid").unwrap_or_default();
-    let url = build_url(&["binding", id])?;
+    let url = artefact_url(&req, "binding")?;
 
     let repo = &req.state().world.repo;
     let result = repo
@@ -86,7 +82,7 @@ pub async fn get_artefact(req: Request) -> Result<Response> {
 pub async fn get_servant(req: Request) -> Result<Response> {
     let a_id = req.param("aid").unwrap_or_default();
     let s_id = req.param("sid").unwrap_or_default();
-    let url = build_url(&["binding", a_id, s_id])?;
+    let url = build_url(&["binding", &qualified_id(&req, a_id), s_id])?;
 
     let registry = &req.state().world.reg;
     let result = registry
@@ -104,7 +100,7 @@ pub async fn link_servant(req: Request) -> Result<Response> {
 
     let a_id = req.param("aid").unwrap_or_default();
     let s_id = req.param("sid").unwrap_or_default();
-    let url = build_url(&["binding", a_id, s_id])?;
+    let url = build_url(&["binding", &qualified_id(&req, a_id), s_id])?;
     let world = &req.state().world;
 
     let result = world.link_binding(&url, decoded_data).await?.binding;
@@ -116,7 +112,7 @@ pub async fn link_servant(req: Request) -> Result<Response> {
 pub async fn unlink_servant(req: Request) -> Result<Response> {
     let a_id = req.param("aid").unwrap_or_default();
     let s_id = req.param("sid").unwrap_or_default();
-    let url = build_url(&["binding", a_id, s_id])?;
+    let url = build_url(&["binding", &qualified_id(&req, a_id), s_id])?;
 
     let world = &req.state().world;
     let result = world.unlink_binding(&url, HashMap::new()).await?.binding;
@@ -0,0 +1,201 @@
+// Copyright 2020-2021, The Tremor Team
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+use crate::api::prelude::*;
+use async_channel::Receiver;
+use http_types::{Method, Url};
+use tide::{Middleware, Next};
+use tremor_runtime::cluster::{
+    capture, restore, AppendRequest, Applied, Cluster, Command, Committed, SnapshotRequest,
+    VoteRequest,
+};
+
+fn node(req: &Request) -> Result<&Cluster> {
+    req.state().cluster.as_ref().ok_or_else(|| {
+        Error::new(
+            StatusCode::NotFound,
+            "The node is not part of a cluster".into(),
+        )
+    })
+}
+
+pub async fn status(req: Request) -> Result<Response> {
+    let result = node(&req)?.status().await?;
+    reply(&req, result, StatusCode::Ok)
+}
+
+pub async fn vote(req: Request) -> Result<Response> {
+    let (req, request): (_, VoteRequest) = decode(req).await?;
+    let result = node(&req)?.vote(request).await?;
+    reply(&req, result, StatusCode::Ok)
+}
+
+pub async fn append(req: Request) -> Result<Response> {
+    let (req, request): (_, AppendRequest) = decode(req).await?;
+    let result = node(&req)?.append(request).await?;
+    reply(&req, result, StatusCode::Ok)
+}
+
+pub async fn snapshot(req: Request) -> Result<Response> {
+    let (req, request): (_, SnapshotRequest) = decode(req).await?;
+    let result = node(&req)?.snapshot(request).await?;
+    reply(&req, result, StatusCode::Ok)
+}
+
+/// Submits a change forwarded by another node
+pub async fn submit(req: Request) -> Result<Response> {
+    let (req, command): (_, Command) = decode(req).await?;
+    let result = node(&req)?.submit(command).await?;
+    reply(&req, result, StatusCode::Ok)
+}
+
+/// Changes that are replicated, everything but reads, the cluster itself,
+/// the log levels, pipeline snapshots and journal replays of the node
+fn replicated(method: Method, path: &str) -> bool {
+    !(matches!(method, Method::Get | Method::Head | Method::Options)
+        || path == "/cluster"
+        || path.starts_with("/cluster/")
+        || path == "/log-level"
+        || path.ends_with("/snapshot")
+        || path.ends_with("/journal/replay"))
+}
+
+fn header(req: &Request, name: headers::HeaderName) -> Option<String> {
+    req.header(name)
+        .map(headers::HeaderValues::last)
+        .map(|v| v.as_str().to_string())
+}
+
+/// Submits changes to the cluster instead of applying them directly, they
+/// are applied on every node once the cluster committed them. The response
+/// is the one of the node the change was submitted to.
+pub struct Replicate;
+
+#[tide::utils::async_trait]
+impl Middleware<State> for Replicate {
+    async fn handle(&self, mut req: Request, next: Next<'_, State>) -> tide::Result {
+        let cluster = match &req.state().cluster {
+            Some(cluster) if replicated(req.method(), req.url().path()) => cluster.clone(),
+            _ => return Ok(next.run(req).await),
+        };
+        let url = req.url();
+        let path = match url.query() {
+            Some(query) => format!("{}?{}", url.path(), query),
+            None => url.path().to_string(),
+        };
+        let command = Command {
+            method: req.method().to_string(),
+            path,
+            content_type: header(&req, headers::CONTENT_TYPE),
+            accept: header(&req, headers::ACCEPT),
+            body: req.body_bytes().await?,
+        };
+        match cluster.submit(command).await {
+            Ok(applied) => {
+                let mut res = Response::builder(applied.status).body(applied.body);
+                if let Some(content_type) = applied.content_type {
+                    res = res.header(headers::CONTENT_TYPE, content_type);
+                }
+                Ok(res.build())
+            }
+            Err(e) => Ok(serialize_error(accept(&req), e.into()).unwrap_or_else(Into::into)),
+        }
+    }
+}
+
+/// Applies a committed change to the node
+async fn respond(app: &tide::Server<State>, command: Command) -> Result<Applied> {
+    let method: Method = command.method.parse()?;
+    let url = Url::parse(&format!("http://localhost{}", command.path))
+        .map_err(|e| Error::new(StatusCode::BadRequest, format!("Invalid path: {}", e)))?;
+    let mut req = http_types::Request::new(method, url);
+    if let Some(content_type) = command.content_type {
+        req.insert_header(headers::CONTENT_TYPE, content_type);
+    }
+    if let Some(accept) = command.accept {
+        req.insert_header(headers::ACCEPT, accept);
+    }
+    req.set_body(command.body);
+    let mut res: http_types::Response = app.respond(req).await?;
+    Ok(Applied {
+        status: res.status().into(),
+        content_type: res.content_type().map(|mime| mime.to_string()),
+        body: res.body_bytes().await?,
+    })
+}
+
+/// Applies the changes committed by the cluster in order, using `app`
+/// without any middleware as they were already authorized and replicated.
+/// The state after each change is snapshotted, so restarted nodes restore
+/// it instead of applying all changes again.
+pub async fn apply(app: tide::Server<State>, cluster: Cluster, committed: Receiver<Committed>) {
+    while let Ok(committed) = committed.recv().await {
+        let (index, term, command) = match committed {
+            Committed::Snapshot(data) => {
+                info!("Restoring the cluster snapshot");
+                if let Err(e) = restore(&app.state().world, &data).await {
+                    error!("Failed to restore the cluster snapshot: {}", e);
+                }
+                continue;
+            }
+            Committed::Command {
+                index,
+                term,
+                command,
+            } => (index, term, command),
+        };
+        debug!("Applying {} {} ({})", command.method, command.path, index);
+        let applied = respond(&app, command).await.unwrap_or_else(|e| {
+            error!("Failed to apply change {}: {}", index, e);
+            Applied {
+                status: e.code.into(),
+                content_type: None,
+                body: e.error.into_bytes(),
+            }
+        });
+        let snapshot = match capture(&app.state().world).await {
+            Ok(snapshot) => Some(snapshot),
+            Err(e) => {
+                error!("Failed to snapshot change {}: {}", index, e);
+                None
+            }
+        };
+        if cluster
+            .applied(index, term, applied, snapshot)
+            .await
+            .is_err()
+        {
+            break;
+        }
+    }
+    warn!("Stopped applying cluster changes");
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn replicated_requests() {
+        assert!(replicated(Method::Post, "/pipeline"));
+        assert!(replicated(Method::Delete, "/ns/team/binding/b/s"));
+        assert!(replicated(Method::Put, "/offramp/out/state"));
+        assert!(!replicated(Method::Get, "/pipeline"));
+        assert!(!replicated(Method::Post, "/cluster/append"));
+        assert!(!replicated(Method::Put, "/log-level"));
+        assert!(!replicated(Method::Post, "/pipeline/main/01/snapshot"));
+        assert!(replicated(Method::Post, "/pipeline/main/01/restore"));
+        assert!(!replicated(Method::Post, "/offramp/out/01/journal/replay"));
+    }
+}
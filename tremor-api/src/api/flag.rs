@@ -0,0 +1,50 @@
+// Copyright 2020-2021, The Tremor Team
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+use crate::api::prelude::*;
+use tremor_runtime::config::Flag;
+use tremor_runtime::flags;
+
+fn name(req: &Request) -> &str {
+    req.param("name").unwrap_or_default()
+}
+
+// ALLOW: We allow this since it's required for generalizing accept functions
+#[allow(clippy::unused_async)]
+pub async fn list(req: Request) -> Result<Response> {
+    let result = flags::list()?;
+    reply(&req, result, StatusCode::Ok)
+}
+
+// ALLOW: We allow this since it's required for generalizing accept functions
+#[allow(clippy::unused_async)]
+pub async fn get(req: Request) -> Result<Response> {
+    let result = flags::get(name(&req))?.ok_or_else(Error::not_found)?;
+    reply(&req, result, StatusCode::Ok)
+}
+
+/// Defines or replaces a flag
+pub async fn put(req: Request) -> Result<Response> {
+    let (req, flag): (_, Flag) = decode(req).await?;
+    flags::set(name(&req), flag.clone())?;
+    info!("Flag {} set to {:?}", name(&req), flag);
+    reply(&req, flag, StatusCode::Ok)
+}
+
+// ALLOW: We allow this since it's required for generalizing accept functions
+#[allow(clippy::unused_async)]
+pub async fn remove(req: Request) -> Result<Response> {
+    let result = flags::remove(name(&req))?.ok_or_else(Error::not_found)?;
+    reply(&req, result, StatusCode::Ok)
+}
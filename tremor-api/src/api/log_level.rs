@@ -0,0 +1,86 @@
+// Copyright 2020-2021, The Tremor Team
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+use crate::api::prelude::*;
+use log::LevelFilter;
+use std::str::FromStr;
+
+/// A log level for a module, or the default log level if no module is given
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Eq)]
+pub struct LogLevel {
+    /// module path prefix, e.g. `tremor_runtime::sink::kafka`
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub module: Option<String>,
+    /// one of `off`, `error`, `warn`, `info`, `debug` or `trace`,
+    /// when absent the override for `module` is removed
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub level: Option<String>,
+}
+
+/// Log levels that can be changed while tremor is running
+pub trait LogLevels: Send + Sync {
+    /// The currently effective levels
+    fn levels(&self) -> Vec<LogLevel>;
+    /// Sets the level for `module` or the default level if `module` is `None`.
+    /// A level of `None` removes the override for `module`.
+    fn set_level(&self, module: Option<&str>, level: Option<LevelFilter>);
+}
+
+fn log_levels(req: &Request) -> Result<&dyn LogLevels> {
+    req.state().log_levels.as_deref().ok_or_else(|| {
+        Error::new(
+            StatusCode::NotImplemented,
+            "Log levels can not be changed for the configured logger".into(),
+        )
+    })
+}
+
+// ALLOW: We allow this since it's required for generalizing accept functions
+#[allow(clippy::unused_async)]
+pub async fn get(req: Request) -> Result<Response> {
+    let levels = log_levels(&req)?.levels();
+    reply(&req, levels, StatusCode::Ok)
+}
+
+pub async fn put(req: Request) -> Result<Response> {
+    let (req, update): (_, LogLevel) = decode(req).await?;
+    let level = update
+        .level
+        .as_deref()
+        .map(LevelFilter::from_str)
+        .transpose()
+        .map_err(|_| {
+            Error::new(
+                StatusCode::BadRequest,
+                format!(
+                    "Invalid log level: {}",
+                    update.level.as_deref().unwrap_or_default()
+                ),
+            )
+        })?;
+    if level.is_none() && update.module.is_none() {
+        return Err(Error::new(
+            StatusCode::BadRequest,
+            "The default log level can not be removed".into(),
+        ));
+    }
+    let log_levels = log_levels(&req)?;
+    log_levels.set_level(update.module.as_deref(), level);
+    info!(
+        "Log level for {} set to {}",
+        update.module.as_deref().unwrap_or("<default>"),
+        update.level.as_deref().unwrap_or("<default>")
+    );
+    reply(&req, log_levels.levels(), StatusCode::Ok)
+}
@@ -0,0 +1,166 @@
+// Copyright 2020-2021, The Tremor Team
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+use crate::api::prelude::*;
+use http_types::Method;
+use tide::{Middleware, Next};
+use tremor_runtime::namespace::{Namespace, Quota};
+use tremor_runtime::utils::constant_time_eq;
+
+pub async fn list(req: Request) -> Result<Response> {
+    let result = req.state().world.ns.list().await;
+    reply(&req, result, StatusCode::Ok)
+}
+
+pub async fn create(req: Request) -> Result<Response> {
+    let (req, namespace): (_, Namespace) = decode(req).await?;
+    let result = req.state().world.ns.create(namespace).await?;
+    reply(&req, result, StatusCode::Created)
+}
+
+pub async fn get(req: Request) -> Result<Response> {
+    let name = req.param("namespace").unwrap_or_default();
+    let result = req
+        .state()
+        .world
+        .ns
+        .find(name)
+        .await
+        .ok_or_else(Error::not_found)?;
+    reply(&req, result, StatusCode::Ok)
+}
+
+pub async fn remove(req: Request) -> Result<Response> {
+    let name = req.param("namespace").unwrap_or_default();
+    let world = &req.state().world;
+    let result = world.ns.remove(name, &world.repo).await?;
+    reply(&req, result, StatusCode::Ok)
+}
+
+pub async fn set_quota(req: Request) -> Result<Response> {
+    let (req, quota): (_, Quota) = decode(req).await?;
+    let name = req.param("namespace").unwrap_or_default();
+    let result = req.state().world.ns.set_quota(name, quota).await?;
+    reply(&req, result, StatusCode::Ok)
+}
+
+/// What a request needs access to
+#[derive(Debug, PartialEq)]
+enum Scope<'path> {
+    /// everything, e.g. managing namespaces or artefacts outside of them
+    All,
+    /// the namespace
+    Namespace(&'path str),
+}
+
+fn scope(method: Method, path: &str) -> Scope<'_> {
+    let segments: Vec<_> = path.split('/').filter(|s| !s.is_empty()).collect();
+    match segments.as_slice() {
+        ["ns", name] if method == Method::Get => Scope::Namespace(name),
+        ["ns"] | ["ns", _] | ["ns", _, "quota"] => Scope::All,
+        ["ns", name, ..] => Scope::Namespace(name),
+        _ => Scope::All,
+    }
+}
+
+fn bearer_token(req: &Request) -> Option<&str> {
+    req.header(headers::AUTHORIZATION)
+        .map(headers::HeaderValues::last)
+        .map(headers::HeaderValue::as_str)
+        .and_then(|value| value.strip_prefix("Bearer "))
+}
+
+/// If the token is one of the tokens, they are compared in constant time
+fn holds(tokens: &[String], token: Option<&str>) -> bool {
+    token.map_or(false, |token| {
+        tokens
+            .iter()
+            .any(|t| constant_time_eq(t.as_bytes(), token.as_bytes()))
+    })
+}
+
+/// Checks the API token of every request. Tokens of the API grant access to
+/// everything, tokens of a namespace to the namespace. Without API tokens
+/// only namespaces with tokens are protected.
+pub struct Authorize;
+
+#[tide::utils::async_trait]
+impl Middleware<State> for Authorize {
+    async fn handle(&self, req: Request, next: Next<'_, State>) -> tide::Result {
+        let token = bearer_token(&req);
+        let state = req.state();
+        let open = state.tokens.is_empty();
+        let admin = holds(&state.tokens, token);
+        let granted = match scope(req.method(), req.url().path()) {
+            Scope::All => admin || open,
+            Scope::Namespace(name) => match state.world.ns.find(name).await {
+                Some(ns) if ns.tokens.is_empty() => admin || open,
+                Some(ns) => admin || holds(&ns.tokens, token),
+                None if admin || open => {
+                    let error =
+                        Error::new(StatusCode::NotFound, "The namespace was not found".into());
+                    return Ok(serialize_error(accept(&req), error).unwrap_or_else(Into::into));
+                }
+                None => false,
+            },
+        };
+        if granted {
+            return Ok(next.run(req).await);
+        }
+        let error = if token.is_some() {
+            Error::new(
+                StatusCode::Forbidden,
+                "The API token does not grant access".into(),
+            )
+        } else {
+            Error::new(StatusCode::Unauthorized, "An API token is required".into())
+        };
+        Ok(serialize_error(accept(&req), error).unwrap_or_else(Into::into))
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn scopes() {
+        assert_eq!(Scope::All, scope(Method::Get, "/pipeline"));
+        assert_eq!(Scope::All, scope(Method::Get, "/ns"));
+        assert_eq!(Scope::All, scope(Method::Post, "/ns"));
+        assert_eq!(Scope::Namespace("team"), scope(Method::Get, "/ns/team"));
+        assert_eq!(Scope::All, scope(Method::Delete, "/ns/team"));
+        assert_eq!(Scope::All, scope(Method::Put, "/ns/team/quota"));
+        assert_eq!(
+            Scope::Namespace("team"),
+            scope(Method::Post, "/ns/team/pipeline")
+        );
+        assert_eq!(
+            Scope::Namespace("team"),
+            scope(Method::Delete, "/ns/team/binding/default/01")
+        );
+    }
+
+    #[test]
+    fn tokens() {
+        let tokens = vec!["snot".to_string(), "badger".to_string()];
+        assert!(holds(&tokens, Some("badger")));
+        assert!(!holds(&tokens, None));
+        assert!(!holds(&[], Some("snot")));
+        // tokens sharing a prefix with a valid one are rejected
+        assert!(!holds(&tokens, Some("sno")));
+        assert!(!holds(&tokens, Some("snotbadger")));
+        assert!(!holds(&tokens, Some("snoT")));
+    }
+}
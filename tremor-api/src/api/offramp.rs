@@ -13,6 +13,7 @@
 // limitations under the License.
 
 use crate::api::prelude::*;
+use hashbrown::HashMap;
 #[derive(Serialize)]
 struct OffRampWrap {
     artefact: tremor_runtime::config::OffRamp,
@@ -21,17 +22,13 @@ struct OffRampWrap {
 
 pub async fn list_artefact(req: Request) -> Result<Response> {
     let repo = &req.state().world.repo;
-    let result: Vec<_> = repo
-        .list_offramps()
-        .await?
-        .iter()
-        .filter_map(|v| v.artefact().map(String::from))
-        .collect();
+    let result = artefact_ids(&req, &repo.list_offramps().await?);
     reply(&req, result, StatusCode::Ok)
 }
 
 pub async fn publish_artefact(req: Request) -> Result<Response> {
-    let (req, data): (_, tremor_runtime::config::OffRamp) = decode(req).await?;
+    let (req, mut data): (_, tremor_runtime::config::OffRamp) = decode(req).await?;
+    data.id = qualified_id(&req, &data.id);
     let url = build_url(&["offramp", &data.id])?;
     let repo = &req.state().world.repo;
     let result = repo.publish_offramp(&url, false, data).await?;
@@ -39,16 +36,14 @@ pub async fn publish_artefact(req: Request) -> Result<Response> {
 }
 
 pub async fn unpublish_artefact(req: Request) -> Result<Response> {
-    let id = req.param("aid").unwrap_or_default();
-    let url = build_url(&["offramp", id])?;
+    let url = artefact_url(&req, "offramp")?;
     let repo = &req.state().world.repo;
     let result = repo.unpublish_offramp(&url).await?;
     reply(&req, result, StatusCode::Ok)
 }
 
 pub async fn get_artefact(req: Request) -> Result<Response> {
-    let id = req.param("aid").unwrap_or_default();
-    let url = build_url(&["offramp", id])?;
+    let url = artefact_url(&req, "offramp")?;
     let repo = &req.state().world.repo;
     let result = repo
         .find_offramp(&url)
@@ -65,3 +60,95 @@ pub async fn get_artefact(req: Request) -> Result<Response> {
 
     reply(&req, result, StatusCode::Ok)
 }
+
+#[derive(Deserialize)]
+struct StateUpdate {
+    state: tremor_runtime::system::CbState,
+}
+
+/// Circuit breaker state of all instances of an offramp
+pub async fn get_state(req: Request) -> Result<Response> {
+    let url = artefact_url(&req, "offramp")?;
+    let world = &req.state().world;
+    let artefact = world
+        .repo
+        .find_offramp(&url)
+        .await?
+        .ok_or_else(Error::not_found)?;
+    let mut result = HashMap::new();
+    for instance in &artefact.instances {
+        if let (Some(name), Some(state)) =
+            (instance.instance(), world.offramp_state(instance).await?)
+        {
+            result.insert(name.to_string(), state);
+        }
+    }
+    reply(&req, result, StatusCode::Ok)
+}
+
+/// Opens or closes the circuit breaker of all instances of an offramp
+pub async fn set_state(req: Request) -> Result<Response> {
+    let (req, update): (_, StateUpdate) = decode(req).await?;
+    let url = artefact_url(&req, "offramp")?;
+    let world = &req.state().world;
+    let artefact = world
+        .repo
+        .find_offramp(&url)
+        .await?
+        .ok_or_else(Error::not_found)?;
+    let mut result = HashMap::new();
+    for instance in &artefact.instances {
+        if let (Some(name), Some(state)) = (
+            instance.instance(),
+            world.set_offramp_state(instance, update.state).await?,
+        ) {
+            result.insert(name.to_string(), state);
+        }
+    }
+    reply(&req, result, StatusCode::Ok)
+}
+
+/// The failed deliveries of an offramp instance, without their payload
+// ALLOW: We allow this since it's required for generalizing accept functions
+#[allow(clippy::unused_async)]
+pub async fn list_journal(req: Request) -> Result<Response> {
+    let url = instance_url(&req, "offramp")?;
+    let result = req
+        .state()
+        .world
+        .offramp_journal(&url)?
+        .ok_or_else(Error::not_found)?;
+    reply(&req, result, StatusCode::Ok)
+}
+
+/// A failed delivery of an offramp instance, with its payload
+// ALLOW: We allow this since it's required for generalizing accept functions
+#[allow(clippy::unused_async)]
+pub async fn get_journal_entry(req: Request) -> Result<Response> {
+    let url = instance_url(&req, "offramp")?;
+    let id = req.param("id").unwrap_or_default();
+    let id = id.parse().map_err(|_| {
+        Error::new(
+            StatusCode::BadRequest,
+            format!("Invalid journal entry: {}", id),
+        )
+    })?;
+    let result = req
+        .state()
+        .world
+        .offramp_journal_entry(&url, id)?
+        .ok_or_else(Error::not_found)?;
+    reply(&req, result, StatusCode::Ok)
+}
+
+/// Replays failed deliveries of an offramp instance
+pub async fn replay_journal(req: Request) -> Result<Response> {
+    let (req, replay): (_, tremor_runtime::system::Replay) = decode(req).await?;
+    let url = instance_url(&req, "offramp")?;
+    let world = &req.state().world;
+    let result = world
+        .replay_offramp_journal(&url, replay)
+        .await?
+        .ok_or_else(Error::not_found)?;
+    reply(&req, result, StatusCode::Ok)
+}
@@ -22,17 +22,13 @@ struct OnRampWrap {
 pub async fn list_artefact(req: Request) -> Result<Response> {
     let repo = &req.state().world.repo;
 
-    let result: Vec<_> = repo
-        .list_onramps()
-        .await?
-        .iter()
-        .filter_map(|v| v.artefact().map(String::from))
-        .collect();
+    let result = artefact_ids(&req, &repo.list_onramps().await?);
     reply(&req, result, StatusCode::Ok)
 }
 
 pub async fn publish_artefact(req: Request) -> Result<Response> {
-    let (req, data): (_, tremor_runtime::config::OnRamp) = decode(req).await?;
+    let (req, mut data): (_, tremor_runtime::config::OnRamp) = decode(req).await?;
+    data.id = qualified_id(&req, &data.id);
     let url = build_url(&["onramp", &data.id])?;
     let repo = &req.state().world.repo;
     let result = repo.publish_onramp(&url, false, data).await?;
@@ -40,16 +36,14 @@ pub async fn publish_artefact(req: Request) -> Result<Response> {
 }
 
 pub async fn unpublish_artefact(req: Request) -> Result<Response> {
-    let id = req.param("aid").unwrap_or_default();
-    let url = build_url(&["onramp", id])?;
+    let url = artefact_url(&req, "onramp")?;
     let repo = &req.state().world.repo;
     let result = repo.unpublish_onramp(&url).await?;
     reply(&req, result, StatusCode::Ok)
 }
 
 pub async fn get_artefact(req: Request) -> Result<Response> {
-    let id = req.param("aid").unwrap_or_default();
-    let url = build_url(&["onramp", id])?;
+    let url = artefact_url(&req, "onramp")?;
     let repo = &req.state().world.repo;
     let result = repo.find_onramp(&url).await?.ok_or_else(Error::not_found)?;
     let result = OnRampWrap {
@@ -12,9 +12,10 @@
 // See the License for the specific language governing permissions and
 // limitations under the License.
 
-use tremor_pipeline::{query::Query, FN_REGISTRY};
+use tremor_pipeline::{query::Query, Snapshot, FN_REGISTRY};
 
 use crate::api::prelude::*;
+use tremor_runtime::repository::{PipelineArtefact, RepoWrapper};
 
 #[derive(Serialize)]
 struct PipelineWrap {
@@ -22,15 +23,46 @@ struct PipelineWrap {
     instances: Vec<String>,
 }
 
+#[derive(Serialize)]
+struct PipelineVersion {
+    version: usize,
+    active: bool,
+}
+
+#[derive(Serialize)]
+struct Restored {
+    taken_ns: u64,
+}
+
+#[derive(Serialize)]
+struct CanaryDeployment {
+    version: usize,
+    instances: usize,
+}
+
+impl PipelineVersion {
+    fn active(wrapper: &RepoWrapper<PipelineArtefact>) -> Self {
+        Self {
+            version: wrapper.version,
+            active: true,
+        }
+    }
+}
+
+fn version_param(req: &Request) -> Result<usize> {
+    let version = req.param("version").unwrap_or_default();
+    version.parse().map_err(|_| {
+        Error::new(
+            StatusCode::BadRequest,
+            format!("Invalid version: {}", version),
+        )
+    })
+}
+
 pub async fn list_artefact(req: Request) -> Result<Response> {
     let repo = &req.state().world.repo;
 
-    let result: Vec<_> = repo
-        .list_pipelines()
-        .await?
-        .iter()
-        .filter_map(|v| v.artefact().map(String::from))
-        .collect();
+    let result = artefact_ids(&req, &repo.list_pipelines().await?);
     reply(&req, result, StatusCode::Ok)
 }
 
@@ -56,9 +88,13 @@ pub async fn publish_artefact(mut req: Request) -> Result<Response> {
                 )
             })?;
 
-            let url = build_url(&["pipeline", id])?;
-            let repo = &req.state().world.repo;
-            let result = repo
+            let url = build_url(&["pipeline", &qualified_id(&req, id)])?;
+            let world = &req.state().world;
+            if let Ok(ns) = req.param("namespace") {
+                world.ns.check_pipeline_quota(ns, &url, &world.repo).await?;
+            }
+            let result = world
+                .repo
                 .publish_pipeline(&url, false, query)
                 .await
                 .map(|result| result.source().to_string())?;
@@ -108,8 +144,7 @@ pub fn reply_trickle_instanced(
 }
 
 pub async fn unpublish_artefact(req: Request) -> Result<Response> {
-    let id = req.param("aid").unwrap_or_default();
-    let url = build_url(&["pipeline", id])?;
+    let url = artefact_url(&req, "pipeline")?;
     let repo = &req.state().world.repo;
     let result = repo
         .unpublish_pipeline(&url)
@@ -119,8 +154,7 @@ pub async fn unpublish_artefact(req: Request) -> Result<Response> {
 }
 
 pub async fn get_artefact(req: Request) -> Result<Response> {
-    let id = req.param("aid").unwrap_or_default();
-    let url = build_url(&["pipeline", id])?;
+    let url = artefact_url(&req, "pipeline")?;
     let repo = &req.state().world.repo;
     let result = repo
         .find_pipeline(&url)
@@ -138,3 +172,87 @@ pub async fn get_artefact(req: Request) -> Result<Response> {
         StatusCode::Ok,
     )
 }
+
+pub async fn list_versions(req: Request) -> Result<Response> {
+    let url = artefact_url(&req, "pipeline")?;
+    let repo = &req.state().world.repo;
+    let result = repo
+        .find_pipeline(&url)
+        .await?
+        .ok_or_else(Error::not_found)?;
+
+    let versions: Vec<_> = (1..=result.versions.len())
+        .map(|version| PipelineVersion {
+            version,
+            active: version == result.version,
+        })
+        .collect();
+    reply(&req, versions, StatusCode::Ok)
+}
+
+pub async fn get_version(req: Request) -> Result<Response> {
+    let version = version_param(&req)?;
+    let url = artefact_url(&req, "pipeline")?;
+    let repo = &req.state().world.repo;
+    let result = repo
+        .find_pipeline(&url)
+        .await?
+        .ok_or_else(Error::not_found)?;
+    let artefact = version
+        .checked_sub(1)
+        .and_then(|i| result.versions.get(i))
+        .ok_or_else(Error::not_found)?;
+    reply_trickle_flat(&req, artefact.source().to_string(), StatusCode::Ok)
+}
+
+pub async fn activate_version(req: Request) -> Result<Response> {
+    let version = version_param(&req)?;
+    let url = artefact_url(&req, "pipeline")?;
+    let world = &req.state().world;
+    let result = world.activate_pipeline(&url, version).await?;
+    reply(&req, PipelineVersion::active(&result), StatusCode::Ok)
+}
+
+pub async fn canary(req: Request) -> Result<Response> {
+    let (req, config): (_, tremor_runtime::config::Canary) = decode(req).await?;
+    let url = artefact_url(&req, "pipeline")?;
+    let world = &req.state().world;
+    let version = config.version;
+    let instances = world.canary_pipeline(&url, config).await?;
+    reply(
+        &req,
+        CanaryDeployment { version, instances },
+        StatusCode::Accepted,
+    )
+}
+
+pub async fn rollback(req: Request) -> Result<Response> {
+    let url = artefact_url(&req, "pipeline")?;
+    let world = &req.state().world;
+    let result = world.rollback_pipeline(&url).await?;
+    reply(&req, PipelineVersion::active(&result), StatusCode::Ok)
+}
+
+/// Snapshot of the state of a pipeline instance
+pub async fn snapshot(req: Request) -> Result<Response> {
+    let url = instance_url(&req, "pipeline")?;
+    let world = &req.state().world;
+    let result = world
+        .snapshot_pipeline(&url)
+        .await?
+        .ok_or_else(Error::not_found)?;
+    reply(&req, result, StatusCode::Ok)
+}
+
+/// Restores the state of a pipeline instance from a snapshot
+pub async fn restore(req: Request) -> Result<Response> {
+    let (req, snapshot): (_, Snapshot) = decode(req).await?;
+    let url = instance_url(&req, "pipeline")?;
+    let world = &req.state().world;
+    let taken_ns = snapshot.taken_ns;
+    if world.restore_pipeline(&url, snapshot).await? {
+        reply(&req, Restored { taken_ns }, StatusCode::Ok)
+    } else {
+        Err(Error::not_found())
+    }
+}
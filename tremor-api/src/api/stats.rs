@@ -0,0 +1,62 @@
+// Copyright 2020-2021, The Tremor Team
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+use crate::api::prelude::*;
+
+/// Statistics of the allocator, fields the allocator in use can't provide
+/// are left out
+#[derive(Serialize, Deserialize, Debug, Clone, Default, PartialEq, Eq)]
+pub struct AllocatorStats {
+    /// name of the allocator tremor was built with
+    pub allocator: String,
+    /// allocations since the start, if they are counted
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub allocations: Option<u64>,
+    /// bytes allocated since the start, if allocations are counted
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub allocated_bytes: Option<u64>,
+    /// resident set size in bytes
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub current_rss: Option<u64>,
+    /// peak resident set size in bytes
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub peak_rss: Option<u64>,
+    /// memory committed by the allocator in bytes
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub current_commit: Option<u64>,
+    /// peak memory committed by the allocator in bytes
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub peak_commit: Option<u64>,
+    /// page faults since the start
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub page_faults: Option<u64>,
+}
+
+/// The allocator of the running tremor
+pub trait Allocator: Send + Sync {
+    /// Current statistics of the allocator
+    fn stats(&self) -> AllocatorStats;
+}
+
+// ALLOW: We allow this since it's required for generalizing accept functions
+#[allow(clippy::unused_async)]
+pub async fn get_allocator(req: Request) -> Result<Response> {
+    let allocator = req.state().allocator.as_deref().ok_or_else(|| {
+        Error::new(
+            StatusCode::NotImplemented,
+            "No allocator statistics are available".into(),
+        )
+    })?;
+    reply(&req, allocator.stats(), StatusCode::Ok)
+}
@@ -128,6 +128,58 @@ impl From<TremorError> for Error {
                 StatusCode::Forbidden,
                 "System artefacts cannot be unpublished".into(),
             ),
+            ErrorKind::ActivateFailedSystemArtefact(_) => Error::new(
+                StatusCode::Forbidden,
+                "The version of system artefacts cannot be changed".into(),
+            ),
+            ErrorKind::ArtefactVersionNotFound(_, _) => {
+                Error::new(StatusCode::NotFound, "Artefact version not found".into())
+            }
+            ErrorKind::RollbackFailedNoPreviousVersion(_) => Error::new(
+                StatusCode::Conflict,
+                "There is no previous version to roll back to".into(),
+            ),
+            ErrorKind::CanaryFailedNoInstances(_) => Error::new(
+                StatusCode::Conflict,
+                "The pipeline has no running instances".into(),
+            ),
+            ErrorKind::InvalidNamespace(_) => {
+                Error::new(StatusCode::BadRequest, "Invalid namespace name".into())
+            }
+            ErrorKind::NamespaceExists(_) => Error::new(
+                StatusCode::Conflict,
+                "A namespace with the requested name already exists".into(),
+            ),
+            ErrorKind::NamespaceNotFound(_) => {
+                Error::new(StatusCode::NotFound, "Namespace not found".into())
+            }
+            ErrorKind::NamespaceNotEmpty(_) => Error::new(
+                StatusCode::Conflict,
+                "The namespace still has artefacts".into(),
+            ),
+            ErrorKind::QuotaExceeded(_, quota) => Error::new(
+                StatusCode::Forbidden,
+                format!("The namespace quota is exceeded: {}", quota),
+            ),
+            ErrorKind::InvalidCanaryPercentage(_) => Error::new(
+                StatusCode::BadRequest,
+                "The canary percentage must be at most 100".into(),
+            ),
+            ErrorKind::InvalidFlagRollout(_, _) => Error::new(
+                StatusCode::BadRequest,
+                "The rollout of the flag must be at most 100".into(),
+            ),
+            ErrorKind::ClusterUnavailable(reason) => Error::new(
+                StatusCode::ServiceUnavailable,
+                format!("The cluster can't accept changes: {}", reason),
+            ),
+            ErrorKind::InstanceNotFound(_) => {
+                Error::new(StatusCode::NotFound, "Instance not found".into())
+            }
+            ErrorKind::RestoreFailed(_, reason) => Error::new(
+                StatusCode::Conflict,
+                format!("The snapshot can't be restored: {}", reason),
+            ),
             _e => Error::new(
                 StatusCode::InternalServerError,
                 "Internal server error".into(),
@@ -27,6 +27,8 @@
 
 //#[macro_use]
 extern crate serde_derive;
+#[macro_use]
+extern crate log;
 
 mod api;
 mod errors;
@@ -12,18 +12,285 @@
 // See the License for the specific language governing permissions and
 // limitations under the License.
 
+use crate::errors::Result;
+#[cfg(feature = "bench")]
+use std::alloc::{GlobalAlloc, Layout};
+#[cfg(feature = "bench")]
+use std::sync::atomic::{AtomicBool, AtomicU64, Ordering};
+use tremor_api::stats::{Allocator, AllocatorStats};
+
+#[cfg(feature = "stdalloc")]
+type Base = std::alloc::System;
 #[cfg(feature = "stdalloc")]
+const BASE: Base = std::alloc::System;
+#[cfg(all(feature = "mimalloc", not(feature = "stdalloc")))]
+type Base = mimalloc_rs::MiMalloc;
+#[cfg(all(feature = "mimalloc", not(feature = "stdalloc")))]
+const BASE: Base = mimalloc_rs::MiMalloc;
+#[cfg(not(any(feature = "stdalloc", feature = "mimalloc")))]
+type Base = snmalloc_rs::SnMalloc;
+#[cfg(not(any(feature = "stdalloc", feature = "mimalloc")))]
+const BASE: Base = snmalloc_rs::SnMalloc;
+
+// small allocations while processing an event are bumped from per-thread
+// chunks with the `arena` feature, see `tremor_common::arena`
+#[cfg(feature = "arena")]
+type Heap = tremor_common::arena::Arena<Base>;
+#[cfg(feature = "arena")]
+const HEAP: Heap = tremor_common::arena::Arena::new(BASE);
+#[cfg(not(feature = "arena"))]
+type Heap = Base;
+#[cfg(not(feature = "arena"))]
+const HEAP: Heap = BASE;
+
+#[cfg(feature = "bench")]
 #[global_allocator]
-static ALLOC: std::alloc::System = std::alloc::System;
-#[cfg(not(feature = "stdalloc"))]
+static ALLOC: Counting<Heap> = Counting(HEAP);
+#[cfg(not(feature = "bench"))]
 #[global_allocator]
-static ALLOC: snmalloc_rs::SnMalloc = snmalloc_rs::SnMalloc;
+static ALLOC: Heap = HEAP;
+
+#[cfg(feature = "bench")]
+static TRACKING: AtomicBool = AtomicBool::new(false);
+#[cfg(feature = "bench")]
+static ALLOCATIONS: AtomicU64 = AtomicU64::new(0);
+#[cfg(feature = "bench")]
+static ALLOCATED_BYTES: AtomicU64 = AtomicU64::new(0);
+
+/// Counts allocations once tracking is enabled, until then it only costs
+/// a relaxed load of a flag. It is only installed with the `bench` feature
+/// so other builds don't pay for it on every allocation.
+#[cfg(feature = "bench")]
+struct Counting<A>(A);
+
+#[cfg(feature = "bench")]
+impl<A> Counting<A> {
+    #[inline]
+    fn count(size: usize) {
+        if TRACKING.load(Ordering::Relaxed) {
+            ALLOCATIONS.fetch_add(1, Ordering::Relaxed);
+            ALLOCATED_BYTES.fetch_add(size as u64, Ordering::Relaxed);
+        }
+    }
+}
+
+#[cfg(feature = "bench")]
+unsafe impl<A: GlobalAlloc> GlobalAlloc for Counting<A> {
+    unsafe fn alloc(&self, layout: Layout) -> *mut u8 {
+        Self::count(layout.size());
+        self.0.alloc(layout)
+    }
+    unsafe fn dealloc(&self, ptr: *mut u8, layout: Layout) {
+        self.0.dealloc(ptr, layout);
+    }
+    unsafe fn alloc_zeroed(&self, layout: Layout) -> *mut u8 {
+        Self::count(layout.size());
+        self.0.alloc_zeroed(layout)
+    }
+    unsafe fn realloc(&self, ptr: *mut u8, layout: Layout, new_size: usize) -> *mut u8 {
+        Self::count(new_size);
+        self.0.realloc(ptr, layout, new_size)
+    }
+}
+
+/// Allocations since tracking was enabled
+#[derive(Serialize, Debug, Clone, Copy, Default)]
+pub(crate) struct Allocations {
+    pub(crate) count: u64,
+    pub(crate) bytes: u64,
+}
+
+/// Starts counting allocations from zero
+///
+/// # Errors
+///  * if tremor isn't built with the `bench` feature
+#[cfg(feature = "bench")]
+#[allow(clippy::unnecessary_wraps)]
+pub(crate) fn track_allocations() -> Result<()> {
+    ALLOCATIONS.store(0, Ordering::Relaxed);
+    ALLOCATED_BYTES.store(0, Ordering::Relaxed);
+    TRACKING.store(true, Ordering::Relaxed);
+    Ok(())
+}
+
+#[cfg(not(feature = "bench"))]
+pub(crate) fn track_allocations() -> Result<()> {
+    Err("Counting allocations requires tremor to be built with the `bench` feature".into())
+}
+
+/// Allocations since `track_allocations` was called, if they are counted
+#[cfg(feature = "bench")]
+pub(crate) fn allocations() -> Option<Allocations> {
+    TRACKING.load(Ordering::Relaxed).then(|| Allocations {
+        count: ALLOCATIONS.load(Ordering::Relaxed),
+        bytes: ALLOCATED_BYTES.load(Ordering::Relaxed),
+    })
+}
+
+#[cfg(not(feature = "bench"))]
+pub(crate) fn allocations() -> Option<Allocations> {
+    None
+}
 
 #[allow(clippy::same_functions_in_if_condition)]
 pub(crate) fn get_allocator_name() -> &'static str {
     if cfg!(feature = "stdalloc") {
         "stdalloc"
+    } else if cfg!(feature = "mimalloc") {
+        "mimalloc"
     } else {
         "snmalloc" // NOTE The default allocator SHOULD be set in the Cargo.toml default features
     }
 }
+
+/// Tuning of the allocator, only mimalloc can be tuned. Both mimalloc and
+/// snmalloc always allocate from per-thread heaps.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub(crate) struct Options {
+    /// back the heaps with (transparent) huge pages
+    pub(crate) huge_pages: bool,
+    /// number of 1GiB huge pages to reserve at startup, interleaved over the
+    /// NUMA nodes
+    pub(crate) reserve_huge_pages: usize,
+    /// number of NUMA nodes to allocate on, `None` detects them
+    pub(crate) numa_nodes: Option<usize>,
+    /// number of segments a thread heap commits on demand before it commits
+    /// new segments eagerly, `None` keeps the default of mimalloc
+    pub(crate) eager_commit_delay: Option<usize>,
+    /// milliseconds before memory freed in a thread heap is returned to the
+    /// OS, `None` keeps the default of mimalloc
+    pub(crate) reset_delay: Option<u64>,
+}
+
+/// Applies the allocator options. They are set through the runtime options
+/// of mimalloc, before the async runtime starts its threads so their heaps
+/// are created with them.
+///
+/// # Errors
+///  * if options are given but the allocator doesn't support them
+///  * if an option is out of range or the huge pages can't be reserved
+pub(crate) fn configure(options: Options) -> Result<()> {
+    if options == Options::default() {
+        return Ok(());
+    }
+    configure_allocator(options)
+}
+
+#[cfg(all(feature = "mimalloc", not(feature = "stdalloc")))]
+fn configure_allocator(options: Options) -> Result<()> {
+    use mimalloc_sys::{
+        mi_option_eager_commit_delay, mi_option_large_os_pages, mi_option_reset_delay,
+        mi_option_use_numa_nodes, mi_reserve_huge_os_pages_interleave,
+    };
+    if options.huge_pages {
+        set_option(mi_option_large_os_pages, 1, "--alloc-huge-pages")?;
+    }
+    if let Some(nodes) = options.numa_nodes {
+        set_option(mi_option_use_numa_nodes, nodes, "--alloc-numa-nodes")?;
+    }
+    if let Some(delay) = options.eager_commit_delay {
+        set_option(
+            mi_option_eager_commit_delay,
+            delay,
+            "--alloc-eager-commit-delay",
+        )?;
+    }
+    if let Some(delay) = options.reset_delay {
+        set_option(mi_option_reset_delay, delay, "--alloc-reset-delay")?;
+    }
+    if options.reserve_huge_pages > 0 {
+        // 0 nodes interleaves over all of them, a timeout of 0 waits until
+        // the pages are reserved
+        // ALLOW: mimalloc only reads the passed values
+        let err = unsafe {
+            mi_reserve_huge_os_pages_interleave(
+                options.reserve_huge_pages,
+                options.numa_nodes.unwrap_or(0),
+                0,
+            )
+        };
+        if err != 0 {
+            return Err(format!(
+                "Failed to reserve {} huge pages: {}",
+                options.reserve_huge_pages,
+                std::io::Error::from_raw_os_error(err)
+            )
+            .into());
+        }
+    }
+    Ok(())
+}
+
+/// Sets a runtime option of mimalloc, it applies to the memory allocated
+/// afterwards
+#[cfg(all(feature = "mimalloc", not(feature = "stdalloc")))]
+fn set_option<T>(option: mimalloc_sys::mi_option_t, value: T, flag: &str) -> Result<()>
+where
+    T: Copy + std::fmt::Display + TryInto<std::os::raw::c_long>,
+{
+    let v = value
+        .try_into()
+        .map_err(|_| format!("{} {} is out of range", flag, value))?;
+    // ALLOW: mimalloc options can be set at any time
+    unsafe { mimalloc_sys::mi_option_set(option, v) };
+    Ok(())
+}
+
+#[cfg(not(all(feature = "mimalloc", not(feature = "stdalloc"))))]
+fn configure_allocator(_options: Options) -> Result<()> {
+    Err(format!(
+        "Allocator options require tremor to be built with the `mimalloc` feature, it uses {}",
+        get_allocator_name()
+    )
+    .into())
+}
+
+/// Statistics of the global allocator for the API
+pub(crate) struct Stats;
+
+impl Allocator for Stats {
+    fn stats(&self) -> AllocatorStats {
+        let counted = allocations();
+        let mut stats = AllocatorStats {
+            allocator: get_allocator_name().to_string(),
+            allocations: counted.map(|a| a.count),
+            allocated_bytes: counted.map(|a| a.bytes),
+            ..AllocatorStats::default()
+        };
+        process_info(&mut stats);
+        stats
+    }
+}
+
+#[cfg(all(feature = "mimalloc", not(feature = "stdalloc")))]
+fn process_info(stats: &mut AllocatorStats) {
+    let mut elapsed = 0;
+    let mut user = 0;
+    let mut system = 0;
+    let mut current_rss = 0;
+    let mut peak_rss = 0;
+    let mut current_commit = 0;
+    let mut peak_commit = 0;
+    let mut page_faults = 0;
+    // ALLOW: mimalloc only writes to the passed pointers
+    unsafe {
+        mimalloc_sys::mi_process_info(
+            &mut elapsed,
+            &mut user,
+            &mut system,
+            &mut current_rss,
+            &mut peak_rss,
+            &mut current_commit,
+            &mut peak_commit,
+            &mut page_faults,
+        );
+    }
+    stats.current_rss = Some(current_rss as u64);
+    stats.peak_rss = Some(peak_rss as u64);
+    stats.current_commit = Some(current_commit as u64);
+    stats.peak_commit = Some(peak_commit as u64);
+    stats.page_faults = Some(page_faults as u64);
+}
+
+#[cfg(not(all(feature = "mimalloc", not(feature = "stdalloc"))))]
+fn process_info(_stats: &mut AllocatorStats) {}
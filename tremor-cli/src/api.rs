@@ -12,318 +12,337 @@
 // See the License for the specific language governing permissions and
 // limitations under the License.
 
-use crate::{
-    errors::{Error, Result},
-    util::load_trickle,
-};
-use clap::{self, ArgMatches};
-use halfbrown::HashMap;
-use http_types::{headers, StatusCode};
-use tremor_script::prelude::*;
-
-use crate::util::{accept, content_type, load, save_config, ser, TremorApp};
-
-pub(crate) async fn run_cmd(mut app: TremorApp, cmd: &ArgMatches) -> Result<()> {
-    if let Some(matches) = cmd.subcommand_matches("version") {
-        conductor_version_cmd(&app, matches).await
-    } else if let Some(matches) = cmd.subcommand_matches("binding") {
-        conductor_binding_cmd(&app, matches).await
-    } else if let Some(matches) = cmd.subcommand_matches("pipeline") {
-        conductor_pipeline_cmd(&app, matches).await
-    } else if let Some(matches) = cmd.subcommand_matches("onramp") {
-        conductor_onramp_cmd(&app, matches).await
-    } else if let Some(matches) = cmd.subcommand_matches("offramp") {
-        conductor_offramp_cmd(&app, matches).await
-    } else if let Some(matches) = cmd.subcommand_matches("target") {
-        conductor_target_cmd(&mut app, matches)
-    } else {
-        Err(Error::from("Invalid command"))
-    }
-}
-
-/////////////////////////
-// API resource models //
-/////////////////////////
-
-#[derive(Deserialize, Debug, Serialize)]
-struct Version {
-    version: String,
-}
-
-#[derive(Deserialize, Debug, Serialize)]
-struct Binding {
-    id: String,
-    description: String,
-    links: HashMap<String, Vec<String>>,
+use crate::cli::{Api, ApiCommand, ApiFormat, ArtefactKind, TargetCommand};
+use crate::errors::{Error, Result};
+use crate::util::{load, load_config, load_trickle, save_config, TargetConfig};
+use async_std::task;
+use http_types::auth::BasicAuth;
+use http_types::{headers, Method};
+use simd_json::prelude::*;
+use simd_json::OwnedValue;
+use std::convert::TryFrom;
+use std::io::{BufReader, Write};
+use std::sync::Arc;
+use surf::{Client, RequestBuilder};
+use tremor_common::file;
+use url::Url;
+
+const JSON: &str = "application/json";
+const YAML: &str = "application/yaml";
+const TRICKLE: &str = "application/vnd.trickle";
+
+/// Order in which artefacts are imported so that references resolve
+const ARTEFACT_KINDS: [ArtefactKind; 4] = [
+    ArtefactKind::Onramp,
+    ArtefactKind::Offramp,
+    ArtefactKind::Pipeline,
+    ArtefactKind::Binding,
+];
+
+/// All published artefacts of a tremor instance
+#[derive(Deserialize, Serialize, Debug, Default, PartialEq)]
+struct Bundle {
+    #[serde(default)]
+    onramp: Vec<OwnedValue>,
+    #[serde(default)]
+    offramp: Vec<OwnedValue>,
+    /// trickle source of the pipelines
+    #[serde(default)]
+    pipeline: Vec<String>,
+    #[serde(default)]
+    binding: Vec<OwnedValue>,
 }
 
-/////////////////////////
-// API host targetting //
-/////////////////////////
-
-fn conductor_target_cmd(app: &mut TremorApp, cmd: &ArgMatches) -> Result<()> {
-    if cmd.subcommand_matches("list").is_some() {
-        conductor_target_list_cmd(app);
-        Ok(())
-    } else if let Some(matches) = cmd.subcommand_matches("create") {
-        conductor_target_create_cmd(app, matches)
-    } else if let Some(matches) = cmd.subcommand_matches("delete") {
-        conductor_target_delete_cmd(app, matches)
-    } else {
-        println!("{}", simd_json::to_string(&app.config.instances)?);
-        Ok(())
+impl Bundle {
+    fn artefacts(&self, kind: ArtefactKind) -> Vec<String> {
+        let specs = match kind {
+            ArtefactKind::Pipeline => return self.pipeline.clone(),
+            ArtefactKind::Onramp => &self.onramp,
+            ArtefactKind::Offramp => &self.offramp,
+            ArtefactKind::Binding => &self.binding,
+        };
+        specs.iter().map(simd_json::OwnedValue::encode).collect()
     }
 }
 
-/////////////////////////////
-// API endpoint targetting //
-/////////////////////////////
-
-fn conductor_target_list_cmd(app: &TremorApp) {
-    println!(
-        "{:?}",
-        app.config
-            .instances
-            .keys()
-            .cloned()
-            .collect::<Vec<String>>()
-    );
-}
-
-fn conductor_target_create_cmd(app: &mut TremorApp, cmd: &ArgMatches) -> Result<()> {
-    let id = cmd.value_of("TARGET_ID").ok_or("TARGET_ID not provided")?;
-    let path_to_file = cmd.value_of("SOURCE").ok_or("SOURCE not provided")?;
-    let json = load(path_to_file)?;
-    let endpoints: Vec<String> = json
-        .as_array()
-        .ok_or_else(|| Error::from("Invalid Configuration"))?
-        .iter()
-        .filter_map(|v| v.as_str().map(String::from))
-        .collect();
-    app.config.instances.insert(id.to_string(), endpoints);
-    save_config(&app.config)
+impl ApiFormat {
+    fn mime(self) -> &'static str {
+        match self {
+            ApiFormat::Json => JSON,
+            ApiFormat::Yaml => YAML,
+        }
+    }
 }
 
-fn conductor_target_delete_cmd(app: &mut TremorApp, cmd: &ArgMatches) -> Result<()> {
-    let id = cmd.value_of("TARGET_ID").ok_or("TARGET_ID not provided")?;
-    app.config.instances.remove(&id.to_string());
-    save_config(&app.config)
+/// Client for the tremor REST API
+struct ApiClient {
+    format: ApiFormat,
+    base: Url,
+    authorization: Option<String>,
+    http: Client,
 }
 
-//////////////////
-// API Version  //
-//////////////////
-
-async fn conductor_version_cmd(app: &TremorApp, cmd: &ArgMatches) -> Result<()> {
-    let endpoint = app.endpoint("version")?;
-    let mut response = surf::get(endpoint).await?;
-    let version: Version = response.body_json().await?;
-    println!(
-        "{}",
-        match cmd.value_of("format") {
-            Some("yaml") => serde_yaml::to_string(&version)?,
-            _ => simd_json::to_string(&version)?,
+impl ApiClient {
+    fn new(api: &Api, config: &TargetConfig) -> Result<Self> {
+        let base = if let Some(endpoint) = &api.endpoint {
+            endpoint.clone()
+        } else {
+            config
+                .instances
+                .get(&api.target)
+                .and_then(|v| v.first())
+                .cloned()
+                .ok_or_else(|| {
+                    Error::from(format!(
+                        "No api endpoint for target `{}` in ~/.tremor/config.yaml",
+                        api.target
+                    ))
+                })?
+        };
+        let authorization = match (&api.token, &api.user) {
+            (Some(token), _) => Some(format!("Bearer {}", token)),
+            (None, Some(user)) => {
+                let (user, password) = user.split_once(':').unwrap_or((user, ""));
+                Some(BasicAuth::new(user, password).value().to_string())
+            }
+            (None, None) => None,
+        };
+        let mut config = surf::Config::new();
+        if let Some(cacert) = &api.cacert {
+            let mut tls = rustls::ClientConfig::new();
+            let mut pem = BufReader::new(crate::open_file(cacert, None)?);
+            tls.root_store
+                .add_pem_file(&mut pem)
+                .map_err(|_e| Error::from(format!("Invalid certificate in {}", cacert)))?;
+            config = config.set_tls_config(Some(Arc::new(tls)));
         }
-    );
-    Ok(())
-}
-
-//////////////////////////////
-// API Pipeline subcommands //
-//////////////////////////////
-
-async fn conductor_pipeline_cmd(app: &TremorApp, cmd: &ArgMatches) -> Result<()> {
-    if cmd.subcommand_matches("list").is_some() {
-        conductor_list_cmd(app, "pipeline").await
-    } else if let Some(matches) = cmd.subcommand_matches("fetch") {
-        conductor_get_cmd(app, matches, "pipeline").await
-    } else if let Some(matches) = cmd.subcommand_matches("delete") {
-        conductor_delete_cmd(app, matches, "pipeline").await
-    } else if let Some(matches) = cmd.subcommand_matches("create") {
-        conductor_create_cmd_trickle(app, matches, "pipeline").await
-    } else if let Some(matches) = cmd.subcommand_matches("instance") {
-        conductor_instance_cmd(app, matches, "pipeline").await
-    } else {
-        Err("Invalid command".into())
+        let http = Client::try_from(config)
+            .map_err(|e| Error::from(format!("Failed to create API client: {}", e)))?;
+        Ok(Self {
+            format: api.format,
+            base: Url::parse(&base)?,
+            authorization,
+            http,
+        })
     }
-}
 
-/////////////////////////////
-// API Binding subcommands //
-/////////////////////////////
-
-async fn conductor_binding_cmd(app: &TremorApp, cmd: &ArgMatches) -> Result<()> {
-    if cmd.subcommand_matches("list").is_some() {
-        conductor_list_cmd(app, "binding").await
-    } else if let Some(matches) = cmd.subcommand_matches("fetch") {
-        conductor_get_cmd(app, matches, "binding").await
-    } else if let Some(matches) = cmd.subcommand_matches("delete") {
-        conductor_delete_cmd(app, matches, "binding").await
-    } else if let Some(matches) = cmd.subcommand_matches("create") {
-        conductor_create_cmd(app, matches, "binding").await
-    } else if let Some(matches) = cmd.subcommand_matches("instance") {
-        conductor_instance_cmd(app, matches, "binding").await
-    } else if let Some(matches) = cmd.subcommand_matches("activate") {
-        conductor_binding_activate_cmd(app, matches).await
-    } else if let Some(matches) = cmd.subcommand_matches("deactivate") {
-        conductor_binding_deactivate_cmd(app, matches).await
-    } else {
-        Err("Invalid command".into())
+    fn request(&self, method: Method, path: &[&str], accept: &str) -> Result<RequestBuilder> {
+        let mut url = self.base.clone();
+        url.path_segments_mut()
+            .map_err(|_e| Error::from(format!("Bad api endpoint {}", self.base)))?
+            .pop_if_empty()
+            .extend(path);
+        let request = RequestBuilder::new(method, url).header(headers::ACCEPT, accept);
+        Ok(if let Some(authorization) = &self.authorization {
+            request.header(headers::AUTHORIZATION, authorization.as_str())
+        } else {
+            request
+        })
     }
-}
-
-async fn conductor_binding_activate_cmd(app: &TremorApp, cmd: &ArgMatches) -> Result<()> {
-    let a_id = cmd
-        .value_of("ARTEFACT_ID")
-        .ok_or("ARTEFACT_ID not provided")?;
-    let s_id = cmd
-        .value_of("INSTANCE_ID")
-        .ok_or("INSTANCE_ID not provided")?;
-    let endpoint = app.endpoint_id_instance("binding", a_id, s_id)?;
-    let path_to_file = cmd.value_of("SOURCE").ok_or("SOURCE not provided")?;
-    let json = load(path_to_file)?;
-    let ser = ser(app, &json)?;
-    let response = surf::post(&endpoint)
-        .header(headers::CONTENT_TYPE, content_type(app))
-        .header(headers::ACCEPT, accept(app))
-        .body(ser)
-        .await?;
-    handle_response(response).await
-}
-
-async fn conductor_binding_deactivate_cmd(app: &TremorApp, cmd: &ArgMatches) -> Result<()> {
-    let a_id = cmd
-        .value_of("ARTEFACT_ID")
-        .ok_or("ARTEFACT_ID not provided")?;
-    let s_id = cmd
-        .value_of("INSTANCE_ID")
-        .ok_or("INSTANCE_ID not provided")?;
-    let endpoint = app.endpoint_id_instance("binding", a_id, s_id)?;
 
-    let response = surf::delete(&endpoint).await?;
-    handle_response(response).await
-}
-
-//////////////////////////
-// API Offramp commands //
-//////////////////////////
-
-async fn conductor_offramp_cmd(app: &TremorApp, cmd: &ArgMatches) -> Result<()> {
-    if cmd.subcommand_matches("list").is_some() {
-        conductor_list_cmd(app, "offramp").await
-    } else if let Some(matches) = cmd.subcommand_matches("fetch") {
-        conductor_get_cmd(app, matches, "offramp").await
-    } else if let Some(matches) = cmd.subcommand_matches("delete") {
-        conductor_delete_cmd(app, matches, "offramp").await
-    } else if let Some(matches) = cmd.subcommand_matches("create") {
-        conductor_create_cmd(app, matches, "offramp").await
-    } else if let Some(matches) = cmd.subcommand_matches("instance") {
-        conductor_instance_cmd(app, matches, "offramp").await
-    } else {
-        Err("Invalid command".into())
+    /// Sends a request and returns the body of a successful response
+    async fn send(&self, request: RequestBuilder) -> Result<String> {
+        let mut response = self.http.send(request).await?;
+        let status = response.status();
+        let body = response.body_string().await?;
+        if status.is_success() {
+            Ok(body)
+        } else {
+            Err(format!(
+                "Unexpected response ( status: {} {} ) {}",
+                status,
+                status.canonical_reason(),
+                body
+            )
+            .into())
+        }
     }
-}
 
-/////////////////////////
-// API Onramp commands //
-/////////////////////////
-
-async fn conductor_onramp_cmd(app: &TremorApp, cmd: &ArgMatches) -> Result<()> {
-    if cmd.subcommand_matches("list").is_some() {
-        conductor_list_cmd(app, "onramp").await
-    } else if let Some(matches) = cmd.subcommand_matches("fetch") {
-        conductor_get_cmd(app, matches, "onramp").await
-    } else if let Some(matches) = cmd.subcommand_matches("delete") {
-        conductor_delete_cmd(app, matches, "onramp").await
-    } else if let Some(matches) = cmd.subcommand_matches("create") {
-        conductor_create_cmd(app, matches, "onramp").await
-    } else if let Some(matches) = cmd.subcommand_matches("instance") {
-        conductor_instance_cmd(app, matches, "onramp").await
-    } else {
-        Err("Invalid command".into())
+    /// Sends a request accepting the output format and prints the response
+    async fn print(&self, method: Method, path: &[&str]) -> Result<()> {
+        let request = self.request(method, path, self.format.mime())?;
+        println!("{}", self.send(request).await?);
+        Ok(())
     }
-}
 
-/////////////////
-// Shared code //
-/////////////////
-
-async fn conductor_get_cmd(app: &TremorApp, cmd: &ArgMatches, endpoint: &str) -> Result<()> {
-    let id = cmd
-        .value_of("ARTEFACT_ID")
-        .ok_or("ARTEFACT_ID not provided")?;
-    let endpoint = app.endpoint_id(endpoint, id)?;
-
-    let response = surf::get(&endpoint).await?;
-    handle_response(response).await
-}
+    async fn publish(&self, kind: ArtefactKind, artefact: String) -> Result<String> {
+        let content_type = if kind == ArtefactKind::Pipeline {
+            TRICKLE
+        } else {
+            JSON
+        };
+        let request = self
+            .request(Method::Post, &[&kind.to_string()], self.format.mime())?
+            .header(headers::CONTENT_TYPE, content_type)
+            .body(artefact);
+        self.send(request).await
+    }
 
-async fn conductor_list_cmd(app: &TremorApp, endpoint: &str) -> Result<()> {
-    let endpoint = app.endpoint(endpoint)?;
-    let response = surf::get(&endpoint).await?;
-    handle_response(response).await
-}
+    async fn fetch_json(&self, path: &[&str]) -> Result<OwnedValue> {
+        let request = self.request(Method::Get, path, JSON)?;
+        let mut body = self.send(request).await?.into_bytes();
+        Ok(simd_json::to_owned_value(&mut body)?)
+    }
 
-async fn conductor_create_cmd(app: &TremorApp, cmd: &ArgMatches, endpoint: &str) -> Result<()> {
-    let endpoint = app.endpoint(endpoint)?;
-    let path_to_file = cmd.value_of("SOURCE").ok_or("SOURCE not provided")?;
-    let json = load(path_to_file)?;
-    let ser = ser(app, &json)?;
-    let response = surf::post(&endpoint)
-        .header(http_types::headers::CONTENT_TYPE, content_type(app))
-        .header("accept", accept(app))
-        .body(ser)
-        .await;
-    handle_response(response?).await
-}
+    async fn export(&self) -> Result<Bundle> {
+        let mut bundle = Bundle::default();
+        for kind in ARTEFACT_KINDS {
+            let kind_name = kind.to_string();
+            let ids = self.fetch_json(&[kind_name.as_str()]).await?;
+            for id in ids
+                .as_array()
+                .into_iter()
+                .flatten()
+                .filter_map(|v| v.as_str())
+            {
+                let artefact = self.fetch_json(&[kind_name.as_str(), id]).await?;
+                match kind {
+                    ArtefactKind::Pipeline => {
+                        bundle
+                            .pipeline
+                            .push(artefact.as_str().unwrap_or_default().to_string());
+                    }
+                    ArtefactKind::Onramp => {
+                        bundle.onramp.extend(artefact.get("artefact").cloned());
+                    }
+                    ArtefactKind::Offramp => {
+                        bundle.offramp.extend(artefact.get("artefact").cloned());
+                    }
+                    ArtefactKind::Binding => {
+                        bundle.binding.extend(artefact.get("artefact").cloned());
+                    }
+                }
+            }
+        }
+        Ok(bundle)
+    }
 
-async fn conductor_create_cmd_trickle(
-    app: &TremorApp,
-    cmd: &ArgMatches,
-    endpoint: &str,
-) -> Result<()> {
-    let endpoint = app.endpoint(endpoint)?;
-    let path_to_file = cmd.value_of("SOURCE").ok_or("SOURCE not provided")?;
-    let ser = load_trickle(path_to_file)?;
-    let response = surf::post(&endpoint)
-        .header(http_types::headers::CONTENT_TYPE, "application/vnd.trickle")
-        .header("accept", accept(app))
-        .body(ser)
-        .await;
-    handle_response(response?).await
+    async fn run(&self, command: ApiCommand) -> Result<()> {
+        match command {
+            ApiCommand::Version => self.print(Method::Get, &["version"]).await,
+            ApiCommand::List { kind } => self.print(Method::Get, &[&kind.to_string()]).await,
+            ApiCommand::Get { kind, id } => {
+                self.print(Method::Get, &[&kind.to_string(), &id]).await
+            }
+            ApiCommand::Unpublish { kind, id } => {
+                self.print(Method::Delete, &[&kind.to_string(), &id]).await
+            }
+            ApiCommand::Instance { kind, id, instance } => {
+                self.print(Method::Get, &[&kind.to_string(), &id, &instance])
+                    .await
+            }
+            ApiCommand::Unlink { id, instance } => {
+                self.print(Method::Delete, &["binding", &id, &instance])
+                    .await
+            }
+            ApiCommand::Publish { kind, source } => {
+                let artefact = if kind == ArtefactKind::Pipeline {
+                    load_trickle(&source)?
+                } else {
+                    load(&source)?.encode()
+                };
+                println!("{}", self.publish(kind, artefact).await?);
+                Ok(())
+            }
+            ApiCommand::Link {
+                id,
+                instance,
+                source,
+            } => {
+                let request = self
+                    .request(
+                        Method::Post,
+                        &["binding", &id, &instance],
+                        self.format.mime(),
+                    )?
+                    .header(headers::CONTENT_TYPE, JSON)
+                    .body(load(&source)?.encode());
+                println!("{}", self.send(request).await?);
+                Ok(())
+            }
+            ApiCommand::Export { output } => {
+                let bundle = self.export().await?;
+                let raw = match self.format {
+                    ApiFormat::Json => simd_json::to_string_pretty(&bundle)?,
+                    ApiFormat::Yaml => serde_yaml::to_string(&bundle)?,
+                };
+                if let Some(output) = output {
+                    file::create(&output)?.write_all(raw.as_bytes())?;
+                } else {
+                    println!("{}", raw);
+                }
+                Ok(())
+            }
+            ApiCommand::Import { source } => {
+                let bundle: Bundle = simd_json::serde::from_owned_value(load(&source)?)?;
+                for kind in ARTEFACT_KINDS {
+                    for artefact in bundle.artefacts(kind) {
+                        self.publish(kind, artefact).await?;
+                    }
+                }
+                Ok(())
+            }
+            ApiCommand::Target { .. } => Err("Targets are not managed via the API".into()),
+        }
+    }
 }
 
-async fn conductor_delete_cmd(app: &TremorApp, cmd: &ArgMatches, endpoint: &str) -> Result<()> {
-    let id = cmd
-        .value_of("ARTEFACT_ID")
-        .ok_or("ARTEFACT_ID not provided")?;
-    let endpoint = app.endpoint_id(endpoint, id)?;
-    let response = surf::delete(&endpoint).await?;
-    handle_response(response).await
+fn target_cmd(command: TargetCommand, mut config: TargetConfig) -> Result<()> {
+    match command {
+        TargetCommand::List => {
+            let mut targets: Vec<_> = config.instances.keys().collect();
+            targets.sort();
+            println!("{}", simd_json::to_string(&targets)?);
+            Ok(())
+        }
+        TargetCommand::Create { target_id, source } => {
+            let endpoints: Vec<String> = load(&source)?
+                .as_array()
+                .ok_or_else(|| Error::from("Invalid Configuration"))?
+                .iter()
+                .filter_map(|v| v.as_str().map(String::from))
+                .collect();
+            config.instances.insert(target_id, endpoints);
+            save_config(&config)
+        }
+        TargetCommand::Delete { target_id } => {
+            config.instances.remove(&target_id);
+            save_config(&config)
+        }
+    }
 }
 
-async fn conductor_instance_cmd(app: &TremorApp, cmd: &ArgMatches, endpoint: &str) -> Result<()> {
-    let a_id = cmd
-        .value_of("ARTEFACT_ID")
-        .ok_or("ARTEFACT_ID not provided")?;
-    let s_id = cmd
-        .value_of("INSTANCE_ID")
-        .ok_or("INSTANCE_ID not provided")?;
-    let endpoint = app.endpoint_id_instance(endpoint, a_id, s_id)?;
-    let response = surf::get(&endpoint).await?;
-    handle_response(response).await
+impl Api {
+    pub(crate) fn run(self) -> Result<()> {
+        let config = load_config()?;
+        if let ApiCommand::Target { command } = self.command {
+            return target_cmd(command, config);
+        }
+        let client = ApiClient::new(&self, &config)?;
+        task::block_on(client.run(self.command))
+    }
 }
 
-async fn handle_response(mut response: surf::Response) -> Result<()> {
-    let status = response.status();
-    match status {
-        StatusCode::Ok | StatusCode::Created => println!("{}", response.body_string().await?),
-        StatusCode::NotFound => eprintln!("Not found"),
-        StatusCode::Conflict => eprintln!("Conflict"),
-        _ => eprintln!(
-            "Unexpected response ( status: {} )",
-            status.canonical_reason()
-        ),
-    };
-    Ok(())
+#[cfg(test)]
+mod test {
+    use super::*;
+    use simd_json::json;
+
+    #[test]
+    fn bundle() -> Result<()> {
+        let bundle: Bundle = simd_json::serde::from_owned_value(json!({
+            "onramp": [{"id": "in", "type": "stdin"}],
+            "pipeline": ["select event from in into out"]
+        }))?;
+        assert_eq!(
+            bundle.artefacts(ArtefactKind::Onramp),
+            vec![r#"{"id":"in","type":"stdin"}"#.to_string()]
+        );
+        assert_eq!(
+            bundle.artefacts(ArtefactKind::Pipeline),
+            vec!["select event from in into out".to_string()]
+        );
+        assert!(bundle.artefacts(ArtefactKind::Binding).is_empty());
+        Ok(())
+    }
 }
@@ -0,0 +1,254 @@
+// Copyright 2020-2021, The Tremor Team
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+use crate::alloc::{self, Allocations};
+use crate::errors::{Error, Result};
+use crate::util::slurp_string;
+use crate::{cli::Bench, env};
+use hdrhistogram::Histogram;
+use std::collections::HashMap;
+use std::io::{BufRead, BufReader, Write};
+use std::time::Duration;
+use tremor_common::ids::OperatorIdGen;
+use tremor_common::{file, time::nanotime};
+use tremor_pipeline::{Event, EventId};
+use tremor_script::highlighter::Term as TermHighlighter;
+use tremor_script::prelude::*;
+use tremor_script::query::Query;
+use tremor_script::script::Script;
+
+/// Number of generated events when neither an input nor a count is given
+const DEFAULT_EVENTS: u64 = 100_000;
+
+/// Latency distribution in nanoseconds
+#[derive(Serialize, Debug, Default, PartialEq)]
+struct Latency {
+    count: u64,
+    min: u64,
+    max: u64,
+    mean: f64,
+    p50: u64,
+    p95: u64,
+    p99: u64,
+    p999: u64,
+}
+
+impl Latency {
+    fn from_histogram(h: &Histogram<u64>) -> Self {
+        Self {
+            count: h.len(),
+            min: h.min(),
+            max: h.max(),
+            mean: h.mean(),
+            p50: h.value_at_quantile(0.5),
+            p95: h.value_at_quantile(0.95),
+            p99: h.value_at_quantile(0.99),
+            p999: h.value_at_quantile(0.999),
+        }
+    }
+
+    /// Reads the fields of a pipeline `latency` metric
+    fn from_metric(metric: &Value) -> Option<(String, Self)> {
+        let node = metric.get("tags")?.get_str("node")?.to_string();
+        let fields = metric.get("fields")?;
+        let field = |name: &str| fields.get_u64(name).unwrap_or_default();
+        Some((
+            node,
+            Self {
+                count: field("count"),
+                min: field("min"),
+                max: field("max"),
+                mean: fields.get_f64("mean").unwrap_or_default(),
+                p50: field("p50"),
+                p95: field("p95"),
+                p99: field("p99"),
+                p999: field("p999"),
+            },
+        ))
+    }
+}
+
+#[derive(Serialize, Debug)]
+struct Report {
+    pipeline: String,
+    events: u64,
+    /// events emitted per output port
+    outputs: HashMap<String, u64>,
+    duration_ns: u64,
+    /// events per second
+    throughput: f64,
+    /// latency of an event through the whole pipeline
+    latency: Latency,
+    /// processing latency per operator
+    nodes: HashMap<String, Latency>,
+    /// allocations during the run, including creating the events, they are
+    /// only counted with the `bench` feature
+    #[serde(skip_serializing_if = "Option::is_none")]
+    allocations: Option<Allocations>,
+}
+
+/// Reads one JSON event per line
+fn load_events(path: &str) -> Result<Vec<Value<'static>>> {
+    let input = BufReader::new(crate::open_file(path, None)?);
+    let mut events = Vec::new();
+    for line in input.lines() {
+        let line = line?;
+        if line.trim().is_empty() {
+            continue;
+        }
+        let value = simd_json::to_owned_value(&mut line.into_bytes())?;
+        events.push(Value::from(value));
+    }
+    Ok(events)
+}
+
+impl Bench {
+    #[allow(clippy::cast_precision_loss)]
+    pub(crate) fn run(&self) -> Result<()> {
+        let raw = slurp_string(&self.pipeline)?;
+        let env = env::setup()?;
+        let query = match Query::parse(
+            &env.module_path,
+            &self.pipeline,
+            &raw,
+            vec![],
+            &env.fun,
+            &env.aggr,
+        ) {
+            Ok(query) => query,
+            Err(e) => {
+                let mut h = TermHighlighter::stderr();
+                if let Err(e) = Script::format_error_from_script(&raw, &mut h, &e) {
+                    eprintln!("Error: {}", e);
+                };
+                return Err(Error::from(format!("Invalid pipeline {}", self.pipeline)));
+            }
+        };
+        let mut pipeline =
+            tremor_pipeline::query::Query(query).to_pipe(&mut OperatorIdGen::new())?;
+        pipeline.record_latencies();
+
+        let inputs = if let Some(input) = &self.input {
+            let inputs = load_events(input)?;
+            if inputs.is_empty() {
+                return Err(Error::from(format!("No events in {}", input)));
+            }
+            Some(inputs)
+        } else {
+            None
+        };
+        let total = self.events.unwrap_or_else(|| {
+            inputs
+                .as_ref()
+                .map_or(DEFAULT_EVENTS, |inputs| inputs.len() as u64)
+        });
+        let interval = self.rate.filter(|r| *r > 0).map(|r| 1_000_000_000 / r);
+
+        let mut latency = Histogram::<u64>::new(2)
+            .map_err(|e| Error::from(format!("Failed to create histogram: {}", e)))?;
+        let mut outputs: HashMap<String, u64> = HashMap::new();
+        let mut returns = vec![];
+        let mut recorded = inputs.as_ref().map(|inputs| inputs.iter().cycle());
+
+        if let Err(e) = alloc::track_allocations() {
+            eprintln!("{}, allocations are not reported", e);
+        }
+        let start = nanotime();
+        for seq in 0..total {
+            if let Some(interval) = interval {
+                let due = start + seq * interval;
+                let now = nanotime();
+                if due > now {
+                    std::thread::sleep(Duration::from_nanos(due - now));
+                }
+            }
+            let data = recorded
+                .as_mut()
+                .and_then(Iterator::next)
+                .cloned()
+                .unwrap_or_else(|| Value::from(simd_json::json!({ "seq": seq })));
+            let ingest_ns = nanotime();
+            let event = Event {
+                id: EventId::new(0, 0, seq),
+                data: data.into(),
+                ingest_ns,
+                ..Event::default()
+            };
+            pipeline.enqueue("in", event, &mut returns)?;
+            latency.saturating_record(nanotime().saturating_sub(ingest_ns));
+            for (port, _) in returns.drain(..) {
+                *outputs.entry(port.to_string()).or_default() += 1;
+            }
+        }
+        let duration_ns = nanotime() - start;
+        let allocations = alloc::allocations();
+
+        let report = Report {
+            pipeline: self.pipeline.clone(),
+            events: total,
+            outputs,
+            duration_ns,
+            throughput: total as f64 / (duration_ns.max(1) as f64 / 1_000_000_000.0),
+            latency: Latency::from_histogram(&latency),
+            nodes: pipeline
+                .latencies(nanotime())
+                .iter()
+                .filter_map(Latency::from_metric)
+                .collect(),
+            allocations,
+        };
+        let report = simd_json::to_string_pretty(&report)?;
+        if let Some(output) = &self.output {
+            file::create(output)?.write_all(report.as_bytes())?;
+        } else {
+            println!("{}", report);
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use simd_json::json;
+
+    #[test]
+    fn latency_from_metric() {
+        let metric = Value::from(json!({
+            "measurement": "latency",
+            "tags": {"pipeline": "main", "node": "select"},
+            "fields": {
+                "count": 2, "min": 1, "max": 3, "mean": 2.0,
+                "p50": 1, "p95": 3, "p99": 3, "p999": 3
+            },
+            "timestamp": 0
+        }));
+        let (node, latency) = Latency::from_metric(&metric).expect("valid metric");
+        assert_eq!(node, "select");
+        assert_eq!(
+            latency,
+            Latency {
+                count: 2,
+                min: 1,
+                max: 3,
+                mean: 2.0,
+                p50: 1,
+                p95: 3,
+                p99: 3,
+                p999: 3
+            }
+        );
+        assert!(Latency::from_metric(&Value::from(json!({"fields": {}}))).is_none());
+    }
+}
@@ -23,6 +23,19 @@ pub(crate) struct Cli {
     /// Instance identifier
     #[clap(short, long, default_value = "tremor")]
     pub(crate) instance: String,
+    /// WebAssembly plugins providing functions to tremor-script, named after
+    /// their file
+    #[cfg(feature = "wasm")]
+    #[clap(long)]
+    pub(crate) wasm: Vec<String>,
+    /// Maximum memory of a WebAssembly plugin in bytes
+    #[cfg(feature = "wasm")]
+    #[clap(long, default_value = "67108864")]
+    pub(crate) wasm_max_memory: usize,
+    /// Fuel of a call of a WebAssembly plugin, roughly the number of instructions
+    #[cfg(feature = "wasm")]
+    #[clap(long, default_value = "100000000")]
+    pub(crate) wasm_max_fuel: u64,
     #[clap(subcommand)]
     pub(crate) command: Command,
 }
@@ -48,8 +61,24 @@ pub(crate) enum Command {
     Run(Run),
     /// Generates documention from tremor script files
     Doc(Doc),
+    /// Benchmarks a trickle pipeline and reports throughput, latencies and,
+    /// with the `bench` feature, allocations as JSON
+    Bench(Bench),
     /// Tremor API client
     Api(Api),
+    /// Interactive shell evaluating tremor-script against an event, its
+    /// metadata and state
+    Repl(Repl),
+    /// Language server for tremor-script and trickle files, speaking the
+    /// language server protocol over stdin and stdout
+    Lsp,
+    /// Formats tremor-script and trickle files in place
+    Fmt(Fmt),
+    /// Lints tremor-script and trickle files
+    Lint(Lint),
+    /// Attaches to a pipeline instance of a server run with
+    /// `--debug-endpoint` to step through it
+    Debug(Debugger),
 }
 
 /// Shell type
@@ -89,6 +118,17 @@ pub(crate) struct Test {
     /// Only print failed tests
     #[clap(short, long)]
     pub(crate) quiet: bool,
+    /// Write an lcov report of the tremor-script covered by unit tests to the
+    /// specified path
+    #[clap(long)]
+    pub(crate) coverage: Option<String>,
+    /// Fail if less than the given percentage of tremor-script lines is
+    /// covered by unit tests
+    #[clap(long)]
+    pub(crate) coverage_threshold: Option<f64>,
+    /// Rewrite the snapshots of snapshot tests with the current outputs
+    #[clap(long)]
+    pub(crate) update: bool,
 }
 
 /// Shell type
@@ -100,8 +140,12 @@ pub(crate) enum TestMode {
     Bench,
     /// Run command tests
     Command,
+    /// Run property based tests of codecs and pre/postprocessor chains
+    Fuzz,
     /// Run integration tests
     Integration,
+    /// Run integration tests as deterministic simulations
+    Simulation,
     /// Run tremor script unit tests
     Unit,
 }
@@ -111,7 +155,9 @@ impl ToString for TestMode {
         match self {
             TestMode::Bench => "bench".to_string(),
             TestMode::Integration => "integration".to_string(),
+            TestMode::Simulation => "simulation".to_string(),
             TestMode::Command => "command".to_string(),
+            TestMode::Fuzz => "fuzz".to_string(),
             TestMode::Unit => "unit".to_string(),
             TestMode::All => "all".to_string(),
         }
@@ -132,6 +178,114 @@ pub(crate) struct Doc {
     pub(crate) dir: String,
     #[clap(default_value = "docs")]
     pub(crate) outdir: String,
+    /// Format of the generated documents
+    #[clap(short, long, arg_enum, default_value_t)]
+    pub(crate) format: DocFormat,
+}
+
+/// Format of generated documentation
+#[derive(ArgEnum, Clone, Copy, Debug, PartialEq, Eq)]
+pub(crate) enum DocFormat {
+    /// Markdown documents
+    Markdown,
+    /// Standalone HTML pages
+    Html,
+}
+
+impl ToString for DocFormat {
+    fn to_string(&self) -> String {
+        match self {
+            DocFormat::Markdown => "markdown".to_string(),
+            DocFormat::Html => "html".to_string(),
+        }
+    }
+}
+impl Default for DocFormat {
+    fn default() -> Self {
+        Self::Markdown
+    }
+}
+
+#[derive(Parser, Debug)]
+pub(crate) struct Bench {
+    /// trickle file of the pipeline to benchmark
+    pub(crate) pipeline: String,
+    /// File with one JSON event per line, events are generated if absent
+    #[clap(short, long)]
+    pub(crate) input: Option<String>,
+    /// Target rate in events per second, as fast as possible if absent
+    #[clap(short, long)]
+    pub(crate) rate: Option<u64>,
+    /// Number of events to send, defaults to the number of input events or 100000
+    #[clap(short = 'n', long)]
+    pub(crate) events: Option<u64>,
+    /// File to write the report to instead of stdout
+    #[clap(short, long)]
+    pub(crate) output: Option<String>,
+}
+
+#[derive(Parser, Debug)]
+pub(crate) struct Repl {
+    /// File with the initial event, e.g. as JSON
+    #[clap(short, long)]
+    pub(crate) event: Option<String>,
+    /// Script to step through statement by statement
+    #[clap(short, long)]
+    pub(crate) script: Option<String>,
+}
+
+#[derive(Parser, Debug)]
+pub(crate) struct Fmt {
+    /// Lists the files that are not formatted and fails if there are any,
+    /// without changing them
+    #[clap(long)]
+    pub(crate) check: bool,
+    /// Files or directories to format
+    #[clap(required = true)]
+    pub(crate) paths: Vec<String>,
+}
+
+#[derive(Parser, Debug)]
+pub(crate) struct Lint {
+    /// Yaml or json file setting the level of rules and the deprecated functions
+    #[clap(short, long)]
+    pub(crate) config: Option<String>,
+    /// Format of the findings
+    #[clap(short, long, arg_enum, default_value_t)]
+    pub(crate) format: LintFormat,
+    /// Files or directories to lint
+    #[clap(required = true)]
+    pub(crate) paths: Vec<String>,
+}
+
+/// Format of lint findings
+#[derive(ArgEnum, Clone, Copy, Debug, PartialEq, Eq)]
+pub(crate) enum LintFormat {
+    /// One finding per line
+    Text,
+    /// A JSON array of the findings
+    Json,
+}
+
+impl ToString for LintFormat {
+    fn to_string(&self) -> String {
+        match self {
+            LintFormat::Text => "text".to_string(),
+            LintFormat::Json => "json".to_string(),
+        }
+    }
+}
+impl Default for LintFormat {
+    fn default() -> Self {
+        Self::Text
+    }
+}
+
+#[derive(Parser, Debug)]
+pub(crate) struct Debugger {
+    /// Websocket URL of the pipeline instance, e.g.
+    /// `ws://localhost:9899/pipeline/main/01`
+    pub(crate) url: String,
 }
 
 #[derive(Parser, Debug)]
@@ -165,6 +319,13 @@ pub(crate) struct Run {
     /// Specifies the port that is printed to the output
     #[clap(short, long)]
     pub(crate) port: Option<String>,
+    /// Nanoseconds to wait between two events
+    #[clap(long)]
+    pub(crate) interval: Option<u64>,
+    /// Treats the input as a capture of `{"ingest_ns": ..., "data": ...}` records
+    /// and replays the data with the captured timing
+    #[clap(long)]
+    pub(crate) replay: bool,
 }
 
 #[derive(Parser, Debug)]
@@ -187,6 +348,9 @@ pub(crate) struct DbgAst {
 
 #[derive(Parser, Debug)]
 pub(crate) struct DbgDot {
+    /// report output ports connected to more than one node on stderr
+    #[clap(short, long)]
+    pub(crate) fan_out: bool,
     /// tremor/json/trickle/troy File
     pub(crate) script: String,
 }
@@ -231,8 +395,44 @@ pub(crate) enum ServerCommand {
 
 #[derive(Parser, Debug)]
 pub(crate) struct ServerRun {
-    /// Paths to files containing pipelines, onramps, offramps to provision
+    /// Paths to files containing pipelines, as trickle or SQL queries,
+    /// onramps, offramps to provision, `http(s)://` and `s3://` URLs are
+    /// fetched first and can pin their content with a `#sha256=<hex>` suffix
     pub(crate) artefacts: Vec<String>,
+    /// Bearer token used to fetch `http(s)://` artefacts
+    #[clap(long)]
+    pub(crate) artefact_token: Option<String>,
+    /// Number of retries when fetching remote artefacts fails
+    #[clap(long, default_value = "3")]
+    pub(crate) artefact_retries: u32,
+    /// Replace `${...}` references in artefacts, `$${` is a literal `${`
+    #[clap(long)]
+    pub(crate) interpolate: bool,
+    /// Environment variables artefacts may reference as `${NAME}`, a trailing
+    /// `*` allows all variables with that prefix
+    #[clap(long)]
+    pub(crate) allow_env: Vec<String>,
+    /// Directories with files artefacts may reference as `${file:/path}`
+    #[clap(long)]
+    pub(crate) allow_file: Vec<String>,
+    /// Secret store to resolve `!secret <name>` tags in yaml artefacts with
+    #[clap(long, arg_enum)]
+    pub(crate) secrets: Option<SecretStoreKind>,
+    /// Directory kubernetes secrets are mounted at
+    #[clap(long, default_value = "/var/run/secrets/tremor")]
+    pub(crate) secrets_dir: String,
+    /// Default proxy for outbound connections of the rest, ws and elastic
+    /// offramps, `http://[user:password@]host[:port]` or `socks5://...`
+    #[clap(long)]
+    pub(crate) proxy: Option<String>,
+    /// Hosts connected to directly instead of through the default proxy,
+    /// `.example.com` matches all subdomains
+    #[clap(long)]
+    pub(crate) no_proxy: Vec<String>,
+    /// Directories whose subdirectories hold onramp and offramp plugins with
+    /// a `plugin.yaml` manifest
+    #[clap(long)]
+    pub(crate) plugin_dir: Vec<String>,
     /// Captures process id if set and stores in a file
     #[clap(short, long)]
     pub(crate) pid: Option<String>,
@@ -242,229 +442,275 @@ pub(crate) struct ServerRun {
     /// The `host:port` to listen for the API
     #[clap(short, long, default_value = "0.0.0.0:9898")]
     pub(crate) api_host: String,
+    /// The `host:port` to accept pipeline debugging sessions on, see `tremor debug`
+    #[clap(long)]
+    pub(crate) debug_endpoint: Option<String>,
     /// Configuration for Log4RS
     #[clap(short, long)]
     pub(crate) logger_config: Option<String>,
+    /// Bearer token granting access to the whole API, without any the API is
+    /// open except for namespaces with their own tokens
+    #[clap(long)]
+    pub(crate) api_token: Vec<String>,
+    /// File to append the audit log of all modifying API operations to
+    #[clap(long)]
+    pub(crate) audit_log: Option<String>,
+    /// Id of the node in its cluster, enables replicating all API changes
+    /// across the cluster
+    #[clap(long)]
+    pub(crate) cluster_id: Option<u64>,
+    /// `<id>=<url>` of the API of every node in the cluster including this
+    /// one, e.g. `1=http://10.0.0.1:9898`
+    #[clap(long)]
+    pub(crate) cluster_node: Vec<String>,
+    /// Directory the node persists the replicated API changes in
+    #[clap(long, default_value = "cluster")]
+    pub(crate) cluster_dir: String,
+    /// URL of a controller to periodically pull the artefacts to run from,
+    /// authenticated with the `--artefact-token`
+    #[clap(long)]
+    pub(crate) controlled_by: Option<String>,
+    /// Seconds between two pulls from the controller
+    #[clap(long, default_value = "30")]
+    pub(crate) control_interval: u64,
+    /// Format of log lines, does not apply if a log4rs configuration is used
+    #[clap(long, arg_enum, default_value_t)]
+    pub(crate) log_format: LogFormat,
     /// function tail-recursion stack depth limit
     #[clap(short, long, default_value = "1024")]
     pub(crate) recursion_limit: u32,
+    /// Count allocations to report them with the allocator statistics of the
+    /// API, this costs two atomic additions per allocation and requires the
+    /// `bench` feature
+    #[clap(long)]
+    pub(crate) alloc_stats: bool,
+    /// Back the heaps of the allocator with (transparent) huge pages,
+    /// requires the `mimalloc` allocator
+    #[clap(long)]
+    pub(crate) alloc_huge_pages: bool,
+    /// Number of 1GiB huge pages the allocator reserves at startup,
+    /// interleaved over the NUMA nodes, requires the `mimalloc` allocator
+    #[clap(long, default_value = "0")]
+    pub(crate) alloc_reserve_huge_pages: usize,
+    /// Number of NUMA nodes the allocator uses, detected if not given,
+    /// requires the `mimalloc` allocator
+    #[clap(long)]
+    pub(crate) alloc_numa_nodes: Option<usize>,
+    /// Number of segments each thread heap of the allocator commits on
+    /// demand before it commits new segments eagerly, requires the
+    /// `mimalloc` allocator
+    #[clap(long)]
+    pub(crate) alloc_eager_commit_delay: Option<usize>,
+    /// Milliseconds before memory freed in a thread heap of the allocator is
+    /// returned to the OS, requires the `mimalloc` allocator
+    #[clap(long)]
+    pub(crate) alloc_reset_delay: Option<u64>,
+}
+
+/// Secret store
+#[derive(ArgEnum, Clone, Copy, Debug, PartialEq, Eq)]
+pub(crate) enum SecretStoreKind {
+    /// `HashiCorp` Vault, configured with the `VAULT_*` environment variables
+    Vault,
+    /// Secrets mounted as files by kubernetes
+    Kubernetes,
+}
+
+impl ToString for SecretStoreKind {
+    fn to_string(&self) -> String {
+        match self {
+            SecretStoreKind::Vault => "vault".to_string(),
+            SecretStoreKind::Kubernetes => "kubernetes".to_string(),
+        }
+    }
+}
+
+/// Log output format
+#[derive(ArgEnum, Clone, Copy, Debug, PartialEq, Eq)]
+pub(crate) enum LogFormat {
+    /// Human readable log lines
+    Plain,
+    /// One JSON object per log line
+    Json,
+}
+
+impl ToString for LogFormat {
+    fn to_string(&self) -> String {
+        match self {
+            LogFormat::Plain => "plain".to_string(),
+            LogFormat::Json => "json".to_string(),
+        }
+    }
+}
+impl Default for LogFormat {
+    fn default() -> Self {
+        Self::Plain
+    }
 }
 
-// TODO: since the API will change this isn't translated yet
 #[derive(Parser, Debug)]
 pub(crate) struct Api {
-    /*
-- api:
-      args:
-        - FORMAT:
-            short: f
-            about: Sets the output format
-            possible_values: [json, yaml]
-            takes_value: true
-        - CONFIG:
-            short: c
-            long: config
-            value_name: FILE
-            about: Sets a custom config file
-            takes_value: true
-      subcommands:
-        - version:
-            about: Get tremor version
-        - target:
-            about: Target one or many tremor server instances
-            subcommands:
-              - list:
-                  about: List registered targets
-              - create:
-                  about: Create a new API target
-                  args:
-                    - TARGET_ID:
-                        about: The unique target id for the targetted tremor servers
-                        required: true
-                        takes_value: true
-                    - SOURCE:
-                        about: JSON or YAML file request body
-                        required: true
-                        takes_value: true
-              - delete:
-                  about: Delete an existing API target
-                  args:
-                    - TARGET_ID:
-                        about: The unique target id for the targetted tremor servers
-                        required: true
-                        takes_value: true
-        - binding:
-            about: Query/update binding specification repository
-            subcommands:
-              - list:
-                  about: List registered binding specifications
-              - fetch:
-                  about: Fetch a binding by artefact id
-                  args:
-                    - ARTEFACT_ID:
-                        about: The unique artefact id for the binding specification
-                        required: true
-                        takes_value: true
-              - delete:
-                  about: Delete a binding by artefact id
-                  args:
-                    - ARTEFACT_ID:
-                        about: The unique artefact id for the binding specification
-                        required: true
-                        takes_value: true
-              - create:
-                  about: Create and register a binding specification
-                  args:
-                    - SOURCE:
-                        about: JSON or YAML file request body
-                        takes_value: true
-                        required: true
-              - instance:
-                  about: Fetch an binding instance by artefact id and instance id
-                  args:
-                    - ARTEFACT_ID:
-                        about: The unique artefact id for the binding specification
-                        required: true
-                        takes_value: true
-                    - INSTANCE_ID:
-                        about: The unique instance id for the binding specification
-                        required: true
-                        takes_value: true
-              - activate:
-                  about: Activate a binding by artefact id and servant instance id
-                  args:
-                    - ARTEFACT_ID:
-                        about: The unique artefact id for the binding specification
-                        required: true
-                        takes_value: true
-                    - INSTANCE_ID:
-                        about: The unique instance id for the binding specification
-                        required: true
-                        takes_value: true
-                    - SOURCE:
-                        about: JSON -r YAML file request body
-                        required: true
-                        takes_value: true
-              - deactivate:
-                  about: Activate a binding by artefact id and servant instance id
-                  args:
-                    - ARTEFACT_ID:
-                        about: The unique artefact id for the binding specification
-                        required: true
-                        takes_value: true
-                    - INSTANCE_ID:
-                        about: The unique instance id for the binding specification
-                        required: true
-                        takes_value: true
-        - pipeline:
-            about: Query/update pipeline specification repository
-            subcommands:
-              - list:
-                  about: List registered pipeline specifications
-              - fetch:
-                  about: Fetch a pipeline by artefact id
-                  args:
-                    - ARTEFACT_ID:
-                        about: The unique artefact id for the pipeline specification
-                        required: true
-                        takes_value: true
-              - delete:
-                  about: Delete a pipeline by artefact id
-                  args:
-                    - ARTEFACT_ID:
-                        about: The unique artefact id for the pipeline specification
-                        required: true
-                        takes_value: true
-              - create:
-                  about: Create and register a pipeline specification
-                  args:
-                    - SOURCE:
-                        about: JSON or YAML file request body
-                        required: false
-                        takes_value: true
-              - instance:
-                  about: Fetch an pipeline instance by artefact id and instance id
-                  args:
-                    - ARTEFACT_ID:
-                        about: The unique artefact id for the pipeline specification
-                        required: true
-                        takes_value: true
-                    - INSTANCE_ID:
-                        about: The unique instance id for the pipeline specification
-                        required: true
-                        takes_value: true
-        - onramp:
-            about: Query/update onramp specification repository
-            subcommands:
-              - list:
-                  about: List registered onramp specifications
-              - fetch:
-                  about: Fetch an onramp by artefact id
-                  args:
-                    - ARTEFACT_ID:
-                        about: The unique artefact id for the onramp specification
-                        required: true
-                        takes_value: true
-              - delete:
-                  about: Delete an onramp by artefact id
-                  args:
-                    - ARTEFACT_ID:
-                        about: The unique artefact id for the onramp specification
-                        required: true
-                        takes_value: true
-              - create:
-                  about: Create and register an onramp specification
-                  args:
-                    - SOURCE:
-                        about: JSON or YAML file request body
-                        required: false
-                        takes_value: true
-              - instance:
-                  about: Fetch an onramp instance by artefact id and instance id
-                  args:
-                    - ARTEFACT_ID:
-                        about: The unique artefact id for the onramp specification
-                        required: true
-                        takes_value: true
-                    - INSTANCE_ID:
-                        about: The unique instance id for the onramp specification
-                        required: true
-                        takes_value: true
-        - offramp:
-            about: Query/update offramp specification repository
-            subcommands:
-              - list:
-                  about: List registered offramp specifications
-              - fetch:
-                  about: Fetch an offramp by artefact id
-                  args:
-                    - ARTEFACT_ID:
-                        about: The unique artefact id for the offramp specification
-                        required: true
-                        takes_value: true
-              - delete:
-                  about: Delete an offramp by artefact id
-                  args:
-                    - ARTEFACT_ID:
-                        about: The unique artefact id for the offramp specification
-                        required: true
-                        takes_value: true
-              - create:
-                  about: Create and register an offramp specification
-                  args:
-                    - SOURCE:
-                        about: JSON or YAML file request body
-                        required: false
-                        takes_value: true
-              - instance:
-                  about: Fetch an offramp instance by artefact id and instance id
-                  args:
-                    - ARTEFACT_ID:
-                        about: The unique artefact id for the offramp specification
-                        required: true
-                        takes_value: true
-                    - INSTANCE_ID:
-                        about: The unique instance id for the offramp specification
-                        required: true
-                        takes_value: true
-    */}
+    /// Sets the output format
+    #[clap(short, long, arg_enum, default_value_t)]
+    pub(crate) format: ApiFormat,
+    /// Target in `~/.tremor/config.yaml` to talk to
+    #[clap(short, long, default_value = "default")]
+    pub(crate) target: String,
+    /// API endpoint to talk to instead of a target, e.g. `https://tremor:9898`
+    #[clap(short, long)]
+    pub(crate) endpoint: Option<String>,
+    /// Bearer token to authenticate with
+    #[clap(long, conflicts_with = "user")]
+    pub(crate) token: Option<String>,
+    /// `user:password` to authenticate with using basic auth
+    #[clap(long)]
+    pub(crate) user: Option<String>,
+    /// PEM file with the CA certificates to trust instead of the default roots
+    #[clap(long)]
+    pub(crate) cacert: Option<String>,
+    #[clap(subcommand)]
+    pub(crate) command: ApiCommand,
+}
+
+#[derive(Parser, Debug)]
+pub(crate) enum ApiCommand {
+    /// Get tremor version
+    Version,
+    /// Manage the API targets in `~/.tremor/config.yaml`
+    Target {
+        #[clap(subcommand)]
+        command: TargetCommand,
+    },
+    /// List published artefacts
+    List {
+        /// Kind of artefact
+        #[clap(arg_enum)]
+        kind: ArtefactKind,
+    },
+    /// Fetch a published artefact
+    Get {
+        /// Kind of artefact
+        #[clap(arg_enum)]
+        kind: ArtefactKind,
+        /// The unique artefact id
+        id: String,
+    },
+    /// Publish an artefact from a JSON or YAML file, or a trickle file for pipelines
+    Publish {
+        /// Kind of artefact
+        #[clap(arg_enum)]
+        kind: ArtefactKind,
+        /// File containing the artefact
+        source: String,
+    },
+    /// Unpublish an artefact
+    Unpublish {
+        /// Kind of artefact
+        #[clap(arg_enum)]
+        kind: ArtefactKind,
+        /// The unique artefact id
+        id: String,
+    },
+    /// Fetch an instance of an artefact
+    Instance {
+        /// Kind of artefact
+        #[clap(arg_enum)]
+        kind: ArtefactKind,
+        /// The unique artefact id
+        id: String,
+        /// The unique instance id
+        instance: String,
+    },
+    /// Link an instance of a binding using the mapping in a JSON or YAML file
+    Link {
+        /// The unique binding id
+        id: String,
+        /// The unique instance id
+        instance: String,
+        /// File containing the mapping
+        source: String,
+    },
+    /// Unlink an instance of a binding
+    Unlink {
+        /// The unique binding id
+        id: String,
+        /// The unique instance id
+        instance: String,
+    },
+    /// Export all published artefacts into a single bundle
+    Export {
+        /// File to write the bundle to, defaults to stdout
+        #[clap(short, long)]
+        output: Option<String>,
+    },
+    /// Publish all artefacts in a bundle created by `export`
+    Import {
+        /// JSON or YAML bundle
+        source: String,
+    },
+}
+
+#[derive(Parser, Debug)]
+pub(crate) enum TargetCommand {
+    /// List registered targets
+    List,
+    /// Create a new API target
+    Create {
+        /// The unique target id for the targetted tremor servers
+        target_id: String,
+        /// JSON or YAML file with a list of endpoints
+        source: String,
+    },
+    /// Delete an existing API target
+    Delete {
+        /// The unique target id for the targetted tremor servers
+        target_id: String,
+    },
+}
+
+/// Output format of the API client
+#[derive(ArgEnum, Clone, Copy, Debug, PartialEq, Eq)]
+pub(crate) enum ApiFormat {
+    /// JSON output
+    Json,
+    /// YAML output
+    Yaml,
+}
+
+impl ToString for ApiFormat {
+    fn to_string(&self) -> String {
+        match self {
+            ApiFormat::Json => "json".to_string(),
+            ApiFormat::Yaml => "yaml".to_string(),
+        }
+    }
+}
+impl Default for ApiFormat {
+    fn default() -> Self {
+        Self::Json
+    }
+}
+
+/// Kind of an API artefact
+#[derive(ArgEnum, Clone, Copy, Debug, PartialEq, Eq)]
+pub(crate) enum ArtefactKind {
+    /// Onramp specifications
+    Onramp,
+    /// Offramp specifications
+    Offramp,
+    /// Pipelines
+    Pipeline,
+    /// Bindings
+    Binding,
+}
+
+impl ToString for ArtefactKind {
+    fn to_string(&self) -> String {
+        match self {
+            ArtefactKind::Onramp => "onramp".to_string(),
+            ArtefactKind::Offramp => "offramp".to_string(),
+            ArtefactKind::Pipeline => "pipeline".to_string(),
+            ArtefactKind::Binding => "binding".to_string(),
+        }
+    }
+}
@@ -137,6 +137,7 @@ impl DbgSrc {
                 }
             }
             SourceKind::Yaml => error!("Unsupported: yaml"),
+            SourceKind::Sql => error!("Unsupported: sql"),
             SourceKind::Unsupported(Some(t)) => error!("Unsupported: {}", t),
             SourceKind::Unsupported(None) => error!("Unsupported: no file type"),
         }
@@ -299,7 +300,7 @@ impl DbgAst {
                     }
                 };
             }
-            SourceKind::Unsupported(_) | SourceKind::Yaml => {
+            SourceKind::Unsupported(_) | SourceKind::Yaml | SourceKind::Sql => {
                 eprintln!("Unsupported");
             }
         };
@@ -334,6 +335,11 @@ impl DbgDot {
                 let g = tremor_pipeline::query::Query(runnable).to_pipe(&mut idgen)?;
 
                 println!("{}", g.dot);
+                if self.fan_out {
+                    for (node, port, targets) in g.fan_outs() {
+                        eprintln!("{}/{} fans out to: {}", node, port, targets.join(", "));
+                    }
+                }
             }
             Err(e) => {
                 if let Err(e) = Script::format_error_from_script(&data.raw, h, &e) {
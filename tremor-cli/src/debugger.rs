@@ -0,0 +1,128 @@
+// Copyright 2020-2021, The Tremor Team
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+//! Client stepping through a pipeline instance of a server run with
+//! `--debug-endpoint`, speaking the protocol of `tremor_runtime::debugger`
+
+use crate::cli::Debugger;
+use crate::errors::{Error, Result};
+use async_std::io;
+use async_std::task;
+use async_tungstenite::async_std::connect_async;
+use async_tungstenite::tungstenite::Message;
+use futures::{SinkExt, StreamExt};
+use tremor_pipeline::debug::Breakpoint;
+use tremor_runtime::debugger::Command;
+
+const HELP: &str = r#"The pipeline pauses before handing an event to a node with a breakpoint,
+and reports the node, event, metadata and state it is paused at.
+
+  break <node|line>   pause before the node, or the node of the statement
+                      spanning the line of the query (short `b`)
+  clear <node|line>   remove a breakpoint
+  pause               pause before the next node (short `p`)
+  step                step to the next node (short `s`)
+  continue            run until the next breakpoint (short `c`)
+  inspect             show the status (short `i`, or an empty line)
+  quit                resume the pipeline and detach (short `q`)"#;
+
+/// An input of the user
+#[derive(Debug, PartialEq)]
+enum Input {
+    Command(Command),
+    Help,
+    Quit,
+}
+
+fn breakpoint(at: &str) -> Breakpoint {
+    at.parse()
+        .map_or_else(|_| Breakpoint::Node(at.to_string()), Breakpoint::Line)
+}
+
+fn parse(line: &str) -> Input {
+    let mut words = line.split_whitespace();
+    let command = match (words.next(), words.next(), words.next()) {
+        (Some("break" | "b"), Some(at), None) => Command::Break(breakpoint(at)),
+        (Some("clear"), Some(at), None) => Command::Clear(breakpoint(at)),
+        (Some("pause" | "p"), None, None) => Command::Pause,
+        (Some("step" | "s"), None, None) => Command::Step,
+        (Some("continue" | "c"), None, None) => Command::Continue,
+        (Some("inspect" | "i"), None, None) | (None, _, _) => Command::Inspect,
+        (Some("quit" | "q"), None, None) => return Input::Quit,
+        _ => return Input::Help,
+    };
+    Input::Command(command)
+}
+
+impl Debugger {
+    pub(crate) fn run(&self) -> Result<()> {
+        task::block_on(self.attach())
+    }
+
+    async fn attach(&self) -> Result<()> {
+        let (ws, _) = connect_async(self.url.as_str()).await?;
+        let (mut ws_tx, mut ws_rx) = ws.split();
+        let printer = task::spawn(async move {
+            while let Some(msg) = ws_rx.next().await {
+                if let Message::Text(text) = msg? {
+                    let update = simd_json::to_owned_value(&mut text.into_bytes())?;
+                    println!("{}", simd_json::to_string_pretty(&update)?);
+                }
+            }
+            Ok::<(), Error>(())
+        });
+        eprintln!("Attached to {}, `help` shows the commands", self.url);
+        let stdin = io::stdin();
+        let mut line = String::new();
+        while stdin.read_line(&mut line).await? > 0 {
+            match parse(&line) {
+                Input::Command(command) => {
+                    let text = simd_json::to_string(&command)?;
+                    ws_tx.send(Message::Text(text)).await?;
+                }
+                Input::Help => eprintln!("{}", HELP),
+                Input::Quit => break,
+            }
+            line.clear();
+        }
+        let text = simd_json::to_string(&Command::Detach)?;
+        ws_tx.send(Message::Text(text)).await?;
+        ws_tx.close().await?;
+        // the server ends the connection without waiting for a close frame
+        printer.cancel().await;
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn parses() {
+        assert_eq!(
+            Input::Command(Command::Break(Breakpoint::Node("select_0".into()))),
+            parse("break select_0\n")
+        );
+        assert_eq!(
+            Input::Command(Command::Clear(Breakpoint::Line(12))),
+            parse("clear 12")
+        );
+        assert_eq!(Input::Command(Command::Step), parse("s"));
+        assert_eq!(Input::Command(Command::Inspect), parse(""));
+        assert_eq!(Input::Quit, parse("quit"));
+        assert_eq!(Input::Help, parse("step 2"));
+        assert_eq!(Input::Help, parse("help"));
+    }
+}
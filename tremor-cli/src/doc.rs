@@ -12,43 +12,22 @@
 // See the License for the specific language governing permissions and
 // limitations under the License.
 
+use crate::cli::DocFormat;
 use crate::errors::{Error, Result};
-use crate::util::visit_path_str;
+use crate::util::{get_source_kind, visit_path_str, SourceKind};
 use crate::{
     cli::Doc,
     env::{self, TremorCliEnv},
 };
 use std::io::Read;
 use std::path::{Path, PathBuf};
+use tremor_common::ids::OperatorIdGen;
+use tremor_script::prelude::*;
+use tremor_script::query::Query;
 use tremor_script::script::Script;
 
-fn gen_doc(
-    is_interactive: bool,
-    rel_path: Option<&Path>,
-    dest_path: Option<&str>,
-    env: &TremorCliEnv,
-    path: &Path,
-) -> Result<()> {
-    let rel_path = rel_path
-        .ok_or_else(|| Error::from(format!("Bad relative path: {}", path.to_string_lossy())))?;
-    let dest_path = dest_path
-        .as_ref()
-        .ok_or_else(|| Error::from("Bad destination path"))?;
-
-    let mut raw = String::new();
-    let mut input = crate::open_file(path, None)?;
-    input.read_to_string(&mut raw)?;
-
-    let name = rel_path
-        .to_string_lossy()
-        .to_string()
-        .rsplit('/')
-        .next()
-        .ok_or_else(|| Error::from("Could not isolate relative path"))?
-        .replace(".tremor", "");
-
-    let path = path.to_str().ok_or_else(|| Error::from("Bad path"))?;
-
+/// Documents a tremor module: its module docs, constants and functions
+fn gen_script_doc(name: &str, path: &str, raw: String, env: &TremorCliEnv) -> Result<String> {
     let runnable = Script::parse(&env.module_path, path, raw, &env.fun)?;
     let docs = runnable.docs();
     let consts = &docs.consts;
@@ -56,7 +35,7 @@ fn gen_doc(
 
     let mut gen = String::new();
     if let Some(m) = &docs.module {
-        gen.push_str(&m.print_with_name(&name));
+        gen.push_str(&m.print_with_name(name));
     }
     if !consts.is_empty() {
         gen.push_str("## Constants");
@@ -71,6 +50,190 @@ fn gen_doc(
             gen.push_str(&f.to_string());
         }
     }
+    Ok(gen)
+}
+
+/// Documents a trickle query: its configuration, documented definitions,
+/// windows, functions and the resulting operator graph
+fn gen_query_doc(name: &str, path: &str, raw: &str, env: &TremorCliEnv) -> Result<String> {
+    let runnable = Query::parse(&env.module_path, path, raw, vec![], &env.fun, &env.aggr)?;
+    let query = runnable.suffix();
+    let docs = &query.docs;
+
+    let mut gen = format!("# {}\n", name);
+    if let Some(doc) = docs.module.as_ref().and_then(|m| m.doc.as_ref()) {
+        gen.push_str(&format!("\n{}\n", doc));
+    }
+
+    if !query.config.is_empty() {
+        gen.push_str("\n## Configuration\n\n");
+        let mut config: Vec<_> = query.config.iter().collect();
+        config.sort_by(|(a, _), (b, _)| a.cmp(b));
+        for (k, v) in config {
+            gen.push_str(&format!("- `{}`: `{}`\n", k, v.encode()));
+        }
+    }
+
+    if !docs.query_decls.is_empty() {
+        gen.push_str("\n## Definitions\n");
+        for d in &docs.query_decls {
+            gen.push_str(&d.to_string());
+        }
+    }
+
+    if !query.windows.is_empty() {
+        gen.push_str("\n## Windows\n");
+        let mut windows: Vec<_> = query.windows.iter().collect();
+        windows.sort_by(|(a, _), (b, _)| a.cmp(b));
+        for (name, w) in windows {
+            let kind = format!("{:?}", w.kind).to_lowercase();
+            gen.push_str(&format!("\n### {}\n\nA {} window.\n\n", name, kind));
+            let mut params: Vec<_> = w.params.iter().collect();
+            params.sort_by(|(a, _), (b, _)| a.cmp(b));
+            for (k, v) in params {
+                gen.push_str(&format!("- `{}`: `{}`\n", k, v.encode()));
+            }
+        }
+    }
+
+    if !docs.fns.is_empty() {
+        gen.push_str("\n## Functions\n");
+        for f in &docs.fns {
+            gen.push_str(&f.to_string());
+        }
+    }
+
+    let mut idgen = OperatorIdGen::new();
+    let graph = tremor_pipeline::query::Query(runnable).to_pipe(&mut idgen)?;
+    gen.push_str("\n## Graph\n\n```dot\n");
+    gen.push_str(&graph.dot);
+    gen.push_str("\n```\n");
+    Ok(gen)
+}
+
+fn escape_html(s: &str) -> String {
+    s.replace('&', "&amp;")
+        .replace('<', "&lt;")
+        .replace('>', "&gt;")
+        .replace('"', "&quot;")
+}
+
+/// Renders the subset of markdown the doc generator emits as HTML
+fn markdown_to_html(title: &str, md: &str) -> String {
+    let mut body = String::new();
+    let mut in_code = false;
+    let mut in_list = false;
+    let mut para: Vec<&str> = Vec::new();
+
+    let flush = |body: &mut String, para: &mut Vec<&str>| {
+        if !para.is_empty() {
+            body.push_str(&format!("<p>{}</p>\n", escape_html(&para.join(" "))));
+            para.clear();
+        }
+    };
+
+    for line in md.lines() {
+        if let Some(lang) = line.trim_start().strip_prefix("```") {
+            if in_code {
+                body.push_str("</code></pre>\n");
+            } else {
+                flush(&mut body, &mut para);
+                if lang.is_empty() {
+                    body.push_str("<pre><code>");
+                } else {
+                    body.push_str(&format!(
+                        "<pre><code class=\"language-{}\">",
+                        escape_html(lang)
+                    ));
+                }
+            }
+            in_code = !in_code;
+            continue;
+        }
+        if in_code {
+            body.push_str(&escape_html(line));
+            body.push('\n');
+            continue;
+        }
+        let trimmed = line.trim();
+        if let Some(item) = trimmed.strip_prefix("- ") {
+            flush(&mut body, &mut para);
+            if !in_list {
+                body.push_str("<ul>\n");
+                in_list = true;
+            }
+            body.push_str(&format!("<li>{}</li>\n", escape_html(item)));
+            continue;
+        }
+        if in_list {
+            body.push_str("</ul>\n");
+            in_list = false;
+        }
+        let level = trimmed.chars().take_while(|c| *c == '#').count();
+        if (1..=6).contains(&level) && trimmed[level..].starts_with(' ') {
+            flush(&mut body, &mut para);
+            body.push_str(&format!(
+                "<h{l}>{}</h{l}>\n",
+                escape_html(trimmed[level..].trim()),
+                l = level
+            ));
+        } else if trimmed.is_empty() {
+            flush(&mut body, &mut para);
+        } else {
+            para.push(trimmed);
+        }
+    }
+    flush(&mut body, &mut para);
+    if in_list {
+        body.push_str("</ul>\n");
+    }
+    if in_code {
+        body.push_str("</code></pre>\n");
+    }
+    format!(
+        "<!DOCTYPE html>\n<html>\n<head>\n<meta charset=\"utf-8\">\n<title>{}</title>\n</head>\n<body>\n{}</body>\n</html>\n",
+        escape_html(title),
+        body
+    )
+}
+
+fn gen_doc(
+    is_interactive: bool,
+    format: DocFormat,
+    rel_path: Option<&Path>,
+    dest_path: Option<&str>,
+    env: &TremorCliEnv,
+    path: &Path,
+) -> Result<()> {
+    // a single file was given, so it is documented relative to its own directory
+    let rel_path = rel_path
+        .or_else(|| path.file_name().map(Path::new))
+        .ok_or_else(|| Error::from(format!("Bad relative path: {}", path.to_string_lossy())))?;
+    let dest_path = dest_path
+        .as_ref()
+        .ok_or_else(|| Error::from("Bad destination path"))?;
+
+    let name = rel_path
+        .file_stem()
+        .map(|s| s.to_string_lossy().to_string())
+        .ok_or_else(|| Error::from("Could not isolate relative path"))?;
+
+    let path = path.to_str().ok_or_else(|| Error::from("Bad path"))?;
+
+    let kind = get_source_kind(path);
+    if !matches!(kind, SourceKind::Tremor | SourceKind::Trickle) {
+        return Ok(());
+    }
+
+    let mut raw = String::new();
+    let mut input = crate::open_file(path, None)?;
+    input.read_to_string(&mut raw)?;
+
+    let gen = if kind == SourceKind::Trickle {
+        gen_query_doc(&name, path, &raw, env)?
+    } else {
+        gen_script_doc(&name, path, raw, env)?
+    };
 
     if is_interactive {
         println!("{}", &gen);
@@ -79,7 +242,16 @@ fn gen_doc(
     let mut dest_file = PathBuf::new();
     dest_file.push(dest_path);
     dest_file.push(&rel_path);
-    dest_file.set_extension("md");
+    let gen = match format {
+        DocFormat::Markdown => {
+            dest_file.set_extension("md");
+            gen
+        }
+        DocFormat::Html => {
+            dest_file.set_extension("html");
+            markdown_to_html(&name, &gen)
+        }
+    };
     let parent = dest_file.parent().and_then(Path::to_str).ok_or_else(|| {
         Error::from(format!(
             "Could not get output path for {}",
@@ -100,6 +272,7 @@ impl Doc {
         env.module_path.add(self.dir.clone());
         let is_interactive = self.interactive;
         let dest_path = self.outdir.clone();
+        let format = self.format;
         visit_path_str(&self.dir, &move |rel_path, src_path| {
             // The closure exposes a 1-arity capture conforming to the PathVisitor 1-arity alias'd fn
             // whilst binding the locally defined is_interactive and dest_path command parameters
@@ -107,7 +280,52 @@ impl Doc {
             //
             // This would be so much more elegant in erlang! Surely there's a more convivial syntax in rust?
             //
-            gen_doc(is_interactive, rel_path, Some(&dest_path), &env, src_path)
+            gen_doc(
+                is_interactive,
+                format,
+                rel_path,
+                Some(&dest_path),
+                &env,
+                src_path,
+            )
         })
     }
 }
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn html() {
+        let md = "# snot\n\nsome <docs>\nspanning lines\n\n## Windows\n\n- `size`: `3`\n\n```dot\na -> b\n```\n";
+        let html = markdown_to_html("snot", md);
+        assert!(html.contains("<title>snot</title>"));
+        assert!(html.contains("<h1>snot</h1>"));
+        assert!(html.contains("<p>some &lt;docs&gt; spanning lines</p>"));
+        assert!(html.contains("<h2>Windows</h2>"));
+        assert!(html.contains("<ul>\n<li>`size`: `3`</li>\n</ul>"));
+        assert!(html.contains("<pre><code class=\"language-dot\">a -&gt; b\n</code></pre>"));
+    }
+
+    #[test]
+    fn query_doc() -> Result<()> {
+        let env = env::setup()?;
+        let src = r#"
+#!config metrics_interval_s = 10
+## A window of three events
+define tumbling window three
+with
+  size = 3
+end;
+select event from in[three] into out;
+"#;
+        let doc = gen_query_doc("snot", "snot.trickle", src, &env)?;
+        assert!(doc.starts_with("# snot\n"));
+        assert!(doc.contains("## Configuration\n\n- `metrics_interval_s`: `10`"));
+        assert!(doc.contains("A window of three events"));
+        assert!(doc.contains("A tumbling window.\n\n- `size`: `3`"));
+        assert!(doc.contains("```dot\ndigraph"));
+        Ok(())
+    }
+}
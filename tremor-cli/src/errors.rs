@@ -55,6 +55,7 @@ error_chain! {
         Url(url::ParseError) #[doc = "Error while parsing a url"];
         Common(tremor_common::Error);
         ParseIntError(std::num::ParseIntError);
+        Ws(async_tungstenite::tungstenite::Error) #[doc = "Error on the debugger websocket"];
     }
     errors {
         TestFailures(stats: crate::test::stats::Stats) {
@@ -62,6 +63,10 @@ error_chain! {
                 display("{} out of {} tests failed.\nFailed tests: {}",
                   stats.fail, stats.fail + stats.skip + stats.pass, stats.print_failed_test_names())
         }
+        CoverageBelowThreshold(rate: f64, threshold: f64) {
+            description("Coverage below threshold")
+                display("{:.2}% of lines are covered, below the threshold of {:.2}%", rate, threshold)
+        }
         FileLoadError(file: String, error: tremor_runtime::errors::Error) {
             description("Failed to load config file")
                 display("An error occurred while loading the file `{}`: {}", file, error)
@@ -0,0 +1,477 @@
+// Copyright 2020-2021, The Tremor Team
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+//! Canonical formatting of tremor-script and trickle sources
+//!
+//! Sources are compiled before they are formatted, so only valid programs
+//! are touched. The formatter works on the token stream of the lexer the
+//! compiler builds its AST from, as it keeps the comments the AST drops, and
+//! only ever changes whitespace:
+//!
+//! * lines are indented by two spaces per nesting level of brackets and
+//!   blocks, with the cases of a `match`, `for` or `fn ... of` one level below
+//!   it and their bodies one level below the case
+//! * runs of spaces are collapsed to one, there is no space inside `(` `)` and
+//!   `[` `]` or before `,` `;` `:`, and one space after `,` and `:`
+//! * trailing whitespace, also of comments, and leading, trailing and repeated blank lines are
+//!   removed
+//!
+//! Strings and heredocs are kept as they are. Formatting is idempotent.
+
+use crate::cli::Fmt;
+use crate::env::{self, TremorCliEnv};
+use crate::errors::{Error, Result};
+use crate::util::{get_source_kind, slurp_string, visit_path_str, SourceKind};
+use std::cell::RefCell;
+use std::path::Path;
+use tremor_script::lexer::{Token, Tokenizer};
+use tremor_script::query::Query;
+use tremor_script::script::Script;
+
+const INDENT: &str = "  ";
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum Kind {
+    /// `(`, `[`, `{` and their pattern and interpolation variants
+    Bracket,
+    /// a block closed by `end`
+    Block,
+    /// the parameters of a `with`, closed by `end` or the `script` following them
+    Params,
+    /// the body of a `match`, `for`, `fn`, `patch` or `merge`
+    Of { cases: bool },
+    /// a `case` or `default` of a `match`, `for` or `fn`, closed by the next
+    /// one or the `end`
+    Case,
+}
+
+#[derive(Debug, Clone, Copy)]
+struct Frame {
+    kind: Kind,
+    /// indentation of the line it was opened on
+    indent: usize,
+}
+
+/// A token and its source text, a heredoc is a single piece
+struct Piece<'input> {
+    token: Token<'input>,
+    text: &'input str,
+}
+
+fn is_comment(token: &Token) -> bool {
+    matches!(
+        token,
+        Token::SingleLineComment(_) | Token::DocComment(_) | Token::ModComment(_)
+    )
+}
+
+/// Whether there is a space between two tokens on a line, given there was
+/// whitespace between them before
+fn is_spaced(before: &Token, after: &Token, was_spaced: bool) -> bool {
+    if is_comment(before) || is_comment(after) {
+        was_spaced
+    } else if matches!(
+        after,
+        Token::Comma | Token::Semi | Token::Colon | Token::RParen | Token::RBracket
+    ) || matches!(
+        before,
+        Token::LParen | Token::LBracket | Token::LPatParen | Token::LPatBracket
+    ) {
+        false
+    } else {
+        matches!(before, Token::Comma | Token::Colon) || was_spaced
+    }
+}
+
+/// The lines of a source as pieces, whitespace is represented by
+/// `Token::Whitespace`
+fn lines(src: &str) -> Result<Vec<Vec<Piece>>> {
+    let mut lines = vec![Vec::new()];
+    let mut heredoc: Option<(usize, usize)> = None;
+    for t in Tokenizer::new(src) {
+        let t = t?;
+        let (start, end) = (t.span.start.absolute(), t.span.end.absolute());
+        if let Some((heredoc_start, depth)) = heredoc {
+            let depth = match t.value {
+                Token::HereDocStart => depth + 1,
+                Token::HereDocEnd => depth - 1,
+                _ => depth,
+            };
+            if depth == 0 {
+                heredoc = None;
+                if let Some(line) = lines.last_mut() {
+                    line.push(Piece {
+                        token: Token::HereDocStart,
+                        text: src.get(heredoc_start..end).unwrap_or_default(),
+                    });
+                }
+            } else {
+                heredoc = Some((heredoc_start, depth));
+            }
+            continue;
+        }
+        match t.value {
+            Token::HereDocStart => heredoc = Some((start, 1)),
+            Token::NewLine => lines.push(Vec::new()),
+            Token::EndOfStream => (),
+            token => {
+                let text = src.get(start..end).unwrap_or_default();
+                let text = if is_comment(&token) {
+                    text.trim_end()
+                } else {
+                    text
+                };
+                if let Some(line) = lines.last_mut() {
+                    line.push(Piece { token, text });
+                }
+            }
+        }
+    }
+    Ok(lines)
+}
+
+/// Tracks the nesting of brackets and blocks across lines
+#[derive(Default)]
+struct Layout<'input> {
+    frames: Vec<Frame>,
+    /// whether the `of` of pending `match`, `for`, `fn`, `patch` and `merge`
+    /// keywords opens cases
+    pending: Vec<bool>,
+    /// the last two tokens, ignoring comments
+    prev: (Option<Token<'input>>, Option<Token<'input>>),
+}
+
+impl<'input> Layout<'input> {
+    fn top(&self) -> Option<Kind> {
+        self.frames.last().map(|f| f.kind)
+    }
+
+    fn pop_if(&mut self, kind: Kind) {
+        if self.top() == Some(kind) {
+            self.frames.pop();
+        }
+    }
+
+    fn push(&mut self, kind: Kind, indent: usize) {
+        self.frames.push(Frame { kind, indent });
+    }
+
+    fn indent(&self) -> usize {
+        self.frames.last().map_or(0, |f| f.indent + 1)
+    }
+
+    /// Indentation of a line, updating the nesting by its tokens
+    fn line(&mut self, line: &[Piece<'input>]) -> usize {
+        let mut indent = None;
+        for piece in line {
+            let token = &piece.token;
+            if matches!(token, Token::Whitespace(_)) {
+                continue;
+            }
+            // closers move the line they start out
+            match token {
+                Token::RParen | Token::RBracket | Token::RBrace => {
+                    self.frames.pop();
+                }
+                Token::End => {
+                    self.pop_if(Kind::Case);
+                    self.frames.pop();
+                }
+                Token::Case | Token::Default => self.pop_if(Kind::Case),
+                Token::Script | Token::Subquery if !self.is_kind() => self.pop_if(Kind::Params),
+                _ => (),
+            }
+            let closes = matches!(
+                token,
+                Token::RParen | Token::RBracket | Token::RBrace | Token::End
+            );
+            if indent.is_none() && !closes {
+                indent = Some(self.indent());
+            }
+            let current = indent.unwrap_or_else(|| self.indent());
+            match token {
+                Token::LParen
+                | Token::LBracket
+                | Token::LBrace
+                | Token::LPatParen
+                | Token::LPatBracket
+                | Token::LPatBrace
+                | Token::Interpol => self.push(Kind::Bracket, current),
+                Token::Case | Token::Default => {
+                    if self.top() == Some(Kind::Of { cases: true }) {
+                        self.push(Kind::Case, current);
+                    }
+                }
+                Token::Script | Token::Subquery if !self.is_kind() => {
+                    self.push(Kind::Block, current);
+                }
+                Token::With => {
+                    if matches!(self.prev.0, Some(Token::RParen)) {
+                        self.pending.pop();
+                        self.push(Kind::Block, current);
+                    } else if matches!(self.prev, (Some(Token::Ident(..)), Some(Token::Module))) {
+                        self.push(Kind::Block, current);
+                    } else {
+                        self.push(Kind::Params, current);
+                    }
+                }
+                Token::Of => {
+                    let cases = self.pending.pop().unwrap_or_default();
+                    self.push(Kind::Of { cases }, current);
+                }
+                Token::Match | Token::For | Token::Fun => self.pending.push(true),
+                Token::Patch | Token::Merge => self.pending.push(false),
+                _ => (),
+            }
+            if !is_comment(token) {
+                self.prev = (Some(token.clone()), self.prev.0.take());
+            }
+        }
+        indent.unwrap_or_else(|| self.indent())
+    }
+
+    /// Whether a `script` or `query` names the kind of a definition
+    fn is_kind(&self) -> bool {
+        matches!(self.prev.0, Some(Token::Define | Token::Create))
+    }
+}
+
+/// Formats a tremor-script or trickle source
+pub(crate) fn format(src: &str) -> Result<String> {
+    let mut layout = Layout::default();
+    let mut formatted = String::with_capacity(src.len());
+    let mut blank = false;
+    for line in lines(src)? {
+        let indent = layout.line(&line);
+        let mut pieces = line
+            .iter()
+            .skip_while(|p| matches!(p.token, Token::Whitespace(_)))
+            .peekable();
+        if pieces.peek().is_none() {
+            blank = !formatted.is_empty();
+            continue;
+        }
+        if blank {
+            formatted.push('\n');
+            blank = false;
+        }
+        for _ in 0..indent {
+            formatted.push_str(INDENT);
+        }
+        let mut last: Option<&Token> = None;
+        let mut was_spaced = false;
+        for piece in pieces {
+            if matches!(piece.token, Token::Whitespace(_)) {
+                was_spaced = true;
+                continue;
+            }
+            if last.map_or(false, |last| is_spaced(last, &piece.token, was_spaced)) {
+                formatted.push(' ');
+            }
+            formatted.push_str(piece.text);
+            last = Some(&piece.token);
+            was_spaced = false;
+        }
+        formatted.push('\n');
+    }
+    // formatting must not change anything but whitespace
+    if significant(src)? != significant(&formatted)? {
+        return Err("Formatting would change the program".into());
+    }
+    Ok(formatted)
+}
+
+fn significant(src: &str) -> Result<Vec<Token>> {
+    Ok(lines(src)?
+        .into_iter()
+        .flatten()
+        .filter(|p| !matches!(p.token, Token::Whitespace(_)))
+        .map(|p| match p.token {
+            // heredocs are compared by their text
+            Token::HereDocStart => Token::HereDocLiteral(p.text.into()),
+            Token::SingleLineComment(c) => Token::SingleLineComment(c.trim_end()),
+            Token::DocComment(c) => Token::DocComment(c.trim_end()),
+            Token::ModComment(c) => Token::ModComment(c.trim_end()),
+            token => token,
+        })
+        .collect())
+}
+
+/// Formats a file, returns if it was formatted already
+fn format_file(env: &TremorCliEnv, path: &Path, check: bool) -> Result<bool> {
+    let name = path.to_str().ok_or_else(|| Error::from("Bad path"))?;
+    let kind = get_source_kind(name);
+    if !matches!(kind, SourceKind::Tremor | SourceKind::Trickle) {
+        return Ok(true);
+    }
+    let src = slurp_string(name)?;
+    if kind == SourceKind::Trickle {
+        Query::parse(&env.module_path, name, &src, vec![], &env.fun, &env.aggr)?;
+    } else {
+        Script::parse(&env.module_path, name, src.clone(), &env.fun)?;
+    }
+    let formatted = format(&src)?;
+    if formatted == src {
+        Ok(true)
+    } else {
+        if check {
+            println!("{}", name);
+        } else {
+            std::fs::write(path, formatted)?;
+        }
+        Ok(false)
+    }
+}
+
+impl Fmt {
+    pub(crate) fn run(&self) -> Result<()> {
+        let mut env = env::setup()?;
+        for path in &self.paths {
+            let path = Path::new(path);
+            let dir = if path.is_dir() {
+                Some(path)
+            } else {
+                path.parent()
+            };
+            if let Some(dir) = dir.and_then(Path::to_str) {
+                env.module_path.add(dir.to_string());
+            }
+        }
+        let unformatted = RefCell::new(0_usize);
+        for path in &self.paths {
+            visit_path_str(path, &|_rel_path, path| {
+                if !format_file(&env, path, self.check)? {
+                    *unformatted.borrow_mut() += 1;
+                }
+                Ok(())
+            })?;
+        }
+        let unformatted = unformatted.into_inner();
+        if self.check && unformatted > 0 {
+            Err(format!("{} files are not formatted", unformatted).into())
+        } else {
+            Ok(())
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn indents() -> Result<()> {
+        let src = r#"
+
+
+fn sum(arr) with
+for arr of
+case (i,e) => [ e , i ] # keep me   
+default =>
+   let x =    1 ;
+x
+end
+end;
+
+
+let r = {"a":[1,-1],"b": match event of
+case %{ a == 1 } => "a  b"
+default => sum( [1, 2] )
+end};
+patch r of
+default => {}
+end;
+let h = """
+    untouched   #{ 1 +  2 }
+""";
+r
+"#;
+        let expected = r#"fn sum(arr) with
+  for arr of
+    case (i, e) => [e, i] # keep me
+    default =>
+      let x = 1;
+      x
+  end
+end;
+
+let r = {"a": [1, -1], "b": match event of
+  case %{ a == 1 } => "a  b"
+  default => sum([1, 2])
+end};
+patch r of
+  default => {}
+end;
+let h = """
+    untouched   #{ 1 +  2 }
+""";
+r
+"#;
+        assert_eq!(expected, format(src)?);
+        assert_eq!(expected, format(expected)?);
+        Ok(())
+    }
+
+    #[test]
+    fn indents_trickle() -> Result<()> {
+        let src = r#"
+define tumbling window w
+with
+size = 3
+end;
+define script s
+with
+  a = 1
+script
+let event.a = args.a;
+event
+end;
+create script s with
+a = 2
+end;
+mod m with
+define script t
+script
+event
+end;
+end;
+select event from in into out;
+"#;
+        let expected = r#"define tumbling window w
+with
+  size = 3
+end;
+define script s
+with
+  a = 1
+script
+  let event.a = args.a;
+  event
+end;
+create script s with
+  a = 2
+end;
+mod m with
+  define script t
+  script
+    event
+  end;
+end;
+select event from in into out;
+"#;
+        assert_eq!(expected, format(src)?);
+        assert_eq!(expected, format(expected)?);
+        Ok(())
+    }
+}
@@ -0,0 +1,799 @@
+// Copyright 2020-2021, The Tremor Team
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+//! Linter for tremor-script and trickle files
+//!
+//! Besides the errors and warnings of the compiler, reported as `compiler`,
+//! it checks the rules:
+//!
+//! * `unused_let`: local variables assigned with `let` but never read
+//! * `shadowed`: `case` bindings reusing the name of a local variable
+//! * `unreachable_case`: cases after a `default`, a `case _` or an unguarded
+//!   `for` case, or with the pattern of an earlier unguarded case
+//! * `deprecated`: calls of deprecated functions
+//! * `error_without_drop`: cases testing for an `error` that neither drop nor
+//!   emit the event
+//! * `numeric_comparison`: exact comparisons of floats and comparisons of
+//!   numbers with strings, booleans or `null`
+//!
+//! Rules are set to `off`, `warning` or `error` in a yaml or json config, e.g.
+//!
+//! ```yaml
+//! rules:
+//!   unused_let: error
+//!   shadowed: off
+//! deprecated:
+//!   "string::format": "use string interpolation instead"
+//! ```
+//!
+//! `unreachable_case` and compiler errors are errors by default, the other
+//! rules warnings. Any error fails the command.
+
+use crate::cli::{Lint, LintFormat};
+use crate::env::{self, TremorCliEnv};
+use crate::errors::{Error, Result};
+use crate::lsp::{ident, tokens};
+use crate::util::{get_source_kind, slurp_string, visit_path_str, SourceKind};
+use std::cell::RefCell;
+use std::collections::BTreeMap;
+use std::ops::Range;
+use std::path::Path;
+use tremor_script::lexer::Token;
+use tremor_script::pos::{Location, Spanned};
+use tremor_script::query::Query;
+use tremor_script::script::Script;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub(crate) enum Rule {
+    Compiler,
+    UnusedLet,
+    Shadowed,
+    UnreachableCase,
+    Deprecated,
+    ErrorWithoutDrop,
+    NumericComparison,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub(crate) enum Level {
+    Off,
+    Warning,
+    Error,
+}
+
+impl std::fmt::Display for Rule {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.write_str(match self {
+            Self::Compiler => "compiler",
+            Self::UnusedLet => "unused_let",
+            Self::Shadowed => "shadowed",
+            Self::UnreachableCase => "unreachable_case",
+            Self::Deprecated => "deprecated",
+            Self::ErrorWithoutDrop => "error_without_drop",
+            Self::NumericComparison => "numeric_comparison",
+        })
+    }
+}
+
+impl Rule {
+    fn default_level(self) -> Level {
+        if self == Self::UnreachableCase {
+            Level::Error
+        } else {
+            Level::Warning
+        }
+    }
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(default)]
+struct Config {
+    /// Levels of the rules, the default levels apply to the rest
+    rules: BTreeMap<Rule, Level>,
+    /// Deprecated functions and what to use instead
+    deprecated: BTreeMap<String, String>,
+}
+
+impl Default for Config {
+    fn default() -> Self {
+        let mut deprecated = BTreeMap::new();
+        deprecated.insert(
+            "string::format".to_string(),
+            "use string interpolation `\"#{...}\"` instead".to_string(),
+        );
+        Self {
+            rules: BTreeMap::new(),
+            deprecated,
+        }
+    }
+}
+
+impl Config {
+    fn level(&self, rule: Rule) -> Level {
+        self.rules
+            .get(&rule)
+            .copied()
+            .unwrap_or_else(|| rule.default_level())
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub(crate) struct Finding {
+    file: String,
+    rule: Rule,
+    level: Level,
+    line: usize,
+    column: usize,
+    message: String,
+}
+
+/// What the cases of an `of` are
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum Cases {
+    /// of a `match` or `fn`
+    Match,
+    /// of a `for`
+    For,
+}
+
+/// A `case` or `default`, as token indices
+#[derive(Debug, Clone)]
+struct Clause {
+    start: usize,
+    is_default: bool,
+    pattern: Range<usize>,
+    guarded: bool,
+    body: Range<usize>,
+}
+
+#[derive(Debug)]
+enum Frame {
+    Bracket,
+    /// a block closed by `end`, with its scope if it has its own locals
+    Block(Option<usize>),
+    /// the parameters of a `with`, closed by `end` or the `script` following them
+    Params,
+    /// the body of a `match`, `for`, `fn`, `patch` or `merge`
+    Of {
+        cases: Option<Cases>,
+        scope: Option<usize>,
+        clauses: Vec<Clause>,
+    },
+}
+
+/// The keywords waiting for their `of` or `with`
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum Pending {
+    Match,
+    For,
+    Fun,
+    Patch,
+}
+
+/// The structure of a source: the scope of each token and the cases of
+/// each `match` and `for`
+#[derive(Debug, Default)]
+struct Structure {
+    /// the token index opening the scope of each token, 0 for the top level
+    scopes: Vec<usize>,
+    /// the cases of each `match`, `fn ... of` and `for`
+    cases: Vec<(Cases, Vec<Clause>)>,
+}
+
+impl Structure {
+    fn new(tokens: &[Spanned]) -> Self {
+        let mut structure = Self::default();
+        let mut frames: Vec<Frame> = Vec::new();
+        let mut pending: Vec<Pending> = Vec::new();
+        for (i, t) in tokens.iter().enumerate() {
+            let prev = i
+                .checked_sub(1)
+                .and_then(|p| tokens.get(p))
+                .map(|t| &t.value);
+            let is_kind = matches!(prev, Some(Token::Define | Token::Create));
+            match &t.value {
+                Token::LParen
+                | Token::LBracket
+                | Token::LBrace
+                | Token::LPatParen
+                | Token::LPatBracket
+                | Token::LPatBrace
+                | Token::Interpol => frames.push(Frame::Bracket),
+                Token::RParen | Token::RBracket | Token::RBrace => {
+                    frames.pop();
+                }
+                Token::Match => pending.push(Pending::Match),
+                Token::For => pending.push(Pending::For),
+                Token::Fun => pending.push(Pending::Fun),
+                Token::Patch | Token::Merge => pending.push(Pending::Patch),
+                Token::Of => {
+                    let kind = pending.pop();
+                    frames.push(Frame::Of {
+                        cases: match kind {
+                            Some(Pending::Match | Pending::Fun) => Some(Cases::Match),
+                            Some(Pending::For) => Some(Cases::For),
+                            _ => None,
+                        },
+                        scope: (kind == Some(Pending::Fun)).then(|| i + 1),
+                        clauses: Vec::new(),
+                    });
+                }
+                Token::With => {
+                    let before = i
+                        .checked_sub(2)
+                        .and_then(|p| tokens.get(p))
+                        .map(|t| &t.value);
+                    if prev == Some(&Token::RParen) {
+                        pending.pop();
+                        frames.push(Frame::Block(Some(i + 1)));
+                    } else if before == Some(&Token::Module) {
+                        frames.push(Frame::Block(None));
+                    } else {
+                        frames.push(Frame::Params);
+                    }
+                }
+                Token::Script | Token::Subquery if !is_kind => {
+                    if matches!(frames.last(), Some(Frame::Params)) {
+                        frames.pop();
+                    }
+                    frames.push(Frame::Block(Some(i + 1)));
+                }
+                Token::End => {
+                    if let Some(Frame::Of {
+                        cases: Some(cases),
+                        mut clauses,
+                        ..
+                    }) = frames.pop()
+                    {
+                        if let Some(last) = clauses.last_mut() {
+                            last.body.end = i;
+                        }
+                        structure.cases.push((cases, clauses));
+                    }
+                }
+                Token::Case | Token::Default | Token::When | Token::EqArrow => {
+                    if let Some(Frame::Of {
+                        cases: Some(_),
+                        clauses,
+                        ..
+                    }) = frames.last_mut()
+                    {
+                        clause(clauses, i, &t.value);
+                    }
+                }
+                _ => (),
+            }
+            let scope = frames
+                .iter()
+                .rev()
+                .find_map(|f| match f {
+                    Frame::Block(scope) | Frame::Of { scope, .. } => *scope,
+                    Frame::Bracket | Frame::Params => None,
+                })
+                .unwrap_or_default();
+            structure.scopes.push(scope);
+        }
+        structure
+    }
+}
+
+/// Tracks the clauses of an `of` by its `case`, `default`, `when` and `=>`
+fn clause(clauses: &mut Vec<Clause>, i: usize, token: &Token) {
+    if let Token::Case | Token::Default = token {
+        if let Some(last) = clauses.last_mut() {
+            last.body.end = i;
+        }
+        clauses.push(Clause {
+            start: i,
+            is_default: token == &Token::Default,
+            pattern: i + 1..i + 1,
+            guarded: false,
+            body: i + 1..i + 1,
+        });
+    } else if let Some(clause) = clauses.last_mut() {
+        // only the first `when` and `=>` of a clause, the body has its own
+        if clause.body.start == clause.start + 1 {
+            if clause.pattern.is_empty() && !clause.guarded {
+                clause.pattern.end = i;
+            }
+            if token == &Token::When {
+                clause.guarded = true;
+            } else {
+                clause.body = i + 1..i + 1;
+            }
+        }
+    }
+}
+
+struct Linter<'a> {
+    config: &'a Config,
+    file: &'a str,
+    findings: Vec<Finding>,
+}
+
+impl<'a> Linter<'a> {
+    fn report(&mut self, rule: Rule, at: Location, message: String) {
+        let level = self.config.level(rule);
+        if level != Level::Off {
+            self.findings.push(Finding {
+                file: self.file.to_string(),
+                rule,
+                level,
+                line: at.line(),
+                column: at.column(),
+                message,
+            });
+        }
+    }
+
+    /// The errors and warnings of the compiler
+    fn compile(&mut self, env: &TremorCliEnv, kind: &SourceKind, src: &str) {
+        let warnings = if kind == &SourceKind::Trickle {
+            Query::parse(
+                &env.module_path,
+                self.file,
+                src,
+                vec![],
+                &env.fun,
+                &env.aggr,
+            )
+            .map(|q| q.warnings.into_iter().collect::<Vec<_>>())
+        } else {
+            Script::parse(&env.module_path, self.file, src.to_string(), &env.fun)
+                .map(|s| s.warnings().cloned().collect())
+        };
+        match warnings {
+            Ok(warnings) => {
+                for w in warnings {
+                    self.report(Rule::Compiler, w.inner.0, w.msg);
+                }
+            }
+            Err(e) => {
+                // errors in used modules are reported at the start
+                let at = match e.error.context() {
+                    (_, Some(r)) | (Some(r), None) if r.cu() == 0 => r.0,
+                    _ => Location::default(),
+                };
+                self.findings.push(Finding {
+                    file: self.file.to_string(),
+                    rule: Rule::Compiler,
+                    level: Level::Error,
+                    line: at.line(),
+                    column: at.column(),
+                    message: e.error.to_string(),
+                });
+            }
+        }
+    }
+
+    fn lint(&mut self, tokens: &[Spanned]) {
+        let structure = Structure::new(tokens);
+        self.locals(tokens, &structure);
+        self.cases(tokens, &structure);
+        self.calls(tokens);
+        self.comparisons(tokens);
+    }
+
+    /// `unused_let` and `shadowed`
+    fn locals(&mut self, tokens: &[Spanned], structure: &Structure) {
+        let value = |i: usize| tokens.get(i).map(|t| &t.value);
+        // `case` bindings by token index
+        let mut bindings = BTreeMap::new();
+        for (cases, clauses) in &structure.cases {
+            for clause in clauses {
+                let pattern = tokens.get(clause.pattern.clone()).unwrap_or_default();
+                match (cases, pattern) {
+                    // `(k, v)`
+                    (Cases::For, [_, _, _, _, _]) => {
+                        bindings.insert(clause.pattern.start + 1, clause.start);
+                        bindings.insert(clause.pattern.start + 3, clause.start);
+                    }
+                    (Cases::Match, [_, eq, ..]) if eq.value == Token::Eq => {
+                        bindings.insert(clause.pattern.start, clause.start);
+                    }
+                    _ => (),
+                }
+            }
+        }
+        let mut assigned: BTreeMap<(usize, &str), usize> = BTreeMap::new();
+        let mut read: Vec<(usize, &str)> = Vec::new();
+        for (i, t) in tokens.iter().enumerate() {
+            let name = if let Some(name) = ident(&t.value) {
+                name
+            } else {
+                continue;
+            };
+            let scope = structure.scopes.get(i).copied().unwrap_or_default();
+            let before = i.checked_sub(1).and_then(value);
+            if let Some(clause) = bindings.get(&i) {
+                if let Some(let_at) = assigned.get(&(scope, name)) {
+                    let line = tokens.get(*let_at).map_or(0, |t| t.span.start.line());
+                    let at = tokens.get(*clause).map_or(t.span.start, |t| t.span.start);
+                    self.report(
+                        Rule::Shadowed,
+                        at,
+                        format!(
+                            "`{}` shadows the local variable assigned on line {}",
+                            name, line
+                        ),
+                    );
+                }
+            } else if before == Some(&Token::Let) {
+                if value(i + 1) == Some(&Token::Eq) {
+                    assigned.entry((scope, name)).or_insert(i);
+                }
+            } else if !matches!(before, Some(Token::Dot | Token::ColonColon))
+                && !matches!(value(i + 1), Some(Token::ColonColon | Token::LParen))
+            {
+                read.push((scope, name));
+            }
+        }
+        for ((scope, name), i) in assigned {
+            if !name.starts_with('_') && !read.contains(&(scope, name)) {
+                if let Some(t) = tokens.get(i) {
+                    self.report(
+                        Rule::UnusedLet,
+                        t.span.start,
+                        format!("`{}` is assigned but never read", name),
+                    );
+                }
+            }
+        }
+    }
+
+    /// `unreachable_case` and `error_without_drop`
+    fn cases(&mut self, tokens: &[Spanned], structure: &Structure) {
+        let slice = |r: &Range<usize>| tokens.get(r.clone()).unwrap_or_default();
+        let line = |i: usize| tokens.get(i).map_or(0, |t| t.span.start.line());
+        for (cases, clauses) in &structure.cases {
+            let mut catch_all: Option<usize> = None;
+            for (n, clause) in clauses.iter().enumerate() {
+                let at = tokens
+                    .get(clause.start)
+                    .map(|t| t.span.start)
+                    .unwrap_or_default();
+                let pattern = slice(&clause.pattern);
+                let same = clauses.iter().take(n).find(|c| {
+                    !c.is_default
+                        && !c.guarded
+                        && !clause.is_default
+                        && slice(&c.pattern)
+                            .iter()
+                            .map(|t| &t.value)
+                            .eq(pattern.iter().map(|t| &t.value))
+                });
+                if let Some(all) = catch_all {
+                    self.report(
+                        Rule::UnreachableCase,
+                        at,
+                        format!(
+                            "This case is unreachable, the case on line {} matches everything",
+                            line(all)
+                        ),
+                    );
+                } else if let Some(same) = same {
+                    self.report(
+                        Rule::UnreachableCase,
+                        at,
+                        format!(
+                            "This case is unreachable, the case on line {} has the same pattern",
+                            line(same.start)
+                        ),
+                    );
+                }
+                let is_catch_all = clause.is_default
+                    || (!clause.guarded
+                        && (*cases == Cases::For
+                            || matches!(pattern, [t] if t.value == Token::DontCare)));
+                if is_catch_all && catch_all.is_none() {
+                    catch_all = Some(clause.start);
+                }
+
+                let is_error = pattern.iter().any(|t| ident(&t.value) == Some("error"));
+                let handled = slice(&clause.body)
+                    .iter()
+                    .any(|t| matches!(t.value, Token::Drop | Token::Emit));
+                if *cases == Cases::Match && is_error && !handled {
+                    self.report(
+                        Rule::ErrorWithoutDrop,
+                        at,
+                        "This case tests for an error but neither drops nor emits the event"
+                            .to_string(),
+                    );
+                }
+            }
+        }
+    }
+
+    /// `deprecated`
+    fn calls(&mut self, tokens: &[Spanned]) {
+        for (i, t) in tokens.iter().enumerate() {
+            if t.value != Token::LParen {
+                continue;
+            }
+            // the path before the `(`, e.g. `string::format`
+            let mut path = Vec::new();
+            let mut j = i;
+            while let Some(name) = j
+                .checked_sub(1)
+                .and_then(|p| tokens.get(p))
+                .and_then(|t| ident(&t.value))
+            {
+                path.push(name);
+                j -= 1;
+                match j.checked_sub(1).and_then(|p| tokens.get(p)) {
+                    Some(t) if t.value == Token::ColonColon => j -= 1,
+                    _ => break,
+                }
+            }
+            path.reverse();
+            let path = path.join("::");
+            if let Some(instead) = self.config.deprecated.get(&path) {
+                let at = tokens.get(j).map_or(t.span.start, |t| t.span.start);
+                self.report(
+                    Rule::Deprecated,
+                    at,
+                    format!("`{}` is deprecated, {}", path, instead),
+                );
+            }
+        }
+    }
+
+    /// `numeric_comparison`
+    fn comparisons(&mut self, tokens: &[Spanned]) {
+        fn is_number(t: &Token) -> bool {
+            matches!(t, Token::IntLiteral(_) | Token::FloatLiteral(..))
+        }
+        for (i, t) in tokens.iter().enumerate() {
+            let lhs = i
+                .checked_sub(1)
+                .and_then(|p| tokens.get(p))
+                .map(|t| &t.value);
+            let rhs = tokens.get(i + 1).map(|t| &t.value);
+            let operands = [lhs, rhs];
+            let any = |f: fn(&Token) -> bool| operands.into_iter().flatten().any(f);
+            let mixed = any(is_number) && any(|t| matches!(t, Token::DQuote));
+            let message = match t.value {
+                Token::EqEq | Token::NotEq if any(|t| matches!(t, Token::FloatLiteral(..))) => {
+                    "Floats are compared exactly, consider comparing their difference against a tolerance"
+                }
+                Token::EqEq | Token::NotEq if mixed => {
+                    "A number is compared with a string, they are never equal"
+                }
+                Token::Gt | Token::Gte | Token::Lt | Token::Lte
+                    if mixed || any(|t| matches!(t, Token::BoolLiteral(_) | Token::Nil)) =>
+                {
+                    "Only numbers and strings can be ordered, and not with each other"
+                }
+                _ => continue,
+            };
+            self.report(Rule::NumericComparison, t.span.start, message.to_string());
+        }
+    }
+}
+
+fn lint_file(env: &TremorCliEnv, config: &Config, path: &Path) -> Result<Vec<Finding>> {
+    let file = path.to_str().ok_or_else(|| Error::from("Bad path"))?;
+    let kind = get_source_kind(file);
+    if !matches!(kind, SourceKind::Tremor | SourceKind::Trickle) {
+        return Ok(Vec::new());
+    }
+    let src = slurp_string(file)?;
+    let mut linter = Linter {
+        config,
+        file,
+        findings: Vec::new(),
+    };
+    linter.compile(env, &kind, &src);
+    linter.lint(&tokens(&src));
+    Ok(linter.findings)
+}
+
+impl Lint {
+    pub(crate) fn run(&self) -> Result<()> {
+        let config: Config = match &self.config {
+            Some(file) => serde_yaml::from_str(&slurp_string(file)?)?,
+            None => Config::default(),
+        };
+        let mut env = env::setup()?;
+        for path in &self.paths {
+            let path = Path::new(path);
+            let dir = if path.is_dir() {
+                Some(path)
+            } else {
+                path.parent()
+            };
+            if let Some(dir) = dir.and_then(Path::to_str) {
+                env.module_path.add(dir.to_string());
+            }
+        }
+        let findings = RefCell::new(Vec::new());
+        for path in &self.paths {
+            visit_path_str(path, &|_rel_path, path| {
+                findings
+                    .borrow_mut()
+                    .append(&mut lint_file(&env, &config, path)?);
+                Ok(())
+            })?;
+        }
+        let mut findings = findings.into_inner();
+        findings.sort_by(|a, b| (&a.file, a.line, a.column).cmp(&(&b.file, b.line, b.column)));
+        match self.format {
+            LintFormat::Text => {
+                for f in &findings {
+                    let level = if f.level == Level::Error {
+                        "error"
+                    } else {
+                        "warning"
+                    };
+                    println!(
+                        "{}:{}:{}: {}[{}]: {}",
+                        f.file, f.line, f.column, level, f.rule, f.message
+                    );
+                }
+            }
+            LintFormat::Json => println!("{}", simd_json::to_string(&findings)?),
+        }
+        let errors = findings.iter().filter(|f| f.level == Level::Error).count();
+        if errors > 0 {
+            Err(format!("{} lint errors", errors).into())
+        } else {
+            Ok(())
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    fn lint(config: &Config, src: &str) -> Vec<Finding> {
+        let mut linter = Linter {
+            config,
+            file: "test.tremor",
+            findings: Vec::new(),
+        };
+        linter.lint(&tokens(src));
+        let mut findings = linter.findings;
+        findings.sort_by_key(|f| (f.line, f.column));
+        findings
+    }
+
+    fn rules(src: &str) -> Vec<(Rule, usize)> {
+        lint(&Config::default(), src)
+            .into_iter()
+            .map(|f| (f.rule, f.line))
+            .collect()
+    }
+
+    #[test]
+    fn locals() {
+        let src = r#"let a = 1;
+let b = 2;
+let _c = 3;
+fn f(x) with
+  let b = x;
+  b
+end;
+for event of
+  case (a, v) => v
+end;
+match event of
+  case a = %{ present x } => a
+  default => b
+end
+"#;
+        assert_eq!(
+            vec![(Rule::Shadowed, 9), (Rule::Shadowed, 12)],
+            rules(src)
+                .into_iter()
+                .filter(|(r, _)| *r == Rule::Shadowed)
+                .collect::<Vec<_>>()
+        );
+        let src = r#"let a = 1;
+let b = 2;
+let b = 3;
+fn f(x) with
+  let b = x;
+  x
+end;
+let e = {};
+let e.x = 1;
+"#;
+        assert_eq!(
+            vec![
+                (Rule::UnusedLet, 1),
+                (Rule::UnusedLet, 2),
+                (Rule::UnusedLet, 5),
+                (Rule::UnusedLet, 8)
+            ],
+            rules(src)
+        );
+    }
+
+    #[test]
+    fn cases() {
+        let src = r#"match event of
+  case %{ present error } => event
+  case 1 => "one"
+  case 1 when true => "one"
+  case 1 => drop
+  case _ => "any"
+  default => "none"
+end;
+for event of
+  case (k, v) => v
+  case (k, v) when true => k
+end
+"#;
+        assert_eq!(
+            vec![
+                (Rule::ErrorWithoutDrop, 2),
+                (Rule::UnreachableCase, 4),
+                (Rule::UnreachableCase, 5),
+                (Rule::UnreachableCase, 7),
+                (Rule::UnreachableCase, 11)
+            ],
+            rules(src)
+        );
+    }
+
+    #[test]
+    fn calls_and_comparisons() {
+        let src = r#"let a = string::format("{}", 1);
+let b = [event.x == 1.0, event.y == 1, 1 != "1", event.z < null, 2 > "1", a];
+b
+"#;
+        assert_eq!(
+            vec![
+                (Rule::Deprecated, 1),
+                (Rule::NumericComparison, 2),
+                (Rule::NumericComparison, 2),
+                (Rule::NumericComparison, 2),
+                (Rule::NumericComparison, 2),
+            ],
+            rules(src)
+        );
+    }
+
+    #[test]
+    fn config() -> Result<()> {
+        let config: Config = serde_yaml::from_str(
+            r#"
+rules:
+  unused_let: error
+  numeric_comparison: off
+deprecated:
+  "std::old": "use std::new"
+"#,
+        )?;
+        assert_eq!(Level::Error, config.level(Rule::UnusedLet));
+        assert_eq!(Level::Off, config.level(Rule::NumericComparison));
+        assert_eq!(Level::Error, config.level(Rule::UnreachableCase));
+        let findings = lint(&config, "let a = std::old(1.0 == 1.0);");
+        assert_eq!(
+            vec![
+                (Rule::UnusedLet, Level::Error),
+                (Rule::Deprecated, Level::Warning)
+            ],
+            findings
+                .iter()
+                .map(|f| (f.rule, f.level))
+                .collect::<Vec<_>>()
+        );
+        Ok(())
+    }
+}
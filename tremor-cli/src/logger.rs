@@ -0,0 +1,240 @@
+// Copyright 2020-2021, The Tremor Team
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+//! The default logger used when no log4rs configuration is given.
+//!
+//! It honours `RUST_LOG` like `env_logger` does, but allows changing the
+//! levels of individual modules at runtime via the API.
+
+use crate::cli::LogFormat;
+use crate::errors::Result;
+use env_logger::filter::{Builder as FilterBuilder, Filter};
+use env_logger::fmt::Formatter;
+use log::{LevelFilter, Log, Metadata, Record};
+use simd_json::prelude::*;
+use std::collections::BTreeMap;
+use std::io::Write;
+use std::str::FromStr;
+use std::sync::{Arc, RwLock};
+use tremor_api::log_level::{LogLevel, LogLevels};
+
+/// Levels by module, `None` is the default level
+type Directives = BTreeMap<Option<String>, LevelFilter>;
+
+pub(crate) struct Logger {
+    inner: env_logger::Logger,
+    state: RwLock<State>,
+}
+
+struct State {
+    /// directives from `RUST_LOG`
+    base: Directives,
+    /// regex filter from `RUST_LOG`
+    regex: Option<String>,
+    /// directives set at runtime
+    overrides: Directives,
+    filter: Filter,
+}
+
+impl State {
+    fn effective(&self) -> Directives {
+        let mut directives = self.base.clone();
+        directives.extend(self.overrides.iter().map(|(m, l)| (m.clone(), *l)));
+        directives
+    }
+
+    fn rebuild(&mut self) {
+        let mut builder = FilterBuilder::new();
+        if let Some(regex) = &self.regex {
+            builder.parse(&format!("/{}", regex));
+        }
+        for (module, level) in self.effective() {
+            builder.filter(module.as_deref(), level);
+        }
+        self.filter = builder.build();
+        log::set_max_level(self.filter.filter());
+    }
+}
+
+/// Parses a `RUST_LOG` style spec, invalid directives are ignored
+/// the same way `env_logger` ignores them
+fn parse_spec(spec: &str) -> (Directives, Option<String>) {
+    let mut parts = spec.splitn(2, '/');
+    let mods = parts.next().unwrap_or_default();
+    let regex = parts.next().map(ToString::to_string);
+    let mut directives = Directives::new();
+    for directive in mods.split(',').map(str::trim).filter(|d| !d.is_empty()) {
+        let mut kv = directive.splitn(2, '=');
+        match (kv.next(), kv.next()) {
+            (Some(level_or_module), None) => {
+                if let Ok(level) = LevelFilter::from_str(level_or_module) {
+                    directives.insert(None, level);
+                } else {
+                    directives.insert(Some(level_or_module.to_string()), LevelFilter::Trace);
+                }
+            }
+            (Some(module), Some(level)) => {
+                if let Ok(level) = LevelFilter::from_str(level) {
+                    directives.insert(Some(module.to_string()), level);
+                }
+            }
+            _ => (),
+        }
+    }
+    (directives, regex)
+}
+
+fn format_json(buf: &mut Formatter, record: &Record) -> std::io::Result<()> {
+    let line = simd_json::json!({
+        "timestamp": buf.timestamp().to_string(),
+        "level": record.level().as_str(),
+        "target": record.target(),
+        "message": record.args().to_string()
+    });
+    writeln!(buf, "{}", line.encode())
+}
+
+impl Logger {
+    fn new(format: LogFormat) -> Self {
+        let mut builder = env_logger::Builder::new();
+        // filtering is done by us, so we can change it at runtime
+        builder.filter_level(LevelFilter::Trace);
+        if format == LogFormat::Json {
+            builder.format(format_json);
+        }
+        let (base, regex) = parse_spec(&std::env::var("RUST_LOG").unwrap_or_default());
+        let mut state = State {
+            base,
+            regex,
+            overrides: Directives::new(),
+            filter: FilterBuilder::new().build(),
+        };
+        state.rebuild();
+        Self {
+            inner: builder.build(),
+            state: RwLock::new(state),
+        }
+    }
+
+    /// Installs the logger as the global logger, returning a handle to change its levels
+    pub(crate) fn init(format: LogFormat) -> Result<Arc<Self>> {
+        let logger = Arc::new(Self::new(format));
+        log::set_boxed_logger(Box::new(Shared(logger.clone())))
+            .map_err(|e| format!("Failed to install logger: {}", e))?;
+        Ok(logger)
+    }
+}
+
+impl LogLevels for Logger {
+    fn levels(&self) -> Vec<LogLevel> {
+        self.state
+            .read()
+            .map(|s| {
+                s.effective()
+                    .into_iter()
+                    .map(|(module, level)| LogLevel {
+                        module,
+                        level: Some(level.as_str().to_lowercase()),
+                    })
+                    .collect()
+            })
+            .unwrap_or_default()
+    }
+
+    fn set_level(&self, module: Option<&str>, level: Option<LevelFilter>) {
+        if let Ok(mut state) = self.state.write() {
+            let module = module.map(ToString::to_string);
+            if let Some(level) = level {
+                state.overrides.insert(module, level);
+            } else {
+                state.overrides.remove(&module);
+            }
+            state.rebuild();
+        }
+    }
+}
+
+/// `log` requires an owned logger, this shares it with the API
+struct Shared(Arc<Logger>);
+
+impl Log for Shared {
+    fn enabled(&self, metadata: &Metadata) -> bool {
+        self.0
+            .state
+            .read()
+            .map(|s| s.filter.enabled(metadata))
+            .unwrap_or_default()
+    }
+
+    fn log(&self, record: &Record) {
+        let matches = self
+            .0
+            .state
+            .read()
+            .map(|s| s.filter.matches(record))
+            .unwrap_or_default();
+        if matches {
+            self.0.inner.log(record);
+        }
+    }
+
+    fn flush(&self) {
+        self.0.inner.flush();
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn spec() {
+        let (d, r) = parse_spec("info,tremor_runtime::sink=debug,snot/badger");
+        assert_eq!(d.get(&None), Some(&LevelFilter::Info));
+        assert_eq!(
+            d.get(&Some("tremor_runtime::sink".to_string())),
+            Some(&LevelFilter::Debug)
+        );
+        assert_eq!(d.get(&Some("snot".to_string())), Some(&LevelFilter::Trace));
+        assert_eq!(r.as_deref(), Some("badger"));
+
+        let (d, r) = parse_spec("");
+        assert!(d.is_empty());
+        assert!(r.is_none());
+    }
+
+    #[test]
+    fn levels() {
+        let logger = Logger::new(LogFormat::Plain);
+        logger.set_level(
+            Some("tremor_runtime::sink::kafka"),
+            Some(LevelFilter::Debug),
+        );
+        logger.set_level(None, Some(LevelFilter::Warn));
+        let levels = logger.levels();
+        assert!(levels.contains(&LogLevel {
+            module: Some("tremor_runtime::sink::kafka".to_string()),
+            level: Some("debug".to_string())
+        }));
+        assert!(levels.contains(&LogLevel {
+            module: None,
+            level: Some("warn".to_string())
+        }));
+        logger.set_level(Some("tremor_runtime::sink::kafka"), None);
+        assert!(!logger
+            .levels()
+            .iter()
+            .any(|l| l.module.as_deref() == Some("tremor_runtime::sink::kafka")));
+    }
+}
@@ -0,0 +1,595 @@
+// Copyright 2020-2021, The Tremor Team
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+//! Language server for tremor-script and trickle files, speaking the
+//! language server protocol over stdin and stdout
+//!
+//! It publishes the errors and warnings of the compiler as diagnostics,
+//! goes to the definitions of functions, constants and modules across the
+//! `use`d modules and completes the functions of the stdlib, the known
+//! metadata and the definitions of a file.
+
+use crate::env::{self, TremorCliEnv};
+use crate::errors::{Error, Result};
+use simd_json::{json, OwnedValue};
+use std::collections::HashMap;
+use std::io::{self, BufRead, Read, Write};
+use tremor_script::lexer::{Token, Tokenizer};
+use tremor_script::pos::{Location, Range, Spanned};
+use tremor_script::prelude::*;
+use tremor_script::query::Query;
+use tremor_script::script::Script;
+
+/// Error code of requests for unsupported methods
+const METHOD_NOT_FOUND: i64 = -32601;
+
+/// Metadata set or used by the onramps and offramps
+const METADATA: [(&str, &str); 10] = [
+    ("binary", "send websocket messages as binary frames"),
+    (
+        "correlation",
+        "correlates the responses of linked transports with their requests",
+    ),
+    (
+        "deadline",
+        "time in nanoseconds after which the event expires",
+    ),
+    (
+        "elastic",
+        "`_index`, `_type`, `_id`, `pipeline` and `action` of elastic documents",
+    ),
+    ("error", "the error of events sent to `err` ports"),
+    (
+        "kafka",
+        "`topic`, `key`, `partition`, `offset` and `headers` of kafka messages",
+    ),
+    ("nats", "`subject` and `headers` of nats messages"),
+    ("priority", "`high` events overtake the queued ones"),
+    (
+        "request",
+        "the http request of `rest` and `webhook` onramps",
+    ),
+    (
+        "response",
+        "the http response of linked `rest` onramps and offramps",
+    ),
+];
+
+/// Kinds of completion items
+const FUNCTION: u64 = 3;
+const PROPERTY: u64 = 10;
+const MODULE: u64 = 9;
+const CONSTANT: u64 = 21;
+
+fn is_ignorable(token: &Token) -> bool {
+    matches!(
+        token,
+        Token::Whitespace(_)
+            | Token::NewLine
+            | Token::SingleLineComment(_)
+            | Token::DocComment(_)
+            | Token::ModComment(_)
+            | Token::LineDirective(_, _)
+    )
+}
+
+/// The significant tokens of a source
+pub(crate) fn tokens(text: &str) -> Vec<Spanned> {
+    Tokenizer::new(text)
+        .tokenize_until_err()
+        .filter(|t| !is_ignorable(&t.value))
+        .collect()
+}
+
+pub(crate) fn ident<'t>(token: &'t Token) -> Option<&'t str> {
+    if let Token::Ident(name, _) = token {
+        Some(&**name)
+    } else {
+        None
+    }
+}
+
+/// The functions, constants, modules and trickle definitions of a source
+fn definitions(text: &str) -> Vec<(String, Range)> {
+    let mut definitions = Vec::new();
+    let mut defining = false;
+    let mut previous = None;
+    for token in tokens(text) {
+        if let Some(name) = ident(&token.value) {
+            if defining || matches!(previous, Some(Token::Fun | Token::Const | Token::Module)) {
+                definitions.push((name.to_string(), Range::from(token.span)));
+                defining = false;
+            }
+        } else if token.value == Token::Define {
+            defining = true;
+        }
+        previous = Some(token.value);
+    }
+    definitions
+}
+
+/// The used modules of a source by their alias
+fn uses(text: &str) -> HashMap<String, Vec<String>> {
+    let mut uses = HashMap::new();
+    let tokens = tokens(text);
+    let mut tokens = tokens.iter().map(|t| &t.value).peekable();
+    while let Some(token) = tokens.next() {
+        if *token != Token::Use {
+            continue;
+        }
+        let mut module = Vec::new();
+        while let Some(segment) = tokens.next().and_then(ident) {
+            module.push(segment.to_string());
+            if tokens.peek() != Some(&&Token::ColonColon) {
+                break;
+            }
+            tokens.next();
+        }
+        let alias = if tokens.peek() == Some(&&Token::As) {
+            tokens.nth(1).and_then(ident).map(ToString::to_string)
+        } else {
+            module.last().cloned()
+        };
+        if let Some(alias) = alias {
+            uses.insert(alias, module);
+        }
+    }
+    uses
+}
+
+/// The path of the identifier at a location, e.g. `string::format`
+fn path_at(text: &str, at: Location) -> Vec<String> {
+    let tokens = tokens(text);
+    let position = (at.line(), at.column());
+    let index = tokens.iter().position(|t| {
+        (t.span.start.line(), t.span.start.column()) <= position
+            && position < (t.span.end.line(), t.span.end.column())
+    });
+    let mut path = Vec::new();
+    let mut segments = index
+        .and_then(|i| tokens.get(..=i))
+        .unwrap_or_default()
+        .iter()
+        .rev();
+    while let Some(segment) = segments.next().and_then(|t| ident(&t.value)) {
+        path.push(segment.to_string());
+        if segments.next().map(|t| &t.value) != Some(&Token::ColonColon) {
+            break;
+        }
+    }
+    path.reverse();
+    path
+}
+
+fn location(uri: &str, range: Range) -> OwnedValue {
+    json!({
+        "uri": uri,
+        "range": lsp_range(range)
+    })
+}
+
+fn lsp_position(at: Location) -> OwnedValue {
+    json!({
+        "line": at.line().saturating_sub(1),
+        "character": at.column().saturating_sub(1)
+    })
+}
+
+fn lsp_range(range: Range) -> OwnedValue {
+    json!({
+        "start": lsp_position(range.0),
+        "end": lsp_position(range.1)
+    })
+}
+
+/// The location of an lsp position
+fn position(params: Option<&OwnedValue>) -> Option<Location> {
+    let position = params?.get("position")?;
+    Some(Location::new(
+        position.get("line")?.as_usize()? + 1,
+        position.get("character")?.as_usize()? + 1,
+        0,
+        0,
+    ))
+}
+
+fn text_document(params: Option<&OwnedValue>) -> Option<&OwnedValue> {
+    params?.get("textDocument")
+}
+
+fn uri(params: Option<&OwnedValue>) -> Option<String> {
+    text_document(params)?
+        .get("uri")?
+        .as_str()
+        .map(ToString::to_string)
+}
+
+fn completion(label: &str, kind: u64, detail: &str) -> OwnedValue {
+    json!({
+        "label": label,
+        "kind": kind,
+        "detail": detail
+    })
+}
+
+fn is_ident_char(c: char) -> bool {
+    c.is_alphanumeric() || c == '_'
+}
+
+fn read_message(input: &mut impl BufRead) -> Result<Option<OwnedValue>> {
+    let mut length = None;
+    loop {
+        let mut line = String::new();
+        if input.read_line(&mut line)? == 0 {
+            return Ok(None);
+        }
+        let line = line.trim_end();
+        if line.is_empty() {
+            break;
+        }
+        if let Some(l) = line.strip_prefix("Content-Length:") {
+            length = Some(l.trim().parse::<usize>()?);
+        }
+    }
+    let length = length.ok_or_else(|| Error::from("Message without Content-Length"))?;
+    let mut body = vec![0; length];
+    input.read_exact(&mut body)?;
+    Ok(Some(simd_json::to_owned_value(&mut body)?))
+}
+
+fn write_message(output: &mut impl Write, message: &OwnedValue) -> Result<()> {
+    let body = message.encode();
+    write!(output, "Content-Length: {}\r\n\r\n{}", body.len(), body)?;
+    output.flush()?;
+    Ok(())
+}
+
+fn publish(output: &mut impl Write, uri: &str, diagnostics: Vec<OwnedValue>) -> Result<()> {
+    write_message(
+        output,
+        &json!({
+            "jsonrpc": "2.0",
+            "method": "textDocument/publishDiagnostics",
+            "params": {
+                "uri": uri,
+                "diagnostics": diagnostics
+            }
+        }),
+    )
+}
+
+struct Server {
+    env: TremorCliEnv,
+    /// the open documents by uri
+    documents: HashMap<String, String>,
+}
+
+impl Server {
+    /// The errors and warnings of the compiler
+    fn diagnostics(&self, uri: &str, text: &str) -> Vec<OwnedValue> {
+        let warnings = if uri.ends_with(".trickle") {
+            Query::parse(
+                &self.env.module_path,
+                uri,
+                text,
+                vec![],
+                &self.env.fun,
+                &self.env.aggr,
+            )
+            .map(|q| q.warnings.into_iter().collect::<Vec<_>>())
+        } else {
+            Script::parse(&self.env.module_path, uri, text.to_string(), &self.env.fun)
+                .map(|s| s.warnings().cloned().collect())
+        };
+        match warnings {
+            Ok(warnings) => warnings
+                .into_iter()
+                .map(|w| {
+                    json!({
+                        "range": lsp_range(w.inner),
+                        "severity": 2,
+                        "source": "tremor",
+                        "message": w.msg
+                    })
+                })
+                .collect(),
+            Err(e) => {
+                // errors in used modules are reported at the start
+                let range = match e.error.context() {
+                    (Some(r), _) | (None, Some(r)) if r.cu() == 0 => r,
+                    _ => Range::default(),
+                };
+                vec![json!({
+                    "range": lsp_range(range),
+                    "severity": 1,
+                    "source": "tremor",
+                    "message": e.error.to_string()
+                })]
+            }
+        }
+    }
+
+    /// The source of a module
+    fn module(&self, module: &[String]) -> Option<(String, String)> {
+        let path = module.join("/");
+        let file = self
+            .env
+            .module_path
+            .resolve(&format!("{}.tremor", path))
+            .or_else(|| self.env.module_path.resolve(&format!("{}.trickle", path)))?;
+        let source = std::fs::read_to_string(&file).ok()?;
+        Some((format!("file://{}", file.display()), source))
+    }
+
+    fn definition(&self, uri: &str, text: &str, at: Location) -> Option<OwnedValue> {
+        let path = path_at(text, at);
+        let (name, module) = path.split_last()?;
+        let uses = uses(text);
+        if module.is_empty() {
+            if let Some((_, range)) = definitions(text).into_iter().find(|(n, _)| n == name) {
+                return Some(location(uri, range));
+            }
+            let (uri, _) = self.module(uses.get(name)?)?;
+            return Some(location(&uri, Range::default()));
+        }
+        let mut module = module.to_vec();
+        if let Some(aliased) = module.first().and_then(|alias| uses.get(alias)) {
+            module.splice(..1, aliased.iter().cloned());
+        }
+        // the path is a module itself in `use` statements
+        let mut full = module.clone();
+        full.push(name.clone());
+        if let Some((uri, _)) = self.module(&full) {
+            return Some(location(&uri, Range::default()));
+        }
+        let (uri, source) = self.module(&module)?;
+        let range = definitions(&source)
+            .into_iter()
+            .find(|(n, _)| n == name)
+            .map_or_else(Range::default, |(_, r)| r);
+        Some(location(&uri, range))
+    }
+
+    fn completions(&self, text: &str, at: Location) -> Vec<OwnedValue> {
+        let line = text
+            .lines()
+            .nth(at.line().saturating_sub(1))
+            .unwrap_or_default();
+        let prefix: String = line.chars().take(at.column().saturating_sub(1)).collect();
+        let before = prefix.trim_end_matches(is_ident_char);
+        if before.ends_with('$') {
+            let mut items: Vec<_> = METADATA
+                .iter()
+                .map(|(name, doc)| completion(name, PROPERTY, doc))
+                .collect();
+            for token in tokens(text).windows(2) {
+                if let [dollar, key] = token {
+                    if let (Token::Dollar, Some(key)) = (&dollar.value, ident(&key.value)) {
+                        if !METADATA.iter().any(|(name, _)| *name == key) {
+                            items.push(completion(key, PROPERTY, "used in this file"));
+                        }
+                    }
+                }
+            }
+            return items;
+        }
+        if let Some(before) = before.strip_suffix("::") {
+            let module = before
+                .get(before.trim_end_matches(is_ident_char).len()..)
+                .unwrap_or_default();
+            let mut items: Vec<_> = self
+                .env
+                .fun
+                .find_module(module)
+                .map(|functions| {
+                    functions
+                        .iter()
+                        .map(|(name, f)| {
+                            let arity = f.arity();
+                            let detail =
+                                format!("{}::{}/{}..{}", module, name, arity.start(), arity.end());
+                            completion(name, FUNCTION, &detail)
+                        })
+                        .collect()
+                })
+                .unwrap_or_default();
+            let uses = uses(text);
+            let target = uses
+                .get(module)
+                .cloned()
+                .unwrap_or_else(|| vec![module.to_string()]);
+            if let Some((_, source)) = self.module(&target) {
+                items.extend(
+                    definitions(&source)
+                        .into_iter()
+                        .map(|(name, _)| completion(&name, FUNCTION, &target.join("::"))),
+                );
+            }
+            return items;
+        }
+        let mut items: Vec<_> = self
+            .env
+            .fun
+            .modules()
+            .map(|module| completion(module, MODULE, "stdlib module"))
+            .collect();
+        items.extend(
+            uses(text)
+                .into_iter()
+                .map(|(alias, module)| completion(&alias, MODULE, &module.join("::"))),
+        );
+        items.extend(
+            definitions(text)
+                .into_iter()
+                .map(|(name, _)| completion(&name, CONSTANT, "defined in this file")),
+        );
+        items
+    }
+
+    /// Handles a request or notification, returns `false` on `exit`
+    fn handle(&mut self, output: &mut impl Write, message: &OwnedValue) -> Result<bool> {
+        let method = message
+            .get("method")
+            .and_then(ValueAccess::as_str)
+            .unwrap_or_default();
+        let params = message.get("params");
+        let result = match method {
+            "initialize" => json!({
+                "capabilities": {
+                    "textDocumentSync": 1,
+                    "definitionProvider": true,
+                    "completionProvider": {"triggerCharacters": ["$", ":"]}
+                },
+                "serverInfo": {
+                    "name": "tremor",
+                    "version": tremor_runtime::version::VERSION
+                }
+            }),
+            "shutdown" => OwnedValue::null(),
+            "exit" => return Ok(false),
+            "textDocument/didOpen" | "textDocument/didChange" => {
+                // documents are synchronized in full
+                let text = text_document(params)
+                    .and_then(|d| d.get("text"))
+                    .or_else(|| {
+                        params?
+                            .get("contentChanges")?
+                            .as_array()?
+                            .last()?
+                            .get("text")
+                    })
+                    .and_then(ValueAccess::as_str);
+                if let (Some(uri), Some(text)) = (uri(params), text) {
+                    let diagnostics = self.diagnostics(&uri, text);
+                    publish(output, &uri, diagnostics)?;
+                    self.documents.insert(uri, text.to_string());
+                }
+                return Ok(true);
+            }
+            "textDocument/didClose" => {
+                if let Some(uri) = uri(params) {
+                    self.documents.remove(&uri);
+                    publish(output, &uri, Vec::new())?;
+                }
+                return Ok(true);
+            }
+            "textDocument/definition" => uri(params)
+                .and_then(|uri| {
+                    let text = self.documents.get(&uri)?;
+                    self.definition(&uri, text, position(params)?)
+                })
+                .unwrap_or_else(OwnedValue::null),
+            "textDocument/completion" => {
+                let items = uri(params)
+                    .and_then(|uri| {
+                        let text = self.documents.get(&uri)?;
+                        Some(self.completions(text, position(params)?))
+                    })
+                    .unwrap_or_default();
+                OwnedValue::from(items)
+            }
+            _ => {
+                // only requests are answered, notifications are ignored
+                if let Some(id) = message.get("id") {
+                    write_message(
+                        output,
+                        &json!({
+                            "jsonrpc": "2.0",
+                            "id": id.clone(),
+                            "error": {
+                                "code": METHOD_NOT_FOUND,
+                                "message": format!("Method {} not found", method)
+                            }
+                        }),
+                    )?;
+                }
+                return Ok(true);
+            }
+        };
+        if let Some(id) = message.get("id") {
+            write_message(
+                output,
+                &json!({
+                    "jsonrpc": "2.0",
+                    "id": id.clone(),
+                    "result": result
+                }),
+            )?;
+        }
+        Ok(true)
+    }
+}
+
+pub(crate) fn run() -> Result<()> {
+    let mut server = Server {
+        env: env::setup()?,
+        documents: HashMap::new(),
+    };
+    let stdin = io::stdin();
+    let mut input = stdin.lock();
+    let stdout = io::stdout();
+    let mut output = stdout.lock();
+    while let Some(message) = read_message(&mut input)? {
+        if !server.handle(&mut output, &message)? {
+            break;
+        }
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn resolves_paths() {
+        let text = "use std::string as s;\nuse foo::bar;\nfn f(x) with\n  s::format(\"{}\", x)\nend;\nf(bar::baz)\n";
+        let uses = uses(text);
+        assert_eq!(
+            Some(&vec!["std".to_string(), "string".to_string()]),
+            uses.get("s")
+        );
+        assert_eq!(
+            Some(&vec!["foo".to_string(), "bar".to_string()]),
+            uses.get("bar")
+        );
+
+        let definitions = definitions(text);
+        assert_eq!(
+            Some("f"),
+            definitions.first().map(|(name, _)| name.as_str())
+        );
+        assert_eq!(
+            vec!["s".to_string(), "format".to_string()],
+            path_at(text, Location::new(4, 7, 0, 0))
+        );
+        assert_eq!(
+            vec!["bar".to_string(), "baz".to_string()],
+            path_at(text, Location::new(6, 8, 0, 0))
+        );
+    }
+
+    #[test]
+    fn frames_messages() -> Result<()> {
+        let mut output = Vec::new();
+        write_message(&mut output, &json!({"jsonrpc": "2.0", "method": "exit"}))?;
+        let message = read_message(&mut output.as_slice())?.ok_or("no message")?;
+        assert_eq!(
+            Some("exit"),
+            message.get("method").and_then(ValueAccess::as_str)
+        );
+        assert!(read_message(&mut output.get(..0).unwrap_or_default())?.is_none());
+        Ok(())
+    }
+}
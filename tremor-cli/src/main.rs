@@ -38,15 +38,23 @@ use tremor_common::file;
 // use tremor_runtime::errors;
 
 mod alloc;
-// mod api;
+mod api;
+mod bench;
 mod completions;
 mod debug;
+mod debugger;
 mod doc;
 mod env;
 mod errors;
+mod fmt;
 // mod explain;
 pub(crate) mod cli;
 mod job;
+mod lint;
+mod logger;
+mod lsp;
+mod remote;
+mod repl;
 mod report;
 mod run;
 mod server;
@@ -91,6 +99,14 @@ fn main() -> Result<()> {
     // let app = app.version(long_version.as_str());
     // let matches = app.clone().get_matches();
 
+    #[cfg(feature = "wasm")]
+    for path in &cli.wasm {
+        let limits = tremor_pipeline::wasm::Limits {
+            max_memory: cli.wasm_max_memory,
+            max_fuel: cli.wasm_max_fuel,
+        };
+        tremor_pipeline::wasm::load(std::path::Path::new(path), limits)?;
+    }
     tremor_runtime::functions::load()?;
     unsafe {
         // We know that instance will only get set once at
@@ -123,13 +139,12 @@ fn run(cli: Cli) -> Result<()> {
         Command::Dbg(d) => d.run(),
         Command::Run(r) => r.run(),
         Command::Doc(d) => d.run(),
-        Command::Api(_) => todo!(),
-        // Some(("api", Some(matches))) => task::block_on(api::run_cmd(
-        //     TremorApp {
-        //         format,
-        //         config: load_config()?,
-        //     },
-        //     matches,
-        // )),
+        Command::Bench(b) => b.run(),
+        Command::Api(a) => a.run(),
+        Command::Repl(r) => r.run(),
+        Command::Lsp => lsp::run(),
+        Command::Fmt(f) => f.run(),
+        Command::Lint(l) => l.run(),
+        Command::Debug(d) => d.run(),
     }
 }
@@ -0,0 +1,346 @@
+// Copyright 2020-2021, The Tremor Team
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+//! Fetching of artefacts given as `http://`, `https://` or `s3://` URLs.
+//!
+//! A URL can pin the expected content with a `#sha256=<hex>` fragment, the
+//! artefact is rejected if the fetched content doesn't match. Credentials for
+//! `s3://` URLs are taken from `AWS_ACCESS_KEY_ID`, `AWS_SECRET_ACCESS_KEY`
+//! and `AWS_SESSION_TOKEN`, the region from `AWS_REGION` and a custom endpoint
+//! from `AWS_ENDPOINT_URL`.
+
+use crate::errors::{Error, Result};
+use async_std::task;
+use chrono::Utc;
+use hmac::{Hmac, Mac};
+use http_types::auth::BasicAuth;
+use http_types::headers;
+use sha2::{Digest, Sha256};
+use std::io::Write;
+use std::time::Duration;
+use tempfile::TempDir;
+use tremor_common::file;
+use url::Url;
+
+/// Base delay between retries, doubled with every attempt
+const RETRY_DELAY_MS: u64 = 500;
+
+#[derive(Debug, PartialEq)]
+struct Remote {
+    url: Url,
+    sha256: Option<String>,
+}
+
+impl Remote {
+    /// Parses an artefact into a remote, returns `None` for local paths
+    fn parse(artefact: &str) -> Result<Option<Self>> {
+        if !["http://", "https://", "s3://"]
+            .iter()
+            .any(|scheme| artefact.starts_with(scheme))
+        {
+            return Ok(None);
+        }
+        let mut url = Url::parse(artefact)?;
+        let sha256 = match url.fragment() {
+            Some(fragment) => Some(
+                fragment
+                    .strip_prefix("sha256=")
+                    .ok_or_else(|| {
+                        Error::from(format!("Unsupported checksum `{}` in {}", fragment, url))
+                    })?
+                    .to_lowercase(),
+            ),
+            None => None,
+        };
+        url.set_fragment(None);
+        Ok(Some(Self { url, sha256 }))
+    }
+
+    /// Name of the artefact, keeping the extension to tell the kind of artefact
+    fn file_name(&self) -> String {
+        self.url
+            .path_segments()
+            .and_then(Iterator::last)
+            .filter(|s| !s.is_empty())
+            .unwrap_or("artefact")
+            .to_string()
+    }
+
+    fn verify(&self, data: &[u8]) -> Result<()> {
+        if let Some(expected) = &self.sha256 {
+            let actual = hex::encode(Sha256::digest(data));
+            if actual != *expected {
+                return Err(format!(
+                    "Checksum mismatch for {}: expected sha256 {} but got {}",
+                    self.url, expected, actual
+                )
+                .into());
+            }
+        }
+        Ok(())
+    }
+
+    async fn fetch(&self, token: Option<&str>) -> Result<Vec<u8>> {
+        let request = if self.url.scheme() == "s3" {
+            s3_request(&self.url)?
+        } else {
+            let mut url = self.url.clone();
+            let basic = if url.username().is_empty() {
+                None
+            } else {
+                let auth = BasicAuth::new(url.username(), url.password().unwrap_or_default());
+                // credentials are sent as a header, not as part of the url
+                url.set_username("").ok();
+                url.set_password(None).ok();
+                Some(auth.value().to_string())
+            };
+            let request = surf::get(url);
+            match (token, basic) {
+                (Some(token), _) => {
+                    request.header(headers::AUTHORIZATION, format!("Bearer {}", token))
+                }
+                (None, Some(basic)) => request.header(headers::AUTHORIZATION, basic),
+                (None, None) => request,
+            }
+        };
+        let mut response = request.await?;
+        let status = response.status();
+        if !status.is_success() {
+            return Err(format!(
+                "Failed to fetch {}: {} {}",
+                self.url,
+                status,
+                status.canonical_reason()
+            )
+            .into());
+        }
+        Ok(response.body_bytes().await?)
+    }
+}
+
+fn hmac_sha256(key: &[u8], data: &str) -> Result<Vec<u8>> {
+    let mut mac = Hmac::<Sha256>::new_from_slice(key)
+        .map_err(|e| Error::from(format!("Invalid signing key: {}", e)))?;
+    mac.update(data.as_bytes());
+    Ok(mac.finalize().into_bytes().to_vec())
+}
+
+/// AWS signature version 4 of a request without query parameters, `headers`
+/// need to be lowercase and sorted by name
+#[allow(clippy::too_many_arguments)]
+fn sigv4_authorization(
+    method: &str,
+    path: &str,
+    headers: &[(&str, &str)],
+    payload_hash: &str,
+    region: &str,
+    service: &str,
+    (access_key, secret_key): (&str, &str),
+    amz_date: &str,
+) -> Result<String> {
+    let date = &amz_date[..8];
+    let canonical_headers: String = headers
+        .iter()
+        .map(|(name, value)| format!("{}:{}\n", name, value.trim()))
+        .collect();
+    let signed_headers = headers
+        .iter()
+        .map(|(name, _)| *name)
+        .collect::<Vec<_>>()
+        .join(";");
+    let canonical_request = format!(
+        "{}\n{}\n\n{}\n{}\n{}",
+        method, path, canonical_headers, signed_headers, payload_hash
+    );
+    let scope = format!("{}/{}/{}/aws4_request", date, region, service);
+    let string_to_sign = format!(
+        "AWS4-HMAC-SHA256\n{}\n{}\n{}",
+        amz_date,
+        scope,
+        hex::encode(Sha256::digest(canonical_request.as_bytes()))
+    );
+    let key = hmac_sha256(format!("AWS4{}", secret_key).as_bytes(), date)?;
+    let key = hmac_sha256(&key, region)?;
+    let key = hmac_sha256(&key, service)?;
+    let key = hmac_sha256(&key, "aws4_request")?;
+    let signature = hex::encode(hmac_sha256(&key, &string_to_sign)?);
+    Ok(format!(
+        "AWS4-HMAC-SHA256 Credential={}/{}, SignedHeaders={}, Signature={}",
+        access_key, scope, signed_headers, signature
+    ))
+}
+
+/// Builds a, signed if credentials are set, GET request for `s3://bucket/key`
+fn s3_request(url: &Url) -> Result<surf::RequestBuilder> {
+    let bucket = url
+        .host_str()
+        .ok_or_else(|| Error::from(format!("No bucket in {}", url)))?;
+    let region = std::env::var("AWS_REGION").unwrap_or_else(|_| "us-east-1".to_string());
+    let http_url = if let Ok(endpoint) = std::env::var("AWS_ENDPOINT_URL") {
+        Url::parse(&format!(
+            "{}/{}{}",
+            endpoint.trim_end_matches('/'),
+            bucket,
+            url.path()
+        ))?
+    } else {
+        Url::parse(&format!(
+            "https://{}.s3.{}.amazonaws.com{}",
+            bucket,
+            region,
+            url.path()
+        ))?
+    };
+    let request = surf::get(http_url.clone());
+    let (access_key, secret_key) = match (
+        std::env::var("AWS_ACCESS_KEY_ID"),
+        std::env::var("AWS_SECRET_ACCESS_KEY"),
+    ) {
+        (Ok(access_key), Ok(secret_key)) => (access_key, secret_key),
+        _ => return Ok(request),
+    };
+    let host = match http_url.port() {
+        Some(port) => format!("{}:{}", http_url.host_str().unwrap_or_default(), port),
+        None => http_url.host_str().unwrap_or_default().to_string(),
+    };
+    let amz_date = Utc::now().format("%Y%m%dT%H%M%SZ").to_string();
+    let payload_hash = hex::encode(Sha256::digest(b""));
+    let session_token = std::env::var("AWS_SESSION_TOKEN").ok();
+    let mut signed = vec![
+        ("host", host.as_str()),
+        ("x-amz-content-sha256", payload_hash.as_str()),
+        ("x-amz-date", amz_date.as_str()),
+    ];
+    if let Some(token) = &session_token {
+        signed.push(("x-amz-security-token", token.as_str()));
+    }
+    let authorization = sigv4_authorization(
+        "GET",
+        http_url.path(),
+        &signed,
+        &payload_hash,
+        &region,
+        "s3",
+        (&access_key, &secret_key),
+        &amz_date,
+    )?;
+    let mut request = request.header(headers::AUTHORIZATION, authorization);
+    for (name, value) in signed.into_iter().skip(1) {
+        request = request.header(name, value);
+    }
+    Ok(request)
+}
+
+/// Downloads remote artefacts and returns the local paths of all artefacts,
+/// local paths are passed through as is. Downloads are stored in a private
+/// temporary directory that is removed once the returned `TempDir` is dropped.
+pub(crate) async fn resolve(
+    artefacts: &[String],
+    token: Option<&str>,
+    retries: u32,
+) -> Result<(Option<TempDir>, Vec<String>)> {
+    let mut dir: Option<TempDir> = None;
+    let mut resolved = Vec::with_capacity(artefacts.len());
+    for (idx, artefact) in artefacts.iter().enumerate() {
+        let remote = if let Some(remote) = Remote::parse(artefact)? {
+            remote
+        } else {
+            resolved.push(artefact.clone());
+            continue;
+        };
+        let mut attempt = 0;
+        let data = loop {
+            match remote.fetch(token).await {
+                Ok(data) => break data,
+                Err(e) if attempt < retries => {
+                    let delay = RETRY_DELAY_MS << attempt.min(10);
+                    warn!(
+                        "Failed to fetch {}, retrying in {}ms: {}",
+                        remote.url, delay, e
+                    );
+                    task::sleep(Duration::from_millis(delay)).await;
+                    attempt += 1;
+                }
+                Err(e) => return Err(e),
+            }
+        };
+        remote.verify(&data)?;
+        let dir = match dir.as_mut() {
+            Some(dir) => dir,
+            None => dir.insert(
+                tempfile::Builder::new()
+                    .prefix("tremor-artefacts-")
+                    .tempdir()?,
+            ),
+        };
+        let path = dir.path().join(format!("{}-{}", idx, remote.file_name()));
+        file::create(&path)?.write_all(&data)?;
+        info!("Fetched {} to {}", remote.url, path.display());
+        resolved.push(path.to_string_lossy().to_string());
+    }
+    Ok((dir, resolved))
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn parse() -> Result<()> {
+        assert_eq!(Remote::parse("tests/main.trickle")?, None);
+        let remote = Remote::parse("https://example.com/cfg/main.trickle#sha256=ABC")?
+            .ok_or_else(|| Error::from("expected remote"))?;
+        assert_eq!(remote.url.as_str(), "https://example.com/cfg/main.trickle");
+        assert_eq!(remote.sha256.as_deref(), Some("abc"));
+        assert_eq!(remote.file_name(), "main.trickle");
+        assert!(Remote::parse("s3://bucket/main.yaml#md5=abc").is_err());
+        Ok(())
+    }
+
+    #[test]
+    fn verify() -> Result<()> {
+        let remote = Remote::parse(
+            "s3://bucket/x.yaml#sha256=2cf24dba5fb0a30e26e83b2ac5b9e29e1b161e5c1fa7425e73043362938b9824",
+        )?
+        .ok_or_else(|| Error::from("expected remote"))?;
+        assert!(remote.verify(b"hello").is_ok());
+        assert!(remote.verify(b"badger").is_err());
+        Ok(())
+    }
+
+    #[test]
+    fn sigv4() -> Result<()> {
+        // `get-vanilla` from the AWS signature version 4 test suite
+        let authorization = sigv4_authorization(
+            "GET",
+            "/",
+            &[
+                ("host", "example.amazonaws.com"),
+                ("x-amz-date", "20150830T123600Z"),
+            ],
+            "e3b0c44298fc1c149afbf4c8996fb92427ae41e4649b934ca495991b7852b855",
+            "us-east-1",
+            "service",
+            ("AKIDEXAMPLE", "wJalrXUtnFEMI/K7MDENG+bPxRfiCYEXAMPLEKEY"),
+            "20150830T123600Z",
+        )?;
+        assert_eq!(
+            authorization,
+            "AWS4-HMAC-SHA256 Credential=AKIDEXAMPLE/20150830/us-east-1/service/aws4_request, \
+             SignedHeaders=host;x-amz-date, \
+             Signature=5fa00fa31553b73ebf1942676e86291e8372ff2a2260956d9b8aae1d763fbf31"
+        );
+        Ok(())
+    }
+}
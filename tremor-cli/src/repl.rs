@@ -0,0 +1,517 @@
+// Copyright 2020-2021, The Tremor Team
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+//! Interactive shell evaluating tremor-script against an event, its
+//! metadata and state
+
+use crate::cli::Repl;
+use crate::env::{self, TremorCliEnv};
+use crate::errors::Result;
+use crate::util::{highlight, slurp_string};
+use std::io::{self, BufRead, Write};
+use tremor_common::time::nanotime;
+use tremor_script::ctx::EventContext;
+use tremor_script::highlighter::{Error as HighlighterError, Highlighter, Term as TermHighlighter};
+use tremor_script::lexer::{Token, Tokenizer};
+use tremor_script::prelude::*;
+use tremor_script::script::{AggrType, Return, Script};
+use tremor_script::Value;
+
+/// Name of the inputs in errors
+const SOURCE: &str = "<repl>";
+
+const HELP: &str = r#"Inputs are evaluated against the event, metadata and state and keep the
+changes made to them. Locals only live for one input, `use`, `const`, `fn`
+and `mod` definitions for the whole session.
+
+  :event [expr]   show or set the event, e.g. `:event {"level": "info"}`
+  :meta [expr]    show or set the metadata
+  :state [expr]   show or set the state
+  :load <file>    load a script to step through
+  :step           run the next statement of the loaded script
+  :run            run the remaining statements of the loaded script
+  :reset          forget the definitions, the context and the loaded script
+  :help           show this help
+  :quit           leave the repl
+"#;
+
+/// What the inputs are evaluated against
+#[derive(Clone, Debug)]
+struct Context {
+    event: Value<'static>,
+    meta: Value<'static>,
+    state: Value<'static>,
+}
+
+impl Default for Context {
+    fn default() -> Self {
+        Self {
+            event: Value::object(),
+            meta: Value::object(),
+            state: Value::null(),
+        }
+    }
+}
+
+#[derive(Clone, Copy, Debug)]
+enum Part {
+    Event,
+    Meta,
+    State,
+}
+
+/// Result of evaluating an input
+enum Outcome {
+    /// the value and the port it's emitted on
+    Emit(Value<'static>, Option<String>),
+    Drop,
+}
+
+/// A script being stepped through
+struct Steps {
+    statements: Vec<String>,
+    /// number of statements run
+    done: usize,
+    /// context before the first statement
+    start: Context,
+}
+
+struct Session {
+    env: TremorCliEnv,
+    /// definitions preceding every input
+    prelude: String,
+    ctx: Context,
+    steps: Option<Steps>,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+enum Block {
+    Patch,
+    Other,
+}
+
+/// Tracks the blocks a token stream is nested in
+#[derive(Default)]
+struct Nesting {
+    blocks: Vec<Block>,
+    /// the last significant token was `intrinsic`
+    intrinsic: bool,
+    /// the last significant token was `=>`
+    arrow: bool,
+}
+
+impl Nesting {
+    fn feed(&mut self, token: &Token) {
+        match token {
+            Token::LParen
+            | Token::LPatParen
+            | Token::LBrace
+            | Token::LPatBrace
+            | Token::Interpol
+            | Token::LBracket
+            | Token::LPatBracket => self.blocks.push(Block::Other),
+            Token::RParen | Token::RBrace | Token::RBracket | Token::End => {
+                self.blocks.pop();
+            }
+            Token::Patch => self.blocks.push(Block::Patch),
+            Token::Match | Token::For | Token::Module => self.blocks.push(Block::Other),
+            // `merge` is an operation of a patch unless it's a value in it
+            Token::Merge => {
+                if self.arrow || self.blocks.last() != Some(&Block::Patch) {
+                    self.blocks.push(Block::Other);
+                }
+            }
+            // intrinsic functions have no body
+            Token::Fun => {
+                if !self.intrinsic {
+                    self.blocks.push(Block::Other);
+                }
+            }
+            _ => (),
+        }
+        if !is_ignorable(token) {
+            self.intrinsic = *token == Token::Intrinsic;
+            self.arrow = *token == Token::EqArrow;
+        }
+    }
+
+    fn is_top(&self) -> bool {
+        self.blocks.is_empty()
+    }
+}
+
+fn is_ignorable(token: &Token) -> bool {
+    matches!(
+        token,
+        Token::Whitespace(_)
+            | Token::NewLine
+            | Token::SingleLineComment(_)
+            | Token::DocComment(_)
+            | Token::ModComment(_)
+    )
+}
+
+/// If the input closes all the blocks it opens
+fn is_complete(input: &str) -> bool {
+    let mut nesting = Nesting::default();
+    for token in Tokenizer::new(input).tokenize_until_err() {
+        nesting.feed(&token.value);
+    }
+    nesting.is_top()
+}
+
+/// The first significant token of the input
+fn first_token(input: &str) -> Option<Token> {
+    Tokenizer::new(input)
+        .tokenize_until_err()
+        .map(|t| t.value)
+        .find(|t| !is_ignorable(t))
+}
+
+/// Definitions are kept for the whole session
+fn is_definition(input: &str) -> bool {
+    matches!(
+        first_token(input),
+        Some(Token::Use | Token::Const | Token::Fun | Token::Module | Token::Intrinsic)
+    )
+}
+
+/// Explicit `emit` and `drop` end the script
+fn ends_script(statement: &str) -> bool {
+    matches!(first_token(statement), Some(Token::Emit | Token::Drop))
+}
+
+/// Splits a script into its top level statements
+fn statements(script: &str) -> Vec<String> {
+    fn push(statements: &mut Vec<String>, statement: Option<&str>) {
+        if let Some(s) = statement.map(str::trim).filter(|s| !s.is_empty()) {
+            statements.push(s.to_string());
+        }
+    }
+    let mut statements = Vec::new();
+    let mut nesting = Nesting::default();
+    let mut start = 0;
+    for token in Tokenizer::new(script).tokenize_until_err() {
+        if token.value == Token::Semi && nesting.is_top() {
+            push(
+                &mut statements,
+                script.get(start..token.span.start.absolute()),
+            );
+            start = token.span.end.absolute();
+        } else {
+            nesting.feed(&token.value);
+        }
+    }
+    push(&mut statements, script.get(start..));
+    statements
+}
+
+fn print_error(source: &str, e: &tremor_script::errors::Error) -> Result<()> {
+    if let (Some(r), _) = e.context() {
+        let mut input = source.to_string();
+        input.push('\n'); // for nicer highlighting
+        let tokens: Vec<_> = Tokenizer::new(&input).tokenize_until_err().collect();
+        let mut h = TermHighlighter::stderr();
+        h.highlight_error(
+            Some(SOURCE),
+            &tokens,
+            "",
+            true,
+            Some(r),
+            Some(HighlighterError::from(e)),
+        )?;
+        h.finalize()?;
+    } else {
+        eprintln!("Error: {}", e);
+    }
+    Ok(())
+}
+
+fn print_outcome(outcome: &Outcome) -> Result<()> {
+    match outcome {
+        Outcome::Emit(value, port) => {
+            if let Some(port) = port {
+                eprintln!("emitted to `{}`:", port);
+            }
+            highlight(true, value)?;
+            println!();
+        }
+        Outcome::Drop => eprintln!("dropped"),
+    }
+    Ok(())
+}
+
+impl Session {
+    fn new(env: TremorCliEnv) -> Self {
+        Self {
+            env,
+            prelude: String::new(),
+            ctx: Context::default(),
+            steps: None,
+        }
+    }
+
+    /// Evaluates the input against the context, the context is only
+    /// updated if it succeeds, errors are printed
+    fn eval(&self, input: &str, ctx: &mut Context) -> Result<Option<Outcome>> {
+        let source = format!("{}{}\n", self.prelude, input);
+        let script =
+            match Script::parse(&self.env.module_path, SOURCE, source.clone(), &self.env.fun) {
+                Ok(script) => script,
+                Err(e) => {
+                    Script::format_error_from_script(&source, &mut TermHighlighter::stderr(), &e)?;
+                    return Ok(None);
+                }
+            };
+        let mut event = ctx.event.clone();
+        let mut meta = ctx.meta.clone();
+        let mut state = ctx.state.clone();
+        let r = script.run(
+            &EventContext::new(nanotime(), None),
+            AggrType::Tick,
+            &mut event,
+            &mut state,
+            &mut meta,
+        );
+        match r {
+            Ok(r) => {
+                let outcome = match r {
+                    Return::Emit { value, port } => Outcome::Emit(value.clone_static(), port),
+                    Return::EmitEvent { port } => Outcome::Emit(event.clone_static(), port),
+                    Return::Drop => Outcome::Drop,
+                };
+                ctx.event = event.clone_static();
+                ctx.meta = meta.clone_static();
+                ctx.state = state;
+                Ok(Some(outcome))
+            }
+            Err(e) => {
+                print_error(&source, &e)?;
+                Ok(None)
+            }
+        }
+    }
+
+    /// Adds a definition to the prelude if it compiles
+    fn define(&mut self, input: &str) -> Result<()> {
+        let prelude = format!("{}{};\n", self.prelude, input.trim().trim_end_matches(';'));
+        let source = format!("{}null\n", prelude);
+        match Script::parse(&self.env.module_path, SOURCE, source.clone(), &self.env.fun) {
+            Ok(_) => self.prelude = prelude,
+            Err(e) => {
+                Script::format_error_from_script(&source, &mut TermHighlighter::stderr(), &e)?;
+            }
+        }
+        Ok(())
+    }
+
+    /// Shows a part of the context or sets it to the value of an expression
+    fn context(&mut self, part: Part, expr: &str) -> Result<()> {
+        if expr.is_empty() {
+            let value = match part {
+                Part::Event => &self.ctx.event,
+                Part::Meta => &self.ctx.meta,
+                Part::State => &self.ctx.state,
+            };
+            highlight(true, value)?;
+            println!();
+            return Ok(());
+        }
+        let mut ctx = self.ctx.clone();
+        match self.eval(expr, &mut ctx)? {
+            Some(Outcome::Emit(value, _)) => match part {
+                Part::Event => self.ctx.event = value,
+                Part::Meta => self.ctx.meta = value,
+                Part::State => self.ctx.state = value,
+            },
+            Some(Outcome::Drop) => eprintln!("The expression has no value"),
+            None => (),
+        }
+        Ok(())
+    }
+
+    fn load(&mut self, path: &str) -> Result<()> {
+        let script = slurp_string(path)?;
+        let statements = statements(&script);
+        eprintln!(
+            "Loaded {} statements, `:step` runs the next one",
+            statements.len()
+        );
+        self.steps = Some(Steps {
+            statements,
+            done: 0,
+            start: self.ctx.clone(),
+        });
+        Ok(())
+    }
+
+    /// Runs the loaded script up to its next statement, returns if there
+    /// are statements left
+    fn step(&mut self) -> Result<bool> {
+        let mut steps = if let Some(steps) = self.steps.take() {
+            steps
+        } else {
+            eprintln!("No script loaded, `:load <file>` loads one");
+            return Ok(false);
+        };
+        let statement = if let Some(statement) = steps.statements.get(steps.done) {
+            statement.clone()
+        } else {
+            eprintln!("The script has no statements");
+            return Ok(false);
+        };
+        steps.done += 1;
+        // running the statements up to this one from the start keeps the
+        // locals of the previous ones
+        let script = steps
+            .statements
+            .get(..steps.done)
+            .map(|s| s.join(";\n"))
+            .unwrap_or_default();
+        eprintln!("[{}/{}] {}", steps.done, steps.statements.len(), statement);
+        let mut ctx = steps.start.clone();
+        if let Some(outcome) = self.eval(&script, &mut ctx)? {
+            self.ctx = ctx;
+            print_outcome(&outcome)?;
+        } else {
+            eprintln!("The statement failed, `:load` the script again to restart it");
+            return Ok(false);
+        }
+        if steps.done == steps.statements.len() || ends_script(&statement) {
+            eprintln!("The script is done");
+            Ok(false)
+        } else {
+            self.steps = Some(steps);
+            Ok(true)
+        }
+    }
+
+    /// Handles an input, returns `false` to leave the repl
+    fn handle(&mut self, input: &str) -> Result<bool> {
+        let (cmd, arg) = input
+            .split_once(char::is_whitespace)
+            .map_or((input, ""), |(cmd, arg)| (cmd, arg.trim()));
+        match cmd {
+            "" => (),
+            ":quit" | ":q" => return Ok(false),
+            ":help" => eprint!("{}", HELP),
+            ":event" => self.context(Part::Event, arg)?,
+            ":meta" => self.context(Part::Meta, arg)?,
+            ":state" => self.context(Part::State, arg)?,
+            ":load" => self.load(arg)?,
+            ":step" => {
+                self.step()?;
+            }
+            ":run" => while self.step()? {},
+            ":reset" => {
+                self.prelude.clear();
+                self.ctx = Context::default();
+                self.steps = None;
+            }
+            _ if cmd.starts_with(':') => {
+                eprintln!("Unknown command `{}`, `:help` shows the commands", cmd);
+            }
+            _ if is_definition(input) => self.define(input)?,
+            _ => {
+                let mut ctx = self.ctx.clone();
+                if let Some(outcome) = self.eval(input, &mut ctx)? {
+                    self.ctx = ctx;
+                    print_outcome(&outcome)?;
+                }
+            }
+        }
+        Ok(true)
+    }
+}
+
+impl Repl {
+    pub(crate) fn run(&self) -> Result<()> {
+        let mut session = Session::new(env::setup()?);
+        if let Some(event) = &self.event {
+            session.context(Part::Event, &slurp_string(event)?)?;
+        }
+        if let Some(script) = &self.script {
+            session.load(script)?;
+        }
+        eprintln!("tremor-script repl, `:help` shows the commands");
+        let stdin = io::stdin();
+        let mut input = String::new();
+        loop {
+            eprint!(
+                "{}",
+                if input.is_empty() {
+                    "tremor> "
+                } else {
+                    "   ...> "
+                }
+            );
+            io::stderr().flush()?;
+            if stdin.lock().read_line(&mut input)? == 0 {
+                return Ok(());
+            }
+            // commands are single lines, expressions continue until their
+            // blocks are closed
+            if !input.trim_start().starts_with(':') && !is_complete(&input) {
+                continue;
+            }
+            let complete = std::mem::take(&mut input);
+            if !session.handle(complete.trim())? {
+                return Ok(());
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn splits_statements() {
+        let script = r#"
+use std::string;
+let x = match event of
+  case %{ a == 1 } => "one";
+  default => "other"
+end;
+let event = patch event of
+  merge => { "b": [1, 2] }
+end;
+let y = patch {} of
+  insert "c" => merge {} of {"d": 1} end
+end;
+emit x
+"#;
+        let statements = statements(script);
+        assert_eq!(5, statements.len());
+        assert_eq!(
+            Some("use std::string"),
+            statements.first().map(String::as_str)
+        );
+        assert_eq!(Some("emit x"), statements.last().map(String::as_str));
+        assert!(ends_script("emit x"));
+        assert!(is_definition("use std::string"));
+        assert!(!is_definition("let x = 1"));
+    }
+
+    #[test]
+    fn completes_blocks() {
+        assert!(is_complete("1 + 2"));
+        assert!(!is_complete("match event of"));
+        assert!(!is_complete("{\"a\": [1,"));
+        assert!(is_complete("match event of\n  default => 1\nend"));
+        assert!(is_complete("intrinsic fn len(s) as string::len"));
+        assert!(!is_complete("fn f(x) with"));
+    }
+}
@@ -17,6 +17,7 @@ use crate::util::{get_source_kind, highlight, slurp_string, SourceKind};
 use crate::{cli::Run, env};
 use std::io::prelude::*;
 use std::io::{self, BufReader, BufWriter, Read, Write};
+use std::time::Duration;
 use tremor_common::time::nanotime;
 use tremor_common::{file, ids::OperatorIdGen};
 use tremor_pipeline::{Event, EventId};
@@ -37,6 +38,10 @@ struct Ingress {
     buffer: Box<dyn BufRead>,
     preprocessor: Box<dyn Preprocessor>,
     codec: Box<dyn Codec>,
+    interval: Option<u64>,
+    replay: bool,
+    /// ingest time of the previous event
+    last: Option<u64>,
 }
 
 type IngressHandler<T> =
@@ -71,9 +76,27 @@ impl Ingress {
             preprocessor,
             codec,
             buffer,
+            interval: cmd.interval,
+            replay: cmd.replay,
+            last: None,
         })
     }
 
+    /// Waits before an event ingested at `at` is handed on, either for the
+    /// configured interval or, when replaying, for the captured time since the
+    /// previous event
+    fn pace(&mut self, at: u64) {
+        let wait = if self.replay {
+            self.last.map(|last| at.saturating_sub(last))
+        } else {
+            self.last.and(self.interval)
+        };
+        self.last = Some(at);
+        if let Some(ns) = wait.filter(|ns| *ns > 0) {
+            std::thread::sleep(Duration::from_nanos(ns));
+        }
+    }
+
     fn process<T>(
         &mut self,
         runnable: &mut T,
@@ -101,6 +124,14 @@ impl Ingress {
                             Err(e) => return Err(e.into()),
                         };
 
+                        let (at, event) = if self.replay {
+                            let (ingest_ns, data) = replayed(&event)?;
+                            (ingest_ns.unwrap_or(at), data)
+                        } else {
+                            (at, event)
+                        };
+                        self.pace(at);
+
                         if self.is_interactive {
                             eprintln!(
                                 "ingress> [codec: {}], [preprocessor: {}]",
@@ -120,6 +151,14 @@ impl Ingress {
     }
 }
 
+/// Splits a captured record into its ingest time and data
+fn replayed<'event>(record: &Value<'event>) -> Result<(Option<u64>, Value<'event>)> {
+    let data = record
+        .get("data")
+        .ok_or_else(|| format!("Captured event without `data`: {}", record.encode()))?;
+    Ok((record.get_u64("ingest_ns"), data.clone()))
+}
+
 struct Egress {
     is_interactive: bool,
     is_pretty: bool,
@@ -163,6 +202,29 @@ impl Egress {
         })
     }
 
+    /// Writes a value with the configured codec and postprocessor
+    fn emit(&mut self, value: &Value) -> Result<()> {
+        if self.is_interactive {
+            eprintln!(
+                "egress> [codec: {}], [postprocessor: {}]",
+                self.codec.name(),
+                self.postprocessor.name()
+            );
+            highlight(self.is_pretty, value)?;
+        }
+
+        let encoded = self.codec.encode(value);
+
+        let ppd = self
+            .postprocessor
+            .process(nanotime(), nanotime(), &encoded?);
+        for packet in ppd? {
+            self.buffer.write_all(&packet)?;
+            self.buffer.flush()?;
+        }
+        Ok(())
+    }
+
     fn process(&mut self, _src: &str, event: &Value, ret: Return) -> Result<()> {
         match ret {
             Return::Drop => Ok(()),
@@ -173,26 +235,7 @@ impl Egress {
                             .write_all(format!("{}\n", value.encode()).as_bytes())?;
                         self.buffer.flush()?;
                     }
-                    _ => {
-                        if self.is_interactive {
-                            eprintln!(
-                                "egress> [codec: {}], [postprocessor: {}]",
-                                self.codec.name(),
-                                self.postprocessor.name()
-                            );
-                            highlight(self.is_pretty, &value)?;
-                        }
-
-                        let encoded = self.codec.encode(&value);
-
-                        let ppd = self
-                            .postprocessor
-                            .process(nanotime(), nanotime(), &encoded?);
-                        for packet in ppd? {
-                            self.buffer.write_all(&packet)?;
-                            self.buffer.flush()?;
-                        }
-                    }
+                    _ => self.emit(&value)?,
                 };
                 self.buffer.flush()?;
                 Ok(())
@@ -202,11 +245,7 @@ impl Egress {
                     "err" | "error" | "stderr" => {
                         eprintln!("{}", event.encode());
                     }
-                    _ => {
-                        self.buffer
-                            .write_all(format!("{}\n", event.encode()).as_bytes())?;
-                        self.buffer.flush()?;
-                    }
+                    _ => self.emit(event)?,
                 };
                 Ok(())
             }
@@ -218,7 +257,7 @@ impl Run {
     pub(crate) fn run(&self) -> Result<()> {
         match get_source_kind(&self.script) {
             SourceKind::Tremor | SourceKind::Json => self.run_tremor_source(),
-            SourceKind::Trickle => self.run_trickle_source(),
+            SourceKind::Trickle | SourceKind::Sql => self.run_trickle_source(),
             SourceKind::Unsupported(_) | SourceKind::Yaml => {
                 Err(format!("Error: Unable to execute source: {}", &self.script).into())
             }
@@ -316,6 +355,12 @@ impl Run {
             std::process::exit(1);
         }
         let raw = raw?;
+        // SQL is translated into trickle and run as such
+        let raw = if get_source_kind(&self.script) == SourceKind::Sql {
+            tremor_script::sql::to_trickle(&raw)?
+        } else {
+            raw
+        };
         let env = env::setup()?;
         let mut h = TermHighlighter::stderr();
 
@@ -421,3 +466,22 @@ impl Run {
         Ok(())
     }
 }
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use simd_json::json;
+
+    #[test]
+    fn replay_record() -> Result<()> {
+        let record = Value::from(json!({"ingest_ns": 42, "data": {"snot": "badger"}}));
+        let (ingest_ns, data) = replayed(&record)?;
+        assert_eq!(ingest_ns, Some(42));
+        assert_eq!(data, Value::from(json!({"snot": "badger"})));
+
+        let (ingest_ns, _) = replayed(&Value::from(json!({"data": 1})))?;
+        assert_eq!(ingest_ns, None);
+        assert!(replayed(&Value::from(json!({"snot": 1}))).is_err());
+        Ok(())
+    }
+}
@@ -12,19 +12,30 @@
 // See the License for the specific language governing permissions and
 // limitations under the License.
 
+use crate::alloc;
+use crate::logger::Logger;
+use crate::remote;
 use crate::{
     cli::ServerCommand,
     errors::{Error, ErrorKind, Result},
 };
 use crate::{
-    cli::ServerRun,
+    cli::{SecretStoreKind, ServerRun},
     util::{get_source_kind, SourceKind},
 };
 use async_std::task;
+use futures::future::try_join_all;
+use std::collections::HashMap;
 use std::io::Write;
-use std::sync::atomic::Ordering;
+use std::path::{Path, PathBuf};
+use std::sync::{atomic::Ordering, Arc};
+use std::time::Duration;
 use tremor_api as api;
 use tremor_common::file;
+use tremor_runtime::agent::{self, Agent};
+use tremor_runtime::cluster::{self, Cluster};
+use tremor_runtime::connectors::proxy::Proxy;
+use tremor_runtime::interpolate::{kubernetes::Kubernetes, vault::Vault, Interpolator};
 use tremor_runtime::system::World;
 use tremor_runtime::{self, version};
 
@@ -38,6 +49,18 @@ impl ServerCommand {
 impl ServerRun {
     pub(crate) fn run(&self) {
         version::print();
+        // before the runtime starts its threads, so their heaps use the options
+        if let Err(e) = alloc::configure(alloc::Options {
+            huge_pages: self.alloc_huge_pages,
+            reserve_huge_pages: self.alloc_reserve_huge_pages,
+            numa_nodes: self.alloc_numa_nodes,
+            eager_commit_delay: self.alloc_eager_commit_delay,
+            reset_delay: self.alloc_reset_delay,
+        }) {
+            eprintln!("error: {}", e);
+            // ALLOW: main.rs
+            ::std::process::exit(1);
+        }
         if let Err(ref e) = task::block_on(self.run_dun()) {
             error!("error: {}", e);
             for e in e.iter().skip(1) {
@@ -52,13 +75,18 @@ impl ServerRun {
     #[cfg(not(tarpaulin_include))]
     pub(crate) async fn run_dun(&self) -> Result<()> {
         // Logging
-        if let Some(logger_config) = &self.logger_config {
-            log4rs::init_file(logger_config, log4rs::config::Deserializers::default())?;
-        } else {
-            env_logger::init();
-        }
+        let log_levels: Option<Arc<dyn api::log_level::LogLevels>> =
+            if let Some(logger_config) = &self.logger_config {
+                log4rs::init_file(logger_config, log4rs::config::Deserializers::default())?;
+                None
+            } else {
+                Some(Logger::init(self.log_format)?)
+            };
         version::log();
-        eprintln!("allocator: {}", crate::alloc::get_allocator_name());
+        eprintln!("allocator: {}", alloc::get_allocator_name());
+        if self.alloc_stats {
+            alloc::track_allocations()?;
+        }
 
         #[cfg(feature = "bert")]
         {
@@ -85,37 +113,135 @@ impl ServerRun {
         // TODO: Allow configuring this for offramps and pipelines
         let (world, handle) = World::start(64).await?;
 
+        let mut interpolator = Interpolator::new(
+            self.allow_env.clone(),
+            self.allow_file.iter().map(PathBuf::from).collect(),
+        );
+        if self.interpolate {
+            interpolator.enable_references();
+        }
+        interpolator.register_store("kubernetes", Box::new(Kubernetes::new(&self.secrets_dir)));
+        if std::env::var("VAULT_ADDR").is_ok() || self.secrets == Some(SecretStoreKind::Vault) {
+            interpolator.register_store("vault", Box::new(Vault::from_env()?));
+        }
+        if let Some(secrets) = self.secrets {
+            interpolator.set_default_store(&secrets.to_string());
+        }
+        tremor_runtime::interpolate::set(interpolator)?;
+        tremor_runtime::connectors::proxy::set_default(self.proxy.clone().map(|url| Proxy {
+            url,
+            no_proxy: self.no_proxy.clone(),
+        }))?;
+        for dir in &self.plugin_dir {
+            let found =
+                tremor_runtime::connectors::plugin::discover(Path::new(dir)).map_err(|e| {
+                    Error::from(format!("Failed to load plugins from `{}`: {}", dir, e))
+                })?;
+            info!("Discovered {} connector plugins in `{}`", found, dir);
+        }
+        // downloaded artefacts are kept for as long as the server runs
+        let (_artefact_dir, artefacts) = remote::resolve(
+            &self.artefacts,
+            self.artefact_token.as_deref(),
+            self.artefact_retries,
+        )
+        .await?;
+        let mut trickle_files = Vec::with_capacity(16);
         let mut yaml_files = Vec::with_capacity(16);
-        // We process trickle files first
-        for config_file in &self.artefacts {
-            let kind = get_source_kind(config_file);
+        for config_file in artefacts {
+            let kind = get_source_kind(&config_file);
             match kind {
-                SourceKind::Trickle => {
-                    if let Err(e) = tremor_runtime::load_query_file(&world, config_file).await {
-                        return Err(ErrorKind::FileLoadError(config_file.to_string(), e).into());
-                    }
-                }
+                SourceKind::Trickle | SourceKind::Sql => trickle_files.push(config_file),
                 SourceKind::Tremor | SourceKind::Json | SourceKind::Unsupported(_) => {
-                    return Err(ErrorKind::UnsupportedFileType(
-                        config_file.to_string(),
-                        kind,
-                        "yaml",
-                    )
-                    .into());
+                    return Err(ErrorKind::UnsupportedFileType(config_file, kind, "yaml").into());
                 }
                 SourceKind::Yaml => yaml_files.push(config_file),
             };
         }
 
-        // We process config files thereafter
-        for config_file in yaml_files {
-            if let Err(e) = tremor_runtime::load_cfg_file(&world, config_file).await {
-                return Err(ErrorKind::FileLoadError(config_file.to_string(), e).into());
-            }
+        // We process trickle files first, they don't depend on each other
+        // so they are loaded in parallel
+        try_join_all(trickle_files.into_iter().map(|config_file| {
+            let world = world.clone();
+            task::spawn(async move {
+                tremor_runtime::load_query_file(&world, &config_file)
+                    .await
+                    .map_err(|e| Error::from(ErrorKind::FileLoadError(config_file, e)))
+            })
+        }))
+        .await?;
+
+        // We process config files thereafter, their artefacts are published
+        // in parallel and their mappings linked once all artefacts are
+        // published, as they may refer to artefacts of other files
+        let mappings = try_join_all(yaml_files.into_iter().map(|config_file| {
+            let world = world.clone();
+            task::spawn(async move {
+                let published = match tremor_runtime::read_cfg_file(&config_file).await {
+                    Ok(config) => tremor_runtime::publish_artefacts(&world, config).await,
+                    Err(e) => Err(e),
+                };
+                match published {
+                    Ok((_, mappings)) => Ok((config_file, mappings)),
+                    Err(e) => Err(Error::from(ErrorKind::FileLoadError(config_file, e))),
+                }
+            })
+        }))
+        .await?;
+        try_join_all(mappings.into_iter().map(|(config_file, mappings)| {
+            let world = world.clone();
+            task::spawn(async move {
+                tremor_runtime::link_mappings(&world, mappings)
+                    .await
+                    .map_err(|e| Error::from(ErrorKind::FileLoadError(config_file, e)))
+            })
+        }))
+        .await?;
+
+        let agent = self.controlled_by.as_ref().map(|url| {
+            Agent::start(
+                world.clone(),
+                agent::Config {
+                    url: url.clone(),
+                    interval: Duration::from_secs(self.control_interval),
+                    token: self.artefact_token.clone(),
+                },
+            )
+        });
+
+        if let Some(endpoint) = self.debug_endpoint.clone() {
+            let world = world.clone();
+            task::spawn(async move {
+                if let Err(e) = tremor_runtime::debugger::listen(world, endpoint).await {
+                    error!("Debugger Error: {}", e);
+                }
+            });
         }
 
         if !self.no_api {
-            let app = api_server(&world);
+            let audit = if let Some(audit_log) = &self.audit_log {
+                api::audit::AuditLog::with_file(audit_log).map_err(|e| {
+                    Error::from(format!("Failed to open audit log `{}`: {}", audit_log, e))
+                })?
+            } else {
+                api::audit::AuditLog::default()
+            };
+            let cluster = self.cluster_config()?.map(Cluster::start).transpose()?;
+            let state = api::State {
+                world: world.clone(),
+                audit: Arc::new(audit),
+                log_levels,
+                allocator: Some(Arc::new(alloc::Stats)),
+                tokens: self.api_token.clone(),
+                cluster: cluster.as_ref().map(|(cluster, _)| cluster.clone()),
+                agent,
+            };
+            if let Some((cluster, committed)) = cluster {
+                let mut apply = tide::Server::with_state(state.clone());
+                routes(&mut apply);
+                task::spawn(api::cluster::apply(apply, cluster, committed));
+            }
+            let app = api_server(state);
             eprintln!("Listening at: http://{}", &self.api_host);
             info!("Listening at: http://{}", &self.api_host);
 
@@ -130,6 +256,33 @@ impl ServerRun {
         warn!("World stopped");
         Ok(())
     }
+
+    fn cluster_config(&self) -> Result<Option<cluster::Config>> {
+        let id = if let Some(id) = self.cluster_id {
+            id
+        } else {
+            return Ok(None);
+        };
+        let nodes = self
+            .cluster_node
+            .iter()
+            .map(|node| {
+                let (id, url) = node.split_once('=').ok_or_else(|| {
+                    Error::from(format!(
+                        "Invalid cluster node `{}`, expected `<id>=<url>`",
+                        node
+                    ))
+                })?;
+                Ok((id.parse()?, url.to_string()))
+            })
+            .collect::<Result<HashMap<_, _>>>()?;
+        Ok(Some(cluster::Config {
+            id,
+            nodes,
+            dir: PathBuf::from(&self.cluster_dir),
+            token: self.api_token.first().cloned(),
+        }))
+    }
 }
 
 async fn handle_api_request<
@@ -150,41 +303,112 @@ async fn handle_api_request<
     })
 }
 
-fn api_server(world: &World) -> tide::Server<api::State> {
-    let mut app = tide::Server::with_state(api::State {
-        world: world.clone(),
-    });
+fn api_server(state: api::State) -> tide::Server<api::State> {
+    let mut app = tide::Server::with_state(state);
+    app.with(api::audit::Audit);
+    app.with(api::namespace::Authorize);
+    app.with(api::cluster::Replicate);
+    routes(&mut app);
+    app
+}
 
+/// Routes of the API, also used to apply changes replicated by the cluster
+fn routes(app: &mut tide::Server<api::State>) {
     app.at("/version")
         .get(|r| handle_api_request(r, api::version::get));
-    app.at("/binding")
+    app.at("/audit")
+        .get(|r| handle_api_request(r, api::audit::get));
+    app.at("/log-level")
+        .get(|r| handle_api_request(r, api::log_level::get))
+        .put(|r| handle_api_request(r, api::log_level::put));
+    app.at("/agent")
+        .get(|r| handle_api_request(r, api::agent::get));
+    app.at("/drops")
+        .get(|r| handle_api_request(r, api::drops::get));
+    app.at("/stats/allocator")
+        .get(|r| handle_api_request(r, api::stats::get_allocator));
+    app.at("/backfill")
+        .get(|r| handle_api_request(r, api::backfill::get));
+    app.at("/debugger")
+        .get(|r| handle_api_request(r, api::debugger::get));
+    app.at("/flag")
+        .get(|r| handle_api_request(r, api::flag::list));
+    app.at("/flag/:name")
+        .get(|r| handle_api_request(r, api::flag::get))
+        .put(|r| handle_api_request(r, api::flag::put))
+        .delete(|r| handle_api_request(r, api::flag::remove));
+    app.at("/cluster")
+        .get(|r| handle_api_request(r, api::cluster::status));
+    app.at("/cluster/vote")
+        .post(|r| handle_api_request(r, api::cluster::vote));
+    app.at("/cluster/append")
+        .post(|r| handle_api_request(r, api::cluster::append));
+    app.at("/cluster/snapshot")
+        .post(|r| handle_api_request(r, api::cluster::snapshot));
+    app.at("/cluster/submit")
+        .post(|r| handle_api_request(r, api::cluster::submit));
+    artefact_routes(app, "");
+    app.at("/ns")
+        .get(|r| handle_api_request(r, api::namespace::list))
+        .post(|r| handle_api_request(r, api::namespace::create));
+    app.at("/ns/:namespace")
+        .get(|r| handle_api_request(r, api::namespace::get))
+        .delete(|r| handle_api_request(r, api::namespace::remove));
+    app.at("/ns/:namespace/quota")
+        .put(|r| handle_api_request(r, api::namespace::set_quota));
+    artefact_routes(app, "/ns/:namespace");
+}
+
+/// Routes for the artefacts, `prefix` is empty or a namespace
+fn artefact_routes(app: &mut tide::Server<api::State>, prefix: &str) {
+    app.at(&format!("{}/binding", prefix))
         .get(|r| handle_api_request(r, api::binding::list_artefact))
         .post(|r| handle_api_request(r, api::binding::publish_artefact));
-    app.at("/binding/:aid")
+    app.at(&format!("{}/binding/:aid", prefix))
         .get(|r| handle_api_request(r, api::binding::get_artefact))
         .delete(|r| handle_api_request(r, api::binding::unpublish_artefact));
-    app.at("/binding/:aid/:sid")
+    app.at(&format!("{}/binding/:aid/:sid", prefix))
         .get(|r| handle_api_request(r, api::binding::get_servant))
         .post(|r| handle_api_request(r, api::binding::link_servant))
         .delete(|r| handle_api_request(r, api::binding::unlink_servant));
-    app.at("/pipeline")
+    app.at(&format!("{}/pipeline", prefix))
         .get(|r| handle_api_request(r, api::pipeline::list_artefact))
         .post(|r| handle_api_request(r, api::pipeline::publish_artefact));
-    app.at("/pipeline/:aid")
+    app.at(&format!("{}/pipeline/:aid", prefix))
         .get(|r| handle_api_request(r, api::pipeline::get_artefact))
         .delete(|r| handle_api_request(r, api::pipeline::unpublish_artefact));
-    app.at("/onramp")
+    app.at(&format!("{}/pipeline/:aid/versions", prefix))
+        .get(|r| handle_api_request(r, api::pipeline::list_versions));
+    app.at(&format!("{}/pipeline/:aid/versions/:version", prefix))
+        .get(|r| handle_api_request(r, api::pipeline::get_version))
+        .put(|r| handle_api_request(r, api::pipeline::activate_version));
+    app.at(&format!("{}/pipeline/:aid/rollback", prefix))
+        .post(|r| handle_api_request(r, api::pipeline::rollback));
+    app.at(&format!("{}/pipeline/:aid/canary", prefix))
+        .post(|r| handle_api_request(r, api::pipeline::canary));
+    app.at(&format!("{}/pipeline/:aid/:sid/snapshot", prefix))
+        .post(|r| handle_api_request(r, api::pipeline::snapshot));
+    app.at(&format!("{}/pipeline/:aid/:sid/restore", prefix))
+        .post(|r| handle_api_request(r, api::pipeline::restore));
+    app.at(&format!("{}/onramp", prefix))
         .get(|r| handle_api_request(r, api::onramp::list_artefact))
         .post(|r| handle_api_request(r, api::onramp::publish_artefact));
-    app.at("/onramp/:aid")
+    app.at(&format!("{}/onramp/:aid", prefix))
         .get(|r| handle_api_request(r, api::onramp::get_artefact))
         .delete(|r| handle_api_request(r, api::onramp::unpublish_artefact));
-    app.at("/offramp")
+    app.at(&format!("{}/offramp", prefix))
         .get(|r| handle_api_request(r, api::offramp::list_artefact))
         .post(|r| handle_api_request(r, api::offramp::publish_artefact));
-    app.at("/offramp/:aid")
+    app.at(&format!("{}/offramp/:aid", prefix))
         .get(|r| handle_api_request(r, api::offramp::get_artefact))
         .delete(|r| handle_api_request(r, api::offramp::unpublish_artefact));
-
-    app
+    app.at(&format!("{}/offramp/:aid/state", prefix))
+        .get(|r| handle_api_request(r, api::offramp::get_state))
+        .put(|r| handle_api_request(r, api::offramp::set_state));
+    app.at(&format!("{}/offramp/:aid/:sid/journal", prefix))
+        .get(|r| handle_api_request(r, api::offramp::list_journal));
+    app.at(&format!("{}/offramp/:aid/:sid/journal/replay", prefix))
+        .post(|r| handle_api_request(r, api::offramp::replay_journal));
+    app.at(&format!("{}/offramp/:aid/:sid/journal/:id", prefix))
+        .get(|r| handle_api_request(r, api::offramp::get_journal_entry));
 }
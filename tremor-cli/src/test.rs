@@ -34,8 +34,12 @@ mod after;
 mod assert;
 mod before;
 mod command;
+mod fuzz;
 mod metadata;
 mod process;
+mod query;
+mod simulation;
+mod snapshot;
 pub mod stats;
 pub mod tag;
 mod unit;
@@ -44,11 +48,16 @@ impl Test {
     #[allow(clippy::too_many_lines)]
     pub(crate) fn run(&self, verbose: bool) -> Result<()> {
         env_logger::init();
+        let coverage = self.coverage.is_some() || self.coverage_threshold.is_some();
+        if coverage {
+            tremor_script::coverage::enable();
+        }
 
         let base_directory = tremor_common::file::canonicalize(&self.path)?;
         let mut config = TestConfig {
             quiet: self.quiet,
             verbose,
+            update: self.update,
             includes: self.includes.clone(),
             excludes: self.excludes.clone(),
             sys_filter: &[],
@@ -68,6 +77,8 @@ impl Test {
         let mut unit_stats = stats::Stats::new();
         let mut cmd_stats = stats::Stats::new();
         let mut integration_stats = stats::Stats::new();
+        let mut simulation_stats = stats::Stats::new();
+        let mut fuzz_stats = stats::Stats::new();
 
         let found: Vec<_> = found.filter_map(std::result::Result::ok).collect();
         let start = nanotime();
@@ -121,6 +132,39 @@ impl Test {
                             }
                         }
                     }
+                    TestMode::Simulation => {
+                        let (s, t) = simulation::run_simulation(
+                            PathBuf::from(&self.path).as_path(),
+                            &config,
+                            stats,
+                        )?;
+                        match t {
+                            Some(x) => {
+                                simulation_stats.merge(&s);
+                                vec![x]
+                            }
+                            None => {
+                                return Err(Error::from(
+                                    "Specified test folder is excluded from running.",
+                                ))
+                            }
+                        }
+                    }
+                    TestMode::Fuzz => {
+                        let (s, t) =
+                            fuzz::run_fuzz(PathBuf::from(&self.path).as_path(), &config, stats)?;
+                        match t {
+                            Some(x) => {
+                                fuzz_stats.merge(&s);
+                                vec![x]
+                            }
+                            None => {
+                                return Err(Error::from(
+                                    "Specified test folder is excluded from running.",
+                                ))
+                            }
+                        }
+                    }
                     // Command tests are their own beast, one singular folder might
                     // well result in many tests run
                     TestMode::Command => {
@@ -171,6 +215,16 @@ impl Test {
                             integration_stats.merge(&s);
                             t
                         }
+                        TestMode::Simulation => {
+                            let (s, t) = simulation::suite_simulation(root, &config)?;
+                            simulation_stats.merge(&s);
+                            t
+                        }
+                        TestMode::Fuzz => {
+                            let (s, t) = fuzz::suite_fuzz(root, &config)?;
+                            fuzz_stats.merge(&s);
+                            t
+                        }
                         TestMode::Command => {
                             let (s, t) = suite_command(root, &config)?;
                             cmd_stats.merge(&s);
@@ -194,21 +248,32 @@ impl Test {
         status::hr();
         status::rollups("All Benchmark", &bench_stats)?;
         status::rollups("All Integration", &integration_stats)?;
+        status::rollups("All Simulation", &simulation_stats)?;
         status::rollups("All Command", &cmd_stats)?;
+        status::rollups("All Fuzz", &fuzz_stats)?;
         status::rollups("All Unit", &unit_stats)?;
         let mut all_stats = stats::Stats::new();
         all_stats.merge(&bench_stats);
         all_stats.merge(&integration_stats);
+        all_stats.merge(&simulation_stats);
         all_stats.merge(&cmd_stats);
+        all_stats.merge(&fuzz_stats);
         all_stats.merge(&unit_stats);
         status::rollups("Total", &all_stats)?;
         let mut stats_map = HashMap::new();
         stats_map.insert("all".to_string(), all_stats.clone());
         stats_map.insert("bench".to_string(), bench_stats);
         stats_map.insert("integration".to_string(), integration_stats);
+        stats_map.insert("simulation".to_string(), simulation_stats);
         stats_map.insert("command".to_string(), cmd_stats);
+        stats_map.insert("fuzz".to_string(), fuzz_stats);
         stats_map.insert("unit".to_string(), unit_stats);
         status::total_duration(elapsed)?;
+        let coverage_rate = if coverage {
+            Some(self.write_coverage()?)
+        } else {
+            None
+        };
 
         let test_run = report::TestRun {
             metadata: report::metadata(),
@@ -225,11 +290,36 @@ impl Test {
             })?;
         }
 
-        if all_stats.fail > 0 {
-            Err(ErrorKind::TestFailures(all_stats).into())
-        } else {
-            Ok(())
+        match (coverage_rate, self.coverage_threshold) {
+            _ if all_stats.fail > 0 => Err(ErrorKind::TestFailures(all_stats).into()),
+            (Some(rate), Some(threshold)) if rate < threshold => {
+                Err(ErrorKind::CoverageBelowThreshold(rate, threshold).into())
+            }
+            _ => Ok(()),
+        }
+    }
+
+    /// Prints the coverage of tremor-script by the tests and writes the lcov
+    /// report, returning the percentage of lines covered
+    fn write_coverage(&self) -> Result<f64> {
+        let coverage = tremor_script::coverage::take();
+        let (lines, lines_hit) = coverage.lines();
+        let (arms, arms_hit) = coverage.arms();
+        let rate = coverage.line_rate();
+        status::h1(
+            "Coverage",
+            &format!(
+                "{:.2}% ({} of {} lines, {} of {} arms)",
+                rate, lines_hit, lines, arms_hit, arms
+            ),
+        )?;
+        if let Some(path) = &self.coverage {
+            let mut file = file::create(path)?;
+            file.write_all(coverage.to_lcov().as_bytes()).map_err(|e| {
+                Error::from(format!("Failed to write coverage to `{}`: {}", path, e))
+            })?;
         }
+        Ok(rate)
     }
 }
 
@@ -411,6 +501,40 @@ fn suite_unit(root: &Path, conf: &TestConfig) -> Result<(stats::Stats, Vec<repor
         reports.push(report);
     }
 
+    let queries = GlobWalkerBuilder::new(root, "steps.yaml")
+        .case_insensitive(true)
+        .file_type(FileType::FILE)
+        .build()
+        .map_err(|e| format!("Unable to walk test path for query tests: {}", e))?;
+
+    for steps in queries.filter_map(std::result::Result::ok) {
+        status::h0("  Query Test Scenario", &steps.path().to_string_lossy())?;
+        let scenario_tags = tag::resolve(base, root)?;
+        status::tags(&scenario_tags, Some(&conf.includes), Some(&conf.excludes))?;
+        let report = query::run_suite(steps.path(), &scenario_tags, conf)?;
+        stats.merge(&report.stats);
+        status::stats(&report.stats, "  ")?;
+        status::duration(report.duration, "    ")?;
+        reports.push(report);
+    }
+
+    let snapshots = GlobWalkerBuilder::new(root, "snapshot.yaml")
+        .case_insensitive(true)
+        .file_type(FileType::FILE)
+        .build()
+        .map_err(|e| format!("Unable to walk test path for snapshot tests: {}", e))?;
+
+    for spec in snapshots.filter_map(std::result::Result::ok) {
+        status::h0("  Snapshot Test Scenario", &spec.path().to_string_lossy())?;
+        let scenario_tags = tag::resolve(base, root)?;
+        status::tags(&scenario_tags, Some(&conf.includes), Some(&conf.excludes))?;
+        let report = snapshot::run_suite(spec.path(), &scenario_tags, conf)?;
+        stats.merge(&report.stats);
+        status::stats(&report.stats, "  ")?;
+        status::duration(report.duration, "    ")?;
+        reports.push(report);
+    }
+
     status::rollups("  Unit", &stats)?;
 
     Ok((stats, reports))
@@ -419,6 +543,7 @@ fn suite_unit(root: &Path, conf: &TestConfig) -> Result<(stats::Stats, Vec<repor
 pub(crate) struct TestConfig {
     pub(crate) quiet: bool,
     pub(crate) verbose: bool,
+    pub(crate) update: bool,
     pub(crate) sys_filter: &'static [&'static str],
     pub(crate) includes: Vec<String>,
     pub(crate) excludes: Vec<String>,
@@ -0,0 +1,452 @@
+// Copyright 2020-2021, The Tremor Team
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+//! Property based tests of codecs and their pre- and postprocessor chains
+//!
+//! A test folder holds a `fuzz.yaml` describing the chain:
+//!
+//! ```yaml
+//! codec: json
+//! preprocessors: [lines]
+//! postprocessors: [lines]
+//! # number of generated cases (default: 1000)
+//! iterations: 1000
+//! # seed of the generator, a random one is picked and reported if absent
+//! seed: 42
+//! # maximal length of mutated inputs in bytes (default: 4096)
+//! max_len: 4096
+//! ```
+//!
+//! Cases are either random values, which must survive encoding,
+//! postprocessing, preprocessing and decoding unchanged, or mutations of
+//! known inputs, which must be decoded or rejected without panicking. Values
+//! decoded from mutated inputs must survive the round trip as well.
+//!
+//! Known inputs are the files in `corpus/` and `crashes/`, raw inputs or,
+//! with a `.json` extension, values to round trip. They are checked before
+//! any generated case. Failing cases are written to `crashes/` so they are
+//! replayed by every later run until they pass and are moved to the corpus.
+
+use super::stats;
+use super::tag;
+use super::{super::status, TestConfig};
+use crate::errors::{Error, Result};
+use crate::report;
+use crate::util::{basename, slurp_string};
+use globwalk::{FileType, GlobWalkerBuilder};
+use rand::rngs::StdRng;
+use rand::{Rng, SeedableRng};
+use std::collections::HashMap;
+use std::io::Read;
+use std::panic::{self, AssertUnwindSafe};
+use std::path::Path;
+use tremor_common::file;
+use tremor_common::time::nanotime;
+use tremor_runtime::codec::{self, Codec};
+use tremor_runtime::postprocessor::{self, Postprocessors};
+use tremor_runtime::preprocessor::{self, Preprocessors};
+use tremor_script::prelude::*;
+use tremor_script::Value;
+
+const CORPUS: &str = "corpus";
+const CRASHES: &str = "crashes";
+/// number of inputs kept for mutation
+const POOL: usize = 64;
+
+#[derive(Deserialize, Debug)]
+#[serde(deny_unknown_fields)]
+struct Spec {
+    codec: String,
+    #[serde(default)]
+    preprocessors: Vec<String>,
+    #[serde(default)]
+    postprocessors: Vec<String>,
+    #[serde(default = "d_iterations")]
+    iterations: u64,
+    seed: Option<u64>,
+    #[serde(default = "d_max_len")]
+    max_len: usize,
+}
+
+fn d_iterations() -> u64 {
+    1000
+}
+
+fn d_max_len() -> usize {
+    4096
+}
+
+/// A fresh codec chain, cases don't share the state of preprocessors
+struct Chain {
+    codec: Box<dyn Codec>,
+    pre: Preprocessors,
+    post: Postprocessors,
+}
+
+impl Chain {
+    fn new(spec: &Spec) -> Result<Self> {
+        Ok(Self {
+            codec: codec::lookup(&spec.codec)?,
+            pre: preprocessor::make_preprocessors(&spec.preprocessors)?,
+            post: postprocessor::make_postprocessors(&spec.postprocessors)?,
+        })
+    }
+
+    fn decode(&mut self, input: &[u8]) -> Result<Vec<Value<'static>>> {
+        let mut ingest_ns = 0;
+        let mut chunks = vec![input.to_vec()];
+        for pp in &mut self.pre {
+            let mut next = Vec::new();
+            for chunk in &chunks {
+                next.append(&mut pp.process(&mut ingest_ns, chunk)?);
+            }
+            chunks = next;
+        }
+        let mut values = Vec::new();
+        for mut chunk in chunks {
+            if let Some(value) = self.codec.decode(&mut chunk, ingest_ns)? {
+                values.push(value.into_static());
+            }
+        }
+        Ok(values)
+    }
+
+    fn encode(&mut self, value: &Value) -> Result<Vec<u8>> {
+        let encoded = self.codec.encode(value)?;
+        Ok(postprocessor::postprocess(&mut self.post, 0, encoded)?.concat())
+    }
+}
+
+/// Runs `f`, turning a panic into an error message
+fn guard<T, F: FnOnce() -> T>(f: F) -> std::result::Result<T, String> {
+    panic::catch_unwind(AssertUnwindSafe(f)).map_err(|e| {
+        e.downcast_ref::<&str>()
+            .map(ToString::to_string)
+            .or_else(|| e.downcast_ref::<String>().cloned())
+            .unwrap_or_default()
+    })
+}
+
+/// Checks that a value survives the chain, returns the failure if any
+fn round_trip(spec: &Spec, value: &Value<'static>) -> Result<Option<String>> {
+    let mut chain = Chain::new(spec)?;
+    let encoded = match guard(|| chain.encode(value)) {
+        Err(msg) => return Ok(Some(format!("panic while encoding {}: {}", value, msg))),
+        // values the codec can't represent are not a failure
+        Ok(Err(_)) => return Ok(None),
+        Ok(Ok(encoded)) => encoded,
+    };
+    Ok(match guard(|| chain.decode(&encoded)) {
+        Err(msg) => Some(format!("panic while decoding {}: {}", value, msg)),
+        Ok(Err(e)) => Some(format!("{} can't be decoded after encoding: {}", value, e)),
+        Ok(Ok(decoded)) if decoded.len() == 1 && decoded[0] == *value => None,
+        Ok(Ok(decoded)) => Some(format!(
+            "{} is decoded as {} after encoding",
+            value,
+            Value::from(decoded)
+        )),
+    })
+}
+
+/// Checks that an input is decoded or rejected and that the decoded values
+/// survive the chain, returns the failure if any
+fn check(spec: &Spec, input: &[u8]) -> Result<Option<String>> {
+    let mut chain = Chain::new(spec)?;
+    match guard(|| chain.decode(input)) {
+        Err(msg) => Ok(Some(format!("panic while decoding: {}", msg))),
+        // rejecting invalid input is fine
+        Ok(Err(_)) => Ok(None),
+        Ok(Ok(values)) => {
+            for value in &values {
+                if let Some(failure) = round_trip(spec, value)? {
+                    return Ok(Some(failure));
+                }
+            }
+            Ok(None)
+        }
+    }
+}
+
+fn string(rng: &mut StdRng) -> String {
+    const CHARS: &[char] = &[
+        'a', 'z', 'A', '0', '9', ' ', '"', '\'', '\\', '/', ',', ':', '=', '\n', '\t', '\0', 'é',
+        'ß', '✓', '🦀',
+    ];
+    (0..rng.gen_range(0..16))
+        .map(|_| CHARS[rng.gen_range(0..CHARS.len())])
+        .collect()
+}
+
+/// A random value, floats are multiples of 1/8 so they have exact decimal
+/// representations
+fn value(rng: &mut StdRng, depth: usize) -> Value<'static> {
+    let kinds = if depth == 0 { 5 } else { 7 };
+    match rng.gen_range(0..kinds) {
+        0 => Value::null(),
+        1 => Value::from(rng.gen::<bool>()),
+        2 => Value::from(rng.gen::<i64>()),
+        3 => Value::from(f64::from(rng.gen::<i32>()) / 8.0),
+        4 => Value::from(string(rng)),
+        5 => Value::from(
+            (0..rng.gen_range(0..4))
+                .map(|_| value(rng, depth - 1))
+                .collect::<Vec<_>>(),
+        ),
+        _ => {
+            let mut record = Value::object();
+            for _ in 0..rng.gen_range(0..4) {
+                record.try_insert(string(rng), value(rng, depth - 1));
+            }
+            record
+        }
+    }
+}
+
+fn mutate(rng: &mut StdRng, input: &[u8], max_len: usize) -> Vec<u8> {
+    let mut data = input.to_vec();
+    for _ in 0..rng.gen_range(1..=4) {
+        let len = data.len();
+        match rng.gen_range(0..5) {
+            0 if len > 0 => data[rng.gen_range(0..len)] ^= 1_u8 << rng.gen_range(0..8),
+            1 => data.insert(rng.gen_range(0..=len), rng.gen()),
+            2 if len > 0 => {
+                data.remove(rng.gen_range(0..len));
+            }
+            3 if len > 0 => {
+                let start = rng.gen_range(0..len);
+                let end = rng.gen_range(start..=len);
+                let at = rng.gen_range(0..=len);
+                let chunk = data[start..end].to_vec();
+                data.splice(at..at, chunk);
+            }
+            _ => data.truncate(rng.gen_range(0..=len)),
+        }
+    }
+    data.truncate(max_len);
+    data
+}
+
+/// A known or generated case
+enum Case {
+    Input(Vec<u8>),
+    Value(Value<'static>),
+}
+
+impl Case {
+    fn check(&self, spec: &Spec) -> Result<Option<String>> {
+        match self {
+            Case::Input(input) => check(spec, input),
+            Case::Value(value) => round_trip(spec, value),
+        }
+    }
+
+    /// Writes the case to the crashes of a test
+    fn save(&self, root: &Path, name: &str) -> Result<String> {
+        let (path, data) = match self {
+            Case::Input(input) => (format!("{}/{}.bin", CRASHES, name), input.clone()),
+            Case::Value(value) => (
+                format!("{}/{}.json", CRASHES, name),
+                simd_json::to_vec(value)?,
+            ),
+        };
+        std::fs::create_dir_all(root.join(CRASHES))?;
+        std::fs::write(root.join(&path), data)?;
+        Ok(path)
+    }
+}
+
+/// Loads the known cases of a test
+fn known(root: &Path) -> Result<Vec<(String, Case)>> {
+    let mut cases = Vec::new();
+    for dir in &[CORPUS, CRASHES] {
+        let dir = root.join(dir);
+        if !dir.is_dir() {
+            continue;
+        }
+        let files = GlobWalkerBuilder::new(&dir, "*")
+            .file_type(FileType::FILE)
+            .sort_by(|a, b| a.file_name().cmp(b.file_name()))
+            .max_depth(1)
+            .build()
+            .map_err(|e| Error::from(format!("Unable to walk corpus: {}", e)))?;
+        for f in files.filter_map(std::result::Result::ok) {
+            let mut data = Vec::new();
+            file::open(f.path())?.read_to_end(&mut data)?;
+            let case = if f.path().extension().map_or(false, |e| e == "json") {
+                Case::Value(Value::from(simd_json::to_owned_value(&mut data)?))
+            } else {
+                Case::Input(data)
+            };
+            cases.push((f.path().to_string_lossy().to_string(), case));
+        }
+    }
+    Ok(cases)
+}
+
+/// Runs a fuzz test, returning the failures by case
+fn fuzz(root: &Path, spec: &Spec, seed: u64) -> Result<Vec<(String, String)>> {
+    let mut failures = Vec::new();
+    let mut pool = Vec::new();
+    for (name, case) in known(root)? {
+        if let Some(failure) = case.check(spec)? {
+            failures.push((name, failure));
+        }
+        if let Case::Input(input) = case {
+            pool.push(input);
+        }
+    }
+
+    let mut rng = StdRng::seed_from_u64(seed);
+    for i in 0..spec.iterations {
+        let case = if pool.is_empty() || rng.gen::<bool>() {
+            let value = value(&mut rng, 3);
+            // encodings of generated values are the inputs for mutations
+            if let Ok(Ok(input)) = guard(|| Chain::new(spec).and_then(|mut c| c.encode(&value))) {
+                if pool.len() >= POOL {
+                    pool.swap_remove(rng.gen_range(0..pool.len()));
+                }
+                pool.push(input);
+            }
+            Case::Value(value)
+        } else {
+            let input = &pool[rng.gen_range(0..pool.len())];
+            Case::Input(mutate(&mut rng, input, spec.max_len))
+        };
+        if let Some(failure) = case.check(spec)? {
+            let path = case.save(root, &format!("{}-{}", seed, i))?;
+            failures.push((path, failure));
+        }
+    }
+    Ok(failures)
+}
+
+pub(crate) fn suite_fuzz(
+    root: &Path,
+    config: &TestConfig,
+) -> Result<(stats::Stats, Vec<report::TestReport>)> {
+    if let Ok(tests) = GlobWalkerBuilder::new(root, &config.meta.includes)
+        .case_insensitive(true)
+        .file_type(FileType::DIR)
+        .build()
+    {
+        let tests = tests.filter_map(std::result::Result::ok);
+
+        let mut suite = vec![];
+        let mut stats = stats::Stats::new();
+
+        status::h0("Framework", "Finding fuzz test scenarios")?;
+
+        for test in tests {
+            let (s, t) = run_fuzz(test.path(), config, stats)?;
+
+            stats = s;
+            if let Some(report) = t {
+                suite.push(report);
+            }
+        }
+
+        status::rollups("\n  Fuzz", &stats)?;
+
+        Ok((stats, suite))
+    } else {
+        Err("Unable to walk test path for fuzz tests".into())
+    }
+}
+
+pub(crate) fn run_fuzz(
+    root: &Path,
+    config: &TestConfig,
+    mut stats: stats::Stats,
+) -> Result<(stats::Stats, Option<report::TestReport>)> {
+    let base = config.base_directory.as_path();
+    let fuzz_root = root.to_string_lossy();
+    let tags = tag::resolve(base, root)?;
+
+    let (matched, is_match) = config.matches(&tags);
+    if !is_match {
+        stats.skip();
+        status::h1("Fuzz", &format!("Skipping {}", &basename(&fuzz_root)))?;
+        status::tags(&tags, Some(&matched), Some(&config.excludes))?;
+        return Ok((stats, None));
+    }
+    let spec: Spec = serde_yaml::from_str(&slurp_string(root.join("fuzz.yaml"))?)?;
+    let seed = spec.seed.unwrap_or_else(|| rand::thread_rng().gen());
+    status::h1(
+        "Fuzz",
+        &format!("Running {} with seed {}", &basename(&fuzz_root), seed),
+    )?;
+    status::tags(&tags, Some(&matched), Some(&config.excludes))?;
+
+    let start = nanotime();
+    // panics are reported as failures, not printed
+    let hook = panic::take_hook();
+    panic::set_hook(Box::new(|_| {}));
+    let failures = fuzz(root, &spec, seed);
+    panic::set_hook(hook);
+    let failures = failures?;
+    let elapsed = nanotime() - start;
+
+    let mut test_stats = stats::Stats::new();
+    let mut elements = Vec::new();
+    for (case, failure) in &failures {
+        status::text("      ", &format!("{}: {}", case, failure))?;
+        elements.push(report::TestElement {
+            description: format!("(-) {}", case),
+            keyword: report::KeywordKind::Test,
+            result: report::ResultKind {
+                status: report::StatusKind::Failed,
+                duration: 0,
+            },
+            info: Some(failure.clone()),
+            hidden: false,
+        });
+    }
+    test_stats.assert();
+    let status = test_stats.report(failures.is_empty(), &fuzz_root);
+    elements.push(report::TestElement {
+        description: format!("{} cases with seed {}", spec.iterations, seed),
+        keyword: report::KeywordKind::Test,
+        result: report::ResultKind {
+            status,
+            duration: elapsed,
+        },
+        info: None,
+        hidden: false,
+    });
+    stats.merge(&test_stats);
+    status::stats(&test_stats, "  ")?;
+    status::duration(elapsed, "    ")?;
+
+    let mut suites = HashMap::new();
+    suites.insert(
+        "fuzz".to_string(),
+        report::TestSuite {
+            name: basename(&fuzz_root),
+            description: format!("fuzz test of the {} codec", spec.codec),
+            elements,
+            evidence: None,
+            stats: test_stats.clone(),
+            duration: elapsed,
+        },
+    );
+    Ok((
+        stats,
+        Some(report::TestReport {
+            description: "Tremor Test Report".into(),
+            elements: suites,
+            stats: test_stats,
+            duration: elapsed,
+        }),
+    ))
+}
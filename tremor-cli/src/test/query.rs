@@ -0,0 +1,302 @@
+// Copyright 2020-2021, The Tremor Team
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+//! Query tests drive a trickle query with a virtual clock.
+//!
+//! A query test is a folder containing a `query.trickle` and a `steps.yaml`
+//! that lists the steps to execute in order:
+//!
+//! ```yaml
+//! - event: {"snot": "badger"}  # send an event to `in` at the current time
+//! - advance: 10000000000       # advance the clock by 10s and send a tick
+//! - tick                       # send a tick at the current time
+//! - expect:                    # assert the events emitted since the last expectation
+//!     port: out                # defaults to `out`
+//!     events: [1]
+//! ```
+//!
+//! The clock starts at `0` and only moves on `advance`, so window emissions
+//! are deterministic.
+
+use crate::env;
+use crate::errors::{Error, Result};
+use crate::report;
+use crate::status;
+use crate::test::{stats, tag, TestConfig};
+use crate::util::slurp_string;
+use simd_json::OwnedValue;
+use std::collections::HashMap;
+use std::path::Path;
+use tremor_common::ids::OperatorIdGen;
+use tremor_common::time::nanotime;
+use tremor_pipeline::{Event, EventId, ExecutableGraph, SignalKind};
+use tremor_script::highlighter::Term as TermHighlighter;
+use tremor_script::prelude::*;
+use tremor_script::query::Query;
+use tremor_script::script::Script;
+
+/// A step of a query test
+#[derive(Deserialize, Debug, PartialEq)]
+#[serde(rename_all = "lowercase")]
+enum Step {
+    /// Sends an event to `in` at the current virtual time
+    Event(OwnedValue),
+    /// Advances the virtual time by the given nanoseconds and sends a tick
+    Advance(u64),
+    /// Sends a tick at the current virtual time
+    Tick,
+    /// Asserts on the events emitted on a port since the last expectation
+    Expect(Expectation),
+}
+
+#[derive(Deserialize, Debug, PartialEq)]
+#[serde(deny_unknown_fields)]
+struct Expectation {
+    #[serde(default = "default_port")]
+    port: String,
+    events: Vec<OwnedValue>,
+}
+
+fn default_port() -> String {
+    "out".to_string()
+}
+
+/// Drives a pipeline with a virtual clock, collecting the emitted events
+pub(crate) struct Runner {
+    pipeline: ExecutableGraph,
+    /// virtual time in nanoseconds
+    now: u64,
+    id: u64,
+    /// events emitted by port
+    pub(crate) emitted: HashMap<String, Vec<Value<'static>>>,
+}
+
+impl Runner {
+    pub(crate) fn new(pipeline: ExecutableGraph) -> Self {
+        Self {
+            pipeline,
+            now: 0,
+            id: 0,
+            emitted: HashMap::new(),
+        }
+    }
+
+    fn collect<P: ToString>(&mut self, returns: Vec<(P, Event)>) {
+        for (port, event) in returns {
+            let emitted = self.emitted.entry(port.to_string()).or_default();
+            emitted.extend(event.value_iter().map(Value::clone_static));
+        }
+    }
+
+    /// Moves the clock forward to `ns`, sending a tick if it moved
+    pub(crate) fn advance_to(&mut self, ns: u64) -> Result<()> {
+        if ns > self.now {
+            self.now = ns;
+            self.tick()?;
+        }
+        Ok(())
+    }
+
+    /// Sends an event to `in` at the current virtual time
+    pub(crate) fn send(&mut self, data: Value<'static>) -> Result<()> {
+        let event = Event {
+            id: EventId::new(0, 0, self.id),
+            data: data.into(),
+            ingest_ns: self.now,
+            ..Event::default()
+        };
+        self.id += 1;
+        let mut returns = vec![];
+        self.pipeline.enqueue("in", event, &mut returns)?;
+        self.collect(returns);
+        Ok(())
+    }
+
+    fn tick(&mut self) -> Result<()> {
+        let signal = Event {
+            ingest_ns: self.now,
+            kind: Some(SignalKind::Tick),
+            ..Event::default()
+        };
+        let mut returns = vec![];
+        self.pipeline.enqueue_signal(signal, &mut returns)?;
+        self.collect(returns);
+        Ok(())
+    }
+
+    /// Executes a step, returning the outcome of expectations
+    fn step(&mut self, step: &Step) -> Result<Option<(bool, String)>> {
+        match step {
+            Step::Event(data) => self.send(Value::from(data.clone()))?,
+            Step::Advance(ns) => {
+                self.now += ns;
+                self.tick()?;
+            }
+            Step::Tick => self.tick()?,
+            Step::Expect(Expectation { port, events }) => {
+                let expected =
+                    Value::from(events.iter().cloned().map(Value::from).collect::<Vec<_>>());
+                let got = Value::from(self.emitted.remove(port).unwrap_or_default());
+                let success = expected == got;
+                let info = if success {
+                    format!("{}: {}", port, expected.encode())
+                } else {
+                    format!("{}: {} != {}", port, expected.encode(), got.encode())
+                };
+                return Ok(Some((success, info)));
+            }
+        }
+        Ok(None)
+    }
+}
+
+/// Loads a trickle query as a pipeline, printing errors in it
+pub(crate) fn load_pipeline(query_path: &Path) -> Result<ExecutableGraph> {
+    let file_name = query_path.to_string_lossy().to_string();
+    let raw = slurp_string(query_path)?;
+    let env = env::setup()?;
+    let query = match Query::parse(
+        &env.module_path,
+        &file_name,
+        &raw,
+        vec![],
+        &env.fun,
+        &env.aggr,
+    ) {
+        Ok(query) => query,
+        Err(e) => {
+            let mut h = TermHighlighter::default();
+            if let Err(e) = Script::format_error_from_script(&raw, &mut h, &e) {
+                eprintln!("Error: {}", e);
+            };
+            return Err(Error::from(format!("Invalid query {}", file_name)));
+        }
+    };
+    Ok(tremor_pipeline::query::Query(query).to_pipe(&mut OperatorIdGen::new())?)
+}
+
+fn parse_steps(raw: &str) -> Result<Vec<Step>> {
+    Ok(serde_yaml::from_str(raw)?)
+}
+
+pub(crate) fn run_suite(
+    steps_path: &Path,
+    scenario_tags: &tag::TagFilter,
+    config: &TestConfig,
+) -> Result<report::TestReport> {
+    let report_start = nanotime();
+    let query_path = steps_path.with_file_name("query.trickle");
+    let name = steps_path
+        .parent()
+        .and_then(Path::file_name)
+        .map(|n| n.to_string_lossy().to_string())
+        .unwrap_or_default();
+    let mut stats = stats::Stats::new();
+    let mut elements = Vec::new();
+
+    if let (_matched, true) = config.matches(scenario_tags) {
+        let steps = parse_steps(&slurp_string(steps_path)?)?;
+        let mut runner = Runner::new(load_pipeline(&query_path)?);
+
+        let ll = steps
+            .iter()
+            .filter(|s| matches!(s, Step::Expect(_)))
+            .count();
+        let mut idx = 0;
+        for step in &steps {
+            let start = nanotime();
+            if let Some((success, info)) = runner.step(step)? {
+                let test_name = format!("{} expectation {}", name, idx + 1);
+                let report = stats.report(success, &test_name);
+                let hidden = config.quiet && success;
+                if !hidden {
+                    status::executing_unit_testcase(idx, ll, success)?;
+                    if !success {
+                        println!("             | {}", info);
+                    }
+                }
+                elements.push(report::TestElement {
+                    description: format!(
+                        "{} Executing test {} of {}",
+                        if success { "(+)" } else { "(-)" },
+                        idx + 1,
+                        ll
+                    ),
+                    keyword: report::KeywordKind::Test,
+                    result: report::ResultKind {
+                        status: report,
+                        duration: nanotime() - start,
+                    },
+                    info: Some(info),
+                    hidden,
+                });
+                stats.assert();
+                idx += 1;
+            }
+        }
+    } else {
+        stats.skip();
+    }
+
+    let mut suites = HashMap::new();
+    suites.insert(
+        name.clone(),
+        report::TestSuite {
+            name: name.clone(),
+            description: name,
+            elements,
+            evidence: None,
+            stats: stats.clone(),
+            duration: nanotime() - report_start,
+        },
+    );
+    Ok(report::TestReport {
+        description: "query test suites".into(),
+        elements: suites,
+        stats,
+        duration: nanotime() - report_start,
+    })
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use simd_json::json;
+
+    #[test]
+    fn steps() -> Result<()> {
+        let steps = parse_steps(
+            r#"
+- event: {"snot": "badger"}
+- advance: 10
+- tick
+- expect:
+    events: [1]
+"#,
+        )?;
+        assert_eq!(
+            steps,
+            vec![
+                Step::Event(json!({"snot": "badger"})),
+                Step::Advance(10),
+                Step::Tick,
+                Step::Expect(Expectation {
+                    port: "out".to_string(),
+                    events: vec![json!(1)]
+                })
+            ]
+        );
+        Ok(())
+    }
+}
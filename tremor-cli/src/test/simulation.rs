@@ -0,0 +1,215 @@
+// Copyright 2020-2021, The Tremor Team
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+//! Integration tests run as deterministic simulations
+//!
+//! A test folder holds the artefacts of a deployment, a `simulation.yaml`
+//! with the fixtures replacing its onramps, and an `assert.yaml`. The events
+//! each offramp instance receives are written to
+//! `sim.<offramp>.<instance>.log`, one JSON encoded event per line, for the
+//! assertions to check.
+
+use super::assert;
+use super::stats;
+use super::tag;
+use super::{super::status, TestConfig};
+use crate::errors::{Error, Result};
+use crate::report;
+use crate::util::{basename, slurp_string};
+use async_std::task;
+use globwalk::{FileType, GlobWalkerBuilder};
+use std::collections::HashMap;
+use std::io::Write;
+use std::path::Path;
+use tremor_common::file;
+use tremor_common::time::nanotime;
+use tremor_runtime::simulation::{self, Report, Simulation};
+
+pub(crate) fn suite_simulation(
+    root: &Path,
+    config: &TestConfig,
+) -> Result<(stats::Stats, Vec<report::TestReport>)> {
+    if let Ok(tests) = GlobWalkerBuilder::new(root, &config.meta.includes)
+        .case_insensitive(true)
+        .file_type(FileType::DIR)
+        .build()
+    {
+        let tests = tests.filter_map(std::result::Result::ok);
+
+        let mut suite = vec![];
+        let mut stats = stats::Stats::new();
+
+        status::h0("Framework", "Finding simulation test scenarios")?;
+
+        for test in tests {
+            let (s, t) = run_simulation(test.path(), config, stats)?;
+
+            stats = s;
+            if let Some(report) = t {
+                suite.push(report);
+            }
+        }
+
+        status::rollups("\n  Simulation", &stats)?;
+
+        Ok((stats, suite))
+    } else {
+        Err("Unable to walk test path for simulation tests".into())
+    }
+}
+
+pub(crate) fn run_simulation(
+    root: &Path,
+    config: &TestConfig,
+    mut stats: stats::Stats,
+) -> Result<(stats::Stats, Option<report::TestReport>)> {
+    let base = config.base_directory.as_path();
+    let sim_root = root.to_string_lossy();
+    let tags = tag::resolve(base, root)?;
+
+    let (matched, is_match) = config.matches(&tags);
+    if is_match {
+        status::h1("Simulation", &format!("Running {}", &basename(&sim_root)))?;
+        // Set cwd to test root, fixtures are relative to it
+        let cwd = std::env::current_dir()?;
+        std::env::set_current_dir(&root)?;
+        status::tags(&tags, Some(&matched), Some(&config.excludes))?;
+
+        let test_report = simulate(root);
+
+        // Restore cwd
+        file::set_current_dir(&cwd)?;
+        let test_report = test_report?;
+
+        if test_report.stats.is_pass() {
+            stats.pass();
+        } else {
+            stats.fail(&sim_root);
+        }
+        stats.assert += &test_report.stats.assert;
+
+        status::stats(&test_report.stats, "  ")?;
+        status::duration(test_report.duration, "    ")?;
+        Ok((stats, Some(test_report)))
+    } else {
+        stats.skip();
+        status::h1("Simulation", &format!("Skipping {}", &basename(&sim_root)))?;
+        status::tags(&tags, Some(&matched), Some(&config.excludes))?;
+        Ok((stats, None))
+    }
+}
+
+/// Loads and runs the simulation, returning the outcome
+async fn load_and_run(root: &Path) -> Result<Report> {
+    let config: simulation::Config =
+        serde_yaml::from_str(&slurp_string(root.join("simulation.yaml"))?)?;
+    let artefacts: Vec<String> = GlobWalkerBuilder::from_patterns(
+        root,
+        &[
+            "*.{yaml,trickle}",
+            "!assert.yaml",
+            "!logger.yaml",
+            "!simulation.yaml",
+        ],
+    )
+    .case_insensitive(true)
+    .sort_by(|a, b| a.file_name().cmp(b.file_name()))
+    .max_depth(1)
+    .build()
+    .map_err(|e| Error::from(format!("Unable to walk path for artefacts: {}", e)))?
+    .filter_map(|x| x.ok().map(|x| x.path().to_string_lossy().to_string()))
+    .collect();
+    let mut simulation = Simulation::load(config, &artefacts).await?;
+    Ok(simulation.run()?)
+}
+
+/// Writes the recorded events and the summary of a simulation to the logs,
+/// returning the status
+fn record(root: &Path, fg_out_file: &Path, fg_err_file: &Path) -> Result<i32> {
+    let mut fg_out = file::create(fg_out_file)?;
+    let mut fg_err = file::create(fg_err_file)?;
+    match task::block_on(load_and_run(root)) {
+        Ok(outcome) => {
+            for (offramp, instances) in &outcome.recorded {
+                for (instance, events) in instances {
+                    let log = root.join(format!("sim.{}.{}.log", offramp, instance));
+                    let mut log = file::create(&log)?;
+                    for event in events {
+                        writeln!(log, "{}", simd_json::to_string(&event.data)?)?;
+                    }
+                }
+            }
+            writeln!(
+                fg_out,
+                "{} events, {} ticks, exited: {}",
+                outcome.events, outcome.ticks, outcome.exited
+            )?;
+            for error in &outcome.errors {
+                writeln!(fg_err, "{}", error)?;
+            }
+            Ok(0)
+        }
+        Err(e) => {
+            writeln!(fg_err, "{}", e)?;
+            Ok(1)
+        }
+    }
+}
+
+fn simulate(root: &Path) -> Result<report::TestReport> {
+    let start = nanotime();
+    let fg_out_file = root.join("fg.out.log");
+    let fg_err_file = root.join("fg.err.log");
+    let status = record(root, &fg_out_file, &fg_err_file)?;
+
+    let mut evidence = HashMap::new();
+    evidence.insert("test: stdout".to_string(), slurp_string(&fg_out_file)?);
+    evidence.insert("test: stderr".to_string(), slurp_string(&fg_err_file)?);
+
+    let mut stats = stats::Stats::new();
+    let mut elements = HashMap::new();
+    let assert_path = root.join("assert.yaml");
+    let (report_stats, report) = if assert_path.is_file() {
+        let assert_yaml = assert::load_assert(&assert_path)?;
+        assert::process(&fg_out_file, &fg_err_file, Some(status), &assert_yaml)?
+    } else {
+        // without assertions a simulation passes if it ran
+        let mut s = stats::Stats::new();
+        if status == 0 {
+            s.pass();
+        } else {
+            s.fail(&basename(&root.to_string_lossy()));
+        }
+        (s, Vec::new())
+    };
+    stats.merge(&report_stats);
+    let elapsed = nanotime() - start;
+    elements.insert(
+        "simulation".to_string(),
+        report::TestSuite {
+            description: "simulation test suite".to_string(),
+            name: basename(&root.to_string_lossy()),
+            elements: report,
+            evidence: Some(evidence),
+            stats: report_stats,
+            duration: elapsed,
+        },
+    );
+    Ok(report::TestReport {
+        description: "Tremor Test Report".into(),
+        elements,
+        stats,
+        duration: elapsed,
+    })
+}
@@ -0,0 +1,328 @@
+// Copyright 2020-2021, The Tremor Team
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+//! Snapshot tests compare the outputs of a trickle query with golden files.
+//!
+//! A snapshot test is a folder containing a `query.trickle`, a
+//! `snapshot.yaml` and recorded inputs in the capture format of
+//! `tremor run --replay`, one `{"ingest_ns": <ns>, "data": <event>}` per line:
+//!
+//! ```yaml
+//! input: in.json  # the recorded inputs (default: `in.json`)
+//! drain: 0        # nanoseconds to advance the clock by after the last input
+//! ```
+//!
+//! The virtual clock moves to the `ingest_ns` of each input, sending a tick
+//! whenever it moves. The events emitted on each port are compared with the
+//! golden file `snapshots/<port>.json`, one event per line. With `--update`
+//! the golden files are rewritten from the outputs instead.
+
+use crate::errors::Result;
+use crate::report;
+use crate::status;
+use crate::test::query::{load_pipeline, Runner};
+use crate::test::{stats, tag, TestConfig};
+use crate::util::slurp_string;
+use simd_json::OwnedValue;
+use std::collections::{BTreeMap, BTreeSet, HashMap};
+use std::path::Path;
+use tremor_common::time::nanotime;
+use tremor_script::prelude::*;
+use tremor_script::Value;
+
+const SNAPSHOTS: &str = "snapshots";
+
+#[derive(Deserialize, Debug, PartialEq)]
+#[serde(deny_unknown_fields)]
+struct Spec {
+    #[serde(default = "d_input")]
+    input: String,
+    #[serde(default)]
+    drain: u64,
+}
+
+fn d_input() -> String {
+    "in.json".to_string()
+}
+
+/// A line of a capture file
+#[derive(Deserialize)]
+struct Captured {
+    ingest_ns: u64,
+    data: OwnedValue,
+}
+
+fn parse_spec(raw: &str) -> Result<Spec> {
+    // an empty spec uses the defaults
+    let raw = if raw.trim().is_empty() { "{}" } else { raw };
+    Ok(serde_yaml::from_str(raw)?)
+}
+
+/// Parses line delimited JSON
+fn parse_lines(raw: &str) -> Result<Vec<Value<'static>>> {
+    raw.lines()
+        .filter(|l| !l.trim().is_empty())
+        .map(|l| {
+            Ok(Value::from(simd_json::to_owned_value(
+                &mut l.as_bytes().to_vec(),
+            )?))
+        })
+        .collect()
+}
+
+/// Describes the differences of two values by path
+fn diff(path: &str, expected: &Value, got: &Value, out: &mut Vec<String>) {
+    if let (Some(e), Some(g)) = (expected.as_object(), got.as_object()) {
+        for (k, ev) in e.iter() {
+            let path = format!("{}.{}", path, k);
+            match g.get(k) {
+                Some(gv) => diff(&path, ev, gv, out),
+                None => out.push(format!("{}: missing, expected {}", path, ev.encode())),
+            }
+        }
+        for (k, gv) in g.iter().filter(|(k, _)| !e.contains_key(*k)) {
+            out.push(format!("{}.{}: unexpected {}", path, k, gv.encode()));
+        }
+    } else if let (Some(e), Some(g)) = (expected.as_array(), got.as_array()) {
+        if e.len() == g.len() {
+            for (i, (ev, gv)) in e.iter().zip(g).enumerate() {
+                diff(&format!("{}[{}]", path, i), ev, gv, out);
+            }
+        } else {
+            out.push(format!(
+                "{}: expected {} elements, got {}: {}",
+                path,
+                e.len(),
+                g.len(),
+                got.encode()
+            ));
+        }
+    } else if expected != got {
+        out.push(format!(
+            "{}: expected {}, got {}",
+            path,
+            expected.encode(),
+            got.encode()
+        ));
+    }
+}
+
+/// Describes the differences of the expected and emitted events of a port
+fn diff_events(expected: &[Value], got: &[Value]) -> Vec<String> {
+    let mut out = Vec::new();
+    for i in 0..expected.len().max(got.len()) {
+        let path = format!("event {}", i + 1);
+        match (expected.get(i), got.get(i)) {
+            (Some(e), Some(g)) => diff(&path, e, g, &mut out),
+            (Some(e), None) => out.push(format!("{}: missing, expected {}", path, e.encode())),
+            (None, Some(g)) => out.push(format!("{}: unexpected {}", path, g.encode())),
+            (None, None) => (),
+        }
+    }
+    out
+}
+
+fn encode_lines(events: &[Value]) -> String {
+    events.iter().map(|e| format!("{}\n", e.encode())).collect()
+}
+
+/// Runs the recorded inputs through the query, returning the emitted events
+fn record(root: &Path, spec: &Spec) -> Result<HashMap<String, Vec<Value<'static>>>> {
+    let mut runner = Runner::new(load_pipeline(&root.join("query.trickle"))?);
+    let raw = slurp_string(root.join(&spec.input))?;
+    let mut now = 0;
+    for line in raw.lines().filter(|l| !l.trim().is_empty()) {
+        let captured: Captured = simd_json::from_slice(&mut line.as_bytes().to_vec())?;
+        now = now.max(captured.ingest_ns);
+        runner.advance_to(now)?;
+        runner.send(Value::from(captured.data))?;
+    }
+    if spec.drain > 0 {
+        runner.advance_to(now + spec.drain)?;
+    }
+    Ok(runner.emitted)
+}
+
+/// Golden files by port
+fn snapshots(root: &Path) -> Result<BTreeMap<String, Vec<Value<'static>>>> {
+    let mut snapshots = BTreeMap::new();
+    let dir = root.join(SNAPSHOTS);
+    if dir.is_dir() {
+        for entry in std::fs::read_dir(&dir)? {
+            let path = entry?.path();
+            if let (Some(port), Some("json")) = (
+                path.file_stem().and_then(std::ffi::OsStr::to_str),
+                path.extension().and_then(std::ffi::OsStr::to_str),
+            ) {
+                snapshots.insert(port.to_string(), parse_lines(&slurp_string(&path)?)?);
+            }
+        }
+    }
+    Ok(snapshots)
+}
+
+/// Rewrites the golden files from the emitted events
+fn update(root: &Path, emitted: &BTreeMap<String, Vec<Value<'static>>>) -> Result<()> {
+    let dir = root.join(SNAPSHOTS);
+    for port in snapshots(root)?.keys() {
+        if !emitted.contains_key(port) {
+            std::fs::remove_file(dir.join(format!("{}.json", port)))?;
+        }
+    }
+    if !emitted.is_empty() {
+        std::fs::create_dir_all(&dir)?;
+    }
+    for (port, events) in emitted {
+        std::fs::write(dir.join(format!("{}.json", port)), encode_lines(events))?;
+    }
+    Ok(())
+}
+
+pub(crate) fn run_suite(
+    spec_path: &Path,
+    scenario_tags: &tag::TagFilter,
+    config: &TestConfig,
+) -> Result<report::TestReport> {
+    let report_start = nanotime();
+    let root = spec_path.parent().unwrap_or_else(|| Path::new("."));
+    let name = root
+        .file_name()
+        .map(|n| n.to_string_lossy().to_string())
+        .unwrap_or_default();
+    let mut stats = stats::Stats::new();
+    let mut elements = Vec::new();
+
+    if let (_matched, true) = config.matches(scenario_tags) {
+        let spec = parse_spec(&slurp_string(spec_path)?)?;
+        let start = nanotime();
+        let emitted: BTreeMap<_, _> = record(root, &spec)?
+            .into_iter()
+            .filter(|(_, events)| !events.is_empty())
+            .collect();
+        if config.update {
+            update(root, &emitted)?;
+        }
+        let mut expected = snapshots(root)?;
+        let ports: BTreeSet<String> = expected.keys().chain(emitted.keys()).cloned().collect();
+        let ll = ports.len();
+        for (idx, port) in ports.into_iter().enumerate() {
+            let expected = expected.remove(&port).unwrap_or_default();
+            let got = emitted.get(&port).map(Vec::as_slice).unwrap_or_default();
+            let differences = diff_events(&expected, got);
+            let success = differences.is_empty();
+            let info = if success {
+                format!("{}: {} events", port, got.len())
+            } else {
+                format!("{}:\n{}", port, differences.join("\n"))
+            };
+            let test_name = format!("{} snapshot of {}", name, port);
+            let report = stats.report(success, &test_name);
+            let hidden = config.quiet && success;
+            if !hidden {
+                status::executing_unit_testcase(idx, ll, success)?;
+                for difference in &differences {
+                    println!("             | {}: {}", port, difference);
+                }
+            }
+            elements.push(report::TestElement {
+                description: format!(
+                    "{} Snapshot of port {}",
+                    if success { "(+)" } else { "(-)" },
+                    port
+                ),
+                keyword: report::KeywordKind::Test,
+                result: report::ResultKind {
+                    status: report,
+                    duration: nanotime() - start,
+                },
+                info: Some(info),
+                hidden,
+            });
+            stats.assert();
+        }
+    } else {
+        stats.skip();
+    }
+
+    let mut suites = HashMap::new();
+    suites.insert(
+        name.clone(),
+        report::TestSuite {
+            name: name.clone(),
+            description: name,
+            elements,
+            evidence: None,
+            stats: stats.clone(),
+            duration: nanotime() - report_start,
+        },
+    );
+    Ok(report::TestReport {
+        description: "snapshot test suites".into(),
+        elements: suites,
+        stats,
+        duration: nanotime() - report_start,
+    })
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use simd_json::json;
+
+    fn values(vs: Vec<OwnedValue>) -> Vec<Value<'static>> {
+        vs.into_iter().map(Value::from).collect()
+    }
+
+    #[test]
+    fn spec() -> Result<()> {
+        assert_eq!(
+            Spec {
+                input: "in.json".to_string(),
+                drain: 0
+            },
+            parse_spec("")?
+        );
+        assert_eq!(
+            Spec {
+                input: "capture.json".to_string(),
+                drain: 10
+            },
+            parse_spec("input: capture.json\ndrain: 10")?
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn diffs() {
+        let expected = values(vec![
+            json!({"a": 1, "b": [1, 2], "c": {"d": "snot"}}),
+            json!(2),
+        ]);
+        let got = values(vec![
+            json!({"a": 1, "b": [1, 3], "c": {"e": "badger"}}),
+            json!(2),
+            json!(3),
+        ]);
+        assert_eq!(
+            vec![
+                "event 1.b[1]: expected 2, got 3",
+                "event 1.c.d: missing, expected \"snot\"",
+                "event 1.c.e: unexpected \"badger\"",
+                "event 3: unexpected 3",
+            ],
+            diff_events(&expected, &got)
+        );
+        assert!(diff_events(&expected, &expected).is_empty());
+    }
+}
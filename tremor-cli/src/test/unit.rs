@@ -222,6 +222,9 @@ pub(crate) fn run_suite(
             runnable.format_warnings_with(&mut h)?;
 
             let script = runnable.script.suffix();
+            if tremor_script::coverage::enabled() {
+                tremor_script::coverage::register(script)?;
+            }
 
             let context = &EventContext::new(nanotime(), None);
             let env = Env {
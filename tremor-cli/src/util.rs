@@ -12,21 +12,17 @@
 // See the License for the specific language governing permissions and
 // limitations under the License.
 
-use crate::errors::Result;
+use crate::errors::{Error, ErrorKind, Result};
 use halfbrown::HashMap;
 use serde::Deserialize;
 use std::fs;
-use std::path::Path;
+use std::io::{Read, Write};
+use std::path::{Path, PathBuf};
 use std::{ffi::OsStr, fmt};
 use tremor_common::file as cfile;
 use tremor_script::highlighter::{Highlighter, Term as TermHighlighter};
 use tremor_script::{lexer, Value};
 
-// pub(crate) enum FormatKind {
-//     Json,
-//     Yaml,
-// }
-
 // Wrapper around fs::read_to_string to provide better erros
 // TODO create a tremor_common variant of fs::read_to_string
 pub(crate) fn slurp_string<P: AsRef<Path>>(file: P) -> Result<String> {
@@ -39,166 +35,68 @@ pub(crate) struct TargetConfig {
     pub(crate) instances: HashMap<String, Vec<String>>, // TODO TremorUrl
 }
 
-// pub(crate) struct TremorApp {
-//     pub(crate) format: FormatKind,
-//     pub(crate) config: TargetConfig,
-// }
-
-// impl TremorApp {
-//     fn default_url(&self) -> Result<url::Url> {
-//         self.config
-//             .instances
-//             .get("default")
-//             .and_then(|v| v.first())
-//             .ok_or_else(|| Error::from("No default api endpoint provided in ~/.tremor/config"))
-//             .and_then(|s| Ok(url::Url::parse(s)?))
-//     }
-
-//     #[allow(clippy::map_err_ignore)] // err is () her
-//     pub(crate) fn endpoint(&self, endpoint: &str) -> Result<url::Url> {
-//         self.default_url().and_then(|mut url| {
-//             url.path_segments_mut()
-//                 .map_err(|_| Error::from("Bad endpoint api"))?
-//                 .push(endpoint);
-//             Ok(url)
-//         })
-//     }
-
-//     #[allow(clippy::map_err_ignore)] // err is () here
-//     pub(crate) fn endpoint_id(&self, endpoint: &str, id: &str) -> Result<url::Url> {
-//         self.default_url().and_then(|mut url| {
-//             url.path_segments_mut()
-//                 .map_err(|_| Error::from("Bad endpoint api"))?
-//                 .push(endpoint)
-//                 .push(id);
-//             Ok(url)
-//         })
-//     }
-
-//     #[allow(clippy::map_err_ignore)] // err is () here
-//     pub(crate) fn endpoint_id_instance(
-//         &self,
-//         endpoint: &str,
-//         id: &str,
-//         instance: &str,
-//     ) -> Result<url::Url> {
-//         self.default_url().and_then(|mut url| {
-//             url.path_segments_mut()
-//                 .map_err(|_| Error::from("Bad endpoint api"))?
-//                 .push(endpoint)
-//                 .push(id)
-//                 .push(instance);
-//             Ok(url)
-//         })
-//     }
-// }
-
-// pub(crate) fn tremor_home_dir() -> Result<PathBuf> {
-//     dirs_next::home_dir()
-//         .ok_or_else(|| Error::from("Expected home_dir"))
-//         .map(|tremor_root| tremor_root.join(".tremor"))
-// }
-
-// pub(crate) fn save_config(config: &TargetConfig) -> Result<()> {
-//     let tremor_root = tremor_home_dir()?;
-//     let dot_config = tremor_root.join("config.yaml");
-//     let raw = serde_yaml::to_vec(&config)?;
-//     let mut file = cfile::create(&dot_config)?;
-//     Ok(file.write_all(&raw)?)
-// }
-
-// pub(crate) fn load_config() -> Result<TargetConfig> {
-//     let tremor_root = tremor_home_dir()?;
-//     let dot_config = tremor_root.join("config.yaml");
-//     let mut default = TargetConfig {
-//         instances: HashMap::new(),
-//     };
-//     default.instances.insert(
-//         "default".to_string(),
-//         vec!["http://localhost:9898".to_string()],
-//     );
-//     let meta = fs::metadata(&tremor_root);
-//     match meta {
-//         Ok(meta) => {
-//             if meta.is_dir() {
-//                 let meta = fs::metadata(dot_config.clone());
-//                 match meta {
-//                     Ok(meta) => {
-//                         if meta.is_file() {
-//                             let mut source = crate::open_file(&dot_config, None)?;
-//                             let mut raw = vec![];
-//                             source.read_to_end(&mut raw)?;
-//                             Ok(serde_yaml::from_slice(raw.as_slice())?)
-//                         } else {
-//                             Ok(default)
-//                         }
-//                     }
-//                     Err(_file) => {
-//                         save_config(&default)?;
-//                         load_config()
-//                     }
-//                 }
-//             } else {
-//                 Ok(default)
-//             }
-//         }
-//         Err(_dir) => {
-//             fs::create_dir(tremor_root)?;
-//             load_config()
-//         }
-//     }
-// }
-
-// pub(crate) fn load(path_to_file: &str) -> Result<simd_json::OwnedValue> {
-//     let mut source = crate::open_file(path_to_file, None)?;
-//     let ext = cfile::extension(path_to_file)
-//         .ok_or_else(|| format!("Could not open fail path {}", path_to_file))?;
-//     let mut raw = vec![];
-//     source.read_to_end(&mut raw)?;
-
-//     if ext == "yaml" || ext == "yml" {
-//         Ok(serde_yaml::from_slice(raw.as_slice())?)
-//     } else if ext == "json" {
-//         Ok(simd_json::to_owned_value(raw.as_mut_slice())?)
-//     } else {
-//         Err(Error::from(format!("Unsupported format: {}", ext)))
-//     }
-// }
-
-// pub(crate) fn load_trickle(path_to_file: &str) -> Result<String> {
-//     let mut source = crate::open_file(path_to_file, None)?;
-//     let ext = cfile::extension(path_to_file)
-//         .ok_or_else(|| format!("Could not open fail path {}", path_to_file))?;
-//     let mut raw = String::new();
-//     source.read_to_string(&mut raw)?;
+pub(crate) fn tremor_home_dir() -> Result<PathBuf> {
+    dirs_next::home_dir()
+        .ok_or_else(|| Error::from("Expected home_dir"))
+        .map(|tremor_root| tremor_root.join(".tremor"))
+}
 
-//     if ext == "trickle" {
-//         Ok(raw)
-//     } else {
-//         Err(Error::from(format!("Unsupported format: {}", ext)))
-//     }
-// }
+pub(crate) fn save_config(config: &TargetConfig) -> Result<()> {
+    let tremor_root = tremor_home_dir()?;
+    fs::create_dir_all(&tremor_root)?;
+    let dot_config = tremor_root.join("config.yaml");
+    let raw = serde_yaml::to_vec(&config)?;
+    let mut file = cfile::create(&dot_config)?;
+    Ok(file.write_all(&raw)?)
+}
 
-// pub(crate) fn content_type(app: &TremorApp) -> &'static str {
-//     match app.format {
-//         FormatKind::Json => "application/json",
-//         FormatKind::Yaml => "application/yaml",
-//     }
-// }
+pub(crate) fn load_config() -> Result<TargetConfig> {
+    let tremor_root = tremor_home_dir()?;
+    let dot_config = tremor_root.join("config.yaml");
+    let mut default = TargetConfig {
+        instances: HashMap::new(),
+    };
+    default.instances.insert(
+        "default".to_string(),
+        vec!["http://localhost:9898".to_string()],
+    );
+    if !tremor_root.is_dir() {
+        return Ok(default);
+    }
+    if dot_config.is_file() {
+        let mut source = crate::open_file(&dot_config, None)?;
+        let mut raw = vec![];
+        source.read_to_end(&mut raw)?;
+        Ok(serde_yaml::from_slice(raw.as_slice())?)
+    } else {
+        Ok(default)
+    }
+}
 
-// pub(crate) fn accept(app: &TremorApp) -> &'static str {
-//     match app.format {
-//         FormatKind::Json => "application/json",
-//         FormatKind::Yaml => "application/yaml",
-//     }
-// }
+/// Loads a JSON or YAML file
+pub(crate) fn load(path_to_file: &str) -> Result<simd_json::OwnedValue> {
+    let mut source = crate::open_file(path_to_file, None)?;
+    let mut raw = vec![];
+    source.read_to_end(&mut raw)?;
+
+    match get_source_kind(path_to_file) {
+        SourceKind::Yaml => Ok(serde_yaml::from_slice(raw.as_slice())?),
+        SourceKind::Json => Ok(simd_json::to_owned_value(raw.as_mut_slice())?),
+        kind => Err(
+            ErrorKind::UnsupportedFileType(path_to_file.to_string(), kind, "json or yaml").into(),
+        ),
+    }
+}
 
-// pub(crate) fn ser(app: &TremorApp, json: &simd_json::OwnedValue) -> Result<String> {
-//     Ok(match app.format {
-//         FormatKind::Json => simd_json::to_string(&json)?,
-//         FormatKind::Yaml => serde_yaml::to_string(&json)?,
-//     })
-// }
+/// Loads a trickle file
+pub(crate) fn load_trickle(path_to_file: &str) -> Result<String> {
+    match get_source_kind(path_to_file) {
+        SourceKind::Trickle => slurp_string(path_to_file),
+        kind => {
+            Err(ErrorKind::UnsupportedFileType(path_to_file.to_string(), kind, "trickle").into())
+        }
+    }
+}
 
 pub(crate) type PathVisitor = dyn Fn(Option<&Path>, &Path) -> Result<()>;
 
@@ -253,6 +151,8 @@ pub enum SourceKind {
     Tremor,
     /// A trickle source file
     Trickle,
+    /// A SQL query translated into trickle
+    Sql,
     /// A json file
     Json,
     /// A yaml file
@@ -265,6 +165,7 @@ impl fmt::Display for SourceKind {
         match self {
             SourceKind::Tremor => write!(f, "tremor"),
             SourceKind::Trickle => write!(f, "trickle"),
+            SourceKind::Sql => write!(f, "sql"),
             SourceKind::Json => write!(f, "json"),
             SourceKind::Unsupported(None) => write!(f, "<NONE>"),
             SourceKind::Unsupported(Some(ext)) => write!(f, "{}", ext),
@@ -278,6 +179,7 @@ pub(crate) fn get_source_kind(path: &str) -> SourceKind {
         Some("json") => SourceKind::Json,
         Some("tremor") => SourceKind::Tremor,
         Some("trickle") => SourceKind::Trickle,
+        Some("sql") => SourceKind::Sql,
         Some("yml" | "yaml") => SourceKind::Yaml,
         otherwise => SourceKind::Unsupported(otherwise.map(ToString::to_string)),
     }
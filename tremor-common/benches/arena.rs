@@ -0,0 +1,50 @@
+// Copyright 2020-2021, The Tremor Team
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+use criterion::{black_box, criterion_group, criterion_main, Criterion};
+use std::alloc::System;
+use tremor_common::arena::{scope, Arena};
+
+#[global_allocator]
+static ALLOC: Arena<System> = Arena::new(System);
+
+/// An event with many small strings, lists and records
+fn event() -> Vec<u8> {
+    let fields: Vec<String> = (0..200)
+        .map(|i| {
+            format!(
+                r#""field{}":{{"name":"value {}","tags":["a","b","c"],"count":{}}}"#,
+                i, i, i
+            )
+        })
+        .collect();
+    format!("{{{}}}", fields.join(",")).into_bytes()
+}
+
+fn process(data: &[u8]) -> usize {
+    let mut data = data.to_vec();
+    let value = simd_json::to_owned_value(&mut data).expect("valid json");
+    simd_json::to_vec(&value).expect("serializable").len()
+}
+
+pub fn arena_benchmark(c: &mut Criterion) {
+    let data = event();
+    let mut group = c.benchmark_group("event");
+    group.bench_function("system", |b| b.iter(|| black_box(process(&data))));
+    group.bench_function("arena", |b| b.iter(|| black_box(scope(|| process(&data)))));
+    group.finish();
+}
+
+criterion_group!(benches, arena_benchmark);
+criterion_main!(benches);
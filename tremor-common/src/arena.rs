@@ -0,0 +1,452 @@
+// Copyright 2020-2021, The Tremor Team
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+//! With the `arena` feature, `Arena` wraps the global allocator. Small
+//! allocations made inside `scope`, while a thread processes an event, are
+//! bumped from a chunk owned by the thread instead of going to the wrapped
+//! allocator. Once everything allocated from the chunk is freed, the next
+//! event reuses it from its start.
+//!
+//! Allocations that outlive the event, like values moved into state or
+//! events sent on, stay valid. Every chunk counts its live allocations and
+//! is only reused once none are left, so these allocations keep their
+//! chunk alive. To not pin a whole chunk with a single allocation:
+//!
+//! * growing an allocation moves it to the wrapped allocator, as growing
+//!   buffers like queues or event sets tend to outlive the event
+//! * `escape` runs code that allocates for longer than the event on the
+//!   wrapped allocator, even inside `scope`
+//!
+//! The chunks are carved out of a region reserved from the wrapped
+//! allocator when the arena is first used. A thread hands its chunk back
+//! when it exits. Freed chunks are kept for reuse, so the arena holds on to
+//! the memory of its busiest moment.
+//!
+//! Without the feature, `scope` and `escape` only run the closure.
+
+/// Runs `f` as the processing of one event, its small allocations come from
+/// the arena of the thread if `Arena` is the global allocator
+#[cfg(not(feature = "arena"))]
+#[inline]
+pub fn scope<R>(f: impl FnOnce() -> R) -> R {
+    f()
+}
+
+/// Runs `f` with its allocations going to the wrapped allocator even inside
+/// `scope`, for allocations known to outlive the event
+#[cfg(not(feature = "arena"))]
+#[inline]
+pub fn escape<R>(f: impl FnOnce() -> R) -> R {
+    f()
+}
+
+#[cfg(feature = "arena")]
+pub use imp::{escape, scope, Arena};
+
+#[cfg(feature = "arena")]
+mod imp {
+    use std::alloc::{GlobalAlloc, Layout};
+    use std::cell::Cell;
+    use std::ptr;
+    use std::sync::atomic::{fence, AtomicBool, AtomicUsize, Ordering};
+
+    /// Size and alignment of a chunk
+    const CHUNK: usize = 256 * 1024;
+    /// Number of chunks in the region
+    const CHUNKS: usize = 4096;
+    /// Allocations larger than this go to the wrapped allocator
+    const MAX_SIZE: usize = CHUNK / 8;
+    /// Allocations aligned to more than this go to the wrapped allocator
+    const MAX_ALIGN: usize = 4096;
+    /// Free space of a chunk starts after its header
+    const HEADER: usize = 64;
+    /// The region is being reserved
+    const RESERVING: usize = 1;
+    /// The region couldn't be reserved, the arena is not used
+    const FAILED: usize = 2;
+
+    /// Start of every chunk
+    struct Header {
+        /// allocations from the chunk that weren't freed, plus one while it
+        /// is the chunk of a thread
+        live: AtomicUsize,
+        /// next chunk in the list of free chunks
+        next: AtomicUsize,
+    }
+
+    /// Address of the region the chunks are carved out of
+    static REGION: AtomicUsize = AtomicUsize::new(0);
+    /// Index of the first chunk that was never used
+    static UNUSED: AtomicUsize = AtomicUsize::new(0);
+    /// First chunk of the list of free chunks
+    static FREE: AtomicUsize = AtomicUsize::new(0);
+    /// Guards the list of free chunks, which is rarely touched
+    static FREE_LOCK: AtomicBool = AtomicBool::new(false);
+
+    /// The chunk of a thread and the offset of its free space, the chunk is
+    /// released when the thread exits
+    struct Current(Cell<(usize, usize)>);
+
+    impl Drop for Current {
+        fn drop(&mut self) {
+            let (chunk, _) = self.0.replace((0, 0));
+            if chunk != 0 {
+                // SAFETY: threads only hold chunks of the region
+                unsafe { release(chunk) };
+            }
+        }
+    }
+
+    thread_local! {
+        /// Nesting depth of `scope` on this thread
+        static DEPTH: Cell<usize> = Cell::new(0);
+        /// The chunk of this thread
+        static CURRENT: Current = Current(Cell::new((0, 0)));
+    }
+
+    /// Wraps an allocator to bump allocations inside `scope` from per
+    /// thread chunks, see the module documentation
+    #[derive(Debug)]
+    pub struct Arena<A> {
+        inner: A,
+    }
+
+    /// # Safety
+    /// `chunk` has to be the address of a chunk of the region
+    unsafe fn header<'chunk>(chunk: usize) -> &'chunk Header {
+        &*(chunk as *const Header)
+    }
+
+    fn region() -> Option<usize> {
+        let region = REGION.load(Ordering::Acquire);
+        (region > FAILED).then(|| region)
+    }
+
+    /// The chunk `ptr` was allocated from, if it was
+    fn chunk_of(ptr: *mut u8) -> Option<usize> {
+        let region = region()?;
+        let addr = ptr as usize;
+        (addr >= region && addr < region + CHUNK * CHUNKS).then(|| addr & !(CHUNK - 1))
+    }
+
+    fn in_scope() -> bool {
+        DEPTH.try_with(|depth| depth.get() > 0).unwrap_or(false)
+    }
+
+    /// Drops a reference to a chunk, it becomes free once the last
+    /// allocation from it is freed and no thread uses it any more
+    ///
+    /// # Safety
+    /// `chunk` has to be the address of a chunk of the region
+    unsafe fn release(chunk: usize) {
+        let header = header(chunk);
+        if header.live.fetch_sub(1, Ordering::Release) == 1 {
+            fence(Ordering::Acquire);
+            while FREE_LOCK
+                .compare_exchange_weak(false, true, Ordering::Acquire, Ordering::Relaxed)
+                .is_err()
+            {
+                std::hint::spin_loop();
+            }
+            header
+                .next
+                .store(FREE.load(Ordering::Relaxed), Ordering::Relaxed);
+            FREE.store(chunk, Ordering::Relaxed);
+            FREE_LOCK.store(false, Ordering::Release);
+        }
+    }
+
+    /// Resets the chunk of the thread when the outermost scope starts, if
+    /// nothing allocated from it is still alive
+    fn reset() {
+        let _ = CURRENT.try_with(|current| {
+            let (chunk, _) = current.0.get();
+            // SAFETY: threads only hold chunks of the region
+            if chunk != 0 && unsafe { header(chunk) }.live.load(Ordering::Acquire) == 1 {
+                current.0.set((chunk, HEADER));
+            }
+        });
+    }
+
+    /// Decrements the depth of `scope` even if the closure panics
+    struct Depth;
+
+    impl Drop for Depth {
+        fn drop(&mut self) {
+            let _ = DEPTH.try_with(|depth| depth.set(depth.get().saturating_sub(1)));
+        }
+    }
+
+    /// Restores the depth of `scope` after `escape`, even if the closure
+    /// panics
+    struct Restore(usize);
+
+    impl Drop for Restore {
+        fn drop(&mut self) {
+            let _ = DEPTH.try_with(|depth| depth.set(self.0));
+        }
+    }
+
+    /// Runs `f` with its allocations going to the wrapped allocator even
+    /// inside `scope`, for allocations known to outlive the event
+    #[inline]
+    pub fn escape<R>(f: impl FnOnce() -> R) -> R {
+        let _restore = Restore(DEPTH.try_with(|depth| depth.replace(0)).unwrap_or(0));
+        f()
+    }
+
+    /// Runs `f` as the processing of one event, its small allocations come
+    /// from the arena of the thread if `Arena` is the global allocator
+    #[inline]
+    pub fn scope<R>(f: impl FnOnce() -> R) -> R {
+        let outermost = DEPTH
+            .try_with(|depth| {
+                let d = depth.get();
+                depth.set(d + 1);
+                d == 0
+            })
+            .unwrap_or(false);
+        if outermost {
+            reset();
+        }
+        let _depth = Depth;
+        f()
+    }
+
+    impl<A: GlobalAlloc> Arena<A> {
+        /// Wraps `inner`, which serves everything the arena doesn't
+        #[must_use]
+        pub const fn new(inner: A) -> Self {
+            Self { inner }
+        }
+
+        /// The region, reserving it on first use. The memory of the region
+        /// is only committed by the operating system once it is touched.
+        unsafe fn reserve(&self) -> Option<usize> {
+            match REGION.load(Ordering::Acquire) {
+                0 => {
+                    if REGION
+                        .compare_exchange(0, RESERVING, Ordering::AcqRel, Ordering::Acquire)
+                        .is_err()
+                    {
+                        return region();
+                    }
+                    let layout = Layout::from_size_align_unchecked(CHUNK * CHUNKS, CHUNK);
+                    let region = self.inner.alloc(layout) as usize;
+                    let region = if region == 0 { FAILED } else { region };
+                    REGION.store(region, Ordering::Release);
+                    (region > FAILED).then(|| region)
+                }
+                // allocations go to the wrapped allocator meanwhile
+                RESERVING | FAILED => None,
+                region => Some(region),
+            }
+        }
+
+        /// A free chunk, held by the calling thread
+        unsafe fn acquire(&self) -> Option<usize> {
+            let region = self.reserve()?;
+            while FREE_LOCK
+                .compare_exchange_weak(false, true, Ordering::Acquire, Ordering::Relaxed)
+                .is_err()
+            {
+                std::hint::spin_loop();
+            }
+            let free = FREE.load(Ordering::Relaxed);
+            if free != 0 {
+                FREE.store(header(free).next.load(Ordering::Relaxed), Ordering::Relaxed);
+            }
+            FREE_LOCK.store(false, Ordering::Release);
+            let chunk = if free == 0 {
+                let index = UNUSED.fetch_add(1, Ordering::Relaxed);
+                if index >= CHUNKS {
+                    return None;
+                }
+                region + index * CHUNK
+            } else {
+                free
+            };
+            ptr::write(
+                chunk as *mut Header,
+                Header {
+                    live: AtomicUsize::new(1),
+                    next: AtomicUsize::new(0),
+                },
+            );
+            Some(chunk)
+        }
+
+        /// Bumps an allocation from the chunk of the thread, `None` outside
+        /// of `scope` or if the arena can't serve it
+        unsafe fn bump(&self, layout: Layout) -> Option<*mut u8> {
+            if layout.size() > MAX_SIZE || layout.align() > MAX_ALIGN || !in_scope() {
+                return None;
+            }
+            CURRENT
+                .try_with(|current| {
+                    let (mut chunk, mut offset) = current.0.get();
+                    let mut start = (offset + layout.align() - 1) & !(layout.align() - 1);
+                    if chunk == 0 || start + layout.size() > CHUNK {
+                        if chunk != 0 {
+                            current.0.set((0, 0));
+                            release(chunk);
+                        }
+                        chunk = self.acquire()?;
+                        offset = HEADER;
+                        start = (offset + layout.align() - 1) & !(layout.align() - 1);
+                    }
+                    header(chunk).live.fetch_add(1, Ordering::Relaxed);
+                    current.0.set((chunk, start + layout.size()));
+                    Some((chunk + start) as *mut u8)
+                })
+                .ok()
+                .flatten()
+        }
+    }
+
+    unsafe impl<A: GlobalAlloc> GlobalAlloc for Arena<A> {
+        #[inline]
+        unsafe fn alloc(&self, layout: Layout) -> *mut u8 {
+            self.bump(layout)
+                .unwrap_or_else(|| self.inner.alloc(layout))
+        }
+
+        #[inline]
+        unsafe fn alloc_zeroed(&self, layout: Layout) -> *mut u8 {
+            if let Some(ptr) = self.bump(layout) {
+                // chunks are reused, so their memory isn't zeroed
+                ptr::write_bytes(ptr, 0, layout.size());
+                ptr
+            } else {
+                self.inner.alloc_zeroed(layout)
+            }
+        }
+
+        #[inline]
+        unsafe fn dealloc(&self, ptr: *mut u8, layout: Layout) {
+            if let Some(chunk) = chunk_of(ptr) {
+                release(chunk);
+            } else {
+                self.inner.dealloc(ptr, layout);
+            }
+        }
+
+        #[inline]
+        unsafe fn realloc(&self, ptr: *mut u8, layout: Layout, new_size: usize) -> *mut u8 {
+            if chunk_of(ptr).is_none() {
+                return self.inner.realloc(ptr, layout, new_size);
+            }
+            // growing buffers tend to outlive the event, in the arena they
+            // would pin their chunk
+            let new_layout = Layout::from_size_align_unchecked(new_size, layout.align());
+            let new = self.inner.alloc(new_layout);
+            if !new.is_null() {
+                ptr::copy_nonoverlapping(ptr, new, layout.size().min(new_size));
+                self.dealloc(ptr, layout);
+            }
+            new
+        }
+    }
+
+    #[cfg(test)]
+    mod test {
+        use super::*;
+        use std::alloc::System;
+
+        static ARENA: Arena<System> = Arena::new(System);
+
+        #[test]
+        fn reuses_chunks() {
+            let layout = Layout::from_size_align(100, 8).expect("valid layout");
+            let (first, second) = unsafe {
+                let first = scope(|| ARENA.alloc(layout));
+                assert!(chunk_of(first).is_some());
+                ARENA.dealloc(first, layout);
+                // the chunk is free again and reused from its start
+                let second = scope(|| ARENA.alloc(layout));
+                ARENA.dealloc(second, layout);
+                (first, second)
+            };
+            assert_eq!(first, second);
+
+            // outside of a scope the wrapped allocator is used
+            unsafe {
+                let ptr = ARENA.alloc(layout);
+                assert!(chunk_of(ptr).is_none());
+                ARENA.dealloc(ptr, layout);
+            }
+        }
+
+        #[test]
+        fn keeps_live_allocations() {
+            let layout = Layout::from_size_align(16, 8).expect("valid layout");
+            unsafe {
+                let kept = scope(|| ARENA.alloc(layout));
+                ptr::write(kept.cast::<u64>(), 42);
+                // the chunk isn't reset while `kept` is alive
+                let other = scope(|| {
+                    let other = ARENA.alloc(layout);
+                    ptr::write(other.cast::<u64>(), 7);
+                    other
+                });
+                assert_ne!(kept, other);
+                assert_eq!(42, ptr::read(kept.cast::<u64>()));
+                ARENA.dealloc(kept, layout);
+                ARENA.dealloc(other, layout);
+
+                // growing moves the allocation to the wrapped allocator and
+                // keeps its content
+                let grown = scope(|| {
+                    let ptr = ARENA.alloc(layout);
+                    ptr::write(ptr.cast::<u64>(), 23);
+                    ARENA.realloc(ptr, layout, 1024)
+                });
+                assert!(chunk_of(grown).is_none());
+                assert_eq!(23, ptr::read(grown.cast::<u64>()));
+                ARENA.dealloc(
+                    grown,
+                    Layout::from_size_align(1024, 8).expect("valid layout"),
+                );
+            }
+        }
+
+        #[test]
+        fn escapes() {
+            let layout = Layout::from_size_align(16, 8).expect("valid layout");
+            unsafe {
+                let (escaped, scoped) = scope(|| {
+                    let escaped = escape(|| ARENA.alloc(layout));
+                    (escaped, ARENA.alloc(layout))
+                });
+                assert!(chunk_of(escaped).is_none());
+                assert!(chunk_of(scoped).is_some());
+                ARENA.dealloc(escaped, layout);
+                ARENA.dealloc(scoped, layout);
+            }
+        }
+
+        #[test]
+        fn releases_chunks_on_thread_exit() {
+            let layout = Layout::from_size_align(16, 8).expect("valid layout");
+            let kept =
+                std::thread::spawn(move || unsafe { scope(|| ARENA.alloc(layout)) as usize })
+                    .join()
+                    .expect("thread didn't panic");
+            let chunk = chunk_of(kept as *mut u8).expect("allocated from a chunk");
+            // only `kept` holds the chunk, the thread let go of it
+            assert_eq!(1, unsafe { header(chunk) }.live.load(Ordering::Acquire));
+            unsafe { ARENA.dealloc(kept as *mut u8, layout) };
+        }
+    }
+}
@@ -73,6 +73,12 @@ impl OperatorIdGen {
     pub fn new() -> Self {
         Self(OPERATOR_ID_BASE)
     }
+    #[must_use]
+    /// generator for the operators of a pipeline nested in the operator
+    /// with the id `uid`, their ids do not collide with the ids of `new`
+    pub fn nested(uid: u64) -> Self {
+        Self(OPERATOR_ID_BASE.wrapping_add(uid.wrapping_sub(OPERATOR_ID_BASE).wrapping_shl(24)))
+    }
     /// return the next id for this generator
     pub fn next_id(&mut self) -> u64 {
         self.0 = self.0.wrapping_add(1);
@@ -146,4 +152,18 @@ mod tests {
             }
         }
     }
+
+    #[test]
+    fn nested_operator_id_gen() {
+        let mut idgen = OperatorIdGen::default();
+        let ids: Vec<u64> = std::iter::repeat_with(|| idgen.next_id())
+            .take(100)
+            .collect();
+        let mut nested = OperatorIdGen::nested(ids[0]);
+        let nested_ids: Vec<u64> = std::iter::repeat_with(|| nested.next_id())
+            .take(100)
+            .collect();
+        assert!(nested_ids.iter().all(|id| !ids.contains(id)));
+        assert!(nested_ids.iter().all(|id| *id > OPERATOR_ID_BASE));
+    }
 }
@@ -22,6 +22,8 @@
     clippy::pedantic
 )]
 
+/// Per-event arena allocation
+pub mod arena;
 /// functions for async related code
 pub mod asy;
 mod errors;
@@ -11,12 +11,26 @@
 // WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
 // See the License for the specific language governing permissions and
 // limitations under the License.
+use std::sync::atomic::{AtomicU64, Ordering};
 use std::time::SystemTime;
 
-/// Get a nanosecond timestamp
+/// The virtual time in nanoseconds, `0` while the system time is used
+static VIRTUAL: AtomicU64 = AtomicU64::new(0);
+
+/// Replaces the system time returned by `nanotime` with a virtual time,
+/// for deterministic simulations, `None` switches back to the system time
+pub fn set_virtual(ns: Option<u64>) {
+    VIRTUAL.store(ns.unwrap_or_default(), Ordering::Relaxed);
+}
+
+/// Get a nanosecond timestamp, the virtual time if one is set
 #[must_use]
 #[allow(clippy::cast_possible_truncation)]
 pub fn nanotime() -> u64 {
+    let virtual_ns = VIRTUAL.load(Ordering::Relaxed);
+    if virtual_ns != 0 {
+        return virtual_ns;
+    }
     // TODO we want to turn this into u128 eventually
     SystemTime::now()
         .duration_since(SystemTime::UNIX_EPOCH)
@@ -24,3 +38,16 @@ pub fn nanotime() -> u64 {
         .expect("Our time was before the unix epoc, this is really bad!")
         .as_nanos() as u64
 }
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn virtual_time() {
+        set_virtual(Some(42));
+        assert_eq!(42, nanotime());
+        set_virtual(None);
+        assert!(nanotime() > 42);
+    }
+}
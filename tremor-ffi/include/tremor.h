@@ -0,0 +1,44 @@
+#pragma once
+
+#include <stddef.h>
+#include <stdint.h>
+
+#ifdef __cplusplus
+extern "C" {
+#endif
+
+/* An embedded tremor runtime */
+typedef struct Runtime tremor_runtime;
+
+/* Functions returning int return 0 on success and -1 on failure, the
+ * message of the last failure on the calling thread is returned by
+ * tremor_last_error until the next call into the library. */
+
+/* Creates a runtime with queues of qsize events (0 for the default), NULL on failure */
+tremor_runtime *tremor_runtime_new(size_t qsize);
+/* Stops and frees a runtime */
+void tremor_runtime_free(tremor_runtime *rt);
+
+/* Deploys a pipeline from a tremor query, `name` is its id unless the query sets one */
+int tremor_deploy_query(tremor_runtime *rt, const char *name, const char *query);
+/* Deploys a YAML configuration of onramps, offramps, bindings and mappings */
+int tremor_deploy_config(tremor_runtime *rt, const char *config);
+
+/* Opens the channel of `embedded` onramps, before deploying them */
+int tremor_open_input(tremor_runtime *rt, const char *channel);
+/* Opens the channel of `embedded` offramps, before deploying them */
+int tremor_open_output(tremor_runtime *rt, const char *channel);
+
+/* Sends a JSON event with JSON metadata, or none for a NULL `meta` */
+int tremor_send(tremor_runtime *rt, const char *channel, const char *data, const char *meta);
+/* Receives a {"data": ..., "meta": ...} JSON event, waiting up to timeout_ms,
+ * NULL if none arrived in time or on failure; free it with tremor_string_free */
+char *tremor_recv(tremor_runtime *rt, const char *channel, uint64_t timeout_ms);
+void tremor_string_free(char *s);
+
+/* The message of the last failure on the calling thread, or NULL */
+const char *tremor_last_error(void);
+
+#ifdef __cplusplus
+}
+#endif
@@ -0,0 +1,127 @@
+# Copyright 2020-2021, The Tremor Team
+#
+# Licensed under the Apache License, Version 2.0 (the "License");
+# you may not use this file except in compliance with the License.
+# You may obtain a copy of the License at
+#
+#     http://www.apache.org/licenses/LICENSE-2.0
+#
+# Unless required by applicable law or agreed to in writing, software
+# distributed under the License is distributed on an "AS IS" BASIS,
+# WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+# See the License for the specific language governing permissions and
+# limitations under the License.
+
+"""Embed the tremor event processing runtime
+
+    with Runtime() as rt:
+        rt.open_input("in")
+        rt.open_output("out")
+        rt.deploy_query("app", "select event from in into out;")
+        rt.deploy_config(open("app.yaml").read())
+        rt.send("in", {"snot": "badger"})
+        event, meta = rt.recv("out", timeout=1.0)
+"""
+
+import ctypes
+import json
+import os
+import sys
+
+__all__ = ["Runtime", "TremorError"]
+
+
+def _load():
+    name = {"darwin": "libtremor_ffi.dylib", "win32": "tremor_ffi.dll"}.get(
+        sys.platform, "libtremor_ffi.so"
+    )
+    path = os.environ.get("TREMOR_FFI_LIB", os.path.join(os.path.dirname(__file__), name))
+    lib = ctypes.CDLL(path)
+    rt = ctypes.c_void_p
+    s = ctypes.c_char_p
+    for fn, args, res in [
+        ("tremor_runtime_new", [ctypes.c_size_t], rt),
+        ("tremor_runtime_free", [rt], None),
+        ("tremor_deploy_query", [rt, s, s], ctypes.c_int),
+        ("tremor_deploy_config", [rt, s], ctypes.c_int),
+        ("tremor_open_input", [rt, s], ctypes.c_int),
+        ("tremor_open_output", [rt, s], ctypes.c_int),
+        ("tremor_send", [rt, s, s, s], ctypes.c_int),
+        # a void pointer so the string can be freed after reading it
+        ("tremor_recv", [rt, s, ctypes.c_uint64], ctypes.c_void_p),
+        ("tremor_string_free", [ctypes.c_void_p], None),
+        ("tremor_last_error", [], s),
+    ]:
+        f = getattr(lib, fn)
+        f.argtypes = args
+        f.restype = res
+    return lib
+
+
+_lib = _load()
+
+
+class TremorError(Exception):
+    """A failure reported by the tremor runtime"""
+
+
+def _check(result):
+    if result != 0:
+        raise TremorError(_lib.tremor_last_error().decode("utf-8"))
+
+
+class Runtime:
+    """An embedded tremor runtime"""
+
+    def __init__(self, qsize=0):
+        self._rt = _lib.tremor_runtime_new(qsize)
+        if not self._rt:
+            raise TremorError(_lib.tremor_last_error().decode("utf-8"))
+
+    def close(self):
+        """Stops the runtime"""
+        if self._rt:
+            _lib.tremor_runtime_free(self._rt)
+            self._rt = None
+
+    def __enter__(self):
+        return self
+
+    def __exit__(self, *_exc):
+        self.close()
+
+    def deploy_query(self, name, query):
+        """Deploys a pipeline from a tremor query"""
+        _check(_lib.tremor_deploy_query(self._rt, name.encode(), query.encode()))
+
+    def deploy_config(self, config):
+        """Deploys a YAML configuration of onramps, offramps, bindings and mappings"""
+        _check(_lib.tremor_deploy_config(self._rt, config.encode()))
+
+    def open_input(self, channel):
+        """Opens the channel of `embedded` onramps, before deploying them"""
+        _check(_lib.tremor_open_input(self._rt, channel.encode()))
+
+    def open_output(self, channel):
+        """Opens the channel of `embedded` offramps, before deploying them"""
+        _check(_lib.tremor_open_output(self._rt, channel.encode()))
+
+    def send(self, channel, event, meta=None):
+        """Sends an event, and optionally its metadata, to a channel"""
+        meta = None if meta is None else json.dumps(meta).encode()
+        _check(_lib.tremor_send(self._rt, channel.encode(), json.dumps(event).encode(), meta))
+
+    def recv(self, channel, timeout=0.0):
+        """Receives an `(event, meta)` tuple from a channel, waiting up to
+        `timeout` seconds, or `None` if none arrived in time"""
+        ptr = _lib.tremor_recv(self._rt, channel.encode(), int(timeout * 1000))
+        if not ptr:
+            error = _lib.tremor_last_error()
+            if error:
+                raise TremorError(error.decode("utf-8"))
+            return None
+        try:
+            event = json.loads(ctypes.string_at(ptr).decode("utf-8"))
+        finally:
+            _lib.tremor_string_free(ptr)
+        return event["data"], event["meta"]
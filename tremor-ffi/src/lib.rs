@@ -0,0 +1,429 @@
+// Copyright 2020-2021, The Tremor Team
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+//! C bindings for embedding the tremor runtime
+//!
+//! A host application creates a runtime, deploys pipelines and
+//! configurations from strings, and exchanges JSON encoded events with
+//! `embedded` onramps and offramps through named channels. See
+//! `include/tremor.h` for the C declarations and `python/` for a thin
+//! Python wrapper on top of them.
+//!
+//! Functions returning `int` return `0` on success and `-1` on failure,
+//! the message of the last failure on the calling thread is available from
+//! `tremor_last_error`.
+
+#![deny(warnings)]
+#![deny(missing_docs)]
+#![recursion_limit = "1024"]
+#![deny(
+    clippy::all,
+    clippy::unwrap_used,
+    clippy::unnecessary_unwrap,
+    clippy::pedantic
+)]
+
+use async_channel::Receiver;
+use async_std::future::timeout;
+use async_std::task::{self, JoinHandle};
+use hashbrown::HashMap;
+use std::cell::RefCell;
+use std::ffi::{CStr, CString};
+use std::os::raw::{c_char, c_int};
+use std::ptr;
+use std::sync::Once;
+use std::time::Duration;
+use tremor_runtime::embed::{Embedded, Injector};
+use tremor_runtime::errors::{Error, Result};
+use tremor_runtime::functions;
+use tremor_runtime::system::World;
+use tremor_value::prelude::*;
+
+static FUNCTIONS: Once = Once::new();
+
+thread_local! {
+    static LAST_ERROR: RefCell<Option<CString>> = RefCell::new(None);
+}
+
+/// A tremor runtime embedded into a host application
+pub struct Runtime {
+    world: World,
+    handle: JoinHandle<Result<()>>,
+    inputs: HashMap<String, Injector>,
+    outputs: HashMap<String, Receiver<Embedded>>,
+}
+
+impl Runtime {
+    fn new(qsize: usize) -> Result<Self> {
+        let mut loaded = Ok(());
+        FUNCTIONS.call_once(|| loaded = functions::load());
+        loaded?;
+        let (world, handle) = task::block_on(World::start(qsize))?;
+        Ok(Self {
+            world,
+            handle,
+            inputs: HashMap::new(),
+            outputs: HashMap::new(),
+        })
+    }
+
+    fn send(&self, channel: &str, data: &str, meta: Option<&str>) -> Result<()> {
+        let injector = self
+            .inputs
+            .get(channel)
+            .ok_or_else(|| Error::from(format!("Input channel {} isn't open", channel)))?;
+        let data = parse(data)?;
+        let meta = meta.map_or_else(|| Ok(Value::object()), parse)?;
+        task::block_on(injector.send(Embedded { data, meta }))
+    }
+
+    fn recv(&self, channel: &str, timeout_ms: u64) -> Result<Option<String>> {
+        let rx = self
+            .outputs
+            .get(channel)
+            .ok_or_else(|| Error::from(format!("Output channel {} isn't open", channel)))?;
+        let event = if timeout_ms == 0 {
+            rx.try_recv().ok()
+        } else {
+            task::block_on(timeout(Duration::from_millis(timeout_ms), rx.recv()))
+                .ok()
+                .transpose()?
+        };
+        Ok(event.map(|e| literal!({"data": e.data, "meta": e.meta}).encode()))
+    }
+}
+
+fn parse(json: &str) -> Result<Value<'static>> {
+    let mut bytes = json.as_bytes().to_vec();
+    Ok(tremor_value::parse_to_value(&mut bytes)?.into_static())
+}
+
+/// Reads a C string argument
+///
+/// # Safety
+///  `ptr` has to be null or point to a nul terminated string
+unsafe fn arg<'a>(ptr: *const c_char, name: &str) -> Result<&'a str> {
+    if ptr.is_null() {
+        return Err(format!("{} is null", name).into());
+    }
+    CStr::from_ptr(ptr)
+        .to_str()
+        .map_err(|e| format!("{} isn't valid UTF-8: {}", name, e).into())
+}
+
+/// Reads the runtime argument
+///
+/// # Safety
+///  `rt` has to be null or a pointer returned by `tremor_runtime_new`
+unsafe fn runtime<'a>(rt: *mut Runtime) -> Result<&'a mut Runtime> {
+    rt.as_mut()
+        .ok_or_else(|| Error::from("The runtime is null"))
+}
+
+fn set_error(e: &Error) {
+    let msg = CString::new(e.to_string().replace('\0', " ")).ok();
+    LAST_ERROR.with(|last| *last.borrow_mut() = msg);
+}
+
+/// Runs `f` with a cleared last error, recording its failure and returning
+/// `default` for it
+fn guard<T, F>(default: T, f: F) -> T
+where
+    F: FnOnce() -> Result<T>,
+{
+    LAST_ERROR.with(|last| *last.borrow_mut() = None);
+    f().unwrap_or_else(|e| {
+        set_error(&e);
+        default
+    })
+}
+
+/// Creates a runtime, with queues of `qsize` events between its artefacts
+/// (`0` for the default), returns null on failure
+#[no_mangle]
+#[must_use]
+pub extern "C" fn tremor_runtime_new(qsize: usize) -> *mut Runtime {
+    let qsize = if qsize == 0 {
+        tremor_runtime::QSIZE
+    } else {
+        qsize
+    };
+    guard(ptr::null_mut(), || {
+        Ok(Box::into_raw(Box::new(Runtime::new(qsize)?)))
+    })
+}
+
+/// Stops a runtime and frees it
+///
+/// # Safety
+///  `rt` has to be null or a pointer returned by `tremor_runtime_new` that
+///  wasn't freed yet
+#[no_mangle]
+pub unsafe extern "C" fn tremor_runtime_free(rt: *mut Runtime) {
+    if rt.is_null() {
+        return;
+    }
+    let rt = Box::from_raw(rt);
+    for injector in rt.inputs.values() {
+        injector.close();
+    }
+    guard((), || {
+        task::block_on(async {
+            rt.world.stop().await?;
+            rt.handle.await
+        })
+    });
+}
+
+/// Deploys a pipeline from a tremor query, using its `#!config id` or
+/// `name` as id
+///
+/// # Safety
+///  `rt` has to be a pointer returned by `tremor_runtime_new`, `name` and
+///  `query` nul terminated strings
+#[no_mangle]
+pub unsafe extern "C" fn tremor_deploy_query(
+    rt: *mut Runtime,
+    name: *const c_char,
+    query: *const c_char,
+) -> c_int {
+    guard(-1, || {
+        let rt = runtime(rt)?;
+        let name = arg(name, "name")?;
+        let query = arg(query, "query")?;
+        task::block_on(rt.world.load_query(name, query))?;
+        Ok(0)
+    })
+}
+
+/// Deploys a YAML configuration of onramps, offramps, bindings and mappings,
+/// the channels of its `embedded` onramps and offramps have to be opened
+/// before
+///
+/// # Safety
+///  `rt` has to be a pointer returned by `tremor_runtime_new`, `config` a
+///  nul terminated string
+#[no_mangle]
+pub unsafe extern "C" fn tremor_deploy_config(rt: *mut Runtime, config: *const c_char) -> c_int {
+    guard(-1, || {
+        let rt = runtime(rt)?;
+        let config = arg(config, "config")?;
+        task::block_on(rt.world.load_config(config))?;
+        Ok(0)
+    })
+}
+
+/// Opens the channel of `embedded` onramps to send events to
+///
+/// # Safety
+///  `rt` has to be a pointer returned by `tremor_runtime_new`, `channel` a
+///  nul terminated string
+#[no_mangle]
+pub unsafe extern "C" fn tremor_open_input(rt: *mut Runtime, channel: *const c_char) -> c_int {
+    guard(-1, || {
+        let rt = runtime(rt)?;
+        let channel = arg(channel, "channel")?;
+        let injector = rt.world.embedded_onramp(channel)?;
+        rt.inputs.insert(channel.to_string(), injector);
+        Ok(0)
+    })
+}
+
+/// Opens the channel of `embedded` offramps to receive events from
+///
+/// # Safety
+///  `rt` has to be a pointer returned by `tremor_runtime_new`, `channel` a
+///  nul terminated string
+#[no_mangle]
+pub unsafe extern "C" fn tremor_open_output(rt: *mut Runtime, channel: *const c_char) -> c_int {
+    guard(-1, || {
+        let rt = runtime(rt)?;
+        let channel = arg(channel, "channel")?;
+        let rx = rt.world.embedded_offramp(channel)?;
+        rt.outputs.insert(channel.to_string(), rx);
+        Ok(0)
+    })
+}
+
+/// Sends a JSON encoded event with JSON encoded metadata, or none for null
+/// `meta`, to the `embedded` onramps of a channel, waiting while they are
+/// busy
+///
+/// # Safety
+///  `rt` has to be a pointer returned by `tremor_runtime_new`, `channel`
+///  and `data` nul terminated strings and `meta` null or one
+#[no_mangle]
+pub unsafe extern "C" fn tremor_send(
+    rt: *mut Runtime,
+    channel: *const c_char,
+    data: *const c_char,
+    meta: *const c_char,
+) -> c_int {
+    guard(-1, || {
+        let rt = runtime(rt)?;
+        let channel = arg(channel, "channel")?;
+        let data = arg(data, "data")?;
+        let meta = if meta.is_null() {
+            None
+        } else {
+            Some(arg(meta, "meta")?)
+        };
+        rt.send(channel, data, meta)?;
+        Ok(0)
+    })
+}
+
+/// Receives an event of the `embedded` offramps of a channel, waiting up to
+/// `timeout_ms` milliseconds for one (`0` to not wait), as
+/// `{"data": ..., "meta": ...}` JSON to be freed with `tremor_string_free`.
+/// Returns null if no event arrived in time or on failure, which sets the
+/// last error.
+///
+/// # Safety
+///  `rt` has to be a pointer returned by `tremor_runtime_new`, `channel` a
+///  nul terminated string
+#[no_mangle]
+pub unsafe extern "C" fn tremor_recv(
+    rt: *mut Runtime,
+    channel: *const c_char,
+    timeout_ms: u64,
+) -> *mut c_char {
+    guard(ptr::null_mut(), || {
+        let rt = runtime(rt)?;
+        let channel = arg(channel, "channel")?;
+        Ok(match rt.recv(channel, timeout_ms)? {
+            Some(event) => CString::new(event)
+                .map_err(|e| Error::from(e.to_string()))?
+                .into_raw(),
+            None => ptr::null_mut(),
+        })
+    })
+}
+
+/// Frees a string returned by `tremor_recv`
+///
+/// # Safety
+///  `s` has to be null or a string returned by `tremor_recv` that wasn't
+///  freed yet
+#[no_mangle]
+pub unsafe extern "C" fn tremor_string_free(s: *mut c_char) {
+    if !s.is_null() {
+        drop(CString::from_raw(s));
+    }
+}
+
+/// The message of the last failure on the calling thread, or null, valid
+/// until the next call into the library on that thread
+#[no_mangle]
+#[must_use]
+pub extern "C" fn tremor_last_error() -> *const c_char {
+    LAST_ERROR.with(|last| last.borrow().as_ref().map_or(ptr::null(), |e| e.as_ptr()))
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    fn c(s: &str) -> CString {
+        CString::new(s).expect("no nul in test strings")
+    }
+
+    fn last_error() -> Option<String> {
+        let e = tremor_last_error();
+        if e.is_null() {
+            None
+        } else {
+            Some(unsafe { CStr::from_ptr(e) }.to_string_lossy().to_string())
+        }
+    }
+
+    #[test]
+    fn roundtrip() {
+        let rt = tremor_runtime_new(0);
+        assert!(!rt.is_null(), "{:?}", last_error());
+        unsafe {
+            assert_eq!(0, tremor_open_input(rt, c("ffi-in").as_ptr()));
+            assert_eq!(0, tremor_open_output(rt, c("ffi-out").as_ptr()));
+            assert_eq!(
+                0,
+                tremor_deploy_query(
+                    rt,
+                    c("ffi").as_ptr(),
+                    c("select {\"snot\": event.snot, \"meta\": $} from in into out;").as_ptr()
+                ),
+                "{:?}",
+                last_error()
+            );
+            let config = c(r#"
+onramp:
+  - id: ffi-in
+    type: embedded
+    config:
+      channel: ffi-in
+offramp:
+  - id: ffi-out
+    type: embedded
+    config:
+      channel: ffi-out
+binding:
+  - id: ffi
+    links:
+      '/onramp/ffi-in/{instance}/out': ['/pipeline/ffi/{instance}/in']
+      '/pipeline/ffi/{instance}/out': ['/offramp/ffi-out/{instance}/in']
+mapping:
+  /binding/ffi/01:
+    instance: "01"
+"#);
+            assert_eq!(
+                0,
+                tremor_deploy_config(rt, config.as_ptr()),
+                "{:?}",
+                last_error()
+            );
+
+            assert_eq!(
+                -1,
+                tremor_send(rt, c("ffi-in").as_ptr(), c("{snot").as_ptr(), ptr::null())
+            );
+            assert!(last_error().is_some());
+            assert_eq!(
+                -1,
+                tremor_send(rt, c("ffi-nope").as_ptr(), c("1").as_ptr(), ptr::null())
+            );
+
+            assert_eq!(
+                0,
+                tremor_send(
+                    rt,
+                    c("ffi-in").as_ptr(),
+                    c(r#"{"snot": "badger"}"#).as_ptr(),
+                    c(r#"{"answer": 42}"#).as_ptr()
+                )
+            );
+            assert!(last_error().is_none());
+            let event = tremor_recv(rt, c("ffi-out").as_ptr(), 10_000);
+            assert!(!event.is_null(), "{:?}", last_error());
+            let mut json = CStr::from_ptr(event).to_bytes().to_vec();
+            tremor_string_free(event);
+            let event = tremor_value::parse_to_value(&mut json).expect("valid json");
+            assert_eq!(Some("badger"), event.get("data").get_str("snot"));
+            assert_eq!(Some(42), event.get("data").get("meta").get_u64("answer"));
+
+            assert!(tremor_recv(rt, c("ffi-out").as_ptr(), 0).is_null());
+            assert!(last_error().is_none());
+
+            tremor_runtime_free(rt);
+        }
+    }
+}
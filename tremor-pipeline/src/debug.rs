@@ -0,0 +1,110 @@
+// Copyright 2020-2021, The Tremor Team
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+use crate::OperatorNode;
+use tremor_script::Value;
+
+/// A point at which a debugged graph pauses before handing an event to an operator
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Breakpoint {
+    /// pause before the node with the given id
+    Node(String),
+    /// pause before the node created from the statement spanning the given
+    /// line of the query source
+    Line(usize),
+}
+
+impl Breakpoint {
+    fn matches(&self, node: &OperatorNode) -> bool {
+        match self {
+            Self::Node(id) => &node.id == id,
+            Self::Line(line) => node
+                .lines
+                .map_or(false, |(start, end)| start <= *line && *line <= end),
+        }
+    }
+}
+
+/// Debugging state of a graph
+#[derive(Debug, Clone, Default)]
+pub struct Debugger {
+    breakpoints: Vec<Breakpoint>,
+    /// pause before every node
+    stepping: bool,
+    /// the graph is paused before the top of its stack
+    pub(crate) paused: bool,
+    /// the top of the stack was already paused at and is to be processed
+    pub(crate) resumed: bool,
+}
+
+impl Debugger {
+    /// Active breakpoints
+    #[must_use]
+    pub fn breakpoints(&self) -> &[Breakpoint] {
+        &self.breakpoints
+    }
+
+    /// Adds a breakpoint
+    pub fn set(&mut self, breakpoint: Breakpoint) {
+        if !self.breakpoints.contains(&breakpoint) {
+            self.breakpoints.push(breakpoint);
+        }
+    }
+
+    /// Removes a breakpoint
+    pub fn clear(&mut self, breakpoint: &Breakpoint) {
+        self.breakpoints.retain(|b| b != breakpoint);
+    }
+
+    /// Pauses before the next node an event is handed to
+    pub fn pause(&mut self) {
+        self.stepping = true;
+    }
+
+    /// Whether the graph pauses before every node
+    #[must_use]
+    pub fn is_stepping(&self) -> bool {
+        self.stepping
+    }
+
+    pub(crate) fn resume(&mut self, stepping: bool) {
+        self.stepping = stepping;
+        self.paused = false;
+        self.resumed = true;
+    }
+
+    pub(crate) fn breaks(&self, node: &OperatorNode) -> bool {
+        self.stepping || self.breakpoints.iter().any(|b| b.matches(node))
+    }
+}
+
+/// Where a debugged graph is paused
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct Paused {
+    /// id of the node the event is handed to next
+    pub node: String,
+    /// input port the event is handed to
+    pub port: String,
+    /// lines of the statement the node was created from
+    pub lines: Option<(usize, usize)>,
+    /// the event value
+    pub event: Value<'static>,
+    /// the event metadata
+    pub meta: Value<'static>,
+    /// state of the node
+    pub state: Value<'static>,
+    /// number of events queued behind this one
+    pub pending: usize,
+}
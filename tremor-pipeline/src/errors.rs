@@ -94,6 +94,16 @@ error_chain! {
             display("Invalid input stream name '{}' for pipeline '{}'.", stream_name, pipeline)
         }
 
+        LimitExceeded(limit: String, max: usize, actual: usize) {
+            description("Resource limit exceeded")
+            display("Resource limit `{}` exceeded: {} bytes (max: {} bytes)", limit, actual, max)
+        }
+
+        Wasm(e: String) {
+            description("WebAssembly plugin error")
+            display("WebAssembly plugin error: {}", e)
+        }
+
     }
 }
 
@@ -14,6 +14,7 @@
 
 use crate::{CbAction, EventId, OpMeta, SignalKind};
 use std::mem::swap;
+use tremor_common::time::nanotime;
 use tremor_script::prelude::*;
 use tremor_script::{EventOriginUri, EventPayload, Value};
 
@@ -44,6 +45,9 @@ pub struct Event {
 }
 
 impl Event {
+    /// metadata key of the deadline of an event
+    pub const DEADLINE: &'static str = "deadline";
+
     /// turns the event in an insight given it's success
     #[must_use]
     pub fn insight(self, success: bool) -> Event {
@@ -107,6 +111,30 @@ impl Event {
         e
     }
 
+    /// The deadline of the event, set in `$deadline` in nanoseconds since epoch
+    #[must_use]
+    pub fn deadline(&self) -> Option<u64> {
+        self.data.suffix().meta().get_u64(Self::DEADLINE)
+    }
+
+    /// If the event has a deadline and it passed, the clock is only read for
+    /// events with a deadline
+    #[must_use]
+    pub fn is_expired(&self) -> bool {
+        self.deadline()
+            .map_or(false, |deadline| nanotime() > deadline)
+    }
+
+    /// Clears the deadline of the event, so it is processed after expiring
+    pub fn clear_deadline(&mut self) {
+        self.data.rent_mut(|data| {
+            let (_, meta) = data.parts_mut();
+            if let Some(meta) = meta.as_object_mut() {
+                meta.remove(Self::DEADLINE);
+            }
+        });
+    }
+
     /// Creates a restore insight from the event, consumes the `op_meta` and `origin_uri` of the
     /// event
     #[must_use]
@@ -16,17 +16,23 @@ use std::{fmt, fmt::Display};
 
 use crate::{
     common_cow,
+    debug::{Debugger, Paused},
     errors::Result,
     errors::{Error, ErrorKind},
-    influx_value,
-    op::{prelude::IN, trickle::window},
-    ConfigMap, ExecPortIndexMap, NodeLookupFn,
+    estimate_size, influx_value,
+    op::{
+        prelude::{ERR, EXPIRED, IN},
+        trickle::window,
+    },
+    ConfigMap, ExecPortIndexMap, Limits, NodeLookupFn, SignalKind, COUNT, FIELDS, LATENCY,
+    MEASUREMENT, TAGS, TIMESTAMP,
 };
 use crate::{op::EventAndInsights, Event, NodeKind, Operator};
 use beef::Cow;
 use halfbrown::HashMap;
-use tremor_common::stry;
-use tremor_script::{srs, Value};
+use hdrhistogram::Histogram;
+use tremor_common::{stry, time::nanotime};
+use tremor_script::{prelude::*, srs, Value};
 
 /// Configuration for a node
 #[derive(Debug, Clone, PartialOrd, Eq, Default)]
@@ -38,6 +44,8 @@ pub struct NodeConfig {
     pub(crate) defn: Option<srs::Stmt>,
     pub(crate) node: Option<srs::Stmt>,
     pub(crate) label: Option<String>,
+    /// first and last line of the statement the node was created from
+    pub(crate) lines: Option<(usize, usize)>,
 }
 
 impl Display for NodeConfig {
@@ -130,6 +138,8 @@ pub struct OperatorNode {
     pub op: Box<dyn Operator>,
     /// Tremor unique identifyer
     pub uid: u64,
+    /// First and last line of the statement the node was created from
+    pub lines: Option<(usize, usize)>,
 }
 
 impl Operator for OperatorNode {
@@ -143,6 +153,19 @@ impl Operator for OperatorNode {
         self.op.on_event(self.uid, port, state, event)
     }
 
+    fn handles_batch(&self) -> bool {
+        self.op.handles_batch()
+    }
+    fn on_batch(
+        &mut self,
+        _uid: u64,
+        port: &str,
+        state: &mut Value<'static>,
+        events: Vec<Event>,
+    ) -> Result<EventAndInsights> {
+        self.op.on_batch(self.uid, port, state, events)
+    }
+
     fn handles_signal(&self) -> bool {
         self.op.handles_signal()
     }
@@ -179,9 +202,51 @@ impl Operator for OperatorNode {
 pub(crate) struct NodeMetrics {
     inputs: HashMap<Cow<'static, str>, u64>,
     outputs: HashMap<Cow<'static, str>, u64>,
+    /// processing latency of the node in nanoseconds since the last report
+    latency: Option<Histogram<u64>>,
 }
 
 impl NodeMetrics {
+    pub(crate) fn record_latency(&mut self, duration_ns: u64) {
+        if self.latency.is_none() {
+            // an auto resizing histogram with 2 significant digits
+            self.latency = Histogram::new(2).ok();
+        }
+        if let Some(latency) = &mut self.latency {
+            latency.saturating_record(duration_ns);
+        }
+    }
+
+    /// Turns the recorded latencies into a metrics value and resets them,
+    /// so that each report covers only the last interval
+    fn latency_to_value(
+        &mut self,
+        tags: &HashMap<Cow<'static, str>, Value<'static>>,
+        timestamp: u64,
+    ) -> Option<Value<'static>> {
+        let latency = self.latency.as_mut().filter(|l| !l.is_empty())?;
+        let mut tags = tags.clone();
+        tags.remove("direction");
+        tags.remove("port");
+        let value = literal!({
+            MEASUREMENT: LATENCY,
+            TAGS: tags,
+            FIELDS: {
+                COUNT: latency.len(),
+                "min": latency.min(),
+                "max": latency.max(),
+                "mean": latency.mean(),
+                "p50": latency.value_at_quantile(0.5),
+                "p95": latency.value_at_quantile(0.95),
+                "p99": latency.value_at_quantile(0.99),
+                "p999": latency.value_at_quantile(0.999)
+            },
+            TIMESTAMP: timestamp
+        });
+        latency.reset();
+        Some(value)
+    }
+
     // this makes sense since we might not need to clone the cow and
     // it might be owned so cloning early would be costly
     #[allow(clippy::ptr_arg)]
@@ -267,17 +332,152 @@ pub struct ExecutableGraph {
     pub(crate) metrics_idx: usize,
     pub(crate) last_metrics: u64,
     pub(crate) metric_interval: Option<u64>,
+    /// when the state sizes were last checked against `max_state_size`
+    pub(crate) last_state_check: u64,
+    /// tags added to every metric of the graph
+    pub(crate) metric_tags: HashMap<Cow<'static, str>, Value<'static>>,
+    /// records operator latencies without a metrics interval
+    pub(crate) record_latencies: bool,
+    pub(crate) limits: Limits,
     /// snot
     pub insights: Vec<(usize, Event)>,
     /// source code of the pipeline
     pub source: Option<String>,
     /// the dot representation of the graph
     pub dot: String,
+    /// attached debugger, if any
+    pub(crate) debugger: Option<Debugger>,
+}
+
+/// The state of the operators of a graph at a point in time, to sync a warm
+/// standby of it or to restore it later
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub struct Snapshot {
+    /// nanoseconds since the epoch at which the snapshot was taken
+    pub taken_ns: u64,
+    /// ids of the nodes, to check the snapshot is of the same pipeline
+    ids: Vec<String>,
+    #[serde(deserialize_with = "static_values")]
+    state: Vec<Value<'static>>,
+    #[serde(deserialize_with = "static_opt_values")]
+    ops: Vec<Option<Value<'static>>>,
+}
+
+fn static_values<'de, D>(deserializer: D) -> std::result::Result<Vec<Value<'static>>, D::Error>
+where
+    D: serde::Deserializer<'de>,
+{
+    let values: Vec<Value<'de>> = serde::Deserialize::deserialize(deserializer)?;
+    Ok(values.into_iter().map(Value::into_static).collect())
+}
+
+fn static_opt_values<'de, D>(
+    deserializer: D,
+) -> std::result::Result<Vec<Option<Value<'static>>>, D::Error>
+where
+    D: serde::Deserializer<'de>,
+{
+    let values: Vec<Option<Value<'de>>> = serde::Deserialize::deserialize(deserializer)?;
+    Ok(values
+        .into_iter()
+        .map(|v| v.map(Value::into_static))
+        .collect())
+}
+
+/// estimated size of an events value and metadata
+fn event_size(event: &Event) -> usize {
+    event
+        .value_meta_iter()
+        .map(|(v, m)| estimate_size(v) + estimate_size(m))
+        .sum()
+}
+
+/// how often the state of the operators is checked against `max_state_size`
+const STATE_CHECK_INTERVAL_NS: u64 = 10_000_000_000;
+
+/// error value reported on the `err` port when a limit is exceeded
+fn limit_error(
+    pipeline: &str,
+    limit: &'static str,
+    e: &Error,
+    max: usize,
+    size: usize,
+) -> Value<'static> {
+    let mut data = Value::object_with_capacity(6);
+    data.try_insert("error", e.to_string());
+    data.try_insert("pipeline", pipeline.to_string());
+    data.try_insert("limit", limit);
+    data.try_insert("max", max);
+    data.try_insert("size", size);
+    data
 }
 
 /// The return of a graph execution
 pub type Returns = Vec<(Cow<'static, str>, Event)>;
 impl ExecutableGraph {
+    /// Output ports that are connected to more than one downstream node, as
+    /// `(node id, port, downstream node ids)` ordered by node id and port
+    #[must_use]
+    pub fn fan_outs(&self) -> Vec<(String, String, Vec<String>)> {
+        let mut fan_outs: Vec<_> = self
+            .port_indexes
+            .iter()
+            .filter_map(|((from, port), targets)| {
+                let mut targets: Vec<String> = targets
+                    .iter()
+                    .filter_map(|(to, _)| self.graph.get(*to))
+                    .map(|n| n.id.clone())
+                    .collect();
+                targets.sort();
+                targets.dedup();
+                let node = self.graph.get(*from)?;
+                if targets.len() > 1 {
+                    Some((node.id.clone(), port.to_string(), targets))
+                } else {
+                    None
+                }
+            })
+            .collect();
+        fan_outs.sort();
+        fan_outs
+    }
+
+    /// The metrics interval of the graph in nanoseconds
+    #[must_use]
+    pub fn metric_interval(&self) -> Option<u64> {
+        self.metric_interval
+    }
+
+    /// Replaces the metrics interval in nanoseconds, `None` disables the
+    /// metrics, and sets the tags added to every metric of the graph
+    pub fn configure_metrics(
+        &mut self,
+        interval: Option<u64>,
+        tags: HashMap<Cow<'static, str>, Value<'static>>,
+    ) {
+        self.metric_interval = interval;
+        self.metric_tags = tags;
+    }
+
+    /// Records the processing latency of every operator, even when no
+    /// metrics interval is configured
+    pub fn record_latencies(&mut self) {
+        self.record_latencies = true;
+    }
+
+    /// The processing latency of each operator since the last report as
+    /// `latency` metrics, resetting the recorded latencies
+    pub fn latencies(&mut self, timestamp: u64) -> Vec<Value<'static>> {
+        let mut tags = self.metric_tags.clone();
+        tags.insert("pipeline".into(), common_cow(&self.id).into());
+        let mut res = Vec::new();
+        for (node, m) in self.graph.iter().zip(self.metrics.iter_mut()) {
+            tags.insert("node".into(), node.id.clone().into());
+            res.extend(m.latency_to_value(&tags, timestamp));
+        }
+        res
+    }
+
     /// Tries to optimise a pipeline
     pub fn optimize(&mut self) -> Option<()> {
         let mut i = 0;
@@ -435,13 +635,52 @@ impl ExecutableGraph {
         event: Event,
         returns: &mut Returns,
     ) -> Result<()> {
+        if stry!(self.push_input(stream_name, event, returns)) {
+            self.run(returns)
+        } else {
+            Ok(())
+        }
+    }
+
+    /// Enqueues a batch of events on the same input stream and processes
+    /// them in one go, operators that handle batches get the events that
+    /// reach them together in a single call.
+    ///
+    /// # Errors
+    /// Errors if the events can not be processed, or an operator fails
+    pub fn enqueue_batch(
+        &mut self,
+        stream_name: &str,
+        events: Vec<Event>,
+        returns: &mut Returns,
+    ) -> Result<()> {
+        let mut pushed = false;
+        for event in events {
+            pushed |= stry!(self.push_input(stream_name, event, returns));
+        }
+        if pushed {
+            self.run(returns)
+        } else {
+            Ok(())
+        }
+    }
+
+    /// Puts an event on the stack of the given input stream, returns false
+    /// if it was rejected.
+    #[inline]
+    fn push_input(
+        &mut self,
+        stream_name: &str,
+        event: Event,
+        returns: &mut Returns,
+    ) -> Result<bool> {
         // Resolve the input stream or entrypoint for this enqueue operation
         if self
             .metric_interval
             .map(|ival| event.ingest_ns - self.last_metrics > ival)
             .unwrap_or_default()
         {
-            let mut tags = HashMap::with_capacity(8);
+            let mut tags = self.metric_tags.clone();
             tags.insert("pipeline".into(), common_cow(&self.id).into());
             self.enqueue_metrics("events", tags, event.ingest_ns);
             self.last_metrics = event.ingest_ns;
@@ -452,23 +691,245 @@ impl ExecutableGraph {
                 self.id.clone(),
             ))
         }));
+        if let Some(max) = self.limits.max_event_size {
+            let size = event_size(&event);
+            if size > max {
+                self.reject_event(input, event, max, size, returns);
+                return Ok(false);
+            }
+        }
         self.stack.push((input, IN, event));
+        Ok(true)
+    }
+
+    /// Drops an event that exceeded `max_event_size`, fails it upstream
+    /// and reports the violation on the `err` port.
+    fn reject_event(
+        &mut self,
+        input: usize,
+        mut event: Event,
+        max: usize,
+        size: usize,
+        returns: &mut Returns,
+    ) {
+        let e = Error::from(ErrorKind::LimitExceeded(
+            "max_event_size".to_string(),
+            max,
+            size,
+        ));
+        warn!("[Pipeline::{}] Dropping event {}: {}", self.id, event.id, e);
+        if event.transactional {
+            self.insights.push((input, event.insight_fail()));
+        }
+        let error = Event {
+            id: event.id,
+            data: limit_error(&self.id, "max_event_size", &e, max, size).into(),
+            ingest_ns: event.ingest_ns,
+            origin_uri: event.origin_uri,
+            ..Event::default()
+        };
+        returns.push((ERR, error));
+    }
+
+    /// Resets the state of every operator whose state grew beyond
+    /// `max_state_size` and reports the violation on the `err` port.
+    ///
+    /// Estimating the state size walks the whole state, so this only runs
+    /// every `STATE_CHECK_INTERVAL_NS` and not on every tick.
+    fn enforce_state_limits(&mut self, ingest_ns: u64, returns: &mut Returns) {
+        let max = if let Some(max) = self.limits.max_state_size {
+            max
+        } else {
+            return;
+        };
+        if ingest_ns.saturating_sub(self.last_state_check) < STATE_CHECK_INTERVAL_NS {
+            return;
+        }
+        self.last_state_check = ingest_ns;
+        for (idx, state) in self.state.ops.iter_mut().enumerate() {
+            let size = estimate_size(state);
+            if size <= max {
+                continue;
+            }
+            *state = Value::null();
+            let node = self
+                .graph
+                .get(idx)
+                .map_or_else(|| idx.to_string(), |n| n.id.to_string());
+            if let Some(metrics) = self.metrics.get_mut(idx) {
+                metrics.inc_output(&ERR);
+            }
+            let e = Error::from(ErrorKind::LimitExceeded(
+                "max_state_size".to_string(),
+                max,
+                size,
+            ));
+            error!(
+                "[Pipeline::{}] Dropped the state of operator {}: {}",
+                self.id, node, e
+            );
+            let mut data = limit_error(&self.id, "max_state_size", &e, max, size);
+            data.try_insert("node", node);
+            let error = Event {
+                data: data.into(),
+                ingest_ns,
+                ..Event::default()
+            };
+            returns.push((ERR, error));
+        }
+    }
+
+    /// Snapshot of the state of the operators, see [`restore`](Self::restore)
+    #[must_use]
+    pub fn snapshot(&self) -> Snapshot {
+        Snapshot {
+            taken_ns: nanotime(),
+            ids: self.graph.iter().map(|node| node.id.clone()).collect(),
+            state: self.state.ops.clone(),
+            ops: self.graph.iter().map(|node| node.op.snapshot()).collect(),
+        }
+    }
+
+    /// Restores the state of the operators from a graph of the same pipeline,
+    /// events in flight in this graph are dropped.
+    ///
+    /// # Errors
+    /// if the snapshot is of a different pipeline or an operator can not
+    /// restore its state
+    pub fn restore(&mut self, snapshot: Snapshot) -> Result<()> {
+        if snapshot.ids.len() != self.graph.len()
+            || self
+                .graph
+                .iter()
+                .zip(&snapshot.ids)
+                .any(|(node, id)| &node.id != id)
+        {
+            return Err("Snapshot is of a different pipeline".into());
+        }
+        for (node, op) in self.graph.iter_mut().zip(&snapshot.ops) {
+            if let Some(op) = op {
+                node.op.restore(op)?;
+            }
+        }
+        self.state.ops = snapshot.state;
+        self.stack.clear();
+        Ok(())
+    }
+
+    /// Attaches a debugger, replacing any attached one
+    pub fn attach_debugger(&mut self, debugger: Debugger) {
+        self.debugger = Some(debugger);
+    }
+
+    /// Detaches the debugger, running a paused graph to completion
+    ///
+    /// # Errors
+    /// if an event fails to be processed after resuming
+    pub fn detach_debugger(&mut self, returns: &mut Returns) -> Result<Option<Debugger>> {
+        let mut debugger = self.debugger.take();
+        if let Some(debugger) = debugger.as_mut().filter(|d| d.paused) {
+            debugger.paused = false;
+            self.run(returns)?;
+        }
+        Ok(debugger)
+    }
+
+    /// The attached debugger
+    pub fn debugger_mut(&mut self) -> Option<&mut Debugger> {
+        self.debugger.as_mut()
+    }
+
+    /// Whether an attached debugger paused the graph, a paused graph
+    /// must be resumed or stepped before anything else is enqueued
+    #[must_use]
+    pub fn is_paused(&self) -> bool {
+        self.debugger.as_ref().map_or(false, |d| d.paused)
+    }
+
+    /// The node, event and state the graph is paused at
+    #[must_use]
+    pub fn paused(&self) -> Option<Paused> {
+        if !self.is_paused() {
+            return None;
+        }
+        let (idx, port, event) = self.stack.last()?;
+        let node = self.graph.get(*idx)?;
+        let data = event.data.suffix();
+        Some(Paused {
+            node: node.id.clone(),
+            port: port.to_string(),
+            lines: node.lines,
+            event: data.value().clone_static(),
+            meta: data.meta().clone_static(),
+            state: self
+                .state
+                .ops
+                .get(*idx)
+                .cloned()
+                .unwrap_or_else(Value::null),
+            pending: self.stack.len() - 1,
+        })
+    }
+
+    /// Hands the event the graph is paused at to its node and pauses
+    /// again before the next node
+    ///
+    /// # Errors
+    /// if the event fails to be processed
+    pub fn step(&mut self, returns: &mut Returns) -> Result<()> {
+        self.resume_with(true, returns)
+    }
+
+    /// Resumes a paused graph until the next breakpoint
+    ///
+    /// # Errors
+    /// if an event fails to be processed
+    pub fn resume(&mut self, returns: &mut Returns) -> Result<()> {
+        self.resume_with(false, returns)
+    }
+
+    fn resume_with(&mut self, stepping: bool, returns: &mut Returns) -> Result<()> {
+        match self.debugger.as_mut() {
+            Some(debugger) if debugger.paused => debugger.resume(stepping),
+            _ => return Ok(()),
+        }
         self.run(returns)
     }
 
+    /// Checks if an attached debugger pauses before the top of the stack
+    #[inline]
+    fn breaks(&mut self) -> bool {
+        if let (Some(debugger), Some((idx, _, event))) = (self.debugger.as_mut(), self.stack.last())
+        {
+            if event.kind.is_none() && !std::mem::take(&mut debugger.resumed) {
+                // ALLOW: the stack only holds indexes of nodes
+                let node = unsafe { self.graph.get_unchecked(*idx) };
+                debugger.paused = debugger.breaks(node);
+                return debugger.paused;
+            }
+        }
+        false
+    }
+
     #[inline]
     fn run(&mut self, returns: &mut Returns) -> Result<()> {
-        while match self.next(returns) {
-            Ok(res) => res,
-            Err(e) => {
-                // if we error handling an event, we need to clear the stack
-                // In the case of branching where the stack has > 1 element
-                // we would keep the old errored event around for the numbers of branches that havent been executed
-                self.stack.clear();
-                return Err(e);
+        while !self.breaks()
+            && match self.next(returns) {
+                Ok(res) => res,
+                Err(e) => {
+                    // if we error handling an event, we need to clear the stack
+                    // In the case of branching where the stack has > 1 element
+                    // we would keep the old errored event around for the numbers of branches that havent been executed
+                    self.stack.clear();
+                    return Err(e);
+                }
             }
-        } {}
-        returns.reverse();
+        {}
+        // returns are accumulated across pauses and only put in order once
+        // the stack is completely processed
+        if !self.is_paused() {
+            returns.reverse();
+        }
         Ok(())
     }
 
@@ -487,8 +948,29 @@ impl ExecutableGraph {
                 } else {
                     // ALLOW: We know the state was initiated
                     let state = unsafe { self.state.ops.get_unchecked_mut(idx) };
-                    let EventAndInsights { events, insights } =
-                        stry!(node.on_event(0, &port, state, event));
+                    // only pay for the clock when latencies are recorded at all
+                    let start =
+                        (self.metric_interval.is_some() || self.record_latencies).then(nanotime);
+                    let EventAndInsights { events, insights } = if node.handles_batch() {
+                        // take all events waiting for the same node and port
+                        let mut batch = vec![event];
+                        while self.stack.last().map_or(false, |(i, p, e)| {
+                            *i == idx && *p == port && e.kind.is_none()
+                        }) {
+                            if let Some((_, _, e)) = self.stack.pop() {
+                                batch.push(e);
+                            }
+                        }
+                        // the stack holds them in reverse
+                        batch.reverse();
+                        stry!(node.on_batch(0, &port, state, batch))
+                    } else {
+                        stry!(node.on_event(0, &port, state, event))
+                    };
+                    if let Some(start) = start {
+                        unsafe { self.metrics.get_unchecked_mut(idx) }
+                            .record_latency(nanotime().saturating_sub(start));
+                    }
 
                     for (out_port, _) in &events {
                         unsafe { self.metrics.get_unchecked_mut(idx) }.inc_output(out_port);
@@ -506,13 +988,33 @@ impl ExecutableGraph {
         }
     }
 
+    /// Routes an event past its deadline to the `expired` port of the node it
+    /// was about to enter, it is dropped if nothing is connected there. The
+    /// event is failed upstream and its deadline cleared, so it can be
+    /// processed by whatever handles expired events.
+    fn expire(&mut self, idx: usize, mut event: Event) {
+        debug!(
+            "[Pipeline::{}] Event {} expired before node {}",
+            self.id,
+            event.id,
+            unsafe { self.graph.get_unchecked(idx) }.id
+        );
+        if event.transactional {
+            self.insights.push((idx, event.insight_fail()));
+            event.transactional = false;
+        }
+        event.clear_deadline();
+        unsafe { self.metrics.get_unchecked_mut(idx) }.inc_output(&EXPIRED);
+        self.enqueue_events(idx, vec![(EXPIRED, event)]);
+    }
+
     fn enqueue_metrics(
         &mut self,
         metric_name: &str,
         mut tags: HashMap<Cow<'static, str>, Value<'static>>,
         ingest_ns: u64,
     ) {
-        for (i, m) in self.metrics.iter().enumerate() {
+        for (i, m) in self.metrics.iter_mut().enumerate() {
             tags.insert("node".into(), unsafe {
                 self.graph.get_unchecked(i).id.clone().into()
             });
@@ -532,6 +1034,19 @@ impl ExecutableGraph {
                 }
             }
 
+            if let Some(value) = m.latency_to_value(&tags, ingest_ns) {
+                self.stack.push((
+                    self.metrics_idx,
+                    IN,
+                    Event {
+                        data: value.into(),
+                        ingest_ns,
+                        origin_uri: None,
+                        ..Event::default()
+                    },
+                ));
+            }
+
             for value in m.to_value(metric_name, &mut tags, ingest_ns) {
                 self.stack.push((
                     self.metrics_idx,
@@ -550,6 +1065,14 @@ impl ExecutableGraph {
     #[inline]
     fn enqueue_events(&mut self, idx: usize, events: Vec<(Cow<'static, str>, Event)>) {
         for (out_port, event) in events {
+            // the deadline is looked up once for every emitted event and not
+            // for every node it enters
+            if event.is_expired() {
+                if let Some(targets) = self.port_indexes.get(&(idx, out_port)).cloned() {
+                    self.enqueue_expired(targets, event);
+                }
+                continue;
+            }
             if let Some((last, rest)) = self
                 .port_indexes
                 .get(&(idx, out_port))
@@ -565,6 +1088,21 @@ impl ExecutableGraph {
             }
         }
     }
+
+    /// Expires an event before each of the nodes it would enter, outputs get
+    /// it as it is and the offramps handle its deadline
+    fn enqueue_expired(&mut self, targets: Vec<(usize, Cow<'static, str>)>, event: Event) {
+        for (idx, in_port) in targets {
+            if let Some(NodeKind::Output(_)) = self.graph.get(idx).map(|n| &n.kind) {
+                if let Some(metrics) = self.metrics.get_mut(idx) {
+                    metrics.inc_input(&in_port);
+                }
+                self.stack.push((idx, in_port, event.clone()));
+            } else {
+                self.expire(idx, event.clone());
+            }
+        }
+    }
     /// Enque a contraflow insight
     pub fn contraflow(&mut self, mut skip_to: Option<usize>, mut insight: Event) -> Event {
         for idx in &self.contraflow {
@@ -583,6 +1121,9 @@ impl ExecutableGraph {
     /// if the singal fails to be processed in the singal flow or if any forward going
     /// events spawned by this signal fail to be processed
     pub fn enqueue_signal(&mut self, signal: Event, returns: &mut Returns) -> Result<()> {
+        if signal.kind == Some(SignalKind::Tick) {
+            self.enforce_state_limits(signal.ingest_ns, returns);
+        }
         if stry!(self.signalflow(signal)) {
             stry!(self.run(returns));
         }
@@ -612,10 +1153,12 @@ impl ExecutableGraph {
 #[cfg(test)]
 mod test {
     use super::*;
+    use crate::debug::Breakpoint;
     use crate::op::{
         identity::PassthroughFactory,
         prelude::{METRICS, OUT},
     };
+    use crate::CbAction;
     use std::{
         collections::hash_map::DefaultHasher,
         hash::{Hash, Hasher},
@@ -646,6 +1189,7 @@ mod test {
             op_type: id.into(),
             op: PassthroughFactory::new_boxed().from_node(uid, &c).unwrap(),
             uid: 0,
+            lines: None,
         }
     }
     #[test]
@@ -693,6 +1237,23 @@ mod test {
         assert_eq!(mi.get("timestamp").unwrap(), &123);
     }
 
+    #[test]
+    fn node_metrics_latency() {
+        let mut m = NodeMetrics::default();
+        let tags = HashMap::default();
+        assert!(m.latency_to_value(&tags, 123).is_none());
+        for i in 1..=100 {
+            m.record_latency(i * 1000);
+        }
+        let l = m.latency_to_value(&tags, 123).unwrap();
+        test_metric(&l, "latency", 100);
+        let fields = l.get("fields").unwrap();
+        assert!(fields.get_u64("min").unwrap() <= 1000);
+        assert!(fields.get_u64("p50").unwrap() <= fields.get_u64("p99").unwrap());
+        // reported latencies are reset
+        assert!(m.latency_to_value(&tags, 456).is_none());
+    }
+
     #[derive(Debug)]
     struct AllOperator {}
 
@@ -746,9 +1307,32 @@ mod test {
             op_type: "test".into(),
             op: Box::new(AllOperator {}),
             uid: 0,
+            lines: None,
         }
     }
 
+    fn test_latencies(metrics: Vec<Event>, n: u64) -> Vec<Event> {
+        let (latencies, metrics): (Vec<_>, Vec<_>) = metrics
+            .into_iter()
+            .partition(|e| e.data.suffix().value().get_str("measurement") == Some("latency"));
+        let nodes: Vec<_> = latencies
+            .iter()
+            .map(|e| {
+                let data = e.data.suffix().value();
+                test_metric(data, "latency", n);
+                assert!(data.get("tags").unwrap().get("port").is_none());
+                assert!(data.get("fields").unwrap().get("p99").is_some());
+                data.get("tags")
+                    .unwrap()
+                    .get_str("node")
+                    .unwrap()
+                    .to_string()
+            })
+            .collect();
+        assert_eq!(nodes, vec!["in", "all-1", "all-2"]);
+        metrics
+    }
+
     fn test_metrics(mut metrics: Vec<Event>, n: u64) {
         // out/in
         let this = metrics.pop().unwrap();
@@ -847,10 +1431,15 @@ mod test {
             // The index of the metrics node in our pipeline
             metrics_idx: 4,
             last_metrics: 0,
+            last_state_check: 0,
+            record_latencies: false,
             metric_interval: Some(1),
+            metric_tags: HashMap::new(),
+            limits: Limits::default(),
             insights: vec![],
             source: None,
             dot: String::from(""),
+            debugger: None,
         };
 
         // Test with one event
@@ -864,7 +1453,7 @@ mod test {
         g.run(&mut returns).unwrap();
         let (ports, metrics): (Vec<_>, Vec<_>) = returns.drain(..).unzip();
         assert!(ports.iter().all(|v| v == "metrics"));
-        test_metrics(metrics, 1);
+        test_metrics(test_latencies(metrics, 1), 1);
 
         // Test with two events
         let e = Event::default();
@@ -883,7 +1472,220 @@ mod test {
         g.run(&mut returns).unwrap();
         let (ports, metrics): (Vec<_>, Vec<_>) = returns.drain(..).unzip();
         assert!(ports.iter().all(|v| v == "metrics"));
-        test_metrics(metrics, 3);
+        // latencies are reset on every report, counts are not
+        test_metrics(test_latencies(metrics, 2), 3);
+    }
+
+    #[test]
+    fn eg_expired() {
+        let mut in_n = pass(1, "in");
+        in_n.kind = NodeKind::Input;
+        let mut out_n = pass(2, "out");
+        out_n.kind = NodeKind::Output(OUT);
+        let mut expired_n = pass(3, "expired");
+        expired_n.kind = NodeKind::Output(EXPIRED);
+
+        // The graph is in -> 1 -> out, with expired events of 1 going to expired
+        let graph = vec![in_n, all_op("all-1"), out_n, expired_n];
+
+        let mut inputs = HashMap::new();
+        inputs.insert("in".into(), 0);
+
+        let mut port_indexes = ExecPortIndexMap::new();
+        port_indexes.insert((0, "out".into()), vec![(1, "in".into())]);
+        port_indexes.insert((1, "out".into()), vec![(2, "in".into())]);
+        port_indexes.insert((1, "expired".into()), vec![(3, "in".into())]);
+
+        let mut g = ExecutableGraph {
+            id: "test".into(),
+            graph,
+            state: State::new(vec![Value::null(); 4]),
+            inputs,
+            stack: vec![],
+            signalflow: vec![],
+            contraflow: vec![],
+            port_indexes,
+            metrics: vec![NodeMetrics::default(); 4],
+            metrics_idx: 4,
+            last_metrics: 0,
+            last_state_check: 0,
+            record_latencies: false,
+            metric_interval: None,
+            metric_tags: HashMap::new(),
+            limits: Limits::default(),
+            insights: vec![],
+            source: None,
+            dot: String::from(""),
+            debugger: None,
+        };
+
+        let event = |deadline: u64| Event {
+            data: (Value::null(), literal!({ "deadline": deadline })).into(),
+            transactional: true,
+            ..Event::default()
+        };
+        let mut returns = Vec::new();
+        g.enqueue("in", event(u64::MAX), &mut returns).unwrap();
+        assert_eq!(returns.len(), 1);
+        assert_eq!(returns[0].0, "out");
+        assert!(g.insights.is_empty());
+        returns.clear();
+
+        g.enqueue("in", event(1), &mut returns).unwrap();
+        assert_eq!(returns.len(), 1);
+        let (port, expired) = returns.pop().unwrap();
+        assert_eq!(port, "expired");
+        // the event is failed upstream and handled without a deadline
+        assert!(expired.deadline().is_none());
+        assert!(!expired.transactional);
+        assert_eq!(g.insights.len(), 1);
+        assert_eq!(g.insights[0].1.cb, CbAction::Fail);
+    }
+
+    #[derive(Debug)]
+    struct BatchOperator {}
+
+    impl Operator for BatchOperator {
+        fn on_event(
+            &mut self,
+            uid: u64,
+            port: &str,
+            state: &mut Value<'static>,
+            event: Event,
+        ) -> Result<EventAndInsights> {
+            self.on_batch(uid, port, state, vec![event])
+        }
+        fn handles_batch(&self) -> bool {
+            true
+        }
+        fn on_batch(
+            &mut self,
+            _uid: u64,
+            _port: &str,
+            state: &mut Value<'static>,
+            events: Vec<Event>,
+        ) -> Result<EventAndInsights> {
+            // remember the size of each batch
+            let len = Value::from(events.len());
+            if let Some(batches) = state.as_array_mut() {
+                batches.push(len);
+            } else {
+                *state = Value::from(vec![len]);
+            }
+            Ok(events
+                .into_iter()
+                .map(|e| (OUT, e))
+                .collect::<Vec<_>>()
+                .into())
+        }
+    }
+
+    fn batch_graph() -> ExecutableGraph {
+        let mut in_n = pass(1, "in");
+        in_n.kind = NodeKind::Input;
+        let mut out_n = pass(2, "out");
+        out_n.kind = NodeKind::Output(OUT);
+        let batch_n = OperatorNode {
+            id: "batch".into(),
+            kind: NodeKind::Operator,
+            op_type: "test".into(),
+            op: Box::new(BatchOperator {}),
+            uid: 0,
+            lines: None,
+        };
+
+        // The graph is in -> batch -> out
+        let graph = vec![in_n, batch_n, out_n];
+
+        let mut inputs = HashMap::new();
+        inputs.insert("in".into(), 0);
+
+        let mut port_indexes = ExecPortIndexMap::new();
+        port_indexes.insert((0, "out".into()), vec![(1, "in".into())]);
+        port_indexes.insert((1, "out".into()), vec![(2, "in".into())]);
+
+        ExecutableGraph {
+            id: "test".into(),
+            graph,
+            state: State::new(vec![Value::null(); 3]),
+            inputs,
+            stack: vec![],
+            signalflow: vec![],
+            contraflow: vec![],
+            port_indexes,
+            metrics: vec![NodeMetrics::default(); 3],
+            metrics_idx: 3,
+            last_metrics: 0,
+            last_state_check: 0,
+            record_latencies: false,
+            metric_interval: None,
+            metric_tags: HashMap::new(),
+            limits: Limits::default(),
+            insights: vec![],
+            source: None,
+            dot: String::from(""),
+            debugger: None,
+        }
+    }
+
+    #[test]
+    fn eg_batch() {
+        let mut g = batch_graph();
+
+        let event = |i: u64| Event {
+            data: Value::from(i).into(),
+            ..Event::default()
+        };
+        let mut returns = Vec::new();
+        g.enqueue_batch("in", vec![event(1), event(2), event(3)], &mut returns)
+            .unwrap();
+        let values: Vec<_> = returns
+            .drain(..)
+            .map(|(port, e)| {
+                assert_eq!(port, "out");
+                e.data.suffix().value().as_u64()
+            })
+            .collect();
+        assert_eq!(values, vec![Some(1), Some(2), Some(3)]);
+
+        g.enqueue("in", event(4), &mut returns).unwrap();
+        assert_eq!(returns.len(), 1);
+        // the batch reaches the operator in one call, the single event in another
+        assert_eq!(g.state.ops[1], Value::from(vec![3_u64, 1]));
+    }
+
+    #[test]
+    fn eg_snapshot() {
+        let mut active = batch_graph();
+        let mut standby = batch_graph();
+        let event = |i: u64| Event {
+            data: Value::from(i).into(),
+            ..Event::default()
+        };
+        let mut returns = Vec::new();
+        active
+            .enqueue_batch("in", vec![event(1), event(2)], &mut returns)
+            .unwrap();
+        assert_eq!(standby.state.ops[1], Value::null());
+
+        standby.restore(active.snapshot()).unwrap();
+        assert_eq!(standby.state.ops[1], Value::from(vec![2_u64]));
+
+        // snapshots survive serialization
+        let snapshot = active.snapshot();
+        let mut json = simd_json::to_vec(&snapshot).unwrap();
+        let decoded: Snapshot = simd_json::from_slice(&mut json).unwrap();
+        assert_eq!(snapshot, decoded);
+
+        // the standby continues where the active graph left off
+        standby.enqueue("in", event(3), &mut returns).unwrap();
+        assert_eq!(standby.state.ops[1], Value::from(vec![2_u64, 1]));
+
+        // snapshots of other pipelines are rejected
+        let mut other = batch_graph();
+        other.graph[1].id = "other".into();
+        assert!(other.restore(active.snapshot()).is_err());
+        assert_eq!(other.state.ops[1], Value::null());
     }
 
     #[test]
@@ -949,10 +1751,15 @@ mod test {
             // The index of the metrics node in our pipeline
             metrics_idx: 5,
             last_metrics: 0,
+            last_state_check: 0,
+            record_latencies: false,
             metric_interval: Some(1),
+            metric_tags: HashMap::new(),
+            limits: Limits::default(),
             insights: vec![],
             source: None,
             dot: String::from(""),
+            debugger: None,
         };
         assert!(g.optimize().is_some());
         // Test with one event
@@ -976,4 +1783,95 @@ mod test {
             Some(&vec![(4_usize, IN)])
         );
     }
+
+    #[test]
+    fn eg_debugger() {
+        let mut in_n = pass(1, "in");
+        in_n.kind = NodeKind::Input;
+        let mut out_n = pass(2, "out");
+        out_n.kind = NodeKind::Output(OUT);
+        let mut all_n = all_op("all-1");
+        all_n.lines = Some((2, 4));
+
+        // The graph is in -> 1 -> out
+        let graph = vec![in_n, all_n, out_n];
+
+        let mut inputs = HashMap::new();
+        inputs.insert("in".into(), 0);
+
+        let mut port_indexes = ExecPortIndexMap::new();
+        port_indexes.insert((0, "out".into()), vec![(1, "in".into())]);
+        port_indexes.insert((1, "out".into()), vec![(2, "in".into())]);
+
+        let mut g = ExecutableGraph {
+            id: "test".into(),
+            graph,
+            state: State::new(vec![Value::null(); 3]),
+            inputs,
+            stack: vec![],
+            signalflow: vec![],
+            contraflow: vec![],
+            port_indexes,
+            metrics: vec![NodeMetrics::default(); 3],
+            metrics_idx: 3,
+            last_metrics: 0,
+            last_state_check: 0,
+            record_latencies: false,
+            metric_interval: None,
+            metric_tags: HashMap::new(),
+            limits: Limits::default(),
+            insights: vec![],
+            source: None,
+            dot: String::from(""),
+            debugger: None,
+        };
+        let event = || Event {
+            data: (literal!({"snot": "badger"}), literal!({"kafka": {}})).into(),
+            ..Event::default()
+        };
+        let mut returns = Vec::new();
+
+        let mut debugger = Debugger::default();
+        debugger.set(Breakpoint::Line(3));
+        g.attach_debugger(debugger);
+        g.enqueue("in", event(), &mut returns).unwrap();
+        assert!(g.is_paused());
+        assert!(returns.is_empty());
+        let paused = g.paused().unwrap();
+        assert_eq!(paused.node, "all-1");
+        assert_eq!(paused.port, "in");
+        assert_eq!(paused.lines, Some((2, 4)));
+        assert_eq!(paused.event, literal!({"snot": "badger"}));
+        assert_eq!(paused.meta, literal!({"kafka": {}}));
+        assert_eq!(paused.pending, 0);
+
+        // stepping pauses before the next node
+        g.step(&mut returns).unwrap();
+        assert_eq!(g.paused().unwrap().node, "out");
+        assert!(returns.is_empty());
+
+        // resuming runs until the next breakpoint
+        g.resume(&mut returns).unwrap();
+        assert!(!g.is_paused());
+        assert!(g.paused().is_none());
+        assert_eq!(returns.len(), 1);
+        assert_eq!(returns[0].0, "out");
+        returns.clear();
+
+        let debugger = g.debugger_mut().unwrap();
+        debugger.clear(&Breakpoint::Line(3));
+        debugger.set(Breakpoint::Node("out".into()));
+        debugger.set(Breakpoint::Node("out".into()));
+        assert_eq!(debugger.breakpoints(), &[Breakpoint::Node("out".into())]);
+        g.enqueue("in", event(), &mut returns).unwrap();
+        assert_eq!(g.paused().unwrap().node, "out");
+
+        // detaching runs a paused graph to completion
+        assert!(g.detach_debugger(&mut returns).unwrap().is_some());
+        assert!(!g.is_paused());
+        assert_eq!(returns.len(), 1);
+        returns.clear();
+        g.enqueue("in", event(), &mut returns).unwrap();
+        assert_eq!(returns.len(), 1);
+    }
 }
@@ -50,10 +50,13 @@ use std::str::FromStr;
 use std::{fmt, sync::Mutex};
 use tremor_script::prelude::*;
 
+/// Step debugging of pipelines
+pub mod debug;
 /// Pipeline Errors
 pub mod errors;
 mod event;
 mod executable_graph;
+mod limits;
 
 #[macro_use]
 mod macros;
@@ -64,12 +67,17 @@ const MEASUREMENT: Cow<'static, str> = Cow::const_str("measurement");
 const TAGS: Cow<'static, str> = Cow::const_str("tags");
 const FIELDS: Cow<'static, str> = Cow::const_str("fields");
 const TIMESTAMP: Cow<'static, str> = Cow::const_str("timestamp");
+const LATENCY: Cow<'static, str> = Cow::const_str("latency");
 
 /// Tools to turn tremor query into pipelines
 pub mod query;
+/// WebAssembly plugins
+#[cfg(feature = "wasm")]
+pub mod wasm;
 pub use crate::event::{Event, ValueIter, ValueMetaIter};
-pub use crate::executable_graph::{ExecutableGraph, OperatorNode};
+pub use crate::executable_graph::{ExecutableGraph, OperatorNode, Snapshot};
 pub(crate) use crate::executable_graph::{NodeMetrics, State};
+pub use crate::limits::{estimate_size, Limits};
 pub use op::{ConfigImpl, InitializableOperator, Operator};
 pub use tremor_script::prelude::EventOriginUri;
 pub(crate) type PortIndexMap =
@@ -179,6 +187,11 @@ lazy_static! {
         let registry: Registry = tremor_script::registry();
         Mutex::new(registry)
     };
+
+    /// Published pipelines by id, embedded into other pipelines by
+    /// `pipeline::subflow` operators
+    pub static ref SUBFLOW_REGISTRY: Mutex<HashMap<String, query::Query>> =
+        Mutex::new(HashMap::new());
 }
 
 pub(crate) fn common_cow(s: &str) -> beef::Cow<'static, str> {
@@ -693,11 +706,21 @@ pub enum SignalKind {
 fn factory(node: &NodeConfig) -> Result<Box<dyn InitializableOperator>> {
     #[cfg(feature = "bert")]
     use op::bert::{SequenceClassificationFactory, SummerizationFactory};
+    #[cfg(feature = "chaos")]
+    use op::chaos::{CorruptFactory, DelayFactory, DropFactory, DuplicateFactory, ShuffleFactory};
     use op::debug::EventHistoryFactory;
-    use op::generic::{BatchFactory, CounterFactory};
+    use op::generic::{
+        BatchFactory, CollectFactory, ContractFactory, CounterFactory, PiiFactory, ReorderFactory,
+        RouterFactory, SplitFactory,
+    };
     use op::grouper::BucketGrouperFactory;
     use op::identity::PassthroughFactory;
-    use op::qos::{BackpressureFactory, PercentileFactory, RoundRobinFactory, WalFactory};
+    use op::pipeline::SubflowFactory;
+    use op::qos::{
+        BackpressureFactory, LoadBalancerFactory, PercentileFactory, RoundRobinFactory, WalFactory,
+    };
+    #[cfg(feature = "wasm")]
+    use op::wasm::PluginFactory;
     let name_parts: Vec<&str> = node.op_type.split("::").collect();
     let factory = match name_parts.as_slice() {
         ["passthrough"] => PassthroughFactory::new_boxed(),
@@ -709,14 +732,34 @@ fn factory(node: &NodeConfig) -> Result<Box<dyn InitializableOperator>> {
             BackpressureFactory::new_boxed()
         }
         ["generic", "counter"] => CounterFactory::new_boxed(),
+        ["generic", "router"] => RouterFactory::new_boxed(),
+        ["generic", "split"] => SplitFactory::new_boxed(),
+        ["generic", "collect"] => CollectFactory::new_boxed(),
+        ["generic", "reorder"] => ReorderFactory::new_boxed(),
+        ["generic", "pii"] => PiiFactory::new_boxed(),
+        ["generic", "contract"] => ContractFactory::new_boxed(),
         ["qos", "backpressure"] => BackpressureFactory::new_boxed(),
         ["qos", "roundrobin"] => RoundRobinFactory::new_boxed(),
+        ["qos", "lb"] => LoadBalancerFactory::new_boxed(),
         ["qos", "wal"] => WalFactory::new_boxed(),
         ["qos", "percentile"] => PercentileFactory::new_boxed(),
+        ["pipeline", "subflow"] => SubflowFactory::new_boxed(),
         #[cfg(feature = "bert")]
         ["bert", "sequence_classification"] => SequenceClassificationFactory::new_boxed(),
         #[cfg(feature = "bert")]
         ["bert", "summarization"] => SummerizationFactory::new_boxed(),
+        #[cfg(feature = "chaos")]
+        ["chaos", "drop"] => DropFactory::new_boxed(),
+        #[cfg(feature = "chaos")]
+        ["chaos", "duplicate"] => DuplicateFactory::new_boxed(),
+        #[cfg(feature = "chaos")]
+        ["chaos", "shuffle"] => ShuffleFactory::new_boxed(),
+        #[cfg(feature = "chaos")]
+        ["chaos", "delay"] => DelayFactory::new_boxed(),
+        #[cfg(feature = "chaos")]
+        ["chaos", "corrupt"] => CorruptFactory::new_boxed(),
+        #[cfg(feature = "wasm")]
+        ["wasm", "plugin"] => PluginFactory::new_boxed(),
         [namespace, name] => {
             return Err(ErrorKind::UnknownOp((*namespace).to_string(), (*name).to_string()).into());
         }
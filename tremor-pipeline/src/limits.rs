@@ -0,0 +1,67 @@
+// Copyright 2020-2021, The Tremor Team
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+use std::mem::size_of;
+use tremor_script::Value;
+
+/// Resource limits enforced by a pipeline
+///
+/// Limits are configured via the `#!config` directives `max_event_size`
+/// and `max_state_size` (both in bytes) of a trickle query.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct Limits {
+    /// Maximum estimated size of a single event (value and metadata)
+    pub max_event_size: Option<usize>,
+    /// Maximum estimated size of the state of a single operator
+    pub max_state_size: Option<usize>,
+}
+
+/// Estimates the memory footprint of a value in bytes.
+///
+/// This is not exact, it counts payload bytes (strings, keys, binaries) plus
+/// the size of a value node for every element, which is good enough
+/// to detect values growing out of bounds.
+#[must_use]
+pub fn estimate_size(value: &Value) -> usize {
+    let node = size_of::<Value>();
+    match value {
+        Value::Static(_) => node,
+        Value::String(s) => node + s.len(),
+        Value::Bytes(b) => node + b.len(),
+        Value::Array(a) => node + a.iter().map(estimate_size).sum::<usize>(),
+        Value::Object(o) => {
+            node + o
+                .iter()
+                .map(|(k, v)| k.len() + estimate_size(v))
+                .sum::<usize>()
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use tremor_script::prelude::*;
+
+    #[test]
+    fn size() {
+        let node = size_of::<Value>();
+        assert_eq!(estimate_size(&Value::null()), node);
+        assert_eq!(estimate_size(&Value::from("snot")), node + 4);
+        assert_eq!(
+            estimate_size(&literal!({"snot": [1, "badger"]})),
+            node + 4 + node + node + node + 6
+        );
+    }
+}
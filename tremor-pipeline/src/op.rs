@@ -14,13 +14,18 @@
 
 #[cfg(feature = "bert")]
 pub mod bert;
+#[cfg(feature = "chaos")]
+pub mod chaos;
 pub mod debug;
 pub mod generic;
 pub mod grouper;
 pub mod identity;
+pub mod pipeline;
 pub mod prelude;
 pub mod qos;
 pub mod trickle;
+#[cfg(feature = "wasm")]
+pub mod wasm;
 
 use self::prelude::OUT;
 use super::{Event, NodeConfig};
@@ -28,6 +33,7 @@ use crate::errors::Result;
 use beef::Cow;
 use halfbrown::HashMap;
 use regex::Regex;
+use tremor_script::prelude::{Mutable, ValueAccess};
 use tremor_script::Value;
 
 lazy_static::lazy_static! {
@@ -38,6 +44,65 @@ lazy_static::lazy_static! {
     };
 }
 
+/// FNV-1a with a final mix, stable across runs and platforms
+pub(crate) fn stable_hash(data: &[u8]) -> u64 {
+    let mut h = data.iter().fold(0xcbf2_9ce4_8422_2325_u64, |h, b| {
+        (h ^ u64::from(*b)).wrapping_mul(0x0100_0000_01b3)
+    });
+    h ^= h >> 33;
+    h = h.wrapping_mul(0xff51_afd7_ed55_8ccd);
+    h ^= h >> 33;
+    h = h.wrapping_mul(0xc4ce_b9fe_1a85_ec53);
+    h ^ (h >> 33)
+}
+
+/// A path into the value, or with a leading `$` into the metadata, of an
+/// event, e.g. `user.id` or `$kafka.key`
+#[derive(Debug, Clone, PartialEq)]
+pub(crate) struct EventPath {
+    meta: bool,
+    segments: Vec<String>,
+}
+
+impl EventPath {
+    pub(crate) fn parse(path: &str) -> Self {
+        let (meta, path) = if let Some(path) = path.strip_prefix('$') {
+            (true, path)
+        } else {
+            (false, path)
+        };
+        Self {
+            meta,
+            segments: path
+                .split('.')
+                .filter(|s| !s.is_empty())
+                .map(String::from)
+                .collect(),
+        }
+    }
+
+    /// The value at the path, if there is one
+    pub(crate) fn get<'event>(&self, event: &'event Event) -> Option<&'event Value<'event>> {
+        let (value, meta) = event.data.suffix().parts();
+        let root = if self.meta { meta } else { value };
+        self.segments
+            .iter()
+            .try_fold(root, |v, k| v.get(k.as_str()))
+    }
+
+    /// The mutable value at the path in the value or metadata, if there is one
+    pub(crate) fn get_mut<'value, 'event>(
+        &self,
+        value: &'value mut Value<'event>,
+        meta: &'value mut Value<'event>,
+    ) -> Option<&'value mut Value<'event>> {
+        let root = if self.meta { meta } else { value };
+        self.segments.iter().try_fold(root, |v, k| {
+            v.as_object_mut().and_then(|o| o.get_mut(k.as_str()))
+        })
+    }
+}
+
 /// Response type for operator callbacks returning both events and insights
 #[derive(Default, Clone, PartialEq, Debug)]
 pub struct EventAndInsights {
@@ -86,6 +151,38 @@ pub trait Operator: std::fmt::Debug + Send {
         event: Event,
     ) -> Result<EventAndInsights>;
 
+    /// Defines if the operator should be called with batches of events,
+    /// defaults to `false`. If set to `true`, `on_batch` should also be
+    /// implemented.
+    #[cfg(not(tarpaulin_include))]
+    fn handles_batch(&self) -> bool {
+        false
+    }
+
+    /// Called with a batch of events that arrived on the same port, in the
+    /// order they were emitted. Defaults to calling `on_event` for each of them.
+    ///
+    /// # Errors
+    /// if one of the events can not be processed
+    fn on_batch(
+        &mut self,
+        uid: u64,
+        port: &str,
+        state: &mut Value<'static>,
+        events: Vec<Event>,
+    ) -> Result<EventAndInsights> {
+        let mut res = EventAndInsights::default();
+        for event in events {
+            let EventAndInsights {
+                mut events,
+                mut insights,
+            } = self.on_event(uid, port, state, event)?;
+            res.events.append(&mut events);
+            res.insights.append(&mut insights);
+        }
+        Ok(res)
+    }
+
     /// Defines if the operatoir shold be called on the singalflow, defaults
     /// to `false`. If set to `true`, `on_signal` should also be implemented.
     #[cfg(not(tarpaulin_include))]
@@ -135,6 +232,21 @@ pub trait Operator: std::fmt::Debug + Send {
         Ok(Vec::new())
     }
 
+    /// Internal state of the operator besides its `state` value, to sync a
+    /// warm standby of the pipeline. Defaults to no internal state.
+    fn snapshot(&self) -> Option<Value<'static>> {
+        None
+    }
+
+    /// Restores internal state taken with `snapshot` from another instance
+    /// of the operator.
+    ///
+    /// # Errors
+    /// if the snapshot can not be restored
+    fn restore(&mut self, _snapshot: &Value<'static>) -> Result<()> {
+        Ok(())
+    }
+
     /// An operator is skippable and doesn't need to be executed
     #[cfg(not(tarpaulin_include))]
     fn skippable(&self) -> bool {
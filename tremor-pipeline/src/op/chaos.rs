@@ -0,0 +1,102 @@
+// Copyright 2020-2021, The Tremor Team
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+//! Operators injecting faults into the event flow, to exercise guaranteed
+//! delivery, deduplication and ordering under realistic failure conditions.
+//!
+//! They are only available with the `chaos` feature and are meant for test
+//! deployments. Each applies its fault to an event with the configured
+//! `probability` and takes an optional `seed`, so runs can be reproduced.
+
+pub mod corrupt;
+pub mod delay;
+pub mod drop;
+pub mod duplicate;
+pub mod shuffle;
+
+pub use corrupt::CorruptFactory;
+pub use delay::DelayFactory;
+pub use drop::DropFactory;
+pub use duplicate::DuplicateFactory;
+pub use shuffle::ShuffleFactory;
+
+use crate::errors::{ErrorKind, Result};
+use rand::rngs::StdRng;
+use rand::seq::SliceRandom;
+use rand::{Rng, SeedableRng};
+
+/// Seeded random decisions of a chaos operator
+#[derive(Debug, Clone)]
+pub(crate) struct Dice {
+    rng: StdRng,
+    probability: f64,
+}
+
+impl Dice {
+    pub(crate) fn new(probability: f64, seed: Option<u64>) -> Result<Self> {
+        if !(0.0..=1.0).contains(&probability) {
+            return Err(ErrorKind::BadOpConfig(format!(
+                "The probability of chaos operators needs to be between 0 and 1, not {}",
+                probability
+            ))
+            .into());
+        }
+        Ok(Self {
+            rng: seed.map_or_else(StdRng::from_entropy, StdRng::seed_from_u64),
+            probability,
+        })
+    }
+
+    /// If the fault applies to the next event
+    pub(crate) fn roll(&mut self) -> bool {
+        self.rng.gen_bool(self.probability)
+    }
+
+    /// A random index below `n`, which must not be 0
+    pub(crate) fn below(&mut self, n: usize) -> usize {
+        self.rng.gen_range(0..n)
+    }
+
+    pub(crate) fn shuffle<T>(&mut self, items: &mut [T]) {
+        items.shuffle(&mut self.rng);
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn probability() {
+        assert!(Dice::new(1.5, None).is_err());
+        assert!(Dice::new(-0.1, None).is_err());
+        assert!(Dice::new(f64::NAN, None).is_err());
+        let mut never = Dice::new(0.0, Some(1)).ok();
+        let mut always = Dice::new(1.0, Some(1)).ok();
+        for _ in 0..100 {
+            assert_eq!(Some(false), never.as_mut().map(Dice::roll));
+            assert_eq!(Some(true), always.as_mut().map(Dice::roll));
+        }
+    }
+
+    #[test]
+    fn seeded() -> Result<()> {
+        let mut a = Dice::new(0.5, Some(42))?;
+        let mut b = Dice::new(0.5, Some(42))?;
+        let a: Vec<bool> = (0..100).map(|_| a.roll()).collect();
+        let b: Vec<bool> = (0..100).map(|_| b.roll()).collect();
+        assert_eq!(a, b);
+        Ok(())
+    }
+}
@@ -0,0 +1,146 @@
+// Copyright 2020-2021, The Tremor Team
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+//! # Corrupt
+//!
+//! Corrupts event payloads at random, like a faulty producer or a broken
+//! codec, by removing a field or element, truncating a string, or replacing
+//! a value with one of a different type.
+//!
+//! ## Configuration
+//!
+//! See [Config](struct.Config.html) for details.
+//!
+//! ## Outputs
+//!
+//! Events, corrupted or not, are sent to `out`.
+
+use super::Dice;
+use crate::errors::{ErrorKind, Result};
+use crate::op::prelude::*;
+use simd_json::StaticNode;
+use tremor_script::prelude::*;
+
+#[derive(Debug, Clone, Deserialize)]
+pub struct Config {
+    /// Probability of an event being corrupted, between 0 and 1
+    pub probability: f64,
+    /// Seed of the random decisions, random if absent
+    pub seed: Option<u64>,
+}
+
+impl ConfigImpl for Config {}
+
+#[derive(Debug, Clone)]
+pub struct Corrupt {
+    dice: Dice,
+}
+
+op!(CorruptFactory(_uid, node) {
+if let Some(map) = &node.config {
+    let config: Config = Config::new(map)?;
+    Ok(Box::new(Corrupt {
+        dice: Dice::new(config.probability, config.seed)?,
+    }))
+} else {
+    Err(ErrorKind::MissingOpConfig(node.id.clone()).into())
+}});
+
+/// Corrupts a random part of the value
+fn corrupt(dice: &mut Dice, value: &mut Value) {
+    match value {
+        Value::Object(o) if !o.is_empty() => {
+            let i = dice.below(o.len());
+            if let Some(k) = o.keys().nth(i).cloned() {
+                if dice.below(2) == 0 {
+                    o.remove(&k);
+                } else if let Some(v) = o.get_mut(&k) {
+                    corrupt(dice, v);
+                }
+            }
+        }
+        Value::Array(a) if !a.is_empty() => {
+            let i = dice.below(a.len());
+            if dice.below(2) == 0 {
+                a.remove(i);
+            } else if let Some(v) = a.get_mut(i) {
+                corrupt(dice, v);
+            }
+        }
+        Value::String(s) if !s.is_empty() => {
+            let keep = dice.below(s.chars().count());
+            *s = s.chars().take(keep).collect::<String>().into();
+        }
+        Value::Static(StaticNode::Bool(b)) => *b = !*b,
+        _ => {
+            let encoded = value.encode();
+            *value = Value::from(encoded);
+        }
+    }
+}
+
+impl Operator for Corrupt {
+    fn on_event(
+        &mut self,
+        _uid: u64,
+        _port: &str,
+        _state: &mut Value<'static>,
+        mut event: Event,
+    ) -> Result<EventAndInsights> {
+        if self.dice.roll() {
+            let dice = &mut self.dice;
+            event.data.rent_mut(|data| {
+                let (value, _) = data.parts_mut();
+                corrupt(dice, value);
+            });
+        }
+        Ok(vec![(OUT, event)].into())
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use tremor_value::literal;
+
+    #[test]
+    fn corrupts() -> Result<()> {
+        let mut dice = Dice::new(1.0, Some(5))?;
+        let original = literal!({
+            "snot": "badger",
+            "list": [1, 2.5, true, null],
+            "nested": {"a": {"b": 42}}
+        });
+        for _ in 0..100 {
+            let mut value = original.clone();
+            corrupt(&mut dice, &mut value);
+            assert_ne!(original, value);
+        }
+
+        let mut op = Corrupt {
+            dice: Dice::new(0.0, None)?,
+        };
+        let mut state = Value::null();
+        let event = Event {
+            data: original.clone().into(),
+            ..Event::default()
+        };
+        let r = op.on_event(0, "in", &mut state, event)?;
+        assert_eq!(
+            Some(&original),
+            r.events.first().map(|(_, e)| e.data.suffix().value())
+        );
+        Ok(())
+    }
+}
@@ -0,0 +1,176 @@
+// Copyright 2020-2021, The Tremor Team
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+//! # Delay
+//!
+//! Delays events at random, like latency spikes of a network or a
+//! downstream system.
+//!
+//! ## Configuration
+//!
+//! See [Config](struct.Config.html) for details.
+//!
+//! ## Outputs
+//!
+//! Delayed events are sent to `out` with the first event or signal arriving
+//! `delay` after them, other events are sent right away and may overtake
+//! delayed ones.
+
+use super::Dice;
+use crate::errors::{ErrorKind, Result};
+use crate::op::prelude::*;
+use std::collections::BTreeMap;
+use tremor_script::prelude::*;
+
+#[derive(Debug, Clone, Deserialize)]
+pub struct Config {
+    /// Probability of an event being delayed, between 0 and 1
+    pub probability: f64,
+    /// Seed of the random decisions, random if absent
+    pub seed: Option<u64>,
+    /// Delay in milliseconds (default: 1000)
+    #[serde(default = "d_delay")]
+    pub delay: u64,
+}
+
+impl ConfigImpl for Config {}
+
+fn d_delay() -> u64 {
+    1000
+}
+
+#[derive(Debug, Clone)]
+pub struct Delay {
+    dice: Dice,
+    delay_ns: u64,
+    /// delayed events by the time they are due and arrival
+    delayed: BTreeMap<(u64, u64), Event>,
+    /// number of events delayed so far
+    arrived: u64,
+    /// the latest ingest time seen
+    clock_ns: u64,
+}
+
+impl Delay {
+    fn new(config: &Config) -> Result<Self> {
+        Ok(Self {
+            dice: Dice::new(config.probability, config.seed)?,
+            delay_ns: config.delay.saturating_mul(1_000_000),
+            delayed: BTreeMap::new(),
+            arrived: 0,
+            clock_ns: 0,
+        })
+    }
+
+    /// Sends the delayed events that are due
+    fn release(&mut self) -> Vec<(Cow<'static, str>, Event)> {
+        let pending = self
+            .delayed
+            .split_off(&(self.clock_ns.saturating_add(1), 0));
+        std::mem::replace(&mut self.delayed, pending)
+            .into_iter()
+            .map(|(_, e)| (OUT, e))
+            .collect()
+    }
+}
+
+op!(DelayFactory(_uid, node) {
+if let Some(map) = &node.config {
+    let config: Config = Config::new(map)?;
+    Ok(Box::new(Delay::new(&config)?))
+} else {
+    Err(ErrorKind::MissingOpConfig(node.id.clone()).into())
+}});
+
+impl Operator for Delay {
+    fn on_event(
+        &mut self,
+        _uid: u64,
+        _port: &str,
+        _state: &mut Value<'static>,
+        event: Event,
+    ) -> Result<EventAndInsights> {
+        self.clock_ns = self.clock_ns.max(event.ingest_ns);
+        let mut events = self.release();
+        if self.dice.roll() {
+            let due = self.clock_ns.saturating_add(self.delay_ns);
+            self.delayed.insert((due, self.arrived), event);
+            self.arrived += 1;
+        } else {
+            events.push((OUT, event));
+        }
+        Ok(events.into())
+    }
+
+    fn handles_signal(&self) -> bool {
+        true
+    }
+
+    fn on_signal(
+        &mut self,
+        _uid: u64,
+        _state: &mut Value<'static>,
+        signal: &mut Event,
+    ) -> Result<EventAndInsights> {
+        self.clock_ns = self.clock_ns.max(signal.ingest_ns);
+        Ok(self.release().into())
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use tremor_value::literal;
+
+    fn seqs(r: EventAndInsights) -> Vec<u64> {
+        r.events
+            .iter()
+            .filter_map(|(_, e)| e.data.suffix().value().get_u64("seq"))
+            .collect()
+    }
+
+    #[test]
+    fn delays() -> Result<()> {
+        let mut op = Delay::new(&Config {
+            probability: 1.0,
+            seed: None,
+            delay: 1,
+        })?;
+        let mut state = Value::null();
+        let mut send = |op: &mut Delay, seq: u64, ingest_ns: u64| {
+            let event = Event {
+                data: literal!({ "seq": seq }).into(),
+                ingest_ns,
+                ..Event::default()
+            };
+            op.on_event(0, "in", &mut state, event).map(seqs)
+        };
+        assert!(send(&mut op, 1, 0)?.is_empty());
+        assert!(send(&mut op, 2, 500_000)?.is_empty());
+        // only the first one is due
+        assert_eq!(vec![1], send(&mut op, 3, 1_000_000)?);
+
+        op.dice = Dice::new(0.0, None)?;
+        // not delayed events overtake delayed ones
+        assert_eq!(vec![4], send(&mut op, 4, 1_000_000)?);
+
+        let mut state = Value::null();
+        let mut signal = Event {
+            ingest_ns: 2_000_000,
+            ..Event::default()
+        };
+        assert_eq!(vec![2, 3], seqs(op.on_signal(0, &mut state, &mut signal)?));
+        Ok(())
+    }
+}
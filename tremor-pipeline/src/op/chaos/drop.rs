@@ -0,0 +1,118 @@
+// Copyright 2020-2021, The Tremor Team
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+//! # Drop
+//!
+//! Drops events at random, silently like a lossy network, or reporting them
+//! as failed like a rejecting sink.
+//!
+//! ## Configuration
+//!
+//! See [Config](struct.Config.html) for details.
+//!
+//! ## Outputs
+//!
+//! Events that are not dropped are sent to `out`.
+
+use super::Dice;
+use crate::errors::{ErrorKind, Result};
+use crate::op::prelude::*;
+use tremor_script::prelude::*;
+
+#[derive(Debug, Clone, Deserialize)]
+pub struct Config {
+    /// Probability of an event being dropped, between 0 and 1
+    pub probability: f64,
+    /// Seed of the random decisions, random if absent
+    pub seed: Option<u64>,
+    /// Report dropped events upstream as failed instead of dropping them
+    /// silently (default: false)
+    #[serde(default)]
+    pub fail: bool,
+}
+
+impl ConfigImpl for Config {}
+
+#[derive(Debug, Clone)]
+pub struct Dropper {
+    dice: Dice,
+    fail: bool,
+}
+
+op!(DropFactory(_uid, node) {
+if let Some(map) = &node.config {
+    let config: Config = Config::new(map)?;
+    Ok(Box::new(Dropper {
+        dice: Dice::new(config.probability, config.seed)?,
+        fail: config.fail,
+    }))
+} else {
+    Err(ErrorKind::MissingOpConfig(node.id.clone()).into())
+}});
+
+impl Operator for Dropper {
+    fn on_event(
+        &mut self,
+        _uid: u64,
+        _port: &str,
+        _state: &mut Value<'static>,
+        mut event: Event,
+    ) -> Result<EventAndInsights> {
+        if !self.dice.roll() {
+            Ok(vec![(OUT, event)].into())
+        } else if self.fail {
+            Ok(EventAndInsights {
+                insights: vec![event.insight_fail()],
+                ..EventAndInsights::default()
+            })
+        } else {
+            Ok(EventAndInsights::default())
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    fn send(op: &mut Dropper) -> Result<EventAndInsights> {
+        let mut state = Value::null();
+        op.on_event(0, "in", &mut state, Event::default())
+    }
+
+    #[test]
+    fn drops() -> Result<()> {
+        let mut op = Dropper {
+            dice: Dice::new(0.5, Some(7))?,
+            fail: false,
+        };
+        let mut passed = 0;
+        for _ in 0..1000 {
+            let r = send(&mut op)?;
+            assert!(r.insights.is_empty());
+            passed += r.events.len();
+        }
+        assert!(passed > 400 && passed < 600, "{} passed", passed);
+
+        op.dice = Dice::new(1.0, None)?;
+        op.fail = true;
+        let r = send(&mut op)?;
+        assert!(r.events.is_empty());
+        assert_eq!(
+            vec![CbAction::Fail],
+            r.insights.iter().map(|i| i.cb).collect::<Vec<_>>()
+        );
+        Ok(())
+    }
+}
@@ -0,0 +1,110 @@
+// Copyright 2020-2021, The Tremor Team
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+//! # Duplicate
+//!
+//! Sends events more than once at random, like a source redelivering them
+//! after a lost acknowledgement.
+//!
+//! ## Configuration
+//!
+//! See [Config](struct.Config.html) for details.
+//!
+//! ## Outputs
+//!
+//! Events and their duplicates, with the same event id, are sent to `out`.
+
+use super::Dice;
+use crate::errors::{ErrorKind, Result};
+use crate::op::prelude::*;
+use tremor_script::prelude::*;
+
+#[derive(Debug, Clone, Deserialize)]
+pub struct Config {
+    /// Probability of an event being duplicated, between 0 and 1
+    pub probability: f64,
+    /// Seed of the random decisions, random if absent
+    pub seed: Option<u64>,
+    /// Number of duplicates sent besides the event (default: 1)
+    #[serde(default = "d_copies")]
+    pub copies: usize,
+}
+
+impl ConfigImpl for Config {}
+
+fn d_copies() -> usize {
+    1
+}
+
+#[derive(Debug, Clone)]
+pub struct Duplicate {
+    dice: Dice,
+    copies: usize,
+}
+
+op!(DuplicateFactory(_uid, node) {
+if let Some(map) = &node.config {
+    let config: Config = Config::new(map)?;
+    Ok(Box::new(Duplicate {
+        dice: Dice::new(config.probability, config.seed)?,
+        copies: config.copies,
+    }))
+} else {
+    Err(ErrorKind::MissingOpConfig(node.id.clone()).into())
+}});
+
+impl Operator for Duplicate {
+    fn on_event(
+        &mut self,
+        _uid: u64,
+        _port: &str,
+        _state: &mut Value<'static>,
+        event: Event,
+    ) -> Result<EventAndInsights> {
+        let copies = if self.dice.roll() { self.copies } else { 0 };
+        let mut events = Vec::with_capacity(copies + 1);
+        for _ in 0..copies {
+            events.push((OUT, event.clone()));
+        }
+        events.push((OUT, event));
+        Ok(events.into())
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::EventId;
+
+    #[test]
+    fn duplicates() -> Result<()> {
+        let mut op = Duplicate {
+            dice: Dice::new(1.0, None)?,
+            copies: 2,
+        };
+        let mut state = Value::null();
+        let event = Event {
+            id: EventId::new(1, 2, 3),
+            ..Event::default()
+        };
+        let r = op.on_event(0, "in", &mut state, event)?;
+        assert_eq!(3, r.events.len());
+        assert!(r.events.iter().all(|(_, e)| e.id == EventId::new(1, 2, 3)));
+
+        op.dice = Dice::new(0.0, None)?;
+        let r = op.on_event(0, "in", &mut state, Event::default())?;
+        assert_eq!(1, r.events.len());
+        Ok(())
+    }
+}
@@ -0,0 +1,190 @@
+// Copyright 2020-2021, The Tremor Team
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+//! # Shuffle
+//!
+//! Reorders events at random, like messages taking different routes through
+//! a network.
+//!
+//! ## Configuration
+//!
+//! See [Config](struct.Config.html) for details.
+//!
+//! ## Outputs
+//!
+//! Events are held back at random and sent to `out` in random order: a held
+//! back event is sent when more than `window` events are held back, or on the
+//! first signal after it was held back for `max_delay`. Events that are not
+//! held back overtake the held back ones.
+
+use super::Dice;
+use crate::errors::{ErrorKind, Result};
+use crate::op::prelude::*;
+use tremor_script::prelude::*;
+
+#[derive(Debug, Clone, Deserialize)]
+pub struct Config {
+    /// Probability of an event being held back, between 0 and 1
+    pub probability: f64,
+    /// Seed of the random decisions, random if absent
+    pub seed: Option<u64>,
+    /// Maximum number of events held back (default: 10)
+    #[serde(default = "d_window")]
+    pub window: usize,
+    /// Maximum time in milliseconds an event is held back (default: 1000)
+    #[serde(default = "d_max_delay")]
+    pub max_delay: u64,
+}
+
+impl ConfigImpl for Config {}
+
+fn d_window() -> usize {
+    10
+}
+
+fn d_max_delay() -> u64 {
+    1000
+}
+
+#[derive(Debug, Clone)]
+pub struct Shuffle {
+    dice: Dice,
+    window: usize,
+    max_delay_ns: u64,
+    /// held back events and the time they were held back at
+    held: Vec<(u64, Event)>,
+    /// the latest ingest time seen
+    clock_ns: u64,
+}
+
+impl Shuffle {
+    fn new(config: &Config) -> Result<Self> {
+        Ok(Self {
+            dice: Dice::new(config.probability, config.seed)?,
+            window: config.window,
+            max_delay_ns: config.max_delay.saturating_mul(1_000_000),
+            held: Vec::new(),
+            clock_ns: 0,
+        })
+    }
+
+    /// Sends the events held back for too long in random order
+    fn release(&mut self) -> Vec<(Cow<'static, str>, Event)> {
+        let deadline = self.clock_ns.saturating_sub(self.max_delay_ns);
+        let (mut expired, held): (Vec<_>, Vec<_>) = self
+            .held
+            .drain(..)
+            .partition(|(held_ns, _)| *held_ns <= deadline);
+        self.held = held;
+        self.dice.shuffle(&mut expired);
+        expired.into_iter().map(|(_, e)| (OUT, e)).collect()
+    }
+}
+
+op!(ShuffleFactory(_uid, node) {
+if let Some(map) = &node.config {
+    let config: Config = Config::new(map)?;
+    Ok(Box::new(Shuffle::new(&config)?))
+} else {
+    Err(ErrorKind::MissingOpConfig(node.id.clone()).into())
+}});
+
+impl Operator for Shuffle {
+    fn on_event(
+        &mut self,
+        _uid: u64,
+        _port: &str,
+        _state: &mut Value<'static>,
+        event: Event,
+    ) -> Result<EventAndInsights> {
+        self.clock_ns = self.clock_ns.max(event.ingest_ns);
+        if !self.dice.roll() {
+            return Ok(vec![(OUT, event)].into());
+        }
+        self.held.push((self.clock_ns, event));
+        if self.held.len() > self.window {
+            let i = self.dice.below(self.held.len());
+            let (_, event) = self.held.swap_remove(i);
+            Ok(vec![(OUT, event)].into())
+        } else {
+            Ok(EventAndInsights::default())
+        }
+    }
+
+    fn handles_signal(&self) -> bool {
+        true
+    }
+
+    fn on_signal(
+        &mut self,
+        _uid: u64,
+        _state: &mut Value<'static>,
+        signal: &mut Event,
+    ) -> Result<EventAndInsights> {
+        self.clock_ns = self.clock_ns.max(signal.ingest_ns);
+        Ok(self.release().into())
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use tremor_value::literal;
+
+    fn send(op: &mut Shuffle, seq: u64) -> Result<Vec<u64>> {
+        let event = Event {
+            data: literal!({ "seq": seq }).into(),
+            ..Event::default()
+        };
+        let mut state = Value::null();
+        Ok(seqs(op.on_event(0, "in", &mut state, event)?))
+    }
+
+    fn seqs(r: EventAndInsights) -> Vec<u64> {
+        r.events
+            .iter()
+            .filter_map(|(_, e)| e.data.suffix().value().get_u64("seq"))
+            .collect()
+    }
+
+    #[test]
+    fn shuffles() -> Result<()> {
+        let mut op = Shuffle::new(&Config {
+            probability: 1.0,
+            seed: Some(3),
+            window: 3,
+            max_delay: 1,
+        })?;
+        let mut out = Vec::new();
+        for seq in 0..10 {
+            out.extend(send(&mut op, seq)?);
+        }
+        // the window is full after the first 3 events
+        assert_eq!(7, out.len());
+        assert_eq!(3, op.held.len());
+
+        let mut state = Value::null();
+        let mut signal = Event {
+            ingest_ns: 2_000_000,
+            ..Event::default()
+        };
+        out.extend(seqs(op.on_signal(0, &mut state, &mut signal)?));
+        assert!(op.held.is_empty());
+        let mut sorted = out.clone();
+        sorted.sort_unstable();
+        assert_eq!((0..10).collect::<Vec<_>>(), sorted);
+        assert_ne!(sorted, out);
+        Ok(())
+    }
+}
@@ -13,7 +13,19 @@
 // limitations under the License.
 
 pub mod batch;
+pub mod collect;
+pub mod contract;
 pub mod counter;
+pub mod pii;
+pub mod reorder;
+pub mod router;
+pub mod split;
 
 pub use batch::BatchFactory;
+pub use collect::CollectFactory;
+pub use contract::ContractFactory;
 pub use counter::CounterFactory;
+pub use pii::PiiFactory;
+pub use reorder::ReorderFactory;
+pub use router::RouterFactory;
+pub use split::SplitFactory;
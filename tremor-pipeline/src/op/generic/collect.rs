@@ -0,0 +1,323 @@
+// Copyright 2020-2021, The Tremor Team
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+//! # Collector
+//!
+//! ## Configuration
+//!
+//! See [Config](struct.Config.html) for details.
+//!
+//! ## Outputs
+//!
+//! Collects events with the same `key` into one event holding the array of
+//! their values, it is sent to `out` once all elements of a
+//! [split](../split/index.html) arrived, after `count` events or when the
+//! group is older than `timeout`, whichever happens first. Elements carrying
+//! a split index are ordered by it. The metadata of the collected event holds
+//! the key and the number of elements, e.g.
+//! `{"collect": {"key": "h1", "count": 3}}`.
+
+use crate::errors::Result;
+use crate::op::prelude::*;
+use crate::op::EventPath;
+use crate::{EventId, EventIdGenerator};
+use tremor_script::prelude::*;
+
+#[derive(Debug, Clone, Deserialize)]
+pub struct Config {
+    /// Path of the value events are grouped by, e.g. `request_id` or
+    /// `$kafka.key` (default: all events form one group)
+    #[serde(default)]
+    pub key: Option<String>,
+    /// Path of the value to collect (default: the whole event)
+    #[serde(default)]
+    pub field: String,
+    /// Metadata key of the split index and total (default: `split`)
+    #[serde(default = "super::split::d_meta")]
+    pub meta: String,
+    /// Maximum number of elements of a collected event
+    #[serde(default)]
+    pub count: Option<usize>,
+    /// Maximum time in milliseconds a group is held back
+    #[serde(default)]
+    pub timeout: Option<u64>,
+}
+
+impl ConfigImpl for Config {}
+
+impl Default for Config {
+    fn default() -> Self {
+        Self {
+            key: None,
+            field: String::new(),
+            meta: super::split::d_meta(),
+            count: None,
+            timeout: None,
+        }
+    }
+}
+
+#[derive(Debug, Clone)]
+struct Group {
+    key: Value<'static>,
+    elements: Vec<(usize, Value<'static>)>,
+    total: Option<usize>,
+    first_ns: u64,
+    id: EventId,
+    transactional: bool,
+}
+
+impl Group {
+    fn is_complete(&self, count: Option<usize>, max_delay_ns: Option<u64>, now_ns: u64) -> bool {
+        let len = self.elements.len();
+        self.total.map_or(false, |total| len >= total)
+            || count.map_or(false, |count| len >= count)
+            || max_delay_ns.map_or(false, |delay| now_ns.saturating_sub(self.first_ns) > delay)
+    }
+
+    fn into_event(mut self) -> (Cow<'static, str>, Event) {
+        self.elements.sort_by_key(|(index, _)| *index);
+        let count = self.elements.len();
+        let values: Vec<Value<'static>> = self.elements.into_iter().map(|(_, v)| v).collect();
+        let meta = literal!({
+            "collect": {
+                "key": self.key,
+                "count": count
+            }
+        });
+        let event = Event {
+            id: self.id,
+            data: (Value::from(values), meta).into(),
+            ingest_ns: self.first_ns,
+            transactional: self.transactional,
+            ..Event::default()
+        };
+        (OUT, event)
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct Collect {
+    key: Option<EventPath>,
+    field: EventPath,
+    meta: String,
+    count: Option<usize>,
+    max_delay_ns: Option<u64>,
+    groups: HashMap<String, Group>,
+    event_id_gen: EventIdGenerator,
+}
+
+impl Collect {
+    fn new(uid: u64, config: Config) -> Self {
+        Self {
+            key: config.key.as_deref().map(EventPath::parse),
+            field: EventPath::parse(&config.field),
+            meta: config.meta,
+            count: config.count,
+            max_delay_ns: config.timeout.map(|ms| ms * 1_000_000),
+            groups: HashMap::new(),
+            event_id_gen: EventIdGenerator::new(uid),
+        }
+    }
+}
+
+op!(CollectFactory(uid, node) {
+    let config = node.config.as_ref().map_or_else(|| Ok(Config::default()), Config::new)?;
+    Ok(Box::new(Collect::new(uid, config)))
+});
+
+impl Operator for Collect {
+    fn on_event(
+        &mut self,
+        _uid: u64,
+        _port: &str,
+        _state: &mut Value<'static>,
+        event: Event,
+    ) -> Result<EventAndInsights> {
+        let key = self
+            .key
+            .as_ref()
+            .and_then(|key| key.get(&event))
+            .map_or_else(Value::null, Value::clone_static);
+        let element = self
+            .field
+            .get(&event)
+            .map_or_else(Value::null, Value::clone_static);
+        let position = event.data.suffix().meta().get(self.meta.as_str());
+        let index = position.and_then(|p| p.get_usize("index"));
+        let total = position.and_then(|p| p.get_usize("total"));
+
+        let encoded = key.encode();
+        let event_id_gen = &mut self.event_id_gen;
+        let group = self.groups.entry(encoded.clone()).or_insert_with(|| Group {
+            key,
+            elements: Vec::new(),
+            total: None,
+            first_ns: event.ingest_ns,
+            id: event_id_gen.next_id(),
+            transactional: false,
+        });
+        // events without a split index keep their arrival order
+        let index = index.unwrap_or_else(|| group.elements.len());
+        group.elements.push((index, element));
+        group.total = total.or(group.total);
+        group.id.track(&event.id);
+        group.transactional = group.transactional || event.transactional;
+
+        if group.is_complete(self.count, self.max_delay_ns, event.ingest_ns) {
+            Ok(self
+                .groups
+                .remove(&encoded)
+                .map_or_else(EventAndInsights::default, |group| {
+                    vec![group.into_event()].into()
+                }))
+        } else {
+            Ok(EventAndInsights::default())
+        }
+    }
+
+    fn handles_signal(&self) -> bool {
+        self.max_delay_ns.is_some()
+    }
+
+    fn on_signal(
+        &mut self,
+        _uid: u64,
+        _state: &mut Value<'static>,
+        signal: &mut Event,
+    ) -> Result<EventAndInsights> {
+        let (count, max_delay_ns) = (self.count, self.max_delay_ns);
+        let expired: Vec<String> = self
+            .groups
+            .iter()
+            .filter(|(_, group)| group.is_complete(count, max_delay_ns, signal.ingest_ns))
+            .map(|(key, _)| key.clone())
+            .collect();
+        let events = expired
+            .iter()
+            .filter_map(|key| self.groups.remove(key))
+            .map(Group::into_event)
+            .collect::<Vec<_>>();
+        Ok(events.into())
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::op::generic::split::{self, Split};
+    use tremor_value::literal;
+
+    fn event(value: Value<'static>, meta: Value<'static>, ingest_ns: u64) -> Event {
+        Event {
+            data: (value, meta).into(),
+            ingest_ns,
+            ..Event::default()
+        }
+    }
+
+    fn values(events: EventAndInsights) -> Vec<(Value<'static>, Value<'static>)> {
+        events
+            .events
+            .into_iter()
+            .map(|(_, e)| {
+                let (value, meta) = e.data.suffix().parts();
+                (value.clone_static(), meta.clone_static())
+            })
+            .collect()
+    }
+
+    #[test]
+    fn reverses_split() -> Result<()> {
+        let mut state = Value::null();
+        let mut split = Split::from(split::Config {
+            field: "items".to_string(),
+            ..split::Config::default()
+        });
+        let mut collect = Collect::new(
+            0,
+            Config {
+                key: Some("id".to_string()),
+                field: "items".to_string(),
+                ..Config::default()
+            },
+        );
+        let original = literal!({"id": "r1", "items": [1, 2, 3]});
+        let mut parts = split
+            .on_event(0, "in", &mut state, event(original, Value::object(), 0))?
+            .events;
+        // elements arriving out of order are put back in place
+        parts.reverse();
+        let mut collected = Vec::new();
+        for (_, part) in parts {
+            collected.append(&mut values(collect.on_event(0, "in", &mut state, part)?));
+        }
+        assert_eq!(
+            vec![(
+                literal!([1, 2, 3]),
+                literal!({"collect": {"key": "r1", "count": 3}})
+            )],
+            collected
+        );
+        assert!(collect.groups.is_empty());
+        Ok(())
+    }
+
+    #[test]
+    fn count_and_timeout() -> Result<()> {
+        let mut state = Value::null();
+        let mut op = Collect::new(
+            0,
+            Config {
+                key: Some("$host".to_string()),
+                field: "v".to_string(),
+                count: Some(2),
+                timeout: Some(1),
+                ..Config::default()
+            },
+        );
+        let mut collect = |v: u64, host: &str, ns: u64| {
+            let e = event(
+                literal!({ "v": v }),
+                literal!({ "host": host.to_string() }),
+                ns,
+            );
+            op.on_event(0, "in", &mut state, e).map(values)
+        };
+        assert!(collect(1, "a", 0)?.is_empty());
+        assert!(collect(2, "b", 0)?.is_empty());
+        assert_eq!(
+            vec![(
+                literal!([1, 3]),
+                literal!({"collect": {"key": "a", "count": 2}})
+            )],
+            collect(3, "a", 10)?
+        );
+
+        let mut signal = Event {
+            ingest_ns: 2_000_000,
+            ..Event::default()
+        };
+        assert!(op.handles_signal());
+        assert_eq!(
+            vec![(
+                literal!([2]),
+                literal!({"collect": {"key": "b", "count": 1}})
+            )],
+            values(op.on_signal(0, &mut state, &mut signal)?)
+        );
+        assert!(op.groups.is_empty());
+        Ok(())
+    }
+}
@@ -0,0 +1,776 @@
+// Copyright 2020-2021, The Tremor Team
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+//! # Schema contracts
+//!
+//! Validates events against the versions of a JSON schema contract.
+//!
+//! The supported JSON schema keywords are `type`, `enum`, `const`,
+//! `properties`, `required`, `additionalProperties`, `items`, `minItems`,
+//! `maxItems`, `minLength`, `maxLength`, `pattern`, `minimum`, `maximum`,
+//! `exclusiveMinimum`, `exclusiveMaximum`, `allOf`, `anyOf`, `oneOf` and
+//! `not`. Schemas using other validation keywords, like `$ref`, are rejected.
+//!
+//! ## Configuration
+//!
+//! See [Config](struct.Config.html) for details.
+//!
+//! ## Outputs
+//!
+//! Events matching a version are sent to `out`, tagged with the contract and
+//! the version, e.g. `{"contract": {"name": "orders", "version": "2"}}`.
+//!
+//! Events matching no version are sent to `violation`, tagged with the
+//! reasons, e.g.
+//! `{"contract": {"name": "orders", "version": null, "errors": ["/id: expected integer"]}}`.
+//! Without a declared version, the errors are the ones of the newest version.
+
+use crate::errors::{Error, ErrorKind, Result};
+use crate::op::prelude::*;
+use crate::op::EventPath;
+use regex::Regex;
+use simd_json::OwnedValue;
+use std::cmp::Ordering;
+use std::path::Path;
+use tremor_script::prelude::*;
+
+const VIOLATION: Cow<'static, str> = Cow::const_str("violation");
+
+/// Validation keywords that aren't supported
+const UNSUPPORTED: [&str; 8] = [
+    "$ref",
+    "patternProperties",
+    "propertyNames",
+    "dependencies",
+    "contains",
+    "uniqueItems",
+    "multipleOf",
+    "if",
+];
+
+const TYPES: [&str; 7] = [
+    "null", "boolean", "integer", "number", "string", "array", "object",
+];
+
+#[derive(Debug, Clone, Deserialize)]
+pub struct VersionConfig {
+    /// Name of the version, e.g. `2` or `2.1`
+    pub version: String,
+    /// The JSON schema of the version
+    pub schema: OwnedValue,
+}
+
+#[derive(Debug, Clone, Deserialize)]
+pub struct Config {
+    /// Name of the contract (default: the id of the operator)
+    #[serde(default)]
+    pub name: Option<String>,
+    /// Directory of the schema files of the contract, named after their
+    /// version, e.g. `1.json`, `2.json` or `2.1.yaml`
+    #[serde(default)]
+    pub directory: Option<String>,
+    /// Schema versions given in the config
+    #[serde(default)]
+    pub versions: Vec<VersionConfig>,
+    /// Path of the version an event declares, e.g. `$schema_version`. Events
+    /// are only validated against their declared version. Without it events
+    /// are validated against the newest version first, then the older ones.
+    #[serde(default)]
+    pub version_field: Option<String>,
+    /// Metadata key of the validation result (default: `contract`)
+    #[serde(default = "d_meta")]
+    pub meta: String,
+}
+
+impl ConfigImpl for Config {}
+
+fn d_meta() -> String {
+    "contract".to_string()
+}
+
+fn invalid<S: Into<String>>(msg: S) -> Error {
+    ErrorKind::BadOpConfig(msg.into()).into()
+}
+
+/// A compiled JSON schema
+#[derive(Debug, Clone, Default)]
+struct Schema {
+    /// `false` schemas reject every value
+    reject: bool,
+    types: Vec<String>,
+    /// from `enum` and `const`
+    values: Option<Vec<OwnedValue>>,
+    properties: Vec<(String, Schema)>,
+    required: Vec<String>,
+    additional_properties: Option<Box<Schema>>,
+    items: Option<Box<Schema>>,
+    min_items: Option<usize>,
+    max_items: Option<usize>,
+    min_length: Option<usize>,
+    max_length: Option<usize>,
+    pattern: Option<Regex>,
+    minimum: Option<f64>,
+    maximum: Option<f64>,
+    exclusive_minimum: Option<f64>,
+    exclusive_maximum: Option<f64>,
+    all_of: Vec<Schema>,
+    any_of: Vec<Schema>,
+    one_of: Vec<Schema>,
+    not: Option<Box<Schema>>,
+}
+
+fn usize_of(schema: &OwnedValue, key: &str) -> Result<Option<usize>> {
+    schema.get(key).map_or(Ok(None), |v| {
+        v.as_u64()
+            .and_then(|n| usize::try_from(n).ok())
+            .map(Some)
+            .ok_or_else(|| invalid(format!("`{}` must be a positive integer", key)))
+    })
+}
+
+fn f64_of(schema: &OwnedValue, key: &str) -> Result<Option<f64>> {
+    schema.get(key).map_or(Ok(None), |v| {
+        v.cast_f64()
+            .map(Some)
+            .ok_or_else(|| invalid(format!("`{}` must be a number", key)))
+    })
+}
+
+fn schemas_of(schema: &OwnedValue, key: &str) -> Result<Vec<Schema>> {
+    schema.get(key).map_or(Ok(Vec::new()), |v| {
+        v.as_array()
+            .ok_or_else(|| invalid(format!("`{}` must be an array of schemas", key)))?
+            .iter()
+            .map(Schema::compile)
+            .collect()
+    })
+}
+
+fn schema_of(schema: &OwnedValue, key: &str) -> Result<Option<Box<Schema>>> {
+    schema
+        .get(key)
+        .map(|s| Schema::compile(s).map(Box::new))
+        .transpose()
+}
+
+fn is_type(value: &Value, t: &str) -> bool {
+    match t {
+        "null" => value.is_null(),
+        "boolean" => value.is_bool(),
+        "integer" => value.is_i64() || value.is_u64(),
+        "number" => value.is_i64() || value.is_u64() || value.is_f64(),
+        "string" => value.is_str(),
+        "array" => value.is_array(),
+        "object" => value.is_object(),
+        _ => false,
+    }
+}
+
+impl Schema {
+    fn compile(schema: &OwnedValue) -> Result<Self> {
+        if let Some(allowed) = schema.as_bool() {
+            return Ok(Self {
+                reject: !allowed,
+                ..Self::default()
+            });
+        }
+        let keys = schema
+            .as_object()
+            .ok_or_else(|| invalid("a schema must be a record or a boolean"))?;
+        if let Some(keyword) = UNSUPPORTED.iter().find(|k| keys.contains_key(**k)) {
+            return Err(invalid(format!("unsupported keyword `{}`", keyword)));
+        }
+
+        let types = match schema.get("type") {
+            None => Vec::new(),
+            Some(t) => {
+                let types: Vec<String> = if let Some(t) = t.as_str() {
+                    vec![t.to_string()]
+                } else {
+                    t.as_array()
+                        .map(|ts| {
+                            ts.iter()
+                                .filter_map(|t| t.as_str().map(String::from))
+                                .collect()
+                        })
+                        .unwrap_or_default()
+                };
+                if let Some(t) = types.iter().find(|t| !TYPES.contains(&t.as_str())) {
+                    return Err(invalid(format!("unknown type `{}`", t)));
+                }
+                if types.is_empty() {
+                    return Err(invalid("`type` must be a string or an array of strings"));
+                }
+                types
+            }
+        };
+        let values = match (schema.get("enum"), schema.get("const")) {
+            (Some(OwnedValue::Array(values)), _) => Some(values.clone()),
+            (Some(_), _) => return Err(invalid("`enum` must be an array")),
+            (None, Some(value)) => Some(vec![value.clone()]),
+            (None, None) => None,
+        };
+        let properties = match schema.get("properties") {
+            None => Vec::new(),
+            Some(properties) => properties
+                .as_object()
+                .ok_or_else(|| invalid("`properties` must be a record"))?
+                .iter()
+                .map(|(k, s)| Ok((k.to_string(), Self::compile(s)?)))
+                .collect::<Result<_>>()?,
+        };
+        let required = match schema.get("required") {
+            None => Vec::new(),
+            Some(required) => required
+                .as_array()
+                .and_then(|r| {
+                    r.iter()
+                        .map(|k| k.as_str().map(String::from))
+                        .collect::<Option<Vec<String>>>()
+                })
+                .ok_or_else(|| invalid("`required` must be an array of strings"))?,
+        };
+        let pattern = schema
+            .get("pattern")
+            .map(|p| {
+                let p = p
+                    .as_str()
+                    .ok_or_else(|| invalid("`pattern` must be a string"))?;
+                Regex::new(p).map_err(|e| invalid(e.to_string()))
+            })
+            .transpose()?;
+
+        Ok(Self {
+            reject: false,
+            types,
+            values,
+            properties,
+            required,
+            additional_properties: schema_of(schema, "additionalProperties")?,
+            items: schema_of(schema, "items")?,
+            min_items: usize_of(schema, "minItems")?,
+            max_items: usize_of(schema, "maxItems")?,
+            min_length: usize_of(schema, "minLength")?,
+            max_length: usize_of(schema, "maxLength")?,
+            pattern,
+            minimum: f64_of(schema, "minimum")?,
+            maximum: f64_of(schema, "maximum")?,
+            exclusive_minimum: f64_of(schema, "exclusiveMinimum")?,
+            exclusive_maximum: f64_of(schema, "exclusiveMaximum")?,
+            all_of: schemas_of(schema, "allOf")?,
+            any_of: schemas_of(schema, "anyOf")?,
+            one_of: schemas_of(schema, "oneOf")?,
+            not: schema_of(schema, "not")?,
+        })
+    }
+
+    fn is_valid(&self, value: &Value) -> bool {
+        let mut errors = Vec::new();
+        self.validate(value, "", &mut errors);
+        errors.is_empty()
+    }
+
+    /// Adds the reasons `value`, found at the JSON pointer `path`, doesn't
+    /// match the schema to `errors`
+    fn validate(&self, value: &Value, path: &str, errors: &mut Vec<String>) {
+        let mut fail = |msg: String| {
+            errors.push(format!(
+                "{}: {}",
+                if path.is_empty() { "/" } else { path },
+                msg
+            ));
+        };
+        if self.reject {
+            return fail("is not allowed".to_string());
+        }
+        if !self.types.is_empty() && !self.types.iter().any(|t| is_type(value, t)) {
+            return fail(format!("expected {}", self.types.join(" or ")));
+        }
+        if let Some(values) = &self.values {
+            if !values.iter().any(|v| value == v) {
+                fail("is not one of the allowed values".to_string());
+            }
+        }
+        if let Some(s) = value.as_str() {
+            let len = s.chars().count();
+            if let Some(min) = self.min_length.filter(|min| len < *min) {
+                fail(format!("is shorter than {} characters", min));
+            }
+            if let Some(max) = self.max_length.filter(|max| len > *max) {
+                fail(format!("is longer than {} characters", max));
+            }
+            if let Some(pattern) = &self.pattern {
+                if !pattern.is_match(s) {
+                    fail(format!("does not match `{}`", pattern));
+                }
+            }
+        }
+        if is_type(value, "number") {
+            let n = value.cast_f64().unwrap_or_default();
+            if let Some(min) = self.minimum.filter(|min| n < *min) {
+                fail(format!("must be at least {}", min));
+            }
+            if let Some(max) = self.maximum.filter(|max| n > *max) {
+                fail(format!("must be at most {}", max));
+            }
+            if let Some(min) = self.exclusive_minimum.filter(|min| n <= *min) {
+                fail(format!("must be greater than {}", min));
+            }
+            if let Some(max) = self.exclusive_maximum.filter(|max| n >= *max) {
+                fail(format!("must be less than {}", max));
+            }
+        }
+        if let Some(a) = value.as_array() {
+            if let Some(min) = self.min_items.filter(|min| a.len() < *min) {
+                fail(format!("must have at least {} items", min));
+            }
+            if let Some(max) = self.max_items.filter(|max| a.len() > *max) {
+                fail(format!("must have at most {} items", max));
+            }
+        }
+        if let Some(o) = value.as_object() {
+            for k in self.required.iter().filter(|k| !o.contains_key(k.as_str())) {
+                fail(format!("missing required property `{}`", k));
+            }
+        }
+        if !self.any_of.is_empty() && !self.any_of.iter().any(|s| s.is_valid(value)) {
+            fail("does not match any of the `anyOf` schemas".to_string());
+        }
+        if !self.one_of.is_empty() && self.one_of.iter().filter(|s| s.is_valid(value)).count() != 1
+        {
+            fail("does not match exactly one of the `oneOf` schemas".to_string());
+        }
+        if self.not.as_ref().map_or(false, |s| s.is_valid(value)) {
+            fail("matches the `not` schema".to_string());
+        }
+
+        // nested values, reported with their own path
+        for s in &self.all_of {
+            s.validate(value, path, errors);
+        }
+        if let (Some(items), Some(a)) = (&self.items, value.as_array()) {
+            for (i, v) in a.iter().enumerate() {
+                items.validate(v, &format!("{}/{}", path, i), errors);
+            }
+        }
+        if let Some(o) = value.as_object() {
+            for (k, v) in o.iter() {
+                let path = format!("{}/{}", path, k);
+                if let Some((_, s)) = self.properties.iter().find(|(p, _)| p == &**k) {
+                    s.validate(v, &path, errors);
+                } else if let Some(s) = &self.additional_properties {
+                    s.validate(v, &path, errors);
+                }
+            }
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, PartialOrd, Ord)]
+enum VersionPart {
+    Number(u64),
+    Name(String),
+}
+
+/// Orders versions like `1 < 2 < 2.1 < 10`
+fn cmp_versions(a: &str, b: &str) -> Ordering {
+    let parts = |v: &str| -> Vec<VersionPart> {
+        v.split('.')
+            .map(|p| {
+                p.parse()
+                    .map_or_else(|_| VersionPart::Name(p.to_string()), VersionPart::Number)
+            })
+            .collect()
+    };
+    parts(a).cmp(&parts(b))
+}
+
+/// Loads the versions in a directory, skipping files that aren't json or yaml
+fn load_directory(dir: &str) -> Result<Vec<VersionConfig>> {
+    let mut versions = Vec::new();
+    for entry in std::fs::read_dir(dir)? {
+        let path = entry?.path();
+        let ext = path.extension().and_then(std::ffi::OsStr::to_str);
+        let version = path.file_stem().and_then(std::ffi::OsStr::to_str);
+        if let (Some(ext @ ("json" | "yaml" | "yml")), Some(version)) = (ext, version) {
+            versions.push(VersionConfig {
+                version: version.to_string(),
+                schema: load_schema(&path, ext)
+                    .map_err(|e| invalid(format!("{}: {}", path.display(), e)))?,
+            });
+        }
+    }
+    Ok(versions)
+}
+
+fn load_schema(path: &Path, ext: &str) -> Result<OwnedValue> {
+    let mut raw = std::fs::read(path)?;
+    Ok(if ext == "json" {
+        simd_json::to_owned_value(&mut raw)?
+    } else {
+        serde_yaml::from_slice(&raw)?
+    })
+}
+
+#[derive(Debug, Clone)]
+struct Version {
+    name: String,
+    schema: Schema,
+}
+
+#[derive(Debug, Clone)]
+pub struct Contract {
+    name: String,
+    /// newest first
+    versions: Vec<Version>,
+    version_field: Option<EventPath>,
+    meta: String,
+}
+
+impl Contract {
+    fn new(name: String, config: Config) -> Result<Self> {
+        let mut configs = config.versions;
+        if let Some(dir) = &config.directory {
+            configs.append(&mut load_directory(dir)?);
+        }
+        if configs.is_empty() {
+            return Err(invalid(format!("contract `{}` has no versions", name)));
+        }
+        configs.sort_by(|a, b| cmp_versions(&b.version, &a.version));
+        let duplicate = configs.windows(2).find_map(|w| match w {
+            [a, b] if a.version == b.version => Some(a.version.clone()),
+            _ => None,
+        });
+        if let Some(version) = duplicate {
+            return Err(invalid(format!("duplicate version `{}`", version)));
+        }
+        let versions = configs
+            .into_iter()
+            .map(|v| {
+                let schema = Schema::compile(&v.schema)
+                    .map_err(|e| invalid(format!("version `{}`: {}", v.version, e)))?;
+                Ok(Version {
+                    name: v.version,
+                    schema,
+                })
+            })
+            .collect::<Result<_>>()?;
+        Ok(Self {
+            name,
+            versions,
+            version_field: config.version_field.as_deref().map(EventPath::parse),
+            meta: config.meta,
+        })
+    }
+
+    /// The matching version, or the declared version and the violations
+    fn check(&self, event: &Event) -> std::result::Result<&str, (Option<String>, Vec<String>)> {
+        let value = event.data.suffix().value();
+        if let Some(field) = &self.version_field {
+            let declared = field.get(event).and_then(|v| {
+                v.as_str()
+                    .map(String::from)
+                    .or_else(|| v.as_u64().map(|v| v.to_string()))
+            });
+            let declared = match declared {
+                Some(declared) => declared,
+                None => return Err((None, vec!["no version is declared".to_string()])),
+            };
+            match self.versions.iter().find(|v| v.name == declared) {
+                Some(version) => {
+                    let mut errors = Vec::new();
+                    version.schema.validate(value, "", &mut errors);
+                    if errors.is_empty() {
+                        Ok(&version.name)
+                    } else {
+                        Err((Some(declared), errors))
+                    }
+                }
+                None => {
+                    let error = format!("unknown version `{}`", declared);
+                    Err((Some(declared), vec![error]))
+                }
+            }
+        } else {
+            let mut newest_errors = None;
+            for version in &self.versions {
+                let mut errors = Vec::new();
+                version.schema.validate(value, "", &mut errors);
+                if errors.is_empty() {
+                    return Ok(&version.name);
+                }
+                newest_errors.get_or_insert(errors);
+            }
+            Err((None, newest_errors.unwrap_or_default()))
+        }
+    }
+}
+
+op!(ContractFactory(_uid, node) {
+if let Some(map) = &node.config {
+    let config: Config = Config::new(map)?;
+    let name = config.name.clone().unwrap_or_else(|| node.id.to_string());
+    Ok(Box::new(Contract::new(name, config)?))
+} else {
+    Err(ErrorKind::MissingOpConfig(node.id.clone()).into())
+}});
+
+impl Operator for Contract {
+    fn on_event(
+        &mut self,
+        _uid: u64,
+        _port: &str,
+        _state: &mut Value<'static>,
+        mut event: Event,
+    ) -> Result<EventAndInsights> {
+        let (port, result) = match self.check(&event) {
+            Ok(version) => (
+                OUT,
+                literal!({
+                    "name": self.name.clone(),
+                    "version": version.to_string()
+                }),
+            ),
+            Err((version, errors)) => (
+                VIOLATION,
+                literal!({
+                    "name": self.name.clone(),
+                    "version": version,
+                    "errors": errors
+                }),
+            ),
+        };
+        event.data.rent_mut(|data| {
+            let (_, meta) = data.parts_mut();
+            meta.try_insert(self.meta.clone(), result);
+        });
+        Ok(vec![(port, event)].into())
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use tremor_value::literal;
+
+    fn contract(yaml: &str) -> Result<Contract> {
+        let map: serde_yaml::Value = serde_yaml::from_str(yaml)?;
+        Contract::new("orders".to_string(), Config::new(&map)?)
+    }
+
+    fn check(
+        op: &mut Contract,
+        value: Value<'static>,
+        meta: Value<'static>,
+    ) -> Result<(String, Value<'static>)> {
+        let event = Event {
+            data: (value, meta).into(),
+            ..Event::default()
+        };
+        let mut state = Value::null();
+        let (port, event) = op
+            .on_event(0, "in", &mut state, event)?
+            .events
+            .pop()
+            .ok_or("no event")?;
+        let result = event
+            .data
+            .suffix()
+            .meta()
+            .get("contract")
+            .cloned()
+            .ok_or("no result")?;
+        Ok((port.to_string(), result))
+    }
+
+    const VERSIONS: &str = r#"
+versions:
+  - version: "1"
+    schema:
+      type: object
+      properties:
+        id: {type: integer, minimum: 1}
+      required: [id]
+  - version: "2"
+    schema:
+      type: object
+      properties:
+        id: {type: string, pattern: "^o-"}
+        items: {type: array, items: {type: string}, minItems: 1}
+      required: [id, items]
+      additionalProperties: false
+"#;
+
+    #[test]
+    fn newest_match() -> Result<()> {
+        let mut op = contract(VERSIONS)?;
+        let (port, result) = check(&mut op, literal!({"id": 7}), Value::object())?;
+        assert_eq!("out", port);
+        assert_eq!(literal!({"name": "orders", "version": "1"}), result);
+
+        let (port, result) = check(
+            &mut op,
+            literal!({"id": "o-7", "items": ["snot"]}),
+            Value::object(),
+        )?;
+        assert_eq!("out", port);
+        assert_eq!(Some("2"), result.get_str("version"));
+
+        // the errors are the ones of the newest version
+        let (port, result) = check(
+            &mut op,
+            literal!({"id": "7", "items": [1], "extra": true}),
+            Value::object(),
+        )?;
+        assert_eq!("violation", port);
+        assert_eq!(
+            literal!({
+                "name": "orders",
+                "version": null,
+                "errors": [
+                    "/id: does not match `^o-`",
+                    "/items/0: expected string",
+                    "/extra: is not allowed"
+                ]
+            }),
+            result
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn declared_version() -> Result<()> {
+        let mut op = contract(&format!("{}version_field: $schema_version", VERSIONS))?;
+        let (port, _) = check(
+            &mut op,
+            literal!({"id": 7}),
+            literal!({"schema_version": 1}),
+        )?;
+        assert_eq!("out", port);
+
+        // matches version 1, but declares version 2
+        let (port, result) = check(
+            &mut op,
+            literal!({"id": 7}),
+            literal!({"schema_version": "2"}),
+        )?;
+        assert_eq!("violation", port);
+        assert_eq!(Some("2"), result.get_str("version"));
+        assert_eq!(
+            literal!([
+                "/: missing required property `items`",
+                "/id: expected string"
+            ]),
+            result.get("errors").cloned().ok_or("no errors")?
+        );
+
+        let (port, result) = check(
+            &mut op,
+            literal!({"id": 7}),
+            literal!({"schema_version": "3"}),
+        )?;
+        assert_eq!("violation", port);
+        assert_eq!(
+            literal!(["unknown version `3`"]),
+            result.get("errors").cloned().ok_or("no errors")?
+        );
+        let (port, _) = check(&mut op, literal!({"id": 7}), Value::object())?;
+        assert_eq!("violation", port);
+        Ok(())
+    }
+
+    #[test]
+    fn keywords() -> Result<()> {
+        let schema = |yaml: &str| -> Result<Schema> {
+            Schema::compile(&serde_yaml::from_str::<OwnedValue>(yaml)?)
+        };
+        let errors = |schema: &Schema, value: Value<'static>| {
+            let mut errors = Vec::new();
+            schema.validate(&value, "", &mut errors);
+            errors
+        };
+
+        let s = schema("{enum: [a, b]}")?;
+        assert!(errors(&s, literal!("a")).is_empty());
+        assert_eq!(
+            vec!["/: is not one of the allowed values"],
+            errors(&s, literal!("c"))
+        );
+
+        let s = schema("{type: [string, \"null\"], minLength: 2, maxLength: 3}")?;
+        assert!(errors(&s, literal!(null)).is_empty());
+        assert_eq!(
+            vec!["/: is shorter than 2 characters"],
+            errors(&s, literal!("a"))
+        );
+        assert_eq!(vec!["/: expected string or null"], errors(&s, literal!(1)));
+
+        let s = schema("{exclusiveMinimum: 0, maximum: 10}")?;
+        assert!(errors(&s, literal!(10.0)).is_empty());
+        assert_eq!(vec!["/: must be greater than 0"], errors(&s, literal!(0)));
+        assert!(errors(&s, literal!("no number")).is_empty());
+
+        let s = schema("{anyOf: [{type: integer}, {enum: [x, y]}], not: {const: y}}")?;
+        assert!(errors(&s, literal!("x")).is_empty());
+        assert_eq!(
+            vec!["/: does not match any of the `anyOf` schemas"],
+            errors(&s, literal!("z"))
+        );
+        assert_eq!(
+            vec!["/: matches the `not` schema"],
+            errors(&s, literal!("y"))
+        );
+
+        let s = schema("{oneOf: [{type: number}, {type: integer}]}")?;
+        assert!(errors(&s, literal!(1.5)).is_empty());
+        assert_eq!(
+            vec!["/: does not match exactly one of the `oneOf` schemas"],
+            errors(&s, literal!(1))
+        );
+
+        assert!(schema("{$ref: \"#/definitions/order\"}").is_err());
+        assert!(schema("{type: decimal}").is_err());
+        assert!(schema("{pattern: \"(\"}").is_err());
+        Ok(())
+    }
+
+    #[test]
+    fn directory() -> Result<()> {
+        let tmp = tempfile::tempdir()?;
+        let dir = tmp.path();
+        std::fs::write(
+            dir.join("2.json"),
+            r#"{"type": "object", "required": ["b"]}"#,
+        )?;
+        std::fs::write(dir.join("10.yaml"), "{type: object, required: [c]}")?;
+        std::fs::write(dir.join("README.md"), "not a schema")?;
+        let mut op = contract(&format!(
+            "directory: {}\nversions: [{{version: \"1\", schema: {{required: [a]}}}}]",
+            dir.display()
+        ))?;
+        let versions: Vec<_> = op.versions.iter().map(|v| v.name.as_str()).collect();
+        assert_eq!(vec!["10", "2", "1"], versions);
+        let (_, result) = check(&mut op, literal!({"b": 1}), Value::object())?;
+        assert_eq!(Some("2"), result.get_str("version"));
+
+        // versions must be unique
+        std::fs::write(dir.join("1.json"), "{}")?;
+        let duplicate = contract(&format!(
+            "directory: {}\nversions: [{{version: \"1\", schema: {{}}}}]",
+            dir.display()
+        ));
+        assert!(duplicate.is_err());
+        Ok(())
+    }
+}
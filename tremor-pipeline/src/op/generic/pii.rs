@@ -0,0 +1,430 @@
+// Copyright 2020-2021, The Tremor Team
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+//! # PII redaction
+//!
+//! Scans strings for personally identifiable information and masks, hashes
+//! or removes it.
+//!
+//! ## Configuration
+//!
+//! See [Config](struct.Config.html) for details.
+//!
+//! ## Outputs
+//!
+//! All events are sent to `out`. For audits the metadata of events with
+//! detected PII lists the number of detections by kind and the fields they
+//! were found in, e.g.
+//! `{"pii": {"detected": {"email": 1}, "fields": ["user.contact"]}}`.
+
+use crate::errors::Result;
+use crate::op::prelude::*;
+use crate::op::{stable_hash, EventPath};
+use regex::{Captures, Regex};
+use tremor_script::prelude::*;
+
+lazy_static::lazy_static! {
+    static ref EMAIL: Regex = {
+        #[allow(clippy::unwrap_used)]
+        // ALLOW: we tested this
+        Regex::new(r"[A-Za-z0-9._%+-]+@[A-Za-z0-9-]+(?:\.[A-Za-z0-9-]+)*\.[A-Za-z]{2,}").unwrap()
+    };
+    static ref CREDIT_CARD: Regex = {
+        #[allow(clippy::unwrap_used)]
+        // ALLOW: we tested this
+        Regex::new(r"\b\d(?:[ -]?\d){12,18}\b").unwrap()
+    };
+    static ref SSN: Regex = {
+        #[allow(clippy::unwrap_used)]
+        // ALLOW: we tested this
+        Regex::new(r"\b\d{3}-\d{2}-\d{4}\b").unwrap()
+    };
+    static ref PHONE: Regex = {
+        #[allow(clippy::unwrap_used)]
+        // ALLOW: we tested this
+        Regex::new(r"(?:\+\d{1,3}[ .-]?)?(?:\(\d{3}\)[ .-]?|\b\d{3}[ .-])\d{3}[ .-]\d{4}\b").unwrap()
+    };
+}
+
+/// Kinds of PII
+#[derive(Debug, Clone, Copy, PartialEq, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Kind {
+    /// credit card numbers passing the Luhn check
+    CreditCard,
+    /// US social security numbers, e.g. `123-45-6789`
+    Ssn,
+    /// phone numbers, e.g. `(555) 123-4567` or `+1 555 123 4567`
+    Phone,
+    /// email addresses
+    Email,
+}
+
+impl Kind {
+    fn name(self) -> &'static str {
+        match self {
+            Self::CreditCard => "credit_card",
+            Self::Ssn => "ssn",
+            Self::Phone => "phone",
+            Self::Email => "email",
+        }
+    }
+
+    fn regex(self) -> &'static Regex {
+        match self {
+            Self::CreditCard => &CREDIT_CARD,
+            Self::Ssn => &SSN,
+            Self::Phone => &PHONE,
+            Self::Email => &EMAIL,
+        }
+    }
+
+    fn matches(self, candidate: &str) -> bool {
+        self != Self::CreditCard || luhn(candidate)
+    }
+}
+
+/// What detected PII is replaced with
+#[derive(Debug, Clone, Copy, PartialEq, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Action {
+    /// letters and digits are replaced by `*`
+    Mask,
+    /// a salted hash, so redacted values can still be correlated
+    Hash,
+    /// the PII is cut out
+    Remove,
+}
+
+impl Default for Action {
+    fn default() -> Self {
+        Self::Mask
+    }
+}
+
+#[derive(Debug, Clone, Deserialize)]
+pub struct Config {
+    /// Paths of the fields to scan, e.g. `user.contact` (default: the whole event)
+    #[serde(default)]
+    pub fields: Vec<String>,
+    /// Kinds of PII to detect (default: all)
+    #[serde(default = "d_detect")]
+    pub detect: Vec<Kind>,
+    /// What PII is replaced with (default: `mask`)
+    #[serde(default)]
+    pub action: Action,
+    /// Salt of the hashes with the `hash` action
+    #[serde(default)]
+    pub salt: String,
+    /// Metadata key of the detections (default: `pii`)
+    #[serde(default = "d_meta")]
+    pub meta: String,
+}
+
+impl ConfigImpl for Config {}
+
+impl Default for Config {
+    fn default() -> Self {
+        Self {
+            fields: Vec::new(),
+            detect: d_detect(),
+            action: Action::default(),
+            salt: String::new(),
+            meta: d_meta(),
+        }
+    }
+}
+
+fn d_detect() -> Vec<Kind> {
+    // card numbers first, so their digits aren't taken for phone numbers
+    vec![Kind::CreditCard, Kind::Ssn, Kind::Phone, Kind::Email]
+}
+
+fn d_meta() -> String {
+    "pii".to_string()
+}
+
+/// Luhn checksum of the digits in a candidate credit card number
+fn luhn(candidate: &str) -> bool {
+    let digits: Vec<u32> = candidate.chars().filter_map(|c| c.to_digit(10)).collect();
+    let sum: u32 = digits
+        .iter()
+        .rev()
+        .enumerate()
+        .map(|(i, d)| match (i % 2, d * 2) {
+            (0, _) => *d,
+            (_, doubled) if doubled > 9 => doubled - 9,
+            (_, doubled) => doubled,
+        })
+        .sum();
+    (13..=19).contains(&digits.len()) && sum % 10 == 0
+}
+
+/// Detections in an event
+#[derive(Debug, Default)]
+struct Detections {
+    counts: Vec<(Kind, usize)>,
+    fields: Vec<String>,
+}
+
+impl Detections {
+    fn add(&mut self, kind: Kind, n: usize) {
+        if let Some((_, count)) = self.counts.iter_mut().find(|(k, _)| *k == kind) {
+            *count += n;
+        } else {
+            self.counts.push((kind, n));
+        }
+    }
+
+    fn into_value(self) -> Value<'static> {
+        let mut detected = Value::object_with_capacity(self.counts.len());
+        for (kind, count) in self.counts {
+            detected.try_insert(kind.name(), count);
+        }
+        literal!({
+            "detected": detected,
+            "fields": self.fields
+        })
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct Pii {
+    fields: Vec<(String, EventPath)>,
+    detect: Vec<Kind>,
+    action: Action,
+    salt: String,
+    meta: String,
+}
+
+impl From<Config> for Pii {
+    fn from(config: Config) -> Self {
+        let fields = if config.fields.is_empty() {
+            vec![String::new()]
+        } else {
+            config.fields
+        };
+        Self {
+            fields: fields
+                .into_iter()
+                .map(|f| {
+                    let path = EventPath::parse(&f);
+                    (f, path)
+                })
+                .collect(),
+            detect: config.detect,
+            action: config.action,
+            salt: config.salt,
+            meta: config.meta,
+        }
+    }
+}
+
+op!(PiiFactory(_uid, node) {
+    let config = node.config.as_ref().map_or_else(|| Ok(Config::default()), Config::new)?;
+    Ok(Box::new(Pii::from(config)))
+});
+
+impl Pii {
+    fn replacement(&self, pii: &str) -> String {
+        match self.action {
+            Action::Mask => pii
+                .chars()
+                .map(|c| if c.is_alphanumeric() { '*' } else { c })
+                .collect(),
+            Action::Hash => {
+                let salted = format!("{}{}", self.salt, pii);
+                format!("{:016x}", stable_hash(salted.as_bytes()))
+            }
+            Action::Remove => String::new(),
+        }
+    }
+
+    /// The redacted string, if it contains PII
+    fn redact_str(&self, s: &str, detections: &mut Detections) -> Option<String> {
+        let mut redacted: Option<String> = None;
+        for kind in &self.detect {
+            let current = redacted.as_deref().unwrap_or(s);
+            let mut hits = 0;
+            let replaced = kind.regex().replace_all(current, |caps: &Captures| {
+                let candidate = caps.get(0).map_or("", |m| m.as_str());
+                if kind.matches(candidate) {
+                    hits += 1;
+                    self.replacement(candidate)
+                } else {
+                    candidate.to_string()
+                }
+            });
+            if hits > 0 {
+                detections.add(*kind, hits);
+                redacted = Some(replaced.into_owned());
+            }
+        }
+        redacted
+    }
+
+    fn redact(&self, value: &mut Value, path: &str, detections: &mut Detections) {
+        match value {
+            Value::String(s) => {
+                if let Some(redacted) = self.redact_str(s, detections) {
+                    *value = Value::from(redacted);
+                    detections.fields.push(path.to_string());
+                }
+            }
+            Value::Array(a) => {
+                for (i, v) in a.iter_mut().enumerate() {
+                    self.redact(v, &join(path, &i.to_string()), detections);
+                }
+            }
+            Value::Object(o) => {
+                for (k, v) in o.iter_mut() {
+                    self.redact(v, &join(path, k), detections);
+                }
+            }
+            _ => (),
+        }
+    }
+}
+
+fn join(path: &str, key: &str) -> String {
+    if path.is_empty() {
+        key.to_string()
+    } else {
+        format!("{}.{}", path, key)
+    }
+}
+
+impl Operator for Pii {
+    fn on_event(
+        &mut self,
+        _uid: u64,
+        _port: &str,
+        _state: &mut Value<'static>,
+        mut event: Event,
+    ) -> Result<EventAndInsights> {
+        event.data.rent_mut(|data| {
+            let (value, meta) = data.parts_mut();
+            let mut detections = Detections::default();
+            for (name, path) in &self.fields {
+                if let Some(field) = path.get_mut(value, meta) {
+                    self.redact(field, name, &mut detections);
+                }
+            }
+            if !detections.fields.is_empty() {
+                meta.try_insert(self.meta.clone(), detections.into_value());
+            }
+        });
+        Ok(event.into())
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use tremor_value::literal;
+
+    fn redact(op: &mut Pii, value: Value<'static>) -> Result<(Value<'static>, Value<'static>)> {
+        let event = Event {
+            data: value.into(),
+            ..Event::default()
+        };
+        let mut state = Value::null();
+        let mut events = op.on_event(0, "in", &mut state, event)?.events;
+        let (_, event) = events.pop().ok_or("no event")?;
+        let (value, meta) = event.data.suffix().parts();
+        Ok((value.clone_static(), meta.clone_static()))
+    }
+
+    #[test]
+    fn luhn_check() {
+        assert!(luhn("4111 1111 1111 1111"));
+        assert!(luhn("5500-0000-0000-0004"));
+        assert!(!luhn("4111 1111 1111 1112"));
+        assert!(!luhn("1234"));
+    }
+
+    #[test]
+    fn masks_whole_event() -> Result<()> {
+        let mut op = Pii::from(Config::default());
+        let (value, meta) = redact(
+            &mut op,
+            literal!({
+                "msg": "mail jane.doe@example.com or call (555) 123-4567",
+                "payment": ["4111 1111 1111 1111", "order 4111 1111 1111 1112"],
+                "ssn": "123-45-6789",
+                "count": 42
+            }),
+        )?;
+        assert_eq!(
+            literal!({
+                "msg": "mail ****.***@*******.*** or call (***) ***-****",
+                "payment": ["**** **** **** ****", "order 4111 1111 1111 1112"],
+                "ssn": "***-**-****",
+                "count": 42
+            }),
+            value
+        );
+        let pii = meta.get("pii").ok_or("no detections")?;
+        let detected = pii.get("detected").ok_or("no counts")?;
+        assert_eq!(Some(1), detected.get_usize("credit_card"));
+        assert_eq!(Some(1), detected.get_usize("ssn"));
+        assert_eq!(Some(1), detected.get_usize("phone"));
+        assert_eq!(Some(1), detected.get_usize("email"));
+        assert_eq!(
+            Some(3),
+            pii.get("fields").and_then(|f| f.as_array()).map(Vec::len)
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn hashes_and_removes_fields() -> Result<()> {
+        let config = |action: &str| -> Result<Config> {
+            let yaml = format!(
+                "{{fields: [user.contact], detect: [email], action: {}, salt: s}}",
+                action
+            );
+            Config::new(&serde_yaml::from_str(&yaml)?)
+        };
+        let event = literal!({
+            "user": {"contact": "jane@example.com"},
+            "note": "jane@example.com"
+        });
+
+        let mut op = Pii::from(config("hash")?);
+        let (value, meta) = redact(&mut op, event.clone())?;
+        let hash = format!("{:016x}", stable_hash(b"sjane@example.com"));
+        assert_eq!(
+            Some(hash.as_str()),
+            value.get("user").and_then(|u| u.get_str("contact"))
+        );
+        // fields that aren't configured are kept
+        assert_eq!(Some("jane@example.com"), value.get_str("note"));
+        assert_eq!(
+            Some(&literal!({"detected": {"email": 1}, "fields": ["user.contact"]})),
+            meta.get("pii")
+        );
+
+        let mut op = Pii::from(config("remove")?);
+        let (value, _) = redact(&mut op, event)?;
+        assert_eq!(
+            Some(""),
+            value.get("user").and_then(|u| u.get_str("contact"))
+        );
+
+        let (_, meta) = redact(&mut op, literal!({"user": {"contact": "none"}}))?;
+        assert!(meta.get("pii").is_none());
+        Ok(())
+    }
+}
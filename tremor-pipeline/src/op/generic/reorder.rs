@@ -0,0 +1,262 @@
+// Copyright 2020-2021, The Tremor Team
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+//! # Reorder
+//!
+//! Buffers events and re-sequences them by a sequence number or timestamp,
+//! e.g. where multiple branches merge for consumers requiring in order
+//! delivery.
+//!
+//! ## Configuration
+//!
+//! See [Config](struct.Config.html) for details.
+//!
+//! ## Outputs
+//!
+//! Events are sent to `out` ordered by `field` once they are held for
+//! `max_delay`, when more than `max_buffer` events are held, or with
+//! `contiguous` as soon as all their predecessors were sent. Events arriving
+//! after a successor was already sent are sent to `late`, events without an
+//! integer at `field` to `err`.
+
+use crate::errors::{ErrorKind, Result};
+use crate::op::prelude::*;
+use crate::op::EventPath;
+use std::collections::BTreeMap;
+use tremor_script::prelude::*;
+
+const LATE: Cow<'static, str> = Cow::const_str("late");
+
+#[derive(Debug, Clone, Deserialize)]
+pub struct Config {
+    /// Path of the sequence number or timestamp, e.g. `seq` or `$kafka.offset`
+    pub field: String,
+    /// Maximum time in milliseconds an event is held back (default: 1000)
+    #[serde(default = "d_max_delay")]
+    pub max_delay: u64,
+    /// Maximum number of events held back (default: 1000)
+    #[serde(default = "d_max_buffer")]
+    pub max_buffer: usize,
+    /// The field is a sequence without gaps, events are sent as soon as their
+    /// predecessor was sent (default: false)
+    #[serde(default)]
+    pub contiguous: bool,
+}
+
+impl ConfigImpl for Config {}
+
+fn d_max_delay() -> u64 {
+    1000
+}
+
+fn d_max_buffer() -> usize {
+    1000
+}
+
+#[derive(Debug, Clone)]
+pub struct Reorder {
+    field: EventPath,
+    max_delay_ns: u64,
+    max_buffer: usize,
+    contiguous: bool,
+    /// held back events by sequence number and arrival
+    buffer: BTreeMap<(u64, u64), Event>,
+    /// arrival time and sequence number of the held back events by arrival
+    arrivals: BTreeMap<u64, (u64, u64)>,
+    /// number of events that arrived so far
+    arrived: u64,
+    /// the latest ingest time seen, so events replayed from a backlog with
+    /// old ingest times are still held back
+    clock_ns: u64,
+    /// sequence number of the last event sent
+    last: Option<u64>,
+}
+
+impl From<Config> for Reorder {
+    fn from(config: Config) -> Self {
+        Self {
+            field: EventPath::parse(&config.field),
+            max_delay_ns: config.max_delay.saturating_mul(1_000_000),
+            max_buffer: config.max_buffer,
+            contiguous: config.contiguous,
+            buffer: BTreeMap::new(),
+            arrivals: BTreeMap::new(),
+            arrived: 0,
+            clock_ns: 0,
+            last: None,
+        }
+    }
+}
+
+op!(ReorderFactory(_uid, node) {
+if let Some(map) = &node.config {
+    let config: Config = Config::new(map)?;
+    if config.field.is_empty() {
+        return Err(ErrorKind::BadOpConfig("reorder needs a field to order by".into()).into());
+    }
+    Ok(Box::new(Reorder::from(config)))
+} else {
+    Err(ErrorKind::MissingOpConfig(node.id.clone()).into())
+}});
+
+impl Reorder {
+    fn is_ready(&self, seq: u64) -> bool {
+        (self.contiguous
+            && self
+                .last
+                .map_or(false, |last| seq <= last.saturating_add(1)))
+            || self.buffer.len() > self.max_buffer
+            || self
+                .arrivals
+                .values()
+                .next()
+                .map_or(false, |(arrival_ns, _)| {
+                    self.clock_ns.saturating_sub(*arrival_ns) > self.max_delay_ns
+                })
+    }
+
+    /// Sends held back events in order as long as the first one is ready
+    fn release(&mut self) -> Vec<(Cow<'static, str>, Event)> {
+        let mut events = Vec::new();
+        while let Some(&(seq, n)) = self.buffer.keys().next() {
+            if !self.is_ready(seq) {
+                break;
+            }
+            self.arrivals.remove(&n);
+            if let Some(event) = self.buffer.remove(&(seq, n)) {
+                events.push((OUT, event));
+            }
+            self.last = Some(seq);
+        }
+        events
+    }
+}
+
+impl Operator for Reorder {
+    fn on_event(
+        &mut self,
+        _uid: u64,
+        _port: &str,
+        _state: &mut Value<'static>,
+        mut event: Event,
+    ) -> Result<EventAndInsights> {
+        let seq = match self.field.get(&event).and_then(|v| v.as_u64()) {
+            Some(seq) => seq,
+            None => {
+                event.data.rent_mut(|data| {
+                    let (_, meta) = data.parts_mut();
+                    meta.try_insert("error", "no integer to order by");
+                });
+                return Ok(vec![(ERR, event)].into());
+            }
+        };
+        if self.last.map_or(false, |last| seq < last) {
+            return Ok(vec![(LATE, event)].into());
+        }
+        self.clock_ns = self.clock_ns.max(event.ingest_ns);
+        let n = self.arrived;
+        self.arrived += 1;
+        self.arrivals.insert(n, (self.clock_ns, seq));
+        self.buffer.insert((seq, n), event);
+        Ok(self.release().into())
+    }
+
+    fn handles_signal(&self) -> bool {
+        true
+    }
+
+    fn on_signal(
+        &mut self,
+        _uid: u64,
+        _state: &mut Value<'static>,
+        signal: &mut Event,
+    ) -> Result<EventAndInsights> {
+        self.clock_ns = self.clock_ns.max(signal.ingest_ns);
+        Ok(self.release().into())
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use tremor_value::literal;
+
+    fn reorder(contiguous: bool) -> Reorder {
+        Reorder::from(Config {
+            field: "seq".to_string(),
+            max_delay: 1,
+            max_buffer: 3,
+            contiguous,
+        })
+    }
+
+    fn send(op: &mut Reorder, seq: u64, ingest_ns: u64) -> Result<Vec<(String, u64)>> {
+        let event = Event {
+            data: literal!({ "seq": seq }).into(),
+            ingest_ns,
+            ..Event::default()
+        };
+        let mut state = Value::null();
+        Ok(seqs(op.on_event(0, "in", &mut state, event)?))
+    }
+
+    fn seqs(events: EventAndInsights) -> Vec<(String, u64)> {
+        events
+            .events
+            .into_iter()
+            .filter_map(|(port, e)| {
+                let seq = e.data.suffix().value().get_u64("seq")?;
+                Some((port.to_string(), seq))
+            })
+            .collect()
+    }
+
+    fn out(seqs: &[u64]) -> Vec<(String, u64)> {
+        seqs.iter().map(|s| ("out".to_string(), *s)).collect()
+    }
+
+    #[test]
+    fn bounded_delay() -> Result<()> {
+        let mut op = reorder(false);
+        assert!(send(&mut op, 3, 0)?.is_empty());
+        assert!(send(&mut op, 1, 0)?.is_empty());
+        assert!(send(&mut op, 2, 0)?.is_empty());
+        // the buffer is full
+        assert_eq!(out(&[1]), send(&mut op, 5, 0)?);
+
+        // held back for longer than max_delay
+        let mut state = Value::null();
+        let mut signal = Event {
+            ingest_ns: 2_000_000,
+            ..Event::default()
+        };
+        assert_eq!(
+            out(&[2, 3, 5]),
+            seqs(op.on_signal(0, &mut state, &mut signal)?)
+        );
+        assert_eq!(vec![("late".to_string(), 4)], send(&mut op, 4, 0)?);
+        Ok(())
+    }
+
+    #[test]
+    fn contiguous() -> Result<()> {
+        let mut op = reorder(true);
+        // the first event waits as there is no predecessor
+        assert!(send(&mut op, 1, 0)?.is_empty());
+        assert_eq!(out(&[1]), send(&mut op, 3, 2_000_000)?);
+        assert!(send(&mut op, 4, 2_000_000)?.is_empty());
+        assert_eq!(out(&[2, 3, 4]), send(&mut op, 2, 2_000_000)?);
+        Ok(())
+    }
+}
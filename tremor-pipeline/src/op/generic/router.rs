@@ -0,0 +1,366 @@
+// Copyright 2020-2021, The Tremor Team
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+//! # Content based router
+//!
+//! ## Configuration
+//!
+//! See [Config](struct.Config.html) for details.
+//!
+//! ## Outputs
+//!
+//! Sends events to the output of the first route whose condition matches, or
+//! of all matching routes with `all`. Events no route matches are sent to the
+//! `fallback` output.
+//!
+//! ## Control
+//!
+//! Events on the input port `control` replace the routing table without
+//! redeploying the pipeline, they have the same format as the config, e.g.
+//! `{"routes": [{"output": "alerts", "field": "level", "equals": "error"}]}`.
+//! Invalid tables are sent to the `err` output and the current table is kept.
+
+use crate::errors::{ErrorKind, Result};
+use crate::op::prelude::*;
+use crate::op::EventPath;
+use regex::Regex;
+use tremor_script::prelude::*;
+
+const CONTROL: &str = "control";
+
+#[derive(Debug, Clone, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Condition {
+    /// the value equals the given one
+    Equals(OwnedValue),
+    /// the value equals one of the given ones
+    OneOf(Vec<OwnedValue>),
+    /// the value is a string starting with the given one
+    Prefix(String),
+    /// the value is a string matching the regular expression
+    Matches(String),
+    /// the value is present (`true`) or absent (`false`)
+    Exists(bool),
+    /// the value is a number greater than the given one
+    Gt(f64),
+    /// the value is a number less than the given one
+    Lt(f64),
+}
+
+#[derive(Debug, Clone, Deserialize)]
+pub struct RouteConfig {
+    /// Output of the matching events
+    pub output: String,
+    /// Path of the value the condition applies to, e.g. `user.region`, or
+    /// `$kafka.topic` for metadata (default: the whole event)
+    #[serde(default)]
+    pub field: String,
+    #[serde(flatten)]
+    pub condition: Condition,
+}
+
+#[derive(Debug, Clone, Deserialize)]
+pub struct Config {
+    /// The routing table, checked in order
+    #[serde(default)]
+    pub routes: Vec<RouteConfig>,
+    /// Output of the events no route matches (default: `out`)
+    #[serde(default = "d_fallback")]
+    pub fallback: String,
+    /// Send events to all matching outputs instead of the first (default: false)
+    #[serde(default)]
+    pub all: bool,
+}
+
+impl ConfigImpl for Config {}
+
+fn d_fallback() -> String {
+    OUT.to_string()
+}
+
+#[derive(Debug, Clone)]
+enum Predicate {
+    Equals(Value<'static>),
+    OneOf(Vec<Value<'static>>),
+    Prefix(String),
+    Matches(Regex),
+    Exists(bool),
+    Gt(f64),
+    Lt(f64),
+}
+
+impl Predicate {
+    fn matches(&self, value: Option<&Value>) -> bool {
+        match (self, value) {
+            (Self::Exists(exists), v) => *exists == v.is_some(),
+            (Self::Equals(expected), Some(v)) => v == expected,
+            (Self::OneOf(expected), Some(v)) => expected.iter().any(|e| v == e),
+            (Self::Prefix(prefix), Some(v)) => v.as_str().map_or(false, |s| s.starts_with(prefix)),
+            (Self::Matches(re), Some(v)) => v.as_str().map_or(false, |s| re.is_match(s)),
+            (Self::Gt(n), Some(v)) => v.cast_f64().map_or(false, |v| v > *n),
+            (Self::Lt(n), Some(v)) => v.cast_f64().map_or(false, |v| v < *n),
+            (_, None) => false,
+        }
+    }
+}
+
+impl TryFrom<Condition> for Predicate {
+    type Error = Error;
+    fn try_from(condition: Condition) -> Result<Self> {
+        Ok(match condition {
+            Condition::Equals(v) => Self::Equals(Value::from(v)),
+            Condition::OneOf(vs) => Self::OneOf(vs.into_iter().map(Value::from).collect()),
+            Condition::Prefix(prefix) => Self::Prefix(prefix),
+            Condition::Matches(re) => {
+                Self::Matches(Regex::new(&re).map_err(|e| ErrorKind::BadOpConfig(e.to_string()))?)
+            }
+            Condition::Exists(exists) => Self::Exists(exists),
+            Condition::Gt(n) => Self::Gt(n),
+            Condition::Lt(n) => Self::Lt(n),
+        })
+    }
+}
+
+#[derive(Debug, Clone)]
+struct Route {
+    output: Cow<'static, str>,
+    /// `None` for the whole event
+    field: Option<EventPath>,
+    predicate: Predicate,
+}
+
+impl Route {
+    fn matches(&self, event: &Event) -> bool {
+        let value = match &self.field {
+            Some(field) => field.get(event),
+            None => Some(event.data.suffix().value()),
+        };
+        self.predicate.matches(value)
+    }
+}
+
+/// A compiled routing table
+#[derive(Debug, Clone)]
+struct Table {
+    routes: Vec<Route>,
+    fallback: Cow<'static, str>,
+    all: bool,
+}
+
+impl TryFrom<Config> for Table {
+    type Error = Error;
+    fn try_from(config: Config) -> Result<Self> {
+        let routes = config
+            .routes
+            .into_iter()
+            .map(|r| {
+                Ok(Route {
+                    output: r.output.into(),
+                    field: if r.field.is_empty() {
+                        None
+                    } else {
+                        Some(EventPath::parse(&r.field))
+                    },
+                    predicate: Predicate::try_from(r.condition)?,
+                })
+            })
+            .collect::<Result<_>>()?;
+        Ok(Self {
+            routes,
+            fallback: config.fallback.into(),
+            all: config.all,
+        })
+    }
+}
+
+impl Table {
+    fn outputs(&self, event: &Event) -> Vec<Cow<'static, str>> {
+        let mut outputs: Vec<Cow<'static, str>> = Vec::new();
+        for route in &self.routes {
+            if route.matches(event) && !outputs.contains(&route.output) {
+                outputs.push(route.output.clone());
+                if !self.all {
+                    break;
+                }
+            }
+        }
+        if outputs.is_empty() {
+            outputs.push(self.fallback.clone());
+        }
+        outputs
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct Router {
+    table: Table,
+}
+
+op!(RouterFactory(_uid, node) {
+if let Some(map) = &node.config {
+    let config: Config = Config::new(map)?;
+    Ok(Box::new(Router { table: Table::try_from(config)? }))
+} else {
+    Err(ErrorKind::MissingOpConfig(node.id.clone()).into())
+}});
+
+impl Router {
+    /// Replaces the routing table with the one in a control event
+    fn reload(&mut self, event: &Event) -> Result<()> {
+        let mut encoded = event.data.suffix().value().encode();
+        let map: serde_yaml::Value = simd_json::serde::from_str(&mut encoded)?;
+        self.table = Table::try_from(Config::new(&map)?)?;
+        info!(
+            "Router table reloaded with {} routes",
+            self.table.routes.len()
+        );
+        Ok(())
+    }
+}
+
+impl Operator for Router {
+    fn on_event(
+        &mut self,
+        _uid: u64,
+        port: &str,
+        _state: &mut Value<'static>,
+        mut event: Event,
+    ) -> Result<EventAndInsights> {
+        if port.eq_ignore_ascii_case(CONTROL) {
+            return Ok(if let Err(e) = self.reload(&event) {
+                error!("Invalid router table: {}", e);
+                event.data.rent_mut(|data| {
+                    let (_, meta) = data.parts_mut();
+                    meta.try_insert("error", e.to_string());
+                });
+                vec![(ERR, event)].into()
+            } else {
+                EventAndInsights::default()
+            });
+        }
+        let mut outputs = self.table.outputs(&event);
+        let last = outputs.pop();
+        let mut events: Vec<_> = outputs
+            .into_iter()
+            .map(|output| (output, event.clone()))
+            .collect();
+        if let Some(last) = last {
+            events.push((last, event));
+        }
+        Ok(events.into())
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use tremor_value::literal;
+
+    fn router(yaml: &str) -> Result<Router> {
+        let map: serde_yaml::Value = serde_yaml::from_str(yaml)?;
+        Ok(Router {
+            table: Table::try_from(Config::new(&map)?)?,
+        })
+    }
+
+    fn route(op: &mut Router, port: &str, value: Value<'static>) -> Result<Vec<String>> {
+        let event = Event {
+            data: (value, literal!({"kafka": {"topic": "audit"}})).into(),
+            ..Event::default()
+        };
+        let mut state = Value::null();
+        Ok(op
+            .on_event(0, port, &mut state, event)?
+            .events
+            .into_iter()
+            .map(|(port, _)| port.to_string())
+            .collect())
+    }
+
+    #[test]
+    fn routes() -> Result<()> {
+        let mut op = router(
+            r#"
+routes:
+  - output: alerts
+    field: level
+    equals: error
+  - output: eu
+    field: region
+    prefix: "eu-"
+  - output: big
+    field: size
+    gt: 1000
+  - output: audit
+    field: $kafka.topic
+    one_of: [audit, security]
+fallback: rest
+"#,
+        )?;
+        assert_eq!(
+            vec!["alerts"],
+            route(
+                &mut op,
+                "in",
+                literal!({"level": "error", "region": "eu-west"})
+            )?
+        );
+        assert_eq!(
+            vec!["eu"],
+            route(
+                &mut op,
+                "in",
+                literal!({"level": "info", "region": "eu-west"})
+            )?
+        );
+        assert_eq!(vec!["big"], route(&mut op, "in", literal!({"size": 1001}))?);
+        assert_eq!(vec!["audit"], route(&mut op, "in", literal!({"size": 1}))?);
+
+        op.table.all = true;
+        assert_eq!(
+            vec!["alerts", "eu", "audit"],
+            route(
+                &mut op,
+                "in",
+                literal!({"level": "error", "region": "eu-west"})
+            )?
+        );
+        op.table.routes.pop();
+        assert_eq!(vec!["rest"], route(&mut op, "in", literal!("snot"))?);
+        Ok(())
+    }
+
+    #[test]
+    fn reload() -> Result<()> {
+        let mut op = router("fallback: out")?;
+        assert_eq!(vec!["out"], route(&mut op, "in", literal!({"id": 7}))?);
+
+        let table = literal!({
+            "routes": [{"output": "sevens", "field": "id", "matches": "^7+$"}]
+        });
+        assert!(route(&mut op, "control", table)?.is_empty());
+        assert_eq!(
+            vec!["sevens"],
+            route(&mut op, "in", literal!({"id": "777"}))?
+        );
+
+        // invalid tables are rejected and the current one is kept
+        let invalid = literal!({
+            "routes": [{"output": "broken", "field": "id", "matches": "("}]
+        });
+        assert_eq!(vec!["err"], route(&mut op, "control", invalid)?);
+        assert_eq!(vec!["sevens"], route(&mut op, "in", literal!({"id": "7"}))?);
+        Ok(())
+    }
+}
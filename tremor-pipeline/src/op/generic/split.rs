@@ -0,0 +1,204 @@
+// Copyright 2020-2021, The Tremor Team
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+//! # Array splitter
+//!
+//! ## Configuration
+//!
+//! See [Config](struct.Config.html) for details.
+//!
+//! ## Outputs
+//!
+//! For each element of the array at `field` an event is sent to `out`, it is a
+//! copy of the original event with the array replaced by the element. The
+//! metadata is extended with the position of the element, e.g.
+//! `{"split": {"index": 0, "total": 3}}`. Events without an array at `field`
+//! are sent to `err`, events with an empty array are dropped.
+//!
+//! The [collect](../collect/index.html) operator reverses the split.
+
+use crate::errors::Result;
+use crate::op::prelude::*;
+use crate::op::EventPath;
+use tremor_script::prelude::*;
+
+#[derive(Debug, Clone, Deserialize)]
+pub struct Config {
+    /// Path of the array to split, e.g. `batch.items` (default: the whole event)
+    #[serde(default)]
+    pub field: String,
+    /// Metadata key for the index and total of the elements (default: `split`)
+    #[serde(default = "d_meta")]
+    pub meta: String,
+}
+
+impl ConfigImpl for Config {}
+
+impl Default for Config {
+    fn default() -> Self {
+        Self {
+            field: String::new(),
+            meta: d_meta(),
+        }
+    }
+}
+
+pub(crate) fn d_meta() -> String {
+    "split".to_string()
+}
+
+#[derive(Debug, Clone)]
+pub struct Split {
+    field: EventPath,
+    meta: String,
+}
+
+impl From<Config> for Split {
+    fn from(config: Config) -> Self {
+        Self {
+            field: EventPath::parse(&config.field),
+            meta: config.meta,
+        }
+    }
+}
+
+op!(SplitFactory(_uid, node) {
+    let config = node.config.as_ref().map_or_else(|| Ok(Config::default()), Config::new)?;
+    Ok(Box::new(Split::from(config)))
+});
+
+impl Operator for Split {
+    fn on_event(
+        &mut self,
+        _uid: u64,
+        _port: &str,
+        _state: &mut Value<'static>,
+        mut event: Event,
+    ) -> Result<EventAndInsights> {
+        let (mut value, mut meta) = {
+            let (value, meta) = event.data.suffix().parts();
+            (value.clone_static(), meta.clone_static())
+        };
+        let elements = match self
+            .field
+            .get_mut(&mut value, &mut meta)
+            .map(|slot| std::mem::replace(slot, Value::null()))
+        {
+            Some(Value::Array(elements)) => elements,
+            _ => {
+                event.data.rent_mut(|data| {
+                    let (_, meta) = data.parts_mut();
+                    meta.try_insert("error", "no array to split");
+                });
+                return Ok(vec![(ERR, event)].into());
+            }
+        };
+        let total = elements.len();
+        let events = elements
+            .into_iter()
+            .enumerate()
+            .map(|(index, element)| {
+                let mut value = value.clone();
+                let mut meta = meta.clone();
+                if let Some(slot) = self.field.get_mut(&mut value, &mut meta) {
+                    *slot = element;
+                }
+                meta.try_insert(
+                    self.meta.clone(),
+                    literal!({"index": index, "total": total}),
+                );
+                let split = Event {
+                    id: event.id.clone(),
+                    data: (value, meta).into(),
+                    ingest_ns: event.ingest_ns,
+                    origin_uri: event.origin_uri.clone(),
+                    transactional: event.transactional,
+                    ..Event::default()
+                };
+                (OUT, split)
+            })
+            .collect::<Vec<_>>();
+        Ok(events.into())
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use tremor_value::literal;
+
+    fn split(op: &mut Split, value: Value<'static>) -> Result<Vec<(String, Event)>> {
+        let event = Event {
+            data: (value, literal!({"kafka": {"topic": "snot"}})).into(),
+            ..Event::default()
+        };
+        let mut state = Value::null();
+        Ok(op
+            .on_event(0, "in", &mut state, event)?
+            .events
+            .into_iter()
+            .map(|(port, event)| (port.to_string(), event))
+            .collect())
+    }
+
+    #[test]
+    fn whole_event() -> Result<()> {
+        let mut op = Split::from(Config::default());
+        let events = split(&mut op, literal!([1, "two", {"three": 3}]))?;
+        assert_eq!(3, events.len());
+        for (i, (port, event)) in events.iter().enumerate() {
+            let (value, meta) = event.data.suffix().parts();
+            assert_eq!("out", port);
+            let position = meta.get("split");
+            assert_eq!(Some(i), position.and_then(|p| p.get_usize("index")));
+            assert_eq!(Some(3), position.and_then(|p| p.get_usize("total")));
+            assert_eq!(
+                Some("snot"),
+                meta.get("kafka").and_then(|k| k.get_str("topic"))
+            );
+            if i == 1 {
+                assert_eq!(&literal!("two"), value);
+            }
+        }
+        Ok(())
+    }
+
+    #[test]
+    fn field() -> Result<()> {
+        let mut op = Split::from(Config {
+            field: "batch.items".to_string(),
+            meta: "item".to_string(),
+        });
+        let events = split(
+            &mut op,
+            literal!({"host": "h1", "batch": {"items": ["a", "b"]}}),
+        )?;
+        assert_eq!(2, events.len());
+        let (_, second) = events.get(1).ok_or("missing element")?;
+        let (value, meta) = second.data.suffix().parts();
+        assert_eq!(&literal!({"host": "h1", "batch": {"items": "b"}}), value);
+        assert_eq!(Some(1), meta.get("item").and_then(|p| p.get_usize("index")));
+
+        assert!(split(&mut op, literal!({"batch": {"items": []}}))?.is_empty());
+        let errors = split(&mut op, literal!({"batch": {"items": 7}}))?;
+        assert_eq!(
+            vec!["err"],
+            errors
+                .iter()
+                .map(|(port, _)| port.as_str())
+                .collect::<Vec<_>>()
+        );
+        Ok(())
+    }
+}
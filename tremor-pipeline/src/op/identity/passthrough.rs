@@ -31,6 +31,24 @@ impl Operator for Passthrough {
     ) -> Result<EventAndInsights> {
         Ok(event.into())
     }
+
+    fn handles_batch(&self) -> bool {
+        true
+    }
+
+    fn on_batch(
+        &mut self,
+        _uid: u64,
+        _port: &str,
+        _state: &mut Value<'static>,
+        events: Vec<Event>,
+    ) -> Result<EventAndInsights> {
+        Ok(events
+            .into_iter()
+            .map(|event| (OUT, event))
+            .collect::<Vec<_>>()
+            .into())
+    }
     // this is just returning true
     #[cfg(not(tarpaulin_include))]
     fn skippable(&self) -> bool {
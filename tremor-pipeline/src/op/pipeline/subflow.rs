@@ -0,0 +1,224 @@
+// Copyright 2020-2021, The Tremor Team
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+//! # Subflow
+//!
+//! Embeds a published pipeline as an operator, so shared processing like
+//! normalization or scrubbing is maintained in one pipeline and reused by
+//! others. In trickle `use pipeline normalize;` defines and creates the
+//! operator `normalize` embedding the pipeline `normalize`.
+//!
+//! The embedded pipeline is resolved when the embedding pipeline is deployed,
+//! republishing it only affects pipelines deployed afterwards.
+//!
+//! ## Configuration
+//!
+//! See [Config](struct.Config.html) for details.
+//!
+//! ## Inputs and outputs
+//!
+//! Events on an input port enter the embedded pipeline on the input stream of
+//! the same name, e.g. `in/alerts` for the port `alerts`. Events it sends to an
+//! output stream leave on the output port of the same name.
+
+use crate::errors::{ErrorKind, Result};
+use crate::op::prelude::*;
+use crate::{ExecutableGraph, SUBFLOW_REGISTRY};
+use std::cell::RefCell;
+use tremor_common::ids::OperatorIdGen;
+use tremor_script::prelude::*;
+
+#[derive(Debug, Clone, Deserialize)]
+pub struct Config {
+    /// Id of the published pipeline to embed
+    pub pipeline: String,
+}
+
+impl ConfigImpl for Config {}
+
+thread_local! {
+    /// pipelines being resolved, to reject pipelines embedding themselves
+    static RESOLVING: RefCell<Vec<String>> = RefCell::new(Vec::new());
+}
+
+/// Turns the published pipeline into the graph of the operator `uid`
+fn resolve(uid: u64, pipeline: &str) -> Result<ExecutableGraph> {
+    if RESOLVING.with(|r| r.borrow().iter().any(|p| p == pipeline)) {
+        return Err(ErrorKind::BadOpConfig(format!("Pipeline {} embeds itself", pipeline)).into());
+    }
+    let query = SUBFLOW_REGISTRY
+        .lock()?
+        .get(pipeline)
+        .cloned()
+        .ok_or_else(|| ErrorKind::BadOpConfig(format!("Pipeline {} is not published", pipeline)))?;
+    RESOLVING.with(|r| r.borrow_mut().push(pipeline.to_string()));
+    let graph = query.to_pipe(&mut OperatorIdGen::nested(uid));
+    RESOLVING.with(|r| r.borrow_mut().pop());
+    graph
+}
+
+#[derive(Debug)]
+pub struct Subflow {
+    pipeline: String,
+    graph: ExecutableGraph,
+}
+
+op!(SubflowFactory(uid, node) {
+if let Some(map) = &node.config {
+    let config: Config = Config::new(map)?;
+    let graph = resolve(uid, &config.pipeline)?;
+    Ok(Box::new(Subflow { pipeline: config.pipeline, graph }))
+} else {
+    Err(ErrorKind::MissingOpConfig(node.id.clone()).into())
+}});
+
+impl Subflow {
+    /// Passes the insights of the embedded pipeline through its contraflow,
+    /// they continue in the contraflow of the embedding pipeline
+    fn insights(&mut self) -> Vec<Event> {
+        let insights = std::mem::take(&mut self.graph.insights);
+        insights
+            .into_iter()
+            .map(|(skip_to, insight)| self.graph.contraflow(Some(skip_to), insight))
+            .collect()
+    }
+}
+
+impl Operator for Subflow {
+    fn on_event(
+        &mut self,
+        _uid: u64,
+        port: &str,
+        _state: &mut Value<'static>,
+        event: Event,
+    ) -> Result<EventAndInsights> {
+        let mut events = Vec::new();
+        if self.graph.inputs.contains_key(port) {
+            self.graph.enqueue(port, event, &mut events)?;
+        } else {
+            self.graph
+                .enqueue(&format!("in/{}", port), event, &mut events)?;
+        }
+        Ok(EventAndInsights {
+            events,
+            insights: self.insights(),
+        })
+    }
+
+    fn handles_signal(&self) -> bool {
+        true
+    }
+
+    fn on_signal(
+        &mut self,
+        _uid: u64,
+        _state: &mut Value<'static>,
+        signal: &mut Event,
+    ) -> Result<EventAndInsights> {
+        let mut events = Vec::new();
+        self.graph.enqueue_signal(signal.clone(), &mut events)?;
+        Ok(EventAndInsights {
+            events,
+            insights: self.insights(),
+        })
+    }
+
+    fn handles_contraflow(&self) -> bool {
+        true
+    }
+
+    fn on_contraflow(&mut self, _uid: u64, insight: &mut Event) {
+        *insight = self.graph.contraflow(None, std::mem::take(insight));
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::query::Query;
+    use crate::FN_REGISTRY;
+    use tremor_script::path::ModulePath;
+    use tremor_value::literal;
+
+    fn publish(id: &str, src: &str) -> Result<()> {
+        let aggr_reg = tremor_script::aggr_registry();
+        let query = Query::parse(
+            &ModulePath { mounts: vec![] },
+            src,
+            "<test>",
+            vec![],
+            &*FN_REGISTRY.lock()?,
+            &aggr_reg,
+        )
+        .map_err(|e| format!("{:?}", e))?;
+        SUBFLOW_REGISTRY.lock()?.insert(id.to_string(), query);
+        Ok(())
+    }
+
+    fn subflow(pipeline: &str) -> Result<Subflow> {
+        Ok(Subflow {
+            pipeline: pipeline.to_string(),
+            graph: resolve(1, pipeline)?,
+        })
+    }
+
+    #[test]
+    fn embeds() -> Result<()> {
+        publish(
+            "subflow_test_normalize",
+            r#"
+select {"host": string::lowercase(event.host)} from in into out;
+select event from in/alerts into out/alerts;
+"#,
+        )?;
+        let mut op = subflow("subflow_test_normalize")?;
+        assert_eq!("subflow_test_normalize", op.pipeline);
+        let mut state = Value::null();
+        let event = Event {
+            data: literal!({"host": "SNOT"}).into(),
+            ..Event::default()
+        };
+        let out = op.on_event(0, "in", &mut state, event.clone())?.events;
+        assert_eq!(1, out.len());
+        for (port, event) in out {
+            assert_eq!("out", port);
+            assert_eq!(&literal!({"host": "snot"}), event.data.suffix().value());
+        }
+        let alerts = op.on_event(0, "alerts", &mut state, event)?.events;
+        assert_eq!(
+            vec!["alerts"],
+            alerts
+                .iter()
+                .map(|(port, _)| port.to_string())
+                .collect::<Vec<_>>()
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn rejects_unknown_and_cyclic() -> Result<()> {
+        assert!(subflow("subflow_test_unknown").is_err());
+        publish(
+            "subflow_test_cyclic",
+            r#"
+define pipeline::subflow operator cyclic with pipeline = "subflow_test_cyclic" end;
+create operator cyclic;
+select event from in into cyclic;
+select event from cyclic into out;
+"#,
+        )?;
+        assert!(subflow("subflow_test_cyclic").is_err());
+        Ok(())
+    }
+}
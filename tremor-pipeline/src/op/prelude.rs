@@ -24,4 +24,5 @@ pub use value_trait::Value as ValueTrait;
 pub const OUT: Cow<'static, str> = Cow::const_str("out");
 pub const IN: Cow<'static, str> = Cow::const_str("in");
 pub const ERR: Cow<'static, str> = Cow::const_str("err");
+pub const EXPIRED: Cow<'static, str> = Cow::const_str("expired");
 pub const METRICS: Cow<'static, str> = Cow::const_str("metrics");
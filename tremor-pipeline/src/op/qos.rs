@@ -13,11 +13,13 @@
 // limitations under the License.
 
 pub mod backpressure;
+pub mod lb;
 pub mod percentile;
 pub mod rr;
 pub mod wal;
 
 pub use backpressure::BackpressureFactory;
+pub use lb::LoadBalancerFactory;
 pub use percentile::PercentileFactory;
 pub use rr::RoundRobinFactory;
 pub use wal::WalFactory;
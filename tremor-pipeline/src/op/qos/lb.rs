@@ -0,0 +1,484 @@
+// Copyright 2020-2021, The Tremor Team
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+//! # Load Balancer
+//!
+//! ## Configuration
+//!
+//! See [Config](struct.Config.html) for details.
+//!
+//! ## Outputs
+//!
+//! Distributes incoming events over the healthy outputs:
+//!
+//! * `round_robin` sends events to each output in turn
+//! * `weighted` does the same in proportion to the `weight` of the outputs
+//! * `hash` sends all events with the same value at `key` to the same output,
+//!   using consistent hashing, so only the keys of an output that turns
+//!   unhealthy move to other outputs
+//!
+//! Outputs are unhealthy once a downstream circuit breaker closes, until it is
+//! restored, or after `max_failures` consecutive failed events until
+//! `recovery_ms` passed. If no output is healthy, the event is sent via the
+//! output port `overflow`.
+
+use crate::errors::{ErrorKind, Result};
+use crate::op::prelude::*;
+use crate::op::{stable_hash, EventPath};
+use tremor_common::time::nanotime;
+use tremor_script::prelude::*;
+
+/// Points on the hash ring per unit of weight
+const VNODES: u64 = 64;
+
+#[derive(Debug, Clone, Copy, PartialEq, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Strategy {
+    RoundRobin,
+    Weighted,
+    Hash,
+}
+
+impl Default for Strategy {
+    fn default() -> Self {
+        Self::RoundRobin
+    }
+}
+
+#[derive(Debug, Clone, Deserialize)]
+#[serde(untagged)]
+pub enum OutputConfig {
+    /// An output of weight 1
+    Name(String),
+    Weighted {
+        output: String,
+        #[serde(default = "d_weight")]
+        weight: u32,
+    },
+}
+
+impl OutputConfig {
+    fn name(&self) -> &str {
+        match self {
+            Self::Name(output) | Self::Weighted { output, .. } => output,
+        }
+    }
+
+    fn weight(&self) -> u32 {
+        match self {
+            Self::Name(_) => 1,
+            Self::Weighted { weight, .. } => *weight,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Deserialize)]
+pub struct Config {
+    /// Outputs to distribute the events over, either a name or an
+    /// `output` with a `weight`
+    pub outputs: Vec<OutputConfig>,
+    /// One of `round_robin`, `weighted` or `hash` (default: `round_robin`)
+    #[serde(default)]
+    pub strategy: Strategy,
+    /// Path of the value the `hash` strategy distributes by, e.g. `user.id`,
+    /// or `$kafka.key` for metadata. Events without it all go to the same output.
+    pub key: Option<String>,
+    /// Consecutive failed events after which an output is unhealthy, not
+    /// enforced if absent
+    pub max_failures: Option<u64>,
+    /// Milliseconds after which an output that failed `max_failures` times is
+    /// used again (default: 10000)
+    #[serde(default = "d_recovery_ms")]
+    pub recovery_ms: u64,
+}
+
+impl ConfigImpl for Config {}
+
+fn d_weight() -> u32 {
+    1
+}
+
+fn d_recovery_ms() -> u64 {
+    10_000
+}
+
+#[derive(Debug, Clone)]
+struct Output {
+    output: String,
+    weight: i64,
+    /// smooth weighted round robin state
+    current: i64,
+    /// closed by a downstream circuit breaker
+    closed: bool,
+    /// consecutive failed events
+    failures: u64,
+    /// unhealthy after too many failures until then
+    down_until: u64,
+}
+
+impl Output {
+    fn is_healthy(&self, now: u64) -> bool {
+        !self.closed && self.down_until <= now
+    }
+}
+
+/// Hash of the value at the path, or of nothing if it is missing
+fn hash_key(key: &EventPath, event: &Event) -> u64 {
+    let encoded = key
+        .get(event)
+        .map(|v| v.as_str().map_or_else(|| v.encode(), ToString::to_string))
+        .unwrap_or_default();
+    stable_hash(encoded.as_bytes())
+}
+
+#[derive(Debug, Clone)]
+pub struct LoadBalancer {
+    pub config: Config,
+    outputs: Vec<Output>,
+    /// points on the hash ring and their output, sorted by hash
+    ring: Vec<(u64, usize)>,
+    key: Option<EventPath>,
+    first: bool,
+}
+
+impl From<Config> for LoadBalancer {
+    fn from(config: Config) -> Self {
+        let outputs = config
+            .outputs
+            .iter()
+            .map(|o| Output {
+                output: o.name().to_string(),
+                weight: if config.strategy == Strategy::Weighted {
+                    i64::from(o.weight())
+                } else {
+                    1
+                },
+                current: 0,
+                closed: false,
+                failures: 0,
+                down_until: 0,
+            })
+            .collect();
+        let mut ring = Vec::new();
+        if config.strategy == Strategy::Hash {
+            for (i, o) in config.outputs.iter().enumerate() {
+                for vnode in 0..VNODES * u64::from(o.weight()) {
+                    ring.push((stable_hash(format!("{}#{}", o.name(), vnode).as_bytes()), i));
+                }
+            }
+            ring.sort_unstable();
+        }
+        let key = config.key.as_deref().map(EventPath::parse);
+        Self {
+            config,
+            outputs,
+            ring,
+            key,
+            first: true,
+        }
+    }
+}
+
+op!(LoadBalancerFactory(_uid, node) {
+if let Some(map) = &node.config {
+    let config: Config = Config::new(map)?;
+    if config.outputs.is_empty() {
+        error!("No outputs supplied for load balancer operators");
+        return Err(ErrorKind::MissingOpConfig(node.id.clone()).into());
+    };
+    if config.outputs.iter().any(|o| o.weight() == 0) {
+        return Err(ErrorKind::BadOpConfig("Load balancer outputs need a weight of at least 1".to_string()).into());
+    }
+    if config.strategy == Strategy::Hash && config.key.is_none() {
+        return Err(ErrorKind::BadOpConfig("The hash strategy of the load balancer needs a `key`".to_string()).into());
+    }
+    Ok(Box::new(LoadBalancer::from(config)))
+} else {
+    Err(ErrorKind::MissingOpConfig(node.id.clone()).into())
+
+}});
+
+impl LoadBalancer {
+    /// smooth weighted round robin over the healthy outputs
+    fn next_weighted(&mut self, now: u64) -> Option<usize> {
+        let mut total = 0;
+        let mut best: Option<(usize, i64)> = None;
+        for (i, o) in self.outputs.iter_mut().enumerate() {
+            if o.is_healthy(now) {
+                o.current += o.weight;
+                total += o.weight;
+                if best.map_or(true, |(_, current)| o.current > current) {
+                    best = Some((i, o.current));
+                }
+            }
+        }
+        let (i, _) = best?;
+        if let Some(o) = self.outputs.get_mut(i) {
+            o.current -= total;
+        }
+        Some(i)
+    }
+
+    /// the first healthy output on the ring at or after the hash of the key
+    fn next_hashed(&self, event: &Event, now: u64) -> Option<usize> {
+        let h = self.key.as_ref().map_or(0, |key| hash_key(key, event));
+        let start = self.ring.partition_point(|(point, _)| *point < h);
+        self.ring
+            .iter()
+            .cycle()
+            .skip(start)
+            .take(self.ring.len())
+            .map(|(_, i)| *i)
+            .find(|i| self.outputs.get(*i).map_or(false, |o| o.is_healthy(now)))
+    }
+
+    fn any_open(&self) -> bool {
+        self.outputs.iter().any(|o| !o.closed)
+    }
+}
+
+impl Operator for LoadBalancer {
+    fn on_event(
+        &mut self,
+        uid: u64,
+        _port: &str,
+        _state: &mut Value<'static>,
+        mut event: Event,
+    ) -> Result<EventAndInsights> {
+        let now = event.ingest_ns;
+        let next = match self.config.strategy {
+            Strategy::RoundRobin | Strategy::Weighted => self.next_weighted(now),
+            Strategy::Hash => self.next_hashed(&event, now),
+        };
+        if let Some((output, id)) = next.and_then(|i| self.outputs.get(i).map(|o| (o, i))) {
+            event.op_meta.insert(uid, id);
+            Ok(vec![(output.output.clone().into(), event)].into())
+        } else {
+            Ok(vec![("overflow".into(), event)].into())
+        }
+    }
+
+    fn handles_signal(&self) -> bool {
+        true
+    }
+
+    fn on_signal(
+        &mut self,
+        _uid: u64,
+        _state: &mut Value<'static>,
+        signal: &mut Event,
+    ) -> Result<EventAndInsights> {
+        if self.first && self.any_open() {
+            let mut e = Event::cb_restore(signal.ingest_ns);
+            e.origin_uri = None;
+            self.first = false;
+
+            Ok(EventAndInsights {
+                insights: vec![e],
+                ..EventAndInsights::default()
+            })
+        } else {
+            Ok(EventAndInsights::default())
+        }
+    }
+
+    fn handles_contraflow(&self) -> bool {
+        true
+    }
+
+    fn on_contraflow(&mut self, uid: u64, insight: &mut Event) {
+        let any_were_available = self.any_open();
+        let max_failures = self.config.max_failures;
+        let recovery_ns = self.config.recovery_ms * 1_000_000;
+        if let Some(o) = insight
+            .op_meta
+            .get(uid)
+            .and_then(OwnedValue::as_usize)
+            .and_then(|id| self.outputs.get_mut(id))
+        {
+            match insight.cb {
+                CbAction::Close => o.closed = true,
+                CbAction::Open => o.closed = false,
+                CbAction::Ack => o.failures = 0,
+                CbAction::Fail => {
+                    o.failures += 1;
+                    if max_failures.map_or(false, |max| o.failures >= max) {
+                        warn!(
+                            "Load balancer output {} failed {} times, taking it out of rotation",
+                            o.output, o.failures
+                        );
+                        o.failures = 0;
+                        o.down_until = nanotime() + recovery_ns;
+                    }
+                }
+                CbAction::None => (),
+            }
+        }
+        let any_available = self.any_open();
+
+        if any_available && !any_were_available {
+            insight.cb = CbAction::Open;
+        } else if any_were_available && !any_available {
+            insight.cb = CbAction::Close;
+        } else if insight.cb.is_cb() {
+            insight.cb = CbAction::None;
+        };
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use tremor_value::literal;
+
+    fn config(yaml: &str) -> Result<Config> {
+        let map: serde_yaml::Value = serde_yaml::from_str(yaml)?;
+        Config::new(&map)
+    }
+
+    fn route(op: &mut LoadBalancer, event: Event) -> Result<String> {
+        let mut state = Value::null();
+        let mut r = op.on_event(0, "in", &mut state, event)?.events;
+        assert_eq!(r.len(), 1);
+        let (out, _event) = r.pop().ok_or("no results")?;
+        Ok(out.to_string())
+    }
+
+    fn keyed(user: &str) -> Event {
+        Event {
+            data: (literal!({ "user": user.to_string() }), Value::object()).into(),
+            ..Event::default()
+        }
+    }
+
+    fn insight(cb: CbAction, output: usize) -> Event {
+        let mut op_meta = OpMeta::default();
+        op_meta.insert(0, output);
+        Event {
+            cb,
+            op_meta,
+            ..Event::default()
+        }
+    }
+
+    #[test]
+    fn weighted() -> Result<()> {
+        let mut op: LoadBalancer = config(
+            r#"
+strategy: weighted
+outputs:
+  - output: out
+    weight: 3
+  - out2
+"#,
+        )?
+        .into();
+        let mut outs = Vec::new();
+        for _ in 0..8 {
+            outs.push(route(&mut op, Event::default())?);
+        }
+        assert_eq!(6, outs.iter().filter(|o| *o == "out").count());
+        assert_eq!(2, outs.iter().filter(|o| *o == "out2").count());
+
+        // closed outputs are skipped, once all are closed the cb closes upstream
+        let mut i = insight(CbAction::Close, 0);
+        op.on_contraflow(0, &mut i);
+        assert_eq!(CbAction::None, i.cb);
+        assert_eq!("out2", route(&mut op, Event::default())?);
+        assert_eq!("out2", route(&mut op, Event::default())?);
+        let mut i = insight(CbAction::Close, 1);
+        op.on_contraflow(0, &mut i);
+        assert_eq!(CbAction::Close, i.cb);
+        assert_eq!("overflow", route(&mut op, Event::default())?);
+        let mut i = insight(CbAction::Open, 0);
+        op.on_contraflow(0, &mut i);
+        assert_eq!(CbAction::Open, i.cb);
+        assert_eq!("out", route(&mut op, Event::default())?);
+        Ok(())
+    }
+
+    #[test]
+    fn round_robin_ignores_weights() -> Result<()> {
+        let mut op: LoadBalancer = config(
+            r#"
+outputs:
+  - output: out
+    weight: 5
+  - out2
+  - out3
+"#,
+        )?
+        .into();
+        let mut outs = Vec::new();
+        for _ in 0..6 {
+            outs.push(route(&mut op, Event::default())?);
+        }
+        assert_eq!(vec!["out", "out2", "out3", "out", "out2", "out3"], outs);
+        Ok(())
+    }
+
+    #[test]
+    fn consistent_hash() -> Result<()> {
+        let mut op: LoadBalancer = config(
+            r#"
+strategy: hash
+key: user
+outputs: [a, b, c]
+"#,
+        )?
+        .into();
+        let users: Vec<String> = (0..100).map(|i| format!("user{}", i)).collect();
+        let mut before = Vec::new();
+        for user in &users {
+            let out = route(&mut op, keyed(user))?;
+            // stable for the same key
+            assert_eq!(out, route(&mut op, keyed(user))?);
+            before.push(out);
+        }
+        for out in ["a", "b", "c"] {
+            assert!(before.iter().any(|o| o == out), "{} got no keys", out);
+        }
+
+        // failing output `b` too often only moves its keys
+        op.config.max_failures = Some(2);
+        let b = 1;
+        op.on_contraflow(0, &mut insight(CbAction::Fail, b));
+        op.on_contraflow(0, &mut insight(CbAction::Fail, b));
+        for (user, out) in users.iter().zip(before.iter()) {
+            let now = route(&mut op, keyed(user))?;
+            if out == "b" {
+                assert_ne!("b", now);
+            } else {
+                assert_eq!(out, &now);
+            }
+        }
+        Ok(())
+    }
+
+    #[test]
+    fn keys() {
+        let event = Event {
+            data: (
+                literal!({"user": {"id": 42}}),
+                literal!({"kafka": {"key": "snot"}}),
+            )
+                .into(),
+            ..Event::default()
+        };
+        let key = |path| hash_key(&EventPath::parse(path), &event);
+        assert_eq!(stable_hash(b"42"), key("user.id"));
+        assert_eq!(stable_hash(b"snot"), key("$kafka.key"));
+        assert_eq!(stable_hash(b""), key("missing"));
+    }
+}
@@ -111,6 +111,15 @@ impl Operator for Percentile {
         true
     }
 
+    fn snapshot(&self) -> Option<Value<'static>> {
+        Some(Value::from(self.perc))
+    }
+
+    fn restore(&mut self, snapshot: &Value<'static>) -> Result<()> {
+        self.perc = snapshot.as_f64().ok_or("Expected number for percentile")?;
+        Ok(())
+    }
+
     fn on_contraflow(&mut self, uid: u64, insight: &mut Event) {
         // If the related event never touched this operator we don't take
         // action
@@ -23,7 +23,12 @@ pub struct Script {
 }
 
 impl Script {
-    pub fn with_stmt(id: String, decl: &srs::Stmt, instance: &srs::Stmt) -> Result<Self> {
+    pub fn with_stmt(
+        id: String,
+        decl: &srs::Stmt,
+        instance: &srs::Stmt,
+        bytecode: bool,
+    ) -> Result<Self> {
         // We require Value to be static here to enforce the constraint that
         // arguments name/value pairs live at least as long as the operator nodes that have
         // dependencies on them.
@@ -42,6 +47,9 @@ impl Script {
         let mut script = srs::ScriptDecl::try_new_from_stmt(decl)?;
 
         script.apply_stmt(instance)?;
+        if bytecode {
+            script.compile();
+        }
 
         Ok(Self { id, script })
     }
@@ -0,0 +1,205 @@
+// Copyright 2020-2021, The Tremor Team
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+//! # WebAssembly plugin
+//!
+//! Hands events to a call of a WebAssembly plugin, see the `wasm` module for
+//! the ABI. Each operator has its own sandboxed instance of the plugin, which
+//! can keep state between calls.
+//!
+//! ## Configuration
+//!
+//! See [Config](struct.Config.html) for details.
+//!
+//! ## Outputs
+//!
+//! The call gets `{"port": <input port>, "event": <event>, "meta": <metadata>}`
+//! and returns the events to send as an array of `[<port>, <event>]` pairs,
+//! which keep the metadata of the input event. Events the call fails for are
+//! sent to `err`.
+
+use crate::errors::{ErrorKind, Result};
+use crate::op::prelude::*;
+use crate::wasm::{Limits, Plugin, Sandbox, DEFAULT_MAX_FUEL, DEFAULT_MAX_MEMORY};
+use std::path::Path;
+use tremor_script::prelude::*;
+
+#[derive(Debug, Clone, Deserialize)]
+pub struct Config {
+    /// Path of the WebAssembly module
+    pub module: String,
+    /// The call handling events (default: `on_event`)
+    #[serde(default = "d_call")]
+    pub call: String,
+    /// Maximum size of the memory of the plugin in bytes (default: 64MiB)
+    #[serde(default = "d_max_memory")]
+    pub max_memory: usize,
+    /// Fuel of a call, roughly the number of instructions (default: 100000000)
+    #[serde(default = "d_max_fuel")]
+    pub max_fuel: u64,
+}
+
+impl ConfigImpl for Config {}
+
+fn d_call() -> String {
+    "on_event".to_string()
+}
+
+fn d_max_memory() -> usize {
+    DEFAULT_MAX_MEMORY
+}
+
+fn d_max_fuel() -> u64 {
+    DEFAULT_MAX_FUEL
+}
+
+#[derive(Debug)]
+pub struct WasmPlugin {
+    call: String,
+    sandbox: Sandbox,
+}
+
+op!(PluginFactory(_uid, node) {
+if let Some(map) = &node.config {
+    let config: Config = Config::new(map)?;
+    let limits = Limits {
+        max_memory: config.max_memory,
+        max_fuel: config.max_fuel,
+    };
+    let plugin = Plugin::from_file(Path::new(&config.module), limits)?;
+    if !plugin.calls().contains(&config.call) {
+        return Err(ErrorKind::BadOpConfig(format!("The plugin {} has no call {}", config.module, config.call)).into());
+    }
+    Ok(Box::new(WasmPlugin {
+        call: config.call,
+        sandbox: plugin.instantiate()?,
+    }))
+} else {
+    Err(ErrorKind::MissingOpConfig(node.id.clone()).into())
+}});
+
+impl WasmPlugin {
+    fn handle(&mut self, port: &str, event: &Event) -> Result<Vec<(Cow<'static, str>, Event)>> {
+        let (value, meta) = event.data.suffix().parts();
+        let input = literal!({
+            "port": port.to_string(),
+            "event": value.clone_static(),
+            "meta": meta.clone_static(),
+        });
+        let output = self.sandbox.call(&self.call, &input)?;
+        let pairs = output.as_array().ok_or_else(|| {
+            ErrorKind::Wasm(format!("{} didn't return an array of events", self.call))
+        })?;
+        pairs
+            .iter()
+            .map(|pair| match pair.as_array().map(Vec::as_slice) {
+                Some([port, value]) => {
+                    let port = port.as_str().ok_or_else(|| {
+                        ErrorKind::Wasm(format!(
+                            "{} returned a port that isn't a string",
+                            self.call
+                        ))
+                    })?;
+                    let out = Event {
+                        id: event.id.clone(),
+                        data: (value.clone(), meta.clone_static()).into(),
+                        ingest_ns: event.ingest_ns,
+                        origin_uri: event.origin_uri.clone(),
+                        transactional: event.transactional,
+                        ..Event::default()
+                    };
+                    Ok((port.to_string().into(), out))
+                }
+                _ => Err(ErrorKind::Wasm(format!(
+                    "{} returned an event that isn't a [port, event] pair",
+                    self.call
+                ))
+                .into()),
+            })
+            .collect()
+    }
+}
+
+impl Operator for WasmPlugin {
+    fn on_event(
+        &mut self,
+        _uid: u64,
+        port: &str,
+        _state: &mut Value<'static>,
+        mut event: Event,
+    ) -> Result<EventAndInsights> {
+        match self.handle(port, &event) {
+            Ok(events) => Ok(events.into()),
+            Err(e) => {
+                event.data.rent_mut(|data| {
+                    let (_, meta) = data.parts_mut();
+                    meta.try_insert("error", e.to_string());
+                });
+                Ok(vec![(ERR, event)].into())
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::wasm::TEST_MODULE;
+
+    fn op(call: &str) -> Result<WasmPlugin> {
+        let plugin = Plugin::new("test", TEST_MODULE.as_bytes(), Limits::default())?;
+        Ok(WasmPlugin {
+            call: call.to_string(),
+            sandbox: plugin.instantiate()?,
+        })
+    }
+
+    fn send(op: &mut WasmPlugin) -> Result<Vec<(String, Value<'static>)>> {
+        let event = Event {
+            data: (literal!({"snot": "badger"}), literal!({"id": 1})).into(),
+            ..Event::default()
+        };
+        let mut state = Value::null();
+        Ok(op
+            .on_event(0, "in", &mut state, event)?
+            .events
+            .into_iter()
+            .map(|(port, e)| (port.to_string(), e.data.suffix().value().clone_static()))
+            .collect())
+    }
+
+    #[test]
+    fn events() -> Result<()> {
+        let mut op = op("split")?;
+        assert_eq!(
+            vec![
+                ("out".to_string(), Value::from(1)),
+                ("err".to_string(), Value::from(2)),
+            ],
+            send(&mut op)?
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn errors() -> Result<()> {
+        for call in ["answer", "fail", "echo"] {
+            let mut op = op(call)?;
+            let sent = send(&mut op)?;
+            assert_eq!(1, sent.len());
+            assert_eq!(Some("err"), sent.first().map(|(port, _)| port.as_str()));
+        }
+        Ok(())
+    }
+}
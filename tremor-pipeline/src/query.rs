@@ -24,7 +24,7 @@ use crate::{
             window,
         },
     },
-    ConfigGraph, Connection, NodeConfig, NodeKind, Operator, OperatorNode, PortIndexMap,
+    ConfigGraph, Connection, Limits, NodeConfig, NodeKind, Operator, OperatorNode, PortIndexMap,
 };
 use beef::Cow;
 use halfbrown::HashMap;
@@ -47,6 +47,13 @@ use tremor_script::{
     srs, AggrRegistry, Registry, Value,
 };
 
+/// `#!config script_backend` of the interpreter
+const INTERPRETER: &str = "interpreter";
+/// `#!config script_backend` of the bytecode backend
+const BYTECODE: &str = "bytecode";
+/// Config key of the backend of `script` operators
+const BACKEND: &str = "backend";
+
 const BUILTIN_NODES: [(Cow<'static, str>, NodeKind); 4] = [
     (IN, NodeKind::Input),
     (OUT, NodeKind::Output(OUT)),
@@ -186,12 +193,34 @@ impl Query {
             .and_then(Value::as_u64)
             .map(|i| i * 1_000_000_000);
 
+        let limits = Limits {
+            max_event_size: query.config.get("max_event_size").and_then(Value::as_usize),
+            max_state_size: query.config.get("max_state_size").and_then(Value::as_usize),
+        };
+
         let pipeline_id = query
             .config
             .get("id")
             .and_then(Value::as_str)
             .unwrap_or("<generated>");
 
+        // `script` operators are interpreted unless bytecode is selected
+        let script_config = match query.config.get("script_backend").and_then(Value::as_str) {
+            None | Some(INTERPRETER) => None,
+            Some(BYTECODE) => {
+                let mut config = serde_yaml::Mapping::new();
+                config.insert(BACKEND.into(), BYTECODE.into());
+                Some(serde_yaml::Value::Mapping(config))
+            }
+            Some(other) => {
+                return Err(ErrorKind::BadOpConfig(format!(
+                    "Unknown script backend `{}`, use `{}` or `{}`",
+                    other, INTERPRETER, BYTECODE
+                ))
+                .into())
+            }
+        };
+
         for (name, node_kind) in &BUILTIN_NODES {
             let id = pipe_graph.add_node(NodeConfig {
                 id: name.to_string(),
@@ -358,6 +387,7 @@ impl Query {
                         label,
                         kind: NodeKind::Select,
                         op_type: "trickle::select".to_string(),
+                        lines: Some(lines(e)),
                         ..NodeConfig::default()
                     };
                     let id = pipe_graph.add_node(node.clone());
@@ -394,6 +424,7 @@ impl Query {
                         id: id.to_string(),
                         kind: NodeKind::Operator,
                         op_type: "passthrough".to_string(),
+                        lines: Some(lines(s.extent(&query.node_meta))),
                         ..NodeConfig::default()
                     };
                     let id = pipe_graph.add_node(node.clone());
@@ -443,6 +474,7 @@ impl Query {
                         id: o.node_id.id().to_string(),
                         kind: NodeKind::Operator,
                         op_type: "trickle::operator".to_string(),
+                        lines: Some(lines(o.extent(&query.node_meta))),
                         ..NodeConfig::default()
                     };
                     let id = pipe_graph.add_node(node.clone());
@@ -503,17 +535,19 @@ impl Query {
                             Ok(inner_stmt)
                         })?;
 
-                    let label = if let Stmt::ScriptDecl(s) = stmt_srs.suffix() {
-                        let e = s.extent(&query.node_meta);
+                    let e = if let Stmt::ScriptDecl(s) = stmt_srs.suffix() {
+                        Some(s.extent(&query.node_meta))
+                    } else {
+                        None
+                    };
+                    let label = e.and_then(|e| {
                         let mut h = Dumb::new();
                         // We're trimming the code so no spaces are at the end then adding a newline
                         // to ensure we're left justified (this is a dot thing, don't question it)
                         h.highlight_str(self.source(), "", false, Some(e))
                             .ok()
                             .map(|_| format!("{}\n", h.to_string().trim_end()))
-                    } else {
-                        None
-                    };
+                    });
 
                     let that_defn = stmt_srs;
 
@@ -521,7 +555,9 @@ impl Query {
                         id: o.node_id.id().to_string(),
                         kind: NodeKind::Script,
                         label,
+                        lines: e.map(lines),
                         op_type: "trickle::script".to_string(),
+                        config: script_config.clone(),
                         defn: Some(that_defn.clone()),
                         node: Some(stmt.clone()),
                         ..NodeConfig::default()
@@ -680,6 +716,8 @@ impl Query {
                 id: pipeline_id.to_string(), // TODO make configurable
                 metrics_idx,
                 last_metrics: 0,
+                last_state_check: 0,
+                record_latencies: false,
                 state: State::new(iter::repeat(Value::null()).take(graph.len()).collect()),
                 graph,
                 inputs: inputs2,
@@ -687,9 +725,12 @@ impl Query {
                 contraflow,
                 signalflow,
                 metric_interval,
+                metric_tags: HashMap::new(),
+                limits,
                 insights: Vec::new(),
                 source: Some(self.0.source.clone()),
                 dot: format!("{}", dot),
+                debugger: None,
             };
             exec.optimize();
 
@@ -778,12 +819,24 @@ fn script(
     let node = node.ok_or_else(|| {
         ErrorKind::MissingOpConfig("trickle operators require a statement".into())
     })?;
+    let bytecode = config
+        .config
+        .as_ref()
+        .and_then(|c| c.get(BACKEND))
+        .and_then(serde_yaml::Value::as_str)
+        == Some(BYTECODE);
     Ok(Box::new(Script::with_stmt(
         config.id.clone(),
         defn.ok_or_else(|| Error::from("Script definition missing"))?,
         node,
+        bytecode,
     )?))
 }
+/// First and last line of a statement
+fn lines(extent: tremor_script::pos::Range) -> (usize, usize) {
+    (extent.0.line(), extent.1.line())
+}
+
 pub(crate) fn supported_operators(
     config: &NodeConfig,
     uid: u64,
@@ -805,6 +858,7 @@ pub(crate) fn supported_operators(
         kind: config.kind.clone(),
         op_type: config.op_type.clone(),
         op,
+        lines: config.lines,
     })
 }
 
@@ -818,6 +872,7 @@ pub(crate) fn make_builtin_node_name_checker() -> impl Fn(&Cow<'static, str>) ->
 #[cfg(test)]
 mod test {
     use super::*;
+    use crate::{CbAction, Event};
     #[test]
     fn query() {
         let module_path = &tremor_script::path::ModulePath { mounts: Vec::new() };
@@ -877,6 +932,226 @@ mod test {
         assert_eq!(out.kind, NodeKind::Output("test_out".into()));
     }
 
+    #[test]
+    fn fan_outs() {
+        let module_path = &tremor_script::path::ModulePath { mounts: Vec::new() };
+        let aggr_reg = tremor_script::aggr_registry();
+
+        let src = "select event from in into out;";
+        let q = Query::parse(
+            module_path,
+            src,
+            "<test>",
+            Vec::new(),
+            &*crate::FN_REGISTRY.lock().unwrap(),
+            &aggr_reg,
+        )
+        .unwrap();
+        let g = q.to_pipe(&mut OperatorIdGen::new()).unwrap();
+        assert!(g.fan_outs().is_empty());
+
+        let src = r#"
+create stream snot;
+select event from in into out;
+select event from in into snot;
+select event from snot into out;
+"#;
+        let q = Query::parse(
+            module_path,
+            src,
+            "<test>",
+            Vec::new(),
+            &*crate::FN_REGISTRY.lock().unwrap(),
+            &aggr_reg,
+        )
+        .unwrap();
+        let g = q.to_pipe(&mut OperatorIdGen::new()).unwrap();
+        let fan_outs = g.fan_outs();
+        assert_eq!(fan_outs.len(), 1);
+        let (node, port, targets) = &fan_outs[0];
+        assert!(node.starts_with("in"));
+        assert_eq!(port, "out");
+        assert_eq!(targets.len(), 2);
+    }
+
+    #[test]
+    fn latencies() {
+        let module_path = &tremor_script::path::ModulePath { mounts: Vec::new() };
+        let aggr_reg = tremor_script::aggr_registry();
+
+        let src = "select event from in into out;";
+        let q = Query::parse(
+            module_path,
+            src,
+            "<test>",
+            Vec::new(),
+            &*crate::FN_REGISTRY.lock().unwrap(),
+            &aggr_reg,
+        )
+        .unwrap();
+        let mut g = q.to_pipe(&mut OperatorIdGen::new()).unwrap();
+        let mut returns = vec![];
+        g.enqueue("in", Event::default(), &mut returns).unwrap();
+        assert!(g.latencies(0).is_empty());
+
+        g.record_latencies();
+        g.enqueue("in", Event::default(), &mut returns).unwrap();
+        let latencies = g.latencies(0);
+        assert!(!latencies.is_empty());
+        for l in &latencies {
+            assert_eq!(l.get_str("measurement"), Some("latency"));
+            assert!(l.get("tags").and_then(|t| t.get_str("node")).is_some());
+        }
+        assert!(g.latencies(0).is_empty());
+    }
+
+    #[test]
+    fn limits() {
+        let module_path = &tremor_script::path::ModulePath { mounts: Vec::new() };
+        let aggr_reg = tremor_script::aggr_registry();
+
+        let src = "#!config max_event_size = 128\nselect event from in into out;";
+        let q = Query::parse(
+            module_path,
+            src,
+            "<test>",
+            Vec::new(),
+            &*crate::FN_REGISTRY.lock().unwrap(),
+            &aggr_reg,
+        )
+        .unwrap();
+        let mut idgen = OperatorIdGen::new();
+        let mut g = q.to_pipe(&mut idgen).unwrap();
+        assert_eq!(g.limits.max_event_size, Some(128));
+        assert_eq!(g.limits.max_state_size, None);
+
+        let mut returns = vec![];
+        g.enqueue("in", Event::default(), &mut returns).unwrap();
+        assert_eq!(returns.len(), 1);
+        assert_eq!(returns[0].0, OUT);
+
+        returns.clear();
+        let event = Event {
+            data: Value::from("snot".repeat(64)).into(),
+            transactional: true,
+            ..Event::default()
+        };
+        g.enqueue("in", event, &mut returns).unwrap();
+        assert_eq!(returns.len(), 1);
+        let (port, error) = &returns[0];
+        assert_eq!(port, &ERR);
+        assert_eq!(
+            error.data.suffix().value().get_str("limit"),
+            Some("max_event_size")
+        );
+        assert_eq!(g.insights.len(), 1);
+        assert_eq!(g.insights[0].1.cb, CbAction::Fail);
+    }
+
+    #[test]
+    fn state_limits() {
+        let module_path = &tremor_script::path::ModulePath { mounts: Vec::new() };
+        let aggr_reg = tremor_script::aggr_registry();
+
+        let src = r#"#!config max_state_size = 128
+define script keep
+script
+  let state = event;
+  event
+end;
+create script keep;
+select event from in into keep;
+select event from keep into out;
+"#;
+        let q = Query::parse(
+            module_path,
+            src,
+            "<test>",
+            Vec::new(),
+            &*crate::FN_REGISTRY.lock().unwrap(),
+            &aggr_reg,
+        )
+        .unwrap();
+        let mut idgen = OperatorIdGen::new();
+        let mut g = q.to_pipe(&mut idgen).unwrap();
+        assert_eq!(g.limits.max_state_size, Some(128));
+
+        let mut returns = vec![];
+        let event = Event {
+            data: Value::from("snot".repeat(64)).into(),
+            ..Event::default()
+        };
+        g.enqueue("in", event, &mut returns).unwrap();
+        assert_eq!(returns.len(), 1);
+        assert_eq!(returns[0].0, OUT);
+
+        let tick = |ingest_ns| Event {
+            kind: Some(crate::SignalKind::Tick),
+            ingest_ns,
+            ..Event::default()
+        };
+        // the state is only checked every `STATE_CHECK_INTERVAL_NS`
+        returns.clear();
+        g.enqueue_signal(tick(1_000_000_000), &mut returns).unwrap();
+        assert!(returns.is_empty());
+
+        g.enqueue_signal(tick(11_000_000_000), &mut returns)
+            .unwrap();
+        assert_eq!(returns.len(), 1);
+        let (port, error) = &returns[0];
+        assert_eq!(port, &ERR);
+        let error = error.data.suffix().value();
+        assert_eq!(error.get_str("limit"), Some("max_state_size"));
+        assert!(error.get_str("node").is_some());
+        assert!(g.state.ops.iter().all(|s| s.is_null()));
+    }
+
+    #[test]
+    fn script_backend() {
+        let module_path = &tremor_script::path::ModulePath { mounts: Vec::new() };
+        let aggr_reg = tremor_script::aggr_registry();
+        let parse = |backend: &str| {
+            let src = format!(
+                r#"#!config script_backend = "{}"
+define script double
+script
+  let event.n = event.n * 2;
+  event
+end;
+create script double;
+select event from in into double;
+select event from double into out;
+"#,
+                backend
+            );
+            Query::parse(
+                module_path,
+                &src,
+                "<test>",
+                Vec::new(),
+                &*crate::FN_REGISTRY.lock().unwrap(),
+                &aggr_reg,
+            )
+            .unwrap()
+        };
+        for backend in &["interpreter", "bytecode"] {
+            let mut idgen = OperatorIdGen::new();
+            let mut g = parse(backend).to_pipe(&mut idgen).unwrap();
+            let mut returns = vec![];
+            let event = Event {
+                data: literal!({"n": 21}).into(),
+                ..Event::default()
+            };
+            g.enqueue("in", event, &mut returns).unwrap();
+            assert_eq!(returns.len(), 1);
+            assert_eq!(returns[0].0, OUT);
+            assert_eq!(returns[0].1.data.suffix().value().get_u64("n"), Some(42));
+        }
+
+        let mut idgen = OperatorIdGen::new();
+        assert!(parse("jit").to_pipe(&mut idgen).is_err());
+    }
+
     #[test]
     fn builtin_nodes() {
         let has_builtin_node_name = make_builtin_node_name_checker();
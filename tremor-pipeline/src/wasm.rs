@@ -0,0 +1,413 @@
+// Copyright 2020-2021, The Tremor Team
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+//! WebAssembly plugins
+//!
+//! Plugins are WASI modules extending tremor with functions callable from
+//! tremor-script and operators usable in trickle, written in any language
+//! compiling to WebAssembly, without recompiling tremor. They run sandboxed:
+//! without access to the file system, network or environment, with bounded
+//! memory, and with a bounded amount of fuel, roughly instructions, per call.
+//!
+//! ## ABI
+//!
+//! Values cross the boundary JSON encoded in the linear memory of the module,
+//! which exports:
+//!
+//! * `memory`
+//! * `alloc(len: i32) -> i32`, returning a buffer of `len` bytes for the input
+//! * calls as `(ptr: i32, len: i32) -> i64`, taking the input buffer and
+//!   returning the output buffer as `ptr << 32 | len`
+//!
+//! The output is `{"ok": <result>}` or `{"error": "<message>"}`.
+//!
+//! Every call of a plugin loaded with [`load`] is a function
+//! `<plugin>::<call>` in tremor-script, taking the array of its arguments.
+//! Operators are described in the `wasm::plugin` operator.
+
+use crate::errors::{Error, ErrorKind, Result};
+use std::fmt::Display;
+use std::path::Path;
+use std::sync::{Arc, Mutex};
+use tremor_script::prelude::*;
+use tremor_script::registry::{
+    mfa, to_runtime_error, FResult, Registry, TremorFn, TremorFnWrapper,
+};
+use wasmtime::{
+    Engine, Instance, Linker, Memory, Module, Store, StoreLimits, StoreLimitsBuilder, TypedFunc,
+    ValType,
+};
+use wasmtime_wasi::{WasiCtx, WasiCtxBuilder};
+
+/// Default memory limit of a plugin instance in bytes
+pub const DEFAULT_MAX_MEMORY: usize = 64 * 1024 * 1024;
+/// Default fuel of a call
+pub const DEFAULT_MAX_FUEL: u64 = 100_000_000;
+
+lazy_static::lazy_static! {
+    /// Plugins providing functions
+    static ref PLUGINS: Mutex<Vec<Plugin>> = Mutex::new(Vec::new());
+}
+
+fn wasm<E: Display>(e: E) -> Error {
+    ErrorKind::Wasm(e.to_string()).into()
+}
+
+/// Resource limits of a plugin instance
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Limits {
+    /// Maximum size of the memory in bytes
+    pub max_memory: usize,
+    /// Fuel of a call
+    pub max_fuel: u64,
+}
+
+impl Default for Limits {
+    fn default() -> Self {
+        Self {
+            max_memory: DEFAULT_MAX_MEMORY,
+            max_fuel: DEFAULT_MAX_FUEL,
+        }
+    }
+}
+
+struct State {
+    wasi: WasiCtx,
+    limits: StoreLimits,
+}
+
+/// A compiled plugin
+#[derive(Clone)]
+pub struct Plugin {
+    name: String,
+    engine: Engine,
+    module: Module,
+    limits: Limits,
+}
+
+impl std::fmt::Debug for Plugin {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "Plugin({})", self.name)
+    }
+}
+
+impl Plugin {
+    /// Compiles a plugin from a WebAssembly module, binary or text
+    ///
+    /// # Errors
+    ///  * if the module is invalid
+    pub fn new(name: &str, module: &[u8], limits: Limits) -> Result<Self> {
+        let mut config = wasmtime::Config::new();
+        config.consume_fuel(true);
+        let engine = Engine::new(&config).map_err(wasm)?;
+        let module = Module::new(&engine, module).map_err(wasm)?;
+        Ok(Self {
+            name: name.to_string(),
+            engine,
+            module,
+            limits,
+        })
+    }
+
+    /// Compiles the plugin in a file, named after the file
+    ///
+    /// # Errors
+    ///  * if the file can't be read or the module is invalid
+    pub fn from_file(path: &Path, limits: Limits) -> Result<Self> {
+        let name = path
+            .file_stem()
+            .map(|s| s.to_string_lossy().to_string())
+            .unwrap_or_default();
+        Self::new(&name, &std::fs::read(path)?, limits)
+    }
+
+    /// The calls exported by the plugin
+    #[must_use]
+    pub fn calls(&self) -> Vec<String> {
+        self.module
+            .exports()
+            .filter(|e| {
+                e.ty().func().map_or(false, |f| {
+                    f.params().eq([ValType::I32, ValType::I32]) && f.results().eq([ValType::I64])
+                })
+            })
+            .map(|e| e.name().to_string())
+            .collect()
+    }
+
+    /// Creates a sandboxed instance of the plugin
+    ///
+    /// # Errors
+    ///  * if the module can't be instantiated or lacks `memory` or `alloc`
+    pub fn instantiate(&self) -> Result<Sandbox> {
+        let state = State {
+            // no preopened directories, environment or arguments
+            wasi: WasiCtxBuilder::new().inherit_stderr().build(),
+            limits: StoreLimitsBuilder::new()
+                .memory_size(self.limits.max_memory)
+                .build(),
+        };
+        let mut store = Store::new(&self.engine, state);
+        store.limiter(|s| &mut s.limits);
+        let mut linker = Linker::new(&self.engine);
+        wasmtime_wasi::add_to_linker(&mut linker, |s: &mut State| &mut s.wasi).map_err(wasm)?;
+        let instance = linker.instantiate(&mut store, &self.module).map_err(wasm)?;
+        let memory = instance
+            .get_memory(&mut store, "memory")
+            .ok_or_else(|| wasm(format!("plugin {} exports no `memory`", self.name)))?;
+        let alloc = instance
+            .get_typed_func::<i32, i32, _>(&mut store, "alloc")
+            .map_err(wasm)?;
+        Ok(Sandbox {
+            name: self.name.clone(),
+            store,
+            instance,
+            memory,
+            alloc,
+            fueled: 0,
+            max_fuel: self.limits.max_fuel,
+        })
+    }
+}
+
+/// A sandboxed instance of a plugin
+pub struct Sandbox {
+    name: String,
+    store: Store<State>,
+    instance: Instance,
+    memory: Memory,
+    alloc: TypedFunc<i32, i32>,
+    /// fuel added so far
+    fueled: u64,
+    max_fuel: u64,
+}
+
+impl std::fmt::Debug for Sandbox {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "Sandbox({})", self.name)
+    }
+}
+
+impl Sandbox {
+    /// Tops the fuel up to the fuel of a call
+    fn refuel(&mut self) -> Result<()> {
+        let consumed = self.store.fuel_consumed().unwrap_or_default();
+        let remaining = self.fueled.saturating_sub(consumed);
+        let fuel = self.max_fuel.saturating_sub(remaining);
+        self.store.add_fuel(fuel).map_err(wasm)?;
+        self.fueled += fuel;
+        Ok(())
+    }
+
+    /// Calls an export of the plugin with the input, returning its result
+    ///
+    /// # Errors
+    ///  * if the call fails, traps, runs out of fuel or returns an error
+    pub fn call(&mut self, export: &str, input: &Value) -> Result<Value<'static>> {
+        let input = input.encode();
+        let len = i32::try_from(input.len()).map_err(wasm)?;
+        self.refuel()?;
+        let ptr = self.alloc.call(&mut self.store, len).map_err(wasm)?;
+        let offset = usize::try_from(ptr).map_err(wasm)?;
+        self.memory
+            .write(&mut self.store, offset, input.as_bytes())
+            .map_err(wasm)?;
+        let f = self
+            .instance
+            .get_typed_func::<(i32, i32), i64, _>(&mut self.store, export)
+            .map_err(wasm)?;
+        let packed = f.call(&mut self.store, (ptr, len)).map_err(wasm)?;
+        let (offset, len) = split(packed);
+        let mut output = vec![0; len];
+        self.memory
+            .read(&self.store, offset, &mut output)
+            .map_err(wasm)?;
+        let output = Value::from(simd_json::to_owned_value(&mut output)?);
+        if let Some(error) = output.get_str("error") {
+            Err(wasm(format!("{}::{}: {}", self.name, export, error)))
+        } else if let Some(ok) = output.get("ok") {
+            Ok(ok.clone_static())
+        } else {
+            Err(wasm(format!(
+                "{}::{} returned neither `ok` nor `error`",
+                self.name, export
+            )))
+        }
+    }
+}
+
+/// Splits a returned buffer into offset and length
+fn split(packed: i64) -> (usize, usize) {
+    // the bits are reinterpreted, both halves are unsigned
+    #[allow(clippy::cast_sign_loss)]
+    let packed = packed as u64;
+    ((packed >> 32) as usize, (packed & 0xffff_ffff) as usize)
+}
+
+/// A function of a plugin, sharing an instance with the other functions
+#[derive(Clone)]
+struct PluginFn {
+    plugin: String,
+    export: String,
+    sandbox: Arc<Mutex<Sandbox>>,
+}
+
+impl TremorFn for PluginFn {
+    fn invoke<'event>(
+        &self,
+        _ctx: &EventContext,
+        args: &[&Value<'event>],
+    ) -> FResult<Value<'event>> {
+        let mfa = || mfa(&self.plugin, &self.export, args.len());
+        let args: Value = args.iter().map(|a| (*a).clone()).collect::<Vec<_>>().into();
+        let mut sandbox = self
+            .sandbox
+            .lock()
+            .map_err(|e| to_runtime_error(mfa(), e))?;
+        sandbox
+            .call(&self.export, &args)
+            .map_err(|e| to_runtime_error(mfa(), e))
+    }
+
+    fn boxed_clone(&self) -> Box<dyn TremorFn> {
+        Box::new(self.clone())
+    }
+
+    fn arity(&self) -> std::ops::RangeInclusive<usize> {
+        0..=usize::MAX
+    }
+}
+
+/// Loads a plugin providing functions, installed into registries by [`install`]
+///
+/// # Errors
+///  * if the plugin can't be loaded
+pub fn load(path: &Path, limits: Limits) -> Result<()> {
+    let plugin = Plugin::from_file(path, limits)?;
+    info!("Loaded WebAssembly plugin {}", plugin.name);
+    PLUGINS.lock()?.push(plugin);
+    Ok(())
+}
+
+/// Installs the functions of the loaded plugins into a registry
+///
+/// # Errors
+///  * if a plugin can't be instantiated
+pub fn install(reg: &mut Registry) -> Result<()> {
+    for plugin in PLUGINS.lock()?.iter() {
+        register(reg, plugin)?;
+    }
+    Ok(())
+}
+
+fn register(reg: &mut Registry, plugin: &Plugin) -> Result<()> {
+    let sandbox = Arc::new(Mutex::new(plugin.instantiate()?));
+    for export in plugin.calls() {
+        let fun = PluginFn {
+            plugin: plugin.name.clone(),
+            export: export.clone(),
+            sandbox: sandbox.clone(),
+        };
+        reg.insert(TremorFnWrapper::new(
+            plugin.name.clone(),
+            export,
+            Box::new(fun),
+        ));
+    }
+    Ok(())
+}
+
+/// A module echoing its input, used in tests
+#[cfg(test)]
+pub(crate) const TEST_MODULE: &str = r#"
+(module
+  (memory (export "memory") 1)
+  (data (i32.const 0) "{\"ok\":")
+  (data (i32.const 1024) "{\"ok\":42}")
+  (data (i32.const 2048) "{\"error\":\"snot\"}")
+  (data (i32.const 3072) "{\"ok\":[[\"out\",1],[\"err\",2]]}")
+  ;; the input is written after `{"ok":`
+  (func (export "alloc") (param i32) (result i32) (i32.const 6))
+  (func (export "echo") (param $ptr i32) (param $len i32) (result i64)
+    (i32.store8 (i32.add (local.get $ptr) (local.get $len)) (i32.const 125))
+    (i64.extend_i32_u (i32.add (local.get $len) (i32.const 7))))
+  (func (export "answer") (param i32 i32) (result i64)
+    (i64.or (i64.shl (i64.const 1024) (i64.const 32)) (i64.const 9)))
+  (func (export "fail") (param i32 i32) (result i64)
+    (i64.or (i64.shl (i64.const 2048) (i64.const 32)) (i64.const 16)))
+  (func (export "split") (param i32 i32) (result i64)
+    (i64.or (i64.shl (i64.const 3072) (i64.const 32)) (i64.const 28)))
+  (func (export "spin") (param i32 i32) (result i64)
+    (loop $l (br $l))
+    (i64.const 0))
+)
+"#;
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use tremor_value::literal;
+
+    fn plugin() -> Result<Plugin> {
+        Plugin::new("test", TEST_MODULE.as_bytes(), Limits::default())
+    }
+
+    #[test]
+    fn calls() -> Result<()> {
+        let plugin = plugin()?;
+        assert_eq!(
+            vec!["echo", "answer", "fail", "split", "spin"],
+            plugin.calls()
+        );
+        let mut sandbox = plugin.instantiate()?;
+        let input = literal!({"snot": ["badger", 1]});
+        assert_eq!(input, sandbox.call("echo", &input)?);
+        assert_eq!(Value::from(42), sandbox.call("answer", &input)?);
+        assert!(sandbox.call("fail", &input).is_err());
+        assert!(sandbox.call("missing", &input).is_err());
+        Ok(())
+    }
+
+    #[test]
+    fn fuel() -> Result<()> {
+        let mut sandbox = Plugin::new(
+            "test",
+            TEST_MODULE.as_bytes(),
+            Limits {
+                max_fuel: 10_000,
+                ..Limits::default()
+            },
+        )?
+        .instantiate()?;
+        assert!(sandbox.call("spin", &Value::null()).is_err());
+        // every call gets its own fuel
+        assert_eq!(Value::null(), sandbox.call("echo", &Value::null())?);
+        Ok(())
+    }
+
+    #[test]
+    fn functions() -> Result<()> {
+        let mut reg = tremor_script::registry();
+        register(&mut reg, &plugin()?)?;
+        let echo = reg.find("test", "echo").map_err(|e| format!("{:?}", e))?;
+        let a = Value::from(1);
+        let b = Value::from("badger");
+        assert_eq!(
+            literal!([1, "badger"]),
+            echo.invoke(&EventContext::new(0, None), &[&a, &b])
+                .map_err(|e| format!("{:?}", e))?
+        );
+        Ok(())
+    }
+}
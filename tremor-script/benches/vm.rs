@@ -0,0 +1,84 @@
+// Copyright 2020-2021, The Tremor Team
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+//! Compares the interpreter with the bytecode backend on scripts that
+//! mostly compute values, the bytecode backend aims for 2-5x the
+//! throughput of the interpreter on them
+
+use criterion::{black_box, criterion_group, criterion_main, BenchmarkId, Criterion};
+use tremor_script::{path::ModulePath, registry, AggrType, EventContext, Script, Value};
+use tremor_value::literal;
+
+const SCRIPTS: [(&str, &str); 3] = [
+    (
+        "arithmetic",
+        "let x = event.a * event.b - event.c; let y = x / 2 + event.a; x + y * 3",
+    ),
+    (
+        "record",
+        r#"{"host": event.host, "cpu": event.cpu.user + event.cpu.system, "tags": [event.a, event.b, $kafka.topic]}"#,
+    ),
+    (
+        "calls",
+        r#"{"host": string::uppercase(event.host), "max": math::max(event.a, event.b)}"#,
+    ),
+];
+
+fn script(src: &str, compile: bool) -> Script {
+    let reg = registry::registry();
+    let mut script = Script::parse(
+        &ModulePath { mounts: vec![] },
+        "bench",
+        src.to_string(),
+        &reg,
+    )
+    .unwrap_or_else(|e| panic!("invalid script {}: {}", src, e.error));
+    if compile {
+        script.compile();
+    }
+    script
+}
+
+fn backends(c: &mut Criterion) {
+    let event = literal!({
+        "a": 7,
+        "b": 3,
+        "c": 2.5,
+        "host": "badger.example.com",
+        "cpu": {"user": 0.25, "system": 0.5}
+    });
+    let context = EventContext::new(0, None);
+    let mut group = c.benchmark_group("script");
+    for (name, src) in &SCRIPTS {
+        for (backend, compile) in &[("interpreter", false), ("bytecode", true)] {
+            let script = script(src, *compile);
+            group.bench_with_input(BenchmarkId::new(*backend, name), &script, |b, script| {
+                b.iter(|| {
+                    let mut event = event.clone();
+                    let mut meta = literal!({"kafka": {"topic": "metrics"}});
+                    let mut state = Value::null();
+                    black_box(
+                        script
+                            .run(&context, AggrType::Emit, &mut event, &mut state, &mut meta)
+                            .is_ok(),
+                    )
+                })
+            });
+        }
+    }
+    group.finish();
+}
+
+criterion_group!(benches, backends);
+criterion_main!(benches);
@@ -54,6 +54,7 @@ use crate::{
     script::Return,
     stry,
     tilde::Extractor,
+    vm::Program,
     EventContext, KnownKey, Value, NO_AGGRS, NO_CONSTS,
 };
 pub(crate) use analyzer::*;
@@ -717,7 +718,7 @@ pub struct Script<'script> {
     /// Aggregate functions
     pub aggregates: Vec<InvokeAggrFn<'script>>,
     windows: HashMap<String, WindowDecl<'script>>,
-    functions: Vec<CustomFn<'script>>,
+    pub(crate) functions: Vec<CustomFn<'script>>,
     /// Locals
     pub locals: usize,
     /// Node metadata
@@ -725,6 +726,9 @@ pub struct Script<'script> {
     #[serde(skip)]
     /// Documentation from the script
     pub docs: Docs,
+    /// The bytecode of the script, if compiled
+    #[serde(skip)]
+    pub(crate) program: Option<Program<'script>>,
 }
 
 impl<'script> Script<'script> {
@@ -773,6 +777,12 @@ impl<'script> Script<'script> {
             }
         })
     }
+    /// Compiles the script to bytecode, which `run` uses from then on
+    /// instead of interpreting the expressions
+    pub fn compile(&mut self) {
+        self.program = Some(Program::compile(&self.exprs));
+    }
+
     /// Runs the script and evaluates to a resulting event
     ///
     /// # Errors
@@ -788,6 +798,14 @@ impl<'script> Script<'script> {
     where
         'script: 'event,
     {
+        // coverage is only recorded by the interpreter
+        if let Some(program) = self
+            .program
+            .as_ref()
+            .filter(|_| !crate::coverage::enabled())
+        {
+            return program.run(self, context, aggr, event, state, meta);
+        }
         let mut local = LocalStack::with_size(self.locals);
 
         let mut exprs = self.exprs.iter().peekable();
@@ -14,9 +14,9 @@
 
 pub(crate) mod raw;
 use super::{
-    error_generic, error_no_consts, error_no_locals, node_id::NodeId, AggrRegistry, EventPath,
-    HashMap, Helper, Ident, ImutExpr, ImutExprInt, InvokeAggrFn, Location, NodeMetas, Path,
-    Registry, Result, Script, Serialize, Stmts, Upable, Value,
+    error_generic, error_no_consts, error_no_locals, node_id::NodeId, AggrRegistry, Docs,
+    EventPath, HashMap, Helper, Ident, ImutExpr, ImutExprInt, InvokeAggrFn, Location, NodeMetas,
+    Path, Registry, Result, Script, Serialize, Stmts, Upable, Value,
 };
 use super::{raw::BaseExpr, Consts};
 use crate::impl_expr_mid;
@@ -38,6 +38,9 @@ pub struct Query<'script> {
     pub scripts: HashMap<String, ScriptDecl<'script>>,
     /// Operators declarations
     pub operators: HashMap<String, OperatorDecl<'script>>,
+    #[serde(skip)]
+    /// Documentation of the declarations in the query
+    pub docs: Docs,
 }
 
 /// Query statement
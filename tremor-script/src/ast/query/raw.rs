@@ -109,6 +109,7 @@ impl<'script> QueryRaw<'script> {
             windows: helper.windows.clone(),
             scripts: helper.scripts.clone(),
             operators: helper.operators.clone(),
+            docs: helper.docs.clone(),
         })
     }
 }
@@ -146,6 +146,7 @@ impl<'script> ScriptRaw<'script> {
             node_meta: helper.meta.clone(),
             functions: helper.func_vec.clone(),
             docs: helper.docs.clone(),
+            program: None,
         })
     }
 }
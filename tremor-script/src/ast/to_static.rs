@@ -1007,6 +1007,7 @@ impl<'script> Script<'script> {
             locals,
             node_meta,
             docs,
+            ..
         } = self;
         Script {
             imports,
@@ -1024,6 +1025,8 @@ impl<'script> Script<'script> {
             locals,
             node_meta,
             docs,
+            // the bytecode borrows from the script, it has to be compiled again
+            program: None,
         }
     }
 }
@@ -0,0 +1,362 @@
+// Copyright 2020-2021, The Tremor Team
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+//! Coverage of tremor-script by tests
+//!
+//! Once enabled, the interpreter counts how often the expressions on each
+//! line and each arm of `match`, `if` and comprehension cases are executed.
+//! Scripts registered with [`register`] also report the lines and arms that
+//! never ran. Arms are identified by the location of their last expression.
+
+use crate::ast::visitors::{ExprVisitor, ImutExprVisitor, VisitRes};
+use crate::ast::walkers::{ExprWalker, ImutExprWalker};
+use crate::ast::{
+    BaseExpr, ClauseGroup, Comprehension, DefaultCase, Expr, Expression, ImutExprInt, NodeMetas,
+    PredicateClause, Script,
+};
+use crate::errors::Result;
+use crate::pos::Location;
+use lazy_static::lazy_static;
+use std::collections::BTreeMap;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::Mutex;
+
+static ENABLED: AtomicBool = AtomicBool::new(false);
+
+lazy_static! {
+    static ref COVERAGE: Mutex<Coverage> = Mutex::new(Coverage::default());
+}
+
+/// Coverage of a source file
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct FileCoverage {
+    /// Executions of the expressions by line
+    pub lines: BTreeMap<usize, u64>,
+    /// Executions of the arms by line and column
+    pub arms: BTreeMap<(usize, usize), u64>,
+}
+
+/// Coverage of source files by path
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct Coverage {
+    /// Coverage by file
+    pub files: BTreeMap<String, FileCoverage>,
+}
+
+impl Coverage {
+    fn file(&mut self, meta: &NodeMetas, loc: Location) -> &mut FileCoverage {
+        let file = meta
+            .cus
+            .get(loc.unit_id)
+            .map(|cu| cu.file_path().to_string_lossy().to_string())
+            .unwrap_or_default();
+        self.files.entry(file).or_default()
+    }
+
+    /// Number of lines found and lines executed
+    #[must_use]
+    pub fn lines(&self) -> (usize, usize) {
+        self.files.values().fold((0, 0), |(found, hit), f| {
+            (
+                found + f.lines.len(),
+                hit + f.lines.values().filter(|h| **h > 0).count(),
+            )
+        })
+    }
+
+    /// Number of arms found and arms executed
+    #[must_use]
+    pub fn arms(&self) -> (usize, usize) {
+        self.files.values().fold((0, 0), |(found, hit), f| {
+            (
+                found + f.arms.len(),
+                hit + f.arms.values().filter(|h| **h > 0).count(),
+            )
+        })
+    }
+
+    /// Percentage of the lines executed, 100 if there are no lines
+    #[must_use]
+    #[allow(clippy::cast_precision_loss)]
+    pub fn line_rate(&self) -> f64 {
+        match self.lines() {
+            (0, _) => 100.0,
+            (found, hit) => hit as f64 * 100.0 / found as f64,
+        }
+    }
+
+    /// Renders the coverage as an lcov tracefile
+    #[must_use]
+    pub fn to_lcov(&self) -> String {
+        let mut lcov = String::new();
+        for (file, coverage) in &self.files {
+            lcov.push_str(&format!("TN:\nSF:{}\n", file));
+            for ((line, column), hits) in &coverage.arms {
+                lcov.push_str(&format!("BRDA:{},0,{},{}\n", line, column, hits));
+            }
+            let arms_hit = coverage.arms.values().filter(|h| **h > 0).count();
+            lcov.push_str(&format!("BRF:{}\nBRH:{}\n", coverage.arms.len(), arms_hit));
+            for (line, hits) in &coverage.lines {
+                lcov.push_str(&format!("DA:{},{}\n", line, hits));
+            }
+            let lines_hit = coverage.lines.values().filter(|h| **h > 0).count();
+            lcov.push_str(&format!("LF:{}\nLH:{}\n", coverage.lines.len(), lines_hit));
+            lcov.push_str("end_of_record\n");
+        }
+        lcov
+    }
+}
+
+/// Starts recording coverage
+pub fn enable() {
+    ENABLED.store(true, Ordering::Relaxed);
+}
+
+/// If coverage is recorded
+#[inline]
+#[must_use]
+pub fn enabled() -> bool {
+    ENABLED.load(Ordering::Relaxed)
+}
+
+/// Stops recording coverage and returns what was recorded
+#[must_use]
+pub fn take() -> Coverage {
+    ENABLED.store(false, Ordering::Relaxed);
+    COVERAGE
+        .lock()
+        .map(|mut c| std::mem::take(&mut *c))
+        .unwrap_or_default()
+}
+
+/// Records the execution of an expression
+pub(crate) fn expr<E: BaseExpr>(meta: &NodeMetas, e: &E) {
+    let loc = e.s(meta);
+    if let Ok(mut c) = COVERAGE.lock() {
+        *c.file(meta, loc).lines.entry(loc.line()).or_default() += 1;
+    }
+}
+
+/// Records the execution of an arm by its last expression
+pub(crate) fn arm<E: BaseExpr>(meta: &NodeMetas, last: &E) {
+    let loc = last.s(meta);
+    if let Ok(mut c) = COVERAGE.lock() {
+        *c.file(meta, loc)
+            .arms
+            .entry((loc.line(), loc.column()))
+            .or_default() += 1;
+    }
+}
+
+/// Registers the lines and arms of a script and of the functions it uses,
+/// so the ones never executed are reported
+///
+/// # Errors
+/// if walking the script fails
+pub fn register(script: &Script) -> Result<()> {
+    let mut registrar = Registrar {
+        meta: &script.node_meta,
+        coverage: Coverage::default(),
+    };
+    let bodies = script.functions.iter().map(|f| &f.body);
+    for exprs in std::iter::once(&script.exprs).chain(bodies) {
+        for e in exprs {
+            ExprWalker::walk_expr(&mut registrar, &mut e.clone())?;
+        }
+    }
+    if let Ok(mut c) = COVERAGE.lock() {
+        for (file, found) in registrar.coverage.files {
+            let file = c.files.entry(file).or_default();
+            for line in found.lines.into_keys() {
+                file.lines.entry(line).or_default();
+            }
+            for arm in found.arms.into_keys() {
+                file.arms.entry(arm).or_default();
+            }
+        }
+    }
+    Ok(())
+}
+
+/// Collects the lines and arms of a script
+struct Registrar<'meta> {
+    meta: &'meta NodeMetas,
+    coverage: Coverage,
+}
+
+impl<'meta> Registrar<'meta> {
+    fn line<E: BaseExpr>(&mut self, e: &E) {
+        let loc = e.s(self.meta);
+        self.coverage
+            .file(self.meta, loc)
+            .lines
+            .entry(loc.line())
+            .or_default();
+    }
+    fn arm<E: BaseExpr>(&mut self, last: &E) {
+        let loc = last.s(self.meta);
+        self.coverage
+            .file(self.meta, loc)
+            .arms
+            .entry((loc.line(), loc.column()))
+            .or_default();
+    }
+    fn clause_group<'script, Ex: Expression + BaseExpr>(
+        &mut self,
+        group: &ClauseGroup<'script, Ex>,
+    ) {
+        // predicate clauses and the groups of combined ones are visited on
+        // their own
+        if let ClauseGroup::SearchTree { tree, .. } = group {
+            for (_, last) in tree.values() {
+                self.arm(last);
+            }
+        }
+    }
+    fn default_case<Ex: Expression + BaseExpr>(&mut self, default: &DefaultCase<Ex>) {
+        match default {
+            DefaultCase::Many { last_expr, .. } => self.arm(last_expr.as_ref()),
+            DefaultCase::One(last_expr) => self.arm(last_expr),
+            DefaultCase::None | DefaultCase::Null => (),
+        }
+    }
+    fn comprehension<'script, Ex: Expression + BaseExpr>(
+        &mut self,
+        comp: &Comprehension<'script, Ex>,
+    ) {
+        for case in &comp.cases {
+            self.arm(&case.last_expr);
+        }
+    }
+}
+
+impl<'script, 'meta> ImutExprWalker<'script> for Registrar<'meta> {}
+impl<'script, 'meta> ExprWalker<'script> for Registrar<'meta> {}
+
+impl<'script, 'meta> ExprVisitor<'script> for Registrar<'meta> {
+    fn visit_expr(&mut self, e: &mut Expr<'script>) -> Result<VisitRes> {
+        self.line(e);
+        Ok(VisitRes::Walk)
+    }
+    fn visit_predicate_clause(
+        &mut self,
+        predicate: &mut PredicateClause<'script, Expr<'script>>,
+    ) -> Result<VisitRes> {
+        self.arm(&predicate.last_expr);
+        Ok(VisitRes::Walk)
+    }
+    fn visit_clause_group(
+        &mut self,
+        group: &mut ClauseGroup<'script, Expr<'script>>,
+    ) -> Result<VisitRes> {
+        self.clause_group(group);
+        Ok(VisitRes::Walk)
+    }
+    fn visit_default_case(&mut self, default: &mut DefaultCase<Expr<'script>>) -> Result<VisitRes> {
+        self.default_case(default);
+        Ok(VisitRes::Walk)
+    }
+    fn visit_comprehension(
+        &mut self,
+        comp: &mut Comprehension<'script, Expr<'script>>,
+    ) -> Result<VisitRes> {
+        self.comprehension(comp);
+        Ok(VisitRes::Walk)
+    }
+}
+
+impl<'script, 'meta> ImutExprVisitor<'script> for Registrar<'meta> {
+    fn visit_expr(&mut self, e: &mut ImutExprInt<'script>) -> Result<VisitRes> {
+        self.line(e);
+        Ok(VisitRes::Walk)
+    }
+    fn visit_predicate_clause(
+        &mut self,
+        predicate: &mut PredicateClause<'script, ImutExprInt<'script>>,
+    ) -> Result<VisitRes> {
+        self.arm(&predicate.last_expr);
+        Ok(VisitRes::Walk)
+    }
+    fn visit_clause_group(
+        &mut self,
+        group: &mut ClauseGroup<'script, ImutExprInt<'script>>,
+    ) -> Result<VisitRes> {
+        self.clause_group(group);
+        Ok(VisitRes::Walk)
+    }
+    fn visit_default_case(
+        &mut self,
+        default: &mut DefaultCase<ImutExprInt<'script>>,
+    ) -> Result<VisitRes> {
+        self.default_case(default);
+        Ok(VisitRes::Walk)
+    }
+    fn visit_comprehension(
+        &mut self,
+        comp: &mut Comprehension<'script, ImutExprInt<'script>>,
+    ) -> Result<VisitRes> {
+        self.comprehension(comp);
+        Ok(VisitRes::Walk)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::path::ModulePath;
+    use crate::prelude::*;
+    use crate::{registry, AggrType, EventContext, Value};
+
+    #[test]
+    fn match_arms() -> Result<()> {
+        let src = r#"match event of
+  case 1 => "one"
+  case 2 => "two"
+  case _ => "other"
+end
+"#;
+        let reg = registry::registry();
+        let script = crate::Script::parse(
+            &ModulePath { mounts: vec![] },
+            "coverage.tremor",
+            src.to_string(),
+            &reg,
+        )?;
+        enable();
+        register(script.script.suffix())?;
+        let mut event = Value::from(1);
+        let mut state = Value::null();
+        let mut meta = Value::object();
+        let context = EventContext::new(0, None);
+        script.run(&context, AggrType::Emit, &mut event, &mut state, &mut meta)?;
+        let coverage = take();
+        assert!(!enabled());
+
+        let file = coverage
+            .files
+            .get("coverage.tremor")
+            .ok_or("no coverage recorded")?;
+        assert_eq!(
+            vec![1, 0, 0],
+            file.arms.values().copied().collect::<Vec<_>>()
+        );
+        assert!(file.lines.get(&1).copied().unwrap_or_default() > 0);
+        assert_eq!(Some(&0), file.lines.get(&3));
+        let lcov = coverage.to_lcov();
+        assert!(lcov.contains("SF:coverage.tremor\n"));
+        assert!(lcov.contains("BRF:3\nBRH:1\n"));
+        assert!(lcov.ends_with("end_of_record\n"));
+        Ok(())
+    }
+}
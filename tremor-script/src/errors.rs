@@ -177,8 +177,8 @@ impl ErrorKind {
             DoubleSubqueryStmt, EmptyInterpolation, EmptyScript, ExtraToken, Generic, Grok,
             InvalidAssign, InvalidBinary, InvalidBitshift, InvalidConst, InvalidDrop, InvalidEmit,
             InvalidExtractor, InvalidFloatLiteral, InvalidFn, InvalidHexLiteral, InvalidIntLiteral,
-            InvalidMod, InvalidRecur, InvalidToken, InvalidUnary, InvalidUtf8Sequence, Io,
-            JsonError, MergeTypeConflict, MissingEffectors, MissingFunction, MissingModule,
+            InvalidMod, InvalidRecur, InvalidSql, InvalidToken, InvalidUnary, InvalidUtf8Sequence,
+            Io, JsonError, MergeTypeConflict, MissingEffectors, MissingFunction, MissingModule,
             ModuleNotFound, Msg, NoClauseHit, NoConstsAllowed, NoEventReferencesAllowed,
             NoLocalsAllowed, NoObjectError, NotConstant, NotFound, Oops, ParseIntError,
             ParserError, PatchKeyExists, PreprocessorError, QueryNodeDuplicateName,
@@ -267,6 +267,7 @@ impl ErrorKind {
             | NotFound
             | ParseIntError(_)
             | ParserError(_)
+            | InvalidSql(_)
             | PreprocessorError(_)
             | UnexpectedEndOfStream
             | Utf8Error(_)
@@ -637,6 +638,10 @@ error_chain! {
             description("Parser user error")
                 display("Parser user error: {}", pos)
         }
+        InvalidSql(msg: String) {
+            description("Invalid SQL query")
+                display("Invalid SQL query: {}", msg)
+        }
         /*
          * Resolve / Assign path walking
          */
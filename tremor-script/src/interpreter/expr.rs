@@ -16,6 +16,7 @@ use super::{
     resolve, resolve_value, set_local_shadow, test_guard, test_predicate_expr, Env, ExecOpts,
     LocalStack, NULL,
 };
+use crate::coverage;
 use crate::errors::{
     err_need_obj, error_assign_array, error_assign_to_const, error_bad_key_err,
     error_invalid_assign_target, error_no_clause_hit, Result,
@@ -74,6 +75,9 @@ impl<'script> Expr<'script> {
         effectors: &'run [Expr<'event>],
         last_effector: &'run Expr<'event>,
     ) -> Result<Cont<'run, 'event>> {
+        if coverage::enabled() {
+            coverage::arm(env.meta, last_effector);
+        }
         for effector in effectors {
             demit!(effector.run(opts.without_result(), env, event, state, meta, local));
         }
@@ -283,7 +287,7 @@ impl<'script> Expr<'script> {
     }
 
     #[inline]
-    fn assign<'run, 'event>(
+    pub(crate) fn assign<'run, 'event>(
         &'run self,
         opts: ExecOpts,
         env: &'run Env<'run, 'event>,
@@ -469,6 +473,9 @@ impl<'script> Expr<'script> {
     where
         'script: 'event,
     {
+        if coverage::enabled() {
+            coverage::expr(env.meta, self);
+        }
         match self {
             Expr::Emit(expr) => match expr.borrow() {
                 EmitExpr {
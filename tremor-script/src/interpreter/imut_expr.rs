@@ -18,6 +18,7 @@ use crate::{
         ImutExprInt, Invoke, InvokeAggr, LocalPath, Match, Merge, Patch, Path, Recur, ReservedPath,
         Segment, UnaryExpr,
     },
+    coverage,
     errors::{
         error_bad_key, error_decreasing_range, error_invalid_unary, error_need_obj, error_need_str,
         error_no_clause_hit, error_oops, error_oops_err, Result,
@@ -143,6 +144,9 @@ impl<'script> ImutExprInt<'script> {
     where
         'script: 'event,
     {
+        if coverage::enabled() {
+            coverage::expr(env.meta, self);
+        }
         match self {
             ImutExprInt::String(s) => s.run(opts, env, event, state, meta, local).map(owned_val),
             ImutExprInt::Recur(Recur { exprs, argc, .. }) => {
@@ -340,6 +344,9 @@ impl<'script> ImutExprInt<'script> {
     where
         'script: 'event,
     {
+        if coverage::enabled() {
+            coverage::arm(env.meta, effector);
+        }
         effector.run(opts, env, event, state, meta, local)
     }
 
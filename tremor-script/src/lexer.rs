@@ -1044,6 +1044,35 @@ impl<'input> Preprocessor {
 
                     take_while!(next, Token::Whitespace(_), iter);
 
+                    //
+                    // use pipeline <ident> [as <alias>] ;
+                    //
+                    let subflow = match &next.value {
+                        Token::Ident(pipeline, _) if alias == "pipeline" => {
+                            Some(pipeline.to_string())
+                        }
+                        _ => None,
+                    };
+                    if let Some(pipeline) = subflow {
+                        alias = pipeline.clone();
+                        take_while!(next, Token::Whitespace(_), iter);
+                        if next.value == Token::As {
+                            take_while!(next, Token::Whitespace(_), iter);
+                            alias = as_ident(next)?.to_string();
+                            take_while!(next, Token::Whitespace(_), iter);
+                        }
+                        if next.value != Token::Semi {
+                            return Err(urt(&next, &["`as`", "`;`"]));
+                        }
+                        // embed the published pipeline as an operator, on the
+                        // same line to keep the locations of what follows
+                        input.push_str(&format!(
+                            "define pipeline::subflow operator {} with pipeline = \"{}\" end; create operator {};",
+                            alias, pipeline, alias
+                        ));
+                        continue;
+                    }
+
                     loop {
                         if next.value == Token::ColonColon {
                             // module path of the form:
@@ -2934,6 +2963,29 @@ mod tests {
         Ok(())
     }
 
+    #[test]
+    fn test_preprocessor_use_pipeline() -> Result<()> {
+        let mut src =
+            "use pipeline normalize as norm;\nselect event from in into norm;\n".to_string();
+        let mut include_stack = IncludeStack::default();
+        let tokens = Preprocessor::preprocess(
+            &ModulePath { mounts: vec![] },
+            "foo",
+            &mut src,
+            0,
+            &mut include_stack,
+        )?;
+        let mut res = String::new();
+        for t in tokens.into_iter().filter_map(Result::ok) {
+            res.push_str(&format!("{}", t.value));
+        }
+        assert_eq!(
+            "define pipeline::subflow operator norm with pipeline = \"normalize\" end; create operator norm;\nselect event from in into norm;\n",
+            res
+        );
+        Ok(())
+    }
+
     #[test]
     fn test_test_literal_format_bug_regression() -> Result<()> {
         let snot = "match %{ test ~= base64|| } of default => \"badger\" end ".to_string();
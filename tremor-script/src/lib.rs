@@ -34,6 +34,8 @@ extern crate pretty_assertions;
 /// The Tremor Script AST
 pub mod ast;
 mod compat;
+/// Coverage of scripts by tests
+pub mod coverage;
 /// Context struct for tremor-script
 pub mod ctx;
 mod datetime;
@@ -62,12 +64,16 @@ pub mod query;
 pub mod registry;
 /// Tremor Script
 pub mod script;
+/// SQL dialect translated into trickle
+pub mod sql;
 /// Self referential structs
 pub mod srs;
 mod std_lib;
 mod tilde;
 /// Utility functions
 pub mod utils;
+/// Bytecode backend
+pub mod vm;
 
 pub use srs::{EventPayload, ValueAndMeta};
 
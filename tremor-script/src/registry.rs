@@ -710,6 +710,11 @@ impl Registry {
     pub fn find_module(&self, module: &str) -> Option<&HashMap<String, TremorFnWrapper>> {
         self.functions.get(module)
     }
+
+    /// The names of the modules in the registry
+    pub fn modules(&self) -> impl Iterator<Item = &str> {
+        self.functions.keys().map(String::as_str)
+    }
 }
 
 /// Wrapper around an aggregate function
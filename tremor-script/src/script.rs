@@ -116,6 +116,12 @@ impl Script {
         })
     }
 
+    /// Compiles the script to bytecode, which `run` uses from then on instead
+    /// of interpreting it
+    pub fn compile(&mut self) {
+        self.script.compile();
+    }
+
     /// Returns the documentation for the script
     #[must_use]
     pub fn docs(&self) -> &Docs {
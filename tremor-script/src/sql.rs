@@ -0,0 +1,955 @@
+// Copyright 2020-2021, The Tremor Team
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+//! A constrained streaming SQL dialect that is translated into trickle.
+//!
+//! ```sql
+//! SELECT country, COUNT(*) AS requests, AVG(duration) AS duration
+//! FROM in
+//! WINDOW TUMBLING (INTERVAL 10 SECONDS)
+//! WHERE status <> 'ok'
+//! GROUP BY country
+//! HAVING requests > 100
+//! INTO out;
+//! ```
+//!
+//! Columns refer to fields of the event, `a.b` to nested ones. Only `FROM`
+//! is required, the stream read from and `INTO` the stream written to
+//! default to `in` and `out`. A `WINDOW` is either `TUMBLING (SIZE n)` or
+//! `TUMBLING (INTERVAL n <unit>)` and is required for aggregates. `HAVING`
+//! filters on the selected columns, by name or by repeating the aggregate.
+
+use crate::errors::{ErrorKind, Result};
+
+/// Name of the window defined for the `WINDOW` clause
+const WINDOW: &str = "sql_window";
+
+/// Words that can't be used as column names without quoting them
+const RESERVED: [&str; 20] = [
+    "SELECT", "FROM", "WINDOW", "WHERE", "GROUP", "BY", "HAVING", "INTO", "AS", "AND", "OR", "NOT",
+    "IS", "IN", "BETWEEN", "NULL", "TRUE", "FALSE", "TUMBLING", "LIMIT",
+];
+
+/// Supported SQL functions, the tremor function they translate to and if
+/// they are aggregates
+const FUNCTIONS: [(&str, &str, bool); 14] = [
+    ("COUNT", "aggr::stats::count", true),
+    ("SUM", "aggr::stats::sum", true),
+    ("AVG", "aggr::stats::mean", true),
+    ("MIN", "aggr::stats::min", true),
+    ("MAX", "aggr::stats::max", true),
+    ("FIRST", "aggr::win::first", true),
+    ("LAST", "aggr::win::last", true),
+    ("LOWER", "string::lowercase", false),
+    ("UPPER", "string::uppercase", false),
+    ("LENGTH", "string::len", false),
+    ("TRIM", "string::trim", false),
+    ("FLOOR", "math::floor", false),
+    ("CEIL", "math::ceil", false),
+    ("ROUND", "math::round", false),
+];
+
+const SYMBOLS: [&str; 18] = [
+    "<>", "!=", "<=", ">=", "||", "(", ")", ",", ".", "*", "+", "-", "/", "%", "=", "<", ">", ";",
+];
+
+/// Translates a SQL query into the trickle query it is equivalent to
+///
+/// # Errors
+/// if the query isn't valid or uses unsupported SQL
+pub fn to_trickle(sql: &str) -> Result<String> {
+    Parser::new(sql)?.select()?.translate()
+}
+
+fn invalid<T>(msg: String) -> Result<T> {
+    Err(ErrorKind::InvalidSql(msg).into())
+}
+
+fn is_reserved(word: &str) -> bool {
+    RESERVED.iter().any(|r| r.eq_ignore_ascii_case(word))
+}
+
+fn is_simple(name: &str) -> bool {
+    name.starts_with(|c: char| c.is_ascii_alphabetic() || c == '_')
+        && name.chars().all(|c| c.is_ascii_alphanumeric() || c == '_')
+}
+
+/// Quotes a string as a trickle string literal
+fn string(s: &str) -> String {
+    let mut res = String::with_capacity(s.len() + 2);
+    res.push('"');
+    for c in s.chars() {
+        match c {
+            '"' => res.push_str("\\\""),
+            '\\' => res.push_str("\\\\"),
+            '#' => res.push_str("\\#"),
+            '\n' => res.push_str("\\n"),
+            '\r' => res.push_str("\\r"),
+            '\t' => res.push_str("\\t"),
+            c => res.push(c),
+        }
+    }
+    res.push('"');
+    res
+}
+
+#[derive(Debug, Clone, PartialEq)]
+enum Token {
+    Word(String),
+    Quoted(String),
+    Str(String),
+    Num(String),
+    Sym(&'static str),
+}
+
+#[derive(Debug)]
+struct Lexeme {
+    token: Token,
+    line: usize,
+    column: usize,
+}
+
+fn tokenize(sql: &str) -> Result<(Vec<Lexeme>, (usize, usize))> {
+    let chars: Vec<char> = sql.chars().collect();
+    let mut tokens = Vec::new();
+    let (mut line, mut column) = (1, 1);
+    let mut i = 0;
+    while let Some(&c) = chars.get(i) {
+        let start = i;
+        let token = if c.is_whitespace() {
+            i += 1;
+            None
+        } else if c == '-' && chars.get(i + 1) == Some(&'-') {
+            while chars.get(i).map_or(false, |c| *c != '\n') {
+                i += 1;
+            }
+            None
+        } else if c.is_ascii_alphabetic() || c == '_' {
+            while chars
+                .get(i)
+                .map_or(false, |c| c.is_ascii_alphanumeric() || *c == '_')
+            {
+                i += 1;
+            }
+            Some(Token::Word(chars[start..i].iter().collect()))
+        } else if c.is_ascii_digit() {
+            while chars.get(i).map_or(false, char::is_ascii_digit) {
+                i += 1;
+            }
+            if chars.get(i) == Some(&'.') && chars.get(i + 1).map_or(false, char::is_ascii_digit) {
+                i += 1;
+                while chars.get(i).map_or(false, char::is_ascii_digit) {
+                    i += 1;
+                }
+            }
+            Some(Token::Num(chars[start..i].iter().collect()))
+        } else if c == '\'' || c == '"' {
+            // quotes are escaped by doubling them
+            let mut s = String::new();
+            i += 1;
+            loop {
+                match chars.get(i) {
+                    None => {
+                        return invalid(format!(
+                            "line {}, column {}: unterminated {}",
+                            line,
+                            column,
+                            if c == '"' { "identifier" } else { "string" }
+                        ))
+                    }
+                    Some(q) if *q == c && chars.get(i + 1) == Some(&c) => {
+                        s.push(c);
+                        i += 2;
+                    }
+                    Some(q) if *q == c => {
+                        i += 1;
+                        break;
+                    }
+                    Some(other) => {
+                        s.push(*other);
+                        i += 1;
+                    }
+                }
+            }
+            Some(if c == '"' {
+                Token::Quoted(s)
+            } else {
+                Token::Str(s)
+            })
+        } else if let Some(sym) = SYMBOLS.iter().find(|sym| {
+            sym.chars()
+                .enumerate()
+                .all(|(j, s)| chars.get(i + j) == Some(&s))
+        }) {
+            i += sym.len();
+            Some(Token::Sym(*sym))
+        } else {
+            return invalid(format!(
+                "line {}, column {}: unexpected character `{}`",
+                line, column, c
+            ));
+        };
+        if let Some(token) = token {
+            tokens.push(Lexeme {
+                token,
+                line,
+                column,
+            });
+        }
+        for c in &chars[start..i] {
+            if *c == '\n' {
+                line += 1;
+                column = 1;
+            } else {
+                column += 1;
+            }
+        }
+    }
+    Ok((tokens, (line, column)))
+}
+
+#[derive(Debug, Clone, PartialEq)]
+enum Expr {
+    /// Path of a field in the event
+    Column(Vec<String>),
+    /// A trickle literal
+    Literal(String),
+    Unary(&'static str, Box<Expr>),
+    Binary(&'static str, Box<Expr>, Box<Expr>),
+    /// Call of one of the `FUNCTIONS` by its SQL name
+    Call(&'static str, Vec<Expr>),
+}
+
+fn binary(op: &'static str, lhs: Expr, rhs: Expr) -> Expr {
+    Expr::Binary(op, Box::new(lhs), Box::new(rhs))
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum Window {
+    Size(u64),
+    Interval(u64),
+}
+
+#[derive(Debug)]
+struct Select {
+    /// Selected columns and their names, `None` for `SELECT *`
+    items: Option<Vec<(String, Expr)>>,
+    from: String,
+    window: Option<Window>,
+    filter: Option<Expr>,
+    groups: Vec<Expr>,
+    having: Option<Expr>,
+    into: String,
+}
+
+/// Where an expression is translated
+#[derive(Clone, Copy)]
+enum Scope<'a> {
+    /// Evaluated on each event, aggregates aren't allowed in the named clause
+    Event(&'static str),
+    /// The select, expressions of the `GROUP BY` refer to the group
+    Select(&'a [Expr]),
+    /// `HAVING`, evaluated on the selected columns
+    Having(&'a [(String, Expr)]),
+}
+
+struct Parser {
+    tokens: Vec<Lexeme>,
+    pos: usize,
+    end: (usize, usize),
+}
+
+impl Parser {
+    fn new(sql: &str) -> Result<Self> {
+        let (tokens, end) = tokenize(sql)?;
+        Ok(Self {
+            tokens,
+            pos: 0,
+            end,
+        })
+    }
+
+    fn peek(&self) -> Option<&Token> {
+        self.tokens.get(self.pos).map(|l| &l.token)
+    }
+
+    fn error<T>(&self, msg: &str) -> Result<T> {
+        let (line, column) = self
+            .tokens
+            .get(self.pos)
+            .map_or(self.end, |l| (l.line, l.column));
+        invalid(format!("line {}, column {}: {}", line, column, msg))
+    }
+
+    fn keyword(&mut self, kw: &str) -> bool {
+        let found = matches!(self.peek(), Some(Token::Word(w)) if w.eq_ignore_ascii_case(kw));
+        if found {
+            self.pos += 1;
+        }
+        found
+    }
+
+    fn expect_keyword(&mut self, kw: &str) -> Result<()> {
+        if self.keyword(kw) {
+            Ok(())
+        } else {
+            self.error(&format!("expected `{}`", kw))
+        }
+    }
+
+    fn symbol(&mut self, sym: &str) -> bool {
+        let found = matches!(self.peek(), Some(Token::Sym(s)) if *s == sym);
+        if found {
+            self.pos += 1;
+        }
+        found
+    }
+
+    fn expect_symbol(&mut self, sym: &str) -> Result<()> {
+        if self.symbol(sym) {
+            Ok(())
+        } else {
+            self.error(&format!("expected `{}`", sym))
+        }
+    }
+
+    fn ident(&mut self) -> Result<String> {
+        match self.peek() {
+            Some(Token::Word(w)) if !is_reserved(w) => {
+                let w = w.clone();
+                self.pos += 1;
+                Ok(w)
+            }
+            Some(Token::Quoted(q)) => {
+                let q = q.clone();
+                self.pos += 1;
+                Ok(q)
+            }
+            _ => self.error("expected a name"),
+        }
+    }
+
+    /// Name of a stream, `in` and `out` are allowed even though `IN` is reserved
+    fn stream(&mut self) -> Result<String> {
+        match self.peek() {
+            Some(Token::Word(w)) => {
+                let w = w.clone();
+                self.pos += 1;
+                Ok(w)
+            }
+            _ => self.error("expected a stream name"),
+        }
+    }
+
+    fn integer(&mut self) -> Result<u64> {
+        match self.peek().and_then(|t| match t {
+            Token::Num(n) => n.parse().ok(),
+            _ => None,
+        }) {
+            Some(n) => {
+                self.pos += 1;
+                Ok(n)
+            }
+            None => self.error("expected an integer"),
+        }
+    }
+
+    fn select(&mut self) -> Result<Select> {
+        self.expect_keyword("SELECT")?;
+        let items = if self.symbol("*") {
+            None
+        } else {
+            let mut items: Vec<(String, Expr)> = Vec::new();
+            loop {
+                let start = self.pos;
+                let expr = self.expr()?;
+                let name = if self.keyword("AS") {
+                    self.ident()?
+                } else {
+                    match &expr {
+                        Expr::Column(path) => path.last().cloned().unwrap_or_default(),
+                        Expr::Call(f, _) => f.to_lowercase(),
+                        _ => {
+                            self.pos = start;
+                            return self.error("name this column with `AS <name>`");
+                        }
+                    }
+                };
+                if items.iter().any(|(n, _)| *n == name) {
+                    return self.error(&format!("column `{}` is selected twice", name));
+                }
+                items.push((name, expr));
+                if !self.symbol(",") {
+                    break;
+                }
+            }
+            Some(items)
+        };
+        self.expect_keyword("FROM")?;
+        let from = self.stream()?;
+        let window = if self.keyword("WINDOW") {
+            Some(self.window()?)
+        } else {
+            None
+        };
+        let filter = if self.keyword("WHERE") {
+            Some(self.expr()?)
+        } else {
+            None
+        };
+        let mut groups = Vec::new();
+        if self.keyword("GROUP") {
+            self.expect_keyword("BY")?;
+            loop {
+                groups.push(self.expr()?);
+                if !self.symbol(",") {
+                    break;
+                }
+            }
+        }
+        let having = if self.keyword("HAVING") {
+            Some(self.expr()?)
+        } else {
+            None
+        };
+        let into = if self.keyword("INTO") {
+            self.stream()?
+        } else {
+            "out".to_string()
+        };
+        self.symbol(";");
+        if self.peek().is_some() {
+            return self.error("unexpected input after the end of the query");
+        }
+        Ok(Select {
+            items,
+            from,
+            window,
+            filter,
+            groups,
+            having,
+            into,
+        })
+    }
+
+    fn window(&mut self) -> Result<Window> {
+        self.expect_keyword("TUMBLING")?;
+        self.expect_symbol("(")?;
+        let window = if self.keyword("SIZE") {
+            Window::Size(self.integer()?)
+        } else if self.keyword("INTERVAL") {
+            let n = self.integer()?;
+            let unit = match self.peek() {
+                Some(Token::Word(w)) => w.to_ascii_uppercase(),
+                _ => return self.error("expected a time unit"),
+            };
+            let ns = match unit.trim_end_matches('S') {
+                "NANOSECOND" => 1,
+                "MILLISECOND" => 1_000_000,
+                "SECOND" => 1_000_000_000,
+                "MINUTE" => 60_000_000_000,
+                "HOUR" => 3_600_000_000_000,
+                _ => return self.error("expected one of `NANOSECONDS`, `MILLISECONDS`, `SECONDS`, `MINUTES` or `HOURS`"),
+            };
+            self.pos += 1;
+            match n.checked_mul(ns) {
+                Some(interval) => Window::Interval(interval),
+                None => return self.error("interval is too large"),
+            }
+        } else {
+            return self.error("expected `SIZE` or `INTERVAL`");
+        };
+        self.expect_symbol(")")?;
+        Ok(window)
+    }
+
+    fn expr(&mut self) -> Result<Expr> {
+        let mut lhs = self.and()?;
+        while self.keyword("OR") {
+            lhs = binary("or", lhs, self.and()?);
+        }
+        Ok(lhs)
+    }
+
+    fn and(&mut self) -> Result<Expr> {
+        let mut lhs = self.not()?;
+        while self.keyword("AND") {
+            lhs = binary("and", lhs, self.not()?);
+        }
+        Ok(lhs)
+    }
+
+    fn not(&mut self) -> Result<Expr> {
+        if self.keyword("NOT") {
+            Ok(Expr::Unary("not ", Box::new(self.not()?)))
+        } else {
+            self.comparison()
+        }
+    }
+
+    fn comparison(&mut self) -> Result<Expr> {
+        const OPS: [(&str, &str); 7] = [
+            ("=", "=="),
+            ("<>", "!="),
+            ("!=", "!="),
+            ("<=", "<="),
+            (">=", ">="),
+            ("<", "<"),
+            (">", ">"),
+        ];
+        let lhs = self.additive()?;
+        for (sym, op) in OPS {
+            if self.symbol(sym) {
+                return Ok(binary(op, lhs, self.additive()?));
+            }
+        }
+        if self.keyword("IS") {
+            let op = if self.keyword("NOT") { "!=" } else { "==" };
+            self.expect_keyword("NULL")?;
+            return Ok(binary(op, lhs, Expr::Literal("null".to_string())));
+        }
+        let negated = self.keyword("NOT");
+        let expr = if self.keyword("IN") {
+            self.expect_symbol("(")?;
+            let mut expr = binary("==", lhs.clone(), self.additive()?);
+            while self.symbol(",") {
+                expr = binary("or", expr, binary("==", lhs.clone(), self.additive()?));
+            }
+            self.expect_symbol(")")?;
+            expr
+        } else if self.keyword("BETWEEN") {
+            let low = binary(">=", lhs.clone(), self.additive()?);
+            self.expect_keyword("AND")?;
+            binary("and", low, binary("<=", lhs, self.additive()?))
+        } else if negated {
+            return self.error("expected `IN` or `BETWEEN`");
+        } else {
+            return Ok(lhs);
+        };
+        Ok(if negated {
+            Expr::Unary("not ", Box::new(expr))
+        } else {
+            expr
+        })
+    }
+
+    fn additive(&mut self) -> Result<Expr> {
+        let mut lhs = self.multiplicative()?;
+        loop {
+            // `||` concatenates strings which is `+` in trickle
+            let op = if self.symbol("+") || self.symbol("||") {
+                "+"
+            } else if self.symbol("-") {
+                "-"
+            } else {
+                return Ok(lhs);
+            };
+            lhs = binary(op, lhs, self.multiplicative()?);
+        }
+    }
+
+    fn multiplicative(&mut self) -> Result<Expr> {
+        let mut lhs = self.unary()?;
+        loop {
+            let op = if self.symbol("*") {
+                "*"
+            } else if self.symbol("/") {
+                "/"
+            } else if self.symbol("%") {
+                "%"
+            } else {
+                return Ok(lhs);
+            };
+            lhs = binary(op, lhs, self.unary()?);
+        }
+    }
+
+    fn unary(&mut self) -> Result<Expr> {
+        if self.symbol("-") {
+            Ok(Expr::Unary("-", Box::new(self.unary()?)))
+        } else {
+            self.primary()
+        }
+    }
+
+    fn primary(&mut self) -> Result<Expr> {
+        let token = match self.peek() {
+            Some(token) => token.clone(),
+            None => return self.error("expected an expression"),
+        };
+        match token {
+            Token::Num(n) => {
+                self.pos += 1;
+                Ok(Expr::Literal(n))
+            }
+            Token::Str(s) => {
+                self.pos += 1;
+                Ok(Expr::Literal(string(&s)))
+            }
+            Token::Sym("(") => {
+                self.pos += 1;
+                let expr = self.expr()?;
+                self.expect_symbol(")")?;
+                Ok(expr)
+            }
+            Token::Word(w)
+                if ["NULL", "TRUE", "FALSE"]
+                    .iter()
+                    .any(|l| l.eq_ignore_ascii_case(&w)) =>
+            {
+                self.pos += 1;
+                Ok(Expr::Literal(w.to_ascii_lowercase()))
+            }
+            Token::Word(w)
+                if matches!(
+                    self.tokens.get(self.pos + 1),
+                    Some(Lexeme {
+                        token: Token::Sym("("),
+                        ..
+                    })
+                ) =>
+            {
+                self.call(&w)
+            }
+            Token::Word(_) | Token::Quoted(_) => {
+                let mut path = vec![self.ident()?];
+                while self.symbol(".") {
+                    path.push(self.ident()?);
+                }
+                Ok(Expr::Column(path))
+            }
+            _ => self.error("expected an expression"),
+        }
+    }
+
+    fn call(&mut self, name: &str) -> Result<Expr> {
+        let name = match FUNCTIONS
+            .iter()
+            .find(|(f, _, _)| f.eq_ignore_ascii_case(name))
+        {
+            Some((f, _, _)) => *f,
+            None => {
+                let supported: Vec<_> = FUNCTIONS.iter().map(|(f, _, _)| *f).collect();
+                return self.error(&format!(
+                    "unknown function `{}`, supported are {}",
+                    name,
+                    supported.join(", ")
+                ));
+            }
+        };
+        // name and opening parenthesis
+        self.pos += 2;
+        let args = if name == "COUNT" {
+            self.expect_symbol("*")?;
+            vec![]
+        } else {
+            vec![self.expr()?]
+        };
+        self.expect_symbol(")")?;
+        Ok(Expr::Call(name, args))
+    }
+}
+
+impl Select {
+    fn translate(&self) -> Result<String> {
+        let mut lines = Vec::new();
+        let from = if let Some(window) = self.window {
+            let (key, value) = match window {
+                Window::Size(size) => ("size", size),
+                Window::Interval(interval) => ("interval", interval),
+            };
+            lines.push(format!("define tumbling window {}", WINDOW));
+            lines.push("with".to_string());
+            lines.push(format!("  {} = {}", key, value));
+            lines.push("end;".to_string());
+            lines.push(String::new());
+            format!("{}[{}]", self.from, WINDOW)
+        } else {
+            self.from.clone()
+        };
+        for stream in [&self.from, &self.into] {
+            if !is_simple(stream) {
+                return invalid(format!("`{}` is not a valid stream name", stream));
+            }
+        }
+        if let Some(items) = &self.items {
+            lines.push("select {".to_string());
+            let last = items.len().saturating_sub(1);
+            for (i, (name, expr)) in items.iter().enumerate() {
+                lines.push(format!(
+                    "  {}: {}{}",
+                    string(name),
+                    self.emit(expr, &Scope::Select(&self.groups))?,
+                    if i == last { "" } else { "," }
+                ));
+            }
+            lines.push("}".to_string());
+        } else if self.window.is_some() || !self.groups.is_empty() {
+            return invalid("`SELECT *` can not be used with `WINDOW` or `GROUP BY`".to_string());
+        } else {
+            lines.push("select event".to_string());
+        }
+        lines.push(format!("from {}", from));
+        if let Some(filter) = &self.filter {
+            lines.push(format!(
+                "where {}",
+                self.emit(filter, &Scope::Event("WHERE"))?
+            ));
+        }
+        if !self.groups.is_empty() {
+            let groups = self
+                .groups
+                .iter()
+                .map(|g| self.emit(g, &Scope::Event("GROUP BY")))
+                .collect::<Result<Vec<_>>>()?;
+            lines.push(format!("group by set({})", groups.join(", ")));
+        }
+        if let Some(having) = &self.having {
+            // with `SELECT *` the selected columns are the ones of the event
+            let scope = self
+                .items
+                .as_ref()
+                .map_or(Scope::Event("HAVING"), |items| Scope::Having(items));
+            lines.push(format!("having {}", self.emit(having, &scope)?));
+        }
+        lines.push(format!("into {};", self.into));
+        lines.push(String::new());
+        Ok(lines.join("\n"))
+    }
+
+    fn emit(&self, expr: &Expr, scope: &Scope) -> Result<String> {
+        match scope {
+            Scope::Select(groups) => {
+                if let Some(i) = groups.iter().position(|g| g == expr) {
+                    return Ok(format!("group[{}]", i));
+                }
+            }
+            Scope::Having(items) => match expr {
+                Expr::Column(path) => {
+                    return match items
+                        .iter()
+                        .find(|(name, _)| path.len() == 1 && path[0] == *name)
+                    {
+                        Some((name, _)) => Ok(format!("event[{}]", string(name))),
+                        None => invalid(format!(
+                            "`HAVING` can only use selected columns, `{}` isn't selected",
+                            path.join(".")
+                        )),
+                    };
+                }
+                Expr::Call(f, _) if is_aggregate(f) => {
+                    return match items.iter().find(|(_, e)| e == expr) {
+                        Some((name, _)) => Ok(format!("event[{}]", string(name))),
+                        None => invalid(format!(
+                            "aggregate `{}` in `HAVING` has to be selected as well",
+                            f
+                        )),
+                    };
+                }
+                _ => (),
+            },
+            Scope::Event(_) => (),
+        }
+        Ok(match expr {
+            Expr::Column(path) => {
+                let mut res = "event".to_string();
+                for segment in path {
+                    res.push('[');
+                    res.push_str(&string(segment));
+                    res.push(']');
+                }
+                res
+            }
+            Expr::Literal(l) => l.clone(),
+            Expr::Unary(op, expr) => format!("{}{}", op, self.emit(expr, scope)?),
+            Expr::Binary(op, lhs, rhs) => format!(
+                "({} {} {})",
+                self.emit(lhs, scope)?,
+                op,
+                self.emit(rhs, scope)?
+            ),
+            Expr::Call(f, args) => {
+                let args_scope = if is_aggregate(f) {
+                    match scope {
+                        Scope::Event(clause) => {
+                            return invalid(format!(
+                                "aggregate `{}` can not be used in {}",
+                                f, clause
+                            ))
+                        }
+                        _ if self.window.is_none() => {
+                            return invalid(format!("aggregate `{}` requires a `WINDOW`", f))
+                        }
+                        _ => Scope::Event("an aggregate"),
+                    }
+                } else {
+                    *scope
+                };
+                let args = args
+                    .iter()
+                    .map(|a| self.emit(a, &args_scope))
+                    .collect::<Result<Vec<_>>>()?;
+                format!("{}({})", tremor_fn(f), args.join(", "))
+            }
+        })
+    }
+}
+
+fn is_aggregate(f: &str) -> bool {
+    FUNCTIONS.iter().any(|(name, _, aggr)| *aggr && *name == f)
+}
+
+fn tremor_fn(f: &str) -> &'static str {
+    FUNCTIONS
+        .iter()
+        .find(|(name, _, _)| *name == f)
+        .map_or("", |(_, tremor, _)| *tremor)
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    fn parse(sql: &str) -> String {
+        let trickle = match to_trickle(sql) {
+            Ok(trickle) => trickle,
+            Err(e) => panic!("{}", e),
+        };
+        let reg = crate::registry();
+        let aggr_reg = crate::aggr_registry();
+        let module_path = crate::path::load();
+        if let Err(e) =
+            crate::Query::parse(&module_path, "test.sql", &trickle, vec![], &reg, &aggr_reg)
+        {
+            panic!("{}\n{}", trickle, e.error());
+        }
+        trickle
+    }
+
+    fn error(sql: &str) -> String {
+        match to_trickle(sql) {
+            Ok(trickle) => panic!("expected an error, got:\n{}", trickle),
+            Err(e) => e.to_string(),
+        }
+    }
+
+    #[test]
+    fn filter() {
+        assert_eq!(
+            parse("select * from in where a.b = 'x' and not (c > 1.5 or d is null)"),
+            r#"select event
+from in
+where ((event["a"]["b"] == "x") and not ((event["c"] > 1.5) or (event["d"] == null)))
+into out;
+"#
+        );
+        assert_eq!(
+            parse("SELECT UPPER(name) AS name, \"a b\" FROM in WHERE c IN (1, 2) INTO err;"),
+            r#"select {
+  "name": string::uppercase(event["name"]),
+  "a b": event["a b"]
+}
+from in
+where ((event["c"] == 1) or (event["c"] == 2))
+into err;
+"#
+        );
+    }
+
+    #[test]
+    fn aggregate() {
+        assert_eq!(
+            parse(
+                "SELECT country, COUNT(*), AVG(duration) AS duration
+                 FROM in WINDOW TUMBLING (INTERVAL 10 SECONDS)
+                 WHERE status <> 'ok' -- only failures
+                 GROUP BY country
+                 HAVING count > 100 OR AVG(duration) BETWEEN 1 AND 2"
+            ),
+            r#"define tumbling window sql_window
+with
+  interval = 10000000000
+end;
+
+select {
+  "country": group[0],
+  "count": aggr::stats::count(),
+  "duration": aggr::stats::mean(event["duration"])
+}
+from in[sql_window]
+where (event["status"] != "ok")
+group by set(event["country"])
+having ((event["count"] > 100) or ((event["duration"] >= 1) and (event["duration"] <= 2)))
+into out;
+"#
+        );
+        assert_eq!(
+            parse("select sum(a) as total from in window tumbling (size 2)"),
+            r#"define tumbling window sql_window
+with
+  size = 2
+end;
+
+select {
+  "total": aggr::stats::sum(event["a"])
+}
+from in[sql_window]
+into out;
+"#
+        );
+    }
+
+    #[test]
+    fn strings() {
+        assert_eq!(string("it's \"#{x}\""), r#""it's \"\#{x}\"""#);
+        assert_eq!(
+            parse("select 'it''s' || a as a from in"),
+            "select {\n  \"a\": (\"it's\" + event[\"a\"])\n}\nfrom in\ninto out;\n"
+        );
+    }
+
+    #[test]
+    fn errors() {
+        assert_eq!(
+            error("select a from in where"),
+            "Invalid SQL query: line 1, column 23: expected an expression"
+        );
+        assert_eq!(
+            error("select a + 1 from in"),
+            "Invalid SQL query: line 1, column 8: name this column with `AS <name>`"
+        );
+        assert_eq!(
+            error("select a from in\nwhere b = 'x"),
+            "Invalid SQL query: line 2, column 11: unterminated string"
+        );
+        assert_eq!(
+            error("select count(*) from in"),
+            "Invalid SQL query: aggregate `COUNT` requires a `WINDOW`"
+        );
+        assert_eq!(
+            error("select a from in window tumbling (size 2) where sum(a) > 1"),
+            "Invalid SQL query: aggregate `SUM` can not be used in WHERE"
+        );
+        assert_eq!(
+            error("select sum(max(a)) as a from in window tumbling (size 2)"),
+            "Invalid SQL query: aggregate `MAX` can not be used in an aggregate"
+        );
+        assert_eq!(
+            error("select a from in having b > 1"),
+            "Invalid SQL query: `HAVING` can only use selected columns, `b` isn't selected"
+        );
+        assert!(error("select median(a) from in").contains("unknown function `median`"));
+        assert!(error("select * from in window tumbling (size 2)").contains("`SELECT *`"));
+    }
+}
@@ -51,6 +51,10 @@ impl Script {
     pub fn suffix(&self) -> &ast::Script {
         &self.script
     }
+    /// Compiles the script to bytecode
+    pub fn compile(&mut self) {
+        self.script.compile();
+    }
     /// Creates a new Payload with a given byte vector and
     /// a function to turn it into a value and metadata set.
     ///
@@ -268,6 +272,10 @@ impl ScriptDecl {
     pub fn raw(&self) -> &[Arc<Pin<Vec<u8>>>] {
         &self.raw
     }
+    /// Compiles the script to bytecode
+    pub fn compile(&mut self) {
+        self.script.script.compile();
+    }
     /// Creates a new decl from a statement
     ///
     /// # Errors
@@ -398,6 +406,10 @@ impl Select {
 ///   - Each Pin is in a Arc so we can clone the data without with both clones
 ///     still pointing to the underlying pin.
 ///
+/// The value and metadata are shared between clones as well and only copied
+/// the first time a clone is mutated (copy on write). This way fanning an
+/// event out to multiple branches is cheap as long as they only read it.
+///
 /// It is essential to never access the parts of the struct outside of it's
 /// implementation! This will void all warenties and likely lead to errors.
 ///
@@ -408,7 +420,7 @@ impl Select {
 pub struct EventPayload {
     /// The vector of raw input values
     raw: Vec<Arc<Pin<Vec<u8>>>>,
-    data: ValueAndMeta<'static>,
+    data: Arc<ValueAndMeta<'static>>,
 }
 
 #[cfg(not(tarpaulin_include))] // this is a simple Debug implementation
@@ -458,7 +470,7 @@ impl EventPayload {
         let raw = vec![Arc::new(raw)];
         Self {
             raw,
-            data: structured,
+            data: Arc::new(structured),
         }
     }
 
@@ -485,7 +497,7 @@ impl EventPayload {
         let raw = vec![Arc::new(raw)];
         Ok(Self {
             raw,
-            data: structured,
+            data: Arc::new(structured),
         })
     }
 
@@ -514,7 +526,9 @@ impl EventPayload {
         // they don't control the owner here.
         f(unsafe {
             // ALLOW: See above explenation
-            mem::transmute::<&'iref ValueAndMeta<'static>, &'iref ValueAndMeta<'iref>>(&self.data)
+            mem::transmute::<&'iref ValueAndMeta<'static>, &'iref ValueAndMeta<'iref>>(
+                self.data.as_ref(),
+            )
         })
     }
 
@@ -543,7 +557,7 @@ impl EventPayload {
         f(unsafe {
             // ALLOW: See above explenation
             mem::transmute::<&'iref mut ValueAndMeta<'static>, &'iref mut ValueAndMeta<'iref>>(
-                &mut self.data,
+                self.data_mut(),
             )
         })
     }
@@ -565,7 +579,7 @@ impl EventPayload {
     where
         'borrow: 'value,
     {
-        let ValueAndMeta { ref v, ref m } = self.data;
+        let ValueAndMeta { ref v, ref m } = *self.data;
         (v, m)
     }
 
@@ -618,11 +632,13 @@ impl EventPayload {
             unsafe {
                 // ALLOW: See above for explenation
                 mem::transmute::<&'iref mut ValueAndMeta<'static>, &'iref mut ValueAndMeta<'iref>>(
-                    &mut self.data,
+                    self.data_mut(),
                 )
             },
             // ALLOW: See above for explenation
-            unsafe { mem::transmute::<ValueAndMeta<'static>, ValueAndMeta<'iref>>(other.data) },
+            unsafe {
+                mem::transmute::<ValueAndMeta<'static>, ValueAndMeta<'iref>>(other.take_data())
+            },
         )
     }
 
@@ -645,7 +661,7 @@ impl EventPayload {
 
         // We can access `other.script` here with it's static lifetime since we did clone the `raw`
         // into our own `raw` before. This equalizes `iref` and `head` for `self` and `other`
-        apply_f(&mut self.data, &other.script)
+        apply_f(self.data_mut(), &other.script)
     }
 
     /// Applies another SRS into this, this functions **needs** to
@@ -664,7 +680,7 @@ impl EventPayload {
 
         // We can access `other.script` here with it's static lifetime since we did clone the `raw`
         // into our own `raw` before. This equalizes `iref` and `head` for `self` and `other`
-        apply_f(&mut self.data, &other.script)
+        apply_f(self.data_mut(), &other.script)
     }
 
     /// Applies another SRS into this, this functions **needs** to
@@ -686,7 +702,26 @@ impl EventPayload {
 
         // We can access `other.script` here with it's static lifetime since we did clone the `raw`
         // into our own `raw` before. This equalizes `iref` and `head` for `self` and `other`
-        apply_f(&mut self.data, &mut other.select)
+        apply_f(self.data_mut(), &mut other.select)
+    }
+
+    /// Returns true if the value and metadata are shared with a clone
+    /// of this payload and would be copied on the next mutation.
+    #[must_use]
+    pub fn is_shared(&self) -> bool {
+        Arc::strong_count(&self.data) > 1
+    }
+
+    /// Mutable access to the data, copying it first if it is shared
+    /// with another clone.
+    fn data_mut(&mut self) -> &mut ValueAndMeta<'static> {
+        Arc::make_mut(&mut self.data)
+    }
+
+    /// Takes the data out of the payload, copying it if it is shared
+    /// with another clone.
+    fn take_data(&mut self) -> ValueAndMeta<'static> {
+        mem::take(self.data_mut())
     }
 }
 
@@ -697,7 +732,7 @@ where
     fn from(vm: T) -> Self {
         Self {
             raw: Vec::new(),
-            data: vm.into(),
+            data: Arc::new(vm.into()),
         }
     }
 }
@@ -812,3 +847,34 @@ where
         }
     }
 }
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn clone_on_write() {
+        let vec = br#"{"key": "value"}"#.to_vec();
+        let e1 = EventPayload::new(vec, |d| {
+            tremor_value::parse_to_value(d)
+                .expect("invalid json")
+                .into()
+        });
+        let mut e2 = e1.clone();
+        assert!(e1.is_shared());
+        assert!(e2.is_shared());
+
+        e2.rent_mut(|d| {
+            d.value_mut().try_insert("snot", "badger");
+        });
+        assert!(!e1.is_shared());
+        assert!(!e2.is_shared());
+
+        let (v1, _) = e1.parts();
+        let (v2, _) = e2.parts();
+        assert_eq!(v1.get_str("key"), Some("value"));
+        assert!(v1.get("snot").is_none());
+        assert_eq!(v2.get_str("key"), Some("value"));
+        assert_eq!(v2.get_str("snot"), Some("badger"));
+    }
+}
@@ -23,11 +23,14 @@ mod array;
 mod base64;
 mod binary;
 mod chash;
+mod coerce;
+mod crypto;
 mod datetime;
 mod dummy;
 mod float;
 mod integer;
 mod json;
+mod jwt;
 mod math;
 mod origin;
 mod path;
@@ -38,6 +41,7 @@ mod record;
 mod stats;
 mod string;
 mod system;
+mod template;
 mod test;
 mod r#type;
 mod url;
@@ -50,11 +54,14 @@ pub fn load(registry: &mut Registry) {
     base64::load(registry);
     binary::load(registry);
     chash::load(registry);
+    coerce::load(registry);
+    crypto::load(registry);
     datetime::load(registry);
     dummy::load(registry);
     float::load(registry);
     integer::load(registry);
     json::load(registry);
+    jwt::load(registry);
     math::load(registry);
     origin::load(registry);
     random::load(registry);
@@ -63,6 +70,7 @@ pub fn load(registry: &mut Registry) {
     record::load(registry);
     string::load(registry);
     system::load(registry);
+    template::load(registry);
     test::load(registry);
     r#type::load(registry);
     url::load(registry);
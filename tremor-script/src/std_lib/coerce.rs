@@ -0,0 +1,409 @@
+// Copyright 2020-2021, The Tremor Team
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+use crate::prelude::*;
+use crate::registry::Registry;
+use crate::tremor_const_fn;
+use chrono::{DateTime, NaiveDate, NaiveDateTime};
+use std::convert::TryFrom;
+use tremor_value::literal;
+
+type CoerceResult<T> = std::result::Result<T, String>;
+
+const COMMA: &[char] = &[','];
+const DOT: &[char] = &['.'];
+const SPACE: &[char] = &[' ', '\u{a0}', '\u{202f}'];
+const APOSTROPHE: &[char] = &['\'', '\u{2019}'];
+
+/// Timestamp formats with an offset, tried after RFC 3339 and RFC 2822
+const WITH_OFFSET: [&str; 3] = [
+    "%d/%b/%Y:%H:%M:%S %z",
+    "%Y-%m-%d %H:%M:%S%.f %z",
+    "%Y-%m-%d %H:%M:%S%.f%:z",
+];
+/// Timestamp formats without an offset, taken as UTC
+const WITHOUT_OFFSET: [&str; 4] = [
+    "%Y-%m-%dT%H:%M:%S%.f",
+    "%Y-%m-%d %H:%M:%S%.f",
+    "%Y/%m/%d %H:%M:%S%.f",
+    "%d/%b/%Y:%H:%M:%S",
+];
+const DATES: [&str; 2] = ["%Y-%m-%d", "%Y/%m/%d"];
+
+/// Thousands separators and decimal mark of a locale like `en`, `de-DE` or
+/// `fr_CH`
+fn separators(locale: &Value) -> CoerceResult<(&'static [char], char)> {
+    let locale = locale
+        .as_str()
+        .ok_or_else(|| "the locale has to be a string".to_string())?;
+    let lower = locale.to_ascii_lowercase().replace('_', "-");
+    let (language, region) = lower.split_once('-').unwrap_or((lower.as_str(), ""));
+    Ok(match (language, region) {
+        (_, "ch") => (APOSTROPHE, '.'),
+        ("" | "en" | "ja" | "ko" | "zh" | "he" | "th", _) => (COMMA, '.'),
+        ("de" | "es" | "it" | "nl" | "pt" | "id" | "tr" | "da" | "el", _) => (DOT, ','),
+        ("fr" | "ru" | "pl" | "cs" | "sk" | "sv" | "fi" | "nb" | "no" | "uk" | "hu", _) => {
+            (SPACE, ',')
+        }
+        _ => return Err(format!("unknown locale `{}`", locale)),
+    })
+}
+
+/// A number without thousands separators
+struct Number {
+    negative: bool,
+    int: String,
+    fraction: String,
+    exponent: String,
+}
+
+impl Number {
+    fn float(&self) -> Option<f64> {
+        let mut s = String::with_capacity(self.int.len() + self.fraction.len() + 8);
+        if self.negative {
+            s.push('-');
+        }
+        s.push_str(&self.int);
+        if !self.fraction.is_empty() {
+            s.push('.');
+            s.push_str(&self.fraction);
+        }
+        if !self.exponent.is_empty() {
+            s.push('e');
+            s.push_str(&self.exponent);
+        }
+        s.parse::<f64>().ok().filter(|f| f.is_finite())
+    }
+
+    /// Integers, with a fraction of only zeros at most
+    fn int(&self) -> Option<Value<'static>> {
+        if !self.exponent.is_empty() || self.fraction.chars().any(|c| c != '0') {
+            None
+        } else if self.negative {
+            format!("-{}", self.int)
+                .parse::<i64>()
+                .ok()
+                .map(Value::from)
+        } else if let Ok(i) = self.int.parse::<i64>() {
+            Some(Value::from(i))
+        } else {
+            self.int.parse::<u64>().ok().map(Value::from)
+        }
+    }
+}
+
+/// Parses a number, thousands separators have to separate groups of three
+/// digits so `1,5` isn't taken as `15`
+fn number(input: &str, thousands: &[char], decimal: char) -> CoerceResult<Number> {
+    let invalid = || format!("`{}` is not a number", input);
+    let digits = |s: &str| !s.is_empty() && s.chars().all(|c| c.is_ascii_digit());
+    let s = input.trim();
+    let (negative, s) = match s.strip_prefix('-') {
+        Some(s) => (true, s),
+        None => (false, s.strip_prefix('+').unwrap_or(s)),
+    };
+    let (int, rest) = s.split_at(
+        s.find(|c: char| c == decimal || c == 'e' || c == 'E')
+            .unwrap_or_else(|| s.len()),
+    );
+    let groups: Vec<&str> = int.split(|c: char| thousands.contains(&c)).collect();
+    let grouped =
+        groups.len() == 1 || (groups[0].len() <= 3 && groups[1..].iter().all(|g| g.len() == 3));
+    if !grouped || !groups.iter().all(|g| digits(g)) {
+        return Err(invalid());
+    }
+    let (fraction, exponent) = match rest.strip_prefix(decimal) {
+        Some(rest) => {
+            let (fraction, exponent) = rest.split_at(
+                rest.find(|c: char| c == 'e' || c == 'E')
+                    .unwrap_or_else(|| rest.len()),
+            );
+            if !digits(fraction) {
+                return Err(invalid());
+            }
+            (fraction, exponent)
+        }
+        None => ("", rest),
+    };
+    // `e` or `E` followed by an optionally signed integer
+    let has_exponent = !exponent.is_empty();
+    let exponent = exponent.get(1..).unwrap_or_default();
+    let unsigned = exponent
+        .strip_prefix('-')
+        .or_else(|| exponent.strip_prefix('+'))
+        .unwrap_or(exponent);
+    if has_exponent && !digits(unsigned) {
+        return Err(invalid());
+    }
+    Ok(Number {
+        negative,
+        int: groups.concat(),
+        fraction: fraction.to_string(),
+        exponent: exponent.to_string(),
+    })
+}
+
+fn int(value: &Value, thousands: &[char], decimal: char) -> CoerceResult<Value<'static>> {
+    match value.value_type() {
+        ValueType::I64 | ValueType::U64 => Ok(value.clone_static()),
+        // integral floats print without a fraction
+        ValueType::F64 => value
+            .as_f64()
+            .and_then(|f| number(&f.to_string(), &[], '.').ok()?.int())
+            .ok_or_else(|| format!("{} is not an integer", value.encode())),
+        ValueType::String => {
+            let s = value.as_str().unwrap_or_default();
+            number(s, thousands, decimal)?
+                .int()
+                .ok_or_else(|| format!("`{}` is not an integer", s))
+        }
+        _ => Err(format!("{} is not a number", value.encode())),
+    }
+}
+
+fn float(value: &Value, thousands: &[char], decimal: char) -> CoerceResult<f64> {
+    if let Some(s) = value.as_str() {
+        number(s, thousands, decimal)?
+            .float()
+            .ok_or_else(|| format!("`{}` is out of range", s))
+    } else {
+        value
+            .cast_f64()
+            .ok_or_else(|| format!("{} is not a number", value.encode()))
+    }
+}
+
+fn boolean(value: &Value) -> CoerceResult<bool> {
+    if let Some(b) = value.as_bool() {
+        return Ok(b);
+    }
+    if let Some(s) = value.as_str() {
+        return match s.trim().to_ascii_lowercase().as_str() {
+            "true" | "t" | "yes" | "y" | "on" | "1" => Ok(true),
+            "false" | "f" | "no" | "n" | "off" | "0" => Ok(false),
+            _ => Err(format!("`{}` is not a boolean", s)),
+        };
+    }
+    match value.as_i64() {
+        Some(0) => Ok(false),
+        Some(1) => Ok(true),
+        _ => Err(format!("{} is not a boolean", value.encode())),
+    }
+}
+
+/// Numbers are seconds, milliseconds, microseconds or nanoseconds since the
+/// epoch depending on their magnitude
+fn epoch(n: u64) -> CoerceResult<u64> {
+    let factor = if n < 100_000_000_000 {
+        1_000_000_000
+    } else if n < 100_000_000_000_000 {
+        1_000_000
+    } else if n < 100_000_000_000_000_000 {
+        1_000
+    } else {
+        1
+    };
+    n.checked_mul(factor)
+        .ok_or_else(|| format!("{} is out of range", n))
+}
+
+// ALLOW: the range is checked before casting
+#[allow(clippy::cast_possible_truncation, clippy::cast_sign_loss)]
+fn epoch_float(f: f64) -> CoerceResult<u64> {
+    let ns = if f < 1e11 {
+        f * 1e9
+    } else if f < 1e14 {
+        f * 1e6
+    } else if f < 1e17 {
+        f * 1e3
+    } else {
+        f
+    };
+    if ns.is_finite() && ns >= 0.0 && ns < 1.8e19 {
+        Ok(ns as u64)
+    } else {
+        Err(format!("{} is out of range", f))
+    }
+}
+
+fn nanos(secs: i64, subsec_nanos: u32) -> Option<u64> {
+    u64::try_from(secs)
+        .ok()?
+        .checked_mul(1_000_000_000)?
+        .checked_add(u64::from(subsec_nanos))
+}
+
+fn date(s: &str) -> CoerceResult<u64> {
+    DateTime::parse_from_rfc3339(s)
+        .or_else(|_| DateTime::parse_from_rfc2822(s))
+        .ok()
+        .or_else(|| {
+            WITH_OFFSET
+                .iter()
+                .find_map(|f| DateTime::parse_from_str(s, f).ok())
+        })
+        .map(|d| nanos(d.timestamp(), d.timestamp_subsec_nanos()))
+        .or_else(|| {
+            WITHOUT_OFFSET
+                .iter()
+                .find_map(|f| NaiveDateTime::parse_from_str(s, f).ok())
+                .map(|d| nanos(d.timestamp(), d.timestamp_subsec_nanos()))
+        })
+        .or_else(|| {
+            DATES
+                .iter()
+                .find_map(|f| NaiveDate::parse_from_str(s, f).ok())
+                .map(|d| nanos(d.and_hms(0, 0, 0).timestamp(), 0))
+        })
+        .flatten()
+        .ok_or_else(|| format!("`{}` is not a known timestamp format", s))
+}
+
+/// Nanoseconds since the epoch
+fn timestamp(value: &Value) -> CoerceResult<u64> {
+    if let Some(n) = value.as_u64() {
+        epoch(n)
+    } else if let Some(s) = value.as_str() {
+        let s = s.trim();
+        if let Ok(n) = s.parse::<u64>() {
+            epoch(n)
+        } else if let Ok(f) = s.parse::<f64>() {
+            epoch_float(f)
+        } else {
+            date(s)
+        }
+    } else if let Some(f) = value.cast_f64() {
+        epoch_float(f)
+    } else {
+        Err(format!("{} is not a timestamp", value.encode()))
+    }
+}
+
+pub fn load(registry: &mut Registry) {
+    registry
+        .insert(tremor_const_fn!(coerce|to_int(_context, _value, _locale) {
+            let (thousands, decimal) = separators(_locale).map_err(to_runtime_error)?;
+            Ok(int(_value, thousands, decimal).unwrap_or_default())
+        }))
+        .insert(tremor_const_fn!(coerce|to_float(_context, _value, _locale) {
+            let (thousands, decimal) = separators(_locale).map_err(to_runtime_error)?;
+            Ok(float(_value, thousands, decimal).map_or_else(|_| Value::null(), Value::from))
+        }))
+        .insert(tremor_const_fn!(coerce|to_bool(_context, _value) {
+            Ok(boolean(_value).map_or_else(|_| Value::null(), Value::from))
+        }))
+        .insert(tremor_const_fn!(coerce|to_timestamp(_context, _value) {
+            Ok(timestamp(_value).map_or_else(|_| Value::null(), Value::from))
+        }))
+        .insert(tremor_const_fn!(coerce|parse(_context, _value, _type, _locale) {
+            let (thousands, decimal) = separators(_locale).map_err(to_runtime_error)?;
+            let res = match _type.as_str() {
+                Some("int") => int(_value, thousands, decimal),
+                Some("float") => float(_value, thousands, decimal).map(Value::from),
+                Some("bool") => boolean(_value).map(Value::from),
+                Some("timestamp") => timestamp(_value).map(Value::from),
+                _ => return Err(to_runtime_error("the type has to be one of `int`, `float`, `bool` or `timestamp`")),
+            };
+            Ok(res.unwrap_or_else(|error| {
+                let input = _value.clone_static();
+                literal!({"error": error, "input": input})
+            }))
+        }));
+}
+
+#[cfg(test)]
+mod test {
+    use crate::registry::fun;
+    use crate::Value;
+
+    #[test]
+    fn to_int() {
+        let f = fun("coerce", "to_int");
+        let en = Value::from("en");
+        assert_val!(f(&[&Value::from("1,234"), &en]), 1234);
+        assert_val!(f(&[&Value::from(" -1,234,567 "), &en]), -1_234_567);
+        assert_val!(f(&[&Value::from("12.00"), &en]), 12);
+        assert_val!(f(&[&Value::from("1.234"), &Value::from("de_DE")]), 1234);
+        assert_val!(f(&[&Value::from("1\u{a0}234"), &Value::from("fr")]), 1234);
+        assert_val!(
+            f(&[&Value::from("1'234'567"), &Value::from("de-CH")]),
+            1_234_567
+        );
+        assert_val!(f(&[&Value::from(3.0), &en]), 3);
+        assert_val!(f(&[&Value::from(42), &en]), 42);
+        for invalid in ["1,5", "12.5", "1e3", "abc", "", "1,,234", "--1"] {
+            assert_val!(f(&[&Value::from(invalid), &en]), Value::null());
+        }
+        assert_val!(f(&[&Value::from(3.5), &en]), Value::null());
+        assert!(f(&[&Value::from("1"), &Value::from("xx")]).is_err());
+    }
+
+    #[test]
+    fn to_float() {
+        let f = fun("coerce", "to_float");
+        let en = Value::from("en");
+        assert_val!(f(&[&Value::from("1.234,5"), &Value::from("de")]), 1234.5);
+        assert_val!(f(&[&Value::from("1,234.5"), &en]), 1234.5);
+        assert_val!(f(&[&Value::from("1e3"), &en]), 1000.0);
+        assert_val!(f(&[&Value::from("-2.5E-1"), &en]), -0.25);
+        assert_val!(f(&[&Value::from(2), &en]), 2.0);
+        assert_val!(f(&[&Value::from("1.2.3"), &en]), Value::null());
+        assert_val!(f(&[&Value::from(true), &en]), Value::null());
+    }
+
+    #[test]
+    fn to_bool() {
+        let f = fun("coerce", "to_bool");
+        assert_val!(f(&[&Value::from(" Yes")]), true);
+        assert_val!(f(&[&Value::from("off")]), false);
+        assert_val!(f(&[&Value::from(1)]), true);
+        assert_val!(f(&[&Value::from("maybe")]), Value::null());
+    }
+
+    #[test]
+    fn to_timestamp() {
+        let f = fun("coerce", "to_timestamp");
+        let ts = 1_609_556_645_500_000_000_u64;
+        assert_val!(f(&[&Value::from("2021-01-02T03:04:05.5Z")]), ts);
+        assert_val!(f(&[&Value::from("2021-01-02T05:04:05.5+02:00")]), ts);
+        assert_val!(f(&[&Value::from("2021-01-02 03:04:05.5")]), ts);
+        assert_val!(f(&[&Value::from(1_609_556_645_500_u64)]), ts);
+        assert_val!(f(&[&Value::from("1609556645.5")]), ts);
+        assert_val!(
+            f(&[&Value::from("10/Oct/2000:13:55:36 -0700")]),
+            971_211_336_000_000_000_u64
+        );
+        assert_val!(
+            f(&[&Value::from("Tue, 10 Oct 2000 20:55:36 GMT")]),
+            971_211_336_000_000_000_u64
+        );
+        assert_val!(
+            f(&[&Value::from("2021-01-02")]),
+            1_609_545_600_000_000_000_u64
+        );
+        assert_val!(f(&[&Value::from("yesterday")]), Value::null());
+        assert_val!(f(&[&Value::from(-1)]), Value::null());
+    }
+
+    #[test]
+    fn parse() {
+        let f = fun("coerce", "parse");
+        let en = Value::from("en");
+        assert_val!(f(&[&Value::from("1,234"), &Value::from("int"), &en]), 1234);
+        let res = f(&[&Value::from("1,5"), &Value::from("int"), &en]).unwrap_or_default();
+        assert_eq!(Some("1,5"), res.get_str("input"));
+        assert_eq!(Some("`1,5` is not a number"), res.get_str("error"));
+        assert!(f(&[&Value::from("1"), &Value::from("date"), &en]).is_err());
+    }
+}
@@ -0,0 +1,157 @@
+// Copyright 2020-2021, The Tremor Team
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+//! AES-256-GCM field encryption.
+//!
+//! Keys are base64 encoded 32 byte strings, usually taken from a secret store
+//! via interpolation, e.g. `crypto::encrypt("${vault:kv/tremor#key}", event.ssn)`.
+//! Ciphertexts are the base64 encoded nonce followed by the sealed data.
+
+use crate::prelude::*;
+use crate::registry::Registry;
+use crate::{tremor_fn, tremor_fn_};
+use aes_gcm::aead::generic_array::GenericArray;
+use aes_gcm::aead::{Aead, NewAead};
+use aes_gcm::Aes256Gcm;
+use rand::RngCore;
+
+const KEY_LEN: usize = 32;
+const NONCE_LEN: usize = 12;
+
+fn cipher(key: &str) -> std::result::Result<Aes256Gcm, String> {
+    let key = base64::decode(key).map_err(|e| format!("invalid key: {}", e))?;
+    if key.len() == KEY_LEN {
+        Ok(Aes256Gcm::new(GenericArray::from_slice(&key)))
+    } else {
+        Err(format!(
+            "invalid key: expected {} bytes but got {}",
+            KEY_LEN,
+            key.len()
+        ))
+    }
+}
+
+fn generate_key() -> Vec<u8> {
+    let mut key = vec![0; KEY_LEN];
+    rand::thread_rng().fill_bytes(&mut key);
+    key
+}
+
+fn seal(key: &str, plaintext: &[u8]) -> std::result::Result<String, String> {
+    let cipher = cipher(key)?;
+    let mut nonce = [0; NONCE_LEN];
+    rand::thread_rng().fill_bytes(&mut nonce);
+    let sealed = cipher
+        .encrypt(GenericArray::from_slice(&nonce), plaintext)
+        .map_err(|_| "encryption failed".to_string())?;
+    let mut data = Vec::with_capacity(NONCE_LEN + sealed.len());
+    data.extend_from_slice(&nonce);
+    data.extend_from_slice(&sealed);
+    Ok(base64::encode(data))
+}
+
+fn open(key: &str, ciphertext: &str) -> std::result::Result<Vec<u8>, String> {
+    let cipher = cipher(key)?;
+    let data = base64::decode(ciphertext).map_err(|e| format!("invalid ciphertext: {}", e))?;
+    if data.len() < NONCE_LEN {
+        return Err("invalid ciphertext: too short".to_string());
+    }
+    let (nonce, sealed) = data.split_at(NONCE_LEN);
+    cipher
+        .decrypt(GenericArray::from_slice(nonce), sealed)
+        .map_err(|_| "decryption failed: wrong key or tampered data".to_string())
+}
+
+fn key<'a>(value: &'a Value) -> std::result::Result<&'a str, &'static str> {
+    value.as_str().ok_or("the key must be a string")
+}
+
+pub fn load(registry: &mut Registry) {
+    registry
+        .insert(tremor_fn! (crypto|generate_key(_context) {
+            Ok(Value::from(base64::encode(generate_key())))
+        }))
+        .insert(tremor_fn! (crypto|encrypt(_context, _key, _input) {
+            let input = _input.as_bytes().ok_or_else(|| FunctionError::BadType { mfa: this_mfa() })?;
+            seal(key(_key).map_err(to_runtime_error)?, input).map(Value::from).map_err(to_runtime_error)
+        }))
+        .insert(tremor_fn! (crypto|decrypt(_context, _key, _input) {
+            let input = _input.as_str().ok_or_else(|| FunctionError::BadType { mfa: this_mfa() })?;
+            open(key(_key).map_err(to_runtime_error)?, input).map(|v| Value::Bytes(v.into())).map_err(to_runtime_error)
+        }))
+        .insert(tremor_fn! (crypto|envelope_encrypt(_context, _key, _input) {
+            let input = _input.as_bytes().ok_or_else(|| FunctionError::BadType { mfa: this_mfa() })?;
+            let data_key = base64::encode(generate_key());
+            let data = seal(&data_key, input).map_err(to_runtime_error)?;
+            let wrapped = seal(key(_key).map_err(to_runtime_error)?, data_key.as_bytes()).map_err(to_runtime_error)?;
+            let mut envelope = Value::object_with_capacity(2);
+            envelope.try_insert("key", wrapped);
+            envelope.try_insert("data", data);
+            Ok(envelope)
+        }))
+        .insert(tremor_fn! (crypto|envelope_decrypt(_context, _key, _envelope) {
+            let (wrapped, data) = match (_envelope.get_str("key"), _envelope.get_str("data")) {
+                (Some(wrapped), Some(data)) => (wrapped, data),
+                _ => return Err(to_runtime_error("an envelope must have a `key` and a `data` string")),
+            };
+            let data_key = open(key(_key).map_err(to_runtime_error)?, wrapped).map_err(to_runtime_error)?;
+            let data_key = String::from_utf8(data_key).map_err(to_runtime_error)?;
+            open(&data_key, data).map(|v| Value::Bytes(v.into())).map_err(to_runtime_error)
+        }));
+}
+
+#[cfg(test)]
+mod test {
+    use crate::prelude::*;
+    use crate::registry::fun;
+    use crate::Value;
+
+    const KEY: &str = "AAECAwQFBgcICQoLDA0ODxAREhMUFRYXGBkaGxwdHh8=";
+
+    #[test]
+    fn roundtrip() -> Result<(), Box<dyn std::error::Error>> {
+        let encrypt = fun("crypto", "encrypt");
+        let decrypt = fun("crypto", "decrypt");
+        let key = Value::from(KEY);
+        let v = Value::from("123-45-6789");
+        let encrypted = encrypt(&[&key, &v]).map_err(|e| format!("{:?}", e))?;
+        assert_ne!(encrypted, v);
+        // every encryption uses a fresh nonce
+        let again = encrypt(&[&key, &v]).map_err(|e| format!("{:?}", e))?;
+        assert_ne!(encrypted, again);
+        assert_val!(
+            decrypt(&[&key, &encrypted]),
+            Value::Bytes("123-45-6789".as_bytes().into())
+        );
+
+        let other = fun("crypto", "generate_key")(&[]).map_err(|e| format!("{:?}", e))?;
+        assert!(decrypt(&[&other, &encrypted]).is_err());
+        assert!(encrypt(&[&Value::from("c25vdA=="), &v]).is_err());
+        Ok(())
+    }
+
+    #[test]
+    fn envelope() -> Result<(), Box<dyn std::error::Error>> {
+        let encrypt = fun("crypto", "envelope_encrypt");
+        let decrypt = fun("crypto", "envelope_decrypt");
+        let key = Value::from(KEY);
+        let v = Value::Bytes("snot".as_bytes().into());
+        let envelope = encrypt(&[&key, &v]).map_err(|e| format!("{:?}", e))?;
+        assert!(envelope.get_str("key").is_some());
+        assert!(envelope.get_str("data").is_some());
+        assert_val!(decrypt(&[&key, &envelope]), v);
+        assert!(decrypt(&[&key, &Value::from("snot")]).is_err());
+        Ok(())
+    }
+}
@@ -0,0 +1,203 @@
+// Copyright 2020-2021, The Tremor Team
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+use crate::prelude::*;
+use crate::registry::Registry;
+use crate::{tremor_const_fn, tremor_fn, tremor_fn_};
+use jsonwebtoken::{Algorithm, DecodingKey, EncodingKey, Header, Validation};
+use simd_json::OwnedValue;
+use std::str::FromStr;
+use tremor_common::time::nanotime;
+
+type JwtResult<T> = std::result::Result<T, String>;
+
+fn algorithm(alg: &str) -> JwtResult<Algorithm> {
+    Algorithm::from_str(alg).map_err(|_| format!("unsupported algorithm `{}`", alg))
+}
+
+/// Shared secret for `HS*`, PEM encoded private key for `RS*`, `PS*` and `ES*`
+fn encoding_key(alg: Algorithm, key: &str) -> JwtResult<EncodingKey> {
+    match alg {
+        Algorithm::HS256 | Algorithm::HS384 | Algorithm::HS512 => {
+            Ok(EncodingKey::from_secret(key.as_bytes()))
+        }
+        Algorithm::RS256
+        | Algorithm::RS384
+        | Algorithm::RS512
+        | Algorithm::PS256
+        | Algorithm::PS384
+        | Algorithm::PS512 => EncodingKey::from_rsa_pem(key.as_bytes()),
+        Algorithm::ES256 | Algorithm::ES384 => EncodingKey::from_ec_pem(key.as_bytes()),
+    }
+    .map_err(|e| format!("invalid key: {}", e))
+}
+
+/// Shared secret for `HS*`, PEM encoded public key for `RS*`, `PS*` and `ES*`
+fn decoding_key(alg: Algorithm, key: &str) -> JwtResult<DecodingKey> {
+    match alg {
+        Algorithm::HS256 | Algorithm::HS384 | Algorithm::HS512 => {
+            Ok(DecodingKey::from_secret(key.as_bytes()))
+        }
+        Algorithm::RS256
+        | Algorithm::RS384
+        | Algorithm::RS512
+        | Algorithm::PS256
+        | Algorithm::PS384
+        | Algorithm::PS512 => DecodingKey::from_rsa_pem(key.as_bytes()),
+        Algorithm::ES256 | Algorithm::ES384 => DecodingKey::from_ec_pem(key.as_bytes()),
+    }
+    .map_err(|e| format!("invalid key: {}", e))
+}
+
+fn encode(claims: &Value, key: &str, alg: &str) -> JwtResult<String> {
+    let alg = algorithm(alg)?;
+    jsonwebtoken::encode(&Header::new(alg), claims, &encoding_key(alg, key)?)
+        .map_err(|e| e.to_string())
+}
+
+/// The `exp` or `nbf` claim in seconds since epoch. Numeric dates may have a
+/// fraction (RFC 7519), claims of any other type are rejected.
+fn numeric_date(claims: &OwnedValue, claim: &str) -> JwtResult<Option<f64>> {
+    claims.get(claim).map_or(Ok(None), |date| {
+        date.cast_f64()
+            .map(Some)
+            .ok_or_else(|| format!("`{}` claim is not a number", claim))
+    })
+}
+
+/// Verifies the signature and, if present, the `exp` and `nbf` claims
+fn decode(token: &str, key: &str, alg: &str) -> JwtResult<Value<'static>> {
+    let alg = algorithm(alg)?;
+    // `exp` is optional for us, so it is checked below rather than by the library
+    let validation = Validation {
+        validate_exp: false,
+        ..Validation::new(alg)
+    };
+    let claims = jsonwebtoken::decode::<OwnedValue>(token, &decoding_key(alg, key)?, &validation)
+        .map_err(|e| e.to_string())?
+        .claims;
+    // ALLOW: seconds since epoch fit into the mantissa of a f64
+    #[allow(clippy::cast_precision_loss)]
+    let now = nanotime() as f64 / 1_000_000_000.0;
+    if numeric_date(&claims, "exp")?.map_or(false, |exp| exp < now) {
+        return Err("token has expired".to_string());
+    }
+    if numeric_date(&claims, "nbf")?.map_or(false, |nbf| nbf > now) {
+        return Err("token is not valid yet".to_string());
+    }
+    Ok(Value::from(claims))
+}
+
+fn decode_unverified(token: &str) -> JwtResult<Value<'static>> {
+    jsonwebtoken::dangerous_insecure_decode::<OwnedValue>(token)
+        .map(|data| Value::from(data.claims))
+        .map_err(|e| e.to_string())
+}
+
+pub fn load(registry: &mut Registry) {
+    registry
+        // not constant, so keys and tokens aren't folded into the script when it is compiled
+        .insert(tremor_fn! (jwt|encode(_context, _claims, _key, _alg) {
+            match (_key.as_str(), _alg.as_str()) {
+                (Some(key), Some(alg)) => encode(_claims, key, alg).map(Value::from).map_err(to_runtime_error),
+                _ => Err(FunctionError::BadType { mfa: this_mfa() }),
+            }
+        }))
+        // not constant since the result depends on the time of the call
+        .insert(tremor_fn! (jwt|decode(_context, _token: String, _key: String, _alg: String) {
+            decode(_token, _key, _alg).map_err(to_runtime_error)
+        }))
+        .insert(tremor_const_fn! (jwt|decode_unverified(_context, _token: String) {
+            decode_unverified(_token).map_err(to_runtime_error)
+        }));
+}
+
+#[cfg(test)]
+mod test {
+    use crate::prelude::*;
+    use crate::registry::fun;
+    use crate::Value;
+    use tremor_value::literal;
+
+    #[test]
+    fn roundtrip() -> Result<(), Box<dyn std::error::Error>> {
+        let encode = fun("jwt", "encode");
+        let decode = fun("jwt", "decode");
+        let claims = literal!({"sub": "snot", "admin": true, "exp": 9_999_999_999_u64});
+        let key = Value::from("badger");
+        let alg = Value::from("HS256");
+        let token = encode(&[&claims, &key, &alg]).map_err(|e| format!("{:?}", e))?;
+        assert_eq!(Some(2), token.as_str().map(|t| t.matches('.').count()));
+        let decoded = decode(&[&token, &key, &alg]).map_err(|e| format!("{:?}", e))?;
+        assert_eq!(Some("snot"), decoded.get_str("sub"));
+        assert_eq!(Some(true), decoded.get_bool("admin"));
+        assert_eq!(Some(9_999_999_999), decoded.get_u64("exp"));
+        let unverified =
+            fun("jwt", "decode_unverified")(&[&token]).map_err(|e| format!("{:?}", e))?;
+        assert_eq!(Some("snot"), unverified.get_str("sub"));
+
+        // wrong key, wrong algorithm
+        assert!(decode(&[&token, &Value::from("snot"), &alg]).is_err());
+        assert!(decode(&[&token, &key, &Value::from("HS512")]).is_err());
+        assert!(encode(&[&claims, &key, &Value::from("none")]).is_err());
+        Ok(())
+    }
+
+    #[test]
+    fn expiry() -> Result<(), Box<dyn std::error::Error>> {
+        let encode = fun("jwt", "encode");
+        let decode = fun("jwt", "decode");
+        let key = Value::from("badger");
+        let alg = Value::from("HS384");
+
+        let expired = literal!({"sub": "snot", "exp": 1});
+        let token = encode(&[&expired, &key, &alg]).map_err(|e| format!("{:?}", e))?;
+        assert!(decode(&[&token, &key, &alg]).is_err());
+
+        let immature = literal!({"sub": "snot", "nbf": 9_999_999_999_u64});
+        let token = encode(&[&immature, &key, &alg]).map_err(|e| format!("{:?}", e))?;
+        assert!(decode(&[&token, &key, &alg]).is_err());
+
+        // tokens without `exp` never expire
+        let eternal = literal!({"sub": "snot"});
+        let token = encode(&[&eternal, &key, &alg]).map_err(|e| format!("{:?}", e))?;
+        let decoded = decode(&[&token, &key, &alg]).map_err(|e| format!("{:?}", e))?;
+        assert_eq!(Some("snot"), decoded.get_str("sub"));
+        Ok(())
+    }
+
+    #[test]
+    fn numeric_dates() -> Result<(), Box<dyn std::error::Error>> {
+        let encode = fun("jwt", "encode");
+        let decode = fun("jwt", "decode");
+        let key = Value::from("badger");
+        let alg = Value::from("HS256");
+        let decodes = |claims: Value| -> Result<bool, Box<dyn std::error::Error>> {
+            let token = encode(&[&claims, &key, &alg]).map_err(|e| format!("{:?}", e))?;
+            Ok(decode(&[&token, &key, &alg]).is_ok())
+        };
+
+        // numeric dates may have a fraction
+        assert!(decodes(literal!({"exp": 9_999_999_999.5}))?);
+        assert!(!decodes(literal!({"exp": 1.5}))?);
+        assert!(decodes(literal!({"nbf": 1.5}))?);
+        assert!(!decodes(literal!({"nbf": 9_999_999_999.5}))?);
+
+        // other types are rejected instead of ignored
+        assert!(!decodes(literal!({"exp": "9999999999"}))?);
+        assert!(!decodes(literal!({"nbf": "1"}))?);
+        assert!(!decodes(literal!({"exp": [1]}))?);
+        Ok(())
+    }
+}
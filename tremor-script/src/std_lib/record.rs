@@ -17,6 +17,304 @@ use crate::registry::Registry;
 use crate::tremor_const_fn;
 use crate::Object;
 
+type PatchResult<T> = std::result::Result<T, String>;
+
+/// Escapes a key for a JSON pointer (RFC 6901)
+fn escape(key: &str) -> String {
+    key.replace('~', "~0").replace('/', "~1")
+}
+
+fn pointer(path: &str) -> PatchResult<Vec<String>> {
+    if path.is_empty() {
+        return Ok(Vec::new());
+    }
+    let path = path
+        .strip_prefix('/')
+        .ok_or_else(|| format!("invalid JSON pointer `{}`", path))?;
+    Ok(path
+        .split('/')
+        .map(|key| key.replace("~1", "/").replace("~0", "~"))
+        .collect())
+}
+
+fn display(path: &[String]) -> String {
+    path.iter().map(|key| format!("/{}", escape(key))).collect()
+}
+
+fn operation<'value>(op: &'static str, path: &str, value: Option<Value<'value>>) -> Value<'value> {
+    let mut res = Object::with_capacity(3);
+    res.insert("op".into(), Value::from(op));
+    res.insert("path".into(), Value::from(path.to_string()));
+    if let Some(value) = value {
+        res.insert("value".into(), value);
+    }
+    Value::from(res)
+}
+
+/// Collects the JSON patch (RFC 6902) operations turning `from` into `to`,
+/// arrays are replaced as a whole
+fn diff<'value>(
+    path: &str,
+    from: &Value<'value>,
+    to: &Value<'value>,
+    ops: &mut Vec<Value<'value>>,
+) {
+    match (from.as_object(), to.as_object()) {
+        (Some(from), Some(to)) => {
+            for (key, value) in from {
+                let path = format!("{}/{}", path, escape(key));
+                match to.get(key) {
+                    Some(new) => diff(&path, value, new, ops),
+                    None => ops.push(operation("remove", &path, None)),
+                }
+            }
+            for (key, value) in to {
+                if !from.contains_key(key) {
+                    let path = format!("{}/{}", path, escape(key));
+                    ops.push(operation("add", &path, Some(value.clone())));
+                }
+            }
+        }
+        _ if from != to => ops.push(operation("replace", path, Some(to.clone()))),
+        _ => (),
+    }
+}
+
+fn get<'a, 'value>(target: &'a Value<'value>, path: &[String]) -> Option<&'a Value<'value>> {
+    path.iter().try_fold(target, |value, key| match value {
+        Value::Object(o) => o.get(key.as_str()),
+        Value::Array(a) => key.parse::<usize>().ok().and_then(|i| a.get(i)),
+        _ => None,
+    })
+}
+
+fn get_mut<'a, 'value>(
+    target: &'a mut Value<'value>,
+    path: &[String],
+) -> Option<&'a mut Value<'value>> {
+    path.iter().try_fold(target, |value, key| match value {
+        Value::Object(o) => o.get_mut(key.as_str()),
+        Value::Array(a) => key.parse::<usize>().ok().and_then(move |i| a.get_mut(i)),
+        _ => None,
+    })
+}
+
+fn index(key: &str, len: usize, path: &[String]) -> PatchResult<usize> {
+    key.parse::<usize>()
+        .ok()
+        .filter(|i| *i < len)
+        .ok_or_else(|| format!("invalid array index at `{}`", display(path)))
+}
+
+fn add<'value>(
+    target: &mut Value<'value>,
+    path: &[String],
+    value: Value<'value>,
+) -> PatchResult<()> {
+    let (key, parent) = if let Some(split) = path.split_last() {
+        split
+    } else {
+        *target = value;
+        return Ok(());
+    };
+    match get_mut(target, parent) {
+        Some(Value::Object(o)) => {
+            o.insert(key.clone().into(), value);
+            Ok(())
+        }
+        Some(Value::Array(a)) => {
+            // `-` appends, an index equal to the length as well
+            let i = if key == "-" {
+                a.len()
+            } else {
+                index(key, a.len() + 1, path)?
+            };
+            a.insert(i, value);
+            Ok(())
+        }
+        _ => Err(format!("can not add at `{}`", display(path))),
+    }
+}
+
+fn remove<'value>(target: &mut Value<'value>, path: &[String]) -> PatchResult<Value<'value>> {
+    let (key, parent) = path
+        .split_last()
+        .ok_or_else(|| "can not remove the whole document".to_string())?;
+    match get_mut(target, parent) {
+        Some(Value::Object(o)) => o
+            .remove(key.as_str())
+            .ok_or_else(|| format!("nothing to remove at `{}`", display(path))),
+        Some(Value::Array(a)) => Ok(a.remove(index(key, a.len(), path)?)),
+        _ => Err(format!("nothing to remove at `{}`", display(path))),
+    }
+}
+
+/// Applies a JSON patch (RFC 6902), all operations succeed or an error is
+/// returned
+fn patch<'value>(target: &mut Value<'value>, patch: &Value<'value>) -> PatchResult<()> {
+    let ops = patch
+        .as_array()
+        .ok_or_else(|| "a JSON patch has to be an array of operations".to_string())?;
+    for op in ops {
+        let name = op.get_str("op").unwrap_or_default();
+        let path = pointer(op.get_str("path").unwrap_or_default())?;
+        let value = || {
+            op.get("value")
+                .cloned()
+                .ok_or_else(|| format!("`{}` at `{}` has no value", name, display(&path)))
+        };
+        let from = || pointer(op.get_str("from").unwrap_or_default());
+        match name {
+            "add" => add(target, &path, value()?)?,
+            "remove" => {
+                remove(target, &path)?;
+            }
+            "replace" => {
+                let value = value()?;
+                *get_mut(target, &path)
+                    .ok_or_else(|| format!("nothing to replace at `{}`", display(&path)))? = value;
+            }
+            "move" => {
+                let value = remove(target, &from()?)?;
+                add(target, &path, value)?;
+            }
+            "copy" => {
+                let from = from()?;
+                let value = get(target, &from)
+                    .cloned()
+                    .ok_or_else(|| format!("nothing to copy at `{}`", display(&from)))?;
+                add(target, &path, value)?;
+            }
+            "test" => {
+                if get(target, &path) != Some(&value()?) {
+                    return Err(format!("test at `{}` failed", display(&path)));
+                }
+            }
+            other => return Err(format!("unknown patch operation `{}`", other)),
+        }
+    }
+    Ok(())
+}
+
+/// Computes the JSON merge patch (RFC 7386) turning `from` into `to`
+fn merge_diff<'value>(from: &Value<'value>, to: &Value<'value>) -> Value<'value> {
+    match (from.as_object(), to.as_object()) {
+        (Some(from), Some(to)) => {
+            let mut res = Object::with_capacity(to.len());
+            for key in from.keys() {
+                if !to.contains_key(key) {
+                    res.insert(key.clone(), Value::null());
+                }
+            }
+            for (key, value) in to {
+                match from.get(key) {
+                    Some(old) if old == value => (),
+                    Some(old) => {
+                        res.insert(key.clone(), merge_diff(old, value));
+                    }
+                    None => {
+                        res.insert(key.clone(), value.clone());
+                    }
+                }
+            }
+            Value::from(res)
+        }
+        _ => to.clone(),
+    }
+}
+
+/// Applies a JSON merge patch (RFC 7386)
+fn merge_patch<'value>(target: &mut Value<'value>, patch: &Value<'value>) {
+    if let Some(patch) = patch.as_object() {
+        if !target.is_object() {
+            *target = Value::object();
+        }
+        if let Some(target) = target.as_object_mut() {
+            for (key, value) in patch {
+                if value.is_null() {
+                    target.remove(key);
+                } else if let Some(old) = target.get_mut(key) {
+                    merge_patch(old, value);
+                } else {
+                    let mut new = Value::null();
+                    merge_patch(&mut new, value);
+                    target.insert(key.clone(), new);
+                }
+            }
+        }
+    } else {
+        *target = patch.clone();
+    }
+}
+
+/// Adds the leaves of `value` to `out`, keys of nested records and indexes
+/// of arrays are joined with `sep`. Empty records and arrays are leaves.
+fn flatten<'value>(key: String, value: &Value<'value>, sep: &str, out: &mut Object<'value>) {
+    match value {
+        Value::Object(o) if !o.is_empty() => {
+            for (k, v) in &**o {
+                flatten(format!("{}{}{}", key, sep, k), v, sep, out);
+            }
+        }
+        Value::Array(a) if !a.is_empty() => {
+            for (i, v) in a.iter().enumerate() {
+                flatten(format!("{}{}{}", key, sep, i), v, sep, out);
+            }
+        }
+        _ => {
+            out.insert(key.into(), value.clone());
+        }
+    }
+}
+
+/// Nests the keys of `input` split by `sep`, records with the keys `0` to
+/// `n - 1` become arrays
+fn unflatten<'value>(input: &Object<'value>, sep: &str) -> PatchResult<Value<'value>> {
+    let conflict = |key: &str| format!("key `{}` conflicts with another key", key);
+    let mut res = Value::object();
+    for (key, value) in input {
+        let key: &str = key;
+        let mut target = &mut res;
+        let mut segments = key.split(sep).peekable();
+        while let Some(segment) = segments.next() {
+            let record = target.as_object_mut().ok_or_else(|| conflict(key))?;
+            if segments.peek().is_none() {
+                if record.contains_key(segment) {
+                    return Err(conflict(key));
+                }
+                record.insert(segment.to_string().into(), value.clone());
+                break;
+            }
+            if !record.contains_key(segment) {
+                record.insert(segment.to_string().into(), Value::object());
+            }
+            target = record.get_mut(segment).ok_or_else(|| conflict(key))?;
+        }
+    }
+    Ok(into_arrays(res))
+}
+
+fn into_arrays(value: Value) -> Value {
+    if let Value::Object(record) = value {
+        let mut record: Object = (*record)
+            .into_iter()
+            .map(|(k, v)| (k, into_arrays(v)))
+            .collect();
+        let len = record.len();
+        if len > 0 && (0..len).all(|i| record.contains_key(i.to_string().as_str())) {
+            Value::from(
+                (0..len)
+                    .filter_map(|i| record.remove(i.to_string().as_str()))
+                    .collect::<Vec<_>>(),
+            )
+        } else {
+            Value::from(record)
+        }
+    } else {
+        value
+    }
+}
+
 pub fn load(registry: &mut Registry) {
     registry
         .insert(tremor_const_fn! (record|len(_context, _input: Object) {
@@ -81,6 +379,42 @@ pub fn load(registry: &mut Registry) {
             } else {
                 (k.clone(), v.clone())
             }).collect::<Object>()))
+        }))
+        .insert(tremor_const_fn!(record|diff(_context, _from, _to) {
+            let mut ops = Vec::new();
+            diff("", _from, _to, &mut ops);
+            Ok(Value::from(ops))
+        }))
+        .insert(tremor_const_fn!(record|patch(_context, _target, _patch) {
+            let mut target = Value::clone(_target);
+            match patch(&mut target, _patch) {
+                Ok(()) => Ok(target),
+                Err(e) => Err(to_runtime_error(e)),
+            }
+        }))
+        .insert(tremor_const_fn!(record|merge_diff(_context, _from, _to) {
+            Ok(merge_diff(_from, _to))
+        }))
+        .insert(tremor_const_fn!(record|merge_patch(_context, _target, _patch) {
+            let mut target = Value::clone(_target);
+            merge_patch(&mut target, _patch);
+            Ok(target)
+        }))
+        .insert(tremor_const_fn!(record|flatten(_context, _input: Object, _sep: String) {
+            if _sep.is_empty() {
+                return Err(to_runtime_error("the separator can not be empty"));
+            }
+            let mut res = Object::with_capacity(_input.len());
+            for (key, value) in &**_input {
+                flatten(key.to_string(), value, _sep, &mut res);
+            }
+            Ok(Value::from(res))
+        }))
+        .insert(tremor_const_fn!(record|unflatten(_context, _input: Object, _sep: String) {
+            if _sep.is_empty() {
+                return Err(to_runtime_error("the separator can not be empty"));
+            }
+            unflatten(_input, _sep).map_err(to_runtime_error)
         }));
 }
 
@@ -89,6 +423,7 @@ mod test {
     use crate::registry::fun;
     use crate::Value;
     use halfbrown::hashmap;
+    use tremor_value::literal;
 
     #[test]
     fn len() {
@@ -211,4 +546,84 @@ mod test {
             })
         );
     }
+
+    #[test]
+    fn diff_and_patch() {
+        let diff = fun("record", "diff");
+        let patch = fun("record", "patch");
+        let from = literal!({"a": 1, "b": {"c": 2, "d": 3}, "e": [1]});
+        let to = literal!({"a": 1, "b": {"c": 4}, "e": [1, 2], "f/g": true});
+        let ops = literal!([
+            {"op": "replace", "path": "/b/c", "value": 4},
+            {"op": "remove", "path": "/b/d"},
+            {"op": "replace", "path": "/e", "value": [1, 2]},
+            {"op": "add", "path": "/f~1g", "value": true}
+        ]);
+        assert_val!(diff(&[&from, &to]), ops.clone());
+        assert_val!(patch(&[&from, &ops]), to.clone());
+        assert_val!(diff(&[&to, &to]), Value::array());
+    }
+
+    #[test]
+    fn patch_operations() {
+        let patch = fun("record", "patch");
+        let target = literal!({"list": [1, 2, 3]});
+        let ops = literal!([
+            {"op": "add", "path": "/list/-", "value": 4},
+            {"op": "remove", "path": "/list/0"},
+            {"op": "move", "from": "/list/0", "path": "/first"},
+            {"op": "copy", "from": "/first", "path": "/second"},
+            {"op": "test", "path": "/second", "value": 2}
+        ]);
+        assert_val!(
+            patch(&[&target, &ops]),
+            literal!({"list": [3, 4], "first": 2, "second": 2})
+        );
+        let failed = literal!([{"op": "test", "path": "/list/0", "value": 2}]);
+        assert!(patch(&[&target, &failed]).is_err());
+        let missing = literal!([{"op": "remove", "path": "/snot"}]);
+        assert!(patch(&[&target, &missing]).is_err());
+    }
+
+    #[test]
+    fn merge_diff_and_patch() {
+        let merge_diff = fun("record", "merge_diff");
+        let merge_patch = fun("record", "merge_patch");
+        let from = literal!({"a": 1, "b": {"c": 2, "d": 3}, "e": 4});
+        let to = literal!({"a": 1, "b": {"c": 2, "d": 5}, "f": {"g": 6}});
+        let diff = literal!({"e": null, "b": {"d": 5}, "f": {"g": 6}});
+        assert_val!(merge_diff(&[&from, &to]), diff.clone());
+        assert_val!(merge_patch(&[&from, &diff]), to);
+        assert_val!(merge_patch(&[&from, &Value::from(1)]), 1);
+    }
+
+    #[test]
+    fn flatten() {
+        let flatten = fun("record", "flatten");
+        let unflatten = fun("record", "unflatten");
+        let nested = literal!({
+            "a": {"b": 1, "c": {"d": "snot"}},
+            "e": [1, {"f": 2}],
+            "g": {},
+            "h": []
+        });
+        let flat = literal!({"a.b": 1, "a.c.d": "snot", "e.0": 1, "e.1.f": 2, "g": {}, "h": []});
+        let sep = Value::from(".");
+        assert_val!(flatten(&[&nested, &sep]), flat.clone());
+        assert_val!(unflatten(&[&flat, &sep]), nested.clone());
+
+        let sep = Value::from("__");
+        assert_val!(
+            flatten(&[&literal!({"a": {"b.c": 1}}), &sep]),
+            literal!({"a__b.c": 1})
+        );
+        assert_val!(
+            unflatten(&[&literal!({"a__b.c": 1}), &sep]),
+            literal!({"a": {"b.c": 1}})
+        );
+
+        let sep = Value::from(".");
+        assert!(unflatten(&[&literal!({"a": 1, "a.b": 2}), &sep]).is_err());
+        assert!(flatten(&[&nested, &Value::from("")]).is_err());
+    }
 }
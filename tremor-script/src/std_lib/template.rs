@@ -0,0 +1,771 @@
+// Copyright 2020-2021, The Tremor Team
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+use crate::prelude::*;
+use crate::registry::Registry;
+use crate::tremor_const_fn;
+use std::cmp::Ordering;
+use tremor_value::literal;
+
+type TemplateResult<T> = std::result::Result<T, String>;
+
+/// Piece of a template before tags are nested
+#[derive(Debug, Clone, Copy)]
+enum Item<'t> {
+    Text(&'t str),
+    Output(&'t str),
+    Tag(&'t str),
+}
+
+#[derive(Debug)]
+enum Node {
+    Text(String),
+    Output(Expr),
+    /// Branches with their conditions and the else branch
+    If(Vec<(Expr, Vec<Node>)>, Vec<Node>),
+    For {
+        key: Option<String>,
+        value: String,
+        iter: Expr,
+        body: Vec<Node>,
+        /// Rendered if there is nothing to loop over
+        empty: Vec<Node>,
+    },
+}
+
+#[derive(Debug)]
+enum Expr {
+    Literal(Value<'static>),
+    /// A variable followed by record keys or array indexes
+    Path(String, Vec<Value<'static>>),
+    Not(Box<Expr>),
+    Binary(&'static str, Box<Expr>, Box<Expr>),
+    Filter(Box<Expr>, String, Vec<Expr>),
+}
+
+/// Splits a template into text and tags, `{{-` / `{%-` strip whitespace
+/// before and `-}}` / `-%}` after the tag
+fn scan(template: &str) -> TemplateResult<Vec<Item>> {
+    let mut items = Vec::new();
+    let mut rest = template;
+    let mut trim_next = false;
+    loop {
+        let start = ["{{", "{%", "{#"]
+            .iter()
+            .filter_map(|open| rest.find(open))
+            .min();
+        let (text, tag) = start.map_or((rest, ""), |i| rest.split_at(i));
+        let mut text = if trim_next { text.trim_start() } else { text };
+        if tag.is_empty() {
+            if !text.is_empty() {
+                items.push(Item::Text(text));
+            }
+            return Ok(items);
+        }
+        let (open, close) = tag.split_at(2);
+        let close_with = match open {
+            "{{" => "}}",
+            "{%" => "%}",
+            _ => "#}",
+        };
+        let end = close
+            .find(close_with)
+            .ok_or_else(|| format!("`{}` is not closed with `{}`", open, close_with))?;
+        let mut inner = &close[..end];
+        if let Some(trimmed) = inner.strip_prefix('-') {
+            inner = trimmed;
+            text = text.trim_end();
+        }
+        trim_next = if let Some(trimmed) = inner.strip_suffix('-') {
+            inner = trimmed;
+            true
+        } else {
+            false
+        };
+        if !text.is_empty() {
+            items.push(Item::Text(text));
+        }
+        match open {
+            "{{" => items.push(Item::Output(inner.trim())),
+            "{%" => items.push(Item::Tag(inner.trim())),
+            _ => (),
+        }
+        rest = &close[end + 2..];
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+enum Token {
+    Ident(String),
+    Literal(Value<'static>),
+    Sym(&'static str),
+}
+
+fn tokenize(expr: &str) -> TemplateResult<Vec<Token>> {
+    const SYMBOLS: [&str; 13] = [
+        "==", "!=", "<=", ">=", "<", ">", ".", "|", "(", ")", ",", "[", "]",
+    ];
+    let mut tokens = Vec::new();
+    let mut chars = expr.char_indices().peekable();
+    while let Some((i, c)) = chars.next() {
+        if c.is_ascii_alphabetic() || c == '_' {
+            let mut end = i + c.len_utf8();
+            while let Some((j, c)) = chars.next_if(|(_, c)| c.is_ascii_alphanumeric() || *c == '_')
+            {
+                end = j + c.len_utf8();
+            }
+            tokens.push(match &expr[i..end] {
+                "true" => Token::Literal(Value::from(true)),
+                "false" => Token::Literal(Value::from(false)),
+                "null" | "none" => Token::Literal(Value::null()),
+                ident => Token::Ident(ident.to_string()),
+            });
+        } else if c.is_ascii_digit()
+            || (c == '-' && chars.peek().map_or(false, |(_, c)| c.is_ascii_digit()))
+        {
+            // no fractions after a `.` so `items.0.1` are two indexes
+            let fraction = tokens.last() != Some(&Token::Sym("."));
+            let mut end = i + 1;
+            while let Some((j, _)) = chars.next_if(|(j, c)| {
+                c.is_ascii_digit()
+                    || (fraction
+                        && *c == '.'
+                        && expr[j + 1..].starts_with(|c: char| c.is_ascii_digit()))
+            }) {
+                end = j + 1;
+            }
+            let n = &expr[i..end];
+            tokens.push(Token::Literal(if let Ok(n) = n.parse::<i64>() {
+                Value::from(n)
+            } else {
+                Value::from(
+                    n.parse::<f64>()
+                        .map_err(|e| format!("invalid number `{}`: {}", n, e))?,
+                )
+            }));
+        } else if c == '"' || c == '\'' {
+            let mut s = String::new();
+            loop {
+                match chars.next() {
+                    Some((_, '\\')) => match chars.next() {
+                        Some((_, 'n')) => s.push('\n'),
+                        Some((_, 't')) => s.push('\t'),
+                        Some((_, c)) => s.push(c),
+                        None => break,
+                    },
+                    Some((_, q)) if q == c => break,
+                    Some((_, c)) => s.push(c),
+                    None => return Err(format!("unterminated string in `{}`", expr)),
+                }
+            }
+            tokens.push(Token::Literal(Value::from(s)));
+        } else if let Some(sym) = SYMBOLS.iter().find(|sym| expr[i..].starts_with(*sym)) {
+            if sym.len() == 2 {
+                chars.next();
+            }
+            tokens.push(Token::Sym(*sym));
+        } else if !c.is_whitespace() {
+            return Err(format!("unexpected `{}` in `{}`", c, expr));
+        }
+    }
+    Ok(tokens)
+}
+
+/// Parses the expression of a single tag
+struct ExprParser<'e> {
+    source: &'e str,
+    tokens: Vec<Token>,
+    pos: usize,
+}
+
+impl<'e> ExprParser<'e> {
+    fn new(source: &'e str) -> TemplateResult<Self> {
+        Ok(Self {
+            source,
+            tokens: tokenize(source)?,
+            pos: 0,
+        })
+    }
+
+    fn error<T>(&self, msg: &str) -> TemplateResult<T> {
+        Err(format!("{} in `{}`", msg, self.source))
+    }
+
+    fn peek(&self) -> Option<&Token> {
+        self.tokens.get(self.pos)
+    }
+
+    fn is(&mut self, token: &Token) -> bool {
+        let found = self.peek() == Some(token);
+        if found {
+            self.pos += 1;
+        }
+        found
+    }
+
+    fn keyword(&mut self, kw: &str) -> bool {
+        self.is(&Token::Ident(kw.to_string()))
+    }
+
+    fn symbol(&mut self, sym: &'static str) -> bool {
+        self.is(&Token::Sym(sym))
+    }
+
+    fn expect(&mut self, sym: &'static str) -> TemplateResult<()> {
+        if self.symbol(sym) {
+            Ok(())
+        } else {
+            self.error(&format!("expected `{}`", sym))
+        }
+    }
+
+    fn ident(&mut self) -> TemplateResult<String> {
+        if let Some(Token::Ident(ident)) = self.peek() {
+            let ident = ident.clone();
+            self.pos += 1;
+            Ok(ident)
+        } else {
+            self.error("expected a name")
+        }
+    }
+
+    fn end(&self) -> TemplateResult<()> {
+        if self.pos == self.tokens.len() {
+            Ok(())
+        } else {
+            self.error("unexpected trailing input")
+        }
+    }
+
+    fn expr(&mut self) -> TemplateResult<Expr> {
+        let mut lhs = self.and()?;
+        while self.keyword("or") {
+            lhs = Expr::Binary("or", Box::new(lhs), Box::new(self.and()?));
+        }
+        Ok(lhs)
+    }
+
+    fn and(&mut self) -> TemplateResult<Expr> {
+        let mut lhs = self.not()?;
+        while self.keyword("and") {
+            lhs = Expr::Binary("and", Box::new(lhs), Box::new(self.not()?));
+        }
+        Ok(lhs)
+    }
+
+    fn not(&mut self) -> TemplateResult<Expr> {
+        if self.keyword("not") {
+            Ok(Expr::Not(Box::new(self.not()?)))
+        } else {
+            let lhs = self.filtered()?;
+            for op in ["==", "!=", "<=", ">=", "<", ">"] {
+                if self.symbol(op) {
+                    return Ok(Expr::Binary(op, Box::new(lhs), Box::new(self.filtered()?)));
+                }
+            }
+            Ok(lhs)
+        }
+    }
+
+    fn filtered(&mut self) -> TemplateResult<Expr> {
+        let mut expr = self.primary()?;
+        while self.symbol("|") {
+            let filter = self.ident()?;
+            let mut args = Vec::new();
+            if self.symbol("(") && !self.symbol(")") {
+                loop {
+                    args.push(self.expr()?);
+                    if !self.symbol(",") {
+                        break;
+                    }
+                }
+                self.expect(")")?;
+            }
+            expr = Expr::Filter(Box::new(expr), filter, args);
+        }
+        Ok(expr)
+    }
+
+    fn primary(&mut self) -> TemplateResult<Expr> {
+        match self.peek().cloned() {
+            Some(Token::Literal(value)) => {
+                self.pos += 1;
+                Ok(Expr::Literal(value))
+            }
+            Some(Token::Sym("(")) => {
+                self.pos += 1;
+                let expr = self.expr()?;
+                self.expect(")")?;
+                Ok(expr)
+            }
+            Some(Token::Ident(name)) => {
+                self.pos += 1;
+                let mut path = Vec::new();
+                loop {
+                    if self.symbol(".") {
+                        match self.peek().cloned() {
+                            Some(Token::Ident(key)) => path.push(Value::from(key)),
+                            Some(Token::Literal(idx)) if idx.is_u64() => path.push(idx),
+                            _ => return self.error("expected a key or index after `.`"),
+                        }
+                        self.pos += 1;
+                    } else if self.symbol("[") {
+                        match self.peek().cloned() {
+                            Some(Token::Literal(key)) if key.is_str() || key.is_u64() => {
+                                path.push(key);
+                            }
+                            _ => return self.error("expected a string or an index after `[`"),
+                        }
+                        self.pos += 1;
+                        self.expect("]")?;
+                    } else {
+                        return Ok(Expr::Path(name, path));
+                    }
+                }
+            }
+            _ => self.error("expected a value"),
+        }
+    }
+}
+
+fn parse_expr(source: &str) -> TemplateResult<Expr> {
+    let mut parser = ExprParser::new(source)?;
+    let expr = parser.expr()?;
+    parser.end()?;
+    Ok(expr)
+}
+
+/// Nests the items of a template into nodes
+struct Parser<'t> {
+    items: Vec<Item<'t>>,
+    pos: usize,
+}
+
+impl<'t> Parser<'t> {
+    /// Parses nodes until one of the `until` tags, which is returned
+    fn nodes(&mut self, until: &[&str]) -> TemplateResult<(Vec<Node>, &'t str)> {
+        let mut nodes = Vec::new();
+        while let Some(&item) = self.items.get(self.pos) {
+            self.pos += 1;
+            match item {
+                Item::Text(text) => nodes.push(Node::Text(text.to_string())),
+                Item::Output(expr) => nodes.push(Node::Output(parse_expr(expr)?)),
+                Item::Tag(tag) => {
+                    let (word, rest) = tag.split_once(char::is_whitespace).unwrap_or((tag, ""));
+                    if until.contains(&word) {
+                        return Ok((nodes, tag));
+                    }
+                    match word {
+                        "if" => nodes.push(self.if_node(rest)?),
+                        "for" => nodes.push(self.for_node(rest)?),
+                        _ => return Err(format!("unexpected `{{% {} %}}`", tag)),
+                    }
+                }
+            }
+        }
+        match until.last() {
+            Some(end) => Err(format!("missing `{{% {} %}}`", end)),
+            None => Ok((nodes, "")),
+        }
+    }
+
+    fn if_node(&mut self, cond: &str) -> TemplateResult<Node> {
+        let mut branches = Vec::new();
+        let mut cond = parse_expr(cond)?;
+        loop {
+            let (body, tag) = self.nodes(&["elif", "else", "endif"])?;
+            branches.push((cond, body));
+            if let Some(elif) = tag.strip_prefix("elif") {
+                cond = parse_expr(elif)?;
+            } else if tag == "else" {
+                let (otherwise, _) = self.nodes(&["endif"])?;
+                return Ok(Node::If(branches, otherwise));
+            } else {
+                return Ok(Node::If(branches, Vec::new()));
+            }
+        }
+    }
+
+    fn for_node(&mut self, head: &str) -> TemplateResult<Node> {
+        let mut parser = ExprParser::new(head)?;
+        let first = parser.ident()?;
+        let (key, value) = if parser.symbol(",") {
+            (Some(first), parser.ident()?)
+        } else {
+            (None, first)
+        };
+        if !parser.keyword("in") {
+            return parser.error("expected `in`");
+        }
+        let iter = parser.expr()?;
+        parser.end()?;
+        let (body, tag) = self.nodes(&["else", "endfor"])?;
+        let empty = if tag == "else" {
+            self.nodes(&["endfor"])?.0
+        } else {
+            Vec::new()
+        };
+        Ok(Node::For {
+            key,
+            value,
+            iter,
+            body,
+            empty,
+        })
+    }
+}
+
+/// Variables of a template, loop variables shadow the context
+struct Scope<'c, 'v> {
+    ctx: &'c Value<'v>,
+    locals: Vec<(String, Value<'static>)>,
+}
+
+impl<'c, 'v> Scope<'c, 'v> {
+    fn lookup(&self, name: &str) -> Value<'static> {
+        self.locals
+            .iter()
+            .rev()
+            .find(|(local, _)| local == name)
+            .map_or_else(
+                || {
+                    self.ctx
+                        .get(name)
+                        .map_or_else(Value::null, Value::clone_static)
+                },
+                |(_, value)| value.clone(),
+            )
+    }
+}
+
+fn truthy(v: &Value) -> bool {
+    if let Some(b) = v.as_bool() {
+        b
+    } else if let Some(s) = v.as_str() {
+        !s.is_empty()
+    } else if let Some(a) = v.as_array() {
+        !a.is_empty()
+    } else if let Some(o) = v.as_object() {
+        !o.is_empty()
+    } else if let Some(n) = v.cast_f64() {
+        n.abs() > 0.0
+    } else {
+        !v.is_null()
+    }
+}
+
+/// Strings as they are, `null` as nothing and everything else as JSON
+fn write(out: &mut String, v: &Value) {
+    if let Some(s) = v.as_str() {
+        out.push_str(s);
+    } else if !v.is_null() {
+        out.push_str(&v.encode());
+    }
+}
+
+fn compare(lhs: &Value, rhs: &Value) -> Option<Ordering> {
+    match (lhs.as_str(), rhs.as_str()) {
+        (Some(l), Some(r)) => Some(l.cmp(r)),
+        _ => lhs.cast_f64()?.partial_cmp(&rhs.cast_f64()?),
+    }
+}
+
+fn eval(expr: &Expr, scope: &Scope) -> TemplateResult<Value<'static>> {
+    Ok(match expr {
+        Expr::Literal(value) => value.clone(),
+        Expr::Path(name, path) => {
+            let mut value = scope.lookup(name);
+            for segment in path {
+                value = if let Some(key) = segment.as_str() {
+                    value.get(key).map_or_else(Value::null, Clone::clone)
+                } else {
+                    segment
+                        .as_usize()
+                        .and_then(|idx| value.get_idx(idx))
+                        .map_or_else(Value::null, Clone::clone)
+                };
+            }
+            value
+        }
+        Expr::Not(expr) => Value::from(!truthy(&eval(expr, scope)?)),
+        Expr::Binary("and", lhs, rhs) => {
+            Value::from(truthy(&eval(lhs, scope)?) && truthy(&eval(rhs, scope)?))
+        }
+        Expr::Binary("or", lhs, rhs) => {
+            Value::from(truthy(&eval(lhs, scope)?) || truthy(&eval(rhs, scope)?))
+        }
+        Expr::Binary(op, lhs, rhs) => {
+            let (lhs, rhs) = (eval(lhs, scope)?, eval(rhs, scope)?);
+            let ord = compare(&lhs, &rhs);
+            Value::from(match *op {
+                "==" => ord.map_or(lhs == rhs, Ordering::is_eq),
+                "!=" => ord.map_or(lhs != rhs, Ordering::is_ne),
+                "<" => ord.map_or(false, Ordering::is_lt),
+                "<=" => ord.map_or(false, Ordering::is_le),
+                ">" => ord.map_or(false, Ordering::is_gt),
+                _ => ord.map_or(false, Ordering::is_ge),
+            })
+        }
+        Expr::Filter(expr, name, args) => {
+            let value = eval(expr, scope)?;
+            let args = args
+                .iter()
+                .map(|arg| eval(arg, scope))
+                .collect::<TemplateResult<Vec<_>>>()?;
+            filter(value, name, &args)?
+        }
+    })
+}
+
+fn text(v: &Value) -> String {
+    let mut out = String::new();
+    write(&mut out, v);
+    out
+}
+
+fn filter(
+    value: Value<'static>,
+    name: &str,
+    args: &[Value<'static>],
+) -> TemplateResult<Value<'static>> {
+    let arity = match name {
+        "default" | "join" => 1,
+        _ => 0,
+    };
+    if args.len() != arity {
+        return Err(format!(
+            "filter `{}` takes {} arguments, not {}",
+            name,
+            arity,
+            args.len()
+        ));
+    }
+    Ok(match name {
+        "upper" => Value::from(text(&value).to_uppercase()),
+        "lower" => Value::from(text(&value).to_lowercase()),
+        "trim" => Value::from(text(&value).trim().to_string()),
+        "capitalize" => {
+            let s = text(&value);
+            let mut chars = s.chars();
+            Value::from(chars.next().map_or_else(String::new, |first| {
+                first
+                    .to_uppercase()
+                    .chain(chars.flat_map(char::to_lowercase))
+                    .collect()
+            }))
+        }
+        "length" => Value::from(if let Some(a) = value.as_array() {
+            a.len()
+        } else if let Some(o) = value.as_object() {
+            o.len()
+        } else {
+            text(&value).chars().count()
+        }),
+        "first" => value
+            .as_array()
+            .and_then(|a| a.first())
+            .cloned()
+            .unwrap_or_default(),
+        "last" => value
+            .as_array()
+            .and_then(|a| a.last())
+            .cloned()
+            .unwrap_or_default(),
+        "json" => Value::from(value.encode()),
+        "default" => {
+            if value.is_null() {
+                args[0].clone()
+            } else {
+                value
+            }
+        }
+        "join" => {
+            let sep = text(&args[0]);
+            let parts: Vec<_> = value
+                .as_array()
+                .map(|a| a.iter().map(text).collect())
+                .unwrap_or_default();
+            Value::from(parts.join(sep.as_str()))
+        }
+        _ => return Err(format!("unknown filter `{}`", name)),
+    })
+}
+
+fn render_nodes(nodes: &[Node], scope: &mut Scope, out: &mut String) -> TemplateResult<()> {
+    for node in nodes {
+        match node {
+            Node::Text(text) => out.push_str(text),
+            Node::Output(expr) => write(out, &eval(expr, scope)?),
+            Node::If(branches, otherwise) => {
+                let mut branch = otherwise;
+                for (cond, body) in branches {
+                    if truthy(&eval(cond, scope)?) {
+                        branch = body;
+                        break;
+                    }
+                }
+                render_nodes(branch, scope, out)?;
+            }
+            Node::For {
+                key,
+                value,
+                iter,
+                body,
+                empty,
+            } => {
+                let iter = eval(iter, scope)?;
+                let entries: Vec<(Value<'static>, Value<'static>)> =
+                    if let Some(a) = iter.as_array() {
+                        a.iter()
+                            .enumerate()
+                            .map(|(i, v)| (Value::from(i), v.clone()))
+                            .collect()
+                    } else if let Some(o) = iter.as_object() {
+                        o.iter()
+                            .map(|(k, v)| (Value::from(k.to_string()), v.clone()))
+                            .collect()
+                    } else if iter.is_null() {
+                        Vec::new()
+                    } else {
+                        return Err(format!("can not loop over `{}`", iter.encode()));
+                    };
+                if entries.is_empty() {
+                    render_nodes(empty, scope, out)?;
+                }
+                let len = entries.len();
+                for (index0, (k, v)) in entries.into_iter().enumerate() {
+                    let index = index0 + 1;
+                    let (first, last) = (index0 == 0, index == len);
+                    let mut vars = vec![
+                        (value.clone(), v),
+                        (
+                            "loop".to_string(),
+                            literal!({
+                                "index": index,
+                                "index0": index0,
+                                "first": first,
+                                "last": last,
+                                "length": len,
+                            }),
+                        ),
+                    ];
+                    if let Some(key) = key {
+                        vars.push((key.clone(), k));
+                    }
+                    let depth = scope.locals.len();
+                    scope.locals.extend(vars);
+                    let res = render_nodes(body, scope, out);
+                    scope.locals.truncate(depth);
+                    res?;
+                }
+            }
+        }
+    }
+    Ok(())
+}
+
+/// Renders a template with the variables of the `ctx` record
+fn render(template: &str, ctx: &Value) -> TemplateResult<String> {
+    let mut parser = Parser {
+        items: scan(template)?,
+        pos: 0,
+    };
+    let (nodes, _) = parser.nodes(&[])?;
+    let mut scope = Scope {
+        ctx,
+        locals: Vec::new(),
+    };
+    let mut out = String::with_capacity(template.len());
+    render_nodes(&nodes, &mut scope, &mut out)?;
+    Ok(out)
+}
+
+pub fn load(registry: &mut Registry) {
+    registry.insert(
+        tremor_const_fn! (template|render(_context, _template: String, _ctx) {
+            render(_template, _ctx).map(Value::from).map_err(to_runtime_error)
+        }),
+    );
+}
+
+#[cfg(test)]
+mod test {
+    use super::render;
+    use crate::registry::fun;
+    use crate::Value;
+    use tremor_value::literal;
+
+    #[test]
+    fn output() {
+        let ctx = literal!({
+            "host": "snot",
+            "tags": ["a", "b"],
+            "load": {"1m": 0.5},
+            "missing": null,
+        });
+        assert_eq!(
+            render(
+                r#"{{ host | upper }}: {{ load["1m"] }} {{ tags.1 }} {{ tags }} {{ nope | default("-") }}{{ missing }}"#,
+                &ctx
+            ),
+            Ok(r#"SNOT: 0.5 b ["a","b"] -"#.to_string())
+        );
+        assert_eq!(
+            render(
+                "{{ tags | join(', ') | capitalize }} ({{ tags | length }}){# ignored #}",
+                &ctx
+            ),
+            Ok("A, b (2)".to_string())
+        );
+    }
+
+    #[test]
+    fn control() {
+        let ctx = literal!({
+            "alerts": [{"host": "a", "level": 3}, {"host": "b", "level": 1}],
+            "labels": {"dc": "eu"},
+        });
+        let template = r#"
+{%- for alert in alerts %}
+{{ loop.index }}. {{ alert.host }}
+{%- if alert.level > 2 and not alert.muted %} (critical)
+{%- elif alert.level == 2 %} (warning)
+{%- else %} (info){% endif %}
+{%- endfor %}
+{% for k, v in labels %}{{ k }}={{ v }}{% endfor %}
+{% for x in nothing %}{{ x }}{% else %}none{% endfor %}"#;
+        assert_eq!(
+            render(template, &ctx),
+            Ok("\n1. a (critical)\n2. b (info)\ndc=eu\nnone".to_string())
+        );
+    }
+
+    #[test]
+    fn errors() {
+        let ctx = Value::null();
+        assert!(render("{{ a", &ctx).is_err());
+        assert!(render("{% if a %}", &ctx).is_err());
+        assert!(render("{% endfor %}", &ctx).is_err());
+        assert!(render("{{ a | nope }}", &ctx).is_err());
+        assert!(render("{% for a in 1 %}{% endfor %}", &ctx).is_err());
+    }
+
+    #[test]
+    fn function() {
+        let f = fun("template", "render");
+        let template = Value::from("Hello {{ name }}!");
+        let ctx = literal!({"name": "badger"});
+        assert_val!(f(&[&template, &ctx]), "Hello badger!");
+    }
+}
@@ -0,0 +1,554 @@
+// Copyright 2020-2021, The Tremor Team
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+//! Bytecode backend for scripts
+//!
+//! Compiles the expressions of a script to a compact stack based bytecode,
+//! run without walking the AST for every event.
+//!
+//! Compiled to bytecode are:
+//!
+//! * literals, locals and constants
+//! * paths of only keys and indexes, e.g. `event.a.b[0]` or `$kafka.topic`
+//! * unary and binary operators
+//! * records with literal field names, and lists
+//! * function calls
+//! * the values of expression statements and of `let` statements
+//!
+//! Everything else runs in the interpreter, as a single instruction when it
+//! is part of a compiled expression:
+//!
+//! * `match`, `patch`, `merge` and comprehensions
+//! * string interpolation, `present`, binaries, aggregates and `recur`
+//! * paths with other segments, e.g. `event[key]` or `event.a[1:2]`, and
+//!   paths starting with an expression
+//! * records with interpolated field names
+//! * statements with effects: the assignment of `let`, `emit`, `drop` and
+//!   statement level `match` and comprehensions
+//!
+//! Every instruction fails with the error the interpreter reports for the
+//! same expression, so nothing is evaluated twice. Only paths that can't be
+//! looked up are handed to the interpreter to report why, as looking up a
+//! path has no effects.
+
+use crate::{
+    ast::{
+        BaseExpr, BinOpKind, Expr, ImutExprInt, Invoke, Literal, Path, ReservedPath, Script,
+        Segment, UnaryOpKind,
+    },
+    ctx::EventContext,
+    errors::{error_invalid_unary, Result},
+    interpreter::{exec_binary, exec_unary, AggrType, Cont, Env, ExecOpts, LocalStack},
+    registry::Registry,
+    script::Return,
+    stry, Object, Value,
+};
+use std::borrow::Cow;
+
+/// An instruction
+#[derive(Clone, Debug, PartialEq)]
+enum Op<'script> {
+    /// Pushes a literal
+    Push(Value<'script>),
+    /// Pushes the value of a local or of a path of keys and indexes
+    Path(ImutExprInt<'script>),
+    /// Replaces the two values on top of the stack with the result of an
+    /// operator
+    Binary(BinOpKind, Node),
+    /// Replaces the value on top of the stack with the result of an
+    /// operator, the node of its operand is kept for errors
+    Unary(UnaryOpKind, Node, Node),
+    /// Replaces the values of the given fields on top of the stack with a
+    /// record of them and the base
+    Record(Object<'script>, Vec<beef::Cow<'script, str>>),
+    /// Replaces the given number of values on top of the stack with a list
+    List(usize),
+    /// Replaces the arguments on top of the stack with the result of a
+    /// function
+    Call(Invoke<'script>),
+    /// Pushes the value of an expression run by the interpreter
+    Interpret(ImutExprInt<'script>),
+}
+
+/// A node of the script by its meta id, to report errors at its location
+#[derive(Clone, Copy, Debug, PartialEq)]
+struct Node(usize);
+
+impl BaseExpr for Node {
+    fn mid(&self) -> usize {
+        self.0
+    }
+}
+
+/// A statement, the expression is kept for the interpreter
+#[derive(Clone, Debug, PartialEq)]
+struct Stmt<'script> {
+    /// The bytecode of the value of the statement, if it has one
+    code: Option<Vec<Op<'script>>>,
+    expr: Expr<'script>,
+}
+
+/// A script compiled to bytecode
+#[derive(Clone, Debug, PartialEq)]
+pub struct Program<'script> {
+    stmts: Vec<Stmt<'script>>,
+}
+
+impl<'script> Program<'script> {
+    /// Compiles the expressions of a script
+    #[must_use]
+    pub fn compile(exprs: &[Expr<'script>]) -> Self {
+        let stmts = exprs
+            .iter()
+            .map(|expr| {
+                let value = match expr {
+                    Expr::Imut(value) => Some(value),
+                    Expr::Assign { expr: value, .. } => {
+                        if let Expr::Imut(value) = value.as_ref() {
+                            Some(value)
+                        } else {
+                            None
+                        }
+                    }
+                    _ => None,
+                };
+                let code = value.map(|value| {
+                    let mut code = Vec::new();
+                    compile(value, &mut code);
+                    code
+                });
+                Stmt {
+                    code,
+                    expr: expr.clone(),
+                }
+            })
+            .collect();
+        Self { stmts }
+    }
+
+    /// Number of instructions
+    #[must_use]
+    pub fn len(&self) -> usize {
+        self.stmts
+            .iter()
+            .map(|s| s.code.as_ref().map_or(1, Vec::len))
+            .sum()
+    }
+
+    /// Tests if the program has no instructions
+    #[must_use]
+    pub fn is_empty(&self) -> bool {
+        self.stmts.is_empty()
+    }
+
+    /// Runs the program of a script, see `Script::run`
+    ///
+    /// # Errors
+    /// on runtime errors
+    pub fn run<'event>(
+        &self,
+        script: &Script<'script>,
+        context: &EventContext,
+        aggr: AggrType,
+        event: &mut Value<'event>,
+        state: &mut Value<'static>,
+        meta: &mut Value<'event>,
+    ) -> Result<Return<'event>>
+    where
+        'script: 'event,
+    {
+        let mut local = LocalStack::with_size(script.locals);
+        let opts = ExecOpts {
+            result_needed: true,
+            aggr,
+        };
+        let env = Env {
+            context,
+            consts: script.consts.run(),
+            aggrs: &script.aggregates,
+            meta: &script.node_meta,
+            recursion_limit: crate::recursion_limit(),
+        };
+
+        let last = self.stmts.len().saturating_sub(1);
+        for (i, stmt) in self.stmts.iter().enumerate() {
+            let opts = if i == last {
+                opts.with_result()
+            } else {
+                opts.without_result()
+            };
+            let cont = match (&stmt.code, &stmt.expr) {
+                // like the interpreter we skip values nobody uses
+                (Some(_), Expr::Imut(_)) if i != last => continue,
+                (Some(code), Expr::Imut(_)) => {
+                    Cont::Cont(stry!(eval(code, opts, &env, event, state, meta, &local)))
+                }
+                (Some(code), Expr::Assign { path, .. }) => {
+                    let value = stry!(eval(
+                        code,
+                        opts.with_result(),
+                        &env,
+                        event,
+                        state,
+                        meta,
+                        &local
+                    ))
+                    .into_owned();
+                    stry!(stmt
+                        .expr
+                        .assign(opts, &env, event, state, meta, &mut local, path, value)
+                        .map(Cont::Cont))
+                }
+                (_, expr) => stry!(expr.run(opts, &env, event, state, meta, &mut local)),
+            };
+            match cont {
+                Cont::Drop => return Ok(Return::Drop),
+                Cont::Emit(value, port) => return Ok(Return::Emit { value, port }),
+                Cont::EmitEvent(port) => return Ok(Return::EmitEvent { port }),
+                Cont::Cont(value) if i == last => {
+                    return Ok(Return::Emit {
+                        value: value.into_owned(),
+                        port: None,
+                    })
+                }
+                Cont::Cont(_) => (),
+            }
+        }
+        Ok(Return::Drop)
+    }
+}
+
+/// If a path only has keys and indexes, which the bytecode looks up itself
+fn is_simple(path: &Path) -> bool {
+    !matches!(path, Path::Expr(_))
+        && path
+            .segments()
+            .iter()
+            .all(|s| matches!(s, Segment::Id { .. } | Segment::Idx { .. }))
+}
+
+/// Compiles an expression, leaving its value on top of the stack
+fn compile<'script>(expr: &ImutExprInt<'script>, code: &mut Vec<Op<'script>>) {
+    match expr {
+        ImutExprInt::Literal(Literal { value, .. }) => code.push(Op::Push(value.clone())),
+        ImutExprInt::Local { .. } => code.push(Op::Path(expr.clone())),
+        ImutExprInt::Path(path) if is_simple(path) => code.push(Op::Path(expr.clone())),
+        ImutExprInt::Binary(b) => {
+            compile(&b.lhs, code);
+            compile(&b.rhs, code);
+            code.push(Op::Binary(b.kind, Node(b.mid())));
+        }
+        ImutExprInt::Unary(u) => {
+            compile(&u.expr, code);
+            code.push(Op::Unary(u.kind, Node(u.mid()), Node(u.expr.mid())));
+        }
+        ImutExprInt::Record(r) if r.fields.iter().all(|f| f.name.as_str().is_some()) => {
+            let mut names = Vec::with_capacity(r.fields.len());
+            for field in &r.fields {
+                compile(&field.value, code);
+                names.push(beef::Cow::from(
+                    field.name.as_str().unwrap_or_default().to_string(),
+                ));
+            }
+            code.push(Op::Record(r.base.clone(), names));
+        }
+        ImutExprInt::List(l) => {
+            for e in &l.exprs {
+                compile(&e.0, code);
+            }
+            code.push(Op::List(l.exprs.len()));
+        }
+        ImutExprInt::Invoke(i)
+        | ImutExprInt::Invoke1(i)
+        | ImutExprInt::Invoke2(i)
+        | ImutExprInt::Invoke3(i) => {
+            for a in &i.args {
+                compile(&a.0, code);
+            }
+            code.push(Op::Call(i.clone()));
+        }
+        other => code.push(Op::Interpret(other.clone())),
+    }
+}
+
+/// Looks up a local or a path of keys and indexes, `None` if it doesn't
+/// exist
+fn lookup<'run, 'event>(
+    expr: &'run ImutExprInt<'event>,
+    env: &'run Env<'run, 'event>,
+    event: &'run Value<'event>,
+    state: &'run Value<'static>,
+    meta: &'run Value<'event>,
+    local: &'run LocalStack<'event>,
+) -> Option<&'run Value<'event>> {
+    let path = match expr {
+        ImutExprInt::Local {
+            idx,
+            is_const: false,
+            ..
+        } => return local.values.get(*idx)?.as_ref(),
+        ImutExprInt::Local {
+            idx,
+            is_const: true,
+            ..
+        } => return env.consts.get(*idx),
+        ImutExprInt::Path(path) => path,
+        _ => return None,
+    };
+    let mut value = match path {
+        Path::Local(p) => local.values.get(p.idx)?.as_ref()?,
+        Path::Const(p) => env.consts.get(p.idx)?,
+        Path::Event(_) => event,
+        Path::Meta(_) => meta,
+        Path::State(_) => state,
+        Path::Reserved(ReservedPath::Args { .. }) => env.consts.args,
+        Path::Reserved(ReservedPath::Group { .. }) => env.consts.group,
+        Path::Reserved(ReservedPath::Window { .. }) => env.consts.window,
+        Path::Expr(_) => return None,
+    };
+    for segment in path.segments() {
+        value = match segment {
+            Segment::Id { key, .. } => key.lookup(value)?,
+            Segment::Idx { idx, .. } => value.as_array()?.get(*idx)?,
+            Segment::Element { .. } | Segment::Range { .. } => return None,
+        };
+    }
+    Some(value)
+}
+
+/// Pops the given number of values off the stack
+fn pop_n<T>(stack: &mut Vec<T>, n: usize) -> Result<Vec<T>> {
+    stack
+        .len()
+        .checked_sub(n)
+        .map(|at| stack.split_off(at))
+        .ok_or_else(|| "bytecode stack underflow".into())
+}
+
+/// Pops a value off the stack
+fn pop<T>(stack: &mut Vec<T>) -> Result<T> {
+    stack.pop().ok_or_else(|| "bytecode stack underflow".into())
+}
+
+/// Evaluates bytecode
+#[allow(clippy::too_many_arguments)]
+fn eval<'run, 'event>(
+    code: &'run [Op<'event>],
+    opts: ExecOpts,
+    env: &'run Env<'run, 'event>,
+    event: &'run Value<'event>,
+    state: &'run Value<'static>,
+    meta: &'run Value<'event>,
+    local: &'run LocalStack<'event>,
+) -> Result<Cow<'run, Value<'event>>> {
+    let mut stack: Vec<Cow<'run, Value<'event>>> = Vec::with_capacity(8);
+    for op in code {
+        let value = match op {
+            Op::Push(value) => Cow::Borrowed(value),
+            Op::Path(expr) => match lookup(expr, env, event, state, meta, local) {
+                Some(value) => Cow::Borrowed(value),
+                // the interpreter reports why the path doesn't exist
+                None => stry!(expr.run(opts, env, event, state, meta, local)),
+            },
+            Op::Binary(kind, node) => {
+                let rhs = stry!(pop(&mut stack));
+                let lhs = stry!(pop(&mut stack));
+                stry!(exec_binary(node, node, env.meta, *kind, &lhs, &rhs))
+            }
+            Op::Unary(kind, node, operand) => {
+                let value = stry!(pop(&mut stack));
+                match exec_unary(*kind, &value) {
+                    Some(value) => value,
+                    None => return error_invalid_unary(node, operand, *kind, &value, env.meta),
+                }
+            }
+            Op::Record(base, names) => {
+                let values = stry!(pop_n(&mut stack, names.len()));
+                let mut object = base.clone();
+                object.reserve(names.len());
+                for (name, value) in names.iter().zip(values) {
+                    object.insert(name.clone(), value.into_owned());
+                }
+                Cow::Owned(Value::from(object))
+            }
+            Op::List(n) => {
+                let values = stry!(pop_n(&mut stack, *n));
+                Cow::Owned(Value::from(
+                    values.into_iter().map(Cow::into_owned).collect::<Vec<_>>(),
+                ))
+            }
+            Op::Call(invoke) => {
+                let args = stry!(pop_n(&mut stack, invoke.args.len()));
+                let args: Vec<&Value<'event>> = args.iter().map(AsRef::as_ref).collect();
+                Cow::Owned(stry!(invoke.invocable.invoke(env, &args).map_err(|e| {
+                    let r: Option<&Registry> = None;
+                    let outer = invoke.extent(env.meta).expand_lines(2);
+                    e.into_err(&outer, invoke, r, env.meta)
+                })))
+            }
+            Op::Interpret(expr) => stry!(expr.run(opts, env, event, state, meta, local)),
+        };
+        stack.push(value);
+    }
+    pop(&mut stack)
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::{path::ModulePath, registry, Script as CompiledScript};
+    use tremor_value::literal;
+
+    /// The outcome of a script with the event, state and metadata after it
+    type Outcome = (
+        Return<'static>,
+        Value<'static>,
+        Value<'static>,
+        Value<'static>,
+    );
+
+    fn run(src: &str, compile: bool, event: Value<'static>) -> Result<Outcome> {
+        let reg = registry::registry();
+        let mut script = CompiledScript::parse(
+            &ModulePath { mounts: vec![] },
+            "test",
+            src.to_string(),
+            &reg,
+        )
+        .map_err(|e| e.error)?;
+        if compile {
+            script.compile();
+        }
+        let mut event: Value = event;
+        let mut meta: Value = literal!({"kafka": {"topic": "snot"}});
+        let mut state = Value::null();
+        let context = EventContext::new(0, None);
+        let r = script
+            .run(&context, AggrType::Emit, &mut event, &mut state, &mut meta)
+            .map(|r| match r {
+                Return::Emit { value, port } => Return::Emit {
+                    value: value.into_static(),
+                    port,
+                },
+                Return::Drop => Return::Drop,
+                Return::EmitEvent { port } => Return::EmitEvent { port },
+            })?;
+        Ok((r, event.into_static(), state, meta.into_static()))
+    }
+
+    fn same(src: &str, event: &Value<'static>) -> Result<()> {
+        let interpreted = run(src, false, event.clone());
+        let compiled = run(src, true, event.clone());
+        match (interpreted, compiled) {
+            (Ok(i), Ok(c)) => assert_eq!(i, c, "{}", src),
+            (Err(i), Err(c)) => assert_eq!(i.to_string(), c.to_string(), "{}", src),
+            (i, c) => panic!("{}: {:?} != {:?}", src, i, c),
+        }
+        Ok(())
+    }
+
+    #[test]
+    fn compiled() -> Result<()> {
+        let event = literal!({"a": 1, "b": [1, 2, 3], "c": {"d": "badger"}, "e": 2.5});
+        for src in &[
+            "event.a + 1",
+            "event.a * event.e - event.b[2]",
+            "-event.a",
+            "not (event.a == 1)",
+            r#"{"x": event.c.d, "y": [event.a, event.b[0], $kafka.topic], "z": "static"}"#,
+            "let x = event.a; let y = x + 41; y",
+            "let event.f = event.c.d; event",
+            "let $kafka.partition = 7; $",
+            "string::uppercase(event.c.d)",
+            "math::max(event.a, event.b[1]) + 1",
+            r#"match event.a of case 1 => "one" default => "other" end"#,
+            r#"let x = {"a": event.a}; emit x"#,
+            r#"drop"#,
+            // errors are reported by the interpreter
+            "event.missing",
+            "event.b[7]",
+            "event.c.d + 1",
+            "string::uppercase(event.a)",
+            "string::uppercase(event.c.d) + event.missing",
+            "math::max(event.a, 1) + event.c.d",
+            "-event.c.d",
+            "let x = event.nope; x",
+        ] {
+            same(src, &event)?;
+        }
+        Ok(())
+    }
+
+    #[test]
+    fn interpreted() -> Result<()> {
+        // constructs the interpreter runs have to behave the same when they
+        // are part of a compiled script
+        let events = [
+            literal!({"a": 1, "b": [1, 2, 3], "c": {"d": "badger"}, "k": "a"}),
+            literal!({"a": "snot", "b": [], "c": {}, "k": "c"}),
+            literal!({}),
+        ];
+        for src in &[
+            // matches, patches and merges
+            r#"match event of case %{a == 1} => "one" case %{present c} => "c" default => "other" end"#,
+            r#"let x = match event.a of case 1 => event.b default => [] end; x"#,
+            r#"patch event of insert "x" => event.a; upsert "a" => 2 end"#,
+            r#"patch event of merge => {"m": event.k} end"#,
+            r#"merge event of {"a": [event.a]} end"#,
+            r#"{"p": patch {} of insert "y" => event.a end, "a": event.a + 1}"#,
+            // comprehensions
+            "for event.b of case (i, e) => e * 2 end",
+            r#"let x = [for event.b of case (i, e) => e + event.a end]; x"#,
+            // paths that aren't only keys and indexes
+            "event[event.k]",
+            "event.b[1:2]",
+            "event.b[1:2][0] + 1",
+            "let k = event.k; event[k]",
+            r#"{"v": event[event.k], "n": 1}"#,
+            // string interpolation, present and binaries
+            r##""#{event.a} of #{event.k}""##,
+            r##"{"#{event.k}": event.a}"##,
+            "present event.c.d",
+            "[present event.a, present event.b[1]]",
+            "<<1, 2>>",
+            // effects
+            "let state = event.a; emit state",
+            r#"let state = {"n": event.a}; let state.m = state.n; state"#,
+            r#"let $tag = event.k; emit event => "err""#,
+            "let event.b = []; drop",
+            r#"emit event => "out""#,
+        ] {
+            for event in &events {
+                same(src, event)?;
+            }
+        }
+        Ok(())
+    }
+
+    #[test]
+    fn program() {
+        let reg = registry::registry();
+        let script = CompiledScript::parse(
+            &ModulePath { mounts: vec![] },
+            "test",
+            "let x = event.a; x + 1".to_string(),
+            &reg,
+        )
+        .expect("valid script");
+        let program = Program::compile(&script.script.suffix().exprs);
+        assert!(!program.is_empty());
+        // path, then path, push, add
+        assert_eq!(4, program.len());
+    }
+}